@@ -0,0 +1,176 @@
+//! BVH Auto Tuning
+
+use super::*;
+use core::pbrt::*;
+use std::time::Instant;
+
+/// Cost of traversing a BVH node relative to a ray-primitive intersection
+/// test. This matches the traversal cost used by the SAH build.
+const TRAVERSAL_COST: f64 = 0.125;
+
+/// Split method and maximum primitives per leaf tried when auto tuning.
+const CANDIDATES: [(SplitMethod, u8); 7] = [
+    (SplitMethod::SAH, 1),
+    (SplitMethod::SAH, 2),
+    (SplitMethod::SAH, 4),
+    (SplitMethod::SAH, 8),
+    (SplitMethod::SAH, 16),
+    (SplitMethod::Middle, 4),
+    (SplitMethod::EqualCounts, 4),
+];
+
+/// Traversal statistics collected by tracing rays through a BVH.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BVHTraversalStats {
+    /// Number of rays traced.
+    pub rays: usize,
+
+    /// Number of nodes whose bounds were tested.
+    pub nodes_visited: usize,
+
+    /// Number of ray-primitive intersection tests.
+    pub primitives_tested: usize,
+}
+
+impl BVHTraversalStats {
+    /// Returns the average cost of a ray in units of ray-primitive
+    /// intersection tests.
+    pub fn cost_per_ray(&self) -> f64 {
+        if self.rays == 0 {
+            0.0
+        } else {
+            (TRAVERSAL_COST * self.nodes_visited as f64 + self.primitives_tested as f64)
+                / self.rays as f64
+        }
+    }
+}
+
+impl BVHAccel {
+    /// Builds BVHs with several split methods and leaf sizes, traces the
+    /// probe rays through each and returns the one with the lowest average
+    /// traversal cost. The cost is computed from counts of node visits and
+    /// primitive tests rather than timings so the choice is deterministic.
+    ///
+    /// * `primitives` - The primitives.
+    /// * `probe_rays` - Rays representative of those traced when rendering.
+    pub fn auto_tune(primitives: &[ArcPrimitive], probe_rays: &[Ray]) -> Self {
+        let mut best: Option<(Self, f64)> = None;
+
+        for (split_method, max_prims_in_node) in CANDIDATES.iter() {
+            let start = Instant::now();
+            let bvh = Self::new(primitives, *max_prims_in_node, *split_method);
+            let build_time = start.elapsed().as_secs_f32();
+
+            let stats = bvh.traversal_stats(probe_rays);
+            let cost = stats.cost_per_ray();
+            info!(
+                "BVH {:?} maxnodeprims {}: {} nodes, built in {:.3}s, {:.2} nodes and \
+                 {:.2} primitives per ray, cost {:.3}.",
+                split_method,
+                max_prims_in_node,
+                bvh.nodes.len(),
+                build_time,
+                stats.nodes_visited as f64 / max(stats.rays, 1) as f64,
+                stats.primitives_tested as f64 / max(stats.rays, 1) as f64,
+                cost
+            );
+
+            if best.as_ref().is_none_or(|(_, best_cost)| cost < *best_cost) {
+                best = Some((bvh, cost));
+            }
+        }
+
+        let (bvh, cost) = best.unwrap();
+        info!(
+            "Auto tuned BVH: splitmethod {:?}, maxnodeprims {}, cost {:.3}.",
+            bvh.split_method, bvh.max_prims_in_node, cost
+        );
+        bvh
+    }
+
+    /// Traces rays through the BVH finding the closest intersections and
+    /// returns the traversal statistics.
+    ///
+    /// * `rays` - The rays.
+    pub fn traversal_stats(&self, rays: &[Ray]) -> BVHTraversalStats {
+        let mut stats = BVHTraversalStats {
+            rays: rays.len(),
+            ..BVHTraversalStats::default()
+        };
+        // Trace the rays the same way as `intersect()`, counting the
+        // primitives tested in the closure.
+        for ray in rays {
+            let mut r = ray.clone();
+            let (_, n_visited) = self.intersect_with_count(&mut r, |p, r| {
+                stats.primitives_tested += 1;
+                p.intersect(r)
+            });
+            stats.nodes_visited += n_visited;
+        }
+        stats
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_primitives::*;
+
+    /// Returns a ray travelling along the z-axis.
+    ///
+    /// * `x` - The x-coordinate of the ray.
+    fn ray(x: Float) -> Ray {
+        Ray::new(
+            Point3f::new(x, 0.5, -1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            INFINITY,
+            0.0,
+            None,
+        )
+    }
+
+    #[test]
+    fn traversal_stats_count_nodes_and_primitives() {
+        let bvh = BVHAccel::new(&boxes(1), 1, SplitMethod::SAH);
+        let stats = bvh.traversal_stats(&[ray(0.5), ray(5.0)]);
+        assert_eq!(stats.rays, 2);
+        assert_eq!(stats.nodes_visited, 2);
+        assert_eq!(stats.primitives_tested, 1);
+        assert_eq!(stats.cost_per_ray(), (2.0 * TRAVERSAL_COST + 1.0) / 2.0);
+
+        let empty = BVHAccel::new(&[], 1, SplitMethod::SAH);
+        assert_eq!(empty.traversal_stats(&[ray(0.5)]).nodes_visited, 0);
+        assert_eq!(BVHTraversalStats::default().cost_per_ray(), 0.0);
+    }
+
+    #[test]
+    fn auto_tune_selects_cheapest_candidate() {
+        let primitives = boxes(64);
+        let rays: Vec<Ray> = (0..64).map(|i| ray(2.0 * i as Float + 0.5)).collect();
+
+        let cost = |bvh: &BVHAccel| bvh.traversal_stats(&rays).cost_per_ray();
+        let costs: Vec<f64> = CANDIDATES
+            .iter()
+            .map(|(split_method, max_prims)| {
+                cost(&BVHAccel::new(&primitives, *max_prims, *split_method))
+            })
+            .collect();
+
+        let bvh = BVHAccel::auto_tune(&primitives, &rays);
+        let best = costs.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert_eq!(cost(&bvh), best);
+
+        // The first candidate with the lowest cost is kept.
+        let i = costs.iter().position(|c| *c == best).unwrap();
+        assert_eq!(bvh.split_method, CANDIDATES[i].0);
+        assert_eq!(bvh.max_prims_in_node, CANDIDATES[i].1);
+
+        // Leaves with one box cost less than leaves with 16 boxes for rays
+        // that each hit a single box.
+        assert!(costs[0] < costs[4], "{:?}", costs);
+    }
+}
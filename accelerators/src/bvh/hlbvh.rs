@@ -4,37 +4,39 @@ use super::common::*;
 use super::morton::*;
 use core::geometry::*;
 use core::pbrt::*;
-use core::primitive::*;
 use rayon::prelude::*;
-use std::cell::RefCell;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 const N_BUCKETS: usize = 12;
 const FIRST_BIT_INDEX: usize = N_BITS - 1 - N_BUCKETS; // The index of the next bit to try splitting
 const MORTON_BITS: u32 = 10;
 const MORTON_SCALE: u32 = 1 << MORTON_BITS;
 
+/// Upper tree nodes with at least this many treelets build their children in
+/// parallel.
+const PARALLEL_TREELET_THRESHOLD: usize = 64;
+
 /// HLBVH builder.
 pub struct HLBVH {}
 
 impl HLBVH {
-    /// Build the BVH structure using HLBVH algorithm.
+    /// Build the BVH structure using HLBVH algorithm. The Morton codes are
+    /// computed and sorted, the treelets are built and the upper tree is built
+    /// in parallel.
+    ///
+    /// Returns the root node and the indices of the primitives in the order
+    /// of the leaf nodes so that primitives in leaf nodes occupy contiguous
+    /// ranges.
     ///
-    /// * `primitives`        - The primitives in the node.
     /// * `max_prims_in_node` - Maximum number of primitives in the node.
     /// * `primitive_info`    - Primitive information.
     /// * `total_nodes`       - Used to return total number of nodes.
-    /// * `ordered_prims`     - Used to return a list of primitives ordered such that
-    ///                         primitives in leaf nodes occupy contiguous ranges in
-    ///                         the vector.
     pub fn build(
-        primitives: &[ArcPrimitive],
         max_prims_in_node: u8,
-        primitive_info: &mut Vec<BVHPrimitiveInfo>,
-        total_nodes: &mut usize,
-        ordered_prims: Arc<Mutex<Vec<ArcPrimitive>>>,
-    ) -> Arc<BVHBuildNode> {
+        primitive_info: &[BVHPrimitiveInfo],
+        total_nodes: &AtomicUsize,
+    ) -> (Arc<BVHBuildNode>, Vec<usize>) {
         // Compute bounds of all primitives in BVH node.
         let bounds = primitive_info
             .iter()
@@ -50,10 +52,9 @@ impl HLBVH {
             })
             .collect();
 
-        // Radix sort primitive Morton indices.
-        let mut morton_prims_cell = RefCell::new(morton_prims);
-        radix_sort(&mut morton_prims_cell);
-        let morton_prims = morton_prims_cell.into_inner();
+        // Sort primitive Morton indices.
+        let mut morton_prims = morton_prims;
+        morton_prims.par_sort_by_key(|mp| mp.morton_code);
 
         // Create LBVH treelets at bottom of BVH.
         const MASK: u32 = 0b00111111111111000000000000000000;
@@ -72,33 +73,29 @@ impl HLBVH {
         }
 
         // Create LBVHs for treelets in parallel.
-        let atomic_total = AtomicUsize::new(0);
-        let ordered_prims_offset = AtomicUsize::new(0);
         let mut treelets: Vec<Arc<BVHBuildNode>> = treelets_to_build
             .par_iter()
             .map(|&(start_index, n_primitives)| {
                 // Generate i^th LBVH treelet.
                 let mut nodes_created = 0;
                 let build_node = Self::emit_lbvh(
-                    primitives,
                     max_prims_in_node as usize,
                     primitive_info,
                     &morton_prims[start_index..],
+                    start_index,
                     n_primitives,
                     &mut nodes_created,
-                    Arc::clone(&ordered_prims),
-                    &ordered_prims_offset,
                     Some(FIRST_BIT_INDEX),
                 );
-                atomic_total.fetch_add(nodes_created, Ordering::SeqCst);
+                total_nodes.fetch_add(nodes_created, Ordering::Relaxed);
                 build_node
             })
             .collect();
 
-        *total_nodes = atomic_total.into_inner();
-
         // Create and return SAH BVH from LBVH treelets.
-        Self::build_upper_sah(&mut treelets, 0, treelets_to_build.len(), total_nodes)
+        let root = Self::build_upper_sah(&mut treelets, total_nodes);
+        let order = morton_prims.iter().map(|mp| mp.primitive_index).collect();
+        (root, order)
     }
 
     /// Builds a treelet by taking primitives with centroids in some region of space
@@ -106,42 +103,33 @@ impl HLBVH {
     /// region of space into two halves along the center of the region along one of
     /// the three axes.
     ///
-    /// * `primitives`           - The primitives.
-    /// * `max_prims_in_node`    - Maximum number of primitives in the node.
-    /// * `primitive_info`       - Primitive information.
-    /// * `morton_prims`         - Morton codes for primitives.
-    /// * `n_primitives`         - Number of primitives.
-    /// * `total_nodes`          - Total number of nodes.
-    /// * `ordered_prims`        - Used to return a list of primitives ordered such that
-    ///                            primitives in leaf nodes occupy contiguous ranges in
-    ///                            the vector.
-    /// * `ordered_prims_offset  - Index in `ordered_prims` for start of this node.
-    /// * `bit_index`            - The bit index.
+    /// * `max_prims_in_node` - Maximum number of primitives in the node.
+    /// * `primitive_info`    - Primitive information.
+    /// * `morton_prims`      - Morton codes for primitives.
+    /// * `first_prim_offset` - Index of the first Morton code in the sorted
+    ///                         Morton codes. Primitives are ordered like the
+    ///                         sorted Morton codes.
+    /// * `n_primitives`      - Number of primitives.
+    /// * `total_nodes`       - Total number of nodes.
+    /// * `bit_index`         - The bit index.
     fn emit_lbvh(
-        primitives: &[ArcPrimitive],
         max_prims_in_node: usize,
         primitive_info: &[BVHPrimitiveInfo],
         morton_prims: &[MortonPrimitive],
+        first_prim_offset: usize,
         n_primitives: usize,
         total_nodes: &mut usize,
-        ordered_prims: Arc<Mutex<Vec<ArcPrimitive>>>,
-        ordered_prims_offset: &AtomicUsize,
         bit_index: Option<usize>,
     ) -> Arc<BVHBuildNode> {
         debug_assert!(n_primitives > 0);
 
         if bit_index.is_none() || n_primitives < max_prims_in_node {
             // Create and return leaf node of LBVH treelet.
-            let mut bounds = Bounds3f::empty();
-            let first_prim_offset = ordered_prims_offset.fetch_add(n_primitives, Ordering::SeqCst);
-
-            let prims = Arc::clone(&ordered_prims);
-            let mut prims2 = prims.lock().expect("unabled to lock ordered_prims");
-            for i in 0..n_primitives {
-                let primitive_index = morton_prims[i].primitive_index;
-                prims2[first_prim_offset + i] = Arc::clone(&primitives[primitive_index]);
-                bounds = bounds.union(&primitive_info[primitive_index].bounds);
-            }
+            let bounds = morton_prims[..n_primitives]
+                .iter()
+                .fold(Bounds3f::empty(), |b, mp| {
+                    b.union(&primitive_info[mp.primitive_index].bounds)
+                });
 
             *total_nodes += 1;
             BVHBuildNode::new_leaf_node(first_prim_offset, n_primitives, bounds)
@@ -152,14 +140,12 @@ impl HLBVH {
                 == (morton_prims[n_primitives - 1].morton_code & mask)
             {
                 return Self::emit_lbvh(
-                    primitives,
                     max_prims_in_node,
                     primitive_info,
                     morton_prims,
+                    first_prim_offset,
                     n_primitives,
                     total_nodes,
-                    Arc::clone(&ordered_prims),
-                    ordered_prims_offset,
                     Some(bit_idx - 1),
                 );
             }
@@ -191,25 +177,21 @@ impl HLBVH {
 
             // Create and return interior LBVH node
             let c0 = Self::emit_lbvh(
-                primitives,
                 max_prims_in_node,
                 primitive_info,
                 morton_prims,
+                first_prim_offset,
                 split_offset,
                 total_nodes,
-                Arc::clone(&ordered_prims),
-                ordered_prims_offset,
                 Some(bit_idx - 1),
             );
             let c1 = Self::emit_lbvh(
-                primitives,
                 max_prims_in_node,
                 primitive_info,
                 &morton_prims[split_offset..],
+                first_prim_offset + split_offset,
                 n_primitives - split_offset,
                 total_nodes,
-                Arc::clone(&ordered_prims),
-                ordered_prims_offset,
                 Some(bit_idx - 1),
             );
 
@@ -219,35 +201,32 @@ impl HLBVH {
         }
     }
 
-    /// Creates a BVH of all the treelets.
+    /// Creates a BVH of all the treelets. Large upper nodes build their
+    /// children in parallel.
     ///
     /// * `treelet_roots` - Treelet roots.
-    /// * `start`         - Starting index. For first call it should be 0.
-    /// * `end`           - Ending index + 1. For first call it should be # of nodes.
     /// * `total_nodes`   - Total number of nodes.
     fn build_upper_sah(
-        treelet_roots: &mut Vec<Arc<BVHBuildNode>>,
-        start: usize,
-        end: usize,
-        total_nodes: &mut usize,
+        treelet_roots: &mut [Arc<BVHBuildNode>],
+        total_nodes: &AtomicUsize,
     ) -> Arc<BVHBuildNode> {
-        debug_assert!(start < end);
+        debug_assert!(!treelet_roots.is_empty());
 
-        let n_nodes = end - start;
+        let n_nodes = treelet_roots.len();
         if n_nodes == 1 {
-            return Arc::clone(&treelet_roots[start]);
+            return Arc::clone(&treelet_roots[0]);
         }
-        *total_nodes += 1;
+        total_nodes.fetch_add(1, Ordering::Relaxed);
 
         // Compute bounds of all nodes under this HLBVH node
         let mut bounds = Bounds3f::empty();
-        for treelet_root in treelet_roots.iter().take(end).skip(start) {
+        for treelet_root in treelet_roots.iter() {
             bounds = bounds.union(&treelet_root.bounds);
         }
 
         // Compute bound of HLBVH node centroids, choose split dimension dim.
         let mut centroid_bounds = Bounds3f::empty();
-        for treelet_root in treelet_roots.iter().take(end).skip(start) {
+        for treelet_root in treelet_roots.iter() {
             let centroid = (treelet_root.bounds.p_min + treelet_root.bounds.p_max) * 0.5;
             centroid_bounds = centroid_bounds.union(&centroid);
         }
@@ -257,23 +236,22 @@ impl HLBVH {
         // Make sure the SAH split below does something... ?
         debug_assert!(centroid_bounds.p_max[dim] != centroid_bounds.p_min[dim]);
 
-        // Allocate BucketInfo for SAH partition buckets
-        let mut buckets = [BucketInfo::default(); N_BUCKETS];
-
-        // Initialize BucketInfo for HLBVH SAH partition buckets
-        for treelet_root in treelet_roots.iter().take(end).skip(start) {
-            let centroid = (treelet_root.bounds.p_min[dim] + treelet_root.bounds.p_max[dim]) * 0.5;
-
-            let mut b = (N_BUCKETS as Float
+        // Returns the bucket for a node.
+        let bucket = |node: &BVHBuildNode| {
+            let centroid = (node.bounds.p_min[dim] + node.bounds.p_max[dim]) * 0.5;
+            let b = (N_BUCKETS as Float
                 * ((centroid - centroid_bounds.p_min[dim])
                     / (centroid_bounds.p_max[dim] - centroid_bounds.p_min[dim])))
                 as usize;
-            if b == N_BUCKETS {
-                b = N_BUCKETS - 1;
-            }
-            debug_assert!(b > 0);
-            debug_assert!(b < N_BUCKETS);
+            b.min(N_BUCKETS - 1)
+        };
 
+        // Allocate BucketInfo for SAH partition buckets
+        let mut buckets = [BucketInfo::default(); N_BUCKETS];
+
+        // Initialize BucketInfo for HLBVH SAH partition buckets
+        for treelet_root in treelet_roots.iter() {
+            let b = bucket(treelet_root);
             buckets[b].count += 1;
             buckets[b].bounds = buckets[b].bounds.union(&treelet_root.bounds);
         }
@@ -310,28 +288,24 @@ impl HLBVH {
         }
 
         // Split nodes and create interior HLBVH SAH node
-        let roots = treelet_roots[start..end].iter_mut();
-        let split = itertools::partition(roots, |node| {
-            let centroid = (node.bounds.p_min[dim] + node.bounds.p_max[dim]) * 0.5;
-            let mut b = (N_BUCKETS as Float
-                * ((centroid - centroid_bounds.p_min[dim])
-                    / (centroid_bounds.p_max[dim] - centroid_bounds.p_min[dim])))
-                as usize;
-            if b == N_BUCKETS {
-                b = N_BUCKETS - 1;
-            }
-            debug_assert!(b > 0);
-            debug_assert!(b < N_BUCKETS);
-            b <= min_cost_split_bucket
+        let mid = itertools::partition(treelet_roots.iter_mut(), |node| {
+            bucket(node) <= min_cost_split_bucket
         });
-        let mid = start + split;
-        debug_assert!(mid > start);
-        debug_assert!(mid < end);
-
-        BVHBuildNode::new_interior_node(
-            dim,
-            Self::build_upper_sah(treelet_roots, start, mid, total_nodes),
-            Self::build_upper_sah(treelet_roots, mid, end, total_nodes),
-        )
+        debug_assert!(mid > 0);
+        debug_assert!(mid < n_nodes);
+
+        let (roots0, roots1) = treelet_roots.split_at_mut(mid);
+        let (c0, c1) = if n_nodes >= PARALLEL_TREELET_THRESHOLD {
+            rayon::join(
+                || Self::build_upper_sah(roots0, total_nodes),
+                || Self::build_upper_sah(roots1, total_nodes),
+            )
+        } else {
+            (
+                Self::build_upper_sah(roots0, total_nodes),
+                Self::build_upper_sah(roots1, total_nodes),
+            )
+        };
+        BVHBuildNode::new_interior_node(dim, c0, c1)
     }
 }
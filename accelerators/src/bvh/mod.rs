@@ -5,16 +5,27 @@ use core::light::*;
 use core::material::*;
 use core::paramset::*;
 use core::primitive::*;
+use core::profiler::*;
+use core::stats::*;
 
+mod auto_tune;
 mod common;
 mod hlbvh;
 mod morton;
 mod sah;
+mod wireframe;
 
+pub use auto_tune::*;
 pub use common::*;
 use hlbvh::*;
+use rayon::prelude::*;
 use sah::*;
-use std::sync::{Arc, Mutex};
+use std::borrow::Borrow;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::Instant;
+
+core::stat_ratio!("BVH/Nodes visited per ray", N_NODES_VISITED);
 
 /// Bounding Volume Hierarchy Accelerator.
 #[derive(Clone)]
@@ -31,6 +42,11 @@ pub struct BVHAccel {
 
     /// The list of nodes.
     pub nodes: Vec<LinearBVHNode>,
+
+    /// The triangle vertices of the primitives packed four at a time to cull
+    /// the primitives in leaf nodes.
+    #[cfg(feature = "simd")]
+    pub triangles: Vec<Triangle4>,
 }
 
 impl BVHAccel {
@@ -51,40 +67,48 @@ impl BVHAccel {
                 max_prims_in_node,
                 split_method,
                 nodes: vec![],
+                #[cfg(feature = "simd")]
+                triangles: vec![],
             }
         } else {
             // Build BVH from primitives.
+            let _p = ProfilePhase::new(Prof::AccelConstruction);
+            let start = Instant::now();
 
             // Initializes primitive_info array for primitives.
-            let mut primitive_info: Vec<BVHPrimitiveInfo> = Vec::with_capacity(n_primitives);
-            for (i, p) in primitives.iter().enumerate() {
-                primitive_info.push(BVHPrimitiveInfo::new(i, p.world_bound()));
-            }
+            let mut primitive_info: Vec<BVHPrimitiveInfo> = primitives
+                .par_iter()
+                .enumerate()
+                .map(|(i, p)| BVHPrimitiveInfo::new(i, p.world_bound()))
+                .collect();
 
             // Build BVH tree for primitives using primitive_info.
-            let mut total_nodes = 0;
-            let ordered_prims =
-                Arc::new(Mutex::new(Vec::<ArcPrimitive>::with_capacity(n_primitives)));
-
-            let root = match split_method {
-                SplitMethod::HLBVH => HLBVH::build(
-                    primitives,
-                    max_prims_in_node,
-                    &mut primitive_info,
-                    &mut total_nodes,
-                    Arc::clone(&ordered_prims),
-                ),
-                _ => SAH::recursive_build(
-                    primitives,
-                    split_method,
-                    max_prims_in_node,
-                    &mut primitive_info,
-                    0,
-                    n_primitives,
-                    &mut total_nodes,
-                    Arc::clone(&ordered_prims),
-                ),
+            let total_nodes = AtomicUsize::new(0);
+            let (root, order) = match split_method {
+                SplitMethod::HLBVH => {
+                    HLBVH::build(max_prims_in_node, &primitive_info, &total_nodes)
+                }
+                _ => {
+                    let root = SAH::recursive_build(
+                        split_method,
+                        max_prims_in_node,
+                        &mut primitive_info,
+                        0,
+                        &total_nodes,
+                    );
+                    let order = primitive_info
+                        .iter()
+                        .map(|pi| pi.primitive_number)
+                        .collect();
+                    (root, order)
+                }
             };
+            let total_nodes = total_nodes.into_inner();
+
+            // Order the primitives so that primitives in leaf nodes occupy
+            // contiguous ranges.
+            let ordered_prims: Vec<ArcPrimitive> =
+                order.iter().map(|&i| Arc::clone(&primitives[i])).collect();
 
             // Compute representation of depth-first traversal of BVH tree.
             let mut nodes = vec![LinearBVHNode::default(); total_nodes];
@@ -93,13 +117,18 @@ impl BVHAccel {
 
             debug_assert!(total_nodes == offset as usize);
 
-            let prims = Arc::clone(&ordered_prims);
-            let prims2 = prims.lock().expect("unabled to lock ordered_prims");
+            #[cfg(feature = "simd")]
+            let triangles = Self::pack_triangles(&ordered_prims);
+
+            STATS.record_build("bvh", n_primitives, start.elapsed());
+
             BVHAccel {
-                primitives: prims2.to_vec(),
+                primitives: ordered_prims,
                 max_prims_in_node,
                 split_method,
                 nodes,
+                #[cfg(feature = "simd")]
+                triangles,
             }
         }
     }
@@ -147,28 +176,102 @@ impl BVHAccel {
 
         my_offset
     }
-}
 
-/// Tag `BVHAccel` as an `Aggregate`.
-impl Aggregate for BVHAccel {}
+    /// Packs the triangle vertices of the primitives four at a time for the
+    /// 4-wide triangle test. Primitives that are not triangles are always
+    /// tested exactly.
+    ///
+    /// * `primitives` - The ordered primitives.
+    #[cfg(feature = "simd")]
+    fn pack_triangles(primitives: &[ArcPrimitive]) -> Vec<Triangle4> {
+        primitives
+            .chunks(4)
+            .map(|chunk| {
+                let triangles: Vec<Option<[Point3f; 3]>> = chunk
+                    .iter()
+                    .map(|p| p.get_shape().and_then(|s| s.triangle_vertices()))
+                    .collect();
+                Triangle4::new(&triangles)
+            })
+            .collect()
+    }
 
-impl Primitive for BVHAccel {
-    /// Returns a bounding box in the world space.
-    fn world_bound(&self) -> Bounds3f {
-        if !self.nodes.is_empty() {
-            self.nodes[0].bounds
-        } else {
-            Bounds3f::empty()
+    /// Calls the given function for the primitives in a leaf node until it
+    /// returns `true`.
+    ///
+    /// * `node`  - The leaf node.
+    /// * `r`     - The ray.
+    /// * `visit` - Tests a primitive against the ray.
+    #[cfg(not(feature = "simd"))]
+    fn visit_leaf<'a, R, F>(&'a self, node: &LinearBVHNode, r: &mut R, mut visit: F) -> bool
+    where
+        R: Borrow<Ray>,
+        F: FnMut(&'a dyn Primitive, &mut R) -> bool,
+    {
+        let start = node.offset as usize;
+        let end = start + node.n_primitives as usize;
+        self.primitives[start..end].iter().any(|p| visit(&**p, r))
+    }
+
+    /// Calls the given function for the primitives in a leaf node until it
+    /// returns `true`. Triangles are tested four at a time first and the ones
+    /// the ray misses are skipped.
+    ///
+    /// * `node`  - The leaf node.
+    /// * `r`     - The ray.
+    /// * `visit` - Tests a primitive against the ray.
+    #[cfg(feature = "simd")]
+    fn visit_leaf<'a, R, F>(&'a self, node: &LinearBVHNode, r: &mut R, mut visit: F) -> bool
+    where
+        R: Borrow<Ray>,
+        F: FnMut(&'a dyn Primitive, &mut R) -> bool,
+    {
+        let start = node.offset as usize;
+        let end = start + node.n_primitives as usize;
+        for group in start / 4..end.div_ceil(4) {
+            let mask = self.triangles[group].intersect_p((*r).borrow());
+            for idx in start.max(4 * group)..end.min(4 * group + 4) {
+                if mask & (1 << (idx % 4)) != 0 && visit(&*self.primitives[idx], r) {
+                    return true;
+                }
+            }
         }
+        false
     }
 
-    /// Returns geometric details if a ray intersects the primitive and updates
-    /// the t_max parameter of the ray. If there is no intersection, `None` is
-    /// returned.
+    /// Returns the closest intersection found by intersecting the primitives
+    /// in the leaves the ray passes through with the given function.
     ///
-    /// * `r`                  - The ray.
-    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+    /// * `r`         - The ray.
+    /// * `intersect` - Intersects a primitive with the ray.
+    fn intersect_with<'a, F>(&'a self, r: &mut Ray, intersect: F) -> Option<SurfaceInteraction<'a>>
+    where
+        F: FnMut(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
+        let _p = ProfilePhase::new(Prof::AccelIntersect);
+        let (si, n_visited) = self.intersect_with_count(r, intersect);
+        if n_visited > 0 {
+            N_NODES_VISITED.add(n_visited as u64, 1);
+        }
+        si
+    }
+
+    /// Returns the closest intersection found by intersecting the primitives
+    /// in the leaves the ray passes through with the given function and the
+    /// number of nodes visited.
+    ///
+    /// * `r`         - The ray.
+    /// * `intersect` - Intersects a primitive with the ray.
+    fn intersect_with_count<'a, F>(
+        &'a self,
+        r: &mut Ray,
+        mut intersect: F,
+    ) -> (Option<SurfaceInteraction<'a>>, usize)
+    where
+        F: FnMut(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
         let mut si: Option<SurfaceInteraction> = None;
+        let mut n_visited = 0;
         if !self.nodes.is_empty() {
             let inv_dir = Vector3f::new(1.0 / r.d.x, 1.0 / r.d.y, 1.0 / r.d.z);
             let dir_is_neg = [
@@ -184,15 +287,16 @@ impl Primitive for BVHAccel {
             loop {
                 // Check ray against BVH node
                 let node = &self.nodes[current_node_index];
+                n_visited += 1;
                 if node.bounds.intersect_p_inv(r, &inv_dir, dir_is_neg) {
                     if node.n_primitives > 0 {
                         // Intersect ray with primitives in leaf BVH node.
-                        for i in 0..node.n_primitives {
-                            let idx = node.offset as usize + i as usize;
-                            if let Some(hit) = self.primitives[idx].intersect(r) {
+                        self.visit_leaf(node, r, |p, r| {
+                            if let Some(hit) = intersect(p, r) {
                                 si = Some(hit);
                             }
-                        }
+                            false
+                        });
                         if to_visit_offset == 0 {
                             break;
                         }
@@ -220,13 +324,19 @@ impl Primitive for BVHAccel {
                 }
             }
         }
-        si
+        (si, n_visited)
     }
 
-    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    /// Returns whether any primitive in the leaves the ray passes through is
+    /// intersected by the given function.
     ///
-    /// * `r`                  - The ray.
-    fn intersect_p(&self, r: &Ray) -> bool {
+    /// * `r`           - The ray.
+    /// * `intersect_p` - Tests a primitive for intersection with the ray.
+    fn intersect_p_with<F>(&self, r: &Ray, intersect_p: F) -> bool
+    where
+        F: Fn(&dyn Primitive, &Ray) -> bool,
+    {
+        let _p = ProfilePhase::new(Prof::AccelIntersectP);
         if !self.nodes.is_empty() {
             let inv_dir = Vector3f::new(1.0 / r.d.x, 1.0 / r.d.y, 1.0 / r.d.z);
             let dir_is_neg = [
@@ -238,18 +348,18 @@ impl Primitive for BVHAccel {
             // Follow ray through BVH nodes to find primitive intersections.
             let (mut to_visit_offset, mut current_node_index) = (0, 0);
             let mut nodes_to_visit = [0_usize; 64];
+            let mut n_visited = 0;
 
             loop {
                 // Check ray against BVH node
                 let node = &self.nodes[current_node_index];
+                n_visited += 1;
                 if node.bounds.intersect_p_inv(r, &inv_dir, dir_is_neg) {
                     if node.n_primitives > 0 {
                         // Intersect ray with primitives in leaf BVH node.
-                        for i in 0..node.n_primitives {
-                            let idx = node.offset as usize + i as usize;
-                            if self.primitives[idx].intersect_p(r) {
-                                return true;
-                            }
+                        if self.visit_leaf(node, &mut &*r, |p, r| intersect_p(p, r)) {
+                            N_NODES_VISITED.add(n_visited, 1);
+                            return true;
                         }
                         if to_visit_offset == 0 {
                             break;
@@ -277,9 +387,62 @@ impl Primitive for BVHAccel {
                     current_node_index = nodes_to_visit[to_visit_offset];
                 }
             }
+            N_NODES_VISITED.add(n_visited, 1);
         }
         false
     }
+}
+
+/// Tag `BVHAccel` as an `Aggregate`.
+impl Aggregate for BVHAccel {}
+
+impl Primitive for BVHAccel {
+    /// Returns a bounding box in the world space.
+    fn world_bound(&self) -> Bounds3f {
+        if !self.nodes.is_empty() {
+            self.nodes[0].bounds
+        } else {
+            Bounds3f::empty()
+        }
+    }
+
+    /// Returns geometric details if a ray intersects the primitive and updates
+    /// the t_max parameter of the ray. If there is no intersection, `None` is
+    /// returned.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, r| p.intersect(r))
+    }
+
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, r| p.intersect_filtered(r, filter))
+    }
+
+    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect_p(&self, r: &Ray) -> bool {
+        self.intersect_p_with(r, |p, r| p.intersect_p(r))
+    }
+
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        self.intersect_p_with(r, |p, r| p.intersect_p_filtered(r, filter))
+    }
 
     /// Returns a reference to the AreaLight that describes the primitive’s
     /// emission distribution, if the primitive is itself a light source.
@@ -332,6 +495,19 @@ impl Primitive for BVHAccel {
             called; should've gone to GeometricPrimitive."
         );
     }
+
+    /// Returns the memory used by the nodes, primitive references and packed
+    /// triangles in bytes.
+    fn memory(&self) -> usize {
+        let memory = std::mem::size_of::<Self>()
+            + self.nodes.capacity() * std::mem::size_of::<LinearBVHNode>()
+            + self.primitives.capacity() * std::mem::size_of::<ArcPrimitive>();
+
+        #[cfg(feature = "simd")]
+        let memory = memory + self.triangles.capacity() * std::mem::size_of::<Triangle4>();
+
+        memory
+    }
 }
 
 impl From<(&ParamSet, &[ArcPrimitive])> for BVHAccel {
@@ -2,7 +2,6 @@
 
 use core::geometry::*;
 use core::pbrt::*;
-use std::cell::RefCell;
 
 /// Stores Morton codes (interleaved bits of coordinate values).
 #[derive(Copy, Clone, Default, Debug)]
@@ -44,58 +43,8 @@ pub fn encode_morton_3(v: &Vector3f) -> u32 {
         | left_shift_3(float_to_bits(v.x))
 }
 
+/// Number of bits in a Morton code.
 pub const N_BITS: usize = 30;
-const BITS_PER_PASS: usize = 6;
-const N_PASSES: usize = N_BITS / BITS_PER_PASS; // This should divide evenly.
-const N_BUCKETS: usize = 1 << BITS_PER_PASS;
-const BIT_MASK: usize = (1 << BITS_PER_PASS) - 1;
-
-/// Sorts a list of morton primitives in place using Radix sort.
-///
-/// * `v` - A `RefCell` containing the morton primitives vector.
-pub fn radix_sort(v: &mut RefCell<Vec<MortonPrimitive>>) {
-    let n = { v.borrow().len() };
-    let mut temp_vector = RefCell::new(vec![MortonPrimitive::default(); n]);
-
-    for pass in 0..N_PASSES {
-        // Perform one pass of radix sort, sorting BITS_PER_PASS bits.
-        let low_bit = pass * BITS_PER_PASS;
-
-        // Set in and out vector pointers for radix sort pass.
-        let (v_in, v_out) = if pass & 1 == 1 {
-            (temp_vector.borrow(), v.get_mut())
-        } else {
-            (v.borrow(), temp_vector.get_mut())
-        };
-
-        // Count number of zero bits in array for current radix sort bit.
-        let mut bucket_count = [0; N_BUCKETS];
-        for mp in v_in.iter() {
-            let bucket = ((mp.morton_code >> low_bit) as usize) & BIT_MASK;
-            debug_assert!(bucket < N_BUCKETS);
-            bucket_count[bucket] += 1;
-        }
-
-        // Compute starting index in output array for each bucket.
-        let mut out_index = [0; N_BUCKETS];
-        for i in 1..N_BUCKETS {
-            // want out_index[0] = 0.
-            out_index[i] = out_index[i - 1] + bucket_count[i - 1];
-        }
-
-        // Store sorted values in output array.
-        for mp in v_in.iter() {
-            let bucket = ((mp.morton_code >> low_bit) as usize) & BIT_MASK;
-            v_out[out_index[bucket]] = *mp;
-            out_index[bucket] += 1;
-        }
-    }
-
-    // Copy final result from temp_vector, if needed.
-    if N_PASSES & 1 == 1 {
-        *v.get_mut() = temp_vector.into_inner();
-    }
-}
 
 /// The bit shifts to compute the Morton code for each 3D coordinate are 
 /// performed in a series of shifts of power-of-two size. First, bits 8 and 9
@@ -3,86 +3,73 @@
 use super::common::*;
 use core::geometry::*;
 use core::pbrt::*;
-use core::primitive::*;
 use order_stat::kth_by;
+use rayon::prelude::*;
 use std::cmp::Ordering;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Arc;
 
 const N_BUCKETS: usize = 12;
 
+/// Nodes with at least this many primitives build their children in parallel.
+const PARALLEL_SUBTREE_THRESHOLD: usize = 4096;
+
+/// Nodes with more than this many primitives compute their bounds and SAH
+/// buckets over chunks of this size in parallel.
+const PARALLEL_CHUNK_SIZE: usize = 16384;
+
 /// Implements Surface Area Heuristic Algorithm
 pub struct SAH {}
 
 impl SAH {
     /// Recursively build the BVH structure for either Middle, EqualCounts or SAH
-    /// algorithm.
+    /// algorithm. The primitive information is reordered so that primitives in
+    /// leaf nodes occupy contiguous ranges; a leaf's primitives start at its
+    /// index in `primitive_info` plus `offset`. Large nodes build their children
+    /// in parallel.
     ///
-    /// * `primitives`        - The primitives in the node.
     /// * `split_method`      - Middle|EqualCounts|SAH
     /// * `max_prims_in_node` - Maximum number of primitives in the node.
-    /// * `primitive_info`    - Primitive information.
-    /// * `start`             - Starting index. For first call it should be 0.
-    /// * `end`               - Ending index + 1. For first call it should be number
-    ///                         of primitives.
+    /// * `primitive_info`    - Primitive information for the primitives in the
+    ///                         node.
+    /// * `offset`            - Index of the first primitive of the node in the
+    ///                         ordered primitives. For first call it should be 0.
     /// * `total_nodes`       - Used to return total number of nodes.
-    /// * `ordered_prims`     - Used to return a list of primitives ordered such that
-    ///                         primitives in leaf nodes occupy contiguous ranges in
-    ///                         the vector.
     pub fn recursive_build(
-        primitives: &[ArcPrimitive],
         split_method: SplitMethod,
         max_prims_in_node: u8,
-        primitive_info: &mut Vec<BVHPrimitiveInfo>,
-        start: usize,
-        end: usize,
-        total_nodes: &mut usize,
-        ordered_prims: Arc<Mutex<Vec<ArcPrimitive>>>,
+        primitive_info: &mut [BVHPrimitiveInfo],
+        offset: usize,
+        total_nodes: &AtomicUsize,
     ) -> Arc<BVHBuildNode> {
-        // Compute bounds of all primitives in BVH node.
-        let mut bounds = Bounds3f::empty();
-        for info in primitive_info.iter().take(end).skip(start) {
-            bounds = bounds.union(&info.bounds);
-        }
+        // Compute bounds of all primitives and their centroids in BVH node.
+        let (bounds, centroid_bounds) = fold_infos(
+            primitive_info,
+            (Bounds3f::empty(), Bounds3f::empty()),
+            |(b, cb), info| (b.union(&info.bounds), cb.union(&info.centroid)),
+            |(b0, cb0), (b1, cb1)| (b0.union(&b1), cb0.union(&cb1)),
+        );
 
-        let mut dim = Axis::default(); // Will be set if we need to make interior node.
+        // Choose split dimension dim.
+        let dim = centroid_bounds.maximum_extent();
 
-        let n_primitives = end - start;
-
-        let interior_midpoint = if n_primitives == 1 {
-            // Create leaf BVHBuildNode.
-            None
-        } else {
-            // Compute bound of primitive centroids, choose split dimension dim.
-            let mut centroid_bounds = Bounds3f::empty();
-            for info in primitive_info.iter().take(end).skip(start) {
-                centroid_bounds = centroid_bounds.union(&info.centroid);
-            }
-            dim = centroid_bounds.maximum_extent();
+        let n_primitives = primitive_info.len();
 
-            // Partition primitives into two sets and build children.
-            if centroid_bounds.p_max[dim] == centroid_bounds.p_min[dim] {
+        let interior_midpoint =
+            if n_primitives == 1 || centroid_bounds.p_max[dim] == centroid_bounds.p_min[dim] {
                 // Create leaf BVHBuildNode.
                 None
             } else {
                 // Partition primitives based on split_method.
                 match split_method {
-                    SplitMethod::Middle => Some(Self::split_middle(
-                        primitive_info,
-                        start,
-                        end,
-                        dim,
-                        &centroid_bounds,
-                    )),
-
-                    SplitMethod::EqualCounts => {
-                        Some(Self::split_equal_counts(primitive_info, start, end, dim))
+                    SplitMethod::Middle => {
+                        Some(Self::split_middle(primitive_info, dim, &centroid_bounds))
                     }
 
+                    SplitMethod::EqualCounts => Some(Self::split_equal_counts(primitive_info, dim)),
+
                     SplitMethod::SAH => Self::split_sah(
                         primitive_info,
-                        start,
-                        end,
-                        n_primitives,
                         dim,
                         &centroid_bounds,
                         &bounds,
@@ -91,72 +78,49 @@ impl SAH {
 
                     _ => panic!("recursive_build(): Invalid split_method={:?}", split_method),
                 }
-            }
-        };
+            };
 
-        *total_nodes += 1;
+        total_nodes.fetch_add(1, AtomicOrdering::Relaxed);
         if let Some(mid) = interior_midpoint {
             // Create interior BVHBuildNode.
-            let c0 = Self::recursive_build(
-                primitives,
-                split_method,
-                max_prims_in_node,
-                primitive_info,
-                start,
-                mid,
-                total_nodes,
-                Arc::clone(&ordered_prims),
-            );
-            let c1 = Self::recursive_build(
-                primitives,
-                split_method,
-                max_prims_in_node,
-                primitive_info,
-                mid,
-                end,
-                total_nodes,
-                Arc::clone(&ordered_prims),
-            );
+            let (infos0, infos1) = primitive_info.split_at_mut(mid);
+            let build = |infos: &mut [BVHPrimitiveInfo], offset: usize| {
+                Self::recursive_build(split_method, max_prims_in_node, infos, offset, total_nodes)
+            };
+            let (c0, c1) = if n_primitives >= PARALLEL_SUBTREE_THRESHOLD {
+                rayon::join(|| build(infos0, offset), || build(infos1, offset + mid))
+            } else {
+                (build(infos0, offset), build(infos1, offset + mid))
+            };
             BVHBuildNode::new_interior_node(dim, c0, c1)
         } else {
             // Create leaf BVHBuildNode.
-            let prims = Arc::clone(&ordered_prims);
-            let mut prims2 = prims.lock().expect("unable to lock ordered_prims");
-            let first_prim_offset = prims2.len();
-            for info in primitive_info.iter().take(end).skip(start) {
-                prims2.push(Arc::clone(&primitives[info.primitive_number]));
-            }
-            BVHBuildNode::new_leaf_node(first_prim_offset, n_primitives, bounds)
+            BVHBuildNode::new_leaf_node(offset, n_primitives, bounds)
         }
     }
 
     /// Linear Bounding Volume Hierarchy using splitting planes that are
     /// midpoint of each region of space.
     ///
-    /// * `primitive_info`  - Vector containing all primitive info.
-    /// * `start`           - Starting index in primitive_info.
-    /// * `end`             - Ending index + 1 in primitive_info.
+    /// * `primitive_info`  - Primitive information for the primitives in the
+    ///                       node.
     /// * `dim`             - Axis used to partition primitives.
-    /// * `centroid_bounds` - Bounding box of primtive centroids in primtive_info
-    ///                       from start to end.
+    /// * `centroid_bounds` - Bounding box of primtive centroids in
+    ///                       `primitive_info`.
     fn split_middle(
-        primitive_info: &mut Vec<BVHPrimitiveInfo>,
-        start: usize,
-        end: usize,
+        primitive_info: &mut [BVHPrimitiveInfo],
         dim: Axis,
         centroid_bounds: &Bounds3f,
     ) -> usize {
         let pmid = (centroid_bounds.p_min[dim] + centroid_bounds.p_max[dim]) / 2.0;
-        let infos = primitive_info[start..end].iter_mut();
-        let split = itertools::partition(infos, |pi| pi.centroid[dim] < pmid);
-        let mid = start + split;
+        let mid = itertools::partition(primitive_info.iter_mut(), |pi| pi.centroid[dim] < pmid);
 
-        if mid != start && mid != end {
+        if mid != 0 && mid != primitive_info.len() {
             mid
         } else {
             // Lots of prims with large overlapping bounding boxes, this may fail
             // to partition; in that case don't use EqualCounts.
-            Self::split_equal_counts(primitive_info, start, end, dim)
+            Self::split_equal_counts(primitive_info, dim)
         }
     }
 
@@ -164,19 +128,13 @@ impl SAH {
     /// of the primitives have smallest centroid coordinate values along the
     /// chosen axis, and second have have the largest centroid coordinate values.
     ///
-    /// * `primitive_info`  - Vector containing all primitive info.
-    /// * `start`           - Starting index in primitive_info.
-    /// * `end`             - Ending index + 1 in primitive_info.
+    /// * `primitive_info`  - Primitive information for the primitives in the
+    ///                       node.
     /// * `dim`             - Axis used to partition primitives.
-    fn split_equal_counts(
-        primitive_info: &mut Vec<BVHPrimitiveInfo>,
-        start: usize,
-        end: usize,
-        dim: Axis,
-    ) -> usize {
-        let mid = (start + end) / 2;
+    fn split_equal_counts(primitive_info: &mut [BVHPrimitiveInfo], dim: Axis) -> usize {
+        let mid = primitive_info.len() / 2;
 
-        Self::kth_element_by(primitive_info, start, mid, end, |&a, &b| {
+        kth_by(primitive_info, mid, |a, b| {
             if a.centroid[dim] < b.centroid[dim] {
                 Ordering::Less
             } else if a.centroid[dim] == b.centroid[dim] {
@@ -189,76 +147,56 @@ impl SAH {
         mid
     }
 
-    /// Partition a subset of items between start and end inclusive such that:
-    /// - k^th element will be in its sorted order
-    /// - elements e in v[start, k - 1] will satisfy f(e, ek) == Ordering::Less
-    /// - elements e in v[k + 1, end] will satisfy f(e, ek) == Ordering::Greater
-    /// and the k^th element is returned.
-    ///
-    /// * `v`     - Vector to partition.
-    /// * `start` - Starting index.
-    /// * `end`   - Ending index + 1.
-    /// * `f`     - Predicate used for partitioning.
-    fn kth_element_by<F, T>(v: &mut Vec<T>, start: usize, k: usize, end: usize, f: F) -> Option<T>
-    where
-        F: Fn(&T, &T) -> Ordering,
-        T: Copy,
-    {
-        if start >= end || end > v.len() || k < start || k >= end {
-            return None;
-        }
-
-        let w = v[start..end].as_mut();
-        let i = *kth_by(w, k - start, |&x, &y| f(&x, &y));
-
-        Some(i)
-    }
-
-    /// Partition primitives using Surface Area Heuristic.
+    /// Partition primitives using Surface Area Heuristic. The primitives of
+    /// large nodes are sorted into the buckets in parallel.
     ///
     /// If the algorithm is able to partition primitives it will return the pivot
     /// index (mid) for interior node creation; otherwise None is returned to
     /// indicate leaf node creation.
     ///
-    /// * `primitive_info`    - Vector containing all primitive info.
-    /// * `start`             - Start index in primitive_info.
-    /// * `end`               - End index in primitive_info.
-    /// * `n_primitives`      - Number of primitives between start and end.
+    /// * `primitive_info`    - Primitive information for the primitives in the
+    ///                         node.
     /// * `dim`               - Axis used to partition primitives.
-    /// * `centroid_bounds`   - Bounding box of primtive centroids in primtive_info
-    ///                         from start to end.
+    /// * `centroid_bounds`   - Bounding box of primtive centroids in
+    ///                         `primitive_info`.
     /// * `bounds`            - Bound box of all primitives in BVH node.
     /// * `max_prims_in_node` - Maximum primitives allowed in node.
     fn split_sah(
-        primitive_info: &mut Vec<BVHPrimitiveInfo>,
-        start: usize,
-        end: usize,
-        n_primitives: usize,
+        primitive_info: &mut [BVHPrimitiveInfo],
         dim: Axis,
         centroid_bounds: &Bounds3f,
         bounds: &Bounds3f,
         max_prims_in_node: u8,
     ) -> Option<usize> {
         // Partition primitives using approximate SAH.
+        let n_primitives = primitive_info.len();
         if n_primitives <= 2 {
             // Partition primitives into equally-sized subsets.
-            Some(Self::split_equal_counts(primitive_info, start, end, dim))
+            Some(Self::split_equal_counts(primitive_info, dim))
         } else {
-            // Allocate BucketInfo for SAH partition buckets.
-            let mut buckets = [BucketInfo::default(); N_BUCKETS];
+            let bucket = |info: &BVHPrimitiveInfo| {
+                let b = (N_BUCKETS as Float * centroid_bounds.offset(&info.centroid)[dim]) as usize;
+                b.min(N_BUCKETS - 1)
+            };
 
             // Initialize BucketInfo for SAH partition buckets.
-            for info in primitive_info.iter().take(end).skip(start) {
-                let mut b =
-                    (N_BUCKETS as Float * centroid_bounds.offset(&info.centroid)[dim]) as usize;
-
-                if b == N_BUCKETS {
-                    b = N_BUCKETS - 1;
-                }
-
-                buckets[b].count += 1;
-                buckets[b].bounds = buckets[b].bounds.union(&info.bounds);
-            }
+            let buckets = fold_infos(
+                primitive_info,
+                [BucketInfo::default(); N_BUCKETS],
+                |mut buckets, info| {
+                    let b = bucket(info);
+                    buckets[b].count += 1;
+                    buckets[b].bounds = buckets[b].bounds.union(&info.bounds);
+                    buckets
+                },
+                |mut buckets, other| {
+                    for (bucket, other) in buckets.iter_mut().zip(other.iter()) {
+                        bucket.count += other.count;
+                        bucket.bounds = bucket.bounds.union(&other.bounds);
+                    }
+                    buckets
+                },
+            );
 
             // Compute costs for splitting after each bucket.
             let mut cost = [0.0_f32; N_BUCKETS - 1];
@@ -296,17 +234,10 @@ impl SAH {
             if n_primitives > max_prims_in_node as usize || min_cost < leaf_cost {
                 // Partition primitives at selected SAH bucket and return the
                 // pivot point as mid.
-                let infos = primitive_info[start..end].iter_mut();
-                let split = itertools::partition(infos, |pi| {
-                    let mut b =
-                        (N_BUCKETS as Float * centroid_bounds.offset(&pi.centroid)[dim]) as usize;
-                    if b == N_BUCKETS {
-                        b = N_BUCKETS - 1;
-                    }
-                    b <= min_cost_split_bucket
+                let mid = itertools::partition(primitive_info.iter_mut(), |pi| {
+                    bucket(pi) <= min_cost_split_bucket
                 });
-                let pmid = start + split;
-                Some(pmid)
+                Some(mid)
             } else {
                 // No split occurred. Indicate creation of leaf node.
                 None
@@ -314,3 +245,26 @@ impl SAH {
         }
     }
 }
+
+/// Folds the primitive information of a node. Large nodes are folded in
+/// chunks in parallel and the results of the chunks are combined.
+///
+/// * `primitive_info` - Primitive information for the primitives in the node.
+/// * `init`           - The initial value.
+/// * `fold`           - Folds a primitive into a value.
+/// * `combine`        - Combines the values of two chunks.
+fn fold_infos<T, F, C>(primitive_info: &[BVHPrimitiveInfo], init: T, fold: F, combine: C) -> T
+where
+    T: Copy + Send + Sync,
+    F: Fn(T, &BVHPrimitiveInfo) -> T + Sync,
+    C: Fn(T, T) -> T + Sync,
+{
+    if primitive_info.len() > PARALLEL_CHUNK_SIZE {
+        primitive_info
+            .par_chunks(PARALLEL_CHUNK_SIZE)
+            .map(|chunk| chunk.iter().fold(init, &fold))
+            .reduce(|| init, &combine)
+    } else {
+        primitive_info.iter().fold(init, fold)
+    }
+}
@@ -0,0 +1,99 @@
+//! BVH Wireframe Export
+
+use super::*;
+use crate::wireframe::*;
+use std::fs;
+
+impl BVHAccel {
+    /// Returns a Wavefront OBJ document with the bounding boxes of the BVH
+    /// nodes as line geometry. Nodes are grouped by depth and the bounds of
+    /// the primitives in the leaves are written to a separate group.
+    ///
+    /// * `max_depth`  - Deepest level of nodes to include. The root is at
+    ///                  depth 0.
+    /// * `primitives` - Include the bounds of the primitives in leaves within
+    ///                  `max_depth`.
+    pub fn wireframe_obj(&self, max_depth: usize, primitives: bool) -> String {
+        // Collect node bounds by depth and the primitive bounds of leaves.
+        let mut levels: Vec<Vec<Bounds3f>> = vec![];
+        let mut prim_bounds: Vec<Bounds3f> = vec![];
+        let mut stack: Vec<(usize, usize)> = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![(0, 0)]
+        };
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            if levels.len() <= depth {
+                levels.resize(depth + 1, vec![]);
+            }
+            levels[depth].push(node.bounds);
+
+            if node.n_primitives > 0 {
+                if primitives {
+                    let start = node.offset as usize;
+                    let end = start + node.n_primitives as usize;
+                    prim_bounds.extend(self.primitives[start..end].iter().map(|p| p.world_bound()));
+                }
+            } else if depth < max_depth {
+                stack.push((node.offset as usize, depth + 1));
+                stack.push((index + 1, depth + 1));
+            }
+        }
+
+        let header = format!("BVH with {} nodes", self.nodes.len());
+        wireframe_obj(&header, &levels, &prim_bounds)
+    }
+
+    /// Write the bounding boxes of the BVH nodes as line geometry to a
+    /// Wavefront OBJ file.
+    ///
+    /// * `path`       - Path to the OBJ file.
+    /// * `max_depth`  - Deepest level of nodes to include. The root is at
+    ///                  depth 0.
+    /// * `primitives` - Include the bounds of the primitives in leaves within
+    ///                  `max_depth`.
+    pub fn write_wireframe_obj(
+        &self,
+        path: &str,
+        max_depth: usize,
+        primitives: bool,
+    ) -> Result<(), String> {
+        fs::write(path, self.wireframe_obj(max_depth, primitives))
+            .map_err(|err| format!("Error writing '{}'. {}", path, err))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_primitives::*;
+
+    #[test]
+    fn bvh_wireframe_contains_node_and_primitive_bounds() {
+        let bvh = BVHAccel::new(&boxes(4), 1, SplitMethod::Middle);
+
+        // Every node is within the root and leaves hold one box each.
+        let root = Bounds3f::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(7.0, 1.0, 1.0));
+        let obj = bvh.wireframe_obj(usize::MAX, true);
+        assert!(obj.starts_with(&format!("# BVH with {} nodes", bvh.nodes.len())));
+        assert!(group_bounds(&obj, "depth_0") == vec![root]);
+        let depth_1 = group_bounds(&obj, "depth_1");
+        assert_eq!(depth_1.len(), 2);
+        assert!(depth_1.iter().all(|b| root.union(b) == root));
+        let mut prims = group_bounds(&obj, "primitives");
+        prims.sort_by(|a, b| a.p_min.x.partial_cmp(&b.p_min.x).unwrap());
+        let expected: Vec<Bounds3f> = boxes(4).iter().map(|p| p.world_bound()).collect();
+        assert!(prims == expected);
+
+        // Limiting the depth leaves out deeper nodes and their primitives.
+        let obj = bvh.wireframe_obj(0, true);
+        assert!(group_bounds(&obj, "depth_0") == vec![root]);
+        assert!(group_bounds(&obj, "depth_1").is_empty());
+        assert!(group_bounds(&obj, "primitives").is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Embree Bindings
+//!
+//! The subset of the Embree 3 API used by `EmbreeAccel`. Layouts follow
+//! `rtcore.h` built with the default `RTC_MAX_INSTANCE_LEVEL_COUNT` of 1.
+
+#![allow(non_camel_case_types, non_snake_case, dead_code)]
+
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+
+pub type RTCDevice = *mut c_void;
+pub type RTCScene = *mut c_void;
+pub type RTCGeometry = *mut c_void;
+
+/// Identifier Embree uses for "no geometry".
+pub const RTC_INVALID_GEOMETRY_ID: c_uint = c_uint::MAX;
+
+/// `RTCError::RTC_ERROR_NONE`.
+pub const RTC_ERROR_NONE: c_uint = 0;
+
+/// `RTCGeometryType::RTC_GEOMETRY_TYPE_TRIANGLE`.
+pub const RTC_GEOMETRY_TYPE_TRIANGLE: c_uint = 0;
+
+/// `RTCBufferType` values.
+pub const RTC_BUFFER_TYPE_INDEX: c_uint = 0;
+pub const RTC_BUFFER_TYPE_VERTEX: c_uint = 1;
+
+/// `RTCFormat` values.
+pub const RTC_FORMAT_UINT3: c_uint = 0x5003;
+pub const RTC_FORMAT_FLOAT3: c_uint = 0x9003;
+
+/// `RTCSceneFlags` values.
+pub const RTC_SCENE_FLAG_ROBUST: c_uint = 1 << 2;
+pub const RTC_SCENE_FLAG_CONTEXT_FILTER_FUNCTION: c_uint = 1 << 3;
+
+/// `RTCIntersectContextFlags::RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT`.
+pub const RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT: c_uint = 0;
+
+/// Arguments of filter callbacks.
+#[repr(C)]
+pub struct RTCFilterFunctionNArguments {
+    pub valid: *mut c_int,
+    pub geometryUserPtr: *mut c_void,
+    pub context: *const RTCIntersectContext,
+    pub ray: *mut c_void,
+    pub hit: *mut c_void,
+    pub N: c_uint,
+}
+
+pub type RTCFilterFunctionN =
+    Option<unsafe extern "C" fn(args: *const RTCFilterFunctionNArguments)>;
+
+/// Per query context passed to the filter callbacks.
+#[repr(C)]
+pub struct RTCIntersectContext {
+    pub flags: c_uint,
+    pub filter: RTCFilterFunctionN,
+    pub instID: [c_uint; 1],
+}
+
+impl Default for RTCIntersectContext {
+    /// Returns the context `rtcInitIntersectContext()` initializes.
+    fn default() -> Self {
+        Self {
+            flags: RTC_INTERSECT_CONTEXT_FLAG_INCOHERENT,
+            filter: None,
+            instID: [RTC_INVALID_GEOMETRY_ID],
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Default)]
+pub struct RTCRay {
+    pub org_x: f32,
+    pub org_y: f32,
+    pub org_z: f32,
+    pub tnear: f32,
+    pub dir_x: f32,
+    pub dir_y: f32,
+    pub dir_z: f32,
+    pub time: f32,
+    pub tfar: f32,
+    pub mask: c_uint,
+    pub id: c_uint,
+    pub flags: c_uint,
+}
+
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Default)]
+pub struct RTCHit {
+    pub Ng_x: f32,
+    pub Ng_y: f32,
+    pub Ng_z: f32,
+    pub u: f32,
+    pub v: f32,
+    pub primID: c_uint,
+    pub geomID: c_uint,
+    pub instID: [c_uint; 1],
+}
+
+#[repr(C, align(16))]
+#[derive(Copy, Clone, Default)]
+pub struct RTCRayHit {
+    pub ray: RTCRay,
+    pub hit: RTCHit,
+}
+
+#[link(name = "embree3")]
+extern "C" {
+    pub fn rtcNewDevice(config: *const c_char) -> RTCDevice;
+    pub fn rtcReleaseDevice(device: RTCDevice);
+    pub fn rtcGetDeviceError(device: RTCDevice) -> c_uint;
+
+    pub fn rtcNewScene(device: RTCDevice) -> RTCScene;
+    pub fn rtcSetSceneFlags(scene: RTCScene, flags: c_uint);
+    pub fn rtcCommitScene(scene: RTCScene);
+    pub fn rtcReleaseScene(scene: RTCScene);
+
+    pub fn rtcNewGeometry(device: RTCDevice, geometry_type: c_uint) -> RTCGeometry;
+    pub fn rtcSetNewGeometryBuffer(
+        geometry: RTCGeometry,
+        buffer_type: c_uint,
+        slot: c_uint,
+        format: c_uint,
+        byte_stride: usize,
+        item_count: usize,
+    ) -> *mut c_void;
+    pub fn rtcCommitGeometry(geometry: RTCGeometry);
+    pub fn rtcAttachGeometry(scene: RTCScene, geometry: RTCGeometry) -> c_uint;
+    pub fn rtcReleaseGeometry(geometry: RTCGeometry);
+
+    pub fn rtcIntersect1(
+        scene: RTCScene,
+        context: *mut RTCIntersectContext,
+        rayhit: *mut RTCRayHit,
+    );
+    pub fn rtcOccluded1(scene: RTCScene, context: *mut RTCIntersectContext, ray: *mut RTCRay);
+}
@@ -0,0 +1,587 @@
+//! Embree Accelerator.
+
+use crate::BVHAccel;
+use core::geometry::*;
+use core::light::*;
+use core::material::*;
+use core::paramset::*;
+use core::primitive::*;
+use std::mem::size_of;
+use std::ptr;
+use std::sync::Arc;
+
+mod ffi;
+
+use ffi::*;
+
+/// Intersects triangles with Intel Embree. Primitives Embree can't represent
+/// (other shapes, triangles with alpha masks, instances) are kept in a native
+/// BVH that is queried after Embree.
+///
+/// Embree only finds candidate triangles. A candidate counts as a hit if the
+/// native triangle test also hits it, so closest hit and occlusion queries
+/// agree with each other and with `BVHAccel` where the two triangle tests
+/// differ in precision.
+pub struct EmbreeAccel {
+    /// The Embree device.
+    device: RTCDevice,
+
+    /// The Embree scene holding the triangles.
+    scene: RTCScene,
+
+    /// The triangles in the Embree scene indexed by Embree primitive ID.
+    triangles: Vec<ArcPrimitive>,
+
+    /// The remaining primitives.
+    others: Option<BVHAccel>,
+
+    /// Bounds of all primitives.
+    bounds: Bounds3f,
+}
+
+// A committed Embree scene can be queried from any thread.
+unsafe impl Send for EmbreeAccel {}
+unsafe impl Sync for EmbreeAccel {}
+
+/// Context passed to Embree queries. Embree only reads the leading
+/// `RTCIntersectContext`; `filter_hit()` casts the pointer back to reach the
+/// ray and the primitive filter.
+#[repr(C)]
+struct FilterContext<'a> {
+    /// The Embree context.
+    context: RTCIntersectContext,
+
+    /// The triangles in the Embree scene.
+    triangles: &'a [ArcPrimitive],
+
+    /// The ray of the query.
+    ray: &'a Ray,
+
+    /// Selects the triangles to consider.
+    filter: Option<PrimitiveFilter<'a>>,
+}
+
+impl<'a> FilterContext<'a> {
+    /// Returns a new context for a query.
+    ///
+    /// * `triangles` - The triangles in the Embree scene.
+    /// * `ray`       - The ray of the query.
+    /// * `filter`    - Selects the triangles to consider.
+    fn new(
+        triangles: &'a [ArcPrimitive],
+        ray: &'a Ray,
+        filter: Option<PrimitiveFilter<'a>>,
+    ) -> Self {
+        Self {
+            context: RTCIntersectContext {
+                filter: Some(filter_hit),
+                ..RTCIntersectContext::default()
+            },
+            triangles,
+            ray,
+            filter,
+        }
+    }
+
+    /// Returns whether a candidate triangle found by Embree is hit.
+    ///
+    /// * `prim_id` - Embree primitive ID of the triangle.
+    fn accepts(&self, prim_id: u32) -> bool {
+        let triangle = self.triangles[prim_id as usize].as_ref();
+        self.filter.is_none_or(|filter| filter(triangle)) && triangle.intersect_p(self.ray)
+    }
+}
+
+/// Rejects candidate hits on triangles the query's primitive filter doesn't
+/// accept or the native triangle test misses.
+///
+/// * `args` - The callback arguments for a single ray.
+unsafe extern "C" fn filter_hit(args: *const RTCFilterFunctionNArguments) {
+    let args = &*args;
+    let context = &*(args.context as *const FilterContext);
+
+    // Queries trace single rays so the hit holds one `RTCHit`.
+    let hit = &*(args.hit as *const RTCHit);
+    if !context.accepts(hit.primID) {
+        *args.valid = 0;
+    }
+}
+
+/// Returns an Embree ray.
+///
+/// * `r` - The ray.
+fn rtc_ray(r: &Ray) -> RTCRay {
+    RTCRay {
+        org_x: r.o.x,
+        org_y: r.o.y,
+        org_z: r.o.z,
+        tnear: 0.0,
+        dir_x: r.d.x,
+        dir_y: r.d.y,
+        dir_z: r.d.z,
+        time: 0.0,
+        tfar: r.t_max,
+        mask: u32::MAX,
+        id: 0,
+        flags: 0,
+    }
+}
+
+impl EmbreeAccel {
+    /// Create a new Embree accelerator.
+    ///
+    /// * `params`     - Parameters of the native BVH for primitives Embree
+    ///                  can't represent.
+    /// * `primitives` - The primitives.
+    pub fn new(params: &ParamSet, primitives: &[ArcPrimitive]) -> Result<Self, String> {
+        let mut triangles = vec![];
+        let mut vertices: Vec<Point3f> = vec![];
+        let mut others = vec![];
+        for p in primitives.iter() {
+            match p.get_shape().and_then(|shape| shape.triangle_vertices()) {
+                Some(v) => {
+                    triangles.push(Arc::clone(p));
+                    vertices.extend_from_slice(&v);
+                }
+                None => others.push(Arc::clone(p)),
+            }
+        }
+        let bounds = primitives
+            .iter()
+            .fold(Bounds3f::empty(), |b, p| b.union(&p.world_bound()));
+
+        let device = unsafe { rtcNewDevice(ptr::null()) };
+        if device.is_null() {
+            let error = unsafe { rtcGetDeviceError(ptr::null_mut()) };
+            return Err(format!("Unable to create Embree device. Error {}.", error));
+        }
+
+        // Dropping `accel` releases the scene and device on errors.
+        let mut accel = Self {
+            device,
+            scene: ptr::null_mut(),
+            triangles,
+            others: None,
+            bounds,
+        };
+        unsafe {
+            accel.scene = rtcNewScene(device);
+            rtcSetSceneFlags(
+                accel.scene,
+                RTC_SCENE_FLAG_ROBUST | RTC_SCENE_FLAG_CONTEXT_FILTER_FUNCTION,
+            );
+            if !accel.triangles.is_empty() {
+                accel.add_triangles(&vertices)?;
+            }
+            rtcCommitScene(accel.scene);
+        }
+        accel.check_error()?;
+
+        if !others.is_empty() {
+            accel.others = Some(BVHAccel::from((params, &others[..])));
+        }
+        Ok(accel)
+    }
+
+    /// Adds a triangle geometry with unshared vertices to the scene.
+    ///
+    /// * `vertices` - Three vertices per triangle in the order of
+    ///                `self.triangles`.
+    unsafe fn add_triangles(&self, vertices: &[Point3f]) -> Result<(), String> {
+        let n_triangles = self.triangles.len();
+        let geometry = rtcNewGeometry(self.device, RTC_GEOMETRY_TYPE_TRIANGLE);
+        let vertex_buffer = rtcSetNewGeometryBuffer(
+            geometry,
+            RTC_BUFFER_TYPE_VERTEX,
+            0,
+            RTC_FORMAT_FLOAT3,
+            3 * size_of::<f32>(),
+            vertices.len(),
+        ) as *mut f32;
+        let index_buffer = rtcSetNewGeometryBuffer(
+            geometry,
+            RTC_BUFFER_TYPE_INDEX,
+            0,
+            RTC_FORMAT_UINT3,
+            3 * size_of::<u32>(),
+            n_triangles,
+        ) as *mut u32;
+        if vertex_buffer.is_null() || index_buffer.is_null() {
+            rtcReleaseGeometry(geometry);
+            self.check_error()?;
+            return Err(String::from("Unable to allocate Embree geometry buffers."));
+        }
+
+        let vertex_buffer = std::slice::from_raw_parts_mut(vertex_buffer, 3 * vertices.len());
+        for (v, p) in vertex_buffer.chunks_exact_mut(3).zip(vertices.iter()) {
+            v.copy_from_slice(&[p.x, p.y, p.z]);
+        }
+        let index_buffer = std::slice::from_raw_parts_mut(index_buffer, 3 * n_triangles);
+        for (i, index) in index_buffer.iter_mut().enumerate() {
+            *index = i as u32;
+        }
+
+        rtcCommitGeometry(geometry);
+        rtcAttachGeometry(self.scene, geometry);
+        rtcReleaseGeometry(geometry);
+        Ok(())
+    }
+
+    /// Returns an error if an Embree call failed.
+    fn check_error(&self) -> Result<(), String> {
+        match unsafe { rtcGetDeviceError(self.device) } {
+            RTC_ERROR_NONE => Ok(()),
+            error => Err(format!("Unable to build Embree scene. Error {}.", error)),
+        }
+    }
+
+    /// Returns the closest intersection with a primitive accepted by the
+    /// filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_with<'a>(
+        &'a self,
+        r: &mut Ray,
+        filter: Option<PrimitiveFilter>,
+    ) -> Option<SurfaceInteraction<'a>> {
+        let mut isect = None;
+        if !self.triangles.is_empty() {
+            let mut rayhit = RTCRayHit {
+                ray: rtc_ray(r),
+                hit: RTCHit {
+                    geomID: RTC_INVALID_GEOMETRY_ID,
+                    ..RTCHit::default()
+                },
+            };
+            let mut context = FilterContext::new(&self.triangles, r, filter);
+            unsafe { rtcIntersect1(self.scene, &mut context.context, &mut rayhit) };
+
+            // Embree found the closest triangle the native test hits;
+            // intersect it again to get the surface interaction.
+            if rayhit.hit.geomID != RTC_INVALID_GEOMETRY_ID {
+                isect = self.triangles[rayhit.hit.primID as usize].intersect(r);
+            }
+        }
+
+        // Anything the native BVH hits is closer than the triangle.
+        if let Some(others) = self.others.as_ref() {
+            let other_isect = match filter {
+                Some(filter) => others.intersect_filtered(r, filter),
+                None => others.intersect(r),
+            };
+            if other_isect.is_some() {
+                isect = other_isect;
+            }
+        }
+        isect
+    }
+
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_with(&self, r: &Ray, filter: Option<PrimitiveFilter>) -> bool {
+        if !self.triangles.is_empty() {
+            let mut ray = rtc_ray(r);
+            let mut context = FilterContext::new(&self.triangles, r, filter);
+            unsafe { rtcOccluded1(self.scene, &mut context.context, &mut ray) };
+
+            // Embree sets `tfar` to -∞ for occluded rays.
+            if ray.tfar < 0.0 {
+                return true;
+            }
+        }
+
+        match (self.others.as_ref(), filter) {
+            (Some(others), Some(filter)) => others.intersect_p_filtered(r, filter),
+            (Some(others), None) => others.intersect_p(r),
+            (None, _) => false,
+        }
+    }
+}
+
+impl Drop for EmbreeAccel {
+    /// Releases the Embree scene and device.
+    fn drop(&mut self) {
+        unsafe {
+            if !self.scene.is_null() {
+                rtcReleaseScene(self.scene);
+            }
+            rtcReleaseDevice(self.device);
+        }
+    }
+}
+
+impl Aggregate for EmbreeAccel {}
+
+impl Primitive for EmbreeAccel {
+    /// Returns a bounding box in the world space.
+    fn world_bound(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// Returns geometric details if a ray intersects the primitive and updates
+    /// the t_max parameter of the ray. If there is no intersection, `None` is
+    /// returned.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, None)
+    }
+
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, Some(filter))
+    }
+
+    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect_p(&self, r: &Ray) -> bool {
+        self.intersect_p_with(r, None)
+    }
+
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        self.intersect_p_with(r, Some(filter))
+    }
+
+    /// Returns a reference to the AreaLight that describes the primitive’s
+    /// emission distribution, if the primitive is itself a light source.
+    /// If the primitive is not emissive, this method should return `None`.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// get_area_light() on the primitive from the ray-primitive intersection.
+    fn get_area_light(&self) -> Option<ArcAreaLight> {
+        error!(
+            "EmbreeAccel::get_area_light() shouldn't be called; \
+            should've gone to GeometricPrimitive."
+        );
+        None
+    }
+
+    /// Returns a reference to the material instance assigned to the primitive.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// get_material() on the primitive from the ray-primitive intersection.
+    fn get_material(&self) -> Option<ArcMaterial> {
+        error!(
+            "EmbreeAccel::get_material() shouldn't be called; \
+            should've gone to GeometricPrimitive."
+        );
+        None
+    }
+
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// compute_scattering_functions() on the primitive from the ray-primitive
+    /// intersection.
+    ///
+    /// * `_si`                   - The surface interaction at the intersection.
+    /// * `_mode`                 - Transport mode.
+    /// * `_allow_multiple_lobes` - Allow multiple lobes.
+    fn compute_scattering_functions(
+        &self,
+        _si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        error!(
+            "EmbreeAccel::compute_scattering_functions() shouldn't be \
+            called; should've gone to GeometricPrimitive."
+        );
+    }
+
+    /// Returns the memory used by the primitive references, the native BVH
+    /// and the vertex and index buffers in bytes. Embree's own hierarchy is
+    /// not included.
+    fn memory(&self) -> usize {
+        let n_triangles = self.triangles.capacity();
+        size_of::<Self>()
+            + n_triangles
+                * (size_of::<ArcPrimitive>() + 9 * size_of::<f32>() + 3 * size_of::<u32>())
+            + self.others.as_ref().map_or(0, |others| others.memory())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SplitMethod;
+    use core::medium::*;
+    use core::pbrt::*;
+    use core::primitives::*;
+    use core::rng::*;
+    use shapes::*;
+    use std::mem::align_of;
+
+    /// A material that doesn't scatter light.
+    struct NoMaterial;
+
+    impl Material for NoMaterial {
+        fn compute_scattering_functions(
+            &self,
+            _si: &mut SurfaceInteraction,
+            _mode: TransportMode,
+            _allow_multiple_lobes: bool,
+        ) {
+        }
+    }
+
+    /// Returns a point with random coordinates in [-1, 1].
+    ///
+    /// * `rng` - The random number generator.
+    fn random_point(rng: &mut RNG) -> Point3f {
+        let mut u = || 2.0 * UniformRandom::<Float>::uniform(rng) - 1.0;
+        Point3f::new(u(), u(), u())
+    }
+
+    /// Returns small random triangles and a sphere, which Embree can't
+    /// represent, in the cube [-1, 1]^3.
+    ///
+    /// * `rng` - The random number generator.
+    fn random_scene(rng: &mut RNG) -> Vec<ArcPrimitive> {
+        let identity = Arc::new(Transform::default());
+        let mut p = vec![];
+        for _ in 0..200 {
+            let c = random_point(rng);
+            p.push(c);
+            p.push(c + Vector3f::from(random_point(rng)) * 0.3);
+            p.push(c + Vector3f::from(random_point(rng)) * 0.3);
+        }
+        let mut shapes = TriangleMesh::create(
+            Arc::clone(&identity),
+            Arc::clone(&identity),
+            false,
+            (0..p.len()).collect(),
+            p,
+            vec![],
+            vec![],
+            vec![],
+            None,
+            None,
+            vec![],
+        );
+        shapes.push(Arc::new(Sphere::new(
+            Arc::clone(&identity),
+            identity,
+            false,
+            0.25,
+            -0.25,
+            0.25,
+            360.0,
+        )));
+
+        let material: ArcMaterial = Arc::new(NoMaterial);
+        shapes
+            .into_iter()
+            .map(|shape| {
+                Arc::new(GeometricPrimitive::new(
+                    shape,
+                    Arc::clone(&material),
+                    None,
+                    MediumInterface::vacuum(),
+                    None,
+                )) as ArcPrimitive
+            })
+            .collect()
+    }
+
+    /// Returns rays from outside the scene towards random points in it.
+    ///
+    /// * `rng` - The random number generator.
+    fn random_rays(rng: &mut RNG) -> Vec<Ray> {
+        (0..2000)
+            .map(|_| {
+                let o = Point3f::from(Vector3f::from(random_point(rng)).normalize() * 3.0);
+                let d = (random_point(rng) - o).normalize();
+                Ray::new(o, d, INFINITY, 0.0, None)
+            })
+            .collect()
+    }
+
+    /// Returns the primitive hit by a ray and the ray parameter of the hit.
+    ///
+    /// * `accel`  - The accelerator.
+    /// * `ray`    - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn hit(
+        accel: &dyn Primitive,
+        ray: &Ray,
+        filter: Option<PrimitiveFilter>,
+    ) -> Option<(*const u8, Float)> {
+        let mut r = ray.clone();
+        let isect = match filter {
+            Some(filter) => accel.intersect_filtered(&mut r, filter),
+            None => accel.intersect(&mut r),
+        };
+        isect.map(|si| {
+            let primitive = si.primitive.unwrap() as *const dyn Primitive as *const u8;
+            (primitive, r.t_max)
+        })
+    }
+
+    #[test]
+    fn bindings_match_rtcore_layouts() {
+        assert_eq!((size_of::<RTCRay>(), align_of::<RTCRay>()), (48, 16));
+        assert_eq!((size_of::<RTCHit>(), align_of::<RTCHit>()), (32, 16));
+        assert_eq!(size_of::<RTCRayHit>(), 80);
+        assert_eq!(size_of::<RTCIntersectContext>(), 24);
+        assert_eq!(size_of::<RTCFilterFunctionNArguments>(), 48);
+    }
+
+    #[test]
+    fn matches_bvh_on_random_scene() {
+        let mut rng = RNG::new(7);
+        let primitives = random_scene(&mut rng);
+        let rays = random_rays(&mut rng);
+        let bvh = BVHAccel::new(&primitives, 4, SplitMethod::SAH);
+        let embree = EmbreeAccel::new(&ParamSet::new(), &primitives).unwrap();
+        assert_eq!(embree.triangles.len(), 200);
+        assert!(embree.others.is_some());
+
+        let filter = |p: &dyn Primitive| p.world_bound().p_min.x < 0.0;
+        let mut n_hits = 0;
+        for ray in rays.iter() {
+            let expected = hit(&bvh, ray, None);
+            assert_eq!(hit(&embree, ray, None), expected);
+            assert_eq!(
+                hit(&embree, ray, Some(&filter)),
+                hit(&bvh, ray, Some(&filter))
+            );
+            n_hits += expected.is_some() as usize;
+
+            // Shadow rays ending halfway to the hit and beyond it.
+            let t = expected.map_or(3.0, |(_, t)| t);
+            for t_max in [0.5 * t, 2.0 * t].iter() {
+                let r = Ray::new(ray.o, ray.d, *t_max, 0.0, None);
+                assert_eq!(embree.intersect_p(&r), bvh.intersect_p(&r));
+                assert_eq!(
+                    embree.intersect_p_filtered(&r, &filter),
+                    bvh.intersect_p_filtered(&r, &filter)
+                );
+            }
+        }
+        assert!(n_hits > rays.len() / 4, "{}", n_hits);
+    }
+}
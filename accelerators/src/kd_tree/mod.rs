@@ -6,9 +6,13 @@ use core::material::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::primitive::*;
-use std::sync::Arc;
+use core::profiler::*;
+use core::stats::*;
+use std::time::Instant;
 
 mod common;
+mod wireframe;
+
 use common::*;
 
 /// KD Tree Accelerator.
@@ -64,6 +68,8 @@ impl KDTreeAccel {
         max_depth: i32,
     ) -> Self {
         // Build kd-tree for accelerator.
+        let _p = ProfilePhase::new(Prof::AccelConstruction);
+        let start = Instant::now();
         let count = primitives.len();
         let next_free_node = 0;
         let n_alloced_nodes = 0;
@@ -85,12 +91,12 @@ impl KDTreeAccel {
 
         // Allocate working memory for kd-tree construction.
         let mut edges: [Vec<BoundEdge>; 3] = [
-            Vec::<BoundEdge>::with_capacity(2 * count),
-            Vec::<BoundEdge>::with_capacity(2 * count),
-            Vec::<BoundEdge>::with_capacity(2 * count),
+            vec![BoundEdge::default(); 2 * count],
+            vec![BoundEdge::default(); 2 * count],
+            vec![BoundEdge::default(); 2 * count],
         ];
 
-        let mut prims0 = Vec::<u32>::with_capacity(count);
+        let mut prims0: Vec<u32> = vec![0_u32; count];
         let mut prims1: Vec<u32> = vec![0_u32; (max_depth + 1) as usize * count];
 
         // Initialize prim_nums for kd-tree construction.
@@ -123,6 +129,8 @@ impl KDTreeAccel {
             0,
         );
 
+        STATS.record_build("kdtree", count, start.elapsed());
+
         kd_tree
     }
 
@@ -157,6 +165,7 @@ impl KDTreeAccel {
                 n_primitives,
                 &mut self.primitive_indices,
             );
+            return;
         }
 
         // Initialize interior node and continue recursion.
@@ -183,7 +192,7 @@ impl KDTreeAccel {
             }
 
             // Sort `edges` for `axis`.
-            edges[axis].sort_by(|e0, e1| {
+            edges[axis][..2 * n_primitives as usize].sort_by(|e0, e1| {
                 if e0.t == e1.t {
                     e0.edge_type.partial_cmp(&e1.edge_type).unwrap()
                 } else {
@@ -290,12 +299,17 @@ impl KDTreeAccel {
             bounds0.p_max[best_axis] = t_split;
             bounds1.p_min[best_axis] = t_split;
 
+            // The children's primitives are copied since the buffers they were
+            // classified into are reused by the recursion.
+            let prims_below = prims0[..n0 as usize].to_vec();
+            let prims_above = prims1[..n1 as usize].to_vec();
+
             let n = n_primitives as usize;
             self.build_tree(
                 node_num + 1,
                 &bounds0,
                 all_prim_bounds,
-                prim_nums,
+                &prims_below,
                 n0,
                 depth - 1,
                 edges,
@@ -311,7 +325,7 @@ impl KDTreeAccel {
                 above_child,
                 &bounds1,
                 all_prim_bounds,
-                prim_nums,
+                &prims_above,
                 n1,
                 depth - 1,
                 edges,
@@ -319,25 +333,20 @@ impl KDTreeAccel {
                 &mut prims1[n..],
                 new_bad_refines,
             );
+            return;
         }
     }
-}
-
-/// Tag `KDTreeAccel` as an `Aggregate`.
-impl Aggregate for KDTreeAccel {}
-
-impl Primitive for KDTreeAccel {
-    /// Returns a bounding box in the world space.
-    fn world_bound(&self) -> Bounds3f {
-        self.bounds
-    }
 
-    /// Returns geometric details if a ray intersects the primitive and updates
-    /// the t_max parameter of the ray. If there is no intersection, `None` is
-    /// returned.
+    /// Returns the closest intersection found by intersecting the primitives
+    /// in the leaves the ray passes through with the given function.
     ///
-    /// * `r`                  - The ray.
-    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+    /// * `r`         - The ray.
+    /// * `intersect` - Intersects a primitive with the ray.
+    fn intersect_with<'a, F>(&'a self, r: &mut Ray, intersect: F) -> Option<SurfaceInteraction<'a>>
+    where
+        F: Fn(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
+        let _p = ProfilePhase::new(Prof::AccelIntersect);
         let mut si: Option<SurfaceInteraction> = None;
 
         // Compute initial parametric range of ray inside kd-tree extent.
@@ -400,13 +409,17 @@ impl Primitive for KDTreeAccel {
                     if n_primitives == 1 {
                         // Check one primitive inside leaf node.
                         let one_primitive = node.one_primitive() as usize;
-                        si = self.primitives[one_primitive].intersect(r);
+                        if let Some(hit) = intersect(&*self.primitives[one_primitive], r) {
+                            si = Some(hit);
+                        }
                     } else {
                         for i in 0..n_primitives as usize {
                             // Check one primitive inside leaf node.
                             let offset = node.primitive_indices_offset() as usize;
                             let index = self.primitive_indices[offset + i] as usize;
-                            si = self.primitives[index].intersect(r);
+                            if let Some(hit) = intersect(&*self.primitives[index], r) {
+                                si = Some(hit);
+                            }
                         }
                     }
 
@@ -427,10 +440,17 @@ impl Primitive for KDTreeAccel {
         si
     }
 
-    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    /// Returns whether any primitive in the leaves the ray passes through is
+    /// intersected by the given function.
     ///
-    /// * `r`                  - The ray.
-    fn intersect_p(&self, r: &Ray) -> bool {
+    /// * `r`           - The ray.
+    /// * `intersect_p` - Tests a primitive for intersection with the ray.
+    fn intersect_p_with<F>(&self, r: &Ray, intersect_p: F) -> bool
+    where
+        F: Fn(&dyn Primitive, &Ray) -> bool,
+    {
+        let _p = ProfilePhase::new(Prof::AccelIntersectP);
+
         // Compute initial parametric range of ray inside kd-tree extent.
         if let Some((mut t_min, mut t_max)) = self.bounds.intersect_p(r) {
             // Prepaer to traverse kd-tree for ray.
@@ -489,20 +509,18 @@ impl Primitive for KDTreeAccel {
                     let n_primitives = node.n_primitives();
                     if n_primitives == 1 {
                         let one_primitive = node.one_primitive() as usize;
-                        let p = Arc::clone(&self.primitives[one_primitive]);
 
                         // Check one primitive inside leaf node.
-                        if p.intersect_p(r) {
+                        if intersect_p(&*self.primitives[one_primitive], r) {
                             return true;
                         }
                     } else {
                         for i in 0..n_primitives as usize {
                             let offset = node.primitive_indices_offset() as usize;
                             let index = self.primitive_indices[offset + i] as usize;
-                            let p = Arc::clone(&self.primitives[index]);
 
                             // Check one primitive inside leaf node.
-                            if p.intersect_p(r) {
+                            if intersect_p(&*self.primitives[index], r) {
                                 return true;
                             }
                         }
@@ -524,6 +542,54 @@ impl Primitive for KDTreeAccel {
 
         false
     }
+}
+
+/// Tag `KDTreeAccel` as an `Aggregate`.
+impl Aggregate for KDTreeAccel {}
+
+impl Primitive for KDTreeAccel {
+    /// Returns a bounding box in the world space.
+    fn world_bound(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// Returns geometric details if a ray intersects the primitive and updates
+    /// the t_max parameter of the ray. If there is no intersection, `None` is
+    /// returned.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, r| p.intersect(r))
+    }
+
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, r| p.intersect_filtered(r, filter))
+    }
+
+    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect_p(&self, r: &Ray) -> bool {
+        self.intersect_p_with(r, |p, r| p.intersect_p(r))
+    }
+
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        self.intersect_p_with(r, |p, r| p.intersect_p_filtered(r, filter))
+    }
 
     /// Returns a reference to the AreaLight that describes the primitive’s
     /// emission distribution, if the primitive is itself a light source.
@@ -576,6 +642,15 @@ impl Primitive for KDTreeAccel {
             called; should've gone to GeometricPrimitive."
         );
     }
+
+    /// Returns the memory used by the nodes and primitive references in
+    /// bytes.
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.nodes.capacity() * std::mem::size_of::<KdAccelNode>()
+            + self.primitive_indices.capacity() * std::mem::size_of::<u32>()
+            + self.primitives.capacity() * std::mem::size_of::<ArcPrimitive>()
+    }
 }
 
 impl From<(&ParamSet, &[ArcPrimitive])> for KDTreeAccel {
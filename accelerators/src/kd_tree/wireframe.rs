@@ -0,0 +1,114 @@
+//! KD Tree Wireframe Export
+
+use super::*;
+use crate::wireframe::*;
+use std::fs;
+
+impl KDTreeAccel {
+    /// Returns a Wavefront OBJ document with the regions of the kd-tree
+    /// nodes as line geometry. Nodes are grouped by depth and the bounds of
+    /// the primitives in the leaves are written to a separate group.
+    ///
+    /// * `max_depth`  - Deepest level of nodes to include. The root is at
+    ///                  depth 0.
+    /// * `primitives` - Include the bounds of the primitives in leaves within
+    ///                  `max_depth`.
+    pub fn wireframe_obj(&self, max_depth: usize, primitives: bool) -> String {
+        // Collect node regions by depth by splitting the tree's bounds and
+        // the primitive bounds of leaves.
+        let mut levels: Vec<Vec<Bounds3f>> = vec![];
+        let mut prim_bounds: Vec<Bounds3f> = vec![];
+        let mut stack: Vec<(usize, Bounds3f, usize)> = if self.next_free_node == 0 {
+            vec![]
+        } else {
+            vec![(0, self.bounds, 0)]
+        };
+        while let Some((index, bounds, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            if levels.len() <= depth {
+                levels.resize(depth + 1, vec![]);
+            }
+            levels[depth].push(bounds);
+
+            if node.is_leaf() {
+                if primitives {
+                    let n = node.n_primitives() as usize;
+                    let prim_nums = if n == 1 {
+                        vec![node.one_primitive() as usize]
+                    } else {
+                        let start = node.primitive_indices_offset() as usize;
+                        self.primitive_indices[start..start + n]
+                            .iter()
+                            .map(|i| *i as usize)
+                            .collect()
+                    };
+                    prim_bounds.extend(prim_nums.iter().map(|i| self.primitives[*i].world_bound()));
+                }
+            } else if depth < max_depth {
+                let axis = node.split_axis() as usize;
+                let mut below = bounds;
+                let mut above = bounds;
+                below.p_max[axis] = node.split_pos();
+                above.p_min[axis] = node.split_pos();
+                stack.push((node.above_child() as usize, above, depth + 1));
+                stack.push((index + 1, below, depth + 1));
+            }
+        }
+
+        let header = format!("kd-tree with {} nodes", self.next_free_node);
+        wireframe_obj(&header, &levels, &prim_bounds)
+    }
+
+    /// Write the regions of the kd-tree nodes as line geometry to a Wavefront
+    /// OBJ file.
+    ///
+    /// * `path`       - Path to the OBJ file.
+    /// * `max_depth`  - Deepest level of nodes to include. The root is at
+    ///                  depth 0.
+    /// * `primitives` - Include the bounds of the primitives in leaves within
+    ///                  `max_depth`.
+    pub fn write_wireframe_obj(
+        &self,
+        path: &str,
+        max_depth: usize,
+        primitives: bool,
+    ) -> Result<(), String> {
+        fs::write(path, self.wireframe_obj(max_depth, primitives))
+            .map_err(|err| format!("Error writing '{}'. {}", path, err))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_primitives::*;
+
+    #[test]
+    fn kd_tree_wireframe_splits_bounds_of_parent_nodes() {
+        let kd_tree = KDTreeAccel::new(&boxes(4), 80, 1, 0.5, 1, -1);
+
+        // The children of a node split its region in two.
+        let root = Bounds3f::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(7.0, 1.0, 1.0));
+        let obj = kd_tree.wireframe_obj(usize::MAX, true);
+        assert!(obj.starts_with(&format!("# kd-tree with {} nodes", kd_tree.next_free_node)));
+        assert!(group_bounds(&obj, "depth_0") == vec![root]);
+        let depth_1 = group_bounds(&obj, "depth_1");
+        assert_eq!(depth_1.len(), 2);
+        assert!(depth_1[0].union(&depth_1[1]) == root);
+        assert_eq!(depth_1[0].p_max.x, depth_1[1].p_min.x);
+        let mut prims = group_bounds(&obj, "primitives");
+        prims.sort_by(|a, b| a.p_min.x.partial_cmp(&b.p_min.x).unwrap());
+        let expected: Vec<Bounds3f> = boxes(4).iter().map(|p| p.world_bound()).collect();
+        assert!(prims == expected);
+
+        // Limiting the depth leaves out deeper nodes and their primitives.
+        let obj = kd_tree.wireframe_obj(0, true);
+        assert!(group_bounds(&obj, "depth_0") == vec![root]);
+        assert!(group_bounds(&obj, "depth_1").is_empty());
+        assert!(group_bounds(&obj, "primitives").is_empty());
+    }
+}
@@ -4,8 +4,15 @@
 extern crate log;
 
 mod bvh;
+#[cfg(feature = "embree")]
+mod embree;
 mod kd_tree;
+#[cfg(test)]
+mod test_primitives;
+mod wireframe;
 
 // Re-export
 pub use bvh::*;
+#[cfg(feature = "embree")]
+pub use embree::*;
 pub use kd_tree::*;
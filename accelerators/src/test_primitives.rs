@@ -0,0 +1,73 @@
+//! Test Primitives
+//!
+//! Primitives for testing the construction and traversal of accelerators
+//! without shapes.
+
+use core::geometry::*;
+use core::light::*;
+use core::material::*;
+use core::pbrt::*;
+use core::primitive::*;
+use std::sync::Arc;
+
+/// A box that only reports its bounds. Intersection tests always miss so
+/// traversal visits every node the ray's bounds test passes.
+pub struct BoxPrimitive {
+    /// The bounds.
+    pub bounds: Bounds3f,
+}
+
+impl Primitive for BoxPrimitive {
+    fn world_bound(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    fn intersect(&self, _r: &mut Ray) -> Option<SurfaceInteraction> {
+        None
+    }
+
+    fn intersect_filtered(
+        &self,
+        _r: &mut Ray,
+        _filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        None
+    }
+
+    fn intersect_p(&self, _r: &Ray) -> bool {
+        false
+    }
+
+    fn intersect_p_filtered(&self, _r: &Ray, _filter: PrimitiveFilter) -> bool {
+        false
+    }
+
+    fn get_area_light(&self) -> Option<ArcAreaLight> {
+        None
+    }
+
+    fn get_material(&self) -> Option<ArcMaterial> {
+        None
+    }
+
+    fn compute_scattering_functions(
+        &self,
+        _si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+    }
+}
+
+/// Returns unit boxes in a row along the x-axis.
+///
+/// * `n` - Number of boxes.
+pub fn boxes(n: usize) -> Vec<ArcPrimitive> {
+    (0..n)
+        .map(|i| {
+            let x = 2.0 * i as Float;
+            let bounds = Bounds3f::new(Point3f::new(x, 0.0, 0.0), Point3f::new(x + 1.0, 1.0, 1.0));
+            Arc::new(BoxPrimitive { bounds }) as ArcPrimitive
+        })
+        .collect()
+}
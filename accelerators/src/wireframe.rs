@@ -0,0 +1,118 @@
+//! Wireframe Export
+
+use core::geometry::*;
+use std::fmt::Write as FmtWrite;
+
+/// Returns a Wavefront OBJ document with bounding boxes as line geometry.
+/// Boxes of the nodes are grouped by depth and the bounds of the primitives
+/// are written to a separate group.
+///
+/// * `header`      - Comment describing the accelerator.
+/// * `levels`      - Bounds of the nodes at each depth.
+/// * `prim_bounds` - Bounds of the primitives.
+pub(crate) fn wireframe_obj(
+    header: &str,
+    levels: &[Vec<Bounds3f>],
+    prim_bounds: &[Bounds3f],
+) -> String {
+    let mut obj = String::new();
+    let mut n_vertices = 0;
+    writeln!(obj, "# {}", header).unwrap();
+    for (depth, boxes) in levels.iter().enumerate() {
+        writeln!(obj, "g depth_{}", depth).unwrap();
+        write_boxes(&mut obj, &mut n_vertices, boxes);
+    }
+    if !prim_bounds.is_empty() {
+        writeln!(obj, "g primitives").unwrap();
+        write_boxes(&mut obj, &mut n_vertices, prim_bounds);
+    }
+    obj
+}
+
+/// Append the vertices and edges of bounding boxes to an OBJ document.
+///
+/// * `obj`        - The OBJ document.
+/// * `n_vertices` - Number of vertices written so far.
+/// * `boxes`      - The bounding boxes.
+fn write_boxes(obj: &mut String, n_vertices: &mut usize, boxes: &[Bounds3f]) {
+    for b in boxes {
+        for corner in 0..8_u8 {
+            let p = b.corner(corner);
+            writeln!(obj, "v {} {} {}", p.x, p.y, p.z).unwrap();
+        }
+
+        // Corners are connected by an edge when their indices differ in
+        // exactly one bit. OBJ indices start at 1.
+        for corner in 0..8_usize {
+            for bit in [1, 2, 4].iter() {
+                if corner & bit == 0 {
+                    let a = *n_vertices + corner + 1;
+                    let b = *n_vertices + (corner | bit) + 1;
+                    writeln!(obj, "l {} {}", a, b).unwrap();
+                }
+            }
+        }
+        *n_vertices += 8;
+    }
+}
+
+/// Returns the bounding boxes written to a group of an OBJ document by
+/// `wireframe_obj()`.
+///
+/// * `obj`   - The OBJ document.
+/// * `group` - Name of the group.
+#[cfg(test)]
+pub(crate) fn group_bounds(obj: &str, group: &str) -> Vec<Bounds3f> {
+    let header = format!("g {}", group);
+    let corners: Vec<Point3f> = obj
+        .lines()
+        .skip_while(|l| *l != header)
+        .skip(1)
+        .take_while(|l| !l.starts_with("g "))
+        .filter(|l| l.starts_with("v "))
+        .map(|l| {
+            let v: Vec<f32> = l[2..].split(' ').map(|v| v.parse().unwrap()).collect();
+            Point3f::new(v[0], v[1], v[2])
+        })
+        .collect();
+    corners
+        .chunks_exact(8)
+        .map(|c| Bounds3f::new(c[0], c[7]))
+        .collect()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wireframe_obj_writes_box_edges_by_depth() {
+        let unit = Bounds3f::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 1.0, 1.0));
+        let half = Bounds3f::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(0.5, 1.0, 1.0));
+        let obj = wireframe_obj("test", &[vec![unit], vec![half, half]], &[unit]);
+        let lines: Vec<&str> = obj.lines().collect();
+
+        assert_eq!(lines[0], "# test");
+        assert_eq!(lines[1], "g depth_0");
+        assert!(lines.contains(&"g depth_1"));
+        assert!(lines.contains(&"g primitives"));
+        assert_eq!(lines.iter().filter(|l| l.starts_with("v ")).count(), 32);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("l ")).count(), 48);
+
+        // Edges of a box connect corners that differ along one axis.
+        assert_eq!(lines[2], "v 0 0 0");
+        assert_eq!(lines[3], "v 1 0 0");
+        assert_eq!(lines[10], "l 1 2");
+        assert!(lines.contains(&"l 31 32"));
+        assert!(lines.iter().all(|l| !l.starts_with("l ") || {
+            let i: Vec<usize> = l[2..].split(' ').map(|v| v.parse().unwrap()).collect();
+            i[0] < i[1] && i[1] <= 32
+        }));
+        assert!(group_bounds(&obj, "depth_1") == vec![half, half]);
+        assert!(group_bounds(&obj, "primitives") == vec![unit]);
+    }
+}
@@ -0,0 +1,160 @@
+//! Batch Rendering
+
+use super::parser::*;
+use super::Api;
+use std::fs::read_to_string;
+use std::time::Instant;
+
+/// Reads a batch file listing scene files to render. Each non-empty line is a
+/// scene file path; lines starting with `#` are comments. Relative paths are
+/// used as is.
+///
+/// * `path` - Path to the batch file.
+pub fn read_batch_file(path: &str) -> Result<Vec<String>, String> {
+    let contents = read_to_string(path)
+        .map_err(|err| format!("Unable to read batch file '{}'. {}", path, err))?;
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Renders each scene with a fresh API state in the same process. Image
+/// textures are loaded through the global `MIPMapCache` so a texture used by
+/// several scenes with the same file path and filtering parameters is only
+/// read and filtered once. Likewise PLY meshes are cached by path and only
+/// read by the first scene using them. Errors in one scene are reported and
+/// the remaining scenes are still rendered.
+///
+/// * `scenes` - Scene file paths.
+///
+/// Returns the number of scenes that failed to parse.
+pub fn render_batch(scenes: &[String]) -> usize {
+    let mut failed = 0;
+
+    for (i, path) in scenes.iter().enumerate() {
+        info!("Rendering scene {}/{} '{}'.", i + 1, scenes.len(), path);
+        let start = Instant::now();
+
+        let mut api = Api::new();
+        api.pbrt_init();
+        if let Err(err) = parse_scene_file(path, &mut api) {
+            error!("{}", err);
+            failed += 1;
+        }
+        api.pbrt_cleanup();
+
+        info!(
+            "Finished scene '{}' in {:.2}s.",
+            path,
+            start.elapsed().as_secs_f32()
+        );
+    }
+
+    failed
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::*;
+    use core::image_io::*;
+    use std::fs;
+
+    #[test]
+    fn batch_file_skips_comments_and_blank_lines() {
+        let path = std::env::temp_dir().join("batch_file_skips_comments.txt");
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, "# Scenes\n  a.pbrt  \n\n#b.pbrt\nc.pbrt\n").unwrap();
+        assert_eq!(
+            read_batch_file(&path).unwrap(),
+            vec![String::from("a.pbrt"), String::from("c.pbrt")]
+        );
+        fs::remove_file(&path).unwrap();
+        assert!(read_batch_file(&path).is_err());
+    }
+
+    #[test]
+    fn batch_renders_remaining_scenes_after_errors() {
+        let dir = std::env::temp_dir();
+        let mut scenes = vec![];
+        let mut images = vec![];
+        for name in ["batch_first", "batch_second"].iter() {
+            let image = dir.join(format!("{}.pfm", name));
+            let image = image.to_string_lossy().into_owned();
+            let scene = square_scene(r#"Integrator "whitted""#, r#"Material "matte""#).replace(
+                r#"Film "image""#,
+                &format!(r#"Film "image" "string filename" "{}""#, image),
+            );
+            let path = dir.join(format!("{}.pbrt", name));
+            let path = path.to_string_lossy().into_owned();
+            fs::write(&path, scene).unwrap();
+            scenes.push(path);
+            images.push(image);
+        }
+        let missing = dir.join("batch_missing.pbrt");
+        scenes.insert(1, missing.to_string_lossy().into_owned());
+
+        assert_eq!(render_batch(&scenes), 1);
+        for path in images.iter() {
+            assert!(read_image(path).is_ok(), "{} not rendered", path);
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_file(&scenes[0]).unwrap();
+        fs::remove_file(&scenes[2]).unwrap();
+    }
+
+    #[test]
+    fn batch_scenes_reuse_ply_meshes() {
+        let dir = std::env::temp_dir();
+        let ply = dir.join("batch_reuse_square.ply");
+        let ply = ply.to_string_lossy().into_owned();
+        fs::write(
+            &ply,
+            "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+             property float y\nproperty float z\nelement face 1\n\
+             property list uchar int vertex_indices\nend_header\n\
+             -1 -1 0\n1 -1 0\n1 1 0\n-1 1 0\n4 0 1 2 3\n",
+        )
+        .unwrap();
+
+        let render_scene = |name: &str| {
+            let image = dir.join(format!("{}.pfm", name));
+            let image = image.to_string_lossy().into_owned();
+            let scene = square_scene(r#"Integrator "whitted""#, r#"Material "matte""#)
+                .replace(
+                    r#"Film "image""#,
+                    &format!(r#"Film "image" "string filename" "{}""#, image),
+                )
+                .replace(
+                    r#"Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  1 -1 0  1 1 0  -1 1 0]"#,
+                    &format!(r#"Shape "plymesh" "string filename" "{}""#, ply),
+                );
+            let path = dir.join(format!("{}.pbrt", name));
+            let path = path.to_string_lossy().into_owned();
+            fs::write(&path, scene).unwrap();
+            assert_eq!(render_batch(std::slice::from_ref(&path)), 0);
+            let rendered = average(&read_image(&image).unwrap());
+            fs::remove_file(&path).unwrap();
+            fs::remove_file(&image).unwrap();
+            rendered
+        };
+
+        // The second scene still renders the square after the file is
+        // overwritten because the mesh read by the first one is reused.
+        let first = render_scene("batch_reuse_first");
+        fs::write(&ply, "not a ply file").unwrap();
+        let second = render_scene("batch_reuse_second");
+        fs::remove_file(&ply).unwrap();
+        assert!(first < 0.75, "{}", first);
+        assert_eq!(first, second);
+    }
+}
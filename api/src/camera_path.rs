@@ -0,0 +1,372 @@
+//! Camera Paths
+//!
+//! Camera paths exported by matchmoving tools replace the scene's camera so
+//! rendered frames line up with tracked footage. Two formats are read:
+//!
+//! * fSpy camera parameters exported as JSON or stored in a `.fspy` project.
+//!   These describe a single frame.
+//! * A JSON camera path with one entry per frame:
+//!
+//! ```json
+//! {
+//!     "sensorWidth": 36,
+//!     "frames": [
+//!         { "frame": 1, "matrix": [1, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 8, 0, 0, 0, 1],
+//!           "focalLength": 35 }
+//!     ]
+//! }
+//! ```
+//!
+//! `matrix` is the camera to world transformation written row by row for a
+//! camera looking down the negative z-axis with the y-axis up, as used by
+//! fSpy, Alembic, Blender and USD. `focalLength` and `sensorWidth` are in
+//! millimetres and can be given once for all frames. `fov` gives the
+//! horizontal field of view in degrees instead.
+//!
+//! With `"bool temporal" true` on the film, consecutive frames rendered from
+//! the same view keep accumulating samples so that a still camera converges.
+//! Moving the camera by more than `"float temporalthreshold"` restarts the
+//! accumulation. If the film also records the `motion` auxiliary channel,
+//! the pixels of the previous frame that saw the same surfaces are
+//! reprojected into the new view with `"float temporalfeedback"` as their
+//! weight and the motion vectors are written to the auxiliary image.
+
+use super::parser::*;
+use super::Api;
+use core::app::OPTIONS;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+/// Default sensor width in millimetres of a full frame camera.
+const DEFAULT_SENSOR_WIDTH: Float = 36.0;
+
+/// Magic number at the start of `.fspy` project files.
+const FSPY_MAGIC: &[u8; 4] = b"fspy";
+
+/// A camera position and lens for one frame of a camera path.
+#[derive(Clone, Debug)]
+pub struct CameraFrame {
+    /// Frame number used to name the output image.
+    pub frame: Int,
+
+    /// Camera to world transformation for a camera looking down the negative
+    /// z-axis with the y-axis up.
+    pub camera_to_world: Transform,
+
+    /// Horizontal field of view in degrees.
+    pub horizontal_fov: Float,
+}
+
+impl CameraFrame {
+    /// Returns the camera to world transformation for a pbrt camera which
+    /// looks down the positive z-axis.
+    pub fn pbrt_camera_to_world(&self) -> Transform {
+        self.camera_to_world * Transform::scale(1.0, 1.0, -1.0)
+    }
+
+    /// Returns the parameters of a perspective camera with the field of
+    /// view of the frame. The horizontal field of view is kept regardless of
+    /// the aspect ratio of the image.
+    ///
+    /// * `params` - Camera parameters from the scene description.
+    /// * `aspect` - Aspect ratio of the image, width over height.
+    pub fn camera_params(&self, params: &ParamSet, aspect: Float) -> ParamSet {
+        // `fov` is the angle spanned by the shorter image axis.
+        let tan_x = (0.5 * self.horizontal_fov).to_radians().tan();
+        let tan_shorter = if aspect > 1.0 { tan_x / aspect } else { tan_x };
+
+        let mut params = params.clone();
+        params.add_float("fov", &[2.0 * tan_shorter.atan().to_degrees()]);
+        params
+    }
+}
+
+/// Reads a camera path. Files with an `.fspy` extension are fSpy projects
+/// and all others are JSON files with fSpy camera parameters or a camera path.
+///
+/// * `path` - Path to the file.
+pub fn read_camera_path(path: &str) -> Result<Vec<CameraFrame>, String> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".abc") {
+        return Err(format!(
+            "Unable to read camera path '{}'. Alembic archives are not supported; \
+             export the camera to a JSON camera path instead.",
+            path
+        ));
+    }
+
+    let json = if lower.ends_with(".fspy") {
+        read_fspy_project(path)?
+    } else {
+        let document = fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read camera path '{}'. {}", path, err))?;
+        JsonValue::parse(&document)?
+    };
+
+    let frames = if let Some(params) = json.get("cameraParameters") {
+        vec![fspy_frame(params)?]
+    } else if json.get("cameraTransform").is_some() {
+        vec![fspy_frame(&json)?]
+    } else {
+        path_frames(&json)?
+    };
+
+    if frames.is_empty() {
+        Err(format!("Camera path '{}' has no frames.", path))
+    } else {
+        Ok(frames)
+    }
+}
+
+/// Returns the camera parameters stored in an fSpy project. The file starts
+/// with a header of four 32-bit little-endian values: the magic number, the
+/// format version, the size of the JSON state and the size of the image
+/// which follows the state.
+///
+/// * `path` - Path to the project.
+fn read_fspy_project(path: &str) -> Result<JsonValue, String> {
+    let bytes =
+        fs::read(path).map_err(|err| format!("Unable to read fSpy project '{}'. {}", path, err))?;
+    if bytes.len() < 16 || &bytes[0..4] != FSPY_MAGIC {
+        return Err(format!("'{}' is not an fSpy project.", path));
+    }
+
+    let state_size = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    let state = bytes
+        .get(16..16 + state_size)
+        .ok_or_else(|| format!("fSpy project '{}' is truncated.", path))?;
+    let state =
+        std::str::from_utf8(state).map_err(|_| format!("fSpy project '{}' is invalid.", path))?;
+
+    let json = JsonValue::parse(state)?;
+    match json.get("cameraParameters") {
+        Some(JsonValue::Null) | None => Err(format!(
+            "fSpy project '{}' has no calibrated camera.",
+            path
+        )),
+        Some(params) => Ok(params.clone()),
+    }
+}
+
+/// Returns the frame described by fSpy camera parameters.
+///
+/// * `params` - The camera parameters.
+fn fspy_frame(params: &JsonValue) -> Result<CameraFrame, String> {
+    let camera_to_world = params
+        .get("cameraTransform")
+        .ok_or_else(|| String::from("fSpy camera parameters have no 'cameraTransform'."))
+        .and_then(matrix)?;
+    let horizontal_fov = params
+        .get("horizontalFieldOfView")
+        .and_then(|v| v.as_f64())
+        .ok_or("fSpy camera parameters have no 'horizontalFieldOfView'.")?;
+
+    let principal_point = params
+        .get("principalPoint")
+        .map_or(vec![], |p| {
+            ["x", "y"]
+                .iter()
+                .filter_map(|c| p.get(c).and_then(|v| v.as_f64()))
+                .collect()
+        });
+    if principal_point.iter().any(|c| c.abs() > 1e-3) {
+        warn!("Ignoring the off-center principal point of the fSpy camera.");
+    }
+
+    Ok(CameraFrame {
+        frame: 1,
+        camera_to_world,
+        horizontal_fov: (horizontal_fov as Float).to_degrees(),
+    })
+}
+
+/// Returns the frames of a JSON camera path.
+///
+/// * `json` - The camera path.
+fn path_frames(json: &JsonValue) -> Result<Vec<CameraFrame>, String> {
+    let frames = json
+        .get("frames")
+        .and_then(|v| v.as_array())
+        .ok_or("Camera path has no 'frames' array.")?;
+
+    let number = |value: &JsonValue, name: &str| value.get(name).and_then(|v| v.as_f64());
+    let mut previous = 0;
+    frames
+        .iter()
+        .map(|entry| {
+            let frame = number(entry, "frame").map_or(previous + 1, |f| f as Int);
+            previous = frame;
+
+            let camera_to_world = entry
+                .get("matrix")
+                .ok_or_else(|| format!("Camera path frame {} has no 'matrix'.", frame))
+                .and_then(matrix)?;
+
+            let horizontal_fov = match number(entry, "fov").or_else(|| number(json, "fov")) {
+                Some(fov) => fov as Float,
+                None => {
+                    let focal_length = number(entry, "focalLength")
+                        .or_else(|| number(json, "focalLength"))
+                        .ok_or_else(|| format!("Camera path frame {} has no lens.", frame))?;
+                    let sensor_width = number(entry, "sensorWidth")
+                        .or_else(|| number(json, "sensorWidth"))
+                        .map_or(DEFAULT_SENSOR_WIDTH, |w| w as Float);
+                    2.0 * (0.5 * sensor_width / focal_length as Float)
+                        .atan()
+                        .to_degrees()
+                }
+            };
+            if horizontal_fov <= 0.0 || horizontal_fov >= 180.0 {
+                return Err(format!(
+                    "Camera path frame {} has an invalid field of view {}.",
+                    frame, horizontal_fov
+                ));
+            }
+
+            Ok(CameraFrame {
+                frame,
+                camera_to_world,
+                horizontal_fov,
+            })
+        })
+        .collect()
+}
+
+/// Returns the transformation for a 4x4 matrix written row by row, either as
+/// 16 numbers, as 4 arrays of rows or as an object with a `rows` member.
+///
+/// * `value` - The matrix.
+fn matrix(value: &JsonValue) -> Result<Transform, String> {
+    let m = value
+        .get("rows")
+        .unwrap_or(value)
+        .as_numbers()
+        .filter(|m| m.len() == 16)
+        .ok_or("Camera matrix must have 16 numbers.")?;
+    let m: Vec<Float> = m.iter().map(|v| *v as Float).collect();
+
+    Ok(Transform::from(Matrix4x4::new(
+        m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13],
+        m[14], m[15],
+    )))
+}
+
+/// Returns the filename of the image for a frame by appending the frame
+/// number to the stem of a filename.
+///
+/// * `filename` - The filename.
+/// * `frame`    - The frame number.
+pub fn frame_filename(filename: &str, frame: Int) -> String {
+    let path = Path::new(filename);
+    let stem = path
+        .file_stem()
+        .map_or(String::new(), |s| s.to_string_lossy().into_owned());
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:04}.{}", stem, frame, ext.to_string_lossy()),
+        None => format!("{}_{:04}", stem, frame),
+    };
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => parent.join(name).to_string_lossy().into_owned(),
+        None => name,
+    }
+}
+
+/// Renders the scene once for each frame of a camera path with a fresh API
+/// state. The frame's camera replaces the scene's camera and the images are
+/// numbered with the frame.
+///
+/// * `paths`  - Scene file paths.
+/// * `frames` - The camera path.
+///
+/// Returns the number of frames that failed to parse.
+pub fn render_camera_path(paths: &[String], frames: &[CameraFrame]) -> usize {
+    let mut failed = 0;
+
+    for (i, frame) in frames.iter().enumerate() {
+        info!(
+            "Rendering frame {} ({}/{}).",
+            frame.frame,
+            i + 1,
+            frames.len()
+        );
+        let start = Instant::now();
+
+        let mut api = Api::new();
+        api.pbrt_init();
+        api.set_camera_frame(frame.clone());
+        for path in paths.iter() {
+            if let Err(err) = parse_scene_file(path, &mut api) {
+                error!("{}", err);
+                failed += 1;
+                break;
+            }
+        }
+        api.pbrt_cleanup();
+
+        info!(
+            "Finished frame {} in {:.2}s.",
+            frame.frame,
+            start.elapsed().as_secs_f32()
+        );
+    }
+
+    failed
+}
+
+/// Returns the filename of the image for a frame from the film parameters or
+/// the command line.
+///
+/// * `film_params` - Film parameters from the scene description.
+/// * `frame`       - The camera frame.
+pub fn frame_image_file(film_params: &ParamSet, frame: &CameraFrame) -> String {
+    let filename = if OPTIONS.image_file.is_empty() {
+        film_params.find_one_string("filename", String::from("pbrt.exr"))
+    } else {
+        OPTIONS.image_file.clone()
+    };
+    frame_filename(&filename, frame.frame)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_path_frames() {
+        let json = JsonValue::parse(
+            r#"{
+                "sensorWidth": 36,
+                "frames": [
+                    { "matrix": [[1, 0, 0, 2], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]],
+                      "focalLength": 18 },
+                    { "frame": 7, "matrix": { "rows": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1] },
+                      "fov": 40 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let frames = path_frames(&json).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].frame, 1);
+        assert!((frames[0].horizontal_fov - 90.0).abs() < 1e-4);
+        let p = frames[0].camera_to_world.transform_point(&Point3f::new(0.0, 0.0, 0.0));
+        assert_eq!(p, Point3f::new(2.0, 0.0, 0.0));
+        assert_eq!(frames[1].frame, 7);
+        assert_eq!(frames[1].horizontal_fov, 40.0);
+    }
+
+    #[test]
+    fn numbered_filenames() {
+        assert_eq!(frame_filename("out.exr", 12), "out_0012.exr");
+        assert_eq!(frame_filename("renders/shot", 3), "renders/shot_0003");
+    }
+}
@@ -0,0 +1,300 @@
+//! GPU Rendering
+//!
+//! With `--gpu` the triangles, materials and lights of the world block are
+//! recorded while it is parsed and rendered with the path tracer of the
+//! `gpu` crate at its end. Scenes are rendered on the CPU instead if they
+//! contain anything the GPU can't render, such as shapes that aren't
+//! triangle meshes, CSG blocks, object instances, clip shapes, textures,
+//! participating media or other integrators, if the camera has no
+//! projection or if there is no GPU.
+
+use super::*;
+use core::spectrum::*;
+use gpu::*;
+
+impl Api {
+    /// Returns whether shapes are recorded for rendering on the GPU.
+    pub(crate) fn records_gpu_scene(&self) -> bool {
+        self.gpu_scene.is_some()
+    }
+
+    /// Stop recording the scene for the GPU so that it's rendered on the CPU.
+    ///
+    /// * `reason` - Describes what the GPU can't render.
+    pub(crate) fn render_gpu_scene_on_cpu(&mut self, reason: &str) {
+        if self.gpu_scene.take().is_some() {
+            warn!("{} Rendering on the CPU.", reason);
+        }
+    }
+
+    /// Record a light source for rendering on the GPU.
+    ///
+    /// * `name`   - Light type.
+    /// * `params` - Light parameters.
+    pub(crate) fn record_gpu_light(&mut self, name: &str, params: &ParamSet) {
+        if let Some(gpu_scene) = self.gpu_scene.as_mut() {
+            if let Err(err) = gpu_scene.add_light(name, params) {
+                self.render_gpu_scene_on_cpu(&err);
+            }
+        }
+    }
+
+    /// Record the triangles of a shape for rendering on the GPU with the
+    /// current material and area light.
+    ///
+    /// * `name`   - Shape type.
+    /// * `params` - Shape parameters.
+    /// * `prims`  - The primitives created for the shape.
+    pub(crate) fn record_gpu_shape(
+        &mut self,
+        name: &str,
+        params: &ParamSet,
+        prims: &[ArcPrimitive],
+    ) {
+        if !self.records_gpu_scene() {
+            return;
+        }
+        if !self.csg_stack.is_empty() {
+            return self.render_gpu_scene_on_cpu("CSG blocks aren't supported on the GPU.");
+        }
+        if self.render_options.current_instance.is_some() {
+            return self.render_gpu_scene_on_cpu("Object instances aren't supported on the GPU.");
+        }
+        if self.current_transforms.is_animated() {
+            return self.render_gpu_scene_on_cpu("Animated shapes aren't supported on the GPU.");
+        }
+
+        let shapes: Vec<ArcShape> = prims.iter().filter_map(|prim| prim.get_shape()).collect();
+        if shapes.is_empty()
+            || shapes
+                .iter()
+                .any(|shape| shape.triangle_vertices().is_none())
+        {
+            let reason = format!("Shape '{}' isn't supported on the GPU.", name);
+            return self.render_gpu_scene_on_cpu(&reason);
+        }
+
+        let current_material = self.graphics_state.current_material.as_ref().unwrap();
+        let material_type = current_material
+            .params
+            .find_one_string("type", current_material.name.clone());
+        let tp = TextureParams::new(
+            params.clone(),
+            current_material.params.clone(),
+            self.graphics_state.float_textures.clone(),
+            self.graphics_state.spectrum_textures.clone(),
+        );
+        let material = GpuMaterial::new(&material_type, &tp).and_then(|material| {
+            match self.graphics_state.area_light.as_ref() {
+                Some(area_light) => {
+                    material.with_area_light(area_light, &self.graphics_state.area_light_params)
+                }
+                None => Ok(material),
+            }
+        });
+        let material = match material {
+            Ok(material) => material,
+            Err(err) => return self.render_gpu_scene_on_cpu(&err),
+        };
+
+        let gpu_scene = self.gpu_scene.as_mut().unwrap();
+        let material = gpu_scene.add_material(material);
+        for shape in shapes.iter() {
+            if let Some(p) = shape.triangle_vertices() {
+                let (hit, _) = shape.sample_area(&Point2f::new(0.5, 0.5));
+                gpu_scene.add_triangle(&p, &hit.n, material);
+            }
+        }
+    }
+
+    /// Render the scene recorded for the GPU and write the image.
+    ///
+    /// * `gpu_scene` - The scene.
+    pub(crate) fn render_gpu(&mut self, gpu_scene: GpuScene) {
+        let options = &self.render_options;
+        if options.integrator_name != "volpath" {
+            warn!(
+                "Integrator '{}' isn't supported on the GPU. Rendering on the CPU.",
+                options.integrator_name
+            );
+            return self.render();
+        }
+        if options.have_scattering_media {
+            warn!("Participating media aren't supported on the GPU. Rendering on the CPU.");
+            return self.render();
+        }
+
+        let mut camera = self.render_options.make_camera(&self.graphics_state);
+        let world_to_raster = match camera.world_to_raster() {
+            Some(world_to_raster) => world_to_raster,
+            None => {
+                warn!(
+                    "Camera '{}' isn't supported on the GPU. Rendering on the CPU.",
+                    self.render_options.camera_name
+                );
+                return self.render();
+            }
+        };
+        let renderer = match GpuRenderer::new() {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                warn!("{} Rendering on the CPU.", err);
+                return self.render();
+            }
+        };
+
+        let options = &self.render_options;
+        let samples_per_pixel = options.sampler_params.find_one_int("pixelsamples", 16);
+        let max_depth = options.integrator_params.find_one_int("maxdepth", 5);
+
+        let gpu_camera = GpuCamera {
+            raster_to_world: world_to_raster.inverse(),
+            pixel_bounds: camera.film().cropped_pixel_bounds,
+        };
+        info!(
+            "Rendering {} triangles on GPU '{}'.",
+            gpu_scene.triangles.len(),
+            renderer.adapter_name()
+        );
+        let pixels = match renderer.render(
+            &gpu_scene,
+            &gpu_camera,
+            samples_per_pixel.max(1) as u32,
+            max_depth.max(0) as u32,
+        ) {
+            Ok(pixels) => pixels,
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        };
+
+        let image: Vec<Spectrum> = pixels
+            .iter()
+            .map(|rgb| Spectrum::from_rgb(rgb, Some(SpectrumType::Illuminant)))
+            .collect();
+        let camera = Arc::get_mut(&mut camera).unwrap();
+        camera.film_mut().set_image(&image);
+        camera.write_image(1.0);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use crate::render_tests::*;
+    use core::image_io::*;
+    use std::fs;
+
+    /// Render a scene with `--gpu` and return the image.
+    ///
+    /// * `name`  - Unique name of the test scene.
+    /// * `scene` - The scene description.
+    fn render_with_gpu_option(name: &str, scene: &str) -> RGBImage {
+        let dir = std::env::temp_dir();
+        let scene_path = dir.join(format!("gpu_test_{}.pbrt", name));
+        let image_path = dir.join(format!("gpu_test_{}.pfm", name));
+        let scene_path = scene_path.to_string_lossy().into_owned();
+        let image_path = image_path.to_string_lossy().into_owned();
+        let film = format!(r#"Film "image" "string filename" "{}""#, image_path);
+        fs::write(&scene_path, scene.replace(r#"Film "image""#, &film)).unwrap();
+
+        let mut api = Api::new();
+        api.gpu = true;
+        api.pbrt_init();
+        parse_scene_file(&scene_path, &mut api).unwrap();
+        let image = read_image(&image_path).unwrap();
+
+        fs::remove_file(&scene_path).unwrap();
+        fs::remove_file(&image_path).unwrap();
+        image
+    }
+
+    /// Render a scene on the GPU and return the image or `None` if there is
+    /// no GPU, in which case the test is skipped.
+    ///
+    /// * `name`  - Unique name of the test scene.
+    /// * `scene` - The scene description.
+    fn render_gpu(name: &str, scene: &str) -> Option<RGBImage> {
+        if let Err(err) = GpuRenderer::new() {
+            eprintln!("Skipping GPU test. {}", err);
+            return None;
+        }
+        Some(render_with_gpu_option(name, scene))
+    }
+
+    #[test]
+    fn gpu_matches_cpu_for_diffuse_square() {
+        let scene = square_scene(
+            r#"Integrator "volpath""#,
+            r#"Material "matte" "rgb Kd" [0.5 0.5 0.5]"#,
+        );
+        let gpu = match render_gpu("diffuse_square", &scene) {
+            Some(image) => average(&image),
+            None => return,
+        };
+        let cpu = average(&render("gpu_diffuse_square", &scene));
+        assert!((gpu - 0.5).abs() < 1e-3, "gpu = {}", gpu);
+        assert!((gpu - cpu).abs() < 0.05, "gpu = {}, cpu = {}", gpu, cpu);
+    }
+
+    #[test]
+    fn gpu_renders_area_lights() {
+        // A two-sided emitting square seen from behind.
+        let scene = square_scene(
+            r#"Integrator "volpath""#,
+            r#"Material "matte" "rgb Kd" [0 0 0]
+AreaLightSource "diffuse" "rgb L" [2 2 2] "bool twosided" "true""#,
+        )
+        .replace(r#"LightSource "infinite" "rgb L" [1 1 1]"#, "");
+        let image = match render_gpu("area_light", &scene) {
+            Some(image) => image,
+            None => return,
+        };
+        assert!((average(&image) - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn scenes_the_gpu_cannot_render_are_rendered_on_the_cpu() {
+        // An emitting square behind a black sphere, a textured square and a
+        // square rendered by another integrator.
+        let sphere = square_scene(
+            r#"Integrator "volpath""#,
+            r#"Material "matte" "rgb Kd" [0 0 0]
+AttributeBegin
+Translate 0 0 -0.5
+Shape "sphere" "float radius" 0.25
+AttributeEnd
+AreaLightSource "diffuse" "rgb L" [2 2 2] "bool twosided" "true""#,
+        )
+        .replace(r#"LightSource "infinite" "rgb L" [1 1 1]"#, "");
+        let texture = square_scene(
+            r#"Integrator "volpath""#,
+            r#"Texture "checks" "spectrum" "checkerboard" "float uscale" 4 "float vscale" 4
+Material "matte" "texture Kd" "checks""#,
+        );
+        let integrator = square_scene(
+            r#"Integrator "whitted""#,
+            r#"Material "matte" "rgb Kd" [0.5 0.5 0.5]"#,
+        );
+
+        for (name, scene) in [
+            ("fallback_sphere", sphere),
+            ("fallback_texture", texture),
+            ("fallback_integrator", integrator),
+        ]
+        .iter()
+        {
+            let gpu = render_with_gpu_option(name, scene);
+            let cpu = render(&format!("gpu_{}", name), scene);
+            for (g, c) in gpu.pixels.iter().zip(cpu.pixels.iter()) {
+                assert_eq!(g.to_rgb(), c.to_rgb(), "{}", name);
+            }
+        }
+    }
+}
@@ -13,13 +13,16 @@ use core::material::*;
 use core::medium::*;
 use core::paramset::*;
 use core::pbrt::*;
+use core::plugin::*;
 use core::primitive::*;
 use core::sampler::*;
 use core::spectrum::*;
+use core::stats::*;
 use core::texture::*;
 use filters::*;
 use lights::*;
 use materials::*;
+use media::*;
 use samplers::*;
 use shapes::*;
 use std::result::Result;
@@ -116,11 +119,38 @@ impl GraphicsState {
                 self.spectrum_textures.clone(),
             );
             self.make_material(&current_material.name, &mp)
+                .map(|mtl| profile_material(mtl, &current_material.name, "(per shape)"))
         } else {
             Ok(Arc::clone(&current_material.material))
         }
     }
 
+    /// Returns the name given to a light with `"string name"` or `None` if it
+    /// is unnamed.
+    ///
+    /// * `params` - Light parameters.
+    pub fn get_light_name(params: &ParamSet) -> Option<String> {
+        let name = params.find_one_string("name", String::new());
+        if name.is_empty() {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    /// Returns the light links given by the shape's parameters or `None` if
+    /// the shape is lit by all lights.
+    ///
+    /// * `geom_params` - Shape parameters.
+    pub fn get_light_links_for_shape(&self, geom_params: &ParamSet) -> Option<Arc<LightLinks>> {
+        let links = LightLinks::from(geom_params);
+        if links.is_empty() {
+            None
+        } else {
+            Some(Arc::new(links))
+        }
+    }
+
     // Attempt to determine if the ParamSet for a shape may provide a value for
     // its material's parameters. Unfortunately, materials don't provide an
     // explicit representation of their parameters that we can query and
@@ -144,9 +174,17 @@ impl GraphicsState {
         }
 
         // Extra special case strings, since plymesh uses "filename", curve "type",
-        // and loopsubdiv "scheme".
+        // loopsubdiv "scheme" and light links "includelights", "excludelights"
+        // and "noshadowlights".
         for (name, param) in ps.strings.iter() {
-            if param.values.len() == 1 && name != "filename" && name != "type" && name != "scheme" {
+            if param.values.len() == 1
+                && name != "filename"
+                && name != "type"
+                && name != "scheme"
+                && name != "includelights"
+                && name != "excludelights"
+                && name != "noshadowlights"
+            {
                 return true;
             }
         }
@@ -213,6 +251,8 @@ impl GraphicsState {
     /// * `world2object`        - Transformation from world space to object space.
     /// * `reverse_orientation` - Indicates whether their surface normal directions.
     /// * `paramset`            - Parameter set.
+    /// * `scene_scale`         - Scene scale declared with
+    ///                           `Option "float scenescale"`.
     pub fn make_shape(
         &self,
         name: &str,
@@ -220,21 +260,34 @@ impl GraphicsState {
         world2object: ArcTransform,
         reverse_orientation: bool,
         paramset: &ParamSet,
+        scene_scale: Option<Float>,
     ) -> Result<Vec<ArcShape>, String> {
         let p = (paramset, object2world, world2object, reverse_orientation);
 
-        match name {
+        let shapes: Result<Vec<ArcShape>, String> = match name {
             "cone" => Ok(vec![Arc::new(Cone::from(p))]),
             "curve" => Ok(Curve::from_props(p)),
             "cylinder" => Ok(vec![Arc::new(Cylinder::from(p))]),
             "disk" => Ok(vec![Arc::new(Disk::from(p))]),
+            "heightfield" => Ok(Heightfield2::from_props(p)),
             "hyperboloid" => Ok(vec![Arc::new(Hyperboloid::from(p))]),
             "loopsubdiv" => Ok(LoopSubDiv::from_props(p)),
+            "metaball" => Ok(Metaball::from_props(p, scene_scale)),
+            "nurbs" => Ok(Nurbs::from_props(p)),
             "paraboloid" => Ok(vec![Arc::new(Paraboloid::from(p))]),
+            "plymesh" => Ok(PlyMesh::from_props(p, &self.float_textures)),
             "sphere" => Ok(vec![Arc::new(Sphere::from(p))]),
             "trianglemesh" => Ok(TriangleMesh::from_props(p, &self.float_textures)),
-            _ => Err(format!("Shape '{}' unknown.", name)),
+            _ => match PLUGINS.read().unwrap().shape(name) {
+                Some(factory) => factory(p.0, p.1, p.2, p.3),
+                None => Err(format!("Shape '{}' unknown.", name)),
+            },
+        };
+
+        if let Ok(shapes) = shapes.as_ref() {
+            STATS.add_memory("shapes", shapes.iter().map(|s| s.memory()).sum());
         }
+        shapes
     }
 
     /// Creates the given type of material from parameter set.
@@ -245,7 +298,22 @@ impl GraphicsState {
         match name {
             "matte" => Ok(Arc::new(MatteMaterial::from(mp))),
             "plastic" => Ok(Arc::new(PlasticMaterial::from(mp))),
+            #[cfg(feature = "sampled-spectrum")]
+            "fluorescent" => Ok(Arc::new(FluorescentMaterial::from(mp))),
+            #[cfg(not(feature = "sampled-spectrum"))]
+            "fluorescent" => {
+                warn!(
+                    "Material 'fluorescent' requires the 'sampled-spectrum' feature. Using 'matte'."
+                );
+                Ok(Arc::new(MatteMaterial::from(mp)))
+            }
             "fourier" => Ok(Arc::new(FourierMaterial::from(mp))),
+            "hair" => Ok(Arc::new(HairMaterial::from(mp))),
+            "kdsubsurface" => Ok(Arc::new(KdSubsurfaceMaterial::from(mp))),
+            "measured" => Ok(Arc::new(MeasuredMaterial::from(mp))),
+            "substrate" => Ok(Arc::new(SubstrateMaterial::from(mp))),
+            "subsurface" => Ok(Arc::new(SubsurfaceMaterial::from(mp))),
+            "translucent" => Ok(Arc::new(TranslucentMaterial::from(mp))),
             "mix" => {
                 let m1 = mp.find_string("namedmaterial1", String::from(""));
                 let mat1 = match self.named_materials.get(&m1) {
@@ -269,10 +337,13 @@ impl GraphicsState {
             }
             "" => Err(String::from("Unable to create material with no name")),
             "none" => Err(String::from("Unable to create material 'none'.")),
-            _ => {
-                warn!("Material '{}' unknown. Using 'matte'.", name);
-                Ok(Arc::new(MatteMaterial::from(mp)))
-            }
+            _ => match PLUGINS.read().unwrap().material(name) {
+                Some(factory) => factory(mp),
+                None => {
+                    warn!("Material '{}' unknown. Using 'matte'.", name);
+                    Ok(Arc::new(MatteMaterial::from(mp)))
+                }
+            },
         }
     }
 
@@ -307,6 +378,7 @@ impl GraphicsState {
             "fbm" => Ok(Arc::new(FBmTexture::<Float>::from(p))),
             "imagemap" => Ok(Arc::new(ImageTexture::<Float>::from(p))),
             "mix" => Ok(Arc::new(MixTexture::<Float>::from(p))),
+            "projection" => Ok(Arc::new(ProjectionTexture::<Float, Float>::from(p))),
             "scale" => Ok(Arc::new(ScaleTexture::<Float>::from(p))),
             "windy" => Ok(Arc::new(WindyTexture::<Float>::from(p))),
             _ => Err(format!("Float texture '{}' unknown.", name)),
@@ -342,9 +414,12 @@ impl GraphicsState {
             "constant" => Ok(Arc::new(ConstantTexture::<Spectrum>::from(p))),
             "dots" => Ok(Arc::new(DotsTexture::<Spectrum>::from(p))),
             "fbm" => Ok(Arc::new(FBmTexture::<Spectrum>::from(p))),
-            "imagemap" => Ok(Arc::new(ImageTexture::<Spectrum>::from(p))),
+            "imagemap" => Ok(Arc::new(ImageTexture::<RGBSpectrum>::from(p))),
             "marble" => Ok(Arc::new(MarbleTexture::from(p))),
             "mix" => Ok(Arc::new(MixTexture::<Spectrum>::from(p))),
+            "projection" => Ok(Arc::new(
+                ProjectionTexture::<RGBSpectrum, Spectrum>::from(p),
+            )),
             "scale" => Ok(Arc::new(ScaleTexture::<Spectrum>::from(p))),
             "uv" => Ok(Arc::new(UVTexture::from(p))),
             "windy" => Ok(Arc::new(WindyTexture::<Spectrum>::from(p))),
@@ -358,11 +433,16 @@ impl GraphicsState {
     /// * `medium2world` - Medium to world space transform.
     /// * `paramset`     - Parameter set.
     pub fn make_medium(
-        _name: &str,
-        _medium2world: ArcTransform,
-        _paramset: &ParamSet,
+        name: &str,
+        medium2world: ArcTransform,
+        paramset: &ParamSet,
     ) -> Result<ArcMedium, String> {
-        Err(String::from("GraphicsState::make_medium() not implemented"))
+        match name {
+            "homogeneous" => Ok(Arc::new(HomogeneousMedium::from(paramset))),
+            "heterogeneous" => GridDensityMedium::from_props(paramset, medium2world)
+                .map(|medium| Arc::new(medium) as ArcMedium),
+            _ => Err(format!("Medium '{}' unknown.", name)),
+        }
     }
 
     /// Creates a light.
@@ -386,6 +466,30 @@ impl GraphicsState {
                 );
                 Ok(Arc::new(PointLight::from(p)))
             }
+            "spot" => {
+                let p = (
+                    paramset,
+                    Arc::clone(&light2world),
+                    medium_interface.outside.clone(),
+                );
+                Ok(Arc::new(SpotLight::from(p)))
+            }
+            "projection" => {
+                let p = (
+                    paramset,
+                    Arc::clone(&light2world),
+                    medium_interface.outside.clone(),
+                );
+                Ok(Arc::new(ProjectionLight::from(p)))
+            }
+            "goniometric" => {
+                let p = (
+                    paramset,
+                    Arc::clone(&light2world),
+                    medium_interface.outside.clone(),
+                );
+                Ok(Arc::new(GonioPhotometricLight::from(p)))
+            }
             "distant" => {
                 let p = (paramset, Arc::clone(&light2world));
                 Ok(Arc::new(DistantLight::from(p)))
@@ -402,31 +506,37 @@ impl GraphicsState {
         }
     }
 
-    /// Creates an area light.
-    ///
-    /// NOTE: Upcasting from AreaLight -> Light is not possible. So we return
-    /// Result<ArcLight, String>.
+    /// Creates an area light. The light is returned both as a `Light` for the
+    /// scene's lights and as an `AreaLight` for the shape's primitive since
+    /// upcasting from AreaLight -> Light is not possible.
     ///
     /// * `name`             - Name.
     /// * `light2world`      - Light to world space transform.
     /// * `medium_interface` - Medium interface.
     /// * `shape`            - Shape
     /// * `paramset`         - Parameter set.
+    /// * `scene_scale`      - Scene scale declared with
+    ///                        `Option "float scenescale"`.
     pub fn make_area_light(
         name: &str,
         light2world: ArcTransform,
         medium_interface: &MediumInterface,
         shape: ArcShape,
         paramset: &ParamSet,
-    ) -> Result<ArcLight, String> {
+        scene_scale: Option<Float>,
+    ) -> Result<(ArcLight, ArcAreaLight), String> {
         let p = (
             paramset,
             Arc::clone(&light2world),
             medium_interface.outside.clone(),
             shape,
+            scene_scale,
         );
         match name {
-            "diffuse" => Ok(Arc::new(DiffuseAreaLight::from(p))),
+            "diffuse" => {
+                let light = Arc::new(DiffuseAreaLight::from(p));
+                Ok((light.clone(), light))
+            }
             _ => Err(format!("AreaLight '{}' unknown.", name)),
         }
     }
@@ -442,11 +552,22 @@ impl GraphicsState {
         paramset: &ParamSet,
     ) -> Result<ArcPrimitive, String> {
         let p = (paramset, prims);
-        match name {
-            "bvh" => Ok(Arc::new(BVHAccel::from(p))),
-            "kdtree" => Ok(Arc::new(KDTreeAccel::from(p))),
-            _ => Err(format!("Accelerator '{}' unknown.", name)),
-        }
+        let accelerator: ArcPrimitive = match name {
+            "bvh" => Arc::new(BVHAccel::from(p)),
+            "kdtree" => Arc::new(KDTreeAccel::from(p)),
+            #[cfg(feature = "embree")]
+            "embree" => Arc::new(EmbreeAccel::new(paramset, prims)?),
+            #[cfg(not(feature = "embree"))]
+            "embree" => {
+                return Err(String::from(
+                    "Accelerator 'embree' is not supported. Rebuild with the 'embree' feature to enable it",
+                ))
+            }
+            _ => return Err(format!("Accelerator '{}' unknown.", name)),
+        };
+
+        STATS.add_memory("accelerators", accelerator.memory());
+        Ok(accelerator)
     }
 
     /// Creates a camera.
@@ -459,6 +580,7 @@ impl GraphicsState {
     /// * `transform_end`    - End time.
     /// * `film`             - The film.
     /// * `medium_interface` - The medium interface.
+    /// * `scene_scale`      - Size of a nominal unit in scene units.
     pub fn make_camera(
         &self,
         name: &str,
@@ -468,6 +590,7 @@ impl GraphicsState {
         transform_end: Float,
         film: Film,
         medium_interface: &MediumInterface,
+        scene_scale: Float,
     ) -> Result<ArcCamera, String> {
         let mut transform_cache = self.transform_cache.lock().unwrap();
 
@@ -491,7 +614,13 @@ impl GraphicsState {
         match name {
             "environment" => Ok(Arc::new(EnvironmentCamera::from(p))),
             "orthographic" => Ok(Arc::new(OrthographicCamera::from(p))),
-            "perspective" => Ok(Arc::new(PerspectiveCamera::from(p))),
+            "perspective" => Ok(Arc::new(PerspectiveCamera::from((
+                p.0,
+                p.1,
+                p.2,
+                p.3,
+                scene_scale,
+            )))),
             "realistic" => Ok(Arc::new(RealisticCamera::from(p))),
             _ => Err(format!("Camera '{}' unknown.", name)),
         }
@@ -548,3 +677,95 @@ impl GraphicsState {
         }
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::*;
+
+    #[test]
+    fn light_and_shadow_links_share_filter() {
+        // The occluder sits behind the camera between the light and the
+        // square so only its shadow can change the image.
+        let scene = |integrator: &str, square: &str, occluder: &str| {
+            format!(
+                r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "random" "integer pixelsamples" 4
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+{}
+WorldBegin
+LightSource "point" "string name" "key" "point from" [0 0 -3] "rgb I" [1 1 1]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3] {}
+    "point P" [-1 -1 0  -1 1 0  1 1 0  1 -1 0]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3] {}
+    "point P" [-2 -2 -2  -2 2 -2  2 2 -2  2 -2 -2]
+WorldEnd
+"#,
+                integrator, square, occluder
+            )
+        };
+        let no_shadow = r#""string noshadowlights" ["key"]"#;
+
+        for (name, integrator) in &[
+            ("whitted", r#"Integrator "whitted""#),
+            ("volpath", r#"Integrator "volpath" "integer maxdepth" 1"#),
+        ] {
+            let lit = |square: &str, occluder: &str| {
+                average(&render(
+                    &format!("light_links_{}", name),
+                    &scene(integrator, square, occluder),
+                )) > 0.0
+            };
+            assert!(!lit("", ""));
+            assert!(lit("", no_shadow));
+            assert!(lit(r#""string includelights" ["key"]"#, no_shadow));
+            assert!(!lit(r#""string excludelights" ["key"]"#, no_shadow));
+            assert!(!lit(r#""string includelights" ["fill"]"#, no_shadow));
+        }
+    }
+
+    #[test]
+    fn kd_tree_renders_like_bvh() {
+        let scene = |accelerator: &str| {
+            let mut spheres = String::new();
+            for i in 0..16 {
+                let (x, y) = ((i % 4) as Float - 1.5, (i / 4) as Float - 1.5);
+                spheres += &format!(
+                    "AttributeBegin Translate {} {} {} Shape \"sphere\" \"float radius\" 0.4 AttributeEnd\n",
+                    x,
+                    y,
+                    0.1 * i as Float
+                );
+            }
+            format!(
+                r#"
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-2 2 -2 2]
+Sampler "random" "integer pixelsamples" 4
+Film "image" "integer xresolution" 16 "integer yresolution" 16
+Integrator "whitted"
+Accelerator "{}"
+WorldBegin
+LightSource "distant" "point from" [1 1 -1] "point to" [0 0 0]
+{}
+WorldEnd
+"#,
+                accelerator, spheres
+            )
+        };
+        let bvh = render("accelerator_bvh", &scene("bvh"));
+        let kd_tree = render("accelerator_kdtree", &scene("kdtree"));
+        assert!(average(&bvh) > 0.01, "{}", average(&bvh));
+        for (a, b) in bvh.pixels.iter().zip(kd_tree.pixels.iter()) {
+            for (a, b) in a.to_rgb().iter().zip(b.to_rgb().iter()) {
+                assert!((a - b).abs() < 1e-4, "{} {}", a, b);
+            }
+        }
+    }
+}
@@ -5,33 +5,69 @@ extern crate log;
 #[macro_use]
 extern crate pest_derive;
 
+#[cfg(feature = "gpu")]
+mod gpu_render;
 mod graphics_state;
 mod material_instance;
+mod primitive_ids;
 mod render_options;
+mod scene_dump;
+mod tessellation_cache;
+mod transform64;
 mod transform_cache;
 mod transform_set;
 
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod render_tests;
+
 use accelerators::*;
+use camera_path::*;
+use core::app::*;
+use core::camera::*;
 use core::geometry::*;
 use core::light::*;
+use core::material::*;
 use core::medium::*;
+use core::microfacet::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::primitive::*;
 use core::primitives::*;
+use core::profiler::*;
+use core::scene::*;
+use core::stats::*;
+use core::texture::*;
 use graphics_state::*;
 use material_instance::*;
+use primitive_ids::*;
 use render_options::*;
+use scene_dump::*;
+use tessellation_cache::*;
 use std::collections::HashMap;
+use std::mem::size_of;
 use std::sync::{Arc, Mutex};
+use transform64::*;
 use transform_cache::*;
 use transform_set::*;
 
+pub mod batch;
+pub mod camera_path;
 pub mod parser;
+pub mod preview;
+pub mod server;
+pub mod session;
 
 /// Map of named material instances.
 pub type NamedMaterialMap = HashMap<String, Arc<MaterialInstance>>;
 
+/// Shapes that are closed and can be combined in CSG blocks without being
+/// flagged with `"bool solid"`.
+const SOLID_SHAPES: [&str; 1] = ["sphere"];
+
 /// Enumerations for API state as we parse the PBRT file format.
 #[derive(Copy, Clone, PartialEq)]
 pub enum ApiState {
@@ -75,6 +111,46 @@ pub struct Api {
 
     /// Caches the transforms.
     transform_cache: Arc<Mutex<TransformCache>>,
+
+    /// Caches tessellated shapes.
+    tessellation_cache: TessellationCache,
+
+    /// Assigns the identifiers of the primitives for the film's ID channels.
+    primitive_ids: PrimitiveIdAllocator,
+
+    /// Used as a stack for the open CSG blocks with their operation and the
+    /// solids combined so far.
+    csg_stack: Vec<(CSGOperation, Vec<ArcPrimitive>)>,
+
+    /// Capture the scene at the end of the world block instead of rendering
+    /// it.
+    capture_world: bool,
+
+    /// The scene captured at the end of the last world block.
+    captured_world: Option<CapturedWorld>,
+
+    /// Scene description recorded for `--dump-scene`.
+    scene_description: Option<SceneDescription>,
+
+    /// Render world blocks on the GPU.
+    gpu: bool,
+
+    /// Scene recorded for rendering on the GPU.
+    #[cfg(feature = "gpu")]
+    gpu_scene: Option<gpu::GpuScene>,
+}
+
+/// Scene description captured at the end of a world block so that it can be
+/// built and rendered later.
+pub(crate) struct CapturedWorld {
+    /// Render options with the primitives and lights of the scene.
+    render_options: RenderOptions,
+
+    /// Graphics state at the end of the world block.
+    graphics_state: GraphicsState,
+
+    /// Memory used by the cached transformations.
+    transform_memory: usize,
 }
 
 impl Api {
@@ -92,9 +168,26 @@ impl Api {
             pushed_transforms: vec![],
             pushed_active_transform_bits: vec![],
             transform_cache: Arc::clone(&transform_cache),
+            tessellation_cache: TessellationCache::default(),
+            primitive_ids: PrimitiveIdAllocator::default(),
+            csg_stack: vec![],
+            capture_world: false,
+            captured_world: None,
+            scene_description: None,
+            gpu: OPTIONS.gpu,
+            #[cfg(feature = "gpu")]
+            gpu_scene: None,
         }
     }
 
+    /// Replaces the scene's camera with a frame of a camera path when the
+    /// scene is rendered.
+    ///
+    /// * `frame` - The camera path frame.
+    pub fn set_camera_frame(&mut self, frame: CameraFrame) {
+        self.render_options.camera_frame = Some(frame);
+    }
+
     /* API Methods */
 
     /// API Initialization.
@@ -102,6 +195,10 @@ impl Api {
         if self.current_api_state != ApiState::Uninitialized {
             error!("pbrt_init() has already been called.");
         }
+        init_albedo_tables();
+        STATS.clear();
+        PROFILER.clear();
+        PROFILER.set_enabled(OPTIONS.profile);
         self.current_api_state = ApiState::OptionsBlock;
     }
 
@@ -118,11 +215,8 @@ impl Api {
     /// Set current tranformation matrix to the identity matrix.
     pub fn pbrt_identity(&mut self) {
         if self.verify_initialized("Identity") {
-            for i in 0..MAX_TRANSFORMS {
-                if self.active_transform_bits & (1 << i) > 0 {
-                    self.current_transforms[i] = Arc::new(Transform::default());
-                }
-            }
+            self.current_transforms
+                .set(self.active_transform_bits, Transform64::default());
         }
     }
 
@@ -133,13 +227,9 @@ impl Api {
     /// * `dz` - Translation in z-direction.
     pub fn pbrt_translate(&mut self, dx: Float, dy: Float, dz: Float) {
         if self.verify_initialized("Translate") {
-            let transform = Transform::translate(&Vector3f::new(dx, dy, dz));
-            for i in 0..MAX_TRANSFORMS {
-                if self.active_transform_bits & (1 << i) > 0 {
-                    let t = *self.current_transforms[i] * transform;
-                    self.current_transforms[i] = Arc::new(t);
-                }
-            }
+            let transform = Transform64::translate(dx as f64, dy as f64, dz as f64);
+            self.current_transforms
+                .concat(self.active_transform_bits, transform);
         }
     }
 
@@ -152,12 +242,11 @@ impl Api {
     ///          |3, 7, 11, 15|
     pub fn pbrt_transform(&mut self, tr: &[Float; 16]) {
         if self.verify_initialized("Transform") {
-            for i in 0..MAX_TRANSFORMS {
-                let t = Transform::from(Matrix4x4::new(
-                    tr[0], tr[4], tr[8], tr[12], tr[1], tr[5], tr[9], tr[13], tr[2], tr[6], tr[10],
-                    tr[14], tr[3], tr[7], tr[11], tr[15],
-                ));
-                self.current_transforms[i] = Arc::new(t);
+            match Transform64::from_column_major(tr) {
+                Some(transform) => self
+                    .current_transforms
+                    .set(self.active_transform_bits, transform),
+                None => error!("Singular matrix passed to Transform."),
             }
         }
     }
@@ -171,13 +260,11 @@ impl Api {
     ///          |3, 7, 11, 15|
     pub fn pbrt_concat_transform(&mut self, tr: &[Float; 16]) {
         if self.verify_initialized("ConcatTransform") {
-            let transform = Transform::from(Matrix4x4::new(
-                tr[0], tr[4], tr[8], tr[12], tr[1], tr[5], tr[9], tr[13], tr[2], tr[6], tr[10],
-                tr[14], tr[3], tr[7], tr[11], tr[15],
-            ));
-            for i in 0..MAX_TRANSFORMS {
-                let t = *self.current_transforms[i] * transform;
-                self.current_transforms[i] = Arc::new(t);
+            match Transform64::from_column_major(tr) {
+                Some(transform) => self
+                    .current_transforms
+                    .concat(self.active_transform_bits, transform),
+                None => error!("Singular matrix passed to ConcatTransform."),
             }
         }
     }
@@ -190,13 +277,10 @@ impl Api {
     /// * `dz`    - z-component of axis vector.
     pub fn pbrt_rotate(&mut self, angle: Float, dx: Float, dy: Float, dz: Float) {
         if self.verify_initialized("Rotate") {
-            let transform = Transform::rotate_axis(angle, &Vector3f::new(dx, dy, dz));
-            for i in 0..MAX_TRANSFORMS {
-                if self.active_transform_bits & (1 << i) > 0 {
-                    let t = *self.current_transforms[i] * transform;
-                    self.current_transforms[i] = Arc::new(t);
-                }
-            }
+            let axis = [dx as f64, dy as f64, dz as f64];
+            let transform = Transform64::rotate(angle as f64, axis);
+            self.current_transforms
+                .concat(self.active_transform_bits, transform);
         }
     }
 
@@ -207,13 +291,9 @@ impl Api {
     /// * `sz` - Scale factor in z-direction.
     pub fn pbrt_scale(&mut self, sx: Float, sy: Float, sz: Float) {
         if self.verify_initialized("Scale") {
-            let transform = Transform::scale(sx, sy, sz);
-            for i in 0..MAX_TRANSFORMS {
-                if self.active_transform_bits & (1 << i) > 0 {
-                    let t = *self.current_transforms[i] * transform;
-                    self.current_transforms[i] = Arc::new(t);
-                }
-            }
+            let transform = Transform64::scale(sx as f64, sy as f64, sz as f64);
+            self.current_transforms
+                .concat(self.active_transform_bits, transform);
         }
     }
 
@@ -242,16 +322,18 @@ impl Api {
         uz: Float,
     ) {
         if self.verify_initialized("LookAt") {
-            let transform = Transform::look_at(
-                &Point3f::new(ex, ey, ez),
-                &Point3f::new(lx, ly, lz),
-                &Vector3f::new(ux, uy, uz),
-            );
-            for i in 0..MAX_TRANSFORMS {
-                if self.active_transform_bits & (1 << i) > 0 {
-                    let t = *self.current_transforms[i] * transform;
-                    self.current_transforms[i] = Arc::new(t);
-                }
+            let pos = [ex as f64, ey as f64, ez as f64];
+            let look = [lx as f64, ly as f64, lz as f64];
+            let up = [ux as f64, uy as f64, uz as f64];
+            match Transform64::look_at(pos, look, up) {
+                Some(transform) => self
+                    .current_transforms
+                    .concat(self.active_transform_bits, transform),
+                None => error!(
+                    "Up vector ({}, {}, {}) and viewing direction passed to LookAt \
+                    are pointing in the same direction.",
+                    ux, uy, uz
+                ),
             }
         }
     }
@@ -349,6 +431,38 @@ impl Api {
         }
     }
 
+    /// Set scene wide options. `scenescale` gives the size of a nominal unit
+    /// (about a metre) in scene units and scales absolute tolerances such as
+    /// the camera near plane and light sampling distance thresholds.
+    /// `mediumoverlap` selects how media in overlapping volumes combine
+    /// (`priority`, `add` or `max`).
+    ///
+    /// * `params` - Option parameters.
+    pub fn pbrt_option(&mut self, params: &ParamSet) {
+        if self.verify_options("Option") {
+            let scale = params.find_float("scenescale");
+            let overlap = params.find_one_string("mediumoverlap", String::new());
+            if scale.is_empty() && overlap.is_empty() {
+                warn!(
+                    "Option not supported; only 'float scenescale' and 'string mediumoverlap' are. Ignoring."
+                );
+            }
+            if !scale.is_empty() {
+                if scale[0] > 0.0 && scale[0].is_finite() {
+                    self.render_options.scene_scale = Some(scale[0]);
+                } else {
+                    warn!("Scene scale {} should be positive. Ignoring.", scale[0]);
+                }
+            }
+            if !overlap.is_empty() {
+                match MediumOverlap::parse(&overlap) {
+                    Ok(overlap) => self.render_options.medium_overlap = overlap,
+                    Err(err) => error!("{}. Using 'priority'.", err),
+                }
+            }
+        }
+    }
+
     /// Set the integrator type and parameters.
     ///
     /// * `name`   - Integrator type name.
@@ -370,6 +484,10 @@ impl Api {
             self.render_options.camera_name = name;
             self.render_options.camera_params = params.clone();
             self.render_options.camera_to_world = self.current_transforms.inverse();
+            self.named_coordinate_systems.insert(
+                String::from("camera"),
+                self.render_options.camera_to_world.clone(),
+            );
         }
     }
 
@@ -384,10 +502,17 @@ impl Api {
             let medium_type = params.find_one_string("type", String::new());
             if medium_type.is_empty() {
                 error!("No parameter string 'type' found in MakeNamedMedium.");
-            } else if let Ok(medium) =
-                GraphicsState::make_medium(&name, self.current_transforms[0].clone(), params)
-            {
-                self.render_options.named_media.insert(name, medium);
+            } else {
+                match GraphicsState::make_medium(
+                    &medium_type,
+                    self.current_transforms[0].clone(),
+                    params,
+                ) {
+                    Ok(medium) => {
+                        self.render_options.named_media.insert(name, medium);
+                    }
+                    Err(err) => error!("{}", err),
+                }
             }
         }
     }
@@ -408,12 +533,21 @@ impl Api {
     pub fn pbrt_world_begin(&mut self) {
         if self.verify_options("WorldBegin") {
             self.current_api_state = ApiState::WorldBlock;
-            for i in 0..MAX_TRANSFORMS {
-                self.current_transforms[i] = Arc::new(Transform::default());
-            }
+            self.current_transforms.reset();
             self.active_transform_bits = ALL_TRANSFORM_BITS;
             self.named_coordinate_systems
                 .insert(String::from("world"), self.current_transforms.clone());
+            if OPTIONS.dump_scene {
+                self.scene_description = Some(SceneDescription::default());
+            }
+            if self.gpu {
+                #[cfg(feature = "gpu")]
+                {
+                    self.gpu_scene = Some(gpu::GpuScene::default());
+                }
+                #[cfg(not(feature = "gpu"))]
+                warn!("Rendering on the GPU requires the 'gpu' feature. Rendering on the CPU.");
+            }
         }
     }
 
@@ -432,18 +566,35 @@ impl Api {
                 self.pushed_transforms.pop();
             }
 
-            // Create scene and render.
-            let mut integrator = match self.render_options.make_integrator(&self.graphics_state) {
-                Ok(integrator) => integrator,
-                Err(err) => panic!("Error creating integrator. {}", err),
-            };
+            // Drop the solids of CSG blocks that were not ended.
+            while self.csg_stack.pop().is_some() {
+                warn!("Missing end to pbrtCSGBegin().");
+            }
 
-            let scene = self.render_options.make_scene();
-            Arc::get_mut(&mut integrator).unwrap().render(scene);
+            // Keep the scene for a `RenderSession`, bake the requested
+            // texture or write the BVH instead of rendering.
+            if self.capture_world {
+                self.capture();
+            } else if let Some(bake) = OPTIONS.bake.as_ref() {
+                self.bake_texture(bake);
+            } else if let Some(dump) = OPTIONS.dump_bvh.as_ref() {
+                self.dump_bvh(dump);
+            } else if let Some(description) = self.scene_description.take() {
+                println!("{}", description.to_json(&self.render_options));
+            } else if self.records_gpu_scene() {
+                #[cfg(feature = "gpu")]
+                {
+                    let gpu_scene = self.gpu_scene.take().unwrap();
+                    self.render_gpu(gpu_scene);
+                }
+            } else {
+                self.render();
+            }
 
             // Clean up after rendering.
             let mut transform_cache = self.transform_cache.lock().unwrap();
             transform_cache.clear();
+            self.tessellation_cache.clear();
 
             self.graphics_state = GraphicsState::new(Arc::clone(&self.transform_cache));
             self.current_api_state = ApiState::OptionsBlock;
@@ -535,6 +686,19 @@ impl Api {
                 self.graphics_state.spectrum_textures.clone(),
             );
 
+            // Projection textures can be placed with a named coordinate system
+            // such as the camera's instead of the current transformation.
+            let projector = params.find_one_string("projector", String::new());
+            let tex2world = match self.named_coordinate_systems.get(&projector) {
+                Some(transforms) => Arc::clone(&transforms[0]),
+                None => {
+                    if !projector.is_empty() {
+                        warn!("Couldn't find named coordinate system '{}'.", projector);
+                    }
+                    Arc::clone(&self.current_transforms[0])
+                }
+            };
+
             if texture_type == "float" {
                 // Create `Float` texture and store in `float_textures`.
                 if self.graphics_state.float_textures.contains_key(&name) {
@@ -543,14 +707,17 @@ impl Api {
 
                 self.warn_if_animated_transform("Texture");
 
-                if let Ok(ft) =
-                    GraphicsState::make_float_texture(&tex_name, &*self.current_transforms[0], &tp)
-                {
+                if let Ok(ft) = GraphicsState::make_float_texture(&tex_name, &tex2world, &tp) {
+                    let ft = profile_texture(ft, &tex_name, &name);
+                    self.render_options.float_textures.push(Arc::clone(&ft));
                     if self.graphics_state.float_textures_shared {
                         let ftm = self.graphics_state.float_textures.clone();
                         self.graphics_state.float_textures = ftm;
                         self.graphics_state.float_textures_shared = false;
                     }
+                    if let Some(description) = self.scene_description.as_mut() {
+                        description.add_texture(&name, "float", &tex_name, params);
+                    }
                     self.graphics_state.float_textures.insert(name, ft);
                 }
             } else if texture_type == "color" || texture_type == "spectrum" {
@@ -561,16 +728,17 @@ impl Api {
 
                 self.warn_if_animated_transform("Texture");
 
-                if let Ok(st) = GraphicsState::make_spectrum_texture(
-                    &tex_name,
-                    &*self.current_transforms[0],
-                    &tp,
-                ) {
+                if let Ok(st) = GraphicsState::make_spectrum_texture(&tex_name, &tex2world, &tp) {
+                    let st = profile_texture(st, &tex_name, &name);
+                    self.render_options.spectrum_textures.push(Arc::clone(&st));
                     if self.graphics_state.spectrum_textures_shared {
                         let stm = self.graphics_state.spectrum_textures.clone();
                         self.graphics_state.spectrum_textures = stm;
                         self.graphics_state.spectrum_textures_shared = false;
                     }
+                    if let Some(description) = self.scene_description.as_mut() {
+                        description.add_texture(&name, "spectrum", &tex_name, params);
+                    }
                     self.graphics_state.spectrum_textures.insert(name, st);
                 }
             } else {
@@ -593,6 +761,10 @@ impl Api {
                 self.graphics_state.spectrum_textures.clone(),
             );
             if let Ok(mtl) = self.graphics_state.make_material(&name, &mp) {
+                if let Some(description) = self.scene_description.as_mut() {
+                    description.add_material(None, &name, params);
+                }
+                let mtl = profile_material(mtl, &name, "(anonymous)");
                 self.graphics_state.current_material = Some(Arc::new(MaterialInstance::new(
                     &name,
                     Arc::clone(&mtl),
@@ -622,6 +794,7 @@ impl Api {
             if mat_name.is_empty() {
                 error!("No parameter string 'type' found in MakeNamedMaterial.");
             } else if let Ok(mtl) = self.graphics_state.make_material(&mat_name, &mp) {
+                let mtl = profile_material(mtl, &mat_name, &name);
                 if self.graphics_state.named_materials.contains_key(&name) {
                     warn!("Named material '{}' redefined.", name);
                 }
@@ -630,6 +803,9 @@ impl Api {
                     self.graphics_state.named_materials = nm;
                     self.graphics_state.named_materials_shared = false;
                 }
+                if let Some(description) = self.scene_description.as_mut() {
+                    description.add_material(Some(&name), &mat_name, params);
+                }
                 let mtli = Arc::new(MaterialInstance::new(&name, Arc::clone(&mtl), params));
                 self.graphics_state.named_materials.insert(name, mtli);
             }
@@ -660,7 +836,15 @@ impl Api {
             let mi = self.create_medium_interface();
             let light2world = self.current_transforms[0].clone();
             match GraphicsState::make_light(&name, light2world, &mi, params) {
-                Ok(lt) => self.render_options.lights.push(lt),
+                Ok(lt) => {
+                    self.render_options.lights.push(lt);
+                    self.render_options.light_names.push(GraphicsState::get_light_name(params));
+                    if let Some(description) = self.scene_description.as_mut() {
+                        description.add_light(&name, params, &self.current_transforms[0]);
+                    }
+                    #[cfg(feature = "gpu")]
+                    self.record_gpu_light(&name, params);
+                }
                 Err(err) => error!("{}", err),
             }
         }
@@ -683,10 +867,49 @@ impl Api {
     /// * `params` - Shape parameters.
     pub fn pbrt_shape(&mut self, name: String, params: &ParamSet) {
         if self.verify_world("Shape") {
+            if params.find_one_bool("clip", false) {
+                self.add_clip_shape(&name, params);
+                return;
+            }
+
+            if !self.csg_stack.is_empty()
+                && !SOLID_SHAPES.contains(&&name[..])
+                && !params.find_one_bool("solid", false)
+            {
+                warn!("Ignoring shape '{}' in CSG block that is not flagged as solid.", name);
+                return;
+            }
+
             let mut prims: Vec<ArcPrimitive> = vec![];
             let mut area_lights: Vec<ArcLight> = vec![]; // Upcasting AreaLight -> Light not possible.
 
-            if self.current_transforms.is_animated() {
+            // Share the tessellation or mesh of identical static shapes once
+            // they repeat. The GPU needs the triangles of each shape.
+            let key = if !self.current_transforms.is_animated()
+                && (TESSELLATED_SHAPES.contains(&&name[..])
+                    || INSTANCED_SHAPES.contains(&&name[..]))
+                && self.graphics_state.area_light.is_none()
+                && !self.records_gpu_scene()
+            {
+                Some(self.tessellation_key(&name, params))
+            } else {
+                None
+            };
+            let repeated = match key {
+                Some(key) if self.tessellation_cache.contains(&key) => Some(key),
+                Some(key) => {
+                    self.tessellation_cache.record(key);
+                    None
+                }
+                None => None,
+            };
+
+            if let Some(key) = repeated {
+                match self.make_tessellated_shape(&name, params, key) {
+                    Some(prim) => prims.push(prim),
+                    None => return,
+                }
+            } else if !self.current_transforms.is_animated() {
                 // Initialize `prims` and `area_lights` for static shape.
 
                 // Create shapes for shape `name`.
@@ -703,6 +926,7 @@ impl Api {
                         Arc::clone(&world2obj),
                         self.graphics_state.reverse_orientation,
                         params,
+                        self.render_options.scene_scale,
                     )
                     .unwrap();
 
@@ -712,34 +936,41 @@ impl Api {
 
                 let mtl = self.graphics_state.get_material_for_shape(params).unwrap();
                 let mi = self.create_medium_interface();
+                let links = self.graphics_state.get_light_links_for_shape(params);
+                let ids = self.primitive_ids.next(&mtl);
 
                 for shape in shapes.iter() {
                     // Possibly create area light for shape.
+                    let mut area = None;
                     if let Some(area_light) = self.graphics_state.area_light.clone() {
-                        if let Ok(area) = GraphicsState::make_area_light(
+                        if let Ok((light, area_light)) = GraphicsState::make_area_light(
                             &area_light,
                             self.current_transforms[0].clone(),
                             &mi,
                             Arc::clone(shape),
                             params,
+                            self.render_options.scene_scale,
                         ) {
-                            area_lights.push(area);
+                            area_lights.push(light);
+                            area = Some(area_light);
                         }
                     }
 
-                    let prim = GeometricPrimitive::new(
+                    let mut prim = GeometricPrimitive::new(
                         Arc::clone(shape),
                         Arc::clone(&mtl),
-                        None,
+                        area,
                         mi.clone(),
+                        links.clone(),
                     );
+                    prim.ids = ids;
                     prims.push(Arc::new(prim));
                 }
             } else {
                 // Initialize `prims` and `area_lights` for animated shape.
 
                 // Create initial shape or shapes for animated shape.
-                if self.graphics_state.area_light.is_none() {
+                if self.graphics_state.area_light.is_some() {
                     warn!("Ignoring currently set area light when creating 'animated shape'.");
                 }
 
@@ -753,6 +984,7 @@ impl Api {
                         Arc::clone(&identity),
                         self.graphics_state.reverse_orientation,
                         params,
+                        self.render_options.scene_scale,
                     )
                     .unwrap();
 
@@ -763,14 +995,18 @@ impl Api {
                 // Create `GeometricPrimitive`(s) for animated shape.
                 let mtl = self.graphics_state.get_material_for_shape(params).unwrap();
                 let mi = self.create_medium_interface();
+                let links = self.graphics_state.get_light_links_for_shape(params);
 
+                let ids = self.primitive_ids.next(&mtl);
                 for shape in shapes.iter() {
-                    let prim = GeometricPrimitive::new(
+                    let mut prim = GeometricPrimitive::new(
                         Arc::clone(shape),
                         Arc::clone(&mtl),
                         None,
                         mi.clone(),
+                        links.clone(),
                     );
+                    prim.ids = ids;
                     prims.push(Arc::new(prim));
                 }
 
@@ -789,6 +1025,7 @@ impl Api {
                 );
                 if prims.len() > 1 {
                     let bvh = BVHAccel::new(&prims, 1, SplitMethod::SAH);
+                    STATS.add_memory("accelerators", bvh.memory());
                     prims = vec![Arc::new(bvh)];
                 }
                 if prims.len() == 1 {
@@ -802,26 +1039,239 @@ impl Api {
                 }
             }
 
-            // Add `prims` and `area_lights` to scene or current instance.
-            if let Some(mut current_instance) = self.render_options.current_instance.clone() {
-                if !area_lights.is_empty() {
-                    warn!("Area lights not supported with object instancing.");
-                    Arc::get_mut(&mut current_instance)
-                        .unwrap()
-                        .append(&mut prims);
-                }
-                Arc::get_mut(&mut current_instance)
-                    .unwrap()
-                    .append(&mut prims);
-            } else {
-                self.render_options.primitives.append(&mut prims);
-                if !area_lights.is_empty() {
-                    self.render_options.lights.append(&mut area_lights);
+            if let Some(description) = self.scene_description.as_mut() {
+                let area_light = self.graphics_state.area_light.as_deref();
+                description.add_shape(&name, prims.len(), area_light);
+            }
+            #[cfg(feature = "gpu")]
+            self.record_gpu_shape(&name, params, &prims);
+            self.add_primitives(prims, area_lights);
+        }
+    }
+
+    /// Adds primitives of a shape and their area lights to the current CSG
+    /// block, the current instance or the scene.
+    ///
+    /// * `prims`       - The primitives.
+    /// * `area_lights` - The area lights.
+    fn add_primitives(&mut self, mut prims: Vec<ArcPrimitive>, mut area_lights: Vec<ArcLight>) {
+        // Add `prims` and `area_lights` to the current CSG block, current
+        // instance or scene.
+        if let Some((_, solids)) = self.csg_stack.last_mut() {
+            if !area_lights.is_empty() {
+                warn!("Area lights not supported in CSG blocks.");
+            }
+            if prims.len() == 1 {
+                solids.append(&mut prims);
+            } else if !prims.is_empty() {
+                let bvh = BVHAccel::new(&prims, 4, SplitMethod::SAH);
+                STATS.add_memory("accelerators", bvh.memory());
+                solids.push(Arc::new(bvh));
+            }
+        } else if let Some(name) = self.render_options.current_instance.clone() {
+            if !area_lights.is_empty() {
+                warn!("Area lights not supported with object instancing.");
+            }
+            if let Some(instance) = self.render_options.instances.get_mut(&name) {
+                instance.append(&mut prims);
+            }
+        } else {
+            self.render_options.primitives.append(&mut prims);
+            if !area_lights.is_empty() {
+                let name = GraphicsState::get_light_name(&self.graphics_state.area_light_params);
+                self.render_options
+                    .light_names
+                    .resize(self.render_options.light_names.len() + area_lights.len(), name);
+                self.render_options.lights.append(&mut area_lights);
+            }
+        }
+    }
+
+    /// Adds a clip shape whose interior is removed from the geometry seen by
+    /// camera rays. The section where it cuts through a solid is shaded with
+    /// the named material given by `"string sectionmaterial"` or the material
+    /// of the cut object if none is given.
+    ///
+    /// * `name`   - Name.
+    /// * `params` - Parameter set.
+    fn add_clip_shape(&mut self, name: &str, params: &ParamSet) {
+        if self.current_transforms.is_animated() {
+            warn!("Animated clip shapes are not supported. Using the start transform.");
+        }
+        if self.render_options.current_instance.is_some() {
+            warn!("Clip shapes are not supported in object instances. Clipping the scene.");
+        }
+        if self.graphics_state.area_light.is_some() {
+            warn!("Ignoring currently set area light for clip shape.");
+        }
+        #[cfg(feature = "gpu")]
+        self.render_gpu_scene_on_cpu("Clip shapes aren't supported on the GPU.");
+
+        let mut transform_cache = self.transform_cache.lock().unwrap();
+        let tr = self.current_transforms[0].clone();
+        let tr_inv = Arc::new(tr.inverse());
+        let obj2world = transform_cache.lookup(Arc::clone(&tr));
+        let world2obj = transform_cache.lookup(tr_inv);
+        let shapes = match self.graphics_state.make_shape(
+            name,
+            obj2world,
+            world2obj,
+            self.graphics_state.reverse_orientation,
+            params,
+            self.render_options.scene_scale,
+        ) {
+            Ok(shapes) => shapes,
+            Err(err) => {
+                error!("Error creating clip shape '{}'. {}", name, err);
+                return;
+            }
+        };
+
+        let section_material = params.find_one_string("sectionmaterial", String::new());
+        let mtl = if section_material.is_empty() {
+            None
+        } else {
+            match self.graphics_state.named_materials.get(&section_material) {
+                Some(mtl) => Some(Arc::clone(&mtl.material)),
+                None => {
+                    error!("Section material '{}' unknown.", section_material);
+                    None
                 }
             }
+        };
+
+        for shape in shapes {
+            let prim = GeometricPrimitive {
+                shape,
+                material: mtl.clone(),
+                area_light: None,
+                medium_interface: MediumInterface::vacuum(),
+                light_links: None,
+                ids: PrimitiveIds::default(),
+            };
+            self.render_options.clip_primitives.push(Arc::new(prim));
         }
     }
 
+    /// Returns the key identifying a static shape that is tessellated into a
+    /// triangle mesh or is a mesh itself. The orientation is the one the
+    /// shape has in object space, so it includes whether the transformation
+    /// to world space swaps handedness.
+    ///
+    /// * `name`   - Name.
+    /// * `params` - Parameter set.
+    fn tessellation_key(&self, name: &str, params: &ParamSet) -> TessellationKey {
+        // A per-shape material depends only on the current material and the
+        // shape parameters which are both part of the key.
+        let current_material = self
+            .graphics_state
+            .current_material
+            .as_ref()
+            .map(|m| Arc::clone(&m.material))
+            .unwrap();
+        TessellationKey::new(
+            name,
+            params,
+            self.object_space_reverse_orientation(),
+            &current_material,
+            &self.create_medium_interface(),
+            &self.graphics_state.float_textures,
+        )
+    }
+
+    /// Returns whether surface normals of a shape created in object space
+    /// are reversed so that they match the shape created in world space.
+    fn object_space_reverse_orientation(&self) -> bool {
+        self.graphics_state.reverse_orientation ^ self.current_transforms[0].swaps_handedness()
+    }
+
+    /// Returns a primitive for a repeated static shape that is tessellated
+    /// into a triangle mesh or is a mesh itself. The mesh and its BVH are
+    /// built once in object space and shared by every shape with the same
+    /// key; each shape places it in the world with a `TransformedPrimitive`.
+    ///
+    /// * `name`   - Name.
+    /// * `params` - Parameter set.
+    /// * `key`    - Key of the shape.
+    fn make_tessellated_shape(
+        &mut self,
+        name: &str,
+        params: &ParamSet,
+        key: TessellationKey,
+    ) -> Option<ArcPrimitive> {
+        let mi = self.create_medium_interface();
+        let reverse_orientation = self.object_space_reverse_orientation();
+
+        let mut transform_cache = self.transform_cache.lock().unwrap();
+        let aggregate = match self.tessellation_cache.get(&key) {
+            Some((aggregate, n_shapes, memory)) => {
+                STATS.record_tessellation(name, n_shapes, memory, true);
+                aggregate
+            }
+            None => {
+                let identity = transform_cache.lookup(Arc::new(Transform::default()));
+                let shapes = self
+                    .graphics_state
+                    .make_shape(
+                        name,
+                        Arc::clone(&identity),
+                        Arc::clone(&identity),
+                        reverse_orientation,
+                        params,
+                        self.render_options.scene_scale,
+                    )
+                    .unwrap();
+                if shapes.is_empty() {
+                    return None;
+                }
+
+                let mtl = self.graphics_state.get_material_for_shape(params).unwrap();
+                let links = self.graphics_state.get_light_links_for_shape(params);
+
+                // Identical shapes sharing the tessellation share its
+                // identifiers.
+                let ids = self.primitive_ids.next(&mtl);
+                let prims: Vec<ArcPrimitive> = shapes
+                    .iter()
+                    .map(|shape| {
+                        let mut prim = GeometricPrimitive::new(
+                            Arc::clone(shape),
+                            Arc::clone(&mtl),
+                            None,
+                            mi.clone(),
+                            links.clone(),
+                        );
+                        prim.ids = ids;
+                        Arc::new(prim) as ArcPrimitive
+                    })
+                    .collect();
+                let aggregate: ArcPrimitive = Arc::new(BVHAccel::new(&prims, 4, SplitMethod::SAH));
+                STATS.add_memory("accelerators", aggregate.memory());
+
+                let memory = shapes
+                    .iter()
+                    .map(|shape| shape.memory() + size_of::<GeometricPrimitive>())
+                    .sum();
+                STATS.record_tessellation(name, shapes.len(), memory, false);
+                self.tessellation_cache
+                    .insert(key, Arc::clone(&aggregate), shapes.len(), memory);
+                aggregate
+            }
+        };
+
+        let obj2world = transform_cache.lookup(Arc::clone(&self.current_transforms[0]));
+        let animated_object2world = AnimatedTransform::new(
+            Arc::clone(&obj2world),
+            Arc::clone(&obj2world),
+            self.render_options.transform_start_time,
+            self.render_options.transform_end_time,
+        );
+        Some(Arc::new(TransformedPrimitive::new(
+            aggregate,
+            animated_object2world,
+        )))
+    }
+
     /// Reverse the orientation of surface normals for shapes that follow this
     /// directive.
     pub fn pbrt_reverse_orientation(&mut self) {
@@ -837,14 +1287,11 @@ impl Api {
         if self.verify_world("ObjectBegin") {
             self.pbrt_attribute_begin();
 
-            if let Some(_current_instance) = self.render_options.current_instance.clone() {
+            if self.render_options.current_instance.is_some() {
                 error!("ObjectBegin called inside of an instance definition.");
             } else {
-                let new_instance: Arc<Vec<ArcPrimitive>> = Arc::new(vec![]);
-                self.render_options
-                    .instances
-                    .insert(name, Arc::clone(&new_instance));
-                self.render_options.current_instance = Some(Arc::clone(&new_instance));
+                self.render_options.instances.insert(name.clone(), vec![]);
+                self.render_options.current_instance = Some(name);
             }
         }
     }
@@ -852,7 +1299,7 @@ impl Api {
     /// End the definition of a named object instance.
     pub fn pbrt_object_end(&mut self) {
         if self.verify_world("ObjectEnd") {
-            if let Some(_current_instance) = self.render_options.current_instance.clone() {
+            if self.render_options.current_instance.is_none() {
                 error!("ObjectEnd called outside of instance definition.");
             }
             self.render_options.current_instance = None;
@@ -861,13 +1308,47 @@ impl Api {
         }
     }
 
+    /// Begin a block of solids combined with a boolean operation. Blocks can
+    /// be nested to combine their results.
+    ///
+    /// * `operation` - The operation; `union`, `intersection` or
+    ///                 `difference`.
+    pub fn pbrt_csg_begin(&mut self, operation: String) {
+        if self.verify_world("CSGBegin") {
+            self.pbrt_attribute_begin();
+            self.csg_stack.push((CSGOperation::from(&operation[..]), vec![]));
+        }
+    }
+
+    /// End a block of solids and add the combined solid to the enclosing
+    /// block, the current instance or the scene.
+    pub fn pbrt_csg_end(&mut self) {
+        if self.verify_world("CSGEnd") {
+            match self.csg_stack.pop() {
+                Some((operation, solids)) => {
+                    let mut solids = solids.into_iter();
+                    if let Some(first) = solids.next() {
+                        let csg = solids.fold(first, |a, b| {
+                            Arc::new(CSGPrimitive::new(operation, a, b)) as ArcPrimitive
+                        });
+                        self.add_primitives(vec![csg], vec![]);
+                    } else {
+                        warn!("CSG block has no solids.");
+                    }
+                    self.pbrt_attribute_end();
+                }
+                None => error!("CSGEnd called outside of CSG block."),
+            }
+        }
+    }
+
     /// Instantiate a named object.
     ///
     /// * `name` - The object instance name.
     pub fn pbrt_object_instance(&mut self, name: String) {
         if self.verify_world("ObjectInstance") {
             // Perform object instance error checking.
-            if let Some(_current_instance) = self.render_options.current_instance.clone() {
+            if self.render_options.current_instance.is_some() {
                 error!("ObjectInstance can't be called inside of instance definition.");
                 return;
             }
@@ -987,7 +1468,7 @@ impl Api {
         match name {
             Some(n) => {
                 if n.is_empty() {
-                    error!("Medium name is empty string for side '{}'.", side);
+                    // An empty name is a vacuum.
                     None
                 } else if let Some(medium) = self.render_options.named_media.get(&n) {
                     Some(medium.clone())
@@ -1008,6 +1489,455 @@ impl Api {
             self.graphics_state.current_outside_medium.clone(),
             "outside",
         );
-        MediumInterface::new(inside, outside)
+        let mut mi = MediumInterface::new(inside, outside);
+        mi.overlap = self.render_options.medium_overlap;
+        mi
+    }
+
+    /// Create the scene and render it.
+    fn render(&mut self) {
+        self.render_options.derive_scene_scale();
+        let camera = self.render_options.make_camera(&self.graphics_state);
+        let mut integrator = match self.render_options.make_integrator(Arc::clone(&camera)) {
+            Ok(integrator) => integrator,
+            Err(err) => panic!("Error creating integrator. {}", err),
+        };
+
+        let transform_memory = self.transform_cache.lock().unwrap().memory();
+        let scene = build_scene(&mut self.render_options, &camera, transform_memory);
+
+        // The integrator must hold the only reference to the camera so it can
+        // write to the film.
+        drop(camera);
+        Arc::get_mut(&mut integrator).unwrap().render(scene);
+
+        if OPTIONS.stats {
+            print!("{}", STATS.report());
+        }
+        if OPTIONS.profile {
+            print!("{}", PROFILER.report());
+        }
+    }
+
+    /// Returns whether shapes are recorded for rendering on the GPU.
+    #[cfg(not(feature = "gpu"))]
+    fn records_gpu_scene(&self) -> bool {
+        false
+    }
+
+    /// Keep the scene described by the world block for rendering later. The
+    /// primitives and lights are moved out of the render options as if the
+    /// scene had been rendered.
+    fn capture(&mut self) {
+        if self.captured_world.is_some() {
+            warn!("Replacing the scene of the previous world block.");
+        }
+
+        let render_options = self.render_options.clone();
+        self.render_options.primitives.clear();
+        self.render_options.clip_primitives.clear();
+        self.render_options.lights.clear();
+        self.render_options.light_names.clear();
+
+        self.captured_world = Some(CapturedWorld {
+            render_options,
+            graphics_state: self.graphics_state.clone(),
+            transform_memory: self.transform_cache.lock().unwrap().memory(),
+        });
+    }
+
+    /// Write a named texture evaluated over the (u, v) domain to an image.
+    ///
+    /// * `bake` - The texture baking options.
+    fn bake_texture(&self, bake: &BakeOptions) {
+        let resolution = Point2::new(bake.resolution[0], bake.resolution[1]);
+        let name = &bake.texture;
+        let path = &bake.image_file;
+
+        let result = if let Some(tex) = self.graphics_state.float_textures.get(name) {
+            bake_float_texture(tex, &resolution, path)
+        } else if let Some(tex) = self.graphics_state.spectrum_textures.get(name) {
+            bake_spectrum_texture(tex, &resolution, path)
+        } else {
+            Err(format!("Texture '{}' not found.", name))
+        };
+
+        if let Err(err) = result {
+            error!("Error baking texture '{}'. {}", name, err);
+        }
+    }
+
+    /// Build the scene's accelerator from its primitives and write the
+    /// bounding boxes of its nodes to a wireframe OBJ file. Accelerators
+    /// other than a BVH or kd-tree are written as a default BVH.
+    ///
+    /// * `dump` - The accelerator export options.
+    fn dump_bvh(&self, dump: &DumpBVHOptions) {
+        let name = &self.render_options.accelerator_name;
+        let params = &self.render_options.accelerator_params;
+        let primitives = &self.render_options.primitives[..];
+        let result = match name.as_str() {
+            "kdtree" => {
+                let kd_tree = KDTreeAccel::from((params, primitives));
+                info!(
+                    "Writing {} kd-tree nodes over {} primitives to '{}'.",
+                    kd_tree.next_free_node,
+                    kd_tree.primitives.len(),
+                    dump.obj_file
+                );
+                kd_tree.write_wireframe_obj(&dump.obj_file, dump.max_depth, dump.primitives)
+            }
+            _ => {
+                let bvh = if name == "bvh" {
+                    BVHAccel::from((params, primitives))
+                } else {
+                    warn!(
+                        "Writing a default BVH instead of the '{}' accelerator.",
+                        name
+                    );
+                    BVHAccel::from((&ParamSet::new(), primitives))
+                };
+                info!(
+                    "Writing {} BVH nodes over {} primitives to '{}'.",
+                    bvh.nodes.len(),
+                    bvh.primitives.len(),
+                    dump.obj_file
+                );
+                bvh.write_wireframe_obj(&dump.obj_file, dump.max_depth, dump.primitives)
+            }
+        };
+
+        if let Err(err) = result {
+            error!("Error writing accelerator. {}", err);
+        }
+    }
+}
+
+/// Build the acceleration structures of a scene and prepare its textures.
+///
+/// * `render_options`   - Render options with the primitives and lights.
+/// * `camera`           - The camera used to trace probe rays when auto tuning
+///                        the accelerator.
+/// * `transform_memory` - Memory used by the cached transformations.
+fn build_scene(
+    render_options: &mut RenderOptions,
+    camera: &ArcCamera,
+    transform_memory: usize,
+) -> Arc<Scene> {
+    let _p = ProfilePhase::new(Prof::SceneConstruction);
+    let scene = render_options.make_scene(camera);
+
+    // Record memory used once the scene is built.
+    let float_textures = render_options.float_textures.iter();
+    let spectrum_textures = render_options.spectrum_textures.iter();
+    STATS.add_memory(
+        "textures",
+        float_textures.map(|t| t.memory()).sum::<usize>()
+            + spectrum_textures.map(|t| t.memory()).sum::<usize>(),
+    );
+    STATS.add_memory("transforms", transform_memory);
+    STATS.record_build_memory();
+
+    let shared = STATS.tessellation_memory_shared();
+    if shared > 0 {
+        info!("Shared meshes saved {} of memory.", format_bytes(shared));
+    }
+    for texture in render_options.float_textures.iter() {
+        texture.preprocess(&scene);
+    }
+    for texture in render_options.spectrum_textures.iter() {
+        texture.preprocess(&scene);
+    }
+
+    scene
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::*;
+    use core::image_io::*;
+    use core::spectrum::*;
+    use std::fs;
+
+    /// Bakes a named texture of a parsed scene to a 4x4 image and returns it.
+    ///
+    /// * `api`     - The API state.
+    /// * `texture` - Name of the texture.
+    fn bake(api: &Api, texture: &str) -> RGBImage {
+        let path = std::env::temp_dir().join(format!("bake_test_{}.pfm", texture));
+        let path = path.to_string_lossy().into_owned();
+        api.bake_texture(&BakeOptions {
+            texture: String::from(texture),
+            resolution: [4, 4],
+            image_file: path.clone(),
+        });
+        let image = read_image(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        image
+    }
+
+    #[test]
+    fn bakes_textures_over_the_uv_domain() {
+        let scene = r#"WorldBegin
+Texture "grey" "spectrum" "constant" "rgb value" [0.2 0.4 0.6]
+Texture "checks" "float" "checkerboard" "float uscale" 2 "float vscale" 2
+    "string aamode" "none"
+"#;
+        let api = parse("bake", scene);
+
+        // The baked colour goes through the spectrum representation.
+        let grey = Spectrum::from_rgb(&[0.2, 0.4, 0.6], None).to_rgb();
+        let image = bake(&api, "grey");
+        assert_eq!(image.resolution, Point2::new(4, 4));
+        for pixel in image.pixels.iter() {
+            for (c, expected) in grey.iter().enumerate() {
+                assert!((pixel[c] - expected).abs() < 1e-5, "{}", pixel[c]);
+            }
+        }
+
+        // Each check covers 2x2 texels.
+        let image = bake(&api, "checks");
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if (x / 2 + y / 2) % 2 == 0 { 1.0 } else { 0.0 };
+                let pixel = image.pixels[y * 4 + x];
+                assert_eq!(pixel[0], expected, "texel ({}, {})", x, y);
+                assert_eq!(pixel[0], pixel[2]);
+            }
+        }
+    }
+
+    #[test]
+    fn object_space_textures_follow_instances() {
+        // The square is moved along the view direction into the next layer
+        // of checks so only the texture can change the image.
+        let scene = |space: &str, translate: &str| {
+            format!(
+                r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "random" "integer pixelsamples" 4
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+Integrator "volpath" "integer maxdepth" 1
+WorldBegin
+LightSource "infinite" "rgb L" [1 1 1]
+ObjectBegin "square"
+Texture "checks" "spectrum" "checkerboard" "integer dimension" 3
+    "rgb tex1" [1 1 1] "rgb tex2" [0 0 0] "string space" "{}"
+Material "matte" "texture Kd" "checks"
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0.5  1 -1 0.5  1 1 0.5  -1 1 0.5]
+ObjectEnd
+{}
+ObjectInstance "square"
+WorldEnd
+"#,
+                space, translate
+            )
+        };
+        let differs = |a: &RGBImage, b: &RGBImage| {
+            a.pixels
+                .iter()
+                .zip(b.pixels.iter())
+                .any(|(pa, pb)| (pa.to_rgb()[0] - pb.to_rgb()[0]).abs() > 0.25)
+        };
+
+        let object = render("object_space", &scene("object", ""));
+        let object_moved = render("object_space_moved", &scene("object", "Translate 0 0 1"));
+        assert!(!differs(&object, &object_moved));
+
+        let world = render("world_space", &scene("world", ""));
+        let world_moved = render("world_space_moved", &scene("world", "Translate 0 0 1"));
+        assert!(differs(&world, &world_moved));
+        assert!(!differs(&object, &world));
+    }
+
+    #[test]
+    fn clip_shapes_only_remove_geometry_from_camera_rays() {
+        // The clip sphere covers the whole view so camera rays see the
+        // environment instead of the grey square.
+        let clip = r#"Shape "sphere" "float radius" 1 "bool clip" "true""#;
+        let square = r#"Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  1 -1 0  1 1 0  -1 1 0]"#;
+        let clipped = average(&render(
+            "clip_camera_rays",
+            &square_scene(r#"Integrator "volpath""#, clip),
+        ));
+        let unclipped = average(&render(
+            "clip_camera_rays_none",
+            &square_scene(r#"Integrator "volpath""#, ""),
+        ));
+        let environment = average(&render(
+            "clip_camera_rays_environment",
+            &square_scene(r#"Integrator "volpath""#, "").replace(square, ""),
+        ));
+        assert!(
+            (clipped - environment).abs() < 1e-3,
+            "clipped = {}, environment = {}",
+            clipped,
+            environment
+        );
+        assert!(unclipped < 0.75, "{}", unclipped);
+
+        // The occluder behind the camera is inside a clip shape but it still
+        // shadows the square.
+        let scene = |occluder: &str| {
+            format!(
+                r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "random" "integer pixelsamples" 4
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+Integrator "volpath" "integer maxdepth" 1
+WorldBegin
+LightSource "point" "point from" [0 0 -3] "rgb I" [1 1 1]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  -1 1 0  1 1 0  1 -1 0]
+{}
+AttributeBegin
+Translate 0 0 -2
+Shape "sphere" "float radius" 0.5 "bool clip" "true"
+AttributeEnd
+WorldEnd
+"#,
+                occluder
+            )
+        };
+        let occluder = r#"Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-0.3 -0.3 -2  -0.3 0.3 -2  0.3 0.3 -2  0.3 -0.3 -2]"#;
+        let lit = average(&render("clip_shadow_rays_lit", &scene("")));
+        let shadowed = average(&render("clip_shadow_rays", &scene(occluder)));
+        assert!(lit > 0.0);
+        assert_eq!(shadowed, 0.0);
+    }
+
+    #[test]
+    fn csg_operations_combine_solid_intervals() {
+        // Two unit spheres overlapping between x = -0.5 and x = 0.5.
+        let scene = |name: &str, operation: &str| {
+            let mut api = parse(
+                name,
+                &format!(
+                    r#"
+Camera "orthographic"
+WorldBegin
+CSGBegin "{}"
+AttributeBegin
+Translate -0.5 0 0
+Shape "sphere"
+AttributeEnd
+AttributeBegin
+Translate 0.5 0 0
+Shape "sphere"
+AttributeEnd
+CSGEnd
+"#,
+                    operation
+                ),
+            );
+            let camera = api.render_options.make_camera(&api.graphics_state);
+            api.render_options.make_scene(&camera)
+        };
+        // Returns the x coordinate of the first hit along a ray parallel to
+        // the x axis.
+        let hit = |scene: &Scene, x: Float, dx: Float| {
+            let mut ray = Ray::new(
+                Point3f::new(x, 0.0, 0.0),
+                Vector3f::new(dx, 0.0, 0.0),
+                INFINITY,
+                0.0,
+                None,
+            );
+            let occluded = scene.intersect_p(&ray);
+            let isect = scene.intersect(&mut ray);
+            assert_eq!(isect.is_some(), occluded);
+            isect.map(|isect| {
+                // The normal faces out of the combined solid.
+                assert!(ray.d.dot(&Vector3f::from(isect.hit.n)) < 0.0);
+                isect.hit.p.x
+            })
+        };
+        let near = |x: Option<Float>, e: Float| x.is_some_and(|x| (x - e).abs() < 1e-3);
+
+        let union = scene("csg_union", "union");
+        assert!(near(hit(&union, -10.0, 1.0), -1.5));
+        assert!(near(hit(&union, 10.0, -1.0), 1.5));
+
+        let intersection = scene("csg_intersection", "intersection");
+        assert!(near(hit(&intersection, -10.0, 1.0), -0.5));
+        assert!(near(hit(&intersection, 10.0, -1.0), 0.5));
+
+        let difference = scene("csg_difference", "difference");
+        assert!(near(hit(&difference, -10.0, 1.0), -1.5));
+        assert!(near(hit(&difference, 0.0, -1.0), -0.5));
+        assert_eq!(hit(&difference, 0.0, 1.0), None);
+    }
+
+    #[test]
+    fn object_instances_are_defined_once_and_placed_per_instance() {
+        let api = parse(
+            "object_instances",
+            r#"
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "orthographic"
+WorldBegin
+ObjectBegin "pair"
+Shape "sphere" "float radius" 0.5
+Translate 1 0 0
+Shape "sphere" "float radius" 0.5
+ObjectInstance "pair"
+ObjectBegin "nested"
+ObjectEnd
+ObjectEnd
+ObjectInstance "pair"
+AttributeBegin
+Translate 0 3 0
+ObjectInstance "pair"
+AttributeEnd
+ObjectInstance "missing"
+"#,
+        );
+
+        // Instances and nested definitions inside a definition are ignored.
+        // The definition ends at the first `ObjectEnd` and the second one
+        // only closes the attribute block opened by the nested definition.
+        let options = &api.render_options;
+        assert_eq!(options.current_instance, None);
+        assert_eq!(options.instances.len(), 1);
+        assert_eq!(options.instances["pair"].len(), 2);
+
+        // Each instance adds one primitive for the whole definition.
+        assert_eq!(options.primitives.len(), 2);
+        let bounds: Vec<Bounds3f> = options.primitives.iter().map(|p| p.world_bound()).collect();
+        assert!((bounds[0].p_min.x + 0.5).abs() < 1e-3 && (bounds[0].p_max.x - 1.5).abs() < 1e-3);
+        assert!((bounds[0].p_min.y + 0.5).abs() < 1e-3);
+        assert!((bounds[1].p_min.y - 2.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn transform_statements_set_the_current_transformation() {
+        let api = parse(
+            "transform_statements",
+            r#"
+WorldBegin
+Transform [1 0 0 0  0 2 0 0  0 0 3 0  4 5 6 1]
+ConcatTransform [1 0 0 0  0 1 0 0  0 0 1 0  1 0 0 1]
+"#,
+        );
+        let t = &api.current_transforms[0];
+        assert_eq!(t.m.m[3], [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            t.transform_point(&Point3f::new(0.0, 0.0, 0.0)),
+            Point3f::new(5.0, 5.0, 6.0)
+        );
+        assert_eq!(
+            t.transform_point(&Point3f::new(1.0, 1.0, 1.0)),
+            Point3f::new(6.0, 7.0, 9.0)
+        );
     }
 }
@@ -0,0 +1,283 @@
+//! JSON Documents
+
+use pest::iterators::Pair;
+use pest::Parser;
+use std::fmt;
+
+/// The `pest` parser generated from a grammar.
+#[derive(Parser)]
+#[grammar = "parser/json/grammar.pest"]
+struct JsonParser;
+
+/// A value in a JSON document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    /// The `null` literal.
+    Null,
+
+    /// A boolean.
+    Bool(bool),
+
+    /// A number.
+    Number(f64),
+
+    /// A string with escape sequences replaced.
+    String(String),
+
+    /// An array.
+    Array(Vec<JsonValue>),
+
+    /// An object's members in document order.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Parses a JSON document and returns its value.
+    ///
+    /// * `document` - The JSON document.
+    pub fn parse(document: &str) -> Result<Self, String> {
+        let json = JsonParser::parse(Rule::json, document)
+            .map_err(|err| format!("Error parsing JSON. {}", err))?
+            .next()
+            .unwrap();
+
+        Ok(Self::from_pair(json.into_inner().next().unwrap()))
+    }
+
+    /// Converts a value rule into a `JsonValue`.
+    ///
+    /// * `pair` - The token pair for the matched rule.
+    fn from_pair(pair: Pair<Rule>) -> Self {
+        match pair.as_rule() {
+            Rule::object => Self::Object(
+                pair.into_inner()
+                    .map(|member| {
+                        let mut inner_rules = member.into_inner();
+                        let name = Self::from_pair(inner_rules.next().unwrap());
+                        let value = Self::from_pair(inner_rules.next().unwrap());
+                        match name {
+                            Self::String(name) => (name, value),
+                            _ => unreachable!(),
+                        }
+                    })
+                    .collect(),
+            ),
+            Rule::array => Self::Array(pair.into_inner().map(Self::from_pair).collect()),
+            Rule::string => Self::String(unescape(pair.into_inner().next().unwrap().as_str())),
+            Rule::number => Self::Number(pair.as_str().parse().unwrap()),
+            Rule::boolean => Self::Bool(pair.as_str() == "true"),
+            Rule::null => Self::Null,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the value of an object member.
+    ///
+    /// * `name` - Name of the member.
+    pub fn get(&self, name: &str) -> Option<&Self> {
+        match self {
+            Self::Object(members) => members.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Returns the number or `None` for other values.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the string or `None` for other values.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the array elements or `None` for other values.
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Returns the numbers of an array with nested arrays flattened or `None`
+    /// if it contains other values.
+    pub fn as_numbers(&self) -> Option<Vec<f64>> {
+        match self {
+            Self::Number(n) => Some(vec![*n]),
+            Self::Array(values) => values.iter().try_fold(vec![], |mut numbers, value| {
+                numbers.extend(value.as_numbers()?);
+                Some(numbers)
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for JsonValue {
+    /// Formats the value as an indented JSON document. Arrays of numbers,
+    /// strings and literals are kept on one line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl JsonValue {
+    /// Returns whether the value is an array or an object.
+    fn is_container(&self) -> bool {
+        matches!(self, Self::Array(_) | Self::Object(_))
+    }
+
+    /// Writes the value with nested values indented.
+    ///
+    /// * `f`      - The formatter.
+    /// * `indent` - Indentation of the line the value starts on.
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Self::Number(_) => write!(f, "null"),
+            Self::String(s) => write!(f, "\"{}\"", escape(s)),
+            Self::Array(values) if !values.iter().any(|v| v.is_container()) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write_indented(f, indent)?;
+                }
+                write!(f, "]")
+            }
+            Self::Array(values) => {
+                writeln!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(f, "{:1$}", "", indent + 2)?;
+                    value.write_indented(f, indent + 2)?;
+                    writeln!(f, "{}", if i + 1 < values.len() { "," } else { "" })?;
+                }
+                write!(f, "{:1$}]", "", indent)
+            }
+            Self::Object(members) if members.is_empty() => write!(f, "{{}}"),
+            Self::Object(members) => {
+                writeln!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    write!(f, "{:1$}\"{2}\": ", "", indent + 2, escape(name))?;
+                    value.write_indented(f, indent + 2)?;
+                    writeln!(f, "{}", if i + 1 < members.len() { "," } else { "" })?;
+                }
+                write!(f, "{:1$}}}", "", indent)
+            }
+        }
+    }
+}
+
+/// Returns a string with the characters that JSON strings cannot contain
+/// replaced by escape sequences.
+///
+/// * `s` - The string.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Replaces the escape sequences in a JSON string.
+///
+/// * `s` - The string without its quotes.
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('b') => result.push('\u{8}'),
+            Some('f') => result.push('\u{c}'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let code = u32::from_str_radix(&hex, 16).unwrap_or(0xfffd);
+                result.push(std::char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            Some(c) => result.push(c),
+            None => (),
+        }
+    }
+    result
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_document() {
+        let value = JsonValue::parse(
+            r#"{ "name": "a\"b\u0041", "frames": [1, -2.5e1, [3]], "ok": true, "none": null }"#,
+        )
+        .unwrap();
+
+        assert_eq!(value.get("name").and_then(|v| v.as_str()), Some("a\"bA"));
+        assert_eq!(
+            value.get("frames").and_then(|v| v.as_numbers()),
+            Some(vec![1.0, -25.0, 3.0])
+        );
+        assert_eq!(value.get("ok"), Some(&JsonValue::Bool(true)));
+        assert_eq!(value.get("none"), Some(&JsonValue::Null));
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn format_document() {
+        let value = JsonValue::Object(vec![
+            (
+                String::from("name"),
+                JsonValue::String(String::from("a\"b\n")),
+            ),
+            (
+                String::from("rgb"),
+                JsonValue::Array(vec![JsonValue::Number(0.5), JsonValue::Number(1.0)]),
+            ),
+            (
+                String::from("lights"),
+                JsonValue::Array(vec![JsonValue::Object(vec![]), JsonValue::Null]),
+            ),
+        ]);
+
+        let document = value.to_string();
+        assert!(document.contains("  \"rgb\": [0.5, 1],\n"));
+        assert!(document.contains("\"lights\": [\n    {},\n    null\n  ]"));
+        assert_eq!(JsonValue::parse(&document), Ok(value));
+    }
+
+    #[test]
+    fn invalid_document() {
+        assert!(JsonValue::parse("{ \"a\": 01 }").is_err());
+        assert!(JsonValue::parse("[1, 2").is_err());
+    }
+}
@@ -0,0 +1,1258 @@
+//! Mitsuba XML Scene Parser
+
+mod obj;
+mod xml;
+
+use crate::Api;
+use core::fileutil::*;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use obj::*;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use xml::*;
+
+/// Mitsuba 0.6 and 3 XML scene parser. Plugins that have no equivalent are
+/// approximated by the closest available one or skipped with a warning.
+pub struct MitsubaFileParser {
+    /// Path to the file to parse.
+    file_path: String,
+
+    /// Parent path for resolving relative file names.
+    parent_path: String,
+}
+
+impl MitsubaFileParser {
+    /// Returns a new instance of `MitsubaFileParser`.
+    ///
+    /// * `path` - File path.
+    pub fn new(path: &str) -> Result<Self, String> {
+        match parent_path(path) {
+            Some(parent) => Ok(Self {
+                file_path: String::from(path),
+                parent_path: parent,
+            }),
+            // We were passed the root path itself which is not a file.
+            None => Err(format!("Invalid path '{}'", path)),
+        }
+    }
+
+    /// Reads a Mitsuba XML scene and calls the API wrapper functions.
+    ///
+    /// * `api` - The PBRT API interface.
+    pub fn parse(&self, api: &mut Api) -> Result<(), String> {
+        let mut scene = load_scene(&self.file_path, &self.parent_path, &mut vec![])?;
+
+        // Substitute `$name` references with values of `default` elements.
+        let mut defaults: Vec<(String, String)> = scene
+            .children_with_tag("default")
+            .filter_map(|d| Some((format!("${}", d.attribute("name")?), d.attribute("value")?)))
+            .map(|(name, value)| (name, String::from(value)))
+            .collect();
+        defaults.sort_by_key(|(name, _)| Reverse(name.len()));
+        substitute_defaults(&mut scene, &defaults);
+
+        // Images are named after the scene like Mitsuba does.
+        let stem = Path::new(&self.file_path)
+            .file_stem()
+            .map_or(String::from("mitsuba"), |s| {
+                s.to_string_lossy().into_owned()
+            });
+
+        MitsubaConverter::new(&self.parent_path, &stem).convert(&scene, api);
+        Ok(())
+    }
+}
+
+/// Loads a scene file and splices the contents of included files into it.
+/// Files that include themselves directly or indirectly are rejected.
+///
+/// * `path`        - Path to the file.
+/// * `parent_path` - Directory for resolving relative paths of includes.
+/// * `including`   - Files whose includes are being loaded.
+fn load_scene(
+    path: &str,
+    parent_path: &str,
+    including: &mut Vec<PathBuf>,
+) -> Result<XmlElement, String> {
+    let file = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    if including.contains(&file) {
+        return Err(format!("Mitsuba scene '{}' includes itself", path));
+    }
+
+    let document =
+        fs::read_to_string(path).map_err(|_| format!("Error reading file '{}'", path))?;
+    let mut scene = XmlElement::parse(&document)?;
+    if scene.tag != "scene" {
+        return Err(format!("'{}' is not a Mitsuba scene", path));
+    }
+    normalize_names(&mut scene);
+
+    let mut children = vec![];
+    for child in scene.children.drain(..) {
+        if child.tag != "include" {
+            children.push(child);
+        } else if let Some(filename) = child.attribute("filename") {
+            including.push(file.clone());
+            let included =
+                load_scene(&resolve_path(parent_path, filename), parent_path, including)?;
+            including.pop();
+            children.extend(included.children);
+        } else {
+            warn!("Ignoring Mitsuba include without a filename.");
+        }
+    }
+    scene.children = children;
+
+    Ok(scene)
+}
+
+/// Converts property names from the camel case used by Mitsuba 0.6 to the
+/// snake case used by Mitsuba 3, e.g. `toWorld` to `to_world`.
+///
+/// * `element` - The element whose descendants are updated.
+fn normalize_names(element: &mut XmlElement) {
+    for child in element.children.iter_mut() {
+        if child.tag != "default" {
+            if let Some(name) = child.attributes.get_mut("name") {
+                let mut snake_case = String::with_capacity(name.len() + 4);
+                for c in name.chars() {
+                    if c.is_ascii_uppercase() {
+                        snake_case.push('_');
+                    }
+                    snake_case.push(c.to_ascii_lowercase());
+                }
+                *name = snake_case;
+            }
+        }
+        normalize_names(child);
+    }
+}
+
+/// Replaces references to default values in the attributes of an element and
+/// its descendants.
+///
+/// * `element`  - The element.
+/// * `defaults` - The `$name` references and their values, longest first.
+fn substitute_defaults(element: &mut XmlElement, defaults: &[(String, String)]) {
+    for value in element.attributes.values_mut() {
+        for (name, default) in defaults.iter() {
+            if value.contains(name.as_str()) {
+                *value = value.replace(name.as_str(), default);
+            }
+        }
+    }
+    for child in element.children.iter_mut() {
+        substitute_defaults(child, defaults);
+    }
+}
+
+/// Returns a path resolved relative to a directory.
+///
+/// * `parent_path` - The directory.
+/// * `path`        - The path.
+fn resolve_path(parent_path: &str, path: &str) -> String {
+    if is_relative_path(path) && !parent_path.is_empty() {
+        format!("{}/{}", parent_path, path)
+    } else {
+        String::from(path)
+    }
+}
+
+/// Converts the elements of a Mitsuba scene into API calls.
+struct MitsubaConverter<'a> {
+    /// Parent path for resolving relative file names.
+    parent_path: String,
+
+    /// Name of the scene file without its extension.
+    scene_name: String,
+
+    /// Elements that can be referenced by their `id` attribute.
+    ids: HashMap<String, &'a XmlElement>,
+
+    /// Names of the materials and textures already created, prefixed by
+    /// their kind.
+    declared: HashSet<String>,
+
+    /// Number of names generated for anonymous materials and textures.
+    n_generated: usize,
+}
+
+impl<'a> MitsubaConverter<'a> {
+    /// Returns a new instance of `MitsubaConverter`.
+    ///
+    /// * `parent_path` - Parent path for resolving relative file names.
+    /// * `scene_name`  - Name of the scene file without its extension.
+    fn new(parent_path: &str, scene_name: &str) -> Self {
+        Self {
+            parent_path: String::from(parent_path),
+            scene_name: String::from(scene_name),
+            ids: HashMap::new(),
+            declared: HashSet::new(),
+            n_generated: 0,
+        }
+    }
+
+    /// Calls the API for the rendering options and the world described by a
+    /// scene and renders it.
+    ///
+    /// * `scene` - The `scene` element.
+    /// * `api`   - The PBRT API interface.
+    fn convert(&mut self, scene: &'a XmlElement, api: &mut Api) {
+        self.collect_ids(scene);
+
+        if let Some(integrator) = scene.children_with_tag("integrator").next() {
+            self.integrator(integrator, api);
+        }
+        match scene.children_with_tag("sensor").next() {
+            Some(sensor) => self.sensor(sensor, api),
+            None => warn!("Mitsuba scene has no sensor. Using the default camera."),
+        }
+
+        api.pbrt_world_begin();
+        for child in scene.children.iter() {
+            match child.tag.as_str() {
+                "bsdf" => {
+                    self.material(child, api);
+                }
+                "shape" => self.shape(child, api),
+                "emitter" => self.emitter(child, api),
+                "integrator" | "sensor" | "texture" | "default" => (),
+                tag => warn!("Ignoring Mitsuba element '{}'.", tag),
+            }
+        }
+        api.pbrt_world_end();
+    }
+
+    /// Records the elements of a subtree that have an `id` attribute.
+    ///
+    /// * `element` - Root of the subtree.
+    fn collect_ids(&mut self, element: &'a XmlElement) {
+        for child in element.children.iter() {
+            match child.attribute("id") {
+                Some(id) if child.tag != "ref" => {
+                    self.ids.insert(String::from(id), child);
+                }
+                _ => (),
+            }
+            self.collect_ids(child);
+        }
+    }
+
+    /// Returns a new name for an anonymous material or texture.
+    ///
+    /// * `kind` - Kind of object.
+    fn generate_name(&mut self, kind: &str) -> String {
+        self.n_generated += 1;
+        format!("mitsuba_{}_{}", kind, self.n_generated)
+    }
+
+    /// Returns an element or the element it references if it is a `ref`.
+    ///
+    /// * `element` - The element.
+    fn dereference(&self, element: &'a XmlElement) -> Option<&'a XmlElement> {
+        if element.tag != "ref" {
+            return Some(element);
+        }
+        let id = element.attribute("id").unwrap_or("");
+        let referenced = self.ids.get(id).copied();
+        if referenced.is_none() {
+            warn!("Mitsuba reference to undefined id '{}'.", id);
+        }
+        referenced
+    }
+
+    /// Returns the BSDFs nested in or referenced by an element.
+    ///
+    /// * `element` - The element.
+    fn nested_bsdfs(&self, element: &'a XmlElement) -> Vec<&'a XmlElement> {
+        element
+            .children
+            .iter()
+            .filter(|c| c.tag == "bsdf" || c.tag == "ref")
+            .filter_map(|c| self.dereference(c))
+            .filter(|c| c.tag == "bsdf")
+            .collect()
+    }
+
+    /// Sets the integrator. Only Whitted-style ray tracing is available so
+    /// every integrator is approximated by it.
+    ///
+    /// * `integrator` - The `integrator` element.
+    /// * `api`        - The PBRT API interface.
+    fn integrator(&mut self, integrator: &XmlElement, api: &mut Api) {
+        let integrator_type = integrator.attribute("type").unwrap_or("");
+        if integrator_type != "direct" {
+            warn!(
+                "Approximating Mitsuba integrator '{}' with 'whitted'.",
+                integrator_type
+            );
+        }
+
+        // Mitsuba uses -1 for unbounded paths.
+        let max_depth = find_int(integrator, "max_depth", -1);
+        let mut params = ParamSet::new();
+        params.add_int("maxdepth", &[if max_depth < 0 { 5 } else { max_depth }]);
+        api.pbrt_integrator(String::from("whitted"), &params);
+    }
+
+    /// Sets the camera, film, pixel filter and sampler.
+    ///
+    /// * `sensor` - The `sensor` element.
+    /// * `api`    - The PBRT API interface.
+    fn sensor(&mut self, sensor: &XmlElement, api: &mut Api) {
+        let film = sensor.children_with_tag("film").next();
+        let (width, height) = film.map_or((768, 576), |f| {
+            (find_int(f, "width", 768), find_int(f, "height", 576))
+        });
+
+        let mut film_params = ParamSet::new();
+        film_params.add_int("xresolution", &[width]);
+        film_params.add_int("yresolution", &[height]);
+        let extension = match film.and_then(|f| f.attribute("type")) {
+            Some("ldrfilm") => "png",
+            _ => "exr",
+        };
+        film_params.add_string("filename", &[format!("{}.{}", self.scene_name, extension)]);
+        api.pbrt_film(String::from("image"), &film_params);
+
+        if let Some(rfilter) = film.and_then(|f| f.children_with_tag("rfilter").next()) {
+            self.filter(rfilter, api);
+        }
+        if let Some(sampler) = sensor.children_with_tag("sampler").next() {
+            self.sampler(sampler, api);
+        }
+
+        // Mitsuba's camera space is right-handed with x pointing left.
+        api.pbrt_scale(-1.0, 1.0, 1.0);
+        if let Some(to_world) = find_transform(sensor, "to_world") {
+            api.pbrt_concat_transform(&column_major(&to_world.inverse()));
+        }
+
+        let sensor_type = sensor.attribute("type").unwrap_or("");
+        let mut params = ParamSet::new();
+        let name = match sensor_type {
+            "perspective" | "thinlens" => {
+                let fov = match find_string(sensor, "focal_length") {
+                    // Focal lengths refer to the width of a 35mm film.
+                    Some(f) => match f.trim_end_matches("mm").parse::<Float>() {
+                        Ok(f) => 2.0 * (18.0 / f).atan().to_degrees(),
+                        Err(_) => 50.0,
+                    },
+                    None => find_float(sensor, "fov", 50.0),
+                };
+                let fov_axis = find_string(sensor, "fov_axis").unwrap_or_else(|| "x".into());
+                params.add_float("fov", &[pbrt_fov(fov, &fov_axis, width, height)]);
+
+                if sensor_type == "thinlens" {
+                    let lens_radius = find_float(sensor, "aperture_radius", 0.03);
+                    let focal_distance = find_float(sensor, "focus_distance", 1.0);
+                    params.add_float("lensradius", &[lens_radius]);
+                    params.add_float("focaldistance", &[focal_distance]);
+                }
+                "perspective"
+            }
+            "orthographic" => "orthographic",
+            _ => {
+                warn!(
+                    "Mitsuba sensor '{}' not supported. Using 'perspective'.",
+                    sensor_type
+                );
+                "perspective"
+            }
+        };
+        params.add_float("nearclip", &[find_float(sensor, "near_clip", 0.0)]);
+        params.add_float("farclip", &[find_float(sensor, "far_clip", INFINITY)]);
+        api.pbrt_camera(String::from(name), &params);
+    }
+
+    /// Sets the pixel filter.
+    ///
+    /// * `rfilter` - The `rfilter` element.
+    /// * `api`     - The PBRT API interface.
+    fn filter(&mut self, rfilter: &XmlElement, api: &mut Api) {
+        let filter_type = rfilter.attribute("type").unwrap_or("");
+        let mut params = ParamSet::new();
+        let (name, width) = match filter_type {
+            "box" => ("box", find_float(rfilter, "radius", 0.5)),
+            "tent" => ("triangle", find_float(rfilter, "radius", 1.0)),
+            "gaussian" => {
+                let stddev = find_float(rfilter, "stddev", 0.5);
+                params.add_float("alpha", &[1.0 / (2.0 * stddev * stddev)]);
+                ("gaussian", 4.0 * stddev)
+            }
+            "mitchell" | "catmullrom" => {
+                let (b, c) = if filter_type == "mitchell" {
+                    (
+                        find_float(rfilter, "b", 1.0 / 3.0),
+                        find_float(rfilter, "c", 1.0 / 3.0),
+                    )
+                } else {
+                    (0.0, 0.5)
+                };
+                params.add_float("B", &[b]);
+                params.add_float("C", &[c]);
+                ("mitchell", 2.0)
+            }
+            "lanczos" => {
+                let lobes = find_int(rfilter, "lobes", 3) as Float;
+                params.add_float("tau", &[lobes]);
+                ("sinc", lobes)
+            }
+            _ => {
+                warn!("Mitsuba filter '{}' not supported.", filter_type);
+                return;
+            }
+        };
+        params.add_float("xwidth", &[width]);
+        params.add_float("ywidth", &[width]);
+        api.pbrt_pixel_filter(String::from(name), &params);
+    }
+
+    /// Sets the sampler.
+    ///
+    /// * `sampler` - The `sampler` element.
+    /// * `api`     - The PBRT API interface.
+    fn sampler(&mut self, sampler: &XmlElement, api: &mut Api) {
+        let sampler_type = sampler.attribute("type").unwrap_or("");
+        let sample_count = max(1, find_int(sampler, "sample_count", 4));
+        let mut params = ParamSet::new();
+        let name = match sampler_type {
+            "stratified" | "multijitter" | "orthogonal" => {
+                let n = (sample_count as Float).sqrt().ceil() as Int;
+                params.add_int("xsamples", &[n]);
+                params.add_int("ysamples", &[n]);
+                "stratified"
+            }
+            "independent" => "random",
+            "ldsampler" => "02sequence",
+            "halton" | "hammersley" => "halton",
+            "sobol" => "sobol",
+            _ => {
+                warn!(
+                    "Mitsuba sampler '{}' not supported. Using 'random'.",
+                    sampler_type
+                );
+                "random"
+            }
+        };
+        params.add_int("pixelsamples", &[sample_count]);
+        api.pbrt_sampler(String::from(name), &params);
+    }
+
+    /// Creates a named material for a BSDF unless it was already created and
+    /// returns its name.
+    ///
+    /// * `bsdf` - The `bsdf` element.
+    /// * `api`  - The PBRT API interface.
+    fn material(&mut self, bsdf: &'a XmlElement, api: &mut Api) -> Option<String> {
+        let name = match bsdf.attribute("id") {
+            Some(id) => String::from(id),
+            None => self.generate_name("bsdf"),
+        };
+        let key = format!("material:{}", name);
+        if !self.declared.contains(&key) {
+            let params = self.material_params(bsdf, api)?;
+            api.pbrt_make_named_material(name.clone(), &params);
+            self.declared.insert(key);
+        }
+        Some(name)
+    }
+
+    /// Returns the parameters of the material that approximates a BSDF.
+    ///
+    /// * `bsdf` - The `bsdf` element.
+    /// * `api`  - The PBRT API interface.
+    fn material_params(&mut self, bsdf: &'a XmlElement, api: &mut Api) -> Option<ParamSet> {
+        let bsdf_type = bsdf.attribute("type").unwrap_or("");
+        let mut params = ParamSet::new();
+        let name = match bsdf_type {
+            "twosided" => {
+                // Surfaces are two-sided already.
+                let nested = self.nested_bsdfs(bsdf);
+                return self.material_params(nested.first()?, api);
+            }
+            "diffuse" | "roughdiffuse" => {
+                self.spectrum_param(bsdf, "reflectance", "Kd", 0.5, &mut params, api);
+                if bsdf_type == "roughdiffuse" {
+                    // Convert the RMS slope to the standard deviation of the
+                    // facet angle in degrees.
+                    let alpha = find_float(bsdf, "alpha", 0.2);
+                    params.add_float("sigma", &[alpha.atan().to_degrees()]);
+                }
+                "matte"
+            }
+            "plastic" | "roughplastic" | "conductor" | "roughconductor" => {
+                if bsdf_type.ends_with("conductor") {
+                    warn!("Approximating Mitsuba BSDF '{}' with 'plastic'.", bsdf_type);
+                    params.add_rgb_spectrum("Kd", &[0.0, 0.0, 0.0]);
+                } else {
+                    self.spectrum_param(bsdf, "diffuse_reflectance", "Kd", 0.5, &mut params, api);
+                }
+                self.spectrum_param(bsdf, "specular_reflectance", "Ks", 1.0, &mut params, api);
+
+                // Smooth surfaces use a tiny roughness.
+                let alpha = if bsdf_type.starts_with("rough") {
+                    0.1
+                } else {
+                    0.001
+                };
+                self.float_param(bsdf, "alpha", "roughness", alpha, &mut params, api);
+                params.add_bool("remaproughness", &[false]);
+                "plastic"
+            }
+            "measured" => {
+                let filename = find_string(bsdf, "filename").unwrap_or_default();
+                params.add_string("filename", &[resolve_path(&self.parent_path, &filename)]);
+                "measured"
+            }
+            "blendbsdf" => {
+                let nested = self.nested_bsdfs(bsdf);
+                if nested.len() != 2 {
+                    warn!("Mitsuba BSDF 'blendbsdf' needs two nested BSDFs.");
+                    return None;
+                }
+                let m1 = self.material(nested[0], api)?;
+                let m2 = self.material(nested[1], api)?;
+                params.add_string("namedmaterial1", &[m1]);
+                params.add_string("namedmaterial2", &[m2]);
+
+                // The weight selects the second BSDF.
+                params.add_float("amount", &[1.0 - find_float(bsdf, "weight", 0.5)]);
+                "mix"
+            }
+            _ => {
+                warn!("Mitsuba BSDF '{}' not supported. Using 'matte'.", bsdf_type);
+                "matte"
+            }
+        };
+        params.add_string("type", &[String::from(name)]);
+        Some(params)
+    }
+
+    /// Creates a texture unless it was already created and returns its name.
+    ///
+    /// * `texture`      - The `texture` element.
+    /// * `texture_type` - Type of the values, `spectrum` or `float`.
+    /// * `api`          - The PBRT API interface.
+    fn texture(
+        &mut self,
+        texture: &'a XmlElement,
+        texture_type: &str,
+        api: &mut Api,
+    ) -> Option<String> {
+        let name = match texture.attribute("id") {
+            Some(id) => String::from(id),
+            None => self.generate_name("texture"),
+        };
+        let key = format!("{}:{}", texture_type, name);
+        if self.declared.contains(&key) {
+            return Some(name);
+        }
+
+        let class = texture.attribute("type").unwrap_or("");
+        let mut params = ParamSet::new();
+        let texture_class = match class {
+            "bitmap" => {
+                let filename = find_string(texture, "filename").unwrap_or_default();
+                params.add_string("filename", &[resolve_path(&self.parent_path, &filename)]);
+                let wrap = match find_string(texture, "wrap_mode").as_deref() {
+                    Some("clamp") => "clamp",
+                    _ => "repeat",
+                };
+                params.add_string("wrap", &[String::from(wrap)]);
+                "imagemap"
+            }
+            "checkerboard" => {
+                for (mitsuba_name, pbrt_name, default) in
+                    [("color0", "tex1", 0.4), ("color1", "tex2", 0.2)].iter()
+                {
+                    if texture_type == "float" {
+                        self.float_param(
+                            texture,
+                            mitsuba_name,
+                            pbrt_name,
+                            *default,
+                            &mut params,
+                            api,
+                        );
+                    } else {
+                        self.spectrum_param(
+                            texture,
+                            mitsuba_name,
+                            pbrt_name,
+                            *default,
+                            &mut params,
+                            api,
+                        );
+                    }
+                }
+                "checkerboard"
+            }
+            _ => {
+                warn!("Mitsuba texture '{}' not supported.", class);
+                return None;
+            }
+        };
+
+        // Only the scale and offset of the texture coordinates carry over.
+        if let Some(to_uv) = find_transform(texture, "to_uv") {
+            let m = to_uv.m.m;
+            params.add_float("uscale", &[m[0][0]]);
+            params.add_float("vscale", &[m[1][1]]);
+            params.add_float("udelta", &[m[0][3]]);
+            params.add_float("vdelta", &[m[1][3]]);
+        }
+
+        api.pbrt_texture(
+            name.clone(),
+            String::from(texture_type),
+            String::from(texture_class),
+            &params,
+        );
+        self.declared.insert(key);
+        Some(name)
+    }
+
+    /// Adds a spectrum parameter for a Mitsuba property that can be a colour
+    /// or a texture.
+    ///
+    /// * `element`      - Element with the property.
+    /// * `name`         - Name of the property.
+    /// * `pbrt_name`    - Name of the parameter.
+    /// * `default`      - Value used when the property is missing.
+    /// * `params`       - The parameter set to update.
+    /// * `api`          - The PBRT API interface.
+    fn spectrum_param(
+        &mut self,
+        element: &'a XmlElement,
+        name: &str,
+        pbrt_name: &str,
+        default: Float,
+        params: &mut ParamSet,
+        api: &mut Api,
+    ) {
+        let property = element.child_named(name).and_then(|p| self.dereference(p));
+        match property {
+            Some(p) if p.tag == "texture" => {
+                if let Some(texture) = self.texture(p, "spectrum", api) {
+                    params.add_texture(pbrt_name, &[texture]);
+                    return;
+                }
+            }
+            Some(p) if p.tag == "spectrum" && p.attribute("filename").is_some() => {
+                let filename = p.attribute("filename").unwrap();
+                let path = resolve_path(&self.parent_path, filename);
+                params.add_sampled_spectrum_files(pbrt_name, &[path]);
+                return;
+            }
+            Some(p) if p.tag == "spectrum" && p.attribute("value").unwrap_or("").contains(':') => {
+                let samples: Vec<Float> = p
+                    .attribute("value")
+                    .unwrap()
+                    .split(',')
+                    .flat_map(|s| parse_floats(&s.replace(':', " ")))
+                    .collect();
+                params.add_sampled_spectrum(pbrt_name, &samples);
+                return;
+            }
+            Some(p) if p.tag == "blackbody" => {
+                let temperature = p.attribute("temperature").unwrap_or("6500");
+                let temperature = parse_floats(temperature.trim_end_matches('K'));
+                if let Some(t) = temperature.first() {
+                    params.add_blackbody_spectrum(pbrt_name, &[*t, 1.0]);
+                    return;
+                }
+            }
+            Some(p) => match parse_colour(p) {
+                Some(rgb) => {
+                    params.add_rgb_spectrum(pbrt_name, &rgb);
+                    return;
+                }
+                None => warn!("Mitsuba property '{}' is not a colour.", name),
+            },
+            None => (),
+        }
+        params.add_rgb_spectrum(pbrt_name, &[default; 3]);
+    }
+
+    /// Adds a float parameter for a Mitsuba property that can be a value or a
+    /// texture.
+    ///
+    /// * `element`      - Element with the property.
+    /// * `name`         - Name of the property.
+    /// * `pbrt_name`    - Name of the parameter.
+    /// * `default`      - Value used when the property is missing.
+    /// * `params`       - The parameter set to update.
+    /// * `api`          - The PBRT API interface.
+    fn float_param(
+        &mut self,
+        element: &'a XmlElement,
+        name: &str,
+        pbrt_name: &str,
+        default: Float,
+        params: &mut ParamSet,
+        api: &mut Api,
+    ) {
+        let property = element.child_named(name).and_then(|p| self.dereference(p));
+        if let Some(p) = property.filter(|p| p.tag == "texture") {
+            if let Some(texture) = self.texture(p, "float", api) {
+                params.add_texture(pbrt_name, &[texture]);
+                return;
+            }
+        }
+        params.add_float(pbrt_name, &[find_float(element, name, default)]);
+    }
+
+    /// Adds a shape with its material and area light.
+    ///
+    /// * `shape` - The `shape` element.
+    /// * `api`   - The PBRT API interface.
+    fn shape(&mut self, shape: &'a XmlElement, api: &mut Api) {
+        let shape_type = shape.attribute("type").unwrap_or("");
+
+        // Materials and textures are created outside the attribute block so
+        // they remain defined for shapes sharing them.
+        let material = match self.nested_bsdfs(shape).first() {
+            Some(bsdf) => self.material(bsdf, api),
+            None => None,
+        };
+
+        api.pbrt_attribute_begin();
+        if let Some(name) = material {
+            api.pbrt_named_material(name);
+        }
+        if let Some(emitter) = shape.children_with_tag("emitter").next() {
+            let mut params = ParamSet::new();
+            self.spectrum_param(emitter, "radiance", "L", 1.0, &mut params, api);
+            api.pbrt_area_light_source(String::from("diffuse"), &params);
+        }
+        if let Some(to_world) = find_transform(shape, "to_world") {
+            api.pbrt_concat_transform(&column_major(&to_world));
+        }
+        if find_bool(shape, "flip_normals", false) {
+            api.pbrt_reverse_orientation();
+        }
+
+        let mut params = ParamSet::new();
+        let name = match shape_type {
+            "sphere" => {
+                let center = find_point(shape, "center").unwrap_or_default();
+                api.pbrt_translate(center.x, center.y, center.z);
+                params.add_float("radius", &[find_float(shape, "radius", 1.0)]);
+                "sphere"
+            }
+            "disk" => "disk",
+            "cylinder" => {
+                let p0 = find_point(shape, "p0").unwrap_or_default();
+                let p1 = find_point(shape, "p1").unwrap_or_else(|| Point3f::new(0.0, 0.0, 1.0));
+                let axis = p1 - p0;
+                if axis.length() > 0.0 {
+                    // Align the z-axis with the cylinder axis.
+                    let up = if abs(axis.x) < 0.9 * axis.length() {
+                        Vector3f::new(1.0, 0.0, 0.0)
+                    } else {
+                        Vector3f::new(0.0, 1.0, 0.0)
+                    };
+                    let frame = Transform::look_at(&p0, &p1, &up).inverse();
+                    api.pbrt_concat_transform(&column_major(&frame));
+                }
+                params.add_float("radius", &[find_float(shape, "radius", 1.0)]);
+                params.add_float("zmin", &[0.0]);
+                params.add_float("zmax", &[axis.length()]);
+                "cylinder"
+            }
+            "rectangle" => {
+                add_quads(&mut params, &[(2, 1.0)]);
+                "trianglemesh"
+            }
+            "cube" => {
+                let faces: Vec<(usize, Float)> = (0..6)
+                    .map(|i| (i / 2, if i % 2 == 0 { 1.0 } else { -1.0 }))
+                    .collect();
+                add_quads(&mut params, &faces);
+                "trianglemesh"
+            }
+            "obj" => {
+                let filename = find_string(shape, "filename").unwrap_or_default();
+                let path = resolve_path(&self.parent_path, &filename);
+                match load_obj(&path, find_bool(shape, "face_normals", false)) {
+                    Ok(mesh) => {
+                        params = mesh;
+                        "trianglemesh"
+                    }
+                    Err(err) => {
+                        error!("{}", err);
+                        ""
+                    }
+                }
+            }
+            _ => {
+                warn!("Mitsuba shape '{}' not supported.", shape_type);
+                ""
+            }
+        };
+        if !name.is_empty() {
+            api.pbrt_shape(String::from(name), &params);
+        }
+
+        api.pbrt_attribute_end();
+    }
+
+    /// Adds a light source.
+    ///
+    /// * `emitter` - The `emitter` element.
+    /// * `api`     - The PBRT API interface.
+    fn emitter(&mut self, emitter: &'a XmlElement, api: &mut Api) {
+        let emitter_type = emitter.attribute("type").unwrap_or("");
+        let mut params = ParamSet::new();
+
+        api.pbrt_attribute_begin();
+        if let Some(to_world) = find_transform(emitter, "to_world") {
+            api.pbrt_concat_transform(&column_major(&to_world));
+        }
+
+        let name = match emitter_type {
+            "point" | "spot" => {
+                if emitter_type == "spot" {
+                    warn!("Approximating Mitsuba emitter 'spot' with 'point'.");
+                }
+                self.spectrum_param(emitter, "intensity", "I", 1.0, &mut params, api);
+                params.add_point3f(
+                    "from",
+                    &[find_point(emitter, "position").unwrap_or_default()],
+                );
+                "point"
+            }
+            "directional" => {
+                self.spectrum_param(emitter, "irradiance", "L", 1.0, &mut params, api);
+                let d =
+                    find_point(emitter, "direction").unwrap_or_else(|| Point3f::new(0.0, 0.0, 1.0));
+                params.add_point3f("from", &[Point3f::default()]);
+                params.add_point3f("to", &[d]);
+                "distant"
+            }
+            "constant" => {
+                self.spectrum_param(emitter, "radiance", "L", 1.0, &mut params, api);
+                "infinite"
+            }
+            "envmap" => {
+                let filename = find_string(emitter, "filename").unwrap_or_default();
+                params.add_string("mapname", &[resolve_path(&self.parent_path, &filename)]);
+                params.add_rgb_spectrum("scale", &[find_float(emitter, "scale", 1.0); 3]);
+
+                // Mitsuba environment maps have the y-axis up.
+                api.pbrt_rotate(-90.0, 1.0, 0.0, 0.0);
+                "infinite"
+            }
+            _ => {
+                warn!("Mitsuba emitter '{}' not supported.", emitter_type);
+                ""
+            }
+        };
+        if !name.is_empty() {
+            api.pbrt_light_source(String::from(name), &params);
+        }
+
+        api.pbrt_attribute_end();
+    }
+}
+
+/// Returns the floating point values in a string separated by commas or
+/// whitespace.
+///
+/// * `s` - The string.
+fn parse_floats(s: &str) -> Vec<Float> {
+    s.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|t| !t.is_empty())
+        .filter_map(|t| t.parse().ok())
+        .collect()
+}
+
+/// Returns the value of a `float` or `integer` property.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+/// * `default` - Value used when the property is missing.
+fn find_float(element: &XmlElement, name: &str, default: Float) -> Float {
+    element
+        .child_named(name)
+        .filter(|p| p.tag == "float" || p.tag == "integer")
+        .and_then(|p| p.attribute("value"))
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(default)
+}
+
+/// Returns the value of an `integer` property.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+/// * `default` - Value used when the property is missing.
+fn find_int(element: &XmlElement, name: &str, default: Int) -> Int {
+    find_float(element, name, default as Float) as Int
+}
+
+/// Returns the value of a `boolean` property.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+/// * `default` - Value used when the property is missing.
+fn find_bool(element: &XmlElement, name: &str, default: bool) -> bool {
+    match element
+        .child_named(name)
+        .filter(|p| p.tag == "boolean")
+        .and_then(|p| p.attribute("value"))
+    {
+        Some(value) => value.trim() == "true",
+        None => default,
+    }
+}
+
+/// Returns the value of a `string` property.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+fn find_string(element: &XmlElement, name: &str) -> Option<String> {
+    element
+        .child_named(name)
+        .filter(|p| p.tag == "string")
+        .and_then(|p| p.attribute("value"))
+        .map(String::from)
+}
+
+/// Returns the value of a `point` or `vector` property.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+fn find_point(element: &XmlElement, name: &str) -> Option<Point3f> {
+    element
+        .child_named(name)
+        .filter(|p| p.tag == "point" || p.tag == "vector")
+        .map(|p| {
+            let v = vector_attributes(p, 0.0);
+            Point3f::new(v.x, v.y, v.z)
+        })
+}
+
+/// Returns a vector from the `value` attribute with one or three values or
+/// from the `x`, `y` and `z` attributes.
+///
+/// * `element` - The element.
+/// * `default` - Value of missing components.
+fn vector_attributes(element: &XmlElement, default: Float) -> Vector3f {
+    if let Some(value) = element.attribute("value") {
+        match parse_floats(value)[..] {
+            [v] => return Vector3f::new(v, v, v),
+            [x, y, z] => return Vector3f::new(x, y, z),
+            _ => warn!("Expected 1 or 3 values in '{}'.", value),
+        }
+    }
+    let component = |name: &str| {
+        element
+            .attribute(name)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(default)
+    };
+    Vector3f::new(component("x"), component("y"), component("z"))
+}
+
+/// Returns the transformation of a `transform` property. Each operation is
+/// applied after the previous ones.
+///
+/// * `element` - Element with the property.
+/// * `name`    - Name of the property.
+fn find_transform(element: &XmlElement, name: &str) -> Option<Transform> {
+    let transform = element.child_named(name).filter(|p| p.tag == "transform")?;
+
+    let mut t = Transform::default();
+    for op in transform.children.iter() {
+        let m = match op.tag.as_str() {
+            "translate" => Transform::translate(&vector_attributes(op, 0.0)),
+            "scale" => {
+                let s = vector_attributes(op, 1.0);
+                Transform::scale(s.x, s.y, s.z)
+            }
+            "rotate" => {
+                let angle = op.attribute("angle").map_or(vec![], parse_floats);
+                let axis = vector_attributes(op, 0.0);
+                Transform::rotate_axis(angle.first().copied().unwrap_or(0.0), &axis)
+            }
+            "matrix" => {
+                let v = parse_floats(op.attribute("value").unwrap_or(""));
+                match v.len() {
+                    16 => Transform::from(Matrix4x4::new(
+                        v[0], v[1], v[2], v[3], v[4], v[5], v[6], v[7], v[8], v[9], v[10], v[11],
+                        v[12], v[13], v[14], v[15],
+                    )),
+                    9 => Transform::from(Matrix4x4::new(
+                        v[0], v[1], v[2], 0.0, v[3], v[4], v[5], 0.0, v[6], v[7], v[8], 0.0, 0.0,
+                        0.0, 0.0, 1.0,
+                    )),
+                    n => {
+                        warn!("Expected 9 or 16 values in Mitsuba matrix, got {}.", n);
+                        continue;
+                    }
+                }
+            }
+            "lookat" => {
+                let point = |name: &str, default: [Float; 3]| match op.attribute(name) {
+                    Some(v) => match parse_floats(v)[..] {
+                        [x, y, z] => Vector3f::new(x, y, z),
+                        _ => Vector3f::new(default[0], default[1], default[2]),
+                    },
+                    None => Vector3f::new(default[0], default[1], default[2]),
+                };
+                let origin = point("origin", [0.0, 0.0, 0.0]);
+                let target = point("target", [0.0, 0.0, 1.0]);
+                let up = point("up", [0.0, 1.0, 0.0]);
+                Transform::look_at(&Point3f::from(origin), &Point3f::from(target), &up).inverse()
+            }
+            tag => {
+                warn!("Mitsuba transform '{}' not supported.", tag);
+                continue;
+            }
+        };
+        t = m * t;
+    }
+    Some(t)
+}
+
+/// Returns the matrix of a transformation in the column-major order used by
+/// `Api::pbrt_concat_transform()`.
+///
+/// * `t` - The transformation.
+fn column_major(t: &Transform) -> [Float; 16] {
+    let mut tr = [0.0; 16];
+    for (i, row) in t.m.m.iter().enumerate() {
+        for (j, v) in row.iter().enumerate() {
+            tr[4 * j + i] = *v;
+        }
+    }
+    tr
+}
+
+/// Returns the linear RGB value of a colour property. Values of `srgb`
+/// properties and hexadecimal values are gamma encoded.
+///
+/// * `property` - The property.
+fn parse_colour(property: &XmlElement) -> Option<[Float; 3]> {
+    let value = property.attribute("value")?.trim();
+    let (rgb, encoded) = if let Some(hex) = value.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(2 * i..2 * i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .map(|c| c as Float / 255.0)
+        };
+        ([channel(0)?, channel(1)?, channel(2)?], true)
+    } else {
+        match parse_floats(value)[..] {
+            [v] => ([v, v, v], false),
+            [r, g, b] => ([r, g, b], false),
+            _ => return None,
+        }
+    };
+
+    match property.tag.as_str() {
+        "rgb" | "color" if !encoded => Some(rgb),
+        "rgb" | "color" | "srgb" => Some([
+            inv_gamma_correct(rgb[0]),
+            inv_gamma_correct(rgb[1]),
+            inv_gamma_correct(rgb[2]),
+        ]),
+        "float" | "spectrum" => Some(rgb),
+        _ => None,
+    }
+}
+
+/// Adds the parameters of a `trianglemesh` made of axis aligned unit squares
+/// centered on the faces of the cube `[-1, 1]^3`.
+///
+/// * `params` - The parameter set to update.
+/// * `faces`  - The axis and direction of the normal of each square.
+fn add_quads(params: &mut ParamSet, faces: &[(usize, Float)]) {
+    let (mut p, mut n, mut uv, mut indices) = (vec![], vec![], vec![], vec![]);
+    for &(axis, sign) in faces.iter() {
+        let base = p.len() as Int;
+        for &(u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            let mut c = [0.0; 3];
+            c[axis] = sign;
+            c[(axis + 1) % 3] = u;
+            c[(axis + 2) % 3] = v;
+            p.push(Point3f::new(c[0], c[1], c[2]));
+
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            n.push(Normal3f::new(normal[0], normal[1], normal[2]));
+            uv.push(Point2f::new(0.5 * (u + 1.0), 0.5 * (v + 1.0)));
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+    params.add_point3f("P", &p);
+    params.add_normal3f("N", &n);
+    params.add_point2f("uv", &uv);
+    params.add_int("indices", &indices);
+}
+
+/// Returns the field of view of the shorter image axis used by perspective
+/// cameras for a Mitsuba field of view.
+///
+/// * `fov`      - Field of view in degrees.
+/// * `fov_axis` - Axis of the field of view.
+/// * `width`    - Image width.
+/// * `height`   - Image height.
+fn pbrt_fov(fov: Float, fov_axis: &str, width: Int, height: Int) -> Float {
+    let (w, h) = (width as Float, height as Float);
+    let aspect = w / h;
+    let t = (fov.to_radians() * 0.5).tan();
+    let tan_x = match fov_axis {
+        "y" => t * aspect,
+        "diagonal" => t * w / (w * w + h * h).sqrt(),
+        "smaller" if w > h => t * aspect,
+        "larger" if w < h => t * aspect,
+        _ => t,
+    };
+    let tan_shorter = if w >= h { tan_x / aspect } else { tan_x };
+    2.0 * tan_shorter.atan().to_degrees()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::*;
+
+    /// Writes Mitsuba scenes to the temporary directory and returns the
+    /// directory and the path of the first one.
+    ///
+    /// * `files` - Names and contents of the files.
+    fn write_scenes(files: &[(&str, &str)]) -> (String, String) {
+        let dir = std::env::temp_dir();
+        for (name, contents) in files.iter() {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let path = dir.join(files[0].0).to_string_lossy().into_owned();
+        (dir.to_string_lossy().into_owned(), path)
+    }
+
+    #[test]
+    fn invalid_path_is_an_error() {
+        assert!(MitsubaFileParser::new("/").is_err());
+        assert!(MitsubaFileParser::new("scene.xml").is_ok());
+    }
+
+    #[test]
+    fn includes_are_spliced() {
+        // The same file may be included more than once.
+        let (dir, path) = write_scenes(&[
+            (
+                "mitsuba_include_main.xml",
+                r#"<scene version="3.0.0">
+                    <include filename="mitsuba_include_bsdf.xml"/>
+                    <include filename="mitsuba_include_bsdf.xml"/>
+                    <shape type="sphere"/>
+                </scene>"#,
+            ),
+            (
+                "mitsuba_include_bsdf.xml",
+                r#"<scene version="3.0.0"><bsdf type="diffuse"/></scene>"#,
+            ),
+        ]);
+        let scene = load_scene(&path, &dir, &mut vec![]).unwrap();
+        let tags: Vec<&str> = scene.children.iter().map(|c| c.tag.as_str()).collect();
+        assert_eq!(tags, ["bsdf", "bsdf", "shape"]);
+    }
+
+    #[test]
+    fn include_cycles_are_rejected() {
+        let (dir, path) = write_scenes(&[
+            (
+                "mitsuba_cycle_a.xml",
+                r#"<scene version="3.0.0"><include filename="mitsuba_cycle_b.xml"/></scene>"#,
+            ),
+            (
+                "mitsuba_cycle_b.xml",
+                r#"<scene version="3.0.0"><include filename="mitsuba_cycle_a.xml"/></scene>"#,
+            ),
+        ]);
+        let err = load_scene(&path, &dir, &mut vec![]).unwrap_err();
+        assert!(err.contains("includes itself"), "{}", err);
+    }
+
+    #[test]
+    fn names_and_defaults_are_resolved() {
+        let mut scene = XmlElement::parse(
+            r#"<scene version="0.6.0">
+                <default name="depth" value="8"/>
+                <integrator type="path">
+                    <integer name="maxDepth" value="$depth"/>
+                </integrator>
+            </scene>"#,
+        )
+        .unwrap();
+        normalize_names(&mut scene);
+        substitute_defaults(&mut scene, &[(String::from("$depth"), String::from("8"))]);
+
+        let integrator = scene.children_with_tag("integrator").next().unwrap();
+        assert_eq!(find_int(integrator, "max_depth", -1), 8);
+    }
+
+    #[test]
+    fn transforms_are_applied_in_order() {
+        let shape = XmlElement::parse(
+            r#"<shape type="sphere">
+                <transform name="to_world">
+                    <translate x="1"/>
+                    <scale value="2"/>
+                </transform>
+            </shape>"#,
+        )
+        .unwrap();
+        let t = find_transform(&shape, "to_world").unwrap();
+        let p = t.transform_point(&Point3f::default());
+        assert_eq!(p, Point3f::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn field_of_view_is_converted_to_shorter_axis() {
+        let fov = pbrt_fov(90.0, "x", 200, 100);
+        assert!((fov - 2.0 * (0.5 as Float).atan().to_degrees()).abs() < 1e-3);
+        assert!((pbrt_fov(60.0, "y", 200, 100) - 60.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn mitsuba_scene_renders() {
+        // A diffuse rectangle filling the view under a uniform environment.
+        let scene = |reflectance: &str| {
+            format!(
+                r#"<scene version="3.0.0">
+    <integrator type="path"><integer name="max_depth" value="1"/></integrator>
+    <sensor type="orthographic">
+        <transform name="to_world">
+            <lookat origin="0, 0, 2" target="0, 0, 0" up="0, 1, 0"/>
+        </transform>
+        <sampler type="independent"><integer name="sample_count" value="16"/></sampler>
+        <film type="hdrfilm">
+            <integer name="width" value="4"/>
+            <integer name="height" value="4"/>
+        </film>
+    </sensor>
+    <emitter type="constant"><rgb name="radiance" value="1, 1, 1"/></emitter>
+    <shape type="rectangle">
+        <bsdf type="diffuse"><rgb name="reflectance" value="{}"/></bsdf>
+    </shape>
+</scene>"#,
+                reflectance
+            )
+        };
+        let grey = average(&render_file("mitsuba_grey", "xml", &scene("0.5, 0.5, 0.5")));
+        let black = average(&render_file("mitsuba_black", "xml", &scene("0, 0, 0")));
+        assert!((grey - 0.5).abs() < 0.1, "{}", grey);
+        assert_eq!(black, 0.0);
+    }
+}
@@ -0,0 +1,118 @@
+//! Wavefront OBJ Meshes
+
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use std::collections::HashMap;
+use std::fs;
+
+/// Loads the polygons of a Wavefront OBJ file as the parameters of a
+/// `trianglemesh` shape. Polygons are triangulated as fans and vertices
+/// that share a position, texture coordinate and normal are merged.
+///
+/// * `path`         - Path to the file.
+/// * `face_normals` - Ignore vertex normals so the mesh is flat shaded.
+pub fn load_obj(path: &str, face_normals: bool) -> Result<ParamSet, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|err| format!("Error reading '{}'. {}", path, err))?;
+
+    let mut positions: Vec<Point3f> = vec![];
+    let mut uvs: Vec<Point2f> = vec![];
+    let mut normals: Vec<Normal3f> = vec![];
+
+    let mut p: Vec<Point3f> = vec![];
+    let mut uv: Vec<Point2f> = vec![];
+    let mut n: Vec<Normal3f> = vec![];
+    let mut indices: Vec<Int> = vec![];
+    let mut vertices: HashMap<(usize, Option<usize>, Option<usize>), Int> = HashMap::new();
+    let (mut has_uv, mut has_n) = (true, !face_normals);
+
+    for (line_no, line) in contents.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let err = || format!("Error parsing '{}', line {}.", path, line_no + 1);
+        match tokens.next() {
+            Some("v") => {
+                let v = parse_floats(tokens, 3).ok_or_else(err)?;
+                positions.push(Point3f::new(v[0], v[1], v[2]));
+            }
+            Some("vt") => {
+                let v = parse_floats(tokens, 2).ok_or_else(err)?;
+                uvs.push(Point2f::new(v[0], v[1]));
+            }
+            Some("vn") => {
+                let v = parse_floats(tokens, 3).ok_or_else(err)?;
+                normals.push(Normal3f::new(v[0], v[1], v[2]));
+            }
+            Some("f") => {
+                let mut face = vec![];
+                for token in tokens {
+                    let mut refs = token.split('/');
+                    let vi = resolve_index(refs.next(), positions.len()).ok_or_else(err)?;
+                    let ti = resolve_index(refs.next(), uvs.len());
+                    let ni = resolve_index(refs.next(), normals.len());
+                    has_uv = has_uv && ti.is_some();
+                    has_n = has_n && ni.is_some();
+
+                    let next = p.len() as Int;
+                    let index = *vertices.entry((vi, ti, ni)).or_insert(next);
+                    if index == next {
+                        p.push(positions[vi]);
+                        uv.push(ti.map_or(Point2f::default(), |i| uvs[i]));
+                        n.push(ni.map_or(Normal3f::default(), |i| normals[i]));
+                    }
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    return Err(err());
+                }
+                for i in 1..face.len() - 1 {
+                    indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => (), // Ignore groups, materials and smoothing.
+        }
+    }
+
+    if indices.is_empty() {
+        return Err(format!("No faces found in '{}'.", path));
+    }
+
+    let mut params = ParamSet::new();
+    params.add_point3f("P", &p);
+    params.add_int("indices", &indices);
+    if has_uv {
+        params.add_point2f("uv", &uv);
+    }
+    if has_n {
+        params.add_normal3f("N", &n);
+    }
+    Ok(params)
+}
+
+/// Parses a fixed number of floating point values.
+///
+/// * `tokens` - The tokens.
+/// * `n`      - Number of values.
+fn parse_floats<'a, I: Iterator<Item = &'a str>>(tokens: I, n: usize) -> Option<Vec<Float>> {
+    let values: Vec<Float> = tokens.take(n).filter_map(|t| t.parse().ok()).collect();
+    if values.len() == n {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+/// Returns the 0-based index for an OBJ index which is 1-based or relative
+/// to the end of the list when negative.
+///
+/// * `token` - The index.
+/// * `len`   - Number of elements defined so far.
+fn resolve_index(token: Option<&str>, len: usize) -> Option<usize> {
+    let i = token?.parse::<i64>().ok()?;
+    let index = if i < 0 { len as i64 + i } else { i - 1 };
+    if index >= 0 && index < len as i64 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,144 @@
+//! XML Documents
+
+use pest::iterators::Pair;
+use pest::Parser;
+use std::collections::HashMap;
+
+/// The `pest` parser generated from a grammar.
+#[derive(Parser)]
+#[grammar = "parser/mitsuba/grammar.pest"]
+struct XmlParser;
+
+/// An element of an XML document. Text content is ignored since Mitsuba
+/// scenes store everything in attributes.
+#[derive(Clone, Debug, Default)]
+pub struct XmlElement {
+    /// The tag name.
+    pub tag: String,
+
+    /// Attribute values by name.
+    pub attributes: HashMap<String, String>,
+
+    /// Child elements in document order.
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// Parses an XML document and returns its root element.
+    ///
+    /// * `document` - The XML document.
+    pub fn parse(document: &str) -> Result<Self, String> {
+        let xml = XmlParser::parse(Rule::xml, document)
+            .map_err(|err| format!("Error parsing XML. {}", err))?
+            .next()
+            .unwrap();
+
+        match xml.into_inner().next() {
+            Some(pair) if pair.as_rule() == Rule::element => Self::from_pair(pair),
+            _ => Err(String::from("XML document has no root element")),
+        }
+    }
+
+    /// Converts an `element` rule into an `XmlElement`.
+    ///
+    /// * `pair` - The token pair for the matched `element` rule.
+    fn from_pair(pair: Pair<Rule>) -> Result<Self, String> {
+        let mut element = Self::default();
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::name => element.tag = String::from(inner.as_str()),
+                Rule::attribute => {
+                    let mut inner_rules = inner.into_inner();
+                    let name = inner_rules.next().unwrap().as_str();
+                    let value = inner_rules.next().unwrap().into_inner().next().unwrap();
+                    element
+                        .attributes
+                        .insert(String::from(name), unescape(value.as_str()));
+                }
+                Rule::element => element.children.push(Self::from_pair(inner)?),
+                Rule::end_tag => {
+                    let end = inner.into_inner().next().unwrap().as_str();
+                    if end != element.tag {
+                        return Err(format!("XML element '{}' closed by '{}'", element.tag, end));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(element)
+    }
+
+    /// Returns the value of an attribute.
+    ///
+    /// * `name` - Name of the attribute.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|s| s.as_str())
+    }
+
+    /// Returns the child elements with a given tag.
+    ///
+    /// * `tag` - The tag name.
+    pub fn children_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a Self> {
+        self.children.iter().filter(move |c| c.tag == tag)
+    }
+
+    /// Returns the child element with a given `name` attribute.
+    ///
+    /// * `name` - The name.
+    pub fn child_named(&self, name: &str) -> Option<&Self> {
+        self.children
+            .iter()
+            .find(|c| c.attribute("name") == Some(name))
+    }
+}
+
+/// Replaces the predefined XML entities in a string.
+///
+/// * `s` - The string.
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nested_elements() {
+        let document = r#"<?xml version="1.0"?>
+            <!-- A comment -->
+            <scene version="0.6.0">
+                <shape type='sphere'>
+                    <float name="radius" value="2"/>
+                </shape>
+                <default name="spp" value="&lt;16&gt;"/>
+            </scene>"#;
+        let scene = XmlElement::parse(document).unwrap();
+        assert_eq!(scene.tag, "scene");
+        assert_eq!(scene.attribute("version"), Some("0.6.0"));
+        assert_eq!(scene.children.len(), 2);
+
+        let shape = scene.children_with_tag("shape").next().unwrap();
+        assert_eq!(shape.attribute("type"), Some("sphere"));
+        let radius = shape.child_named("radius").unwrap();
+        assert_eq!(radius.attribute("value"), Some("2"));
+
+        let default = scene.children_with_tag("default").next().unwrap();
+        assert_eq!(default.attribute("value"), Some("<16>"));
+    }
+
+    #[test]
+    fn mismatched_end_tag() {
+        assert!(XmlElement::parse("<scene><shape></bsdf></scene>").is_err());
+    }
+}
@@ -9,6 +9,17 @@ use pest::Parser;
 use std::fs;
 use std::result::Result;
 
+mod json;
+mod mitsuba;
+#[cfg(feature = "usd")]
+mod usd;
+
+// Re-export
+pub use json::*;
+pub use mitsuba::*;
+#[cfg(feature = "usd")]
+pub use usd::*;
+
 /// The `pest` parser generated from a grammar.
 #[derive(Parser)]
 #[grammar = "parser/grammar.pest"]
@@ -23,6 +34,45 @@ pub struct PbrtFileParser {
     parent_path: String,
 }
 
+/// Reads a scene file with the parser for its format and calls the API
+/// wrapper functions. Files with an `.xml` extension are Mitsuba scenes,
+/// `.usda`, `.usd` and `.usdc` files are USD stages and all others use the
+/// PBRT file format.
+///
+/// * `path` - File path.
+/// * `api`  - The PBRT API interface.
+pub fn parse_scene_file(path: &str, api: &mut Api) -> Result<(), String> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".xml") {
+        MitsubaFileParser::new(path)?.parse(api)
+    } else if lower.ends_with(".usda") || lower.ends_with(".usd") || lower.ends_with(".usdc") {
+        parse_usd_file(path, api)
+    } else {
+        PbrtFileParser::new(path).parse(api)
+    }
+}
+
+/// Reads a USD stage and calls the API wrapper functions.
+///
+/// * `path` - File path.
+/// * `api`  - The PBRT API interface.
+#[cfg(feature = "usd")]
+fn parse_usd_file(path: &str, api: &mut Api) -> Result<(), String> {
+    UsdFileParser::new(path).parse(api)
+}
+
+/// Reports that USD support was not built.
+///
+/// * `path` - File path.
+/// * `_api` - The PBRT API interface.
+#[cfg(not(feature = "usd"))]
+fn parse_usd_file(path: &str, _api: &mut Api) -> Result<(), String> {
+    Err(format!(
+        "Unable to read USD stage '{}'. Rebuild with the 'usd' feature to enable USD support.",
+        path
+    ))
+}
+
 impl PbrtFileParser {
     /// Returns a new instance of `PbrtFileParser`.
     ///
@@ -140,6 +190,13 @@ impl PbrtFileParser {
                 api.pbrt_object_begin(str);
             }
             Rule::object_end_stmt => api.pbrt_object_end(),
+            Rule::csg_begin_stmt => {
+                let mut inner_rules = next_pair.into_inner();
+                let str = self.parse_quoted_str(&mut inner_rules);
+                debug!("CSGBegin: '{}'", str);
+                api.pbrt_csg_begin(str);
+            }
+            Rule::csg_end_stmt => api.pbrt_csg_end(),
             _ => unreachable!(),
         }
     }
@@ -168,6 +225,11 @@ impl PbrtFileParser {
                 self.parse_named_param_list(&mut inner_rules, "MakeNamedMedium", api)
             }
             Rule::sampler_stmt => self.parse_named_param_list(&mut inner_rules, "Sampler", api),
+            Rule::scene_option_stmt => {
+                let params = self.parse_param_list(inner_rules.next().unwrap().into_inner());
+                debug!("Option {:}", params);
+                api.pbrt_option(&params);
+            }
             _ => unreachable!(),
         }
     }
@@ -215,8 +277,12 @@ impl PbrtFileParser {
             }
             Rule::reverse_orientation_stmt => api.pbrt_reverse_orientation(),
             Rule::medium_interface_stmt => {
-                let inside_medium = inner_rules.next().unwrap().as_str().to_string();
-                let outside_medium = inner_rules.next().unwrap().as_str().to_string();
+                // A single medium name is used for both sides.
+                let inside_medium = self.parse_quoted_str(&mut inner_rules);
+                let outside_medium = match inner_rules.peek() {
+                    Some(_) => self.parse_quoted_str(&mut inner_rules),
+                    None => inside_medium.clone(),
+                };
                 debug!("MediumInterface: '{}', '{}'", inside_medium, outside_medium);
                 api.pbrt_medium_interface(inside_medium, outside_medium);
             }
@@ -311,19 +377,22 @@ impl PbrtFileParser {
                 api.pbrt_look_at(ex, ey, ez, lx, ly, lz, ux, uy, uz);
             }
             Rule::coordinate_system_stmt => {
-                let mut inner_rules = next_pair.into_inner();
+                let quoted_ident_expr = next_pair.into_inner().next().unwrap();
+                let mut inner_rules = quoted_ident_expr.into_inner();
                 let name = self.parse_quoted_ident(&mut inner_rules);
                 debug!("CoordinateSystem: '{}'", name);
                 api.pbrt_coordinate_system(name);
             }
             Rule::coord_sys_transform_stmt => {
-                let mut inner_rules = next_pair.into_inner();
+                let quoted_ident_expr = next_pair.into_inner().next().unwrap();
+                let mut inner_rules = quoted_ident_expr.into_inner();
                 let name = self.parse_quoted_ident(&mut inner_rules);
                 debug!("CoordSysTransform: '{}'", name);
                 api.pbrt_coord_sys_transform(name);
             }
             Rule::transform_stmt => {
-                let tr = self.parse_float_list(next_pair.into_inner());
+                let float_list_expr = next_pair.into_inner().next().unwrap();
+                let tr = self.parse_float_list(float_list_expr.into_inner());
                 assert!(
                     tr.len() == 16,
                     "float_list in transform_stmt not of len 16."
@@ -331,11 +400,12 @@ impl PbrtFileParser {
                 debug!("Transform: {:?}", tr);
                 api.pbrt_transform(&[
                     tr[0], tr[1], tr[2], tr[3], tr[4], tr[5], tr[6], tr[7], tr[8], tr[9], tr[10],
-                    tr[11], tr[11], tr[12], tr[13], tr[14],
+                    tr[11], tr[12], tr[13], tr[14], tr[15],
                 ]);
             }
             Rule::concat_transform_stmt => {
-                let tr = self.parse_float_list(next_pair.into_inner());
+                let float_list_expr = next_pair.into_inner().next().unwrap();
+                let tr = self.parse_float_list(float_list_expr.into_inner());
                 assert!(
                     tr.len() == 16,
                     "float_list in concat_transform_stmt not of len 16."
@@ -343,7 +413,7 @@ impl PbrtFileParser {
                 debug!("ConcatTransform: {:?}", tr);
                 api.pbrt_concat_transform(&[
                     tr[0], tr[1], tr[2], tr[3], tr[4], tr[5], tr[6], tr[7], tr[8], tr[9], tr[10],
-                    tr[11], tr[11], tr[12], tr[13], tr[14],
+                    tr[11], tr[12], tr[13], tr[14], tr[15],
                 ]);
             }
             Rule::transform_times_stmt => {
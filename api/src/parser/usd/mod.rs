@@ -0,0 +1,1696 @@
+//! USD Scene Parser
+
+mod usda;
+mod usdc;
+
+use crate::Api;
+use core::fileutil::*;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use usda::*;
+use usdc::*;
+
+/// Maximum number of nested sublayers, references, payloads and inherited
+/// classes. This guards against cycles.
+const MAX_COMPOSITION_DEPTH: usize = 32;
+
+/// The composition arcs of a prim in order of decreasing strength.
+const COMPOSITION_ARCS: [&str; 4] = ["inherits", "references", "payload", "specializes"];
+
+/// USD scene parser for text (`usda`) and binary (`usdc`) layers. It supports
+/// meshes and the geometric primitives, transforms, references and
+/// instancing, `UsdPreviewSurface` materials, cameras and `UsdLux` lights.
+/// Other prims are skipped with a warning.
+pub struct UsdFileParser {
+    /// Path to the file to parse.
+    file_path: String,
+}
+
+impl UsdFileParser {
+    /// Returns a new instance of `UsdFileParser`.
+    ///
+    /// * `path` - File path.
+    pub fn new(path: &str) -> Self {
+        if parent_path(path).is_none() {
+            // We were passed the root path itself which is not a file.
+            panic!("Invalid path '{}'", path);
+        }
+        Self {
+            file_path: String::from(path),
+        }
+    }
+
+    /// Reads a USD stage and calls the API wrapper functions.
+    ///
+    /// * `api` - The PBRT API interface.
+    pub fn parse(&self, api: &mut Api) -> Result<(), String> {
+        let mut composer = UsdComposer::default();
+        let prims = composer.compose_stage(&self.file_path)?;
+
+        // Images are named after the stage.
+        let stem = Path::new(&self.file_path)
+            .file_stem()
+            .map_or(String::from("usd"), |s| s.to_string_lossy().into_owned());
+
+        UsdConverter::new(&stem).convert(&prims, api);
+        Ok(())
+    }
+}
+
+/// Loads layers and composes their prims into a single namespace.
+#[derive(Default)]
+struct UsdComposer {
+    /// Loaded layers by file path.
+    layers: HashMap<String, UsdLayer>,
+}
+
+impl UsdComposer {
+    /// Returns the composed root prims of the stage for a root layer.
+    ///
+    /// * `path` - Path to the root layer.
+    fn compose_stage(&mut self, path: &str) -> Result<Vec<UsdPrim>, String> {
+        let prims = self.layer(path)?.prims.clone();
+        Ok(prims
+            .into_iter()
+            .map(|prim| {
+                let prim_path = format!("/{}", prim.name);
+                self.compose(prim, &prim_path, path, 0)
+            })
+            .collect())
+    }
+
+    /// Returns a layer and loads it the first time it is used.
+    ///
+    /// * `path` - Path to the layer.
+    fn layer(&mut self, path: &str) -> Result<&UsdLayer, String> {
+        if !self.layers.contains_key(path) {
+            let layer = load_layer(path, 0)?;
+            self.layers.insert(String::from(path), layer);
+        }
+        Ok(&self.layers[path])
+    }
+
+    /// Composes a prim with its selected variants and the prims targeted by
+    /// its composition arcs. Opinions of the prim itself are the strongest.
+    ///
+    /// * `prim`       - The prim specification.
+    /// * `path`       - Path of the prim on the stage.
+    /// * `layer_path` - Path of the layer defining the prim, used for
+    ///                  resolving arcs that target prims in the same layer.
+    /// * `depth`      - Number of composition arcs followed to reach the prim.
+    fn compose(
+        &mut self,
+        mut prim: UsdPrim,
+        path: &str,
+        layer_path: &str,
+        depth: usize,
+    ) -> UsdPrim {
+        for (set_name, variants) in std::mem::take(&mut prim.variant_sets) {
+            let selection = prim
+                .meta("variants")
+                .and_then(|v| v.get(&set_name))
+                .and_then(|v| v.as_str())
+                .map(String::from);
+            let variant = match selection {
+                Some(name) => variants.into_iter().find(|v| v.name == name),
+                None => variants.into_iter().next(),
+            };
+            if let Some(variant) = variant {
+                prim.merge(variant);
+            }
+        }
+
+        let children = std::mem::take(&mut prim.children);
+        prim.children = children
+            .into_iter()
+            .map(|child| {
+                let child_path = format!("{}/{}", path, child.name);
+                self.compose(child, &child_path, layer_path, depth)
+            })
+            .collect();
+
+        let targets: Vec<UsdValue> = COMPOSITION_ARCS
+            .iter()
+            .filter_map(|arc| prim.meta(arc))
+            .flat_map(|v| v.as_list().to_vec())
+            .collect();
+        for target in targets.iter() {
+            if depth >= MAX_COMPOSITION_DEPTH {
+                warn!(
+                    "Composition of USD prim '{}' is too deep. Ignoring arcs.",
+                    path
+                );
+                break;
+            }
+            match self.arc_target(target, layer_path) {
+                Ok(Some((source, source_path, source_layer))) => {
+                    let mut source = self.compose(source, &source_path, &source_layer, depth + 1);
+                    remap_paths(&mut source, &source_path, path);
+                    prim.merge(source);
+                }
+                Ok(None) => (),
+                Err(err) => error!("{}", err),
+            }
+        }
+
+        prim
+    }
+
+    /// Returns the prim targeted by a composition arc with its path and the
+    /// path of its layer.
+    ///
+    /// * `target`     - The arc target.
+    /// * `layer_path` - Path of the layer authoring the arc.
+    fn arc_target(
+        &mut self,
+        target: &UsdValue,
+        layer_path: &str,
+    ) -> Result<Option<(UsdPrim, String, String)>, String> {
+        let (asset, prim_path) = match target {
+            UsdValue::Path(path) => (String::from(layer_path), Some(path.clone())),
+            UsdValue::Asset(asset) => (asset.clone(), None),
+            UsdValue::Reference(asset, path) => (asset.clone(), Some(path.clone())),
+            _ => return Ok(None),
+        };
+
+        let layer = self.layer(&asset)?;
+        let prim_path = match prim_path {
+            Some(path) => path,
+            None => match layer.default_prim() {
+                Some(name) => format!("/{}", name),
+                None => return Err(format!("USD layer '{}' has no prims", asset)),
+            },
+        };
+        match layer.find(&prim_path) {
+            Some(prim) => Ok(Some((prim.clone(), prim_path, asset))),
+            None => Err(format!("USD prim '{}' not found in '{}'", prim_path, asset)),
+        }
+    }
+}
+
+/// Loads a layer and the sublayers it includes. Asset paths are resolved
+/// relative to the layer authoring them.
+///
+/// * `path`  - Path to the layer.
+/// * `depth` - Number of enclosing layers.
+fn load_layer(path: &str, depth: usize) -> Result<UsdLayer, String> {
+    let data = fs::read(path).map_err(|_| format!("Error reading file '{}'", path))?;
+    let mut layer = if data.starts_with(USDC_MAGIC) {
+        UsdLayer::parse_crate(&data)
+    } else {
+        String::from_utf8(data)
+            .map_err(|_| String::from("Layer is not valid UTF-8"))
+            .and_then(|document| UsdLayer::parse(&document))
+    }
+    .map_err(|err| format!("'{}': {}", path, err))?;
+
+    let parent = parent_path(path).unwrap_or_default();
+    for prim in layer.prims.iter_mut() {
+        prim.visit_values_mut(&mut |value| resolve_asset(value, &parent));
+    }
+
+    let sublayers: Vec<String> = layer
+        .metadata
+        .get("subLayers")
+        .map_or(vec![], |v| v.as_list().to_vec())
+        .iter()
+        .filter_map(|v| v.as_str())
+        .map(|s| resolve_path(&parent, s))
+        .collect();
+    for sublayer_path in sublayers.iter() {
+        if depth >= MAX_COMPOSITION_DEPTH {
+            warn!(
+                "USD sublayers of '{}' are nested too deep. Ignoring them.",
+                path
+            );
+            break;
+        }
+
+        // Earlier sublayers are stronger than later ones.
+        let sublayer = load_layer(sublayer_path, depth + 1)?;
+        for prim in sublayer.prims {
+            match layer.prims.iter_mut().find(|p| p.name == prim.name) {
+                Some(existing) => existing.merge(prim),
+                None => layer.prims.push(prim),
+            }
+        }
+        if !layer.metadata.contains_key("defaultPrim") {
+            if let Some(default_prim) = sublayer.metadata.get("defaultPrim") {
+                layer
+                    .metadata
+                    .insert(String::from("defaultPrim"), default_prim.clone());
+            }
+        }
+    }
+
+    Ok(layer)
+}
+
+/// Resolves the asset path of an asset or reference value.
+///
+/// * `value`       - The value.
+/// * `parent_path` - Directory of the layer authoring the value.
+fn resolve_asset(value: &mut UsdValue, parent_path: &str) {
+    match value {
+        UsdValue::Asset(asset) | UsdValue::Reference(asset, _) if !asset.is_empty() => {
+            *asset = resolve_path(parent_path, asset);
+        }
+        _ => (),
+    }
+}
+
+/// Replaces the prefix of paths that point into a composed subtree so they
+/// point to the same prims in the namespace of the prim using the subtree.
+///
+/// * `prim` - Root of the subtree.
+/// * `from` - Path of the root in its layer.
+/// * `to`   - Path of the root on the stage.
+fn remap_paths(prim: &mut UsdPrim, from: &str, to: &str) {
+    if from == to {
+        return;
+    }
+    prim.visit_values_mut(&mut |value| {
+        if let UsdValue::Path(path) = value {
+            let rest = path.strip_prefix(from).unwrap_or("-");
+            if rest.is_empty() || rest.starts_with('/') || rest.starts_with('.') {
+                *path = format!("{}{}", to, rest);
+            }
+        }
+    });
+}
+
+/// Returns a path resolved relative to a directory.
+///
+/// * `parent_path` - The directory.
+/// * `path`        - The path.
+fn resolve_path(parent_path: &str, path: &str) -> String {
+    if is_relative_path(path) && !parent_path.is_empty() {
+        format!("{}/{}", parent_path, path)
+    } else {
+        String::from(path)
+    }
+}
+
+/// Converts composed USD prims into API calls.
+struct UsdConverter<'a> {
+    /// Name of the stage file without its extension.
+    scene_name: String,
+
+    /// Prims by path.
+    prims: HashMap<String, &'a UsdPrim>,
+
+    /// Paths of the prims in document order.
+    paths: Vec<String>,
+
+    /// Paths of the materials created.
+    materials: HashSet<String>,
+
+    /// Emitted radiance of the materials with an emissive colour.
+    emission: HashMap<String, [Float; 3]>,
+
+    /// Names of the textures created, prefixed by their type.
+    textures: HashSet<String>,
+
+    /// Names of the object instances defined.
+    prototypes: HashSet<String>,
+
+    /// Whether an object instance is being defined.
+    in_prototype: bool,
+}
+
+impl<'a> UsdConverter<'a> {
+    /// Returns a new instance of `UsdConverter`.
+    ///
+    /// * `scene_name` - Name of the stage file without its extension.
+    fn new(scene_name: &str) -> Self {
+        Self {
+            scene_name: String::from(scene_name),
+            prims: HashMap::new(),
+            paths: vec![],
+            materials: HashSet::new(),
+            emission: HashMap::new(),
+            textures: HashSet::new(),
+            prototypes: HashSet::new(),
+            in_prototype: false,
+        }
+    }
+
+    /// Calls the API for the camera and the world described by the composed
+    /// root prims of a stage and renders it.
+    ///
+    /// * `roots` - The root prims.
+    /// * `api`   - The PBRT API interface.
+    fn convert(&mut self, roots: &'a [UsdPrim], api: &mut Api) {
+        for root in roots.iter() {
+            self.index(root, &format!("/{}", root.name));
+        }
+
+        self.camera(api);
+
+        // Only Whitted-style ray tracing is available.
+        let mut params = ParamSet::new();
+        params.add_int("maxdepth", &[5]);
+        api.pbrt_integrator(String::from("whitted"), &params);
+
+        api.pbrt_world_begin();
+
+        // Materials are created outside attribute blocks so they remain
+        // defined for all prims binding them.
+        let materials: Vec<(String, &'a UsdPrim)> = self
+            .paths
+            .iter()
+            .map(|path| (path.clone(), self.prims[path]))
+            .filter(|(_, prim)| prim.type_name == "Material" && prim.is_active_def())
+            .collect();
+        for (path, prim) in materials.iter() {
+            self.material(path, prim, api);
+        }
+
+        for root in roots.iter() {
+            let path = format!("/{}", root.name);
+            self.prim(root, &path, &Transform::default(), None, api);
+        }
+
+        api.pbrt_world_end();
+    }
+
+    /// Records the paths of a prim and its descendants.
+    ///
+    /// * `prim` - The prim.
+    /// * `path` - Path of the prim.
+    fn index(&mut self, prim: &'a UsdPrim, path: &str) {
+        self.prims.insert(String::from(path), prim);
+        self.paths.push(String::from(path));
+        for child in prim.children.iter() {
+            self.index(child, &format!("{}/{}", path, child.name));
+        }
+    }
+
+    /// Returns the transformation from the space of a prim to world space.
+    ///
+    /// * `path` - Path of the prim.
+    fn world_transform(&self, path: &str) -> Transform {
+        let mut t = Transform::default();
+        let mut ancestor = String::new();
+        for name in path.split('/').filter(|n| !n.is_empty()) {
+            ancestor = format!("{}/{}", ancestor, name);
+            if let Some(prim) = self.prims.get(&ancestor) {
+                let (local, reset) = local_transform(prim);
+                t = if reset { local } else { t * local };
+            }
+        }
+        t
+    }
+
+    /// Sets the film and the camera from the `RenderSettings` prim and the
+    /// camera it uses or the first camera on the stage.
+    ///
+    /// * `api` - The PBRT API interface.
+    fn camera(&self, api: &mut Api) {
+        let settings = self
+            .paths
+            .iter()
+            .map(|path| self.prims[path])
+            .find(|prim| prim.type_name == "RenderSettings");
+
+        let camera_path = settings
+            .and_then(|s| s.value("camera"))
+            .and_then(|v| v.as_list().first())
+            .and_then(|v| v.as_str())
+            .map(String::from)
+            .or_else(|| {
+                self.paths
+                    .iter()
+                    .find(|path| self.prims[*path].type_name == "Camera")
+                    .cloned()
+            });
+        let camera = camera_path
+            .as_ref()
+            .and_then(|path| self.prims.get(path).copied());
+
+        // Apertures are in tenths of a scene unit like the focal length.
+        let (h_aperture, v_aperture) = camera.map_or((20.955, 15.2908), |c| {
+            (
+                float_attribute(c, "horizontalAperture", 20.955),
+                float_attribute(c, "verticalAperture", 15.2908),
+            )
+        });
+
+        let resolution = settings
+            .and_then(|s| attribute(s, "resolution"))
+            .map(|v| v.as_floats())
+            .filter(|r| r.len() == 2);
+        let (width, height) = match resolution {
+            Some(r) => (r[0] as Int, r[1] as Int),
+            None => (
+                1280,
+                max(1, (1280.0 * v_aperture / h_aperture).round() as Int),
+            ),
+        };
+
+        let mut film_params = ParamSet::new();
+        film_params.add_int("xresolution", &[width]);
+        film_params.add_int("yresolution", &[height]);
+        film_params.add_string("filename", &[format!("{}.exr", self.scene_name)]);
+        api.pbrt_film(String::from("image"), &film_params);
+
+        let (path, camera) = match (camera_path, camera) {
+            (Some(path), Some(camera)) => (path, camera),
+            _ => {
+                warn!("USD stage has no camera. Using the default camera.");
+                return;
+            }
+        };
+
+        // USD cameras look down the negative z-axis of a right-handed frame.
+        api.pbrt_scale(1.0, 1.0, -1.0);
+        let camera_to_world = self.world_transform(&path);
+        api.pbrt_concat_transform(&column_major(&camera_to_world.inverse()));
+
+        let aspect = width as Float / height as Float;
+        let focal_length = float_attribute(camera, "focalLength", 50.0);
+        let mut params = ParamSet::new();
+        let name = if token_attribute(camera, "projection") == Some("orthographic") {
+            let half_width = 0.05 * h_aperture;
+            let half_height = half_width / aspect;
+            params.add_float(
+                "screenwindow",
+                &[-half_width, half_width, -half_height, half_height],
+            );
+            "orthographic"
+        } else {
+            let tan_x = 0.5 * h_aperture / focal_length;
+            let tan_shorter = if aspect >= 1.0 { tan_x / aspect } else { tan_x };
+            params.add_float("fov", &[2.0 * tan_shorter.atan().to_degrees()]);
+
+            let f_stop = float_attribute(camera, "fStop", 0.0);
+            if f_stop > 0.0 {
+                let lens_radius = 0.1 * focal_length / (2.0 * f_stop);
+                params.add_float("lensradius", &[lens_radius]);
+                params.add_float(
+                    "focaldistance",
+                    &[float_attribute(camera, "focusDistance", 1.0)],
+                );
+            }
+
+            if let Some(range) = attribute(camera, "clippingRange")
+                .map(|v| v.as_floats())
+                .filter(|r| r.len() == 2)
+            {
+                params.add_float("nearclip", &[range[0]]);
+                params.add_float("farclip", &[range[1]]);
+            }
+            "perspective"
+        };
+        api.pbrt_camera(String::from(name), &params);
+    }
+
+    /// Creates a named material for a `Material` prim whose surface is a
+    /// `UsdPreviewSurface`. It is approximated by the `plastic` material.
+    ///
+    /// * `path`     - Path of the material.
+    /// * `material` - The `Material` prim.
+    /// * `api`      - The PBRT API interface.
+    fn material(&mut self, path: &str, material: &'a UsdPrim, api: &mut Api) {
+        let surface = match self.surface_shader(material) {
+            Some(surface) => surface,
+            None => {
+                warn!(
+                    "USD material '{}' has no UsdPreviewSurface. Ignoring it.",
+                    path
+                );
+                return;
+            }
+        };
+
+        let mut params = ParamSet::new();
+        self.spectrum_input(surface, "diffuseColor", "Kd", [0.18; 3], &mut params, api);
+
+        let specular_workflow = match self.input(surface, "useSpecularWorkflow") {
+            Some(ShaderInput::Value(v)) => v.as_number() == Some(1.0),
+            _ => false,
+        };
+        if specular_workflow {
+            self.spectrum_input(surface, "specularColor", "Ks", [0.0; 3], &mut params, api);
+        } else {
+            params.add_rgb_spectrum("Ks", &[1.0, 1.0, 1.0]);
+            if let Some(ShaderInput::Value(v)) = self.input(surface, "metallic") {
+                if v.as_number().unwrap_or(0.0) > 0.0 {
+                    warn!(
+                        "Approximating metallic USD material '{}' with 'plastic'.",
+                        path
+                    );
+                }
+            }
+        }
+
+        // The microfacet alpha is the square of the roughness.
+        match self.input(surface, "roughness") {
+            Some(ShaderInput::Texture(texture)) => match self.texture(&texture, "float", api) {
+                Some(name) => params.add_texture("roughness", &[name]),
+                None => params.add_float("roughness", &[0.25]),
+            },
+            input => {
+                let roughness = match input {
+                    Some(ShaderInput::Value(v)) => v.as_number().unwrap_or(0.5) as Float,
+                    _ => 0.5,
+                };
+                params.add_float("roughness", &[max(roughness * roughness, 0.001)]);
+            }
+        }
+        params.add_bool("remaproughness", &[false]);
+        params.add_string("type", &[String::from("plastic")]);
+
+        api.pbrt_make_named_material(String::from(path), &params);
+        self.materials.insert(String::from(path));
+
+        if let Some(ShaderInput::Value(v)) = self.input(surface, "emissiveColor") {
+            let c = v.as_floats();
+            if c.len() >= 3 && c.iter().take(3).any(|x| *x > 0.0) {
+                self.emission.insert(String::from(path), [c[0], c[1], c[2]]);
+            }
+        }
+    }
+
+    /// Returns the `UsdPreviewSurface` shader connected to the surface
+    /// output of a material or the first one defined in it.
+    ///
+    /// * `material` - The `Material` prim.
+    fn surface_shader(&self, material: &'a UsdPrim) -> Option<&'a UsdPrim> {
+        let connected = material
+            .value("outputs:surface.connect")
+            .and_then(|v| v.as_list().first())
+            .and_then(|v| v.as_str())
+            .and_then(|target| self.connection_source(target))
+            .map(|source| source.prim)
+            .filter(|prim| shader_id(prim) == Some("UsdPreviewSurface"));
+
+        connected.or_else(|| {
+            material
+                .children
+                .iter()
+                .find(|c| shader_id(c) == Some("UsdPreviewSurface"))
+        })
+    }
+
+    /// Returns the prim and property targeted by a connection.
+    ///
+    /// * `target` - The path of the source property.
+    fn connection_source(&self, target: &str) -> Option<ConnectionSource<'a>> {
+        let dot = target.rfind('.')?;
+        let prim = *self.prims.get(&target[..dot])?;
+        Some(ConnectionSource {
+            prim,
+            path: String::from(&target[..dot]),
+            property: String::from(&target[dot + 1..]),
+        })
+    }
+
+    /// Returns the value of a shader input or the texture connected to it.
+    /// Connections to the inputs of enclosing materials and node graphs are
+    /// followed.
+    ///
+    /// * `shader` - The shader prim.
+    /// * `name`   - Name of the input without the `inputs:` prefix.
+    fn input(&self, shader: &'a UsdPrim, name: &str) -> Option<ShaderInput<'a>> {
+        let mut prim = shader;
+        let mut property = format!("inputs:{}", name);
+        for _ in 0..MAX_COMPOSITION_DEPTH {
+            let target = prim
+                .value(&format!("{}.connect", property))
+                .and_then(|v| v.as_list().first())
+                .and_then(|v| v.as_str());
+            let target = match target {
+                Some(target) => target,
+                None => return attribute(prim, &property).map(ShaderInput::Value),
+            };
+
+            let source = self.connection_source(target)?;
+            match source.prim.type_name.as_str() {
+                "Material" | "NodeGraph" => {
+                    prim = source.prim;
+                    property = source.property;
+                }
+                _ if shader_id(source.prim) == Some("UsdUVTexture") => {
+                    return Some(ShaderInput::Texture(source));
+                }
+                _ => {
+                    warn!("Unsupported USD shader connection to '{}'.", target);
+                    return None;
+                }
+            }
+        }
+        None
+    }
+
+    /// Adds a spectrum parameter for a shader input that can be a colour or a
+    /// texture.
+    ///
+    /// * `shader`    - The shader prim.
+    /// * `name`      - Name of the input.
+    /// * `pbrt_name` - Name of the parameter.
+    /// * `default`   - Value used when the input is missing.
+    /// * `params`    - The parameter set to update.
+    /// * `api`       - The PBRT API interface.
+    fn spectrum_input(
+        &mut self,
+        shader: &'a UsdPrim,
+        name: &str,
+        pbrt_name: &str,
+        default: [Float; 3],
+        params: &mut ParamSet,
+        api: &mut Api,
+    ) {
+        match self.input(shader, name) {
+            Some(ShaderInput::Texture(texture)) => {
+                if let Some(texture_name) = self.texture(&texture, "spectrum", api) {
+                    params.add_texture(pbrt_name, &[texture_name]);
+                    return;
+                }
+            }
+            Some(ShaderInput::Value(v)) => {
+                let c = v.as_floats();
+                if c.len() >= 3 {
+                    params.add_rgb_spectrum(pbrt_name, &c[..3]);
+                    return;
+                }
+            }
+            None => (),
+        }
+        params.add_rgb_spectrum(pbrt_name, &default);
+    }
+
+    /// Creates an `imagemap` texture for the output of a `UsdUVTexture`
+    /// shader unless it was already created and returns its name.
+    ///
+    /// * `source`       - The texture output.
+    /// * `texture_type` - Type of the values, `spectrum` or `float`.
+    /// * `api`          - The PBRT API interface.
+    fn texture(
+        &mut self,
+        source: &ConnectionSource<'a>,
+        texture_type: &str,
+        api: &mut Api,
+    ) -> Option<String> {
+        let name = format!("{}.{}", source.path, source.property);
+        let key = format!("{}:{}", texture_type, name);
+        if self.textures.contains(&key) {
+            return Some(name);
+        }
+
+        let shader = source.prim;
+        let filename = match attribute(shader, "inputs:file").and_then(|v| v.as_str()) {
+            Some(filename) => String::from(filename),
+            None => {
+                warn!("USD texture '{}' has no file.", source.path);
+                return None;
+            }
+        };
+
+        let mut params = ParamSet::new();
+        let wrap = match token_attribute(shader, "inputs:wrapS") {
+            Some("clamp") => "clamp",
+            Some("black") => "black",
+            _ => "repeat",
+        };
+        params.add_string("wrap", &[String::from(wrap)]);
+
+        let lower = filename.to_lowercase();
+        let gamma = match token_attribute(shader, "inputs:sourceColorSpace") {
+            Some("raw") => false,
+            Some("sRGB") => true,
+            _ => texture_type == "spectrum" && !lower.ends_with(".exr") && !lower.ends_with(".hdr"),
+        };
+        params.add_bool("gamma", &[gamma]);
+        params.add_string("filename", &[filename]);
+
+        if let Some(scale) = attribute(shader, "inputs:scale").map(|v| v.as_floats()) {
+            if let Some(s) = scale.first() {
+                params.add_float("scale", &[*s]);
+            }
+        }
+
+        // Only the scale and translation of a 2D transform carry over.
+        let st = shader
+            .value("inputs:st.connect")
+            .and_then(|v| v.as_list().first())
+            .and_then(|v| v.as_str())
+            .and_then(|target| self.connection_source(target))
+            .filter(|s| shader_id(s.prim) == Some("UsdTransform2d"));
+        if let Some(transform) = st {
+            let scale = float_attributes(transform.prim, "inputs:scale");
+            let translation = float_attributes(transform.prim, "inputs:translation");
+            if scale.len() == 2 {
+                params.add_float("uscale", &[scale[0]]);
+                params.add_float("vscale", &[scale[1]]);
+            }
+            if translation.len() == 2 {
+                params.add_float("udelta", &[translation[0]]);
+                params.add_float("vdelta", &[translation[1]]);
+            }
+        }
+
+        api.pbrt_texture(
+            name.clone(),
+            String::from(texture_type),
+            String::from("imagemap"),
+            &params,
+        );
+        self.textures.insert(key);
+        Some(name)
+    }
+
+    /// Converts a prim and its descendants. Instanceable prims with
+    /// references are converted into object instances sharing the
+    /// referenced prims.
+    ///
+    /// * `prim`     - The prim.
+    /// * `path`     - Path of the prim.
+    /// * `parent`   - Transformation of the parent prim to world space.
+    /// * `material` - Path of the material bound to the parent prim.
+    /// * `api`      - The PBRT API interface.
+    fn prim(
+        &mut self,
+        prim: &'a UsdPrim,
+        path: &str,
+        parent: &Transform,
+        material: Option<&str>,
+        api: &mut Api,
+    ) {
+        if !prim.is_active_def()
+            || prim.type_name == "Material"
+            || token_attribute(prim, "visibility") == Some("invisible")
+            || token_attribute(prim, "purpose") == Some("guide")
+        {
+            return;
+        }
+
+        let (local, reset) = local_transform(prim);
+        let world = if reset { local } else { *parent * local };
+
+        let binding = ["material:binding", "material:binding:preview"]
+            .iter()
+            .filter_map(|name| prim.value(name))
+            .filter_map(|v| v.as_list().first())
+            .filter_map(|v| v.as_str())
+            .find(|p| self.materials.contains(*p));
+        let material = binding.or(material);
+
+        let instanceable = prim
+            .meta("instanceable")
+            .and_then(|v| v.as_number())
+            .unwrap_or(0.0)
+            > 0.0;
+        if instanceable && !self.in_prototype {
+            if let Some(key) = prototype_key(prim) {
+                if !self.prototypes.contains(&key) {
+                    self.prototypes.insert(key.clone());
+                    self.in_prototype = true;
+                    api.pbrt_object_begin(key.clone());
+                    self.prim_contents(prim, path, &Transform::default(), material, api);
+                    api.pbrt_object_end();
+                    self.in_prototype = false;
+                }
+
+                api.pbrt_attribute_begin();
+                api.pbrt_transform(&column_major(&world));
+                api.pbrt_object_instance(key);
+                api.pbrt_attribute_end();
+                return;
+            }
+        }
+
+        self.prim_contents(prim, path, &world, material, api);
+    }
+
+    /// Converts the geometry or light of a prim and its descendants.
+    ///
+    /// * `prim`     - The prim.
+    /// * `path`     - Path of the prim.
+    /// * `world`    - Transformation of the prim to world space.
+    /// * `material` - Path of the material bound to the prim.
+    /// * `api`      - The PBRT API interface.
+    fn prim_contents(
+        &mut self,
+        prim: &'a UsdPrim,
+        path: &str,
+        world: &Transform,
+        material: Option<&str>,
+        api: &mut Api,
+    ) {
+        match prim.type_name.as_str() {
+            "Mesh" => self.mesh(prim, path, world, material, api),
+            "Sphere" | "Cube" | "Cylinder" | "Cone" | "Plane" => {
+                self.gprim(prim, world, material, api)
+            }
+            "SphereLight" | "DiskLight" | "RectLight" | "DistantLight" | "DomeLight" => {
+                self.light(prim, path, world, api)
+            }
+            "PointInstancer" => {
+                // Prototypes are only rendered by the instances.
+                self.point_instancer(prim, path, world, material, api);
+                return;
+            }
+            "" | "Xform" | "Scope" | "Camera" | "RenderSettings" | "RenderProduct" => (),
+            type_name => warn!("Ignoring USD prim '{}' of type '{}'.", path, type_name),
+        }
+
+        for child in prim.children.iter() {
+            let child_path = format!("{}/{}", path, child.name);
+            self.prim(child, &child_path, world, material, api);
+        }
+    }
+
+    /// Adds a shape with the bound material and the area light of an
+    /// emissive material.
+    ///
+    /// * `prim`     - The prim.
+    /// * `name`     - Name of the shape.
+    /// * `params`   - Parameters of the shape.
+    /// * `world`    - Transformation of the shape to world space.
+    /// * `material` - Path of the bound material.
+    /// * `reverse`  - Whether to reverse the orientation of the normals.
+    /// * `api`      - The PBRT API interface.
+    #[allow(clippy::too_many_arguments)]
+    fn shape(
+        &mut self,
+        prim: &UsdPrim,
+        name: &str,
+        params: &ParamSet,
+        world: &Transform,
+        material: Option<&str>,
+        reverse: bool,
+        api: &mut Api,
+    ) {
+        api.pbrt_attribute_begin();
+        api.pbrt_transform(&column_major(world));
+        if reverse {
+            api.pbrt_reverse_orientation();
+        }
+
+        match material {
+            Some(material) => {
+                api.pbrt_named_material(String::from(material));
+                if let Some(l) = self.emission.get(material) {
+                    if !self.in_prototype {
+                        let mut light_params = ParamSet::new();
+                        light_params.add_rgb_spectrum("L", l);
+                        api.pbrt_area_light_source(String::from("diffuse"), &light_params);
+                    }
+                }
+            }
+            None => {
+                // Unbound prims use their display colour.
+                let colour = attribute(prim, "primvars:displayColor")
+                    .map(|v| v.as_floats())
+                    .filter(|c| c.len() >= 3)
+                    .map_or([0.18; 3], |c| [c[0], c[1], c[2]]);
+                let mut material_params = ParamSet::new();
+                material_params.add_rgb_spectrum("Kd", &colour);
+                api.pbrt_material(String::from("matte"), &material_params);
+            }
+        }
+
+        api.pbrt_shape(String::from(name), params);
+        api.pbrt_attribute_end();
+    }
+
+    /// Adds a polygon mesh as a triangle mesh. Polygons are triangulated as
+    /// fans and subdivision surfaces are rendered as their control mesh.
+    ///
+    /// * `prim`     - The `Mesh` prim.
+    /// * `path`     - Path of the prim.
+    /// * `world`    - Transformation of the prim to world space.
+    /// * `material` - Path of the bound material.
+    /// * `api`      - The PBRT API interface.
+    fn mesh(
+        &mut self,
+        prim: &'a UsdPrim,
+        path: &str,
+        world: &Transform,
+        material: Option<&str>,
+        api: &mut Api,
+    ) {
+        let points = float_attributes(prim, "points");
+        let counts = index_attributes(prim, "faceVertexCounts");
+        let indices = index_attributes(prim, "faceVertexIndices");
+        if counts.iter().sum::<usize>() != indices.len() {
+            error!("USD mesh '{}' has inconsistent face vertex counts.", path);
+            return;
+        }
+        let n_points = points.len() / 3;
+
+        let normals = Primvar::find(prim, &["primvars:normals", "normals"], 3);
+        let st = Primvar::find(prim, &["primvars:st", "primvars:UVMap", "primvars:uv"], 2).or_else(
+            || {
+                // Use any texture coordinate primvar.
+                let mut names: Vec<&String> = prim
+                    .properties
+                    .iter()
+                    .filter(|(name, p)| {
+                        name.starts_with("primvars:") && p.type_name == "texCoord2f[]"
+                    })
+                    .map(|(name, _)| name)
+                    .collect();
+                names.sort();
+                names
+                    .first()
+                    .and_then(|name| Primvar::find(prim, &[name.as_str()], 2))
+            },
+        );
+
+        let mut vertices: HashMap<(usize, Option<usize>, Option<usize>), Int> = HashMap::new();
+        let (mut p, mut n, mut uv, mut triangles) = (vec![], vec![], vec![], vec![]);
+        let (mut has_n, mut has_uv) = (normals.is_some(), st.is_some());
+        let mut corner = 0;
+        for (face, &count) in counts.iter().enumerate() {
+            let mut polygon = Vec::with_capacity(count);
+            for (c, &pi) in indices.iter().enumerate().skip(corner).take(count) {
+                if pi >= n_points {
+                    error!("USD mesh '{}' has out of bounds vertex index {}.", path, pi);
+                    return;
+                }
+                let ni = normals.as_ref().and_then(|pv| pv.index(face, c, pi));
+                let ti = st.as_ref().and_then(|pv| pv.index(face, c, pi));
+                has_n = has_n && ni.is_some();
+                has_uv = has_uv && ti.is_some();
+
+                let next = p.len() as Int;
+                let index = *vertices.entry((pi, ni, ti)).or_insert(next);
+                if index == next {
+                    p.push(Point3f::new(
+                        points[3 * pi],
+                        points[3 * pi + 1],
+                        points[3 * pi + 2],
+                    ));
+                    n.push(match (&normals, ni) {
+                        (Some(pv), Some(i)) => {
+                            let v = pv.value(i);
+                            Normal3f::new(v[0], v[1], v[2])
+                        }
+                        _ => Normal3f::default(),
+                    });
+                    uv.push(match (&st, ti) {
+                        (Some(pv), Some(i)) => {
+                            let v = pv.value(i);
+                            Point2f::new(v[0], v[1])
+                        }
+                        _ => Point2f::default(),
+                    });
+                }
+                polygon.push(index);
+            }
+            corner += count;
+
+            if let Some((&first, rest)) = polygon.split_first() {
+                for edge in rest.windows(2) {
+                    triangles.extend_from_slice(&[first, edge[0], edge[1]]);
+                }
+            }
+        }
+        if triangles.is_empty() {
+            warn!("USD mesh '{}' has no faces.", path);
+            return;
+        }
+
+        let mut params = ParamSet::new();
+        params.add_point3f("P", &p);
+        params.add_int("indices", &triangles);
+        if has_n {
+            params.add_normal3f("N", &n);
+        }
+        if has_uv {
+            params.add_point2f("uv", &uv);
+        }
+
+        let left_handed = token_attribute(prim, "orientation") == Some("leftHanded");
+        self.shape(
+            prim,
+            "trianglemesh",
+            &params,
+            world,
+            material,
+            left_handed,
+            api,
+        );
+    }
+
+    /// Adds a geometric primitive. Cylinders and cones are closed with disks.
+    ///
+    /// * `prim`     - The prim.
+    /// * `world`    - Transformation of the prim to world space.
+    /// * `material` - Path of the bound material.
+    /// * `api`      - The PBRT API interface.
+    fn gprim(
+        &mut self,
+        prim: &'a UsdPrim,
+        world: &Transform,
+        material: Option<&str>,
+        api: &mut Api,
+    ) {
+        // Cylinders, cones and planes are aligned with the z-axis in pbrt.
+        let axis = match token_attribute(prim, "axis") {
+            Some("X") => Transform::rotate_y(90.0),
+            Some("Y") => Transform::rotate_x(-90.0),
+            _ => Transform::default(),
+        };
+        let aligned = *world * axis;
+
+        let mut params = ParamSet::new();
+        match prim.type_name.as_str() {
+            "Sphere" => {
+                params.add_float("radius", &[float_attribute(prim, "radius", 1.0)]);
+                self.shape(prim, "sphere", &params, world, material, false, api);
+            }
+            "Cube" => {
+                let h = 0.5 * float_attribute(prim, "size", 2.0);
+                let faces: Vec<(usize, Float, Float, Float, Float)> = (0..6)
+                    .map(|i| {
+                        let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                        (i / 2, sign, sign * h, h, h)
+                    })
+                    .collect();
+                add_quads(&mut params, &faces);
+                self.shape(prim, "trianglemesh", &params, world, material, false, api);
+            }
+            "Plane" => {
+                let half_width = 0.5 * float_attribute(prim, "width", 2.0);
+                let half_length = 0.5 * float_attribute(prim, "length", 2.0);
+                add_quads(&mut params, &[(2, 1.0, 0.0, half_width, half_length)]);
+                self.shape(
+                    prim,
+                    "trianglemesh",
+                    &params,
+                    &aligned,
+                    material,
+                    false,
+                    api,
+                );
+            }
+            "Cylinder" | "Cone" => {
+                let radius = float_attribute(prim, "radius", 1.0);
+                let h = 0.5 * float_attribute(prim, "height", 2.0);
+                params.add_float("radius", &[radius]);
+                if prim.type_name == "Cylinder" {
+                    params.add_float("zmin", &[-h]);
+                    params.add_float("zmax", &[h]);
+                    self.shape(prim, "cylinder", &params, &aligned, material, false, api);
+
+                    let top = aligned * Transform::translate(&Vector3f::new(0.0, 0.0, h));
+                    let mut cap_params = ParamSet::new();
+                    cap_params.add_float("radius", &[radius]);
+                    self.shape(prim, "disk", &cap_params, &top, material, false, api);
+                } else {
+                    // The apex is at the top.
+                    params.add_float("height", &[2.0 * h]);
+                    let base = aligned * Transform::translate(&Vector3f::new(0.0, 0.0, -h));
+                    self.shape(prim, "cone", &params, &base, material, false, api);
+                }
+
+                let bottom = aligned * Transform::translate(&Vector3f::new(0.0, 0.0, -h));
+                let mut cap_params = ParamSet::new();
+                cap_params.add_float("radius", &[radius]);
+                self.shape(prim, "disk", &cap_params, &bottom, material, true, api);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Adds a `UsdLux` light. Sphere, disk and rectangle lights are area
+    /// lights emitting their radiance from the surface.
+    ///
+    /// * `prim`  - The light prim.
+    /// * `path`  - Path of the prim.
+    /// * `world` - Transformation of the light to world space.
+    /// * `api`   - The PBRT API interface.
+    fn light(&mut self, prim: &'a UsdPrim, path: &str, world: &Transform, api: &mut Api) {
+        if self.in_prototype {
+            warn!("Ignoring USD light '{}' in an instance.", path);
+            return;
+        }
+
+        // Lights authored before USD 21.02 have no `inputs:` prefix.
+        let input = |name: &str| {
+            attribute(prim, &format!("inputs:{}", name)).or_else(|| attribute(prim, name))
+        };
+        let input_float = |name: &str, default: Float| {
+            input(name)
+                .and_then(|v| v.as_number())
+                .map_or(default, |v| v as Float)
+        };
+
+        let colour = input("color")
+            .map(|v| v.as_floats())
+            .filter(|c| c.len() >= 3)
+            .map_or([1.0; 3], |c| [c[0], c[1], c[2]]);
+        let scale = input_float("intensity", 1.0) * input_float("exposure", 0.0).exp2();
+        let l = [colour[0] * scale, colour[1] * scale, colour[2] * scale];
+
+        let mut params = ParamSet::new();
+        let mut shape_params = ParamSet::new();
+        let (shape_name, reverse) = match prim.type_name.as_str() {
+            "SphereLight" => {
+                let radius = input_float("radius", 0.5);
+                let as_point = input("treatAsPoint").and_then(|v| v.as_number()) == Some(1.0);
+                if radius <= 0.0 || as_point {
+                    params.add_rgb_spectrum("I", &l);
+                    self.light_source("point", &params, world, api);
+                    return;
+                }
+                shape_params.add_float("radius", &[radius]);
+                ("sphere", false)
+            }
+            "DiskLight" => {
+                // Disks face the positive z-axis but the light emits towards
+                // the negative z-axis.
+                shape_params.add_float("radius", &[input_float("radius", 0.5)]);
+                ("disk", true)
+            }
+            "RectLight" => {
+                let half_width = 0.5 * input_float("width", 1.0);
+                let half_height = 0.5 * input_float("height", 1.0);
+                add_quads(
+                    &mut shape_params,
+                    &[(2, -1.0, 0.0, half_width, half_height)],
+                );
+                ("trianglemesh", false)
+            }
+            "DistantLight" => {
+                params.add_rgb_spectrum("L", &l);
+                params.add_point3f("from", &[Point3f::new(0.0, 0.0, 0.0)]);
+                params.add_point3f("to", &[Point3f::new(0.0, 0.0, -1.0)]);
+                self.light_source("distant", &params, world, api);
+                return;
+            }
+            "DomeLight" => {
+                params.add_rgb_spectrum("L", &l);
+                if let Some(file) = input("texture:file").and_then(|v| v.as_str()) {
+                    params.add_string("mapname", &[String::from(file)]);
+                }
+
+                // Dome light textures have the y-axis up.
+                let orientation = *world * Transform::rotate_x(-90.0);
+                self.light_source("infinite", &params, &orientation, api);
+                return;
+            }
+            _ => unreachable!(),
+        };
+
+        api.pbrt_attribute_begin();
+        api.pbrt_transform(&column_major(world));
+        if reverse {
+            api.pbrt_reverse_orientation();
+        }
+        params.add_rgb_spectrum("L", &l);
+        api.pbrt_area_light_source(String::from("diffuse"), &params);
+
+        // The light itself does not reflect light.
+        let mut material_params = ParamSet::new();
+        material_params.add_rgb_spectrum("Kd", &[0.0, 0.0, 0.0]);
+        api.pbrt_material(String::from("matte"), &material_params);
+
+        api.pbrt_shape(String::from(shape_name), &shape_params);
+        api.pbrt_attribute_end();
+    }
+
+    /// Adds a light source with a transformation.
+    ///
+    /// * `name`   - Name of the light.
+    /// * `params` - Parameters of the light.
+    /// * `world`  - Transformation of the light to world space.
+    /// * `api`    - The PBRT API interface.
+    fn light_source(&self, name: &str, params: &ParamSet, world: &Transform, api: &mut Api) {
+        api.pbrt_attribute_begin();
+        api.pbrt_transform(&column_major(world));
+        api.pbrt_light_source(String::from(name), params);
+        api.pbrt_attribute_end();
+    }
+
+    /// Adds the instances of a `PointInstancer`.
+    ///
+    /// * `prim`     - The `PointInstancer` prim.
+    /// * `path`     - Path of the prim.
+    /// * `world`    - Transformation of the prim to world space.
+    /// * `material` - Path of the bound material.
+    /// * `api`      - The PBRT API interface.
+    fn point_instancer(
+        &mut self,
+        prim: &'a UsdPrim,
+        path: &str,
+        world: &Transform,
+        material: Option<&str>,
+        api: &mut Api,
+    ) {
+        if self.in_prototype {
+            warn!("Ignoring nested USD point instancer '{}'.", path);
+            return;
+        }
+
+        let prototypes: Vec<Option<String>> = prim
+            .value("prototypes")
+            .map_or(vec![], |v| v.as_list().to_vec())
+            .iter()
+            .map(|v| {
+                let prototype_path = v.as_str()?;
+                let prototype = *self.prims.get(prototype_path)?;
+                let key = String::from(prototype_path);
+                if !self.prototypes.contains(&key) {
+                    self.prototypes.insert(key.clone());
+                    self.in_prototype = true;
+                    api.pbrt_object_begin(key.clone());
+                    self.prim(
+                        prototype,
+                        prototype_path,
+                        &Transform::default(),
+                        material,
+                        api,
+                    );
+                    api.pbrt_object_end();
+                    self.in_prototype = false;
+                }
+                Some(key)
+            })
+            .collect();
+
+        let proto_indices = index_attributes(prim, "protoIndices");
+        let positions = float_attributes(prim, "positions");
+        let orientations = float_attributes(prim, "orientations");
+        let scales = float_attributes(prim, "scales");
+        for (i, proto_index) in proto_indices.iter().enumerate() {
+            let key = match prototypes.get(*proto_index) {
+                Some(Some(key)) => key,
+                _ => continue,
+            };
+
+            let mut t = Transform::default();
+            if let Some(p) = positions.get(3 * i..3 * i + 3) {
+                t = t * Transform::translate(&Vector3f::new(p[0], p[1], p[2]));
+            }
+            if let Some(q) = orientations.get(4 * i..4 * i + 4) {
+                // Quaternions are written with the real part first.
+                let q = Quaternion::new(Vector3f::new(q[1], q[2], q[3]), q[0]);
+                t = t * Transform::from(q.normalize());
+            }
+            if let Some(s) = scales.get(3 * i..3 * i + 3) {
+                t = t * Transform::scale(s[0], s[1], s[2]);
+            }
+
+            api.pbrt_attribute_begin();
+            api.pbrt_transform(&column_major(&(*world * t)));
+            api.pbrt_object_instance(key.clone());
+            api.pbrt_attribute_end();
+        }
+    }
+}
+
+/// A property of a prim that is the source of a connection.
+struct ConnectionSource<'a> {
+    /// The prim.
+    prim: &'a UsdPrim,
+
+    /// Path of the prim.
+    path: String,
+
+    /// Name of the property.
+    property: String,
+}
+
+/// The value of a shader input.
+enum ShaderInput<'a> {
+    /// An authored value.
+    Value(&'a UsdValue),
+
+    /// A connected texture.
+    Texture(ConnectionSource<'a>),
+}
+
+/// A primitive variable of a mesh.
+struct Primvar {
+    /// The values.
+    values: Vec<Float>,
+
+    /// Number of components per element.
+    size: usize,
+
+    /// Indices of the element used for each face, face corner or point.
+    indices: Option<Vec<usize>>,
+
+    /// How the values are interpolated across the mesh.
+    interpolation: String,
+}
+
+impl Primvar {
+    /// Returns the first authored primitive variable with one of the given
+    /// names.
+    ///
+    /// * `prim`  - The prim.
+    /// * `names` - The names.
+    /// * `size`  - Number of components per element.
+    fn find(prim: &UsdPrim, names: &[&str], size: usize) -> Option<Self> {
+        names.iter().find_map(|name| {
+            let property = prim.properties.get(*name)?;
+            let values = attribute(prim, name)?.as_floats();
+            let indices = attribute(prim, &format!("{}:indices", name))
+                .map(|v| v.as_floats().iter().map(|i| *i as usize).collect());
+            let interpolation = property
+                .metadata
+                .get("interpolation")
+                .and_then(|v| v.as_str())
+                .unwrap_or("vertex");
+            Some(Self {
+                values,
+                size,
+                indices,
+                interpolation: String::from(interpolation),
+            })
+        })
+    }
+
+    /// Returns the index of the element for a face corner.
+    ///
+    /// * `face`   - Index of the face.
+    /// * `corner` - Index of the face corner across all faces.
+    /// * `point`  - Index of the point at the corner.
+    fn index(&self, face: usize, corner: usize, point: usize) -> Option<usize> {
+        let i = match self.interpolation.as_str() {
+            "constant" => 0,
+            "uniform" => face,
+            "faceVarying" => corner,
+            _ => point,
+        };
+        let i = match &self.indices {
+            Some(indices) => *indices.get(i)?,
+            None => i,
+        };
+        if (i + 1) * self.size <= self.values.len() {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the components of an element.
+    ///
+    /// * `i` - Index of the element.
+    fn value(&self, i: usize) -> &[Float] {
+        &self.values[i * self.size..(i + 1) * self.size]
+    }
+}
+
+/// Returns the value of an attribute or its earliest time sample.
+///
+/// * `prim` - The prim.
+/// * `name` - Name of the attribute.
+fn attribute<'a>(prim: &'a UsdPrim, name: &str) -> Option<&'a UsdValue> {
+    match prim.value(name) {
+        Some(UsdValue::None) => None,
+        Some(value) => Some(value),
+        None => match prim.value(&format!("{}.timeSamples", name)) {
+            Some(UsdValue::Dictionary(samples)) => samples
+                .iter()
+                .filter_map(|(time, value)| Some((time.parse::<f64>().ok()?, value)))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+                .map(|(_, value)| value),
+            _ => None,
+        },
+    }
+}
+
+/// Returns the value of a scalar attribute.
+///
+/// * `prim`    - The prim.
+/// * `name`    - Name of the attribute.
+/// * `default` - Value used when the attribute is missing.
+fn float_attribute(prim: &UsdPrim, name: &str, default: Float) -> Float {
+    attribute(prim, name)
+        .and_then(|v| v.as_number())
+        .map_or(default, |v| v as Float)
+}
+
+/// Returns the numbers in the value of an attribute.
+///
+/// * `prim` - The prim.
+/// * `name` - Name of the attribute.
+fn float_attributes(prim: &UsdPrim, name: &str) -> Vec<Float> {
+    attribute(prim, name).map_or(vec![], |v| v.as_floats())
+}
+
+/// Returns the non-negative integers in the value of an attribute.
+///
+/// * `prim` - The prim.
+/// * `name` - Name of the attribute.
+fn index_attributes(prim: &UsdPrim, name: &str) -> Vec<usize> {
+    float_attributes(prim, name)
+        .iter()
+        .map(|i| max(0.0, *i) as usize)
+        .collect()
+}
+
+/// Returns the value of a token attribute.
+///
+/// * `prim` - The prim.
+/// * `name` - Name of the attribute.
+fn token_attribute<'a>(prim: &'a UsdPrim, name: &str) -> Option<&'a str> {
+    attribute(prim, name).and_then(|v| v.as_str())
+}
+
+/// Returns the shader identifier of a `Shader` prim.
+///
+/// * `prim` - The prim.
+fn shader_id(prim: &UsdPrim) -> Option<&str> {
+    token_attribute(prim, "info:id")
+}
+
+/// Returns the name of the object instance shared by instanceable prims
+/// with the same references.
+///
+/// * `prim` - The prim.
+fn prototype_key(prim: &UsdPrim) -> Option<String> {
+    let targets: Vec<String> = ["references", "payload"]
+        .iter()
+        .filter_map(|arc| prim.meta(arc))
+        .flat_map(|v| v.as_list().iter())
+        .filter_map(|v| match v {
+            UsdValue::Path(path) => Some(path.clone()),
+            UsdValue::Asset(asset) => Some(asset.clone()),
+            UsdValue::Reference(asset, path) => Some(format!("{}{}", asset, path)),
+            _ => None,
+        })
+        .collect();
+    if targets.is_empty() {
+        None
+    } else {
+        Some(targets.join(";"))
+    }
+}
+
+/// Returns the transformation of a prim relative to its parent given by its
+/// transform operations and whether it ignores the parent transformation.
+///
+/// * `prim` - The prim.
+fn local_transform(prim: &UsdPrim) -> (Transform, bool) {
+    let mut t = Transform::default();
+    let mut reset = false;
+
+    let order = prim.value("xformOpOrder").map_or(&[][..], |v| v.as_list());
+    for op in order.iter().filter_map(|v| v.as_str()) {
+        if op == "!resetXformStack!" {
+            t = Transform::default();
+            reset = true;
+            continue;
+        }
+
+        let (name, invert) = match op.strip_prefix("!invert!") {
+            Some(name) => (name, true),
+            None => (op, false),
+        };
+        match transform_op(prim, name) {
+            Some(m) if invert => t = t * m.inverse(),
+            Some(m) => t = t * m,
+            None => warn!("Ignoring USD transform operation '{}'.", op),
+        }
+    }
+
+    (t, reset)
+}
+
+/// Returns the transformation of a transform operation.
+///
+/// * `prim` - The prim.
+/// * `name` - Name of the operation attribute, e.g. `xformOp:translate`.
+fn transform_op(prim: &UsdPrim, name: &str) -> Option<Transform> {
+    let v = attribute(prim, name)?.as_floats();
+    let op_type = name.split(':').nth(1)?;
+    match (op_type, v.len()) {
+        ("translate", 3) => Some(Transform::translate(&Vector3f::new(v[0], v[1], v[2]))),
+        ("scale", 3) => Some(Transform::scale(v[0], v[1], v[2])),
+        ("rotateX", 1) => Some(Transform::rotate_x(v[0])),
+        ("rotateY", 1) => Some(Transform::rotate_y(v[0])),
+        ("rotateZ", 1) => Some(Transform::rotate_z(v[0])),
+        ("orient", 4) => {
+            // Quaternions are written with the real part first.
+            let q = Quaternion::new(Vector3f::new(v[1], v[2], v[3]), v[0]);
+            Some(Transform::from(q.normalize()))
+        }
+        ("transform", 16) => {
+            // Matrices are written row by row for row vectors.
+            Some(Transform::from(Matrix4x4::new(
+                v[0], v[4], v[8], v[12], v[1], v[5], v[9], v[13], v[2], v[6], v[10], v[14], v[3],
+                v[7], v[11], v[15],
+            )))
+        }
+        (rotate, 3) if rotate.len() == 9 && rotate.starts_with("rotate") => {
+            // The angles are given for the x, y and z axes and the rotations
+            // are applied in the order of the axes in the name.
+            let mut t = Transform::default();
+            for axis in rotate[6..].chars() {
+                let r = match axis {
+                    'X' => Transform::rotate_x(v[0]),
+                    'Y' => Transform::rotate_y(v[1]),
+                    'Z' => Transform::rotate_z(v[2]),
+                    _ => return None,
+                };
+                t = r * t;
+            }
+            Some(t)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the matrix of a transformation in the column-major order used by
+/// `Api::pbrt_transform()`.
+///
+/// * `t` - The transformation.
+fn column_major(t: &Transform) -> [Float; 16] {
+    let mut tr = [0.0; 16];
+    for (i, row) in t.m.m.iter().enumerate() {
+        for (j, v) in row.iter().enumerate() {
+            tr[4 * j + i] = *v;
+        }
+    }
+    tr
+}
+
+/// Adds the parameters of a `trianglemesh` made of axis aligned rectangles
+/// facing away from their axis.
+///
+/// * `params` - The parameter set to update.
+/// * `quads`  - The axis of the normal, its sign, the offset along the axis
+///              and the half extents along the next two axes of each
+///              rectangle.
+fn add_quads(params: &mut ParamSet, quads: &[(usize, Float, Float, Float, Float)]) {
+    let (mut p, mut n, mut uv, mut indices) = (vec![], vec![], vec![], vec![]);
+    for &(axis, sign, offset, half_u, half_v) in quads.iter() {
+        let base = p.len() as Int;
+        for &(u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            let mut c = [0.0; 3];
+            c[axis] = offset;
+            c[(axis + 1) % 3] = u * half_u;
+            c[(axis + 2) % 3] = v * half_v;
+            p.push(Point3f::new(c[0], c[1], c[2]));
+
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            n.push(Normal3f::new(normal[0], normal[1], normal[2]));
+            uv.push(Point2f::new(0.5 * (u + 1.0), 0.5 * (v + 1.0)));
+        }
+
+        // Counter-clockwise when seen from the side the normal points to.
+        if sign > 0.0 {
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        } else {
+            indices.extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+        }
+    }
+    params.add_point3f("P", &p);
+    params.add_normal3f("N", &n);
+    params.add_point2f("uv", &uv);
+    params.add_int("indices", &indices);
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::*;
+
+    #[test]
+    fn usd_instanceable_prims_match_copies() {
+        // Two halves of the view are covered by a grey quad in front of a
+        // white dome light.
+        let stage = |instanceable: bool, right: bool| {
+            let instance = |name: &str, x: Float| {
+                format!(
+                    r#"
+    def "{}" (
+        instanceable = {}
+        references = </Quad>
+    )
+    {{
+        double3 xformOp:translate = ({}, 0, 0)
+        uniform token[] xformOpOrder = ["xformOp:translate"]
+    }}
+"#,
+                    name, instanceable, x
+                )
+            };
+            format!(
+                r#"#usda 1.0
+(
+    defaultPrim = "World"
+)
+
+class Mesh "Quad"
+{{
+    int[] faceVertexCounts = [4]
+    int[] faceVertexIndices = [0, 1, 2, 3]
+    point3f[] points = [(-0.5, -1, 0), (0.5, -1, 0), (0.5, 1, 0), (-0.5, 1, 0)]
+}}
+
+def RenderSettings "Settings"
+{{
+    int2 resolution = (8, 8)
+    rel camera = </World/Camera>
+}}
+
+def Xform "World"
+{{
+    def Camera "Camera"
+    {{
+        token projection = "orthographic"
+        float horizontalAperture = 20
+        double3 xformOp:translate = (0, 0, 5)
+        uniform token[] xformOpOrder = ["xformOp:translate"]
+    }}
+
+    def DomeLight "Dome"
+    {{
+        float inputs:intensity = 1
+    }}
+{}{}}}
+"#,
+                instance("Left", -0.5),
+                if right {
+                    instance("Right", 0.5)
+                } else {
+                    String::new()
+                }
+            )
+        };
+
+        let instanced = average(&render_file("usd_instanced", "usda", &stage(true, true)));
+        let copies = average(&render_file("usd_copies", "usda", &stage(false, true)));
+        let half = average(&render_file("usd_half", "usda", &stage(true, false)));
+        assert!((instanced - copies).abs() < 0.02, "{}", instanced - copies);
+        assert!(instanced < half - 0.1, "{} {}", instanced, half);
+    }
+}
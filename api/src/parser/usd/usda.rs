@@ -0,0 +1,571 @@
+//! USDA Layers
+
+use core::pbrt::*;
+use pest::iterators::Pair;
+use pest::Parser;
+use std::collections::HashMap;
+
+/// The `pest` parser generated from a grammar.
+#[derive(Parser)]
+#[grammar = "parser/usd/grammar.pest"]
+struct UsdaParser;
+
+/// A value of an attribute or of a metadata field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UsdValue {
+    /// The `None` value that blocks an opinion.
+    None,
+
+    /// A number.
+    Number(f64),
+
+    /// A quoted string.
+    String(String),
+
+    /// An unquoted token such as `true`.
+    Token(String),
+
+    /// An asset path.
+    Asset(String),
+
+    /// A path to a prim or property without the angle brackets.
+    Path(String),
+
+    /// A reference to a prim in another layer given by the asset and path.
+    Reference(String, String),
+
+    /// A parenthesized tuple.
+    Tuple(Vec<UsdValue>),
+
+    /// A list in square brackets.
+    List(Vec<UsdValue>),
+
+    /// A dictionary of values in document order.
+    Dictionary(Vec<(String, UsdValue)>),
+}
+
+impl UsdValue {
+    /// Returns the number for numeric values and booleans.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            Self::Token(t) if t == "true" => Some(1.0),
+            Self::Token(t) if t == "false" => Some(0.0),
+            Self::List(values) | Self::Tuple(values) if values.len() == 1 => values[0].as_number(),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of strings, tokens, assets and paths.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) | Self::Token(s) | Self::Asset(s) | Self::Path(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns all numbers in the value with tuples and lists flattened.
+    pub fn as_floats(&self) -> Vec<Float> {
+        let mut floats = vec![];
+        self.flatten_into(&mut floats);
+        floats
+    }
+
+    /// Returns the items of a list or the value itself as a list of one item.
+    pub fn as_list(&self) -> &[UsdValue] {
+        match self {
+            Self::List(values) => values,
+            Self::None => &[],
+            _ => std::slice::from_ref(self),
+        }
+    }
+
+    /// Returns the value of a dictionary entry.
+    ///
+    /// * `key` - The key.
+    pub fn get(&self, key: &str) -> Option<&UsdValue> {
+        match self {
+            Self::Dictionary(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Appends the numbers in the value to a list.
+    ///
+    /// * `floats` - The list.
+    fn flatten_into(&self, floats: &mut Vec<Float>) {
+        match self {
+            Self::Number(n) => floats.push(*n as Float),
+            Self::Tuple(values) | Self::List(values) => {
+                for v in values.iter() {
+                    v.flatten_into(floats);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// An attribute or relationship of a prim.
+#[derive(Clone, Debug, Default)]
+pub struct UsdProperty {
+    /// The type name, e.g. `point3f[]` or `rel`.
+    pub type_name: String,
+
+    /// The authored value.
+    pub value: Option<UsdValue>,
+
+    /// Metadata fields such as `interpolation`.
+    pub metadata: HashMap<String, UsdValue>,
+}
+
+/// A prim specification.
+#[derive(Clone, Debug, Default)]
+pub struct UsdPrim {
+    /// The specifier; `def`, `over` or `class`.
+    pub specifier: String,
+
+    /// The schema type name, e.g. `Mesh`. Empty for typeless prims.
+    pub type_name: String,
+
+    /// The prim name.
+    pub name: String,
+
+    /// Metadata fields such as `references` or `instanceable`.
+    pub metadata: HashMap<String, UsdValue>,
+
+    /// Properties by name. Connections and time samples are stored under
+    /// the property name followed by `.connect` and `.timeSamples`.
+    pub properties: HashMap<String, UsdProperty>,
+
+    /// Child prims in document order.
+    pub children: Vec<UsdPrim>,
+
+    /// Variant sets by name with their variants. Each variant is stored as a
+    /// prim holding the opinions it contributes.
+    pub variant_sets: Vec<(String, Vec<UsdPrim>)>,
+}
+
+impl UsdPrim {
+    /// Returns the child prim with a given name.
+    ///
+    /// * `name` - The name.
+    pub fn child(&self, name: &str) -> Option<&Self> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// Returns the value of a property.
+    ///
+    /// * `name` - The property name.
+    pub fn value(&self, name: &str) -> Option<&UsdValue> {
+        self.properties.get(name).and_then(|p| p.value.as_ref())
+    }
+
+    /// Returns the value of a metadata field.
+    ///
+    /// * `name` - The field name.
+    pub fn meta(&self, name: &str) -> Option<&UsdValue> {
+        self.metadata.get(name)
+    }
+
+    /// Returns `true` if the prim is a `def` that is not deactivated.
+    pub fn is_active_def(&self) -> bool {
+        self.specifier == "def" && self.meta("active").and_then(|v| v.as_number()) != Some(0.0)
+    }
+
+    /// Merges a weaker opinion of the same prim into this one. Values already
+    /// authored here win and child prims with the same name are merged.
+    ///
+    /// * `weaker` - The weaker prim specification.
+    pub fn merge(&mut self, weaker: Self) {
+        if self.specifier != "def" && weaker.specifier != "over" {
+            self.specifier = weaker.specifier;
+        }
+        if self.type_name.is_empty() {
+            self.type_name = weaker.type_name;
+        }
+        for (key, value) in weaker.metadata {
+            self.metadata.entry(key).or_insert(value);
+        }
+        for (name, property) in weaker.properties {
+            self.properties.entry(name).or_insert(property);
+        }
+        for (name, variants) in weaker.variant_sets {
+            if !self.variant_sets.iter().any(|(n, _)| *n == name) {
+                self.variant_sets.push((name, variants));
+            }
+        }
+        for child in weaker.children {
+            match self.children.iter_mut().find(|c| c.name == child.name) {
+                Some(existing) => existing.merge(child),
+                None => self.children.push(child),
+            }
+        }
+    }
+
+    /// Calls a function on the values of all metadata fields and properties
+    /// of this prim and its descendants.
+    ///
+    /// * `f` - The function.
+    pub fn visit_values_mut<F: FnMut(&mut UsdValue)>(&mut self, f: &mut F) {
+        for value in self.metadata.values_mut() {
+            visit_value_mut(value, f);
+        }
+        for property in self.properties.values_mut() {
+            if let Some(value) = property.value.as_mut() {
+                visit_value_mut(value, f);
+            }
+        }
+        for child in self.children.iter_mut() {
+            child.visit_values_mut(f);
+        }
+        for (_, variants) in self.variant_sets.iter_mut() {
+            for variant in variants.iter_mut() {
+                variant.visit_values_mut(f);
+            }
+        }
+    }
+}
+
+/// Calls a function on a value and the values nested in it.
+///
+/// * `value` - The value.
+/// * `f`     - The function.
+pub fn visit_value_mut<F: FnMut(&mut UsdValue)>(value: &mut UsdValue, f: &mut F) {
+    match value {
+        UsdValue::Tuple(values) | UsdValue::List(values) => {
+            for v in values.iter_mut() {
+                visit_value_mut(v, f);
+            }
+        }
+        UsdValue::Dictionary(entries) => {
+            for (_, v) in entries.iter_mut() {
+                visit_value_mut(v, f);
+            }
+        }
+        _ => f(value),
+    }
+}
+
+/// A layer parsed from a `usda` file.
+#[derive(Clone, Debug, Default)]
+pub struct UsdLayer {
+    /// Layer metadata such as `defaultPrim` and `subLayers`.
+    pub metadata: HashMap<String, UsdValue>,
+
+    /// The root prims.
+    pub prims: Vec<UsdPrim>,
+}
+
+impl UsdLayer {
+    /// Parses the text of a `usda` layer.
+    ///
+    /// * `document` - The layer contents.
+    pub fn parse(document: &str) -> Result<Self, String> {
+        if !document.starts_with("#usda") {
+            return Err(String::from("Missing '#usda' header"));
+        }
+
+        let usda = UsdaParser::parse(Rule::usda, document)
+            .map_err(|err| format!("Error parsing USD layer. {}", err))?
+            .next()
+            .unwrap();
+
+        let mut layer = Self::default();
+        for pair in usda.into_inner() {
+            match pair.as_rule() {
+                Rule::metadata => layer.metadata = parse_metadata(pair),
+                Rule::prim => layer.prims.push(parse_prim(pair)),
+                Rule::EOI => (),
+                _ => unreachable!(),
+            }
+        }
+        Ok(layer)
+    }
+
+    /// Returns the prim at an absolute path.
+    ///
+    /// * `path` - The path.
+    pub fn find(&self, path: &str) -> Option<&UsdPrim> {
+        let mut names = path.split('/').filter(|n| !n.is_empty());
+        let root = names.next()?;
+        let mut prim = self.prims.iter().find(|p| p.name == root)?;
+        for name in names {
+            prim = prim.child(name)?;
+        }
+        Some(prim)
+    }
+
+    /// Returns the name of the default prim or the first root prim.
+    pub fn default_prim(&self) -> Option<String> {
+        match self.metadata.get("defaultPrim").and_then(|v| v.as_str()) {
+            Some(name) => Some(String::from(name)),
+            None => self.prims.first().map(|p| p.name.clone()),
+        }
+    }
+}
+
+/// Converts a `prim` or `variant` rule into a prim specification.
+///
+/// * `pair` - The token pair.
+fn parse_prim(pair: Pair<Rule>) -> UsdPrim {
+    let mut prim = UsdPrim {
+        specifier: String::from("def"),
+        ..UsdPrim::default()
+    };
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::specifier => prim.specifier = String::from(inner.as_str()),
+            Rule::prim_type => prim.type_name = String::from(inner.as_str()),
+            Rule::string => prim.name = parse_string(inner),
+            Rule::metadata => prim.metadata = parse_metadata(inner),
+            Rule::prim => prim.children.push(parse_prim(inner)),
+            Rule::variant_set => {
+                let mut inner_rules = inner.into_inner();
+                let name = parse_string(inner_rules.next().unwrap());
+                let variants = inner_rules
+                    .map(|variant| UsdPrim {
+                        specifier: String::from("over"),
+                        ..parse_prim(variant)
+                    })
+                    .collect();
+                prim.variant_sets.push((name, variants));
+            }
+            Rule::property => {
+                let (name, property) = parse_property(inner);
+                prim.properties.insert(name, property);
+            }
+            Rule::reorder => (),
+            _ => unreachable!(),
+        }
+    }
+
+    prim
+}
+
+/// Converts a `property` rule into a property name and specification.
+///
+/// * `pair` - The token pair.
+fn parse_property(pair: Pair<Rule>) -> (String, UsdProperty) {
+    let mut name = String::new();
+    let mut property = UsdProperty::default();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::list_op | Rule::qualifier => (),
+            Rule::type_name => property.type_name = String::from(inner.as_str()),
+            Rule::property_name => name = String::from(inner.as_str()),
+            Rule::value => property.value = Some(parse_value(inner)),
+            Rule::metadata => property.metadata = parse_metadata(inner),
+            _ => unreachable!(),
+        }
+    }
+
+    (name, property)
+}
+
+/// Converts a `metadata` rule into a map of fields. List editing operations
+/// are applied as if they were explicit values and documentation strings are
+/// stored as `doc`.
+///
+/// * `pair` - The token pair.
+fn parse_metadata(pair: Pair<Rule>) -> HashMap<String, UsdValue> {
+    let mut metadata = HashMap::new();
+
+    for item in pair.into_inner() {
+        let mut name = String::from("doc");
+        let mut value = UsdValue::None;
+        for inner in item.into_inner() {
+            match inner.as_rule() {
+                Rule::string => value = UsdValue::String(parse_string(inner)),
+                Rule::list_op => (),
+                Rule::name => name = String::from(inner.as_str()),
+                Rule::value => value = parse_value(inner),
+                _ => unreachable!(),
+            }
+        }
+        metadata.insert(name, value);
+    }
+
+    metadata
+}
+
+/// Converts a `value` rule into a value.
+///
+/// * `pair` - The token pair.
+fn parse_value(pair: Pair<Rule>) -> UsdValue {
+    let inner = pair.into_inner().next().unwrap();
+    match inner.as_rule() {
+        Rule::none => UsdValue::None,
+        Rule::reference => {
+            let mut inner_rules = inner.into_inner();
+            let asset = parse_asset(inner_rules.next().unwrap());
+            let path = inner_rules.next().unwrap().into_inner().next().unwrap();
+            UsdValue::Reference(asset, String::from(path.as_str()))
+        }
+        Rule::tuple => UsdValue::Tuple(inner.into_inner().map(parse_value).collect()),
+        Rule::list => UsdValue::List(inner.into_inner().map(parse_value).collect()),
+        Rule::dictionary => UsdValue::Dictionary(
+            inner
+                .into_inner()
+                .map(|entry| {
+                    let mut key = String::new();
+                    let mut value = UsdValue::None;
+                    for e in entry.into_inner() {
+                        match e.as_rule() {
+                            Rule::type_name => (),
+                            Rule::dict_key => {
+                                let k = e.into_inner().next().unwrap();
+                                key = match k.as_rule() {
+                                    Rule::string => parse_string(k),
+                                    _ => String::from(k.as_str()),
+                                };
+                            }
+                            Rule::value => value = parse_value(e),
+                            _ => unreachable!(),
+                        }
+                    }
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Rule::path => UsdValue::Path(String::from(inner.into_inner().next().unwrap().as_str())),
+        Rule::asset => UsdValue::Asset(parse_asset(inner)),
+        Rule::string => UsdValue::String(parse_string(inner)),
+        Rule::number => UsdValue::Number(parse_number(inner.as_str())),
+        Rule::token => UsdValue::Token(String::from(inner.as_str())),
+        _ => unreachable!(),
+    }
+}
+
+/// Returns the contents of a `string` rule with escapes replaced.
+///
+/// * `pair` - The token pair.
+fn parse_string(pair: Pair<Rule>) -> String {
+    let s = pair.into_inner().next().unwrap().as_str();
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(c) => unescaped.push(c),
+            None => (),
+        }
+    }
+    unescaped
+}
+
+/// Returns the path of an `asset` rule.
+///
+/// * `pair` - The token pair.
+fn parse_asset(pair: Pair<Rule>) -> String {
+    String::from(pair.into_inner().next().unwrap().as_str())
+}
+
+/// Parses a number including `inf` and `nan`.
+///
+/// * `s` - The number.
+fn parse_number(s: &str) -> f64 {
+    match s {
+        "inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        "nan" | "-nan" => f64::NAN,
+        _ => s.parse().unwrap_or(0.0),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_layer() {
+        let document = r#"#usda 1.0
+            (
+                defaultPrim = "World"
+                upAxis = "Y"
+            )
+
+            def Xform "World" (
+                prepend apiSchemas = ["MaterialBindingAPI"]
+            )
+            {
+                # A comment.
+                double3 xformOp:translate = (1, 2, -3.5e1)
+                uniform token[] xformOpOrder = ["xformOp:translate"]
+                rel material:binding = </World/Looks/Red>
+
+                def Mesh "Quad" (
+                    references = @./quad.usda@</Quad>
+                )
+                {
+                    int[] faceVertexCounts = [4]
+                    texCoord2f[] primvars:st = [(0, 0), (1, 0), (1, 1), (0, 1)] (
+                        interpolation = "faceVarying"
+                    )
+                    float3 xformOp:scale.timeSamples = {
+                        0: (1, 1, 1),
+                        10: (2, 2, 2),
+                    }
+                }
+            }
+        "#;
+
+        let layer = UsdLayer::parse(document).unwrap();
+        assert_eq!(layer.default_prim(), Some(String::from("World")));
+
+        let world = layer.find("/World").unwrap();
+        assert_eq!(world.type_name, "Xform");
+        assert_eq!(
+            world.value("xformOp:translate").unwrap().as_floats(),
+            vec![1.0, 2.0, -35.0]
+        );
+        assert_eq!(
+            world.value("material:binding"),
+            Some(&UsdValue::Path(String::from("/World/Looks/Red")))
+        );
+
+        let quad = layer.find("/World/Quad").unwrap();
+        assert_eq!(
+            quad.meta("references"),
+            Some(&UsdValue::Reference(
+                String::from("./quad.usda"),
+                String::from("/Quad")
+            ))
+        );
+        let st = quad.properties.get("primvars:st").unwrap();
+        assert_eq!(st.type_name, "texCoord2f[]");
+        assert_eq!(
+            st.metadata.get("interpolation").and_then(|v| v.as_str()),
+            Some("faceVarying")
+        );
+        let samples = quad.value("xformOp:scale.timeSamples").unwrap();
+        assert_eq!(samples.get("10").unwrap().as_floats(), vec![2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn merge_weaker_opinions() {
+        let document = r#"#usda 1.0
+            over "A" { float radius = 2 }
+            def Sphere "B" { float radius = 1  double height = 3 }
+        "#;
+        let layer = UsdLayer::parse(document).unwrap();
+
+        let mut a = layer.prims[0].clone();
+        a.merge(layer.prims[1].clone());
+        assert_eq!(a.specifier, "def");
+        assert_eq!(a.type_name, "Sphere");
+        assert_eq!(a.value("radius").and_then(|v| v.as_number()), Some(2.0));
+        assert_eq!(a.value("height").and_then(|v| v.as_number()), Some(3.0));
+    }
+}
@@ -0,0 +1,1689 @@
+//! USDC Layers
+
+use super::usda::*;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+/// Identifies a binary `usdc` layer.
+pub const USDC_MAGIC: &[u8] = b"PXR-USDC";
+
+/// Oldest version of the crate file format that can be read. Sections of
+/// older files are not compressed.
+const MIN_VERSION: (u8, u8, u8) = (0, 4, 0);
+
+/// Arrays with fewer elements are never compressed.
+const MIN_COMPRESSED_ARRAY_SIZE: usize = 16;
+
+/// Number of bytes of a section name in the table of contents.
+const SECTION_NAME_SIZE: usize = 16;
+
+/// Spec types of prims and properties.
+const SPEC_ATTRIBUTE: u32 = 1;
+const SPEC_PRIM: u32 = 6;
+const SPEC_PSEUDO_ROOT: u32 = 7;
+const SPEC_RELATIONSHIP: u32 = 8;
+
+/// Types of the values stored in a crate file.
+const TYPE_BOOL: u8 = 1;
+const TYPE_UCHAR: u8 = 2;
+const TYPE_INT: u8 = 3;
+const TYPE_UINT: u8 = 4;
+const TYPE_INT64: u8 = 5;
+const TYPE_UINT64: u8 = 6;
+const TYPE_HALF: u8 = 7;
+const TYPE_FLOAT: u8 = 8;
+const TYPE_DOUBLE: u8 = 9;
+const TYPE_STRING: u8 = 10;
+const TYPE_TOKEN: u8 = 11;
+const TYPE_ASSET_PATH: u8 = 12;
+const TYPE_MATRIX2D: u8 = 13;
+const TYPE_MATRIX3D: u8 = 14;
+const TYPE_MATRIX4D: u8 = 15;
+const TYPE_QUATD: u8 = 16;
+const TYPE_QUATF: u8 = 17;
+const TYPE_QUATH: u8 = 18;
+const TYPE_VEC2D: u8 = 19;
+const TYPE_VEC4I: u8 = 30;
+const TYPE_DICTIONARY: u8 = 31;
+const TYPE_TOKEN_LIST_OP: u8 = 32;
+const TYPE_STRING_LIST_OP: u8 = 33;
+const TYPE_PATH_LIST_OP: u8 = 34;
+const TYPE_REFERENCE_LIST_OP: u8 = 35;
+const TYPE_INT_LIST_OP: u8 = 36;
+const TYPE_INT64_LIST_OP: u8 = 37;
+const TYPE_UINT_LIST_OP: u8 = 38;
+const TYPE_UINT64_LIST_OP: u8 = 39;
+const TYPE_PATH_VECTOR: u8 = 40;
+const TYPE_TOKEN_VECTOR: u8 = 41;
+const TYPE_SPECIFIER: u8 = 42;
+const TYPE_PERMISSION: u8 = 43;
+const TYPE_VARIABILITY: u8 = 44;
+const TYPE_VARIANT_SELECTION_MAP: u8 = 45;
+const TYPE_TIME_SAMPLES: u8 = 46;
+const TYPE_PAYLOAD: u8 = 47;
+const TYPE_DOUBLE_VECTOR: u8 = 48;
+const TYPE_STRING_VECTOR: u8 = 50;
+const TYPE_VALUE_BLOCK: u8 = 51;
+const TYPE_VALUE: u8 = 52;
+const TYPE_PAYLOAD_LIST_OP: u8 = 55;
+const TYPE_TIME_CODE: u8 = 56;
+
+/// Bits of the header of a list editing operation.
+const LIST_OP_EXPLICIT_ITEMS: u8 = 1 << 1;
+const LIST_OP_ADDED_ITEMS: u8 = 1 << 2;
+const LIST_OP_DELETED_ITEMS: u8 = 1 << 3;
+const LIST_OP_ORDERED_ITEMS: u8 = 1 << 4;
+const LIST_OP_PREPENDED_ITEMS: u8 = 1 << 5;
+const LIST_OP_APPENDED_ITEMS: u8 = 1 << 6;
+
+/// Fields listing the children of a spec. The children are stored as specs
+/// of their own.
+const CHILDREN_FIELDS: [&str; 6] = [
+    "primChildren",
+    "properties",
+    "variantSetChildren",
+    "variantChildren",
+    "targetChildren",
+    "connectionChildren",
+];
+
+/// Scalar types of the numeric values.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Scalar {
+    U8,
+    I32,
+    U32,
+    I64,
+    U64,
+    F16,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    /// Returns the number of bytes of a value.
+    fn size(&self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::F16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+        }
+    }
+}
+
+/// Returns the scalar type and the number of rows and columns of numeric
+/// value types. Vectors and quaternions have one row.
+///
+/// * `type_id` - The value type.
+fn numeric_type(type_id: u8) -> Option<(Scalar, usize, usize)> {
+    let scalars = [Scalar::F64, Scalar::F32, Scalar::F16, Scalar::I32];
+    match type_id {
+        TYPE_UCHAR => Some((Scalar::U8, 1, 1)),
+        TYPE_INT => Some((Scalar::I32, 1, 1)),
+        TYPE_UINT => Some((Scalar::U32, 1, 1)),
+        TYPE_INT64 => Some((Scalar::I64, 1, 1)),
+        TYPE_UINT64 => Some((Scalar::U64, 1, 1)),
+        TYPE_HALF => Some((Scalar::F16, 1, 1)),
+        TYPE_FLOAT => Some((Scalar::F32, 1, 1)),
+        TYPE_DOUBLE | TYPE_TIME_CODE => Some((Scalar::F64, 1, 1)),
+        TYPE_MATRIX2D..=TYPE_MATRIX4D => {
+            let n = (type_id - TYPE_MATRIX2D + 2) as usize;
+            Some((Scalar::F64, n, n))
+        }
+        TYPE_QUATD..=TYPE_QUATH => Some((scalars[(type_id - TYPE_QUATD) as usize], 1, 4)),
+        TYPE_VEC2D..=TYPE_VEC4I => {
+            let i = (type_id - TYPE_VEC2D) as usize;
+            Some((scalars[i % 4], 1, 2 + i / 4))
+        }
+        _ => None,
+    }
+}
+
+/// A value representation. It holds the type of a value and either the value
+/// itself or the offset where the value is stored.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ValueRep(u64);
+
+impl ValueRep {
+    /// Returns whether the value is an array.
+    fn is_array(&self) -> bool {
+        self.0 & (1 << 63) != 0
+    }
+
+    /// Returns whether the value is stored in the payload.
+    fn is_inlined(&self) -> bool {
+        self.0 & (1 << 62) != 0
+    }
+
+    /// Returns whether the elements of an array are compressed.
+    fn is_compressed(&self) -> bool {
+        self.0 & (1 << 61) != 0
+    }
+
+    /// Returns the value type.
+    fn type_id(&self) -> u8 {
+        (self.0 >> 48) as u8
+    }
+
+    /// Returns the inlined value or the offset of the value.
+    fn payload(&self) -> u64 {
+        self.0 & ((1 << 48) - 1)
+    }
+}
+
+/// Reads little endian values from a buffer.
+#[derive(Clone)]
+struct Cursor<'a> {
+    /// The buffer.
+    data: &'a [u8],
+
+    /// Offset of the next value.
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Returns a new `Cursor`.
+    ///
+    /// * `data` - The buffer.
+    /// * `pos`  - Offset of the first value.
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    /// Returns the next bytes.
+    ///
+    /// * `n` - Number of bytes.
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| String::from("Unexpected end of USD crate data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Returns the next bytes as an array.
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8, String> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    /// Returns the next count of elements. Counts are only used to size
+    /// vectors so they are limited to the size of the buffer.
+    fn count(&mut self) -> Result<usize, String> {
+        let n = self.u64()?;
+        if n > self.data.len() as u64 {
+            return Err(format!("Invalid USD crate element count {}", n));
+        }
+        Ok(n as usize)
+    }
+
+    /// Returns the next scalar as a double.
+    ///
+    /// * `scalar` - The scalar type.
+    fn scalar(&mut self, scalar: Scalar) -> Result<f64, String> {
+        Ok(match scalar {
+            Scalar::U8 => self.u8()? as f64,
+            Scalar::I32 => self.i32()? as f64,
+            Scalar::U32 => self.u32()? as f64,
+            Scalar::I64 => self.i64()? as f64,
+            Scalar::U64 => self.u64()? as f64,
+            Scalar::F16 => half_to_f32(self.u16()?) as f64,
+            Scalar::F32 => self.f32()? as f64,
+            Scalar::F64 => self.f64()?,
+        })
+    }
+}
+
+/// Converts a half precision float to single precision.
+///
+/// * `h` - The bits of the half precision float.
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * (2.0f32).powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        e => (1.0 + mantissa / 1024.0) * (2.0f32).powi(e - 15),
+    }
+}
+
+/// Decompresses data written with `TfFastCompression`. It starts with the
+/// number of chunks; zero chunks is a single LZ4 block and otherwise each
+/// chunk is an LZ4 block prefixed with its size.
+///
+/// * `data`     - The compressed data.
+/// * `max_size` - Maximum size of the decompressed data.
+fn decompress(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let lz4 = |block: &[u8], output: &mut [u8]| {
+        lz4_flex::block::decompress_into(block, output)
+            .map_err(|err| format!("Error decompressing USD crate data. {}", err))
+    };
+
+    let mut cursor = Cursor::new(data, 0);
+    let n_chunks = cursor.u8()?;
+    let mut output = vec![0; max_size];
+    let mut size = 0;
+    if n_chunks == 0 {
+        size = lz4(&data[1..], &mut output)?;
+    } else {
+        for _ in 0..n_chunks {
+            let chunk_size = cursor.u32()? as usize;
+            size += lz4(cursor.bytes(chunk_size)?, &mut output[size..])?;
+        }
+    }
+    output.truncate(size);
+    Ok(output)
+}
+
+/// Decodes integers written with `Usd_IntegerCompression`. The differences
+/// between consecutive integers are stored after a common difference and a
+/// two bit code per integer that selects the common difference or the size
+/// of the stored difference.
+///
+/// * `encoded` - The encoded integers.
+/// * `n`       - Number of integers.
+/// * `wide`    - Whether the integers have 64 bits instead of 32.
+fn decode_ints(encoded: &[u8], n: usize, wide: bool) -> Result<Vec<i64>, String> {
+    let mut cursor = Cursor::new(encoded, 0);
+    let common = if wide {
+        cursor.i64()?
+    } else {
+        cursor.i32()? as i64
+    };
+    let codes = cursor.bytes((2 * n).div_ceil(8))?;
+
+    let mut values = Vec::with_capacity(n);
+    let mut previous: i64 = 0;
+    for i in 0..n {
+        let delta = match ((codes[i / 4] >> (2 * (i % 4))) & 3, wide) {
+            (0, _) => common,
+            (1, false) => cursor.i8()? as i64,
+            (1, true) | (2, false) => cursor.i16()? as i64,
+            (2, true) | (3, false) => cursor.i32()? as i64,
+            _ => cursor.i64()?,
+        };
+        previous = if wide {
+            previous.wrapping_add(delta)
+        } else {
+            (previous as i32).wrapping_add(delta as i32) as i64
+        };
+        values.push(previous);
+    }
+    Ok(values)
+}
+
+/// Reads the size of compressed integers and decodes them.
+///
+/// * `cursor` - Cursor at the compressed size.
+/// * `n`      - Number of integers.
+/// * `wide`   - Whether the integers have 64 bits instead of 32.
+fn read_compressed_ints(cursor: &mut Cursor, n: usize, wide: bool) -> Result<Vec<i64>, String> {
+    let size = if wide { 8 } else { 4 };
+    let compressed_size = cursor.count()?;
+    let compressed = cursor.bytes(compressed_size)?;
+    let max_size = if n > 0 {
+        size + (2 * n).div_ceil(8) + n * size
+    } else {
+        0
+    };
+    decode_ints(&decompress(compressed, max_size)?, n, wide)
+}
+
+/// A spec and its fields.
+struct Spec {
+    /// The spec type.
+    spec_type: u32,
+
+    /// Names and values of the fields.
+    fields: Vec<(String, ValueRep)>,
+}
+
+/// Reads the structural sections of a crate file and unpacks values.
+struct CrateReader<'a> {
+    /// The file contents.
+    data: &'a [u8],
+
+    /// The file format version.
+    version: (u8, u8, u8),
+
+    /// The tokens.
+    tokens: Vec<String>,
+
+    /// Token indices of the strings.
+    strings: Vec<usize>,
+
+    /// The paths.
+    paths: Vec<String>,
+
+    /// Specs by path.
+    specs: HashMap<String, Spec>,
+}
+
+impl<'a> CrateReader<'a> {
+    /// Reads the table of contents and the structural sections.
+    ///
+    /// * `data` - The file contents.
+    fn new(data: &'a [u8]) -> Result<Self, String> {
+        let mut cursor = Cursor::new(data, 0);
+        if cursor.bytes(USDC_MAGIC.len())? != USDC_MAGIC {
+            return Err(String::from("Missing 'PXR-USDC' header"));
+        }
+        let version = cursor.bytes(8)?;
+        let version = (version[0], version[1], version[2]);
+        if version < MIN_VERSION {
+            return Err(format!(
+                "USD crate file version {}.{}.{} is not supported",
+                version.0, version.1, version.2
+            ));
+        }
+
+        let mut reader = Self {
+            data,
+            version,
+            tokens: vec![],
+            strings: vec![],
+            paths: vec![],
+            specs: HashMap::new(),
+        };
+
+        let mut sections = HashMap::new();
+        let mut toc = Cursor::new(data, cursor.u64()? as usize);
+        for _ in 0..toc.count()? {
+            let name = toc.bytes(SECTION_NAME_SIZE)?;
+            let name = name.split(|b| *b == 0).next().unwrap_or(&[]);
+            let start = toc.u64()? as usize;
+            toc.u64()?;
+            sections.insert(String::from_utf8_lossy(name).into_owned(), start);
+        }
+        let section = |name: &str| match sections.get(name) {
+            Some(start) => Ok(Cursor::new(data, *start)),
+            None => Err(format!("USD crate file has no '{}' section", name)),
+        };
+
+        reader.read_tokens(&mut section("TOKENS")?)?;
+        reader.read_strings(&mut section("STRINGS")?)?;
+        let fields = reader.read_fields(&mut section("FIELDS")?)?;
+        let field_sets = reader.read_field_sets(&mut section("FIELDSETS")?)?;
+        reader.read_paths(&mut section("PATHS")?)?;
+        reader.read_specs(&mut section("SPECS")?, &fields, &field_sets)?;
+        Ok(reader)
+    }
+
+    /// Reads the null terminated tokens.
+    ///
+    /// * `cursor` - Cursor at the section.
+    fn read_tokens(&mut self, cursor: &mut Cursor) -> Result<(), String> {
+        let n = cursor.count()?;
+        let size = cursor.count()?;
+        let compressed_size = cursor.count()?;
+        let data = decompress(cursor.bytes(compressed_size)?, size)?;
+        self.tokens = data
+            .split(|b| *b == 0)
+            .take(n)
+            .map(|t| String::from_utf8_lossy(t).into_owned())
+            .collect();
+        if self.tokens.len() != n {
+            return Err(String::from("Invalid USD crate tokens"));
+        }
+        Ok(())
+    }
+
+    /// Reads the token indices of the strings.
+    ///
+    /// * `cursor` - Cursor at the section.
+    fn read_strings(&mut self, cursor: &mut Cursor) -> Result<(), String> {
+        let n = cursor.count()?;
+        self.strings = (0..n)
+            .map(|_| cursor.u32().map(|i| i as usize))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Reads the field names and value representations.
+    ///
+    /// * `cursor` - Cursor at the section.
+    fn read_fields(&self, cursor: &mut Cursor) -> Result<Vec<(String, ValueRep)>, String> {
+        let n = cursor.count()?;
+        let names = read_compressed_ints(cursor, n, false)?;
+        let reps_size = cursor.count()?;
+        let reps = decompress(cursor.bytes(reps_size)?, 8 * n)?;
+        let mut reps = Cursor::new(&reps, 0);
+        names
+            .iter()
+            .map(|name| Ok((self.token(*name as u32)?, ValueRep(reps.u64()?))))
+            .collect()
+    }
+
+    /// Reads the field sets. Each set is a list of field indices terminated
+    /// by `u32::MAX`.
+    ///
+    /// * `cursor` - Cursor at the section.
+    fn read_field_sets(&self, cursor: &mut Cursor) -> Result<Vec<u32>, String> {
+        let n = cursor.count()?;
+        Ok(read_compressed_ints(cursor, n, false)?
+            .iter()
+            .map(|i| *i as u32)
+            .collect())
+    }
+
+    /// Reads the paths. They are stored in depth first order with the token
+    /// of the last element of each path and the distance to its next sibling.
+    ///
+    /// * `cursor` - Cursor at the section.
+    fn read_paths(&mut self, cursor: &mut Cursor) -> Result<(), String> {
+        self.paths = vec![String::new(); cursor.count()?];
+        let n = cursor.count()?;
+        let path_indices = read_compressed_ints(cursor, n, false)?;
+        let element_tokens = read_compressed_ints(cursor, n, false)?;
+        let jumps = read_compressed_ints(cursor, n, false)?;
+
+        // Siblings that still have to be visited with their parent paths.
+        let mut pending = vec![(0, String::new())];
+        while let Some((mut i, mut parent)) = pending.pop() {
+            loop {
+                if i >= n {
+                    return Err(String::from("Invalid USD crate paths"));
+                }
+                let path = if parent.is_empty() {
+                    String::from("/")
+                } else {
+                    let token = element_tokens[i] as i32;
+                    let element = self.token(token.unsigned_abs())?;
+                    if token < 0 {
+                        format!("{}.{}", parent, element)
+                    } else {
+                        child_path(&parent, &element)
+                    }
+                };
+                let slot = self
+                    .paths
+                    .get_mut(path_indices[i] as usize)
+                    .ok_or_else(|| String::from("Invalid USD crate path index"))?;
+                *slot = path.clone();
+
+                let jump = jumps[i] as i32;
+                let has_child = jump > 0 || jump == -1;
+                let has_sibling = jump >= 0;
+                if has_child {
+                    if has_sibling {
+                        pending.push((i + jump as usize, parent));
+                    }
+                    parent = path;
+                } else if !has_sibling {
+                    break;
+                }
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the specs with their fields.
+    ///
+    /// * `cursor`     - Cursor at the section.
+    /// * `fields`     - The fields.
+    /// * `field_sets` - The field sets.
+    fn read_specs(
+        &mut self,
+        cursor: &mut Cursor,
+        fields: &[(String, ValueRep)],
+        field_sets: &[u32],
+    ) -> Result<(), String> {
+        let n = cursor.count()?;
+        let paths = read_compressed_ints(cursor, n, false)?;
+        let sets = read_compressed_ints(cursor, n, false)?;
+        let spec_types = read_compressed_ints(cursor, n, false)?;
+
+        for i in 0..n {
+            let path = self
+                .paths
+                .get(paths[i] as usize)
+                .ok_or_else(|| String::from("Invalid USD crate path index"))?
+                .clone();
+            let spec_fields = field_sets
+                .iter()
+                .skip(sets[i] as usize)
+                .take_while(|f| **f != u32::MAX)
+                .map(|f| {
+                    fields
+                        .get(*f as usize)
+                        .cloned()
+                        .ok_or_else(|| String::from("Invalid USD crate field index"))
+                })
+                .collect::<Result<_, _>>()?;
+            let spec = Spec {
+                spec_type: spec_types[i] as u32,
+                fields: spec_fields,
+            };
+            self.specs.insert(path, spec);
+        }
+        Ok(())
+    }
+
+    /// Returns a token.
+    ///
+    /// * `i` - Index of the token.
+    fn token(&self, i: u32) -> Result<String, String> {
+        self.tokens
+            .get(i as usize)
+            .cloned()
+            .ok_or_else(|| format!("Invalid USD crate token index {}", i))
+    }
+
+    /// Returns a string.
+    ///
+    /// * `i` - Index of the string.
+    fn string(&self, i: u32) -> Result<String, String> {
+        match self.strings.get(i as usize) {
+            Some(token) => self.token(*token as u32),
+            None => Err(format!("Invalid USD crate string index {}", i)),
+        }
+    }
+
+    /// Returns a path.
+    ///
+    /// * `i` - Index of the path.
+    fn path(&self, i: u32) -> Result<String, String> {
+        self.paths
+            .get(i as usize)
+            .cloned()
+            .ok_or_else(|| format!("Invalid USD crate path index {}", i))
+    }
+
+    /// Returns a cursor at an offset in the file.
+    ///
+    /// * `offset` - The offset.
+    fn at(&self, offset: u64) -> Cursor<'a> {
+        Cursor::new(self.data, offset as usize)
+    }
+
+    /// Returns the value of a value representation.
+    ///
+    /// * `rep` - The value representation.
+    fn value(&self, rep: ValueRep) -> Result<UsdValue, String> {
+        if rep.is_array() {
+            return self.array(rep);
+        }
+
+        let type_id = rep.type_id();
+        let payload = rep.payload();
+        if rep.is_inlined() {
+            return self.inlined_value(type_id, payload);
+        }
+
+        let mut cursor = self.at(payload);
+        match type_id {
+            TYPE_DICTIONARY => self.dictionary(&mut cursor),
+            TYPE_TOKEN_LIST_OP => {
+                self.list_op(&mut cursor, |r, c| Ok(UsdValue::Token(r.token(c.u32()?)?)))
+            }
+            TYPE_STRING_LIST_OP => self.list_op(&mut cursor, |r, c| {
+                Ok(UsdValue::String(r.string(c.u32()?)?))
+            }),
+            TYPE_PATH_LIST_OP => {
+                self.list_op(&mut cursor, |r, c| Ok(UsdValue::Path(r.path(c.u32()?)?)))
+            }
+            TYPE_REFERENCE_LIST_OP => self.list_op(&mut cursor, |r, c| r.reference(c)),
+            TYPE_PAYLOAD_LIST_OP => self.list_op(&mut cursor, |r, c| r.payload(c)),
+            TYPE_INT_LIST_OP | TYPE_UINT_LIST_OP => {
+                self.list_op(&mut cursor, |_, c| Ok(UsdValue::Number(c.i32()? as f64)))
+            }
+            TYPE_INT64_LIST_OP | TYPE_UINT64_LIST_OP => {
+                self.list_op(&mut cursor, |_, c| Ok(UsdValue::Number(c.i64()? as f64)))
+            }
+            TYPE_PATH_VECTOR => {
+                self.vector(&mut cursor, |r, c| Ok(UsdValue::Path(r.path(c.u32()?)?)))
+            }
+            TYPE_TOKEN_VECTOR => {
+                self.vector(&mut cursor, |r, c| Ok(UsdValue::Token(r.token(c.u32()?)?)))
+            }
+            TYPE_STRING_VECTOR => self.vector(&mut cursor, |r, c| {
+                Ok(UsdValue::String(r.string(c.u32()?)?))
+            }),
+            TYPE_DOUBLE_VECTOR => self.vector(&mut cursor, |_, c| Ok(UsdValue::Number(c.f64()?))),
+            TYPE_VARIANT_SELECTION_MAP => {
+                let n = cursor.count()?;
+                let entries = (0..n)
+                    .map(|_| {
+                        let key = self.string(cursor.u32()?)?;
+                        Ok((key, UsdValue::String(self.string(cursor.u32()?)?)))
+                    })
+                    .collect::<Result<_, String>>()?;
+                Ok(UsdValue::Dictionary(entries))
+            }
+            TYPE_TIME_SAMPLES => self.time_samples(&mut cursor),
+            TYPE_PAYLOAD => self.payload(&mut cursor),
+            TYPE_VALUE => self.nested_value(&mut cursor),
+            _ if numeric_type(type_id).is_some() => self.numeric(&mut cursor, type_id),
+            _ => Ok(UsdValue::None),
+        }
+    }
+
+    /// Returns a value stored in the payload of its representation.
+    ///
+    /// * `type_id` - The value type.
+    /// * `payload` - The payload.
+    fn inlined_value(&self, type_id: u8, payload: u64) -> Result<UsdValue, String> {
+        let bits = payload as u32;
+        Ok(match type_id {
+            TYPE_BOOL => bool_token(payload != 0),
+            TYPE_STRING => UsdValue::String(self.string(bits)?),
+            TYPE_TOKEN => UsdValue::Token(self.token(bits)?),
+            TYPE_ASSET_PATH => UsdValue::Asset(self.token(bits)?),
+            TYPE_SPECIFIER => {
+                let specifiers = ["def", "over", "class"];
+                UsdValue::Token(String::from(
+                    *specifiers.get(bits as usize).unwrap_or(&"def"),
+                ))
+            }
+            TYPE_VARIABILITY => {
+                UsdValue::Token(String::from(if bits == 1 { "uniform" } else { "varying" }))
+            }
+            TYPE_PERMISSION => {
+                UsdValue::Token(String::from(if bits == 1 { "private" } else { "public" }))
+            }
+            TYPE_VALUE_BLOCK => UsdValue::None,
+            _ => match numeric_type(type_id) {
+                // Scalars are stored directly and doubles as floats.
+                Some((scalar, 1, 1)) => UsdValue::Number(match scalar {
+                    Scalar::U8 => (bits & 0xff) as f64,
+                    Scalar::U32 => bits as f64,
+                    Scalar::I32 | Scalar::I64 | Scalar::U64 => bits as i32 as f64,
+                    Scalar::F16 => half_to_f32(bits as u16) as f64,
+                    Scalar::F32 | Scalar::F64 => f32::from_bits(bits) as f64,
+                }),
+
+                // Vectors with small integer components and diagonal
+                // matrices are stored with a signed byte per component.
+                Some((_, rows, cols)) => {
+                    let bytes = payload.to_le_bytes();
+                    let numbers: Vec<f64> = if rows == 1 {
+                        (0..cols).map(|i| bytes[i] as i8 as f64).collect()
+                    } else {
+                        (0..rows * cols)
+                            .map(|i| {
+                                let (r, c) = (i / cols, i % cols);
+                                if r == c {
+                                    bytes[r] as i8 as f64
+                                } else {
+                                    0.0
+                                }
+                            })
+                            .collect()
+                    };
+                    numeric_value(type_id, rows, cols, &numbers)
+                }
+                None => UsdValue::None,
+            },
+        })
+    }
+
+    /// Returns a numeric value stored at a cursor.
+    ///
+    /// * `cursor`  - The cursor.
+    /// * `type_id` - The value type.
+    fn numeric(&self, cursor: &mut Cursor, type_id: u8) -> Result<UsdValue, String> {
+        let (scalar, rows, cols) = numeric_type(type_id)
+            .ok_or_else(|| format!("USD crate values of type {} not supported", type_id))?;
+        let numbers = (0..rows * cols)
+            .map(|_| cursor.scalar(scalar))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(match (rows, cols) {
+            (1, 1) => UsdValue::Number(numbers[0]),
+            _ => numeric_value(type_id, rows, cols, &numbers),
+        })
+    }
+
+    /// Returns an array value.
+    ///
+    /// * `rep` - The value representation.
+    fn array(&self, rep: ValueRep) -> Result<UsdValue, String> {
+        // Empty arrays are not stored.
+        if rep.payload() == 0 {
+            return Ok(UsdValue::List(vec![]));
+        }
+
+        let mut cursor = self.at(rep.payload());
+        if self.version < (0, 5, 0) {
+            // Skip the rank of the array shape.
+            cursor.u32()?;
+        }
+        let n = if self.version < (0, 7, 0) {
+            cursor.u32()? as usize
+        } else {
+            cursor.count()?
+        };
+
+        let type_id = rep.type_id();
+        if let Some((scalar, 1, 1)) = numeric_type(type_id) {
+            if rep.is_compressed() && self.version >= (0, 5, 0) && n >= MIN_COMPRESSED_ARRAY_SIZE {
+                let numbers = self.compressed_array(&mut cursor, scalar, n)?;
+                return Ok(UsdValue::List(
+                    numbers.into_iter().map(UsdValue::Number).collect(),
+                ));
+            }
+        }
+
+        let values = (0..n)
+            .map(|_| match type_id {
+                TYPE_BOOL => Ok(bool_token(cursor.u8()? != 0)),
+                TYPE_STRING => Ok(UsdValue::String(self.string(cursor.u32()?)?)),
+                TYPE_TOKEN => Ok(UsdValue::Token(self.token(cursor.u32()?)?)),
+                TYPE_ASSET_PATH => Ok(UsdValue::Asset(self.token(cursor.u32()?)?)),
+                _ => self.numeric(&mut cursor, type_id),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(UsdValue::List(values))
+    }
+
+    /// Returns the elements of a compressed array of integers or floats.
+    /// Floats are compressed as integers or as indices into a table.
+    ///
+    /// * `cursor` - Cursor after the array size.
+    /// * `scalar` - The element type.
+    /// * `n`      - Number of elements.
+    fn compressed_array(
+        &self,
+        cursor: &mut Cursor,
+        scalar: Scalar,
+        n: usize,
+    ) -> Result<Vec<f64>, String> {
+        match scalar {
+            Scalar::F16 | Scalar::F32 | Scalar::F64 => match cursor.u8()? {
+                b'i' => Ok(read_compressed_ints(cursor, n, false)?
+                    .into_iter()
+                    .map(|i| i as f64)
+                    .collect()),
+                b't' => {
+                    let table_size = cursor.u32()? as usize;
+                    let table = (0..table_size)
+                        .map(|_| cursor.scalar(scalar))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    read_compressed_ints(cursor, n, false)?
+                        .into_iter()
+                        .map(|i| {
+                            table
+                                .get(i as u32 as usize)
+                                .copied()
+                                .ok_or_else(|| String::from("Invalid USD crate table index"))
+                        })
+                        .collect()
+                }
+                code => Err(format!("Invalid USD crate float array code {}", code)),
+            },
+            Scalar::I64 | Scalar::U64 => Ok(read_compressed_ints(cursor, n, true)?
+                .into_iter()
+                .map(|i| i as f64)
+                .collect()),
+            Scalar::U32 => Ok(read_compressed_ints(cursor, n, false)?
+                .into_iter()
+                .map(|i| i as u32 as f64)
+                .collect()),
+            _ => Ok(read_compressed_ints(cursor, n, false)?
+                .into_iter()
+                .map(|i| i as f64)
+                .collect()),
+        }
+    }
+
+    /// Returns a value stored elsewhere that a cursor points to with an
+    /// offset relative to the cursor.
+    ///
+    /// * `cursor` - The cursor.
+    fn nested_value(&self, cursor: &mut Cursor) -> Result<UsdValue, String> {
+        let start = cursor.pos as i64;
+        let offset = cursor.i64()?;
+        let rep = ValueRep(self.at((start + offset) as u64).u64()?);
+        self.value(rep)
+    }
+
+    /// Returns a dictionary.
+    ///
+    /// * `cursor` - Cursor at the dictionary.
+    fn dictionary(&self, cursor: &mut Cursor) -> Result<UsdValue, String> {
+        let n = cursor.count()?;
+        let entries = (0..n)
+            .map(|_| {
+                let key = self.string(cursor.u32()?)?;
+                Ok((key, self.nested_value(cursor)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(UsdValue::Dictionary(entries))
+    }
+
+    /// Returns the items of a vector.
+    ///
+    /// * `cursor` - Cursor at the vector.
+    /// * `item`   - Reads an item.
+    fn vector<F>(&self, cursor: &mut Cursor, item: F) -> Result<UsdValue, String>
+    where
+        F: Fn(&Self, &mut Cursor) -> Result<UsdValue, String>,
+    {
+        let n = cursor.count()?;
+        let items = (0..n)
+            .map(|_| item(self, cursor))
+            .collect::<Result<_, _>>()?;
+        Ok(UsdValue::List(items))
+    }
+
+    /// Returns the items of a list editing operation as if they were
+    /// explicit. Deleted items are ignored.
+    ///
+    /// * `cursor` - Cursor at the operation.
+    /// * `item`   - Reads an item.
+    fn list_op<F>(&self, cursor: &mut Cursor, item: F) -> Result<UsdValue, String>
+    where
+        F: Fn(&Self, &mut Cursor) -> Result<UsdValue, String>,
+    {
+        let header = cursor.u8()?;
+        let mut lists = HashMap::new();
+        for bit in [
+            LIST_OP_EXPLICIT_ITEMS,
+            LIST_OP_ADDED_ITEMS,
+            LIST_OP_PREPENDED_ITEMS,
+            LIST_OP_APPENDED_ITEMS,
+            LIST_OP_DELETED_ITEMS,
+            LIST_OP_ORDERED_ITEMS,
+        ] {
+            if header & bit != 0 {
+                if let UsdValue::List(items) = self.vector(cursor, &item)? {
+                    lists.insert(bit, items);
+                }
+            }
+        }
+
+        let items = [
+            LIST_OP_EXPLICIT_ITEMS,
+            LIST_OP_PREPENDED_ITEMS,
+            LIST_OP_ADDED_ITEMS,
+            LIST_OP_APPENDED_ITEMS,
+        ]
+        .iter()
+        .filter_map(|bit| lists.remove(bit))
+        .flatten()
+        .collect();
+        Ok(UsdValue::List(items))
+    }
+
+    /// Returns a reference to a prim in a layer.
+    ///
+    /// * `cursor` - Cursor at the reference.
+    fn reference(&self, cursor: &mut Cursor) -> Result<UsdValue, String> {
+        let asset = self.string(cursor.u32()?)?;
+        let path = self.path(cursor.u32()?)?;
+
+        // Skip the layer offset and the custom data.
+        cursor.f64()?;
+        cursor.f64()?;
+        self.dictionary(cursor)?;
+
+        Ok(arc_target(asset, path))
+    }
+
+    /// Returns a payload.
+    ///
+    /// * `cursor` - Cursor at the payload.
+    fn payload(&self, cursor: &mut Cursor) -> Result<UsdValue, String> {
+        let asset = self.string(cursor.u32()?)?;
+        let path = self.path(cursor.u32()?)?;
+        if self.version >= (0, 8, 0) {
+            // Skip the layer offset.
+            cursor.f64()?;
+            cursor.f64()?;
+        }
+        Ok(arc_target(asset, path))
+    }
+
+    /// Returns time samples as a dictionary of values by time.
+    ///
+    /// * `cursor` - Cursor at the time samples.
+    fn time_samples(&self, cursor: &mut Cursor) -> Result<UsdValue, String> {
+        // The times and the values are stored after offsets relative to the
+        // offsets themselves.
+        let start = cursor.pos as i64;
+        let times_offset = cursor.i64()?;
+        let mut times_cursor = self.at((start + times_offset) as u64);
+        let times = self.value(ValueRep(times_cursor.u64()?))?.as_floats();
+
+        let start = times_cursor.pos as i64;
+        let values_offset = times_cursor.i64()?;
+        let mut values_cursor = self.at((start + values_offset) as u64);
+        let n = values_cursor.count()?;
+        if n != times.len() {
+            return Err(String::from("Invalid USD crate time samples"));
+        }
+        let samples = times
+            .iter()
+            .map(|t| {
+                let rep = ValueRep(values_cursor.u64()?);
+                Ok((format!("{}", t), self.value(rep)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(UsdValue::Dictionary(samples))
+    }
+
+    /// Returns the layer with the prims below the pseudo-root.
+    fn layer(&self) -> Result<UsdLayer, String> {
+        let mut layer = UsdLayer::default();
+        let root = match self.specs.get("/") {
+            Some(root) if root.spec_type == SPEC_PSEUDO_ROOT => root,
+            _ => return Err(String::from("USD crate file has no pseudo-root")),
+        };
+        for (name, rep) in root.fields.iter() {
+            if name == "primChildren" {
+                for child in self.value(*rep)?.as_list() {
+                    let name = child.as_str().unwrap_or_default();
+                    layer.prims.push(self.prim(&child_path("/", name), name)?);
+                }
+            } else {
+                layer
+                    .metadata
+                    .insert(metadata_name(name), self.value(*rep)?);
+            }
+        }
+        Ok(layer)
+    }
+
+    /// Returns a prim or variant specification with its children, properties
+    /// and variant sets.
+    ///
+    /// * `path` - Path of the spec.
+    /// * `name` - Name of the prim or variant.
+    fn prim(&self, path: &str, name: &str) -> Result<UsdPrim, String> {
+        let spec = self
+            .specs
+            .get(path)
+            .ok_or_else(|| format!("USD crate file has no spec for '{}'", path))?;
+        let mut prim = UsdPrim {
+            specifier: String::from(if spec.spec_type == SPEC_PRIM {
+                "def"
+            } else {
+                "over"
+            }),
+            name: String::from(name),
+            ..UsdPrim::default()
+        };
+
+        let names = |rep: &ValueRep| -> Result<Vec<String>, String> {
+            Ok(self
+                .value(*rep)?
+                .as_list()
+                .iter()
+                .filter_map(|v| v.as_str())
+                .map(String::from)
+                .collect())
+        };
+        for (field, rep) in spec.fields.iter() {
+            match field.as_str() {
+                "specifier" | "typeName" => {
+                    let value = self.value(*rep)?;
+                    let value = String::from(value.as_str().unwrap_or_default());
+                    if field == "specifier" {
+                        prim.specifier = value;
+                    } else {
+                        prim.type_name = value;
+                    }
+                }
+                "primChildren" => {
+                    for child in names(rep)? {
+                        prim.children
+                            .push(self.prim(&child_path(path, &child), &child)?);
+                    }
+                }
+                "properties" => {
+                    for property in names(rep)? {
+                        self.property(&format!("{}.{}", path, property), &property, &mut prim)?;
+                    }
+                }
+                "variantSetChildren" => {
+                    for set in names(rep)? {
+                        let variants = match self.specs.get(&format!("{}{{{}=}}", path, set)) {
+                            Some(spec) => spec.fields.iter().find(|(f, _)| f == "variantChildren"),
+                            None => None,
+                        };
+                        let mut prims = vec![];
+                        if let Some((_, rep)) = variants {
+                            for variant in names(rep)? {
+                                let variant_path = format!("{}{{{}={}}}", path, set, variant);
+                                prims.push(self.prim(&variant_path, &variant)?);
+                            }
+                        }
+                        prim.variant_sets.push((set, prims));
+                    }
+                }
+                "variantSetNames" => (),
+                _ => {
+                    prim.metadata
+                        .insert(metadata_name(field), self.value(*rep)?);
+                }
+            }
+        }
+        Ok(prim)
+    }
+
+    /// Adds an attribute or relationship to a prim. Connections and time
+    /// samples are added as properties of their own like in `usda` layers.
+    ///
+    /// * `path` - Path of the property.
+    /// * `name` - Name of the property.
+    /// * `prim` - The prim.
+    fn property(&self, path: &str, name: &str, prim: &mut UsdPrim) -> Result<(), String> {
+        let spec = match self.specs.get(path) {
+            Some(spec)
+                if spec.spec_type == SPEC_ATTRIBUTE || spec.spec_type == SPEC_RELATIONSHIP =>
+            {
+                spec
+            }
+            _ => return Ok(()),
+        };
+
+        let mut property = UsdProperty::default();
+        if spec.spec_type == SPEC_RELATIONSHIP {
+            property.type_name = String::from("rel");
+        }
+        let mut extra = vec![];
+        for (field, rep) in spec.fields.iter() {
+            let value = self.value(*rep)?;
+            match field.as_str() {
+                "typeName" => property.type_name = String::from(value.as_str().unwrap_or_default()),
+                "default" => property.value = Some(value),
+                "targetPaths" => property.value = Some(single_or_list(value)),
+                "connectionPaths" => extra.push(("connect", single_or_list(value))),
+                "timeSamples" => extra.push(("timeSamples", value)),
+                "variability" | "custom" => (),
+                f if CHILDREN_FIELDS.contains(&f) => (),
+                _ => {
+                    property.metadata.insert(metadata_name(field), value);
+                }
+            }
+        }
+
+        let declared = property.value.is_some() || extra.is_empty();
+        for (suffix, value) in extra {
+            let mut p = property.clone();
+            p.value = Some(value);
+            prim.properties.insert(format!("{}.{}", name, suffix), p);
+        }
+        if declared {
+            prim.properties.insert(String::from(name), property);
+        }
+        Ok(())
+    }
+}
+
+impl UsdLayer {
+    /// Reads a binary `usdc` layer.
+    ///
+    /// * `data` - The layer contents.
+    pub fn parse_crate(data: &[u8]) -> Result<Self, String> {
+        CrateReader::new(data)?.layer()
+    }
+}
+
+/// Returns the path of a child prim or variant selection.
+///
+/// * `parent`  - Path of the parent.
+/// * `element` - Name of the child or the `{set=variant}` selection.
+fn child_path(parent: &str, element: &str) -> String {
+    if element.starts_with('{') {
+        format!("{}{}", parent, element)
+    } else if parent == "/" {
+        format!("/{}", element)
+    } else {
+        format!("{}/{}", parent, element)
+    }
+}
+
+/// Returns the name used in `usda` layers for a field.
+///
+/// * `field` - The field name.
+fn metadata_name(field: &str) -> String {
+    String::from(match field {
+        "inheritPaths" => "inherits",
+        "variantSelection" => "variants",
+        "documentation" => "doc",
+        _ => field,
+    })
+}
+
+/// Returns the token used in `usda` layers for a boolean.
+///
+/// * `b` - The boolean.
+fn bool_token(b: bool) -> UsdValue {
+    UsdValue::Token(String::from(if b { "true" } else { "false" }))
+}
+
+/// Returns a single item instead of a list of one item.
+///
+/// * `value` - The list.
+fn single_or_list(value: UsdValue) -> UsdValue {
+    match value {
+        UsdValue::List(mut items) if items.len() == 1 => items.remove(0),
+        value => value,
+    }
+}
+
+/// Returns the target of a reference or payload like in `usda` layers.
+///
+/// * `asset` - Path of the layer; empty for the same layer.
+/// * `path`  - Path of the prim; empty for the default prim.
+fn arc_target(asset: String, path: String) -> UsdValue {
+    match (asset.is_empty(), path.is_empty()) {
+        (true, _) => UsdValue::Path(path),
+        (false, true) => UsdValue::Asset(asset),
+        (false, false) => UsdValue::Reference(asset, path),
+    }
+}
+
+/// Returns the value of a vector, quaternion or matrix. Quaternions are
+/// stored with the real part last but written with it first.
+///
+/// * `type_id` - The value type.
+/// * `rows`    - Number of rows.
+/// * `cols`    - Number of columns.
+/// * `numbers` - The components in row-major order.
+fn numeric_value(type_id: u8, rows: usize, cols: usize, numbers: &[f64]) -> UsdValue {
+    let row = |r: usize| {
+        UsdValue::Tuple(
+            numbers[r * cols..(r + 1) * cols]
+                .iter()
+                .map(|n| UsdValue::Number(*n))
+                .collect(),
+        )
+    };
+    if rows == 1 {
+        let v = row(0);
+        if (TYPE_QUATD..=TYPE_QUATH).contains(&type_id) {
+            reorder_quaternion(v)
+        } else {
+            v
+        }
+    } else {
+        UsdValue::Tuple((0..rows).map(row).collect())
+    }
+}
+
+/// Moves the real part of a quaternion from the last to the first component.
+///
+/// * `q` - The quaternion.
+fn reorder_quaternion(q: UsdValue) -> UsdValue {
+    match q {
+        UsdValue::Tuple(mut c) if c.len() == 4 => {
+            c.rotate_right(1);
+            UsdValue::Tuple(c)
+        }
+        q => q,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compresses data like `TfFastCompression` with a single chunk.
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut compressed = vec![0];
+        compressed.extend(lz4_flex::block::compress(data));
+        compressed
+    }
+
+    /// Encodes 32-bit integers like `Usd_IntegerCompression` with every
+    /// difference stored in full.
+    fn encode_ints(values: &[i32]) -> Vec<u8> {
+        let mut encoded = 0i32.to_le_bytes().to_vec();
+        encoded.extend(vec![0xff; (2 * values.len()).div_ceil(8)]);
+        let mut previous = 0i32;
+        for v in values.iter() {
+            encoded.extend(v.wrapping_sub(previous).to_le_bytes());
+            previous = *v;
+        }
+        encoded
+    }
+
+    /// Writes a crate file.
+    #[derive(Default)]
+    struct CrateWriter {
+        data: Vec<u8>,
+        tokens: Vec<String>,
+        strings: Vec<u32>,
+        fields: Vec<(u32, u64)>,
+        field_sets: Vec<i32>,
+        specs: Vec<(i32, i32, i32)>,
+    }
+
+    impl CrateWriter {
+        fn new() -> Self {
+            let mut writer = Self::default();
+            writer.data.extend(USDC_MAGIC);
+            writer.data.extend([0, 8, 0, 0, 0, 0, 0, 0]);
+            writer.data.extend([0; 8]);
+            writer
+        }
+
+        fn token(&mut self, token: &str) -> u32 {
+            match self.tokens.iter().position(|t| t == token) {
+                Some(i) => i as u32,
+                None => {
+                    self.tokens.push(String::from(token));
+                    self.tokens.len() as u32 - 1
+                }
+            }
+        }
+
+        fn string(&mut self, s: &str) -> u32 {
+            let token = self.token(s);
+            self.strings.push(token);
+            self.strings.len() as u32 - 1
+        }
+
+        /// Appends bytes and returns their offset.
+        fn write(&mut self, bytes: &[u8]) -> u64 {
+            self.data.extend(bytes);
+            (self.data.len() - bytes.len()) as u64
+        }
+
+        fn write_ints(&mut self, values: &[i32]) {
+            let compressed = compress(&encode_ints(values));
+            self.write(&(compressed.len() as u64).to_le_bytes());
+            self.write(&compressed);
+        }
+
+        /// Adds a spec with its fields.
+        fn spec(&mut self, path: i32, spec_type: u32, fields: Vec<(&str, u64)>) {
+            self.specs
+                .push((path, self.field_sets.len() as i32, spec_type as i32));
+            for (name, rep) in fields {
+                let name = self.token(name);
+                self.field_sets.push(self.fields.len() as i32);
+                self.fields.push((name, rep));
+            }
+            self.field_sets.push(-1);
+        }
+
+        /// Writes the sections after the values and returns the file.
+        fn finish(mut self, paths: &[(i32, i32, i32)]) -> Vec<u8> {
+            let mut sections = vec![];
+
+            let mut tokens = vec![];
+            for t in self.tokens.iter() {
+                tokens.extend(t.as_bytes());
+                tokens.push(0);
+            }
+            let compressed = compress(&tokens);
+            let start = self.write(&(self.tokens.len() as u64).to_le_bytes());
+            self.write(&(tokens.len() as u64).to_le_bytes());
+            self.write(&(compressed.len() as u64).to_le_bytes());
+            self.write(&compressed);
+            sections.push(("TOKENS", start));
+
+            let start = self.write(&(self.strings.len() as u64).to_le_bytes());
+            for s in self.strings.clone() {
+                self.write(&s.to_le_bytes());
+            }
+            sections.push(("STRINGS", start));
+
+            let start = self.write(&(self.fields.len() as u64).to_le_bytes());
+            let fields = self.fields.clone();
+            self.write_ints(&fields.iter().map(|f| f.0 as i32).collect::<Vec<_>>());
+            let reps: Vec<u8> = fields.iter().flat_map(|f| f.1.to_le_bytes()).collect();
+            let compressed = compress(&reps);
+            self.write(&(compressed.len() as u64).to_le_bytes());
+            self.write(&compressed);
+            sections.push(("FIELDS", start));
+
+            let start = self.write(&(self.field_sets.len() as u64).to_le_bytes());
+            let field_sets = self.field_sets.clone();
+            self.write_ints(&field_sets);
+            sections.push(("FIELDSETS", start));
+
+            let start = self.write(&(paths.len() as u64).to_le_bytes());
+            self.write(&(paths.len() as u64).to_le_bytes());
+            self.write_ints(&paths.iter().map(|p| p.0).collect::<Vec<_>>());
+            self.write_ints(&paths.iter().map(|p| p.1).collect::<Vec<_>>());
+            self.write_ints(&paths.iter().map(|p| p.2).collect::<Vec<_>>());
+            sections.push(("PATHS", start));
+
+            let start = self.write(&(self.specs.len() as u64).to_le_bytes());
+            let specs = self.specs.clone();
+            self.write_ints(&specs.iter().map(|s| s.0).collect::<Vec<_>>());
+            self.write_ints(&specs.iter().map(|s| s.1).collect::<Vec<_>>());
+            self.write_ints(&specs.iter().map(|s| s.2).collect::<Vec<_>>());
+            sections.push(("SPECS", start));
+
+            let toc = self.write(&(sections.len() as u64).to_le_bytes());
+            for (name, start) in sections {
+                let mut section_name = [0; SECTION_NAME_SIZE];
+                section_name[..name.len()].copy_from_slice(name.as_bytes());
+                self.write(&section_name);
+                self.write(&start.to_le_bytes());
+                self.write(&0u64.to_le_bytes());
+            }
+            self.data[16..24].copy_from_slice(&toc.to_le_bytes());
+            self.data
+        }
+    }
+
+    /// Returns a value representation.
+    fn rep(type_id: u8, inlined: bool, payload: u64) -> u64 {
+        ((type_id as u64) << 48) | if inlined { 1 << 62 } else { 0 } | payload
+    }
+
+    /// Returns a value representation of an array.
+    fn array_rep(type_id: u8, compressed: bool, offset: u64) -> u64 {
+        rep(type_id, false, offset) | (1 << 63) | if compressed { 1 << 61 } else { 0 }
+    }
+
+    #[test]
+    fn decode_ints_accumulates_differences() {
+        let values = [3, -7, 100_000, 100_000, i32::MIN];
+        let decoded = decode_ints(&encode_ints(&values), values.len(), false).unwrap();
+        assert_eq!(decoded, vec![3, -7, 100_000, 100_000, i32::MIN as i64]);
+
+        // Common differences and small differences in one byte.
+        let encoded = [2, 0, 0, 0, 0b0001_0000, 5];
+        let decoded = decode_ints(&encoded, 4, false).unwrap();
+        assert_eq!(decoded, vec![2, 4, 9, 11]);
+    }
+
+    #[test]
+    fn decompress_chunks() {
+        let data: Vec<u8> = (0..64).map(|i| (i % 7) as u8).collect();
+        assert_eq!(decompress(&compress(&data), data.len()).unwrap(), data);
+
+        let chunk = lz4_flex::block::compress(&data);
+        let mut chunked = vec![2];
+        for _ in 0..2 {
+            chunked.extend((chunk.len() as u32).to_le_bytes());
+            chunked.extend(&chunk);
+        }
+        assert_eq!(
+            decompress(&chunked, 2 * data.len()).unwrap(),
+            [data.clone(), data].concat()
+        );
+    }
+
+    #[test]
+    fn invalid_crate_is_an_error() {
+        assert!(UsdLayer::parse_crate(b"PXR-USDC").is_err());
+
+        let mut old = CrateWriter::new().finish(&[(0, 0, -1)]);
+        old[9] = 3;
+        assert!(UsdLayer::parse_crate(&old)
+            .unwrap_err()
+            .contains("not supported"));
+    }
+
+    #[test]
+    fn parse_crate_matches_usda() {
+        let document = r#"#usda 1.0
+            (
+                defaultPrim = "World"
+            )
+
+            def Xform "World"
+            {
+                def Mesh "Quad" (
+                    instanceable = true
+                    prepend references = @./quad.usda@</Quad>
+                )
+                {
+                    point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0.5)]
+                    int[] faceVertexIndices = [0, 1, 2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0, 1, 2, 2, 1, 0]
+                    double3 xformOp:translate.timeSamples = {
+                        0: (1, 2, 3),
+                        2.5: (4, 5, 6),
+                    }
+                    rel material:binding = </World/Red>
+                }
+
+                def Material "Red"
+                {
+                }
+            }
+        "#;
+        let usda = UsdLayer::parse(document).unwrap();
+
+        let mut writer = CrateWriter::new();
+        let root = writer.token("");
+        let world = writer.token("World") as i32;
+        let quad = writer.token("Quad") as i32;
+        let red = writer.token("Red") as i32;
+        let points = writer.token("points") as i32;
+        let indices = writer.token("faceVertexIndices") as i32;
+        let translate = writer.token("xformOp:translate") as i32;
+        let binding = writer.token("material:binding") as i32;
+
+        // Values that are not inlined.
+        let vector = |w: &mut CrateWriter, items: &[i32]| {
+            let offset = w.write(&(items.len() as u64).to_le_bytes());
+            for i in items {
+                w.write(&(*i as u32).to_le_bytes());
+            }
+            offset
+        };
+        let root_children = vector(&mut writer, &[world]);
+        let world_children = vector(&mut writer, &[quad, red]);
+        let quad_properties = vector(&mut writer, &[points, indices, translate, binding]);
+
+        let asset = writer.string("./quad.usda");
+        let references = writer.write(&[LIST_OP_PREPENDED_ITEMS]);
+        writer.write(&1u64.to_le_bytes());
+        writer.write(&asset.to_le_bytes());
+        writer.write(&8u32.to_le_bytes());
+        writer.write(&[0; 16]);
+        writer.write(&0u64.to_le_bytes());
+
+        let target = writer.write(&[LIST_OP_EXPLICIT_ITEMS]);
+        writer.write(&1u64.to_le_bytes());
+        writer.write(&7u32.to_le_bytes());
+
+        let points_value = writer.write(&3u64.to_le_bytes());
+        for p in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.5] {
+            writer.write(&p.to_le_bytes());
+        }
+
+        let index_values = [0, 1, 2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0, 1, 2, 2, 1, 0];
+        let indices_value = writer.write(&(index_values.len() as u64).to_le_bytes());
+        writer.write_ints(&index_values);
+
+        let times = writer.write(&2u64.to_le_bytes());
+        writer.write(&0f64.to_le_bytes());
+        writer.write(&2.5f64.to_le_bytes());
+        let samples = [[1.0f64, 2.0, 3.0], [4.0, 5.0, 6.0]]
+            .iter()
+            .map(|s| {
+                let offset = writer.write(&s[0].to_le_bytes());
+                writer.write(&s[1].to_le_bytes());
+                writer.write(&s[2].to_le_bytes());
+                offset
+            })
+            .collect::<Vec<_>>();
+        let time_samples = writer.write(&8i64.to_le_bytes());
+        writer.write(&rep(TYPE_DOUBLE_VECTOR, false, times).to_le_bytes());
+        writer.write(&8i64.to_le_bytes());
+        writer.write(&2u64.to_le_bytes());
+        for s in samples {
+            writer.write(&rep(TYPE_VEC2D + 4, false, s).to_le_bytes());
+        }
+
+        // Specs.
+        let token = |w: &mut CrateWriter, t: &str| rep(TYPE_TOKEN, true, w.token(t) as u64);
+        let default_prim = token(&mut writer, "World");
+        writer.spec(
+            0,
+            SPEC_PSEUDO_ROOT,
+            vec![
+                ("defaultPrim", default_prim),
+                ("primChildren", rep(TYPE_TOKEN_VECTOR, false, root_children)),
+            ],
+        );
+        let xform = token(&mut writer, "Xform");
+        writer.spec(
+            1,
+            SPEC_PRIM,
+            vec![
+                ("specifier", rep(TYPE_SPECIFIER, true, 0)),
+                ("typeName", xform),
+                (
+                    "primChildren",
+                    rep(TYPE_TOKEN_VECTOR, false, world_children),
+                ),
+            ],
+        );
+        let mesh = token(&mut writer, "Mesh");
+        writer.spec(
+            2,
+            SPEC_PRIM,
+            vec![
+                ("specifier", rep(TYPE_SPECIFIER, true, 0)),
+                ("typeName", mesh),
+                ("instanceable", rep(TYPE_BOOL, true, 1)),
+                ("references", rep(TYPE_REFERENCE_LIST_OP, false, references)),
+                ("properties", rep(TYPE_TOKEN_VECTOR, false, quad_properties)),
+            ],
+        );
+        let point3f = token(&mut writer, "point3f[]");
+        writer.spec(
+            3,
+            SPEC_ATTRIBUTE,
+            vec![
+                ("typeName", point3f),
+                ("default", array_rep(TYPE_VEC2D + 5, false, points_value)),
+            ],
+        );
+        let int_array = token(&mut writer, "int[]");
+        writer.spec(
+            4,
+            SPEC_ATTRIBUTE,
+            vec![
+                ("typeName", int_array),
+                ("default", array_rep(TYPE_INT, true, indices_value)),
+            ],
+        );
+        let double3 = token(&mut writer, "double3");
+        writer.spec(
+            5,
+            SPEC_ATTRIBUTE,
+            vec![
+                ("typeName", double3),
+                ("timeSamples", rep(TYPE_TIME_SAMPLES, false, time_samples)),
+            ],
+        );
+        writer.spec(
+            6,
+            SPEC_RELATIONSHIP,
+            vec![("targetPaths", rep(TYPE_PATH_LIST_OP, false, target))],
+        );
+        let material = token(&mut writer, "Material");
+        writer.spec(
+            7,
+            SPEC_PRIM,
+            vec![
+                ("specifier", rep(TYPE_SPECIFIER, true, 0)),
+                ("typeName", material),
+            ],
+        );
+
+        // Paths in depth first order with the distance to the next sibling;
+        // -1 when there is only a child and -2 for a leaf without siblings.
+        // `/Quad` is only the target of the reference.
+        let paths = [
+            (0, root as i32, -1),
+            (1, world, 7),
+            (2, quad, 5),
+            (3, -points, 0),
+            (4, -indices, 0),
+            (5, -translate, 0),
+            (6, -binding, -2),
+            (7, red, -2),
+            (8, quad, -2),
+        ];
+        let data = writer.finish(&paths);
+        let usdc = UsdLayer::parse_crate(&data).unwrap();
+
+        // Layers are read as crate files by their header, not their name.
+        let path = std::env::temp_dir().join("usdc_test_layer.usd");
+        std::fs::write(&path, &data).unwrap();
+        let loaded = super::super::load_layer(&path.to_string_lossy(), 0);
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.unwrap().find("/World/Quad").is_some());
+
+        assert_eq!(usdc.default_prim(), usda.default_prim());
+        for path in ["/World", "/World/Quad", "/World/Red"] {
+            let (c, a) = (usdc.find(path).unwrap(), usda.find(path).unwrap());
+            assert_eq!(c.specifier, a.specifier, "{}", path);
+            assert_eq!(c.type_name, a.type_name, "{}", path);
+            assert_eq!(c.children.len(), a.children.len(), "{}", path);
+        }
+
+        let (c, a) = (
+            usdc.find("/World/Quad").unwrap(),
+            usda.find("/World/Quad").unwrap(),
+        );
+        assert_eq!(c.meta("instanceable"), a.meta("instanceable"));
+        assert_eq!(
+            c.meta("references").unwrap().as_list(),
+            a.meta("references").unwrap().as_list()
+        );
+        for name in [
+            "points",
+            "faceVertexIndices",
+            "xformOp:translate.timeSamples",
+            "material:binding",
+        ] {
+            let (pc, pa) = (&c.properties[name], &a.properties[name]);
+            assert_eq!(pc.type_name, pa.type_name, "{}", name);
+            assert_eq!(pc.value, pa.value, "{}", name);
+        }
+        assert_eq!(c.properties.len(), a.properties.len());
+    }
+}
@@ -0,0 +1,157 @@
+//! Material Preview
+
+use super::{GraphicsState, TransformCache};
+use cameras::*;
+use core::camera::*;
+use core::film::*;
+use core::geometry::*;
+use core::image_io::*;
+use core::integrator::*;
+use core::light::*;
+use core::medium::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::primitive::*;
+use core::primitives::*;
+use core::sampler::*;
+use core::scene::*;
+use core::spectrum::*;
+use filters::*;
+use integrators::*;
+use lights::*;
+use samplers::*;
+use shapes::*;
+use std::sync::{Arc, Mutex};
+
+/// Renders a unit sphere with the given material under a canned environment
+/// made up of a constant environment light and a distant key light. This
+/// does not require a scene description and is meant for showing material
+/// swatches in editors.
+///
+/// * `name`              - Material type (matte, plastic, etc).
+/// * `params`            - Material parameters. Textures must be given as
+///                         constant values since there are no named textures.
+/// * `resolution`        - Width and height of the square swatch in pixels.
+/// * `samples_per_pixel` - Number of samples per pixel.
+pub fn render_material_preview(
+    name: &str,
+    params: &ParamSet,
+    resolution: usize,
+    samples_per_pixel: usize,
+) -> Result<RGBImage, String> {
+    if resolution == 0 || samples_per_pixel == 0 {
+        return Err(format!(
+            "Invalid preview resolution {} or samples per pixel {}.",
+            resolution, samples_per_pixel
+        ));
+    }
+
+    // Create the material.
+    let graphics_state = GraphicsState::new(Arc::new(Mutex::new(TransformCache::default())));
+    let mp = TextureParams::new(
+        params.clone(),
+        params.clone(),
+        graphics_state.float_textures.clone(),
+        graphics_state.spectrum_textures.clone(),
+    );
+    let material = graphics_state.make_material(name, &mp)?;
+
+    // Create the sphere at the origin.
+    let identity = Arc::new(Transform::default());
+    let sphere = Arc::new(Sphere::new(
+        Arc::clone(&identity),
+        Arc::clone(&identity),
+        false,
+        1.0,
+        -1.0,
+        1.0,
+        360.0,
+    ));
+    let aggregate: ArcPrimitive = Arc::new(GeometricPrimitive::new(
+        sphere,
+        material,
+        None,
+        MediumInterface::vacuum(),
+        None,
+    ));
+
+    // Create the lights. The scene initializes them with its bounds.
+    let environment =
+        InfiniteAreaLight::new(Arc::clone(&identity), Spectrum::new(0.5), 4, "", 0.0, None);
+    let key = DistantLight::new(
+        Arc::clone(&identity),
+        Spectrum::new(2.5),
+        Vector3f::new(-1.0, 1.0, 1.0),
+    );
+    let lights: Vec<ArcLight> = vec![Arc::new(environment), Arc::new(key)];
+    let light_names = vec![None; lights.len()];
+    let scene = Arc::new(Scene::new(aggregate, lights, light_names));
+
+    // Create a camera looking at the sphere along -z.
+    let res = Point2i::new(resolution as Int, resolution as Int);
+    let crop_window = Bounds2f::new(Point2f::new(0.0, 0.0), Point2f::new(1.0, 1.0));
+    let filter = Arc::new(BoxFilter::from(&ParamSet::new()));
+    let film = Film::new(&res, &crop_window, filter, 35.0, "", None, None);
+
+    let world_to_camera = Transform::look_at(
+        &Point3f::new(0.0, 0.0, 4.0),
+        &Point3f::new(0.0, 0.0, 0.0),
+        &Vector3f::new(0.0, 1.0, 0.0),
+    );
+    let camera_to_world = Arc::new(world_to_camera.inverse());
+    let camera_transform =
+        AnimatedTransform::new(Arc::clone(&camera_to_world), camera_to_world, 0.0, 1.0);
+    let mut camera_params = ParamSet::new();
+    camera_params.add_float("fov", &[35.0]);
+    let camera: ArcCamera = Arc::new(PerspectiveCamera::from((
+        &camera_params,
+        &camera_transform,
+        film,
+        None,
+        1.0,
+    )));
+
+    // Trace rays through each pixel and average the radiance with a box filter.
+    let pixel_bounds = Bounds2i::new(Point2i::new(0, 0), res);
+    let mut sampler: ArcSampler = Arc::new(RandomSampler::new(samples_per_pixel, None));
+    let integrator = WhittedIntegrator::new(
+        MaxDepths::new(5),
+        Arc::clone(&camera),
+        Arc::clone(&sampler),
+        pixel_bounds,
+    );
+    sampler = Sampler::clone(&*sampler, 0);
+
+    let mut pixels = vec![RGBSpectrum::default(); resolution * resolution];
+    for pixel in pixel_bounds {
+        Arc::get_mut(&mut sampler).unwrap().start_pixel(&pixel);
+
+        let mut l = Spectrum::new(0.0);
+        loop {
+            let camera_sample = Arc::get_mut(&mut sampler)
+                .unwrap()
+                .get_camera_sample(&pixel);
+            let (mut ray, ray_weight) = camera.generate_ray_differential(&camera_sample);
+            ray.scale_differentials(1.0 / (samples_per_pixel as Float).sqrt());
+
+            if ray_weight > 0.0 {
+                let li = integrator.li(&mut ray, Arc::clone(&scene), &mut sampler, 0);
+                if !li.has_nans() && !li.y().is_infinite() {
+                    l += li * ray_weight;
+                }
+            }
+
+            if !Arc::get_mut(&mut sampler).unwrap().start_next_sample() {
+                break;
+            }
+        }
+
+        let offset = pixel.y as usize * resolution + pixel.x as usize;
+        pixels[offset] = (l / samples_per_pixel as Float).to_rgb_spectrum();
+    }
+
+    Ok(RGBImage {
+        pixels,
+        resolution: Point2::new(resolution, resolution),
+    })
+}
@@ -0,0 +1,38 @@
+//! Primitive Identifiers
+
+use core::material::ArcMaterial;
+use core::primitive::PrimitiveIds;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Assigns the identifiers written to the film's ID channels. Shapes are
+/// numbered in the order of their `Shape` directives and materials in the
+/// order they are first used by a shape. Shapes that share a cached
+/// tessellation keep the identifiers of the first one.
+#[derive(Default)]
+pub struct PrimitiveIdAllocator {
+    /// Number of `Shape` directives so far.
+    n_shapes: u32,
+
+    /// Material identifiers keyed by the material's address. Primitives keep
+    /// their materials alive so an address can't be reused by another
+    /// material while the scene exists.
+    materials: HashMap<usize, u32>,
+}
+
+impl PrimitiveIdAllocator {
+    /// Returns the identifiers for the primitives created by a new `Shape`
+    /// directive.
+    ///
+    /// * `material` - The material of the shape.
+    pub fn next(&mut self, material: &ArcMaterial) -> PrimitiveIds {
+        self.n_shapes += 1;
+        let key = Arc::as_ptr(material) as *const () as usize;
+        let n_materials = self.materials.len() as u32;
+        let material = *self.materials.entry(key).or_insert(n_materials + 1);
+        PrimitiveIds {
+            shape: self.n_shapes,
+            material,
+        }
+    }
+}
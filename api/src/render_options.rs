@@ -1,20 +1,34 @@
 //! Render options
 
+use super::camera_path::*;
 use super::graphics_state::GraphicsState;
 use super::transform_set::*;
 use accelerators::*;
+use core::app::OPTIONS;
 use core::camera::*;
+use core::film::*;
+use core::geometry::*;
 use core::integrator::*;
 use core::light::*;
 use core::medium::*;
 use core::paramset::*;
 use core::pbrt::*;
+use core::plugin::*;
 use core::primitive::*;
 use core::scene::*;
+use core::spectrum::*;
+use core::stats::*;
+use core::texture::*;
 use integrators::*;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+/// Number of probe rays along each film axis when auto tuning the accelerator.
+const PROBE_RAYS_PER_AXIS: usize = 64;
+
+/// Integrators that record light paths for light path expression AOVs.
+const LIGHT_PATH_INTEGRATORS: [&str; 2] = ["volpath", "whitted"];
+
 /// Stores rendering options.
 #[derive(Clone)]
 pub struct RenderOptions {
@@ -69,17 +83,43 @@ pub struct RenderOptions {
     /// Lights.
     pub lights: Vec<ArcLight>,
 
+    /// Names of the lights used for light linking.
+    pub light_names: Vec<Option<String>>,
+
     /// Primitives.
     pub primitives: Vec<ArcPrimitive>,
 
+    /// Clip shapes whose interiors are removed from the geometry seen by
+    /// camera rays.
+    pub clip_primitives: Vec<ArcPrimitive>,
+
     /// Object instances (each is a collection of primitives).
-    pub instances: HashMap<String, Arc<Vec<ArcPrimitive>>>,
+    pub instances: HashMap<String, Vec<ArcPrimitive>>,
 
-    /// Current instance (a collection of primitives).
-    pub current_instance: Option<Arc<Vec<ArcPrimitive>>>,
+    /// Name of the instance being defined.
+    pub current_instance: Option<String>,
 
     /// Is there scattering media in the scene.
     pub have_scattering_media: bool,
+
+    /// Scene scale declared with `Option "float scenescale"`.
+    pub scene_scale: Option<Float>,
+
+    /// Scene scale derived from the world bounds when the scene is rendered.
+    pub derived_scene_scale: Option<Float>,
+
+    /// How overlapping media are combined declared with
+    /// `Option "string mediumoverlap"`.
+    pub medium_overlap: MediumOverlap,
+
+    /// Camera path frame that replaces the scene's camera.
+    pub camera_frame: Option<CameraFrame>,
+
+    /// Floating point textures to prepare once the scene is created.
+    pub float_textures: Vec<ArcTexture<Float>>,
+
+    /// Spectrum textures to prepare once the scene is created.
+    pub spectrum_textures: Vec<ArcTexture<Spectrum>>,
 }
 
 impl RenderOptions {
@@ -103,41 +143,78 @@ impl RenderOptions {
             camera_to_world: TransformSet::default(),
             named_media: HashMap::new(),
             lights: vec![],
+            light_names: vec![],
             primitives: vec![],
+            clip_primitives: vec![],
             instances: HashMap::new(),
             current_instance: None,
             have_scattering_media: false,
+            scene_scale: None,
+            derived_scene_scale: None,
+            medium_overlap: MediumOverlap::default(),
+            camera_frame: None,
+            float_textures: vec![],
+            spectrum_textures: vec![],
         }
     }
 
     /// Returns an `Integrator` based on the render options.
     ///
-    /// * `gs` - The `GraphicsState`.
-    pub fn make_integrator(&self, gs: &GraphicsState) -> Result<ArcIntegrator, String> {
-        let camera = self.make_camera(gs);
-        let sampler = GraphicsState::make_sampler(
-            &self.sampler_name,
-            &self.sampler_params,
-            camera.get_film_sample_bounds(),
-        )?;
+    /// * `camera` - The camera.
+    pub fn make_integrator(&self, camera: ArcCamera) -> Result<ArcIntegrator, String> {
+        let sampler = if self.integrator_name == "preview" {
+            // The preview integrator traces one camera ray per pixel.
+            let mut sampler_params = ParamSet::new();
+            sampler_params.add_int("pixelsamples", &[1]);
+            GraphicsState::make_sampler("random", &sampler_params, camera.get_film_sample_bounds())?
+        } else {
+            GraphicsState::make_sampler(
+                &self.sampler_name,
+                &self.sampler_params,
+                camera.get_film_sample_bounds(),
+            )?
+        };
 
         let integrator: Result<ArcIntegrator, String> = match self.integrator_name.as_str() {
+            "bdpt" => {
+                let p = (&self.integrator_params, sampler, camera);
+                Ok(Arc::new(BDPTIntegrator::from(p)))
+            }
+            "volpath" => {
+                let p = (&self.integrator_params, sampler, camera);
+                Ok(Arc::new(VolPathIntegrator::from(p)))
+            }
             "whitted" => {
                 let p = (&self.integrator_params, sampler, camera);
                 Ok(Arc::new(WhittedIntegrator::from(p)))
             }
-            _ => Err(format!("Integrator '{}' unknown.", self.integrator_name)),
+            "preview" => {
+                let p = (&self.integrator_params, sampler, camera);
+                Ok(Arc::new(PreviewIntegrator::from(p)))
+            }
+            #[cfg(feature = "polarization")]
+            "polarizedwhitted" => {
+                let p = (&self.integrator_params, sampler, camera);
+                Ok(Arc::new(PolarizedWhittedIntegrator::from(p)))
+            }
+            #[cfg(not(feature = "polarization"))]
+            "polarizedwhitted" => Err(String::from(
+                "Integrator 'polarizedwhitted' requires polarization support. Rebuild with the 'polarization' feature to enable it.",
+            )),
+            name => match PLUGINS.read().unwrap().integrator(name) {
+                Some(factory) => factory(&self.integrator_params, sampler, camera),
+                None => Err(format!("Integrator '{}' unknown.", name)),
+            },
         };
 
         if integrator.is_ok() {
             if self.have_scattering_media
                 && self.integrator_name != "volpath"
-                && self.integrator_name != "bdpt"
                 && self.integrator_name != "mlt"
             {
                 warn!(
                     "Scene has scattering media but '{}' integrator doesn't support 
-                volume scattering. Consider using 'volpath', 'bdpt', or 'mlt'.",
+                volume scattering. Consider using 'volpath' or 'mlt'.",
                     self.integrator_name
                 );
             }
@@ -151,23 +228,87 @@ impl RenderOptions {
         integrator
     }
 
+    /// Derives the scene scale from the world bounds of the primitives if the
+    /// scene did not declare one with `Option "float scenescale"` and is far
+    /// outside the nominal size. This happens before the camera and scene
+    /// are created but after shapes, which derive a scale from their own
+    /// bounds instead.
+    pub fn derive_scene_scale(&mut self) {
+        self.derived_scene_scale = self.scene_scale_from_bounds();
+        if let Some(scale) = self.derived_scene_scale {
+            info!(
+                "Scene is outside the nominal size. Using scene scale {}.",
+                scale
+            );
+        }
+    }
+
+    /// Returns the size of a nominal unit in scene units used to scale
+    /// absolute tolerances. This is the declared scene scale, the derived one
+    /// or 1.
+    pub fn scene_scale(&self) -> Float {
+        self.scene_scale.or(self.derived_scene_scale).unwrap_or(1.0)
+    }
+
+    /// Returns the scene scale derived from the world bounds of the primitives
+    /// or `None` if the scene declared a scale or has a nominal size.
+    pub fn scene_scale_from_bounds(&self) -> Option<Float> {
+        if self.scene_scale.is_some() || self.primitives.is_empty() {
+            return None;
+        }
+
+        let bounds = self
+            .primitives
+            .iter()
+            .fold(Bounds3f::empty(), |b, p| b.union(&p.world_bound()));
+        let (_, radius) = bounds.bounding_sphere();
+        scene_scale_from_radius(radius)
+    }
+
     /// Returns a `Scene` based on the render options.
-    pub fn make_scene(&mut self) -> Arc<Scene> {
-        let scene = match GraphicsState::make_accelerator(
-            &self.accelerator_name,
-            &self.primitives,
-            &self.accelerator_params,
-        ) {
-            Ok(accelerator) => Arc::new(Scene::new(accelerator, self.lights.clone())),
+    ///
+    /// * `camera` - The camera used to trace probe rays when auto tuning the
+    ///              accelerator.
+    pub fn make_scene(&mut self, camera: &ArcCamera) -> Arc<Scene> {
+        // Move the lights into the scene so they can be preprocessed.
+        let lights = std::mem::take(&mut self.lights);
+        let light_names = std::mem::take(&mut self.light_names);
+        let accelerator = if OPTIONS.auto_tune && self.accelerator_name == "bvh" {
+            let probe_rays = make_probe_rays(camera);
+            let bvh: ArcPrimitive = Arc::new(BVHAccel::auto_tune(&self.primitives, &probe_rays));
+            STATS.add_memory("accelerators", bvh.memory());
+            Ok(bvh)
+        } else {
+            if OPTIONS.auto_tune {
+                warn!(
+                    "Auto tuning is only supported for 'bvh' accelerator, not '{}'.",
+                    self.accelerator_name
+                );
+            }
+            GraphicsState::make_accelerator(
+                &self.accelerator_name,
+                &self.primitives,
+                &self.accelerator_params,
+            )
+        };
+        let mut scene = match accelerator {
+            Ok(accelerator) => Scene::new(accelerator, lights, light_names),
             Err(err) => {
                 warn!("Error: {}. Using BVH.", err);
                 let accelerator = Arc::new(BVHAccel::new(&self.primitives, 1, SplitMethod::SAH));
-                Arc::new(Scene::new(accelerator, self.lights.clone()))
+                STATS.add_memory("accelerators", accelerator.memory());
+                Scene::new(accelerator, lights, light_names)
             }
         };
+        if !self.clip_primitives.is_empty() {
+            let clip = BVHAccel::new(&self.clip_primitives, 1, SplitMethod::SAH);
+            STATS.add_memory("accelerators", clip.memory());
+            scene.clip_aggregate = Some(Arc::new(clip));
+        }
+        STATS.add_memory("lights", scene.lights.iter().map(|l| l.memory()).sum());
         self.primitives.clear();
-        self.lights.clear();
-        scene
+        self.clip_primitives.clear();
+        Arc::new(scene)
     }
 
     /// Returns a `Camera` based on the render options.
@@ -178,11 +319,38 @@ impl RenderOptions {
             Ok(f) => f,
             Err(err) => panic!("{}", err),
         };
-        let film = match GraphicsState::make_film(&self.film_name, &self.film_params, filter) {
+        // Each frame of a camera path is written to its own image.
+        let film_params = match self.camera_frame.as_ref() {
+            Some(frame) => {
+                let mut film_params = self.film_params.clone();
+                film_params.add_string("filename", &[frame_image_file(&self.film_params, frame)]);
+                film_params
+            }
+            None => self.film_params.clone(),
+        };
+        let mut film = match GraphicsState::make_film(&self.film_name, &film_params, filter) {
             Ok(f) => f,
             Err(err) => panic!("{}", err),
         };
 
+        // Only some integrators record the paths light path expression AOVs
+        // select from. Others would write black AOV images.
+        if film.has_aovs() && !LIGHT_PATH_INTEGRATORS.contains(&self.integrator_name.as_str()) {
+            warn!(
+                "Integrator '{}' doesn't support light path expression AOVs. Use {}. Ignoring the 'lpe' parameter.",
+                self.integrator_name,
+                LIGHT_PATH_INTEGRATORS.join(" or ")
+            );
+            film.set_aovs(vec![]);
+        }
+
+        // Record how lights were configured in the output image.
+        for (i, light) in self.lights.iter().enumerate() {
+            for (name, value) in light.film_metadata() {
+                film.metadata.insert(format!("light{}.{}", i, name), value);
+            }
+        }
+
         let inside_medium = gs
             .current_inside_medium
             .clone()
@@ -194,17 +362,358 @@ impl RenderOptions {
 
         let medium_interface = MediumInterface::new(inside_medium, outside_medium);
 
-        match gs.make_camera(
-            &self.camera_name,
-            &self.camera_params,
-            &self.camera_to_world,
+        // A camera path frame replaces the scene's camera.
+        let (camera_name, camera_params, camera_to_world) = match self.camera_frame.as_ref() {
+            Some(frame) => {
+                let resolution = film.full_resolution;
+                let aspect = resolution.x as Float / resolution.y as Float;
+                let camera_to_world = TransformSet::from_transform(&frame.pbrt_camera_to_world());
+                (
+                    "perspective",
+                    frame.camera_params(&self.camera_params, aspect),
+                    camera_to_world,
+                )
+            }
+            None => (
+                self.camera_name.as_str(),
+                self.camera_params.clone(),
+                self.camera_to_world.clone(),
+            ),
+        };
+
+        // Frames rendered from the same view continue accumulating samples.
+        if film.temporal {
+            let lens: Vec<Float> = ["fov", "lensradius", "focaldistance", "screenwindow"]
+                .iter()
+                .flat_map(|name| camera_params.find_float(name))
+                .collect();
+            film.set_view(CameraView::new(&camera_to_world[0], &lens));
+        }
+
+        let mut camera = match gs.make_camera(
+            camera_name,
+            &camera_params,
+            &camera_to_world,
             self.transform_start_time,
             self.transform_end_time,
             film,
             &medium_interface,
+            self.scene_scale(),
         ) {
             Ok(camera) => camera,
             Err(err) => panic!("{}", err),
+        };
+
+        // The next frame reprojects this one with the camera's projection.
+        if let (true, Some(world_to_raster)) = (camera.film().temporal, camera.world_to_raster()) {
+            let camera = Arc::get_mut(&mut camera).unwrap();
+            camera.film_mut().set_world_to_raster(&world_to_raster);
+        }
+        camera
+    }
+}
+
+/// Returns camera rays through a regular grid over the film used as a quick
+/// probe pass when auto tuning the accelerator.
+///
+/// * `camera` - The camera.
+fn make_probe_rays(camera: &ArcCamera) -> Vec<Ray> {
+    let bounds = camera.get_film_sample_bounds();
+    let extent = bounds.diagonal();
+
+    let n = PROBE_RAYS_PER_AXIS;
+    let mut rays = Vec::with_capacity(n * n);
+    for y in 0..n {
+        for x in 0..n {
+            let p_film = Point2f::new(
+                bounds.p_min.x as Float + (x as Float + 0.5) / n as Float * extent.x as Float,
+                bounds.p_min.y as Float + (y as Float + 0.5) / n as Float * extent.y as Float,
+            );
+            let sample = CameraSample::new(p_film, Point2f::new(0.5, 0.5), 0.5);
+            let (ray, weight) = camera.generate_ray(&sample);
+            if weight > 0.0 {
+                rays.push(ray);
+            }
         }
     }
+    rays
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::render_tests::*;
+    use core::camera::*;
+    use core::geometry::*;
+    use core::image_io::*;
+    use core::integrator::*;
+    use core::pbrt::*;
+    use std::fs;
+
+    /// Returns a scene with a sphere.
+    ///
+    /// * `option` - Option statement.
+    /// * `radius` - Radius of the sphere.
+    fn sphere_scene(option: &str, radius: Float) -> String {
+        format!(
+            "{}\nWorldBegin\nShape \"sphere\" \"float radius\" {}\n",
+            option, radius
+        )
+    }
+
+    #[test]
+    fn scene_scale_is_derived_from_world_bounds() {
+        let api = parse("scale_nominal", &sphere_scene("", 1.0));
+        assert_eq!(api.render_options.scene_scale_from_bounds(), None);
+
+        let api = parse("scale_millimetre", &sphere_scene("", 1e-3));
+        let scale = api.render_options.scene_scale_from_bounds().unwrap();
+        assert!(
+            (scale - 1e-3 * Float::sqrt(3.0) / 1e-2).abs() < 1e-5,
+            "{}",
+            scale
+        );
+
+        let mut api = parse("scale_kilometre", &sphere_scene("", 1e5));
+        let scale = api.render_options.scene_scale_from_bounds().unwrap();
+        assert!(
+            (scale - 1e5 * Float::sqrt(3.0) / 1e4).abs() < 1e-3,
+            "{}",
+            scale
+        );
+        assert_eq!(api.render_options.scene_scale(), 1.0);
+        api.render_options.derive_scene_scale();
+        assert_eq!(api.render_options.scene_scale(), scale);
+    }
+
+    #[test]
+    fn declared_scene_scale_is_not_derived() {
+        let scene = sphere_scene(r#"Option "float scenescale" 1"#, 1e-3);
+        let api = parse("scale_declared", &scene);
+        assert_eq!(api.render_options.scene_scale, Some(1.0));
+        assert_eq!(api.render_options.scene_scale_from_bounds(), None);
+
+        let scene = sphere_scene(r#"Option "float scenescale" -1"#, 1.0);
+        let api = parse("scale_negative", &scene);
+        assert_eq!(api.render_options.scene_scale, None);
+    }
+
+    #[test]
+    fn whitted_specular_depth_limits() {
+        // A smooth dielectric square reflects and transmits the environment.
+        // Remapping would make the zero roughness slightly glossy.
+        let material = r#"Material "subsurface" "bool remaproughness" "false""#;
+        let full = average(&render(
+            "whitted_specular_depth_full",
+            &square_scene(r#"Integrator "whitted""#, material),
+        ));
+        let reflection_only = average(&render(
+            "whitted_specular_depth_reflection",
+            &square_scene(
+                r#"Integrator "whitted" "integer max_transmission_depth" 1"#,
+                material,
+            ),
+        ));
+        let none = average(&render(
+            "whitted_specular_depth_none",
+            &square_scene(
+                r#"Integrator "whitted" "integer max_specular_depth" 1"#,
+                material,
+            ),
+        ));
+
+        // Fresnel reflection at normal incidence with eta = 1.33.
+        let fr = ((1.33 - 1.0) / (1.33 + 1.0) as Float).powi(2);
+        assert!((reflection_only - fr).abs() < 1e-3, "{}", reflection_only);
+        assert!(full > 10.0 * reflection_only, "{}", full);
+        assert_eq!(none, 0.0);
+    }
+
+    #[test]
+    fn spatial_light_distribution_prefers_nearby_lights() {
+        let mut api = parse(
+            "spatial_light_distribution",
+            r#"
+Camera "orthographic"
+WorldBegin
+LightSource "point" "point from" [-9 0 -1] "rgb I" [1 1 1]
+LightSource "point" "point from" [9 0 -1] "rgb I" [1 1 1]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-10 -1 0  10 -1 0  10 1 0  -10 1 0]
+"#,
+        );
+        let camera = api.render_options.make_camera(&api.graphics_state);
+        let scene = api.render_options.make_scene(&camera);
+        let distribution = SpatialLightDistribution::new(scene);
+
+        let left = distribution.lookup(&Point3f::new(-9.0, 0.0, 0.0));
+        assert!(left.discrete_pdf(0) > 0.9, "{}", left.discrete_pdf(0));
+        let right = distribution.lookup(&Point3f::new(9.0, 0.0, 0.0));
+        assert!(right.discrete_pdf(1) > 0.9, "{}", right.discrete_pdf(1));
+    }
+
+    #[test]
+    fn volpath_subsurface_exit_lighting_matches_uniform_light_selection() {
+        // Two lights of very different power so the spatial distribution is
+        // far from uniform at the exit points of subsurface paths.
+        let scene = |strategy: &str| {
+            format!(
+                r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "random" "integer pixelsamples" 256
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+Integrator "volpath" "string lightsamplestrategy" "{}"
+WorldBegin
+LightSource "point" "point from" [-1 0 -1] "rgb I" [1 1 1]
+LightSource "point" "point from" [1 0 -1] "rgb I" [20 20 20]
+Material "subsurface" "float scale" 10 "bool remaproughness" "false"
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  -1 1 0  1 1 0  1 -1 0]
+WorldEnd
+"#,
+                strategy
+            )
+        };
+        let uniform = average(&render("subsurface_uniform", &scene("uniform")));
+        let spatial = average(&render("subsurface_spatial", &scene("spatial")));
+
+        // The smooth surface has no non-specular lobe so all light comes from
+        // direct lighting at the exit points.
+        assert!(uniform > 0.1, "{}", uniform);
+        assert!(
+            (spatial - uniform).abs() < 0.05 * uniform,
+            "{} {}",
+            uniform,
+            spatial
+        );
+    }
+
+    #[test]
+    fn world_to_raster_projects_camera_rays_to_their_film_position() {
+        for camera in ["perspective", "orthographic"].iter() {
+            let api = parse(
+                &format!("world_to_raster_{}", camera),
+                &format!(
+                    r#"
+Film "image" "integer xresolution" [8] "integer yresolution" [6]
+LookAt 1 2 -5  0 0 0  0 1 0
+Camera "{}"
+WorldBegin
+"#,
+                    camera
+                ),
+            );
+            let camera = api.render_options.make_camera(&api.graphics_state);
+            let world_to_raster = camera.world_to_raster().unwrap();
+            for p_film in [Point2f::new(3.5, 2.5), Point2f::new(0.25, 5.0)].iter() {
+                let sample = CameraSample::new(*p_film, Point2f::new(0.5, 0.5), 0.0);
+                let (ray, _) = camera.generate_ray(&sample);
+                let p = world_to_raster.transform_point(&ray.at(4.0));
+                assert!((p.x - p_film.x).abs() < 1e-3, "{} {}", p, p_film);
+                assert!((p.y - p_film.y).abs() < 1e-3, "{} {}", p, p_film);
+            }
+        }
+    }
+
+    #[test]
+    fn volpath_records_light_path_expression_aovs() {
+        let scene = |integrator: &str| {
+            format!(
+                r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "halton" "integer pixelsamples" 16
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+    "string lpe" ["direct:C<RD>L" "emission:CL"]
+Integrator "{}"
+WorldBegin
+LightSource "infinite" "rgb L" [1 1 1]
+Material "matte" "rgb Kd" [0.5 0.5 0.5]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  1 -1 0  1 1 0  -1 1 0]
+WorldEnd
+"#,
+                integrator
+            )
+        };
+        let aov_image = |name: &str, aov: &str| {
+            let path = std::env::temp_dir().join(format!("render_test_{}_{}.pfm", name, aov));
+            let image = read_image(&path.to_string_lossy()).ok();
+            let _ = fs::remove_file(&path);
+            image
+        };
+
+        // The square hides the environment so all light is reflected once.
+        let image = render("lpe_volpath", &scene("volpath"));
+        let direct = average(&aov_image("lpe_volpath", "direct").unwrap());
+        let emission = average(&aov_image("lpe_volpath", "emission").unwrap());
+        assert!(direct > 0.3, "{}", direct);
+        assert!((direct - average(&image)).abs() < 1e-3, "{}", direct);
+        assert!(emission == 0.0, "{}", emission);
+
+        // Integrators that don't record paths don't write AOVs.
+        render("lpe_preview", &scene("preview"));
+        assert!(aov_image("lpe_preview", "direct").is_none());
+    }
+
+    #[test]
+    fn bdpt_converges_to_volpath() {
+        let scene = |integrator: &str, world: &str| {
+            format!(
+                r#"
+LookAt 0 1 -3  0 1 0  0 1 0
+Camera "perspective" "float fov" 40
+Sampler "random" "integer pixelsamples" 256
+Film "image" "integer xresolution" 8 "integer yresolution" 8
+Integrator "{}" "integer maxdepth" 5
+WorldBegin
+{}
+WorldEnd
+"#,
+                integrator, world
+            )
+        };
+
+        // Furnace test: a grey wall filling the view under a uniform white
+        // environment reflects half of it.
+        let furnace = r#"
+LightSource "infinite" "rgb L" [1 1 1]
+Material "matte" "rgb Kd" [0.5 0.5 0.5]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-4 -3 0  4 -3 0  4 5 0  -4 5 0]"#;
+        for integrator in &["bdpt", "volpath"] {
+            let l = average(&render(
+                &format!("furnace_{}", integrator),
+                &scene(integrator, furnace),
+            ));
+            assert!((l - 0.5).abs() < 0.02, "{} {}", integrator, l);
+        }
+
+        // A floor and a wall lit by an area light above them, so some of the
+        // light reaches the camera after several bounces.
+        let room = r#"
+AttributeBegin
+AreaLightSource "diffuse" "rgb L" [8 8 8] "bool twosided" "true"
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-0.5 2 -0.5  0.5 2 -0.5  0.5 2 0.5  -0.5 2 0.5]
+AttributeEnd
+Material "matte" "rgb Kd" [0.5 0.5 0.5]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-4 0 -4  4 0 -4  4 0 4  -4 0 4]
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-4 0 1  4 0 1  4 4 1  -4 4 1]"#;
+        let bdpt = average(&render("room_bdpt", &scene("bdpt", room)));
+        let volpath = average(&render("room_volpath", &scene("volpath", room)));
+        assert!(volpath > 0.05, "{}", volpath);
+        assert!(
+            (bdpt - volpath).abs() < 0.05 * volpath,
+            "{} {}",
+            bdpt,
+            volpath
+        );
+    }
 }
@@ -0,0 +1,99 @@
+//! Render Tests
+//!
+//! Helpers for tests that render small scenes through a `RenderSession` and
+//! check the images.
+
+use super::parser::*;
+use super::session::RenderSession;
+use super::Api;
+use core::image_io::*;
+use core::pbrt::*;
+use core::spectrum::*;
+use std::fs;
+
+/// Render a scene and return the image. The scene and the image are written
+/// to the temporary directory.
+///
+/// * `name`  - Unique name of the test scene.
+/// * `scene` - The scene description.
+pub fn render(name: &str, scene: &str) -> RGBImage {
+    render_file(name, "pbrt", scene)
+}
+
+/// Render a scene in the format given by a file extension and return the
+/// image. The scene and the image are written to the temporary directory.
+///
+/// * `name`      - Unique name of the test scene.
+/// * `extension` - File extension of the scene format.
+/// * `scene`     - The scene description.
+pub fn render_file(name: &str, extension: &str, scene: &str) -> RGBImage {
+    let dir = std::env::temp_dir();
+    let scene_path = dir.join(format!("render_test_{}.{}", name, extension));
+    let image_path = dir.join(format!("render_test_{}.pfm", name));
+    let scene_path = scene_path.to_string_lossy().into_owned();
+    let image_path = image_path.to_string_lossy().into_owned();
+    fs::write(&scene_path, scene).unwrap();
+
+    let mut session = RenderSession::new(std::slice::from_ref(&scene_path));
+    session.set_image_file(&image_path).unwrap();
+    session.render(None).unwrap();
+    let image = read_image(&image_path).unwrap();
+
+    fs::remove_file(&scene_path).unwrap();
+    fs::remove_file(&image_path).unwrap();
+    image
+}
+
+/// Parse a scene without rendering it and return the API state. Scenes
+/// should leave out `WorldEnd` to inspect the world block. The scene is
+/// written to the temporary directory.
+///
+/// * `name`  - Unique name of the test scene.
+/// * `scene` - The scene description.
+pub fn parse(name: &str, scene: &str) -> Api {
+    let path = std::env::temp_dir().join(format!("parse_test_{}.pbrt", name));
+    let path = path.to_string_lossy().into_owned();
+    fs::write(&path, scene).unwrap();
+
+    let mut api = Api::new();
+    api.pbrt_init();
+    parse_scene_file(&path, &mut api).unwrap();
+    fs::remove_file(&path).unwrap();
+    api
+}
+
+/// Returns the average of the RGB values of all pixels.
+///
+/// * `image` - The image.
+pub fn average(image: &RGBImage) -> Float {
+    let sum: Float = image
+        .pixels
+        .iter()
+        .map(|p| p.to_rgb().iter().sum::<Float>())
+        .sum();
+    sum / (3 * image.pixels.len()) as Float
+}
+
+/// Returns a scene with a 4x4 pixel orthographic view of a square that fills
+/// the image, lit by a uniform white environment.
+///
+/// * `integrator` - The integrator statement.
+/// * `material`   - The material statement for the square.
+pub fn square_scene(integrator: &str, material: &str) -> String {
+    format!(
+        r#"
+LookAt 0 0 -1  0 0 0  0 1 0
+Camera "orthographic" "float screenwindow" [-0.5 0.5 -0.5 0.5]
+Sampler "random" "integer pixelsamples" 4
+Film "image" "integer xresolution" 4 "integer yresolution" 4
+{}
+WorldBegin
+LightSource "infinite" "rgb L" [1 1 1]
+{}
+Shape "trianglemesh" "integer indices" [0 1 2 0 2 3]
+    "point P" [-1 -1 0  1 -1 0  1 1 0  -1 1 0]
+WorldEnd
+"#,
+        integrator, material
+    )
+}
@@ -0,0 +1,357 @@
+//! Scene Dumps
+//!
+//! With `--dump-scene` the scene description is recorded while parsing and
+//! printed as a JSON document at the end of the world block instead of
+//! rendering it.
+
+use super::parser::JsonValue;
+use super::render_options::RenderOptions;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::spectrum::*;
+use std::collections::BTreeMap;
+
+/// Scene description recorded while parsing.
+#[derive(Clone, Default)]
+pub struct SceneDescription {
+    /// Light sources.
+    lights: Vec<JsonValue>,
+
+    /// Kind (`float` or `spectrum`) and description of the textures by name.
+    textures: BTreeMap<String, (String, JsonValue)>,
+
+    /// Named and anonymous materials in the order they were declared.
+    materials: Vec<JsonValue>,
+
+    /// Number of shape statements and primitives added by each shape type.
+    shapes: BTreeMap<String, [usize; 2]>,
+
+    /// Number of area lights by area light type.
+    area_lights: BTreeMap<String, usize>,
+}
+
+impl SceneDescription {
+    /// Record a light source.
+    ///
+    /// * `light_type`     - Light type.
+    /// * `params`         - Light parameters.
+    /// * `light_to_world` - Light to world transformation.
+    pub fn add_light(&mut self, light_type: &str, params: &ParamSet, light_to_world: &Transform) {
+        self.lights.push(JsonValue::Object(vec![
+            member("type", string_json(light_type)),
+            member("params", params_json(params, None)),
+            member("lightToWorld", transform_json(light_to_world)),
+        ]));
+    }
+
+    /// Record a texture.
+    ///
+    /// * `name`         - Texture name.
+    /// * `kind`         - Kind of texture value (`float` or `spectrum`).
+    /// * `texture_type` - Texture type.
+    /// * `params`       - Texture parameters.
+    pub fn add_texture(&mut self, name: &str, kind: &str, texture_type: &str, params: &ParamSet) {
+        let texture = JsonValue::Object(vec![
+            member("kind", string_json(kind)),
+            member("type", string_json(texture_type)),
+            member("params", params_json(params, Some(self))),
+        ]);
+        self.textures
+            .insert(String::from(name), (String::from(kind), texture));
+    }
+
+    /// Record a material. Texture references in its parameters are resolved
+    /// against the textures declared so far.
+    ///
+    /// * `name`          - Material name or `None` for anonymous materials.
+    /// * `material_type` - Material type.
+    /// * `params`        - Material parameters.
+    pub fn add_material(&mut self, name: Option<&str>, material_type: &str, params: &ParamSet) {
+        let mut members = vec![];
+        if let Some(name) = name {
+            members.push(member("name", string_json(name)));
+        }
+        members.push(member("type", string_json(material_type)));
+        members.push(member("params", params_json(params, Some(self))));
+        self.materials.push(JsonValue::Object(members));
+    }
+
+    /// Record a shape statement.
+    ///
+    /// * `shape_type`   - Shape type.
+    /// * `n_primitives` - Number of primitives added by the statement.
+    /// * `area_light`   - Area light type if the shape is emissive.
+    pub fn add_shape(&mut self, shape_type: &str, n_primitives: usize, area_light: Option<&str>) {
+        let counts = self.shapes.entry(String::from(shape_type)).or_default();
+        counts[0] += 1;
+        counts[1] += n_primitives;
+        if let Some(area_light) = area_light {
+            *self
+                .area_lights
+                .entry(String::from(area_light))
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the scene as a JSON document.
+    ///
+    /// * `render_options` - Render options at the end of the world block.
+    pub fn to_json(&self, render_options: &RenderOptions) -> JsonValue {
+        let ro = render_options;
+        let camera = JsonValue::Object(vec![
+            member("type", string_json(&ro.camera_name)),
+            member("params", params_json(&ro.camera_params, None)),
+            member("cameraToWorld", transform_json(&ro.camera_to_world[0])),
+        ]);
+        let shapes = self
+            .shapes
+            .iter()
+            .map(|(shape_type, counts)| {
+                member(
+                    shape_type,
+                    JsonValue::Object(vec![
+                        member("statements", number_json(counts[0])),
+                        member("primitives", number_json(counts[1])),
+                    ]),
+                )
+            })
+            .collect();
+        let mut instances: Vec<(String, JsonValue)> = ro
+            .instances
+            .iter()
+            .map(|(name, prims)| member(name, number_json(prims.len())))
+            .collect();
+        instances.sort_by(|a, b| a.0.cmp(&b.0));
+
+        JsonValue::Object(vec![
+            member("camera", camera),
+            member("film", directive_json(&ro.film_name, &ro.film_params)),
+            member("filter", directive_json(&ro.filter_name, &ro.filter_params)),
+            member(
+                "sampler",
+                directive_json(&ro.sampler_name, &ro.sampler_params),
+            ),
+            member(
+                "integrator",
+                directive_json(&ro.integrator_name, &ro.integrator_params),
+            ),
+            member(
+                "accelerator",
+                directive_json(&ro.accelerator_name, &ro.accelerator_params),
+            ),
+            member("lights", JsonValue::Array(self.lights.clone())),
+            member(
+                "areaLights",
+                JsonValue::Object(
+                    self.area_lights
+                        .iter()
+                        .map(|(light_type, n)| member(light_type, number_json(*n)))
+                        .collect(),
+                ),
+            ),
+            member(
+                "textures",
+                JsonValue::Object(
+                    self.textures
+                        .iter()
+                        .map(|(name, (_, texture))| member(name, texture.clone()))
+                        .collect(),
+                ),
+            ),
+            member("materials", JsonValue::Array(self.materials.clone())),
+            member("shapes", JsonValue::Object(shapes)),
+            member("primitives", number_json(ro.primitives.len())),
+            member("instances", JsonValue::Object(instances)),
+        ])
+    }
+}
+
+/// Returns an object member.
+///
+/// * `name`  - Member name.
+/// * `value` - Member value.
+fn member(name: &str, value: JsonValue) -> (String, JsonValue) {
+    (String::from(name), value)
+}
+
+/// Returns a JSON string.
+///
+/// * `s` - The string.
+fn string_json(s: &str) -> JsonValue {
+    JsonValue::String(String::from(s))
+}
+
+/// Returns a JSON number for a count.
+///
+/// * `n` - The count.
+fn number_json(n: usize) -> JsonValue {
+    JsonValue::Number(n as f64)
+}
+
+/// Returns a JSON array of numbers.
+///
+/// * `values` - The numbers.
+fn numbers_json(values: &[Float]) -> JsonValue {
+    JsonValue::Array(
+        values
+            .iter()
+            .map(|v| JsonValue::Number(*v as f64))
+            .collect(),
+    )
+}
+
+/// Returns a JSON object with the type and parameters of a scene-wide
+/// directive such as `Film` or `Sampler`.
+///
+/// * `directive_type` - Type given in the directive.
+/// * `params`         - Parameters.
+fn directive_json(directive_type: &str, params: &ParamSet) -> JsonValue {
+    JsonValue::Object(vec![
+        member("type", string_json(directive_type)),
+        member("params", params_json(params, None)),
+    ])
+}
+
+/// Returns the rows of a transformation matrix as a JSON array.
+///
+/// * `t` - The transformation.
+fn transform_json(t: &Transform) -> JsonValue {
+    JsonValue::Array(t.m.m.iter().map(|row| numbers_json(row)).collect())
+}
+
+/// Returns a JSON object with a member for each parameter named by its type
+/// and name as in scene files. Spectra are given as linear RGB. Parameters
+/// with a single value are given as that value instead of an array.
+///
+/// * `params`      - The parameters.
+/// * `description` - Resolves texture references against the textures
+///                   recorded so far if given.
+fn params_json(params: &ParamSet, description: Option<&SceneDescription>) -> JsonValue {
+    let mut members = vec![];
+    let mut add = |param_type: &str, name: &str, values: Vec<JsonValue>| {
+        let value = if values.len() == 1 {
+            values.into_iter().next().unwrap()
+        } else {
+            JsonValue::Array(values)
+        };
+        members.push(member(&format!("{} {}", param_type, name), value));
+    };
+
+    for (name, p) in params.bools.iter() {
+        add(
+            "bool",
+            name,
+            p.values.iter().map(|v| JsonValue::Bool(*v)).collect(),
+        );
+    }
+    for (name, p) in params.ints.iter() {
+        let values = p.values.iter().map(|v| JsonValue::Number(*v as f64));
+        add("integer", name, values.collect());
+    }
+    for (name, p) in params.floats.iter() {
+        let values = p.values.iter().map(|v| JsonValue::Number(*v as f64));
+        add("float", name, values.collect());
+    }
+    for (name, p) in params.point2fs.iter() {
+        add(
+            "point2",
+            name,
+            p.values.iter().map(|v| numbers_json(&[v.x, v.y])).collect(),
+        );
+    }
+    for (name, p) in params.vector2fs.iter() {
+        add(
+            "vector2",
+            name,
+            p.values.iter().map(|v| numbers_json(&[v.x, v.y])).collect(),
+        );
+    }
+    for (name, p) in params.point3fs.iter() {
+        let values = p.values.iter().map(|v| numbers_json(&[v.x, v.y, v.z]));
+        add("point3", name, values.collect());
+    }
+    for (name, p) in params.vector3fs.iter() {
+        let values = p.values.iter().map(|v| numbers_json(&[v.x, v.y, v.z]));
+        add("vector3", name, values.collect());
+    }
+    for (name, p) in params.normal3fs.iter() {
+        let values = p.values.iter().map(|v| numbers_json(&[v.x, v.y, v.z]));
+        add("normal", name, values.collect());
+    }
+    for (name, p) in params.spectra.iter() {
+        add(
+            "rgb",
+            name,
+            p.values.iter().map(|v| numbers_json(&v.to_rgb())).collect(),
+        );
+    }
+    for (name, p) in params.strings.iter() {
+        add(
+            "string",
+            name,
+            p.values.iter().map(|v| string_json(v)).collect(),
+        );
+    }
+    for (name, p) in params.textures.iter() {
+        let values = p.values.iter().map(|tex_name| match description {
+            Some(d) => {
+                let mut texture = vec![member("name", string_json(tex_name))];
+                match d.textures.get(tex_name) {
+                    Some((kind, _)) => texture.push(member("kind", string_json(kind))),
+                    None => texture.push(member("unresolved", JsonValue::Bool(true))),
+                }
+                JsonValue::Object(texture)
+            }
+            None => string_json(tex_name),
+        });
+        add("texture", name, values.collect());
+    }
+
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+    JsonValue::Object(members)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn materials_resolve_texture_references() {
+        let mut description = SceneDescription::default();
+        let mut tex_params = ParamSet::new();
+        tex_params.add_float("uscale", &[8.0]);
+        description.add_texture("checks", "spectrum", "checkerboard", &tex_params);
+
+        let mut params = ParamSet::new();
+        params.add_texture("Kd", &[String::from("checks")]);
+        params.add_texture("bumpmap", &[String::from("missing")]);
+        params.add_float("sigma", &[10.0]);
+        description.add_material(Some("wall"), "matte", &params);
+
+        let material = &description.materials[0];
+        let params = material.get("params").unwrap();
+        assert_eq!(material.get("name").and_then(|v| v.as_str()), Some("wall"));
+        assert_eq!(
+            params
+                .get("texture Kd")
+                .and_then(|v| v.get("kind"))
+                .and_then(|v| v.as_str()),
+            Some("spectrum")
+        );
+        assert_eq!(
+            params
+                .get("texture bumpmap")
+                .and_then(|v| v.get("unresolved")),
+            Some(&JsonValue::Bool(true))
+        );
+        assert_eq!(
+            params.get("float sigma").and_then(|v| v.as_f64()),
+            Some(10.0)
+        );
+    }
+}
@@ -0,0 +1,745 @@
+//! Render Service
+//!
+//! A small HTTP/JSON API for submitting render jobs to a long running process.
+//!
+//! * `POST /jobs`               - Submit scene text in the request body or a
+//!                                scene file with `?path=FILE`.
+//! * `GET /jobs`                - List all jobs.
+//! * `GET /jobs/{id}`           - Query the state and progress of a job.
+//! * `GET /jobs/{id}/image`     - Fetch the current image of a job.
+//! * `DELETE /jobs/{id}`        - Cancel a queued or rendering job.
+
+use super::parser::*;
+use super::Api;
+use core::app::ServeOptions;
+use core::image_io::encode_pfm;
+use core::integrator::RENDER_PROGRESS;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// State of a render job.
+#[derive(Clone, Debug, PartialEq)]
+enum JobState {
+    /// Waiting for earlier jobs to finish.
+    Queued,
+
+    /// Being rendered.
+    Rendering,
+
+    /// Rendered and the image written.
+    Done,
+
+    /// Failed with an error message.
+    Failed(String),
+
+    /// Cancelled before it finished.
+    Cancelled,
+}
+
+impl JobState {
+    /// Returns the name of the state used in responses.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Rendering => "rendering",
+            Self::Done => "done",
+            Self::Failed(_) => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// A render job.
+struct Job {
+    /// Path to the scene file.
+    scene_file: String,
+
+    /// Current state.
+    state: JobState,
+
+    /// Path to the image written by the job.
+    image_file: Option<String>,
+}
+
+/// An HTTP response.
+struct Response {
+    /// Status code and reason phrase.
+    status: &'static str,
+
+    /// Content type of the body.
+    content_type: &'static str,
+
+    /// Response body.
+    body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns a JSON response.
+    ///
+    /// * `status` - Status code and reason phrase.
+    /// * `json`   - JSON body.
+    fn json(status: &'static str, json: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: json.into_bytes(),
+        }
+    }
+
+    /// Returns a JSON response with an error message.
+    ///
+    /// * `status`  - Status code and reason phrase.
+    /// * `message` - The error message.
+    fn error(status: &'static str, message: &str) -> Self {
+        Self::json(
+            status,
+            format!("{{\"error\":\"{}\"}}", escape_json(message)),
+        )
+    }
+}
+
+/// An HTTP request.
+struct Request {
+    /// Request method.
+    method: String,
+
+    /// Path split into non-empty segments.
+    segments: Vec<String>,
+
+    /// Query string without the leading `?`.
+    query: String,
+
+    /// Request body.
+    body: Vec<u8>,
+}
+
+/// Accepts render jobs over HTTP and renders them one at a time in a worker
+/// thread. Each job gets a fresh API state the same way batch rendering does.
+pub struct RenderServer {
+    /// All submitted jobs indexed by job id.
+    jobs: Arc<Mutex<Vec<Job>>>,
+
+    /// Queue of job ids for the worker thread.
+    queue: Sender<usize>,
+
+    /// Directory where submitted scene text is stored.
+    scene_dir: PathBuf,
+}
+
+impl RenderServer {
+    /// Create a new `RenderServer` and start its worker thread.
+    pub fn new() -> Result<Self, String> {
+        let scene_dir = std::env::temp_dir().join("pbr-rust-serve");
+        fs::create_dir_all(&scene_dir).map_err(|err| {
+            format!(
+                "Unable to create scene directory '{}'. {}",
+                scene_dir.display(),
+                err
+            )
+        })?;
+
+        let jobs = Arc::new(Mutex::new(vec![]));
+        let (queue, receiver) = channel();
+        let worker_jobs = Arc::clone(&jobs);
+        thread::spawn(move || run_jobs(worker_jobs, receiver));
+
+        // Write images after every pass so partial results can be fetched.
+        RENDER_PROGRESS.set_progressive(true);
+
+        Ok(Self {
+            jobs,
+            queue,
+            scene_dir,
+        })
+    }
+
+    /// Listens for requests until the process is terminated.
+    ///
+    /// * `options` - The service options.
+    pub fn serve(&self, options: &ServeOptions) -> Result<(), String> {
+        let listener = TcpListener::bind(&options.address)
+            .map_err(|err| format!("Unable to listen on '{}'. {}", options.address, err))?;
+        info!("Listening for render jobs on '{}'.", options.address);
+
+        // Each connection is handled in its own thread so a slow client
+        // doesn't hold up the others.
+        thread::scope(|scope| {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        scope.spawn(move || {
+                            if let Err(err) = self.handle_connection(&mut stream, options) {
+                                warn!("Error handling request. {}", err);
+                            }
+                        });
+                    }
+                    Err(err) => warn!("Error accepting connection. {}", err),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Reads a request from a connection and writes the response.
+    ///
+    /// * `stream`  - The connection.
+    /// * `options` - The service options.
+    fn handle_connection(
+        &self,
+        stream: &mut TcpStream,
+        options: &ServeOptions,
+    ) -> Result<(), String> {
+        let timeout = Some(Duration::from_secs(options.timeout));
+        stream
+            .set_read_timeout(timeout)
+            .and_then(|_| stream.set_write_timeout(timeout))
+            .map_err(|err| format!("{}", err))?;
+
+        let response = match read_request(stream, options.max_body_size) {
+            Ok(request) => self.handle(&request),
+            Err(response) => response,
+        };
+
+        let header = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            response.content_type,
+            response.body.len()
+        );
+        stream
+            .write_all(header.as_bytes())
+            .and_then(|_| stream.write_all(&response.body))
+            .map_err(|err| format!("{}", err))
+    }
+
+    /// Routes a request.
+    ///
+    /// * `request` - The request.
+    fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.segments.iter().map(String::as_str).collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["jobs"]) => self.list_jobs(),
+            ("POST", ["jobs"]) => self.submit_job(request),
+            ("GET", ["jobs", id]) => {
+                self.with_job(id, |id, job| Response::json("200 OK", job_json(id, job)))
+            }
+            ("GET", ["jobs", id, "image"]) => self.with_job(id, |_, job| job_image(job)),
+            ("DELETE", ["jobs", id]) => self.cancel_job(id),
+            (_, ["jobs"]) | (_, ["jobs", _]) | (_, ["jobs", _, "image"]) => {
+                Response::error("405 Method Not Allowed", "Method not allowed.")
+            }
+            _ => Response::error("404 Not Found", "Not found."),
+        }
+    }
+
+    /// Returns the status of all jobs.
+    fn list_jobs(&self) -> Response {
+        let jobs = self.jobs.lock().unwrap();
+        let list: Vec<String> = jobs
+            .iter()
+            .enumerate()
+            .map(|(id, job)| job_json(id, job))
+            .collect();
+        Response::json("200 OK", format!("[{}]", list.join(",")))
+    }
+
+    /// Queues a new job for a scene file given by the `path` query parameter
+    /// or for scene text in the request body. Relative includes in scene text
+    /// are resolved against the directory the text is stored in so scenes
+    /// with includes should be submitted by path.
+    ///
+    /// * `request` - The request.
+    fn submit_job(&self, request: &Request) -> Response {
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = jobs.len();
+
+        let scene_file = match query_param(&request.query, "path") {
+            Some(path) => path,
+            None if request.body.is_empty() => {
+                return Response::error("400 Bad Request", "Missing scene text or 'path'.");
+            }
+            None => {
+                let path = self.scene_dir.join(format!("job-{}.pbrt", id));
+                if let Err(err) = fs::write(&path, &request.body) {
+                    return Response::error(
+                        "500 Internal Server Error",
+                        &format!("Unable to store scene. {}", err),
+                    );
+                }
+                path.to_string_lossy().to_string()
+            }
+        };
+
+        info!("Queued job {} for scene '{}'.", id, scene_file);
+        jobs.push(Job {
+            scene_file,
+            state: JobState::Queued,
+            image_file: None,
+        });
+        if self.queue.send(id).is_err() {
+            jobs[id].state = JobState::Failed(String::from("Render worker stopped."));
+        }
+
+        Response::json("201 Created", job_json(id, &jobs[id]))
+    }
+
+    /// Cancels a job. Queued jobs are skipped and a job being rendered stops
+    /// after the tiles in progress.
+    ///
+    /// * `id` - The job id.
+    fn cancel_job(&self, id: &str) -> Response {
+        self.with_job_mut(id, |id, job| {
+            match job.state {
+                JobState::Queued => job.state = JobState::Cancelled,
+                JobState::Rendering => RENDER_PROGRESS.cancel(),
+                _ => (),
+            }
+            Response::json("200 OK", job_json(id, job))
+        })
+    }
+
+    /// Calls a function with a job or returns an error response if the id is
+    /// not valid.
+    ///
+    /// * `id` - The job id.
+    /// * `f`  - The function.
+    fn with_job<F>(&self, id: &str, f: F) -> Response
+    where
+        F: FnOnce(usize, &Job) -> Response,
+    {
+        self.with_job_mut(id, |id, job| f(id, job))
+    }
+
+    /// Calls a function with a mutable job or returns an error response if the
+    /// id is not valid.
+    ///
+    /// * `id` - The job id.
+    /// * `f`  - The function.
+    fn with_job_mut<F>(&self, id: &str, f: F) -> Response
+    where
+        F: FnOnce(usize, &mut Job) -> Response,
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        match id.parse::<usize>() {
+            Ok(id) if id < jobs.len() => f(id, &mut jobs[id]),
+            _ => Response::error("404 Not Found", &format!("Unknown job '{}'.", id)),
+        }
+    }
+}
+
+/// Renders queued jobs one at a time until the server is dropped.
+///
+/// * `jobs`  - All submitted jobs.
+/// * `queue` - Queue of job ids.
+fn run_jobs(jobs: Arc<Mutex<Vec<Job>>>, queue: Receiver<usize>) {
+    for id in queue {
+        let scene_file = {
+            let mut jobs = jobs.lock().unwrap();
+            if jobs[id].state != JobState::Queued {
+                continue;
+            }
+
+            // Reset while holding the lock so a cancel request cannot be lost.
+            RENDER_PROGRESS.reset();
+            jobs[id].state = JobState::Rendering;
+            jobs[id].scene_file.clone()
+        };
+
+        info!("Rendering job {} '{}'.", id, scene_file);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| render_scene(&scene_file)));
+
+        let mut jobs = jobs.lock().unwrap();
+        jobs[id].image_file = RENDER_PROGRESS.image_file();
+        jobs[id].state = match result {
+            _ if RENDER_PROGRESS.is_cancelled() => JobState::Cancelled,
+            Ok(Ok(())) => JobState::Done,
+            Ok(Err(err)) => JobState::Failed(err),
+            Err(_) => JobState::Failed(String::from("Rendering panicked.")),
+        };
+        info!("Job {} {}.", id, jobs[id].state.name());
+    }
+}
+
+/// Parses and renders a scene file with a fresh API state.
+///
+/// * `path` - Path to the scene file.
+fn render_scene(path: &str) -> Result<(), String> {
+    let mut api = Api::new();
+    api.pbrt_init();
+    let result = parse_scene_file(path, &mut api);
+    api.pbrt_cleanup();
+    result
+}
+
+/// Returns the image of a job. While a job is rendering the current film
+/// values are returned as a PFM image, so tiles show up as they finish.
+/// Otherwise the last image written by the job is returned.
+///
+/// * `job` - The job.
+fn job_image(job: &Job) -> Response {
+    let image_file = match job.state {
+        JobState::Rendering => match RENDER_PROGRESS.snapshot() {
+            Some(snapshot) => {
+                let resolution = snapshot.resolution;
+                return Response {
+                    status: "200 OK",
+                    content_type: "image/x-portable-floatmap",
+                    body: encode_pfm(&snapshot.rgb, resolution.x as u32, resolution.y as u32),
+                };
+            }
+            None => RENDER_PROGRESS.image_file(),
+        },
+        _ => job.image_file.clone(),
+    };
+
+    match image_file {
+        Some(path) => match fs::read(&path) {
+            Ok(body) => Response {
+                status: "200 OK",
+                content_type: image_content_type(&path),
+                body,
+            },
+            Err(err) => Response::error(
+                "500 Internal Server Error",
+                &format!("Unable to read image '{}'. {}", path, err),
+            ),
+        },
+        None => Response::error("404 Not Found", "No image has been written yet."),
+    }
+}
+
+/// Returns the JSON representation of a job.
+///
+/// * `id`  - The job id.
+/// * `job` - The job.
+fn job_json(id: usize, job: &Job) -> String {
+    let mut json = format!(
+        "{{\"id\":{},\"scene\":\"{}\",\"state\":\"{}\"",
+        id,
+        escape_json(&job.scene_file),
+        job.state.name()
+    );
+    if job.state == JobState::Rendering {
+        json += &format!(
+            ",\"passes\":{},\"progress\":{:.3}",
+            RENDER_PROGRESS.passes_done(),
+            RENDER_PROGRESS.pass_fraction()
+        );
+    }
+    if let JobState::Failed(err) = &job.state {
+        json += &format!(",\"error\":\"{}\"", escape_json(err));
+    }
+    json + "}"
+}
+
+/// Reads an HTTP request from a connection or returns the error response.
+///
+/// * `stream`        - The connection.
+/// * `max_body_size` - Maximum size of the request body in bytes.
+fn read_request(stream: &mut TcpStream, max_body_size: usize) -> Result<Request, Response> {
+    let bad_request = |message: String| Response::error("400 Bad Request", &message);
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|err| bad_request(format!("Unable to read request. {}", err)))?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| bad_request(String::from("Missing request method.")))?
+        .to_string();
+    let target = parts
+        .next()
+        .ok_or_else(|| bad_request(String::from("Missing request path.")))?;
+
+    // Only the body length is needed from the headers.
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader
+            .read_line(&mut header)
+            .map_err(|err| bad_request(format!("Unable to read request header. {}", err)))?;
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().map_err(|_| {
+                    bad_request(format!("Invalid Content-Length '{}'.", value.trim()))
+                })?;
+            }
+        }
+    }
+    if content_length > max_body_size {
+        return Err(Response::error(
+            "413 Payload Too Large",
+            &format!(
+                "Request body of {} bytes exceeds the maximum of {} bytes.",
+                content_length, max_body_size
+            ),
+        ));
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| bad_request(format!("Unable to read request body. {}", err)))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(Request {
+        method,
+        segments: path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect(),
+        query: String::from(query),
+        body,
+    })
+}
+
+/// Returns the decoded value of a query string parameter.
+///
+/// * `query` - The query string.
+/// * `name`  - Parameter name.
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Decodes `%XX` escapes and `+` in a query string value.
+///
+/// * `s` - The value.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Escapes a string for use in a JSON string literal.
+///
+/// * `s` - The string.
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns the content type for an image file based on its extension.
+///
+/// * `path` - Path to the image file.
+fn image_content_type(path: &str) -> &'static str {
+    match path.rsplit('.').next().map(str::to_lowercase).as_deref() {
+        Some("png") => "image/png",
+        Some("exr") => "image/x-exr",
+        Some("pfm") => "image/x-portable-floatmap",
+        _ => "application/octet-stream",
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::geometry::*;
+
+    /// Returns a server without a worker thread and the queue of job ids.
+    fn server() -> (RenderServer, Receiver<usize>) {
+        let (queue, receiver) = channel();
+        let server = RenderServer {
+            jobs: Arc::new(Mutex::new(vec![])),
+            queue,
+            scene_dir: std::env::temp_dir(),
+        };
+        (server, receiver)
+    }
+
+    /// Returns a request without a body.
+    ///
+    /// * `method` - Request method.
+    /// * `target` - Path and query string.
+    fn request(method: &str, target: &str) -> Request {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        Request {
+            method: String::from(method),
+            segments: path
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            query: String::from(query),
+            body: vec![],
+        }
+    }
+
+    #[test]
+    fn routes_job_requests() {
+        let (server, queue) = server();
+
+        let response = server.handle(&request("POST", "/jobs?path=scenes%2Fa+b.pbrt"));
+        assert_eq!(response.status, "201 Created");
+        assert_eq!(queue.try_recv(), Ok(0));
+        assert_eq!(server.jobs.lock().unwrap()[0].scene_file, "scenes/a b.pbrt");
+
+        let response = server.handle(&request("GET", "/jobs/0"));
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(
+            String::from_utf8(response.body).unwrap(),
+            r#"{"id":0,"scene":"scenes/a b.pbrt","state":"queued"}"#
+        );
+
+        let response = server.handle(&request("DELETE", "/jobs/0"));
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(server.jobs.lock().unwrap()[0].state, JobState::Cancelled);
+
+        let response = server.handle(&request("GET", "/jobs/0/image"));
+        assert_eq!(response.status, "404 Not Found");
+
+        let errors = [
+            ("POST", "/jobs", "400 Bad Request"),
+            ("GET", "/jobs/1", "404 Not Found"),
+            ("GET", "/jobs/x", "404 Not Found"),
+            ("PUT", "/jobs", "405 Method Not Allowed"),
+            ("GET", "/other", "404 Not Found"),
+        ];
+        for (method, target, status) in errors.iter() {
+            assert_eq!(server.handle(&request(method, target)).status, *status);
+        }
+    }
+
+    /// Handles a connection on which a client sent the given bytes and
+    /// returns the response the client receives.
+    ///
+    /// * `sent`    - Bytes sent by the client.
+    /// * `options` - The service options.
+    fn exchange(sent: &[u8], options: &ServeOptions) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(sent).unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let (server, _queue) = server();
+        server.handle_connection(&mut stream, options).unwrap();
+        drop(stream);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn rejects_large_bodies_and_idle_clients() {
+        let options = ServeOptions {
+            address: String::new(),
+            max_body_size: 16,
+            timeout: 1,
+        };
+
+        let response = exchange(
+            b"POST /jobs HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n",
+            &options,
+        );
+        assert!(
+            response.starts_with("HTTP/1.1 413 Payload Too Large"),
+            "{}",
+            response
+        );
+
+        let response = exchange(b"GET /jobs HTTP/1.1\r\n\r\n", &options);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        // A client that never sends its request is dropped after the timeout.
+        let response = exchange(b"GET /jobs HTTP/1.1\r\n", &options);
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn rendering_job_serves_film_snapshot() {
+        let (server, _queue) = server();
+        server.jobs.lock().unwrap().push(Job {
+            scene_file: String::from("scene.pbrt"),
+            state: JobState::Rendering,
+            image_file: None,
+        });
+
+        let resolution = Point2i::new(2, 1);
+        let bounds = Bounds2i::new(Point2i::new(1, 0), Point2i::new(2, 1));
+        RENDER_PROGRESS.update_snapshot(&resolution, &bounds, &[0.5, 1.0, 2.0]);
+
+        let response = server.handle(&request("GET", "/jobs/0/image"));
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(response.content_type, "image/x-portable-floatmap");
+
+        let header = b"PF\n2 1\n-1\n";
+        assert_eq!(&response.body[..header.len()], header);
+        let values: Vec<f32> = response.body[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(values, vec![0.0, 0.0, 0.0, 0.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn decodes_query_parameters() {
+        assert_eq!(
+            query_param("a=1&path=x%2By+z", "path"),
+            Some(String::from("x+y z"))
+        );
+        assert_eq!(query_param("a=1", "path"), None);
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(escape_json("a\"b\\c\nd\u{1}"), "a\\\"b\\\\c\\nd\\u0001");
+    }
+}
@@ -0,0 +1,232 @@
+//! Render Sessions
+//!
+//! A `RenderSession` splits rendering a scene into stages that can be run
+//! separately and reused:
+//!
+//! 1. `parse()` reads the scene files.
+//! 2. `build_accelerators()` builds the acceleration structures.
+//! 3. `render()` renders the built scene with a new camera, sampler and
+//!    integrator.
+//!
+//! Each stage runs the earlier ones if needed. The scene can be rendered any
+//! number of times, for example with different samplers or integrators,
+//! without parsing it or building its acceleration structures again.
+
+use super::parser::*;
+use super::render_options::RenderOptions;
+use super::*;
+
+/// Renders a scene in stages.
+pub struct RenderSession {
+    /// Scene file paths.
+    paths: Vec<String>,
+
+    /// The parsed scene.
+    world: Option<CapturedWorld>,
+
+    /// The scene with its acceleration structures.
+    scene: Option<Arc<Scene>>,
+}
+
+impl RenderSession {
+    /// Create a new `RenderSession`.
+    ///
+    /// * `paths` - Scene file paths.
+    pub fn new(paths: &[String]) -> Self {
+        Self {
+            paths: paths.to_vec(),
+            world: None,
+            scene: None,
+        }
+    }
+
+    /// Returns whether the scene files have been parsed.
+    pub fn is_parsed(&self) -> bool {
+        self.world.is_some()
+    }
+
+    /// Returns whether the acceleration structures have been built.
+    pub fn is_built(&self) -> bool {
+        self.scene.is_some()
+    }
+
+    /// Parse the scene files. Only the last world block is kept. Does
+    /// nothing if they have already been parsed.
+    pub fn parse(&mut self) -> Result<(), String> {
+        if self.is_parsed() {
+            return Ok(());
+        }
+
+        let mut api = Api::new();
+        api.capture_world = true;
+        api.pbrt_init();
+        let result = self
+            .paths
+            .iter()
+            .try_for_each(|path| parse_scene_file(path, &mut api));
+        api.pbrt_cleanup();
+        result?;
+
+        match api.captured_world.take() {
+            Some(world) => {
+                self.world = Some(world);
+                Ok(())
+            }
+            None => Err(String::from("Scene has no world block")),
+        }
+    }
+
+    /// Build the acceleration structures, parsing the scene files first if
+    /// needed. Does nothing if they have already been built.
+    pub fn build_accelerators(&mut self) -> Result<(), String> {
+        if self.is_built() {
+            return Ok(());
+        }
+        self.parse()?;
+
+        let world = self.world.as_mut().unwrap();
+        let options = &mut world.render_options;
+        options.derive_scene_scale();
+
+        // Building the scene moves the lights out of the render options but
+        // cameras and integrators created later still need them. They are
+        // only shared after the scene has preprocessed them with its bounds.
+        let camera = options.make_camera(&world.graphics_state);
+        let scene = build_scene(options, &camera, world.transform_memory);
+        options.lights = scene.lights.clone();
+        options.light_names = scene.light_names.clone();
+        self.scene = Some(scene);
+        Ok(())
+    }
+
+    /// Override the integrator of the scene for subsequent renders.
+    ///
+    /// * `name`   - Name of the integrator.
+    /// * `params` - Integrator parameters.
+    pub fn set_integrator(&mut self, name: &str, params: &ParamSet) -> Result<(), String> {
+        let options = self.render_options()?;
+        options.integrator_name = String::from(name);
+        options.integrator_params = params.clone();
+        Ok(())
+    }
+
+    /// Override the output image of subsequent renders.
+    ///
+    /// * `filename` - Filename of the output image.
+    pub fn set_image_file(&mut self, filename: &str) -> Result<(), String> {
+        let options = self.render_options()?;
+        options
+            .film_params
+            .add_string("filename", &[String::from(filename)]);
+        Ok(())
+    }
+
+    /// Render the scene and write the image, parsing the scene files and
+    /// building the acceleration structures first if needed.
+    ///
+    /// * `sampler_override` - Name and parameters of a sampler to use instead
+    ///                        of the scene's sampler.
+    pub fn render(&mut self, sampler_override: Option<(&str, &ParamSet)>) -> Result<(), String> {
+        self.build_accelerators()?;
+
+        let world = self.world.as_ref().unwrap();
+        let scene = Arc::clone(self.scene.as_ref().unwrap());
+
+        let mut options = world.render_options.clone();
+        if let Some((name, params)) = sampler_override {
+            options.sampler_name = String::from(name);
+            options.sampler_params = params.clone();
+        }
+
+        // Each render writes to a new film.
+        let camera = options.make_camera(&world.graphics_state);
+        let mut integrator = options
+            .make_integrator(Arc::clone(&camera))
+            .map_err(|err| format!("Error creating integrator. {}", err))?;
+
+        // The integrator must hold the only reference to the camera so it can
+        // write to the film.
+        drop(camera);
+        Arc::get_mut(&mut integrator).unwrap().render(scene);
+
+        if OPTIONS.stats {
+            print!("{}", STATS.report());
+        }
+        if OPTIONS.profile {
+            print!("{}", PROFILER.report());
+        }
+        Ok(())
+    }
+
+    /// Returns the render options of the parsed scene.
+    fn render_options(&mut self) -> Result<&mut RenderOptions, String> {
+        self.parse()?;
+        Ok(&mut self.world.as_mut().unwrap().render_options)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_tests::{average, square_scene};
+    use core::image_io::*;
+    use std::fs;
+
+    #[test]
+    fn session_renders_built_scene_with_overrides() {
+        let dir = std::env::temp_dir();
+        let scene_path = dir.join("session_test.pbrt");
+        let scene_path = scene_path.to_string_lossy().into_owned();
+        let scene = square_scene(
+            r#"Integrator "whitted""#,
+            r#"Material "matte" "rgb Kd" [0.5 0.5 0.5]"#,
+        );
+        fs::write(&scene_path, scene).unwrap();
+
+        let mut session = RenderSession::new(std::slice::from_ref(&scene_path));
+        assert!(!session.is_parsed());
+        session.parse().unwrap();
+        assert!(session.is_parsed() && !session.is_built());
+        session.build_accelerators().unwrap();
+        assert!(session.is_built());
+
+        // Render with the scene's integrator and sampler.
+        let render = |session: &mut RenderSession, name: &str, sampler| {
+            let image_path = dir.join(format!("session_test_{}.pfm", name));
+            let image_path = image_path.to_string_lossy().into_owned();
+            session.set_image_file(&image_path).unwrap();
+            session.render(sampler).unwrap();
+            let image = read_image(&image_path).unwrap();
+            fs::remove_file(&image_path).unwrap();
+            image
+        };
+        let whitted = average(&render(&mut session, "whitted", None));
+
+        // Render the same built scene again with another integrator and
+        // sampler.
+        let mut sampler_params = ParamSet::new();
+        sampler_params.add_int("pixelsamples", &[16]);
+        session.set_integrator("volpath", &ParamSet::new()).unwrap();
+        let volpath = average(&render(
+            &mut session,
+            "volpath",
+            Some(("halton", &sampler_params)),
+        ));
+        fs::remove_file(&scene_path).unwrap();
+
+        assert!(whitted > 0.3, "{}", whitted);
+        assert!((volpath - 0.5).abs() < 0.05, "{}", volpath);
+        assert!(session.is_built());
+    }
+
+    #[test]
+    fn missing_scene_file_is_an_error() {
+        let mut session = RenderSession::new(&[String::from("missing_session_test.pbrt")]);
+        assert!(session.render(None).is_err());
+        assert!(!session.is_parsed());
+    }
+}
@@ -0,0 +1,345 @@
+//! Tessellation Cache
+
+use core::material::ArcMaterial;
+use core::medium::MediumInterface;
+use core::paramset::{ParamSet, ParamSetMap};
+use core::pbrt::Float;
+use core::primitive::ArcPrimitive;
+use core::spectrum::CoefficientSpectrum;
+use core::texture::FloatTextureMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Shapes that are tessellated into triangle meshes when created. Their
+/// tessellations are cached so repeated shapes share one mesh and one BVH.
+pub const TESSELLATED_SHAPES: [&str; 3] = ["loopsubdiv", "heightfield", "nurbs"];
+
+/// Meshes that are instanced automatically. Scenes exported without
+/// instancing often repeat the same mesh data under different transforms;
+/// identical meshes are detected by comparing their data and share one mesh
+/// and one BVH like tessellated shapes.
+pub const INSTANCED_SHAPES: [&str; 2] = ["trianglemesh", "plymesh"];
+
+/// Identifies a tessellated shape or mesh by everything its object space
+/// aggregate depends on. Parameters are stored as the bytes of their values
+/// sorted by type and name since their order in a `ParamSet` isn't fixed.
+/// Materials, media and float textures are identified by address since a
+/// name can be redefined.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TessellationKey {
+    /// Shape name.
+    name: String,
+
+    /// Whether surface normals are reversed in object space.
+    reverse_orientation: bool,
+
+    /// Address of the material.
+    material: usize,
+
+    /// Addresses of the inside and outside media.
+    media: [usize; 2],
+
+    /// Names and addresses of the float textures.
+    textures: Vec<(String, usize)>,
+
+    /// Type, name and value bytes of each parameter.
+    params: Vec<(u8, String, Vec<u8>)>,
+}
+
+impl TessellationKey {
+    /// Returns the key identifying a tessellated shape or mesh.
+    ///
+    /// * `name`                - Shape name.
+    /// * `params`              - Shape parameters.
+    /// * `reverse_orientation` - Indicates whether to reverse surface normals
+    ///                           in object space.
+    /// * `material`            - Current material in the graphics state.
+    /// * `mi`                  - Medium interface for the shape.
+    /// * `float_textures`      - Float textures in the graphics state.
+    pub fn new(
+        name: &str,
+        params: &ParamSet,
+        reverse_orientation: bool,
+        material: &ArcMaterial,
+        mi: &MediumInterface,
+        float_textures: &FloatTextureMap,
+    ) -> Self {
+        let medium = |m: &Option<_>| {
+            m.as_ref()
+                .map_or(0, |m| Arc::as_ptr(m) as *const () as usize)
+        };
+        let mut textures: Vec<(String, usize)> = params
+            .textures
+            .values()
+            .flat_map(|item| item.values.iter())
+            .map(|t| {
+                let ptr = float_textures
+                    .get(t)
+                    .map_or(0, |t| Arc::as_ptr(t) as *const () as usize);
+                (t.clone(), ptr)
+            })
+            .collect();
+        textures.sort_unstable();
+
+        let floats = |v: &[Float]| v.iter().flat_map(|f| f.to_bits().to_le_bytes()).collect();
+        let mut key_params = vec![];
+        add_params(&mut key_params, 0, &params.bools, |v| vec![*v as u8]);
+        add_params(&mut key_params, 1, &params.ints, |v| {
+            v.to_le_bytes().to_vec()
+        });
+        add_params(&mut key_params, 2, &params.floats, |v| floats(&[*v]));
+        add_params(&mut key_params, 3, &params.point2fs, |v| {
+            floats(&[v.x, v.y])
+        });
+        add_params(&mut key_params, 4, &params.vector2fs, |v| {
+            floats(&[v.x, v.y])
+        });
+        add_params(&mut key_params, 5, &params.point3fs, |v| {
+            floats(&[v.x, v.y, v.z])
+        });
+        add_params(&mut key_params, 6, &params.vector3fs, |v| {
+            floats(&[v.x, v.y, v.z])
+        });
+        add_params(&mut key_params, 7, &params.normal3fs, |v| {
+            floats(&[v.x, v.y, v.z])
+        });
+        add_params(&mut key_params, 8, &params.spectra, |v| floats(v.samples()));
+        add_params(&mut key_params, 9, &params.illuminants, |v| {
+            floats(v.samples())
+        });
+        add_params(&mut key_params, 10, &params.strings, |v| {
+            let mut bytes = v.as_bytes().to_vec();
+            bytes.push(0);
+            bytes
+        });
+        add_params(&mut key_params, 11, &params.textures, |v| {
+            let mut bytes = v.as_bytes().to_vec();
+            bytes.push(0);
+            bytes
+        });
+        key_params.sort_unstable();
+
+        Self {
+            name: String::from(name),
+            reverse_orientation,
+            material: Arc::as_ptr(material) as *const () as usize,
+            media: [medium(&mi.inside), medium(&mi.outside)],
+            textures,
+            params: key_params,
+        }
+    }
+}
+
+/// Appends the type, name and value bytes of parameters to a key.
+///
+/// * `key_params` - Parameters of the key.
+/// * `kind`       - Identifies the parameter type.
+/// * `params`     - The parameters.
+/// * `bytes`      - Returns the bytes of a value.
+fn add_params<T, F>(
+    key_params: &mut Vec<(u8, String, Vec<u8>)>,
+    kind: u8,
+    params: &ParamSetMap<T>,
+    bytes: F,
+) where
+    T: fmt::Display,
+    F: Fn(&T) -> Vec<u8>,
+{
+    for (name, item) in params.iter() {
+        let values = item.values.iter().flat_map(&bytes).collect();
+        key_params.push((kind, name.clone(), values));
+    }
+}
+
+/// A cached aggregate.
+struct CachedAggregate {
+    /// Object space aggregate of the shape.
+    primitive: ArcPrimitive,
+
+    /// Number of shapes in the aggregate.
+    n_shapes: usize,
+
+    /// Memory used by the shapes in bytes.
+    memory: usize,
+}
+
+/// Stores the object space aggregate of each tessellated shape that is
+/// repeated. Shapes are only instanced once their key repeats so unique
+/// shapes are created in world space as usual.
+#[derive(Default)]
+pub struct TessellationCache {
+    /// The keys of shapes seen so far with the aggregate of those that
+    /// repeat.
+    primitives: HashMap<TessellationKey, Option<CachedAggregate>>,
+}
+
+impl TessellationCache {
+    /// Returns whether a shape with a key was recorded before, i.e. the
+    /// shape repeats.
+    ///
+    /// * `key` - The key.
+    pub fn contains(&self, key: &TessellationKey) -> bool {
+        self.primitives.contains_key(key)
+    }
+
+    /// Records the key of a shape that is not instanced.
+    ///
+    /// * `key` - The key.
+    pub fn record(&mut self, key: TessellationKey) {
+        self.primitives.entry(key).or_insert(None);
+    }
+
+    /// Returns the cached aggregate for a key, its number of shapes and the
+    /// memory used by them.
+    ///
+    /// * `key` - The key.
+    pub fn get(&self, key: &TessellationKey) -> Option<(ArcPrimitive, usize, usize)> {
+        self.primitives
+            .get(key)
+            .and_then(Option::as_ref)
+            .map(|c| (Arc::clone(&c.primitive), c.n_shapes, c.memory))
+    }
+
+    /// Caches the aggregate for a key.
+    ///
+    /// * `key`       - The key.
+    /// * `primitive` - Object space aggregate of the tessellated shape.
+    /// * `n_shapes`  - Number of shapes in the aggregate.
+    /// * `memory`    - Memory used by the shapes in bytes.
+    pub fn insert(
+        &mut self,
+        key: TessellationKey,
+        primitive: ArcPrimitive,
+        n_shapes: usize,
+        memory: usize,
+    ) {
+        self.primitives.insert(
+            key,
+            Some(CachedAggregate {
+                primitive,
+                n_shapes,
+                memory,
+            }),
+        );
+    }
+
+    /// Clear the cached tessellations.
+    pub fn clear(&mut self) {
+        self.primitives.clear();
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::render_tests::*;
+    use crate::Api;
+    use core::geometry::*;
+    use core::pbrt::*;
+
+    /// Returns a bilinear NURBS patch statement.
+    ///
+    /// * `z` - Height of the patch.
+    fn nurbs_patch(z: f32) -> String {
+        format!(
+            r#"Shape "nurbs" "integer nu" 2 "integer nv" 2
+    "integer uorder" 2 "integer vorder" 2
+    "float uknots" [0 0 1 1] "float vknots" [0 0 1 1]
+    "point P" [0 0 {z}  1 0 {z}  0 1 {z}  1 1 {z}]
+"#,
+            z = z
+        )
+    }
+
+    /// Returns the number of cached aggregates.
+    ///
+    /// * `api` - The API state.
+    fn cached(api: &Api) -> usize {
+        let primitives = &api.tessellation_cache.primitives;
+        primitives.values().filter(|c| c.is_some()).count()
+    }
+
+    /// A triangle mesh statement.
+    const TRIANGLE: &str = r#"Shape "trianglemesh" "integer indices" [0 1 2]
+    "point P" [-1 -1 0  1 -1 0  0 1 0]
+"#;
+
+    #[test]
+    fn identical_nurbs_share_tessellation() {
+        let single = parse("nurbs_single", &format!("WorldBegin\n{}", nurbs_patch(0.0)));
+        let n_triangles = single.render_options.primitives.len();
+        assert_eq!(cached(&single), 0);
+
+        // The first patch is created in world space and the second one,
+        // which repeats it, shares one tessellation.
+        let scene = format!(
+            "WorldBegin\n{}Translate 2 0 0\n{}Translate 2 0 0\n{}",
+            nurbs_patch(0.0),
+            nurbs_patch(0.0),
+            nurbs_patch(1.0)
+        );
+        let api = parse("nurbs_tessellation", &scene);
+        assert_eq!(api.render_options.primitives.len(), 2 * n_triangles + 1);
+        assert_eq!(cached(&api), 1);
+    }
+
+    #[test]
+    fn mirrored_meshes_share_tessellation_by_orientation() {
+        let scene = |statements: &str| {
+            let api = parse(
+                "mirrored_meshes",
+                &format!("WorldBegin\n{}{}{}", TRIANGLE, statements, TRIANGLE),
+            );
+            cached(&api)
+        };
+        assert_eq!(scene(""), 1);
+        assert_eq!(scene("Scale -1 1 1\n"), 0);
+        assert_eq!(scene("ReverseOrientation\n"), 0);
+        assert_eq!(scene("Scale -1 1 1\nReverseOrientation\n"), 1);
+    }
+
+    #[test]
+    fn instanced_meshes_keep_world_space_normals() {
+        // The second mesh repeats the first one behind it.
+        let placed = r#"Rotate 30 0 0 1
+Rotate 20 1 0 0
+Scale -1 1 1
+"#;
+        let mut api = parse(
+            "instanced_normals",
+            &format!(
+                "Camera \"orthographic\"\nWorldBegin\nAttributeBegin\n{}{}AttributeEnd\n\
+                 Translate 0 0 5\n{}{}",
+                placed, TRIANGLE, placed, TRIANGLE
+            ),
+        );
+        assert_eq!(cached(&api), 1);
+        let camera = api.render_options.make_camera(&api.graphics_state);
+        let scene = api.render_options.make_scene(&camera);
+
+        let normals = |z: Float| {
+            let mut ray = Ray::new(
+                Point3f::new(0.0, 0.0, z),
+                Vector3f::new(0.0, 0.0, 1.0),
+                INFINITY,
+                0.0,
+                None,
+            );
+            let isect = scene.intersect(&mut ray).unwrap();
+            (isect.hit.n, isect.shading.n)
+        };
+        let (world_n, world_ns) = normals(-10.0);
+        let (instance_n, instance_ns) = normals(2.0);
+        assert!(
+            (world_n - instance_n).length() < 1e-4,
+            "{:?} {:?}",
+            world_n,
+            instance_n
+        );
+        assert!((world_ns - instance_ns).length() < 1e-4);
+    }
+}
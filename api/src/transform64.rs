@@ -0,0 +1,317 @@
+//! Double Precision Transformations
+//!
+//! The current transformation matrix is accumulated in double precision while
+//! parsing scene files so that scenes chaining many `Translate`, `Rotate`,
+//! `Scale`, `LookAt` and `ConcatTransform` statements don't drift. It is only
+//! converted to a `Transform` for the objects created from it.
+
+use core::geometry::*;
+use core::pbrt::*;
+use std::ops::Mul;
+
+/// A 4x4 matrix of f64 values in row-major order.
+pub type Matrix64 = [[f64; 4]; 4];
+
+/// Identity matrix.
+const IDENTITY_MATRIX64: Matrix64 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// A transformation with its matrix and inverse in double precision.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform64 {
+    /// The transformation matrix.
+    m: Matrix64,
+
+    /// The inverse transformation matrix.
+    m_inv: Matrix64,
+}
+
+impl Transform64 {
+    /// Create a transformation from a matrix. Returns `None` if the matrix is
+    /// singular.
+    ///
+    /// * `m` - The matrix in row-major order.
+    pub fn from_matrix(m: Matrix64) -> Option<Self> {
+        inverse(&m).map(|m_inv| Self { m, m_inv })
+    }
+
+    /// Create a transformation from a matrix given in column-major order as in
+    /// scene files. Returns `None` if the matrix is singular.
+    ///
+    /// * `tr` - The matrix in column-major order.
+    pub fn from_column_major(tr: &[Float; 16]) -> Option<Self> {
+        let mut m = [[0.0; 4]; 4];
+        for (i, v) in tr.iter().enumerate() {
+            m[i % 4][i / 4] = *v as f64;
+        }
+        Self::from_matrix(m)
+    }
+
+    /// Create a transformation representing a translation.
+    ///
+    /// * `dx` - Translation in x-direction.
+    /// * `dy` - Translation in y-direction.
+    /// * `dz` - Translation in z-direction.
+    #[rustfmt::skip]
+    pub fn translate(dx: f64, dy: f64, dz: f64) -> Self {
+        Self {
+            m: [
+                [1.0, 0.0, 0.0, dx],
+                [0.0, 1.0, 0.0, dy],
+                [0.0, 0.0, 1.0, dz],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+            m_inv: [
+                [1.0, 0.0, 0.0, -dx],
+                [0.0, 1.0, 0.0, -dy],
+                [0.0, 0.0, 1.0, -dz],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Create a transformation representing a scale.
+    ///
+    /// * `x` - Scaling factor in x-axis.
+    /// * `y` - Scaling factor in y-axis.
+    /// * `z` - Scaling factor in z-axis.
+    #[rustfmt::skip]
+    pub fn scale(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            m: [
+                [x,   0.0, 0.0, 0.0],
+                [0.0, y,   0.0, 0.0],
+                [0.0, 0.0, z,   0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+            m_inv: [
+                [1.0 / x, 0.0,     0.0,     0.0],
+                [0.0,     1.0 / y, 0.0,     0.0],
+                [0.0,     0.0,     1.0 / z, 0.0],
+                [0.0,     0.0,     0.0,     1.0],
+            ],
+        }
+    }
+
+    /// Create a transformation representing a rotation about an axis.
+    ///
+    /// * `theta` - Angle in degrees.
+    /// * `axis`  - Axis of rotation.
+    pub fn rotate(theta: f64, axis: [f64; 3]) -> Self {
+        let [x, y, z] = normalize(axis);
+        let (sin_theta, cos_theta) = theta.to_radians().sin_cos();
+
+        let mut m = IDENTITY_MATRIX64;
+        m[0][0] = x * x + (1.0 - x * x) * cos_theta;
+        m[0][1] = x * y * (1.0 - cos_theta) - z * sin_theta;
+        m[0][2] = x * z * (1.0 - cos_theta) + y * sin_theta;
+
+        m[1][0] = x * y * (1.0 - cos_theta) + z * sin_theta;
+        m[1][1] = y * y + (1.0 - y * y) * cos_theta;
+        m[1][2] = y * z * (1.0 - cos_theta) - x * sin_theta;
+
+        m[2][0] = x * z * (1.0 - cos_theta) - y * sin_theta;
+        m[2][1] = y * z * (1.0 - cos_theta) + x * sin_theta;
+        m[2][2] = z * z + (1.0 - z * z) * cos_theta;
+
+        Self {
+            m,
+            m_inv: transpose(&m),
+        }
+    }
+
+    /// Create a transformation to point a camera to a desired location.
+    /// Returns `None` if the up vector and viewing direction are parallel.
+    ///
+    /// * `pos`  - Position of camera.
+    /// * `look` - Position to point towards.
+    /// * `up`   - Up vector.
+    #[rustfmt::skip]
+    pub fn look_at(pos: [f64; 3], look: [f64; 3], up: [f64; 3]) -> Option<Self> {
+        let dir = normalize([look[0] - pos[0], look[1] - pos[1], look[2] - pos[2]]);
+        let right = cross(normalize(up), dir);
+        if right == [0.0; 3] {
+            return None;
+        }
+        let right = normalize(right);
+        let new_up = cross(dir, right);
+
+        let camera_to_world = [
+            [right[0], new_up[0], dir[0], pos[0]],
+            [right[1], new_up[1], dir[1], pos[1]],
+            [right[2], new_up[2], dir[2], pos[2]],
+            [0.0,      0.0,       0.0,    1.0],
+        ];
+        inverse(&camera_to_world).map(|m| Self {
+            m,
+            m_inv: camera_to_world,
+        })
+    }
+
+    /// Returns the inverse transformation.
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.m_inv,
+            m_inv: self.m,
+        }
+    }
+
+    /// Returns the transformation in `Float` precision.
+    pub fn to_transform(self) -> Transform {
+        Transform {
+            m: to_matrix4x4(&self.m),
+            m_inv: to_matrix4x4(&self.m_inv),
+        }
+    }
+}
+
+impl Default for Transform64 {
+    /// Returns the identity transformation.
+    fn default() -> Self {
+        Self {
+            m: IDENTITY_MATRIX64,
+            m_inv: IDENTITY_MATRIX64,
+        }
+    }
+}
+
+impl From<&Transform> for Transform64 {
+    /// Convert a `Transform` to double precision.
+    ///
+    /// * `t` - The transformation.
+    fn from(t: &Transform) -> Self {
+        Self {
+            m: from_matrix4x4(&t.m),
+            m_inv: from_matrix4x4(&t.m_inv),
+        }
+    }
+}
+
+impl Mul for Transform64 {
+    type Output = Self;
+
+    /// Composes this transformation with another one.
+    ///
+    /// * `rhs` - The transformation to compose with.
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            m: mul(&self.m, &rhs.m),
+            m_inv: mul(&rhs.m_inv, &self.m_inv),
+        }
+    }
+}
+
+/// Returns the product of two matrices.
+///
+/// * `a` - The first matrix.
+/// * `b` - The second matrix.
+fn mul(a: &Matrix64, b: &Matrix64) -> Matrix64 {
+    let mut r = [[0.0; 4]; 4];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    r
+}
+
+/// Returns the transpose of a matrix.
+///
+/// * `m` - The matrix.
+fn transpose(m: &Matrix64) -> Matrix64 {
+    let mut r = [[0.0; 4]; 4];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = m[j][i];
+        }
+    }
+    r
+}
+
+/// Returns the inverse of a matrix using Gauss-Jordan elimination with
+/// partial pivoting or `None` if the matrix is singular.
+///
+/// * `m` - The matrix.
+fn inverse(m: &Matrix64) -> Option<Matrix64> {
+    // Augment the matrix with the identity and reduce the left half to the
+    // identity.
+    let mut a = *m;
+    let mut inv = IDENTITY_MATRIX64;
+    for col in 0..4 {
+        let pivot = (col..4)
+            .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+            .unwrap();
+        if a[pivot][col] == 0.0 || !a[pivot][col].is_finite() {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let pivinv = 1.0 / a[col][col];
+        for k in 0..4 {
+            a[col][k] *= pivinv;
+            inv[col][k] *= pivinv;
+        }
+
+        for row in 0..4 {
+            if row != col {
+                let f = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= f * a[col][k];
+                    inv[row][k] -= f * inv[col][k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+/// Convert a matrix to a `Matrix4x4`.
+///
+/// * `m` - The matrix.
+fn to_matrix4x4(m: &Matrix64) -> Matrix4x4 {
+    let mut r = Matrix4x4::default();
+    for (i, row) in r.m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = m[i][j] as Float;
+        }
+    }
+    r
+}
+
+/// Convert a `Matrix4x4` to double precision.
+///
+/// * `m` - The matrix.
+fn from_matrix4x4(m: &Matrix4x4) -> Matrix64 {
+    let mut r = [[0.0; 4]; 4];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = m.m[i][j] as f64;
+        }
+    }
+    r
+}
+
+/// Returns a normalized vector.
+///
+/// * `v` - The vector.
+fn normalize(v: [f64; 3]) -> [f64; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+/// Returns the cross product of two vectors.
+///
+/// * `a` - The first vector.
+/// * `b` - The second vector.
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
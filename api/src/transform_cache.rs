@@ -1,7 +1,8 @@
 //! Transform Cache
 
-use core::geometry::ArcTransform;
+use core::geometry::{ArcTransform, Transform};
 use std::collections::HashSet;
+use std::mem::size_of;
 use std::sync::Arc;
 
 /// Allocates and stores a single `Transform` reference for each unique
@@ -26,6 +27,12 @@ impl TransformCache {
         }
     }
 
+    /// Returns the memory used by the cached transformations in bytes.
+    pub fn memory(&self) -> usize {
+        self.transforms.capacity() * size_of::<ArcTransform>()
+            + self.transforms.len() * size_of::<Transform>()
+    }
+
     /// Clear the cached transformations.
     pub fn clear(&mut self) {
         self.transforms.clear();
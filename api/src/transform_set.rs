@@ -1,8 +1,9 @@
 //! Transform Set
 
 #![allow(dead_code)]
+use super::transform64::Transform64;
 use core::geometry::{ArcTransform, Transform};
-use std::ops::{Index, IndexMut};
+use std::ops::Index;
 use std::sync::Arc;
 
 /// Number of transformations to store.
@@ -18,23 +19,65 @@ pub const END_TRANSFORM_BITS: usize = 1 << 1;
 /// Transformation for both starting and ending time.
 pub const ALL_TRANSFORM_BITS: usize = (1 << MAX_TRANSFORMS) - 1;
 
-/// Stores an array of transformations.
+/// Stores an array of transformations. They are accumulated in double
+/// precision and each one is available in `Float` precision by indexing.
 #[derive(Clone, Debug, Default)]
 pub struct TransformSet {
     /// The transformations.
     t: [ArcTransform; MAX_TRANSFORMS],
+
+    /// The transformations in double precision.
+    t64: [Transform64; MAX_TRANSFORMS],
 }
 
 impl TransformSet {
+    /// Returns a new `TransformSet` with all transformations set to the same
+    /// transformation.
+    ///
+    /// * `t` - The transformation.
+    pub fn from_transform(t: &Transform) -> Self {
+        let mut set = Self::default();
+        set.set(ALL_TRANSFORM_BITS, Transform64::from(t));
+        set
+    }
+
     /// Returns a new `TransformSet` containing the inverse transformations.
     pub fn inverse(&self) -> Self {
         let mut t_inv = Self::default();
         for i in 0..self.t.len() {
             t_inv.t[i] = Arc::new(self.t[i].inverse());
+            t_inv.t64[i] = self.t64[i].inverse();
         }
         t_inv
     }
 
+    /// Replace the selected transformations.
+    ///
+    /// * `bits` - Bits selecting the transformations.
+    /// * `t`    - The new transformation.
+    pub fn set(&mut self, bits: usize, t: Transform64) {
+        for i in 0..self.t.len() {
+            if bits & (1 << i) > 0 {
+                self.t64[i] = t;
+                self.t[i] = Arc::new(t.to_transform());
+            }
+        }
+    }
+
+    /// Compose the selected transformations with a transformation that is
+    /// applied first.
+    ///
+    /// * `bits` - Bits selecting the transformations.
+    /// * `t`    - The transformation to compose with.
+    pub fn concat(&mut self, bits: usize, t: Transform64) {
+        for i in 0..self.t.len() {
+            if bits & (1 << i) > 0 {
+                self.t64[i] = self.t64[i] * t;
+                self.t[i] = Arc::new(self.t64[i].to_transform());
+            }
+        }
+    }
+
     /// Returns `true` if 2 successive transformations are not the same
     /// indicating that this is storing animated transforms.
     pub fn is_animated(&self) -> bool {
@@ -48,9 +91,7 @@ impl TransformSet {
 
     /// Reset transforms to identity.
     pub fn reset(&mut self) {
-        for i in 0..self.t.len() {
-            self.t[i] = Arc::new(Transform::default());
-        }
+        self.set(ALL_TRANSFORM_BITS, Transform64::default());
     }
 }
 
@@ -65,11 +106,91 @@ impl Index<usize> for TransformSet {
     }
 }
 
-impl IndexMut<usize> for TransformSet {
-    /// Return mutable `Transform` at the given index.
-    ///
-    /// * `index` - The index.
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.t[index]
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::geometry::*;
+    use core::pbrt::*;
+
+    /// Returns the largest absolute difference between the elements of a
+    /// transformation matrix and its inverse and the given matrices.
+    fn max_error(t: &Transform, m: &Matrix4x4, m_inv: &Matrix4x4) -> Float {
+        let mut err: Float = 0.0;
+        for i in 0..4 {
+            for j in 0..4 {
+                err = err.max((t.m.m[i][j] - m.m[i][j]).abs());
+                err = err.max((t.m_inv.m[i][j] - m_inv.m[i][j]).abs());
+            }
+        }
+        err
+    }
+
+    #[test]
+    fn full_turn_in_small_rotations_is_identity() {
+        let mut set = TransformSet::default();
+        let axis = [1.0, 2.0, 3.0];
+        for _ in 0..3600 {
+            set.concat(
+                ALL_TRANSFORM_BITS,
+                Transform64::rotate(0.1_f32 as f64, axis),
+            );
+        }
+        set.concat(
+            ALL_TRANSFORM_BITS,
+            Transform64::rotate(-(0.1_f32 as f64) * 3600.0, axis),
+        );
+        for i in 0..MAX_TRANSFORMS {
+            let err = max_error(&set[i], &IDENTITY_MATRIX, &IDENTITY_MATRIX);
+            assert!(err < 1e-6, "error {} in transform {}", err, i);
+        }
+    }
+
+    #[test]
+    fn nested_transforms_do_not_drift() {
+        // Mimic deeply nested blocks each adding a translation, rotation and
+        // scale, then undo them in reverse order.
+        let mut set = TransformSet::default();
+        for i in 0..100 {
+            let d = 0.1 * i as f64;
+            set.concat(ALL_TRANSFORM_BITS, Transform64::translate(d, -d, 0.5));
+            set.concat(
+                ALL_TRANSFORM_BITS,
+                Transform64::rotate(7.3, [0.0, 1.0, 1.0]),
+            );
+            set.concat(ALL_TRANSFORM_BITS, Transform64::scale(1.1, 0.9, 1.0));
+        }
+        for i in (0..100).rev() {
+            let d = 0.1 * i as f64;
+            set.concat(
+                ALL_TRANSFORM_BITS,
+                Transform64::scale(1.0 / 1.1, 1.0 / 0.9, 1.0),
+            );
+            set.concat(
+                ALL_TRANSFORM_BITS,
+                Transform64::rotate(-7.3, [0.0, 1.0, 1.0]),
+            );
+            set.concat(ALL_TRANSFORM_BITS, Transform64::translate(-d, d, -0.5));
+        }
+        let err = max_error(&set[0], &IDENTITY_MATRIX, &IDENTITY_MATRIX);
+        assert!(err < 1e-5, "error {}", err);
+    }
+
+    #[test]
+    fn only_active_transforms_change() {
+        let mut set = TransformSet::default();
+        set.concat(END_TRANSFORM_BITS, Transform64::translate(1.0, 2.0, 3.0));
+        assert_eq!(set[0].m, IDENTITY_MATRIX);
+        assert_eq!(
+            set[1].m,
+            Transform::translate(&Vector3f::new(1.0, 2.0, 3.0)).m
+        );
+        assert!(set.is_animated());
+
+        let inv = set.inverse();
+        assert_eq!(inv[1].m, set[1].m_inv);
     }
 }
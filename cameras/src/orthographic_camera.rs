@@ -74,6 +74,16 @@ impl OrthographicCamera {
 }
 
 impl Camera for OrthographicCamera {
+    /// Returns the film that captures the rendered image.
+    fn film(&self) -> &Film {
+        &self.data.film
+    }
+
+    /// Returns the film that captures the rendered image for updating.
+    fn film_mut(&mut self) -> &mut Film {
+        &mut self.data.film
+    }
+
     /// Returns the sample bounds accounting for the half-pixel offsets when
     /// converting from discrete to continuous pixel coordinates.
     fn get_film_sample_bounds(&self) -> Bounds2i {
@@ -102,6 +112,16 @@ impl Camera for OrthographicCamera {
         self.data.film.write_image(splat_scale);
     }
 
+    /// Returns the transformation from world space to raster space at the
+    /// time the shutter opens.
+    fn world_to_raster(&self) -> Option<Transform> {
+        let camera_to_world = self
+            .data
+            .camera_to_world
+            .interpolate(self.data.shutter_open);
+        Some(self.proj_data.raster_to_camera.inverse() * camera_to_world.inverse())
+    }
+
     /// Returns a ray corresponding to a given sample. It also returns, a floating
     /// point value that affects how much the radiance arriving at the film plane
     /// will contribute to final image.
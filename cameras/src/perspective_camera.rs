@@ -3,10 +3,12 @@
 use core::camera::*;
 use core::film::*;
 use core::geometry::*;
+use core::light::*;
 use core::medium::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::sampling::*;
+use core::spectrum::*;
 use std::mem::swap;
 
 /// Perspective camera.
@@ -26,6 +28,9 @@ pub struct PerspectiveCamera {
 
     /// Area covered by the image plane bounds at z=1.
     pub a: Float,
+
+    /// Clipping applied to camera rays.
+    pub clipping: CameraClipping,
 }
 
 impl PerspectiveCamera {
@@ -41,6 +46,8 @@ impl PerspectiveCamera {
     /// * `fov`             - The field-of-view angle in degrees.
     /// * `film`            - The film to capture the rendered image.
     /// * `medium`          - Scattering medium the camera lies in.
+    /// * `scene_scale`     - Size of a nominal unit in scene units used to
+    ///                       scale the near and far planes.
     pub fn new(
         camera_to_world: AnimatedTransform,
         screen_window: Bounds2f,
@@ -51,6 +58,7 @@ impl PerspectiveCamera {
         fov: Float,
         film: Film,
         medium: Option<ArcMedium>,
+        scene_scale: Float,
     ) -> Self {
         let film_clone = film;
         let res = film_clone.full_resolution;
@@ -64,7 +72,7 @@ impl PerspectiveCamera {
         );
         let proj_data = ProjectiveCameraData::new(
             &data,
-            Transform::perspective(fov, 1e-2, 1000.0),
+            Transform::perspective(fov, 1e-2 * scene_scale, 1000.0 * scene_scale),
             screen_window,
             lens_radius,
             focal_distance,
@@ -106,11 +114,76 @@ impl PerspectiveCamera {
             dx_camera,
             dy_camera,
             a,
+            clipping: CameraClipping::default(),
+        }
+    }
+
+    /// Returns the area of the lens or 1 for a pinhole camera.
+    fn lens_area(&self) -> Float {
+        let lens_radius = self.proj_data.lens_radius;
+        if lens_radius > 0.0 {
+            PI * lens_radius * lens_radius
+        } else {
+            1.0
         }
     }
+
+    /// Returns the cosine of the angle between a ray leaving the camera and
+    /// the viewing direction and the raster position the ray passes through.
+    /// Returns `None` if the ray points away from the camera or doesn't pass
+    /// through the film.
+    ///
+    /// * `ray` - The ray leaving the camera.
+    fn raster_point(&self, ray: &Ray) -> Option<(Float, Point2f)> {
+        // Interpolate camera matrix and check if ray direction points away
+        // from the camera.
+        let camera_to_world = self.data.camera_to_world.interpolate(ray.time);
+        let cos_theta = ray
+            .d
+            .dot(&camera_to_world.transform_vector(&Vector3f::new(0.0, 0.0, 1.0)));
+        if cos_theta <= 0.0 {
+            return None;
+        }
+
+        // Map ray `(p, w)` onto the raster grid.
+        let focus = if self.proj_data.lens_radius > 0.0 {
+            self.proj_data.focal_distance
+        } else {
+            1.0
+        };
+        let p_focus = ray.at(focus / cos_theta);
+        let p_camera = camera_to_world.inverse().transform_point(&p_focus);
+        let p_raster = self
+            .proj_data
+            .raster_to_camera
+            .inverse()
+            .transform_point(&p_camera);
+
+        // Return `None` for points outside the image extent.
+        let sample_bounds = self.data.film.get_sample_bounds();
+        if p_raster.x < sample_bounds.p_min.x as Float
+            || p_raster.x >= sample_bounds.p_max.x as Float
+            || p_raster.y < sample_bounds.p_min.y as Float
+            || p_raster.y >= sample_bounds.p_max.y as Float
+        {
+            return None;
+        }
+
+        Some((cos_theta, Point2f::new(p_raster.x, p_raster.y)))
+    }
 }
 
 impl Camera for PerspectiveCamera {
+    /// Returns the film that captures the rendered image.
+    fn film(&self) -> &Film {
+        &self.data.film
+    }
+
+    /// Returns the film that captures the rendered image for updating.
+    fn film_mut(&mut self) -> &mut Film {
+        &mut self.data.film
+    }
+
     /// Returns the sample bounds accounting for the half-pixel offsets when
     /// converting from discrete to continuous pixel coordinates.
     fn get_film_sample_bounds(&self) -> Bounds2i {
@@ -139,6 +212,16 @@ impl Camera for PerspectiveCamera {
         self.data.film.write_image(splat_scale);
     }
 
+    /// Returns the transformation from world space to raster space at the
+    /// time the shutter opens.
+    fn world_to_raster(&self) -> Option<Transform> {
+        let camera_to_world = self
+            .data
+            .camera_to_world
+            .interpolate(self.data.shutter_open);
+        Some(self.proj_data.raster_to_camera.inverse() * camera_to_world.inverse())
+    }
+
     /// Returns a ray corresponding to a given sample. It also returns, a floating
     /// point value that affects how much the radiance arriving at the film plane
     /// will contribute to final image.
@@ -172,6 +255,10 @@ impl Camera for PerspectiveCamera {
             ray.d = (p_focus - ray.o).normalize();
         }
 
+        if self.clipping.is_enabled() {
+            self.clipping.clip(&mut ray);
+        }
+
         (self.data.camera_to_world.transform_ray(&ray), 1.0)
     }
 
@@ -240,26 +327,116 @@ impl Camera for PerspectiveCamera {
         };
         ray.differentials = Some(rd);
 
+        if self.clipping.is_enabled() {
+            self.clipping.clip(&mut ray);
+        }
+
         (self.data.camera_to_world.transform_ray(&ray), 1.0)
     }
 
+    /// Evaluate the importance emitted from the point on the camera in a
+    /// direction. The `include_raster_point` is true, then a raster position
+    /// associated with the ray on the film is returned as well.
+    ///
+    /// * `ray`                  - The ray.
+    /// * `include_raster_point` - Indicates whether or not to return the raster
+    ///                            position.
+    fn we(&self, ray: &Ray, include_raster_point: bool) -> (Spectrum, Option<Point2f>) {
+        // Compute the raster position the ray passes through or return zero
+        // importance if it points away from the camera or leaves the film.
+        let (cos_theta, p_raster) = match self.raster_point(ray) {
+            Some(p) => p,
+            None => return (Spectrum::new(0.0), None),
+        };
+        let p_raster = if include_raster_point {
+            Some(p_raster)
+        } else {
+            None
+        };
+
+        // Return importance for point on image plane.
+        let cos2_theta = cos_theta * cos_theta;
+        let we = 1.0 / (self.a * self.lens_area() * cos2_theta * cos2_theta);
+        (Spectrum::new(we), p_raster)
+    }
+
     /// Return the spatial and directional PDFs, as a tuple, for sampling a
     /// particular ray leaving the camera.
     ///
     /// * `ray` - The ray.
-    fn pdf_we(&self, _ray: &Ray) -> PDFResult {
-        panic!("NOT IMPLEMENTED");
+    fn pdf_we(&self, ray: &Ray) -> PDFResult {
+        match self.raster_point(ray) {
+            Some((cos_theta, _)) => PDFResult::new(
+                1.0 / self.lens_area(),
+                1.0 / (self.a * cos_theta * cos_theta * cos_theta),
+            ),
+            None => PDFResult::new(0.0, 0.0),
+        }
+    }
+
+    /// Samples a point on the lens and returns the importance arriving at a
+    /// reference point from it along with the PDF with respect to the solid
+    /// angle at the reference point.
+    ///
+    /// * `hit` - The reference point.
+    /// * `u`   - Used to sample point on the lens.
+    fn sample_wi(&self, hit: &Hit, u: &Point2f) -> SampleResult {
+        // Uniformly sample a lens interaction `lens`.
+        let p_lens = self.proj_data.lens_radius * concentric_sample_disk(u);
+        let p_lens_world = self
+            .data
+            .camera_to_world
+            .transform_point(hit.time, &Point3f::new(p_lens.x, p_lens.y, 0.0));
+        let n_lens = Normal3f::from(
+            self.data
+                .camera_to_world
+                .transform_vector(hit.time, &Vector3f::new(0.0, 0.0, 1.0)),
+        );
+        let lens = Hit::new(
+            p_lens_world,
+            hit.time,
+            Vector3f::default(),
+            Vector3f::default(),
+            n_lens,
+            Some(MediumInterface::from(self.data.medium.clone())),
+        );
+
+        // Compute PDF and importance arriving at the reference point.
+        let mut wi = lens.p - hit.p;
+        let dist = wi.length();
+        wi /= dist;
+        let pdf = (dist * dist) / (n_lens.abs_dot(&wi) * self.lens_area());
+        let (we, p_raster) = self.we(&lens.spawn_ray(&(-wi)), true);
+
+        let vis = VisibilityTester::new(lens, hit.p);
+        SampleResult::new(we, wi, pdf, p_raster, vis)
     }
 }
 
-impl From<(&ParamSet, &AnimatedTransform, Film, Option<ArcMedium>)> for PerspectiveCamera {
+impl
+    From<(
+        &ParamSet,
+        &AnimatedTransform,
+        Film,
+        Option<ArcMedium>,
+        Float,
+    )> for PerspectiveCamera
+{
     /// Create a `PerspectiveCamera` from given parameter set, animated transform,
-    /// film and medium.
+    /// film, medium and scene scale.
     ///
-    /// * `p` - A tuple containing  parameter set, animated transform, film and
-    ///         medium.
-    fn from(p: (&ParamSet, &AnimatedTransform, Film, Option<ArcMedium>)) -> Self {
-        let (params, cam2world, film, medium) = p;
+    /// * `p` - A tuple containing  parameter set, animated transform, film,
+    ///         medium and scene scale.
+    fn from(
+        p: (
+            &ParamSet,
+            &AnimatedTransform,
+            Film,
+            Option<ArcMedium>,
+            Float,
+        ),
+    ) -> Self {
+        let (params, cam2world, film, medium, scene_scale) = p;
 
         // Extract common camera parameters from `ParamSet`
         let mut shutter_open = params.find_one_float("shutteropen", 0.0);
@@ -310,7 +487,7 @@ impl From<(&ParamSet, &AnimatedTransform, Film, Option<ArcMedium>)> for Perspect
             fov = 2.0 * half_fov;
         }
 
-        Self::new(
+        let mut camera = Self::new(
             cam2world.clone(),
             screen,
             shutter_open,
@@ -320,6 +497,9 @@ impl From<(&ParamSet, &AnimatedTransform, Film, Option<ArcMedium>)> for Perspect
             fov,
             film,
             medium.clone(),
-        )
+            scene_scale,
+        );
+        camera.clipping = CameraClipping::from(params);
+        camera
     }
 }
@@ -649,6 +649,16 @@ impl From<(&ParamSet, &AnimatedTransform, Film, Option<ArcMedium>)> for Realisti
 }
 
 impl Camera for RealisticCamera {
+    /// Returns the film that captures the rendered image.
+    fn film(&self) -> &Film {
+        &self.data.film
+    }
+
+    /// Returns the film that captures the rendered image for updating.
+    fn film_mut(&mut self) -> &mut Film {
+        &mut self.data.film
+    }
+
     /// Returns the sample bounds accounting for the half-pixel offsets when
     /// converting from discrete to continuous pixel coordinates.
     fn get_film_sample_bounds(&self) -> Bounds2i {
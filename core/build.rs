@@ -0,0 +1,21 @@
+//! Build Script
+//!
+//! Records the compiler version so plugins built with another compiler can
+//! be rejected.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=PBR_RUSTC_VERSION={}", version);
+    println!("cargo:rerun-if-changed=build.rs");
+}
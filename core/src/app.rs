@@ -1,7 +1,7 @@
 //! Application related stuff
 
 #![allow(dead_code)]
-use crate::pbrt::Float;
+use crate::pbrt::{Float, Int};
 use clap::*;
 
 lazy_static! {
@@ -24,14 +24,143 @@ pub struct Options {
     /// Path to the image file.
     pub image_file: String,
 
-    /// The crop window x0, x1, y0, y1.
-    pub crop_window: [[Float; 2]; 2],
+    /// The crop window [[x0, x1], [y0, y1]] overriding the scene's.
+    pub crop_window: Option<[[Float; 2]; 2]>,
+
+    /// Pixel bounds x0, x1, y0, y1 to render overriding the scene's crop
+    /// window.
+    pub pixel_bounds: Option<[Int; 4]>,
+
+    /// Path to a previously rendered image to composite the crop window
+    /// into.
+    pub composite: Option<String>,
 
     /// Input file paths. Empty vector implies read from stdin.
     pub paths: Vec<String>,
 
     /// Tile size.
     pub tile_size: usize,
+
+    /// Wall-clock rendering budget in seconds. When set, rendering passes
+    /// are repeated until the budget expires.
+    pub time_limit: Option<Float>,
+
+    /// Time in seconds after which a tile that is still rendering splits its
+    /// remaining pixels into new tiles for idle threads.
+    pub tile_split_time: Option<Float>,
+
+    /// Texture baking options when running the `bake` subcommand.
+    pub bake: Option<BakeOptions>,
+
+    /// Path to a file listing scene files to render one after another in the
+    /// same process.
+    pub batch: Option<String>,
+
+    /// Select the BVH split method and leaf size from traversal statistics
+    /// of probe rays before rendering.
+    pub auto_tune: bool,
+
+    /// Report texture and material evaluation statistics after rendering.
+    pub stats: bool,
+
+    /// Report the time spent in each phase of rendering after rendering.
+    pub profile: bool,
+
+    /// Write quick low resolution previews before rendering at full
+    /// resolution.
+    pub coarse_to_fine: bool,
+
+    /// Rendering service options when running the `serve` subcommand.
+    pub serve: Option<ServeOptions>,
+
+    /// Path to a camera path that replaces the scene's camera with one
+    /// rendered frame per camera.
+    pub camera_path: Option<String>,
+
+    /// Directories or dynamic libraries to load plugins from.
+    pub plugins: Vec<String>,
+
+    /// Image comparison options when running the `compare` subcommand.
+    pub compare: Option<CompareOptions>,
+
+    /// Accelerator export options when running the `dumpbvh` subcommand.
+    pub dump_bvh: Option<DumpBVHOptions>,
+
+    /// Print the resolved scene description as JSON instead of rendering.
+    pub dump_scene: bool,
+
+    /// Render triangle meshes with the path tracer of the `gpu` crate.
+    pub gpu: bool,
+
+    /// Maximum bytes of image texture texels kept in memory. Textures are read
+    /// on first use and least recently used levels are dropped when set.
+    pub texture_memory: Option<usize>,
+
+    /// Address and port of a tev image viewer that finished tiles are sent
+    /// to while rendering.
+    pub display_server: Option<String>,
+
+    /// Interval in seconds between writes of the render checkpoint.
+    pub checkpoint_interval: Option<Float>,
+
+    /// Continue an interrupted render from its checkpoint.
+    pub resume: bool,
+}
+
+/// Options for the `bake` subcommand which writes a named texture to an image
+/// instead of rendering the scene.
+#[derive(Clone, Debug)]
+pub struct BakeOptions {
+    /// Name of the texture to bake.
+    pub texture: String,
+
+    /// Image resolution.
+    pub resolution: [usize; 2],
+
+    /// Path to the image file.
+    pub image_file: String,
+}
+
+/// Options for the `compare` subcommand which reports error metrics of an
+/// image against a reference instead of rendering.
+#[derive(Clone, Debug)]
+pub struct CompareOptions {
+    /// Path to the image.
+    pub image_file: String,
+
+    /// Path to the reference image.
+    pub reference_file: String,
+
+    /// Path to write the per-pixel FLIP error to.
+    pub error_file: Option<String>,
+}
+
+/// Options for the `dumpbvh` subcommand which writes the bounding boxes of the
+/// scene's BVH or kd-tree to a wireframe OBJ file instead of rendering the
+/// scene.
+#[derive(Clone, Debug)]
+pub struct DumpBVHOptions {
+    /// Deepest level of nodes to write. The root is at depth 0.
+    pub max_depth: usize,
+
+    /// Write the bounds of the primitives in leaves.
+    pub primitives: bool,
+
+    /// Path to the OBJ file.
+    pub obj_file: String,
+}
+
+/// Options for the `serve` subcommand which accepts render jobs over HTTP.
+#[derive(Clone, Debug)]
+pub struct ServeOptions {
+    /// Address and port to listen on.
+    pub address: String,
+
+    /// Maximum size of a request body in bytes.
+    pub max_body_size: usize,
+
+    /// Seconds to wait for a client to send a request or receive a response.
+    pub timeout: u64,
 }
 
 impl Options {
@@ -59,10 +188,44 @@ impl Options {
                 Arg::with_name("cropwindow")
                     .short("c")
                     .long("cropwindow")
-                    .value_name("x0 y0 x1 y1")
+                    .value_name("x0 x1 y0 y1")
                     .number_of_values(4)
                     .takes_value(true)
-                    .help("Specify an image crop window."),
+                    .help(
+                        "Specify an image crop window in [0, 1] overriding the
+                        scene's.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("pixelbounds")
+                    .long("pixelbounds")
+                    .value_name("x0 x1 y0 y1")
+                    .number_of_values(4)
+                    .takes_value(true)
+                    .conflicts_with("cropwindow")
+                    .help(
+                        "Render only the pixels with x0 <= x < x1 and y0 <= y < y1
+                        overriding the scene's crop window.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("pixel")
+                    .long("pixel")
+                    .value_name("x y")
+                    .number_of_values(2)
+                    .takes_value(true)
+                    .conflicts_with_all(&["cropwindow", "pixelbounds"])
+                    .help("Render only the given pixel."),
+            )
+            .arg(
+                Arg::with_name("composite")
+                    .long("composite")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .help(
+                        "Render only the pixels affected by the crop window and
+                        composite them into the given previously rendered image.",
+                    ),
             )
             .arg(
                 Arg::with_name("quick")
@@ -96,6 +259,263 @@ impl Options {
                     .takes_value(true)
                     .help("Size in pixels of square tiles rendered per thread."),
             )
+            .arg(
+                Arg::with_name("dump-scene")
+                    .long("dump-scene")
+                    .takes_value(false)
+                    .help("Print the resolved scene description as JSON instead of rendering."),
+            )
+            .arg(
+                Arg::with_name("gpu")
+                    .long("gpu")
+                    .takes_value(false)
+                    .help("Render triangle meshes with the GPU path tracer."),
+            )
+            .arg(
+                Arg::with_name("tile-split-time")
+                    .long("tile-split-time")
+                    .value_name("SECONDS")
+                    .takes_value(true)
+                    .help(
+                        "Split tiles that take longer than the given number of
+                        seconds and requeue their remaining pixels.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("time-limit")
+                    .long("time-limit")
+                    .value_name("SECONDS")
+                    .takes_value(true)
+                    .help(
+                        "Keep adding samples per pixel until the given number of
+                        seconds has elapsed.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("texture-memory")
+                    .long("texture-memory")
+                    .value_name("MB")
+                    .takes_value(true)
+                    .help(
+                        "Keep at most the given number of megabytes of image textures
+                        in memory, reading textures on first use and dropping the
+                        least recently used MIPMap levels.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("checkpoint")
+                    .long("checkpoint")
+                    .value_name("SECONDS")
+                    .takes_value(true)
+                    .help(
+                        "Write the accumulated image and render progress to
+                        <outfile>.checkpoint at the given interval in seconds.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("resume")
+                    .long("resume")
+                    .takes_value(false)
+                    .help("Continue an interrupted render from its checkpoint."),
+            )
+            .arg(
+                Arg::with_name("display-server")
+                    .long("display-server")
+                    .value_name("HOST:PORT")
+                    .takes_value(true)
+                    .help(
+                        "Send the image to a tev viewer listening at the given
+                        address as tiles finish rendering.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("batch")
+                    .long("batch")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .help(
+                        "Render each scene file listed in the given file, one per
+                        line, sharing loaded textures between scenes.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("auto-tune")
+                    .long("auto-tune")
+                    .takes_value(false)
+                    .help(
+                        "Choose the BVH split method and maximum primitives per leaf
+                        from a quick probe pass before rendering.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .long("stats")
+                    .takes_value(false)
+                    .help(
+                        "Report evaluation counts, time and memory for each texture
+                        and material after rendering.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .takes_value(false)
+                    .help(
+                        "Report the time spent in each phase of rendering such as
+                        BVH traversal and BSDF evaluation after rendering.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("coarse-to-fine")
+                    .long("coarse-to-fine")
+                    .takes_value(false)
+                    .help(
+                        "Write previews rendered at 1/8 and 1/4 resolution before
+                        rendering at full resolution.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("camera-path")
+                    .long("camera-path")
+                    .value_name("FILE")
+                    .takes_value(true)
+                    .help(
+                        "Render one frame for each camera in the given fSpy or JSON
+                        camera path, replacing the scene's camera.",
+                    ),
+            )
+            .arg(
+                Arg::with_name("plugins")
+                    .long("plugins")
+                    .value_name("PATH")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help(
+                        "Load shape, material and integrator plugins from the given
+                        directory or dynamic library. Plugins must be Rust libraries
+                        built with the same compiler and features. May be repeated.",
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("bake")
+                    .about("Write a named texture evaluated over (u, v) to an image.")
+                    .arg(
+                        Arg::with_name("texture")
+                            .long("texture")
+                            .value_name("NAME")
+                            .required(true)
+                            .takes_value(true)
+                            .help("Name of the texture to bake."),
+                    )
+                    .arg(
+                        Arg::with_name("resolution")
+                            .short("r")
+                            .long("resolution")
+                            .value_name("x y")
+                            .number_of_values(2)
+                            .takes_value(true)
+                            .help("Image resolution in pixels (default 512 512)."),
+                    )
+                    .arg(
+                        Arg::with_name("outfile")
+                            .short("o")
+                            .long("outfile")
+                            .value_name("FILE")
+                            .required(true)
+                            .takes_value(true)
+                            .help("Write the baked texture to the given filename."),
+                    )
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .required(false)
+                            .multiple(true)
+                            .help("Input files declaring the texture"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("dumpbvh")
+                    .about("Write the bounding boxes of the scene's BVH or kd-tree to a wireframe OBJ file.")
+                    .arg(
+                        Arg::with_name("depth")
+                            .short("d")
+                            .long("depth")
+                            .value_name("NUM")
+                            .takes_value(true)
+                            .help("Deepest level of nodes to write (default all)."),
+                    )
+                    .arg(
+                        Arg::with_name("primitives")
+                            .long("primitives")
+                            .takes_value(false)
+                            .help("Also write the bounds of the primitives in leaves."),
+                    )
+                    .arg(
+                        Arg::with_name("outfile")
+                            .short("o")
+                            .long("outfile")
+                            .value_name("FILE")
+                            .required(true)
+                            .takes_value(true)
+                            .help("Write the wireframe to the given filename."),
+                    )
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .required(false)
+                            .multiple(true)
+                            .help("Input files"),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("serve")
+                    .about("Accept render jobs over a small HTTP/JSON API.")
+                    .arg(
+                        Arg::with_name("address")
+                            .long("address")
+                            .value_name("HOST:PORT")
+                            .default_value("127.0.0.1:8080")
+                            .takes_value(true)
+                            .help("Address to listen on."),
+                    )
+                    .arg(
+                        Arg::with_name("max-body-size")
+                            .long("max-body-size")
+                            .value_name("BYTES")
+                            .default_value("16777216")
+                            .takes_value(true)
+                            .help("Reject requests with larger bodies."),
+                    )
+                    .arg(
+                        Arg::with_name("timeout")
+                            .long("timeout")
+                            .value_name("SECS")
+                            .default_value("30")
+                            .takes_value(true)
+                            .help("Close connections idle for longer than this."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("compare")
+                    .about("Report MSE, relMSE and FLIP of an image against a reference.")
+                    .arg(
+                        Arg::with_name("errorfile")
+                            .short("e")
+                            .long("errorfile")
+                            .value_name("FILE")
+                            .takes_value(true)
+                            .help("Write the per-pixel FLIP error to the given filename."),
+                    )
+                    .arg(
+                        Arg::with_name("IMAGE")
+                            .required(true)
+                            .help("Image to compare"),
+                    )
+                    .arg(
+                        Arg::with_name("REFERENCE")
+                            .required(true)
+                            .help("Reference image"),
+                    ),
+            )
             .get_matches();
 
         let max_threads = num_cpus::get();
@@ -123,23 +543,41 @@ impl Options {
             }
         };
 
-        let crop_window = match matches.values_of("cropwindow") {
-            Some(s) => {
-                let v: Vec<&str> = s.collect();
+        let crop_window = matches.values_of("cropwindow").map(|s| {
+            let v: Vec<&str> = s.collect();
+            [
                 [
-                    [
-                        v[0].parse::<Float>().expect("Invalid cropwindow.x0"),
-                        v[1].parse::<Float>().expect("Invalid cropwindow.y0"),
-                    ],
-                    [
-                        v[2].parse::<Float>().expect("Invalid cropwindow.x1"),
-                        v[3].parse::<Float>().expect("Invalid cropwindow.y1"),
-                    ],
-                ]
+                    v[0].parse::<Float>().expect("Invalid cropwindow.x0"),
+                    v[1].parse::<Float>().expect("Invalid cropwindow.x1"),
+                ],
+                [
+                    v[2].parse::<Float>().expect("Invalid cropwindow.y0"),
+                    v[3].parse::<Float>().expect("Invalid cropwindow.y1"),
+                ],
+            ]
+        });
+
+        let pixel_bounds = match (matches.values_of("pixelbounds"), matches.values_of("pixel")) {
+            (Some(s), _) => {
+                let v: Vec<&str> = s.collect();
+                Some([
+                    v[0].parse::<Int>().expect("Invalid pixelbounds.x0"),
+                    v[1].parse::<Int>().expect("Invalid pixelbounds.x1"),
+                    v[2].parse::<Int>().expect("Invalid pixelbounds.y0"),
+                    v[3].parse::<Int>().expect("Invalid pixelbounds.y1"),
+                ])
             }
-            _ => [[0.0, 1.0], [0.0, 1.0]],
+            (None, Some(s)) => {
+                let v: Vec<&str> = s.collect();
+                let x = v[0].parse::<Int>().expect("Invalid pixel.x");
+                let y = v[1].parse::<Int>().expect("Invalid pixel.y");
+                Some([x, x + 1, y, y + 1])
+            }
+            _ => None,
         };
 
+        let composite = matches.value_of("composite").map(String::from);
+
         let quick_render = match matches.value_of("quick") {
             Some(s) => s.parse::<bool>().expect("Invalid quick"),
             _ => false,
@@ -150,11 +588,142 @@ impl Options {
             _ => false,
         };
 
-        let paths: Vec<String> = match matches.values_of("INPUT") {
+        let time_limit = matches.value_of("time-limit").map(|s| {
+            let t = s.parse::<Float>().expect("Invalid time-limit");
+
+            if t <= 0.0 {
+                panic!("Invalid time-limit");
+            }
+
+            t
+        });
+
+        let tile_split_time = matches.value_of("tile-split-time").map(|s| {
+            let t = s.parse::<Float>().expect("Invalid tile-split-time");
+
+            if t <= 0.0 {
+                panic!("Invalid tile-split-time");
+            }
+
+            t
+        });
+
+        let texture_memory = matches.value_of("texture-memory").map(|s| {
+            let mb = s.parse::<usize>().expect("Invalid texture-memory");
+
+            if mb == 0 {
+                panic!("Invalid texture-memory");
+            }
+
+            mb * 1024 * 1024
+        });
+
+        let checkpoint_interval = matches.value_of("checkpoint").map(|s| {
+            let seconds = s.parse::<Float>().expect("Invalid checkpoint");
+
+            if seconds <= 0.0 {
+                panic!("Invalid checkpoint");
+            }
+
+            seconds
+        });
+
+        let resume = matches.is_present("resume");
+
+        let display_server = matches.value_of("display-server").map(String::from);
+
+        let batch = matches.value_of("batch").map(String::from);
+
+        let auto_tune = matches.is_present("auto-tune");
+
+        let stats = matches.is_present("stats");
+
+        let profile = matches.is_present("profile");
+
+        let coarse_to_fine = matches.is_present("coarse-to-fine");
+
+        let dump_scene = matches.is_present("dump-scene");
+
+        let gpu = matches.is_present("gpu");
+
+        let camera_path = matches.value_of("camera-path").map(String::from);
+
+        let plugins: Vec<String> = match matches.values_of("plugins") {
+            Some(p) => p.map(String::from).collect(),
+            None => vec![],
+        };
+
+        let mut paths: Vec<String> = match matches.values_of("INPUT") {
             Some(p) => p.map(String::from).collect(),
             None => vec![],
         };
 
+        let bake = matches.subcommand_matches("bake").map(|m| {
+            if let Some(p) = m.values_of("INPUT") {
+                paths.extend(p.map(String::from));
+            }
+
+            let resolution = match m.values_of("resolution") {
+                Some(s) => {
+                    let v: Vec<&str> = s.collect();
+                    [
+                        v[0].parse::<usize>().expect("Invalid resolution.x"),
+                        v[1].parse::<usize>().expect("Invalid resolution.y"),
+                    ]
+                }
+                _ => [512, 512],
+            };
+
+            BakeOptions {
+                texture: m.value_of("texture").unwrap().to_string(),
+                resolution,
+                image_file: m.value_of("outfile").unwrap().to_string(),
+            }
+        });
+
+        let dump_bvh = matches.subcommand_matches("dumpbvh").map(|m| {
+            if let Some(p) = m.values_of("INPUT") {
+                paths.extend(p.map(String::from));
+            }
+
+            DumpBVHOptions {
+                max_depth: m
+                    .value_of("depth")
+                    .map_or(usize::MAX, |s| s.parse::<usize>().expect("Invalid depth")),
+                primitives: m.is_present("primitives"),
+                obj_file: m.value_of("outfile").unwrap().to_string(),
+            }
+        });
+
+        let serve = matches.subcommand_matches("serve").map(|m| {
+            let timeout = m
+                .value_of("timeout")
+                .unwrap()
+                .parse::<u64>()
+                .expect("Invalid timeout");
+            if timeout == 0 {
+                panic!("Invalid timeout");
+            }
+
+            ServeOptions {
+                address: m.value_of("address").unwrap().to_string(),
+                max_body_size: m
+                    .value_of("max-body-size")
+                    .unwrap()
+                    .parse::<usize>()
+                    .expect("Invalid max-body-size"),
+                timeout,
+            }
+        });
+
+        let compare = matches
+            .subcommand_matches("compare")
+            .map(|m| CompareOptions {
+                image_file: m.value_of("IMAGE").unwrap().to_string(),
+                reference_file: m.value_of("REFERENCE").unwrap().to_string(),
+                error_file: m.value_of("errorfile").map(String::from),
+            });
+
         let tile_size = match matches.value_of("tilesize") {
             Some(s) => {
                 let n = s.parse::<usize>().expect("Invalid tilesize");
@@ -175,8 +744,29 @@ impl Options {
             quiet,
             image_file,
             crop_window,
+            pixel_bounds,
+            composite,
             paths,
             tile_size,
+            time_limit,
+            tile_split_time,
+            bake,
+            batch,
+            auto_tune,
+            stats,
+            profile,
+            coarse_to_fine,
+            serve,
+            camera_path,
+            plugins,
+            compare,
+            dump_bvh,
+            dump_scene,
+            gpu,
+            texture_memory,
+            display_server,
+            checkpoint_interval,
+            resume,
         }
     }
 }
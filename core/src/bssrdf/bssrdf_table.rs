@@ -0,0 +1,268 @@
+//! BSSRDF Table
+
+use super::*;
+use crate::interpolation::*;
+use crate::medium::*;
+use crate::reflection::*;
+
+/// Number of albedo samples in the tabulated profiles.
+pub const BSSRDF_RHO_SAMPLES: usize = 100;
+
+/// Number of radius samples in the tabulated profiles.
+pub const BSSRDF_RADIUS_SAMPLES: usize = 64;
+
+/// Number of samples used to integrate the photon beam diffusion terms.
+const BEAM_DIFFUSION_SAMPLES: usize = 100;
+
+/// Stores the radial scattering profile of a homogeneous medium with unit
+/// attenuation coefficient for a range of single scattering albedos. Profiles
+/// for other attenuation coefficients are found by scaling the radius.
+#[derive(Clone, Default)]
+pub struct BSSRDFTable {
+    /// Single scattering albedo samples.
+    pub rho_samples: Vec<Float>,
+
+    /// Optical radius samples.
+    pub radius_samples: Vec<Float>,
+
+    /// Profile values with a row of radius samples for each albedo sample.
+    /// The values include the 2πr factor of the polar coordinates.
+    pub profile: Vec<Float>,
+
+    /// Effective albedo for each albedo sample given by integrating the
+    /// profile over the plane.
+    pub rho_eff: Vec<Float>,
+
+    /// Discrete CDFs of the rows of `profile`.
+    pub profile_cdf: Vec<Float>,
+}
+
+impl BSSRDFTable {
+    /// Returns the profile value for an albedo and a radius sample.
+    ///
+    /// * `rho_index`    - Index of the albedo sample.
+    /// * `radius_index` - Index of the radius sample.
+    pub fn eval_profile(&self, rho_index: usize, radius_index: usize) -> Float {
+        self.profile[rho_index * self.radius_samples.len() + radius_index]
+    }
+
+    /// Returns the profile interpolated at an albedo and an optical radius
+    /// along with the interpolated effective albedo or `None` if either is
+    /// outside the table.
+    ///
+    /// * `rho` - Single scattering albedo.
+    /// * `r`   - Optical radius.
+    pub fn interpolate(&self, rho: Float, r: Float) -> Option<(Float, Float)> {
+        let (rho_weights, rho_offset) = catmull_rom_weights(&self.rho_samples, rho)?;
+        let (radius_weights, radius_offset) = catmull_rom_weights(&self.radius_samples, r)?;
+
+        let mut sr = 0.0;
+        let mut rho_eff = 0.0;
+        for (i, rho_weight) in rho_weights.iter().enumerate() {
+            if *rho_weight == 0.0 {
+                continue;
+            }
+            let rho_index = (rho_offset + i as isize) as usize;
+            rho_eff += self.rho_eff[rho_index] * rho_weight;
+            for (j, radius_weight) in radius_weights.iter().enumerate() {
+                if *radius_weight != 0.0 {
+                    let radius_index = (radius_offset + j as isize) as usize;
+                    sr += self.eval_profile(rho_index, radius_index) * rho_weight * radius_weight;
+                }
+            }
+        }
+        Some((sr, rho_eff))
+    }
+}
+
+/// Returns the table of radial scattering profiles computed with the photon
+/// beam diffusion model.
+///
+/// * `g`   - The asymmetry parameter of the phase function.
+/// * `eta` - Relative index of refraction of the boundary.
+pub fn compute_beam_diffusion_bssrdf(g: Float, eta: Float) -> BSSRDFTable {
+    // Choose radius values of the diffusion profile discretization.
+    let mut radius_samples = vec![0.0; BSSRDF_RADIUS_SAMPLES];
+    radius_samples[1] = 2.5e-3;
+    for i in 2..BSSRDF_RADIUS_SAMPLES {
+        radius_samples[i] = radius_samples[i - 1] * 1.2;
+    }
+
+    // Choose albedo values of the diffusion profile discretization.
+    let rho_samples: Vec<Float> = (0..BSSRDF_RHO_SAMPLES)
+        .map(|i| {
+            (1.0 - (-8.0 * i as Float / (BSSRDF_RHO_SAMPLES - 1) as Float).exp())
+                / (1.0 - (-8.0 as Float).exp())
+        })
+        .collect();
+
+    // Compute the scattering profile for each albedo.
+    let mut profile = Vec::with_capacity(BSSRDF_RHO_SAMPLES * BSSRDF_RADIUS_SAMPLES);
+    let mut profile_cdf = Vec::with_capacity(BSSRDF_RHO_SAMPLES * BSSRDF_RADIUS_SAMPLES);
+    let mut rho_eff = Vec::with_capacity(BSSRDF_RHO_SAMPLES);
+    for rho in rho_samples.iter() {
+        let row: Vec<Float> = radius_samples
+            .iter()
+            .map(|r| {
+                TWO_PI
+                    * r
+                    * (beam_diffusion_ss(*rho, 1.0 - rho, g, eta, *r)
+                        + beam_diffusion_ms(*rho, 1.0 - rho, g, eta, *r))
+            })
+            .collect();
+        let (cdf, sum) = integrate_catmull_rom(&radius_samples, &row);
+        profile.extend(row);
+        profile_cdf.extend(cdf);
+        rho_eff.push(sum);
+    }
+
+    BSSRDFTable {
+        rho_samples,
+        radius_samples,
+        profile,
+        rho_eff,
+        profile_cdf,
+    }
+}
+
+/// Returns the scattering coefficients that give the desired effective
+/// albedo and mean free path for each spectral channel.
+///
+/// * `table`   - The tabulated profiles.
+/// * `rho_eff` - Desired effective albedo.
+/// * `mfp`     - Desired mean free path.
+pub fn subsurface_from_diffuse(
+    table: &BSSRDFTable,
+    rho_eff: &Spectrum,
+    mfp: &Spectrum,
+) -> ScatteringProperties {
+    let mut sigma_a = Spectrum::new(0.0);
+    let mut sigma_s = Spectrum::new(0.0);
+    for c in 0..rho_eff.samples().len() {
+        let rho = invert_catmull_rom(&table.rho_samples, &table.rho_eff, rho_eff[c]);
+        sigma_s[c] = rho / mfp[c];
+        sigma_a[c] = (1.0 - rho) / mfp[c];
+    }
+    ScatteringProperties { sigma_a, sigma_s }
+}
+
+/// Returns the multiple scattering term of the photon beam diffusion profile
+/// at a given radius.
+///
+/// * `sigma_s` - Scattering coefficient.
+/// * `sigma_a` - Absorption coefficient.
+/// * `g`       - The asymmetry parameter of the phase function.
+/// * `eta`     - Relative index of refraction of the boundary.
+/// * `r`       - Radius.
+fn beam_diffusion_ms(sigma_s: Float, sigma_a: Float, g: Float, eta: Float, r: Float) -> Float {
+    // Compute reduced scattering coefficients and albedo.
+    let sigmap_s = sigma_s * (1.0 - g);
+    let sigmap_t = sigma_a + sigmap_s;
+    let rhop = sigmap_s / sigmap_t;
+
+    // Compute the non-classical diffusion coefficient using Grosjean's
+    // approximation and the effective transport coefficient.
+    let d_g = (2.0 * sigma_a + sigmap_s) / (3.0 * sigmap_t * sigmap_t);
+    let sigma_tr = (sigma_a / d_g).sqrt();
+
+    // Determine linear extrapolation distance using the Fresnel moments.
+    let fm1 = fresnel_moment1(eta);
+    let fm2 = fresnel_moment2(eta);
+    let ze = -2.0 * d_g * (1.0 + 3.0 * fm2) / (1.0 - 2.0 * fm1);
+
+    // Determine exitance scale factors.
+    let c_phi = 0.25 * (1.0 - 2.0 * fm1);
+    let c_e = 0.5 * (1.0 - 3.0 * fm2);
+
+    let mut ed = 0.0;
+    for i in 0..BEAM_DIFFUSION_SAMPLES {
+        // Sample real point source depth and compute the virtual source depth.
+        let zr = -(1.0 - (i as Float + 0.5) / BEAM_DIFFUSION_SAMPLES as Float).ln() / sigmap_t;
+        let zv = -zr + 2.0 * ze;
+        let dr = (r * r + zr * zr).sqrt();
+        let dv = (r * r + zv * zv).sqrt();
+
+        // Compute dipole fluence rate and fluence vector irradiance.
+        let phi_d = INV_FOUR_PI / d_g * ((-sigma_tr * dr).exp() / dr - (-sigma_tr * dv).exp() / dv);
+        let ed_n = INV_FOUR_PI
+            * (zr * (1.0 + sigma_tr * dr) * (-sigma_tr * dr).exp() / (dr * dr * dr)
+                - zv * (1.0 + sigma_tr * dv) * (-sigma_tr * dv).exp() / (dv * dv * dv));
+
+        // Add contribution from the dipole for depth `zr`.
+        let e = phi_d * c_phi + ed_n * c_e;
+        let kappa = 1.0 - (-2.0 * sigmap_t * (dr + zr)).exp();
+        ed += kappa * rhop * rhop * e;
+    }
+    ed / BEAM_DIFFUSION_SAMPLES as Float
+}
+
+/// Returns the single scattering term of the photon beam diffusion profile
+/// at a given radius.
+///
+/// * `sigma_s` - Scattering coefficient.
+/// * `sigma_a` - Absorption coefficient.
+/// * `g`       - The asymmetry parameter of the phase function.
+/// * `eta`     - Relative index of refraction of the boundary.
+/// * `r`       - Radius.
+fn beam_diffusion_ss(sigma_s: Float, sigma_a: Float, g: Float, eta: Float, r: Float) -> Float {
+    // Compute material parameters and the minimum `t` below the critical
+    // angle.
+    let sigma_t = sigma_a + sigma_s;
+    let rho = sigma_s / sigma_t;
+    let t_crit = r * (eta * eta - 1.0).sqrt();
+
+    let mut ess = 0.0;
+    for i in 0..BEAM_DIFFUSION_SAMPLES {
+        // Evaluate the single scattering integrand and add it to `ess`.
+        let ti =
+            t_crit - (1.0 - (i as Float + 0.5) / BEAM_DIFFUSION_SAMPLES as Float).ln() / sigma_t;
+
+        // Determine length `d` of the connecting segment and `cos(θo)`.
+        let d = (r * r + ti * ti).sqrt();
+        let cos_theta_o = ti / d;
+
+        ess += rho * (-sigma_t * (d + t_crit)).exp() / (d * d)
+            * phase_hg(cos_theta_o, g)
+            * (1.0 - fr_dielectric(-cos_theta_o, 1.0, eta))
+            * abs(cos_theta_o);
+    }
+    ess / BEAM_DIFFUSION_SAMPLES as Float
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effective_albedo_increases_with_albedo() {
+        let table = compute_beam_diffusion_bssrdf(0.0, 1.33);
+        assert_eq!(table.rho_eff[0], 0.0);
+        assert!(table.rho_eff.windows(2).all(|w| w[0] < w[1]));
+        assert!(table.rho_eff[BSSRDF_RHO_SAMPLES - 1] < 1.1);
+    }
+
+    #[test]
+    fn subsurface_from_diffuse_round_trip() {
+        let table = compute_beam_diffusion_bssrdf(0.0, 1.33);
+        let kd = Spectrum::new(0.5);
+        let mfp = Spectrum::new(0.25);
+        let props = subsurface_from_diffuse(&table, &kd, &mfp);
+
+        // The mean free path is the reciprocal of the attenuation.
+        let sigma_t = props.sigma_t();
+        assert!((sigma_t[0] - 4.0).abs() < 1e-4);
+
+        // The albedo found gives back the effective albedo.
+        let rho = props.sigma_s[0] / sigma_t[0];
+        let (weights, offset) = catmull_rom_weights(&table.rho_samples, rho).unwrap();
+        let rho_eff = (0..4)
+            .filter(|i| weights[*i] != 0.0)
+            .map(|i| weights[i] * table.rho_eff[(offset + i as isize) as usize])
+            .sum::<Float>();
+        assert!((rho_eff - 0.5).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,174 @@
+//! Bidirectional scattering surface reflectance distribution function.
+
+use crate::geometry::*;
+use crate::pbrt::*;
+use crate::rng::*;
+use crate::scene::*;
+use crate::spectrum::*;
+use std::sync::Arc;
+
+mod bssrdf_table;
+mod separable_bssrdf_adapter;
+mod tabulated_bssrdf;
+
+// Re-export
+pub use bssrdf_table::*;
+pub use separable_bssrdf_adapter::*;
+pub use tabulated_bssrdf::*;
+
+/// BSSRDF trait provides common behavior.
+pub trait BSSRDF {
+    /// Returns the value of the BSSRDF for light arriving at `pi` from
+    /// direction `wi` and leaving at the point it was created for.
+    ///
+    /// * `pi` - The incident point.
+    /// * `wi` - The incident direction.
+    fn s(&self, pi: &SurfaceInteraction, wi: &Vector3f) -> Spectrum;
+
+    /// Samples an incident point on the surface of the same material. Returns
+    /// the value of the spatial profile, the incident point and the
+    /// probability density of sampling it. The incident point's BSDF accounts
+    /// for the directional part of the BSSRDF.
+    ///
+    /// * `scene` - The scene.
+    /// * `u1`    - Sample value used to select the probe axis and channel.
+    /// * `u2`    - Sample values used to select the probe location.
+    fn sample_s<'s>(
+        &self,
+        scene: &'s Scene,
+        u1: Float,
+        u2: &Point2f,
+    ) -> (Spectrum, Option<SurfaceInteraction<'s>>, Float);
+}
+
+/// Atomic reference counted `BSSRDF`.
+pub type ArcBSSRDF = Arc<dyn BSSRDF + Send + Sync>;
+
+/// Probability of choosing each local axis as the probe axis. The surface
+/// normal is chosen half the time and each tangent axis a quarter.
+pub const PROBE_AXIS_PROBABILITIES: [Float; 3] = [0.5, 0.25, 0.25];
+
+/// The probe axis and spectral channel used to sample an incident point for
+/// a separable BSSRDF.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProbeSelection {
+    /// Index of the probe axis; 0 is the surface normal and 1 and 2 are the
+    /// tangent axes.
+    pub axis: usize,
+
+    /// Spectral channel whose profile is sampled.
+    pub channel: usize,
+
+    /// The sample value remapped to [0, 1) for reuse when sampling the radius.
+    pub u: Float,
+}
+
+impl ProbeSelection {
+    /// Selects the probe axis and spectral channel from a single sample value.
+    /// Each (axis, channel) pair is assigned a contiguous interval of [0, 1)
+    /// proportional to its probability so a stratified `u` drawn from a
+    /// sampler dimension (`Sampler::get_1d()`) is stratified over the pairs
+    /// as well, rather than selecting them independently at random.
+    ///
+    /// * `u`          - Sample value in [0, 1).
+    /// * `n_channels` - Number of spectral channels.
+    pub fn new(u: Float, n_channels: usize) -> Self {
+        assert!(n_channels > 0, "BSSRDF needs at least one spectral channel");
+
+        let last_axis = PROBE_AXIS_PROBABILITIES.len() - 1;
+        let mut start = 0.0;
+        for (axis, p_axis) in PROBE_AXIS_PROBABILITIES.iter().enumerate() {
+            let end = start + p_axis;
+            if u < end || axis == last_axis {
+                // Split the axis interval evenly between channels.
+                let v = clamp((u - start) / p_axis, 0.0, ONE_MINUS_EPSILON) * n_channels as Float;
+                let channel = min(v as usize, n_channels - 1);
+                let u = min(v - channel as Float, ONE_MINUS_EPSILON);
+                return Self { axis, channel, u };
+            }
+            start = end;
+        }
+        unreachable!()
+    }
+
+    /// Returns the probability of the selected axis and channel.
+    ///
+    /// * `n_channels` - Number of spectral channels.
+    pub fn pdf(&self, n_channels: usize) -> Float {
+        PROBE_AXIS_PROBABILITIES[self.axis] / n_channels as Float
+    }
+}
+
+/// Returns the first moment of the Fresnel reflectance of a dielectric
+/// interface using a polynomial fit.
+///
+/// * `eta` - Relative index of refraction.
+pub fn fresnel_moment1(eta: Float) -> Float {
+    let eta2 = eta * eta;
+    let eta3 = eta2 * eta;
+    let eta4 = eta3 * eta;
+    let eta5 = eta4 * eta;
+    if eta < 1.0 {
+        0.45966 - 1.73965 * eta + 3.37668 * eta2 - 3.904945 * eta3 + 2.49277 * eta4 - 0.68441 * eta5
+    } else {
+        -4.61686 + 11.1136 * eta - 10.4646 * eta2 + 5.11455 * eta3 - 1.27198 * eta4 + 0.12746 * eta5
+    }
+}
+
+/// Returns the second moment of the Fresnel reflectance of a dielectric
+/// interface using a polynomial fit.
+///
+/// * `eta` - Relative index of refraction.
+pub fn fresnel_moment2(eta: Float) -> Float {
+    let eta2 = eta * eta;
+    let eta3 = eta2 * eta;
+    let eta4 = eta3 * eta;
+    let eta5 = eta4 * eta;
+    if eta < 1.0 {
+        0.27614 - 0.87350 * eta + 1.12077 * eta2 - 0.65095 * eta3 + 0.07883 * eta4 + 0.04860 * eta5
+    } else {
+        let r_eta = 1.0 / eta;
+        let r_eta2 = r_eta * r_eta;
+        let r_eta3 = r_eta2 * r_eta;
+        -547.033 + 45.3087 * r_eta3 - 218.725 * r_eta2 + 458.843 * r_eta + 404.557 * eta
+            - 189.519 * eta2
+            + 54.9327 * eta3
+            - 9.00603 * eta4
+            + 0.63942 * eta5
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::RGB_SAMPLES;
+
+    #[test]
+    fn probe_selection_is_stratified() {
+        // Stratified samples should visit every (axis, channel) pair in
+        // proportion to its probability.
+        let n = 1200;
+        let mut counts = [[0_usize; RGB_SAMPLES]; 3];
+        for i in 0..n {
+            let u = (i as Float + 0.5) / n as Float;
+            let s = ProbeSelection::new(u, RGB_SAMPLES);
+            assert!((0.0..1.0).contains(&s.u));
+            counts[s.axis][s.channel] += 1;
+        }
+        for (axis, axis_counts) in counts.iter().enumerate() {
+            for (channel, count) in axis_counts.iter().enumerate() {
+                let p = ProbeSelection {
+                    axis,
+                    channel,
+                    u: 0.0,
+                }
+                .pdf(RGB_SAMPLES);
+                assert_eq!(*count, (p * n as Float).round() as usize);
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Separable BSSRDF Adapter
+
+use super::*;
+use crate::material::*;
+use crate::reflection::*;
+
+/// BxDF for the directional part of a separable BSSRDF at the incident
+/// point. It lets the incident point be shaded like any other surface.
+#[derive(Clone)]
+pub struct SeparableBSSRDFAdapter {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Relative index of refraction of the boundary.
+    eta: Float,
+
+    /// Indicates whether incident ray started from a light source or from
+    /// camera.
+    mode: TransportMode,
+}
+
+impl SeparableBSSRDFAdapter {
+    /// Create a new instance of `SeparableBSSRDFAdapter`.
+    ///
+    /// * `eta`  - Relative index of refraction of the boundary.
+    /// * `mode` - Indicates whether incident ray started from a light source
+    ///            or from camera.
+    pub fn new(eta: Float, mode: TransportMode) -> Self {
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_DIFFUSE),
+            eta,
+            mode,
+        }
+    }
+}
+
+impl BxDF for SeparableBSSRDFAdapter {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `_wo` - Outgoing direction (ignored).
+    /// * `wi`  - Incident direction.
+    fn f(&self, _wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        let f = separable_sw(cos_theta(wi), self.eta);
+
+        // Account for the change in radiance when light refracts into the
+        // medium; only radiance is affected.
+        if self.mode == TransportMode::Radiance {
+            Spectrum::new(f * self.eta * self.eta)
+        } else {
+            Spectrum::new(f)
+        }
+    }
+}
+
+/// Returns the directional term of a separable BSSRDF. It is the Fresnel
+/// transmittance normalized so that it integrates to 1 over the hemisphere
+/// with the cosine factor.
+///
+/// * `cos_theta` - Cosine of the angle between the direction and the normal.
+/// * `eta`       - Relative index of refraction of the boundary.
+pub fn separable_sw(cos_theta: Float, eta: Float) -> Float {
+    let c = 1.0 - 2.0 * fresnel_moment1(1.0 / eta);
+    (1.0 - fr_dielectric(cos_theta, 1.0, eta)) / (c * PI)
+}
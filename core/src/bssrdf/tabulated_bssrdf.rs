@@ -0,0 +1,413 @@
+//! Tabulated BSSRDF
+
+use super::*;
+use crate::interpolation::*;
+use crate::material::*;
+use crate::reflection::*;
+
+/// Implements a separable BSSRDF for a homogeneous medium whose radial
+/// scattering profile is interpolated from a `BSSRDFTable`. Incident points
+/// are found by tracing probe rays through the object along one of the axes
+/// of the shading coordinate system at the outgoing point.
+#[derive(Clone)]
+pub struct TabulatedBSSRDF {
+    /// The outgoing point.
+    p: Point3f,
+
+    /// Time when the interaction at the outgoing point occurred.
+    time: Float,
+
+    /// The outgoing direction.
+    wo: Vector3f,
+
+    /// Shading normal at the outgoing point.
+    ns: Normal3f,
+
+    /// First tangent of the shading coordinate system at the outgoing point.
+    ss: Vector3f,
+
+    /// Second tangent of the shading coordinate system at the outgoing point.
+    ts: Vector3f,
+
+    /// Relative index of refraction of the boundary.
+    eta: Float,
+
+    /// Material of the surface at the outgoing point. Only incident points
+    /// on surfaces with the same material are sampled.
+    material: Option<ArcMaterial>,
+
+    /// Indicates whether incident ray started from a light source or from
+    /// camera.
+    mode: TransportMode,
+
+    /// Attenuation coefficient σt = σa + σs.
+    sigma_t: Spectrum,
+
+    /// Single scattering albedo σs / σt.
+    rho: Spectrum,
+
+    /// The tabulated scattering profiles.
+    table: Arc<BSSRDFTable>,
+}
+
+impl TabulatedBSSRDF {
+    /// Create a new `TabulatedBSSRDF` at a surface interaction. The material
+    /// is taken from the primitive at the interaction.
+    ///
+    /// * `po`      - The outgoing surface interaction.
+    /// * `eta`     - Relative index of refraction of the boundary.
+    /// * `mode`    - Indicates whether incident ray started from a light
+    ///               source or from camera.
+    /// * `sigma_a` - Absorption coefficient σa.
+    /// * `sigma_s` - Scattering coefficient σs.
+    /// * `table`   - The tabulated scattering profiles.
+    pub fn new(
+        po: &SurfaceInteraction,
+        eta: Float,
+        mode: TransportMode,
+        sigma_a: &Spectrum,
+        sigma_s: &Spectrum,
+        table: Arc<BSSRDFTable>,
+    ) -> Self {
+        let ns = po.shading.n;
+        let ss = po.shading.dpdu.normalize();
+        let ts = Vector3f::from(ns).cross(&ss);
+
+        let sigma_t = *sigma_a + *sigma_s;
+        let mut rho = Spectrum::new(0.0);
+        for c in 0..sigma_t.samples().len() {
+            rho[c] = if sigma_t[c] != 0.0 {
+                sigma_s[c] / sigma_t[c]
+            } else {
+                0.0
+            };
+        }
+
+        Self {
+            p: po.hit.p,
+            time: po.hit.time,
+            wo: po.hit.wo,
+            ns,
+            ss,
+            ts,
+            eta,
+            material: po.primitive.and_then(|primitive| primitive.get_material()),
+            mode,
+            sigma_t,
+            rho,
+            table,
+        }
+    }
+
+    /// Returns the radial scattering profile at a distance.
+    ///
+    /// * `r` - The distance.
+    pub fn sr(&self, r: Float) -> Spectrum {
+        let mut sr = Spectrum::new(0.0);
+        for c in 0..self.sigma_t.samples().len() {
+            // Convert `r` into unitless optical radius.
+            let r_optical = r * self.sigma_t[c];
+
+            // Interpolate the tabulated profile.
+            let mut s = match self.table.interpolate(self.rho[c], r_optical) {
+                Some((s, _rho_eff)) => s,
+                None => continue,
+            };
+
+            // Cancel the 2πr factor included in the table.
+            if r_optical != 0.0 {
+                s /= TWO_PI * r_optical;
+            }
+            sr[c] = s * self.sigma_t[c] * self.sigma_t[c];
+        }
+        sr.clamp_default()
+    }
+
+    /// Samples a distance proportional to the radial scattering profile of
+    /// a spectral channel. Returns a negative value if the channel doesn't
+    /// scatter.
+    ///
+    /// * `ch` - The spectral channel.
+    /// * `u`  - Sample value.
+    pub fn sample_sr(&self, ch: usize, u: Float) -> Float {
+        if self.sigma_t[ch] == 0.0 {
+            return -1.0;
+        }
+        let (r_optical, _fval, _pdf) = sample_catmull_rom_2d(
+            &self.table.rho_samples,
+            &self.table.radius_samples,
+            &self.table.profile,
+            &self.table.profile_cdf,
+            self.rho[ch],
+            u,
+        );
+        r_optical / self.sigma_t[ch]
+    }
+
+    /// Returns the probability density of `sample_sr()` sampling a distance.
+    ///
+    /// * `ch` - The spectral channel.
+    /// * `r`  - The distance.
+    pub fn pdf_sr(&self, ch: usize, r: Float) -> Float {
+        // Convert `r` into unitless optical radius.
+        let r_optical = r * self.sigma_t[ch];
+
+        // Interpolate the tabulated profile and effective albedo.
+        let (mut sr, rho_eff) = match self.table.interpolate(self.rho[ch], r_optical) {
+            Some(x) => x,
+            None => return 0.0,
+        };
+
+        // Cancel the 2πr factor included in the table.
+        if r_optical != 0.0 {
+            sr /= TWO_PI * r_optical;
+        }
+        max(0.0, sr * self.sigma_t[ch] * self.sigma_t[ch] / rho_eff)
+    }
+
+    /// Returns the spatial profile between the outgoing and incident points.
+    ///
+    /// * `pi` - The incident point.
+    pub fn sp(&self, pi: &SurfaceInteraction) -> Spectrum {
+        self.sr(self.p.distance(pi.hit.p))
+    }
+
+    /// Samples an incident point on a surface with the same material by
+    /// tracing a probe ray through the object.
+    ///
+    /// * `scene` - The scene.
+    /// * `u1`    - Sample value used to select the probe axis and channel and
+    ///             the distance.
+    /// * `u2`    - Sample values used to select the angle around the probe
+    ///             axis and one of the points found.
+    pub fn sample_sp<'s>(
+        &self,
+        scene: &'s Scene,
+        u1: Float,
+        u2: &Point2f,
+    ) -> (Spectrum, Option<SurfaceInteraction<'s>>, Float) {
+        let none = (Spectrum::new(0.0), None, 0.0);
+        let material = match self.material.as_ref() {
+            Some(material) => material,
+            None => return none,
+        };
+
+        // Choose projection axis and spectral channel for BSSRDF sampling.
+        let n_channels = self.sigma_t.samples().len();
+        let selection = ProbeSelection::new(u1, n_channels);
+        let (vx, vy, vz) = self.probe_frame(selection.axis);
+
+        // Sample BSSRDF profile in polar coordinates.
+        let r = self.sample_sr(selection.channel, selection.u);
+        if r < 0.0 {
+            return none;
+        }
+        let phi = TWO_PI * u2[0];
+
+        // Compute BSSRDF profile bounds and intersection height.
+        let r_max = self.sample_sr(selection.channel, 0.999);
+        if r >= r_max {
+            return none;
+        }
+        let l = 2.0 * (r_max * r_max - r * r).sqrt();
+
+        // Compute BSSRDF sampling ray segment.
+        let p_start = self.p + r * (vx * cos(phi) + vy * sin(phi)) - l * vz / 2.0;
+        let p_target = p_start + l * vz;
+
+        // Accumulate chain of intersections along the probe segment that
+        // share the material.
+        let mut found: Vec<SurfaceInteraction<'s>> = vec![];
+        let mut base = Hit::new(
+            p_start,
+            self.time,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::default(),
+            None,
+        );
+        loop {
+            let mut ray = base.spawn_ray_to_point(&p_target);
+            if ray.d.length_squared() == 0.0 {
+                break;
+            }
+            let si = match scene.intersect(&mut ray) {
+                Some(si) => si,
+                None => break,
+            };
+            base = si.hit.clone();
+            let same_material = match si.primitive.and_then(|p| p.get_material()) {
+                Some(m) => Arc::as_ptr(&m) as *const u8 == Arc::as_ptr(material) as *const u8,
+                None => false,
+            };
+            if same_material {
+                found.push(si);
+            }
+        }
+
+        // Randomly choose one of several intersections during BSSRDF sampling.
+        let n_found = found.len();
+        if n_found == 0 {
+            return none;
+        }
+        let selected = min((u2[1] * n_found as Float) as usize, n_found - 1);
+        let pi = found.swap_remove(selected);
+
+        // Compute sample PDF and return the spatial BSSRDF term `sp`.
+        let pdf = self.pdf_sp(&pi) / n_found as Float;
+        (self.sp(&pi), Some(pi), pdf)
+    }
+
+    /// Returns the probability density of `sample_sp()` sampling an incident
+    /// point, accounting for all the probe axes and spectral channels that
+    /// could have found it.
+    ///
+    /// * `pi` - The incident point.
+    pub fn pdf_sp(&self, pi: &SurfaceInteraction) -> Float {
+        let d = self.p - pi.hit.p;
+        let n = Vector3f::from(pi.hit.n);
+        let n_channels = self.sigma_t.samples().len();
+        let ch_prob = 1.0 / n_channels as Float;
+
+        let mut pdf = 0.0;
+        for (axis, axis_prob) in PROBE_AXIS_PROBABILITIES.iter().enumerate() {
+            // Project the offset onto the plane perpendicular to the axis.
+            let (_vx, _vy, vz) = self.probe_frame(axis);
+            let d_axis = d.dot(&vz);
+            let r_proj = max(0.0, d.length_squared() - d_axis * d_axis).sqrt();
+            let n_axis = abs(n.dot(&vz));
+            for ch in 0..n_channels {
+                pdf += self.pdf_sr(ch, r_proj) * n_axis * ch_prob * axis_prob;
+            }
+        }
+        pdf
+    }
+
+    /// Returns the tangents of the plane in which the distance is sampled and
+    /// the probe direction for a probe axis.
+    ///
+    /// * `axis` - Index of the probe axis; 0 is the shading normal.
+    fn probe_frame(&self, axis: usize) -> (Vector3f, Vector3f, Vector3f) {
+        let ns = Vector3f::from(self.ns);
+        match axis {
+            0 => (self.ss, self.ts, ns),
+            1 => (self.ts, ns, self.ss),
+            _ => (ns, self.ss, self.ts),
+        }
+    }
+}
+
+impl BSSRDF for TabulatedBSSRDF {
+    /// Returns the value of the BSSRDF for light arriving at `pi` from
+    /// direction `wi` and leaving at the point it was created for.
+    ///
+    /// * `pi` - The incident point.
+    /// * `wi` - The incident direction.
+    fn s(&self, pi: &SurfaceInteraction, wi: &Vector3f) -> Spectrum {
+        let ft = fr_dielectric(self.wo.dot(&self.ns), 1.0, self.eta);
+        let sw = separable_sw(wi.dot(&pi.shading.n), self.eta);
+        (1.0 - ft) * self.sp(pi) * sw
+    }
+
+    /// Samples an incident point on the surface of the same material. Returns
+    /// the value of the spatial profile, the incident point and the
+    /// probability density of sampling it. The incident point's BSDF accounts
+    /// for the directional part of the BSSRDF.
+    ///
+    /// * `scene` - The scene.
+    /// * `u1`    - Sample value used to select the probe axis and channel.
+    /// * `u2`    - Sample values used to select the probe location.
+    fn sample_s<'s>(
+        &self,
+        scene: &'s Scene,
+        u1: Float,
+        u2: &Point2f,
+    ) -> (Spectrum, Option<SurfaceInteraction<'s>>, Float) {
+        let (sp, pi, pdf) = self.sample_sp(scene, u1, u2);
+        match pi {
+            Some(mut pi) if !sp.is_black() => {
+                // Initialize material model at sampled surface interaction.
+                let mut bsdf = BSDF::new(&pi, None);
+                bsdf.add(Arc::new(SeparableBSSRDFAdapter::new(self.eta, self.mode)));
+                pi.bsdf = Some(bsdf);
+                pi.hit.wo = Vector3f::from(pi.shading.n);
+                (sp, Some(pi), pdf)
+            }
+            _ => (Spectrum::new(0.0), None, 0.0),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bssrdf(sigma_a: Float, sigma_s: Float) -> TabulatedBSSRDF {
+        let identity = Arc::new(Transform::default());
+        let shape_data = Arc::new(ShapeData::new(Arc::clone(&identity), Some(identity), false));
+        let si = SurfaceInteraction::new(
+            Point3f::default(),
+            Vector3f::default(),
+            Point2f::default(),
+            Vector3f::new(0.0, 0.0, 1.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Normal3f::default(),
+            Normal3f::default(),
+            0.0,
+            shape_data,
+            None,
+        );
+        let table = Arc::new(compute_beam_diffusion_bssrdf(0.0, 1.33));
+        TabulatedBSSRDF::new(
+            &si,
+            1.33,
+            TransportMode::Radiance,
+            &Spectrum::new(sigma_a),
+            &Spectrum::new(sigma_s),
+            table,
+        )
+    }
+
+    /// Integrates a radial function over the plane.
+    fn integrate_radial(f: impl Fn(Float) -> Float, r_max: Float) -> Float {
+        let n = 20_000;
+        let dr = r_max / n as Float;
+        (0..n)
+            .map(|i| {
+                let r = (i as Float + 0.5) * dr;
+                f(r) * TWO_PI * r * dr
+            })
+            .sum()
+    }
+
+    #[test]
+    fn profile_pdf_is_normalized() {
+        let bssrdf = bssrdf(0.2, 3.0);
+        let r_max = bssrdf.sample_sr(0, 0.99999);
+        let total = integrate_radial(|r| bssrdf.pdf_sr(0, r), r_max);
+        assert!((total - 1.0).abs() < 0.02, "pdf integrates to {}", total);
+    }
+
+    #[test]
+    fn sampled_radius_follows_pdf() {
+        // The fraction of sampled radii below the median should be a half.
+        let bssrdf = bssrdf(0.2, 3.0);
+        let median = bssrdf.sample_sr(0, 0.5);
+        let below = integrate_radial(|r| bssrdf.pdf_sr(0, r), median);
+        assert!((below - 0.5).abs() < 0.02, "{} below median", below);
+    }
+
+    #[test]
+    fn profile_integrates_to_effective_albedo() {
+        let bssrdf = bssrdf(0.2, 3.0);
+        let r_max = bssrdf.sample_sr(0, 0.99999);
+        let total = integrate_radial(|r| bssrdf.sr(r)[0], r_max);
+        let (_sr, rho_eff) = bssrdf.table.interpolate(3.0 / 3.2, 0.0).unwrap();
+        assert!((total - rho_eff).abs() < 0.02 * rho_eff);
+    }
+}
@@ -4,6 +4,7 @@ use crate::film::*;
 use crate::geometry::*;
 use crate::light::*;
 use crate::medium::*;
+use crate::paramset::*;
 use crate::pbrt::*;
 use crate::spectrum::*;
 use std::fmt;
@@ -11,6 +12,12 @@ use std::sync::Arc;
 
 /// Light trait provides common behavior.
 pub trait Camera {
+    /// Returns the film that captures the rendered image.
+    fn film(&self) -> &Film;
+
+    /// Returns the film that captures the rendered image for updating.
+    fn film_mut(&mut self) -> &mut Film;
+
     /// Returns the sample bounds accounting for the half-pixel offsets when
     /// converting from discrete to continuous pixel coordinates..
     fn get_film_sample_bounds(&self) -> Bounds2i;
@@ -31,6 +38,65 @@ pub trait Camera {
     /// * `splat_scale` - Scale factor for `add_splat()` (default = 1.0).
     fn write_image(&mut self, splat_scale: Float);
 
+    /// Add a name/value pair to be written as an attribute of the output image.
+    ///
+    /// * `name`  - Attribute name.
+    /// * `value` - Attribute value.
+    fn add_film_metadata(&mut self, name: &str, value: &str) {
+        self.film_mut()
+            .metadata
+            .insert(String::from(name), String::from(value));
+    }
+
+    /// Add a contribution to the image that isn't weighted by the
+    /// reconstruction filter such as light paths connected to the camera.
+    ///
+    /// * `p` - Raster position of the contribution.
+    /// * `v` - The contribution.
+    fn add_film_splat(&mut self, p: &Point2f, v: &Spectrum) {
+        self.film_mut().add_splat(p, v);
+    }
+
+    /// Replace the image with a low resolution preview.
+    ///
+    /// * `bounds`     - Pixel bounds covered by the preview.
+    /// * `block_size` - Width of the block of pixels covered by each preview
+    ///                  pixel.
+    /// * `img`        - The preview pixels in row major order.
+    fn set_film_preview(&mut self, bounds: &Bounds2i, block_size: Int, img: &[Spectrum]) {
+        self.film_mut().set_preview(bounds, block_size, img);
+    }
+
+    /// Clear the image.
+    fn clear_film(&mut self) {
+        self.film_mut().clear();
+    }
+
+    /// Write the accumulated image and the progress of the render to the
+    /// film's checkpoint file.
+    ///
+    /// * `checkpoint` - The progress of the render.
+    fn write_film_checkpoint(&self, checkpoint: &RenderCheckpoint) -> Result<(), String> {
+        self.film().write_checkpoint(checkpoint)
+    }
+
+    /// Restore the accumulated image from the film's checkpoint file and
+    /// return the progress of the render.
+    fn read_film_checkpoint(&mut self) -> Result<RenderCheckpoint, String> {
+        self.film_mut().read_checkpoint()
+    }
+
+    /// Delete the film's checkpoint file.
+    fn remove_film_checkpoint(&self) {
+        self.film().remove_checkpoint();
+    }
+
+    /// Returns the transformation from world space to raster space at the
+    /// time the shutter opens or `None` if the camera has no projection.
+    fn world_to_raster(&self) -> Option<Transform> {
+        None
+    }
+
     /// Returns a ray corresponding to a given sample. It also returns, a floating
     /// point value that affects how much the radiance arriving at the film plane
     /// will contribute to final image.
@@ -112,13 +178,13 @@ pub trait Camera {
     /// * `ray` - The ray.
     fn pdf_we(&self, ray: &Ray) -> PDFResult;
 
-    /// Returns a PDF value with respect to the solid angle at a reference point.
+    /// Samples a point on the lens and returns the importance arriving at a
+    /// reference point from it along with the PDF with respect to the solid
+    /// angle at the reference point.
     ///
-    /// * `interaction`          - The interaction point.
-    /// * `u`                    - Used to sample point on the lens.
-    /// * `include_raster_point` - Indicates whether or not to return the raster
-    ///                            position.
-    fn sample_wi(&self, _interaction: &Interaction, _u: &Point2f) -> SampleResult {
+    /// * `hit` - The reference point.
+    /// * `u`   - Used to sample point on the lens.
+    fn sample_wi(&self, _hit: &Hit, _u: &Point2f) -> SampleResult {
         panic!("Camera::sample_wi() is not implemented");
     }
 }
@@ -176,29 +242,30 @@ impl fmt::Display for CameraSample {
 #[derive(Clone)]
 pub struct SampleResult {
     /// The sample value.
-    spectrum: Spectrum,
+    pub spectrum: Spectrum,
 
-    /// Direction from lens to interaction point.
-    wi: Vector3f,
+    /// Direction from the interaction point to the lens.
+    pub wi: Vector3f,
 
     /// The PDF value.
-    pdf: Float,
+    pub pdf: Float,
 
     /// Raster position.
-    p_raster: Option<Point2f>,
+    pub p_raster: Option<Point2f>,
 
-    /// Visibility tester.
-    vis: VisibilityTester,
+    /// Visibility tester from the point on the lens to the interaction point.
+    pub vis: VisibilityTester,
 }
 
 impl SampleResult {
     /// Create a new `SampleResult`.
     ///
     /// * `spectrum` - The sample value.
-    /// * `wi`       - Direction from lens to interaction point.
+    /// * `wi`       - Direction from the interaction point to the lens.
     /// * `pdf`      - The PDF value.
     /// * `p_raster` - Raster position.
-    /// * `vis`      - Visibility tester.
+    /// * `vis`      - Visibility tester from the point on the lens to the
+    ///                interaction point.
     pub fn new(
         spectrum: Spectrum,
         wi: Vector3f,
@@ -218,13 +285,12 @@ impl SampleResult {
 
 /// Stores the spatial and directional PDFs for sampling a ray.
 #[derive(Copy, Clone, Default)]
-#[allow(unused)]
 pub struct PDFResult {
     /// Spatial PDF.
-    pos: Float,
+    pub pos: Float,
 
     /// Directional PDF.
-    dir: Float,
+    pub dir: Float,
 }
 
 impl PDFResult {
@@ -344,3 +410,161 @@ impl ProjectiveCameraData {
         }
     }
 }
+
+/// Clipping applied to camera rays. Geometry closer than the near distance,
+/// farther than the far distance or on the negative side of a clipping plane
+/// is not seen by the camera. Planes are given in camera space by the
+/// coefficients `[a, b, c, d]` of `ax + by + cz + d = 0` and keep the points
+/// where `ax + by + cz + d >= 0`. Only camera rays are clipped; rays scattered
+/// from surfaces still see the clipped geometry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraClipping {
+    /// Near clipping distance along camera rays.
+    pub near: Float,
+
+    /// Far clipping distance along camera rays.
+    pub far: Float,
+
+    /// Clipping planes in camera space.
+    pub planes: Vec<[Float; 4]>,
+}
+
+impl CameraClipping {
+    /// Returns the range of ray parameters that is not clipped or `None` if
+    /// the whole ray is clipped.
+    ///
+    /// * `ray` - The ray in camera space.
+    pub fn t_range(&self, ray: &Ray) -> Option<(Float, Float)> {
+        let mut t_min = max(self.near, 0.0);
+        let mut t_max = min(self.far, ray.t_max);
+
+        for [a, b, c, d] in self.planes.iter() {
+            let n = Vector3f::new(*a, *b, *c);
+            let dist = n.dot(&Vector3f::from(ray.o)) + d;
+            let n_dot_d = n.dot(&ray.d);
+            if n_dot_d == 0.0 {
+                if dist < 0.0 {
+                    return None;
+                }
+            } else {
+                // Parametric distance to the plane; the ray enters the kept
+                // half space there if heading towards it or leaves otherwise.
+                let t = -dist / n_dot_d;
+                if n_dot_d > 0.0 {
+                    t_min = max(t_min, t);
+                } else {
+                    t_max = min(t_max, t);
+                }
+            }
+        }
+
+        if t_min < t_max {
+            Some((t_min, t_max))
+        } else {
+            None
+        }
+    }
+
+    /// Clips a camera space ray by moving its origin to the start of the
+    /// unclipped range and shortening it. Ray differential origins are moved
+    /// the same distance along their directions. A ray that is clipped
+    /// entirely is given a zero length so it doesn't hit anything.
+    ///
+    /// * `ray` - The ray in camera space.
+    pub fn clip(&self, ray: &mut Ray) {
+        match self.t_range(ray) {
+            Some((t_min, t_max)) => {
+                if t_min > 0.0 {
+                    ray.o = ray.at(t_min);
+                    if let Some(rd) = ray.differentials.as_mut() {
+                        rd.rx_origin += rd.rx_direction * t_min;
+                        rd.ry_origin += rd.ry_direction * t_min;
+                    }
+                }
+                ray.t_max = t_max - t_min;
+            }
+            None => ray.t_max = 0.0,
+        }
+    }
+
+    /// Returns whether any clipping is applied.
+    pub fn is_enabled(&self) -> bool {
+        self.near > 0.0 || self.far < INFINITY || !self.planes.is_empty()
+    }
+}
+
+impl Default for CameraClipping {
+    /// Returns a `CameraClipping` that doesn't clip anything.
+    fn default() -> Self {
+        Self {
+            near: 0.0,
+            far: INFINITY,
+            planes: vec![],
+        }
+    }
+}
+
+impl From<&ParamSet> for CameraClipping {
+    /// Create `CameraClipping` from given parameter set.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        let near = params.find_one_float("nearclip", 0.0);
+        let far = params.find_one_float("farclip", INFINITY);
+        if far <= near {
+            warn!(
+                "'farclip' {} should be greater than 'nearclip' {}.",
+                far, near
+            );
+        }
+
+        let coefficients = params.find_float("clipplanes");
+        let chunks = coefficients.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            error!("'clipplanes' should have four values per plane. Ignoring extra values.");
+        }
+        let planes = chunks.map(|p| [p[0], p[1], p[2], p[3]]).collect();
+
+        Self { near, far, planes }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipping_limits_ray_range() {
+        let ray = |d: Vector3f| Ray::new(Point3f::default(), d, INFINITY, 0.0, None);
+        let forward = Vector3f::new(0.0, 0.0, 1.0);
+
+        let clipping = CameraClipping {
+            near: 1.0,
+            far: 10.0,
+            planes: vec![[0.0, 0.0, -1.0, 5.0]],
+        };
+        assert_eq!(clipping.t_range(&ray(forward)), Some((1.0, 5.0)));
+
+        // Keep z >= 8 so the plane overrides the near distance.
+        let clipping = CameraClipping {
+            planes: vec![[0.0, 0.0, 1.0, -8.0]],
+            ..clipping
+        };
+        assert_eq!(clipping.t_range(&ray(forward)), Some((8.0, 10.0)));
+
+        // A ray parallel to the plane on the clipped side is removed.
+        let mut r = ray(Vector3f::new(1.0, 0.0, 0.0));
+        assert_eq!(clipping.t_range(&r), None);
+        clipping.clip(&mut r);
+        assert_eq!(r.t_max, 0.0);
+
+        let mut r = ray(forward);
+        clipping.clip(&mut r);
+        assert_eq!(r.o, Point3f::new(0.0, 0.0, 8.0));
+        assert_eq!(r.t_max, 2.0);
+    }
+}
@@ -0,0 +1,105 @@
+//! Film Accumulation
+
+use crate::pbrt::*;
+
+/// How sample contributions are summed into the film.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Accumulation {
+    /// Plain summation.
+    Plain,
+
+    /// Neumaier compensated summation. A second value per sum holds the low
+    /// order bits lost by the last addition so that very high sample counts
+    /// do not lose precision.
+    Compensated,
+}
+
+impl Default for Accumulation {
+    /// Returns `Accumulation::Plain`.
+    fn default() -> Self {
+        Self::Plain
+    }
+}
+
+impl Accumulation {
+    /// Parse an accumulation mode.
+    ///
+    /// * `s` - The name of the mode.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "compensated" => Ok(Self::Compensated),
+            _ => Err(format!("Unknown accumulation mode '{}'", s)),
+        }
+    }
+
+    /// Add a value to a running sum.
+    ///
+    /// * `sum` - The running sum.
+    /// * `c`   - Compensation for the running sum. Unused for plain summation.
+    /// * `x`   - The value to add.
+    #[inline(always)]
+    pub fn add(&self, sum: &mut Float, c: &mut Float, x: Float) {
+        match self {
+            Self::Plain => *sum += x,
+            Self::Compensated => compensated_add(sum, c, x),
+        }
+    }
+}
+
+/// Add a value to a running sum with Neumaier's variant of Kahan summation.
+/// The compensation is added back to each value before summing so that it
+/// stays smaller than the rounding error of `sum`. The final result is
+/// `sum + c`.
+///
+/// * `sum` - The running sum.
+/// * `c`   - Compensation for the running sum.
+/// * `x`   - The value to add.
+#[inline(always)]
+pub fn compensated_add(sum: &mut Float, c: &mut Float, x: Float) {
+    let y = x + *c;
+    let t = *sum + y;
+    *c = if abs(*sum) >= abs(y) {
+        (*sum - t) + y
+    } else {
+        (y - t) + *sum
+    };
+    *sum = t;
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compensated_sum_keeps_precision() {
+        let n = 1_000_000;
+        let x = 0.1;
+
+        let mut plain = 0.0;
+        let mut sum = 0.0;
+        let mut c = 0.0;
+        for _ in 0..n {
+            Accumulation::Plain.add(&mut plain, &mut 0.0, x);
+            Accumulation::Compensated.add(&mut sum, &mut c, x);
+        }
+
+        let expected = (n as f64 * x as f64) as Float;
+        assert!(abs(plain - expected) / expected > 1e-3);
+        assert!(abs(sum + c - expected) / expected < 1e-6);
+    }
+
+    #[test]
+    fn accumulation_parses_modes() {
+        assert_eq!(Accumulation::parse("plain"), Ok(Accumulation::Plain));
+        assert_eq!(
+            Accumulation::parse("compensated"),
+            Ok(Accumulation::Compensated)
+        );
+        assert!(Accumulation::parse("kahan").is_err());
+    }
+}
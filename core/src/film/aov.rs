@@ -0,0 +1,95 @@
+//! Light Path Expression AOVs
+//!
+//! Each AOV holds the part of the radiance arriving at the film along the
+//! paths matching a light path expression. AOVs are declared with the film's
+//! `lpe` parameter as `name:expression` strings and written alongside the
+//! main output as `<filename>_<name>.<extension>`.
+//!
+//! Only the `volpath` and `whitted` integrators record the paths of their
+//! contributions so other integrators ignore the AOVs.
+
+use super::{Film, FilmTile, Pixel};
+use crate::image_io::*;
+use crate::integrator::LightPathAov;
+use std::path::Path;
+use std::sync::Arc;
+
+impl Film {
+    /// Set the light path expression AOVs to accumulate.
+    ///
+    /// * `aovs` - The AOVs.
+    pub fn set_aovs(&mut self, aovs: Vec<LightPathAov>) {
+        let n = self.cropped_pixel_bounds.area() as usize;
+        self.aov_pixels = vec![vec![Pixel::default(); n]; aovs.len()];
+        self.aovs = Arc::new(aovs);
+    }
+
+    /// Returns whether the film accumulates light path expression AOVs.
+    pub fn has_aovs(&self) -> bool {
+        !self.aovs.is_empty()
+    }
+
+    /// Merge the `FilmTile`'s AOV contributions into the AOV images.
+    ///
+    /// * `tile` - The `FilmTile` to merge.
+    pub(super) fn merge_aov_tile(&mut self, tile: &FilmTile) {
+        for pixel in tile.get_pixel_bounds() {
+            let tile_pixel = tile.get_pixel_offset(&pixel);
+            let merge_pixel = self.get_pixel_offset(&pixel);
+            for (pixels, tile_pixels) in self.aov_pixels.iter_mut().zip(tile.aov_pixels.iter()) {
+                pixels[merge_pixel].merge(self.accumulation, &tile_pixels[tile_pixel]);
+            }
+        }
+    }
+
+    /// Write each AOV image next to the main output with the same options.
+    /// AOVs only cover the pixels that were rendered.
+    pub(super) fn write_aovs(&self) {
+        let output = &self.outputs[0];
+        for (aov, pixels) in self.aovs.iter().zip(self.aov_pixels.iter()) {
+            let rgb = self.to_rgb(pixels, 0.0);
+            let aov_output = ImageOutput {
+                path: aov_path(&output.path, &aov.name),
+                ..output.clone()
+            };
+            if let Err(err) = write_image_output(
+                &aov_output,
+                &rgb,
+                &self.cropped_pixel_bounds,
+                &self.metadata,
+            ) {
+                error!("Error writing AOV image {}. {}.", aov_output.path, err);
+            }
+        }
+    }
+}
+
+/// Returns the path of an AOV image written alongside an output image.
+///
+/// * `path` - Path of the output image.
+/// * `name` - Name of the AOV.
+pub fn aov_path(path: &str, name: &str) -> String {
+    let p = Path::new(path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let file_name = match p.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{}_{}.{}", stem, name, ext),
+        None => format!("{}_{}", stem, name),
+    };
+    p.with_file_name(file_name).to_string_lossy().into_owned()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aov_path_appends_name_to_stem() {
+        assert_eq!(aov_path("out.exr", "direct"), "out_direct.exr");
+        assert_eq!(aov_path("renders/out.png", "spec"), "renders/out_spec.png");
+        assert_eq!(aov_path("out", "spec"), "out_spec");
+    }
+}
@@ -0,0 +1,502 @@
+//! Auxiliary Channels
+//!
+//! Auxiliary channels hold information about the first surface seen through
+//! each pixel, such as its albedo and shading normal for a denoiser or its
+//! depth and identifiers for compositing, its motion since the previous frame
+//! of a temporal accumulation, and diagnostics of the samples taken in each
+//! pixel for tuning the sampler. They are declared with the
+//! film's `auxiliary` parameter and written alongside the main output as a
+//! multi-channel OpenEXR image `<filename>_aux.exr`.
+
+use super::{aov_path, Film, FilmTile};
+use crate::geometry::*;
+use crate::image_io::*;
+use crate::integrator::PixelVariance;
+use crate::pbrt::*;
+use crate::primitive::PrimitiveIds;
+use crate::spectrum::*;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Auxiliary channels a film can accumulate.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AuxiliaryChannel {
+    /// Directional albedo of the surface's BSDF.
+    Albedo,
+
+    /// Shading normal in world space.
+    Normal,
+
+    /// Distance from the camera to the surface.
+    Depth,
+
+    /// Identifier of the `Shape` directive the surface was created from.
+    PrimitiveId,
+
+    /// Identifier of the surface's material.
+    MaterialId,
+
+    /// Raster space offset from the pixel to where the surface was seen in
+    /// the previous frame of a temporal accumulation.
+    Motion,
+
+    /// Sample variance of the luminance of the pixel's samples.
+    Variance,
+
+    /// Number of samples taken in the pixel.
+    SampleCount,
+}
+
+impl AuxiliaryChannel {
+    /// Parses an auxiliary channel name. Names are case insensitive.
+    ///
+    /// * `name` - One of `albedo`, `normal`, `depth`, `primitiveid`,
+    ///            `materialid`, `motion`, `variance` or `samplecount`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match &name.to_lowercase()[..] {
+            "albedo" => Ok(Self::Albedo),
+            "normal" => Ok(Self::Normal),
+            "depth" => Ok(Self::Depth),
+            "primitiveid" => Ok(Self::PrimitiveId),
+            "materialid" => Ok(Self::MaterialId),
+            "motion" => Ok(Self::Motion),
+            "variance" => Ok(Self::Variance),
+            "samplecount" => Ok(Self::SampleCount),
+            _ => Err(format!("Unknown auxiliary channel '{}'", name)),
+        }
+    }
+
+    /// Returns the names of the image channels written for the auxiliary
+    /// channel.
+    pub fn names(&self) -> &'static [&'static str] {
+        match self {
+            Self::Albedo => &["Albedo.R", "Albedo.G", "Albedo.B"],
+            Self::Normal => &["N.X", "N.Y", "N.Z"],
+            Self::Depth => &["Z"],
+            Self::PrimitiveId => &["PrimitiveID"],
+            Self::MaterialId => &["MaterialID"],
+            Self::Motion => &["Motion.X", "Motion.Y"],
+            Self::Variance => &["Variance"],
+            Self::SampleCount => &["SampleCount"],
+        }
+    }
+
+    /// Returns `true` if the channel describes the first surface seen by the
+    /// camera rays rather than the samples.
+    pub fn is_surface(&self) -> bool {
+        !matches!(self, Self::Variance | Self::SampleCount)
+    }
+}
+
+/// Values of the auxiliary channels for a camera ray.
+#[derive(Copy, Clone, Default)]
+pub struct AuxiliarySample {
+    /// Directional albedo of the surface's BSDF.
+    pub albedo: Spectrum,
+
+    /// Shading normal in world space.
+    pub normal: Normal3f,
+
+    /// Position of the surface in world space.
+    pub p: Point3f,
+
+    /// Distance from the camera to the surface or `None` if the ray didn't
+    /// hit a surface.
+    pub depth: Option<Float>,
+
+    /// Identifiers of the surface.
+    pub ids: PrimitiveIds,
+}
+
+/// Accumulated auxiliary values of a pixel. Albedo is averaged over all
+/// samples; normal, position and depth over the samples that hit a surface. The
+/// identifiers are those of the sample with the largest filter weight since
+/// averaging them is meaningless.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AuxiliaryPixel {
+    /// Sum of weighted albedos.
+    pub albedo: [Float; 3],
+
+    /// Sum of weighted shading normals.
+    pub normal: [Float; 3],
+
+    /// Sum of weighted positions.
+    pub p: [Float; 3],
+
+    /// Sum of weighted depths.
+    pub depth: Float,
+
+    /// Sum of filter weights of all samples.
+    pub weight_sum: Float,
+
+    /// Sum of filter weights of the samples that hit a surface.
+    pub hit_weight_sum: Float,
+
+    /// Identifiers of the sample with the largest filter weight.
+    pub ids: PrimitiveIds,
+
+    /// Filter weight of the sample the identifiers came from.
+    pub ids_weight: Float,
+
+    /// Luminance statistics of the samples taken in the pixel.
+    pub variance: PixelVariance,
+}
+
+impl AuxiliaryPixel {
+    /// Add a sample.
+    ///
+    /// * `sample` - The auxiliary values of the sample.
+    /// * `weight` - Filter weight of the sample.
+    pub fn add(&mut self, sample: &AuxiliarySample, weight: Float) {
+        for i in 0..3 {
+            self.albedo[i] += sample.albedo[i] * weight;
+        }
+        self.weight_sum += weight;
+
+        if let Some(depth) = sample.depth {
+            for i in 0..3 {
+                self.normal[i] += sample.normal[i] * weight;
+                self.p[i] += sample.p[i] * weight;
+            }
+            self.depth += depth * weight;
+            self.hit_weight_sum += weight;
+        }
+
+        if weight > self.ids_weight {
+            self.ids = sample.ids;
+            self.ids_weight = weight;
+        }
+    }
+
+    /// Merge the samples accumulated by another pixel.
+    ///
+    /// * `other` - The other pixel.
+    pub fn merge(&mut self, other: &AuxiliaryPixel) {
+        for i in 0..3 {
+            self.albedo[i] += other.albedo[i];
+            self.normal[i] += other.normal[i];
+            self.p[i] += other.p[i];
+        }
+        self.depth += other.depth;
+        self.weight_sum += other.weight_sum;
+        self.hit_weight_sum += other.hit_weight_sum;
+
+        if other.ids_weight > self.ids_weight {
+            self.ids = other.ids;
+            self.ids_weight = other.ids_weight;
+        }
+        self.variance.merge(&other.variance);
+    }
+
+    /// Returns the final albedo.
+    pub fn albedo(&self) -> [Float; 3] {
+        let inv_wt = safe_inverse(self.weight_sum);
+        [
+            self.albedo[0] * inv_wt,
+            self.albedo[1] * inv_wt,
+            self.albedo[2] * inv_wt,
+        ]
+    }
+
+    /// Returns the final shading normal. It is not renormalized so edges
+    /// between surfaces stay visible.
+    pub fn normal(&self) -> [Float; 3] {
+        let inv_wt = safe_inverse(self.hit_weight_sum);
+        [
+            self.normal[0] * inv_wt,
+            self.normal[1] * inv_wt,
+            self.normal[2] * inv_wt,
+        ]
+    }
+
+    /// Returns the final position or `None` if no sample hit a surface.
+    pub fn p(&self) -> Option<Point3f> {
+        if self.hit_weight_sum != 0.0 {
+            let inv_wt = 1.0 / self.hit_weight_sum;
+            Some(Point3f::new(
+                self.p[0] * inv_wt,
+                self.p[1] * inv_wt,
+                self.p[2] * inv_wt,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the final depth or 0 if no sample hit a surface.
+    pub fn depth(&self) -> Float {
+        self.depth * safe_inverse(self.hit_weight_sum)
+    }
+}
+
+/// Returns `1 / w` or 0 if `w` is 0.
+///
+/// * `w` - Sum of filter weights.
+fn safe_inverse(w: Float) -> Float {
+    if w != 0.0 {
+        1.0 / w
+    } else {
+        0.0
+    }
+}
+
+impl FilmTile {
+    /// Returns whether the tile accumulates auxiliary channels.
+    pub fn has_auxiliary(&self) -> bool {
+        !self.aux_pixels.is_empty()
+    }
+
+    /// Returns whether the tile accumulates auxiliary channels that need the
+    /// first surface seen by the camera rays.
+    pub fn has_surface_auxiliary(&self) -> bool {
+        self.aux_channels.iter().any(|c| c.is_surface())
+    }
+
+    /// Add the auxiliary values of a camera ray for a sample.
+    ///
+    /// * `p_film`        - Point on film.
+    /// * `sample`        - The auxiliary values.
+    /// * `sample_weight` - Weight for the sample's contribution. Samples with
+    ///                     no weight are ignored.
+    pub fn add_auxiliary(
+        &mut self,
+        p_film: Point2f,
+        sample: &AuxiliarySample,
+        sample_weight: Float,
+    ) {
+        if sample_weight == 0.0 {
+            return;
+        }
+        for (pixel_offset, filter_weight) in self.filter_footprint(p_film) {
+            self.aux_pixels[pixel_offset].add(sample, filter_weight);
+        }
+    }
+
+    /// Record the luminance statistics of the samples taken in a pixel.
+    /// Pixels outside the tile's pixel bounds are ignored.
+    ///
+    /// * `pixel`    - The pixel with respect to the overall image.
+    /// * `variance` - Luminance statistics of the samples.
+    pub fn add_pixel_variance(&mut self, pixel: &Point2i, variance: &PixelVariance) {
+        if self.has_auxiliary() && self.get_pixel_bounds().contains_exclusive(pixel) {
+            let offset = self.get_pixel_offset(pixel);
+            self.aux_pixels[offset].variance.merge(variance);
+        }
+    }
+}
+
+impl Film {
+    /// Set the auxiliary channels to accumulate.
+    ///
+    /// * `channels` - The auxiliary channels.
+    pub fn set_auxiliary(&mut self, channels: Vec<AuxiliaryChannel>) {
+        let n = if channels.is_empty() {
+            0
+        } else {
+            self.cropped_pixel_bounds.area() as usize
+        };
+        self.aux_pixels = vec![AuxiliaryPixel::default(); n];
+        self.auxiliary = Arc::new(channels);
+    }
+
+    /// Keep the luminance statistics of the pixels across passes for
+    /// adaptive sampling even if no auxiliary channels are written.
+    pub fn track_pixel_variance(&mut self) {
+        if self.aux_pixels.is_empty() {
+            let n = self.cropped_pixel_bounds.area() as usize;
+            self.aux_pixels = vec![AuxiliaryPixel::default(); n];
+        }
+    }
+
+    /// Returns the luminance statistics of the samples merged into a pixel.
+    /// Pixels are empty unless auxiliary channels are written or the pixel
+    /// variance is tracked.
+    ///
+    /// * `pixel` - The pixel coordinates with respect to the overall image.
+    pub fn pixel_variance(&self, pixel: &Point2i) -> PixelVariance {
+        if self.aux_pixels.is_empty() || !self.cropped_pixel_bounds.contains_exclusive(pixel) {
+            PixelVariance::default()
+        } else {
+            self.aux_pixels[self.get_pixel_offset(pixel)].variance
+        }
+    }
+
+    /// Merge the `FilmTile`'s auxiliary values into the film.
+    ///
+    /// * `tile` - The `FilmTile` to merge.
+    pub(super) fn merge_auxiliary_tile(&mut self, tile: &FilmTile) {
+        if self.aux_pixels.is_empty() || !tile.has_auxiliary() {
+            return;
+        }
+        for pixel in tile.get_pixel_bounds() {
+            let tile_pixel = tile.get_pixel_offset(&pixel);
+            let merge_pixel = self.get_pixel_offset(&pixel);
+            self.aux_pixels[merge_pixel].merge(&tile.aux_pixels[tile_pixel]);
+        }
+    }
+
+    /// Write the auxiliary channels as a multi-channel OpenEXR image next to
+    /// the main output.
+    pub(super) fn write_auxiliary(&self) {
+        if self.auxiliary.is_empty() {
+            return;
+        }
+
+        let path = aov_path(&self.outputs[0].path, "aux");
+        let path = Path::new(&path).with_extension("exr");
+        let path = path.to_string_lossy();
+
+        let mut channels = vec![];
+        for channel in self.auxiliary.iter() {
+            for (i, name) in channel.names().iter().enumerate() {
+                let samples = match channel {
+                    AuxiliaryChannel::Albedo => {
+                        ChannelSamples::F32(self.aux_pixels.iter().map(|p| p.albedo()[i]).collect())
+                    }
+                    AuxiliaryChannel::Normal => {
+                        ChannelSamples::F32(self.aux_pixels.iter().map(|p| p.normal()[i]).collect())
+                    }
+                    AuxiliaryChannel::Depth => {
+                        ChannelSamples::F32(self.aux_pixels.iter().map(|p| p.depth()).collect())
+                    }
+                    AuxiliaryChannel::PrimitiveId => {
+                        ChannelSamples::U32(self.aux_pixels.iter().map(|p| p.ids.shape).collect())
+                    }
+                    AuxiliaryChannel::MaterialId => ChannelSamples::U32(
+                        self.aux_pixels.iter().map(|p| p.ids.material).collect(),
+                    ),
+                    AuxiliaryChannel::Motion => {
+                        ChannelSamples::F32(self.motion_vectors().iter().map(|m| m[i]).collect())
+                    }
+                    AuxiliaryChannel::Variance => ChannelSamples::F32(
+                        self.aux_pixels
+                            .iter()
+                            .map(|p| p.variance.variance())
+                            .collect(),
+                    ),
+                    AuxiliaryChannel::SampleCount => ChannelSamples::U32(
+                        self.aux_pixels
+                            .iter()
+                            .map(|p| p.variance.n as u32)
+                            .collect(),
+                    ),
+                };
+                channels.push((String::from(*name), samples));
+            }
+        }
+
+        if let Err(err) =
+            write_exr_channels(&path, channels, &self.cropped_pixel_bounds, &self.metadata)
+        {
+            error!("Error writing auxiliary image {}. {}.", path, err);
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::*;
+
+    /// A box filter with half a pixel radius.
+    struct BoxFilter(FilterData);
+
+    impl Filter for BoxFilter {
+        fn get_data(&self) -> &FilterData {
+            &self.0
+        }
+
+        fn evaluate(&self, _p: &Point2f) -> Float {
+            1.0
+        }
+    }
+
+    #[test]
+    fn parse_auxiliary_channel() {
+        assert_eq!(
+            AuxiliaryChannel::parse("Albedo"),
+            Ok(AuxiliaryChannel::Albedo)
+        );
+        assert_eq!(
+            AuxiliaryChannel::parse("primitiveid"),
+            Ok(AuxiliaryChannel::PrimitiveId)
+        );
+        assert!(!AuxiliaryChannel::parse("samplecount").unwrap().is_surface());
+        assert!(AuxiliaryChannel::parse("Motion").unwrap().is_surface());
+        assert!(AuxiliaryChannel::parse("velocity").is_err());
+    }
+
+    #[test]
+    fn auxiliary_pixel_averages_hits() {
+        let hit = AuxiliarySample {
+            albedo: Spectrum::new(0.5),
+            normal: Normal3f::new(0.0, 0.0, 1.0),
+            p: Point3f::new(1.0, 0.0, 2.0),
+            depth: Some(2.0),
+            ids: PrimitiveIds {
+                shape: 3,
+                material: 1,
+            },
+        };
+        let miss = AuxiliarySample::default();
+
+        let mut a = AuxiliaryPixel::default();
+        a.add(&hit, 0.25);
+        let mut b = AuxiliaryPixel::default();
+        b.add(&miss, 0.75);
+        a.merge(&b);
+
+        assert_eq!(a.albedo(), [0.125, 0.125, 0.125]);
+        assert_eq!(a.normal(), [0.0, 0.0, 1.0]);
+        assert_eq!(a.p(), Some(Point3f::new(1.0, 0.0, 2.0)));
+        assert_eq!(a.depth(), 2.0);
+        assert_eq!(a.ids, PrimitiveIds::default());
+        assert_eq!(AuxiliaryPixel::default().depth(), 0.0);
+        assert_eq!(AuxiliaryPixel::default().p(), None);
+    }
+
+    #[test]
+    fn film_keeps_pixel_variance_across_tiles() {
+        let filter = Arc::new(BoxFilter(FilterData::new(Vector2f::new(0.5, 0.5))));
+        let mut film = Film::new(
+            &Point2i::new(4, 3),
+            &Bounds2f::new(Point2f::new(0.0, 0.0), Point2f::new(1.0, 1.0)),
+            filter,
+            35.0,
+            "",
+            None,
+            None,
+        );
+        let pixel = Point2i::new(1, 2);
+        let sample_bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(4, 3));
+
+        // Without auxiliary channels the statistics aren't kept.
+        let mut tile = film.get_film_tile(sample_bounds);
+        assert!(!tile.has_auxiliary());
+        let mut variance = PixelVariance::default();
+        variance.add(1.0);
+        tile.add_pixel_variance(&pixel, &variance);
+        film.merge_film_tile(&tile);
+        assert_eq!(film.pixel_variance(&pixel), PixelVariance::default());
+
+        film.track_pixel_variance();
+        let mut all = PixelVariance::default();
+        for y in [1.0, 3.0].iter() {
+            let mut tile = film.get_film_tile(sample_bounds);
+            assert!(tile.has_auxiliary());
+            assert!(!tile.has_surface_auxiliary());
+            let mut variance = PixelVariance::default();
+            variance.add(*y);
+            all.add(*y);
+            tile.add_pixel_variance(&pixel, &variance);
+            film.merge_film_tile(&tile);
+        }
+        assert_eq!(film.pixel_variance(&pixel), all);
+        assert_eq!(film.pixel_variance(&Point2i::new(0, 0)).n, 0);
+        assert_eq!(film.pixel_variance(&Point2i::new(4, 0)).n, 0);
+    }
+}
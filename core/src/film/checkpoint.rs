@@ -0,0 +1,402 @@
+//! Render Checkpoints
+//!
+//! A checkpoint stores the film's accumulated pixels with the progress of the
+//! render so that an interrupted render can continue where it stopped. It is
+//! written next to the output image as `<filename>.checkpoint`.
+
+use super::{AuxiliaryPixel, Film, Pixel};
+use crate::geometry::*;
+use crate::integrator::PixelVariance;
+use crate::pbrt::*;
+use crate::primitive::PrimitiveIds;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs;
+use std::io::{Cursor, Read};
+
+/// Identifies a checkpoint file and its version.
+const MAGIC: &[u8; 8] = b"PBRCKPT4";
+
+/// Progress of a render stored in a checkpoint.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderCheckpoint {
+    /// The pass in progress starting at 0.
+    pub pass: usize,
+
+    /// Samples per pixel taken by the completed passes.
+    pub samples_per_pixel: usize,
+
+    /// Sample bounds of the tiles of the pass in progress that have been
+    /// merged into the film.
+    pub done_tiles: Vec<Bounds2i>,
+}
+
+impl Film {
+    /// Returns the path of the film's checkpoint file.
+    pub fn checkpoint_path(&self) -> String {
+        format!("{}.checkpoint", self.filename)
+    }
+
+    /// Write the accumulated pixels and the progress of the render to the
+    /// checkpoint file. The previous checkpoint is only replaced once the new
+    /// one is complete.
+    ///
+    /// * `checkpoint` - The progress of the render.
+    pub fn write_checkpoint(&self, checkpoint: &RenderCheckpoint) -> Result<(), String> {
+        let path = self.checkpoint_path();
+        let tmp_path = format!("{}.tmp", path);
+        let bytes = self.checkpoint_bytes(checkpoint);
+        fs::write(&tmp_path, bytes)
+            .and_then(|_| fs::rename(&tmp_path, &path))
+            .map_err(|err| format!("Error writing checkpoint {}. {}", path, err))?;
+        info!(
+            "Checkpoint written to {} in pass {} with {} tiles done.",
+            path,
+            checkpoint.pass,
+            checkpoint.done_tiles.len()
+        );
+        Ok(())
+    }
+
+    /// Restore the accumulated pixels from the checkpoint file and return the
+    /// progress of the render. The film is left unchanged if the checkpoint
+    /// can't be read or was written for a different film.
+    pub fn read_checkpoint(&mut self) -> Result<RenderCheckpoint, String> {
+        let path = self.checkpoint_path();
+        let bytes =
+            fs::read(&path).map_err(|err| format!("Error reading checkpoint {}. {}", path, err))?;
+        self.restore_checkpoint_bytes(&bytes)
+            .map_err(|err| format!("Invalid checkpoint {}. {}", path, err))
+    }
+
+    /// Delete the checkpoint file once the render is complete.
+    pub fn remove_checkpoint(&self) {
+        let path = self.checkpoint_path();
+        if fs::metadata(&path).is_ok() {
+            if let Err(err) = fs::remove_file(&path) {
+                warn!("Unable to remove checkpoint {}. {}.", path, err);
+            }
+        }
+    }
+
+    /// Returns the serialized checkpoint.
+    ///
+    /// * `checkpoint` - The progress of the render.
+    fn checkpoint_bytes(&self, checkpoint: &RenderCheckpoint) -> Vec<u8> {
+        let n_pixels = self.pixels.len() * (1 + self.aov_pixels.len());
+        let mut bytes = Vec::with_capacity(
+            64 + 16 * checkpoint.done_tiles.len() + 56 * n_pixels + 72 * self.aux_pixels.len(),
+        );
+        bytes.extend_from_slice(MAGIC);
+
+        // Writing to a `Vec` can't fail.
+        let b = &self.cropped_pixel_bounds;
+        for v in [b.p_min.x, b.p_min.y, b.p_max.x, b.p_max.y].iter() {
+            bytes.write_i32::<LittleEndian>(*v).unwrap();
+        }
+        bytes
+            .write_u32::<LittleEndian>(self.aov_pixels.len() as u32)
+            .unwrap();
+        bytes
+            .write_u32::<LittleEndian>(self.aux_pixels.len() as u32)
+            .unwrap();
+        bytes
+            .write_u64::<LittleEndian>(checkpoint.pass as u64)
+            .unwrap();
+        bytes
+            .write_u64::<LittleEndian>(checkpoint.samples_per_pixel as u64)
+            .unwrap();
+        bytes
+            .write_u64::<LittleEndian>(checkpoint.done_tiles.len() as u64)
+            .unwrap();
+        for t in checkpoint.done_tiles.iter() {
+            for v in [t.p_min.x, t.p_min.y, t.p_max.x, t.p_max.y].iter() {
+                bytes.write_i32::<LittleEndian>(*v).unwrap();
+            }
+        }
+
+        let pixels = self.aov_pixels.iter().flatten();
+        for p in self.pixels.iter().chain(pixels) {
+            for v in pixel_values(p).iter() {
+                bytes.write_f32::<LittleEndian>(*v).unwrap();
+            }
+        }
+        for p in self.aux_pixels.iter() {
+            for v in aux_pixel_values(p).iter() {
+                bytes.write_f32::<LittleEndian>(*v).unwrap();
+            }
+        }
+        bytes
+    }
+
+    /// Restore the pixels from a serialized checkpoint and return the
+    /// progress of the render.
+    ///
+    /// * `bytes` - The serialized checkpoint.
+    fn restore_checkpoint_bytes(&mut self, bytes: &[u8]) -> Result<RenderCheckpoint, String> {
+        let err = |e: std::io::Error| format!("{}", e);
+        let mut r = Cursor::new(bytes);
+
+        let mut magic = [0_u8; 8];
+        r.read_exact(&mut magic).map_err(err)?;
+        if &magic != MAGIC {
+            return Err(String::from("Not a checkpoint file"));
+        }
+
+        let mut b = [0_i32; 4];
+        r.read_i32_into::<LittleEndian>(&mut b).map_err(err)?;
+        let bounds = Bounds2i::new(Point2i::new(b[0], b[1]), Point2i::new(b[2], b[3]));
+        if bounds != self.cropped_pixel_bounds {
+            return Err(format!(
+                "Pixel bounds {} don't match the film's {}",
+                bounds, self.cropped_pixel_bounds
+            ));
+        }
+        let n_aovs = r.read_u32::<LittleEndian>().map_err(err)? as usize;
+        if n_aovs != self.aov_pixels.len() {
+            return Err(format!(
+                "{} AOVs don't match the film's {}",
+                n_aovs,
+                self.aov_pixels.len()
+            ));
+        }
+        let n_aux_pixels = r.read_u32::<LittleEndian>().map_err(err)? as usize;
+        if n_aux_pixels != self.aux_pixels.len() {
+            return Err(String::from("Auxiliary channels don't match the film's"));
+        }
+
+        let pass = r.read_u64::<LittleEndian>().map_err(err)? as usize;
+        let samples_per_pixel = r.read_u64::<LittleEndian>().map_err(err)? as usize;
+        let n_tiles = r.read_u64::<LittleEndian>().map_err(err)? as usize;
+        let mut done_tiles = Vec::with_capacity(min(n_tiles, bytes.len() / 16));
+        for _ in 0..n_tiles {
+            r.read_i32_into::<LittleEndian>(&mut b).map_err(err)?;
+            done_tiles.push(Bounds2i::new(
+                Point2i::new(b[0], b[1]),
+                Point2i::new(b[2], b[3]),
+            ));
+        }
+
+        let mut values = vec![0.0; 14 * self.pixels.len() * (1 + n_aovs)];
+        r.read_f32_into::<LittleEndian>(&mut values).map_err(err)?;
+        let mut aux_values = vec![0.0; 18 * n_aux_pixels];
+        r.read_f32_into::<LittleEndian>(&mut aux_values)
+            .map_err(err)?;
+        if (r.position() as usize) < bytes.len() {
+            return Err(String::from("Unexpected data after the pixels"));
+        }
+
+        let mut chunks = values.chunks_exact(14);
+        let pixels = self.aov_pixels.iter_mut().flatten();
+        for (p, v) in self.pixels.iter_mut().chain(pixels).zip(&mut chunks) {
+            *p = pixel_from_values(v);
+        }
+        for (p, v) in self.aux_pixels.iter_mut().zip(aux_values.chunks_exact(18)) {
+            *p = aux_pixel_from_values(v);
+        }
+        self.is_preview = false;
+
+        Ok(RenderCheckpoint {
+            pass,
+            samples_per_pixel,
+            done_tiles,
+        })
+    }
+}
+
+/// Returns the accumulated values of a pixel.
+///
+/// * `p` - The pixel.
+fn pixel_values(p: &Pixel) -> [Float; 14] {
+    [
+        p.xyz[0],
+        p.xyz[1],
+        p.xyz[2],
+        p.filter_weight_sum,
+        p.splat_xyz[0],
+        p.splat_xyz[1],
+        p.splat_xyz[2],
+        p.xyz_err[0],
+        p.xyz_err[1],
+        p.xyz_err[2],
+        p.filter_weight_err,
+        p.splat_err[0],
+        p.splat_err[1],
+        p.splat_err[2],
+    ]
+}
+
+/// Returns a pixel from its accumulated values.
+///
+/// * `v` - The values returned by `pixel_values()`.
+fn pixel_from_values(v: &[Float]) -> Pixel {
+    Pixel {
+        xyz: [v[0], v[1], v[2]],
+        filter_weight_sum: v[3],
+        splat_xyz: [v[4], v[5], v[6]],
+        xyz_err: [v[7], v[8], v[9]],
+        filter_weight_err: v[10],
+        splat_err: [v[11], v[12], v[13]],
+        ..Pixel::default()
+    }
+}
+
+/// Returns the accumulated values of an auxiliary pixel. Identifiers and the
+/// sample count are stored bit for bit.
+///
+/// * `p` - The auxiliary pixel.
+fn aux_pixel_values(p: &AuxiliaryPixel) -> [Float; 18] {
+    [
+        p.albedo[0],
+        p.albedo[1],
+        p.albedo[2],
+        p.normal[0],
+        p.normal[1],
+        p.normal[2],
+        p.p[0],
+        p.p[1],
+        p.p[2],
+        p.depth,
+        p.weight_sum,
+        p.hit_weight_sum,
+        Float::from_bits(p.ids.shape),
+        Float::from_bits(p.ids.material),
+        p.ids_weight,
+        Float::from_bits(p.variance.n as u32),
+        p.variance.mean,
+        p.variance.m2,
+    ]
+}
+
+/// Returns an auxiliary pixel from its accumulated values.
+///
+/// * `v` - The values returned by `aux_pixel_values()`.
+fn aux_pixel_from_values(v: &[Float]) -> AuxiliaryPixel {
+    AuxiliaryPixel {
+        albedo: [v[0], v[1], v[2]],
+        normal: [v[3], v[4], v[5]],
+        p: [v[6], v[7], v[8]],
+        depth: v[9],
+        weight_sum: v[10],
+        hit_weight_sum: v[11],
+        ids: PrimitiveIds {
+            shape: v[12].to_bits(),
+            material: v[13].to_bits(),
+        },
+        ids_weight: v[14],
+        variance: PixelVariance {
+            n: v[15].to_bits() as usize,
+            mean: v[16],
+            m2: v[17],
+        },
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::*;
+    use crate::spectrum::*;
+    use std::sync::Arc;
+
+    /// A box filter with half a pixel radius.
+    struct BoxFilter(FilterData);
+
+    impl Filter for BoxFilter {
+        fn get_data(&self) -> &FilterData {
+            &self.0
+        }
+
+        fn evaluate(&self, _p: &Point2f) -> Float {
+            1.0
+        }
+    }
+
+    fn film(filename: &str) -> Film {
+        let filter = Arc::new(BoxFilter(FilterData::new(Vector2f::new(0.5, 0.5))));
+        Film::new(
+            &Point2i::new(4, 3),
+            &Bounds2f::new(Point2f::new(0.0, 0.0), Point2f::new(1.0, 1.0)),
+            filter,
+            35.0,
+            filename,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let dir = std::env::temp_dir();
+        let filename = dir.join("checkpoint_round_trip.exr");
+        let filename = filename.to_str().unwrap();
+
+        let mut a = film(filename);
+        let sample_bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 2));
+        let mut tile = a.get_film_tile(sample_bounds);
+        tile.add_sample(Point2f::new(1.5, 0.5), Spectrum::new(2.0), 1.0);
+        a.merge_film_tile(&tile);
+        a.add_splat(&Point2f::new(3.5, 2.5), &Spectrum::new(1.0));
+
+        let checkpoint = RenderCheckpoint {
+            pass: 2,
+            samples_per_pixel: 16,
+            done_tiles: vec![sample_bounds],
+        };
+        a.write_checkpoint(&checkpoint).unwrap();
+
+        let mut b = film(filename);
+        assert_eq!(b.read_checkpoint().unwrap(), checkpoint);
+        assert_eq!(b.to_rgb(&b.pixels, 1.0), a.to_rgb(&a.pixels, 1.0));
+
+        a.remove_checkpoint();
+        assert!(b.read_checkpoint().is_err());
+    }
+
+    #[test]
+    fn tile_splats_are_checkpointed_with_the_tile() {
+        let dir = std::env::temp_dir();
+        let filename = dir.join("tile_splats_are_checkpointed_with_the_tile.exr");
+        let filename = filename.to_str().unwrap();
+
+        // Tiles in flight are rendered again on resume so their splats must
+        // only reach the film when the tile is merged.
+        let mut a = film(filename);
+        let empty = a.checkpoint_bytes(&RenderCheckpoint::default());
+        let sample_bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 2));
+        let mut tile = a.get_film_tile(sample_bounds);
+        tile.add_splat(Point2f::new(3.5, 2.5), Spectrum::new(1.0));
+        assert_eq!(a.checkpoint_bytes(&RenderCheckpoint::default()), empty);
+
+        a.merge_film_tile(&tile);
+        let mut b = film(filename);
+        b.add_splat(&Point2f::new(3.5, 2.5), &Spectrum::new(1.0));
+        assert_eq!(
+            a.checkpoint_bytes(&RenderCheckpoint::default()),
+            b.checkpoint_bytes(&RenderCheckpoint::default())
+        );
+    }
+
+    #[test]
+    fn checkpoint_must_match_film() {
+        let dir = std::env::temp_dir();
+        let filename = dir.join("checkpoint_must_match_film.exr");
+        let filename = filename.to_str().unwrap();
+
+        let a = film(filename);
+        let bytes = a.checkpoint_bytes(&RenderCheckpoint::default());
+
+        let mut b = film(filename);
+        b.cropped_pixel_bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 2));
+        assert!(b.restore_checkpoint_bytes(&bytes).is_err());
+
+        let mut c = film(filename);
+        assert!(c
+            .restore_checkpoint_bytes(&bytes[..bytes.len() - 1])
+            .is_err());
+        assert!(c.restore_checkpoint_bytes(&bytes).is_ok());
+    }
+}
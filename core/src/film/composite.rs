@@ -0,0 +1,159 @@
+//! Region of Interest Compositing
+//!
+//! When only part of a frame changed, the crop window can be rendered by
+//! itself and composited into the previously rendered image. The crop window
+//! is grown by the reconstruction filter's radius so that every pixel whose
+//! filter footprint overlaps the changed region is rendered again and no seam
+//! is left where stale pixels meet new ones.
+
+use super::Film;
+use crate::geometry::*;
+use crate::image_io::*;
+use crate::pbrt::*;
+use crate::spectrum::*;
+
+impl Film {
+    /// Render the pixels affected by the crop window and composite them into
+    /// an existing image when writing the output.
+    ///
+    /// * `path` - Path to the previously rendered image.
+    pub fn set_composite(&mut self, path: &str) {
+        let radius = self.filter.get_data().radius;
+        self.cropped_pixel_bounds =
+            composite_bounds(&self.cropped_pixel_bounds, &radius, &self.full_resolution);
+        self.pixels = vec![Default::default(); self.cropped_pixel_bounds.area() as usize];
+        self.composite = Some(String::from(path));
+    }
+
+    /// Returns the pixels of the output image and their bounds. The rendered
+    /// pixels are composited into the existing image if one was given and
+    /// written by themselves if it cannot be read.
+    ///
+    /// * `rgb` - RGB values of the rendered pixels.
+    pub(super) fn composite(&self, rgb: Vec<Float>) -> (Vec<Float>, Bounds2i) {
+        let path = match self.composite.as_ref() {
+            Some(path) => path,
+            None => return (rgb, self.cropped_pixel_bounds),
+        };
+
+        let result = read_image(path).and_then(|image| {
+            composite_rgb(
+                &image,
+                &rgb,
+                &self.cropped_pixel_bounds,
+                &self.full_resolution,
+            )
+        });
+        match result {
+            Ok(full) => {
+                let full_bounds = Bounds2i::new(Point2i::new(0, 0), self.full_resolution);
+                (full, full_bounds)
+            }
+            Err(err) => {
+                error!(
+                    "Unable to composite into '{}'. {}. Writing the crop window only.",
+                    path, err
+                );
+                (rgb, self.cropped_pixel_bounds)
+            }
+        }
+    }
+}
+
+/// Returns the pixel bounds to render so that every pixel whose filter
+/// footprint overlaps a changed region is included. A sample affects pixels
+/// whose centres are within the filter radius so the region grows by the
+/// radius rounded to the nearest pixel.
+///
+/// * `region`          - Pixel bounds of the changed region.
+/// * `filter_radius`   - Radius of the reconstruction filter.
+/// * `full_resolution` - Resolution of the image.
+pub fn composite_bounds(
+    region: &Bounds2i,
+    filter_radius: &Vector2f,
+    full_resolution: &Point2i,
+) -> Bounds2i {
+    let grow = Vector2i::new(
+        (filter_radius.x + 0.5).floor() as Int,
+        (filter_radius.y + 0.5).floor() as Int,
+    );
+    let image_bounds = Bounds2i::new(Point2i::new(0, 0), *full_resolution);
+    Bounds2i::new(region.p_min - grow, region.p_max + grow).intersect(&image_bounds)
+}
+
+/// Returns the RGB values of an image with the pixels in the given bounds
+/// replaced.
+///
+/// * `image`           - The existing image.
+/// * `rgb`             - RGB values of the pixels to replace.
+/// * `bounds`          - Pixel bounds of `rgb`.
+/// * `full_resolution` - Expected resolution of the image.
+pub fn composite_rgb(
+    image: &RGBImage,
+    rgb: &[Float],
+    bounds: &Bounds2i,
+    full_resolution: &Point2i,
+) -> Result<Vec<Float>, String> {
+    let width = image.resolution.x;
+    if width as Int != full_resolution.x || image.resolution.y as Int != full_resolution.y {
+        return Err(format!(
+            "Image resolution {}x{} doesn't match the film resolution {}x{}",
+            width, image.resolution.y, full_resolution.x, full_resolution.y
+        ));
+    }
+
+    let mut full: Vec<Float> = image.pixels.iter().flat_map(|p| p.to_rgb()).collect();
+    let region_width = (bounds.p_max.x - bounds.p_min.x) as usize;
+    for p in *bounds {
+        let src =
+            3 * ((p.y - bounds.p_min.y) as usize * region_width + (p.x - bounds.p_min.x) as usize);
+        let dst = 3 * (p.y as usize * width + p.x as usize);
+        full[dst..dst + 3].copy_from_slice(&rgb[src..src + 3]);
+    }
+    Ok(full)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composite_bounds_cover_filter_footprint() {
+        let region = Bounds2i::new(Point2i::new(4, 4), Point2i::new(8, 6));
+        let resolution = Point2i::new(10, 10);
+
+        // Pixel centres within 2 pixels of the region for a radius of 2.
+        let bounds = composite_bounds(&region, &Vector2f::new(2.0, 1.5), &resolution);
+        assert_eq!(bounds.p_min, Point2i::new(2, 2));
+        assert_eq!(bounds.p_max, Point2i::new(10, 8));
+
+        // A box filter only affects the pixels it is centred on but a
+        // sample on the edge between two pixels counts towards both.
+        let bounds = composite_bounds(&region, &Vector2f::new(0.5, 0.5), &resolution);
+        assert_eq!(bounds.p_min, Point2i::new(3, 3));
+        assert_eq!(bounds.p_max, Point2i::new(9, 7));
+    }
+
+    #[test]
+    fn composite_rgb_replaces_region() {
+        let image = RGBImage {
+            pixels: vec![RGBSpectrum::new(1.0); 12],
+            resolution: Point2::new(4, 3),
+        };
+        let bounds = Bounds2i::new(Point2i::new(1, 1), Point2i::new(3, 2));
+        let rgb = [2.0, 2.0, 2.0, 3.0, 3.0, 3.0];
+
+        let full = composite_rgb(&image, &rgb, &bounds, &Point2i::new(4, 3)).unwrap();
+        let red: Vec<Float> = full.iter().step_by(3).copied().collect();
+        assert_eq!(
+            red,
+            vec![1.0, 1.0, 1.0, 1.0, 1.0, 2.0, 3.0, 1.0, 1.0, 1.0, 1.0, 1.0]
+        );
+
+        assert!(composite_rgb(&image, &rgb, &bounds, &Point2i::new(3, 4)).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! Display Server
+//!
+//! Streams the image to a running tev viewer over its TCP IPC protocol so a
+//! render can be monitored while it progresses. Every packet starts with its
+//! total length as a little endian `u32` followed by a packet type byte;
+//! strings are null terminated and numbers are little endian.
+
+use super::Film;
+use crate::geometry::*;
+use crate::integrator::RENDER_PROGRESS;
+use crate::pbrt::*;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+
+/// tev packet type closing an image.
+const CLOSE_IMAGE: u8 = 2;
+
+/// tev packet type creating an empty image.
+const CREATE_IMAGE: u8 = 4;
+
+/// tev packet type updating a region of an image with interleaved channels.
+const UPDATE_IMAGE_V3: u8 = 6;
+
+/// Names of the channels sent to the viewer.
+const CHANNELS: [&str; 3] = ["R", "G", "B"];
+
+/// Connection to a tev viewer. Packets are written by a background thread so
+/// sending a tile never waits on the network.
+pub struct DisplayServer {
+    /// Name of the image in the viewer.
+    name: String,
+
+    /// Queue of packets for the background thread.
+    sender: Sender<Vec<u8>>,
+}
+
+impl DisplayServer {
+    /// Connects to a tev viewer and creates an empty image replacing any
+    /// image of the same name.
+    ///
+    /// * `address`    - Address and port of the viewer (e.g. localhost:14158).
+    /// * `name`       - Name of the image in the viewer.
+    /// * `resolution` - Resolution of the image.
+    pub fn connect(address: &str, name: &str, resolution: &Point2i) -> Result<Self, String> {
+        let mut stream = TcpStream::connect(address)
+            .map_err(|err| format!("Unable to connect to display server {}. {}", address, err))?;
+
+        let (sender, receiver) = channel::<Vec<u8>>();
+        let address = String::from(address);
+        thread::spawn(move || {
+            for packet in receiver.iter() {
+                if let Err(err) = stream.write_all(&packet) {
+                    warn!("Lost connection to display server {}. {}.", address, err);
+                    break;
+                }
+            }
+        });
+
+        let display = Self {
+            name: String::from(name),
+            sender,
+        };
+        display.send(close_image_packet(name));
+        display.send(create_image_packet(name, resolution, &CHANNELS));
+        Ok(display)
+    }
+
+    /// Sends the RGB values of a region of the image.
+    ///
+    /// * `bounds` - The region relative to the image origin.
+    /// * `rgb`    - The RGB values of the region in row major order.
+    pub fn update(&self, bounds: &Bounds2i, rgb: &[Float]) {
+        self.send(update_image_packet(&self.name, &CHANNELS, bounds, rgb));
+    }
+
+    /// Queues a packet. Packets are dropped once the connection is lost.
+    ///
+    /// * `packet` - The packet.
+    fn send(&self, packet: Vec<u8>) {
+        let _ = self.sender.send(packet);
+    }
+}
+
+impl Film {
+    /// Connects to a tev viewer and streams the image to it as tiles are
+    /// merged. The viewer image covers the cropped pixel bounds.
+    ///
+    /// * `address` - Address and port of the viewer.
+    pub fn set_display_server(&mut self, address: &str) {
+        let resolution = Point2i::from(self.cropped_pixel_bounds.diagonal());
+        match DisplayServer::connect(address, &self.filename, &resolution) {
+            Ok(display) => self.display = Some(Arc::new(display)),
+            Err(err) => warn!("{}. Not displaying the image.", err),
+        }
+    }
+
+    /// Sends the current values of a region of the image to the viewer, if
+    /// any, and to the render progress snapshot when rendering progressively.
+    /// Splats are only sent with the final image because their scale is not
+    /// known until the end of a pass.
+    ///
+    /// * `bounds`      - The region in the overall image.
+    /// * `splat_scale` - Scale factor for `add_splat()`.
+    pub(super) fn update_display(&self, bounds: &Bounds2i, splat_scale: Float) {
+        let progressive = RENDER_PROGRESS.is_progressive();
+        if self.display.is_none() && !progressive {
+            return;
+        }
+
+        let mut rgb = Vec::with_capacity(3 * bounds.area() as usize);
+        for p in *bounds {
+            let pixel = &self.pixels[self.get_pixel_offset(&p)];
+            rgb.extend_from_slice(&self.pixel_rgb(pixel, splat_scale));
+        }
+        let offset = Vector2i::from(self.cropped_pixel_bounds.p_min);
+        let bounds = Bounds2i::new(bounds.p_min - offset, bounds.p_max - offset);
+
+        if let Some(display) = self.display.as_ref() {
+            display.update(&bounds, &rgb);
+        }
+        if progressive {
+            let resolution = Point2i::from(self.cropped_pixel_bounds.diagonal());
+            RENDER_PROGRESS.update_snapshot(&resolution, &bounds, &rgb);
+        }
+    }
+}
+
+/// Returns a tev packet with the packet type and length header.
+///
+/// * `packet_type` - The packet type.
+/// * `payload`     - The packet contents.
+fn packet(packet_type: u8, payload: &[u8]) -> Vec<u8> {
+    let length = (payload.len() + 5) as u32;
+    let mut bytes = Vec::with_capacity(length as usize);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.push(packet_type);
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// Appends a null terminated string to a packet.
+///
+/// * `bytes` - The packet contents.
+/// * `s`     - The string.
+fn push_str(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend_from_slice(s.as_bytes());
+    bytes.push(0);
+}
+
+/// Returns a packet closing the named image.
+///
+/// * `name` - Name of the image.
+pub fn close_image_packet(name: &str) -> Vec<u8> {
+    let mut payload = vec![];
+    push_str(&mut payload, name);
+    packet(CLOSE_IMAGE, &payload)
+}
+
+/// Returns a packet creating an empty image with the given channels.
+///
+/// * `name`       - Name of the image.
+/// * `resolution` - Resolution of the image.
+/// * `channels`   - Names of the channels.
+pub fn create_image_packet(name: &str, resolution: &Point2i, channels: &[&str]) -> Vec<u8> {
+    let mut payload = vec![1]; // Grab focus.
+    push_str(&mut payload, name);
+    payload.extend_from_slice(&resolution.x.to_le_bytes());
+    payload.extend_from_slice(&resolution.y.to_le_bytes());
+    payload.extend_from_slice(&(channels.len() as i32).to_le_bytes());
+    for channel in channels.iter() {
+        push_str(&mut payload, channel);
+    }
+    packet(CREATE_IMAGE, &payload)
+}
+
+/// Returns a packet updating a region of an image.
+///
+/// * `name`     - Name of the image.
+/// * `channels` - Names of the channels.
+/// * `bounds`   - The region relative to the image origin.
+/// * `values`   - The channel values interleaved per pixel in row major
+///                order.
+pub fn update_image_packet(
+    name: &str,
+    channels: &[&str],
+    bounds: &Bounds2i,
+    values: &[Float],
+) -> Vec<u8> {
+    let n_channels = channels.len();
+    let extent = bounds.diagonal();
+    assert_eq!(values.len(), n_channels * bounds.area() as usize);
+
+    let mut payload = vec![0]; // Don't grab focus.
+    push_str(&mut payload, name);
+    payload.extend_from_slice(&(n_channels as i32).to_le_bytes());
+    for channel in channels.iter() {
+        push_str(&mut payload, channel);
+    }
+    for v in [bounds.p_min.x, bounds.p_min.y, extent.x, extent.y].iter() {
+        payload.extend_from_slice(&v.to_le_bytes());
+    }
+    for c in 0..n_channels {
+        payload.extend_from_slice(&(c as i64).to_le_bytes());
+    }
+    for _ in 0..n_channels {
+        payload.extend_from_slice(&(n_channels as i64).to_le_bytes());
+    }
+    for v in values.iter() {
+        payload.extend_from_slice(&v.to_le_bytes());
+    }
+    packet(UPDATE_IMAGE_V3, &payload)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn create_image_layout() {
+        let bytes = create_image_packet("a", &Point2i::new(2, 3), &CHANNELS);
+        let expected: Vec<u8> = [
+            &26_u32.to_le_bytes()[..],
+            &[CREATE_IMAGE, 1, b'a', 0],
+            &2_i32.to_le_bytes(),
+            &3_i32.to_le_bytes(),
+            &3_i32.to_le_bytes(),
+            b"R\0G\0B\0",
+        ]
+        .concat();
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn update_image_layout() {
+        let bounds = Bounds2i::new(Point2i::new(4, 5), Point2i::new(5, 6));
+        let bytes = update_image_packet("a", &["Y"], &bounds, &[0.5]);
+        let expected: Vec<u8> = [
+            &50_u32.to_le_bytes()[..],
+            &[UPDATE_IMAGE_V3, 0, b'a', 0],
+            &1_i32.to_le_bytes(),
+            b"Y\0",
+            &4_i32.to_le_bytes(),
+            &5_i32.to_le_bytes(),
+            &1_i32.to_le_bytes(),
+            &1_i32.to_le_bytes(),
+            &0_i64.to_le_bytes(),
+            &1_i64.to_le_bytes(),
+            &0.5_f32.to_le_bytes(),
+        ]
+        .concat();
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn streams_packets_to_viewer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let resolution = Point2i::new(1, 1);
+        let bounds = Bounds2i::new(Point2i::new(0, 0), resolution);
+
+        let display = DisplayServer::connect(&address, "img", &resolution).unwrap();
+        display.update(&bounds, &[1.0, 2.0, 3.0]);
+        drop(display);
+
+        let mut received = vec![];
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_to_end(&mut received).unwrap();
+        let expected = [
+            close_image_packet("img"),
+            create_image_packet("img", &resolution, &CHANNELS),
+            update_image_packet("img", &CHANNELS, &bounds, &[1.0, 2.0, 3.0]),
+        ]
+        .concat();
+        assert_eq!(received, expected);
+    }
+}
@@ -1,8 +1,10 @@
 //! Film tile
 
-use super::{FILTER_TABLE_SIZE, FILTER_TABLE_WIDTH};
+use super::{compensated_add, Accumulation, AuxiliaryChannel, AuxiliaryPixel, FILTER_TABLE_SIZE, FILTER_TABLE_WIDTH};
 use crate::geometry::*;
+use crate::integrator::{LightPathAov, LightPathRecorder};
 use crate::pbrt::*;
+use crate::profiler::*;
 use crate::spectrum::*;
 use std::sync::Arc;
 
@@ -26,6 +28,26 @@ pub struct FilmTile {
 
     /// Maximum sample luminence.
     max_sample_luminance: Float,
+
+    /// How sample contributions are summed.
+    accumulation: Accumulation,
+
+    /// Light path expression AOVs.
+    aovs: Arc<Vec<LightPathAov>>,
+
+    /// Contributions of all pixels in the tile for each AOV.
+    pub aov_pixels: Vec<Vec<FilmTilePixel>>,
+
+    /// Auxiliary values of all pixels in the tile. Empty unless the film
+    /// accumulates auxiliary channels.
+    pub aux_pixels: Vec<AuxiliaryPixel>,
+
+    /// Auxiliary channels of the film.
+    pub aux_channels: Arc<Vec<AuxiliaryChannel>>,
+
+    /// Contributions splatted from the tile's samples. They are added to the
+    /// film when the tile is merged.
+    pub splats: Vec<(Point2f, Spectrum)>,
 }
 
 impl FilmTile {
@@ -36,25 +58,42 @@ impl FilmTile {
     /// * `filter_table`         - Filter table.
     /// * `max_sample_luminance` - Optional maximum sample luminence to use use.
     ///                            Defaults to `INFINITY`.
+    /// * `accumulation`         - How sample contributions are summed.
+    /// * `aovs`                 - Light path expression AOVs.
     pub fn new(
         pixel_bounds: Bounds2i,
         filter_radius: Vector2f,
         filter_table: Arc<[Float; FILTER_TABLE_SIZE]>,
         max_sample_luminance: Option<Float>,
+        accumulation: Accumulation,
+        aovs: Arc<Vec<LightPathAov>>,
     ) -> Self {
+        let n_pixels = max(0, pixel_bounds.area() as usize);
         Self {
             pixel_bounds,
             filter_radius,
             inv_filter_radius: Vector2f::new(1.0 / filter_radius.x, 1.0 / filter_radius.y),
             filter_table: Arc::clone(&filter_table),
-            pixels: vec![FilmTilePixel::default(); max(0, pixel_bounds.area() as usize)],
+            pixels: vec![FilmTilePixel::default(); n_pixels],
             max_sample_luminance: match max_sample_luminance {
                 Some(luminence) => luminence,
                 None => INFINITY,
             },
+            accumulation,
+            aov_pixels: vec![vec![FilmTilePixel::default(); n_pixels]; aovs.len()],
+            aovs,
+            aux_pixels: vec![],
+            aux_channels: Arc::new(vec![]),
+            splats: vec![],
         }
     }
 
+    /// Returns whether the tile has light path expression AOVs that need the
+    /// paths of the contributions to be recorded.
+    pub fn has_aovs(&self) -> bool {
+        !self.aovs.is_empty()
+    }
+
     /// Add the radiance carried by a ray for a sample. This should be called by
     /// integrators.
     ///
@@ -62,13 +101,68 @@ impl FilmTile {
     /// * `l`              - Radiance value `L`.
     /// * `sample_weight`  - Weight for the sample's contribution.
     pub fn add_sample(&mut self, p_film: Point2f, l: Spectrum, sample_weight: Float) {
+        let _p = ProfilePhase::new(Prof::AddFilmSample);
+        let l = l * self.luminance_scale(&l);
+        for (pixel_offset, filter_weight) in self.filter_footprint(p_film) {
+            let pixel = &mut self.pixels[pixel_offset];
+            pixel.add(self.accumulation, l * sample_weight, filter_weight);
+        }
+    }
+
+    /// Add the radiance carried by a ray for a sample and the part of it that
+    /// arrived along the paths matching each light path expression AOV.
+    ///
+    /// * `p_film`         - Point on film.
+    /// * `l`              - Radiance value `L`.
+    /// * `paths`          - The contributions to `L` and their paths.
+    /// * `sample_weight`  - Weight for the sample's contribution.
+    pub fn add_sample_with_paths(
+        &mut self,
+        p_film: Point2f,
+        l: Spectrum,
+        paths: &LightPathRecorder,
+        sample_weight: Float,
+    ) {
+        // AOVs are scaled like the sample so they still add up to it.
+        let scale = self.luminance_scale(&l) * sample_weight;
+        let values = paths.aov_values(&self.aovs);
+        for (pixel_offset, filter_weight) in self.filter_footprint(p_film) {
+            let pixel = &mut self.pixels[pixel_offset];
+            pixel.add(self.accumulation, l * scale, filter_weight);
+            for (pixels, value) in self.aov_pixels.iter_mut().zip(values.iter()) {
+                pixels[pixel_offset].add(self.accumulation, *value * scale, filter_weight);
+            }
+        }
+    }
+
+    /// Add a contribution that isn't weighted by the reconstruction filter
+    /// such as light paths connected to the camera. It is added to the film
+    /// when the tile is merged.
+    ///
+    /// * `p` - Raster position of the contribution.
+    /// * `v` - The contribution.
+    pub fn add_splat(&mut self, p: Point2f, v: Spectrum) {
+        self.splats.push((p, v));
+    }
+
+    /// Returns the factor that scales a sample down to the maximum sample
+    /// luminance.
+    ///
+    /// * `l` - Radiance value `L`.
+    fn luminance_scale(&self, l: &Spectrum) -> Float {
         let ly = l.y();
-        let l = if ly > self.max_sample_luminance {
-            l * self.max_sample_luminance / ly
+        if ly > self.max_sample_luminance {
+            self.max_sample_luminance / ly
         } else {
-            l
-        };
+            1.0
+        }
+    }
 
+    /// Returns the offsets and filter weights of the pixels a sample
+    /// contributes to.
+    ///
+    /// * `p_film` - Point on film.
+    pub(super) fn filter_footprint(&self, p_film: Point2f) -> Vec<(usize, Float)> {
         // Compute sample's raster bounds.
         let p_film_discrete = p_film - Vector2f::new(0.5, 0.5);
         let mut p0 = Point2i::from((p_film_discrete - self.filter_radius).ceil());
@@ -99,20 +193,18 @@ impl FilmTile {
             })
             .collect();
 
+        let mut footprint = Vec::with_capacity(ifx.len() * ify.len());
         for y in p0.y..p1.y {
             for x in p0.x..p1.x {
                 // Evaluate filter value at `(x, y)` pixel.
                 let offset =
                     ify[(y - p0.y) as usize] * filter_table_size + ifx[(x - p0.x) as usize];
                 let filter_weight = self.filter_table[offset];
-
-                // Update pixel values with filtered sample contribution.
                 let pixel_offset = self.get_pixel_offset(&Point2i::new(x, y));
-
-                self.pixels[pixel_offset].contrib_sum += l * sample_weight * filter_weight;
-                self.pixels[pixel_offset].filter_weight_sum += filter_weight;
+                footprint.push((pixel_offset, filter_weight));
             }
         }
+        footprint
     }
 
     /// Converts pixel coordinates with respect to the overall image and to
@@ -145,4 +237,49 @@ pub struct FilmTilePixel {
 
     /// Sum of filter weights.
     pub filter_weight_sum: Float,
+
+    /// Compensation for `contrib_sum` with compensated accumulation.
+    pub contrib_err: Spectrum,
+
+    /// Compensation for `filter_weight_sum` with compensated accumulation.
+    pub filter_weight_err: Float,
+}
+
+impl FilmTilePixel {
+    /// Add a filtered sample contribution.
+    ///
+    /// * `accumulation`  - How sample contributions are summed.
+    /// * `l`             - Weighted radiance of the sample.
+    /// * `filter_weight` - Filter weight at the pixel.
+    fn add(&mut self, accumulation: Accumulation, l: Spectrum, filter_weight: Float) {
+        match accumulation {
+            Accumulation::Plain => {
+                self.contrib_sum += l * filter_weight;
+                self.filter_weight_sum += filter_weight;
+            }
+            Accumulation::Compensated => {
+                let contrib = l * filter_weight;
+                let sums = self.contrib_sum.samples_mut();
+                let errs = self.contrib_err.samples_mut();
+                for (i, v) in contrib.samples().iter().enumerate() {
+                    compensated_add(&mut sums[i], &mut errs[i], *v);
+                }
+                compensated_add(
+                    &mut self.filter_weight_sum,
+                    &mut self.filter_weight_err,
+                    filter_weight,
+                );
+            }
+        }
+    }
+
+    /// Returns the sum of weighted contributions including compensation.
+    pub fn contrib(&self) -> Spectrum {
+        self.contrib_sum + self.contrib_err
+    }
+
+    /// Returns the sum of filter weights including compensation.
+    pub fn filter_weight(&self) -> Float {
+        self.filter_weight_sum + self.filter_weight_err
+    }
 }
@@ -0,0 +1,469 @@
+//! Film History
+//!
+//! Frames rendered from the same view keep accumulating samples. When the
+//! camera moves and the film records the `motion` auxiliary channel, the
+//! pixels of the previous frame are reprojected into the new view instead
+//! using the world space positions seen through the pixels of both frames.
+
+use super::{AuxiliaryChannel, Film, Pixel};
+use crate::geometry::*;
+use crate::pbrt::*;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// Pixels accumulated by previous frames. Frames are rendered one at a
+    /// time with a fresh scene so a single global instance carries them from
+    /// one frame to the next.
+    pub static ref FILM_HISTORY: Mutex<FilmHistory> = Mutex::new(FilmHistory::new());
+}
+
+/// Describes the view of a camera for detecting camera movement between
+/// frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraView {
+    /// Camera to world transformation.
+    pub camera_to_world: Transform,
+
+    /// Lens parameters such as field of view and focal distance.
+    pub lens: Vec<Float>,
+
+    /// Transformation from world space to raster space for cameras with a
+    /// projection.
+    pub world_to_raster: Option<Transform>,
+}
+
+impl CameraView {
+    /// Create a new `CameraView`.
+    ///
+    /// * `camera_to_world` - Camera to world transformation.
+    /// * `lens`            - Lens parameters such as field of view and focal
+    ///                       distance.
+    pub fn new(camera_to_world: &Transform, lens: &[Float]) -> Self {
+        Self {
+            camera_to_world: *camera_to_world,
+            lens: lens.to_vec(),
+            world_to_raster: None,
+        }
+    }
+
+    /// Returns the largest difference between the matrix elements and lens
+    /// parameters of two views or `INFINITY` if they cannot be compared.
+    ///
+    /// * `other` - The other view.
+    pub fn delta(&self, other: &Self) -> Float {
+        if self.lens.len() != other.lens.len() {
+            return INFINITY;
+        }
+
+        let a = &self.camera_to_world.m.m;
+        let b = &other.camera_to_world.m.m;
+        let matrix_delta = (0..16)
+            .map(|i| abs(a[i / 4][i % 4] - b[i / 4][i % 4]))
+            .fold(0.0, max);
+        self.lens
+            .iter()
+            .zip(other.lens.iter())
+            .map(|(a, b)| abs(a - b))
+            .fold(matrix_delta, max)
+    }
+}
+
+/// Double-buffered pixels accumulated over frames. The front buffer holds the
+/// last completed accumulation and seeds the next frame while it is written
+/// into the back buffer. The history restarts when the camera moves unless
+/// it can be reprojected.
+pub struct FilmHistory {
+    /// The two pixel buffers.
+    buffers: [Vec<Pixel>; 2],
+
+    /// World space positions seen through the pixels of each buffer. They are
+    /// empty if the positions were not recorded.
+    positions: [Vec<Option<Point3f>>; 2],
+
+    /// Index of the front buffer.
+    front: usize,
+
+    /// View the front buffer was rendered with, `None` if it is empty.
+    view: Option<CameraView>,
+
+    /// Pixel bounds of the front buffer.
+    bounds: Bounds2i,
+
+    /// Number of passes accumulated in the front buffer.
+    passes: usize,
+
+    /// Number of passes accumulated in the frame being rendered, including
+    /// those restored from the front buffer.
+    pending_passes: usize,
+}
+
+impl FilmHistory {
+    /// Returns an empty `FilmHistory`.
+    pub fn new() -> Self {
+        Self {
+            buffers: [vec![], vec![]],
+            positions: [vec![], vec![]],
+            front: 0,
+            view: None,
+            bounds: Bounds2i::default(),
+            passes: 0,
+            pending_passes: 0,
+        }
+    }
+
+    /// Discards the accumulated pixels.
+    pub fn reset(&mut self) {
+        self.view = None;
+        self.passes = 0;
+        self.pending_passes = 0;
+    }
+
+    /// Returns the accumulated pixels for a new frame or `None` if there are
+    /// none or the camera moved by more than a threshold since they were
+    /// rendered, in which case the passes restart and the front buffer can
+    /// only be reprojected.
+    ///
+    /// * `view`      - View of the new frame.
+    /// * `bounds`    - Pixel bounds of the new frame.
+    /// * `threshold` - Largest change in the view that keeps the history.
+    pub fn restore(
+        &mut self,
+        view: &CameraView,
+        bounds: &Bounds2i,
+        threshold: Float,
+    ) -> Option<&[Pixel]> {
+        let delta = match self.view.as_ref() {
+            Some(v) if self.bounds == *bounds => v.delta(view),
+            Some(_) => INFINITY,
+            None => {
+                self.pending_passes = 0;
+                return None;
+            }
+        };
+
+        if delta > threshold {
+            info!("Camera moved. Restarting temporal accumulation.");
+            self.pending_passes = 0;
+            None
+        } else {
+            self.pending_passes = self.passes;
+            Some(&self.buffers[self.front])
+        }
+    }
+
+    /// Returns the number of passes accumulated in the frame being rendered.
+    /// Passes continue from this number so that each frame adds new samples.
+    pub fn pending_passes(&self) -> usize {
+        self.pending_passes
+    }
+
+    /// Records the number of passes accumulated in the frame being rendered.
+    ///
+    /// * `passes` - Number of passes including those restored from history.
+    pub fn set_pending_passes(&mut self, passes: usize) {
+        self.pending_passes = passes;
+    }
+
+    /// Returns the front buffer for reprojecting it into a frame rendered
+    /// from a different view or `None` if it is empty or its positions or
+    /// projection are unknown.
+    pub fn reprojection(&self) -> Option<Reprojection> {
+        let view = self.view.as_ref()?;
+        let world_to_raster = view.world_to_raster?;
+        let positions = &self.positions[self.front];
+        if positions.is_empty() {
+            return None;
+        }
+
+        Some(Reprojection {
+            world_to_raster,
+            world_to_camera: view.camera_to_world.inverse(),
+            bounds: self.bounds,
+            pixels: self.buffers[self.front].clone(),
+            positions: positions.clone(),
+        })
+    }
+
+    /// Writes the pixels of the frame being rendered into the back buffer
+    /// and makes it the front buffer.
+    ///
+    /// * `view`      - View of the frame.
+    /// * `bounds`    - Pixel bounds of the frame.
+    /// * `pixels`    - The accumulated pixels.
+    /// * `positions` - World space positions seen through the pixels or empty
+    ///                 if they were not recorded.
+    pub fn store(
+        &mut self,
+        view: &CameraView,
+        bounds: &Bounds2i,
+        pixels: &[Pixel],
+        positions: &[Option<Point3f>],
+    ) {
+        let back = 1 - self.front;
+        self.buffers[back].clear();
+        self.buffers[back].extend_from_slice(pixels);
+        self.positions[back].clear();
+        self.positions[back].extend_from_slice(positions);
+        self.front = back;
+        self.view = Some(view.clone());
+        self.bounds = *bounds;
+        self.passes = self.pending_passes;
+    }
+}
+
+impl Default for FilmHistory {
+    /// Returns an empty `FilmHistory`.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pixels of a previous frame looked up by the world space positions seen
+/// through the pixels of a frame rendered from a different view.
+#[derive(Clone)]
+pub struct Reprojection {
+    /// Transformation from world space to raster space of the previous frame.
+    pub world_to_raster: Transform,
+
+    /// Transformation from world space to camera space of the previous frame.
+    pub world_to_camera: Transform,
+
+    /// Pixel bounds of the previous frame.
+    pub bounds: Bounds2i,
+
+    /// The accumulated pixels of the previous frame.
+    pub pixels: Vec<Pixel>,
+
+    /// World space positions seen through the pixels of the previous frame.
+    pub positions: Vec<Option<Point3f>>,
+}
+
+impl Reprojection {
+    /// Returns the raster position of a world space point in the previous
+    /// frame or `None` if it was behind the camera.
+    ///
+    /// * `p` - The point.
+    pub fn raster(&self, p: &Point3f) -> Option<Point2f> {
+        if self.world_to_camera.transform_point(p).z <= 0.0 {
+            return None;
+        }
+        let p_raster = self.world_to_raster.transform_point(p);
+        Some(Point2f::new(p_raster.x, p_raster.y))
+    }
+
+    /// Returns the pixel of the previous frame that saw a world space point
+    /// or `None` if the point was outside the frame or hidden by another
+    /// surface.
+    ///
+    /// * `p` - The point.
+    pub fn lookup(&self, p: &Point3f) -> Option<&Pixel> {
+        let pi = Point2i::from(self.raster(p)?.floor());
+        if !self.bounds.contains_exclusive(&pi) {
+            return None;
+        }
+
+        let width = self.bounds.p_max.x - self.bounds.p_min.x;
+        let offset = ((pi.x - self.bounds.p_min.x) + (pi.y - self.bounds.p_min.y) * width) as usize;
+        let seen = self.positions[offset]?;
+
+        // The pixel saw a different surface if the positions are far apart
+        // compared to their distance from the camera.
+        let distance = Vector3f::from(self.world_to_camera.transform_point(p)).length();
+        if seen.distance(*p) > REPROJECTION_TOLERANCE * distance {
+            None
+        } else {
+            Some(&self.pixels[offset])
+        }
+    }
+}
+
+/// Largest distance between the positions seen through a pixel in two frames
+/// relative to their distance from the camera for the pixel to be reused.
+pub const REPROJECTION_TOLERANCE: Float = 0.01;
+
+impl Film {
+    /// Sets the transformation from world space to raster space of the view
+    /// of the frame so that it can be reprojected into the next frame.
+    ///
+    /// * `world_to_raster` - The transformation.
+    pub fn set_world_to_raster(&mut self, world_to_raster: &Transform) {
+        if let Some(view) = self.view.as_mut() {
+            view.world_to_raster = Some(*world_to_raster);
+        }
+    }
+
+    /// Returns whether the film records the motion of the pixels needed for
+    /// reprojecting frames.
+    pub(super) fn records_motion(&self) -> bool {
+        self.auxiliary.contains(&AuxiliaryChannel::Motion)
+    }
+
+    /// Returns the world space positions seen through the pixels or an empty
+    /// list if the film doesn't record motion.
+    pub(super) fn positions(&self) -> Vec<Option<Point3f>> {
+        if self.records_motion() {
+            self.aux_pixels.iter().map(|p| p.p()).collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Returns the accumulated pixels with those of the previous frame that
+    /// saw the same surfaces added with the feedback weight or `None` if the
+    /// previous frame is not reprojected. Splats are not reprojected.
+    pub(super) fn reprojected_pixels(&self) -> Option<Vec<Pixel>> {
+        let reprojection = self.reprojection.as_ref()?;
+        let w = self.temporal_feedback;
+        let mut pixels = self.pixels.clone();
+        for (pixel, aux) in pixels.iter_mut().zip(self.aux_pixels.iter()) {
+            if let Some(previous) = aux.p().and_then(|p| reprojection.lookup(&p)) {
+                let xyz = previous.xyz();
+                for (i, colour) in xyz.iter().enumerate() {
+                    self.accumulation
+                        .add(&mut pixel.xyz[i], &mut pixel.xyz_err[i], w * colour);
+                }
+                self.accumulation.add(
+                    &mut pixel.filter_weight_sum,
+                    &mut pixel.filter_weight_err,
+                    w * previous.filter_weight(),
+                );
+            }
+        }
+        Some(pixels)
+    }
+
+    /// Returns the raster space offset from the centre of each pixel to where
+    /// the surface seen through it was in the previous frame. It is zero if
+    /// the previous frame is not reprojected or the pixel saw no surface.
+    pub(super) fn motion_vectors(&self) -> Vec<[Float; 2]> {
+        let mut motion = vec![[0.0; 2]; self.aux_pixels.len()];
+        if let Some(reprojection) = self.reprojection.as_ref() {
+            for pixel in self.cropped_pixel_bounds {
+                let offset = self.get_pixel_offset(&pixel);
+                let p_raster = self.aux_pixels[offset]
+                    .p()
+                    .and_then(|p| reprojection.raster(&p));
+                if let Some(p_raster) = p_raster {
+                    motion[offset] = [
+                        p_raster.x - (pixel.x as Float + 0.5),
+                        p_raster.y - (pixel.y as Float + 0.5),
+                    ];
+                }
+            }
+        }
+        motion
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(v: Float) -> Vec<Pixel> {
+        vec![
+            Pixel {
+                xyz: [v; 3],
+                filter_weight_sum: 1.0,
+                ..Pixel::default()
+            };
+            4
+        ]
+    }
+
+    #[test]
+    fn film_history_restarts_when_camera_moves() {
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 2));
+        let view = CameraView::new(
+            &Transform::translate(&Vector3f::new(0.0, 0.0, 1.0)),
+            &[45.0],
+        );
+        let moved = CameraView::new(
+            &Transform::translate(&Vector3f::new(0.0, 0.5, 1.0)),
+            &[45.0],
+        );
+        let zoomed = CameraView::new(&view.camera_to_world, &[30.0]);
+
+        let mut history = FilmHistory::new();
+        assert!(history.restore(&view, &bounds, 1e-4).is_none());
+        history.set_pending_passes(1);
+        history.store(&view, &bounds, &pixels(1.0), &[]);
+
+        // A still camera continues from the accumulated pixels and passes.
+        assert_eq!(
+            history.restore(&view, &bounds, 1e-4).unwrap()[0].xyz,
+            [1.0; 3]
+        );
+        assert_eq!(history.pending_passes(), 1);
+        history.set_pending_passes(2);
+        history.store(&view, &bounds, &pixels(2.0), &[]);
+        assert_eq!(
+            history.restore(&view, &bounds, 1e-4).unwrap()[0].xyz,
+            [2.0; 3]
+        );
+        assert_eq!(history.pending_passes(), 2);
+
+        // Movement, lens changes and resizing restart it.
+        assert!(history.restore(&zoomed, &bounds, 1e-4).is_none());
+        assert_eq!(history.pending_passes(), 0);
+        history.store(&view, &bounds, &pixels(3.0), &[]);
+        assert!(history.restore(&moved, &bounds, 1e-4).is_none());
+        history.store(&view, &bounds, &pixels(3.0), &[]);
+        let resized = Bounds2i::new(Point2i::new(0, 0), Point2i::new(4, 1));
+        assert!(history.restore(&view, &resized, 1e-4).is_none());
+
+        // Small changes within the threshold keep it.
+        history.store(&view, &bounds, &pixels(3.0), &[]);
+        assert!(history.restore(&moved, &bounds, 1.0).is_some());
+    }
+
+    #[test]
+    fn reprojection_finds_surfaces_seen_by_previous_frame() {
+        // The previous camera looked down +z with one pixel per unit.
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 2));
+        let mut view = CameraView::new(&Transform::default(), &[45.0]);
+        view.world_to_raster = Some(Transform::translate(&Vector3f::new(1.0, 1.0, 0.0)));
+        let mut pixels = pixels(1.0);
+        pixels[3].xyz = [4.0; 3];
+        let positions = vec![
+            Some(Point3f::new(-0.5, -0.5, 2.0)),
+            None,
+            Some(Point3f::new(-0.5, 0.5, 2.0)),
+            Some(Point3f::new(0.5, 0.5, 2.0)),
+        ];
+
+        let mut history = FilmHistory::new();
+        assert!(history.reprojection().is_none());
+        history.store(&view, &bounds, &pixels, &[]);
+        assert!(history.reprojection().is_none());
+        history.store(&view, &bounds, &pixels, &positions);
+        let reprojection = history.reprojection().unwrap();
+
+        let p = Point3f::new(0.5, 0.5, 2.0);
+        assert_eq!(reprojection.raster(&p), Some(Point2f::new(1.5, 1.5)));
+        assert_eq!(reprojection.lookup(&p).unwrap().xyz, [4.0; 3]);
+
+        // Points close to the surface seen through the pixel reuse it while
+        // occluded points, points behind the camera, pixels that saw nothing
+        // and points outside the frame don't.
+        let near = Point3f::new(0.51, 0.5, 2.0);
+        assert_eq!(reprojection.lookup(&near).unwrap().xyz, [4.0; 3]);
+        assert!(reprojection.lookup(&Point3f::new(0.5, 0.5, 3.0)).is_none());
+        assert!(reprojection.raster(&Point3f::new(0.5, 0.5, -2.0)).is_none());
+        assert!(reprojection.lookup(&Point3f::new(0.5, -0.5, 2.0)).is_none());
+        assert!(reprojection.lookup(&Point3f::new(2.5, 0.5, 2.0)).is_none());
+
+        // Moving the camera keeps the previous frame for reprojection.
+        let moved = CameraView::new(
+            &Transform::translate(&Vector3f::new(0.5, 0.0, 0.0)),
+            &[45.0],
+        );
+        assert!(history.restore(&moved, &bounds, 1e-4).is_none());
+        assert!(history.reprojection().is_some());
+        history.reset();
+        assert!(history.reprojection().is_none());
+    }
+}
@@ -5,15 +5,31 @@ use crate::app::OPTIONS;
 use crate::filter::*;
 use crate::geometry::*;
 use crate::image_io::*;
+use crate::integrator::{LightPathAov, RENDER_PROGRESS};
 use crate::paramset::*;
 use crate::pbrt::*;
 use crate::spectrum::*;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+mod accumulation;
+mod aov;
+mod auxiliary;
+mod checkpoint;
+mod composite;
+mod display;
 mod film_tile;
+mod history;
 
 // Re-export.
+pub use accumulation::*;
+pub use aov::*;
+pub use auxiliary::*;
+pub use checkpoint::*;
+pub use composite::*;
+pub use display::*;
 pub use film_tile::*;
+pub use history::*;
 
 /// Filter table width.
 pub const FILTER_TABLE_WIDTH: usize = 16;
@@ -39,9 +55,18 @@ pub struct Pixel {
     /// Holds an unweighted sum of sample splats.
     pub splat_xyz: [Float; 3],
 
+    /// Compensation for `xyz` with compensated accumulation.
+    pub xyz_err: [Float; 3],
+
+    /// Compensation for `filter_weight_sum` with compensated accumulation.
+    pub filter_weight_err: Float,
+
+    /// Compensation for `splat_xyz` with compensated accumulation.
+    pub splat_err: [Float; 3],
+
     /// Used to pad this struct to the 32-bit/64-bit. This will work for both
     /// `Float` => `f32` and `Float` => `f64`.
-    pad: Float,
+    pad: [Float; 2],
 }
 
 /// Models the sensing device in a simulated camera. It stores all of the sample
@@ -60,9 +85,20 @@ pub struct Film {
     /// Filename of output image.
     pub filename: String,
 
+    /// Output image files and how they are written. The first writes to
+    /// `filename` and any others are written alongside it.
+    pub outputs: Vec<ImageOutput>,
+
     /// Crop window of the subset of the image to render.
     pub cropped_pixel_bounds: Bounds2i,
 
+    /// Name/value pairs written as attributes of the output image.
+    pub metadata: BTreeMap<String, String>,
+
+    /// Chromatic adaptation applied to pixel colours before converting them
+    /// to RGB.
+    pub white_balance: Option<WhiteBalance>,
+
     /// The filter table.
     filter_table: Arc<[Float; FILTER_TABLE_SIZE]>,
 
@@ -72,8 +108,91 @@ pub struct Film {
     /// Maximum sample luminence.
     max_sample_luminance: Float,
 
+    /// How sample contributions are summed.
+    pub accumulation: Accumulation,
+
+    /// Accumulate pixels over frames rendered from the same view.
+    pub temporal: bool,
+
+    /// Largest change in the view between frames that keeps the pixels
+    /// accumulated by previous frames.
+    pub temporal_threshold: Float,
+
+    /// Weight of the pixels of the previous frame reprojected into a frame
+    /// rendered from a different view.
+    pub temporal_feedback: Float,
+
+    /// Path to a previously rendered image the rendered pixels are
+    /// composited into.
+    pub composite: Option<String>,
+
+    /// View of the frame used for temporal accumulation.
+    view: Option<CameraView>,
+
+    /// Previous frame reprojected into this one when the camera moved.
+    reprojection: Option<Reprojection>,
+
+    /// Whether the pixels hold a preview instead of rendered samples.
+    is_preview: bool,
+
     /// Stores the image pixels.
     pixels: Vec<Pixel>,
+
+    /// Light path expression AOVs.
+    aovs: Arc<Vec<LightPathAov>>,
+
+    /// Stores the image pixels of each AOV.
+    aov_pixels: Vec<Vec<Pixel>>,
+
+    /// Auxiliary channels.
+    auxiliary: Arc<Vec<AuxiliaryChannel>>,
+
+    /// Stores the auxiliary values of the image pixels.
+    aux_pixels: Vec<AuxiliaryPixel>,
+
+    /// Viewer the image is streamed to while rendering.
+    display: Option<Arc<DisplayServer>>,
+}
+
+impl Pixel {
+    /// Merge a `FilmTilePixel`'s contribution into the pixel.
+    ///
+    /// * `accumulation` - How sample contributions are summed.
+    /// * `tile_pixel`   - The `FilmTilePixel` to merge.
+    pub fn merge(&mut self, accumulation: Accumulation, tile_pixel: &FilmTilePixel) {
+        let xyz = tile_pixel.contrib().to_xyz();
+        for (i, colour) in xyz.iter().enumerate() {
+            accumulation.add(&mut self.xyz[i], &mut self.xyz_err[i], *colour);
+        }
+        accumulation.add(
+            &mut self.filter_weight_sum,
+            &mut self.filter_weight_err,
+            tile_pixel.filter_weight(),
+        );
+    }
+
+    /// Returns the weighted sum of contributions including compensation.
+    pub fn xyz(&self) -> [Float; 3] {
+        [
+            self.xyz[0] + self.xyz_err[0],
+            self.xyz[1] + self.xyz_err[1],
+            self.xyz[2] + self.xyz_err[2],
+        ]
+    }
+
+    /// Returns the sum of filter weights including compensation.
+    pub fn filter_weight(&self) -> Float {
+        self.filter_weight_sum + self.filter_weight_err
+    }
+
+    /// Returns the sum of splats including compensation.
+    pub fn splat_xyz(&self) -> [Float; 3] {
+        [
+            self.splat_xyz[0] + self.splat_err[0],
+            self.splat_xyz[1] + self.splat_err[1],
+            self.splat_xyz[2] + self.splat_err[2],
+        ]
+    }
 }
 
 impl Film {
@@ -137,14 +256,48 @@ impl Film {
             filter,
             filter_table: Arc::new(filter_table),
             filename: String::from(filename),
+            outputs: vec![ImageOutput::new(filename)],
             cropped_pixel_bounds,
+            metadata: BTreeMap::new(),
+            white_balance: None,
+            accumulation: Accumulation::default(),
+            temporal: false,
+            temporal_threshold: 1e-4,
+            temporal_feedback: 0.5,
+            composite: None,
+            view: None,
+            reprojection: None,
+            is_preview: false,
             scale: scale.unwrap_or(1.0),
             max_sample_luminance: match max_sample_luminance {
                 Some(luminence) => luminence,
                 None => INFINITY,
             },
             pixels,
+            aovs: Arc::new(vec![]),
+            aov_pixels: vec![],
+            auxiliary: Arc::new(vec![]),
+            aux_pixels: vec![],
+            display: None,
+        }
+    }
+
+    /// Restrict the image to the given pixels instead of the crop window.
+    /// This must be called before any samples are added.
+    ///
+    /// * `bounds` - The pixels in the overall image.
+    pub fn set_pixel_bounds(&mut self, bounds: &Bounds2i) {
+        let full = Bounds2i::new(Point2i::new(0, 0), self.full_resolution);
+        let clipped = bounds.intersect(&full);
+        if clipped.area() == 0 {
+            error!(
+                "Pixel bounds {} don't overlap the {}x{} image. Ignoring them.",
+                bounds, self.full_resolution.x, self.full_resolution.y
+            );
+            return;
         }
+        self.cropped_pixel_bounds = clipped;
+        self.pixels = vec![Pixel::default(); clipped.area() as usize];
     }
 
     /// Returns the sample bounds accounting for the half-pixel offsets when
@@ -199,36 +352,81 @@ impl Film {
             + Point2i::new(1, 1);
         let tile_pixel_bounds = Bounds2i::new(p0, p1).intersect(&self.cropped_pixel_bounds);
 
-        FilmTile::new(
+        let mut tile = FilmTile::new(
             tile_pixel_bounds,
             filter_data.radius,
             Arc::clone(&self.filter_table),
             Some(self.max_sample_luminance),
-        )
+            self.accumulation,
+            Arc::clone(&self.aovs),
+        );
+        if !self.aux_pixels.is_empty() {
+            tile.aux_pixels = vec![AuxiliaryPixel::default(); tile.pixels.len()];
+            tile.aux_channels = Arc::clone(&self.auxiliary);
+        }
+        tile
     }
 
-    /// Clear the splats for all pixels in the image.
+    /// Clear the pixel values and splats for all pixels in the image. With
+    /// temporal accumulation the pixels accumulated by previous frames are
+    /// restored.
     pub fn clear(&mut self) {
         for pixel in self.cropped_pixel_bounds {
             let pixel_offset = self.get_pixel_offset(&pixel);
-            self.pixels[pixel_offset].splat_xyz = [0.0; 3];
-            self.pixels[pixel_offset].filter_weight_sum = 0.0;
+            self.pixels[pixel_offset] = Pixel::default();
+        }
+        for pixels in self.aov_pixels.iter_mut() {
+            pixels.iter_mut().for_each(|p| *p = Pixel::default());
+        }
+        for p in self.aux_pixels.iter_mut() {
+            *p = AuxiliaryPixel::default();
+        }
+        self.is_preview = false;
+        self.restore_history();
+    }
+
+    /// Sets the view of the frame for temporal accumulation and restores the
+    /// pixels accumulated by previous frames if the camera has not moved.
+    ///
+    /// * `view` - View of the frame.
+    pub fn set_view(&mut self, view: CameraView) {
+        self.view = Some(view);
+        self.restore_history();
+    }
+
+    /// Restores the pixels accumulated by previous frames from the same
+    /// view. If the view changed the previous frame is reprojected when the
+    /// film records motion.
+    fn restore_history(&mut self) {
+        if let (true, Some(view)) = (self.temporal, self.view.as_ref()) {
+            let mut history = FILM_HISTORY.lock().unwrap();
+            let bounds = self.cropped_pixel_bounds;
+            match history.restore(view, &bounds, self.temporal_threshold) {
+                Some(pixels) => {
+                    self.pixels.copy_from_slice(pixels);
+                    self.reprojection = None;
+                }
+                None if self.records_motion() => self.reprojection = history.reprojection(),
+                None => self.reprojection = None,
+            }
         }
     }
 
-    /// Merge the `FilmTile`'s pixel contribution into the image.
+    /// Merge the `FilmTile`'s pixel contribution and splats into the image.
     ///
     /// * `tile` - The `FilmTile` to merge.
     pub fn merge_film_tile(&mut self, tile: &FilmTile) {
         for pixel in tile.get_pixel_bounds() {
             let tile_pixel = tile.get_pixel_offset(&pixel);
             let merge_pixel = self.get_pixel_offset(&pixel);
-            let xyz = tile.pixels[tile_pixel].contrib_sum.to_xyz();
-            for (i, colour) in xyz.iter().enumerate() {
-                self.pixels[merge_pixel].xyz[i] += colour;
-            }
-            self.pixels[merge_pixel].filter_weight_sum += tile.pixels[tile_pixel].filter_weight_sum;
+            self.pixels[merge_pixel].merge(self.accumulation, &tile.pixels[tile_pixel]);
+        }
+        self.merge_aov_tile(tile);
+        self.merge_auxiliary_tile(tile);
+        for (p, v) in tile.splats.iter() {
+            self.add_splat(p, v);
         }
+        self.update_display(&tile.get_pixel_bounds(), 0.0);
     }
 
     /// Sets all pixel values in the cropped area with the given spectrum values.
@@ -237,10 +435,42 @@ impl Film {
     pub fn set_image(&mut self, img: &[Spectrum]) {
         let n_pixels = self.cropped_pixel_bounds.area();
         for i in (0..n_pixels).map(|i| i as usize) {
-            self.pixels[i].xyz = img[i].to_xyz();
-            self.pixels[i].filter_weight_sum = 1.0;
-            self.pixels[i].splat_xyz = [0.0; 3];
+            self.pixels[i] = Pixel {
+                xyz: img[i].to_xyz(),
+                filter_weight_sum: 1.0,
+                ..Pixel::default()
+            };
+        }
+    }
+
+    /// Sets all pixel values in the cropped area from a low resolution image
+    /// by replicating each of its pixels over a square block of pixels.
+    ///
+    /// * `bounds`     - Pixel bounds covered by the low resolution image.
+    /// * `block_size` - Width of the block of pixels covered by each low
+    ///                  resolution pixel.
+    /// * `img`        - The spectrum values of the low resolution image in
+    ///                  row major order.
+    pub fn set_preview(&mut self, bounds: &Bounds2i, block_size: Int, img: &[Spectrum]) {
+        let extent = bounds.diagonal();
+        let nx = max(1, (extent.x + block_size - 1) / block_size);
+        let ny = max(1, (extent.y + block_size - 1) / block_size);
+        if img.len() < (nx * ny) as usize {
+            error!("Preview image has {} pixels. Expected {}.", img.len(), nx * ny);
+            return;
+        }
+
+        for pixel in self.cropped_pixel_bounds {
+            let bx = clamp((pixel.x - bounds.p_min.x) / block_size, 0, nx - 1);
+            let by = clamp((pixel.y - bounds.p_min.y) / block_size, 0, ny - 1);
+            let pixel_offset = self.get_pixel_offset(&pixel);
+            self.pixels[pixel_offset] = Pixel {
+                xyz: img[(by * nx + bx) as usize].to_xyz(),
+                filter_weight_sum: 1.0,
+                ..Pixel::default()
+            };
         }
+        self.is_preview = true;
     }
 
     /// Add `splat` contributions to a pixel.
@@ -281,8 +511,10 @@ impl Film {
 
             let xyz = v.to_xyz();
             let pixel_offset = self.get_pixel_offset(&pi);
+            let p = &mut self.pixels[pixel_offset];
             for (i, colour) in xyz.iter().enumerate() {
-                self.pixels[pixel_offset].splat_xyz[i] += colour;
+                self.accumulation
+                    .add(&mut p.splat_xyz[i], &mut p.splat_err[i], *colour);
             }
         }
     }
@@ -291,45 +523,89 @@ impl Film {
     ///
     /// * `splat_scale` - Scale factor for `add_splat()` (default = 1.0).
     pub fn write_image(&mut self, splat_scale: Float) {
+        // Add the reprojected pixels of the previous frame and keep the
+        // accumulated pixels for the next frame.
+        let reprojected = if self.is_preview {
+            None
+        } else {
+            self.reprojected_pixels()
+        };
+        let pixels = reprojected.as_deref().unwrap_or(&self.pixels);
+        if let (true, false, Some(view)) = (self.temporal, self.is_preview, self.view.as_ref()) {
+            let mut history = FILM_HISTORY.lock().unwrap();
+            history.store(view, &self.cropped_pixel_bounds, pixels, &self.positions());
+        }
+
         info!("Converting image to RGB and computing final weighted pixel values");
+        let rgb = self.to_rgb(pixels, splat_scale);
 
+        // Composite into an existing image and write RGB image to each output.
+        let (rgb, output_bounds) = self.composite(rgb);
+        for output in self.outputs.iter() {
+            if let Err(err) = write_image_output(output, &rgb, &output_bounds, &self.metadata) {
+                panic!("Error writing output image {}. {:}.", output.path, err);
+            }
+        }
+        self.write_aovs();
+        self.write_auxiliary();
+        self.update_display(&self.cropped_pixel_bounds, splat_scale);
+        RENDER_PROGRESS.set_image_file(&self.filename);
+    }
+
+    /// Returns the final weighted RGB values of pixels in the cropped area.
+    ///
+    /// * `pixels`      - The pixels.
+    /// * `splat_scale` - Scale factor for `add_splat()`.
+    fn to_rgb(&self, pixels: &[Pixel], splat_scale: Float) -> Vec<Float> {
         let n = 3 * self.cropped_pixel_bounds.area() as usize;
         let mut rgb = vec![0.0; n];
 
         for p in self.cropped_pixel_bounds {
-            // Convert pixel XYZ color to RGB.
             let pixel_offset = self.get_pixel_offset(&p);
             let rgb_offset = 3 * pixel_offset;
+            let pixel_rgb = self.pixel_rgb(&pixels[pixel_offset], splat_scale);
+            rgb[rgb_offset..rgb_offset + 3].copy_from_slice(&pixel_rgb);
+        }
+        rgb
+    }
 
-            let pixel_rgb = xyz_to_rgb(&self.pixels[pixel_offset].xyz);
-            rgb[rgb_offset] = pixel_rgb[0];
-            rgb[rgb_offset + 1] = pixel_rgb[1];
-            rgb[rgb_offset + 2] = pixel_rgb[2];
-
-            // Normalize pixel with weight sum.
-            let filter_weight_sum = self.pixels[pixel_offset].filter_weight_sum;
-            if filter_weight_sum != 0.0 {
-                let inv_wt = 1.0 / filter_weight_sum;
-                rgb[rgb_offset] = max(0.0, rgb[rgb_offset] * inv_wt);
-                rgb[rgb_offset + 1] = max(0.0, rgb[rgb_offset + 1] * inv_wt);
-                rgb[rgb_offset + 2] = max(0.0, rgb[rgb_offset + 2] * inv_wt);
-            }
-
-            // Add splat value at pixel.
-            let splat_rgb = xyz_to_rgb(&self.pixels[pixel_offset].splat_xyz);
-            rgb[rgb_offset] += splat_scale * splat_rgb[0];
-            rgb[rgb_offset + 1] += splat_scale * splat_rgb[1];
-            rgb[rgb_offset + 2] += splat_scale * splat_rgb[2];
-
-            // Scale pixel value by `scale`.
-            rgb[rgb_offset] *= self.scale;
-            rgb[rgb_offset + 1] *= self.scale;
-            rgb[rgb_offset + 2] *= self.scale;
+    /// Returns the final weighted RGB value of a pixel.
+    ///
+    /// * `pixel`       - The pixel.
+    /// * `splat_scale` - Scale factor for `add_splat()`.
+    fn pixel_rgb(&self, pixel: &Pixel, splat_scale: Float) -> [Float; 3] {
+        // Convert pixel XYZ color to RGB.
+        let mut rgb = xyz_to_rgb(&self.white_balance(&pixel.xyz()));
+
+        // Normalize pixel with weight sum.
+        let filter_weight_sum = pixel.filter_weight();
+        if filter_weight_sum != 0.0 {
+            let inv_wt = 1.0 / filter_weight_sum;
+            rgb[0] = max(0.0, rgb[0] * inv_wt);
+            rgb[1] = max(0.0, rgb[1] * inv_wt);
+            rgb[2] = max(0.0, rgb[2] * inv_wt);
         }
 
-        // Write RGB image
-        if let Err(err) = write_image(&self.filename, &rgb, &self.cropped_pixel_bounds) {
-            panic!("Error writing output image {}. {:}.", self.filename, err);
+        // Add splat value at pixel.
+        let splat_rgb = xyz_to_rgb(&self.white_balance(&pixel.splat_xyz()));
+        rgb[0] += splat_scale * splat_rgb[0];
+        rgb[1] += splat_scale * splat_rgb[1];
+        rgb[2] += splat_scale * splat_rgb[2];
+
+        // Scale pixel value by `scale`.
+        rgb[0] *= self.scale;
+        rgb[1] *= self.scale;
+        rgb[2] *= self.scale;
+        rgb
+    }
+
+    /// Returns an XYZ colour adapted with the film's white balance.
+    ///
+    /// * `xyz` - The XYZ colour.
+    fn white_balance(&self, xyz: &[Float; 3]) -> [Float; 3] {
+        match &self.white_balance {
+            Some(wb) => wb.apply(xyz),
+            None => *xyz,
         }
     }
 }
@@ -365,9 +641,18 @@ impl From<(&ParamSet, ArcFilter)> for Film {
             yres = max(1, yres / 4);
         }
 
-        let cr = params.find_float("cropwindow");
+        // A crop window or pixel bounds from the command line override those
+        // of the scene. Pixel bounds take precedence over a crop window.
+        let (cr, pb) = match (OPTIONS.crop_window, OPTIONS.pixel_bounds) {
+            (_, Some(pb)) => (vec![], pb.to_vec()),
+            (Some(cw), None) => (vec![cw[0][0], cw[0][1], cw[1][0], cw[1][1]], vec![]),
+            (None, None) => (
+                params.find_float("cropwindow"),
+                params.find_int("pixelbounds"),
+            ),
+        };
         let cwi = cr.len();
-        let mut crop = Bounds2f::default();
+        let mut crop = Bounds2f::new(Point2f::new(0.0, 0.0), Point2f::new(1.0, 1.0));
         if cwi == 4 {
             crop.p_min.x = clamp(min(cr[0], cr[1]), 0.0, 1.0);
             crop.p_max.x = clamp(max(cr[0], cr[1]), 0.0, 1.0);
@@ -375,23 +660,12 @@ impl From<(&ParamSet, ArcFilter)> for Film {
             crop.p_max.y = clamp(max(cr[2], cr[3]), 0.0, 1.0);
         } else if cwi > 0 {
             panic!("{} values supplied for 'cropwindow'. Expected 4.", cwi);
-        } else {
-            crop = Bounds2f::new(
-                Point2f::new(
-                    clamp(OPTIONS.crop_window[0][0], 0.0, 1.0),
-                    clamp(OPTIONS.crop_window[1][0], 0.0, 1.0),
-                ),
-                Point2f::new(
-                    clamp(OPTIONS.crop_window[0][1], 0.0, 1.0),
-                    clamp(OPTIONS.crop_window[1][1], 0.0, 1.0),
-                ),
-            );
         }
 
         let scale = params.find_one_float("scale", 1.0);
         let diagonal = params.find_one_float("diagonal", 35.0);
         let max_sample_luminance = params.find_one_float("maxsampleluminance", INFINITY);
-        Self::new(
+        let mut film = Self::new(
             &Point2i::new(xres, yres),
             &crop,
             Arc::clone(&filter),
@@ -399,6 +673,121 @@ impl From<(&ParamSet, ArcFilter)> for Film {
             &filename,
             Some(scale),
             Some(max_sample_luminance),
-        )
+        );
+
+        if pb.len() == 4 {
+            film.set_pixel_bounds(&Bounds2i::new(
+                Point2i::new(pb[0], pb[2]),
+                Point2i::new(pb[1], pb[3]),
+            ));
+        } else if !pb.is_empty() {
+            error!(
+                "{} values supplied for 'pixelbounds'. Expected 4.",
+                pb.len()
+            );
+        }
+
+        // Options for the main output.
+        let output = &mut film.outputs[0];
+        let bit_depth = params.find_one_int("bitdepth", 0);
+        if bit_depth != 0 {
+            output.bit_depth = BitDepth::from_bits(bit_depth);
+            if output.bit_depth.is_none() {
+                error!("Unsupported 'bitdepth' {}. Using the default.", bit_depth);
+            }
+        }
+        output.dither = params.find_one_bool("dither", false);
+        let channels = params.find_one_string("channels", String::from("rgb"));
+        match OutputChannel::parse_list(&channels) {
+            Ok(channels) => output.channels = channels,
+            Err(err) => error!("{}. Writing RGB.", err),
+        }
+
+        // White balance from a colour temperature or reference white.
+        let adaptation = params.find_one_string("adaptation", String::from("bradford"));
+        let adaptation = ChromaticAdaptation::parse(&adaptation).unwrap_or_else(|err| {
+            error!("{}. Using 'bradford'.", err);
+            ChromaticAdaptation::Bradford
+        });
+        let white_point = params.find_float("whitepoint");
+        let temperature = params.find_one_float("whitebalance", 0.0);
+        if white_point.len() == 2 {
+            let xy = [white_point[0], white_point[1]];
+            film.white_balance = Some(WhiteBalance::from_chromaticity(&xy, adaptation));
+        } else if !white_point.is_empty() {
+            error!(
+                "{} values supplied for 'whitepoint'. Expected 2.",
+                white_point.len()
+            );
+        } else if temperature > 0.0 {
+            let tint = params.find_one_float("tint", 0.0);
+            film.white_balance = Some(WhiteBalance::from_temperature(
+                temperature,
+                tint,
+                adaptation,
+            ));
+        }
+
+        // Compensated sums avoid precision loss at very high sample counts.
+        let accumulation = params.find_one_string("accumulation", String::from("plain"));
+        film.accumulation = Accumulation::parse(&accumulation).unwrap_or_else(|err| {
+            error!("{}. Using 'plain'.", err);
+            Accumulation::Plain
+        });
+
+        // Frames rendered from the same view accumulate samples.
+        film.temporal = params.find_one_bool("temporal", false);
+        film.temporal_threshold = params.find_one_float("temporalthreshold", 1e-4);
+        film.temporal_feedback = params.find_one_float("temporalfeedback", 0.5);
+        if !film.temporal {
+            FILM_HISTORY.lock().unwrap().reset();
+        }
+
+        // Re-render the pixels affected by the crop window and composite them
+        // into an existing image.
+        let composite =
+            params.find_one_string("composite", OPTIONS.composite.clone().unwrap_or_default());
+        if !composite.is_empty() {
+            film.set_composite(&composite);
+        }
+
+        // Light path expression AOVs.
+        let mut aovs = vec![];
+        for spec in params.find_string("lpe") {
+            match LightPathAov::parse(&spec) {
+                Ok(aov) => aovs.push(aov),
+                Err(err) => error!("{}. Ignoring AOV '{}'.", err, spec),
+            }
+        }
+        if !aovs.is_empty() {
+            film.set_aovs(aovs);
+        }
+
+        // Auxiliary channels describing the first surface seen through each
+        // pixel.
+        let mut auxiliary = vec![];
+        for name in params.find_string("auxiliary") {
+            match AuxiliaryChannel::parse(&name) {
+                Ok(channel) if !auxiliary.contains(&channel) => auxiliary.push(channel),
+                Ok(_) => warn!("Auxiliary channel '{}' specified more than once.", name),
+                Err(err) => error!("{}. Ignoring it.", err),
+            }
+        }
+        film.set_auxiliary(auxiliary);
+
+        // Stream the image to a tev viewer while rendering.
+        if let Some(address) = OPTIONS.display_server.as_ref() {
+            film.set_display_server(address);
+        }
+
+        // Additional outputs written from the same film.
+        for spec in params.find_string("outputs") {
+            match ImageOutput::parse(&spec) {
+                Ok(output) => film.outputs.push(output),
+                Err(err) => error!("{}. Ignoring output '{}'.", err, spec),
+            }
+        }
+
+        film
     }
 }
@@ -4,6 +4,7 @@
 use crate::geometry::*;
 use crate::medium::*;
 use crate::pbrt::*;
+use crate::primitive::*;
 use std::sync::Arc;
 
 mod medium_interaction;
@@ -30,6 +31,15 @@ impl<'a> Interaction<'a> {
             Self::Medium { mi } => &mi.hit,
         }
     }
+
+    /// Returns the primitive of a surface interaction. Medium interactions
+    /// are not on a primitive.
+    pub fn get_primitive(&self) -> Option<&'a dyn Primitive> {
+        match self {
+            Self::Surface { si } => si.primitive,
+            Self::Medium { .. } => None,
+        }
+    }
 }
 
 /// Hit provides common data shared by implementations of `Interaction` trait.
@@ -157,9 +167,9 @@ impl Hit {
     pub fn get_medium(&self) -> Option<ArcMedium> {
         if let Some(mi) = self.medium_interface.clone() {
             if mi.is_medium_transition() {
-                mi.inside
-            } else {
                 None
+            } else {
+                mi.inside
             }
         } else {
             None
@@ -63,6 +63,10 @@ pub struct SurfaceInteraction<'a> {
 
     /// The primitive.
     pub primitive: Option<&'a dyn Primitive>,
+
+    /// Transformation from the space of the object instance containing the
+    /// shape to world space or `None` if the shape is not instanced.
+    pub instance_to_world: Option<Transform>,
 }
 
 impl<'a> SurfaceInteraction<'a> {
@@ -119,6 +123,23 @@ impl<'a> SurfaceInteraction<'a> {
             bsdf: None,
             bssrdf: None,
             primitive,
+            instance_to_world: None,
+        }
+    }
+
+    /// Returns the transformation from world space to the shape's object
+    /// space including any object instance transformation. Points mapped to
+    /// object space are identical for every instance of an object. The
+    /// shape's precomputed world to object transformation is used so only
+    /// instanced shapes need the transformations composed.
+    pub fn world_to_object(&self) -> Transform {
+        let instance_to_object = match self.shape_data.world_to_object.as_ref() {
+            Some(world_to_object) => **world_to_object,
+            None => self.shape_data.object_to_world.inverse(),
+        };
+        match self.instance_to_world {
+            Some(instance_to_world) => instance_to_object * instance_to_world.inverse(),
+            None => instance_to_object,
         }
     }
 
@@ -136,21 +157,16 @@ impl<'a> SurfaceInteraction<'a> {
         dndv: Normal3f,
         orientation_is_authoritative: bool,
     ) {
-        // Compute normal.
-        let mut hit_n = self.hit.n;
+        // Compute the shading normal and orient it and the geometric normal
+        // to the same hemisphere.
         let mut shading_n = Normal3::from(dpdu.cross(&dpdv)).normalize();
-
-        if self.shape_data.reverse_orientation ^ self.shape_data.transform_swaps_handedness {
-            shading_n = -self.shading.n;
-            if orientation_is_authoritative {
-                hit_n = hit_n.face_forward(&shading_n.into());
-            } else {
-                shading_n = shading_n.face_forward(&hit_n.into());
-            }
+        if orientation_is_authoritative {
+            self.hit.n = self.hit.n.face_forward(&shading_n.into());
+        } else {
+            shading_n = shading_n.face_forward(&self.hit.n.into());
         }
 
         // Initialize shading partial derivative values.
-        self.hit.n = hit_n;
         self.shading = Shading::new(shading_n, dpdu, dpdv, dndu, dndv);
     }
 
@@ -269,6 +285,21 @@ impl<'a> SurfaceInteraction<'a> {
             Spectrum::new(0.0)
         }
     }
+
+    /// Returns the emitted radiance at a surface point intersected by a ray
+    /// for an area light if the light's emission is visible to rays at the
+    /// given path depth.
+    ///
+    /// * `w`     - The outgoing direction.
+    /// * `depth` - Path depth of the ray; 0 for camera rays.
+    pub fn le_at_depth(&self, w: &Vector3f, depth: usize) -> Spectrum {
+        match self.primitive.and_then(|p| p.get_area_light()) {
+            Some(area_light) if area_light.visibility().emission_visible(depth) => {
+                area_light.l(&self.hit, w)
+            }
+            _ => Spectrum::new(0.0),
+        }
+    }
 }
 
 /// Shading geometry used for perturbed values for bump mapping.
@@ -313,3 +344,65 @@ impl Shading {
         }
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an interaction on the z = 0 plane with normal +z, or -z if the
+    /// orientation is reversed.
+    fn interaction(reverse_orientation: bool) -> SurfaceInteraction<'static> {
+        let identity = Arc::new(Transform::default());
+        let shape_data = Arc::new(ShapeData::new(
+            Arc::clone(&identity),
+            Some(identity),
+            reverse_orientation,
+        ));
+        SurfaceInteraction::new(
+            Point3f::default(),
+            Vector3f::default(),
+            Point2f::default(),
+            Vector3f::new(0.0, 0.0, 1.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Normal3f::default(),
+            Normal3f::default(),
+            0.0,
+            shape_data,
+            None,
+        )
+    }
+
+    #[test]
+    fn shading_geometry_uses_new_normal() {
+        // The shading normal follows the new partial derivatives.
+        let mut si = interaction(false);
+        let dpdu = Vector3f::new(1.0, 0.0, -1.0);
+        let dpdv = Vector3f::new(0.0, 1.0, 0.0);
+        si.set_shading_geometry(dpdu, dpdv, si.dndu, si.dndv, false);
+        let expected = Normal3f::new(1.0, 0.0, 1.0).normalize();
+        assert!((si.shading.n - expected).length() < 1e-6);
+        assert_eq!(si.hit.n, Normal3f::new(0.0, 0.0, 1.0));
+
+        // With reversed orientation the shading normal is flipped to the
+        // side of the geometric normal.
+        let mut si = interaction(true);
+        si.set_shading_geometry(dpdu, dpdv, si.dndu, si.dndv, false);
+        assert!((si.shading.n + expected).length() < 1e-6);
+        assert_eq!(si.hit.n, Normal3f::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn authoritative_shading_normal_flips_geometric_normal() {
+        let mut si = interaction(false);
+        let dpdu = Vector3f::new(1.0, 0.0, 0.0);
+        let dpdv = Vector3f::new(0.0, -1.0, 0.0);
+        si.set_shading_geometry(dpdu, dpdv, si.dndu, si.dndv, true);
+        assert_eq!(si.shading.n, Normal3f::new(0.0, 0.0, -1.0));
+        assert_eq!(si.hit.n, Normal3f::new(0.0, 0.0, -1.0));
+    }
+}
@@ -1,4 +1,7 @@
 //! Geometry
+//!
+//! The math types are defined in the `pbrt-math` crate and re-exported here
+//! with the rays, interactions and shapes used by the renderer.
 
 // Define macros for property based testing.
 #[cfg(test)]
@@ -13,30 +16,6 @@ macro_rules! prop_range {
     };
 }
 
-#[cfg(test)]
-#[macro_export]
-macro_rules! prop_non_zero_range {
-    ($name: ident, $t: ty, $r: expr) => {
-        prop_compose! {
-            fn $name()(f in $r.prop_filter("non-zero", |x| !(*x).is_zero())) -> $t {
-                f
-            }
-        }
-    };
-}
-
-#[cfg(test)]
-#[macro_export]
-macro_rules! prop_vector2 {
-    ($name: ident, $t: ty, $xr: expr, $yr: expr) => {
-        prop_compose! {
-            fn $name()(x in $xr, y in $yr) -> Vector2<$t> {
-                Vector2 { x, y }
-            }
-        }
-    };
-}
-
 #[cfg(test)]
 #[macro_export]
 macro_rules! prop_vector3 {
@@ -49,30 +28,6 @@ macro_rules! prop_vector3 {
     };
 }
 
-#[cfg(test)]
-#[macro_export]
-macro_rules! prop_normal3 {
-    ($name: ident, $t: ty, $xr: expr, $yr: expr, $zr: expr) => {
-        prop_compose! {
-            fn $name()(x in $xr, y in $yr, z in $zr) -> Normal3<$t> {
-                Normal3 { x, y, z }
-            }
-        }
-    };
-}
-
-#[cfg(test)]
-#[macro_export]
-macro_rules! prop_point2 {
-    ($name: ident, $t: ty, $xr: expr, $yr: expr) => {
-        prop_compose! {
-            fn $name()(x in $xr, y in $yr) -> Point2<$t> {
-                Point2 { x, y }
-            }
-        }
-    };
-}
-
 #[cfg(test)]
 #[macro_export]
 macro_rules! prop_point3 {
@@ -85,41 +40,18 @@ macro_rules! prop_point3 {
     };
 }
 
-mod animated_transform;
-mod bounds2;
-mod bounds3;
-mod common;
-mod coordinate_system;
 mod interaction;
-mod interval;
-mod matrix4x4;
-mod normal;
-mod point2;
-mod point3;
-mod quaternion;
 mod ray;
 mod shape;
-mod transform;
-mod util;
-mod vector2;
-mod vector3;
+#[cfg(feature = "simd")]
+mod simd;
+mod transform_ray;
 
 // Re-export
-pub use animated_transform::*;
-pub use bounds2::*;
-pub use bounds3::*;
-pub use common::*;
-pub use coordinate_system::*;
 pub use interaction::*;
-pub use interval::*;
-pub use matrix4x4::*;
-pub use normal::*;
-pub use point2::*;
-pub use point3::*;
-pub use quaternion::*;
+pub use pbrt_math::geometry::*;
 pub use ray::*;
 pub use shape::*;
-pub use transform::*;
-pub use util::*;
-pub use vector2::*;
-pub use vector3::*;
+#[cfg(feature = "simd")]
+pub use simd::*;
+pub use transform_ray::*;
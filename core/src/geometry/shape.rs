@@ -6,6 +6,10 @@ use crate::low_discrepency::radical_inverse;
 use crate::pbrt::*;
 use std::sync::Arc;
 
+/// Light samples closer than this to the reference point are discarded to
+/// avoid unbounded solid angle PDFs. Scaled by the scene scale.
+pub const MIN_LIGHT_SAMPLE_DISTANCE: Float = 1e-6;
+
 /// Shape common functions
 pub trait Shape {
     /// Returns the underlying shape data.
@@ -37,9 +41,23 @@ pub trait Shape {
         self.intersect(r, test_alpha_texture).is_some()
     }
 
+    /// Returns the world space vertices of a triangle without alpha masks so
+    /// that accelerators can build their own representation of it. Other
+    /// shapes return `None`.
+    fn triangle_vertices(&self) -> Option<[Point3f; 3]> {
+        None
+    }
+
     /// Returns the surface area of the shape in object space.
     fn area(&self) -> Float;
 
+    /// Returns the memory used by the shape in bytes. Shapes that share data
+    /// with other shapes include their share of it. Defaults to the size of
+    /// the shape itself.
+    fn memory(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+
     /// Sample a point on the surface and return the PDF with respect to area on
     /// the surface.
     ///
@@ -51,13 +69,15 @@ pub trait Shape {
     /// Sample a point on the shape given a reference point and return the PDF
     /// with respect to the solid angle from ref.
     ///
-    /// * `hit` - Reference point on shape.
-    /// * `u`   - Sample value to use.
-    fn sample_solid_angle(&self, hit: &Hit, u: &Point2f) -> (Hit, Float) {
+    /// * `hit`          - Reference point on shape.
+    /// * `u`            - Sample value to use.
+    /// * `min_distance` - Samples this close to the reference point are
+    ///                    discarded.
+    fn sample_solid_angle(&self, hit: &Hit, u: &Point2f, min_distance: Float) -> (Hit, Float) {
         let (intr, mut pdf) = self.sample_area(u);
         let mut wi = intr.p - hit.p;
 
-        if wi.length_squared() == 0.0 {
+        if wi.length_squared() <= min_distance * min_distance {
             pdf = 0.0;
         } else {
             wi = wi.normalize();
@@ -81,9 +101,11 @@ pub trait Shape {
 
     /// Returns the PDF with respect to solid angle.
     ///
-    /// * `hit` - The interaction hit point.
-    /// * `wi`  - The incident direction.
-    fn pdf_solid_angle(&self, hit: &Hit, wi: &Vector3f) -> Float {
+    /// * `hit`          - The interaction hit point.
+    /// * `wi`           - The incident direction.
+    /// * `min_distance` - Samples this close to the reference point have zero
+    ///                    probability.
+    fn pdf_solid_angle(&self, hit: &Hit, wi: &Vector3f, min_distance: Float) -> Float {
         // Intersect sample ray with area light geometry.
         let ray = hit.spawn_ray(wi);
 
@@ -95,6 +117,10 @@ pub trait Shape {
             isect: isect_light,
         }) = self.intersect(&ray, false)
         {
+            if hit.p.distance_squared(isect_light.hit.p) <= min_distance * min_distance {
+                return 0.0;
+            }
+
             // Convert light sample weight to solid angle measure.
             let pdf = hit.p.distance_squared(isect_light.hit.p)
                 / (isect_light.hit.n.abs_dot(&(-*wi)) * self.area());
@@ -130,7 +156,7 @@ pub trait Shape {
 
         for i in 0..n_samples {
             let u = Point2f::new(radical_inverse(0, i as u64), radical_inverse(1, i as u64));
-            let (p_shape, pdf) = self.sample_solid_angle(&hit, &u);
+            let (p_shape, pdf) = self.sample_solid_angle(&hit, &u, 0.0);
             let ray = Ray::new(*p, p_shape.p - *p, 0.999, 0.0, None);
             if pdf > 0.0 && !self.intersect_p(&ray, true) {
                 solid_angle += 1.0_f64 / pdf as f64;
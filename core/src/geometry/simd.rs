@@ -0,0 +1,292 @@
+//! SIMD Intersection Tests
+//!
+//! 4-wide ray-bounds and ray-triangle tests used by the accelerators to
+//! cull nodes and primitives before the exact scalar tests run. They are
+//! conservative: a lane that is reported missed is guaranteed to miss, but a
+//! lane that is reported hit may still miss the exact test.
+
+use crate::geometry::*;
+use crate::pbrt::*;
+use wide::{f32x4, CmpGe, CmpLe, CmpLt};
+
+/// Relative tolerance used to keep the triangle test conservative.
+const TRIANGLE_EPSILON: Float = 1e-3;
+
+/// Returns a bit mask with the lanes whose comparison result is set.
+///
+/// * `m` - Result of a lane wise comparison.
+fn to_mask(m: f32x4) -> u8 {
+    m.move_mask() as u8
+}
+
+/// Four rays stored one component per SIMD lane.
+#[derive(Copy, Clone)]
+pub struct RayPacket4 {
+    /// Origins.
+    o: [f32x4; 3],
+
+    /// Reciprocals of the directions.
+    inv_dir: [f32x4; 3],
+
+    /// Lanes holding a ray.
+    valid: u8,
+}
+
+impl RayPacket4 {
+    /// Create a new `RayPacket4` from up to 4 rays. Missing lanes are never
+    /// reported as hits.
+    ///
+    /// * `rays` - The rays.
+    pub fn new(rays: &[&Ray]) -> Self {
+        debug_assert!(rays.len() <= 4);
+
+        let mut o = [[0.0; 4]; 3];
+        let mut inv_dir = [[0.0; 4]; 3];
+        for (lane, r) in rays.iter().enumerate() {
+            for axis in 0..3 {
+                o[axis][lane] = r.o[axis];
+                inv_dir[axis][lane] = 1.0 / r.d[axis];
+            }
+        }
+
+        Self {
+            o: [o[0].into(), o[1].into(), o[2].into()],
+            inv_dir: [inv_dir[0].into(), inv_dir[1].into(), inv_dir[2].into()],
+            valid: ((1_u16 << rays.len()) - 1) as u8,
+        }
+    }
+
+    /// Returns a bit mask of the rays that intersect the bounding box.
+    ///
+    /// * `bounds` - The bounding box.
+    /// * `t_max`  - The maximum parametric distance of each ray.
+    pub fn intersect_p(&self, bounds: &Bounds3f, t_max: [Float; 4]) -> u8 {
+        // Update t_far to ensure robust ray–bounds intersection like the
+        // scalar test.
+        let robust = f32x4::splat(1.0 + 2.0 * gamma(3));
+
+        let mut t0 = f32x4::splat(0.0);
+        let mut t1 = f32x4::from(t_max);
+        for axis in 0..3 {
+            let t_near = (f32x4::splat(bounds.p_min[axis]) - self.o[axis]) * self.inv_dir[axis];
+            let t_far = (f32x4::splat(bounds.p_max[axis]) - self.o[axis]) * self.inv_dir[axis];
+            t0 = t0.max(t_near.min(t_far));
+            t1 = t1.min(t_near.max(t_far) * robust);
+        }
+
+        to_mask(t0.cmp_le(t1)) & self.valid
+    }
+}
+
+/// Four triangles stored one component per SIMD lane.
+#[derive(Copy, Clone)]
+pub struct Triangle4 {
+    /// First vertices.
+    p0: [f32x4; 3],
+
+    /// Edges from the first to the second vertices.
+    e1: [f32x4; 3],
+
+    /// Edges from the first to the third vertices.
+    e2: [f32x4; 3],
+
+    /// Lanes that always have to be tested exactly because they do not hold
+    /// a triangle.
+    always: u8,
+}
+
+impl Triangle4 {
+    /// Create a new `Triangle4` from up to 4 world space triangles. Lanes
+    /// that are missing or `None` are always reported as hits so that they
+    /// fall through to the exact test.
+    ///
+    /// * `triangles` - The triangle vertices.
+    pub fn new(triangles: &[Option<[Point3f; 3]>]) -> Self {
+        debug_assert!(triangles.len() <= 4);
+
+        let mut p0 = [[0.0; 4]; 3];
+        let mut e1 = [[0.0; 4]; 3];
+        let mut e2 = [[0.0; 4]; 3];
+        let mut always = 0xf_u8;
+        for (lane, t) in triangles.iter().enumerate() {
+            if let Some([a, b, c]) = t {
+                for axis in 0..3 {
+                    p0[axis][lane] = a[axis];
+                    e1[axis][lane] = b[axis] - a[axis];
+                    e2[axis][lane] = c[axis] - a[axis];
+                }
+                always &= !(1 << lane);
+            }
+        }
+
+        Self {
+            p0: [p0[0].into(), p0[1].into(), p0[2].into()],
+            e1: [e1[0].into(), e1[1].into(), e1[2].into()],
+            e2: [e2[0].into(), e2[1].into(), e2[2].into()],
+            always,
+        }
+    }
+
+    /// Returns a bit mask of the triangles the ray may intersect using the
+    /// Möller–Trumbore test with a tolerance on the barycentrics and the
+    /// parametric distance.
+    ///
+    /// * `ray` - The ray.
+    pub fn intersect_p(&self, ray: &Ray) -> u8 {
+        let d = [
+            f32x4::splat(ray.d.x),
+            f32x4::splat(ray.d.y),
+            f32x4::splat(ray.d.z),
+        ];
+        let o = [
+            f32x4::splat(ray.o.x),
+            f32x4::splat(ray.o.y),
+            f32x4::splat(ray.o.z),
+        ];
+
+        let p = cross(&d, &self.e2);
+        let det = dot(&self.e1, &p);
+        let t = [o[0] - self.p0[0], o[1] - self.p0[1], o[2] - self.p0[2]];
+        let q = cross(&t, &self.e1);
+
+        // Keep the barycentrics and distance scaled by the determinant and
+        // flip their signs so that the comparisons work for both facings.
+        let sign = det
+            .cmp_lt(f32x4::splat(0.0))
+            .blend(f32x4::splat(-1.0), f32x4::splat(1.0));
+        let abs_det = det.abs();
+        let u = dot(&t, &p) * sign;
+        let v = dot(&d, &q) * sign;
+        let t_hit = dot(&self.e2, &q) * sign;
+
+        // Near parallel rays give unreliable results, so leave them to the
+        // exact test.
+        let d_len = ray.d.length();
+        let e_len = dot(&self.e1, &self.e1).sqrt() * dot(&self.e2, &self.e2).sqrt();
+        let parallel = abs_det.cmp_le(f32x4::splat(TRIANGLE_EPSILON * d_len) * e_len);
+
+        let tol = f32x4::splat(TRIANGLE_EPSILON) * abs_det;
+        let t_tol = tol * dot(&t, &t).sqrt() / f32x4::splat(d_len);
+        let t_max = f32x4::splat(ray.t_max * (1.0 + TRIANGLE_EPSILON));
+        let hit = u.cmp_ge(-tol)
+            & v.cmp_ge(-tol)
+            & (u + v).cmp_le(abs_det + tol)
+            & t_hit.cmp_ge(-t_tol)
+            & t_hit.cmp_le(t_max * abs_det);
+
+        to_mask(hit | parallel) | self.always
+    }
+}
+
+/// Returns the lane wise cross product of two vectors.
+///
+/// * `a` - The first vector.
+/// * `b` - The second vector.
+fn cross(a: &[f32x4; 3], b: &[f32x4; 3]) -> [f32x4; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Returns the lane wise dot product of two vectors.
+///
+/// * `a` - The first vector.
+/// * `b` - The second vector.
+fn dot(a: &[f32x4; 3], b: &[f32x4; 3]) -> f32x4 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray(o: Point3f, d: Vector3f, t_max: Float) -> Ray {
+        Ray::new(o, d, t_max, 0.0, None)
+    }
+
+    #[test]
+    fn packet_matches_scalar_bounds_test() {
+        let bounds = Bounds3f::new(Point3f::new(-1.0, -1.0, -1.0), Point3f::new(1.0, 1.0, 1.0));
+        let o = Point3f::new(0.0, 0.0, -5.0);
+        let z = Vector3f::new(0.0, 0.0, 1.0);
+        let rays = [
+            ray(o, z, INFINITY),
+            ray(o, Vector3f::new(0.0, 1.0, 1.0), INFINITY),
+            ray(o, z, 3.0),
+            ray(
+                Point3f::new(0.5, 0.5, 0.5),
+                Vector3f::new(-1.0, 0.2, 0.1),
+                0.1,
+            ),
+        ];
+        let packet = RayPacket4::new(&[&rays[0], &rays[1], &rays[2], &rays[3]]);
+        let t_max = [rays[0].t_max, rays[1].t_max, rays[2].t_max, rays[3].t_max];
+        let mask = packet.intersect_p(&bounds, t_max);
+        assert_eq!(mask, 0b1001);
+        for (lane, r) in rays.iter().enumerate() {
+            assert_eq!(mask & (1 << lane) != 0, bounds.intersect_p(r).is_some());
+        }
+
+        let packet = RayPacket4::new(&[&rays[0]]);
+        assert_eq!(packet.intersect_p(&bounds, t_max), 0b0001);
+    }
+
+    #[test]
+    fn triangle_test_is_conservative() {
+        let tri = [
+            Point3f::new(0.0, 0.0, 0.0),
+            Point3f::new(1.0, 0.0, 0.0),
+            Point3f::new(0.0, 1.0, 0.0),
+        ];
+        let back = [tri[0], tri[2], tri[1]];
+        let far = [
+            Point3f::new(0.0, 0.0, 10.0),
+            Point3f::new(1.0, 0.0, 10.0),
+            Point3f::new(0.0, 1.0, 10.0),
+        ];
+        let triangles = Triangle4::new(&[Some(tri), Some(back), Some(far)]);
+
+        // Lane 3 holds no triangle and is always reported.
+        let r = ray(
+            Point3f::new(0.25, 0.25, -1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            INFINITY,
+        );
+        assert_eq!(triangles.intersect_p(&r), 0b1111);
+
+        let r = ray(
+            Point3f::new(0.25, 0.25, -1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            5.0,
+        );
+        assert_eq!(triangles.intersect_p(&r), 0b1011);
+
+        let r = ray(
+            Point3f::new(0.75, 0.75, -1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            INFINITY,
+        );
+        assert_eq!(triangles.intersect_p(&r), 0b1000);
+
+        let r = ray(
+            Point3f::new(0.25, 0.25, 1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            INFINITY,
+        );
+        assert_eq!(triangles.intersect_p(&r), 0b1100);
+
+        // A ray hitting an edge exactly must not be culled.
+        let r = ray(
+            Point3f::new(0.5, 0.5, -1.0),
+            Vector3f::new(0.0, 0.0, 1.0),
+            INFINITY,
+        );
+        assert_eq!(triangles.intersect_p(&r), 0b1111);
+    }
+}
@@ -0,0 +1,284 @@
+//! Ray Transformations
+//!
+//! Transformations of rays and surface interactions and ray intersections
+//! with bounding boxes. These extend the math types with operations on the
+//! renderer's ray and interaction types.
+
+use crate::geometry::*;
+use crate::pbrt::*;
+use std::mem::swap;
+use std::sync::Arc;
+
+/// Applies a `Transform` to rays and surface interactions.
+pub trait TransformRay {
+    /// Applies transformation to a given ray.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray(&self, r: &Ray) -> Ray;
+
+    /// Returns the transformed ray with absolute errors due to applying the
+    /// transformation to its origin and direction.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray_with_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f);
+
+    /// Transforms the ray taking into account absolute errors due to applying the
+    /// transformation to its origin and direction.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray_with_abs_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f);
+
+    /// Applies transformation to a given surface interaction.
+    ///
+    /// * `si` - The surface interaction.
+    fn transform_surface_interaction<'a>(
+        &self,
+        si: &SurfaceInteraction<'a>,
+    ) -> SurfaceInteraction<'a>;
+}
+
+impl TransformRay for Transform {
+    /// Applies transformation to a given ray.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray(&self, r: &Ray) -> Ray {
+        let (mut o, o_error) = self.transform_point_with_error(&r.o);
+        let d = self.transform_vector(&r.d);
+
+        // Offset ray origin to edge of error bounds and compute t_max.
+        let length_squared = d.length_squared();
+        let mut t_max = r.t_max;
+        if length_squared > 0.0 {
+            let dt = d.abs().dot(&o_error) / length_squared;
+            o += d * dt;
+            t_max -= dt;
+        }
+
+        // Handle differentials.
+        if let Some(diff) = r.differentials {
+            let td = RayDifferential::new(
+                self.transform_point(&diff.rx_origin),
+                self.transform_point(&diff.ry_origin),
+                self.transform_vector(&diff.rx_direction),
+                self.transform_vector(&diff.ry_direction),
+            );
+            Ray::new_with_differentials(o, d, t_max, r.time, td, r.medium.clone())
+        } else {
+            Ray::new(o, d, t_max, r.time, r.medium.clone())
+        }
+    }
+
+    /// Returns the transformed ray with absolute errors due to applying the
+    /// transformation to its origin and direction.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray_with_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f) {
+        let (mut o, o_error) = self.transform_point_with_error(&r.o);
+        let (d, d_error) = self.transform_vector_with_error(&r.d);
+
+        // Offset ray origin to edge of error bounds.
+        let length_sqquared = d.length_squared();
+        if length_sqquared > 0.0 {
+            let dt = d.abs().dot(&o_error) / length_sqquared;
+            o += d * dt;
+        }
+
+        // Handle differentials.
+        if let Some(diff) = r.differentials {
+            let td = RayDifferential::new(
+                self.transform_point(&diff.rx_origin),
+                self.transform_point(&diff.ry_origin),
+                self.transform_vector(&diff.rx_direction),
+                self.transform_vector(&diff.ry_direction),
+            );
+
+            (
+                Ray::new_with_differentials(o, d, r.t_max, r.time, td, r.medium.clone()),
+                o_error,
+                d_error,
+            )
+        } else {
+            (
+                Ray::new(o, d, r.t_max, r.time, r.medium.clone()),
+                o_error,
+                d_error,
+            )
+        }
+    }
+
+    /// Transforms the ray taking into account absolute errors due to applying the
+    /// transformation to its origin and direction.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray_with_abs_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f) {
+        let (mut tr, o_error_in, d_error_in) = self.transform_ray_with_error(r);
+
+        // Calculate error in result for origin and direction.
+        let o_error_out = self.transform_point_abs_error(&tr.o, &o_error_in);
+        let d_error_out = self.transform_vector_abs_error(&tr.d, &d_error_in);
+        // Offset ray origin to edge of error bounds.
+        let length_sqquared = tr.d.length_squared();
+        if length_sqquared > 0.0 {
+            let dt = tr.d.abs().dot(&o_error_out) / length_sqquared;
+            tr.o += tr.d * dt;
+        }
+
+        (tr, o_error_out, d_error_out)
+    }
+
+    /// Applies transformation to a given surface interaction.
+    ///
+    /// * `si` - The surface interaction.
+    fn transform_surface_interaction<'a>(
+        &self,
+        si: &SurfaceInteraction<'a>,
+    ) -> SurfaceInteraction<'a> {
+        // Transform p and p_error in SurfaceInteraction
+        let (p, p_error) = self.transform_point_with_error(&si.hit.p);
+        let instance_to_world = si.instance_to_world;
+
+        // Transform remaining members of SurfaceInteraction.
+        let mut ret = SurfaceInteraction::new(
+            p,
+            p_error,
+            si.uv,
+            self.transform_vector(&si.hit.wo).normalize(),
+            self.transform_vector(&si.dpdu),
+            self.transform_vector(&si.dpdv),
+            self.transform_normal(&si.dndu),
+            self.transform_normal(&si.dndv),
+            si.hit.time,
+            Arc::clone(&si.shape_data),
+            si.primitive,
+        );
+
+        // Transform n in SurfaceInteraction.hit. Like normals computed from
+        // the transformed partial derivatives, it is flipped when the
+        // transformation swaps handedness.
+        let flip = if self.swaps_handedness() { -1.0 } else { 1.0 };
+        let n = self.transform_normal(&si.hit.n).normalize() * flip;
+        ret.hit.n = n;
+
+        // Handle transformations for shading parameters..
+        ret.shading = Shading::new(
+            self.transform_normal(&si.shading.n).normalize() * flip,
+            self.transform_vector(&si.shading.dpdu),
+            self.transform_vector(&si.shading.dpdv),
+            self.transform_normal(&si.shading.dndu),
+            self.transform_normal(&si.shading.dndv),
+        );
+        ret.shading.n = ret.shading.n.face_forward(&Vector3::from(n));
+        ret.instance_to_world = instance_to_world;
+
+        ret
+    }
+}
+
+/// Applies an `AnimatedTransform` to rays.
+pub trait AnimatedTransformRay {
+    /// Applies animated transformation to a given ray.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray(&self, r: &Ray) -> Ray;
+}
+
+impl AnimatedTransformRay for AnimatedTransform {
+    /// Applies animated transformation to a given ray.
+    ///
+    /// * `r` - The ray.
+    fn transform_ray(&self, r: &Ray) -> Ray {
+        self.interpolate(r.time).transform_ray(r)
+    }
+}
+
+/// Intersects rays with a bounding box.
+pub trait IntersectRay {
+    /// Returns the near and far ray parameters where it intersects the bounding
+    /// box. If no intersection occurs `None` is returned.
+    ///
+    /// * `ray` - The ray
+    fn intersect_p(&self, ray: &Ray) -> Option<(Float, Float)>;
+
+    /// Uses the reciprocal of a rays direction and returns `true` if it
+    /// intersects the bounding box; otherwise `false`.
+    ///
+    /// * `ray`        - The ray.
+    /// * `inv_dir`    - Reciprocal of `ray`'s direction.
+    /// * `dir_is_neg` - Ray direction is negative.
+    fn intersect_p_inv(&self, ray: &Ray, inv_dir: &Vector3f, dir_is_neg: [u8; 3]) -> bool;
+}
+
+impl<T> IntersectRay for Bounds3<T>
+where
+    T: num_traits::Float + Copy + PartialOrd + Into<Float>,
+{
+    /// Returns the near and far ray parameters where it intersects the bounding
+    /// box. If no intersection occurs `None` is returned.
+    ///
+    /// * `ray` - The ray
+    fn intersect_p(&self, ray: &Ray) -> Option<(Float, Float)> {
+        let mut t0 = 0.0;
+        let mut t1 = ray.t_max;
+
+        for i in 0..3 {
+            // Update interval for ith bounding box slab
+            let inv_ray_dir = 1.0 / ray.d[i];
+
+            let mut t_near = (self.p_min[i].into() - ray.o[i]) * inv_ray_dir;
+            let mut t_far = (self.p_max[i].into() - ray.o[i]) * inv_ray_dir;
+
+            // Update parametric interval from slab intersection values
+            if t_near > t_far {
+                swap(&mut t_near, &mut t_far);
+            }
+
+            // Update tFar to ensure robust ray–bounds intersection
+            t0 = if t_near > t0 { t_near } else { t0 };
+            t1 = if t_far < t1 { t_far } else { t1 };
+            if t0 > t1 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+
+    /// Uses the reciprocal of a rays direction and returns `true` if it
+    /// intersects the bounding box; otherwise `false`.
+    ///
+    /// * `ray`        - The ray.
+    /// * `inv_dir`    - Reciprocal of `ray`'s direction.
+    /// * `dir_is_neg` - Ray direction is negative.
+    #[rustfmt::skip]
+    fn intersect_p_inv(&self, ray: &Ray, inv_dir: &Vector3f, dir_is_neg: [u8; 3]) -> bool {
+        let bounds = *self;
+
+        // Check for ray intersection against and slabs
+        let mut t_min   = (bounds[    dir_is_neg[0]].x.into() - ray.o.x) * inv_dir.x;
+        let mut t_max   = (bounds[1 - dir_is_neg[0]].x.into() - ray.o.x) * inv_dir.x;
+        let t_y_min     = (bounds[    dir_is_neg[1]].y.into() - ray.o.y) * inv_dir.y;
+        let mut t_y_max = (bounds[1 - dir_is_neg[1]].y.into() - ray.o.y) * inv_dir.y;
+
+        // Update t_max and t_y_max to ensure robust bounds intersection
+        let gamma_3 = gamma(3);
+        t_max   *= 1.0 + 2.0 * gamma_3;
+        t_y_max *= 1.0 + 2.0 * gamma_3;
+
+        if t_min > t_y_max || t_y_min > t_max { return false; }
+
+        if t_y_min > t_min { t_min = t_y_min; }
+        if t_y_max < t_max { t_max = t_y_max; }
+
+        // Check for ray intersection against slab
+        let t_z_min = (bounds[    dir_is_neg[2]].z.into() - ray.o.z) * inv_dir.z;
+        let t_z_max = (bounds[1 - dir_is_neg[2]].z.into() - ray.o.z) * inv_dir.z;
+
+        // Update t_z_max to ensure robust bounds intersection
+        if t_min > t_z_max || t_z_min > t_max { return false; }
+
+        if t_z_min > t_min { t_min = t_z_min; }
+        if t_z_max < t_max { t_max = t_z_max; }
+
+        t_min < ray.t_max && t_max > 0.0
+    }
+}
@@ -7,6 +7,7 @@ use exr::prelude as exrs;
 use exr::prelude::*;
 use image::*;
 use regex::Regex;
+use std::collections::BTreeMap;
 use std::result::Result;
 
 /// Stores RGB image data.
@@ -24,6 +25,7 @@ pub struct RGBImage {
 pub fn read_image(path: &str) -> Result<RGBImage, String> {
     match get_extension_from_filename(path) {
         Some(".exr") => read_exr(path),
+        Some(".pfm") => read_pfm(path),
         Some(_extension) => read_8_bit(path),
         None => Err(format!(
             "Can't determine file type from suffix of filename {}.",
@@ -32,25 +34,38 @@ pub fn read_image(path: &str) -> Result<RGBImage, String> {
     }
 }
 
-/// Read a single layer OpenEXR file.
+/// Read a single layer OpenEXR file with half or single precision float
+/// channels. Layers with `R`, `G` and `B` channels are read as RGB; layers
+/// with only a luminance `Y` channel are read as gray.
 ///
 /// * `path` - Input file path.
 fn read_exr(path: &str) -> Result<RGBImage, String> {
     let reader = exrs::read()
         .no_deep_data()
         .largest_resolution_level()
-        .rgba_channels(
-            |resolution, _channels| {
+        .specific_channels()
+        .optional("R", 0.0_f32)
+        .optional("G", 0.0_f32)
+        .optional("B", 0.0_f32)
+        .optional("Y", 0.0_f32)
+        .collect_pixels(
+            |resolution, (r, g, b, y)| {
                 let width = resolution.width();
                 let height = resolution.height();
-                RGBImage {
+                let image = RGBImage {
                     pixels: vec![RGBSpectrum::default(); width * height],
                     resolution: Point2::new(width, height),
-                }
+                };
+                let has_rgb = r.is_some() || g.is_some() || b.is_some();
+                (image, has_rgb, y.is_some())
             },
-            |img, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+            |(img, has_rgb, _), position, (r, g, b, y): (f32, f32, f32, f32)| {
                 let offset = position.y() * img.resolution.x + position.x();
-                img.pixels[offset] = RGBSpectrum::from(vec![r, g, b]);
+                img.pixels[offset] = if *has_rgb {
+                    RGBSpectrum::from(vec![r, g, b])
+                } else {
+                    RGBSpectrum::new(y)
+                };
             },
         )
         .first_valid_layer()
@@ -58,11 +73,95 @@ fn read_exr(path: &str) -> Result<RGBImage, String> {
 
     // Return the `RGBImage`.
     match reader.from_file(path) {
-        Ok(image) => Ok(image.layer_data.channel_data.pixels),
+        Ok(image) => match image.layer_data.channel_data.pixels {
+            (image, true, _) | (image, false, true) => Ok(image),
+            _ => Err(format!("No RGB or Y channels found in {}", path)),
+        },
         Err(err) => Err(format!("{:}", err)),
     }
 }
 
+/// Read a portable float map (PFM) file with one or three channels. Single
+/// channel images are read as gray.
+///
+/// * `path` - Input file path.
+fn read_pfm(path: &str) -> Result<RGBImage, String> {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) => return Err(format!("Error reading PFM file {}. {:}.", path, err)),
+    };
+
+    // Read the identifier, resolution and scale separated by whitespace.
+    let mut pos = 0;
+    let mut header: Vec<String> = Vec::with_capacity(4);
+    while header.len() < 4 {
+        while pos < data.len() && data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while pos < data.len() && !data[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(format!("Premature end of PFM header in {}", path));
+        }
+        header.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+    }
+    // A single whitespace character separates the header from the data.
+    pos += 1;
+
+    let n_channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        id => return Err(format!("Unknown PFM identifier '{}' in {}", id, path)),
+    };
+    let parse = |s: &str| s.parse::<usize>().ok().filter(|&v| v > 0);
+    let (width, height) = match (parse(&header[1]), parse(&header[2])) {
+        (Some(width), Some(height)) => (width, height),
+        _ => return Err(format!("Invalid PFM resolution in {}", path)),
+    };
+    // A negative scale means little endian data.
+    let scale = match header[3].parse::<f32>() {
+        Ok(scale) if scale != 0.0 => scale,
+        _ => return Err(format!("Invalid PFM scale in {}", path)),
+    };
+
+    let n_floats = width * height * n_channels;
+    if data.len() < pos + 4 * n_floats {
+        return Err(format!("Premature end of PFM data in {}", path));
+    }
+    let abs_scale = scale.abs();
+    let floats: Vec<f32> = data[pos..pos + 4 * n_floats]
+        .chunks_exact(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            let v = if scale < 0.0 {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            v * abs_scale
+        })
+        .collect();
+
+    // Flip in y; PFM stores the bottom row first.
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in floats.chunks_exact(width * n_channels).rev() {
+        pixels.extend(row.chunks_exact(n_channels).map(|p| {
+            if n_channels == 1 {
+                RGBSpectrum::new(p[0])
+            } else {
+                RGBSpectrum::from(vec![p[0], p[1], p[2]])
+            }
+        }));
+    }
+
+    Ok(RGBImage {
+        pixels,
+        resolution: Point2::new(width, height),
+    })
+}
+
 /// Read an 8-bit image format.
 ///
 /// * `path` - Input file path.
@@ -94,22 +193,217 @@ fn read_8_bit(path: &str) -> Result<RGBImage, String> {
     Ok(RGBImage { pixels, resolution })
 }
 
+/// Bit depth of the channels in an output image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BitDepth {
+    /// 8-bit integer channels.
+    Eight,
+
+    /// 16-bit integer channels or half precision floats for OpenEXR.
+    Sixteen,
+
+    /// 32-bit floating point channels.
+    ThirtyTwo,
+}
+
+impl BitDepth {
+    /// Returns the bit depth for a number of bits.
+    ///
+    /// * `bits` - Number of bits; 8, 16 or 32.
+    pub fn from_bits(bits: i32) -> Option<Self> {
+        match bits {
+            8 => Some(Self::Eight),
+            16 => Some(Self::Sixteen),
+            32 => Some(Self::ThirtyTwo),
+            _ => None,
+        }
+    }
+}
+
+/// A channel written to an output image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputChannel {
+    /// Red.
+    Red,
+
+    /// Green.
+    Green,
+
+    /// Blue.
+    Blue,
+
+    /// Luminance.
+    Luminance,
+}
+
+impl OutputChannel {
+    /// Returns the channels named by the letters `r`, `g`, `b` and `y` in a
+    /// string such as `rgb` or `y`.
+    ///
+    /// * `s` - The channel letters.
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        if s.is_empty() {
+            return Err(String::from("No output channels specified"));
+        }
+        s.chars()
+            .map(|c| match c.to_ascii_lowercase() {
+                'r' => Ok(Self::Red),
+                'g' => Ok(Self::Green),
+                'b' => Ok(Self::Blue),
+                'y' => Ok(Self::Luminance),
+                _ => Err(format!("Unknown output channel '{}' in '{}'", c, s)),
+            })
+            .collect()
+    }
+
+    /// Returns the channel name used in OpenEXR files.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Red => "R",
+            Self::Green => "G",
+            Self::Blue => "B",
+            Self::Luminance => "Y",
+        }
+    }
+
+    /// Returns the value of the channel for an RGB pixel.
+    ///
+    /// * `rgb` - RGB pixel value.
+    fn value(&self, rgb: &[Float]) -> Float {
+        match self {
+            Self::Red => rgb[0],
+            Self::Green => rgb[1],
+            Self::Blue => rgb[2],
+            Self::Luminance => 0.212671 * rgb[0] + 0.715160 * rgb[1] + 0.072169 * rgb[2],
+        }
+    }
+}
+
+/// Options for writing one output image file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageOutput {
+    /// Output file path.
+    pub path: String,
+
+    /// Bit depth. `None` uses the default for the file format; 8-bit for PNG
+    /// and TGA and 32-bit for OpenEXR and PFM.
+    pub bit_depth: Option<BitDepth>,
+
+    /// Whether to dither integer formats when quantizing.
+    pub dither: bool,
+
+    /// Channels to write.
+    pub channels: Vec<OutputChannel>,
+}
+
+impl ImageOutput {
+    /// Create a new `ImageOutput` writing RGB at the default bit depth.
+    ///
+    /// * `path` - Output file path.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: String::from(path),
+            bit_depth: None,
+            dither: false,
+            channels: vec![
+                OutputChannel::Red,
+                OutputChannel::Green,
+                OutputChannel::Blue,
+            ],
+        }
+    }
+
+    /// Parses an output specification of the form `path[:option...]` where
+    /// the options are a bit depth (`8`, `16` or `32`), `dither` or channel
+    /// letters such as `rgb` or `y`. For example `beauty.exr:16` or
+    /// `preview.png:8:dither`.
+    ///
+    /// * `spec` - The output specification.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        // Options follow the last ':' separated token that contains a '.' so
+        // paths containing ':' are left intact.
+        let tokens: Vec<&str> = spec.split(':').collect();
+        let n_path = tokens
+            .iter()
+            .rposition(|t| t.contains('.'))
+            .map_or(tokens.len(), |i| i + 1);
+        let mut output = Self::new(&tokens[..n_path].join(":"));
+
+        for option in &tokens[n_path..] {
+            if let Ok(bits) = option.parse::<i32>() {
+                output.bit_depth = Some(
+                    BitDepth::from_bits(bits)
+                        .ok_or(format!("Unsupported bit depth {} in '{}'", bits, spec))?,
+                );
+            } else if *option == "dither" {
+                output.dither = true;
+            } else {
+                output.channels = OutputChannel::parse_list(option)?;
+            }
+        }
+        Ok(output)
+    }
+}
+
 /// Write the output image to given path.
 ///
 /// * `path`             - Output file path.
 /// * `rgb`              - Floating point RGB pixel data.
 /// * `output_bounds`    - The bounds for the image output.
-pub fn write_image(path: &str, rgb: &[Float], output_bounds: &Bounds2i) -> Result<(), String> {
+/// * `metadata`         - Name/value pairs to store as image attributes. These
+///                        are only written to formats that support them.
+pub fn write_image(
+    path: &str,
+    rgb: &[Float],
+    output_bounds: &Bounds2i,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    write_image_output(&ImageOutput::new(path), rgb, output_bounds, metadata)
+}
+
+/// Write the output image with the given bit depth, dithering and channels.
+///
+/// * `output`           - Output file options.
+/// * `rgb`              - Floating point RGB pixel data.
+/// * `output_bounds`    - The bounds for the image output.
+/// * `metadata`         - Name/value pairs to store as image attributes. These
+///                        are only written to formats that support them.
+pub fn write_image_output(
+    output: &ImageOutput,
+    rgb: &[Float],
+    output_bounds: &Bounds2i,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), String> {
     let resolution = output_bounds.diagonal();
     let res_x = resolution.x as u32;
     let res_y = resolution.y as u32;
+    let path = &output.path[..];
 
-    match get_extension_from_filename(path) {
-        Some(".exr") => write_exr(path, rgb, res_x, res_y),
-        Some(".tga") => write_8_bit(path, rgb, res_x, res_y, ImageFormat::Tga),
-        Some(".png") => write_8_bit(path, rgb, res_x, res_y, ImageFormat::Png),
-        Some(extension) => Err(format!("Extension {} is not supported", extension)),
-        None => Err(format!(
+    match (get_extension_from_filename(path), output.bit_depth) {
+        (Some(".exr"), Some(BitDepth::Eight)) => {
+            Err(format!("8-bit output is not supported for {}", path))
+        }
+        (Some(".exr"), _) => write_exr(output, rgb, res_x, res_y, metadata),
+        (Some(".pfm"), None) | (Some(".pfm"), Some(BitDepth::ThirtyTwo)) => {
+            write_pfm(output, rgb, res_x, res_y)
+        }
+        (Some(".tga"), None) | (Some(".tga"), Some(BitDepth::Eight)) => {
+            write_integer(output, rgb, res_x, res_y, ImageFormat::Tga)
+        }
+        (Some(".png"), None) | (Some(".png"), Some(BitDepth::Eight)) => {
+            write_integer(output, rgb, res_x, res_y, ImageFormat::Png)
+        }
+        (Some(".png"), Some(BitDepth::Sixteen)) => {
+            write_integer(output, rgb, res_x, res_y, ImageFormat::Png)
+        }
+        (Some(".tga"), Some(bit_depth))
+        | (Some(".png"), Some(bit_depth))
+        | (Some(".pfm"), Some(bit_depth)) => Err(format!(
+            "{:?} bit depth is not supported for {}",
+            bit_depth, path
+        )),
+        (Some(extension), _) => Err(format!("Extension {} is not supported", extension)),
+        (None, _) => Err(format!(
             "Can't determine file type from suffix of filename {}",
             path
         )),
@@ -133,77 +427,384 @@ fn get_extension_from_filename(path: &str) -> Option<&str> {
         .map(|c| c.get(1).map_or("", |m| m.as_str()))
 }
 
-/// Writes the image in OpenEXR format.
+/// Writes the image in OpenEXR format with half or single precision floats.
 ///
-/// * `path`        - Output file path.
+/// * `output`      - Output file options.
 /// * `rgb`         - Floating point RGB pixel data.
 /// * `res_x`       - X resolution.
 /// * `res_y`       - Y resolution.
-fn write_exr(path: &str, rgb: &[Float], res_x: u32, res_y: u32) -> Result<(), String> {
+/// * `metadata`    - Name/value pairs to store as text attributes.
+fn write_exr(
+    output: &ImageOutput,
+    rgb: &[Float],
+    res_x: u32,
+    res_y: u32,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let path = &output.path[..];
     info!("Writing image {} with resolution {}x{}", path, res_x, res_y);
 
-    match write_rgb_file(
-        String::from(path),
-        res_x as usize,
-        res_y as usize,
-        |x, y| {
-            let offset = 3 * (y * (res_x as usize) + x);
-            (rgb[offset], rgb[offset + 1], rgb[offset + 2])
-        },
-    ) {
+    let channels: Vec<AnyChannel<exrs::FlatSamples>> = output
+        .channels
+        .iter()
+        .map(|channel| {
+            let values = rgb.chunks_exact(3).map(|p| channel.value(p));
+            let samples = if output.bit_depth == Some(BitDepth::Sixteen) {
+                exrs::FlatSamples::F16(values.map(f16::from_f32).collect())
+            } else {
+                exrs::FlatSamples::F32(values.collect())
+            };
+            AnyChannel::new(channel.name(), samples)
+        })
+        .collect();
+
+    write_exr_layer(path, channels, res_x, res_y, metadata)
+}
+
+/// Samples of a channel written to a multi-channel OpenEXR image.
+pub enum ChannelSamples {
+    /// Single precision floating point samples.
+    F32(Vec<Float>),
+
+    /// Unsigned integer samples such as identifiers.
+    U32(Vec<u32>),
+}
+
+/// Writes named channels as an OpenEXR image. Each channel holds one sample
+/// per pixel in row major order.
+///
+/// * `path`          - Output file path.
+/// * `channels`      - Names and samples of the channels.
+/// * `output_bounds` - The bounds for the image output.
+/// * `metadata`      - Name/value pairs to store as text attributes.
+pub fn write_exr_channels(
+    path: &str,
+    channels: Vec<(String, ChannelSamples)>,
+    output_bounds: &Bounds2i,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let resolution = output_bounds.diagonal();
+    let res_x = resolution.x as u32;
+    let res_y = resolution.y as u32;
+    info!("Writing image {} with resolution {}x{}", path, res_x, res_y);
+
+    let channels = channels
+        .into_iter()
+        .map(|(name, samples)| {
+            let samples = match samples {
+                ChannelSamples::F32(v) => exrs::FlatSamples::F32(v),
+                ChannelSamples::U32(v) => exrs::FlatSamples::U32(v),
+            };
+            AnyChannel::new(&name[..], samples)
+        })
+        .collect();
+    write_exr_layer(path, channels, res_x, res_y, metadata)
+}
+
+/// Writes the channels as a single layer OpenEXR image.
+///
+/// * `path`     - Output file path.
+/// * `channels` - The channels.
+/// * `res_x`    - X resolution.
+/// * `res_y`    - Y resolution.
+/// * `metadata` - Name/value pairs to store as text attributes.
+fn write_exr_layer(
+    path: &str,
+    channels: Vec<AnyChannel<exrs::FlatSamples>>,
+    res_x: u32,
+    res_y: u32,
+    metadata: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let layer = Layer::new(
+        (res_x as usize, res_y as usize),
+        LayerAttributes::default(),
+        Encoding::default(),
+        AnyChannels::sort(SmallVec::from_vec(channels)),
+    );
+    let mut image = Image::from_layer(layer);
+
+    for (name, value) in metadata {
+        match (Text::new_or_none(name), Text::new_or_none(value)) {
+            (Some(name), Some(value)) => {
+                image
+                    .attributes
+                    .other
+                    .insert(name, AttributeValue::Text(value));
+            }
+            _ => warn!("Skipping non-ASCII image attribute {}.", name),
+        }
+    }
+
+    match image.write().to_file(path) {
         Ok(()) => Ok(()),
         Err(err) => Err(format!("Error saving output image {}. {:}.", path, err)),
     }
 }
 
-/// Writes the image in an 8-bit image format.
+/// Writes the image as a little endian portable float map (PFM). A single
+/// channel is written as a gray image and three channels as an RGB image.
 ///
-/// * `path`         - Output file path.
+/// * `output` - Output file options.
+/// * `rgb`    - Floating point RGB pixel data.
+/// * `res_x`  - X resolution.
+/// * `res_y`  - Y resolution.
+fn write_pfm(output: &ImageOutput, rgb: &[Float], res_x: u32, res_y: u32) -> Result<(), String> {
+    let path = &output.path[..];
+    info!("Writing image {} with resolution {}x{}", path, res_x, res_y);
+
+    let id = match output.channels.len() {
+        1 => "Pf",
+        3 => "PF",
+        n => {
+            return Err(format!(
+                "{} channels can't be written to {}; use 1 or 3",
+                n, path
+            ))
+        }
+    };
+    let mut bytes = format!("{}\n{} {}\n-1\n", id, res_x, res_y).into_bytes();
+
+    // Write the bottom row first.
+    let width = res_x as usize;
+    for row in rgb.chunks_exact(3 * width).rev() {
+        for pixel in row.chunks_exact(3) {
+            for channel in output.channels.iter() {
+                bytes.extend_from_slice(&channel.value(pixel).to_le_bytes());
+            }
+        }
+    }
+
+    match std::fs::write(path, bytes) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(format!("Error saving output image {}. {:}.", path, err)),
+    }
+}
+
+/// Returns an RGB image encoded as a little endian portable float map (PFM).
+///
+/// * `rgb`   - Floating point RGB pixel data.
+/// * `res_x` - X resolution.
+/// * `res_y` - Y resolution.
+pub fn encode_pfm(rgb: &[Float], res_x: u32, res_y: u32) -> Vec<u8> {
+    let mut bytes = format!("PF\n{} {}\n-1\n", res_x, res_y).into_bytes();
+
+    // Write the bottom row first.
+    for row in rgb.chunks_exact(3 * res_x as usize).rev() {
+        for v in row.iter() {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+/// Writes the image in an integer image format. A single channel is written
+/// as a grayscale image and three channels as an RGB image.
+///
+/// * `output`       - Output file options.
 /// * `rgb`          - Floating point RGB pixel data.
 /// * `res_x`        - X resolution.
 /// * `res_y`        - Y resolution.
 /// * `image_format` - Image format.
-fn write_8_bit(
-    path: &str,
+fn write_integer(
+    output: &ImageOutput,
     rgb: &[Float],
     res_x: u32,
     res_y: u32,
     image_format: ImageFormat,
 ) -> std::result::Result<(), String> {
+    let path = &output.path[..];
     info!("Writing image {} with resolution {}x{}", path, res_x, res_y);
 
-    // Allocate an image buffer.
-    let mut imgbuf = ImageBuffer::new(res_x, res_y);
-    let mut offset = 0;
-    for y in 0..res_y {
-        for x in 0..res_x {
-            // 8-bit format; apply gamma and clamp.
-            let rgb = apply_gamma(&[rgb[offset], rgb[offset + 1], rgb[offset + 2]]);
-            imgbuf.put_pixel(x, y, Rgb(rgb));
-            offset += 3;
+    // Apply gamma and quantize the selected channels.
+    let n_channels = output.channels.len();
+    let max_value = if output.bit_depth == Some(BitDepth::Sixteen) {
+        65535.0
+    } else {
+        255.0
+    };
+    let mut values = Vec::with_capacity(n_channels * rgb.len() / 3);
+    for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+        for (c, channel) in output.channels.iter().enumerate() {
+            let offset = if output.dither {
+                dither_offset(i, c)
+            } else {
+                0.5
+            };
+            values.push(quantize(channel.value(pixel), max_value, offset));
         }
     }
 
+    let sixteen_bit = output.bit_depth == Some(BitDepth::Sixteen);
+    let colour_type = match (n_channels, sixteen_bit) {
+        (1, false) => ColorType::L8,
+        (3, false) => ColorType::Rgb8,
+        (1, true) => ColorType::L16,
+        (3, true) => ColorType::Rgb16,
+        _ => {
+            return Err(format!(
+                "{} channels can't be written to {}; use 1 or 3",
+                n_channels, path
+            ))
+        }
+    };
+    let bytes: Vec<u8> = if sixteen_bit {
+        values
+            .iter()
+            .flat_map(|v| v.to_ne_bytes().to_vec())
+            .collect()
+    } else {
+        values.iter().map(|v| *v as u8).collect()
+    };
+
     // Write the output file.
-    match imgbuf.save_with_format(String::from(path), image_format) {
+    match save_buffer_with_format(path, &bytes, res_x, res_y, colour_type, image_format) {
         Ok(()) => Ok(()),
         Err(err) => Err(format!("Error saving output image {}. {:}.", path, err)),
     }
 }
 
-/// Apply gamma correction to a RGB floating point pixel and return the
-/// clamped 8-bit values.
+/// Apply gamma correction to a floating point channel value and return it
+/// quantized to the range [0, `max_value`].
 ///
-/// * `rgb` - RGB floating point pixel value.
+/// * `v`         - Value to quantize.
+/// * `max_value` - Maximum integer value.
+/// * `offset`    - Offset in [0, 1) added before truncating; 0.5 rounds to
+///                 the nearest value and a random offset dithers.
 #[inline]
-fn apply_gamma(rgb: &[Float; 3]) -> [u8; 3] {
-    [clamp_byte(rgb[0]), clamp_byte(rgb[1]), clamp_byte(rgb[2])]
+fn quantize(v: Float, max_value: Float, offset: Float) -> u16 {
+    clamp(max_value * gamma_correct(v) + offset, 0.0, max_value) as u16
 }
 
-/// Clamp floating point value to 8-bit range [0, 255].
+/// Returns a pseudo-random dithering offset in [0, 1) for a channel of a
+/// pixel. The offset is a hash of the pixel and channel so images are
+/// reproducible.
 ///
-/// * `v` - Value to clamp.
+/// * `pixel`   - Pixel index.
+/// * `channel` - Channel index.
 #[inline]
-fn clamp_byte(v: Float) -> u8 {
-    clamp(255.0 * gamma_correct(v) + 0.5, 0.0, 255.0) as u8
+fn dither_offset(pixel: usize, channel: usize) -> Float {
+    // Finalizer from MurmurHash3.
+    let mut h = (pixel as u64) << 2 | channel as u64;
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    (h >> 40) as Float / (1_u64 << 24) as Float
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_image_output() {
+        let output = ImageOutput::parse("renders/preview.png:16:dither:y").unwrap();
+        assert_eq!(output.path, "renders/preview.png");
+        assert_eq!(output.bit_depth, Some(BitDepth::Sixteen));
+        assert!(output.dither);
+        assert_eq!(output.channels, vec![OutputChannel::Luminance]);
+
+        let output = ImageOutput::parse("C:/renders/beauty.exr").unwrap();
+        assert_eq!(output, ImageOutput::new("C:/renders/beauty.exr"));
+
+        assert!(ImageOutput::parse("beauty.exr:12").is_err());
+        assert!(ImageOutput::parse("beauty.exr:rgq").is_err());
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let dir = std::env::temp_dir();
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(1, 2));
+        let rgb = [0.5, 1.0, 2.0, 16.0, 0.25, 0.0];
+
+        let path = dir.join("round_trip.pfm").to_string_lossy().into_owned();
+        write_image(&path, &rgb, &bounds, &BTreeMap::new()).unwrap();
+        let image = read_image(&path).unwrap();
+        assert_eq!(image.resolution, Point2::new(1, 2));
+        assert_eq!(image.pixels[0].to_rgb(), [0.5, 1.0, 2.0]);
+        assert_eq!(image.pixels[1].to_rgb(), [16.0, 0.25, 0.0]);
+
+        let mut output = ImageOutput::new(&path);
+        output.channels = vec![OutputChannel::Green];
+        write_image_output(&output, &rgb, &bounds, &BTreeMap::new()).unwrap();
+        let image = read_image(&path).unwrap();
+        assert_eq!(image.pixels[0].to_rgb(), [1.0, 1.0, 1.0]);
+        assert_eq!(image.pixels[1].to_rgb(), [0.25, 0.25, 0.25]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pfm_big_endian_scaled() {
+        // Big endian data is indicated by a positive scale.
+        let mut bytes = b"PF\n1 1\n2.0\n".to_vec();
+        for v in &[0.25_f32, 0.5, 1.0] {
+            bytes.extend_from_slice(&v.to_be_bytes());
+        }
+        let path = std::env::temp_dir().join("big_endian.pfm");
+        std::fs::write(&path, bytes).unwrap();
+        let image = read_image(&path.to_string_lossy()).unwrap();
+        assert_eq!(image.pixels[0].to_rgb(), [0.5, 1.0, 2.0]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tga_round_trip() {
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 1));
+        let path = std::env::temp_dir().join("round_trip.tga");
+        let path = path.to_string_lossy();
+        write_image(
+            &path,
+            &[1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            &bounds,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let image = read_image(&path).unwrap();
+        assert_eq!(image.resolution, Point2::new(2, 1));
+        assert_eq!(image.pixels[0].to_rgb(), [1.0, 0.0, 0.0]);
+        assert_eq!(image.pixels[1].to_rgb(), [0.0, 0.0, 1.0]);
+        std::fs::remove_file(&*path).unwrap();
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let dir = std::env::temp_dir();
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(2, 1));
+        let rgb = [0.5, 1.0, 2.0, 16.0, 0.25, 0.0];
+
+        // Values above 1 survive both half and single precision.
+        for spec in &["round_trip_half.exr:16", "round_trip_float.exr:32"] {
+            let mut output = ImageOutput::parse(spec).unwrap();
+            output.path = dir.join(&output.path).to_string_lossy().into_owned();
+            write_image_output(&output, &rgb, &bounds, &BTreeMap::new()).unwrap();
+
+            let image = read_image(&output.path).unwrap();
+            assert_eq!(image.resolution, Point2::new(2, 1));
+            assert_eq!(image.pixels[0].to_rgb(), [0.5, 1.0, 2.0]);
+            assert_eq!(image.pixels[1].to_rgb(), [16.0, 0.25, 0.0]);
+            std::fs::remove_file(&output.path).unwrap();
+        }
+
+        // Luminance only images are read as gray.
+        let path = dir.join("round_trip_y.exr").to_string_lossy().into_owned();
+        let mut output = ImageOutput::new(&path);
+        output.channels = vec![OutputChannel::Luminance];
+        write_image_output(
+            &output,
+            &[2.0, 2.0, 2.0, 0.0, 0.0, 0.0],
+            &bounds,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let image = read_image(&path).unwrap();
+        assert!((image.pixels[0][1] - 2.0).abs() < 1e-5);
+        assert_eq!(image.pixels[0][0], image.pixels[0][2]);
+        assert_eq!(image.pixels[1].to_rgb(), [0.0, 0.0, 0.0]);
+        std::fs::remove_file(&path).unwrap();
+    }
 }
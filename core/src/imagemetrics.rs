@@ -0,0 +1,567 @@
+//! Image Metrics
+//!
+//! Error metrics for comparing a rendered image against a reference:
+//!
+//! * Mean squared error (MSE) over all channels.
+//! * Relative mean squared error (relMSE) which divides the squared error by
+//!   the squared reference value so that bright and dark regions contribute
+//!   equally.
+//! * FLIP, a perceptual difference in [0, 1] which models how noticeable the
+//!   differences are when flipping between the images on a monitor.
+//!
+//! MSE and relMSE work with any number of channels per pixel including
+//! spectral samples. FLIP compares linear RGB images clamped to [0, 1].
+
+use crate::image_io::*;
+use crate::pbrt::*;
+use crate::spectrum::*;
+use rayon::prelude::*;
+use std::fmt;
+
+/// Added to the squared reference value in relMSE to avoid dividing by zero.
+const REL_MSE_EPSILON: Float = 0.01;
+
+/// Distance in meters from the viewer to the monitor assumed by FLIP.
+const MONITOR_DISTANCE: Float = 0.7;
+
+/// Width in meters of the monitor assumed by FLIP.
+const MONITOR_WIDTH: Float = 0.7;
+
+/// Horizontal resolution in pixels of the monitor assumed by FLIP.
+const MONITOR_RESOLUTION: Float = 3840.0;
+
+/// Contrast sensitivity function parameters `[a1, b1, a2, b2]` for the
+/// achromatic, red-green and blue-yellow channels.
+const CSF_PARAMS: [[Float; 4]; 3] = [
+    [1.0, 0.0047, 0.0, 1e-5],
+    [1.0, 0.0053, 0.0, 1e-5],
+    [34.1, 0.04, 13.5, 0.025],
+];
+
+/// Exponent applied to the colour difference.
+const FLIP_QC: Float = 0.7;
+
+/// Exponent applied to the feature difference.
+const FLIP_QF: Float = 0.5;
+
+/// Fraction of the maximum colour difference below which differences are
+/// compressed.
+const FLIP_PC: Float = 0.4;
+
+/// Error assigned to colour differences of `FLIP_PC` times the maximum.
+const FLIP_PT: Float = 0.95;
+
+/// Width in degrees of the edge and point detection filters.
+const FLIP_FEATURE_WIDTH: Float = 0.082;
+
+/// Metrics for an image compared against a reference.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImageMetrics {
+    /// Mean squared error.
+    pub mse: Float,
+
+    /// Relative mean squared error.
+    pub rel_mse: Float,
+
+    /// Mean FLIP error.
+    pub flip: Float,
+}
+
+impl ImageMetrics {
+    /// Compute all metrics for an image.
+    ///
+    /// * `image`     - The image.
+    /// * `reference` - The reference image.
+    pub fn compute(image: &RGBImage, reference: &RGBImage) -> Result<Self, String> {
+        check_resolution(image, reference)?;
+
+        let a = spectrum_samples(&image.pixels);
+        let b = spectrum_samples(&reference.pixels);
+        let errors = flip_error_map(image, reference)?;
+        Ok(Self {
+            mse: mse(&a, &b)?,
+            rel_mse: rel_mse(&a, &b)?,
+            flip: mean(&errors),
+        })
+    }
+
+    /// Read two images and compute all metrics.
+    ///
+    /// * `image`     - Path to the image.
+    /// * `reference` - Path to the reference image.
+    pub fn compare_files(image: &str, reference: &str) -> Result<Self, String> {
+        let a = read_image(image).map_err(|err| format!("{}: {}", image, err))?;
+        let b = read_image(reference).map_err(|err| format!("{}: {}", reference, err))?;
+        Self::compute(&a, &b)
+    }
+}
+
+impl fmt::Display for ImageMetrics {
+    /// Formats the metrics.
+    ///
+    /// * `f` - Formatter.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MSE {:.6e}, relMSE {:.6e}, FLIP {:.6}",
+            self.mse, self.rel_mse, self.flip
+        )
+    }
+}
+
+/// Returns the samples of all pixels in order. This flattens RGB or spectral
+/// images for `mse()` and `rel_mse()`.
+///
+/// * `pixels` - The pixels.
+pub fn spectrum_samples<S: CoefficientSpectrum>(pixels: &[S]) -> Vec<Float> {
+    pixels
+        .iter()
+        .flat_map(|p| p.samples().iter().copied())
+        .collect()
+}
+
+/// Returns the mean squared error over all values. Pairs with non-finite
+/// values are skipped.
+///
+/// * `image`     - Values of the image.
+/// * `reference` - Values of the reference image.
+pub fn mse(image: &[Float], reference: &[Float]) -> Result<Float, String> {
+    mean_error(image, reference, |v, r| (v - r) * (v - r))
+}
+
+/// Returns the relative mean squared error over all values. Pairs with
+/// non-finite values are skipped.
+///
+/// * `image`     - Values of the image.
+/// * `reference` - Values of the reference image.
+pub fn rel_mse(image: &[Float], reference: &[Float]) -> Result<Float, String> {
+    mean_error(image, reference, |v, r| {
+        (v - r) * (v - r) / (r * r + REL_MSE_EPSILON as f64)
+    })
+}
+
+/// Returns the mean FLIP error.
+///
+/// * `image`     - The image.
+/// * `reference` - The reference image.
+pub fn flip(image: &RGBImage, reference: &RGBImage) -> Result<Float, String> {
+    flip_error_map(image, reference).map(|errors| mean(&errors))
+}
+
+/// Returns the FLIP error of each pixel in [0, 1] in row major order.
+///
+/// * `image`     - The image.
+/// * `reference` - The reference image.
+pub fn flip_error_map(image: &RGBImage, reference: &RGBImage) -> Result<Vec<Float>, String> {
+    check_resolution(image, reference)?;
+
+    let width = image.resolution.x;
+    let height = image.resolution.y;
+    let ppd = MONITOR_DISTANCE * (MONITOR_RESOLUTION / MONITOR_WIDTH) * PI / 180.0;
+
+    let a = FlipImage::new(image, width, height, ppd);
+    let b = FlipImage::new(reference, width, height, ppd);
+
+    // Largest colour difference, between green and blue.
+    let green = hunt(&xyz_to_lab(&linear_rgb_to_xyz(&[0.0, 1.0, 0.0])));
+    let blue = hunt(&xyz_to_lab(&linear_rgb_to_xyz(&[0.0, 0.0, 1.0])));
+    let c_max = hyab(&green, &blue).powf(FLIP_QC);
+
+    Ok((0..width * height)
+        .map(|i| {
+            // Colour difference compressed so that large differences are
+            // near 1.
+            let d = hyab(&a.colour[i], &b.colour[i]).powf(FLIP_QC);
+            let delta_c = if d < FLIP_PC * c_max {
+                FLIP_PT / (FLIP_PC * c_max) * d
+            } else {
+                FLIP_PT + (d - FLIP_PC * c_max) / (c_max - FLIP_PC * c_max) * (1.0 - FLIP_PT)
+            };
+
+            // Edges and points that differ amplify the colour difference.
+            let delta_f = (max(abs(a.edges[i] - b.edges[i]), abs(a.points[i] - b.points[i]))
+                / (2.0 as Float).sqrt())
+            .powf(FLIP_QF);
+
+            clamp(delta_c.powf(1.0 - delta_f), 0.0, 1.0)
+        })
+        .collect())
+}
+
+/// Returns an error if two images have different resolutions.
+///
+/// * `image`     - The image.
+/// * `reference` - The reference image.
+fn check_resolution(image: &RGBImage, reference: &RGBImage) -> Result<(), String> {
+    if image.resolution != reference.resolution {
+        Err(format!(
+            "Image resolution {}x{} does not match reference {}x{}",
+            image.resolution.x, image.resolution.y, reference.resolution.x, reference.resolution.y
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the mean of an error function over pairs of finite values.
+///
+/// * `image`     - Values of the image.
+/// * `reference` - Values of the reference image.
+/// * `error`     - The error function.
+fn mean_error<F>(image: &[Float], reference: &[Float], error: F) -> Result<Float, String>
+where
+    F: Fn(f64, f64) -> f64,
+{
+    if image.len() != reference.len() {
+        return Err(format!(
+            "Image has {} values but reference has {}",
+            image.len(),
+            reference.len()
+        ));
+    }
+
+    let (sum, n) = image
+        .iter()
+        .zip(reference.iter())
+        .filter(|(v, r)| v.is_finite() && r.is_finite())
+        .fold((0.0, 0_usize), |(sum, n), (v, r)| {
+            (sum + error(*v as f64, *r as f64), n + 1)
+        });
+    Ok(if n > 0 {
+        (sum / n as f64) as Float
+    } else {
+        0.0
+    })
+}
+
+/// Returns the mean of values.
+///
+/// * `values` - The values.
+fn mean(values: &[Float]) -> Float {
+    if values.is_empty() {
+        0.0
+    } else {
+        (values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64) as Float
+    }
+}
+
+/// Per-pixel quantities of an image used by FLIP.
+struct FlipImage {
+    /// Hunt adjusted L*a*b* colour after filtering with the contrast
+    /// sensitivity functions.
+    colour: Vec<[Float; 3]>,
+
+    /// Edge strength.
+    edges: Vec<Float>,
+
+    /// Point strength.
+    points: Vec<Float>,
+}
+
+impl FlipImage {
+    /// Compute the quantities for an image.
+    ///
+    /// * `image`  - The image.
+    /// * `width`  - Image width.
+    /// * `height` - Image height.
+    /// * `ppd`    - Pixels per degree of visual angle.
+    fn new(image: &RGBImage, width: usize, height: usize, ppd: Float) -> Self {
+        // Convert to the opponent YCxCz space in separate planes.
+        let mut planes = [vec![], vec![], vec![]];
+        for p in image.pixels.iter() {
+            let s = p.samples();
+            let rgb = [
+                clamp(s[0], 0.0, 1.0),
+                clamp(s[1], 0.0, 1.0),
+                clamp(s[2], 0.0, 1.0),
+            ];
+            let ycxcz = xyz_to_ycxcz(&linear_rgb_to_xyz(&rgb));
+            for (plane, v) in planes.iter_mut().zip(ycxcz.iter()) {
+                plane.push(*v);
+            }
+        }
+
+        // Filter each channel with its contrast sensitivity function and
+        // clamp the result to the RGB gamut.
+        let (csf, radius) = csf_kernels(ppd);
+        let filtered: Vec<Vec<Float>> = (0..3)
+            .map(|c| convolve(&planes[c], width, height, &csf[c], radius))
+            .collect();
+        let colour = (0..width * height)
+            .map(|i| {
+                let ycxcz = [filtered[0][i], filtered[1][i], filtered[2][i]];
+                let rgb = xyz_to_linear_rgb(&ycxcz_to_xyz(&ycxcz));
+                let rgb = [
+                    clamp(rgb[0], 0.0, 1.0),
+                    clamp(rgb[1], 0.0, 1.0),
+                    clamp(rgb[2], 0.0, 1.0),
+                ];
+                hunt(&xyz_to_lab(&linear_rgb_to_xyz(&rgb)))
+            })
+            .collect();
+
+        // Detect edges and points in normalized achromatic values.
+        let y: Vec<Float> = planes[0].iter().map(|v| (v + 16.0) / 116.0).collect();
+        let (edge, point, radius) = feature_kernels(ppd);
+        let magnitude = |kernel: &[Float]| {
+            let n = 2 * radius + 1;
+            let transposed: Vec<Float> = (0..n * n).map(|i| kernel[(i % n) * n + i / n]).collect();
+            let gx = convolve(&y, width, height, kernel, radius);
+            let gy = convolve(&y, width, height, &transposed, radius);
+            gx.iter()
+                .zip(gy.iter())
+                .map(|(x, y)| (x * x + y * y).sqrt())
+                .collect::<Vec<Float>>()
+        };
+
+        Self {
+            colour,
+            edges: magnitude(&edge),
+            points: magnitude(&point),
+        }
+    }
+}
+
+/// Returns the normalized contrast sensitivity filters for the YCxCz
+/// channels and their radius in pixels.
+///
+/// * `ppd` - Pixels per degree of visual angle.
+fn csf_kernels(ppd: Float) -> ([Vec<Float>; 3], usize) {
+    let max_b = CSF_PARAMS[2][1];
+    let radius = (3.0 * (max_b / (2.0 * PI * PI)).sqrt() * ppd).ceil() as usize;
+    let r = radius as i32;
+
+    let kernel = |params: &[Float; 4]| {
+        let [a1, b1, a2, b2] = *params;
+        let k: Vec<Float> = filter_offsets(r)
+            .map(|(x, y)| {
+                let d2 = (x * x + y * y) as Float / (ppd * ppd);
+                let g = |a: Float, b: Float| a * (PI / b).sqrt() * (-PI * PI * d2 / b).exp();
+                g(a1, b1) + if a2 > 0.0 { g(a2, b2) } else { 0.0 }
+            })
+            .collect();
+        let sum: Float = k.iter().sum();
+        k.iter().map(|v| v / sum).collect()
+    };
+
+    (
+        [
+            kernel(&CSF_PARAMS[0]),
+            kernel(&CSF_PARAMS[1]),
+            kernel(&CSF_PARAMS[2]),
+        ],
+        radius,
+    )
+}
+
+/// Returns the edge and point detection filters in x, which are the first
+/// and second derivatives of a Gaussian, and their radius in pixels. The
+/// positive and negative weights of each filter are normalized separately.
+///
+/// * `ppd` - Pixels per degree of visual angle.
+fn feature_kernels(ppd: Float) -> (Vec<Float>, Vec<Float>, usize) {
+    let sd = 0.5 * FLIP_FEATURE_WIDTH * ppd;
+    let radius = (3.0 * sd).ceil() as usize;
+    let r = radius as i32;
+
+    let normalize = |k: Vec<Float>| {
+        let positive: Float = k.iter().filter(|v| **v > 0.0).sum();
+        let negative: Float = -k.iter().filter(|v| **v < 0.0).sum::<Float>();
+        k.iter()
+            .map(|v| if *v > 0.0 { v / positive } else { v / negative })
+            .collect::<Vec<Float>>()
+    };
+
+    let gaussian = |x: i32, y: i32| (-((x * x + y * y) as Float) / (2.0 * sd * sd)).exp();
+    let edge = filter_offsets(r)
+        .map(|(x, y)| -(x as Float) * gaussian(x, y))
+        .collect();
+    let point = filter_offsets(r)
+        .map(|(x, y)| ((x * x) as Float / (sd * sd) - 1.0) * gaussian(x, y))
+        .collect();
+    (normalize(edge), normalize(point), radius)
+}
+
+/// Returns the offsets `(x, y)` of a square filter in row major order.
+///
+/// * `r` - Filter radius.
+fn filter_offsets(r: i32) -> impl Iterator<Item = (i32, i32)> {
+    (-r..=r).flat_map(move |y| (-r..=r).map(move |x| (x, y)))
+}
+
+/// Convolves an image with a square filter. Pixels outside the image are
+/// clamped to the edge.
+///
+/// * `data`   - The image in row major order.
+/// * `width`  - Image width.
+/// * `height` - Image height.
+/// * `kernel` - The filter in row major order.
+/// * `radius` - The filter radius.
+fn convolve(
+    data: &[Float],
+    width: usize,
+    height: usize,
+    kernel: &[Float],
+    radius: usize,
+) -> Vec<Float> {
+    let n = 2 * radius + 1;
+    let mut result = vec![0.0; width * height];
+    result
+        .par_chunks_mut(max(1, width))
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, v) in row.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for ky in 0..n {
+                    let sy = clamp(y as i64 + ky as i64 - radius as i64, 0, height as i64 - 1);
+                    for kx in 0..n {
+                        let sx = clamp(x as i64 + kx as i64 - radius as i64, 0, width as i64 - 1);
+                        sum += kernel[ky * n + kx] * data[sy as usize * width + sx as usize];
+                    }
+                }
+                *v = sum;
+            }
+        });
+    result
+}
+
+/// Reference white of the sRGB colour space.
+fn white() -> [Float; 3] {
+    linear_rgb_to_xyz(&[1.0, 1.0, 1.0])
+}
+
+/// Converts linear sRGB to XYZ.
+///
+/// * `rgb` - Linear RGB colour.
+fn linear_rgb_to_xyz(rgb: &[Float; 3]) -> [Float; 3] {
+    [
+        0.4124564 * rgb[0] + 0.3575761 * rgb[1] + 0.1804375 * rgb[2],
+        0.2126729 * rgb[0] + 0.7151522 * rgb[1] + 0.0721750 * rgb[2],
+        0.0193339 * rgb[0] + 0.119192 * rgb[1] + 0.9503041 * rgb[2],
+    ]
+}
+
+/// Converts XYZ to linear sRGB.
+///
+/// * `xyz` - XYZ colour.
+fn xyz_to_linear_rgb(xyz: &[Float; 3]) -> [Float; 3] {
+    [
+        3.2404542 * xyz[0] - 1.5371385 * xyz[1] - 0.4985314 * xyz[2],
+        -0.969266 * xyz[0] + 1.8760108 * xyz[1] + 0.0415560 * xyz[2],
+        0.0556434 * xyz[0] - 0.2040259 * xyz[1] + 1.0572252 * xyz[2],
+    ]
+}
+
+/// Converts XYZ to the linearized opponent YCxCz space.
+///
+/// * `xyz` - XYZ colour.
+fn xyz_to_ycxcz(xyz: &[Float; 3]) -> [Float; 3] {
+    let w = white();
+    let (x, y, z) = (xyz[0] / w[0], xyz[1] / w[1], xyz[2] / w[2]);
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+/// Converts YCxCz to XYZ.
+///
+/// * `ycxcz` - YCxCz colour.
+fn ycxcz_to_xyz(ycxcz: &[Float; 3]) -> [Float; 3] {
+    let w = white();
+    let y = (ycxcz[0] + 16.0) / 116.0;
+    let x = ycxcz[1] / 500.0 + y;
+    let z = y - ycxcz[2] / 200.0;
+    [x * w[0], y * w[1], z * w[2]]
+}
+
+/// Converts XYZ to CIE L*a*b*.
+///
+/// * `xyz` - XYZ colour.
+fn xyz_to_lab(xyz: &[Float; 3]) -> [Float; 3] {
+    let delta: Float = 6.0 / 29.0;
+    let f = |t: Float| {
+        if t > delta * delta * delta {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+
+    let w = white();
+    let (x, y, z) = (f(xyz[0] / w[0]), f(xyz[1] / w[1]), f(xyz[2] / w[2]));
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+/// Applies the Hunt effect which reduces chroma at low luminance.
+///
+/// * `lab` - L*a*b* colour.
+fn hunt(lab: &[Float; 3]) -> [Float; 3] {
+    [lab[0], 0.01 * lab[0] * lab[1], 0.01 * lab[0] * lab[2]]
+}
+
+/// Returns the HyAB colour difference.
+///
+/// * `a` - First L*a*b* colour.
+/// * `b` - Second L*a*b* colour.
+fn hyab(a: &[Float; 3], b: &[Float; 3]) -> Float {
+    let da = a[1] - b[1];
+    let db = a[2] - b[2];
+    abs(a[0] - b[0]) + (da * da + db * db).sqrt()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+
+    fn image(width: usize, height: usize, f: impl Fn(usize, usize) -> Float) -> RGBImage {
+        RGBImage {
+            pixels: (0..width * height)
+                .map(|i| RGBSpectrum::new(f(i % width, i / width)))
+                .collect(),
+            resolution: Point2::new(width, height),
+        }
+    }
+
+    #[test]
+    fn mse_and_rel_mse() {
+        assert_eq!(mse(&[1.0, 2.0], &[1.0, 2.0]), Ok(0.0));
+        assert_eq!(mse(&[1.0, 2.0, 3.0, 4.0], &[1.0, 2.0, 5.0, 4.0]), Ok(1.0));
+        assert!((rel_mse(&[2.0], &[1.0]).unwrap() - 1.0 / 1.01).abs() < 1e-6);
+
+        // Non-finite values are skipped and lengths must match.
+        assert_eq!(mse(&[Float::NAN, 1.0], &[0.0, 3.0]), Ok(4.0));
+        assert!(mse(&[1.0], &[1.0, 2.0]).is_err());
+
+        // Spectral images are compared sample by sample.
+        let a = [SampledSpectrum::new(1.0)];
+        let b = [SampledSpectrum::new(0.5)];
+        let v = mse(&spectrum_samples(&a), &spectrum_samples(&b)).unwrap();
+        assert!((v - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn flip_orders_differences() {
+        let checker = |x: usize, y: usize| if (x / 4 + y / 4) & 1 == 0 { 0.8 } else { 0.2 };
+        let reference = image(24, 24, checker);
+        let noisy = image(24, 24, |x, y| {
+            checker(x, y) + if (x * 7 + y * 13) % 5 == 0 { 0.05 } else { 0.0 }
+        });
+        let flat = image(24, 24, |_, _| 0.5);
+
+        let same = ImageMetrics::compute(&reference, &reference).unwrap();
+        assert_eq!(same.mse, 0.0);
+        assert_eq!(same.flip, 0.0);
+
+        let small = flip(&noisy, &reference).unwrap();
+        let large = flip(&flat, &reference).unwrap();
+        assert!(small > 0.0 && small < large && large <= 1.0);
+
+        let black = image(8, 8, |_, _| 0.0);
+        let white = image(8, 8, |_, _| 1.0);
+        assert!(flip(&black, &white).unwrap() > 0.9);
+        assert!(flip(&black, &image(4, 8, |_, _| 0.0)).is_err());
+    }
+}
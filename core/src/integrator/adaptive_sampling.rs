@@ -0,0 +1,224 @@
+//! Adaptive Sampling
+
+use crate::paramset::*;
+use crate::pbrt::*;
+
+/// Controls adaptive sampling. Every pixel takes at least `min_samples`
+/// samples; after that, sampling stops once the variance of the pixel's mean
+/// luminance is at most `max_variance`. The statistics are kept in the film
+/// across passes. When `max_samples` exceeds the sampler's samples per pixel,
+/// further passes sample only the pixels that haven't converged until they
+/// reach `max_samples`, so noisy pixels get more samples than smooth ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AdaptiveSampling {
+    /// Minimum number of samples taken in every pixel.
+    pub min_samples: usize,
+
+    /// Variance of the mean luminance below which a pixel is converged. Zero
+    /// disables adaptive sampling.
+    pub max_variance: Float,
+
+    /// Maximum number of samples taken in a pixel over all passes. Zero
+    /// limits pixels to a single pass of the sampler's samples per pixel.
+    pub max_samples: usize,
+}
+
+impl AdaptiveSampling {
+    /// Create a new `AdaptiveSampling`.
+    ///
+    /// * `min_samples`  - Minimum number of samples taken in every pixel.
+    /// * `max_variance` - Variance of the mean luminance below which a pixel
+    ///                    is converged. Zero disables adaptive sampling.
+    /// * `max_samples`  - Maximum number of samples taken in a pixel over all
+    ///                    passes. Zero limits pixels to a single pass.
+    pub fn new(min_samples: usize, max_variance: Float, max_samples: usize) -> Self {
+        Self {
+            min_samples: max(min_samples, 2),
+            max_variance,
+            max_samples,
+        }
+    }
+
+    /// Returns `true` if adaptive sampling is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.max_variance > 0.0
+    }
+
+    /// Returns `true` if no more samples need to be taken for a pixel.
+    ///
+    /// * `variance` - Luminance statistics of the samples taken in the pixel.
+    pub fn is_converged(&self, variance: &PixelVariance) -> bool {
+        self.is_enabled()
+            && variance.n >= self.min_samples
+            && variance.variance_of_mean() <= self.max_variance
+    }
+
+    /// Returns `true` if a pixel has converged or taken the maximum number of
+    /// samples.
+    ///
+    /// * `variance` - Luminance statistics of the samples taken in the pixel.
+    pub fn is_done(&self, variance: &PixelVariance) -> bool {
+        self.is_converged(variance)
+            || (self.is_enabled() && self.max_samples > 0 && variance.n >= self.max_samples)
+    }
+}
+
+impl From<&ParamSet> for AdaptiveSampling {
+    /// Create `AdaptiveSampling` from the integrator's `maxvariance`,
+    /// `minsamples` and `maxsamples` parameters.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        let max_variance = params.find_one_float("maxvariance", 0.0);
+        let min_samples = params.find_one_int("minsamples", 16);
+        let max_samples = params.find_one_int("maxsamples", 0);
+        if max_variance < 0.0 {
+            error!("'maxvariance' must be non-negative. Adaptive sampling is disabled.");
+            return Self::default();
+        }
+        Self::new(
+            max(min_samples, 0) as usize,
+            max_variance,
+            max(max_samples, 0) as usize,
+        )
+    }
+}
+
+/// Running estimate of the mean and variance of the luminance of the samples
+/// taken in a pixel using Welford's algorithm.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PixelVariance {
+    /// Number of samples.
+    pub n: usize,
+
+    /// Mean luminance.
+    pub mean: Float,
+
+    /// Sum of squared differences from the mean.
+    pub m2: Float,
+}
+
+impl PixelVariance {
+    /// Adds the luminance of a sample.
+    ///
+    /// * `y` - Luminance.
+    pub fn add(&mut self, y: Float) {
+        self.n += 1;
+        let delta = y - self.mean;
+        self.mean += delta / self.n as Float;
+        self.m2 += delta * (y - self.mean);
+    }
+
+    /// Combines the statistics of samples taken separately, such as in
+    /// different passes.
+    ///
+    /// * `other` - Statistics of the other samples.
+    pub fn merge(&mut self, other: &PixelVariance) {
+        if other.n == 0 {
+            return;
+        }
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        let other_fraction = other.n as Float / n as Float;
+        self.mean += delta * other_fraction;
+        self.m2 += other.m2 + delta * delta * self.n as Float * other_fraction;
+        self.n = n;
+    }
+
+    /// Returns the sample variance of the luminance.
+    pub fn variance(&self) -> Float {
+        if self.n > 1 {
+            self.m2 / (self.n - 1) as Float
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the variance of the estimate of the mean luminance.
+    pub fn variance_of_mean(&self) -> Float {
+        if self.n > 0 {
+            self.variance() / self.n as Float
+        } else {
+            INFINITY
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_variance() {
+        let mut v = PixelVariance::default();
+        for y in [1.0, 2.0, 3.0, 4.0].iter() {
+            v.add(*y);
+        }
+        assert_eq!(v.n, 4);
+        assert!((v.mean - 2.5).abs() < 1e-6);
+        assert!((v.variance() - 5.0 / 3.0).abs() < 1e-6);
+        assert!((v.variance_of_mean() - 5.0 / 12.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn merge_pixel_variance() {
+        let mut a = PixelVariance::default();
+        let mut b = PixelVariance::default();
+        let mut all = PixelVariance::default();
+        for (i, y) in [1.0, 2.0, 3.0, 4.0, 8.0].iter().enumerate() {
+            if i < 2 {
+                a.add(*y);
+            } else {
+                b.add(*y);
+            }
+            all.add(*y);
+        }
+        a.merge(&PixelVariance::default());
+        a.merge(&b);
+        assert_eq!(a.n, all.n);
+        assert!((a.mean - all.mean).abs() < 1e-6);
+        assert!((a.variance() - all.variance()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn converges_after_min_samples() {
+        let mut params = ParamSet::new();
+        assert!(!AdaptiveSampling::from(&params).is_enabled());
+
+        params.add_float("maxvariance", &[0.01]);
+        params.add_int("minsamples", &[4]);
+        let adaptive = AdaptiveSampling::from(&params);
+
+        let mut v = PixelVariance::default();
+        for _ in 0..3 {
+            v.add(0.5);
+        }
+        assert!(!adaptive.is_converged(&v));
+        v.add(0.5);
+        assert!(adaptive.is_converged(&v));
+
+        v.add(10.0);
+        assert!(!adaptive.is_converged(&v));
+        assert!(!adaptive.is_done(&v));
+    }
+
+    #[test]
+    fn done_after_max_samples() {
+        let adaptive = AdaptiveSampling::new(2, 0.01, 8);
+        let mut v = PixelVariance::default();
+        for i in 0..7 {
+            v.add((i % 2) as Float * 10.0);
+        }
+        assert!(!adaptive.is_done(&v));
+        v.add(10.0);
+        assert!(!adaptive.is_converged(&v));
+        assert!(adaptive.is_done(&v));
+
+        // Without adaptive sampling no pixel is done early.
+        assert!(!AdaptiveSampling::new(2, 0.0, 8).is_done(&v));
+    }
+}
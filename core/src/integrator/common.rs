@@ -1,8 +1,11 @@
 //! Common
 
 use crate::geometry::*;
+use crate::integrator::*;
 use crate::light::*;
 use crate::pbrt::*;
+use crate::primitive::*;
+use crate::profiler::*;
 use crate::reflection::*;
 use crate::sampler::*;
 use crate::sampling::*;
@@ -10,7 +13,8 @@ use crate::scene::*;
 use crate::spectrum::*;
 use std::sync::Arc;
 
-/// Uniformly sample all lights in the scene for direct lighting.
+/// Uniformly sample all lights in the scene for direct lighting. Lights that
+/// are not linked to the interaction are skipped.
 ///
 /// * `it`              - The intersection information.
 /// * `scene`           - The scene.
@@ -25,7 +29,9 @@ pub fn uniform_sample_all_lights(
     n_light_samples: &[usize],
     handle_media: bool,
 ) -> Spectrum {
+    let _p = ProfilePhase::new(Prof::DirectLighting);
     let mut l = Spectrum::new(0.0);
+    let primitive = it.get_primitive();
 
     for (j, light) in scene.lights.iter().enumerate() {
         // Accumulate contribution of j^th light to `l`.
@@ -41,17 +47,21 @@ pub fn uniform_sample_all_lights(
             // Use a single sample for illumination from `light`.
             let u_light = Arc::get_mut(sampler).unwrap().get_2d();
             let u_scattering = Arc::get_mut(sampler).unwrap().get_2d();
+            if !scene.light_illuminates(j, primitive) {
+                continue;
+            }
             l += estimate_direct(
                 &(*it).clone(),
                 &u_scattering,
                 Arc::clone(light),
+                j,
                 &u_light,
                 Arc::clone(&scene),
                 sampler,
                 handle_media,
                 false,
             );
-        } else {
+        } else if scene.light_illuminates(j, primitive) {
             // Estimate direct lighting using sample arrays
             let mut ld = Spectrum::new(0.0);
             for k in 0..n_samples {
@@ -59,6 +69,7 @@ pub fn uniform_sample_all_lights(
                     &(*it).clone(),
                     &u_scattering_array[k],
                     Arc::clone(light),
+                    j,
                     &u_light_array[k],
                     Arc::clone(&scene),
                     sampler,
@@ -73,7 +84,8 @@ pub fn uniform_sample_all_lights(
 }
 
 /// Uniformly sample from one random light in the scene for direct lighting and
-/// multiply result by number of lights to compensate.
+/// multiply result by number of lights to compensate. Nothing is added if the
+/// light is not linked to the interaction.
 ///
 /// * `it`            - The intersection information.
 /// * `scene`         - The scene.
@@ -88,6 +100,30 @@ pub fn uniform_sample_one_light(
     handle_media: bool,
     light_distrib: Option<&Distribution1D>,
 ) -> Spectrum {
+    let bsdf_flags = BxDFType::from(BSDF_ALL & !BSDF_SPECULAR);
+    sample_light(it, scene, sampler, handle_media, light_distrib, bsdf_flags)
+}
+
+/// Sample direct lighting scattered by the given BSDF lobes from one random
+/// light in the scene.
+///
+/// * `it`            - The intersection information.
+/// * `scene`         - The scene.
+/// * `sampler`       - The sampler.
+/// * `handle_media`  - Indicates whether effects of volumetric attenuation
+///                     should be considered.
+/// * `light_distrib` - PDF for the light's distribution.
+/// * `bsdf_flags`    - The BSDF lobes to consider.
+fn sample_light(
+    it: &Interaction,
+    scene: Arc<Scene>,
+    sampler: &mut ArcSampler,
+    handle_media: bool,
+    light_distrib: Option<&Distribution1D>,
+    bsdf_flags: BxDFType,
+) -> Spectrum {
+    let _p = ProfilePhase::new(Prof::DirectLighting);
+
     // Randomly choose a single light to sample, `light`.
     let n_lights = scene.lights.len();
     if n_lights == 0 {
@@ -111,25 +147,82 @@ pub fn uniform_sample_one_light(
     let light = Arc::clone(&Arc::clone(&scene).lights[light_num]);
     let u_light = Arc::get_mut(sampler).unwrap().get_2d();
     let u_scattering = Arc::get_mut(sampler).unwrap().get_2d();
-    let estimate = estimate_direct(
+    if !scene.light_illuminates(light_num, it.get_primitive()) {
+        return Spectrum::new(0.0);
+    }
+    let estimate = estimate_direct_lobes(
         it,
         &u_scattering,
         light,
+        light_num,
         &u_light,
         Arc::clone(&scene),
         sampler,
         handle_media,
-        false,
+        bsdf_flags,
     );
     estimate / light_pdf
 }
 
+/// Sample direct lighting from one light chosen with the light distribution
+/// at the interaction point, or uniformly if there is none. This is the
+/// direct lighting routine shared by all interactions including exit points
+/// of subsurface scattering.
+///
+/// * `it`                 - The intersection information.
+/// * `scene`              - The scene.
+/// * `sampler`            - The sampler.
+/// * `handle_media`       - Indicates whether effects of volumetric
+///                          attenuation should be considered.
+/// * `light_distribution` - Light sampling distribution for the scene.
+pub fn sample_one_light(
+    it: &Interaction,
+    scene: Arc<Scene>,
+    sampler: &mut ArcSampler,
+    handle_media: bool,
+    light_distribution: Option<&ArcLightDistribution>,
+) -> Spectrum {
+    let distrib = light_distribution.map(|ld| ld.lookup(&it.get_hit().p));
+    uniform_sample_one_light(it, scene, sampler, handle_media, distrib.as_deref())
+}
+
+/// Sample direct lighting scattered by some of the BSDF lobes from one light
+/// chosen like `sample_one_light()`. Integrators use it to separate the
+/// direct lighting of each lobe. Medium interactions ignore `bsdf_flags`.
+///
+/// * `it`                 - The intersection information.
+/// * `scene`              - The scene.
+/// * `sampler`            - The sampler.
+/// * `handle_media`       - Indicates whether effects of volumetric
+///                          attenuation should be considered.
+/// * `light_distribution` - Light sampling distribution for the scene.
+/// * `bsdf_flags`         - The BSDF lobes to consider.
+pub fn sample_one_light_lobes(
+    it: &Interaction,
+    scene: Arc<Scene>,
+    sampler: &mut ArcSampler,
+    handle_media: bool,
+    light_distribution: Option<&ArcLightDistribution>,
+    bsdf_flags: BxDFType,
+) -> Spectrum {
+    let distrib = light_distribution.map(|ld| ld.lookup(&it.get_hit().p));
+    sample_light(
+        it,
+        scene,
+        sampler,
+        handle_media,
+        distrib.as_deref(),
+        bsdf_flags,
+    )
+}
+
 /// Compute a direct lighting estimate for a light source sample by applying
 /// multiple importance sampling.
 ///
 /// * `it`           - The intersection information.
 /// * `u_scattering` - Scattering sample.
 /// * `light`        - The light.
+/// * `light_index`  - Index of the light in the scene.
 /// * `u_light`      - Light sample.
 /// * `scene`        - The scene.
 /// * `sampler`      - The sampler.
@@ -141,6 +234,7 @@ pub fn estimate_direct(
     it: &Interaction,
     u_scattering: &Point2f,
     light: ArcLight,
+    light_index: usize,
     u_light: &Point2f,
     scene: Arc<Scene>,
     sampler: &mut ArcSampler,
@@ -152,16 +246,69 @@ pub fn estimate_direct(
     } else {
         BxDFType::from(BSDF_ALL & !BSDF_SPECULAR)
     };
+    estimate_direct_lobes(
+        it,
+        u_scattering,
+        light,
+        light_index,
+        u_light,
+        scene,
+        sampler,
+        handle_media,
+        bsdf_flags,
+    )
+}
+
+/// Compute a direct lighting estimate for a light source sample scattered by
+/// the given BSDF lobes.
+///
+/// * `it`           - The intersection information.
+/// * `u_scattering` - Scattering sample.
+/// * `light`        - The light.
+/// * `light_index`  - Index of the light in the scene.
+/// * `u_light`      - Light sample.
+/// * `scene`        - The scene.
+/// * `sampler`      - The sampler.
+/// * `handle_media` - Indicates whether effects of volumetric attenuation
+///                    should be considered.
+/// * `bsdf_flags`   - The BSDF lobes to consider.
+#[allow(clippy::too_many_arguments)]
+fn estimate_direct_lobes(
+    it: &Interaction,
+    u_scattering: &Point2f,
+    light: ArcLight,
+    light_index: usize,
+    u_light: &Point2f,
+    scene: Arc<Scene>,
+    sampler: &mut ArcSampler,
+    handle_media: bool,
+    bsdf_flags: BxDFType,
+) -> Spectrum {
+    if !light.visibility().illumination {
+        return Spectrum::new(0.0);
+    }
+
     let mut ld = Spectrum::new(0.0);
     let hit = it.get_hit();
     let mut scattering_pdf = 0.0;
 
+    // Returns the light scattered by the surface or medium for light arriving
+    // from `wi`. Surfaces may re-emit the light at other wavelengths.
+    let scatter = |f: &Spectrum, wi: &Vector3f, li: &Spectrum| match it {
+        Interaction::Surface { si } => match &si.bsdf {
+            Some(bsdf) => bsdf.scatter(&hit.wo, wi, li, bsdf_flags) * wi.abs_dot(&si.shading.n),
+            None => Spectrum::new(0.0),
+        },
+        Interaction::Medium { .. } => *f * *li,
+    };
+
     // Sample light source with multiple importance sampling.
     let Li {
         mut wi,
         pdf: light_pdf,
         visibility,
         value: mut li,
+        ..
     } = light.sample_li(hit, u_light);
     if light_pdf > 0.0 && !li.is_black() {
         // Compute BSDF or phase function's value for light sample.
@@ -177,16 +324,16 @@ pub fn estimate_direct(
             }
             Interaction::Medium { mi } => {
                 // Evaluate phase function for light sampling strategy.
-                let p = mi.phase.p(&mi.hit.wo, &wi);
-                f = Spectrum::new(p);
-                scattering_pdf = p;
+                let (p, pdf) = phase_light_sample(mi, &wi);
+                f = p;
+                scattering_pdf = pdf;
                 info!("  medium p: {}", p);
             }
         }
 
         if !f.is_black() {
             // Compute effect of visibility for light source sample.
-            if let Some(vis) = visibility {
+            if let Some(vis) = visibility.map(|vis| vis.for_light(light_index)) {
                 if handle_media {
                     li *= vis.tr(Arc::clone(&scene), Arc::clone(sampler));
                 } else if !vis.unoccluded(Arc::clone(&scene)) {
@@ -202,10 +349,10 @@ pub fn estimate_direct(
             // Add light's contribution to reflected radiance
             if !li.is_black() {
                 if light.is_delta_light() {
-                    ld += f * li / light_pdf;
+                    ld += scatter(&f, &wi, &li) / light_pdf;
                 } else {
-                    let weight = power_heuristic(1, light_pdf, 1, scattering_pdf);
-                    ld += f * li * weight / light_pdf;
+                    let weight = mis_weight(light_pdf, scattering_pdf);
+                    ld += scatter(&f, &wi, &li) * weight / light_pdf;
                 }
             }
         }
@@ -221,20 +368,21 @@ pub fn estimate_direct(
                 if let Some(bsdf) = si.bsdf.clone() {
                     let BxDFSample {
                         f: f1,
-                        pdf: _scattering_pdf,
+                        pdf,
                         wi: wi2,
                         sampled_type,
                     } = bsdf.sample_f(&hit.wo, u_scattering, bsdf_flags);
                     wi = wi2;
                     f = f1 * wi.abs_dot(&si.shading.n);
+                    scattering_pdf = pdf;
                     sampled_specular = sampled_type.matches(BSDF_SPECULAR);
                 }
             }
             Interaction::Medium { mi } => {
                 // Sample scattered direction for medium interactions.
-                let (p, wi2) = mi.phase.sample_p(&mi.hit.wo, u_scattering);
-                f = Spectrum::new(p);
-                scattering_pdf = p;
+                let (p, pdf, wi2) = phase_scattering_sample(mi, u_scattering);
+                f = p;
+                scattering_pdf = pdf;
                 wi = wi2;
             }
         }
@@ -251,16 +399,19 @@ pub fn estimate_direct(
                 if light_pdf == 0.0 {
                     return ld;
                 }
-                weight = power_heuristic(1, scattering_pdf, 1, light_pdf);
+                weight = mis_weight(scattering_pdf, light_pdf);
             }
 
-            // Find intersection and compute transmittance.
+            // Find intersection and compute transmittance ignoring surfaces
+            // that don't cast shadows for the light.
             let mut ray = hit.spawn_ray(&wi);
+            let shadow_filter = scene.shadow_filter(light_index);
+            let filter = shadow_filter.as_ref().map(|f| f as PrimitiveFilter);
             let light_isect_and_tr = if handle_media {
-                scene.intersect_tr(&mut ray, Arc::clone(sampler))
+                scene.intersect_tr(&mut ray, Arc::clone(sampler), filter)
             } else {
                 scene
-                    .intersect(&mut ray)
+                    .intersect_filtered(&mut ray, filter)
                     .map(|light_isect| (light_isect, Spectrum::new(1.0)))
             };
 
@@ -278,12 +429,18 @@ pub fn estimate_direct(
                         }
                     }
                 }
-            } else if let Some(rd) = ray.differentials {
-                li = light.le(&rd);
+            } else {
+                li = light.le(&ray);
             }
 
             if !li.is_black() {
-                ld += f * li * tr * weight / scattering_pdf;
+                // Specular BxDFs only scatter light from the sampled direction.
+                let l = if sampled_specular {
+                    f * li * tr
+                } else {
+                    scatter(&f, &wi, &(li * tr))
+                };
+                ld += l * weight / scattering_pdf;
             }
         }
     }
@@ -298,7 +455,49 @@ pub fn compute_light_power_distribution(scene: Arc<Scene>) -> Option<Distributio
     if scene.lights.is_empty() {
         None
     } else {
-        let light_power: Vec<Float> = scene.lights.iter().map(|light| light.power().y()).collect();
+        let light_power: Vec<Float> = scene
+            .lights
+            .iter()
+            .map(|light| {
+                if light.visibility().illumination {
+                    light.power().y()
+                } else {
+                    0.0
+                }
+            })
+            .collect();
         Some(Distribution1D::new(light_power))
     }
 }
+
+/// Returns the phase function value at a medium interaction for a direction
+/// sampled from a light and the PDF of sampling that direction with the phase
+/// function for multiple importance sampling.
+///
+/// * `mi` - The medium interaction.
+/// * `wi` - Incident direction sampled from the light.
+pub fn phase_light_sample(mi: &MediumInteraction, wi: &Vector3f) -> (Spectrum, Float) {
+    let p = mi.phase.p(&mi.hit.wo, wi);
+    (Spectrum::new(p), mi.phase.pdf(&mi.hit.wo, wi))
+}
+
+/// Samples the phase function at a medium interaction and returns the phase
+/// function value, the PDF and the sampled incident direction.
+///
+/// * `mi` - The medium interaction.
+/// * `u`  - Sample value in [0, 1)^2.
+pub fn phase_scattering_sample(mi: &MediumInteraction, u: &Point2f) -> (Spectrum, Float, Vector3f) {
+    let (p, wi) = mi.phase.sample_p(&mi.hit.wo, u);
+    (Spectrum::new(p), mi.phase.pdf(&mi.hit.wo, &wi), wi)
+}
+
+/// Returns the multiple importance sampling weight using the power heuristic
+/// for a sample taken with one strategy when combined with one sample of
+/// another strategy such as light and BSDF or phase function sampling.
+///
+/// * `sample_pdf` - PDF of the strategy the sample was taken with.
+/// * `other_pdf`  - PDF of the other strategy for the same direction.
+#[inline]
+pub fn mis_weight(sample_pdf: Float, other_pdf: Float) -> Float {
+    power_heuristic(1, sample_pdf, 1, other_pdf)
+}
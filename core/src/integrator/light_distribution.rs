@@ -0,0 +1,248 @@
+//! Light Distribution
+
+use crate::geometry::*;
+use crate::integrator::*;
+use crate::low_discrepency::*;
+use crate::pbrt::*;
+use crate::sampling::*;
+use crate::scene::*;
+use crate::spectrum::*;
+use std::sync::{Arc, RwLock};
+
+/// Number of points sampled in a voxel to estimate light contributions.
+const SPATIAL_SAMPLES_PER_VOXEL: u64 = 128;
+
+/// Maximum number of voxels along the largest extent of the scene bounds.
+const SPATIAL_MAX_VOXELS: Float = 64.0;
+
+/// Provides a distribution for selecting a light to sample for direct
+/// lighting at a point.
+pub trait LightDistribution {
+    /// Returns the distribution for sampling lights at a point.
+    ///
+    /// * `p` - The point.
+    fn lookup(&self, p: &Point3f) -> Arc<Distribution1D>;
+}
+
+/// Atomic reference counted `LightDistribution`.
+pub type ArcLightDistribution = Arc<dyn LightDistribution + Send + Sync>;
+
+/// Returns the light distribution for a light sampling strategy or `None` if
+/// the scene has no lights. Unknown strategies fall back to `spatial`.
+///
+/// * `name`  - The strategy; `uniform`, `power` or `spatial`.
+/// * `scene` - The scene.
+pub fn create_light_sample_distribution(
+    name: &str,
+    scene: Arc<Scene>,
+) -> Option<ArcLightDistribution> {
+    if scene.lights.is_empty() {
+        return None;
+    }
+    match name {
+        "uniform" => Some(Arc::new(UniformLightDistribution::new(&scene))),
+        "power" => Some(Arc::new(PowerLightDistribution::new(scene))),
+        "spatial" => Some(Arc::new(SpatialLightDistribution::new(scene))),
+        _ => {
+            warn!(
+                "Light sample distribution type '{}' unknown. Using 'spatial'.",
+                name
+            );
+            Some(Arc::new(SpatialLightDistribution::new(scene)))
+        }
+    }
+}
+
+/// Samples all lights with equal probability.
+pub struct UniformLightDistribution {
+    /// The distribution.
+    distrib: Arc<Distribution1D>,
+}
+
+impl UniformLightDistribution {
+    /// Create a new `UniformLightDistribution`.
+    ///
+    /// * `scene` - The scene.
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            distrib: Arc::new(Distribution1D::new(vec![1.0; scene.lights.len()])),
+        }
+    }
+}
+
+impl LightDistribution for UniformLightDistribution {
+    /// Returns the distribution for sampling lights at a point.
+    ///
+    /// * `_p` - The point.
+    fn lookup(&self, _p: &Point3f) -> Arc<Distribution1D> {
+        Arc::clone(&self.distrib)
+    }
+}
+
+/// Samples lights in proportion to their emitted power.
+pub struct PowerLightDistribution {
+    /// The distribution.
+    distrib: Arc<Distribution1D>,
+}
+
+impl PowerLightDistribution {
+    /// Create a new `PowerLightDistribution`.
+    ///
+    /// * `scene` - The scene.
+    pub fn new(scene: Arc<Scene>) -> Self {
+        let n_lights = scene.lights.len();
+        let distrib = compute_light_power_distribution(scene)
+            .unwrap_or_else(|| Distribution1D::new(vec![1.0; n_lights]));
+        Self {
+            distrib: Arc::new(distrib),
+        }
+    }
+}
+
+impl LightDistribution for PowerLightDistribution {
+    /// Returns the distribution for sampling lights at a point.
+    ///
+    /// * `_p` - The point.
+    fn lookup(&self, _p: &Point3f) -> Arc<Distribution1D> {
+        Arc::clone(&self.distrib)
+    }
+}
+
+/// Samples lights in proportion to an estimate of their contribution to the
+/// region of the scene containing the point. The scene bounds are divided
+/// into voxels and the distribution for a voxel is computed the first time a
+/// point inside it is looked up.
+pub struct SpatialLightDistribution {
+    /// The scene.
+    scene: Arc<Scene>,
+
+    /// Number of voxels along each axis.
+    n_voxels: [usize; 3],
+
+    /// Lazily computed distributions for each voxel.
+    voxels: Vec<RwLock<Option<Arc<Distribution1D>>>>,
+}
+
+impl SpatialLightDistribution {
+    /// Create a new `SpatialLightDistribution`.
+    ///
+    /// * `scene` - The scene.
+    pub fn new(scene: Arc<Scene>) -> Self {
+        // Compute the number of voxels so they are roughly cubes with the
+        // largest extent of the bounds divided into `SPATIAL_MAX_VOXELS`.
+        let b = scene.world_bound;
+        let diag = b.diagonal();
+        let bmax = max(diag.x, max(diag.y, diag.z));
+        let mut n_voxels = [1_usize; 3];
+        for (i, d) in [diag.x, diag.y, diag.z].iter().enumerate() {
+            if bmax > 0.0 {
+                n_voxels[i] = max(1, (d / bmax * SPATIAL_MAX_VOXELS).round() as usize);
+            }
+        }
+
+        let n = n_voxels[0] * n_voxels[1] * n_voxels[2];
+        let voxels = (0..n).map(|_| RwLock::new(None)).collect();
+        Self {
+            scene,
+            n_voxels,
+            voxels,
+        }
+    }
+
+    /// Returns the voxel coordinates of a point clamped to the grid.
+    ///
+    /// * `p` - The point.
+    fn voxel(&self, p: &Point3f) -> [usize; 3] {
+        let offset = self.scene.world_bound.offset(p);
+        let mut pi = [0_usize; 3];
+        for (i, o) in [offset.x, offset.y, offset.z].iter().enumerate() {
+            let v = (o * self.n_voxels[i] as Float) as isize;
+            pi[i] = clamp(v, 0, self.n_voxels[i] as isize - 1) as usize;
+        }
+        pi
+    }
+
+    /// Computes the distribution for a voxel by sampling points inside it
+    /// and accumulating the contribution of each light.
+    ///
+    /// * `pi` - The voxel coordinates.
+    fn compute_distribution(&self, pi: [usize; 3]) -> Distribution1D {
+        let b = self.scene.world_bound;
+        let p0 = Point3f::new(
+            pi[0] as Float / self.n_voxels[0] as Float,
+            pi[1] as Float / self.n_voxels[1] as Float,
+            pi[2] as Float / self.n_voxels[2] as Float,
+        );
+        let p1 = Point3f::new(
+            (pi[0] + 1) as Float / self.n_voxels[0] as Float,
+            (pi[1] + 1) as Float / self.n_voxels[1] as Float,
+            (pi[2] + 1) as Float / self.n_voxels[2] as Float,
+        );
+        let voxel_bounds = Bounds3f::new(b.lerp(&p0), b.lerp(&p1));
+
+        // Estimate the contribution of each light at points in the voxel
+        // using Halton points for both the positions and light samples.
+        let mut light_contrib = vec![0.0; self.scene.lights.len()];
+        for i in 0..SPATIAL_SAMPLES_PER_VOXEL {
+            let po = voxel_bounds.lerp(&Point3f::new(
+                radical_inverse(0, i),
+                radical_inverse(1, i),
+                radical_inverse(2, i),
+            ));
+            let hit = Hit::new(
+                po,
+                0.0,
+                Vector3f::default(),
+                Vector3f::new(1.0, 0.0, 0.0),
+                Normal3f::default(),
+                None,
+            );
+            let u = Point2f::new(radical_inverse(3, i), radical_inverse(4, i));
+            for (j, light) in self.scene.lights.iter().enumerate() {
+                let li = light.sample_li(&hit, &u);
+                if li.pdf > 0.0 {
+                    // Visibility is not tested; it would be too expensive
+                    // and lights are rarely fully occluded from a voxel.
+                    light_contrib[j] += li.value.y() / li.pdf;
+                }
+            }
+        }
+
+        // Give every light a small minimum probability since it may light
+        // points in the voxel that were not sampled.
+        let sum_contrib: Float = light_contrib.iter().sum();
+        let avg_contrib =
+            sum_contrib / (SPATIAL_SAMPLES_PER_VOXEL as Float * light_contrib.len() as Float);
+        let min_contrib = if avg_contrib > 0.0 {
+            0.001 * avg_contrib
+        } else {
+            1.0
+        };
+        for c in light_contrib.iter_mut() {
+            *c = max(*c, min_contrib);
+        }
+
+        Distribution1D::new(light_contrib)
+    }
+}
+
+impl LightDistribution for SpatialLightDistribution {
+    /// Returns the distribution for sampling lights at a point.
+    ///
+    /// * `p` - The point.
+    fn lookup(&self, p: &Point3f) -> Arc<Distribution1D> {
+        let pi = self.voxel(p);
+        let index = (pi[2] * self.n_voxels[1] + pi[1]) * self.n_voxels[0] + pi[0];
+        let voxel = &self.voxels[index];
+
+        if let Some(distrib) = voxel.read().unwrap().as_ref() {
+            return Arc::clone(distrib);
+        }
+
+        // Compute outside the lock; another thread may get here first, in
+        // which case its distribution is kept.
+        let distrib = Arc::new(self.compute_distribution(pi));
+        let mut entry = voxel.write().unwrap();
+        Arc::clone(entry.get_or_insert(distrib))
+    }
+}
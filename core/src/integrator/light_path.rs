@@ -0,0 +1,686 @@
+//! Light Path Expressions
+//!
+//! Light path expressions classify the paths light takes to the camera by the
+//! sequence of scattering events along them. The film writes the radiance
+//! arriving along paths that match an expression to an additional image, an
+//! AOV. Paths are written from the camera to the light and the supported
+//! subset of the syntax is:
+//!
+//! * `C`             - The camera.
+//! * `L`             - A light source or emissive surface.
+//! * `R`, `T`        - Reflection or transmission by any lobe.
+//! * `V`             - Scattering in a participating medium.
+//! * `D`, `G`, `S`   - Diffuse, glossy or specular scattering in either
+//!                     direction.
+//! * `<XY>`          - Scattering matching both a direction and a lobe such
+//!                     as `<RD>`. `.` leaves either unconstrained.
+//! * `.`             - Any scattering event at a surface or in a medium.
+//! * `(...)`, `|`    - Grouping and alternation.
+//! * `*`, `+`, `?`   - Zero or more, one or more and at most one repetitions.
+//!
+//! For example `C<RD>L` selects direct diffuse reflection and `CS+<RD>L`
+//! diffuse reflection seen through mirrors and glass.
+
+use crate::reflection::*;
+use crate::spectrum::*;
+
+/// Scattering lobe of a path event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lobe {
+    /// Diffuse scattering.
+    Diffuse,
+
+    /// Glossy scattering.
+    Glossy,
+
+    /// Perfectly specular scattering.
+    Specular,
+}
+
+impl Lobe {
+    /// Returns the `BSDF_*` flag of the lobe.
+    pub fn flag(&self) -> u8 {
+        match self {
+            Self::Diffuse => BSDF_DIFFUSE,
+            Self::Glossy => BSDF_GLOSSY,
+            Self::Specular => BSDF_SPECULAR,
+        }
+    }
+}
+
+/// An event along a light path.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathEvent {
+    /// The camera.
+    Camera,
+
+    /// Scattering at a surface.
+    Scatter {
+        /// Whether light was transmitted through the surface instead of
+        /// reflected.
+        transmission: bool,
+
+        /// The scattering lobe.
+        lobe: Lobe,
+    },
+
+    /// Scattering in a participating medium.
+    Volume,
+
+    /// Emission by a light source or emissive surface.
+    Light,
+}
+
+impl PathEvent {
+    /// Returns the surface scattering event of a sampled BxDF lobe.
+    ///
+    /// * `sampled_type` - The sampled lobe.
+    pub fn scatter(sampled_type: BxDFType) -> Self {
+        let lobe = if sampled_type.matches(BSDF_SPECULAR) {
+            Lobe::Specular
+        } else if sampled_type.matches(BSDF_GLOSSY) {
+            Lobe::Glossy
+        } else {
+            Lobe::Diffuse
+        };
+        Self::Scatter {
+            transmission: sampled_type.matches(BSDF_TRANSMISSION),
+            lobe,
+        }
+    }
+
+    /// Returns the `BxDFType` selecting the lobes of a scattering event or
+    /// `None` for other events.
+    pub fn bxdf_type(&self) -> Option<BxDFType> {
+        match self {
+            Self::Scatter { transmission, lobe } => {
+                let direction = if *transmission {
+                    BSDF_TRANSMISSION
+                } else {
+                    BSDF_REFLECTION
+                };
+                Some(BxDFType::from(direction | lobe.flag()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Scattering events of the non-specular lobes that direct lighting is
+/// evaluated for.
+pub const DIRECT_LIGHTING_EVENTS: [PathEvent; 4] = [
+    PathEvent::Scatter {
+        transmission: false,
+        lobe: Lobe::Diffuse,
+    },
+    PathEvent::Scatter {
+        transmission: false,
+        lobe: Lobe::Glossy,
+    },
+    PathEvent::Scatter {
+        transmission: true,
+        lobe: Lobe::Diffuse,
+    },
+    PathEvent::Scatter {
+        transmission: true,
+        lobe: Lobe::Glossy,
+    },
+];
+
+/// Matches a single path event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum EventPattern {
+    /// Matches the camera.
+    Camera,
+
+    /// Matches a light.
+    Light,
+
+    /// Matches scattering in a medium.
+    Volume,
+
+    /// Matches a surface scattering event. `None` leaves the direction or
+    /// lobe unconstrained and an unconstrained pattern also matches
+    /// scattering in a medium.
+    Scatter {
+        transmission: Option<bool>,
+        lobe: Option<Lobe>,
+    },
+}
+
+impl EventPattern {
+    /// Returns whether the pattern matches an event.
+    ///
+    /// * `event` - The event.
+    fn matches(&self, event: &PathEvent) -> bool {
+        match (self, event) {
+            (Self::Camera, PathEvent::Camera) => true,
+            (Self::Light, PathEvent::Light) => true,
+            (Self::Volume, PathEvent::Volume) => true,
+            (
+                Self::Scatter {
+                    transmission: None,
+                    lobe: None,
+                },
+                PathEvent::Volume,
+            ) => true,
+            (
+                Self::Scatter {
+                    transmission: t,
+                    lobe: l,
+                },
+                PathEvent::Scatter { transmission, lobe },
+            ) => t.iter().all(|t| t == transmission) && l.iter().all(|l| l == lobe),
+            _ => false,
+        }
+    }
+}
+
+/// Repetition of part of an expression.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Repetition {
+    /// `?`
+    ZeroOrOne,
+
+    /// `*`
+    ZeroOrMore,
+
+    /// `+`
+    OneOrMore,
+}
+
+/// Node of a parsed light path expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// A single event.
+    Event(EventPattern),
+
+    /// Nodes matched one after another.
+    Sequence(Vec<Node>),
+
+    /// Any one of the nodes.
+    Alternation(Vec<Node>),
+
+    /// A repeated node.
+    Repeat(Box<Node>, Repetition),
+}
+
+impl Node {
+    /// Returns the positions in a path where matches of the node can end
+    /// given the positions where they can start.
+    ///
+    /// * `path`   - The path.
+    /// * `starts` - Whether a match can start before each event of the path
+    ///              and at its end.
+    fn advance(&self, path: &[PathEvent], starts: &[bool]) -> Vec<bool> {
+        match self {
+            Self::Event(pattern) => {
+                let mut ends = vec![false; starts.len()];
+                for (i, event) in path.iter().enumerate() {
+                    ends[i + 1] = starts[i] && pattern.matches(event);
+                }
+                ends
+            }
+            Self::Sequence(nodes) => nodes
+                .iter()
+                .fold(starts.to_vec(), |acc, node| node.advance(path, &acc)),
+            Self::Alternation(nodes) => {
+                let mut ends = vec![false; starts.len()];
+                for node in nodes {
+                    for (end, e) in ends.iter_mut().zip(node.advance(path, starts)) {
+                        *end |= e;
+                    }
+                }
+                ends
+            }
+            Self::Repeat(node, repetition) => {
+                let mut ends = match repetition {
+                    Repetition::OneOrMore => vec![false; starts.len()],
+                    _ => starts.to_vec(),
+                };
+
+                // Only newly reached positions need to be expanded again.
+                let mut frontier = starts.to_vec();
+                loop {
+                    let next = node.advance(path, &frontier);
+                    let mut reached = false;
+                    for i in 0..next.len() {
+                        frontier[i] = next[i] && !ends[i];
+                        ends[i] |= next[i];
+                        reached |= frontier[i];
+                    }
+                    if !reached || *repetition == Repetition::ZeroOrOne {
+                        break;
+                    }
+                }
+                ends
+            }
+        }
+    }
+}
+
+/// A parsed light path expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightPathExpression {
+    /// The root node.
+    root: Node,
+}
+
+impl LightPathExpression {
+    /// Parse a light path expression.
+    ///
+    /// * `s` - The expression.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut parser = Parser {
+            source: s,
+            chars: &chars,
+            pos: 0,
+        };
+        let root = parser.alternation()?;
+        match parser.peek() {
+            None => Ok(Self { root }),
+            Some(c) => Err(parser.unexpected(c)),
+        }
+    }
+
+    /// Returns whether the expression matches a whole path.
+    ///
+    /// * `path` - The path from the camera to the light.
+    pub fn matches(&self, path: &[PathEvent]) -> bool {
+        let mut starts = vec![false; path.len() + 1];
+        starts[0] = true;
+        self.root.advance(path, &starts)[path.len()]
+    }
+}
+
+/// Recursive descent parser for light path expressions.
+struct Parser<'a> {
+    /// The expression as given.
+    source: &'a str,
+
+    /// Characters of the expression without whitespace.
+    chars: &'a [char],
+
+    /// Position of the next character.
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the next character without consuming it.
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Returns an error for an unexpected character.
+    ///
+    /// * `c` - The character.
+    fn unexpected(&self, c: char) -> String {
+        format!(
+            "Unexpected '{}' in light path expression '{}'",
+            c, self.source
+        )
+    }
+
+    /// Parse sequences separated by `|`.
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            nodes.push(self.sequence()?);
+        }
+        Ok(if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Node::Alternation(nodes)
+        })
+    }
+
+    /// Parse repeated atoms up to the end of a group or alternative.
+    fn sequence(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let mut node = self.atom()?;
+            while let Some(repetition) = match self.peek() {
+                Some('?') => Some(Repetition::ZeroOrOne),
+                Some('*') => Some(Repetition::ZeroOrMore),
+                Some('+') => Some(Repetition::OneOrMore),
+                _ => None,
+            } {
+                self.pos += 1;
+                node = Node::Repeat(Box::new(node), repetition);
+            }
+            nodes.push(node);
+        }
+        Ok(if nodes.len() == 1 {
+            nodes.pop().unwrap()
+        } else {
+            Node::Sequence(nodes)
+        })
+    }
+
+    /// Parse an event or a group.
+    fn atom(&mut self) -> Result<Node, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| format!("Unexpected end of light path expression '{}'", self.source))?;
+        self.pos += 1;
+        let any = EventPattern::Scatter {
+            transmission: None,
+            lobe: None,
+        };
+        let pattern = match c {
+            'C' => EventPattern::Camera,
+            'L' => EventPattern::Light,
+            'V' => EventPattern::Volume,
+            '.' => any,
+            '(' => {
+                let node = self.alternation()?;
+                return match self.peek() {
+                    Some(')') => {
+                        self.pos += 1;
+                        Ok(node)
+                    }
+                    Some(c) => Err(self.unexpected(c)),
+                    None => Err(format!(
+                        "Missing ')' in light path expression '{}'",
+                        self.source
+                    )),
+                };
+            }
+            '<' => {
+                let mut pattern = any;
+                loop {
+                    match self.peek() {
+                        Some('>') => break,
+                        Some(c) => {
+                            pattern = self.constrain(pattern, c)?;
+                            self.pos += 1;
+                        }
+                        None => {
+                            return Err(format!(
+                                "Missing '>' in light path expression '{}'",
+                                self.source
+                            ))
+                        }
+                    }
+                }
+                self.pos += 1;
+                pattern
+            }
+            _ => self.constrain(any, c)?,
+        };
+        Ok(Node::Event(pattern))
+    }
+
+    /// Returns a scattering event pattern further constrained by a direction
+    /// or lobe letter. `.` leaves the pattern unchanged.
+    ///
+    /// * `pattern` - The pattern.
+    /// * `c`       - The letter.
+    fn constrain(&self, pattern: EventPattern, c: char) -> Result<EventPattern, String> {
+        let (transmission, lobe) = match pattern {
+            EventPattern::Scatter { transmission, lobe } => (transmission, lobe),
+            _ => unreachable!(),
+        };
+        let constrained = match (c, transmission, lobe) {
+            ('.', _, _) => pattern,
+            ('R', None, _) | ('T', None, _) => EventPattern::Scatter {
+                transmission: Some(c == 'T'),
+                lobe,
+            },
+            ('D', _, None) | ('G', _, None) | ('S', _, None) => EventPattern::Scatter {
+                transmission,
+                lobe: Some(match c {
+                    'D' => Lobe::Diffuse,
+                    'G' => Lobe::Glossy,
+                    _ => Lobe::Specular,
+                }),
+            },
+            _ => return Err(self.unexpected(c)),
+        };
+        Ok(constrained)
+    }
+}
+
+/// An AOV with the radiance arriving along paths that match a light path
+/// expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightPathAov {
+    /// Name of the AOV used in the output filename.
+    pub name: String,
+
+    /// The light path expression.
+    pub expression: LightPathExpression,
+}
+
+impl LightPathAov {
+    /// Parses an AOV specification of the form `name:expression`, for
+    /// example `diffuse:C<RD>L`.
+    ///
+    /// * `spec` - The AOV specification.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, expression) = match spec.find(':') {
+            Some(i) => (spec[..i].trim(), &spec[i + 1..]),
+            None => return Err(format!("Expected 'name:expression' in '{}'", spec)),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(format!("Invalid AOV name '{}'", name));
+        }
+        Ok(Self {
+            name: String::from(name),
+            expression: LightPathExpression::parse(expression)?,
+        })
+    }
+}
+
+/// Records the radiance contributions of a camera ray with the paths they
+/// arrived along.
+#[derive(Clone)]
+pub struct LightPathRecorder {
+    /// Events of the current path starting at the camera.
+    path: Vec<PathEvent>,
+
+    /// Throughput from the camera to each event of the current path.
+    throughput: Vec<Spectrum>,
+
+    /// The contributions and their paths ending at a light.
+    contributions: Vec<(Vec<PathEvent>, Spectrum)>,
+}
+
+impl LightPathRecorder {
+    /// Returns a new `LightPathRecorder` at the camera.
+    pub fn new() -> Self {
+        Self {
+            path: vec![PathEvent::Camera],
+            throughput: vec![Spectrum::new(1.0)],
+            contributions: vec![],
+        }
+    }
+
+    /// Discard the contributions and return to the camera.
+    pub fn clear(&mut self) {
+        self.path.truncate(1);
+        self.throughput.truncate(1);
+        self.contributions.clear();
+    }
+
+    /// Extend the current path by a scattering event.
+    ///
+    /// * `event`  - The event.
+    /// * `weight` - Throughput of the event.
+    pub fn push(&mut self, event: PathEvent, weight: Spectrum) {
+        let beta = *self.throughput.last().unwrap() * weight;
+        self.path.push(event);
+        self.throughput.push(beta);
+    }
+
+    /// Remove the last event of the current path.
+    pub fn pop(&mut self) {
+        if self.path.len() > 1 {
+            self.path.pop();
+            self.throughput.pop();
+        }
+    }
+
+    /// Record light emitted towards the last event of the current path.
+    ///
+    /// * `le` - The emitted radiance.
+    pub fn record(&mut self, le: Spectrum) {
+        if !le.is_black() {
+            let mut path = self.path.clone();
+            path.push(PathEvent::Light);
+            let beta = *self.throughput.last().unwrap();
+            self.contributions.push((path, beta * le));
+        }
+    }
+
+    /// Record light scattered towards the last event of the current path by
+    /// one more event directly from a light.
+    ///
+    /// * `event` - The scattering event.
+    /// * `l`     - The scattered radiance.
+    pub fn record_scattered(&mut self, event: PathEvent, l: Spectrum) {
+        if !l.is_black() {
+            self.push(event, Spectrum::new(1.0));
+            self.record(l);
+            self.pop();
+        }
+    }
+
+    /// Returns the contributions and their paths.
+    pub fn contributions(&self) -> &[(Vec<PathEvent>, Spectrum)] {
+        &self.contributions
+    }
+
+    /// Returns the sum of the contributions matching each AOV's expression.
+    ///
+    /// * `aovs` - The AOVs.
+    pub fn aov_values(&self, aovs: &[LightPathAov]) -> Vec<Spectrum> {
+        aovs.iter()
+            .map(|aov| {
+                self.contributions
+                    .iter()
+                    .filter(|(path, _)| aov.expression.matches(path))
+                    .fold(Spectrum::new(0.0), |sum, (_, l)| sum + *l)
+            })
+            .collect()
+    }
+}
+
+impl Default for LightPathRecorder {
+    /// Returns a new `LightPathRecorder` at the camera.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RD: PathEvent = PathEvent::Scatter {
+        transmission: false,
+        lobe: Lobe::Diffuse,
+    };
+    const RS: PathEvent = PathEvent::Scatter {
+        transmission: false,
+        lobe: Lobe::Specular,
+    };
+    const TS: PathEvent = PathEvent::Scatter {
+        transmission: true,
+        lobe: Lobe::Specular,
+    };
+
+    fn matches(expression: &str, events: &[PathEvent]) -> bool {
+        let mut path = vec![PathEvent::Camera];
+        path.extend_from_slice(events);
+        path.push(PathEvent::Light);
+        LightPathExpression::parse(expression)
+            .unwrap()
+            .matches(&path)
+    }
+
+    #[test]
+    fn expressions_match_lobe_sequences() {
+        assert!(matches("C<RD>L", &[RD]));
+        assert!(!matches("C<RD>L", &[RS]));
+        assert!(!matches("C<RD>L", &[RS, RD]));
+        assert!(matches("CL", &[]));
+        assert!(matches("C<RD>*L", &[]));
+        assert!(matches("C<RD>*L", &[RD, RD]));
+        assert!(matches("CS+<RD>L", &[RS, TS, RD]));
+        assert!(!matches("CS+<RD>L", &[RD]));
+        assert!(matches("C.*L", &[TS, RD, RS]));
+        assert!(matches("C<T.>S?DL", &[TS, RD]));
+        assert!(matches("C<T.>S?DL", &[TS, RS, RD]));
+        assert!(matches("C(<RS>|<TS>)+L", &[RS, TS]));
+        assert!(!matches("C(<RS>|<TS>)+L", &[RS, RD]));
+        assert!(matches("C (R S)* L", &[RS, RS]));
+        assert!(matches("C(D*)*L", &[RD, RD]));
+        assert!(matches("CV+L", &[PathEvent::Volume, PathEvent::Volume]));
+        assert!(!matches("CV<RD>L", &[RD, PathEvent::Volume]));
+        assert!(matches("C.*L", &[PathEvent::Volume, RS]));
+        assert!(!matches("CDL", &[PathEvent::Volume]));
+    }
+
+    #[test]
+    fn sampled_lobes_map_to_scattering_events() {
+        assert_eq!(
+            PathEvent::scatter(BxDFType::from(BSDF_REFLECTION | BSDF_DIFFUSE)),
+            RD
+        );
+        assert_eq!(
+            PathEvent::scatter(BxDFType::from(BSDF_TRANSMISSION | BSDF_SPECULAR)),
+            TS
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for expression in [
+            "C<RD", "C(DL", "C<RT>L", "C<DS>L", "C<V>L", "CX", "C)L", "*C",
+        ]
+        .iter()
+        {
+            assert!(
+                LightPathExpression::parse(expression).is_err(),
+                "{}",
+                expression
+            );
+        }
+        assert!(LightPathAov::parse("C<RD>L").is_err());
+        assert!(LightPathAov::parse("a/b:C<RD>L").is_err());
+        assert_eq!(
+            LightPathAov::parse("diffuse:C<RD>L").unwrap().name,
+            "diffuse"
+        );
+    }
+
+    #[test]
+    fn recorder_weights_contributions_by_throughput() {
+        let mut recorder = LightPathRecorder::new();
+        recorder.record(Spectrum::new(1.0));
+        recorder.push(RS, Spectrum::new(0.5));
+        recorder.record_scattered(RD, Spectrum::new(2.0));
+        recorder.pop();
+        recorder.record_scattered(RD, Spectrum::new(4.0));
+
+        let aovs = [
+            LightPathAov::parse("direct:C<RD>L").unwrap(),
+            LightPathAov::parse("mirror:CS+DL").unwrap(),
+            LightPathAov::parse("emission:CL").unwrap(),
+        ];
+        let values = recorder.aov_values(&aovs);
+        for (value, expected) in values.iter().zip([4.0, 1.0, 1.0].iter()) {
+            assert!((value.y() - Spectrum::new(*expected).y()).abs() < 1e-4);
+        }
+    }
+}
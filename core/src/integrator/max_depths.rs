@@ -0,0 +1,140 @@
+//! Maximum Path Depths
+
+use crate::paramset::*;
+use crate::pbrt::*;
+use crate::reflection::*;
+
+/// Stores the maximum path depth overall and per BxDF lobe type. A lobe can
+/// only be sampled to extend a path if the next depth is within the overall
+/// limit and the limit for every type of the lobe.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaxDepths {
+    /// Maximum depth for all paths.
+    pub total: usize,
+
+    /// Maximum depth for extending paths by diffuse lobes.
+    pub diffuse: usize,
+
+    /// Maximum depth for extending paths by glossy lobes.
+    pub glossy: usize,
+
+    /// Maximum depth for extending paths by specular lobes.
+    pub specular: usize,
+
+    /// Maximum depth for extending paths by transmission lobes.
+    pub transmission: usize,
+}
+
+impl MaxDepths {
+    /// Create a new `MaxDepths` with the same limit for all lobe types.
+    ///
+    /// * `max_depth` - Maximum depth.
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            total: max_depth,
+            diffuse: max_depth,
+            glossy: max_depth,
+            specular: max_depth,
+            transmission: max_depth,
+        }
+    }
+
+    /// Returns whether a path at the given depth can be extended by sampling
+    /// a lobe of the given type.
+    ///
+    /// * `bxdf_type` - The lobe type.
+    /// * `depth`     - The current path depth.
+    pub fn allows(&self, bxdf_type: BxDFType, depth: usize) -> bool {
+        let next = depth + 1;
+        next < self.total
+            && (!bxdf_type.matches(BSDF_DIFFUSE) || next < self.diffuse)
+            && (!bxdf_type.matches(BSDF_GLOSSY) || next < self.glossy)
+            && (!bxdf_type.matches(BSDF_SPECULAR) || next < self.specular)
+            && (!bxdf_type.matches(BSDF_TRANSMISSION) || next < self.transmission)
+    }
+
+    /// Returns whether a path vertex at the given depth can scatter light
+    /// with a lobe of the given type. This is the limit used by path tracers
+    /// where the vertex found after the last scattering event still adds the
+    /// light emitted there.
+    ///
+    /// * `bxdf_type` - The lobe type.
+    /// * `depth`     - Depth of the vertex.
+    pub fn scatters(&self, bxdf_type: BxDFType, depth: usize) -> bool {
+        depth < self.total
+            && (!bxdf_type.matches(BSDF_DIFFUSE) || depth < self.diffuse)
+            && (!bxdf_type.matches(BSDF_GLOSSY) || depth < self.glossy)
+            && (!bxdf_type.matches(BSDF_SPECULAR) || depth < self.specular)
+            && (!bxdf_type.matches(BSDF_TRANSMISSION) || depth < self.transmission)
+    }
+}
+
+impl From<&ParamSet> for MaxDepths {
+    /// Create `MaxDepths` from given parameter set. The overall limit is read
+    /// from `max_depth` or `maxdepth` as used by pbrt scene files. The per
+    /// lobe limits default to the overall limit.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        let maxdepth = params.find_one_int("maxdepth", 5);
+        let total = params.find_one_int("max_depth", maxdepth) as usize;
+
+        Self {
+            total,
+            diffuse: params.find_one_int("max_diffuse_depth", total as Int) as usize,
+            glossy: params.find_one_int("max_glossy_depth", total as Int) as usize,
+            specular: params.find_one_int("max_specular_depth", total as Int) as usize,
+            transmission: params.find_one_int("max_transmission_depth", total as Int) as usize,
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_limit_for_all_lobes() {
+        let depths = MaxDepths::new(3);
+        let t = BxDFType::from(BSDF_REFLECTION | BSDF_SPECULAR);
+        assert!(depths.allows(t, 0));
+        assert!(depths.allows(t, 1));
+        assert!(!depths.allows(t, 2));
+    }
+
+    #[test]
+    fn per_lobe_limit() {
+        let mut params = ParamSet::new();
+        params.add_int("max_depth", &[5]);
+        params.add_int("max_transmission_depth", &[2]);
+        let depths = MaxDepths::from(&params);
+        assert_eq!(depths.specular, 5);
+        assert_eq!(depths.transmission, 2);
+
+        let reflect = BxDFType::from(BSDF_REFLECTION | BSDF_SPECULAR);
+        let transmit = BxDFType::from(BSDF_TRANSMISSION | BSDF_SPECULAR);
+        assert!(depths.allows(reflect, 2));
+        assert!(depths.allows(transmit, 0));
+        assert!(!depths.allows(transmit, 1));
+
+        assert!(depths.scatters(reflect, 4));
+        assert!(!depths.scatters(reflect, 5));
+        assert!(depths.scatters(transmit, 1));
+        assert!(!depths.scatters(transmit, 2));
+    }
+
+    #[test]
+    fn pbrt_maxdepth() {
+        let mut params = ParamSet::new();
+        params.add_int("maxdepth", &[2]);
+        let depths = MaxDepths::from(&params);
+        assert_eq!(depths, MaxDepths::new(2));
+
+        params.add_int("max_depth", &[3]);
+        assert_eq!(MaxDepths::from(&params).total, 3);
+    }
+}
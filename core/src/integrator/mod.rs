@@ -1,7 +1,12 @@
 //! Integrator
 
 mod sampler_integrator;
+mod adaptive_sampling;
 mod common;
+mod light_distribution;
+mod light_path;
+mod max_depths;
+mod render_progress;
 
 use crate::geometry::*;
 use crate::sampler::*;
@@ -10,7 +15,12 @@ use crate::spectrum::*;
 use std::sync::Arc;
 
 // Re-export.
+pub use adaptive_sampling::*;
 pub use common::*;
+pub use light_distribution::*;
+pub use light_path::*;
+pub use max_depths::*;
+pub use render_progress::*;
 pub use sampler_integrator::*;
 
 /// Integrator interface.
@@ -0,0 +1,220 @@
+//! Render Progress
+
+use crate::geometry::*;
+use crate::pbrt::*;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+lazy_static! {
+    /// Progress of the render in progress. Only one scene is rendered at a
+    /// time so a single global instance is shared by all integrators.
+    pub static ref RENDER_PROGRESS: RenderProgress = RenderProgress::new();
+}
+
+/// RGB values of the image being rendered.
+#[derive(Clone, Debug)]
+pub struct ImageSnapshot {
+    /// Resolution of the image.
+    pub resolution: Point2i,
+
+    /// RGB values in row major order.
+    pub rgb: Vec<Float>,
+}
+
+/// Tracks progress of the current render so it can be queried and cancelled
+/// from other threads.
+pub struct RenderProgress {
+    /// Number of completed passes.
+    passes_done: AtomicUsize,
+
+    /// Number of tiles in the current pass.
+    tiles_total: AtomicUsize,
+
+    /// Number of completed tiles in the current pass.
+    tiles_done: AtomicUsize,
+
+    /// Whether the render has been cancelled.
+    cancelled: AtomicBool,
+
+    /// Whether the image should be written after every pass so partial
+    /// results are available while rendering.
+    progressive: AtomicBool,
+
+    /// Path of the last image written.
+    image_file: Mutex<Option<String>>,
+
+    /// Current values of the image while rendering progressively.
+    snapshot: Mutex<Option<ImageSnapshot>>,
+}
+
+impl RenderProgress {
+    /// Create a new `RenderProgress`.
+    pub fn new() -> Self {
+        Self {
+            passes_done: AtomicUsize::new(0),
+            tiles_total: AtomicUsize::new(0),
+            tiles_done: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+            progressive: AtomicBool::new(false),
+            image_file: Mutex::new(None),
+            snapshot: Mutex::new(None),
+        }
+    }
+
+    /// Clears progress and cancellation before rendering a new scene.
+    pub fn reset(&self) {
+        self.passes_done.store(0, Ordering::SeqCst);
+        self.tiles_total.store(0, Ordering::SeqCst);
+        self.tiles_done.store(0, Ordering::SeqCst);
+        self.cancelled.store(false, Ordering::SeqCst);
+        *self.image_file.lock().unwrap() = None;
+        *self.snapshot.lock().unwrap() = None;
+    }
+
+    /// Records the start of a pass.
+    ///
+    /// * `n_tiles` - Number of tiles in the pass.
+    pub fn start_pass(&self, n_tiles: usize) {
+        self.tiles_done.store(0, Ordering::SeqCst);
+        self.tiles_total.store(n_tiles, Ordering::SeqCst);
+    }
+
+    /// Records tiles added to the current pass by splitting slow tiles.
+    ///
+    /// * `n_tiles` - Number of tiles added.
+    pub fn add_tiles(&self, n_tiles: usize) {
+        self.tiles_total.fetch_add(n_tiles, Ordering::SeqCst);
+    }
+
+    /// Records completion of a tile.
+    pub fn tile_done(&self) {
+        self.tiles_done.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Records completion of a pass.
+    pub fn pass_done(&self) {
+        self.passes_done.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns the number of completed passes.
+    pub fn passes_done(&self) -> usize {
+        self.passes_done.load(Ordering::SeqCst)
+    }
+
+    /// Returns the completed fraction of the current pass in [0, 1].
+    pub fn pass_fraction(&self) -> f32 {
+        let total = self.tiles_total.load(Ordering::SeqCst);
+        if total == 0 {
+            0.0
+        } else {
+            self.tiles_done.load(Ordering::SeqCst) as f32 / total as f32
+        }
+    }
+
+    /// Requests that the current render stops as soon as possible. Tiles
+    /// already started are finished but no image is written.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the render has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Sets whether the image is written after every pass.
+    ///
+    /// * `progressive` - Write the image after every pass.
+    pub fn set_progressive(&self, progressive: bool) {
+        self.progressive.store(progressive, Ordering::SeqCst);
+    }
+
+    /// Returns whether the image is written after every pass.
+    pub fn is_progressive(&self) -> bool {
+        self.progressive.load(Ordering::SeqCst)
+    }
+
+    /// Records the path of an image that was written.
+    ///
+    /// * `path` - Path to the image file.
+    pub fn set_image_file(&self, path: &str) {
+        *self.image_file.lock().unwrap() = Some(String::from(path));
+    }
+
+    /// Returns the path of the last image written.
+    pub fn image_file(&self) -> Option<String> {
+        self.image_file.lock().unwrap().clone()
+    }
+
+    /// Updates a region of the image snapshot. The snapshot is cleared to
+    /// black if the resolution changes.
+    ///
+    /// * `resolution` - Resolution of the image.
+    /// * `bounds`     - The region relative to the image origin.
+    /// * `rgb`        - The RGB values of the region in row major order.
+    pub fn update_snapshot(&self, resolution: &Point2i, bounds: &Bounds2i, rgb: &[Float]) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        if !snapshot
+            .as_ref()
+            .is_some_and(|s| s.resolution == *resolution)
+        {
+            *snapshot = Some(ImageSnapshot {
+                resolution: *resolution,
+                rgb: vec![0.0; 3 * (resolution.x * resolution.y) as usize],
+            });
+        }
+        let snapshot = snapshot.as_mut().unwrap();
+
+        let width = 3 * bounds.diagonal().x as usize;
+        for (y, row) in (bounds.p_min.y..bounds.p_max.y).zip(rgb.chunks_exact(width)) {
+            let offset = 3 * (y * resolution.x + bounds.p_min.x) as usize;
+            snapshot.rgb[offset..offset + width].copy_from_slice(row);
+        }
+    }
+
+    /// Returns a copy of the current image snapshot.
+    pub fn snapshot(&self) -> Option<ImageSnapshot> {
+        self.snapshot.lock().unwrap().clone()
+    }
+}
+
+impl Default for RenderProgress {
+    /// Returns a new `RenderProgress`.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_is_updated_by_region() {
+        let progress = RenderProgress::new();
+        assert!(progress.snapshot().is_none());
+
+        let resolution = Point2i::new(2, 2);
+        let bounds = Bounds2i::new(Point2i::new(1, 0), Point2i::new(2, 2));
+        progress.update_snapshot(&resolution, &bounds, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let snapshot = progress.snapshot().unwrap();
+        assert_eq!(snapshot.resolution, resolution);
+        assert_eq!(
+            snapshot.rgb,
+            vec![0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 4.0, 5.0, 6.0]
+        );
+
+        // A new resolution starts a new image.
+        let resolution = Point2i::new(1, 1);
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(1, 1));
+        progress.update_snapshot(&resolution, &bounds, &[7.0, 8.0, 9.0]);
+        assert_eq!(progress.snapshot().unwrap().rgb, vec![7.0, 8.0, 9.0]);
+
+        progress.reset();
+        assert!(progress.snapshot().is_none());
+    }
+}
@@ -3,15 +3,30 @@
 use super::*;
 use crate::app::OPTIONS;
 use crate::camera::*;
+use crate::film::{AuxiliarySample, FilmTile, RenderCheckpoint, FILM_HISTORY};
 use crate::geometry::*;
+use crate::material::TransportMode;
 use crate::pbrt::*;
+use crate::profiler::*;
 use crate::reflection::*;
 use crate::sampler::*;
 use crate::scene::*;
 use crate::spectrum::*;
 use itertools::iproduct;
 use rayon::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+/// Width of the pixel blocks covered by each preview pixel, from coarsest to
+/// finest, when rendering previews before the full resolution image.
+const PREVIEW_BLOCK_SIZES: [Int; 2] = [8, 4];
+
+/// Smallest height or width in pixels of the tiles created by splitting slow
+/// tiles.
+const MIN_SPLIT_TILE_SIZE: Int = 2;
+
+crate::stat_counter!("Integrator/Camera rays traced", N_CAMERA_RAYS);
 
 /// Common data for sampler integrators.
 pub struct SamplerIntegratorData {
@@ -19,32 +34,37 @@ pub struct SamplerIntegratorData {
     /// to trace rays and for supplying sample positions used by integrators.
     pub sampler: ArcSampler,
 
-    /// The camera.
-    pub camera: Arc<Mutex<ArcCamera>>,
+    /// The camera. Rays are generated under the read lock so tiles render
+    /// concurrently; the film is only modified under the write lock.
+    pub camera: Arc<RwLock<ArcCamera>>,
 
     /// Pixel bounds for the image.
     pub pixel_bounds: Bounds2i,
     
-    /// Maximum recursion depth.
-    pub max_depth: usize,
+    /// Maximum recursion depths.
+    pub max_depths: MaxDepths,
+
+    /// Adaptive sampling controls. Disabled by default.
+    pub adaptive_sampling: AdaptiveSampling,
 }
 
 impl SamplerIntegratorData {
     /// Create a new `SamplerIntegratorData`.
     ///
-    /// * `max_depth`    - Maximum recursion depth.
+    /// * `max_depths`   - Maximum recursion depths.
     /// * `camera`       - The camera.
     /// * `sampler`      - Sampler responsible for choosing point on image plane
     ///                    from which to trace rays.
     /// * `pixel_bounds` - Pixel bounds for the image.
     pub fn new(
-        max_depth: usize,
+        max_depths: MaxDepths,
         camera: ArcCamera, sampler: ArcSampler, pixel_bounds: Bounds2i) -> Self {
         Self {
-            camera: Arc::new(Mutex::new(Arc::clone(&camera))),
-            max_depth,
+            camera: Arc::new(RwLock::new(Arc::clone(&camera))),
+            max_depths,
             sampler,
             pixel_bounds,
+            adaptive_sampling: AdaptiveSampling::default(),
         }
     }
 }
@@ -71,6 +91,24 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
         sampler: &mut ArcSampler,
         depth: usize,
     ) -> Spectrum {
+        match self.specular_reflect_ray(ray, isect, sampler) {
+            Some((mut rd, weight)) => weight * self.li(&mut rd, scene, sampler, depth + 1),
+            None => Spectrum::new(0.0),
+        }
+    }
+
+    /// Returns the ray for specular reflection and its weight or `None` if
+    /// the surface doesn't reflect specularly.
+    ///
+    /// * `ray`     - The ray.
+    /// * `isect`   - The surface interaction.
+    /// * `sampler` - The sampler.
+    fn specular_reflect_ray(
+        &self,
+        ray: &Ray,
+        isect: &SurfaceInteraction,
+        sampler: &mut ArcSampler,
+    ) -> Option<(Ray, Spectrum)> {
         if let Some(bsdf) = isect.bsdf.clone() {
             // Compute specular reflection direction `wi` and BSDF value.
             let wo = isect.hit.wo;
@@ -113,14 +151,11 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
                     ));
                 }
 
-                return f
-                    * self.li(&mut rd, Arc::clone(&scene), sampler, depth + 1)
-                    * wi.abs_dot(&ns)
-                    / pdf;
+                return Some((rd, f * wi.abs_dot(&ns) / pdf));
             }
         }
 
-        Spectrum::new(0.0)
+        None
     }
 
     /// Trace rays for specular refraction.
@@ -138,6 +173,24 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
         sampler: &mut ArcSampler,
         depth: usize,
     ) -> Spectrum {
+        match self.specular_transmit_ray(ray, isect, sampler) {
+            Some((mut rd, weight)) => weight * self.li(&mut rd, scene, sampler, depth + 1),
+            None => Spectrum::new(0.0),
+        }
+    }
+
+    /// Returns the ray for specular refraction and its weight or `None` if
+    /// the surface doesn't transmit specularly.
+    ///
+    /// * `ray`     - The ray.
+    /// * `isect`   - The surface interaction.
+    /// * `sampler` - The sampler.
+    fn specular_transmit_ray(
+        &self,
+        ray: &Ray,
+        isect: &SurfaceInteraction,
+        sampler: &mut ArcSampler,
+    ) -> Option<(Ray, Spectrum)> {
         if let Some(bsdf) = &isect.bsdf {
             let wo = isect.hit.wo;
             let p = isect.hit.p;
@@ -238,14 +291,80 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
                     ));
                 }
 
-                return f
-                    * self.li(&mut rd, Arc::clone(&scene), sampler, depth + 1)
-                    * wi.abs_dot(&ns)
-                    / pdf;
+                return Some((rd, f * wi.abs_dot(&ns) / pdf));
+            }
+        }
+
+        None
+    }
+
+    /// Returns the incident radiance at the origin of a camera ray. Paths the
+    /// integrator splats to the film are added to the tile instead so that
+    /// they are merged with it. Integrators that don't splat use `li()`.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `tile`    - The film tile of the camera ray.
+    fn li_splats(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        _tile: &mut FilmTile,
+    ) -> Spectrum {
+        self.li(ray, scene, sampler, 0)
+    }
+
+    /// Returns the incident radiance at the origin of a camera ray and records
+    /// the paths it arrived along for light path expression AOVs. Integrators
+    /// that don't record paths leave `paths` empty.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `paths`   - Records the contributions and their paths.
+    fn li_paths(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        _paths: &mut LightPathRecorder,
+    ) -> Spectrum {
+        self.li(ray, scene, sampler, 0)
+    }
+
+    /// Returns the values of the film's auxiliary channels for a camera ray
+    /// from the first surface it hits.
+    ///
+    /// * `ray`   - The camera ray.
+    /// * `scene` - The scene.
+    fn auxiliary_sample(&self, ray: &Ray, scene: &Scene) -> AuxiliarySample {
+        let mut aux = AuxiliarySample::default();
+        let mut r = ray.clone();
+        if let Some(mut isect) = scene.intersect(&mut r) {
+            let wo = isect.hit.wo;
+            isect.compute_scattering_functions(&r, true, TransportMode::Radiance);
+            if let Some(bsdf) = isect.bsdf.as_ref() {
+                aux.albedo = bsdf.albedo(&wo);
+            }
+            aux.normal = isect.shading.n;
+            aux.p = isect.hit.p;
+            aux.depth = Some(ray.o.distance(isect.hit.p));
+            if let Some(primitive) = isect.primitive {
+                aux.ids = primitive.get_ids();
             }
         }
+        aux
+    }
 
-        Spectrum::new(0.0)
+    /// Returns the scale factor for splats when writing the image after the
+    /// given number of samples per pixel. Integrators that don't splat
+    /// contributions use 1.
+    ///
+    /// * `_samples_per_pixel` - Number of samples taken in each pixel.
+    fn splat_scale(&self, _samples_per_pixel: usize) -> Float {
+        1.0
     }
 
     /// Render the scene.
@@ -255,10 +374,227 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
     ///
     /// * `scene` - The scene.
     fn render(&mut self, scene: Arc<Scene>) {
+        // Keep the pixel statistics in the film so that passes continue
+        // adaptive sampling where the previous ones stopped.
+        if self.get_data().adaptive_sampling.is_enabled() {
+            let mut camera = self.get_data().camera.write().unwrap();
+            Arc::get_mut(&mut *camera)
+                .unwrap()
+                .film_mut()
+                .track_pixel_variance();
+        }
+
+        // Passes continue after those accumulated by previous frames so that
+        // new samples are taken.
+        let mut checkpoint = RenderCheckpoint {
+            pass: FILM_HISTORY.lock().unwrap().pending_passes(),
+            ..RenderCheckpoint::default()
+        };
+
+        // Continue an interrupted render from its checkpoint.
+        let mut resumed = false;
+        if OPTIONS.resume {
+            let mut camera = self.get_data().camera.write().unwrap();
+            match Arc::get_mut(&mut *camera).unwrap().read_film_checkpoint() {
+                Ok(restored) => {
+                    info!(
+                        "Resuming pass {} with {} tiles done.",
+                        restored.pass,
+                        restored.done_tiles.len()
+                    );
+                    checkpoint = restored;
+                    resumed = true;
+                }
+                Err(err) => warn!("{}. Starting a new render.", err),
+            }
+        }
+
+        // Show the composition quickly in interactive use before the full
+        // resolution passes.
+        if !resumed && (OPTIONS.coarse_to_fine || RENDER_PROGRESS.is_progressive()) {
+            self.render_previews(Arc::clone(&scene));
+            if RENDER_PROGRESS.is_cancelled() {
+                info!("Rendering cancelled.");
+                return;
+            }
+        }
+
+        // Render a single pass or keep adding passes until the time limit
+        // would be exceeded by another one. When sampling adaptively, passes
+        // are added while pixels can take more samples without a time limit
+        // and stop early once all pixels are done with one.
+        let start = Instant::now();
+        let mut n_passes = 0;
+        let mut samples_per_pixel = checkpoint.samples_per_pixel;
+        let progress = RenderState::new(checkpoint);
+        loop {
+            samples_per_pixel += self.render_pass(Arc::clone(&scene), &progress);
+            if RENDER_PROGRESS.is_cancelled() {
+                // Keep the tiles that were merged before cancelling.
+                if OPTIONS.checkpoint_interval.is_some() {
+                    let camera = self.get_data().camera.read().unwrap();
+                    progress.write_checkpoint(&**camera);
+                }
+                info!("Rendering cancelled.");
+                return;
+            }
+            n_passes += 1;
+            RENDER_PROGRESS.pass_done();
+            let pass = progress.pass_done(samples_per_pixel);
+            FILM_HISTORY.lock().unwrap().set_pending_passes(pass);
+
+            let adaptive_sampling = self.get_data().adaptive_sampling;
+            let more_passes = match OPTIONS.time_limit {
+                Some(time_limit) => {
+                    let elapsed = start.elapsed().as_secs_f64() as Float;
+                    let pass_time = elapsed / n_passes as Float;
+                    elapsed + pass_time <= time_limit
+                        && (!adaptive_sampling.is_enabled() || self.has_pixels_to_sample())
+                }
+                None => adaptive_sampling.max_samples > 0 && self.has_pixels_to_sample(),
+            };
+            if !more_passes {
+                break;
+            }
+
+            // Make the partial result available before the next pass.
+            if RENDER_PROGRESS.is_progressive() {
+                let splat_scale = self.splat_scale(samples_per_pixel);
+                let mut camera = self.get_data().camera.write().unwrap();
+                Arc::get_mut(&mut *camera).unwrap().write_image(splat_scale);
+            }
+        }
+
+        info!("Rendering finished.");
+
+        // Save final image after rendering.
+        let splat_scale = self.splat_scale(samples_per_pixel);
+        let data = self.get_data();
+        let camera_clone = Arc::clone(&data.camera);
+        let mut camera = camera_clone.write().unwrap();
+        let camera = Arc::get_mut(&mut *camera).unwrap();
+        if OPTIONS.time_limit.is_some() {
+            let elapsed = start.elapsed().as_secs_f64();
+            info!(
+                "Rendered {} passes with {} samples per pixel in {:.3}s.",
+                n_passes, samples_per_pixel, elapsed
+            );
+            camera.add_film_metadata("samplesPerPixel", &samples_per_pixel.to_string());
+            camera.add_film_metadata("renderTime", &format!("{:.3}", elapsed));
+        }
+        camera.write_image(splat_scale);
+        camera.remove_film_checkpoint();
+        info!("Output image written.");
+    }
+
+    /// Render previews at decreasing block sizes with one sample per block
+    /// and write each to the output image with the blocks filling the full
+    /// resolution image. The film is cleared afterwards.
+    ///
+    /// * `scene` - The scene.
+    fn render_previews(&self, scene: Arc<Scene>) {
+        let data = self.get_data();
+        let bounds = data.pixel_bounds;
+        let extent = bounds.diagonal();
+
+        for block_size in PREVIEW_BLOCK_SIZES.iter().copied() {
+            let nx = max(1, (extent.x + block_size - 1) / block_size);
+            let ny = max(1, (extent.y + block_size - 1) / block_size);
+
+            // Trace one ray through the center of each block. Rows are
+            // rendered in parallel with a sampler for each row.
+            let rows: Vec<Vec<Spectrum>> = (0..ny)
+                .into_par_iter()
+                .map(|by| {
+                    let mut sampler = Sampler::clone(&*data.sampler, by as u64);
+                    (0..nx)
+                        .map(|bx| {
+                            if RENDER_PROGRESS.is_cancelled() {
+                                return Spectrum::new(0.0);
+                            }
+
+                            let p_film = Point2f::new(
+                                min(
+                                    (bounds.p_min.x + bx * block_size) as Float
+                                        + 0.5 * block_size as Float,
+                                    bounds.p_max.x as Float,
+                                ),
+                                min(
+                                    (bounds.p_min.y + by * block_size) as Float
+                                        + 0.5 * block_size as Float,
+                                    bounds.p_max.y as Float,
+                                ),
+                            );
+                            let pixel = Point2i::new(p_film.x as Int, p_film.y as Int);
+                            Arc::get_mut(&mut sampler).unwrap().start_pixel(&pixel);
+                            let mut camera_sample =
+                                Arc::get_mut(&mut sampler).unwrap().get_camera_sample(&pixel);
+                            camera_sample.p_film = p_film;
+
+                            let (mut ray, ray_weight) = {
+                                let camera = data.camera.read().unwrap();
+                                camera.generate_ray_differential(&camera_sample)
+                            };
+                            ray.scale_differentials(block_size as Float);
+
+                            if ray_weight > 0.0 {
+                                let l = self.li(&mut ray, Arc::clone(&scene), &mut sampler, 0);
+                                if l.has_nans() || l.y() < 0.0 || l.y().is_infinite() {
+                                    Spectrum::new(0.0)
+                                } else {
+                                    l * ray_weight
+                                }
+                            } else {
+                                Spectrum::new(0.0)
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+
+            if RENDER_PROGRESS.is_cancelled() {
+                return;
+            }
+
+            let preview: Vec<Spectrum> = rows.into_iter().flatten().collect();
+            let mut camera = data.camera.write().unwrap();
+            let camera = Arc::get_mut(&mut *camera).unwrap();
+            camera.set_film_preview(&bounds, block_size, &preview);
+            camera.write_image(1.0);
+            info!("Preview at 1/{} resolution written.", block_size);
+        }
+
+        let mut camera = data.camera.write().unwrap();
+        Arc::get_mut(&mut *camera).unwrap().clear_film();
+    }
+
+    /// Returns `true` if adaptive sampling is enabled and some pixel has
+    /// neither converged nor taken the maximum number of samples.
+    fn has_pixels_to_sample(&self) -> bool {
+        let data = self.get_data();
+        if !data.adaptive_sampling.is_enabled() {
+            return false;
+        }
+        let camera = data.camera.read().unwrap();
+        let film = camera.film();
+        data.pixel_bounds
+            .into_iter()
+            .any(|pixel| !data.adaptive_sampling.is_done(&film.pixel_variance(&pixel)))
+    }
+
+    /// Render one pass over the image and merge it into the film. Each pass
+    /// takes `samples_per_pixel` samples in every pixel and uses different
+    /// sampler seeds and sample indices from previous passes. Tiles that were
+    /// merged before the render was resumed are skipped. Returns the number
+    /// of samples per pixel taken.
+    ///
+    /// * `scene`    - The scene.
+    /// * `progress` - Progress of the render.
+    fn render_pass(&self, scene: Arc<Scene>, progress: &RenderState) -> usize {
         // Compute number of tiles, `n_tiles`, to use for parallel rendering.
         let data = self.get_data();
         let sample_bounds = Arc::clone(&data.camera)
-            .lock()
+            .read()
             .unwrap()
             .get_film_sample_bounds();
         let sample_extent = sample_bounds.diagonal();
@@ -270,44 +606,113 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
 
         info!("Rendering {}x{} tiles", n_tiles.x, n_tiles.y);
 
-        // Parallelize.
-        let tiles = iproduct!(0..n_tiles.x, 0..n_tiles.y).par_bridge();
-        tiles.for_each(|(tile_x, tile_y)| {
-            let camera_clone = Arc::clone(&data.camera);
+        // Compute sample bounds and seeds of the tiles. Parts of tiles that
+        // are left after resuming get seeds like split tiles.
+        let pass = progress.pass();
+        let done_tiles = progress.done_tiles();
+        let mut tiles = vec![];
+        for (tile_x, tile_y) in iproduct!(0..n_tiles.x, 0..n_tiles.y) {
+            let x0 = sample_bounds.p_min.x + tile_x as i32 * tile_size;
+            let x1 = min(x0 + tile_size, sample_bounds.p_max.x);
+            let y0 = sample_bounds.p_min.y + tile_y as i32 * tile_size;
+            let y1 = min(y0 + tile_size, sample_bounds.p_max.y);
+            let tile_bounds = Bounds2i::new(Point2i::new(x0, y0), Point2i::new(x1, y1));
 
-            // Render section of image corresponding to `tile`.
-            let tile = Point2::new(tile_x, tile_y);
+            let seed = ((pass * n_tiles.y + tile_y) * n_tiles.x + tile_x) as u64;
+            let remaining = remaining_tiles(&tile_bounds, &done_tiles);
+            if remaining == [tile_bounds] {
+                tiles.push((tile_bounds, seed));
+            } else {
+                for bounds in remaining {
+                    tiles.push((bounds, progress.split_seed(seed)));
+                }
+            }
+        }
+        RENDER_PROGRESS.start_pass(tiles.len());
 
-            // Get sampler instance for tile.
-            let seed = tile.y * n_tiles.x + tile.x;
-            let mut tile_sampler = Sampler::clone(&*data.sampler, seed as u64);
+        // Parallelize. Slow tiles requeue their unrendered pixels as new
+        // tiles so they can be picked up by idle threads.
+        rayon::scope(|s| {
+            for (tile_bounds, seed) in tiles {
+                let scene = Arc::clone(&scene);
+                s.spawn(move |s| self.render_tile(s, scene, tile_bounds, seed, progress));
+            }
+        });
 
-            let samples_per_pixel = {
-                let tile_sampler_data = Arc::get_mut(&mut tile_sampler).unwrap().get_data();
-                tile_sampler_data.samples_per_pixel
-            };
+        let mut sampler = Sampler::clone(&*data.sampler, 0);
+        Arc::get_mut(&mut sampler)
+            .unwrap()
+            .get_data()
+            .samples_per_pixel
+    }
 
-            // Compute sample bounds for tile.
-            let x0 = sample_bounds.p_min.x + tile.x as i32 * tile_size;
-            let x1 = min(x0 + tile_size, sample_bounds.p_max.x);
-            let y0 = sample_bounds.p_min.y + tile.y as i32 * tile_size;
-            let y1 = min(y0 + tile_size, sample_bounds.p_max.y);
-            let tile_bounds = Bounds2i::new(Point2i::new(x0, y0), Point2i::new(x1, y1));
+    /// Render the pixels of a tile and merge them into the film. If the tile
+    /// takes longer than the tile split time, its remaining rows are split
+    /// in two and spawned as new tiles.
+    ///
+    /// * `s`           - Scope for spawning split tiles.
+    /// * `scene`       - The scene.
+    /// * `tile_bounds` - Sample bounds of the tile.
+    /// * `seed`        - Seed for the tile's sampler.
+    /// * `progress`    - Progress of the render.
+    fn render_tile<'s>(
+        &'s self,
+        s: &rayon::Scope<'s>,
+        scene: Arc<Scene>,
+        tile_bounds: Bounds2i,
+        seed: u64,
+        progress: &'s RenderState,
+    ) {
+        // Skip remaining tiles once the render is cancelled.
+        if RENDER_PROGRESS.is_cancelled() {
+            return;
+        }
 
-            info!(
-                "Starting image tile ({}, {}) -> {:}",
-                tile_x, tile_y, tile_bounds
-            );
+        let _p = ProfilePhase::new(Prof::IntegratorRender);
+        let data = self.get_data();
+        let camera_clone = Arc::clone(&data.camera);
+        let start = Instant::now();
 
-            // Get `FilmTile` for tile.
-            let mut film_tile = {
-                let camera = camera_clone.lock().unwrap();
-                camera.get_film_tile(tile_bounds)
-            };
+        // Get sampler instance for tile.
+        let mut tile_sampler = Sampler::clone(&*data.sampler, seed);
 
-            // Loop over pixels in tile to render them.
-            for pixel in tile_bounds {
-                Arc::get_mut(&mut tile_sampler).unwrap().start_pixel(&pixel);
+        let samples_per_pixel = {
+            let tile_sampler_data = Arc::get_mut(&mut tile_sampler).unwrap().get_data();
+            tile_sampler_data.first_sample_index =
+                progress.pass() * tile_sampler_data.samples_per_pixel;
+            tile_sampler_data.samples_per_pixel
+        };
+
+        info!("Starting image tile {:}", tile_bounds);
+
+        // Get `FilmTile` for tile.
+        let mut film_tile = {
+            let camera = camera_clone.read().unwrap();
+            camera.get_film_tile(tile_bounds)
+        };
+
+        // Record the paths of contributions when the film has light path
+        // expression AOVs.
+        let mut paths = LightPathRecorder::new();
+        let record_paths = film_tile.has_aovs();
+        let record_surface = film_tile.has_surface_auxiliary();
+        let record_variance = film_tile.has_auxiliary();
+
+        // Render the tile one row at a time so that a slow tile can be split
+        // between rows.
+        let mut rendered_bounds = tile_bounds;
+        for y in tile_bounds.p_min.y..tile_bounds.p_max.y {
+            let row_bounds = Bounds2i::new(
+                Point2i::new(tile_bounds.p_min.x, y),
+                Point2i::new(tile_bounds.p_max.x, y + 1),
+            );
+
+            // Loop over pixels in row to render them.
+            for pixel in row_bounds {
+                {
+                    let _p = ProfilePhase::new(Prof::StartPixel);
+                    Arc::get_mut(&mut tile_sampler).unwrap().start_pixel(&pixel);
+                }
 
                 // Do this check after the StartPixel() call; this keeps the
                 // usage of RNG values from (most) Samplers that use RNGs
@@ -316,6 +721,18 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
                     continue;
                 }
 
+                // Track the variance of the pixel's samples to stop taking
+                // samples once it converges when sampling adaptively. The
+                // samples of previous passes are kept in the film.
+                let prior = if data.adaptive_sampling.is_enabled() {
+                    camera_clone.read().unwrap().film().pixel_variance(&pixel)
+                } else {
+                    PixelVariance::default()
+                };
+                if data.adaptive_sampling.is_done(&prior) {
+                    continue;
+                }
+                let mut variance = PixelVariance::default();
                 loop {
                     // Initialize `CameraSample` for current sample.
                     let camera_sample = Arc::get_mut(&mut tile_sampler)
@@ -324,15 +741,35 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
 
                     // Generate camera ray for current sample.
                     let (mut ray, ray_weight) = {
-                        let camera = camera_clone.lock().unwrap();
+                        let _p = ProfilePhase::new(Prof::GenerateCameraRay);
+                        let camera = camera_clone.read().unwrap();
                         camera.generate_ray_differential(&camera_sample)
                     };
                     ray.scale_differentials(1.0 / (samples_per_pixel as Float).sqrt());
+                    N_CAMERA_RAYS.inc();
+
+                    // Record the first surface seen by the camera ray for the
+                    // auxiliary channels.
+                    if record_surface && ray_weight > 0.0 {
+                        let aux = self.auxiliary_sample(&ray, &scene);
+                        film_tile.add_auxiliary(camera_sample.p_film, &aux, ray_weight);
+                    }
 
                     // Evaluate radiance along camera ray.
                     let mut l = Spectrum::new(0.0);
+                    paths.clear();
                     if ray_weight > 0.0 {
-                        l = self.li(&mut ray, scene.clone(), &mut tile_sampler, 0);
+                        let _p = ProfilePhase::new(Prof::SamplerIntegratorLi);
+                        l = if record_paths {
+                            self.li_paths(&mut ray, scene.clone(), &mut tile_sampler, &mut paths)
+                        } else {
+                            self.li_splats(
+                                &mut ray,
+                                scene.clone(),
+                                &mut tile_sampler,
+                                &mut film_tile,
+                            )
+                        };
                     }
 
                     // Issue warning if unexpected radiance value returned.
@@ -370,32 +807,285 @@ pub trait SamplerIntegrator: Integrator + Send + Sync {
                     );
 
                     // Add camera ray's contribution to image.
-                    film_tile.add_sample(camera_sample.p_film, l, ray_weight);
+                    if record_paths {
+                        // Samples set to black don't contribute to the AOVs.
+                        if l.is_black() {
+                            paths.clear();
+                        }
+                        film_tile.add_sample_with_paths(
+                            camera_sample.p_film,
+                            l,
+                            &paths,
+                            ray_weight,
+                        );
+                    } else {
+                        film_tile.add_sample(camera_sample.p_film, l, ray_weight);
+                    }
+
+                    variance.add(l.y() * ray_weight);
+                    let mut total = prior;
+                    total.merge(&variance);
+                    if data.adaptive_sampling.is_done(&total) {
+                        break;
+                    }
 
                     if !Arc::get_mut(&mut tile_sampler).unwrap().start_next_sample() {
                         break;
                     }
                 }
+
+                // Keep the pixel's sample statistics for the diagnostic
+                // channels.
+                if record_variance {
+                    film_tile.add_pixel_variance(&pixel, &variance);
+                }
             }
 
-            info!(
-                "Finished image tile ({}, {}) -> {:}",
-                tile_x, tile_y, tile_bounds
-            );
+            // Requeue the remaining rows as two tiles if this one is slow.
+            if let Some(split_time) = OPTIONS.tile_split_time {
+                let remaining =
+                    Bounds2i::new(Point2i::new(tile_bounds.p_min.x, y + 1), tile_bounds.p_max);
+                let elapsed = start.elapsed().as_secs_f64() as Float;
+                if elapsed > split_time {
+                    if let Some(halves) = split_tile(&remaining) {
+                        info!(
+                            "Splitting slow image tile {:} after {:.3}s",
+                            tile_bounds, elapsed
+                        );
+                        RENDER_PROGRESS.add_tiles(2);
+                        for half in [halves.0, halves.1].iter().copied() {
+                            let seed = progress.split_seed(seed);
+                            let scene = Arc::clone(&scene);
+                            s.spawn(move |s| self.render_tile(s, scene, half, seed, progress));
+                        }
+                        rendered_bounds.p_max.y = y + 1;
+                        break;
+                    }
+                }
+            }
+        }
 
-            // Merge image tile into `Film`.
-            let mut camera = camera_clone.lock().unwrap();
-            Arc::get_mut(&mut *camera)
-                .unwrap()
-                .merge_film_tile(&film_tile);
-        });
+        info!("Finished image tile {:}", rendered_bounds);
 
-        info!("Rendering finished.");
+        // Merge image tile into `Film`.
+        let _p = ProfilePhase::new(Prof::MergeFilmTile);
+        let mut camera = camera_clone.write().unwrap();
+        Arc::get_mut(&mut *camera)
+            .unwrap()
+            .merge_film_tile(&film_tile);
+        progress.tile_done(&**camera, rendered_bounds);
+        RENDER_PROGRESS.tile_done();
+    }
+}
 
-        // Save final image after rendering.
-        let camera_clone = Arc::clone(&data.camera);
-        let mut camera = camera_clone.lock().unwrap();
-        Arc::get_mut(&mut *camera).unwrap().write_image(1.0);
-        info!("Output image written.");
+/// Progress of a render shared by the tiles of a pass and used to write
+/// checkpoints.
+pub struct RenderState {
+    /// The pass in progress and the tiles of it merged into the film.
+    checkpoint: Mutex<RenderCheckpoint>,
+
+    /// Time the last checkpoint was written.
+    last_checkpoint: Mutex<Instant>,
+
+    /// Number of tiles split in the pass in progress.
+    n_splits: AtomicU64,
+}
+
+impl RenderState {
+    /// Create a new `RenderState`.
+    ///
+    /// * `checkpoint` - The progress to start from.
+    pub fn new(checkpoint: RenderCheckpoint) -> Self {
+        Self {
+            checkpoint: Mutex::new(checkpoint),
+            last_checkpoint: Mutex::new(Instant::now()),
+            n_splits: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the pass in progress starting at 0.
+    pub fn pass(&self) -> usize {
+        self.checkpoint.lock().unwrap().pass
+    }
+
+    /// Returns the sample bounds of the tiles of the pass in progress that
+    /// have been merged into the film.
+    pub fn done_tiles(&self) -> Vec<Bounds2i> {
+        self.checkpoint.lock().unwrap().done_tiles.clone()
+    }
+
+    /// Returns a seed for a tile split from the tile with the given seed. The
+    /// original seed is kept in the low bits so that seeds stay unique across
+    /// passes.
+    ///
+    /// * `seed` - Seed of the original tile.
+    pub fn split_seed(&self, seed: u64) -> u64 {
+        let split = self.n_splits.fetch_add(1, Ordering::SeqCst) + 1;
+        (split << 32) | (seed & 0xffff_ffff)
+    }
+
+    /// Record a tile merged into the film and write a checkpoint if the
+    /// checkpoint interval has elapsed. The camera's film must not be
+    /// modified by other threads during the call.
+    ///
+    /// * `camera` - The camera.
+    /// * `bounds` - Sample bounds of the tile.
+    pub fn tile_done(&self, camera: &dyn Camera, bounds: Bounds2i) {
+        self.checkpoint.lock().unwrap().done_tiles.push(bounds);
+        if let Some(interval) = OPTIONS.checkpoint_interval {
+            let elapsed = self.last_checkpoint.lock().unwrap().elapsed();
+            if elapsed.as_secs_f64() as Float >= interval {
+                self.write_checkpoint(camera);
+            }
+        }
+    }
+
+    /// Start the next pass and return its number.
+    ///
+    /// * `samples_per_pixel` - Samples per pixel taken by the completed passes.
+    pub fn pass_done(&self, samples_per_pixel: usize) -> usize {
+        let mut checkpoint = self.checkpoint.lock().unwrap();
+        checkpoint.pass += 1;
+        checkpoint.samples_per_pixel = samples_per_pixel;
+        checkpoint.done_tiles.clear();
+        self.n_splits.store(0, Ordering::SeqCst);
+        checkpoint.pass
+    }
+
+    /// Write the film and the progress to the film's checkpoint file.
+    ///
+    /// * `camera` - The camera.
+    pub fn write_checkpoint(&self, camera: &dyn Camera) {
+        let checkpoint = self.checkpoint.lock().unwrap().clone();
+        if let Err(err) = camera.write_film_checkpoint(&checkpoint) {
+            error!("{}", err);
+        }
+        *self.last_checkpoint.lock().unwrap() = Instant::now();
+    }
+}
+
+/// Returns tiles covering the part of a tile that isn't covered by tiles that
+/// were already rendered. Rows of uncovered pixels with the same extent are
+/// joined into one tile.
+///
+/// * `tile` - Sample bounds of the tile.
+/// * `done` - Sample bounds of the rendered tiles.
+fn remaining_tiles(tile: &Bounds2i, done: &[Bounds2i]) -> Vec<Bounds2i> {
+    let mut tiles = vec![];
+    let mut open: Vec<Bounds2i> = vec![];
+    for y in tile.p_min.y..tile.p_max.y {
+        // Find the spans of the row that aren't covered.
+        let mut covered: Vec<(Int, Int)> = done
+            .iter()
+            .filter(|b| b.p_min.y <= y && y < b.p_max.y)
+            .map(|b| (max(b.p_min.x, tile.p_min.x), min(b.p_max.x, tile.p_max.x)))
+            .filter(|(x0, x1)| x0 < x1)
+            .collect();
+        covered.sort_unstable();
+        let mut spans = vec![];
+        let mut x = tile.p_min.x;
+        for (x0, x1) in covered {
+            if x0 > x {
+                spans.push((x, x0));
+            }
+            x = max(x, x1);
+        }
+        if x < tile.p_max.x {
+            spans.push((x, tile.p_max.x));
+        }
+
+        // Extend the tiles of the previous row that have the same span.
+        let mut next = vec![];
+        for (x0, x1) in spans {
+            match open.iter().position(|t| t.p_min.x == x0 && t.p_max.x == x1) {
+                Some(i) => {
+                    let mut t = open.swap_remove(i);
+                    t.p_max.y = y + 1;
+                    next.push(t);
+                }
+                None => next.push(Bounds2i::new(Point2i::new(x0, y), Point2i::new(x1, y + 1))),
+            }
+        }
+        tiles.append(&mut open);
+        open = next;
+    }
+    tiles.append(&mut open);
+    tiles
+}
+
+/// Split a tile in half along its longer axis. Returns `None` if the halves
+/// would be smaller than the minimum split tile size.
+///
+/// * `bounds` - Bounds of the tile.
+fn split_tile(bounds: &Bounds2i) -> Option<(Bounds2i, Bounds2i)> {
+    let extent = bounds.diagonal();
+    let mut first = *bounds;
+    let mut second = *bounds;
+    if extent.x >= extent.y {
+        if extent.x < 2 * MIN_SPLIT_TILE_SIZE {
+            return None;
+        }
+        first.p_max.x = bounds.p_min.x + extent.x / 2;
+        second.p_min.x = first.p_max.x;
+    } else {
+        if extent.y < 2 * MIN_SPLIT_TILE_SIZE {
+            return None;
+        }
+        first.p_max.y = bounds.p_min.y + extent.y / 2;
+        second.p_min.y = first.p_max.y;
+    }
+    Some((first, second))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_tile_halves_longer_axis() {
+        let wide = Bounds2i::new(Point2i::new(0, 10), Point2i::new(16, 14));
+        let (a, b) = split_tile(&wide).unwrap();
+        assert_eq!(a, Bounds2i::new(Point2i::new(0, 10), Point2i::new(8, 14)));
+        assert_eq!(b, Bounds2i::new(Point2i::new(8, 10), Point2i::new(16, 14)));
+
+        let tall = Bounds2i::new(Point2i::new(0, 0), Point2i::new(4, 7));
+        let (a, b) = split_tile(&tall).unwrap();
+        assert_eq!(a, Bounds2i::new(Point2i::new(0, 0), Point2i::new(4, 3)));
+        assert_eq!(b, Bounds2i::new(Point2i::new(0, 3), Point2i::new(4, 7)));
+
+        let small = Bounds2i::new(Point2i::new(0, 0), Point2i::new(3, 1));
+        assert!(split_tile(&small).is_none());
+    }
+
+    #[test]
+    fn remaining_tiles_exclude_rendered_tiles() {
+        let tile = Bounds2i::new(Point2i::new(0, 0), Point2i::new(8, 8));
+        assert_eq!(remaining_tiles(&tile, &[]), vec![tile]);
+        assert!(remaining_tiles(&tile, &[tile]).is_empty());
+
+        // A tile split after its first two rows with one half rendered.
+        let done = [
+            Bounds2i::new(Point2i::new(0, 0), Point2i::new(8, 2)),
+            Bounds2i::new(Point2i::new(0, 2), Point2i::new(4, 8)),
+            Bounds2i::new(Point2i::new(16, 0), Point2i::new(24, 8)),
+        ];
+        assert_eq!(
+            remaining_tiles(&tile, &done),
+            vec![Bounds2i::new(Point2i::new(4, 2), Point2i::new(8, 8))]
+        );
+
+        // A rendered tile in the middle leaves a frame around it.
+        let done = [Bounds2i::new(Point2i::new(2, 2), Point2i::new(6, 6))];
+        let remaining = remaining_tiles(&tile, &done);
+        let area: Int = remaining.iter().map(|t| t.area()).sum();
+        assert_eq!(area, 64 - 16);
+        for t in remaining.iter() {
+            assert!(t.intersect(&done[0]).area() == 0);
+        }
+        assert_eq!(remaining.len(), 4);
     }
 }
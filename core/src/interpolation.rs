@@ -41,11 +41,13 @@ pub fn catmull_rom(nodes: &[Float], values: &[Float], x: Float) -> Float {
         + (t3 - t2) * d1
 }
 
-/// Returns the weights and the index offset for Catmull-Rom spline.
+/// Returns the weights and the index offset for Catmull-Rom spline. The
+/// offset is -1 when `x` lies in the first interval; the first weight is
+/// zero in that case.
 ///
 /// * `nodes` - Interpolations nodes.
 /// * `x`     - Variable to interpolate.
-pub fn catmull_rom_weights(nodes: &[Float], x: Float) -> Option<([Float; 4], usize)> {
+pub fn catmull_rom_weights(nodes: &[Float], x: Float) -> Option<([Float; 4], isize)> {
     // Return None if `x` is out of bounds.
     let size = nodes.len();
     if !(x >= nodes[0] && x <= nodes[size - 1]) {
@@ -54,7 +56,7 @@ pub fn catmull_rom_weights(nodes: &[Float], x: Float) -> Option<([Float; 4], usi
 
     // Search for the interval `idx` containing `x`.
     let idx = find_interval(size, |i| nodes[i] <= x);
-    let offset = idx - 1;
+    let offset = idx as isize - 1;
     let x0 = nodes[idx];
     let x1 = nodes[idx + 1];
 
@@ -220,7 +222,7 @@ pub fn sample_catmull_rom_2d(
     let interpolate = |array: &[Float], idx: usize| -> Float {
         (0..4).fold(0.0, |a, i| {
             if weights[i] != 0.0 {
-                a + array[(offset + i) * size2 + idx] * weights[i]
+                a + array[(offset + i as isize) as usize * size2 + idx] * weights[i]
             } else {
                 a
             }
@@ -348,8 +350,7 @@ pub fn integrate_catmull_rom(x: &[Float], values: &[Float]) -> (Vec<Float>, Floa
 /// * `values` - Value of the function.
 /// * `u`      - Uniform random variate ξ.
 #[allow(non_snake_case)]
-#[allow(unused)]
-fn invert_catmull_rom(x: &[Float], values: &[Float], u: Float) -> Float {
+pub fn invert_catmull_rom(x: &[Float], values: &[Float], u: Float) -> Float {
     let n = x.len();
 
     // Stop when `u` is out of bounds.
@@ -11,12 +11,12 @@ extern crate log;
 pub mod app;
 pub mod bssrdf;
 pub mod camera;
-pub mod efloat;
 pub mod fileutil;
 pub mod film;
 pub mod filter;
 pub mod geometry;
 pub mod image_io;
+pub mod imagemetrics;
 pub mod integrator;
 pub mod interpolation;
 pub mod light;
@@ -28,8 +28,12 @@ pub mod microfacet;
 pub mod mipmap;
 pub mod paramset;
 pub mod pbrt;
+pub mod plugin;
+#[cfg(feature = "polarization")]
+pub mod polarization;
 pub mod primitive;
 pub mod primitives;
+pub mod profiler;
 pub mod reflection;
 pub mod rng;
 pub mod sampler;
@@ -37,4 +41,8 @@ pub mod sampling;
 pub mod scene;
 pub mod sobol_matrices;
 pub mod spectrum;
+pub mod stats;
 pub mod texture;
+
+// Re-export the math types that aren't part of `geometry` and `pbrt`.
+pub use pbrt_math::efloat;
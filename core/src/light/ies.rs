@@ -0,0 +1,304 @@
+//! IES Photometric Profiles
+
+use crate::geometry::*;
+use crate::paramset::*;
+use crate::pbrt::*;
+use std::fs;
+
+/// Angular intensity distribution of a luminaire read from an IES LM-63
+/// photometric data file. Only type C photometry is supported. Vertical
+/// angles are measured from the nadir and horizontal angles around it.
+///
+/// Directions are given in the profile's coordinate system where the nadir
+/// points down the +z axis and the 0° horizontal angle lies along the +x
+/// axis.
+#[derive(Clone, Debug)]
+pub struct IESProfile {
+    /// Vertical angles in degrees in ascending order.
+    vertical_angles: Vec<Float>,
+
+    /// Horizontal angles in degrees in ascending order.
+    horizontal_angles: Vec<Float>,
+
+    /// Candela values normalized to a peak of 1. There is a row of values
+    /// for the vertical angles for each horizontal angle.
+    candela: Vec<Float>,
+
+    /// Peak intensity in candela.
+    pub max_candela: Float,
+}
+
+impl IESProfile {
+    /// Read an `IESProfile` from an IES file.
+    ///
+    /// * `path` - Path to the file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("{}", err))?;
+        Self::parse(&contents)
+    }
+
+    /// Parse the contents of an IES file.
+    ///
+    /// * `contents` - The file contents.
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        // Skip the keyword lines up to and including the `TILT=` line.
+        let mut lines = contents.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) if line.trim_start().starts_with("TILT") => {
+                    break line.split_once('=').map_or("", |x| x.1).trim().to_string();
+                }
+                Some(_) => continue,
+                None => return Err(String::from("Missing 'TILT=' line")),
+            }
+        };
+
+        let rest: Vec<&str> = lines.collect();
+        let mut values = rest
+            .iter()
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse::<Float>()
+                    .map_err(|err| format!("Invalid value '{}'. {}", s, err))
+            });
+        let mut next = || {
+            values
+                .next()
+                .unwrap_or_else(|| Err(String::from("Unexpected end of file")))
+        };
+
+        // Tilt data only applies when the lamp is not upright; skip it.
+        if tilt == "INCLUDE" {
+            let _lamp_to_luminaire_geometry = next()?;
+            let n = next()? as usize;
+            for _ in 0..2 * n {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            warn!("Ignoring tilt data in '{}'.", tilt);
+        }
+
+        let _num_lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let n_vertical = next()? as usize;
+        let n_horizontal = next()? as usize;
+        let photometric_type = next()? as i32;
+        let _units_type = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let ballast_factor = next()?;
+        let _ballast_lamp_photometric_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(format!(
+                "Photometric type {} not supported. Only type C (1) is.",
+                photometric_type
+            ));
+        }
+        if n_vertical == 0 || n_horizontal == 0 {
+            return Err(String::from("No vertical or horizontal angles"));
+        }
+
+        let vertical_angles = (0..n_vertical)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..n_horizontal)
+            .map(|_| next())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut candela = (0..n_vertical * n_horizontal)
+            .map(|_| next().map(|v| v * multiplier * ballast_factor))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let sorted = |a: &[Float]| a.windows(2).all(|w| w[0] <= w[1]);
+        if !sorted(&vertical_angles) || !sorted(&horizontal_angles) {
+            return Err(String::from("Angles must be in ascending order"));
+        }
+
+        let max_candela = candela.iter().cloned().fold(0.0, Float::max);
+        if max_candela > 0.0 {
+            for v in candela.iter_mut() {
+                *v /= max_candela;
+            }
+        }
+
+        Ok(Self {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            max_candela,
+        })
+    }
+
+    /// Returns the intensity in a direction relative to the peak intensity.
+    ///
+    /// * `w` - The direction in the profile's coordinate system.
+    pub fn evaluate(&self, w: &Vector3f) -> Float {
+        let w = w.normalize();
+        let theta = spherical_theta(&w).to_degrees();
+        let phi = self.fold_horizontal_angle(spherical_phi(&w).to_degrees());
+
+        // Find the surrounding vertical angles. There is no light outside the
+        // measured range.
+        let va = &self.vertical_angles;
+        let nv = va.len();
+        if theta < va[0] || theta > va[nv - 1] {
+            return 0.0;
+        }
+        let (v0, tv) = interval(va, theta);
+
+        let ha = &self.horizontal_angles;
+        let (h0, th) = interval(ha, phi);
+
+        let value = |h: usize, v: usize| self.candela[h * nv + v];
+        let at_h = |h: usize| {
+            if nv == 1 {
+                value(h, 0)
+            } else {
+                lerp(tv, value(h, v0), value(h, v0 + 1))
+            }
+        };
+        if ha.len() == 1 {
+            at_h(0)
+        } else {
+            lerp(th, at_h(h0), at_h(h0 + 1))
+        }
+    }
+
+    /// Returns the average intensity over the sphere of directions relative to
+    /// the peak intensity.
+    pub fn average(&self) -> Float {
+        let (n_theta, n_phi) = (64, 128);
+        let mut sum = 0.0;
+        let mut weight_sum = 0.0;
+        for i in 0..n_theta {
+            let theta = PI * (i as Float + 0.5) / n_theta as Float;
+            let sin_theta = sin(theta);
+            for j in 0..n_phi {
+                let phi = TWO_PI * (j as Float + 0.5) / n_phi as Float;
+                sum += self.evaluate(&spherical_direction(sin_theta, cos(theta), phi)) * sin_theta;
+                weight_sum += sin_theta;
+            }
+        }
+        sum / weight_sum
+    }
+
+    /// Maps a horizontal angle in [0, 360) into the range covered by the
+    /// profile using the symmetry implied by the last horizontal angle.
+    ///
+    /// * `phi` - The horizontal angle in degrees.
+    fn fold_horizontal_angle(&self, phi: Float) -> Float {
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        if last <= 0.0 {
+            // Rotationally symmetric.
+            0.0
+        } else if last <= 90.0 {
+            // Symmetric in each quadrant.
+            let phi = if phi > 180.0 { 360.0 - phi } else { phi };
+            if phi > 90.0 {
+                180.0 - phi
+            } else {
+                phi
+            }
+        } else if last <= 180.0 {
+            // Symmetric about the 0-180° plane.
+            if phi > 180.0 {
+                360.0 - phi
+            } else {
+                phi
+            }
+        } else {
+            phi
+        }
+    }
+}
+
+/// Returns the photometric profile read from the file given by the `iesfile`
+/// parameter or `None` if there is no such file.
+///
+/// * `params` - Parameter set.
+pub fn ies_profile(params: &ParamSet) -> Option<IESProfile> {
+    let iesfile = params.find_one_filename("iesfile", String::from(""));
+    if iesfile.is_empty() {
+        return None;
+    }
+    match IESProfile::from_file(&iesfile) {
+        Ok(ies) => Some(ies),
+        Err(err) => {
+            warn!("Problem reading file '{}'. {}", iesfile, err);
+            None
+        }
+    }
+}
+
+/// Returns the index of the interval of sorted values containing `x` and the
+/// offset of `x` within that interval.
+///
+/// * `values` - Values in ascending order.
+/// * `x`      - The value to find.
+fn interval(values: &[Float], x: Float) -> (usize, Float) {
+    if values.len() < 2 {
+        return (0, 0.0);
+    }
+    let i = find_interval(values.len(), |i| values[i] <= x);
+    let width = values[i + 1] - values[i];
+    let t = if width > 0.0 {
+        clamp((x - values[i]) / width, 0.0, 1.0)
+    } else {
+        0.0
+    };
+    (i, t)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IES: &str = "IESNA:LM-63-2002
+[TEST] test
+[MANUFAC] none
+TILT=NONE
+1 1000 1 3 2 1 1 0 0 0
+1 1 100
+0 45 90
+0 90
+100 50 0
+200, 100, 0
+";
+
+    #[test]
+    fn parses_and_interpolates() {
+        let ies = IESProfile::parse(IES).unwrap();
+        assert_eq!(ies.max_candela, 200.0);
+
+        // Nadir along +z.
+        let down = Vector3f::new(0.0, 0.0, 1.0);
+        assert!((ies.evaluate(&down) - 0.5).abs() < 1e-5);
+
+        // Horizontal.
+        assert!(ies.evaluate(&Vector3f::new(1.0, 0.0, 0.0)).abs() < 1e-5);
+
+        // Halfway between the 0° and 90° horizontal angles and 45° vertical.
+        let w = spherical_direction(sin(PI / 4.0), cos(PI / 4.0), PI / 4.0);
+        assert!((ies.evaluate(&w) - 0.375).abs() < 1e-4);
+
+        // Quadrant symmetry maps 270° onto 90°.
+        let w = spherical_direction(sin(PI / 4.0), cos(PI / 4.0), 1.5 * PI);
+        assert!((ies.evaluate(&w) - 0.5).abs() < 1e-4);
+
+        // No light above the horizon.
+        assert_eq!(ies.evaluate(&Vector3f::new(0.0, 0.0, -1.0)), 0.0);
+    }
+
+    #[test]
+    fn rejects_missing_tilt() {
+        assert!(IESProfile::parse("IESNA:LM-63-2002\n1 1000 1").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Light Links
+
+use crate::paramset::*;
+
+/// Stores which named lights illuminate an object and which it casts shadows
+/// for. Lighting artists use this to keep a fill light off a backdrop, light
+/// a hero object on its own or stop a foreground object from shadowing a key
+/// light without moving the lights.
+///
+/// Links are enforced when lights are sampled for direct lighting. Indirect
+/// lighting follows them approximately: each bounce is lit according to the
+/// links of the surface it hits, but emission that a bounced ray reaches
+/// directly is not filtered.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LightLinks {
+    /// Names of the only lights that illuminate the object; all lights if
+    /// empty.
+    pub include: Vec<String>,
+
+    /// Names of lights that do not illuminate the object.
+    pub exclude: Vec<String>,
+
+    /// Names of lights the object does not cast shadows for.
+    pub no_shadow: Vec<String>,
+}
+
+/// The kinds of link between lights and objects.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightLinkType {
+    /// The light illuminates the object.
+    Illumination,
+
+    /// The object casts shadows for the light.
+    Shadow,
+}
+
+impl LightLinks {
+    /// Returns whether the links don't restrict any lights.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.no_shadow.is_empty()
+    }
+
+    /// Returns whether a light illuminates the object. Unnamed lights can't
+    /// be included by name so they only illuminate objects without an
+    /// include list.
+    ///
+    /// * `light_name` - Name of the light.
+    pub fn illuminated_by(&self, light_name: Option<&str>) -> bool {
+        match light_name {
+            Some(name) => {
+                (self.include.is_empty() || self.include.iter().any(|n| n == name))
+                    && !self.exclude.iter().any(|n| n == name)
+            }
+            None => self.include.is_empty(),
+        }
+    }
+
+    /// Returns whether the object is linked to a light.
+    ///
+    /// * `link_type`  - The kind of link.
+    /// * `light_name` - Name of the light.
+    pub fn linked(&self, link_type: LightLinkType, light_name: Option<&str>) -> bool {
+        match link_type {
+            LightLinkType::Illumination => self.illuminated_by(light_name),
+            LightLinkType::Shadow => self.casts_shadow_for(light_name),
+        }
+    }
+
+    /// Returns whether the object blocks light from a light. Unnamed lights
+    /// are shadowed by all objects.
+    ///
+    /// * `light_name` - Name of the light.
+    pub fn casts_shadow_for(&self, light_name: Option<&str>) -> bool {
+        match light_name {
+            Some(name) => !self.no_shadow.iter().any(|n| n == name),
+            None => true,
+        }
+    }
+}
+
+impl From<&ParamSet> for LightLinks {
+    /// Create `LightLinks` from given parameter set.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        Self {
+            include: params.find_string("includelights"),
+            exclude: params.find_string("excludelights"),
+            no_shadow: params.find_string("noshadowlights"),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_and_exclude_lists() {
+        let mut params = ParamSet::new();
+        params.add_string("includelights", &[String::from("key"), String::from("rim")]);
+        params.add_string("excludelights", &[String::from("rim")]);
+        let links = LightLinks::from(&params);
+
+        assert!(links.illuminated_by(Some("key")));
+        assert!(!links.illuminated_by(Some("rim")));
+        assert!(!links.illuminated_by(Some("fill")));
+        assert!(!links.illuminated_by(None));
+        assert!(links.casts_shadow_for(Some("key")));
+
+        let links = LightLinks::default();
+        assert!(links.is_empty());
+        assert!(links.illuminated_by(Some("fill")));
+        assert!(links.illuminated_by(None));
+    }
+
+    #[test]
+    fn shadow_exclusions() {
+        let mut params = ParamSet::new();
+        params.add_string("noshadowlights", &[String::from("key")]);
+        let links = LightLinks::from(&params);
+
+        assert!(!links.is_empty());
+        assert!(links.illuminated_by(Some("key")));
+        assert!(!links.casts_shadow_for(Some("key")));
+        assert!(links.casts_shadow_for(Some("fill")));
+        assert!(links.casts_shadow_for(None));
+    }
+}
@@ -0,0 +1,54 @@
+//! Light Visibility
+
+use crate::paramset::*;
+
+/// Stores whether a light is seen directly by camera rays and whether it
+/// illuminates the scene. Lighting artists use this to hide an environment or
+/// area light from view while keeping its illumination or to show a backdrop
+/// that does not light the scene.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightVisibility {
+    /// Emission is visible to camera rays.
+    pub camera: bool,
+
+    /// Emission illuminates the scene through light sampling and indirect
+    /// rays.
+    pub illumination: bool,
+}
+
+impl LightVisibility {
+    /// Returns whether emitted radiance should be added for a ray that hits
+    /// or escapes to the light.
+    ///
+    /// * `depth` - Path depth of the ray; 0 for camera rays.
+    pub fn emission_visible(&self, depth: usize) -> bool {
+        if depth == 0 {
+            self.camera
+        } else {
+            self.illumination
+        }
+    }
+}
+
+impl Default for LightVisibility {
+    /// Returns a `LightVisibility` that is visible to camera rays and
+    /// illuminates the scene.
+    fn default() -> Self {
+        Self {
+            camera: true,
+            illumination: true,
+        }
+    }
+}
+
+impl From<&ParamSet> for LightVisibility {
+    /// Create `LightVisibility` from given parameter set.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        Self {
+            camera: params.find_one_bool("camera", true),
+            illumination: params.find_one_bool("illumination", true),
+        }
+    }
+}
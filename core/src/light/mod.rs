@@ -7,7 +7,10 @@ use crate::scene::*;
 use crate::spectrum::*;
 use std::sync::Arc;
 
+mod ies;
+mod light_links;
 mod light_type;
+mod light_visibility;
 mod visibility_tester;
 
 /// Return value for `Light::sample_li()`.
@@ -24,6 +27,10 @@ pub struct Li {
 
     /// Radiance arriving at intersection point.
     pub value: Spectrum,
+
+    /// Surface normal at the sampled point on the light or zero for lights
+    /// that don't have a surface.
+    pub n_light: Normal3f,
 }
 
 impl Li {
@@ -44,8 +51,17 @@ impl Li {
             pdf,
             visibility,
             value,
+            n_light: Normal3f::zero(),
         }
     }
+
+    /// Returns the `Li` with the surface normal at the sampled point on the
+    /// light.
+    ///
+    /// * `n_light` - Surface normal at the sampled point on the light.
+    pub fn with_light_normal(self, n_light: Normal3f) -> Self {
+        Self { n_light, ..self }
+    }
 }
 
 /// Return value for `Light::sample_le()`.
@@ -140,8 +156,8 @@ pub trait Light {
     /// Returns emitted radiance due to that light along a ray that escapes the
     /// scene bounds.
     ///
-    /// * `r` - The ray differentials.
-    fn le(&self, _r: &RayDifferential) -> Spectrum {
+    /// * `ray` - The ray.
+    fn le(&self, _ray: &Ray) -> Spectrum {
         Spectrum::new(0.0)
     }
 
@@ -169,6 +185,24 @@ pub trait Light {
     fn is_delta_light(&self) -> bool {
         self.get_type().is_delta_light()
     }
+
+    /// Returns name/value pairs describing how the light was configured that
+    /// should be recorded in the output image's metadata.
+    fn film_metadata(&self) -> Vec<(String, String)> {
+        vec![]
+    }
+
+    /// Returns whether the light is visible to camera rays and whether it
+    /// illuminates the scene.
+    fn visibility(&self) -> LightVisibility {
+        LightVisibility::default()
+    }
+
+    /// Returns the memory used by the light in bytes. Defaults to the size of
+    /// the light itself.
+    fn memory(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
 }
 
 /// Atomic reference counted `Light`.
@@ -187,5 +221,8 @@ pub trait AreaLight: Light {
 pub type ArcAreaLight = Arc<dyn AreaLight + Send + Sync>;
 
 // Re-export
+pub use ies::*;
+pub use light_links::*;
 pub use light_type::*;
+pub use light_visibility::*;
 pub use visibility_tester::*;
@@ -1,6 +1,7 @@
 //! Visibility Tester
 
 use crate::geometry::*;
+use crate::primitive::*;
 use crate::sampler::*;
 use crate::scene::*;
 use crate::spectrum::*;
@@ -16,6 +17,10 @@ pub struct VisibilityTester {
 
     /// Second endpoint of shadow ray.
     pub p1: Point3f,
+
+    /// Index of the light in the scene whose shadow exclusions apply or
+    /// `None` if every surface blocks the shadow ray.
+    pub light_index: Option<usize>,
 }
 
 impl VisibilityTester {
@@ -24,15 +29,35 @@ impl VisibilityTester {
     /// * `p0` - One endpoint of shadow ray.
     /// * `p1` - Second endpoint of shadow ray.
     pub fn new(p0: Hit, p1: Point3f) -> Self {
-        Self { p0, p1 }
+        Self {
+            p0,
+            p1,
+            light_index: None,
+        }
+    }
+
+    /// Returns the `VisibilityTester` with the shadow exclusions of a light.
+    ///
+    /// * `light_index` - Index of the light in the scene.
+    pub fn for_light(self, light_index: usize) -> Self {
+        Self {
+            light_index: Some(light_index),
+            ..self
+        }
     }
 
     /// Traces a shadow ray between `p0` and `p1` through the scene and returns
-    /// true if the points are visible to each other.
+    /// true if the points are visible to each other. Surfaces that don't cast
+    /// shadows for the light are ignored.
     ///
     /// * `scene` - The scene.
     pub fn unoccluded(&self, scene: Arc<Scene>) -> bool {
-        scene.intersect_p(&self.p0.spawn_ray_to_point(&self.p1))
+        let ray = self.p0.spawn_ray_to_point(&self.p1);
+        let shadow_filter = self
+            .light_index
+            .and_then(|light| scene.shadow_filter(light));
+        let filter = shadow_filter.as_ref().map(|f| f as PrimitiveFilter);
+        !scene.intersect_p_filtered(&ray, filter)
     }
 
     /// Computes the beam transmittance, the fraction of radiance transmitted
@@ -44,9 +69,13 @@ impl VisibilityTester {
     pub fn tr(&self, scene: Arc<Scene>, sampler: ArcSampler) -> Spectrum {
         let mut ray = self.p0.spawn_ray_to_point(&self.p1);
         let mut tr = Spectrum::new(1.0);
+        let shadow_filter = self
+            .light_index
+            .and_then(|light| scene.shadow_filter(light));
+        let filter = shadow_filter.as_ref().map(|f| f as PrimitiveFilter);
 
         loop {
-            if let Some(isect) = scene.intersect(&mut ray) {
+            if let Some(isect) = scene.intersect_filtered(&mut ray, filter) {
                 // Handle opaque surface along ray's path.
                 if let Some(_material) = isect.primitive.map(|p| p.get_material()) {
                     return Spectrum::new(0.0);
@@ -73,3 +102,89 @@ impl VisibilityTester {
         tr
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::light::*;
+    use crate::material::*;
+
+    /// An opaque box that only answers shadow ray queries.
+    struct Occluder {
+        bounds: Bounds3f,
+    }
+
+    impl Primitive for Occluder {
+        fn world_bound(&self) -> Bounds3f {
+            self.bounds
+        }
+
+        fn intersect(&self, _r: &mut Ray) -> Option<SurfaceInteraction<'_>> {
+            None
+        }
+
+        fn intersect_filtered(
+            &self,
+            _r: &mut Ray,
+            _filter: PrimitiveFilter,
+        ) -> Option<SurfaceInteraction<'_>> {
+            None
+        }
+
+        fn intersect_p(&self, r: &Ray) -> bool {
+            self.bounds.intersect_p(r).is_some()
+        }
+
+        fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+            filter(self) && self.intersect_p(r)
+        }
+
+        fn get_area_light(&self) -> Option<ArcAreaLight> {
+            None
+        }
+
+        fn get_material(&self) -> Option<ArcMaterial> {
+            None
+        }
+
+        fn compute_scattering_functions(
+            &self,
+            _si: &mut SurfaceInteraction,
+            _mode: TransportMode,
+            _allow_multiple_lobes: bool,
+        ) {
+        }
+    }
+
+    #[test]
+    fn shadow_rays_are_blocked_by_occluders() {
+        let occluder = Occluder {
+            bounds: Bounds3f::new(Point3f::new(-1.0, -1.0, 4.0), Point3f::new(1.0, 1.0, 5.0)),
+        };
+        let scene = Arc::new(Scene::new(Arc::new(occluder), vec![], vec![]));
+        let p0 = Hit::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            0.0,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::new(0.0, 0.0, 1.0),
+            None,
+        );
+
+        // The segment ends before the occluder.
+        let vis = VisibilityTester::new(p0.clone(), Point3f::new(0.0, 0.0, 3.0));
+        assert!(vis.unoccluded(Arc::clone(&scene)));
+
+        // The segment passes through the occluder.
+        let vis = VisibilityTester::new(p0.clone(), Point3f::new(0.0, 0.0, 10.0));
+        assert!(!vis.unoccluded(Arc::clone(&scene)));
+
+        // The segment passes beside the occluder.
+        let vis = VisibilityTester::new(p0, Point3f::new(3.0, 0.0, 10.0));
+        assert!(vis.unoccluded(scene));
+    }
+}
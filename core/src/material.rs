@@ -1,9 +1,14 @@
 //! Material
 
+use crate::app::OPTIONS;
 use crate::geometry::*;
+use crate::mipmap::*;
 use crate::pbrt::*;
+use crate::spectrum::*;
+use crate::stats::*;
 use crate::texture::*;
 use std::sync::Arc;
+use std::time::Instant;
 
 // TransportMode enumeration.
 #[derive(Copy, Clone, PartialEq)]
@@ -80,7 +85,104 @@ pub trait Material {
 
         si.set_shading_geometry(dpdu, dpdv, si.shading.dndu, si.shading.dndv, false);
     }
+
+    /// Update the normal at the surface interaction using a tangent space
+    /// normal map. The map's red, green and blue channels give the normal
+    /// along `dpdu`, the bitangent and the shading normal remapped to [0, 1].
+    ///
+    /// * `normal_map` - Normal map.
+    /// * `si`         - Surface interaction.
+    fn normal_map(&self, normal_map: ArcMIPMap<RGBSpectrum>, si: &mut SurfaceInteraction) {
+        // Look up the normal in the tangent space and remap it to [-1, 1].
+        let c = normal_map.lookup_triangle(&si.uv, 0.0);
+        let ns = Vector3f::new(2.0 * c[0] - 1.0, 2.0 * c[1] - 1.0, 2.0 * c[2] - 1.0);
+        if ns.length_squared() == 0.0 {
+            return;
+        }
+
+        // Transform the normal to world space using the shading frame.
+        let n = Vector3f::from(si.shading.n);
+        let s = si.shading.dpdu.normalize();
+        let t = n.cross(&s);
+        let ns = ns.normalize();
+        let ns = (ns.x * s + ns.y * t + ns.z * n).normalize();
+
+        // Make the shading partial derivatives perpendicular to the new normal
+        // while preserving their lengths.
+        let ulen = si.shading.dpdu.length();
+        let vlen = si.shading.dpdv.length();
+        let dpdu = (si.shading.dpdu - si.shading.dpdu.dot(&ns) * ns).normalize() * ulen;
+        let dpdv = ns.cross(&dpdu).normalize() * vlen;
+
+        si.set_shading_geometry(dpdu, dpdv, si.shading.dndu, si.shading.dndv, false);
+    }
+
+    /// Update the normal at the surface interaction using a bump map if
+    /// present or otherwise a normal map if present.
+    ///
+    /// * `bump_map`   - Optional bump map.
+    /// * `normal_map` - Optional normal map.
+    /// * `si`         - Surface interaction.
+    fn perturb_normal(
+        &self,
+        bump_map: &Option<ArcTexture<Float>>,
+        normal_map: &Option<ArcMIPMap<RGBSpectrum>>,
+        si: &mut SurfaceInteraction,
+    ) {
+        if let Some(bump_map) = bump_map {
+            self.bump(Arc::clone(bump_map), si);
+        } else if let Some(normal_map) = normal_map {
+            self.normal_map(Arc::clone(normal_map), si);
+        }
+    }
 }
 
 /// Atomic reference counted `Material`.
 pub type ArcMaterial = Arc<dyn Material + Send + Sync>;
+
+/// Wraps a material and records its evaluations in the statistics.
+pub struct ProfiledMaterial {
+    /// The material.
+    material: ArcMaterial,
+
+    /// Counter for evaluations of the material.
+    counter: Arc<ProfileCounter>,
+}
+
+impl Material for ProfiledMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode.
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available.
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        mode: TransportMode,
+        allow_multiple_lobes: bool,
+    ) {
+        let start = Instant::now();
+        self.material
+            .compute_scattering_functions(si, mode, allow_multiple_lobes);
+        self.counter.record(start);
+    }
+}
+
+/// Returns the material wrapped so its evaluations are recorded in the
+/// statistics if they are enabled; otherwise the material itself.
+///
+/// * `material` - The material.
+/// * `category` - Material type such as `plastic`.
+/// * `name`     - Material name.
+pub fn profile_material(material: ArcMaterial, category: &str, name: &str) -> ArcMaterial {
+    if OPTIONS.stats {
+        let counter = STATS.counter("material", category, name, 0);
+        Arc::new(ProfiledMaterial { material, counter })
+    } else {
+        material
+    }
+}
@@ -5,7 +5,7 @@ use crate::geometry::*;
 use crate::pbrt::*;
 
 /// Henyey-Greenstein phase function.
-struct HenyeyGreenstein {
+pub struct HenyeyGreenstein {
     /// The asymmetry parameter. It is the average value of the product of the
     /// phase function being approximated and the cosine of the angle between two
     /// directions. Isotropic phase functions use g = 0.
@@ -35,15 +35,10 @@ impl PhaseFunction for HenyeyGreenstein {
     /// * `wo` - Outgoing direction.
     /// * `u`  - Sample value in [0, 1)^2.
     fn sample_p(&self, wo: &Vector3f, u: &Point2f) -> (Float, Vector3f) {
-        // Compute $\cos \theta$ for Henyey--Greenstein sample
-        let cos_theta = if abs(self.g) < 1e-3 {
-            1.0 - 2.0 * u[0]
-        } else {
-            let sqr_term = (1.0 - self.g * self.g) / (1.0 + self.g - 2.0 * self.g * u[0]);
-            -(1.0 + self.g * self.g - sqr_term * sqr_term) / (2.0 * self.g)
-        };
+        // Compute cos(θ) for Henyey-Greenstein sample.
+        let cos_theta = sample_hg_cos_theta(u[0], self.g);
 
-        // Compute direction _wi_ for Henyey--Greenstein sample
+        // Compute direction `wi` for Henyey-Greenstein sample.
         let sin_theta = max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
         let phi = 2.0 * PI * u[1];
 
@@ -54,6 +49,26 @@ impl PhaseFunction for HenyeyGreenstein {
     }
 }
 
+/// Returns cos(θ) distributed according to the Henyey-Greenstein phase
+/// function by inverting its CDF. The expression is rearranged to avoid
+/// dividing by `g` so it is exact and continuous in `u` as `g` approaches 0
+/// where it reduces to uniform sampling of the sphere.
+///
+/// * `u` - Sample value in [0, 1).
+/// * `g` - Asymmetry parametery.
+#[inline]
+pub fn sample_hg_cos_theta(u: Float, g: Float) -> Float {
+    let a = 1.0 - 2.0 * u;
+    let t = 1.0 + g * a;
+    if t == 0.0 {
+        // Only possible for |g| = 1 where all light scatters in one direction.
+        return if g > 0.0 { -1.0 } else { 1.0 };
+    }
+    let g2 = g * g;
+    let cos_theta = -((1.0 + g2) * (2.0 * a + g * a * a) + g * (3.0 - g2)) / (2.0 * t * t);
+    clamp(cos_theta, -1.0, 1.0)
+}
+
 /// Computes the Henyey-Greenstein phase function which can be used by other
 /// phase function
 ///
@@ -64,3 +79,44 @@ pub fn phase_hg(cos_theta: Float, g: Float) -> Float {
     let denom = 1.0 + g * g + 2.0 * g * cos_theta;
     INV_FOUR_PI * (1.0 - g * g) / (denom * denom.sqrt())
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampling_matches_pdf() {
+        let wo = Vector3f::new(0.0, 0.0, 1.0);
+        for g in [-0.7, -1e-4, 0.0, 0.002, 0.5, 0.9].iter() {
+            let hg = HenyeyGreenstein::new(*g);
+            for i in 0..16 {
+                let u = Point2f::new((i as Float + 0.5) / 16.0, 0.3);
+                let (p, wi) = hg.sample_p(&wo, &u);
+                assert!((p - hg.pdf(&wo, &wi)).abs() < 1e-3 * p, "g = {}", g);
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_inverts_cdf() {
+        // Integrate pdf of cos(θ) with midpoint rule and compare to `u`.
+        let n = 4096;
+        for g in [-0.7, 0.0, 1e-3, 0.5].iter() {
+            for u in [0.1, 0.5, 0.9].iter() {
+                let cos_theta = sample_hg_cos_theta(*u, *g);
+                let mut cdf = 0.0;
+                for i in 0..n {
+                    let mu = -1.0 + 2.0 * (i as Float + 0.5) / n as Float;
+                    if mu < cos_theta {
+                        cdf += TWO_PI * phase_hg(mu, *g) * 2.0 / n as Float;
+                    }
+                }
+                assert!((cdf - u).abs() < 2e-3, "g = {}, u = {}", g, u);
+            }
+        }
+    }
+}
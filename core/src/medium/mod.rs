@@ -2,16 +2,23 @@
 
 #![allow(dead_code)]
 use crate::geometry::*;
+use crate::pbrt::*;
 use crate::sampler::*;
 use crate::spectrum::*;
 use std::sync::Arc;
 
 mod henyey_greenstein;
+mod measured_media;
+mod overlap;
 mod phase_function;
+mod scattering_properties;
 
 // Re-exports
 pub use henyey_greenstein::*;
+pub use measured_media::*;
+pub use overlap::*;
 pub use phase_function::*;
+pub use scattering_properties::*;
 
 /// Medium trait to handle volumetric scattering properties.
 pub trait Medium {
@@ -20,6 +27,48 @@ pub trait Medium {
     /// * `ray`     - The ray.
     /// * `sampler` - The sampler.
     fn tr(&self, ray: &Ray, sampler: ArcSampler) -> Spectrum;
+
+    /// Samples a scattering interaction along a given ray up to its `t_max`.
+    /// Returns the ratio of beam transmittance to the sampling density times
+    /// the scattering coefficient if an interaction was sampled, and the
+    /// interaction if any. Media that don't implement distance sampling only
+    /// absorb light and return the beam transmittance without an interaction.
+    ///
+    /// * `ray`     - The ray.
+    /// * `sampler` - The sampler.
+    fn sample(&self, ray: &Ray, sampler: &mut ArcSampler) -> (Spectrum, Option<MediumInteraction>) {
+        (self.tr(ray, Arc::clone(sampler)), None)
+    }
+
+    /// Returns the media overlapping in the region if this combines several.
+    fn overlapping(&self) -> Option<&[ArcMedium]> {
+        None
+    }
+
+    /// Returns a bound on the attenuation coefficient σt in all channels
+    /// along a given ray per unit distance. Overlapping media are sampled by
+    /// delta tracking with the bounds of their media; media that don't
+    /// provide one return `None`.
+    ///
+    /// * `_ray` - The ray.
+    fn majorant(&self, _ray: &Ray) -> Option<Float> {
+        None
+    }
+
+    /// Returns the absorption and scattering coefficients σa and σs at a
+    /// point for media that provide a `majorant()`.
+    ///
+    /// * `_p` - The point.
+    fn coefficients(&self, _p: &Point3f) -> (Spectrum, Spectrum) {
+        (Spectrum::new(0.0), Spectrum::new(0.0))
+    }
+
+    /// Returns the phase function at a point.
+    ///
+    /// * `_p` - The point.
+    fn phase(&self, _p: &Point3f) -> ArcPhaseFunction {
+        Arc::new(HenyeyGreenstein::new(0.0))
+    }
 }
 
 /// Atomic reference counted `Medium`.
@@ -33,6 +82,9 @@ pub struct MediumInterface {
 
     /// Represent the exterior of a geometric primitive.
     pub outside: Option<ArcMedium>,
+
+    /// How the interior medium combines with others a ray is already in.
+    pub overlap: MediumOverlap,
 }
 
 impl MediumInterface {
@@ -44,6 +96,7 @@ impl MediumInterface {
         Self {
             inside: inside.clone(),
             outside: outside.clone(),
+            overlap: MediumOverlap::default(),
         }
     }
 
@@ -52,6 +105,7 @@ impl MediumInterface {
         Self {
             inside: None,
             outside: None,
+            overlap: MediumOverlap::default(),
         }
     }
 
@@ -59,7 +113,7 @@ impl MediumInterface {
     /// two distinct media.
     pub fn is_medium_transition(&self) -> bool {
         match (self.inside.clone(), self.outside.clone()) {
-            (Some(inside), Some(outside)) => Arc::ptr_eq(&inside, &outside),
+            (Some(inside), Some(outside)) => !Arc::ptr_eq(&inside, &outside),
             (Some(_), None) => true,
             (None, Some(_)) => true,
             (None, None) => false,
@@ -75,6 +129,7 @@ impl From<ArcMedium> for MediumInterface {
         Self {
             inside: Some(Arc::clone(&medium)),
             outside: Some(Arc::clone(&medium)),
+            overlap: MediumOverlap::default(),
         }
     }
 }
@@ -87,6 +142,55 @@ impl From<Option<ArcMedium>> for MediumInterface {
         Self {
             inside: medium.clone(),
             outside: medium.clone(),
+            overlap: MediumOverlap::default(),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Vacuum {}
+
+    impl Medium for Vacuum {
+        fn tr(&self, _ray: &Ray, _sampler: ArcSampler) -> Spectrum {
+            Spectrum::new(1.0)
         }
     }
+
+    #[test]
+    fn medium_transition_requires_distinct_media() {
+        let a: ArcMedium = Arc::new(Vacuum {});
+        let b: ArcMedium = Arc::new(Vacuum {});
+
+        assert!(!MediumInterface::vacuum().is_medium_transition());
+        assert!(!MediumInterface::from(Arc::clone(&a)).is_medium_transition());
+        assert!(
+            MediumInterface::new(Some(Arc::clone(&a)), Some(Arc::clone(&b))).is_medium_transition()
+        );
+        assert!(MediumInterface::new(Some(Arc::clone(&a)), None).is_medium_transition());
+        assert!(MediumInterface::new(None, Some(Arc::clone(&b))).is_medium_transition());
+
+        // A surface inside a single medium reports that medium.
+        let hit = |mi: MediumInterface| {
+            Hit::new(
+                Point3f::default(),
+                0.0,
+                Vector3f::default(),
+                Vector3f::default(),
+                Normal3f::default(),
+                Some(mi),
+            )
+        };
+        let medium = hit(MediumInterface::from(Arc::clone(&a))).get_medium();
+        assert!(medium.is_some_and(|m| Arc::ptr_eq(&m, &a)));
+        assert!(hit(MediumInterface::new(Some(a), Some(b)))
+            .get_medium()
+            .is_none());
+    }
 }
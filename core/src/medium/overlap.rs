@@ -0,0 +1,459 @@
+//! Overlapping Media
+//!
+//! By default a ray crossing a medium boundary takes the medium on the other
+//! side of the interface so the medium entered last replaces any it was
+//! already in. With the `add` and `max` policies the media a ray is in
+//! overlap instead; entering a volume adds its medium to those the ray is in
+//! and leaving it removes the medium again.
+
+use super::{ArcMedium, Medium, MediumInterface};
+use crate::geometry::*;
+use crate::pbrt::*;
+use crate::sampler::*;
+use crate::spectrum::*;
+use std::sync::Arc;
+
+/// How the densities of overlapping media are combined.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MediumOverlap {
+    /// The medium entered last replaces the others.
+    Priority,
+
+    /// Densities are added.
+    Add,
+
+    /// The largest density is used.
+    Max,
+}
+
+impl MediumOverlap {
+    /// Returns the policy for the given name.
+    ///
+    /// * `name` - Policy name (priority, add, max).
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "priority" => Ok(Self::Priority),
+            "add" => Ok(Self::Add),
+            "max" => Ok(Self::Max),
+            _ => Err(format!("Unknown medium overlap policy '{}'", name)),
+        }
+    }
+
+    /// Returns the combined beam transmittance of overlapping media along a
+    /// ray segment given the transmittance of each. Adding densities
+    /// multiplies the transmittances. The largest density gives the smallest
+    /// transmittance in each channel; this is exact for homogeneous media and
+    /// an approximation for heterogeneous media whose densest medium changes
+    /// along the segment.
+    ///
+    /// * `tr` - Beam transmittance of each medium.
+    pub fn combine_tr(&self, tr: &[Spectrum]) -> Spectrum {
+        match self {
+            Self::Priority => tr.last().copied().unwrap_or_else(|| Spectrum::new(1.0)),
+            Self::Add => tr.iter().fold(Spectrum::new(1.0), |acc, t| acc * *t),
+            Self::Max => {
+                let mut result = Spectrum::new(1.0);
+                for t in tr {
+                    for (r, v) in result.samples_mut().iter_mut().zip(t.samples()) {
+                        *r = r.min(*v);
+                    }
+                }
+                result
+            }
+        }
+    }
+}
+
+impl Default for MediumOverlap {
+    /// Returns the default policy where the medium entered last is used.
+    fn default() -> Self {
+        Self::Priority
+    }
+}
+
+/// A region where several media overlap.
+pub struct OverlappingMedium {
+    /// The overlapping media in the order they were entered.
+    media: Vec<ArcMedium>,
+
+    /// How the densities are combined.
+    policy: MediumOverlap,
+}
+
+impl OverlappingMedium {
+    /// Returns a medium for the given media. A single medium is returned
+    /// as is and `None` if there are none.
+    ///
+    /// * `media`  - The media.
+    /// * `policy` - How the densities are combined.
+    pub fn from_media(media: Vec<ArcMedium>, policy: MediumOverlap) -> Option<ArcMedium> {
+        match media.len() {
+            0 => None,
+            1 => media.into_iter().next(),
+            _ => Some(Arc::new(Self { media, policy })),
+        }
+    }
+}
+
+impl Medium for OverlappingMedium {
+    /// Returns the beam transmittance along a given ray.
+    ///
+    /// * `ray`     - The ray.
+    /// * `sampler` - The sampler.
+    fn tr(&self, ray: &Ray, sampler: ArcSampler) -> Spectrum {
+        let tr: Vec<Spectrum> = self
+            .media
+            .iter()
+            .map(|m| m.tr(ray, Arc::clone(&sampler)))
+            .collect();
+        self.policy.combine_tr(&tr)
+    }
+
+    /// Samples a scattering interaction along a given ray up to its `t_max`
+    /// by delta tracking over the combined majorant of the media. With the
+    /// `Max` policy the medium with the largest attenuation at a point
+    /// scatters. If a medium has no majorant the media only absorb light.
+    /// The interaction takes the ray's medium so rays scattered from it
+    /// remain in the overlapping media.
+    ///
+    /// * `ray`     - The ray.
+    /// * `sampler` - The sampler.
+    fn sample(&self, ray: &Ray, sampler: &mut ArcSampler) -> (Spectrum, Option<MediumInteraction>) {
+        if self.policy == MediumOverlap::Priority {
+            return self.media.last().unwrap().sample(ray, sampler);
+        }
+        let majorant = match self.majorant(ray) {
+            Some(majorant) => majorant,
+            None => return (self.tr(ray, Arc::clone(sampler)), None),
+        };
+        let ray_length = ray.d.length();
+        let t_max = ray.t_max * ray_length;
+        if majorant <= 0.0 || !t_max.is_finite() {
+            return (self.tr(ray, Arc::clone(sampler)), None);
+        }
+
+        let sampler = Arc::get_mut(sampler).unwrap();
+        let mut beta = Spectrum::new(1.0);
+        let mut t = 0.0;
+        loop {
+            t -= (1.0 - sampler.get_1d()).ln() / majorant;
+            if t >= t_max {
+                return (beta, None);
+            }
+            let p = ray.at(t / ray_length);
+
+            // Combine the coefficients of the media at the point.
+            let coefficients: Vec<(Spectrum, Spectrum)> =
+                self.media.iter().map(|m| m.coefficients(&p)).collect();
+            let scatterers: Vec<usize> = match self.policy {
+                MediumOverlap::Add => (0..self.media.len()).collect(),
+                _ => {
+                    let sigma_t: Vec<Float> = coefficients
+                        .iter()
+                        .map(|(a, s)| average(&(*a + *s)))
+                        .collect();
+                    let densest =
+                        (0..sigma_t.len())
+                            .fold(0, |k, i| if sigma_t[i] > sigma_t[k] { i } else { k });
+                    vec![densest]
+                }
+            };
+            let (sigma_a, sigma_s) = scatterers
+                .iter()
+                .fold((Spectrum::new(0.0), Spectrum::new(0.0)), |(a, s), i| {
+                    (a + coefficients[*i].0, s + coefficients[*i].1)
+                });
+            let sigma_n = Spectrum::new(majorant) - sigma_a - sigma_s;
+
+            // Choose absorption, scattering or a null collision with
+            // probabilities given by the average of the coefficients.
+            let p_absorb = average(&sigma_a) / majorant;
+            let p_scatter = average(&sigma_s) / majorant;
+            let u = sampler.get_1d();
+            if u < p_absorb {
+                return (Spectrum::new(0.0), None);
+            } else if u < p_absorb + p_scatter {
+                // Choose the scattering medium by its share of the scattering.
+                let mut u = sampler.get_1d() * average(&sigma_s);
+                let mut i = *scatterers
+                    .iter()
+                    .rev()
+                    .find(|j| average(&coefficients[**j].1) > 0.0)
+                    .unwrap();
+                for j in scatterers.iter() {
+                    let share = average(&coefficients[*j].1);
+                    if u < share {
+                        i = *j;
+                        break;
+                    }
+                    u -= share;
+                }
+                beta *= coefficients[i].1 / average(&coefficients[i].1);
+                let mi = ray.medium.as_ref().map(|medium| {
+                    MediumInteraction::new(
+                        p,
+                        -ray.d,
+                        ray.time,
+                        Arc::clone(medium),
+                        self.media[i].phase(&p),
+                    )
+                });
+                return (beta, mi);
+            } else {
+                beta *= sigma_n / average(&sigma_n);
+            }
+        }
+    }
+
+    /// Returns the media overlapping in the region.
+    fn overlapping(&self) -> Option<&[ArcMedium]> {
+        Some(&self.media)
+    }
+
+    /// Returns a bound on the combined attenuation coefficient σt along a
+    /// given ray if all media provide one.
+    ///
+    /// * `ray` - The ray.
+    fn majorant(&self, ray: &Ray) -> Option<Float> {
+        let majorants: Option<Vec<Float>> = self.media.iter().map(|m| m.majorant(ray)).collect();
+        majorants.map(|majorants| match self.policy {
+            MediumOverlap::Add => majorants.iter().sum(),
+            _ => majorants.iter().fold(0.0, |a, b| max(a, *b)),
+        })
+    }
+}
+
+/// Returns the average of the samples of a spectrum.
+///
+/// * `s` - The spectrum.
+fn average(s: &Spectrum) -> Float {
+    s.samples().iter().sum::<Float>() / s.samples().len() as Float
+}
+
+impl MediumInterface {
+    /// Returns the interface seen by a ray crossing it from a medium. With
+    /// the `Priority` policy this is the interface itself. Otherwise the
+    /// side the ray comes from is the medium it is in and the other side
+    /// adds the interior medium when entering or removes it when leaving.
+    ///
+    /// * `current`  - The medium the ray is in.
+    /// * `entering` - Whether the ray crosses from the outside to the inside.
+    pub fn resolve(&self, current: Option<ArcMedium>, entering: bool) -> Self {
+        let overlap = self.overlap;
+        if overlap == MediumOverlap::Priority {
+            return self.clone();
+        }
+
+        let (inside, outside) = if entering {
+            let outside = current.or_else(|| self.outside.clone());
+            let mut media = components(&outside);
+            for medium in components(&self.inside) {
+                if !media.iter().any(|m| Arc::ptr_eq(m, &medium)) {
+                    media.push(medium);
+                }
+            }
+            (OverlappingMedium::from_media(media, overlap), outside)
+        } else {
+            let inside = current.or_else(|| self.inside.clone());
+            let media = components(&inside);
+            let removed = components(&self.inside);
+            let remaining: Vec<ArcMedium> = media
+                .iter()
+                .filter(|m| !removed.iter().any(|r| Arc::ptr_eq(m, r)))
+                .cloned()
+                .collect();
+
+            // A ray that started inside the volume isn't in any medium it
+            // overlaps so it leaves to the exterior medium.
+            let outside = if remaining.len() == media.len() || remaining.is_empty() {
+                self.outside.clone()
+            } else {
+                OverlappingMedium::from_media(remaining, overlap)
+            };
+            (inside, outside)
+        };
+
+        Self {
+            inside,
+            outside,
+            overlap,
+        }
+    }
+}
+
+/// Returns the individual media in a region.
+///
+/// * `medium` - The medium of the region.
+fn components(medium: &Option<ArcMedium>) -> Vec<ArcMedium> {
+    match medium {
+        Some(m) => match m.overlapping() {
+            Some(media) => media.to_vec(),
+            None => vec![Arc::clone(m)],
+        },
+        None => vec![],
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+
+    struct TestMedium;
+
+    impl Medium for TestMedium {
+        fn tr(&self, _ray: &Ray, _sampler: ArcSampler) -> Spectrum {
+            Spectrum::new(1.0)
+        }
+    }
+
+    /// A homogeneous medium with gray coefficients.
+    struct GrayMedium {
+        sigma_a: Float,
+        sigma_s: Float,
+    }
+
+    impl Medium for GrayMedium {
+        fn tr(&self, ray: &Ray, _sampler: ArcSampler) -> Spectrum {
+            Spectrum::new((-(self.sigma_a + self.sigma_s) * ray.t_max * ray.d.length()).exp())
+        }
+
+        fn majorant(&self, _ray: &Ray) -> Option<Float> {
+            Some(self.sigma_a + self.sigma_s)
+        }
+
+        fn coefficients(&self, _p: &Point3f) -> (Spectrum, Spectrum) {
+            (Spectrum::new(self.sigma_a), Spectrum::new(self.sigma_s))
+        }
+    }
+
+    /// A sampler that returns uniform random numbers.
+    struct UniformSampler {
+        data: SamplerData,
+        rng: RNG,
+    }
+
+    impl Sampler for UniformSampler {
+        fn get_data(&mut self) -> &mut SamplerData {
+            &mut self.data
+        }
+
+        fn clone(&self, _seed: u64) -> ArcSampler {
+            unimplemented!()
+        }
+
+        fn get_1d(&mut self) -> Float {
+            self.rng.uniform()
+        }
+
+        fn get_2d(&mut self) -> Point2f {
+            Point2f::new(self.get_1d(), self.get_1d())
+        }
+    }
+
+    /// Returns the average weight of samples along a unit ray through the
+    /// overlapping media and the fraction of them that scattered.
+    fn sample_overlap(media: Vec<ArcMedium>, policy: MediumOverlap) -> (Float, Float) {
+        let medium = OverlappingMedium::from_media(media, policy).unwrap();
+        let mut sampler: ArcSampler = Arc::new(UniformSampler {
+            data: SamplerData::new(1),
+            rng: RNG::new(7),
+        });
+        let ray = Ray::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Vector3f::new(2.0, 0.0, 0.0),
+            0.5,
+            0.0,
+            Some(Arc::clone(&medium)),
+        );
+        let n = 20000;
+        let (mut beta, mut scattered) = (0.0, 0);
+        for _ in 0..n {
+            let (b, mi) = medium.sample(&ray, &mut sampler);
+            beta += b[0];
+            if mi.is_some() {
+                scattered += 1;
+            }
+        }
+        (beta / n as Float, scattered as Float / n as Float)
+    }
+
+    fn same(a: &Option<ArcMedium>, b: &[&ArcMedium]) -> bool {
+        let media = components(a);
+        media.len() == b.len() && media.iter().zip(b).all(|(m, b)| Arc::ptr_eq(m, b))
+    }
+
+    #[test]
+    fn overlapping_media_are_entered_and_left() {
+        let fog: ArcMedium = Arc::new(TestMedium);
+        let smoke: ArcMedium = Arc::new(TestMedium);
+        let mut plume = MediumInterface::new(Some(Arc::clone(&smoke)), None);
+        plume.overlap = MediumOverlap::Add;
+
+        // Entering the plume from the fog keeps the fog.
+        let mi = plume.resolve(Some(Arc::clone(&fog)), true);
+        assert!(same(&mi.outside, &[&fog]));
+        assert!(same(&mi.inside, &[&fog, &smoke]));
+
+        // Leaving it returns to the fog instead of the plume's exterior.
+        let mi = plume.resolve(mi.inside, false);
+        assert!(same(&mi.inside, &[&fog, &smoke]));
+        assert!(same(&mi.outside, &[&fog]));
+
+        // Without overlap the exterior is used.
+        let mi = plume.resolve(Some(Arc::clone(&smoke)), false);
+        assert!(mi.outside.is_none());
+        plume.overlap = MediumOverlap::Priority;
+        let mi = plume.resolve(Some(Arc::clone(&fog)), true);
+        assert!(mi.outside.is_none());
+        assert!(same(&mi.inside, &[&smoke]));
+    }
+
+    #[test]
+    fn transmittance_is_combined_by_policy() {
+        // Set two samples so the test holds for any spectrum representation.
+        let mut tr = [Spectrum::new(1.0), Spectrum::new(1.0)];
+        tr[0][0] = 0.5;
+        tr[0][1] = 0.2;
+        tr[1][0] = 0.4;
+        tr[1][1] = 0.8;
+        let add = MediumOverlap::Add.combine_tr(&tr);
+        assert_eq!([add[0], add[1], add[2]], [0.2, 0.16000001, 1.0]);
+        let max = MediumOverlap::Max.combine_tr(&tr);
+        assert_eq!([max[0], max[1], max[2]], [0.4, 0.2, 1.0]);
+        assert!(MediumOverlap::parse("min").is_err());
+    }
+
+    #[test]
+    fn delta_tracking_samples_combined_media() {
+        let gray = |sigma_a, sigma_s| -> ArcMedium { Arc::new(GrayMedium { sigma_a, sigma_s }) };
+
+        // Absorption adds up so the weights estimate the transmittance.
+        let (beta, scattered) =
+            sample_overlap(vec![gray(0.5, 0.0), gray(0.25, 0.0)], MediumOverlap::Add);
+        assert!((beta - (-0.75 as Float).exp()).abs() < 0.01, "{}", beta);
+        assert!(scattered == 0.0);
+
+        // Scattering media scatter before the end of the ray with the
+        // probability given by the combined or the largest density.
+        let media = || vec![gray(0.0, 1.0), gray(0.0, 0.5)];
+        let (beta, scattered) = sample_overlap(media(), MediumOverlap::Add);
+        assert!((beta - 1.0).abs() < 1e-4, "{}", beta);
+        assert!(
+            (scattered - (1.0 - (-1.5 as Float).exp())).abs() < 0.01,
+            "{}",
+            scattered
+        );
+        let (beta, scattered) = sample_overlap(media(), MediumOverlap::Max);
+        assert!((beta - 1.0).abs() < 1e-4, "{}", beta);
+        assert!(
+            (scattered - (1.0 - (-1.0 as Float).exp())).abs() < 0.01,
+            "{}",
+            scattered
+        );
+    }
+}
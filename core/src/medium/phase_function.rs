@@ -18,6 +18,16 @@ pub trait PhaseFunction {
     /// * `wo` - Outgoing direction.
     /// * `u`  - Sample value in [0, 1)^2.
     fn sample_p(&self, wo: &Vector3f, u: &Point2f) -> (Float, Vector3f);
+
+    /// Returns the PDF of sampling the incident direction with `sample_p()`.
+    /// Default is the phase function value for phase functions that are
+    /// sampled exactly.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn pdf(&self, wo: &Vector3f, wi: &Vector3f) -> Float {
+        self.p(wo, wi)
+    }
 }
 
 /// Atomic reference counted `PhaseFunction`.
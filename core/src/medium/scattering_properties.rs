@@ -0,0 +1,252 @@
+//! Medium Scattering Properties
+
+use super::get_medium_scattering_properties;
+use crate::paramset::*;
+use crate::pbrt::*;
+use crate::spectrum::*;
+
+/// Default absorption coefficients in mm^-1 (measured skim milk).
+const DEFAULT_SIGMA_A: [Float; 3] = [0.0011, 0.0024, 0.014];
+
+/// Default scattering coefficients in mm^-1 (measured skim milk).
+const DEFAULT_SIGMA_S: [Float; 3] = [2.55, 3.21, 3.77];
+
+/// Mean free paths in scene units outside this range are likely the result of
+/// coefficients given in the wrong units.
+const MEAN_FREE_PATH_RANGE: (Float, Float) = (1e-6, 1e4);
+
+/// Units of length for scattering coefficients and scenes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LengthUnit {
+    /// Millimetres.
+    Millimetre,
+
+    /// Centimetres.
+    Centimetre,
+
+    /// Metres.
+    Metre,
+}
+
+impl LengthUnit {
+    /// Returns the length of the unit in metres.
+    pub fn metres(&self) -> Float {
+        match self {
+            Self::Millimetre => 0.001,
+            Self::Centimetre => 0.01,
+            Self::Metre => 1.0,
+        }
+    }
+
+    /// Returns the unit for the given name or `None` if it is not recognized.
+    ///
+    /// * `name` - Unit name (mm, cm, m).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "mm" => Some(Self::Millimetre),
+            "cm" => Some(Self::Centimetre),
+            "m" => Some(Self::Metre),
+            _ => None,
+        }
+    }
+}
+
+/// Absorption and scattering coefficients of participating media and
+/// subsurface scattering materials expressed per scene unit length.
+#[derive(Copy, Clone)]
+pub struct ScatteringProperties {
+    /// Absorption coefficient σa.
+    pub sigma_a: Spectrum,
+
+    /// Scattering coefficient σs.
+    pub sigma_s: Spectrum,
+}
+
+impl ScatteringProperties {
+    /// Returns the attenuation coefficient σt = σa + σs.
+    pub fn sigma_t(&self) -> Spectrum {
+        self.sigma_a + self.sigma_s
+    }
+
+    /// Returns the factor converting coefficients given per `units` to per
+    /// `scene_units`. Unknown units are logged and ignored.
+    ///
+    /// * `units`       - Units of the coefficients (mm, cm, m) or an empty
+    ///                   string if they are given per scene unit.
+    /// * `scene_units` - Units of the scene.
+    pub fn unit_scale(units: &str, scene_units: &str) -> Float {
+        if units.is_empty() {
+            return 1.0;
+        }
+        match (LengthUnit::parse(units), LengthUnit::parse(scene_units)) {
+            (Some(from), Some(to)) => to.metres() / from.metres(),
+            (None, _) => {
+                warn!("Unknown medium units '{}'. Ignoring.", units);
+                1.0
+            }
+            (_, None) => {
+                warn!("Unknown scene units '{}'. Ignoring.", scene_units);
+                1.0
+            }
+        }
+    }
+
+    /// Create `ScatteringProperties` from parameters found with the given
+    /// functions. A named `preset` from the measured media table takes the
+    /// place of `sigma_a` and `sigma_s`. The coefficients are multiplied by
+    /// `scale` and, if `units` is given, converted from per `units` to per
+    /// `sceneunits` (default to metres). Warnings are logged for values that
+    /// look off by orders of magnitude.
+    ///
+    /// * `preset`        - Name of a measured medium or an empty string.
+    /// * `find_float`    - Finds a float parameter.
+    /// * `find_string`   - Finds a string parameter.
+    /// * `find_spectrum` - Finds a spectrum parameter.
+    fn find<F, S, P>(preset: &str, find_float: F, find_string: S, find_spectrum: P) -> Self
+    where
+        F: Fn(&str, Float) -> Float,
+        S: Fn(&str, String) -> String,
+        P: Fn(&str, Spectrum) -> Spectrum,
+    {
+        let scale = find_float("scale", 1.0);
+        let units = find_string("units", String::new());
+        let scene_units = find_string("sceneunits", String::from("m"));
+        let unit_scale = Self::unit_scale(&units, &scene_units);
+
+        // Use the measured coefficients of a named medium if one is given.
+        let measured = if preset.is_empty() {
+            None
+        } else {
+            let measured = get_medium_scattering_properties(preset);
+            if measured.is_none() {
+                warn!("Medium preset '{}' not found. Using defaults.", preset);
+            }
+            measured
+        };
+        let (sigma_a, sigma_s) = match measured {
+            Some(measured) => (measured.sigma_a, measured.sigma_s),
+            None => (
+                find_spectrum("sigma_a", Spectrum::from_rgb(&DEFAULT_SIGMA_A, None)),
+                find_spectrum("sigma_s", Spectrum::from_rgb(&DEFAULT_SIGMA_S, None)),
+            ),
+        };
+        let props = Self {
+            sigma_a: sigma_a * (scale * unit_scale),
+            sigma_s: sigma_s * (scale * unit_scale),
+        };
+
+        // Validate the scaled coefficients.
+        if scale <= 0.0 {
+            warn!("Medium 'scale' {} should be positive.", scale);
+        }
+        let negative = |s: &Spectrum| s.to_rgb().iter().any(|c| *c < 0.0);
+        if negative(&props.sigma_a) || negative(&props.sigma_s) {
+            warn!("Medium 'sigma_a' and 'sigma_s' should not be negative.");
+        }
+        let sigma_t = props.sigma_t().max_component_value();
+        if sigma_t > 0.0 {
+            Self::check_mean_free_path(1.0 / sigma_t);
+        }
+
+        props
+    }
+
+    /// Logs a warning if a mean free path looks off by orders of magnitude,
+    /// which is likely the result of coefficients given in the wrong units.
+    ///
+    /// * `mean_free_path` - Mean free path in scene units.
+    pub fn check_mean_free_path(mean_free_path: Float) {
+        let (lo, hi) = MEAN_FREE_PATH_RANGE;
+        if mean_free_path < lo || mean_free_path > hi {
+            warn!(
+                "Medium mean free path {} scene units looks off by orders of magnitude. \
+                 Check 'scale' and 'units'.",
+                mean_free_path
+            );
+        }
+    }
+}
+
+impl From<&ParamSet> for ScatteringProperties {
+    /// Create `ScatteringProperties` from given parameter set. A named
+    /// `preset` from the measured media table takes the place of `sigma_a`
+    /// and `sigma_s`. The coefficients are multiplied by `scale` and, if
+    /// `units` is given, converted from per `units` to per `sceneunits`
+    /// (default to metres). Warnings are logged for values that look off by
+    /// orders of magnitude.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        Self::find(
+            &params.find_one_string("preset", String::new()),
+            |name, default| params.find_one_float(name, default),
+            |name, default| params.find_one_string(name, default),
+            |name, default| params.find_one_spectrum(name, default),
+        )
+    }
+}
+
+impl From<&TextureParams> for ScatteringProperties {
+    /// Create `ScatteringProperties` from the parameters of a subsurface
+    /// scattering material like `From<&ParamSet>` does for media. Shape
+    /// parameters take precedence over material parameters and the measured
+    /// medium is given by `name` as in pbrt.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        Self::find(
+            &tp.find_string("name", tp.find_string("preset", String::new())),
+            |name, default| tp.find_float(name, default),
+            |name, default| tp.find_string(name, default),
+            |name, default| tp.find_spectrum(name, default),
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_conversion() {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("sigma_a", &[1.0, 1.0, 1.0]);
+        params.add_rgb_spectrum("sigma_s", &[2.0, 2.0, 2.0]);
+        params.add_string("units", &[String::from("mm")]);
+        params.add_string("sceneunits", &[String::from("cm")]);
+        params.add_float("scale", &[0.5]);
+
+        // Luminance of RGB white for the spectrum representation in use.
+        let white = Spectrum::from_rgb(&[1.0, 1.0, 1.0], None).y();
+        let props = ScatteringProperties::from(&params);
+        assert!((props.sigma_a.y() - 5.0 * white).abs() < 1e-4);
+        assert!((props.sigma_s.y() - 10.0 * white).abs() < 1e-4);
+    }
+
+    #[test]
+    fn no_units_leaves_coefficients() {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("sigma_s", &[2.0, 2.0, 2.0]);
+
+        let white = Spectrum::from_rgb(&[1.0, 1.0, 1.0], None).y();
+        let props = ScatteringProperties::from(&params);
+        assert!((props.sigma_s.y() - 2.0 * white).abs() < 1e-4);
+    }
+
+    #[test]
+    fn preset_replaces_coefficients() {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("sigma_a", &[1.0, 1.0, 1.0]);
+        params.add_string("preset", &[String::from("Skin1")]);
+        params.add_float("scale", &[2.0]);
+
+        let skin = get_medium_scattering_properties("Skin1").unwrap();
+        let props = ScatteringProperties::from(&params);
+        assert!((props.sigma_a.y() - 2.0 * skin.sigma_a.y()).abs() < 1e-4);
+        assert!((props.sigma_s.y() - 2.0 * skin.sigma_s.y()).abs() < 1e-4);
+    }
+}
@@ -69,6 +69,11 @@ where
         self.v_res
     }
 
+    /// Returns the memory used by the array data in bytes.
+    pub fn memory(&self) -> usize {
+        self.data.len() * std::mem::size_of::<T>()
+    }
+
     /// Returns a linear `Vec<T>`.
     pub fn linear_vec(&self) -> Vec<T> {
         let mut a = Vec::with_capacity(self.u_res * self.v_res);
@@ -0,0 +1,192 @@
+//! Microfacet Albedo Tables
+
+#![allow(dead_code)]
+use super::*;
+
+/// Number of roughness values in the albedo tables.
+pub const ALBEDO_TABLE_ALPHA_SAMPLES: usize = 32;
+
+/// Number of cos(θ) values in the albedo tables.
+pub const ALBEDO_TABLE_COS_THETA_SAMPLES: usize = 32;
+
+/// Number of stratified samples per dimension used to estimate each entry.
+const ALBEDO_TABLE_STRATA: usize = 16;
+
+lazy_static! {
+    /// Directional albedo table for the Trowbridge-Reitz distribution.
+    pub static ref TROWBRIDGE_REITZ_ALBEDO: AlbedoTable =
+        AlbedoTable::new(|alpha| TrowbridgeReitzDistribution::new(alpha, alpha, true));
+
+    /// Directional albedo table for the Beckmann-Spizzichino distribution.
+    pub static ref BECKMANN_ALBEDO: AlbedoTable =
+        AlbedoTable::new(|alpha| BeckmannDistribution::new(alpha, alpha, true));
+}
+
+/// Stores the directional albedo of a perfectly reflective (no Fresnel)
+/// single scattering microfacet BRDF over isotropic roughness α in [0, 1]
+/// and cos(θo) in [0, 1] along with its cosine weighted hemispherical
+/// average for each roughness.
+pub struct AlbedoTable {
+    /// Directional albedo indexed by roughness and then cos(θo).
+    albedo: Vec<Float>,
+
+    /// Average albedo for each roughness.
+    average_albedo: Vec<Float>,
+}
+
+impl AlbedoTable {
+    /// Create a new `AlbedoTable` by numerically integrating the microfacet
+    /// BRDF. A fixed stratified pattern is used so the table is deterministic.
+    ///
+    /// * `distribution` - Returns the microfacet distribution for a given
+    ///                    isotropic roughness α.
+    pub fn new<D, F>(distribution: F) -> Self
+    where
+        D: MicrofacetDistribution,
+        F: Fn(Float) -> D,
+    {
+        let n_alpha = ALBEDO_TABLE_ALPHA_SAMPLES;
+        let n_cos_theta = ALBEDO_TABLE_COS_THETA_SAMPLES;
+
+        let mut albedo = Vec::with_capacity(n_alpha * n_cos_theta);
+        let mut average_albedo = Vec::with_capacity(n_alpha);
+        for a in 0..n_alpha {
+            let d = distribution(a as Float / (n_alpha - 1) as Float);
+
+            let mut average = 0.0;
+            for c in 0..n_cos_theta {
+                let cos_theta = max(c as Float / (n_cos_theta - 1) as Float, 1e-3);
+                let e = directional_albedo(&d, cos_theta);
+                albedo.push(e);
+
+                // Trapezoidal rule for E_avg = 2 ∫ E(μ) μ dμ.
+                let w = if c == 0 || c == n_cos_theta - 1 {
+                    0.5
+                } else {
+                    1.0
+                };
+                average += w * e * cos_theta;
+            }
+            average_albedo.push(clamp(2.0 * average / (n_cos_theta - 1) as Float, 0.0, 1.0));
+        }
+
+        Self {
+            albedo,
+            average_albedo,
+        }
+    }
+
+    /// Returns the directional albedo by bilinearly interpolating the table.
+    ///
+    /// * `alpha`     - Isotropic roughness α.
+    /// * `cos_theta` - Cosine of the angle between outgoing direction and the
+    ///                 surface normal.
+    pub fn albedo(&self, alpha: Float, cos_theta: Float) -> Float {
+        let n_cos_theta = ALBEDO_TABLE_COS_THETA_SAMPLES;
+        let (a0, a1, ta) = Self::lookup(alpha, ALBEDO_TABLE_ALPHA_SAMPLES);
+        let (c0, c1, tc) = Self::lookup(abs(cos_theta), n_cos_theta);
+
+        let e0 = lerp(
+            tc,
+            self.albedo[a0 * n_cos_theta + c0],
+            self.albedo[a0 * n_cos_theta + c1],
+        );
+        let e1 = lerp(
+            tc,
+            self.albedo[a1 * n_cos_theta + c0],
+            self.albedo[a1 * n_cos_theta + c1],
+        );
+        lerp(ta, e0, e1)
+    }
+
+    /// Returns the cosine weighted hemispherical average albedo by linearly
+    /// interpolating the table.
+    ///
+    /// * `alpha` - Isotropic roughness α.
+    pub fn average_albedo(&self, alpha: Float) -> Float {
+        let (a0, a1, ta) = Self::lookup(alpha, ALBEDO_TABLE_ALPHA_SAMPLES);
+        lerp(ta, self.average_albedo[a0], self.average_albedo[a1])
+    }
+
+    /// Returns the table indices bracketing a value in [0, 1] and the
+    /// interpolation weight between them.
+    ///
+    /// * `x` - The value.
+    /// * `n` - Number of table entries.
+    fn lookup(x: Float, n: usize) -> (usize, usize, Float) {
+        let x = clamp(x, 0.0, 1.0) * (n - 1) as Float;
+        let i0 = min(x.floor() as usize, n - 2);
+        (i0, i0 + 1, x - i0 as Float)
+    }
+}
+
+/// Estimates the directional albedo of a microfacet BRDF without Fresnel
+/// using stratified samples of visible normals.
+///
+/// * `distribution` - The microfacet distribution.
+/// * `cos_theta`    - Cosine of the angle between outgoing direction and the
+///                    surface normal.
+fn directional_albedo<D: MicrofacetDistribution>(distribution: &D, cos_theta: Float) -> Float {
+    let wo = Vector3f::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+
+    let n = ALBEDO_TABLE_STRATA;
+    let mut sum = 0.0;
+    for i in 0..n {
+        for j in 0..n {
+            let u = Point2f::new(
+                (i as Float + 0.5) / n as Float,
+                (j as Float + 0.5) / n as Float,
+            );
+            let wh = distribution.sample_wh(&wo, &u);
+            let wo_dot_wh = wo.dot(&wh);
+            if wo_dot_wh <= 0.0 {
+                continue;
+            }
+
+            let wi = reflect(&wo, &wh);
+            if wi.z <= 0.0 {
+                continue;
+            }
+
+            let pdf = distribution.pdf(&wo, &wh) / (4.0 * wo_dot_wh);
+            if pdf > 0.0 {
+                let f = distribution.d(&wh) * distribution.g(&wo, &wi) / (4.0 * wo.z * wi.z);
+                sum += f * wi.z / pdf;
+            }
+        }
+    }
+    clamp(sum / (n * n) as Float, 0.0, 1.0)
+}
+
+/// Forces computation of the albedo tables so it does not happen lazily
+/// during rendering.
+pub fn init_albedo_tables() {
+    lazy_static::initialize(&TROWBRIDGE_REITZ_ALBEDO);
+    lazy_static::initialize(&BECKMANN_ALBEDO);
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smooth_surfaces_reflect_all_energy() {
+        let e = TROWBRIDGE_REITZ_ALBEDO.albedo(0.0, 1.0);
+        assert!(e > 0.98 && e <= 1.0, "E = {}", e);
+    }
+
+    #[test]
+    fn rough_surfaces_lose_energy() {
+        for table in [&*TROWBRIDGE_REITZ_ALBEDO, &*BECKMANN_ALBEDO].iter() {
+            let smooth = table.average_albedo(0.1);
+            let rough = table.average_albedo(1.0);
+            assert!(rough < smooth, "{} >= {}", rough, smooth);
+            assert!(rough > 0.0 && smooth <= 1.0);
+            assert!(table.albedo(1.0, 0.2) < table.albedo(0.1, 0.2));
+        }
+    }
+}
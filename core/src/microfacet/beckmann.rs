@@ -4,7 +4,7 @@
 use crate::geometry::*;
 use crate::pbrt::*;
 use crate::reflection::*;
-use super::MicrofacetDistribution;
+use super::{MicrofacetDistribution, BECKMANN_ALBEDO};
 
 /// Implements the Beckmann–Spizzichino distribution which based on Gaussian 
 /// distribution of microfacet slopes.
@@ -62,6 +62,21 @@ impl MicrofacetDistribution for BeckmannDistribution {
         self.sample_visible_area
     }
 
+    /// Returns the directional albedo of a perfectly reflective microfacet
+    /// BRDF using this distribution. Anisotropic roughness is approximated
+    /// by the geometric mean of `alpha_x` and `alpha_y`.
+    ///
+    /// * `cos_theta` - Cosine of the angle between outgoing direction and the
+    ///                 surface normal.
+    fn albedo(&self, cos_theta: Float) -> Option<Float> {
+        Some(BECKMANN_ALBEDO.albedo((self.alpha_x * self.alpha_y).sqrt(), cos_theta))
+    }
+
+    /// Returns the cosine weighted hemispherical average of `albedo()`.
+    fn average_albedo(&self) -> Option<Float> {
+        Some(BECKMANN_ALBEDO.average_albedo((self.alpha_x * self.alpha_y).sqrt()))
+    }
+
     /// Return the differential area of microfacets oriented with the surface
     /// normal `wh`.
     ///
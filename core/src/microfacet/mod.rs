@@ -6,10 +6,12 @@ use crate::pbrt::*;
 use crate::reflection::*;
 use std::sync::Arc;
 
+mod albedo_table;
 mod beckmann;
 mod trowbridge_reitz;
 
 // Re-exports
+pub use albedo_table::*;
 pub use beckmann::*;
 pub use trowbridge_reitz::*;
 
@@ -64,6 +66,21 @@ pub trait MicrofacetDistribution {
             self.d(wh) * abs_cos_theta(wh)
         }
     }
+
+    /// Returns the directional albedo of a perfectly reflective microfacet
+    /// BRDF using this distribution or `None` if it is not tabulated.
+    ///
+    /// * `cos_theta` - Cosine of the angle between outgoing direction and the
+    ///                 surface normal.
+    fn albedo(&self, _cos_theta: Float) -> Option<Float> {
+        None
+    }
+
+    /// Returns the cosine weighted hemispherical average of `albedo()` or
+    /// `None` if it is not tabulated.
+    fn average_albedo(&self) -> Option<Float> {
+        None
+    }
 }
 
 /// Atomic reference counted `BSDF`.
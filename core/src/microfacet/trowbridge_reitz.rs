@@ -4,7 +4,7 @@
 use crate::geometry::*;
 use crate::pbrt::*;
 use crate::reflection::*;
-use super::MicrofacetDistribution;
+use super::{MicrofacetDistribution, TROWBRIDGE_REITZ_ALBEDO};
 
 /// Implements the anisotropic variant of the Trowbridge-Reitz distribution.
 #[derive(Copy, Clone, Default)]
@@ -61,6 +61,21 @@ impl MicrofacetDistribution for TrowbridgeReitzDistribution {
         self.sample_visible_area
     }
 
+    /// Returns the directional albedo of a perfectly reflective microfacet
+    /// BRDF using this distribution. Anisotropic roughness is approximated
+    /// by the geometric mean of `alpha_x` and `alpha_y`.
+    ///
+    /// * `cos_theta` - Cosine of the angle between outgoing direction and the
+    ///                 surface normal.
+    fn albedo(&self, cos_theta: Float) -> Option<Float> {
+        Some(TROWBRIDGE_REITZ_ALBEDO.albedo((self.alpha_x * self.alpha_y).sqrt(), cos_theta))
+    }
+
+    /// Returns the cosine weighted hemispherical average of `albedo()`.
+    fn average_albedo(&self) -> Option<Float> {
+        Some(TROWBRIDGE_REITZ_ALBEDO.average_albedo((self.alpha_x * self.alpha_y).sqrt()))
+    }
+
     /// Return the differential area of microfacets oriented with the surface
     /// normal `wh`.
     ///
@@ -143,7 +158,7 @@ fn trowbridge_reitz_sample_11(cos_theta: Float, u1: Float, u2: Float) -> (Float,
     // special case (normal incidence)
     if cos_theta > 0.9999 {
         let r = (u1 / (1.0 - u1)).sqrt();
-        let phi = 6.28318530718 * u2; // TODO: Why not use TWO_PI * u2.
+        let phi = TWO_PI * u2;
         let slope_x = r * cos(phi);
         let slope_y = r * sin(phi);
         return (slope_x, slope_y);
@@ -0,0 +1,271 @@
+//! Texture Memory Budget
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, Weak};
+
+/// Interface for `MIPMap`s whose pyramid levels can be dropped to stay within
+/// the texture memory budget and loaded again on demand.
+pub(crate) trait EvictableMIPMap: Send + Sync {
+    /// Returns the last use and size in bytes of every resident pyramid level
+    /// as `(level, last_used, bytes)`.
+    fn resident_levels(&self) -> Vec<(usize, u64, usize)>;
+
+    /// Drops the texels of a pyramid level and returns the bytes released.
+    ///
+    /// * `level` - The MIPMap level.
+    fn evict_level(&self, level: usize) -> usize;
+}
+
+/// Tracks the `MIPMap`s loaded through the `MIPMapCache` that are subject to
+/// the texture memory budget.
+struct TextureMemoryBudget {
+    /// Maximum bytes of resident texels. `None` disables the budget.
+    max_bytes: Option<usize>,
+
+    /// The registered `MIPMap`s.
+    mipmaps: Vec<Weak<dyn EvictableMIPMap>>,
+}
+
+lazy_static! {
+    /// The global texture memory budget.
+    static ref BUDGET: Mutex<TextureMemoryBudget> = Mutex::new(TextureMemoryBudget {
+        max_bytes: None,
+        mipmaps: vec![],
+    });
+}
+
+/// Clock used to order pyramid levels by their last use. It advances on every
+/// lookup and every time a level is loaded.
+static CLOCK: AtomicU64 = AtomicU64::new(1);
+
+/// Sets the maximum number of bytes of image texture texels that are kept in
+/// memory. When set, the `MIPMapCache` defers reading images until their
+/// first lookup and drops the least recently used pyramid levels when the
+/// budget is exceeded; they are resampled from a finer level or the image
+/// file when needed.
+///
+/// * `max_bytes` - The budget in bytes or `None` to keep every texture.
+pub fn set_texture_memory_budget(max_bytes: Option<usize>) {
+    BUDGET
+        .lock()
+        .expect("Unable to access texture budget mutex")
+        .max_bytes = max_bytes;
+}
+
+/// Returns the texture memory budget in bytes, if any.
+pub fn texture_memory_budget() -> Option<usize> {
+    BUDGET
+        .lock()
+        .expect("Unable to access texture budget mutex")
+        .max_bytes
+}
+
+/// Returns the bytes of texels currently resident in `MIPMap`s that are
+/// subject to the texture memory budget.
+pub fn texture_memory_resident() -> usize {
+    let budget = BUDGET
+        .lock()
+        .expect("Unable to access texture budget mutex");
+    budget
+        .mipmaps
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|m| m.resident_levels().iter().map(|(_, _, b)| b).sum::<usize>())
+        .sum()
+}
+
+/// Advances the clock and returns the new value.
+pub(crate) fn next_tick() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Registers a `MIPMap` with the texture memory budget.
+///
+/// * `mipmap` - The `MIPMap`.
+pub(crate) fn register_mipmap(mipmap: Weak<dyn EvictableMIPMap>) {
+    let mut budget = BUDGET
+        .lock()
+        .expect("Unable to access texture budget mutex");
+    budget.mipmaps.retain(|m| m.strong_count() > 0);
+    budget.mipmaps.push(mipmap);
+}
+
+/// Drops least recently used pyramid levels until the resident texels fit the
+/// budget. Ties are broken by dropping the largest level first so a texture
+/// that was just loaded keeps its coarse levels. A lookup holds its own
+/// reference to the texels of the levels it reads so dropping them never
+/// affects a lookup in progress.
+///
+/// This must not be called while holding a lock on a `MIPMap` level.
+pub(crate) fn enforce_texture_memory_budget() {
+    let budget = BUDGET
+        .lock()
+        .expect("Unable to access texture budget mutex");
+    let max_bytes = match budget.max_bytes {
+        Some(max_bytes) => max_bytes,
+        None => return,
+    };
+
+    let mipmaps: Vec<_> = budget.mipmaps.iter().filter_map(Weak::upgrade).collect();
+    let mut levels: Vec<(u64, usize, usize, usize)> = vec![];
+    for (i, mipmap) in mipmaps.iter().enumerate() {
+        for (level, last_used, bytes) in mipmap.resident_levels() {
+            levels.push((last_used, bytes, i, level));
+        }
+    }
+
+    let mut resident: usize = levels.iter().map(|l| l.1).sum();
+    if resident <= max_bytes {
+        return;
+    }
+
+    // Oldest first, then largest first.
+    levels.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+    for &(_, _, i, level) in levels.iter() {
+        if resident <= max_bytes {
+            break;
+        }
+        let freed = mipmaps[i].evict_level(level);
+        resident -= freed.min(resident);
+    }
+
+    debug!(
+        "Texture memory resident {} bytes, budget {} bytes.",
+        resident, max_bytes
+    );
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+    use crate::mipmap::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    /// Returns a lazily loaded 64x64 constant texture registered with the
+    /// budget and a counter of how often its image was read.
+    fn texture(value: Float) -> (Arc<MIPMap<Float>>, Arc<AtomicUsize>) {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reads);
+        let mipmap = Arc::new(MIPMap::from_loader(
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok((Point2::new(64, 64), vec![value; 64 * 64]))
+            }),
+            FilteringMethod::Trilinear,
+            ImageWrap::Repeat,
+            0.0,
+        ));
+        register_mipmap(Arc::downgrade(&mipmap) as Weak<dyn EvictableMIPMap>);
+        (mipmap, reads)
+    }
+
+    #[test]
+    fn drops_least_recently_used_levels() {
+        let st = Point2f::new(0.25, 0.75);
+        let full = MIPMap::new(
+            &Point2::new(64, 64),
+            &[0.0; 64 * 64],
+            FilteringMethod::Trilinear,
+            ImageWrap::Repeat,
+            0.0,
+        )
+        .memory();
+        set_texture_memory_budget(Some(full + full / 2));
+
+        // Images are not read until the first lookup.
+        let (a, a_reads) = texture(1.0);
+        assert_eq!(a.memory(), 0);
+        assert_eq!(a.lookup_triangle(&st, 0.0), 1.0);
+        assert_eq!(a_reads.load(Ordering::SeqCst), 1);
+        assert_eq!(a.memory(), full);
+        assert_eq!(a.lookup_triangle(&st, 1.0), 1.0);
+
+        // Loading a second texture drops the least recently used levels of
+        // the first.
+        let (b, b_reads) = texture(2.0);
+        assert_eq!(b.lookup_triangle(&st, 0.0), 2.0);
+        assert_eq!(b_reads.load(Ordering::SeqCst), 1);
+        assert!(a.memory() < full);
+        assert!(a.memory() + b.memory() <= full + full / 2);
+
+        // The coarsest level of the first texture is still resident.
+        assert_eq!(a.lookup_triangle(&st, 1.0), 1.0);
+        assert_eq!(a_reads.load(Ordering::SeqCst), 1);
+
+        // The finest level is read again on demand.
+        assert_eq!(a.lookup_triangle(&st, 0.0), 1.0);
+        assert_eq!(a_reads.load(Ordering::SeqCst), 2);
+        assert!(b.memory() < full);
+
+        set_texture_memory_budget(None);
+    }
+
+    #[test]
+    fn restores_only_the_requested_level() {
+        let resolution = Point2::new(64, 64);
+        let img: Vec<Float> = (0..64 * 64).map(|i| (i % 7) as Float).collect();
+        let eager = MIPMap::new(
+            &resolution,
+            &img,
+            FilteringMethod::Trilinear,
+            ImageWrap::Repeat,
+            0.0,
+        );
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&reads);
+        let lazy = MIPMap::from_loader(
+            Arc::new(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok((resolution, img.clone()))
+            }),
+            FilteringMethod::Trilinear,
+            ImageWrap::Repeat,
+            0.0,
+        );
+        let resident = |m: &MIPMap<Float>| -> Vec<usize> {
+            m.resident_levels()
+                .iter()
+                .map(|(level, _, _)| *level)
+                .collect()
+        };
+        let same_texels = |level: usize| {
+            let (a, b) = (eager.level(level), lazy.level(level));
+            (0..a.v_size()).all(|t| (0..a.u_size()).all(|s| a[(s, t)] == b[(s, t)]))
+        };
+
+        // Lookups mark levels as recently used.
+        lazy.level(0);
+        lazy.level(5);
+        let last_used = |level: usize| {
+            lazy.resident_levels()
+                .iter()
+                .find(|(l, _, _)| *l == level)
+                .map(|(_, last_used, _)| *last_used)
+                .unwrap()
+        };
+        assert!(last_used(5) > last_used(0));
+        assert!(last_used(0) > last_used(1));
+
+        // A level is resampled from a finer resident level.
+        for level in 2..5 {
+            lazy.evict_level(level);
+        }
+        assert!(same_texels(3));
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert_eq!(resident(&lazy), vec![0, 1, 3, 5, 6]);
+
+        // Without a finer resident level the image is read again.
+        for level in 0..4 {
+            lazy.evict_level(level);
+        }
+        assert!(same_texels(2));
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+        assert_eq!(resident(&lazy), vec![2, 5, 6]);
+    }
+}
@@ -1,5 +1,6 @@
 //! MIPMap Cache for ImageTexture
 
+use super::budget::*;
 use super::convert_in::*;
 use super::tex_info::*;
 use crate::image_io::*;
@@ -8,7 +9,7 @@ use crate::spectrum::*;
 use std::collections::HashMap;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign};
 use std::result::Result;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Weak};
 
 /// Interface for caching and retrieving `MIPMap`s.
 pub trait MIPMapCacheProvider<Tmemory> {
@@ -58,13 +59,19 @@ macro_rules! cache_provider {
 cache_provider!(RGBSpectrum, RGB_SPECTRUM_MIPMAPS);
 cache_provider!(Float, FLOAT_MIPMAPS);
 
-/// Load an image texture from file and build the `MIPMap`.
+/// Build the `MIPMap` for an image texture. Without a texture memory budget
+/// the image is read immediately and all levels stay in memory. With a budget
+/// the image is read on the first lookup and levels may be dropped and read
+/// again later.
 ///
 /// * `info` - Texture information.
 fn generate_mipmap<Tmemory>(info: &TexInfo) -> Result<Arc<MIPMap<Tmemory>>, String>
 where
     Tmemory: Copy
         + Default
+        + Send
+        + Sync
+        + 'static
         + Mul<Float, Output = Tmemory>
         + MulAssign<Float>
         + Div<Float, Output = Tmemory>
@@ -72,9 +79,43 @@ where
         + Add<Tmemory, Output = Tmemory>
         + AddAssign
         + Clamp<Float>,
-    Spectrum: ConvertIn<Tmemory>,
+    RGBSpectrum: ConvertIn<Tmemory>,
 {
-    // Create `MipMap` for `filename`.
+    if texture_memory_budget().is_none() {
+        let (resolution, texels) = read_texels(info)?;
+        return Ok(Arc::new(MIPMap::new(
+            &resolution,
+            &texels,
+            info.filtering_method,
+            info.wrap_mode,
+            info.max_anisotropy,
+        )));
+    }
+
+    // Report missing files now rather than on the first lookup.
+    if let Err(err) = std::fs::metadata(&info.path) {
+        return Err(format!("Error reading texture {}, {:}.", info.path, err));
+    }
+
+    let loader_info = info.clone();
+    let mipmap = Arc::new(MIPMap::from_loader(
+        Arc::new(move || read_texels(&loader_info)),
+        info.filtering_method,
+        info.wrap_mode,
+        info.max_anisotropy,
+    ));
+    register_mipmap(Arc::downgrade(&mipmap) as Weak<dyn EvictableMIPMap>);
+    Ok(mipmap)
+}
+
+/// Read an image texture from file and return its resolution and texels.
+///
+/// * `info` - Texture information.
+fn read_texels<Tmemory>(info: &TexInfo) -> Result<(Point2<usize>, Vec<Tmemory>), String>
+where
+    RGBSpectrum: ConvertIn<Tmemory>,
+{
+    debug!("Reading texture {}.", info.path);
     let RGBImage {
         pixels: mut texels,
         resolution,
@@ -89,23 +130,15 @@ where
         for x in 0..resolution.x {
             let o1 = y * resolution.x + x;
             let o2 = (resolution.y - 1 - y) * resolution.x + x;
-            let tmp = texels[o1];
-            texels[o2] = texels[o1];
-            texels[o1] = tmp;
+            texels.swap(o1, o2);
         }
     }
 
-    // Convert texels to type M and create MIPMap.
+    // Convert texels to type M.
     let converted_texels: Vec<Tmemory> = texels
         .iter()
         .map(|texel| (*texel).convert_in(info.scale, info.gamma))
         .collect();
 
-    Ok(Arc::new(MIPMap::new(
-        &resolution,
-        &converted_texels,
-        info.filtering_method,
-        info.wrap_mode,
-        info.max_anisotropy,
-    )))
+    Ok((resolution, converted_texels))
 }
@@ -8,13 +8,16 @@ use crate::pbrt::*;
 use crate::texture::*;
 use std::hash::Hash;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
+mod budget;
 mod cache;
 mod convert_in;
 mod tex_info;
 
 // Re-export
+pub use budget::{set_texture_memory_budget, texture_memory_budget, texture_memory_resident};
 pub use cache::*;
 pub use convert_in::*;
 pub use tex_info::*;
@@ -22,6 +25,8 @@ pub use tex_info::*;
 /// Size of the weights lookup table.
 const WEIGHT_LUT_SIZE: usize = 128;
 
+crate::stat_memory!("Memory/Texture MIP maps", TEXTURE_MEMORY);
+
 /// Enumeration for the image wrapping convention for out-of-bounds texels.
 #[derive(Copy, Clone, Hash, PartialEq)]
 pub enum ImageWrap {
@@ -36,8 +41,9 @@ pub enum ImageWrap {
 /// Holds details for image reconstruction.
 #[derive(Copy, Clone, Default)]
 pub struct ResampleWeight {
-    /// Offset to the first texel.
-    pub first_texel: usize,
+    /// Offset to the first texel. It is negative for texels before the
+    /// start of the image.
+    pub first_texel: isize,
 
     /// The weight values for 4 texels.
     pub weight: [Float; 4],
@@ -53,9 +59,87 @@ pub enum FilteringMethod {
     Ewa,
 }
 
+/// Reads the full resolution image of a `MIPMap` and returns its resolution
+/// and texels.
+pub type TexelLoader<T> = Arc<dyn Fn() -> Result<(Point2<usize>, Vec<T>), String> + Send + Sync>;
+
+/// A level of the image pyramid. Its texels may be dropped to stay within the
+/// texture memory budget and loaded again on demand.
+struct MIPMapLevel<T> {
+    /// Size in s-dimension.
+    u_size: usize,
+
+    /// Size in t-dimension.
+    v_size: usize,
+
+    /// The texels if they are resident.
+    texels: RwLock<Option<Arc<BlockedArray<T>>>>,
+
+    /// Texture memory budget clock value at the last lookup.
+    last_used: AtomicU64,
+}
+
+impl<T> MIPMapLevel<T> {
+    /// Create a new resident `MIPMapLevel`.
+    ///
+    /// * `texels`    - The texels.
+    /// * `last_used` - Texture memory budget clock value.
+    fn new(texels: BlockedArray<T>, last_used: u64) -> Self
+    where
+        T: Copy + Default,
+    {
+        Self {
+            u_size: texels.u_size(),
+            v_size: texels.v_size(),
+            texels: RwLock::new(Some(Arc::new(texels))),
+            last_used: AtomicU64::new(last_used),
+        }
+    }
+
+    /// Returns the resident texels, if any.
+    fn resident(&self) -> Option<Arc<BlockedArray<T>>> {
+        self.texels
+            .read()
+            .expect("Unable to access mipmap level lock")
+            .as_ref()
+            .map(Arc::clone)
+    }
+}
+
+impl<T> Clone for MIPMapLevel<T> {
+    /// Returns a copy of the level sharing the resident texels.
+    fn clone(&self) -> Self {
+        Self {
+            u_size: self.u_size,
+            v_size: self.v_size,
+            texels: RwLock::new(self.resident()),
+            last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Stores the image pyramid of increasingly lower resolution prefiltered
+/// versions of the original image.
+struct Pyramid<T> {
+    /// Image resolution.
+    resolution: Point2<usize>,
+
+    /// The levels with the highest resolution first.
+    levels: Vec<MIPMapLevel<T>>,
+}
+
+impl<T> Clone for Pyramid<T> {
+    /// Returns a copy of the pyramid sharing the resident texels.
+    fn clone(&self) -> Self {
+        Self {
+            resolution: self.resolution,
+            levels: self.levels.clone(),
+        }
+    }
+}
+
 /// Implements methods for efficient texture filtering with spatially varying
 /// filter widths.
-#[derive(Clone)]
 pub struct MIPMap<T> {
     /// MIP-Map method to use.
     filtering_method: FilteringMethod,
@@ -63,12 +147,16 @@ pub struct MIPMap<T> {
     /// Determines how to handle out-of-bounds texels.
     wrap_mode: ImageWrap,
 
-    /// Image resolution.
-    resolution: Point2<usize>,
+    /// The image pyramid. It is built on the first lookup for `MIPMap`s
+    /// created with a `TexelLoader`.
+    pyramid: OnceLock<Pyramid<T>>,
 
-    /// Stores the image pyramid of increasingly lower resolution prefiltered
-    /// versions of the original image.
-    pyramid: Vec<BlockedArray<T>>,
+    /// Reads the image again when levels are dropped to stay within the
+    /// texture memory budget. `None` keeps all levels resident.
+    loader: Option<TexelLoader<T>>,
+
+    /// Ensures only one thread reads the image again at a time.
+    reload_lock: Mutex<()>,
 
     /// Precomputed lookup table of Gaussian filter function values.
     weight_lut: [Float; WEIGHT_LUT_SIZE],
@@ -81,6 +169,21 @@ pub struct MIPMap<T> {
 /// Atomic reference counted `MIPMap`.
 pub type ArcMIPMap<T> = Arc<MIPMap<T>>;
 
+impl<T> Clone for MIPMap<T> {
+    /// Returns a copy of the `MIPMap` sharing the resident texels.
+    fn clone(&self) -> Self {
+        Self {
+            filtering_method: self.filtering_method,
+            wrap_mode: self.wrap_mode,
+            pyramid: self.pyramid.clone(),
+            loader: self.loader.clone(),
+            reload_lock: Mutex::new(()),
+            weight_lut: self.weight_lut,
+            max_anisotropy: self.max_anisotropy,
+        }
+    }
+}
+
 impl<T> MIPMap<T>
 where
     T: Copy
@@ -94,6 +197,8 @@ where
         + AddAssign
         + Clamp<Float>,
 {
+    /// Create a new `MIPMap` from an image.
+    ///
     /// * `resolution`       - Image resolution.
     /// * `img`              - Image data.
     /// * `filtering_method` - MIPMap filtering method to use.
@@ -107,109 +212,53 @@ where
         wrap_mode: ImageWrap,
         max_anisotropy: Float,
     ) -> Self {
-        let mut resampled_image: Vec<T> = vec![];
-
-        let resolution = if !resolution[0].is_power_of_two() || !resolution[1].is_power_of_two() {
-            // Resample image to power-of-two resolution.
-            let res_pow2 = Point2::new(
-                resolution[0].next_power_of_two(),
-                resolution[1].next_power_of_two(),
-            );
-            info!(
-                "Resampling MIPMap from {}x{} to {}x{}",
-                resolution[0], resolution[1], res_pow2[0], res_pow2[1],
-            );
-
-            // Resample image in `s` direction.
-            let s_weights = resample_weights(resolution[0], res_pow2[0]);
-            resampled_image.resize(res_pow2[0] * res_pow2[1], T::default());
-
-            // Apply `s_weights` in the `s` direction.
-            for t in 0..resolution[1] {
-                for s in 0..res_pow2[0] {
-                    // Compute texel `(s, t)` in `s`-zoomed image.
-                    resampled_image[t * res_pow2[0] + s] = T::default(); // Should be zero.
-                    for j in 0..4 {
-                        let orig_s = s_weights[s].first_texel + j;
-                        let orig_s = match wrap_mode {
-                            ImageWrap::Repeat => rem(orig_s, resolution[0]),
-                            ImageWrap::Clamp => clamp(orig_s, 0, resolution[0] - 1),
-                            _ => orig_s,
-                        };
-
-                        if orig_s < resolution[0] {
-                            resampled_image[t * res_pow2[0] + s] +=
-                                img[t * resolution[0] + orig_s] * s_weights[s].weight[j];
-                        }
-                    }
-                }
-            }
-
-            // Resample image in `t` direction.
-            let t_weights = resample_weights(resolution[1], res_pow2[1]);
-
-            // Apply `t_weights` in the `t` direction.
-            let mut work_data = vec![T::default(); res_pow2[1]];
-            for s in 0..res_pow2[0] {
-                for t in 0..res_pow2[1] {
-                    work_data[t] = T::default(); // Should be zero.
-                    for j in 0..4 {
-                        let offset = t_weights[t].first_texel + j;
-                        let offset = match wrap_mode {
-                            ImageWrap::Repeat => rem(offset, resolution[1]),
-                            ImageWrap::Clamp => clamp(offset, 0, resolution[1] - 1),
-                            _ => offset,
-                        };
-
-                        if offset < resolution[1] {
-                            work_data[t] +=
-                                resampled_image[offset * res_pow2[0] + s] * t_weights[t].weight[j];
-                        }
-                    }
-                }
-                for t in 0..res_pow2[1] {
-                    resampled_image[t * res_pow2[0] + s] = work_data[t].clamp_default();
-                }
-            }
-
-            res_pow2
-        } else {
-            *resolution
-        };
-
-        // Initialize levels of MIPMap from image.
-        let n_levels = 1 + Log2::log2(max(resolution[0], resolution[1])) as usize;
-        let mut pyramid: Vec<BlockedArray<T>> = Vec::with_capacity(n_levels);
-
-        // Initialize most detailed level of MIPMap
-        pyramid.push(BlockedArray::from_slice(
-            resolution[0],
-            resolution[1],
-            if resampled_image.len() > 0 {
-                &resampled_image
-            } else {
-                img
-            },
-        ));
+        Self::with_pyramid(
+            OnceLock::from(Pyramid::new(resolution, img, wrap_mode)),
+            None,
+            filtering_method,
+            wrap_mode,
+            max_anisotropy,
+        )
+    }
 
-        for i in 1..n_levels {
-            // Initialize i^th MIPMap level from `i-1` level.
-            let s_res = max(1, pyramid[i - 1].u_size() / 2);
-            let t_res = max(1, pyramid[i - 1].v_size() / 2);
-            pyramid.push(BlockedArray::new(s_res, t_res));
-
-            // Filter four texels from finer level of pyramid.
-            for t in 0..t_res {
-                for s in 0..s_res {
-                    let tx0 = texel(&pyramid, wrap_mode, i - 1, 2 * s, 2 * t);
-                    let tx1 = texel(&pyramid, wrap_mode, i - 1, 2 * s + 1, 2 * t);
-                    let tx2 = texel(&pyramid, wrap_mode, i - 1, 2 * s, 2 * t + 1);
-                    let tx3 = texel(&pyramid, wrap_mode, i - 1, 2 * s + 1, 2 * t + 1);
-                    pyramid[i][(s, t)] = (tx0 + tx1 + tx2 + tx3) * 0.25;
-                }
-            }
-        }
+    /// Create a new `MIPMap` that reads its image on the first lookup. Levels
+    /// may be dropped to stay within the texture memory budget; the image is
+    /// read again when they are needed.
+    ///
+    /// * `loader`           - Reads the image.
+    /// * `filtering_method` - MIPMap filtering method to use.
+    /// * `wrap_mode`        - Determines how to handle out-of-bounds texels.
+    /// * `max_anisotropy`   - Used to clamp the ellipse eccentricity (EWA).
+    ///                        Set to 0 if EWA is not being used.
+    pub fn from_loader(
+        loader: TexelLoader<T>,
+        filtering_method: FilteringMethod,
+        wrap_mode: ImageWrap,
+        max_anisotropy: Float,
+    ) -> Self {
+        Self::with_pyramid(
+            OnceLock::new(),
+            Some(loader),
+            filtering_method,
+            wrap_mode,
+            max_anisotropy,
+        )
+    }
 
+    /// Create a new `MIPMap` with the given pyramid storage.
+    ///
+    /// * `pyramid`          - The image pyramid.
+    /// * `loader`           - Reads the image.
+    /// * `filtering_method` - MIPMap filtering method to use.
+    /// * `wrap_mode`        - Determines how to handle out-of-bounds texels.
+    /// * `max_anisotropy`   - Used to clamp the ellipse eccentricity (EWA).
+    fn with_pyramid(
+        pyramid: OnceLock<Pyramid<T>>,
+        loader: Option<TexelLoader<T>>,
+        filtering_method: FilteringMethod,
+        wrap_mode: ImageWrap,
+        max_anisotropy: Float,
+    ) -> Self {
         // Initialize EWA filter weights.
         let mut weight_lut = [0.0; WEIGHT_LUT_SIZE];
         let alpha = 2.0;
@@ -221,26 +270,143 @@ where
         Self {
             filtering_method,
             wrap_mode,
-            resolution,
             pyramid,
+            loader,
+            reload_lock: Mutex::new(()),
             weight_lut,
             max_anisotropy,
         }
     }
 
+    /// Returns the image pyramid, reading the image if this is the first
+    /// lookup.
+    fn pyramid(&self) -> &Pyramid<T> {
+        if let Some(pyramid) = self.pyramid.get() {
+            return pyramid;
+        }
+
+        let pyramid = self.pyramid.get_or_init(|| {
+            let loader = self.loader.as_ref().expect("MIPMap has no image");
+            let (resolution, img) = match loader() {
+                Ok(image) => image,
+                Err(err) => {
+                    error!("{}", err);
+                    (Point2::new(1, 1), vec![T::default()])
+                }
+            };
+            Pyramid::new(&resolution, &img, self.wrap_mode)
+        });
+        budget::enforce_texture_memory_budget();
+        pyramid
+    }
+
+    /// Returns the texels of a pyramid level, restoring them if they were
+    /// dropped to stay within the texture memory budget. Every lookup marks
+    /// the level as the most recently used.
+    ///
+    /// * `level` - The MIPMap level.
+    fn level(&self, level: usize) -> Arc<BlockedArray<T>> {
+        // Only levels that can be dropped need to track their use.
+        let l = &self.pyramid().levels[level];
+        if self.loader.is_some() {
+            l.last_used.store(budget::next_tick(), Ordering::Relaxed);
+        }
+
+        match l.resident() {
+            Some(texels) => texels,
+            None => self.reload(level),
+        }
+    }
+
+    /// Restores a pyramid level that was dropped. It is resampled from the
+    /// nearest finer level that is resident or, if there is none, from the
+    /// image read again. Other dropped levels stay dropped.
+    ///
+    /// * `level` - The MIPMap level.
+    fn reload(&self, level: usize) -> Arc<BlockedArray<T>> {
+        let texels = {
+            let _guard = self
+                .reload_lock
+                .lock()
+                .expect("Unable to access mipmap reload mutex");
+
+            // Another thread may have restored the level while we waited.
+            let pyramid = self.pyramid();
+            let l = &pyramid.levels[level];
+            if let Some(texels) = l.resident() {
+                return texels;
+            }
+
+            let source = (0..level)
+                .rev()
+                .find_map(|i| pyramid.levels[i].resident().map(|texels| (i, texels)))
+                .or_else(|| {
+                    let loader = self.loader.as_ref().expect("MIPMap has no image");
+                    match loader() {
+                        Ok((resolution, img)) => Some((
+                            0,
+                            Arc::new(Pyramid::base_level(&resolution, &img, self.wrap_mode)),
+                        )),
+                        Err(err) => {
+                            error!("{}", err);
+                            None
+                        }
+                    }
+                });
+
+            let restored = match source {
+                Some((mut i, mut texels)) => {
+                    while i < level {
+                        texels = Arc::new(Pyramid::downsample(&texels, self.wrap_mode));
+                        i += 1;
+                    }
+                    texels
+                }
+                None => Arc::new(BlockedArray::new(l.u_size, l.v_size)),
+            };
+            let restored = if restored.u_size() == l.u_size && restored.v_size() == l.v_size {
+                restored
+            } else {
+                Arc::new(BlockedArray::new(l.u_size, l.v_size))
+            };
+
+            *l.texels
+                .write()
+                .expect("Unable to access mipmap level lock") = Some(Arc::clone(&restored));
+            l.last_used.store(budget::next_tick(), Ordering::Relaxed);
+            restored
+        };
+
+        budget::enforce_texture_memory_budget();
+        texels
+    }
+
     /// Returns the width of the highest resolution level.
     pub fn width(&self) -> usize {
-        self.resolution[0]
+        self.pyramid().resolution[0]
     }
 
     /// Returns the height of the highest resolution level.
     pub fn height(&self) -> usize {
-        self.resolution[1]
+        self.pyramid().resolution[1]
     }
 
     /// Returns the number of MIPMap levels.
     pub fn levels(&self) -> usize {
-        self.pyramid.len()
+        self.pyramid().levels.len()
+    }
+
+    /// Returns the memory used by the resident levels of the image pyramid in
+    /// bytes.
+    pub fn memory(&self) -> usize {
+        self.pyramid.get().map_or(0, |pyramid| {
+            pyramid
+                .levels
+                .iter()
+                .filter_map(MIPMapLevel::resident)
+                .map(|texels| texels.memory())
+                .sum()
+        })
     }
 
     /// Applies the appropriate filter method based on `method` over the texture
@@ -252,13 +418,14 @@ where
     pub fn lookup(&self, st: &Point2f, dst0: &Vector2f, dst1: &Vector2f) -> T {
         match self.filtering_method {
             FilteringMethod::Trilinear => {
-                let width = max(
-                    max(abs(dst0[0]), abs(dst0[1])),
-                    max(abs(dst1[0]), abs(dst1[1])),
-                );
+                let width = 2.0
+                    * max(
+                        max(abs(dst0[0]), abs(dst0[1])),
+                        max(abs(dst1[0]), abs(dst1[1])),
+                    );
                 self.lookup_triangle(st, width)
             }
-            FilteringMethod::Ewa => self.lookup_ewa(st, &dst0, &dst1),
+            FilteringMethod::Ewa => self.lookup_ewa(st, dst0, dst1),
         }
     }
 
@@ -276,7 +443,7 @@ where
         if level < 0.0 {
             self.triangle(0, st)
         } else if level >= (levels - 1) as Float {
-            texel(&self.pyramid, self.wrap_mode, levels - 1, 0, 0)
+            texel(&self.level(levels - 1), self.wrap_mode, 0, 0)
         } else {
             // Do lerp() manually to avoid adding trait bound on T such that
             // `Float: Mul<T, Output=T>` and messing up Float multiplications.
@@ -335,19 +502,21 @@ where
     fn triangle(&self, level: usize, st: &Point2f) -> T {
         let level = clamp(level, 0, self.levels() - 1);
 
-        let s = st[0] * self.pyramid[level].u_size() as Float - 0.5;
-        let t = st[1] * self.pyramid[level].v_size() as Float - 0.5;
+        let l = self.level(level);
 
-        let s0 = s.floor() as usize;
-        let t0 = t.floor() as usize;
+        let s = st[0] * l.u_size() as Float - 0.5;
+        let t = st[1] * l.v_size() as Float - 0.5;
+
+        let s0 = s.floor() as isize;
+        let t0 = t.floor() as isize;
 
         let ds = s - s0 as Float;
         let dt = t - t0 as Float;
 
-        let tx0 = texel(&self.pyramid, self.wrap_mode, level, s0, t0);
-        let tx1 = texel(&self.pyramid, self.wrap_mode, level, s0, t0 + 1);
-        let tx2 = texel(&self.pyramid, self.wrap_mode, level, s0 + 1, t0);
-        let tx3 = texel(&self.pyramid, self.wrap_mode, level, s0 + 1, t0 + 1);
+        let tx0 = texel(&l, self.wrap_mode, s0, t0);
+        let tx1 = texel(&l, self.wrap_mode, s0, t0 + 1);
+        let tx2 = texel(&l, self.wrap_mode, s0 + 1, t0);
+        let tx3 = texel(&l, self.wrap_mode, s0 + 1, t0 + 1);
 
         tx0 * (1.0 - ds) * (1.0 - dt)
             + tx1 * (1.0 - ds) * dt
@@ -366,11 +535,12 @@ where
     fn ewa(&self, level: usize, st: &Point2f, dst0: &Vector2f, dst1: &Vector2f) -> T {
         let levels = self.levels();
         if level >= levels {
-            return texel(&self.pyramid, self.wrap_mode, levels - 1, 0, 0);
+            return texel(&self.level(levels - 1), self.wrap_mode, 0, 0);
         }
 
-        let u_size = self.pyramid[level].u_size();
-        let v_size = self.pyramid[level].v_size();
+        let l = self.level(level);
+        let u_size = l.u_size();
+        let v_size = l.v_size();
 
         // Convert EWA coordinates to appropriate scale for level.
         let st = [st[0] * u_size as Float - 0.5, st[1] * v_size as Float - 0.5];
@@ -391,10 +561,10 @@ where
         let inv_det = 1.0 / det;
         let u_sqrt = (det * c).sqrt();
         let v_sqrt = (a * det).sqrt();
-        let s0 = (st[0] - 2.0 * inv_det * u_sqrt).ceil() as usize;
-        let s1 = (st[0] + 2.0 * inv_det * u_sqrt).floor() as usize;
-        let t0 = (st[1] - 2.0 * inv_det * v_sqrt).ceil() as usize;
-        let t1 = (st[1] + 2.0 * inv_det * v_sqrt).floor() as usize;
+        let s0 = (st[0] - 2.0 * inv_det * u_sqrt).ceil() as isize;
+        let s1 = (st[0] + 2.0 * inv_det * u_sqrt).floor() as isize;
+        let t0 = (st[1] - 2.0 * inv_det * v_sqrt).ceil() as isize;
+        let t1 = (st[1] + 2.0 * inv_det * v_sqrt).floor() as isize;
 
         // Scan over ellipse bound and compute quadratic equation.
         let mut sum = T::default();
@@ -411,7 +581,7 @@ where
                         WEIGHT_LUT_SIZE - 1,
                     );
                     let weight = self.weight_lut[index];
-                    sum += texel(&self.pyramid, self.wrap_mode, level, is, it) * weight;
+                    sum += texel(&l, self.wrap_mode, is, it) * weight;
                     sum_wts += weight;
                 }
             }
@@ -420,6 +590,189 @@ where
     }
 }
 
+impl<T> Pyramid<T>
+where
+    T: Copy
+        + Clone
+        + Default
+        + Mul<Float, Output = T>
+        + MulAssign<Float>
+        + Div<Float, Output = T>
+        + DivAssign<Float>
+        + Add<T, Output = T>
+        + AddAssign
+        + Clamp<Float>,
+{
+    /// Build the image pyramid from an image, resampling it to a power-of-two
+    /// resolution first if needed.
+    ///
+    /// * `resolution` - Image resolution.
+    /// * `img`        - Image data.
+    /// * `wrap_mode`  - Determines how to handle out-of-bounds texels.
+    fn new(resolution: &Point2<usize>, img: &[T], wrap_mode: ImageWrap) -> Self {
+        // Initialize levels of MIPMap from image.
+        let base = Self::base_level(resolution, img, wrap_mode);
+        let resolution = Point2::new(base.u_size(), base.v_size());
+        let n_levels = 1 + Log2::log2(max(resolution[0], resolution[1])) as usize;
+        let mut pyramid: Vec<BlockedArray<T>> = Vec::with_capacity(n_levels);
+        pyramid.push(base);
+        for i in 1..n_levels {
+            // Initialize i^th MIPMap level from `i-1` level.
+            let level = Self::downsample(&pyramid[i - 1], wrap_mode);
+            pyramid.push(level);
+        }
+
+        TEXTURE_MEMORY.add(pyramid.iter().map(|texels| texels.memory()).sum());
+
+        let tick = budget::next_tick();
+        Self {
+            resolution,
+            levels: pyramid
+                .into_iter()
+                .map(|texels| MIPMapLevel::new(texels, tick))
+                .collect(),
+        }
+    }
+
+    /// Returns the most detailed level of the pyramid for an image,
+    /// resampling it to a power-of-two resolution if needed.
+    ///
+    /// * `resolution` - Image resolution.
+    /// * `img`        - Image data.
+    /// * `wrap_mode`  - Determines how to handle out-of-bounds texels.
+    fn base_level(resolution: &Point2<usize>, img: &[T], wrap_mode: ImageWrap) -> BlockedArray<T> {
+        let mut resampled_image: Vec<T> = vec![];
+
+        let resolution = if !resolution[0].is_power_of_two() || !resolution[1].is_power_of_two() {
+            // Resample image to power-of-two resolution.
+            let res_pow2 = Point2::new(
+                resolution[0].next_power_of_two(),
+                resolution[1].next_power_of_two(),
+            );
+            info!(
+                "Resampling MIPMap from {}x{} to {}x{}",
+                resolution[0], resolution[1], res_pow2[0], res_pow2[1],
+            );
+
+            // Resample image in `s` direction.
+            let s_weights = resample_weights(resolution[0], res_pow2[0]);
+            resampled_image.resize(res_pow2[0] * res_pow2[1], T::default());
+
+            // Apply `s_weights` in the `s` direction.
+            for t in 0..resolution[1] {
+                for s in 0..res_pow2[0] {
+                    // Compute texel `(s, t)` in `s`-zoomed image.
+                    resampled_image[t * res_pow2[0] + s] = T::default(); // Should be zero.
+                    for j in 0..4 {
+                        let orig_s = s_weights[s].first_texel + j as isize;
+                        if let Some(orig_s) = wrap_index(orig_s, resolution[0], wrap_mode) {
+                            resampled_image[t * res_pow2[0] + s] +=
+                                img[t * resolution[0] + orig_s] * s_weights[s].weight[j];
+                        }
+                    }
+                }
+            }
+
+            // Resample image in `t` direction.
+            let t_weights = resample_weights(resolution[1], res_pow2[1]);
+
+            // Apply `t_weights` in the `t` direction.
+            let mut work_data = vec![T::default(); res_pow2[1]];
+            for s in 0..res_pow2[0] {
+                for t in 0..res_pow2[1] {
+                    work_data[t] = T::default(); // Should be zero.
+                    for j in 0..4 {
+                        let offset = t_weights[t].first_texel + j as isize;
+                        if let Some(offset) = wrap_index(offset, resolution[1], wrap_mode) {
+                            work_data[t] +=
+                                resampled_image[offset * res_pow2[0] + s] * t_weights[t].weight[j];
+                        }
+                    }
+                }
+                for t in 0..res_pow2[1] {
+                    resampled_image[t * res_pow2[0] + s] = work_data[t].clamp_default();
+                }
+            }
+
+            res_pow2
+        } else {
+            *resolution
+        };
+
+        // Initialize most detailed level of MIPMap
+        BlockedArray::from_slice(
+            resolution[0],
+            resolution[1],
+            if resampled_image.len() > 0 {
+                &resampled_image
+            } else {
+                img
+            },
+        )
+    }
+
+    /// Returns the next coarser pyramid level by filtering four texels of a
+    /// level for each texel.
+    ///
+    /// * `prev`      - The finer level.
+    /// * `wrap_mode` - Determines how to handle out-of-bounds texels.
+    fn downsample(prev: &BlockedArray<T>, wrap_mode: ImageWrap) -> BlockedArray<T> {
+        let s_res = max(1, prev.u_size() / 2);
+        let t_res = max(1, prev.v_size() / 2);
+        let mut level = BlockedArray::new(s_res, t_res);
+
+        // Filter four texels from finer level of pyramid.
+        for t in 0..t_res {
+            for s in 0..s_res {
+                let (s2, t2) = (2 * s as isize, 2 * t as isize);
+                let tx0 = texel(prev, wrap_mode, s2, t2);
+                let tx1 = texel(prev, wrap_mode, s2 + 1, t2);
+                let tx2 = texel(prev, wrap_mode, s2, t2 + 1);
+                let tx3 = texel(prev, wrap_mode, s2 + 1, t2 + 1);
+                level[(s, t)] = (tx0 + tx1 + tx2 + tx3) * 0.25;
+            }
+        }
+        level
+    }
+}
+
+impl<T> budget::EvictableMIPMap for MIPMap<T>
+where
+    T: Copy + Default + Send + Sync,
+{
+    /// Returns the last use and size in bytes of every resident pyramid level
+    /// as `(level, last_used, bytes)`.
+    fn resident_levels(&self) -> Vec<(usize, u64, usize)> {
+        match self.pyramid.get() {
+            Some(pyramid) => pyramid
+                .levels
+                .iter()
+                .enumerate()
+                .filter_map(|(i, l)| {
+                    l.resident()
+                        .map(|texels| (i, l.last_used.load(Ordering::Relaxed), texels.memory()))
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Drops the texels of a pyramid level and returns the bytes released.
+    ///
+    /// * `level` - The MIPMap level.
+    fn evict_level(&self, level: usize) -> usize {
+        match self.pyramid.get() {
+            Some(pyramid) => pyramid.levels[level]
+                .texels
+                .write()
+                .expect("Unable to access mipmap level lock")
+                .take()
+                .map_or(0, |texels| texels.memory()),
+            None => 0,
+        }
+    }
+}
+
 /// Resample the weights for texels at a new resolution of the image size.
 ///
 /// * `old_res` - The old resolution.
@@ -434,7 +787,7 @@ fn resample_weights(old_res: usize, new_res: usize) -> Vec<ResampleWeight> {
         // Compute image resampling weights for i^th texel.
         let center = (i as Float + 0.5) * old_res as Float / new_res as Float;
 
-        wt[i].first_texel = ((center - filterwidth) + 0.5).floor() as usize;
+        wt[i].first_texel = ((center - filterwidth) + 0.5).floor() as isize;
         for j in 0..4 {
             let pos = wt[i].first_texel as Float + j as Float + 0.5;
             wt[i].weight[j] = lanczos((pos - center) / filterwidth, 2.0);
@@ -450,39 +803,102 @@ fn resample_weights(old_res: usize, new_res: usize) -> Vec<ResampleWeight> {
     wt
 }
 
-/// Returns the texel from the MIPMap pyramid level.
+/// Returns the index of a texel accounting for boundary conditions or `None`
+/// if it is outside the image and `wrap_mode` is `ImageWrap::Black`.
 ///
-/// * `pyramid`   - The MIPMap pyramid.
+/// * `i`         - The texel index.
+/// * `size`      - Number of texels.
+/// * `wrap_mode` - The image wrap mode.
+fn wrap_index(i: isize, size: usize, wrap_mode: ImageWrap) -> Option<usize> {
+    let size = size as isize;
+    match wrap_mode {
+        ImageWrap::Repeat => Some(rem(i, size) as usize),
+        ImageWrap::Clamp => Some(clamp(i, 0, size - 1) as usize),
+        ImageWrap::Black => {
+            if i < 0 || i >= size {
+                None
+            } else {
+                Some(i as usize)
+            }
+        }
+    }
+}
+
+/// Returns the texel from a MIPMap pyramid level.
+///
+/// * `l`         - The MIPMap pyramid level.
 /// * `wrap_mode` - The image wrap mode.
-/// * `level`     - MIPMap Level.
 /// * `s`         - s-index.
 /// * `t`         - t-index.
-fn texel<T>(
-    pyramid: &[BlockedArray<T>],
-    wrap_mode: ImageWrap,
-    level: usize,
-    s: usize,
-    t: usize,
-) -> T
+fn texel<T>(l: &BlockedArray<T>, wrap_mode: ImageWrap, s: isize, t: isize) -> T
 where
     T: Copy + Default,
 {
-    assert!(level < pyramid.len());
+    // Compute texel `(s, t)` accounting for boundary conditions.
+    match (
+        wrap_index(s, l.u_size(), wrap_mode),
+        wrap_index(t, l.v_size(), wrap_mode),
+    ) {
+        (Some(s), Some(t)) => l[(s, t)],
+        _ => T::default(),
+    }
+}
 
-    let l = &pyramid[level];
-    let u_size = l.u_size();
-    let v_size = l.v_size();
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
 
-    // Compute texel `(s, t)` accounting for boundary conditions.
-    match wrap_mode {
-        ImageWrap::Repeat => l[(rem(s, u_size), rem(t, v_size))],
-        ImageWrap::Clamp => l[(clamp(s, 0, u_size - 1), clamp(t, 0, v_size - 1))],
-        ImageWrap::Black => {
-            if s >= u_size || t >= v_size {
-                T::default()
-            } else {
-                l[(s, t)]
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a 2x2 `MIPMap` with texels 1, 2, 3 and 4.
+    fn mipmap(wrap_mode: ImageWrap, filtering_method: FilteringMethod) -> MIPMap<Float> {
+        MIPMap::new(
+            &Point2::new(2, 2),
+            &[1.0, 2.0, 3.0, 4.0],
+            filtering_method,
+            wrap_mode,
+            8.0,
+        )
+    }
+
+    #[test]
+    fn triangle_wraps_texels_before_the_origin() {
+        let st = Point2f::new(0.0, 0.0);
+        let repeat = mipmap(ImageWrap::Repeat, FilteringMethod::Trilinear);
+        assert_eq!(repeat.lookup_triangle(&st, 0.0), 2.5);
+        let clamp = mipmap(ImageWrap::Clamp, FilteringMethod::Trilinear);
+        assert_eq!(clamp.lookup_triangle(&st, 0.0), 1.0);
+        let black = mipmap(ImageWrap::Black, FilteringMethod::Trilinear);
+        assert_eq!(black.lookup_triangle(&st, 0.0), 0.25);
+    }
+
+    #[test]
+    fn trilinear_filter_width_spans_both_differentials() {
+        // Differentials of one texel on each side of the lookup span the
+        // whole image and select the coarsest level.
+        let m = mipmap(ImageWrap::Repeat, FilteringMethod::Trilinear);
+        let st = Point2f::new(0.25, 0.25);
+        let dst = Vector2f::new(0.5, 0.0);
+        assert_eq!(m.lookup(&st, &dst, &dst), 2.5);
+        assert_eq!(
+            m.lookup(&st, &Vector2f::default(), &Vector2f::default()),
+            1.0
+        );
+    }
+
+    #[test]
+    fn ewa_averages_constant_image() {
+        let m = MIPMap::new(
+            &Point2::new(8, 8),
+            &[0.5; 64],
+            FilteringMethod::Ewa,
+            ImageWrap::Repeat,
+            8.0,
+        );
+        let st = Point2f::new(0.01, 0.02);
+        let value = m.lookup(&st, &Vector2f::new(0.2, 0.05), &Vector2f::new(-0.01, 0.03));
+        assert!((value - 0.5).abs() < 1e-5);
     }
 }
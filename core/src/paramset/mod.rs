@@ -30,6 +30,8 @@ pub struct ParamSet {
     pub vector3fs: ParamSetMap<Vector3f>,
     pub normal3fs: ParamSetMap<Normal3f>,
     pub spectra: ParamSetMap<Spectrum>,
+    /// RGB and XYZ spectra in `spectra` converted as illuminants.
+    pub illuminants: ParamSetMap<Spectrum>,
     pub strings: ParamSetMap<String>,
     pub textures: ParamSetMap<String>,
     pub cached_spectra: HashMap<String, Spectrum>,
@@ -138,6 +140,7 @@ impl ParamSet {
             vector3fs: HashMap::new(),
             normal3fs: HashMap::new(),
             spectra: HashMap::new(),
+            illuminants: HashMap::new(),
             strings: HashMap::new(),
             textures: HashMap::new(),
             cached_spectra: HashMap::new(),
@@ -194,10 +197,31 @@ impl ParamSet {
     paramset_find!(find_texture, String, textures);
     paramset_add!(add_texture, String, textures);
 
-    paramset_erase!(erase_spectrum, spectra);
     paramset_find_one!(find_one_spectrum, Spectrum, spectra);
     paramset_find!(find_spectrum, Spectrum, spectra);
 
+    /// Remove a spectrum. Returns `true` if it existed.
+    ///
+    /// * `name` - Parameter name.
+    pub fn erase_spectrum(&mut self, name: &str) -> bool {
+        self.illuminants.remove(name);
+        self.spectra.remove(name).is_some()
+    }
+
+    /// Finds a spectrum describing emitted light. RGB and XYZ values are
+    /// converted as illuminants so that white stays white when rendering
+    /// with `SampledSpectrum`; other spectra are used as given.
+    ///
+    /// * `name`    - Parameter name.
+    /// * `default` - Default value.
+    pub fn find_one_illuminant(&self, name: &str, default: Spectrum) -> Spectrum {
+        match self.illuminants.get(name) {
+            Some(param) if param.values.len() == 1 => param.values[0],
+            Some(_) => default,
+            None => self.find_one_spectrum(name, default),
+        }
+    }
+
     /// Add/replace an RGB spectrum.
     ///
     /// * `name`   - Parameter name.
@@ -206,14 +230,19 @@ impl ParamSet {
         let n = values.len();
         assert!(n % 3 == 0, "RGB spectrum values % 3 != 0");
 
-        self.spectra.insert(
+        let spectra = |spectrum_type| {
+            (0..n)
+                .step_by(3)
+                .map(|i| {
+                    Spectrum::from_rgb(&[values[i], values[i + 1], values[i + 2]], spectrum_type)
+                })
+                .collect()
+        };
+        self.spectra
+            .insert(String::from(name), ParamSetItem::new(spectra(None)));
+        self.illuminants.insert(
             String::from(name),
-            ParamSetItem::new(
-                (0..n)
-                    .step_by(3)
-                    .map(|i| Spectrum::from_rgb(&[values[i], values[i + 1], values[i + 2]], None))
-                    .collect(),
-            ),
+            ParamSetItem::new(spectra(Some(SpectrumType::Illuminant))),
         );
     }
 
@@ -225,22 +254,33 @@ impl ParamSet {
         let n = values.len();
         assert!(n % 3 == 0, "XYZ spectrum values % 3 != 0");
 
-        self.spectra.insert(
+        let spectra = |spectrum_type| {
+            (0..n)
+                .step_by(3)
+                .map(|i| {
+                    Spectrum::from_xyz(&[values[i], values[i + 1], values[i + 2]], spectrum_type)
+                })
+                .collect()
+        };
+        self.spectra
+            .insert(String::from(name), ParamSetItem::new(spectra(None)));
+        self.illuminants.insert(
             String::from(name),
-            ParamSetItem::new(
-                (0..n)
-                    .step_by(3)
-                    .map(|i| Spectrum::from_xyz(&[values[i], values[i + 1], values[i + 2]], None))
-                    .collect(),
-            ),
+            ParamSetItem::new(spectra(Some(SpectrumType::Illuminant))),
         );
     }
 
     /// Add/replace a blackbody spectrum.
     ///
     /// * `name`   - Parameter name.
-    /// * `values` - List of (temperature (Kelvin), scale) values in a linear array.
+    /// * `values` - List of (temperature (Kelvin), scale) values in a linear
+    ///              array or a single temperature with a scale of 1.
     pub fn add_blackbody_spectrum(&mut self, name: &str, values: &[Float]) {
+        let values = if values.len() == 1 {
+            vec![values[0], 1.0]
+        } else {
+            values.to_vec()
+        };
         let n = values.len();
         assert!(n % 2 == 0, "Blackbody spectrum values % 2 != 0");
 
@@ -259,6 +299,7 @@ impl ParamSet {
             })
             .collect();
 
+        self.illuminants.remove(name);
         self.spectra
             .insert(String::from(name), ParamSetItem::new(spectra));
     }
@@ -270,18 +311,25 @@ impl ParamSet {
     pub fn add_sampled_spectrum(&mut self, name: &str, values: &[Float]) {
         let samples = Sample::list(values);
         let spectra = vec![Spectrum::from(&samples)];
+        self.illuminants.remove(name);
         self.spectra
             .insert(String::from(name), ParamSetItem::new(spectra));
     }
 
-    /// Add/replace a spectra from files.
+    /// Add/replace a spectra from files or built-in named spectra.
     ///
     /// * `name`  - Parameter name.
-    /// * `paths` - List of paths to the data files.
+    /// * `paths` - List of paths to the data files or names of spectra
+    ///             (e.g. "glass-BK7", "metal-Au-eta").
     pub fn add_sampled_spectrum_files(&mut self, name: &str, paths: &[String]) {
         let mut spectra: Vec<Spectrum> = vec![];
 
         for path in paths {
+            if let Some(spectrum) = get_named_spectrum(path) {
+                spectra.push(spectrum);
+                continue;
+            }
+
             match absolute_path(path) {
                 Ok(abs_path) => {
                     if let Some(spectrum) = self.cached_spectra.get(&abs_path) {
@@ -313,6 +361,7 @@ impl ParamSet {
             }
         }
 
+        self.illuminants.remove(name);
         self.spectra
             .insert(String::from(name), ParamSetItem::new(spectra));
     }
@@ -341,6 +390,7 @@ impl ParamSet {
         self.vector3fs.clear();
         self.normal3fs.clear();
         self.spectra.clear();
+        self.illuminants.clear();
         self.strings.clear();
         self.textures.clear();
         self.cached_spectra.clear();
@@ -364,3 +414,30 @@ impl fmt::Display for ParamSet {
         Ok(())
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_and_blackbody_spectra() {
+        let mut params = ParamSet::new();
+        params.add_sampled_spectrum_files("eta", &[String::from("metal-Au-eta")]);
+        params.add_blackbody_spectrum("L", &[6500.0]);
+        params.add_blackbody_spectrum("I", &[6500.0, 2.0]);
+
+        let eta = params.find_one_spectrum("eta", Spectrum::new(0.0));
+        let expected = get_named_spectrum("metal-Au-eta").unwrap();
+        assert_eq!(eta.samples(), expected.samples());
+
+        // A single temperature has unit scale.
+        let l = params.find_one_illuminant("L", Spectrum::new(0.0));
+        let i = params.find_one_illuminant("I", Spectrum::new(0.0));
+        assert!(l.y() > 0.0);
+        assert!((i.y() - 2.0 * l.y()).abs() < 1e-5);
+    }
+}
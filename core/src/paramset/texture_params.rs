@@ -1,7 +1,8 @@
 //! Texture Parameters
 
 use super::*;
-use crate::texture::{FloatTextureMap, SpectrumTextureMap};
+use crate::mipmap::*;
+use crate::texture::{ConstantTexture, FloatTextureMap, SpectrumTextureMap};
 use std::sync::Arc;
 
 /// Stores texture, geometry and material parameters of different types in hashmaps.
@@ -52,14 +53,37 @@ impl TextureParams {
         }
     }
 
-    /// Returns a floating point texture.
+    /// Returns a floating point texture for a parameter. A `texture`
+    /// parameter selects a named texture and a `float` parameter gives a
+    /// constant value. Shape parameters take precedence over material
+    /// parameters. Returns `None` if neither is given.
     ///
     /// * `name` - Parameter name.
     pub fn get_float_texture(&self, name: &str) -> Option<ArcTexture<Float>> {
-        self.float_textures.get(&String::from(name)).cloned()
+        let tex_name = self.find_texture(name, String::new());
+        if !tex_name.is_empty() {
+            match self.float_textures.get(&tex_name) {
+                Some(tex) => return Some(Arc::clone(tex)),
+                None => error!(
+                    "Couldn't find float texture named '{}' for parameter '{}'.",
+                    tex_name, name
+                ),
+            }
+        }
+
+        let values = self.geom_params.find_float(name);
+        let values = if values.is_empty() {
+            self.mat_params.find_float(name)
+        } else {
+            values
+        };
+        values
+            .first()
+            .map(|v| Arc::new(ConstantTexture::new(*v)) as ArcTexture<Float>)
     }
 
-    /// Returns a floating point texture or a default texture if not found.
+    /// Returns a floating point texture for a parameter or a default texture
+    /// if neither a `texture` nor a `float` parameter is given.
     ///
     /// * `name`    - Parameter name.
     /// * `default` - Default texture.
@@ -68,19 +92,40 @@ impl TextureParams {
         name: &str,
         default: ArcTexture<Float>,
     ) -> ArcTexture<Float> {
-        self.float_textures
-            .get(&String::from(name))
-            .map_or(Arc::clone(&default), |v| Arc::clone(&v))
+        self.get_float_texture(name).unwrap_or(default)
     }
 
-    /// Returns a spectrum point texture.
+    /// Returns a spectrum texture for a parameter. A `texture` parameter
+    /// selects a named texture and a spectrum parameter such as `rgb` gives
+    /// a constant value. Shape parameters take precedence over material
+    /// parameters. Returns `None` if neither is given.
     ///
     /// * `name` - Parameter name.
     pub fn get_spectrum_texture(&self, name: &str) -> Option<ArcTexture<Spectrum>> {
-        self.spectrum_textures.get(&String::from(name)).cloned()
+        let tex_name = self.find_texture(name, String::new());
+        if !tex_name.is_empty() {
+            match self.spectrum_textures.get(&tex_name) {
+                Some(tex) => return Some(Arc::clone(tex)),
+                None => error!(
+                    "Couldn't find spectrum texture named '{}' for parameter '{}'.",
+                    tex_name, name
+                ),
+            }
+        }
+
+        let values = self.geom_params.find_spectrum(name);
+        let values = if values.is_empty() {
+            self.mat_params.find_spectrum(name)
+        } else {
+            values
+        };
+        values
+            .first()
+            .map(|v| Arc::new(ConstantTexture::new(*v)) as ArcTexture<Spectrum>)
     }
 
-    /// Returns a spectrum point texture or a default texture if not found.
+    /// Returns a spectrum texture for a parameter or a default texture if
+    /// neither a `texture` nor a spectrum parameter is given.
     ///
     /// * `name`    - Parameter name.
     /// * `default` - Default texture.
@@ -89,9 +134,35 @@ impl TextureParams {
         name: &str,
         default: ArcTexture<Spectrum>,
     ) -> ArcTexture<Spectrum> {
-        self.spectrum_textures
-            .get(&String::from(name))
-            .map_or(Arc::clone(&default), |v| Arc::clone(&v))
+        self.get_spectrum_texture(name).unwrap_or(default)
+    }
+
+    /// Returns the tangent space normal map given by the image file in the
+    /// `normalmap` parameter. Returns `None` if the parameter is not given
+    /// or the image can't be read.
+    pub fn get_normal_map(&self) -> Option<ArcMIPMap<RGBSpectrum>> {
+        let path = self.find_filename("normalmap", String::new());
+        if path.is_empty() {
+            return None;
+        }
+
+        // Texels are encoded directions rather than colours so they are not
+        // gamma corrected.
+        let tex_info = TexInfo::new(
+            &path,
+            FilteringMethod::Trilinear,
+            ImageWrap::Repeat,
+            1.0,
+            false,
+            0.0,
+        );
+        match MIPMapCache::get(tex_info) {
+            Ok(mipmap) => Some(mipmap),
+            Err(err) => {
+                error!("Unable to load normal map: {}", err);
+                None
+            }
+        }
     }
 
     texture_params_find!(find_float, Float, find_one_float);
@@ -103,6 +174,7 @@ impl TextureParams {
     texture_params_find!(find_vector3f, Vector3f, find_one_vector3f);
     texture_params_find!(find_normal3f, Normal3f, find_one_normal3f);
     texture_params_find!(find_spectrum, Spectrum, find_one_spectrum);
+    texture_params_find!(find_texture, String, find_one_texture);
 }
 
 impl Default for TextureParams {
@@ -116,3 +188,52 @@ impl Default for TextureParams {
         )
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texture_params_resolve_textures_and_constants() {
+        let checks: ArcTexture<Float> = Arc::new(ConstantTexture::new(0.5));
+        let dots: ArcTexture<Float> = Arc::new(ConstantTexture::new(0.25));
+        let mut float_textures = HashMap::new();
+        float_textures.insert(String::from("checks"), Arc::clone(&checks));
+        float_textures.insert(String::from("dots"), Arc::clone(&dots));
+
+        let mut mat_params = ParamSet::new();
+        mat_params.add_texture("roughness", &[String::from("checks")]);
+        mat_params.add_float("sigma", &[10.0]);
+        mat_params.add_texture("bumpmap", &[String::from("missing")]);
+        let mut geom_params = ParamSet::new();
+        geom_params.add_texture("roughness", &[String::from("dots")]);
+        geom_params.add_float("sigma", &[20.0]);
+
+        // Textures given by name are found in the texture map.
+        let tp = TextureParams::new(
+            ParamSet::new(),
+            mat_params.clone(),
+            float_textures.clone(),
+            HashMap::new(),
+        );
+        assert!(Arc::ptr_eq(
+            &tp.get_float_texture("roughness").unwrap(),
+            &checks
+        ));
+        assert!(tp.get_float_texture("sigma").is_some());
+        assert!(tp.get_float_texture("bumpmap").is_none());
+        assert!(tp.get_spectrum_texture("Kd").is_none());
+
+        // Shape parameters override material parameters.
+        let tp = TextureParams::new(geom_params, mat_params, float_textures, HashMap::new());
+        assert!(Arc::ptr_eq(
+            &tp.get_float_texture("roughness").unwrap(),
+            &dots
+        ));
+        assert!(tp.get_float_texture("sigma").is_some());
+    }
+}
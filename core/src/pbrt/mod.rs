@@ -1,12 +1,10 @@
 //! PBRT common stuff
+//!
+//! The numeric types, constants and functions are defined in the `pbrt-math`
+//! crate and re-exported here with the renderer's scene scale.
 
-mod axis;
-mod clamp;
-mod common;
-mod log2;
+mod scene_scale;
 
 // Re-export
-pub use axis::*;
-pub use clamp::*;
-pub use common::*;
-pub use log2::*;
+pub use pbrt_math::pbrt::*;
+pub use scene_scale::*;
@@ -0,0 +1,69 @@
+//! Scene Scale
+
+use super::*;
+
+/// Scene radii in this range are rendered without scaling tolerances when the
+/// scene does not declare a scale.
+pub const NOMINAL_SCENE_RADIUS_RANGE: (Float, Float) = (1e-2, 1e4);
+
+/// Returns a scene scale derived from the radius of the world bounds if it is
+/// outside the nominal range; otherwise `None`. The scale is the factor by
+/// which the radius exceeds the nearest end of the range.
+///
+/// The scene scale is the size of a nominal unit in scene units. Absolute
+/// tolerances are chosen for scenes modelled in units of roughly a metre and
+/// are multiplied by it so they stay proportional in millimetre or kilometre
+/// scale scenes.
+///
+/// * `radius` - Radius of the world bounding sphere.
+pub fn scene_scale_from_radius(radius: Float) -> Option<Float> {
+    let (lo, hi) = NOMINAL_SCENE_RADIUS_RANGE;
+    if radius.is_finite() && radius > 0.0 && (radius < lo || radius > hi) {
+        Some(radius / clamp(radius, lo, hi))
+    } else {
+        None
+    }
+}
+
+/// Returns the scale used for tolerances of an object created before the
+/// world bounds are known. This is the declared scene scale if there is one;
+/// otherwise the scale derived from the radius of the object's own bounds.
+///
+/// * `declared` - Scene scale declared with `Option "float scenescale"`.
+/// * `radius`   - Radius of the object's bounding sphere.
+pub fn object_scene_scale(declared: Option<Float>, radius: Float) -> Float {
+    declared
+        .or_else(|| scene_scale_from_radius(radius))
+        .unwrap_or(1.0)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nominal_scenes_are_not_scaled() {
+        assert_eq!(scene_scale_from_radius(1.0), None);
+        assert_eq!(scene_scale_from_radius(5000.0), None);
+        assert_eq!(scene_scale_from_radius(0.0), None);
+        assert_eq!(scene_scale_from_radius(INFINITY), None);
+    }
+
+    #[test]
+    fn extreme_scenes_are_scaled() {
+        assert!((scene_scale_from_radius(1e-3).unwrap() - 0.1).abs() < 1e-6);
+        assert!((scene_scale_from_radius(1e6).unwrap() - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn declared_scale_overrides_object_bounds() {
+        assert_eq!(object_scene_scale(Some(1000.0), 1.0), 1000.0);
+        assert_eq!(object_scene_scale(Some(1.0), 1e6), 1.0);
+        assert_eq!(object_scene_scale(None, 1.0), 1.0);
+        assert!((object_scene_scale(None, 1e-3) - 0.1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,492 @@
+//! Plugins
+//!
+//! Shapes, materials and integrators can be provided by dynamic libraries
+//! that are loaded at runtime. A plugin exports a `PluginDeclaration` named
+//! `pbr_plugin_declaration`, usually with the `export_plugin!` macro, whose
+//! `register` function adds factories to a `PluginRegistrar`. Scene files
+//! then refer to them by name like any built-in type. Built-in types take
+//! precedence over plugins with the same name.
+//!
+//! This is a Rust-ABI-only plugin system. There is no C API: plugins are
+//! Rust crates built as `cdylib`s against this crate and they exchange Rust
+//! trait objects with the renderer, so they must be built with the same
+//! compiler, the same version of this crate and the same features. Only the
+//! header at the start of the declaration is C-compatible. It holds the ABI
+//! version, a hash of the layout of the types passed to factories and the
+//! compiler and crate versions, and it is checked before the Rust `register`
+//! function is called so mismatched plugins are rejected instead of
+//! crashing.
+//!
+//! Loading libraries requires the `rust-plugins` feature.
+
+#![allow(dead_code)]
+use crate::camera::*;
+use crate::geometry::*;
+use crate::integrator::*;
+use crate::material::*;
+use crate::paramset::*;
+use crate::pbrt::*;
+use crate::sampler::*;
+use crate::spectrum::*;
+use std::collections::HashMap;
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::mem::{align_of, size_of};
+use std::os::raw::c_char;
+use std::path::Path;
+use std::sync::RwLock;
+
+lazy_static! {
+    /// The global plugin registry.
+    pub static ref PLUGINS: RwLock<PluginRegistry> = RwLock::new(PluginRegistry::new());
+}
+
+/// Version of the plugin interface. Incremented whenever the layout of
+/// `PluginDeclaration` or the factory signatures change.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+
+/// Version of this crate which plugins must be built against. It is
+/// terminated by a nul character so it can be stored in the header.
+pub const CORE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Version of the compiler that built this crate, terminated by a nul
+/// character.
+pub const RUSTC_VERSION: &str = concat!(env!("PBR_RUSTC_VERSION"), "\0");
+
+/// Hash of the sizes and alignments of the types passed to factories. They
+/// change with the `sampled-spectrum` and `polarization` features.
+pub const PLUGIN_LAYOUT_HASH: u64 = layout_hash(&[
+    (size_of::<Float>(), align_of::<Float>()),
+    (size_of::<Spectrum>(), align_of::<Spectrum>()),
+    (size_of::<Transform>(), align_of::<Transform>()),
+    (size_of::<Ray>(), align_of::<Ray>()),
+    (size_of::<Bounds3f>(), align_of::<Bounds3f>()),
+    (
+        size_of::<SurfaceInteraction<'static>>(),
+        align_of::<SurfaceInteraction<'static>>(),
+    ),
+    (size_of::<ParamSet>(), align_of::<ParamSet>()),
+    (size_of::<TextureParams>(), align_of::<TextureParams>()),
+    (
+        size_of::<PluginDeclaration>(),
+        align_of::<PluginDeclaration>(),
+    ),
+]);
+
+/// Name of the symbol holding the `PluginDeclaration` of a plugin.
+pub const PLUGIN_DECLARATION_SYMBOL: &str = "pbr_plugin_declaration";
+
+/// Creates shapes from a parameter set, the object to world and world to
+/// object transformations and whether to reverse the surface orientation.
+pub type ShapeFactory =
+    fn(&ParamSet, ArcTransform, ArcTransform, bool) -> Result<Vec<ArcShape>, String>;
+
+/// Creates a material from a parameter set.
+pub type MaterialFactory = fn(&TextureParams) -> Result<ArcMaterial, String>;
+
+/// Creates an integrator from a parameter set, sampler and camera.
+pub type IntegratorFactory = fn(&ParamSet, ArcSampler, ArcCamera) -> Result<ArcIntegrator, String>;
+
+/// Describes a plugin. The fields before `register` form a C-compatible
+/// header whose layout does not depend on the compiler. The ABI version is
+/// the first field so that it can be checked before anything else is read.
+#[repr(C)]
+pub struct PluginDeclaration {
+    /// Version of the plugin interface the plugin was built with.
+    pub abi_version: u32,
+
+    /// Layout hash of the types the plugin was built with.
+    pub layout_hash: u64,
+
+    /// Nul terminated version of the compiler the plugin was built with.
+    pub rustc_version: *const c_char,
+
+    /// Nul terminated version of this crate the plugin was built with.
+    pub core_version: *const c_char,
+
+    /// Registers the plugin's factories.
+    pub register: fn(registrar: &mut dyn PluginRegistrar),
+}
+
+// The version strings are static and never written.
+unsafe impl Sync for PluginDeclaration {}
+
+impl PluginDeclaration {
+    /// Returns an error if the plugin was not built with the same interface,
+    /// types, compiler and crate version as the renderer.
+    pub fn check(&self) -> Result<(), String> {
+        if self.abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "Plugin ABI version {} does not match {}",
+                self.abi_version, PLUGIN_ABI_VERSION
+            ));
+        }
+        if self.layout_hash != PLUGIN_LAYOUT_HASH {
+            return Err(String::from(
+                "Plugin was built with different types. Check the enabled features.",
+            ));
+        }
+
+        let rustc_version = c_str(self.rustc_version);
+        if rustc_version != RUSTC_VERSION.trim_end_matches('\0') {
+            return Err(format!(
+                "Plugin was built with '{}' instead of '{}'",
+                rustc_version,
+                RUSTC_VERSION.trim_end_matches('\0')
+            ));
+        }
+        let core_version = c_str(self.core_version);
+        if core_version != CORE_VERSION.trim_end_matches('\0') {
+            return Err(format!(
+                "Plugin was built against core {} instead of {}",
+                core_version,
+                CORE_VERSION.trim_end_matches('\0')
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the text of a nul terminated string in a plugin header.
+///
+/// * `s` - The string.
+fn c_str(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+    }
+}
+
+/// Returns the FNV-1a hash of the sizes and alignments of types.
+///
+/// * `layouts` - Size and alignment of each type.
+const fn layout_hash(layouts: &[(usize, usize)]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < layouts.len() {
+        hash = (hash ^ layouts[i].0 as u64).wrapping_mul(0x100000001b3);
+        hash = (hash ^ layouts[i].1 as u64).wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Interface used by plugins to register their factories by name.
+pub trait PluginRegistrar {
+    /// Register a shape.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the shapes.
+    fn register_shape(&mut self, name: &str, factory: ShapeFactory);
+
+    /// Register a material.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the material.
+    fn register_material(&mut self, name: &str, factory: MaterialFactory);
+
+    /// Register an integrator.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the integrator.
+    fn register_integrator(&mut self, name: &str, factory: IntegratorFactory);
+}
+
+/// Declares the `PluginDeclaration` of a plugin.
+///
+/// * `register` - Function that registers the plugin's factories.
+#[macro_export]
+macro_rules! export_plugin {
+    ($register:expr) => {
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static pbr_plugin_declaration: $crate::plugin::PluginDeclaration =
+            $crate::plugin::PluginDeclaration {
+                abi_version: $crate::plugin::PLUGIN_ABI_VERSION,
+                layout_hash: $crate::plugin::PLUGIN_LAYOUT_HASH,
+                rustc_version: $crate::plugin::RUSTC_VERSION.as_ptr() as *const _,
+                core_version: $crate::plugin::CORE_VERSION.as_ptr() as *const _,
+                register: $register,
+            };
+    };
+}
+
+/// Stores the factories registered by plugins.
+#[derive(Default)]
+pub struct PluginRegistry {
+    /// Shape factories by name.
+    shapes: HashMap<String, ShapeFactory>,
+
+    /// Material factories by name.
+    materials: HashMap<String, MaterialFactory>,
+
+    /// Integrator factories by name.
+    integrators: HashMap<String, IntegratorFactory>,
+
+    /// Paths of the loaded libraries.
+    paths: Vec<String>,
+
+    /// The loaded libraries. They are never unloaded because the factories
+    /// point into them.
+    libraries: Vec<Library>,
+
+    /// Plugin being registered, used to report name conflicts.
+    current: String,
+}
+
+impl PluginRegistry {
+    /// Returns an empty `PluginRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the factory for a shape.
+    ///
+    /// * `name` - Name of the shape.
+    pub fn shape(&self, name: &str) -> Option<ShapeFactory> {
+        self.shapes.get(name).copied()
+    }
+
+    /// Returns the factory for a material.
+    ///
+    /// * `name` - Name of the material.
+    pub fn material(&self, name: &str) -> Option<MaterialFactory> {
+        self.materials.get(name).copied()
+    }
+
+    /// Returns the factory for an integrator.
+    ///
+    /// * `name` - Name of the integrator.
+    pub fn integrator(&self, name: &str) -> Option<IntegratorFactory> {
+        self.integrators.get(name).copied()
+    }
+
+    /// Load the plugins in the given paths. Directories are searched for
+    /// dynamic libraries. Libraries that were already loaded are skipped and
+    /// failures are logged.
+    ///
+    /// * `paths` - Paths to directories or libraries.
+    pub fn load_all(&mut self, paths: &[String]) {
+        let ext = std::env::consts::DLL_EXTENSION;
+
+        for path in paths.iter() {
+            let mut libs: Vec<String> = vec![];
+            if Path::new(path).is_dir() {
+                match fs::read_dir(path) {
+                    Ok(entries) => {
+                        libs = entries
+                            .filter_map(|entry| entry.ok().map(|e| e.path()))
+                            .filter(|p| p.extension() == Some(OsStr::new(ext)))
+                            .map(|p| p.to_string_lossy().into_owned())
+                            .collect();
+                        libs.sort();
+                    }
+                    Err(err) => error!("Unable to read plugin directory '{}': {}", path, err),
+                }
+            } else {
+                libs.push(path.clone());
+            }
+
+            for lib in libs.iter() {
+                if !self.paths.contains(lib) {
+                    if let Err(err) = self.load(lib) {
+                        error!("Unable to load plugin '{}': {}", lib, err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Load a plugin and register its factories.
+    ///
+    /// * `path` - Path to the dynamic library.
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let library = Library::open(path)?;
+        let declaration = library.declaration()?;
+        declaration.check()?;
+
+        self.current = path.to_string();
+        (declaration.register)(self);
+        info!("Loaded plugin '{}'.", path);
+
+        self.paths.push(path.to_string());
+        self.libraries.push(library);
+        Ok(())
+    }
+}
+
+impl PluginRegistrar for PluginRegistry {
+    /// Register a shape.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the shapes.
+    fn register_shape(&mut self, name: &str, factory: ShapeFactory) {
+        if self.shapes.insert(name.to_string(), factory).is_some() {
+            warn!("Shape '{}' redefined by plugin '{}'.", name, self.current);
+        }
+    }
+
+    /// Register a material.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the material.
+    fn register_material(&mut self, name: &str, factory: MaterialFactory) {
+        if self.materials.insert(name.to_string(), factory).is_some() {
+            warn!(
+                "Material '{}' redefined by plugin '{}'.",
+                name, self.current
+            );
+        }
+    }
+
+    /// Register an integrator.
+    ///
+    /// * `name`    - Name used in scene files.
+    /// * `factory` - Creates the integrator.
+    fn register_integrator(&mut self, name: &str, factory: IntegratorFactory) {
+        if self.integrators.insert(name.to_string(), factory).is_some() {
+            warn!(
+                "Integrator '{}' redefined by plugin '{}'.",
+                name, self.current
+            );
+        }
+    }
+}
+
+/// Handle to a dynamic library opened with `dlopen`.
+struct Library {
+    /// The handle.
+    #[cfg(all(feature = "rust-plugins", unix))]
+    handle: *mut libc::c_void,
+}
+
+// The handle is only used to look up symbols, which `dlsym` allows from any
+// thread.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+#[cfg(all(feature = "rust-plugins", unix))]
+impl Library {
+    /// Open a dynamic library.
+    ///
+    /// * `path` - Path to the library.
+    fn open(path: &str) -> Result<Self, String> {
+        let c_path = std::ffi::CString::new(path).map_err(|err| format!("{}", err))?;
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            Err(dl_error())
+        } else {
+            Ok(Self { handle })
+        }
+    }
+
+    /// Returns the `PluginDeclaration` exported by the library.
+    fn declaration(&self) -> Result<&'static PluginDeclaration, String> {
+        let symbol = std::ffi::CString::new(PLUGIN_DECLARATION_SYMBOL).unwrap();
+        let ptr = unsafe { libc::dlsym(self.handle, symbol.as_ptr()) };
+        if ptr.is_null() {
+            Err(dl_error())
+        } else {
+            // The library stays loaded for the lifetime of the process.
+            Ok(unsafe { &*(ptr as *const PluginDeclaration) })
+        }
+    }
+}
+
+/// Returns the last error reported by `dlopen` or `dlsym`.
+#[cfg(all(feature = "rust-plugins", unix))]
+fn dl_error() -> String {
+    let err = unsafe { libc::dlerror() };
+    if err.is_null() {
+        String::from("Unknown error")
+    } else {
+        unsafe { std::ffi::CStr::from_ptr(err) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(not(all(feature = "rust-plugins", unix)))]
+impl Library {
+    /// Reports that plugins are not supported.
+    ///
+    /// * `_path` - Path to the library.
+    fn open(_path: &str) -> Result<Self, String> {
+        if cfg!(feature = "rust-plugins") {
+            Err(String::from(
+                "Plugins are only supported on Unix-like systems.",
+            ))
+        } else {
+            Err(String::from(
+                "Plugins are not supported. Rebuild with the 'rust-plugins' feature to enable them.",
+            ))
+        }
+    }
+
+    /// Returns the `PluginDeclaration` exported by the library.
+    fn declaration(&self) -> Result<&'static PluginDeclaration, String> {
+        unreachable!()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_material(_mp: &TextureParams) -> Result<ArcMaterial, String> {
+        Err(String::from("test"))
+    }
+
+    fn register(registrar: &mut dyn PluginRegistrar) {
+        registrar.register_material("test", create_material);
+    }
+
+    #[test]
+    fn plugin_registry_finds_registered_factories() {
+        let mut registry = PluginRegistry::new();
+        register(&mut registry);
+
+        assert!(registry.material("test").is_some());
+        assert!(registry.material("matte").is_none());
+        assert!(registry.shape("test").is_none());
+        assert!(registry.integrator("test").is_none());
+    }
+
+    #[test]
+    fn plugin_declaration_checks_header() {
+        export_plugin!(register);
+        assert!(pbr_plugin_declaration.check().is_ok());
+
+        let declaration =
+            |abi_version, layout_hash, rustc_version: &'static str| PluginDeclaration {
+                abi_version,
+                layout_hash,
+                rustc_version: rustc_version.as_ptr() as *const c_char,
+                core_version: CORE_VERSION.as_ptr() as *const c_char,
+                register,
+            };
+        let rustc = RUSTC_VERSION;
+        assert!(declaration(PLUGIN_ABI_VERSION, PLUGIN_LAYOUT_HASH, rustc)
+            .check()
+            .is_ok());
+        assert!(declaration(1, PLUGIN_LAYOUT_HASH, rustc).check().is_err());
+        assert!(declaration(PLUGIN_ABI_VERSION, 0, rustc).check().is_err());
+        assert!(
+            declaration(PLUGIN_ABI_VERSION, PLUGIN_LAYOUT_HASH, "rustc 1.0.0\0")
+                .check()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn plugin_registry_reports_load_errors() {
+        let mut registry = PluginRegistry::new();
+        assert!(registry.load("/nonexistent/libplugin.so").is_err());
+        assert!(registry.paths.is_empty());
+    }
+}
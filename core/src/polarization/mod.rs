@@ -0,0 +1,90 @@
+//! Polarization
+//!
+//! Polarized light is carried as Stokes vectors and surface interactions are
+//! described by Mueller matrices. A Stokes vector is defined relative to a
+//! reference frame perpendicular to the direction the light propagates in.
+//! The frame is given by its x-axis; the y-axis is `d × x` for propagation
+//! direction `d`. Mueller matrices for Fresnel interfaces use the frames whose
+//! x-axis is perpendicular to the plane of incidence (s-polarization) for
+//! both the incident and scattered light.
+
+use crate::geometry::*;
+use crate::pbrt::*;
+
+mod mueller;
+mod stokes;
+
+// Re-export.
+pub use mueller::*;
+pub use stokes::*;
+
+/// Returns the x-axis of the frame perpendicular to the plane of incidence
+/// for a direction. Directions along the normal use the fallback axis.
+///
+/// * `n`        - The surface normal.
+/// * `w`        - The direction.
+/// * `fallback` - Axis used when `w` is parallel to `n`.
+pub fn s_axis(n: &Vector3f, w: &Vector3f, fallback: &Vector3f) -> Vector3f {
+    let s = n.cross(w);
+    if s.length_squared() > 1e-12 {
+        s.normalize()
+    } else {
+        *fallback
+    }
+}
+
+/// Returns an x-axis perpendicular to a direction for light with no
+/// preferred frame.
+///
+/// * `d` - The direction of propagation.
+pub fn any_axis(d: &Vector3f) -> Vector3f {
+    coordinate_system(&d.normalize()).0
+}
+
+/// Returns the Mueller matrix that changes the reference frame of a Stokes
+/// vector from one x-axis to another.
+///
+/// * `from` - The x-axis of the current frame.
+/// * `to`   - The x-axis of the new frame.
+/// * `d`    - The direction of propagation.
+pub fn rotate_frame(from: &Vector3f, to: &Vector3f, d: &Vector3f) -> Mueller {
+    let y = d.normalize().cross(from);
+    let theta = to.dot(&y).atan2(to.dot(from));
+    Mueller::rotator(theta)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum::*;
+
+    #[test]
+    fn frame_rotation() {
+        // Horizontally polarized light along +z is vertical relative to a
+        // frame whose x-axis is the y-axis.
+        let d = Vector3f::new(0.0, 0.0, 1.0);
+        let x = Vector3f::new(1.0, 0.0, 0.0);
+        let y = Vector3f::new(0.0, 1.0, 0.0);
+        let s = Stokes::new(
+            Spectrum::new(1.0),
+            Spectrum::new(1.0),
+            Spectrum::new(0.0),
+            Spectrum::new(0.0),
+        );
+
+        let r = rotate_frame(&x, &y, &d).apply(&s);
+        assert!((r.s[1][0] + 1.0).abs() < 1e-5);
+        assert!(r.s[2][0].abs() < 1e-5);
+
+        // Rotating back restores the original.
+        let r = rotate_frame(&y, &x, &d).apply(&r);
+        assert!((r.s[1][0] - 1.0).abs() < 1e-5);
+
+        // The frame for normal incidence uses the fallback.
+        assert_eq!(s_axis(&d, &d, &x), x);
+    }
+}
@@ -0,0 +1,420 @@
+//! Mueller Matrices
+
+use super::*;
+use crate::spectrum::*;
+use std::mem::swap;
+use std::ops::{Add, Div, Mul, Sub};
+
+/// Describes how an interaction changes the intensity and polarization of
+/// light given as a Stokes vector.
+#[derive(Copy, Clone, Default)]
+pub struct Mueller {
+    /// The matrix elements in row major order.
+    pub m: [[Spectrum; 4]; 4],
+}
+
+impl Mueller {
+    /// Returns a `Mueller` matrix with constant elements.
+    ///
+    /// * `m` - The matrix elements in row major order.
+    pub fn from_constants(m: [[Float; 4]; 4]) -> Self {
+        let mut r = Self::default();
+        for (row, values) in r.m.iter_mut().zip(m.iter()) {
+            for (e, v) in row.iter_mut().zip(values.iter()) {
+                *e = Spectrum::new(*v);
+            }
+        }
+        r
+    }
+
+    /// Returns the identity matrix.
+    pub fn identity() -> Self {
+        let mut r = Self::default();
+        for i in 0..4 {
+            r.m[i][i] = Spectrum::new(1.0);
+        }
+        r
+    }
+
+    /// Returns a matrix that scales the intensity and removes all
+    /// polarization. This is used for interactions that do not model
+    /// polarization.
+    ///
+    /// * `f` - Scale for the intensity.
+    pub fn depolarizer(f: Spectrum) -> Self {
+        let mut r = Self::default();
+        r.m[0][0] = f;
+        r
+    }
+
+    /// Returns the matrix that rotates the reference frame by an angle.
+    ///
+    /// * `theta` - Angle in radians from the current x-axis to the new one.
+    pub fn rotator(theta: Float) -> Self {
+        let (sin, cos) = (2.0 * theta).sin_cos();
+        Self::from_constants([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cos, sin, 0.0],
+            [0.0, -sin, cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Returns the matrix of an ideal linear polarizer.
+    ///
+    /// * `theta` - Angle in radians of the transmission axis from the x-axis.
+    pub fn linear_polarizer(theta: Float) -> Self {
+        let horizontal = Self::from_constants([
+            [0.5, 0.5, 0.0, 0.0],
+            [0.5, 0.5, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 0.0],
+        ]);
+        Self::rotator(-theta) * horizontal * Self::rotator(theta)
+    }
+
+    /// Returns the matrix for reflection at an interface between a
+    /// dielectric and a dielectric or conductor.
+    ///
+    /// * `cos_theta_i` - Cosine of the angle between the incident direction
+    ///                   and the surface normal. Negative values are on the
+    ///                   interior side.
+    /// * `eta_i`       - Index of refraction for the exterior side.
+    /// * `eta_t`       - Index of refraction for the interior side.
+    /// * `k`           - Absorption coefficient of the interior side.
+    pub fn fresnel_reflection(
+        cos_theta_i: Float,
+        eta_i: Spectrum,
+        eta_t: Spectrum,
+        k: Spectrum,
+    ) -> Self {
+        let mut r = Self::default();
+        for c in 0..eta_i.samples().len() {
+            let (rs, rp) = reflection_amplitudes(
+                cos_theta_i,
+                eta_i.samples()[c],
+                eta_t.samples()[c],
+                k.samples()[c],
+            );
+            let (r_s, r_p) = (rs.norm(), rp.norm());
+            let x = rs * rp.conj();
+            set_channel(
+                &mut r,
+                c,
+                [
+                    [0.5 * (r_s + r_p), 0.5 * (r_s - r_p), 0.0, 0.0],
+                    [0.5 * (r_s - r_p), 0.5 * (r_s + r_p), 0.0, 0.0],
+                    [0.0, 0.0, x.re, x.im],
+                    [0.0, 0.0, -x.im, x.re],
+                ],
+            );
+        }
+        r
+    }
+
+    /// Returns the matrix for transmission through an interface between two
+    /// dielectrics.
+    ///
+    /// * `cos_theta_i` - Cosine of the angle between the incident direction
+    ///                   and the surface normal. Negative values are on the
+    ///                   interior side.
+    /// * `eta_i`       - Index of refraction for the exterior side.
+    /// * `eta_t`       - Index of refraction for the interior side.
+    pub fn fresnel_transmission(cos_theta_i: Float, eta_i: Float, eta_t: Float) -> Self {
+        let (mut cos_theta_i, mut eta_i, mut eta_t) = (clamp(cos_theta_i, -1.0, 1.0), eta_i, eta_t);
+        if cos_theta_i < 0.0 {
+            swap(&mut eta_i, &mut eta_t);
+            cos_theta_i = -cos_theta_i;
+        }
+
+        // Handle total internal reflection.
+        let eta = eta_t / eta_i;
+        let sin_2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+        if sin_2_theta_t >= 1.0 || cos_theta_i == 0.0 {
+            return Self::default();
+        }
+
+        let cos_theta_t = (1.0 - sin_2_theta_t).sqrt();
+        let ts = 2.0 * cos_theta_i / (cos_theta_i + eta * cos_theta_t);
+        let tp = 2.0 * cos_theta_i / (eta * cos_theta_i + cos_theta_t);
+        let f = eta * cos_theta_t / cos_theta_i;
+        let (t_s, t_p) = (f * ts * ts, f * tp * tp);
+        Self::from_constants([
+            [0.5 * (t_s + t_p), 0.5 * (t_s - t_p), 0.0, 0.0],
+            [0.5 * (t_s - t_p), 0.5 * (t_s + t_p), 0.0, 0.0],
+            [0.0, 0.0, f * ts * tp, 0.0],
+            [0.0, 0.0, 0.0, f * ts * tp],
+        ])
+    }
+
+    /// Returns the Stokes vector after the interaction.
+    ///
+    /// * `s` - The incident Stokes vector.
+    pub fn apply(&self, s: &Stokes) -> Stokes {
+        let mut r = Stokes::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                r.s[i] += self.m[i][j] * s.s[j];
+            }
+        }
+        r
+    }
+
+    /// Returns `true` if all elements are zero.
+    pub fn is_black(&self) -> bool {
+        self.m.iter().all(|row| row.iter().all(|v| v.is_black()))
+    }
+}
+
+impl Add for Mueller {
+    type Output = Self;
+
+    /// Adds the given matrix and returns the result.
+    ///
+    /// * `other` - The matrix to add.
+    fn add(self, other: Self) -> Self::Output {
+        let mut r = self;
+        for i in 0..4 {
+            for j in 0..4 {
+                r.m[i][j] += other.m[i][j];
+            }
+        }
+        r
+    }
+}
+
+impl Mul for Mueller {
+    type Output = Self;
+
+    /// Returns the matrix for applying `other` followed by `self`.
+    ///
+    /// * `other` - The matrix to multiply with.
+    fn mul(self, other: Self) -> Self::Output {
+        let mut r = Self::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    r.m[i][j] += self.m[i][k] * other.m[k][j];
+                }
+            }
+        }
+        r
+    }
+}
+
+impl Mul<Spectrum> for Mueller {
+    type Output = Self;
+
+    /// Scales the matrix elements by a spectrum.
+    ///
+    /// * `s` - The scale.
+    fn mul(self, s: Spectrum) -> Self::Output {
+        let mut r = self;
+        for row in r.m.iter_mut() {
+            for v in row.iter_mut() {
+                *v *= s;
+            }
+        }
+        r
+    }
+}
+
+impl Mul<Float> for Mueller {
+    type Output = Self;
+
+    /// Scales the matrix elements by a constant.
+    ///
+    /// * `f` - The scale.
+    fn mul(self, f: Float) -> Self::Output {
+        self * Spectrum::new(f)
+    }
+}
+
+/// Sets the elements of one spectral channel of a matrix.
+///
+/// * `r` - The matrix.
+/// * `c` - Index of the channel.
+/// * `m` - The elements in row major order.
+fn set_channel(r: &mut Mueller, c: usize, m: [[Float; 4]; 4]) {
+    for (row, values) in r.m.iter_mut().zip(m.iter()) {
+        for (e, v) in row.iter_mut().zip(values.iter()) {
+            e.samples_mut()[c] = *v;
+        }
+    }
+}
+
+/// Returns the Fresnel amplitude coefficients for s- and p-polarized light
+/// reflected at an interface with a complex index of refraction.
+///
+/// * `cos_theta_i` - Cosine of the angle between the incident direction and
+///                   the surface normal. Negative values are on the interior
+///                   side.
+/// * `eta_i`       - Index of refraction for the exterior side.
+/// * `eta_t`       - Index of refraction for the interior side.
+/// * `k`           - Absorption coefficient of the interior side.
+fn reflection_amplitudes(
+    cos_theta_i: Float,
+    eta_i: Float,
+    eta_t: Float,
+    k: Float,
+) -> (Complex, Complex) {
+    let mut cos_theta_i = clamp(cos_theta_i, -1.0, 1.0);
+    let mut eta = Complex::new(eta_t / eta_i, k / eta_i);
+    if cos_theta_i < 0.0 {
+        eta = Complex::new(1.0, 0.0) / eta;
+        cos_theta_i = -cos_theta_i;
+    }
+
+    // Complex Snell's law gives the transmitted angle including total
+    // internal reflection.
+    let sin_2_theta_i = Complex::new(1.0 - cos_theta_i * cos_theta_i, 0.0);
+    let sin_2_theta_t = sin_2_theta_i / (eta * eta);
+    let cos_theta_t = (Complex::new(1.0, 0.0) - sin_2_theta_t).sqrt();
+    let cos_i = Complex::new(cos_theta_i, 0.0);
+
+    let rs = (cos_i - eta * cos_theta_t) / (cos_i + eta * cos_theta_t);
+    let rp = (eta * cos_i - cos_theta_t) / (eta * cos_i + cos_theta_t);
+    (rs, rp)
+}
+
+/// A complex number used for Fresnel amplitudes.
+#[derive(Copy, Clone, Debug)]
+struct Complex {
+    /// Real part.
+    re: Float,
+
+    /// Imaginary part.
+    im: Float,
+}
+
+impl Complex {
+    /// Create a new `Complex`.
+    ///
+    /// * `re` - Real part.
+    /// * `im` - Imaginary part.
+    fn new(re: Float, im: Float) -> Self {
+        Self { re, im }
+    }
+
+    /// Returns the complex conjugate.
+    fn conj(&self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    /// Returns the squared magnitude.
+    fn norm(&self) -> Float {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// Returns the principal square root.
+    fn sqrt(&self) -> Self {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Self::new(0.0, 0.0);
+        }
+        let re = (0.5 * (n + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (n - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    /// Adds the given complex number.
+    ///
+    /// * `o` - The other complex number.
+    fn add(self, o: Self) -> Self {
+        Self::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    /// Subtracts the given complex number.
+    ///
+    /// * `o` - The other complex number.
+    fn sub(self, o: Self) -> Self {
+        Self::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    /// Multiplies by the given complex number.
+    ///
+    /// * `o` - The other complex number.
+    fn mul(self, o: Self) -> Self {
+        Self::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    /// Divides by the given complex number.
+    ///
+    /// * `o` - The other complex number.
+    fn div(self, o: Self) -> Self {
+        let d = o.norm();
+        Self::new(
+            (self.re * o.re + self.im * o.im) / d,
+            (self.im * o.re - self.re * o.im) / d,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflection::{fr_conductor, fr_dielectric};
+
+    #[test]
+    fn fresnel_matches_unpolarized() {
+        let one = Spectrum::new(1.0);
+        let zero = Spectrum::new(0.0);
+        let unpolarized = Stokes::unpolarized(one);
+        for &cos_theta_i in [1.0, 0.7, 0.3, -0.5, -0.9].iter() {
+            let r = Mueller::fresnel_reflection(cos_theta_i, one, Spectrum::new(1.5), zero)
+                .apply(&unpolarized);
+            let t = Mueller::fresnel_transmission(cos_theta_i, 1.0, 1.5).apply(&unpolarized);
+            let f = fr_dielectric(cos_theta_i, 1.0, 1.5);
+            assert!((r.s[0][0] - f).abs() < 1e-4);
+            assert!((r.s[0][0] + t.s[0][0] - 1.0).abs() < 1e-4);
+        }
+
+        let (eta, k) = (Spectrum::new(0.2), Spectrum::new(3.9));
+        let r = Mueller::fresnel_reflection(1.0, one, eta, k).apply(&unpolarized);
+        assert!((r.s[0][0] - fr_conductor(1.0, one, eta, k)[0]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn brewster_angle_polarizes() {
+        // Reflected light at Brewster's angle is s-polarized.
+        let cos_theta_i = (1.5 as Float).atan().cos();
+        let one = Spectrum::new(1.0);
+        let r = Mueller::fresnel_reflection(cos_theta_i, one, Spectrum::new(1.5), Spectrum::new(0.0))
+            .apply(&Stokes::unpolarized(one));
+        assert!((r.s[0][0] - r.s[1][0]).abs() < 1e-5);
+        assert!((r.degree_of_polarization()[0] - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn crossed_polarizers_block_light() {
+        let s = Stokes::unpolarized(Spectrum::new(1.0));
+        let m = Mueller::linear_polarizer(0.5 * PI) * Mueller::linear_polarizer(0.0);
+        assert!(m.apply(&s).s[0][0].abs() < 1e-6);
+
+        let m = Mueller::linear_polarizer(0.25 * PI) * Mueller::linear_polarizer(0.0);
+        assert!((m.apply(&s).s[0][0] - 0.25).abs() < 1e-6);
+    }
+}
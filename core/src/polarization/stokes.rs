@@ -0,0 +1,112 @@
+//! Stokes Vectors
+
+use crate::pbrt::*;
+use crate::spectrum::*;
+use std::ops::{Add, AddAssign, Div, Mul};
+
+/// Describes the intensity and polarization state of light. The components
+/// are the total intensity, horizontal versus vertical, +45° versus -45°
+/// and right versus left circular polarization.
+#[derive(Copy, Clone, Default)]
+pub struct Stokes {
+    /// The Stokes parameters s0, s1, s2 and s3.
+    pub s: [Spectrum; 4],
+}
+
+impl Stokes {
+    /// Create a new `Stokes` vector.
+    ///
+    /// * `s0` - Total intensity.
+    /// * `s1` - Horizontal versus vertical linear polarization.
+    /// * `s2` - +45° versus -45° linear polarization.
+    /// * `s3` - Right versus left circular polarization.
+    pub fn new(s0: Spectrum, s1: Spectrum, s2: Spectrum, s3: Spectrum) -> Self {
+        Self {
+            s: [s0, s1, s2, s3],
+        }
+    }
+
+    /// Create a new `Stokes` vector for unpolarized light.
+    ///
+    /// * `l` - The intensity.
+    pub fn unpolarized(l: Spectrum) -> Self {
+        let zero = Spectrum::new(0.0);
+        Self::new(l, zero, zero, zero)
+    }
+
+    /// Returns the total intensity.
+    pub fn intensity(&self) -> Spectrum {
+        self.s[0]
+    }
+
+    /// Returns the fraction of the intensity that is polarized.
+    pub fn degree_of_polarization(&self) -> Spectrum {
+        let mut dop = Spectrum::new(0.0);
+        let s0 = self.s[0].samples();
+        let s1 = self.s[1].samples();
+        let s2 = self.s[2].samples();
+        let s3 = self.s[3].samples();
+        for (i, v) in dop.samples_mut().iter_mut().enumerate() {
+            if s0[i] > 0.0 {
+                let p = (s1[i] * s1[i] + s2[i] * s2[i] + s3[i] * s3[i]).sqrt();
+                *v = clamp(p / s0[i], 0.0, 1.0);
+            }
+        }
+        dop
+    }
+
+    /// Returns `true` if the intensity is zero.
+    pub fn is_black(&self) -> bool {
+        self.s[0].is_black()
+    }
+}
+
+impl Add for Stokes {
+    type Output = Self;
+
+    /// Adds the given Stokes vector and returns the result.
+    ///
+    /// * `other` - The Stokes vector to add.
+    fn add(self, other: Self) -> Self::Output {
+        let mut r = self;
+        r += other;
+        r
+    }
+}
+
+impl AddAssign for Stokes {
+    /// Performs the `+=` operation.
+    ///
+    /// * `other` - The Stokes vector to add.
+    fn add_assign(&mut self, other: Self) {
+        for (a, b) in self.s.iter_mut().zip(other.s.iter()) {
+            *a += *b;
+        }
+    }
+}
+
+impl Mul<Float> for Stokes {
+    type Output = Self;
+
+    /// Scales the Stokes vector by a constant.
+    ///
+    /// * `f` - The scaling factor.
+    fn mul(self, f: Float) -> Self::Output {
+        let mut r = self;
+        for v in r.s.iter_mut() {
+            *v *= f;
+        }
+        r
+    }
+}
+
+impl Div<Float> for Stokes {
+    type Output = Self;
+
+    /// Divides the Stokes vector by a constant.
+    ///
+    /// * `f` - The divisor.
+    fn div(self, f: Float) -> Self::Output {
+        self * (1.0 / f)
+    }
+}
@@ -17,11 +17,31 @@ pub trait Primitive {
     /// * `r`                  - The ray.
     fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction>;
 
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    /// Aggregates and transformed primitives pass the filter on so it is
+    /// applied to the primitives that carry shapes.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction>;
+
     /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
     ///
     /// * `r`                  - The ray.
     fn intersect_p(&self, r: &Ray) -> bool;
 
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool;
+
     /// Returns a reference to the AreaLight that describes the primitive’s
     /// emission distribution, if the primitive is itself a light source.
     /// If the primitive is not emissive, this method should return `None`.  
@@ -34,6 +54,24 @@ pub trait Primitive {
     /// intersected the same object by comparing their Material pointers.
     fn get_material(&self) -> Option<ArcMaterial>;
 
+    /// Returns the lights that do or don't illuminate the primitive or `None`
+    /// if it is lit by all lights.
+    fn get_light_links(&self) -> Option<Arc<LightLinks>> {
+        None
+    }
+
+    /// Returns the identifiers written to the ID channels of the image.
+    /// Primitives that don't carry shapes have no identifiers.
+    fn get_ids(&self) -> PrimitiveIds {
+        PrimitiveIds::default()
+    }
+
+    /// Returns the shape of a primitive that carries one directly. Aggregates
+    /// and transformed primitives return `None`.
+    fn get_shape(&self) -> Option<ArcShape> {
+        None
+    }
+
     /// Initializes representations of the light-scattering properties of the
     /// material at the intersection point on the surface.
     ///
@@ -49,11 +87,35 @@ pub trait Primitive {
         mode: TransportMode,
         allow_multiple_lobes: bool,
     );
+
+    /// Returns the memory used by the primitive in bytes, excluding the
+    /// shapes and primitives it refers to. Defaults to the size of the
+    /// primitive itself.
+    fn memory(&self) -> usize {
+        std::mem::size_of_val(self)
+    }
+}
+
+/// Identifiers of the shape and material of a primitive. Identifiers start
+/// at 1 in the order shapes and materials are declared in the scene; 0 means
+/// no identifier.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PrimitiveIds {
+    /// Identifies the `Shape` directive the primitive was created from.
+    pub shape: u32,
+
+    /// Identifies the material of the primitive.
+    pub material: u32,
 }
 
 /// Atomic referenced counted `Primitive`.
 pub type ArcPrimitive = Arc<dyn Primitive + Send + Sync>;
 
+/// Predicate that selects the primitives carrying shapes an intersection
+/// query considers. Ray type masks, light and shadow linking and holdouts
+/// filter primitives with it.
+pub type PrimitiveFilter<'a> = &'a dyn Fn(&dyn Primitive) -> bool;
+
 /// Aggregate trait defines common behaviours for ray intersection accelerators.
 pub trait Aggregate: Primitive {}
 
@@ -0,0 +1,322 @@
+//! Constructive Solid Geometry
+
+use crate::geometry::*;
+use crate::light::*;
+use crate::material::*;
+use crate::pbrt::*;
+use crate::primitive::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// Maximum number of child surfaces considered along a ray.
+const MAX_CSG_CROSSINGS: usize = 128;
+
+/// Boolean operations for combining solids.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CSGOperation {
+    /// Points inside either solid.
+    Union,
+
+    /// Points inside both solids.
+    Intersection,
+
+    /// Points inside the first solid but not the second.
+    Difference,
+}
+
+impl CSGOperation {
+    /// Returns whether a point is inside the combined solid.
+    ///
+    /// * `in_a` - Whether the point is inside the first solid.
+    /// * `in_b` - Whether the point is inside the second solid.
+    pub fn contains(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Self::Union => in_a || in_b,
+            Self::Intersection => in_a && in_b,
+            Self::Difference => in_a && !in_b,
+        }
+    }
+}
+
+impl From<&str> for CSGOperation {
+    /// Returns the operation for its name in a scene file.
+    ///
+    /// * `name` - Name of the operation.
+    fn from(name: &str) -> Self {
+        match name {
+            "union" => Self::Union,
+            "intersection" => Self::Intersection,
+            "difference" => Self::Difference,
+            op => {
+                warn!("CSG operation '{}' unknown. Using 'union'.", op);
+                Self::Union
+            }
+        }
+    }
+}
+
+impl fmt::Display for CSGOperation {
+    /// Formats the operation with its name in a scene file.
+    ///
+    /// * `f` - Formatter.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Union => write!(f, "union"),
+            Self::Intersection => write!(f, "intersection"),
+            Self::Difference => write!(f, "difference"),
+        }
+    }
+}
+
+/// CSGPrimitive combines two closed primitives with a boolean operation. The
+/// surface is found by walking the intervals of the ray inside each child,
+/// which are bounded by hits where the ray enters (faces the geometric normal)
+/// and leaves them. Children must be closed with outward facing normals and
+/// may be other `CSGPrimitive`s.
+#[derive(Clone)]
+pub struct CSGPrimitive {
+    /// The operation.
+    pub operation: CSGOperation,
+
+    /// The first solid.
+    pub a: ArcPrimitive,
+
+    /// The second solid.
+    pub b: ArcPrimitive,
+
+    /// The bounding box in world space.
+    bounds: Bounds3f,
+}
+
+impl CSGPrimitive {
+    /// Create a new CSG primitive.
+    ///
+    /// * `operation` - The operation.
+    /// * `a`         - The first solid.
+    /// * `b`         - The second solid.
+    pub fn new(operation: CSGOperation, a: ArcPrimitive, b: ArcPrimitive) -> Self {
+        let bounds = match operation {
+            CSGOperation::Union => a.world_bound().union(&b.world_bound()),
+            CSGOperation::Intersection => a.world_bound().intersect(&b.world_bound()),
+            CSGOperation::Difference => a.world_bound(),
+        };
+        Self {
+            operation,
+            a: Arc::clone(&a),
+            b: Arc::clone(&b),
+            bounds,
+        }
+    }
+
+    /// Returns the first hit with a child at or after a distance along the
+    /// ray and its distance.
+    ///
+    /// * `child`     - The child.
+    /// * `r`         - The ray.
+    /// * `t0`        - Distance along the ray to start from.
+    /// * `from`      - Surface interaction at `t0` to spawn the ray from.
+    /// * `intersect` - Intersects a child with a ray.
+    fn next_hit<'a, F>(
+        child: &'a dyn Primitive,
+        r: &Ray,
+        t0: Float,
+        from: Option<&SurfaceInteraction<'a>>,
+        intersect: &F,
+    ) -> Option<(Float, SurfaceInteraction<'a>)>
+    where
+        F: Fn(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
+        let mut ray = match from {
+            Some(isect) => isect.hit.spawn_ray(&r.d),
+            None => r.clone(),
+        };
+        ray.t_max = r.t_max - t0;
+        intersect(child, &mut ray).map(|isect| (t0 + ray.t_max, isect))
+    }
+
+    /// Returns whether the ray is inside a child before its next hit.
+    ///
+    /// * `r`   - The ray.
+    /// * `hit` - The next hit with the child.
+    fn inside_before(r: &Ray, hit: &Option<(Float, SurfaceInteraction)>) -> bool {
+        match hit {
+            Some((_, isect)) => r.d.dot(&Vector3f::from(isect.hit.n)) > 0.0,
+            None => false,
+        }
+    }
+
+    /// Walks the hits with both children in order and returns the first one
+    /// where the ray crosses the surface of the combined solid.
+    ///
+    /// * `r`         - The ray.
+    /// * `intersect` - Intersects a child with a ray.
+    fn intersect_with<'a, F>(&'a self, r: &mut Ray, intersect: F) -> Option<SurfaceInteraction<'a>>
+    where
+        F: Fn(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
+        let (a, b) = (&*self.a, &*self.b);
+        let mut hit_a = Self::next_hit(a, r, 0.0, None, &intersect);
+        let mut hit_b = Self::next_hit(b, r, 0.0, None, &intersect);
+
+        for _ in 0..MAX_CSG_CROSSINGS {
+            let from_a = match (&hit_a, &hit_b) {
+                (Some((ta, _)), Some((tb, _))) => ta <= tb,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+
+            let in_a = Self::inside_before(r, &hit_a);
+            let in_b = Self::inside_before(r, &hit_b);
+            let before = self.operation.contains(in_a, in_b);
+            let after = if from_a {
+                self.operation.contains(!in_a, in_b)
+            } else {
+                self.operation.contains(in_a, !in_b)
+            };
+
+            let hit = if from_a { &mut hit_a } else { &mut hit_b };
+            let (t, isect) = hit.take().unwrap();
+            if before != after {
+                let mut isect = isect;
+                if !from_a && self.operation == CSGOperation::Difference {
+                    // The second solid's surface bounds the result from the
+                    // inside.
+                    isect.hit.n = -isect.hit.n;
+                    isect.shading.n = -isect.shading.n;
+                }
+                r.t_max = t;
+                return Some(isect);
+            }
+
+            let child = if from_a { a } else { b };
+            *hit = Self::next_hit(child, r, t, Some(&isect), &intersect);
+        }
+
+        None
+    }
+}
+
+impl Primitive for CSGPrimitive {
+    /// Returns a bounding box in the world space.
+    fn world_bound(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// Returns geometric details if a ray intersects the primitive and updates
+    /// the t_max parameter of the ray. If there is no intersection, `None` is
+    /// returned.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+        self.bounds.intersect_p(r)?;
+        self.intersect_with(r, |p, ray| p.intersect(ray))
+    }
+
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        self.bounds.intersect_p(r)?;
+        self.intersect_with(r, |p, ray| p.intersect_filtered(ray, filter))
+    }
+
+    /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect_p(&self, r: &Ray) -> bool {
+        self.intersect(&mut r.clone()).is_some()
+    }
+
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        self.intersect_filtered(&mut r.clone(), filter).is_some()
+    }
+
+    /// Returns a reference to the AreaLight that describes the primitive’s
+    /// emission distribution, if the primitive is itself a light source.
+    /// If the primitive is not emissive, this method should return `None`.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// get_area_light() on the primitive from the ray-primitive intersection.
+    fn get_area_light(&self) -> Option<ArcAreaLight> {
+        error!(
+            "CSGPrimitive::get_area_light() shouldn't be called; \
+            should've gone to GeometricPrimitive."
+        );
+        None
+    }
+
+    /// Returns a reference to the material instance assigned to the primitive.
+    /// If `None` is returned, ray intersections with the primitive should be
+    /// ignored; the primitive only serves to delineate a volume of space for
+    /// participating media. This method is also used to check if two rays have
+    /// intersected the same object by comparing their Material pointers.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// get_material() on the primitive from the ray-primitive intersection.
+    fn get_material(&self) -> Option<ArcMaterial> {
+        error!(
+            "CSGPrimitive::get_material() shouldn't be called; \
+            should've gone to GeometricPrimitive."
+        );
+        None
+    }
+
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// *NOTE*: This should never be called. Calling code should directly call
+    /// compute_scattering_functions() on the primitive from the ray-primitive
+    /// intersection.
+    ///
+    /// * `_si`                   - The surface interaction at the intersection.
+    /// * `_mode`                 - Transport mode.
+    /// * `_allow_multiple_lobes` - Allow multiple lobes.
+    fn compute_scattering_functions(
+        &self,
+        _si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        error!(
+            "CSGPrimitive::compute_scattering_functions() shouldn't be \
+            called; should've gone to GeometricPrimitive."
+        );
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_classify_points() {
+        let cases = [(false, false), (true, false), (false, true), (true, true)];
+        let expected = [
+            (CSGOperation::Union, [false, true, true, true]),
+            (CSGOperation::Intersection, [false, false, false, true]),
+            (CSGOperation::Difference, [false, true, false, false]),
+        ];
+        for (op, inside) in expected.iter() {
+            for ((in_a, in_b), &e) in cases.iter().zip(inside.iter()) {
+                assert_eq!(op.contains(*in_a, *in_b), e, "{} {} {}", op, in_a, in_b);
+            }
+            assert_eq!(CSGOperation::from(&format!("{}", op)[..]), *op);
+        }
+    }
+}
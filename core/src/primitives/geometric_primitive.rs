@@ -23,6 +23,12 @@ pub struct GeometricPrimitive {
     /// Information about the participating media on the inside and outside
     /// the primitive.
     pub medium_interface: MediumInterface,
+
+    /// Lights that do or don't illuminate the primitive.
+    pub light_links: Option<Arc<LightLinks>>,
+
+    /// Identifiers written to the ID channels of the image.
+    pub ids: PrimitiveIds,
 }
 
 impl GeometricPrimitive {
@@ -34,17 +40,22 @@ impl GeometricPrimitive {
     ///                        characterisitics if it emits light.
     /// * `medium_interface` - Information about the participating media on the
     ///                        inside and outside the primitive.
+    /// * `light_links`      - Lights that do or don't illuminate the
+    ///                        primitive.
     pub fn new(
         shape: ArcShape,
         material: ArcMaterial,
         area_light: Option<ArcAreaLight>,
         medium_interface: MediumInterface,
+        light_links: Option<Arc<LightLinks>>,
     ) -> Self {
         Self {
             shape: Arc::clone(&shape),
             material: Some(Arc::clone(&material)),
             area_light: area_light.clone(),
             medium_interface: medium_interface.clone(),
+            light_links,
+            ids: PrimitiveIds::default(),
         }
     }
 }
@@ -71,7 +82,8 @@ impl Primitive for GeometricPrimitive {
             // intersection.
             let is_medium_transition = self.medium_interface.is_medium_transition();
             it.isect.hit.medium_interface = if is_medium_transition {
-                Some(self.medium_interface.clone())
+                let entering = r.d.dot(&it.isect.hit.n) < 0.0;
+                Some(self.medium_interface.resolve(r.medium.clone(), entering))
             } else if let Some(medium) = r.medium.clone() {
                 Some(MediumInterface::from(medium))
             } else {
@@ -84,6 +96,23 @@ impl Primitive for GeometricPrimitive {
         }
     }
 
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        if filter(self) {
+            self.intersect(r)
+        } else {
+            None
+        }
+    }
+
     /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
     ///
     /// * `r`                  - The ray.
@@ -91,6 +120,15 @@ impl Primitive for GeometricPrimitive {
         self.shape.intersect_p(r, true)
     }
 
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        filter(self) && self.intersect_p(r)
+    }
+
     /// Returns a reference to the AreaLight that describes the primitive’s
     /// emission distribution, if the primitive is itself a light source.
     /// If the primitive is not emissive, this method should return `None`.  
@@ -107,6 +145,22 @@ impl Primitive for GeometricPrimitive {
         self.material.clone()
     }
 
+    /// Returns the lights that do or don't illuminate the primitive or `None`
+    /// if it is lit by all lights.
+    fn get_light_links(&self) -> Option<Arc<LightLinks>> {
+        self.light_links.clone()
+    }
+
+    /// Returns the identifiers written to the ID channels of the image.
+    fn get_ids(&self) -> PrimitiveIds {
+        self.ids
+    }
+
+    /// Returns the shape.
+    fn get_shape(&self) -> Option<ArcShape> {
+        Some(Arc::clone(&self.shape))
+    }
+
     /// Initializes representations of the light-scattering properties of the
     /// material at the intersection point on the surface.
     ///
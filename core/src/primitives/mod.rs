@@ -1,8 +1,10 @@
 //! Primitve
 
+mod csg_primitive;
 mod geometric_primitive;
 mod transformed_primitive;
 
 // Re-export
+pub use csg_primitive::*;
 pub use geometric_primitive::*;
 pub use transformed_primitive::*;
@@ -28,28 +28,27 @@ impl TransformedPrimitive {
             primitive_to_world,
         }
     }
-}
 
-impl Primitive for TransformedPrimitive {
-    /// Returns a bounding box in the world space.
-    fn world_bound(&self) -> Bounds3f {
-        self.primitive_to_world
-            .motion_bounds(&self.primitive.world_bound())
-    }
-
-    /// Returns geometric details if a ray intersects the primitive and updates
-    /// the t_max parameter of the ray. If there is no intersection, `None` is
-    /// returned.
+    /// Transforms the ray into the primitive's space, intersects it with the
+    /// given function and transforms the intersection back to world space.
     ///
-    /// * `r`                  - The ray.
-    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+    /// * `r`         - The ray.
+    /// * `intersect` - Intersects the underlying primitive with the ray.
+    fn intersect_with<'a, F>(&'a self, r: &mut Ray, intersect: F) -> Option<SurfaceInteraction<'a>>
+    where
+        F: FnOnce(&'a dyn Primitive, &mut Ray) -> Option<SurfaceInteraction<'a>>,
+    {
         let interpolated_prim_to_world = self.primitive_to_world.interpolate(r.time);
         let mut ray = interpolated_prim_to_world.inverse().transform_ray(r);
 
-        if let Some(mut it) = self.primitive.intersect(&mut ray) {
+        if let Some(mut it) = intersect(&*self.primitive, &mut ray) {
             r.t_max = ray.t_max;
             if !interpolated_prim_to_world.is_identity() {
                 it = interpolated_prim_to_world.transform_surface_interaction(&it);
+                it.instance_to_world = Some(match it.instance_to_world {
+                    Some(t) => *interpolated_prim_to_world * t,
+                    None => *interpolated_prim_to_world,
+                });
             }
 
             debug_assert!(it.hit.n.dot(&it.shading.n) > 0.0);
@@ -59,6 +58,36 @@ impl Primitive for TransformedPrimitive {
             None
         }
     }
+}
+
+impl Primitive for TransformedPrimitive {
+    /// Returns a bounding box in the world space.
+    fn world_bound(&self) -> Bounds3f {
+        self.primitive_to_world
+            .motion_bounds(&self.primitive.world_bound())
+    }
+
+    /// Returns geometric details if a ray intersects the primitive and updates
+    /// the t_max parameter of the ray. If there is no intersection, `None` is
+    /// returned.
+    ///
+    /// * `r`                  - The ray.
+    fn intersect(&self, r: &mut Ray) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, ray| p.intersect(ray))
+    }
+
+    /// Returns geometric details of the closest intersection with a primitive
+    /// accepted by the filter and updates the t_max parameter of the ray.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_filtered(
+        &self,
+        r: &mut Ray,
+        filter: PrimitiveFilter,
+    ) -> Option<SurfaceInteraction> {
+        self.intersect_with(r, |p, ray| p.intersect_filtered(ray, filter))
+    }
 
     /// Returns `true` if a ray-primitive intersection succeeds; otherwise `false`.
     ///
@@ -69,6 +98,16 @@ impl Primitive for TransformedPrimitive {
         self.primitive.intersect_p(&mut ray)
     }
 
+    /// Returns `true` if the ray intersects a primitive accepted by the
+    /// filter; otherwise `false`.
+    ///
+    /// * `r`      - The ray.
+    /// * `filter` - Selects the primitives to consider.
+    fn intersect_p_filtered(&self, r: &Ray, filter: PrimitiveFilter) -> bool {
+        let interpolated_prim_to_world = self.primitive_to_world.interpolate(r.time);
+        let ray = interpolated_prim_to_world.inverse().transform_ray(r);
+        self.primitive.intersect_p_filtered(&ray, filter)
+    }
     /// Returns a reference to the AreaLight that describes the primitive’s
     /// emission distribution, if the primitive is itself a light source.
     /// If the primitive is not emissive, this method should return `None`.  
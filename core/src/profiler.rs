@@ -0,0 +1,368 @@
+//! Profiler
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+lazy_static! {
+    /// Time spent in the profiled phases by all threads.
+    pub static ref PROFILER: Profiler = Profiler::new();
+}
+
+thread_local! {
+    /// The phases the calling thread is in.
+    static THREAD_PROFILE: ThreadProfile = ThreadProfile::new();
+}
+
+/// Whether `ProfilePhase` records time. Checked before touching any thread
+/// local state so that disabled phases cost a single load.
+static PROFILER_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Nanoseconds a thread spent in each set of phases keyed by the bits of the
+/// phases.
+type PhaseNanos = Arc<Mutex<HashMap<u64, u64>>>;
+
+/// Phases of rendering the profiler attributes time to. Phases nest and are
+/// listed from the outermost to the innermost; a phase entered inside
+/// another is reported below it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Prof {
+    /// Building the scene after the world block was parsed.
+    SceneConstruction,
+
+    /// Building acceleration structures.
+    AccelConstruction,
+
+    /// Rendering a tile of the image.
+    IntegratorRender,
+
+    /// Computing the radiance along a camera ray.
+    SamplerIntegratorLi,
+
+    /// Generating camera rays.
+    GenerateCameraRay,
+
+    /// Sampling direct lighting.
+    DirectLighting,
+
+    /// Finding the closest intersection of a ray in an acceleration
+    /// structure.
+    AccelIntersect,
+
+    /// Testing a shadow ray for any intersection in an acceleration
+    /// structure.
+    AccelIntersectP,
+
+    /// Evaluating, sampling and computing the PDF of BSDFs.
+    BSDFEvaluation,
+
+    /// Preparing the sampler for a pixel.
+    StartPixel,
+
+    /// Generating sample values.
+    GetSample,
+
+    /// Adding a sample to a film tile.
+    AddFilmSample,
+
+    /// Merging a film tile into the film.
+    MergeFilmTile,
+}
+
+/// All profiled phases in nesting order.
+#[rustfmt::skip]
+const PHASES: [Prof; 13] = [
+    Prof::SceneConstruction, Prof::AccelConstruction, Prof::IntegratorRender,
+    Prof::SamplerIntegratorLi, Prof::GenerateCameraRay, Prof::DirectLighting,
+    Prof::AccelIntersect, Prof::AccelIntersectP, Prof::BSDFEvaluation,
+    Prof::StartPixel, Prof::GetSample, Prof::AddFilmSample, Prof::MergeFilmTile,
+];
+
+impl Prof {
+    /// Returns the bit of the phase in a set of phases.
+    fn bit(self) -> u64 {
+        1 << self as u64
+    }
+
+    /// Returns the name of the phase.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SceneConstruction => "Scene construction",
+            Self::AccelConstruction => "Acceleration structure creation",
+            Self::IntegratorRender => "Integrator::render()",
+            Self::SamplerIntegratorLi => "SamplerIntegrator::li()",
+            Self::GenerateCameraRay => "Camera::generate_ray()",
+            Self::DirectLighting => "Direct lighting",
+            Self::AccelIntersect => "Accelerator::intersect()",
+            Self::AccelIntersectP => "Accelerator::intersect_p()",
+            Self::BSDFEvaluation => "BSDF evaluation",
+            Self::StartPixel => "Sampler::start_pixel()",
+            Self::GetSample => "Sampler::get_sample()",
+            Self::AddFilmSample => "FilmTile::add_sample()",
+            Self::MergeFilmTile => "Film::merge_film_tile()",
+        }
+    }
+}
+
+/// The phases a thread is in and the time it spent in each set of phases.
+struct ThreadProfile {
+    /// Bits of the phases the thread is in.
+    phases: Cell<u64>,
+
+    /// When the thread last entered or left a phase.
+    since: Cell<Instant>,
+
+    /// Nanoseconds spent in each set of phases.
+    nanos: PhaseNanos,
+}
+
+impl ThreadProfile {
+    /// Create a new `ThreadProfile` and register it with the profiler.
+    fn new() -> Self {
+        let nanos = Arc::new(Mutex::new(HashMap::new()));
+        PROFILER.threads.lock().unwrap().push(Arc::clone(&nanos));
+        Self {
+            phases: Cell::new(0),
+            since: Cell::new(Instant::now()),
+            nanos,
+        }
+    }
+
+    /// Attributes the time since the last transition to the current phases
+    /// and switches to a new set of phases.
+    ///
+    /// * `phases` - Bits of the new phases.
+    fn transition(&self, phases: u64) {
+        let now = Instant::now();
+        let current = self.phases.get();
+        if current != 0 {
+            let nanos = now.duration_since(self.since.get()).as_nanos() as u64;
+            *self.nanos.lock().unwrap().entry(current).or_default() += nanos;
+        }
+        self.phases.set(phases);
+        self.since.set(now);
+    }
+}
+
+/// Attributes the time until it is dropped to a phase on the calling thread
+/// when profiling is enabled.
+///
+/// ```ignore
+/// let _p = ProfilePhase::new(Prof::BSDFEvaluation);
+/// ```
+#[must_use = "the phase ends when the ProfilePhase is dropped"]
+pub struct ProfilePhase {
+    /// Bit of the phase entered or 0 if profiling is disabled or the thread
+    /// was already in the phase.
+    bit: u64,
+}
+
+impl ProfilePhase {
+    /// Enter a phase.
+    ///
+    /// * `phase` - The phase.
+    pub fn new(phase: Prof) -> Self {
+        if !PROFILER_ENABLED.load(Ordering::Relaxed) {
+            return Self { bit: 0 };
+        }
+
+        let bit = phase.bit();
+        let entered = THREAD_PROFILE
+            .try_with(|t| {
+                let phases = t.phases.get();
+                if phases & bit == 0 {
+                    t.transition(phases | bit);
+                    true
+                } else {
+                    false
+                }
+            })
+            .unwrap_or(false);
+        Self {
+            bit: if entered { bit } else { 0 },
+        }
+    }
+}
+
+impl Drop for ProfilePhase {
+    /// Leave the phase.
+    fn drop(&mut self) {
+        if self.bit != 0 {
+            let _ = THREAD_PROFILE.try_with(|t| t.transition(t.phases.get() & !self.bit));
+        }
+    }
+}
+
+/// Collects the time threads spend in the profiled phases.
+pub struct Profiler {
+    /// Nanoseconds spent in each set of phases by each thread.
+    threads: Mutex<Vec<PhaseNanos>>,
+}
+
+impl Profiler {
+    /// Create a new `Profiler`.
+    pub fn new() -> Self {
+        Self {
+            threads: Mutex::new(vec![]),
+        }
+    }
+
+    /// Enables or disables recording time in `ProfilePhase`.
+    ///
+    /// * `enabled` - Whether to record time.
+    pub fn set_enabled(&self, enabled: bool) {
+        PROFILER_ENABLED.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether time is recorded.
+    pub fn is_enabled(&self) -> bool {
+        PROFILER_ENABLED.load(Ordering::Relaxed)
+    }
+
+    /// Removes the recorded times before rendering a new scene.
+    pub fn clear(&self) {
+        for nanos in self.threads.lock().unwrap().iter() {
+            nanos.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the nanoseconds spent in each set of phases by all threads
+    /// keyed by the bits of the phases. Time a thread spends in phases it
+    /// has not left yet is not included.
+    pub fn phase_nanos(&self) -> BTreeMap<u64, u64> {
+        let mut totals = BTreeMap::new();
+        for nanos in self.threads.lock().unwrap().iter() {
+            for (phases, n) in nanos.lock().unwrap().iter() {
+                *totals.entry(*phases).or_default() += n;
+            }
+        }
+        totals
+    }
+
+    /// Returns a breakdown of the time spent in each phase. The first part
+    /// lists every nesting of phases with the time spent inside it; the
+    /// second lists the time spent in each phase excluding the phases nested
+    /// in it. Percentages are of the total time threads spent in phases.
+    pub fn report(&self) -> String {
+        let phase_nanos = self.phase_nanos();
+        let total: u64 = phase_nanos.values().sum();
+
+        let mut report = String::from("Profile\n");
+        if total == 0 {
+            return report;
+        }
+
+        // Add the time of each set of phases to every enclosing nesting and
+        // to its innermost phase.
+        let mut inclusive: BTreeMap<Vec<usize>, u64> = BTreeMap::new();
+        let mut exclusive = [0_u64; PHASES.len()];
+        for (phases, nanos) in phase_nanos.iter() {
+            let mut path = vec![];
+            for (i, phase) in PHASES.iter().enumerate() {
+                if phases & phase.bit() != 0 {
+                    path.push(i);
+                    *inclusive.entry(path.clone()).or_default() += nanos;
+                }
+            }
+            if let Some(&innermost) = path.last() {
+                exclusive[innermost] += nanos;
+            }
+        }
+
+        let percent = |nanos: u64| 100.0 * nanos as f64 / total as f64;
+        let _ = writeln!(
+            report,
+            "  {:<9} {:<48} {:>8} {:>12}",
+            "phases", "phase", "%", "time (ms)"
+        );
+        for (path, nanos) in inclusive.iter() {
+            let name = format!(
+                "{:indent$}{}",
+                "",
+                PHASES[path[path.len() - 1]].name(),
+                indent = 2 * (path.len() - 1)
+            );
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<48} {:>8.2} {:>12.3}",
+                "",
+                name,
+                percent(*nanos),
+                *nanos as f64 * 1e-6
+            );
+        }
+
+        let mut exclusive: Vec<(Prof, u64)> = PHASES
+            .iter()
+            .copied()
+            .zip(exclusive.iter().copied())
+            .filter(|(_, nanos)| *nanos > 0)
+            .collect();
+        exclusive.sort_by_key(|(_, nanos)| std::cmp::Reverse(*nanos));
+        let _ = writeln!(
+            report,
+            "  {:<9} {:<48} {:>8} {:>12}",
+            "exclusive", "phase", "%", "time (ms)"
+        );
+        for (phase, nanos) in exclusive {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<48} {:>8.2} {:>12.3}",
+                "",
+                phase.name(),
+                percent(nanos),
+                nanos as f64 * 1e-6
+            );
+        }
+        report
+    }
+}
+
+impl Default for Profiler {
+    /// Returns a new `Profiler`.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn nested_phases_are_reported_below_their_parent() {
+        PROFILER.set_enabled(true);
+        std::thread::spawn(|| {
+            let _outer = ProfilePhase::new(Prof::SceneConstruction);
+            std::thread::sleep(Duration::from_millis(2));
+            {
+                let _inner = ProfilePhase::new(Prof::MergeFilmTile);
+                let _again = ProfilePhase::new(Prof::MergeFilmTile);
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        })
+        .join()
+        .unwrap();
+        PROFILER.set_enabled(false);
+
+        let outer = Prof::SceneConstruction.bit();
+        let inner = outer | Prof::MergeFilmTile.bit();
+        let phase_nanos = PROFILER.phase_nanos();
+        assert!(phase_nanos[&outer] >= 2_000_000);
+        assert!(phase_nanos[&inner] >= 2_000_000);
+
+        let report = PROFILER.report();
+        let outer_line = report.find("  Scene construction").unwrap();
+        let inner_line = report.find("    Film::merge_film_tile()").unwrap();
+        assert!(outer_line < inner_line);
+        assert!(report.contains("exclusive"));
+    }
+}
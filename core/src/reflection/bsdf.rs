@@ -2,6 +2,7 @@
 
 #![allow(dead_code)]
 use super::*;
+use crate::profiler::*;
 use crate::rng::*;
 
 /// Maximum number of BxDFs that can be stored in `BSDF`.
@@ -97,6 +98,7 @@ impl BSDF {
     /// * `wi_w`      - Incident direction in world-space.
     /// * `bxdf_type` - The `BxdFType` to evaluate.
     pub fn f(&self, wo_w: &Vector3f, wi_w: &Vector3f, bxdf_type: BxDFType) -> Spectrum {
+        let _p = ProfilePhase::new(Prof::BSDFEvaluation);
         let wi = self.world_to_local(wi_w);
         let wo = self.world_to_local(wo_w);
 
@@ -115,6 +117,39 @@ impl BSDF {
         }
     }
 
+    /// Returns the light scattered towards `wo_w` for light arriving from
+    /// `wi_w`. Unlike `f()` this allows BxDFs to change the wavelength of the
+    /// incident light.
+    ///
+    /// * `wo_w`      - Outgoing direction in world-space.
+    /// * `wi_w`      - Incident direction in world-space.
+    /// * `li`        - The incident light.
+    /// * `bxdf_type` - The `BxdFType` to evaluate.
+    pub fn scatter(
+        &self,
+        wo_w: &Vector3f,
+        wi_w: &Vector3f,
+        li: &Spectrum,
+        bxdf_type: BxDFType,
+    ) -> Spectrum {
+        let wi = self.world_to_local(wi_w);
+        let wo = self.world_to_local(wo_w);
+
+        if wo.z == 0.0 {
+            Spectrum::new(0.0)
+        } else {
+            let reflect = wi_w.dot(&self.ng) * wo_w.dot(&self.ng) > 0.0;
+            self.bxdfs
+                .iter()
+                .filter(|bxdf| {
+                    bxdf.matches(bxdf_type)
+                        && ((reflect && bxdf.get_type().matches(BSDF_REFLECTION))
+                            || (!reflect && bxdf.get_type().matches(BSDF_TRANSMISSION)))
+                })
+                .fold(Spectrum::new(0.0), |a, bxdf| a + bxdf.scatter(&wo, &wi, li))
+        }
+    }
+
     /// Returns the value of the BSDF given the outgpoing direction.
     /// direction.
     ///
@@ -122,6 +157,7 @@ impl BSDF {
     /// * `u`         - The 2D uniform random values.
     /// * `bxdf_type` - The `BxdFType` to evaluate.
     pub fn sample_f(&self, wo_w: &Vector3f, u: &Point2f, bxdf_type: BxDFType) -> BxDFSample {
+        let _p = ProfilePhase::new(Prof::BSDFEvaluation);
         // Choose which `BxDF` to sample.
         let matching_comps = self.num_components(bxdf_type);
         if matching_comps == 0 {
@@ -133,16 +169,13 @@ impl BSDF {
         );
 
         // Get BxDF for chosen component.
-        let mut count = comp;
-        let mut bxdf: Option<ArcBxDF> = None;
-        for b in self.bxdfs.iter() {
-            if b.matches(bxdf_type) && count == 0 {
-                bxdf = Some(Arc::clone(b));
-                break;
-            }
-            count -= 1;
-        }
-        let bxdf = bxdf.expect("bsdf::sample_f() did not find matching bxdf");
+        let bxdf = self
+            .bxdfs
+            .iter()
+            .filter(|b| b.matches(bxdf_type))
+            .nth(comp)
+            .map(Arc::clone)
+            .expect("bsdf::sample_f() did not find matching bxdf");
 
         // Remap BxDF sample `u` to `[0,1)^2`.
         let u_remapped = Point2f::new(
@@ -156,8 +189,9 @@ impl BSDF {
             return BxDFSample::default();
         }
 
-        let sampled_type = bxdf.get_type();
+        // BxDFs with several lobes report which one they sampled.
         let sample = bxdf.sample_f(&wo, &u_remapped);
+        let sampled_type = sample.sampled_type;
         let mut pdf = sample.pdf;
         if pdf == 0.0 {
             return BxDFSample::from(sampled_type);
@@ -167,7 +201,7 @@ impl BSDF {
         // Compute overall PDF with all matching BxDFs.
         if !(bxdf.get_type().matches(BSDF_SPECULAR) && matching_comps > 1) {
             for b in self.bxdfs.iter() {
-                if Arc::ptr_eq(&b, &bxdf) && b.matches(bxdf_type) {
+                if !Arc::ptr_eq(b, &bxdf) && b.matches(bxdf_type) {
                     pdf += b.pdf(&wo, &sample.wi);
                 }
             }
@@ -176,8 +210,9 @@ impl BSDF {
             pdf /= matching_comps as Float;
         }
 
-        // Compute value of BSDF for sampled direction.
-        let f = if !(bxdf.get_type().matches(BSDF_SPECULAR)) {
+        // Compute value of BSDF for sampled direction. Specular BxDFs only
+        // have a value for their sampled direction.
+        let f = if !bxdf.get_type().matches(BSDF_SPECULAR) && matching_comps > 1 {
             let reflect = wi_world.dot(&self.ng) * wo_w.dot(&self.ng) > 0.0;
             self.bxdfs
                 .iter()
@@ -188,7 +223,7 @@ impl BSDF {
                 })
                 .fold(Spectrum::new(0.0), |a, bxdf| a + bxdf.f(&wo, &sample.wi))
         } else {
-            Spectrum::new(0.0)
+            sample.f
         };
         BxDFSample::new(f, pdf, wi_world, sampled_type)
     }
@@ -207,6 +242,21 @@ impl BSDF {
             .fold(Spectrum::new(0.0), |a, bxdf| a + bxdf.rho_hd(&wo, u))
     }
 
+    /// Returns the directional albedo of all BxDFs for the outgoing direction.
+    /// This is intended for auxiliary outputs such as the denoiser albedo AOV.
+    ///
+    /// * `wo_w` - Outgoing direction in world-space.
+    pub fn albedo(&self, wo_w: &Vector3f) -> Spectrum {
+        let wo = self.world_to_local(wo_w);
+        if wo.z == 0.0 {
+            return Spectrum::new(0.0);
+        }
+
+        self.bxdfs
+            .iter()
+            .fold(Spectrum::new(0.0), |a, bxdf| a + bxdf.albedo(&wo))
+    }
+
     /// Computes the hemispherical-hemispherical-directional reflectance function ρ.
     ///
     /// * `u1`        - Samples used b Monte Carlo algorithm.
@@ -226,6 +276,7 @@ impl BSDF {
     /// * `wi_w`      - Incident direction in world-space.
     /// * `bxdf_type` - The `BxdFType` to evaluate.
     pub fn pdf(&self, wo_w: &Vector3f, wi_w: &Vector3f, bxdf_type: BxDFType) -> Float {
+        let _p = ProfilePhase::new(Prof::BSDFEvaluation);
         if self.bxdfs.len() == 0 {
             return 0.0;
         }
@@ -248,7 +299,101 @@ impl BSDF {
             0.0
         }
     }
+
+    /// Returns the x-axis of the polarization frame for a direction. It is
+    /// perpendicular to the plane containing the direction and the normal.
+    ///
+    /// * `w` - Direction in world-space.
+    #[cfg(feature = "polarization")]
+    pub fn polarization_axis(&self, w: &Vector3f) -> Vector3f {
+        s_axis(&Vector3f::from(self.ns), w, &self.ss)
+    }
+
+    /// Returns the Mueller matrix for light arriving from `wi_w` scattered
+    /// towards `wo_w`. The frame of each direction uses the x-axis from
+    /// `polarization_axis()`. Specular components are only included if
+    /// `wi_w` is their sampled direction so they should be evaluated
+    /// separately from the others.
+    ///
+    /// * `wo_w`      - Outgoing direction in world-space.
+    /// * `wi_w`      - Incident direction in world-space.
+    /// * `bxdf_type` - The `BxdFType` to evaluate.
+    #[cfg(feature = "polarization")]
+    pub fn mueller(&self, wo_w: &Vector3f, wi_w: &Vector3f, bxdf_type: BxDFType) -> Mueller {
+        let wi = self.world_to_local(wi_w);
+        let wo = self.world_to_local(wo_w);
+
+        if wo.z == 0.0 {
+            Mueller::default()
+        } else {
+            let reflect = wi_w.dot(&self.ng) * wo_w.dot(&self.ng) > 0.0;
+            self.bxdfs
+                .iter()
+                .filter(|bxdf| {
+                    bxdf.matches(bxdf_type)
+                        && ((reflect && bxdf.get_type().matches(BSDF_REFLECTION))
+                            || (!reflect && bxdf.get_type().matches(BSDF_TRANSMISSION)))
+                })
+                .fold(Mueller::default(), |a, bxdf| a + bxdf.mueller(&wo, &wi))
+        }
+    }
 }
 
 /// Atomic reference counted `BSDF`.
 pub type ArcBSDF = Arc<BSDF>;
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_f_pdf_matches_pdf() {
+        let identity = Arc::new(Transform::default());
+        let shape_data = Arc::new(ShapeData::new(Arc::clone(&identity), Some(identity), false));
+        let si = SurfaceInteraction::new(
+            Point3f::default(),
+            Vector3f::default(),
+            Point2f::default(),
+            Vector3f::new(0.0, 0.0, 1.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Normal3f::default(),
+            Normal3f::default(),
+            0.0,
+            shape_data,
+            None,
+        );
+        let wo = Vector3f::new(0.0, 0.6, 0.8);
+        let all = BxDFType::from(BSDF_ALL);
+
+        // A single lobe is sampled with its own density.
+        let mut bsdf = BSDF::new(&si, None);
+        bsdf.add(Arc::new(LambertianReflection::new(Spectrum::new(0.5))));
+        let sample = bsdf.sample_f(&wo, &Point2f::new(0.3, 0.7), all);
+        assert!((sample.pdf - bsdf.pdf(&wo, &sample.wi, all)).abs() < 1e-6);
+        assert!((sample.pdf - sample.wi.z.abs() * INV_PI).abs() < 1e-6);
+
+        // Multiple lobes are sampled with the average of their densities.
+        bsdf.add(Arc::new(OrenNayar::new(Spectrum::new(0.5), 20.0)));
+        let sample = bsdf.sample_f(&wo, &Point2f::new(0.3, 0.7), all);
+        assert!((sample.pdf - bsdf.pdf(&wo, &sample.wi, all)).abs() < 1e-6);
+
+        // A lone specular lobe keeps the value for its sampled direction and
+        // components that don't match the type are skipped.
+        let mut bsdf = BSDF::new(&si, None);
+        bsdf.add(Arc::new(LambertianReflection::new(Spectrum::new(0.5))));
+        bsdf.add(Arc::new(SpecularReflection::new(
+            Spectrum::new(1.0),
+            Arc::new(FresnelNoOp::new()),
+        )));
+        let specular = BxDFType::from(BSDF_REFLECTION | BSDF_SPECULAR);
+        let sample = bsdf.sample_f(&wo, &Point2f::new(0.3, 0.7), specular);
+        assert_eq!(sample.pdf, 1.0);
+        assert!((sample.f[0] - 1.0 / wo.z).abs() < 1e-5);
+        assert!(sample.sampled_type.matches(BSDF_SPECULAR));
+    }
+}
@@ -0,0 +1,121 @@
+//! Fluorescent Reflection
+
+#![allow(dead_code)]
+
+use super::*;
+
+/// BRDF for diffuse fluorescent surfaces that absorb light at some
+/// wavelengths and re-emit it equally in all directions at other wavelengths.
+#[derive(Clone)]
+pub struct FluorescentReflection {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Describes the re-emitted light for each wavelength of incident light.
+    reradiation: Arc<ReradiationMatrix>,
+
+    /// Scale applied to the re-emitted light.
+    scale: Float,
+
+    /// Light re-emitted for incident light with unit value at all wavelengths.
+    white: Spectrum,
+}
+
+impl FluorescentReflection {
+    /// Create a new instance of `FluorescentReflection`.
+    ///
+    /// * `reradiation` - Describes the re-emitted light for each wavelength of
+    ///                   incident light.
+    /// * `scale`       - Scale applied to the re-emitted light.
+    pub fn new(reradiation: Arc<ReradiationMatrix>, scale: Float) -> Self {
+        let white = reradiation.apply(&Spectrum::new(1.0)) * scale;
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_DIFFUSE),
+            reradiation,
+            scale,
+            white,
+        }
+    }
+}
+
+impl BxDF for FluorescentReflection {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions. Since the re-emitted light depends on the spectrum of the
+    /// incident light, this is the value for light with unit value at all
+    /// wavelengths.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, _wo: &Vector3f, _wi: &Vector3f) -> Spectrum {
+        self.white * INV_PI
+    }
+
+    /// Returns the light re-emitted towards `wo` for light arriving from `wi`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    /// * `li` - The incident light.
+    fn scatter(&self, _wo: &Vector3f, _wi: &Vector3f, li: &Spectrum) -> Spectrum {
+        self.reradiation.apply(li) * (self.scale * INV_PI)
+    }
+
+    /// Computes the hemispherical-directional reflectance function ρ.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `u`  - Samples used by Monte Carlo algorithm.
+    fn rho_hd(&self, _wo: &Vector3f, _u: &[Point2f]) -> Spectrum {
+        self.white
+    }
+
+    /// Returns the directional albedo for the outgoing direction.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, _wo: &Vector3f) -> Spectrum {
+        self.white
+    }
+
+    /// Computes the hemispherical-hemispherical-directional reflectance function ρ.
+    ///
+    /// * `u1` - Samples used b Monte Carlo algorithm.
+    /// * `u2` - Samples used b Monte Carlo algorithm.
+    fn rho_hh(&self, u1: &[Point2f], u2: &[Point2f]) -> Spectrum {
+        assert!(u1.len() == u2.len());
+        self.white
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fluorescence_changes_wavelength() {
+        let m = ReradiationMatrix::new(&[440.0, 460.0], &[540.0, 560.0], &[0.05; 4]).unwrap();
+        let bxdf = FluorescentReflection::new(Arc::new(m), 1.0);
+        let wo = Vector3f::new(0.0, 0.0, 1.0);
+        let wi = Vector3f::new(0.0, 0.6, 0.8);
+
+        // Blue light is re-emitted as green light.
+        let mut blue = Spectrum::new(0.0);
+        blue[10] = 1.0;
+        let l = bxdf.scatter(&wo, &wi, &blue);
+        assert_eq!(l[10], 0.0);
+        assert!(l[30] > 0.0);
+
+        // Green light is not absorbed.
+        let mut green = Spectrum::new(0.0);
+        green[30] = 1.0;
+        assert!(bxdf.scatter(&wo, &wi, &green).is_black());
+
+        assert!(bxdf.f(&wo, &wi)[30] > 0.0);
+    }
+}
@@ -74,7 +74,10 @@ impl BxDF for FourierBSDF {
                 // Add contribution of `(a, b)` to `ak` values.
                 let weight = weights_i[a] * weights_o[b];
                 if weight != 0.0 {
-                    let (m, ap) = self.bsdf_table.get_ak(offset_i + a, offset_o + b);
+                    let (m, ap) = self.bsdf_table.get_ak(
+                        (offset_i + a as isize) as usize,
+                        (offset_o + b as isize) as usize,
+                    );
                     m_max = max(m_max, m);
                     for c in 0..self.bsdf_table.n_channels {
                         for k in 0..m {
@@ -157,7 +160,10 @@ impl BxDF for FourierBSDF {
                 // Add contribution of `(a, b)` to `ak` values.
                 let weight = weights_i[a] * weights_o[b];
                 if weight != 0.0 {
-                    let (m, ap) = self.bsdf_table.get_ak(offset_i + a, offset_o + b);
+                    let (m, ap) = self.bsdf_table.get_ak(
+                        (offset_i + a as isize) as usize,
+                        (offset_o + b as isize) as usize,
+                    );
                     m_max = max(m_max, m);
                     for c in 0..self.bsdf_table.n_channels {
                         for k in 0..m {
@@ -257,7 +263,10 @@ impl BxDF for FourierBSDF {
                     continue;
                 }
 
-                let (order, coeffs) = self.bsdf_table.get_ak(offset_i + i, offset_o + o);
+                let (order, coeffs) = self.bsdf_table.get_ak(
+                    (offset_i + i as isize) as usize,
+                    (offset_o + o as isize) as usize,
+                );
                 m_max = max(m_max, order);
 
                 for k in 0..order {
@@ -272,7 +281,8 @@ impl BxDF for FourierBSDF {
             if weights_o[o] == 0.0 {
                 a
             } else {
-                a + weights_o[o] * self.bsdf_table.cdf[(offset_o + o) * n_mu + n_mu - 1] * TWO_PI
+                let offset = (offset_o + o as isize) as usize;
+                a + weights_o[o] * self.bsdf_table.cdf[offset * n_mu + n_mu - 1] * TWO_PI
             }
         });
 
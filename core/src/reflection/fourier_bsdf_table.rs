@@ -5,8 +5,9 @@ use crate::interpolation::*;
 use crate::pbrt::*;
 use std::str;
 
-/// Stores the measured Fourier BSDF data.
-#[derive(Clone, Debug)]
+/// Stores the measured Fourier BSDF data. The default table has no channels
+/// and is used in place of a table that could not be loaded.
+#[derive(Clone, Debug, Default)]
 pub struct FourierBSDFTable {
     /// Relative index of refraction over the surface boundary between two media.
     pub eta: Float,
@@ -127,7 +128,95 @@ impl FourierBSDFTable {
     /// Returns Catmull-Rom weights and index offset for a given zenith angle.
     ///
     /// * `cos_theta` - The zenith angle to interpolate from `mu`.
-    pub fn get_weights_and_offset(&self, cos_theta: Float) -> Option<([Float; 4], usize)> {
+    pub fn get_weights_and_offset(&self, cos_theta: Float) -> Option<([Float; 4], isize)> {
         catmull_rom_weights(&self.mu, cos_theta)
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::*;
+    use crate::material::*;
+    use crate::reflection::*;
+    use std::fs;
+
+    /// Writes a monochromatic table for a Lambertian reflector with
+    /// reflectance `r` and returns its path.
+    fn write_lambertian_table(name: &str, r: Float) -> String {
+        let mu: Vec<f32> = vec![-1.0, -0.5, 0.0, 0.5, 1.0];
+        let n_mu = mu.len();
+
+        let mut bytes = b"SCATFUN\x01".to_vec();
+        let put_i32 = |bytes: &mut Vec<u8>, v: i32| bytes.extend(&v.to_ne_bytes());
+        for v in &[1, n_mu as i32, (n_mu * n_mu) as i32, 1, 1, 1, 0, 0, 0] {
+            put_i32(&mut bytes, *v);
+        }
+        bytes.extend(&1.0_f32.to_ne_bytes());
+        for _ in 0..4 {
+            put_i32(&mut bytes, 0);
+        }
+        for v in mu.iter() {
+            bytes.extend(&v.to_ne_bytes());
+        }
+        for _ in 0..n_mu * n_mu {
+            bytes.extend(&0.0_f32.to_ne_bytes());
+        }
+        for i in 0..n_mu * n_mu {
+            put_i32(&mut bytes, i as i32);
+            put_i32(&mut bytes, 1);
+        }
+        // Coefficients are stored by outgoing then incident direction and
+        // include the cosine of the incident direction.
+        for mu_o in mu.iter() {
+            for mu_i in mu.iter() {
+                let a0 = if mu_i * mu_o < 0.0 {
+                    r * INV_PI * abs(*mu_i)
+                } else {
+                    0.0
+                };
+                bytes.extend(&a0.to_ne_bytes());
+            }
+        }
+
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, bytes).unwrap();
+        String::from(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn reads_and_evaluates_table() {
+        let path = write_lambertian_table("fourier_bsdf_table_test.bsdf", 0.5);
+        let table = FourierBSDFTable::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(table.n_channels, 1);
+        assert_eq!(table.m_max, 1);
+        assert_eq!(table.mu.len(), 5);
+        assert_eq!(table.eta, 1.0);
+
+        let bsdf = FourierBSDF::new(Arc::new(table), TransportMode::Radiance);
+        let sin_theta = (0.75 as Float).sqrt();
+        let wo = Vector3f::new(sin_theta, 0.0, 0.5);
+        let wi = Vector3f::new(0.0, sin_theta, 0.5);
+        let expected = Spectrum::new(0.5 * INV_PI).y();
+        assert!((bsdf.f(&wo, &wi).y() - expected).abs() < 1e-5);
+
+        // No transmission.
+        let wi = Vector3f::new(0.0, sin_theta, -0.5);
+        assert_eq!(bsdf.f(&wo, &wi).y(), 0.0);
+    }
+
+    #[test]
+    fn rejects_invalid_header() {
+        let path = std::env::temp_dir().join("fourier_bsdf_table_invalid.bsdf");
+        fs::write(&path, b"NOTABSDF").unwrap();
+        let result = FourierBSDFTable::from_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
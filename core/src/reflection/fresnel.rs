@@ -13,6 +13,16 @@ pub trait Fresnel {
     /// * `cos_thata_i` - Cosine of the angle made by incident direction and
     ///                   surface normal.
     fn evaluate(&self, cos_theta_i: Float) -> Spectrum;
+
+    /// Returns the Mueller matrix for light reflected by the surface. Default
+    /// is to depolarize with the value of `evaluate()`.
+    ///
+    /// * `cos_thata_i` - Cosine of the angle made by incident direction and
+    ///                   surface normal.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, cos_theta_i: Float) -> Mueller {
+        Mueller::depolarizer(self.evaluate(cos_theta_i))
+    }
 }
 
 /// Atomic reference counted `Fresnel`.
@@ -46,6 +56,20 @@ impl Fresnel for FresnelDielectric {
     fn evaluate(&self, cos_theta_i: Float) -> Spectrum {
         Spectrum::new(fr_dielectric(cos_theta_i, self.eta_i, self.eta_t))
     }
+
+    /// Returns the Mueller matrix for light reflected by the surface.
+    ///
+    /// * `cos_thata_i` - Cosine of the angle made by incident direction and
+    ///                   surface normal.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, cos_theta_i: Float) -> Mueller {
+        Mueller::fresnel_reflection(
+            cos_theta_i,
+            Spectrum::new(self.eta_i),
+            Spectrum::new(self.eta_t),
+            Spectrum::new(0.0),
+        )
+    }
 }
 
 /// Implements `Fresnel` for conductors materials.
@@ -82,6 +106,15 @@ impl Fresnel for FresnelConductor {
         // normal.
         fr_conductor(abs(cos_theta_i), self.eta_i, self.eta_t, self.k)
     }
+
+    /// Returns the Mueller matrix for light reflected by the surface.
+    ///
+    /// * `cos_thata_i` - Cosine of the angle made by incident direction and
+    ///                   surface normal.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, cos_theta_i: Float) -> Mueller {
+        Mueller::fresnel_reflection(abs(cos_theta_i), self.eta_i, self.eta_t, self.k)
+    }
 }
 
 /// Implements `Fresnel` for materials that reflect 100% of all incoming light.
@@ -102,6 +135,15 @@ impl Fresnel for FresnelNoOp {
     fn evaluate(&self, _cos_theta_i: Float) -> Spectrum {
         Spectrum::new(1.0)
     }
+
+    /// Returns the Mueller matrix for light reflected by the surface.
+    ///
+    /// * `cos_thata_i` - Cosine of the angle made by incident direction and
+    ///                   surface normal.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, _cos_theta_i: Float) -> Mueller {
+        Mueller::identity()
+    }
 }
 
 /// returns the fresnel reflection for dielectric materials and unpolarized light.
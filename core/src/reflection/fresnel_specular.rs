@@ -110,4 +110,23 @@ impl BxDF for FresnelSpecular {
             }
         }
     }
+
+    /// Returns the Mueller matrix for light arriving from the sampled
+    /// direction `wi` scattered towards `wo`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        if same_hemisphere(wo, wi) {
+            Mueller::fresnel_reflection(
+                cos_theta(wi),
+                Spectrum::new(self.eta_a),
+                Spectrum::new(self.eta_b),
+                Spectrum::new(0.0),
+            ) * (self.r / abs_cos_theta(wi))
+        } else {
+            transmission_mueller(wo, wi, self.t, self.eta_a, self.eta_b, self.mode)
+        }
+    }
 }
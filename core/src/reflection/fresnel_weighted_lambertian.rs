@@ -0,0 +1,78 @@
+//! Fresnel Weighted Lambertian Reflection
+
+#![allow(dead_code)]
+
+use super::*;
+
+/// BRDF for a Lambertian base layer beneath a smooth dielectric coating. The
+/// diffuse reflection is weighted by the fraction of light transmitted through
+/// the coating on the way in and out so it fades at grazing angles where the
+/// coating's specular reflection dominates.
+#[derive(Clone)]
+pub struct FresnelWeightedLambertian {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Reflectance spectrum of the base layer.
+    r: Spectrum,
+
+    /// Index of refraction of the coating.
+    eta: Float,
+}
+
+impl FresnelWeightedLambertian {
+    /// Create a new instance of `FresnelWeightedLambertian`.
+    ///
+    /// * `r`   - Reflectance spectrum of the base layer.
+    /// * `eta` - Index of refraction of the coating.
+    pub fn new(r: Spectrum, eta: Float) -> Self {
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_DIFFUSE),
+            r,
+            eta,
+        }
+    }
+
+    /// Returns the fraction of light transmitted through the coating.
+    ///
+    /// * `w` - The direction.
+    fn transmittance(&self, w: &Vector3f) -> Float {
+        1.0 - fr_dielectric(abs_cos_theta(w), 1.0, self.eta)
+    }
+}
+
+impl BxDF for FresnelWeightedLambertian {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        self.r * (INV_PI * self.transmittance(wo) * self.transmittance(wi))
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grazing_angles_are_darker() {
+        let bxdf = FresnelWeightedLambertian::new(Spectrum::new(1.0), 1.5);
+        let normal = Vector3f::new(0.0, 0.0, 1.0);
+        let grazing = Vector3f::new(0.995, 0.0, 0.1).normalize();
+        let e_normal = bxdf.albedo(&normal)[0];
+        let e_grazing = bxdf.albedo(&grazing)[0];
+        assert!(e_normal < 1.0 && e_normal > 0.8, "E = {}", e_normal);
+        assert!(e_grazing < e_normal);
+    }
+}
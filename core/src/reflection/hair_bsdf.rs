@@ -0,0 +1,650 @@
+//! Hair Scattering Model
+
+use super::*;
+
+/// Number of scattering lobes modeled explicitly. Light that scatters more
+/// often than this is handled by a single remaining lobe.
+const P_MAX: usize = 3;
+
+/// Constant √(π/8) used to convert the azimuthal roughness to the logistic
+/// scale factor.
+const SQRT_PI_OVER_8: Float = 0.626_657_07;
+
+/// Absorption coefficients of eumelanin for RGB at unit concentration.
+const EUMELANIN_SIGMA_A: [Float; 3] = [0.419, 0.697, 1.37];
+
+/// Absorption coefficients of pheomelanin for RGB at unit concentration.
+const PHEOMELANIN_SIGMA_A: [Float; 3] = [0.187, 0.4, 1.05];
+
+/// BSDF for the Marschner hair scattering model with the extensions by
+/// d'Eon et al. and Chiang et al. Hair is modeled as a dielectric cylinder
+/// with an absorbing interior and cuticle scales that tilt the reflected
+/// lobes.
+///
+/// The local coordinate system has the x-axis along the hair and the
+/// z-axis along the surface normal.
+#[derive(Clone)]
+pub struct HairBSDF {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Offset across the width of the hair in [-1, 1].
+    h: Float,
+
+    /// Angle between the incident direction and the hair surface normal in
+    /// the azimuthal plane.
+    gamma_o: Float,
+
+    /// Index of refraction of the hair interior.
+    eta: Float,
+
+    /// Absorption coefficient of the hair interior.
+    sigma_a: Spectrum,
+
+    /// Longitudinal roughness.
+    beta_m: Float,
+
+    /// Azimuthal roughness.
+    beta_n: Float,
+
+    /// Longitudinal variance for each lobe.
+    v: [Float; P_MAX + 1],
+
+    /// Logistic scale factor for the azimuthal distribution.
+    s: Float,
+
+    /// sin(2^k α) for the cuticle scale angle α.
+    sin_2k_alpha: [Float; 3],
+
+    /// cos(2^k α) for the cuticle scale angle α.
+    cos_2k_alpha: [Float; 3],
+}
+
+impl HairBSDF {
+    /// Create a new instance of `HairBSDF`.
+    ///
+    /// * `h`       - Offset across the width of the hair in [-1, 1].
+    /// * `eta`     - Index of refraction of the hair interior.
+    /// * `sigma_a` - Absorption coefficient of the hair interior.
+    /// * `beta_m`  - Longitudinal roughness in [0, 1].
+    /// * `beta_n`  - Azimuthal roughness in [0, 1].
+    /// * `alpha`   - Angle of the cuticle scales in degrees.
+    pub fn new(
+        h: Float,
+        eta: Float,
+        sigma_a: Spectrum,
+        beta_m: Float,
+        beta_n: Float,
+        alpha: Float,
+    ) -> Self {
+        debug_assert!((-1.0..=1.0).contains(&h));
+        debug_assert!((0.0..=1.0).contains(&beta_m));
+        debug_assert!((0.0..=1.0).contains(&beta_n));
+
+        // Compute longitudinal variance from `beta_m`.
+        let v0 = sqr(0.726 * beta_m + 0.812 * sqr(beta_m) + 3.7 * beta_m.powi(20));
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+
+        // Compute azimuthal logistic scale factor from `beta_n`.
+        let s = SQRT_PI_OVER_8 * (0.265 * beta_n + 1.194 * sqr(beta_n) + 5.372 * beta_n.powi(22));
+
+        // Compute α terms for hair scales.
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = sin(alpha.to_radians());
+        cos_2k_alpha[0] = safe_sqrt(1.0 - sqr(sin_2k_alpha[0]));
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = sqr(cos_2k_alpha[i - 1]) - sqr(sin_2k_alpha[i - 1]);
+        }
+
+        Self {
+            bxdf_type: BxDFType::from(BSDF_GLOSSY | BSDF_REFLECTION | BSDF_TRANSMISSION),
+            h,
+            gamma_o: safe_asin(h),
+            eta,
+            sigma_a,
+            beta_m,
+            beta_n,
+            v,
+            s,
+            sin_2k_alpha,
+            cos_2k_alpha,
+        }
+    }
+
+    /// Returns the absorption coefficient for the given concentrations of
+    /// eumelanin and pheomelanin pigments.
+    ///
+    /// * `ce` - Concentration of eumelanin.
+    /// * `cp` - Concentration of pheomelanin.
+    pub fn sigma_a_from_concentration(ce: Float, cp: Float) -> Spectrum {
+        let mut sigma_a = [0.0; 3];
+        for i in 0..3 {
+            sigma_a[i] = ce * EUMELANIN_SIGMA_A[i] + cp * PHEOMELANIN_SIGMA_A[i];
+        }
+        Spectrum::from_rgb(&sigma_a, None)
+    }
+
+    /// Returns the absorption coefficient that gives approximately the
+    /// desired hair color after multiple scattering.
+    ///
+    /// * `c`      - The desired color.
+    /// * `beta_n` - Azimuthal roughness.
+    pub fn sigma_a_from_reflectance(c: &Spectrum, beta_n: Float) -> Spectrum {
+        let d = 5.969 - 0.215 * beta_n + 2.532 * sqr(beta_n) - 10.73 * beta_n.powi(3)
+            + 5.574 * beta_n.powi(4)
+            + 0.245 * beta_n.powi(5);
+        let mut sigma_a = *c;
+        for s in sigma_a.samples_mut() {
+            *s = sqr(s.ln() / d);
+        }
+        sigma_a
+    }
+
+    /// Returns sin(θo) and cos(θo) for the outgoing direction rotated by the
+    /// cuticle scale angle for a given lobe.
+    ///
+    /// * `p`           - The lobe.
+    /// * `sin_theta_o` - sin(θo).
+    /// * `cos_theta_o` - cos(θo).
+    fn rotate_for_scales(
+        &self,
+        p: usize,
+        sin_theta_o: Float,
+        cos_theta_o: Float,
+    ) -> (Float, Float) {
+        let (sin_theta_op, cos_theta_op) = match p {
+            0 => (
+                sin_theta_o * self.cos_2k_alpha[1] - cos_theta_o * self.sin_2k_alpha[1],
+                cos_theta_o * self.cos_2k_alpha[1] + sin_theta_o * self.sin_2k_alpha[1],
+            ),
+            1 => (
+                sin_theta_o * self.cos_2k_alpha[0] + cos_theta_o * self.sin_2k_alpha[0],
+                cos_theta_o * self.cos_2k_alpha[0] - sin_theta_o * self.sin_2k_alpha[0],
+            ),
+            2 => (
+                sin_theta_o * self.cos_2k_alpha[2] + cos_theta_o * self.sin_2k_alpha[2],
+                cos_theta_o * self.cos_2k_alpha[2] - sin_theta_o * self.sin_2k_alpha[2],
+            ),
+            _ => (sin_theta_o, cos_theta_o),
+        };
+        (sin_theta_op, abs(cos_theta_op))
+    }
+
+    /// Returns the refracted azimuthal angle γt for the outgoing direction.
+    ///
+    /// * `sin_theta_o` - sin(θo).
+    /// * `cos_theta_o` - cos(θo).
+    fn gamma_t(&self, sin_theta_o: Float, cos_theta_o: Float) -> Float {
+        let etap = (self.eta * self.eta - sqr(sin_theta_o)).sqrt() / cos_theta_o;
+        safe_asin(self.h / etap)
+    }
+
+    /// Returns the transmittance of a single path through the hair interior.
+    ///
+    /// * `sin_theta_o` - sin(θo).
+    /// * `cos_theta_o` - cos(θo).
+    fn transmittance(&self, sin_theta_o: Float, cos_theta_o: Float) -> Spectrum {
+        // Compute cos(θt) for refracted ray.
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = safe_sqrt(1.0 - sqr(sin_theta_t));
+
+        // Compute cos(γt) for refracted ray.
+        let cos_gamma_t = cos(self.gamma_t(sin_theta_o, cos_theta_o));
+
+        (-self.sigma_a * (2.0 * cos_gamma_t / cos_theta_t)).exp()
+    }
+
+    /// Returns the probability of sampling each lobe for the outgoing
+    /// direction.
+    ///
+    /// * `cos_theta_o` - cos(θo).
+    fn compute_ap_pdf(&self, cos_theta_o: Float) -> [Float; P_MAX + 1] {
+        let sin_theta_o = safe_sqrt(1.0 - cos_theta_o * cos_theta_o);
+        let t = self.transmittance(sin_theta_o, cos_theta_o);
+        let ap = ap(cos_theta_o, self.eta, self.h, &t);
+
+        // Compute PDF for the lobes from their luminance.
+        let sum_y: Float = ap.iter().map(|a| a.y()).sum();
+        let mut ap_pdf = [0.0; P_MAX + 1];
+        for i in 0..=P_MAX {
+            ap_pdf[i] = ap[i].y() / sum_y;
+        }
+        ap_pdf
+    }
+
+    /// Returns the PDF of sampling the incident direction from the lobe
+    /// probabilities.
+    ///
+    /// * `wo`     - Outgoing direction.
+    /// * `wi`     - Incident direction.
+    /// * `ap_pdf` - The probability of sampling each lobe.
+    fn lobe_pdf(&self, wo: &Vector3f, wi: &Vector3f, ap_pdf: &[Float; P_MAX + 1]) -> Float {
+        let sin_theta_o = wo.x;
+        let cos_theta_o = safe_sqrt(1.0 - sqr(sin_theta_o));
+        let phi_o = atan2(wo.z, wo.y);
+
+        let sin_theta_i = wi.x;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+        let phi_i = atan2(wi.z, wi.y);
+
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+        let dphi = phi_i - phi_o;
+
+        let mut pdf = 0.0;
+        for (p, ap_p) in ap_pdf.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.rotate_for_scales(p, sin_theta_o, cos_theta_o);
+            pdf += mp(
+                cos_theta_i,
+                cos_theta_op,
+                sin_theta_i,
+                sin_theta_op,
+                self.v[p],
+            ) * ap_p
+                * np(dphi, p, self.s, self.gamma_o, gamma_t);
+        }
+        pdf += mp(
+            cos_theta_i,
+            cos_theta_o,
+            sin_theta_i,
+            sin_theta_o,
+            self.v[P_MAX],
+        ) * ap_pdf[P_MAX]
+            * INV_TWO_PI;
+        pdf
+    }
+}
+
+impl BxDF for HairBSDF {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        // Compute hair coordinate system terms related to `wo`.
+        let sin_theta_o = wo.x;
+        let cos_theta_o = safe_sqrt(1.0 - sqr(sin_theta_o));
+        let phi_o = atan2(wo.z, wo.y);
+
+        // Compute hair coordinate system terms related to `wi`.
+        let sin_theta_i = wi.x;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+        let phi_i = atan2(wi.z, wi.y);
+
+        // Compute the attenuation of each lobe.
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+        let t = self.transmittance(sin_theta_o, cos_theta_o);
+        let phi = phi_i - phi_o;
+        let ap = ap(cos_theta_o, self.eta, self.h, &t);
+
+        // Evaluate hair BSDF.
+        let mut fsum = Spectrum::new(0.0);
+        for (p, ap_p) in ap.iter().enumerate().take(P_MAX) {
+            let (sin_theta_op, cos_theta_op) = self.rotate_for_scales(p, sin_theta_o, cos_theta_o);
+            fsum += *ap_p
+                * mp(
+                    cos_theta_i,
+                    cos_theta_op,
+                    sin_theta_i,
+                    sin_theta_op,
+                    self.v[p],
+                )
+                * np(phi, p, self.s, self.gamma_o, gamma_t);
+        }
+
+        // Compute contribution of remaining terms after `P_MAX`.
+        fsum += ap[P_MAX]
+            * mp(
+                cos_theta_i,
+                cos_theta_o,
+                sin_theta_i,
+                sin_theta_o,
+                self.v[P_MAX],
+            )
+            * INV_TWO_PI;
+
+        let abs_cos_theta_i = abs_cos_theta(wi);
+        if abs_cos_theta_i > 0.0 {
+            fsum /= abs_cos_theta_i;
+        }
+        fsum
+    }
+
+    /// Returns the value of the BxDF given the outgpoing direction.
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `u`  - The 2D uniform random values.
+    fn sample_f(&self, wo: &Vector3f, u: &Point2f) -> BxDFSample {
+        // Compute hair coordinate system terms related to `wo`.
+        let sin_theta_o = wo.x;
+        let cos_theta_o = safe_sqrt(1.0 - sqr(sin_theta_o));
+        let phi_o = atan2(wo.z, wo.y);
+
+        // Derive four random samples from `u`.
+        let mut u0 = demux_float(u[0]);
+        let u1 = demux_float(u[1]);
+
+        // Determine which term `p` to sample for hair scattering.
+        let ap_pdf = self.compute_ap_pdf(cos_theta_o);
+        let mut p = 0;
+        while p < P_MAX {
+            if u0[0] < ap_pdf[p] {
+                break;
+            }
+            u0[0] -= ap_pdf[p];
+            p += 1;
+        }
+
+        // Rotate sin(θo) and cos(θo) to account for hair scale tilt.
+        let (sin_theta_op, cos_theta_op) = self.rotate_for_scales(p, sin_theta_o, cos_theta_o);
+
+        // Sample Mp to compute θi.
+        let u10 = max(u1[0], 1e-5);
+        let cos_theta = 1.0 + self.v[p] * (u10 + (1.0 - u10) * (-2.0 / self.v[p]).exp()).ln();
+        let sin_theta = safe_sqrt(1.0 - sqr(cos_theta));
+        let cos_phi = cos(TWO_PI * u1[1]);
+        let sin_theta_i = -cos_theta * sin_theta_op + sin_theta * cos_phi * cos_theta_op;
+        let cos_theta_i = safe_sqrt(1.0 - sqr(sin_theta_i));
+
+        // Sample Np to compute Δϕ.
+        let gamma_t = self.gamma_t(sin_theta_o, cos_theta_o);
+        let dphi = if p < P_MAX {
+            phi(p, self.gamma_o, gamma_t) + sample_trimmed_logistic(u0[1], self.s, -PI, PI)
+        } else {
+            TWO_PI * u0[1]
+        };
+
+        // Compute `wi` from sampled hair scattering angles.
+        let phi_i = phi_o + dphi;
+        let wi = Vector3f::new(
+            sin_theta_i,
+            cos_theta_i * cos(phi_i),
+            cos_theta_i * sin(phi_i),
+        );
+
+        let pdf = self.lobe_pdf(wo, &wi, &ap_pdf);
+        BxDFSample::new(self.f(wo, &wi), pdf, wi, self.bxdf_type)
+    }
+
+    /// Evaluates the PDF for the sampling method.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn pdf(&self, wo: &Vector3f, wi: &Vector3f) -> Float {
+        let cos_theta_o = safe_sqrt(1.0 - sqr(wo.x));
+        let ap_pdf = self.compute_ap_pdf(cos_theta_o);
+        self.lobe_pdf(wo, wi, &ap_pdf)
+    }
+}
+
+/// Returns `x^2`.
+///
+/// * `x` - The value.
+#[inline]
+fn sqr(x: Float) -> Float {
+    x * x
+}
+
+/// Returns the square root clamping negative values to 0.
+///
+/// * `x` - The value.
+#[inline]
+fn safe_sqrt(x: Float) -> Float {
+    max(0.0, x).sqrt()
+}
+
+/// Returns the arcsine clamping the value to [-1, 1].
+///
+/// * `x` - The value.
+#[inline]
+fn safe_asin(x: Float) -> Float {
+    asin(clamp(x, -1.0, 1.0))
+}
+
+/// Returns the modified Bessel function of the first kind of order 0.
+///
+/// * `x` - The value.
+fn i0(x: Float) -> Float {
+    let mut val = 0.0;
+    let mut x2i = 1.0;
+    let mut ifact: i64 = 1;
+    let mut i4: i64 = 1;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= i;
+        }
+        val += x2i / (i4 as Float * sqr(ifact as Float));
+        x2i *= x * x;
+        i4 *= 4;
+    }
+    val
+}
+
+/// Returns the natural logarithm of `i0()` avoiding overflow for large values.
+///
+/// * `x` - The value.
+fn log_i0(x: Float) -> Float {
+    if x > 12.0 {
+        x + 0.5 * (-(TWO_PI.ln()) + (1.0 / x).ln() + 1.0 / (8.0 * x))
+    } else {
+        i0(x).ln()
+    }
+}
+
+/// Returns the longitudinal scattering function.
+///
+/// * `cos_theta_i` - cos(θi).
+/// * `cos_theta_o` - cos(θo).
+/// * `sin_theta_i` - sin(θi).
+/// * `sin_theta_o` - sin(θo).
+/// * `v`           - The longitudinal variance.
+fn mp(
+    cos_theta_i: Float,
+    cos_theta_o: Float,
+    sin_theta_i: Float,
+    sin_theta_o: Float,
+    v: Float,
+) -> Float {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        (log_i0(a) - b - 1.0 / v + std::f32::consts::LN_2 + (1.0 / (2.0 * v)).ln()).exp()
+    } else {
+        ((-b).exp() * i0(a)) / ((1.0 / v).sinh() * 2.0 * v)
+    }
+}
+
+/// Returns the attenuation for each lobe due to reflection and absorption.
+///
+/// * `cos_theta_o` - cos(θo).
+/// * `eta`         - Index of refraction of the hair interior.
+/// * `h`           - Offset across the width of the hair.
+/// * `t`           - Transmittance of a single path through the interior.
+fn ap(cos_theta_o: Float, eta: Float, h: Float, t: &Spectrum) -> [Spectrum; P_MAX + 1] {
+    // Compute p = 0 attenuation at initial cylinder intersection.
+    let cos_gamma_o = safe_sqrt(1.0 - h * h);
+    let cos_theta = cos_theta_o * cos_gamma_o;
+    let f = fr_dielectric(cos_theta, 1.0, eta);
+
+    let mut ap = [Spectrum::new(0.0); P_MAX + 1];
+    ap[0] = Spectrum::new(f);
+
+    // Compute p = 1 attenuation term.
+    ap[1] = sqr(1.0 - f) * *t;
+
+    // Compute attenuation terms up to p = `P_MAX`.
+    for p in 2..P_MAX {
+        ap[p] = ap[p - 1] * *t * f;
+    }
+
+    // Compute attenuation term accounting for remaining orders of scattering.
+    ap[P_MAX] = ap[P_MAX - 1] * f * *t / (Spectrum::new(1.0) - *t * f);
+    ap
+}
+
+/// Returns the net change in azimuthal direction for a lobe.
+///
+/// * `p`       - The lobe.
+/// * `gamma_o` - Angle γo of the outgoing direction.
+/// * `gamma_t` - Angle γt of the refracted direction.
+#[inline]
+fn phi(p: usize, gamma_o: Float, gamma_t: Float) -> Float {
+    let p = p as Float;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+/// Returns the logistic distribution.
+///
+/// * `x` - The value.
+/// * `s` - The scale factor.
+#[inline]
+fn logistic(x: Float, s: Float) -> Float {
+    let x = abs(x);
+    (-x / s).exp() / (s * sqr(1.0 + (-x / s).exp()))
+}
+
+/// Returns the cumulative distribution function of the logistic distribution.
+///
+/// * `x` - The value.
+/// * `s` - The scale factor.
+#[inline]
+fn logistic_cdf(x: Float, s: Float) -> Float {
+    1.0 / (1.0 + (-x / s).exp())
+}
+
+/// Returns the logistic distribution normalized over the range [a, b].
+///
+/// * `x` - The value.
+/// * `s` - The scale factor.
+/// * `a` - Lower bound.
+/// * `b` - Upper bound.
+#[inline]
+fn trimmed_logistic(x: Float, s: Float, a: Float, b: Float) -> Float {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+/// Returns the azimuthal scattering function for a lobe.
+///
+/// * `phi_d`   - The azimuthal angle difference.
+/// * `p`       - The lobe.
+/// * `s`       - The logistic scale factor.
+/// * `gamma_o` - Angle γo of the outgoing direction.
+/// * `gamma_t` - Angle γt of the refracted direction.
+fn np(phi_d: Float, p: usize, s: Float, gamma_o: Float, gamma_t: Float) -> Float {
+    let mut dphi = phi_d - phi(p, gamma_o, gamma_t);
+
+    // Remap `dphi` to [-π, π].
+    while dphi > PI {
+        dphi -= TWO_PI;
+    }
+    while dphi < -PI {
+        dphi += TWO_PI;
+    }
+    trimmed_logistic(dphi, s, -PI, PI)
+}
+
+/// Samples the logistic distribution normalized over the range [a, b].
+///
+/// * `u` - The uniform random value.
+/// * `s` - The scale factor.
+/// * `a` - Lower bound.
+/// * `b` - Upper bound.
+fn sample_trimmed_logistic(u: Float, s: Float, a: Float, b: Float) -> Float {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * (1.0 / (u * k + logistic_cdf(a, s)) - 1.0).ln();
+    clamp(x, a, b)
+}
+
+/// Returns the even bits of a value compacted into the lower half.
+///
+/// * `x` - The value.
+fn compact_1_by_1(x: u32) -> u32 {
+    let mut x = x & 0x5555_5555;
+    x = (x ^ (x >> 1)) & 0x3333_3333;
+    x = (x ^ (x >> 2)) & 0x0f0f_0f0f;
+    x = (x ^ (x >> 4)) & 0x00ff_00ff;
+    x = (x ^ (x >> 8)) & 0x0000_ffff;
+    x
+}
+
+/// Derives two uniform random values from the interleaved bits of one.
+///
+/// * `f` - The uniform random value.
+fn demux_float(f: Float) -> [Float; 2] {
+    let v = (f as f64 * (1u64 << 32) as f64) as u64;
+    let bits = [compact_1_by_1(v as u32), compact_1_by_1((v >> 1) as u32)];
+    [
+        bits[0] as Float / (1 << 16) as Float,
+        bits[1] as Float / (1 << 16) as Float,
+    ]
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::*;
+
+    /// Returns a random direction on the unit sphere.
+    fn random_direction(rng: &mut RNG) -> Vector3f {
+        uniform_sample_sphere(&Point2f::new(rng.uniform(), rng.uniform()))
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Without absorption all light is scattered somewhere.
+        let mut rng = RNG::default();
+        let wo = random_direction(&mut rng);
+        for beta_m in [0.3, 0.6, 1.0].iter() {
+            for beta_n in [0.3, 0.6, 1.0].iter() {
+                let n = 50_000;
+                let mut sum = Spectrum::new(0.0);
+                for _ in 0..n {
+                    let u: Float = rng.uniform();
+                    let h = -1.0 + 2.0 * u;
+                    let hair = HairBSDF::new(h, 1.55, Spectrum::new(0.0), *beta_m, *beta_n, 0.0);
+                    let wi = random_direction(&mut rng);
+                    sum += hair.f(&wo, &wi) * abs_cos_theta(&wi);
+                }
+                let avg = sum.y() / (n as Float * uniform_sphere_pdf());
+                assert!((0.95..=1.05).contains(&avg), "{}", avg);
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_weights() {
+        // `f * cos / pdf` is close to 1 for sampled directions without
+        // absorption.
+        let mut rng = RNG::default();
+        for beta_m in [0.2, 0.5, 1.0].iter() {
+            for beta_n in [0.2, 0.5, 1.0].iter() {
+                for _ in 0..1000 {
+                    let u: Float = rng.uniform();
+                    let h = -1.0 + 2.0 * u;
+                    let hair = HairBSDF::new(h, 1.55, Spectrum::new(0.0), *beta_m, *beta_n, 0.0);
+                    let wo = random_direction(&mut rng);
+                    let u = Point2f::new(rng.uniform(), rng.uniform());
+                    let sample = hair.sample_f(&wo, &u);
+                    if sample.pdf > 0.0 {
+                        let w = sample.f.y() * abs_cos_theta(&sample.wi) / sample.pdf;
+                        assert!((0.99..=1.01).contains(&w), "{}", w);
+                        let pdf = hair.pdf(&wo, &sample.wi);
+                        assert!((pdf - sample.pdf).abs() <= 1e-3 * pdf.max(1.0));
+                    }
+                }
+            }
+        }
+    }
+}
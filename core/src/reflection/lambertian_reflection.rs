@@ -52,6 +52,13 @@ impl BxDF for LambertianReflection {
         self.r
     }
 
+    /// Returns the directional albedo for the outgoing direction.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, _wo: &Vector3f) -> Spectrum {
+        self.r
+    }
+
     /// Computes the hemispherical-hemispherical-directional reflectance function ρ.
     ///
     /// * `u1` - Samples used b Monte Carlo algorithm.
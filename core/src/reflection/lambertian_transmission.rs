@@ -0,0 +1,118 @@
+//! Lambertian Transmission
+
+#![allow(dead_code)]
+
+use super::*;
+
+/// BTDF for the Lambertian model for perfect diffuse transmission that
+/// scatters incident illumination equally in all directions of the opposite
+/// hemisphere.
+#[derive(Clone)]
+pub struct LambertianTransmission {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Transmission spectrum which gives the fraction of incident light that
+    /// is scattered.
+    t: Spectrum,
+}
+
+impl LambertianTransmission {
+    /// Create a new instance of `LambertianTransmission`.
+    ///
+    /// * `t` - Transmission spectrum which gives the fraction of incident
+    ///         light that is scattered.
+    pub fn new(t: Spectrum) -> Self {
+        Self {
+            bxdf_type: BxDFType::from(BSDF_TRANSMISSION | BSDF_DIFFUSE),
+            t,
+        }
+    }
+}
+
+impl BxDF for LambertianTransmission {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, _wo: &Vector3f, _wi: &Vector3f) -> Spectrum {
+        self.t * INV_PI
+    }
+
+    /// Returns the value of the BxDF given the outgpoing direction.
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `u`  - The 2D uniform random values.
+    fn sample_f(&self, wo: &Vector3f, u: &Point2f) -> BxDFSample {
+        // Cosine-sample the hemisphere on the opposite side of `wo`.
+        let mut wi = cosine_sample_hemisphere(u);
+        if wo.z > 0.0 {
+            wi.z *= -1.0;
+        }
+        let pdf = self.pdf(wo, &wi);
+        BxDFSample::new(self.f(wo, &wi), pdf, wi, self.bxdf_type)
+    }
+
+    /// Evaluates the PDF for the sampling method.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn pdf(&self, wo: &Vector3f, wi: &Vector3f) -> Float {
+        if same_hemisphere(wo, wi) {
+            0.0
+        } else {
+            abs_cos_theta(wi) * INV_PI
+        }
+    }
+
+    /// Computes the hemispherical-directional reflectance function ρ.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `u`  - Samples used by Monte Carlo algorithm.
+    fn rho_hd(&self, _wo: &Vector3f, _u: &[Point2f]) -> Spectrum {
+        self.t
+    }
+
+    /// Returns the directional albedo for the outgoing direction.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, _wo: &Vector3f) -> Spectrum {
+        self.t
+    }
+
+    /// Computes the hemispherical-hemispherical-directional reflectance function ρ.
+    ///
+    /// * `u1` - Samples used b Monte Carlo algorithm.
+    /// * `u2` - Samples used b Monte Carlo algorithm.
+    fn rho_hh(&self, u1: &[Point2f], u2: &[Point2f]) -> Spectrum {
+        assert!(u1.len() == u2.len());
+        self.t
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_opposite_hemisphere() {
+        let bxdf = LambertianTransmission::new(Spectrum::new(0.5));
+        let wo = Vector3f::new(0.0, 0.6, 0.8);
+        let sample = bxdf.sample_f(&wo, &Point2f::new(0.3, 0.7));
+        assert!(sample.wi.z < 0.0);
+        assert!((sample.pdf - abs_cos_theta(&sample.wi) * INV_PI).abs() < 1e-6);
+        assert!((sample.f[0] - 0.5 * INV_PI).abs() < 1e-6);
+        assert_eq!(bxdf.pdf(&wo, &wo), 0.0);
+    }
+}
@@ -0,0 +1,49 @@
+//! MERL Measured BRDF Model
+
+use super::*;
+use std::sync::Arc;
+
+/// BRDF for isotropic materials measured by MERL. Sampling uses the default
+/// cosine-weighted hemisphere since the table has no sampling data.
+#[derive(Clone)]
+pub struct MERLBRDF {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// The BRDF data.
+    brdf_table: Arc<MERLBRDFTable>,
+}
+
+impl MERLBRDF {
+    /// Create a new instance of `MERLBRDF`.
+    ///
+    /// * `brdf_table` - The BRDF data.
+    pub fn new(brdf_table: Arc<MERLBRDFTable>) -> Self {
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_GLOSSY),
+            brdf_table: Arc::clone(&brdf_table),
+        }
+    }
+}
+
+impl BxDF for MERLBRDF {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        if !same_hemisphere(wo, wi) {
+            return Spectrum::new(0.0);
+        }
+
+        // The table is measured above the surface.
+        let (wo, wi) = if wo.z < 0.0 { (-*wo, -*wi) } else { (*wo, *wi) };
+        Spectrum::from_rgb(&self.brdf_table.lookup(&wo, &wi), None)
+    }
+}
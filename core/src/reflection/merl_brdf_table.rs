@@ -0,0 +1,160 @@
+//! MERL BRDF Table
+
+use super::bsdf_reader::*;
+use crate::geometry::*;
+use crate::pbrt::*;
+use byteorder::{LittleEndian, ReadBytesExt};
+
+/// Number of samples of the half vector elevation.
+const THETA_H_RES: usize = 90;
+
+/// Number of samples of the difference vector elevation.
+const THETA_D_RES: usize = 90;
+
+/// Number of samples of the difference vector azimuth over `[0, π)`.
+const PHI_D_RES: usize = 180;
+
+/// Number of samples per channel.
+const CHANNEL_SIZE: usize = THETA_H_RES * THETA_D_RES * PHI_D_RES;
+
+/// Scale factors that convert stored values to reflectance per channel.
+const RGB_SCALE: [Float; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+/// Stores an isotropic BRDF measured by MERL tabulated over the half and
+/// difference vector angles of Rusinkiewicz's parameterization.
+#[derive(Clone, Debug)]
+pub struct MERLBRDFTable {
+    /// Red, green and blue samples stored one channel after the other.
+    pub data: Vec<Float>,
+}
+
+impl MERLBRDFTable {
+    /// Loads a MERL BRDF from a binary file.
+    ///
+    /// * `path` - The path to the `.binary` file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let mut file = open_file(path)?;
+
+        let mut dims = [0_i32; 3];
+        file.read_i32_into::<LittleEndian>(&mut dims)
+            .map_err(|err| format!("Error reading dimensions. {}.", err))?;
+        if dims != [THETA_H_RES as i32, THETA_D_RES as i32, PHI_D_RES as i32] {
+            return Err(format!("Unsupported MERL BRDF dimensions {:?}", dims));
+        }
+
+        let mut data = vec![0.0_f64; 3 * CHANNEL_SIZE];
+        file.read_f64_into::<LittleEndian>(&mut data)
+            .map_err(|err| format!("Error reading {} f64. {}.", data.len(), err))?;
+
+        Ok(Self {
+            data: data.iter().map(|&v| v as Float).collect(),
+        })
+    }
+
+    /// Returns the RGB reflectance for a pair of directions in the same
+    /// hemisphere as the surface normal.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    pub fn lookup(&self, wo: &Vector3f, wi: &Vector3f) -> [Float; 3] {
+        let i = Self::index(wo, wi);
+        let mut rgb = [0.0; 3];
+        for (c, v) in rgb.iter_mut().enumerate() {
+            // Negative values mark missing measurements.
+            *v = max(0.0, self.data[i + c * CHANNEL_SIZE] * RGB_SCALE[c]);
+        }
+        rgb
+    }
+
+    /// Returns the index of the sample for a pair of directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn index(wo: &Vector3f, wi: &Vector3f) -> usize {
+        // Convert to the half and difference vectors.
+        let wh = (*wi + *wo).normalize();
+        let theta_h = spherical_theta(&wh);
+        let phi_h = wh.y.atan2(wh.x);
+        let wd = rotate(
+            &rotate(wi, &Vector3f::new(0.0, 0.0, 1.0), -phi_h),
+            &Vector3f::new(0.0, 1.0, 0.0),
+            -theta_h,
+        );
+        let theta_d = spherical_theta(&wd);
+        let mut phi_d = wd.y.atan2(wd.x);
+
+        // The half vector elevation is sampled more densely near the normal.
+        let theta_h_index = if theta_h <= 0.0 {
+            0
+        } else {
+            let theta_h_deg = theta_h * INV_PI * 2.0 * THETA_H_RES as Float;
+            clamp(
+                (theta_h_deg * THETA_H_RES as Float).sqrt() as usize,
+                0,
+                THETA_H_RES - 1,
+            )
+        };
+        let theta_d_index = clamp(
+            (theta_d * INV_PI * 2.0 * THETA_D_RES as Float) as usize,
+            0,
+            THETA_D_RES - 1,
+        );
+
+        // Reciprocity makes the difference azimuth symmetric.
+        if phi_d < 0.0 {
+            phi_d += PI;
+        }
+        let phi_d_index = clamp(
+            (phi_d * INV_PI * PHI_D_RES as Float) as usize,
+            0,
+            PHI_D_RES - 1,
+        );
+
+        phi_d_index + PHI_D_RES * (theta_d_index + THETA_D_RES * theta_h_index)
+    }
+}
+
+/// Rotates a vector around an axis.
+///
+/// * `v`     - The vector.
+/// * `axis`  - Normalized axis of rotation.
+/// * `angle` - The angle in radians.
+fn rotate(v: &Vector3f, axis: &Vector3f, angle: Float) -> Vector3f {
+    let (sin_angle, cos_angle) = angle.sin_cos();
+    *v * cos_angle + *axis * (axis.dot(v) * (1.0 - cos_angle)) + axis.cross(v) * sin_angle
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_uses_half_and_difference_angles() {
+        let mut data = vec![0.0; 3 * CHANNEL_SIZE];
+        data[0] = 1500.0;
+        data[CHANNEL_SIZE] = 1500.0;
+        data[2 * CHANNEL_SIZE] = 1500.0;
+        let table = MERLBRDFTable { data };
+
+        // Retro-reflection at normal incidence is the first sample.
+        let n = Vector3f::new(0.0, 0.0, 1.0);
+        let rgb = table.lookup(&n, &n);
+        for (v, e) in rgb.iter().zip([1.0, 1.15, 1.66].iter()) {
+            assert!(abs(v - e) < 1e-6);
+        }
+
+        // Mirror directions have a half vector along the normal and a
+        // difference elevation equal to the angle of incidence.
+        let (sin_theta, cos_theta) = (31.5 as Float).to_radians().sin_cos();
+        let wo = Vector3f::new(sin_theta, 0.0, cos_theta);
+        let wi = Vector3f::new(-sin_theta, 0.0, cos_theta);
+        let i = MERLBRDFTable::index(&wo, &wi);
+        assert_eq!(i / PHI_D_RES % THETA_D_RES, 31);
+        assert_eq!(i / (PHI_D_RES * THETA_D_RES), 0);
+        assert_eq!(table.lookup(&wo, &wi), [0.0, 0.0, 0.0]);
+    }
+}
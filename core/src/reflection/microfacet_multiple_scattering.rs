@@ -0,0 +1,128 @@
+//! Microfacet Multiple Scattering Energy Compensation
+
+#![allow(dead_code)]
+use super::*;
+use crate::microfacet::*;
+
+/// BRDF that adds back the energy lost by single scattering microfacet models
+/// at high roughness using the method of Kulla and Conty. It uses the
+/// precomputed albedo tables of the microfacet distribution and evaluates to
+/// zero for distributions without them.
+#[derive(Clone)]
+pub struct MicrofacetMultipleScattering {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// Reflectance spectrum which gives the fraction of compensated energy
+    /// that is scattered.
+    r: Spectrum,
+
+    /// The microfacet distribution model.
+    distribution: ArcMicrofacetDistribution,
+
+    /// Average albedo of the microfacet distribution.
+    average_albedo: Float,
+}
+
+impl MicrofacetMultipleScattering {
+    /// Create a new instance of `MicrofacetMultipleScattering`.
+    ///
+    /// * `r`            - Reflectance spectrum which gives the fraction of
+    ///                    compensated energy that is scattered.
+    /// * `distribution` - Microfacet distribution.
+    pub fn new(r: Spectrum, distribution: ArcMicrofacetDistribution) -> Self {
+        let average_albedo = distribution.average_albedo().unwrap_or(1.0);
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_GLOSSY),
+            r,
+            distribution: Arc::clone(&distribution),
+            average_albedo,
+        }
+    }
+
+    /// Returns the energy lost by single scattering for a direction.
+    ///
+    /// * `w` - The direction.
+    fn energy_loss(&self, w: &Vector3f) -> Float {
+        1.0 - self.distribution.albedo(abs_cos_theta(w)).unwrap_or(1.0)
+    }
+}
+
+impl BxDF for MicrofacetMultipleScattering {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        if !same_hemisphere(wo, wi) || self.average_albedo >= 1.0 {
+            Spectrum::new(0.0)
+        } else {
+            self.r * (self.energy_loss(wo) * self.energy_loss(wi))
+                / (PI * (1.0 - self.average_albedo))
+        }
+    }
+
+    /// Returns the directional albedo for the outgoing direction. This is
+    /// exactly the energy lost by single scattering.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, wo: &Vector3f) -> Spectrum {
+        if self.average_albedo >= 1.0 {
+            Spectrum::new(0.0)
+        } else {
+            self.r * self.energy_loss(wo)
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the directional albedo of a BxDF by integrating it over the
+    /// hemisphere with the midpoint rule.
+    fn integrate_albedo(bxdf: &dyn BxDF, wo: &Vector3f) -> Float {
+        let n = 128;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let cos_theta = (i as Float + 0.5) / n as Float;
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = TWO_PI * (j as Float + 0.5) / n as Float;
+                let wi = Vector3f::new(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+                sum += bxdf.f(wo, &wi)[0] * cos_theta;
+            }
+        }
+        sum * TWO_PI / (n * n) as Float
+    }
+
+    #[test]
+    fn compensation_restores_energy() {
+        let distrib: ArcMicrofacetDistribution =
+            Arc::new(TrowbridgeReitzDistribution::new(1.0, 1.0, true));
+        let single = MicrofacetReflection::new(
+            Spectrum::new(1.0),
+            Arc::clone(&distrib),
+            Arc::new(FresnelNoOp::new()),
+        );
+        let multiple = MicrofacetMultipleScattering::new(Spectrum::new(1.0), distrib);
+
+        for cos_theta in [0.2 as Float, 0.5, 0.9].iter() {
+            let wo = Vector3f::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, *cos_theta);
+            let e_single = integrate_albedo(&single, &wo);
+            let e = e_single + integrate_albedo(&multiple, &wo);
+            assert!(e_single < 0.9, "E = {}", e_single);
+            assert!((e - 1.0).abs() < 0.02, "E = {}", e);
+        }
+    }
+}
@@ -110,4 +110,47 @@ impl BxDF for MicrofacetReflection {
             0.0
         }
     }
+
+    /// Returns the directional albedo for the outgoing direction using the
+    /// distribution's precomputed albedo table when available. Fresnel is
+    /// evaluated at the outgoing direction.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, wo: &Vector3f) -> Spectrum {
+        let cos_theta_o = abs_cos_theta(wo);
+        match self.distribution.albedo(cos_theta_o) {
+            Some(e) => self.r * self.fresnel.evaluate(cos_theta_o) * e,
+            None => self.rho_hd(wo, &albedo_samples()),
+        }
+    }
+
+    /// Returns the Mueller matrix for light arriving from `wi` scattered
+    /// towards `wo`. Each microfacet reflects like a specular surface so the
+    /// Fresnel matrix is applied in the plane of incidence of the microfacet.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        let cos_theta_o = abs_cos_theta(wo);
+        let cos_theta_i = abs_cos_theta(wi);
+        let wh = *wi + *wo;
+        if cos_theta_i == 0.0 || cos_theta_o == 0.0 || wh.length_squared() == 0.0 {
+            return Mueller::default();
+        }
+
+        let n = Vector3f::new(0.0, 0.0, 1.0);
+        let wh = wh.normalize().face_forward(&n);
+        let fresnel = self.fresnel.mueller(wi.dot(&wh));
+
+        // Rotate between the frames of the surface and the microfacet.
+        let x = Vector3f::new(1.0, 0.0, 0.0);
+        let s_h = s_axis(&wh, wi, &s_axis(&n, wi, &x));
+        let m = rotate_frame(&s_h, &s_axis(&n, wo, &x), wo)
+            * fresnel
+            * rotate_frame(&s_axis(&n, wi, &x), &s_h, &-*wi);
+
+        m * (self.r * self.distribution.d(&wh) * self.distribution.g(wo, wi)
+            / (4.0 * cos_theta_i * cos_theta_o))
+    }
 }
@@ -3,6 +3,8 @@
 #![allow(dead_code)]
 use crate::geometry::*;
 use crate::pbrt::*;
+#[cfg(feature = "polarization")]
+use crate::polarization::*;
 use crate::sampling::*;
 use crate::spectrum::*;
 use std::sync::Arc;
@@ -12,15 +14,25 @@ mod bsdf_reader;
 mod bxdf_sample;
 mod bxdf_type;
 mod common;
+#[cfg(feature = "sampled-spectrum")]
+mod fluorescent_reflection;
 mod fourier_bsdf;
 mod fourier_bsdf_table;
 mod fresnel;
 mod fresnel_blend;
 mod fresnel_specular;
+mod fresnel_weighted_lambertian;
+mod hair_bsdf;
 mod lambertian_reflection;
+mod lambertian_transmission;
+mod merl_brdf;
+mod merl_brdf_table;
+mod microfacet_multiple_scattering;
 mod microfacet_reflection;
 mod microfacet_transmission;
 mod oren_nayar;
+mod rgl_brdf;
+mod rgl_brdf_table;
 mod scaled_bxdf;
 mod specular_reflection;
 mod specular_transmission;
@@ -30,15 +42,25 @@ pub use bsdf::*;
 pub use bxdf_sample::*;
 pub use bxdf_type::*;
 pub use common::*;
+#[cfg(feature = "sampled-spectrum")]
+pub use fluorescent_reflection::*;
 pub use fourier_bsdf::*;
 pub use fourier_bsdf_table::*;
 pub use fresnel::*;
 pub use fresnel_blend::*;
 pub use fresnel_specular::*;
+pub use fresnel_weighted_lambertian::*;
+pub use hair_bsdf::*;
 pub use lambertian_reflection::*;
+pub use lambertian_transmission::*;
+pub use merl_brdf::*;
+pub use merl_brdf_table::*;
+pub use microfacet_multiple_scattering::*;
 pub use microfacet_reflection::*;
 pub use microfacet_transmission::*;
 pub use oren_nayar::*;
+pub use rgl_brdf::*;
+pub use rgl_brdf_table::*;
 pub use scaled_bxdf::*;
 pub use specular_reflection::*;
 pub use specular_transmission::*;
@@ -123,6 +145,52 @@ pub trait BxDF {
         }
         r / (PI * u1.len() as Float)
     }
+
+    /// Returns the directional albedo for the outgoing direction. This is
+    /// used for auxiliary outputs such as the denoiser albedo AOV. Default is
+    /// to estimate `rho_hd()` with a fixed stratified pattern.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, wo: &Vector3f) -> Spectrum {
+        self.rho_hd(wo, &albedo_samples())
+    }
+
+    /// Returns the light scattered towards `wo` for light arriving from `wi`.
+    /// Fluorescent BxDFs override this to re-emit light at other wavelengths.
+    /// Default is the product of `f()` and the incident light.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    /// * `li` - The incident light.
+    fn scatter(&self, wo: &Vector3f, wi: &Vector3f, li: &Spectrum) -> Spectrum {
+        self.f(wo, wi) * *li
+    }
+
+    /// Returns the Mueller matrix for light arriving from `wi` scattered
+    /// towards `wo`. For specular BxDFs `wi` must be the sampled direction.
+    /// The frame of each direction has its x-axis perpendicular to the plane
+    /// containing the direction and the normal. Default is to depolarize
+    /// with the value of `f()`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        Mueller::depolarizer(self.f(wo, wi))
+    }
+}
+
+/// Returns a fixed 4x4 stratified sample pattern used to estimate albedo.
+pub(crate) fn albedo_samples() -> Vec<Point2f> {
+    const N: usize = 4;
+    (0..N * N)
+        .map(|i| {
+            Point2f::new(
+                ((i % N) as Float + 0.5) / N as Float,
+                ((i / N) as Float + 0.5) / N as Float,
+            )
+        })
+        .collect()
 }
 
 /// Atomic reference counted `BxDF`.
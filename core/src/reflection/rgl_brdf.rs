@@ -0,0 +1,207 @@
+//! RGL Measured BRDF Model
+
+use super::*;
+use crate::spectrum::Sample;
+use std::sync::Arc;
+
+/// BRDF for materials from the RGL material database. The table is indexed
+/// by the outgoing direction and importance sampled through its visible
+/// normal distribution and luminance.
+#[derive(Clone)]
+pub struct RGLBRDF {
+    /// BxDF type.
+    bxdf_type: BxDFType,
+
+    /// The BRDF data.
+    brdf_table: Arc<RGLBRDFTable>,
+}
+
+impl RGLBRDF {
+    /// Create a new instance of `RGLBRDF`.
+    ///
+    /// * `brdf_table` - The BRDF data.
+    pub fn new(brdf_table: Arc<RGLBRDFTable>) -> Self {
+        Self {
+            bxdf_type: BxDFType::from(BSDF_REFLECTION | BSDF_GLOSSY),
+            brdf_table: Arc::clone(&brdf_table),
+        }
+    }
+
+    /// Returns the BRDF value at a point in the warped space.
+    ///
+    /// * `sample` - Point in the warped space.
+    /// * `u_wm`   - Spherical coordinates of the half vector in `[0, 1]^2`.
+    /// * `wo`     - Outgoing direction.
+    /// * `wi`     - Incident direction.
+    fn eval(&self, sample: &Point2f, u_wm: &Point2f, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        let table = &self.brdf_table;
+        let (theta_o, phi_o) = (spherical_theta(wo), wo.y.atan2(wo.x));
+
+        let samples: Vec<Sample> = table
+            .wavelengths
+            .iter()
+            .map(|&lambda| {
+                let value = table.spectra.evaluate(sample, &[phi_o, theta_o, lambda]);
+                Sample::new(lambda, max(0.0, value))
+            })
+            .collect();
+
+        let u_wo = Point2f::new(theta2u(theta_o), phi2u(phi_o));
+        let scale = table.ndf.evaluate(u_wm, &[])
+            / (4.0 * table.sigma.evaluate(&u_wo, &[]) * abs_cos_theta(wi));
+        Spectrum::from(&samples) * scale
+    }
+
+    /// Returns the spherical coordinates of the half vector mapped to
+    /// `[0, 1]^2` and the sine of its elevation.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wm` - The half vector.
+    fn warp_half_vector(&self, wo: &Vector3f, wm: &Vector3f) -> (Point2f, Float) {
+        let phi_o = wo.y.atan2(wo.x);
+        let phi_m = wm.y.atan2(wm.x);
+        let phi = if self.brdf_table.isotropic {
+            phi_m - phi_o
+        } else {
+            phi_m
+        };
+        let mut u_wm = Point2f::new(theta2u(spherical_theta(wm)), phi2u(phi));
+        u_wm.y -= u_wm.y.floor();
+        (u_wm, (wm.x * wm.x + wm.y * wm.y).sqrt())
+    }
+
+    /// Returns the Jacobian of the mapping from the warped space to the
+    /// incident direction.
+    ///
+    /// * `wo`          - Outgoing direction.
+    /// * `wm`          - The half vector.
+    /// * `u_wm`        - Spherical coordinates of the half vector in `[0, 1]^2`.
+    /// * `sin_theta_m` - Sine of the elevation of the half vector.
+    fn jacobian(wo: &Vector3f, wm: &Vector3f, u_wm: &Point2f, sin_theta_m: Float) -> Float {
+        4.0 * wo.dot(wm) * max(2.0 * PI * PI * u_wm.x * sin_theta_m, 1e-6)
+    }
+}
+
+impl BxDF for RGLBRDF {
+    /// Returns the BxDF type.
+    fn get_type(&self) -> BxDFType {
+        self.bxdf_type
+    }
+
+    /// Returns the value of the distribution function for the given pair of
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn f(&self, wo: &Vector3f, wi: &Vector3f) -> Spectrum {
+        if !same_hemisphere(wo, wi) {
+            return Spectrum::new(0.0);
+        }
+
+        // The table is measured above the surface.
+        let (wo, wi) = if wo.z < 0.0 { (-*wo, -*wi) } else { (*wo, *wi) };
+        let wm = wi + wo;
+        if wm.length_squared() == 0.0 {
+            return Spectrum::new(0.0);
+        }
+        let wm = wm.normalize();
+
+        // Map the half vector back to the warped space.
+        let (u_wm, _) = self.warp_half_vector(&wo, &wm);
+        let params = [wo.y.atan2(wo.x), spherical_theta(&wo)];
+        let (sample, _) = self.brdf_table.vndf.invert(&u_wm, &params);
+
+        self.eval(&sample, &u_wm, &wo, &wi)
+    }
+
+    /// Returns the value of the BxDF given the outgpoing direction.
+    /// directions.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `u`  - The 2D uniform random values.
+    fn sample_f(&self, wo: &Vector3f, u: &Point2f) -> BxDFSample {
+        let table = &self.brdf_table;
+        let flip = wo.z < 0.0;
+        let wo = if flip { -*wo } else { *wo };
+        let (theta_o, phi_o) = (spherical_theta(&wo), wo.y.atan2(wo.x));
+        let params = [phi_o, theta_o];
+
+        // Sample the luminance and warp the point by the visible normals.
+        let (sample, lum_pdf) = table.luminance.sample(&Point2f::new(u.y, u.x), &params);
+        let (u_wm, vndf_pdf) = table.vndf.sample(&sample, &params);
+
+        let mut phi_m = u2phi(u_wm.y);
+        if table.isotropic {
+            phi_m += phi_o;
+        }
+        let theta_m = u2theta(u_wm.x);
+        let (sin_theta_m, cos_theta_m) = theta_m.sin_cos();
+        let wm = spherical_direction(sin_theta_m, cos_theta_m, phi_m);
+
+        let wi = reflect(&wo, &wm);
+        if wi.z <= 0.0 {
+            return BxDFSample::from(self.bxdf_type);
+        }
+
+        let f = self.eval(&sample, &u_wm, &wo, &wi);
+        let pdf = vndf_pdf * lum_pdf / Self::jacobian(&wo, &wm, &u_wm, sin_theta_m);
+        let wi = if flip { -wi } else { wi };
+        BxDFSample::new(f, pdf, wi, self.bxdf_type)
+    }
+
+    /// Evaluates the PDF for the sampling method.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    fn pdf(&self, wo: &Vector3f, wi: &Vector3f) -> Float {
+        if !same_hemisphere(wo, wi) {
+            return 0.0;
+        }
+
+        let (wo, wi) = if wo.z < 0.0 { (-*wo, -*wi) } else { (*wo, *wi) };
+        let wm = wi + wo;
+        if wm.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wm = wm.normalize();
+
+        let (u_wm, sin_theta_m) = self.warp_half_vector(&wo, &wm);
+        let params = [wo.y.atan2(wo.x), spherical_theta(&wo)];
+        let (sample, vndf_pdf) = self.brdf_table.vndf.invert(&u_wm, &params);
+        let lum_pdf = self.brdf_table.luminance.evaluate(&sample, &params);
+
+        vndf_pdf * lum_pdf / Self::jacobian(&wo, &wm, &u_wm, sin_theta_m)
+    }
+}
+
+/// Maps an elevation angle to `[0, 1]`.
+///
+/// * `theta` - The angle.
+#[inline]
+fn theta2u(theta: Float) -> Float {
+    (theta * (2.0 * INV_PI)).sqrt()
+}
+
+/// Maps an azimuth angle to `[0, 1]`.
+///
+/// * `phi` - The angle.
+#[inline]
+fn phi2u(phi: Float) -> Float {
+    (phi + PI) * INV_TWO_PI
+}
+
+/// Maps a value in `[0, 1]` to an elevation angle.
+///
+/// * `u` - The value.
+#[inline]
+fn u2theta(u: Float) -> Float {
+    u * u * PI_OVER_TWO
+}
+
+/// Maps a value in `[0, 1]` to an azimuth angle.
+///
+/// * `u` - The value.
+#[inline]
+fn u2phi(u: Float) -> Float {
+    (2.0 * u - 1.0) * PI
+}
@@ -0,0 +1,330 @@
+//! RGL BRDF Table
+
+use crate::pbrt::*;
+use crate::sampling::*;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read};
+
+/// The first 12 bytes of a tensor file.
+const TENSOR_HEADER: &[u8; 12] = b"tensor_file\0";
+
+/// Tensor data type for unsigned bytes.
+const TENSOR_UINT8: u8 = 1;
+
+/// Tensor data type for 32-bit floating point values.
+const TENSOR_FLOAT32: u8 = 10;
+
+/// A field of a tensor file.
+#[derive(Clone, Debug)]
+struct TensorField {
+    /// The data type.
+    dtype: u8,
+
+    /// The shape.
+    shape: Vec<usize>,
+
+    /// Offset of the data in the file.
+    offset: usize,
+}
+
+/// The fields of a tensor file used by the RGL material database.
+struct TensorFile {
+    /// The file contents.
+    bytes: Vec<u8>,
+
+    /// The fields by name.
+    fields: HashMap<String, TensorField>,
+}
+
+impl TensorFile {
+    /// Parses the fields of a tensor file.
+    ///
+    /// * `bytes` - The file contents.
+    fn parse(bytes: Vec<u8>) -> Result<Self, String> {
+        let err = |e: std::io::Error| format!("Error reading tensor file. {}.", e);
+        let mut cursor = Cursor::new(&bytes);
+
+        let mut header = [0_u8; 12];
+        cursor.read_exact(&mut header).map_err(err)?;
+        if &header != TENSOR_HEADER {
+            return Err(String::from("Invalid tensor file header"));
+        }
+        let mut version = [0_u8; 2];
+        cursor.read_exact(&mut version).map_err(err)?;
+        if version != [1, 0] {
+            return Err(format!(
+                "Unsupported tensor file version {}.{}",
+                version[0], version[1]
+            ));
+        }
+
+        let n_fields = cursor.read_u32::<LittleEndian>().map_err(err)?;
+        let mut fields = HashMap::new();
+        for _ in 0..n_fields {
+            let name_len = cursor.read_u16::<LittleEndian>().map_err(err)? as usize;
+            let mut name = vec![0_u8; name_len];
+            cursor.read_exact(&mut name).map_err(err)?;
+            let ndim = cursor.read_u16::<LittleEndian>().map_err(err)? as usize;
+            let dtype = cursor.read_u8().map_err(err)?;
+            let offset = cursor.read_u64::<LittleEndian>().map_err(err)? as usize;
+            let mut shape = vec![0_usize; ndim];
+            for s in shape.iter_mut() {
+                *s = cursor.read_u64::<LittleEndian>().map_err(err)? as usize;
+            }
+            fields.insert(
+                String::from_utf8_lossy(&name).into_owned(),
+                TensorField {
+                    dtype,
+                    shape,
+                    offset,
+                },
+            );
+        }
+
+        Ok(Self { bytes, fields })
+    }
+
+    /// Returns a field with the expected data type and number of dimensions.
+    ///
+    /// * `name`  - Name of the field.
+    /// * `dtype` - Data type.
+    /// * `ndim`  - Number of dimensions.
+    fn field(&self, name: &str, dtype: u8, ndim: usize) -> Result<&TensorField, String> {
+        match self.fields.get(name) {
+            Some(field) if field.dtype == dtype && field.shape.len() == ndim => Ok(field),
+            Some(_) => Err(format!("Tensor field '{}' has an unexpected type", name)),
+            None => Err(format!("Tensor field '{}' is missing", name)),
+        }
+    }
+
+    /// Returns the data of a field as bytes.
+    ///
+    /// * `field` - The field.
+    /// * `size`  - Size of each value in bytes.
+    fn data(&self, field: &TensorField, size: usize) -> Result<&[u8], String> {
+        let len = field.shape.iter().product::<usize>() * size;
+        self.bytes
+            .get(field.offset..field.offset + len)
+            .ok_or_else(|| String::from("Tensor field data is truncated"))
+    }
+
+    /// Returns the shape and values of a 32-bit floating point field.
+    ///
+    /// * `name` - Name of the field.
+    /// * `ndim` - Number of dimensions.
+    fn floats(&self, name: &str, ndim: usize) -> Result<(Vec<usize>, Vec<Float>), String> {
+        let field = self.field(name, TENSOR_FLOAT32, ndim)?;
+        let data = self.data(field, 4)?;
+        let mut values = vec![0.0_f32; data.len() / 4];
+        Cursor::new(data)
+            .read_f32_into::<LittleEndian>(&mut values)
+            .map_err(|e| format!("Error reading tensor field '{}'. {}.", name, e))?;
+        Ok((
+            field.shape.clone(),
+            values.iter().map(|&v| v as Float).collect(),
+        ))
+    }
+
+    /// Returns the values of a 1D byte field.
+    ///
+    /// * `name` - Name of the field.
+    fn bytes(&self, name: &str) -> Result<&[u8], String> {
+        let field = self.field(name, TENSOR_UINT8, 1)?;
+        self.data(field, 1)
+    }
+}
+
+/// Stores a BRDF from the RGL material database by Dupuy and Jakob. The BRDF
+/// is tabulated in a space warped by the visible normal distribution of the
+/// material so a few samples capture it well and the warp doubles as an
+/// importance sampling scheme.
+#[derive(Clone, Debug)]
+pub struct RGLBRDFTable {
+    /// Description of the material.
+    pub description: String,
+
+    /// Indicates the BRDF only depends on the azimuth difference.
+    pub isotropic: bool,
+
+    /// Normal distribution function.
+    pub ndf: PiecewiseLinear2D,
+
+    /// Projected area of the microfacets.
+    pub sigma: PiecewiseLinear2D,
+
+    /// Visible normal distribution function for each incident direction.
+    pub vndf: PiecewiseLinear2D,
+
+    /// Luminance of the BRDF in the warped space used for sampling.
+    pub luminance: PiecewiseLinear2D,
+
+    /// Spectral BRDF values in the warped space.
+    pub spectra: PiecewiseLinear2D,
+
+    /// Wavelengths in nanometers where `spectra` is stored.
+    pub wavelengths: Vec<Float>,
+}
+
+impl RGLBRDFTable {
+    /// Loads an RGL BRDF from a `.bsdf` tensor file.
+    ///
+    /// * `path` - The path to the file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not open {}. {}", path, e))?;
+        Self::from_bytes(bytes)
+    }
+
+    /// Loads an RGL BRDF from the contents of a tensor file.
+    ///
+    /// * `bytes` - The file contents.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        let tf = TensorFile::parse(bytes)?;
+
+        let description = String::from_utf8_lossy(tf.bytes("description")?).into_owned();
+        let (_, theta_i) = tf.floats("theta_i", 1)?;
+        let (_, phi_i) = tf.floats("phi_i", 1)?;
+        let (_, wavelengths) = tf.floats("wavelengths", 1)?;
+        let (ndf_shape, ndf) = tf.floats("ndf", 2)?;
+        let (sigma_shape, sigma) = tf.floats("sigma", 2)?;
+        let (vndf_shape, vndf) = tf.floats("vndf", 4)?;
+        let (luminance_shape, luminance) = tf.floats("luminance", 4)?;
+        let (spectra_shape, spectra) = tf.floats("spectra", 5)?;
+
+        let (n_phi, n_theta) = (phi_i.len(), theta_i.len());
+        if vndf_shape[..2] != [n_phi, n_theta]
+            || luminance_shape[..2] != [n_phi, n_theta]
+            || spectra_shape[..3] != [n_phi, n_theta, wavelengths.len()]
+            || luminance_shape[2..] != spectra_shape[3..]
+        {
+            return Err(String::from("Invalid RGL BRDF tensor shapes"));
+        }
+
+        let isotropic = n_phi <= 2;
+        if !isotropic {
+            let reduction = (TWO_PI / (phi_i[n_phi - 1] - phi_i[0])).round() as i32;
+            if reduction > 1 {
+                return Err(format!(
+                    "RGL BRDFs with {}-fold symmetry are not supported",
+                    reduction
+                ));
+            }
+        }
+
+        let params = vec![phi_i.clone(), theta_i.clone()];
+        Ok(Self {
+            description,
+            isotropic,
+            ndf: PiecewiseLinear2D::new(&ndf, ndf_shape[1], ndf_shape[0], vec![], false, false)?,
+            sigma: PiecewiseLinear2D::new(
+                &sigma,
+                sigma_shape[1],
+                sigma_shape[0],
+                vec![],
+                false,
+                false,
+            )?,
+            vndf: PiecewiseLinear2D::new(
+                &vndf,
+                vndf_shape[3],
+                vndf_shape[2],
+                params.clone(),
+                true,
+                true,
+            )?,
+            luminance: PiecewiseLinear2D::new(
+                &luminance,
+                luminance_shape[3],
+                luminance_shape[2],
+                params,
+                true,
+                true,
+            )?,
+            spectra: PiecewiseLinear2D::new(
+                &spectra,
+                spectra_shape[4],
+                spectra_shape[3],
+                vec![phi_i, theta_i, wavelengths.clone()],
+                false,
+                false,
+            )?,
+            wavelengths,
+        })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a tensor file with the given 32-bit float and byte fields.
+    fn tensor_file(fields: &[(&str, Vec<usize>, Vec<f32>)], bytes: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut header = TENSOR_HEADER.to_vec();
+        header.extend_from_slice(&[1, 0]);
+        header.extend_from_slice(&((fields.len() + bytes.len()) as u32).to_le_bytes());
+        let header_len = header.len()
+            + fields
+                .iter()
+                .map(|(n, s, _)| n.len() + 13 + 8 * s.len())
+                .sum::<usize>()
+            + bytes.iter().map(|(n, _)| n.len() + 21).sum::<usize>();
+
+        let mut data = vec![];
+        let mut add = |name: &str, dtype: u8, shape: &[usize], values: &[u8]| {
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+            header.extend_from_slice(&(shape.len() as u16).to_le_bytes());
+            header.push(dtype);
+            header.extend_from_slice(&((header_len + data.len()) as u64).to_le_bytes());
+            for s in shape {
+                header.extend_from_slice(&(*s as u64).to_le_bytes());
+            }
+            data.extend_from_slice(values);
+        };
+        for (name, shape, values) in fields {
+            let values: Vec<u8> = values
+                .iter()
+                .flat_map(|v| v.to_le_bytes().to_vec())
+                .collect();
+            add(name, TENSOR_FLOAT32, shape, &values);
+        }
+        for (name, values) in bytes {
+            add(name, TENSOR_UINT8, &[values.len()], values);
+        }
+        header.extend_from_slice(&data);
+        header
+    }
+
+    #[test]
+    fn load_isotropic_brdf() {
+        let grid = |n: usize| vec![1.0; n];
+        let bytes = tensor_file(
+            &[
+                ("theta_i", vec![2], vec![0.0, 1.5]),
+                ("phi_i", vec![1], vec![0.0]),
+                ("wavelengths", vec![2], vec![450.0, 650.0]),
+                ("ndf", vec![2, 2], grid(4)),
+                ("sigma", vec![2, 2], grid(4)),
+                ("vndf", vec![1, 2, 2, 2], grid(8)),
+                ("luminance", vec![1, 2, 2, 2], grid(8)),
+                ("spectra", vec![1, 2, 2, 2, 2], grid(16)),
+            ],
+            &[("description", b"test".to_vec()), ("jacobian", vec![1])],
+        );
+
+        let table = RGLBRDFTable::from_bytes(bytes).unwrap();
+        assert_eq!(table.description, "test");
+        assert!(table.isotropic);
+        assert_eq!(table.wavelengths, vec![450.0, 650.0]);
+
+        let p = crate::geometry::Point2f::new(0.3, 0.6);
+        assert!(abs(table.vndf.evaluate(&p, &[0.0, 0.7]) - 1.0) < 1e-5);
+
+        assert!(RGLBRDFTable::from_bytes(b"not a tensor file".to_vec()).is_err());
+    }
+}
@@ -68,6 +68,13 @@ impl BxDF for ScaledBxDF {
         self.scale * self.bxdf.rho_hd(wo, samples)
     }
 
+    /// Returns the directional albedo for the outgoing direction.
+    ///
+    /// * `wo` - Outgoing direction.
+    fn albedo(&self, wo: &Vector3f) -> Spectrum {
+        self.scale * self.bxdf.albedo(wo)
+    }
+
     /// Computes the hemispherical-hemispherical-directional reflectance function ρ.
     ///
     /// * `samples1` - Samples used b Monte Carlo algorithm.
@@ -75,4 +82,23 @@ impl BxDF for ScaledBxDF {
     fn rho_hh(&self, samples1: &[Point2f], samples2: &[Point2f]) -> Spectrum {
         self.scale * self.bxdf.rho_hh(samples1, samples2)
     }
+
+    /// Returns the light scattered towards `wo` for light arriving from `wi`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    /// * `li` - The incident light.
+    fn scatter(&self, wo: &Vector3f, wi: &Vector3f, li: &Spectrum) -> Spectrum {
+        self.scale * self.bxdf.scatter(wo, wi, li)
+    }
+
+    /// Returns the Mueller matrix for light arriving from `wi` scattered
+    /// towards `wo`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        self.bxdf.mueller(wo, wi) * self.scale
+    }
 }
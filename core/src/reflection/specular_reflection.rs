@@ -57,4 +57,14 @@ impl BxDF for SpecularReflection {
         let s = self.fresnel.evaluate(cos_theta(&wi)) * self.r / abs_cos_theta(&wi);
         BxDFSample::new(s, pdf, wi, self.bxdf_type)
     }
+
+    /// Returns the Mueller matrix for light arriving from the sampled
+    /// direction `wi` scattered towards `wo`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, _wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        self.fresnel.mueller(cos_theta(wi)) * (self.r / abs_cos_theta(wi))
+    }
 }
@@ -93,4 +93,45 @@ impl BxDF for SpecularTransmission {
             BxDFSample::from(self.bxdf_type)
         }
     }
+
+    /// Returns the Mueller matrix for light arriving from the sampled
+    /// direction `wi` scattered towards `wo`.
+    ///
+    /// * `wo` - Outgoing direction.
+    /// * `wi` - Incident direction.
+    #[cfg(feature = "polarization")]
+    fn mueller(&self, wo: &Vector3f, wi: &Vector3f) -> Mueller {
+        transmission_mueller(wo, wi, self.t, self.eta_a, self.eta_b, self.mode)
+    }
+}
+
+/// Returns the Mueller matrix for specular transmission from `wi` to `wo`
+/// scaled like the sampled BTDF value.
+///
+/// * `wo`    - Outgoing direction.
+/// * `wi`    - Incident direction.
+/// * `t`     - Spectrum used to scale the transmitted colour.
+/// * `eta_a` - Index of refraction above the surface.
+/// * `eta_b` - Index of refraction below the surface.
+/// * `mode`  - Indicates whether incident ray started from a light source
+///             or from camera.
+#[cfg(feature = "polarization")]
+pub(crate) fn transmission_mueller(
+    wo: &Vector3f,
+    wi: &Vector3f,
+    t: Spectrum,
+    eta_a: Float,
+    eta_b: Float,
+    mode: TransportMode,
+) -> Mueller {
+    let entering = cos_theta(wo) > 0.0;
+    let (eta_i, eta_t) = if entering { (eta_a, eta_b) } else { (eta_b, eta_a) };
+    let mut scale = t / abs_cos_theta(wi);
+
+    // Account for non-symmetry with transmission to different medium
+    if mode == TransportMode::Radiance {
+        scale *= (eta_i * eta_i) / (eta_t * eta_t);
+    }
+
+    Mueller::fresnel_transmission(cos_theta(wi), eta_a, eta_b) * scale
 }
@@ -14,6 +14,12 @@ pub struct SamplerData {
     /// Sample number of the pixel currently being generated.
     pub current_pixel_sample_index: usize,
 
+    /// Index of the first sample generated for each pixel. Progressive
+    /// rendering advances this by `samples_per_pixel` on every pass so that
+    /// samplers with deterministic sequences continue them instead of
+    /// repeating the same samples.
+    pub first_sample_index: usize,
+
     /// Stores sizes of requested 1D sample arrays.
     pub samples_1d_array_sizes: Vec<usize>,
 
@@ -44,6 +50,7 @@ impl SamplerData {
             samples_per_pixel,
             current_pixel: Point2i::default(),
             current_pixel_sample_index: 0,
+            first_sample_index: 0,
             samples_1d_array_sizes: vec![],
             samples_2d_array_sizes: vec![],
             sample_array_1d: vec![],
@@ -40,7 +40,9 @@ pub trait Sampler {
     /// sample vector.
     fn get_2d(&mut self) -> Point2f;
 
-    /// Returns an initialized `CameraSample` for a given pixel.
+    /// Returns an initialized `CameraSample` for a given pixel. Sample times
+    /// are stratified across the pixel's samples with `golden_ratio_time()`
+    /// to reduce banding in motion blur.
     ///
     /// * `p_raster` - The pixel.
     fn get_camera_sample(&mut self, p_raster: &Point2i) -> CameraSample {
@@ -51,7 +53,15 @@ pub trait Sampler {
         );
 
         let p_lens = self.get_2d();
-        let time = self.get_1d();
+
+        // The time dimension is still consumed so later dimensions are not
+        // shifted but the value is replaced by a stratified sequence.
+        self.get_1d();
+        let data = self.get_data();
+        let time = golden_ratio_time(
+            p_raster,
+            data.first_sample_index + data.current_pixel_sample_index,
+        );
 
         CameraSample::new(p_film, p_lens, time)
     }
@@ -112,5 +122,99 @@ pub trait Sampler {
     }
 }
 
+/// Returns the time for a sample in a pixel from the golden ratio sequence
+/// with a Cranley-Patterson rotation chosen per pixel. Consecutive samples are
+/// well distributed over the shutter interval for any sample count, including
+/// progressive passes, and neighbouring pixels use decorrelated offsets.
+///
+/// * `pixel`        - The pixel.
+/// * `sample_index` - Index of the sample in the pixel.
+pub fn golden_ratio_time(pixel: &Point2i, sample_index: usize) -> Float {
+    const INV_GOLDEN_RATIO: f64 = 0.618_033_988_749_894_8;
+
+    let seed = ((pixel.x as u32 as u64) << 32) | pixel.y as u32 as u64;
+    let rotation: Float = RNG::new(seed).uniform();
+    let t = (rotation as f64 + sample_index as f64 * INV_GOLDEN_RATIO).fract();
+    min(t as Float, ONE_MINUS_EPSILON)
+}
+
 /// Atomic reference counted `Sampler`.
 pub type ArcSampler = Arc<dyn Sampler + Send + Sync>;
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_ratio_time_is_stratified() {
+        // Every interval of width 1/n holds at most 2 of the first n samples.
+        let pixel = Point2i::new(3, 7);
+        let n = 32;
+        let mut counts = vec![0; n];
+        for i in 0..n {
+            let t = golden_ratio_time(&pixel, i);
+            assert!((0.0..1.0).contains(&t));
+            counts[(t * n as Float) as usize] += 1;
+        }
+        assert!(counts.iter().all(|c| *c <= 2), "{:?}", counts);
+        assert!(golden_ratio_time(&pixel, 0) != golden_ratio_time(&Point2i::new(4, 7), 0));
+    }
+
+    /// A sampler that returns the dimension of each value in the current
+    /// sample divided by 100.
+    struct CountingSampler {
+        data: SamplerData,
+        n: usize,
+    }
+
+    impl Sampler for CountingSampler {
+        fn get_data(&mut self) -> &mut SamplerData {
+            &mut self.data
+        }
+
+        fn clone(&self, _seed: u64) -> ArcSampler {
+            unimplemented!()
+        }
+
+        fn start_next_sample(&mut self) -> bool {
+            self.n = 0;
+            self.data.start_next_sample()
+        }
+
+        fn get_1d(&mut self) -> Float {
+            self.n += 1;
+            (self.n - 1) as Float / 100.0
+        }
+
+        fn get_2d(&mut self) -> Point2f {
+            Point2f::new(self.get_1d(), self.get_1d())
+        }
+    }
+
+    #[test]
+    fn camera_sample_times_are_stratified() {
+        let pixel = Point2i::new(3, 7);
+        let mut sampler = CountingSampler {
+            data: SamplerData::new(8),
+            n: 0,
+        };
+        sampler.data.first_sample_index = 8;
+        sampler.start_pixel(&pixel);
+
+        for i in 0..8 {
+            let camera_sample = sampler.get_camera_sample(&pixel);
+            assert_eq!(camera_sample.p_lens, Point2f::new(0.02, 0.03));
+
+            // Samples of later passes continue the sequence.
+            assert_eq!(camera_sample.time, golden_ratio_time(&pixel, 8 + i));
+
+            // The time dimension is still consumed.
+            assert_eq!(sampler.get_1d(), 0.05);
+            sampler.start_next_sample();
+        }
+    }
+}
@@ -1,6 +1,7 @@
 //! Pixel Sampler.
 
 use super::*;
+use crate::profiler::*;
 use std::sync::Arc;
 
 /// Implementation for generating all sample values for all sample vectors of
@@ -86,6 +87,7 @@ impl Sampler for PixelSampler {
     /// Returns the sample value for the next dimension of the current sample
     /// vector.
     fn get_1d(&mut self) -> Float {
+        let _p = ProfilePhase::new(Prof::GetSample);
         assert!(self.data.current_pixel_sample_index < self.data.samples_per_pixel);
         if self.current_1d_dimension < self.samples_1d.len() {
             let r =
@@ -100,6 +102,7 @@ impl Sampler for PixelSampler {
     /// Returns the sample value for the next two dimensions of the current
     /// sample vector.
     fn get_2d(&mut self) -> Point2f {
+        let _p = ProfilePhase::new(Prof::GetSample);
         assert!(self.data.current_pixel_sample_index < self.data.samples_per_pixel);
         if self.current_2d_dimension < self.samples_2d.len() {
             let r =
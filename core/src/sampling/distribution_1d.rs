@@ -54,6 +54,12 @@ impl Distribution1D {
         self.func.len()
     }
 
+    /// Returns the memory used by the distribution in bytes.
+    pub fn memory(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + (self.func.capacity() + self.cdf.capacity()) * std::mem::size_of::<Float>()
+    }
+
     /// Return a sample in [0, 1), PDF and offset from the distribution given
     /// a random sample.
     ///
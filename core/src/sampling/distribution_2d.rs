@@ -32,6 +32,16 @@ impl Distribution2D {
         }
     }
 
+    /// Returns the memory used by the distribution in bytes.
+    pub fn memory(&self) -> usize {
+        self.p_marginal.memory()
+            + self
+                .p_conditional_v
+                .iter()
+                .map(Distribution1D::memory)
+                .sum::<usize>()
+    }
+
     /// Return a sample point and PDF from the distribution given a random sample.
     ///
     /// - `u` - The random sample.
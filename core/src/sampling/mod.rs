@@ -3,8 +3,10 @@
 mod common;
 mod distribution_1d;
 mod distribution_2d;
+mod piecewise_linear_2d;
 
 // Re-export.
 pub use common::*;
 pub use distribution_1d::*;
 pub use distribution_2d::*;
+pub use piecewise_linear_2d::*;
@@ -0,0 +1,411 @@
+//! 2D Piecewise-Linear Distribution.
+
+use crate::geometry::*;
+use crate::pbrt::*;
+use crate::rng::*;
+
+/// Represents a bilinearly interpolated 2D function over the unit square that
+/// can be evaluated, importance sampled and inverted. The function can vary
+/// with additional parameters; it is then stored as a grid of slices and
+/// linearly interpolated between the two nearest slices along each parameter.
+/// This is the warp used by the Dupuy and Jakob measured BRDF format.
+#[derive(Clone, Debug)]
+pub struct PiecewiseLinear2D {
+    /// Resolution of the function in each slice.
+    size: [usize; 2],
+
+    /// Size of a bilinear patch in the unit square.
+    patch_size: [Float; 2],
+
+    /// Number of bilinear patches along each dimension.
+    inv_patch_size: [Float; 2],
+
+    /// Values of each parameter where slices are stored.
+    param_values: Vec<Vec<Float>>,
+
+    /// Number of slices between consecutive values of each parameter.
+    param_strides: Vec<usize>,
+
+    /// Function values of all slices.
+    data: Vec<Float>,
+
+    /// Marginal CDF of the rows of all slices.
+    marginal_cdf: Vec<Float>,
+
+    /// Conditional CDF of the columns in each row of all slices.
+    conditional_cdf: Vec<Float>,
+}
+
+impl PiecewiseLinear2D {
+    /// Returns a new `PiecewiseLinear2D` for the function values.
+    ///
+    /// * `data`         - Function values of all slices in row major order
+    ///                    with the slices for the last parameter varying
+    ///                    fastest.
+    /// * `size_x`       - Horizontal resolution of a slice.
+    /// * `size_y`       - Vertical resolution of a slice.
+    /// * `param_values` - Values of each parameter where slices are stored.
+    /// * `normalize`    - Normalize each slice to integrate to 1.
+    /// * `build_cdf`    - Build the CDFs needed for sampling and inversion.
+    pub fn new(
+        data: &[Float],
+        size_x: usize,
+        size_y: usize,
+        param_values: Vec<Vec<Float>>,
+        normalize: bool,
+        build_cdf: bool,
+    ) -> Result<Self, String> {
+        if size_x < 2 || size_y < 2 {
+            return Err(String::from("PiecewiseLinear2D needs at least 2x2 values"));
+        }
+
+        // Compute the stride of each parameter in slices.
+        let mut slices = 1;
+        let mut param_strides = vec![0; param_values.len()];
+        for (i, values) in param_values.iter().enumerate().rev() {
+            if values.is_empty() {
+                return Err(String::from("PiecewiseLinear2D parameter has no values"));
+            }
+            param_strides[i] = if values.len() > 1 { slices } else { 0 };
+            slices *= values.len();
+        }
+
+        let n_values = size_x * size_y;
+        if data.len() != slices * n_values {
+            return Err(format!(
+                "PiecewiseLinear2D expected {} values but got {}",
+                slices * n_values,
+                data.len()
+            ));
+        }
+
+        let mut data = data.to_vec();
+        let (mut marginal_cdf, mut conditional_cdf) = if build_cdf {
+            (vec![0.0; slices * size_y], vec![0.0; slices * n_values])
+        } else {
+            (vec![], vec![])
+        };
+
+        for slice in 0..slices {
+            let offset = slice * n_values;
+            let values = &mut data[offset..offset + n_values];
+
+            let mut normalization = 1.0;
+            if build_cdf {
+                // Construct the conditional CDF of each row.
+                let conditional = &mut conditional_cdf[offset..offset + n_values];
+                for y in 0..size_y {
+                    let mut sum = 0.0;
+                    let i = y * size_x;
+                    conditional[i] = 0.0;
+                    for x in 0..size_x - 1 {
+                        sum += 0.5 * (values[i + x] as f64 + values[i + x + 1] as f64);
+                        conditional[i + x + 1] = sum as Float;
+                    }
+                }
+
+                // Construct the marginal CDF of the rows.
+                let marginal = &mut marginal_cdf[slice * size_y..(slice + 1) * size_y];
+                let mut sum = 0.0;
+                marginal[0] = 0.0;
+                for y in 0..size_y - 1 {
+                    sum += 0.5
+                        * (conditional[(y + 1) * size_x - 1] as f64
+                            + conditional[(y + 2) * size_x - 1] as f64);
+                    marginal[y + 1] = sum as Float;
+                }
+
+                if normalize {
+                    normalization = 1.0 / marginal[size_y - 1];
+                    marginal.iter_mut().for_each(|v| *v *= normalization);
+                    conditional.iter_mut().for_each(|v| *v *= normalization);
+                }
+            } else if normalize {
+                let mut sum = 0.0;
+                for y in 0..size_y - 1 {
+                    for x in 0..size_x - 1 {
+                        let i = y * size_x + x;
+                        let avg = 0.25
+                            * (values[i]
+                                + values[i + 1]
+                                + values[i + size_x]
+                                + values[i + size_x + 1]);
+                        sum += avg as f64;
+                    }
+                }
+                normalization = (1.0 / sum) as Float;
+            }
+
+            if normalize {
+                values.iter_mut().for_each(|v| *v *= normalization);
+            }
+        }
+
+        Ok(Self {
+            size: [size_x, size_y],
+            patch_size: [1.0 / (size_x - 1) as Float, 1.0 / (size_y - 1) as Float],
+            inv_patch_size: [(size_x - 1) as Float, (size_y - 1) as Float],
+            param_values,
+            param_strides,
+            data,
+            marginal_cdf,
+            conditional_cdf,
+        })
+    }
+
+    /// Returns the interpolation weights of the two nearest slices along each
+    /// parameter and the offset of the first slice.
+    ///
+    /// * `params` - Parameter values.
+    fn param_weights(&self, params: &[Float]) -> (Vec<[Float; 2]>, usize) {
+        debug_assert!(params.len() == self.param_values.len());
+
+        let mut slice_offset = 0;
+        let weights = self
+            .param_values
+            .iter()
+            .zip(params.iter())
+            .zip(self.param_strides.iter())
+            .map(|((values, &param), &stride)| {
+                if values.len() == 1 {
+                    return [1.0, 0.0];
+                }
+                let i = find_interval(values.len(), |i| values[i] <= param);
+                let (p0, p1) = (values[i], values[i + 1]);
+                let w1 = clamp((param - p0) / (p1 - p0), 0.0, 1.0);
+                slice_offset += stride * i;
+                [1.0 - w1, w1]
+            })
+            .collect();
+        (weights, slice_offset)
+    }
+
+    /// Returns a value interpolated between the nearest slices.
+    ///
+    /// * `data`       - The values.
+    /// * `i0`         - Index of the value in the first slice.
+    /// * `slice_size` - Number of values in a slice.
+    /// * `weights`    - Interpolation weights along each parameter.
+    fn lookup(
+        &self,
+        data: &[Float],
+        i0: usize,
+        slice_size: usize,
+        weights: &[[Float; 2]],
+    ) -> Float {
+        let n_corners = 1 << weights.len();
+        (0..n_corners).fold(0.0, |result, corner| {
+            let mut offset = 0;
+            let mut weight = 1.0;
+            for (d, w) in weights.iter().enumerate() {
+                let bit = (corner >> d) & 1;
+                offset += self.param_strides[d] * slice_size * bit;
+                weight *= w[bit];
+            }
+            if weight == 0.0 {
+                result
+            } else {
+                result + weight * data[i0 + offset]
+            }
+        })
+    }
+
+    /// Returns the value of the function at a point.
+    ///
+    /// * `p`      - Point in the unit square.
+    /// * `params` - Parameter values.
+    pub fn evaluate(&self, p: &Point2f, params: &[Float]) -> Float {
+        let (weights, slice_offset) = self.param_weights(params);
+        let [size_x, size_y] = self.size;
+
+        // Compute linear interpolation weights.
+        let pos = [p.x * self.inv_patch_size[0], p.y * self.inv_patch_size[1]];
+        let x = min(max(pos[0], 0.0) as usize, size_x - 2);
+        let y = min(max(pos[1], 0.0) as usize, size_y - 2);
+        let w1 = [pos[0] - x as Float, pos[1] - y as Float];
+        let w0 = [1.0 - w1[0], 1.0 - w1[1]];
+
+        let slice_size = size_x * size_y;
+        let index = x + y * size_x + slice_offset * slice_size;
+        let v00 = self.lookup(&self.data, index, slice_size, &weights);
+        let v10 = self.lookup(&self.data, index + 1, slice_size, &weights);
+        let v01 = self.lookup(&self.data, index + size_x, slice_size, &weights);
+        let v11 = self.lookup(&self.data, index + size_x + 1, slice_size, &weights);
+
+        (w0[1] * (w0[0] * v00 + w1[0] * v10) + w1[1] * (w0[0] * v01 + w1[0] * v11))
+            * self.inv_patch_size[0]
+            * self.inv_patch_size[1]
+    }
+
+    /// Returns a point in the unit square sampled proportional to the
+    /// function and its PDF.
+    ///
+    /// * `u`      - The 2D uniform random values.
+    /// * `params` - Parameter values.
+    pub fn sample(&self, u: &Point2f, params: &[Float]) -> (Point2f, Float) {
+        let (weights, slice_offset) = self.param_weights(params);
+        let [size_x, size_y] = self.size;
+        let mut sample = [
+            clamp(u.x, 0.0, ONE_MINUS_EPSILON),
+            clamp(u.y, 0.0, ONE_MINUS_EPSILON),
+        ];
+
+        // Sample the row with the marginal CDF.
+        let offset = slice_offset * size_y;
+        let fetch_marginal = |i| self.lookup(&self.marginal_cdf, offset + i, size_y, &weights);
+        let row = find_interval(size_y, |i| fetch_marginal(i) < sample[1]);
+        sample[1] -= fetch_marginal(row);
+
+        let slice_size = size_x * size_y;
+        let mut offset = row * size_x + slice_offset * slice_size;
+        let r0 = self.lookup(
+            &self.conditional_cdf,
+            offset + size_x - 1,
+            slice_size,
+            &weights,
+        );
+        let r1 = self.lookup(
+            &self.conditional_cdf,
+            offset + 2 * size_x - 1,
+            slice_size,
+            &weights,
+        );
+        sample[1] = Self::invert_linear(r0, r1, sample[1]);
+
+        // Sample the column with the conditional CDF interpolated between the
+        // rows.
+        sample[0] *= (1.0 - sample[1]) * r0 + sample[1] * r1;
+        let fetch_conditional = |i| {
+            let v0 = self.lookup(&self.conditional_cdf, offset + i, slice_size, &weights);
+            let v1 = self.lookup(
+                &self.conditional_cdf,
+                offset + i + size_x,
+                slice_size,
+                &weights,
+            );
+            (1.0 - sample[1]) * v0 + sample[1] * v1
+        };
+        let col = find_interval(size_x, |i| fetch_conditional(i) < sample[0]);
+        sample[0] -= fetch_conditional(col);
+
+        offset += col;
+        let v00 = self.lookup(&self.data, offset, slice_size, &weights);
+        let v10 = self.lookup(&self.data, offset + 1, slice_size, &weights);
+        let v01 = self.lookup(&self.data, offset + size_x, slice_size, &weights);
+        let v11 = self.lookup(&self.data, offset + size_x + 1, slice_size, &weights);
+        let c0 = (1.0 - sample[1]) * v00 + sample[1] * v01;
+        let c1 = (1.0 - sample[1]) * v10 + sample[1] * v11;
+        sample[0] = Self::invert_linear(c0, c1, sample[0]);
+
+        let p = Point2f::new(
+            (col as Float + sample[0]) * self.patch_size[0],
+            (row as Float + sample[1]) * self.patch_size[1],
+        );
+        let pdf = ((1.0 - sample[0]) * c0 + sample[0] * c1)
+            * self.inv_patch_size[0]
+            * self.inv_patch_size[1];
+        (p, pdf)
+    }
+
+    /// Returns the random values that `sample()` maps to a point and the PDF
+    /// of the point.
+    ///
+    /// * `p`      - Point in the unit square.
+    /// * `params` - Parameter values.
+    pub fn invert(&self, p: &Point2f, params: &[Float]) -> (Point2f, Float) {
+        let (weights, slice_offset) = self.param_weights(params);
+        let [size_x, size_y] = self.size;
+
+        // Fetch values at the corners of the bilinear patch.
+        let pos = [p.x * self.inv_patch_size[0], p.y * self.inv_patch_size[1]];
+        let x = min(max(pos[0], 0.0) as usize, size_x - 2);
+        let y = min(max(pos[1], 0.0) as usize, size_y - 2);
+        let mut sample = [pos[0] - x as Float, pos[1] - y as Float];
+
+        let slice_size = size_x * size_y;
+        let offset = x + y * size_x + slice_offset * slice_size;
+        let v00 = self.lookup(&self.data, offset, slice_size, &weights);
+        let v10 = self.lookup(&self.data, offset + 1, slice_size, &weights);
+        let v01 = self.lookup(&self.data, offset + size_x, slice_size, &weights);
+        let v11 = self.lookup(&self.data, offset + size_x + 1, slice_size, &weights);
+        let c0 = (1.0 - sample[1]) * v00 + sample[1] * v01;
+        let c1 = (1.0 - sample[1]) * v10 + sample[1] * v11;
+        let pdf = (1.0 - sample[0]) * c0 + sample[0] * c1;
+
+        // Invert the column.
+        sample[0] *= c0 + 0.5 * sample[0] * (c1 - c0);
+        let v0 = self.lookup(&self.conditional_cdf, offset, slice_size, &weights);
+        let v1 = self.lookup(&self.conditional_cdf, offset + size_x, slice_size, &weights);
+        sample[0] += (1.0 - sample[1]) * v0 + sample[1] * v1;
+
+        let offset = y * size_x + slice_offset * slice_size;
+        let r0 = self.lookup(
+            &self.conditional_cdf,
+            offset + size_x - 1,
+            slice_size,
+            &weights,
+        );
+        let r1 = self.lookup(
+            &self.conditional_cdf,
+            offset + 2 * size_x - 1,
+            slice_size,
+            &weights,
+        );
+        sample[0] /= (1.0 - sample[1]) * r0 + sample[1] * r1;
+
+        // Invert the row.
+        sample[1] *= r0 + 0.5 * sample[1] * (r1 - r0);
+        let offset = y + slice_offset * size_y;
+        sample[1] += self.lookup(&self.marginal_cdf, offset, size_y, &weights);
+
+        (
+            Point2f::new(sample[0], sample[1]),
+            pdf * self.inv_patch_size[0] * self.inv_patch_size[1],
+        )
+    }
+
+    /// Returns the position in `[0, 1]` where the integral of a linear
+    /// function between `v0` and `v1` reaches a value.
+    ///
+    /// * `v0`    - Value at 0.
+    /// * `v1`    - Value at 1.
+    /// * `value` - The integral to reach.
+    fn invert_linear(v0: Float, v1: Float, value: Float) -> Float {
+        if abs(v0 - v1) < 1e-4 * (v0 + v1) {
+            2.0 * value / (v0 + v1)
+        } else {
+            (v0 - max(0.0, v0 * v0 - 2.0 * value * (v0 - v1)).sqrt()) / (v0 - v1)
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_and_invert_round_trip() {
+        // A ramp along x in two slices with different slopes.
+        let data = [1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 3.0, 2.0, 1.0, 3.0, 2.0, 1.0];
+        let dist = PiecewiseLinear2D::new(&data, 3, 2, vec![vec![0.0, 1.0]], true, true).unwrap();
+
+        for &param in [0.0, 0.25, 1.0].iter() {
+            for &(ux, uy) in [(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)].iter() {
+                let u = Point2f::new(ux, uy);
+                let (p, pdf) = dist.sample(&u, &[param]);
+                let (u2, pdf2) = dist.invert(&p, &[param]);
+                assert!(abs(u2.x - ux) < 1e-4 && abs(u2.y - uy) < 1e-4);
+                assert!(abs(pdf - pdf2) < 1e-4);
+                assert!(abs(pdf - dist.evaluate(&p, &[param])) < 1e-4);
+            }
+        }
+
+        // A normalized ramp 1 + 2x averages to 1.
+        let (p, pdf) = dist.sample(&Point2f::new(0.5, 0.5), &[0.0]);
+        assert!(abs(pdf - (1.0 + 2.0 * p.x) / 2.0 * 1.0) < 1e-3);
+    }
+}
@@ -2,11 +2,28 @@
 
 use crate::geometry::*;
 use crate::light::*;
+use crate::pbrt::*;
 use crate::primitive::*;
 use crate::sampler::*;
 use crate::spectrum::*;
 use std::sync::Arc;
 
+/// Maximum number of clip shape surfaces considered along a ray.
+const MAX_CLIP_CROSSINGS: usize = 64;
+
+crate::stat_counter!(
+    "Intersections/Regular ray intersection tests",
+    N_INTERSECTION_TESTS
+);
+crate::stat_counter!(
+    "Intersections/Shadow ray intersection tests",
+    N_SHADOW_TESTS
+);
+
+/// Interval of the ray parameter inside clip shapes and the surface
+/// interaction where the ray leaves them.
+type ClipInterval<'a> = (Float, Float, Option<SurfaceInteraction<'a>>);
+
 /// Scene.
 #[derive(Clone)]
 pub struct Scene {
@@ -16,28 +33,98 @@ pub struct Scene {
     /// All light sources in the scene.
     pub lights: Vec<ArcLight>,
 
+    /// Names of the light sources used for light linking; `None` for unnamed
+    /// lights.
+    pub light_names: Vec<Option<String>>,
+
     /// Infinite light sources in the scene.
     pub infinite_lights: Vec<ArcLight>,
 
     /// The bounding box of the scene geometry.
     pub world_bound: Bounds3f,
+
+    /// An aggregate of clip shapes whose interiors are removed from the scene
+    /// geometry seen by camera rays.
+    pub clip_aggregate: Option<ArcPrimitive>,
 }
 
 impl Scene {
-    /// Creates a new `Scene`.
+    /// Creates a new `Scene` and preprocesses the lights with the scene
+    /// geometry. Lights that need the scene bounds such as infinite and
+    /// distant lights must not be shared so they can be updated.
     ///
-    /// * `aggregate` - An aggregate of all primitives in the scene.
-    /// * `lights`    - All light sources in the scene.
-    pub fn new(aggregate: ArcPrimitive, lights: Vec<ArcLight>) -> Self {
-        Self {
+    /// * `aggregate`   - An aggregate of all primitives in the scene.
+    /// * `lights`      - All light sources in the scene.
+    /// * `light_names` - Names of the light sources used for light linking.
+    pub fn new(
+        aggregate: ArcPrimitive,
+        mut lights: Vec<ArcLight>,
+        light_names: Vec<Option<String>>,
+    ) -> Self {
+        assert_eq!(lights.len(), light_names.len());
+        let mut scene = Self {
             aggregate: Arc::clone(&aggregate),
             world_bound: aggregate.world_bound(),
-            lights: lights.iter().map(|l| Arc::clone(&l)).collect(),
-            infinite_lights: lights
-                .iter()
-                .filter(|l| l.get_type().matches(INFINITE_LIGHT))
-                .map(|l| Arc::clone(&l))
-                .collect(),
+            lights: vec![],
+            light_names,
+            infinite_lights: vec![],
+            clip_aggregate: None,
+        };
+
+        for light in lights.iter_mut() {
+            let needs_bounds = light
+                .get_type()
+                .matches(INFINITE_LIGHT | DELTA_DIRECTION_LIGHT);
+            if let Some(l) = Arc::get_mut(light) {
+                l.preprocess(&scene);
+            } else if needs_bounds {
+                warn!("Shared light cannot be preprocessed with the scene bounds.");
+            }
+        }
+
+        scene.infinite_lights = lights
+            .iter()
+            .filter(|l| l.get_type().matches(INFINITE_LIGHT))
+            .map(Arc::clone)
+            .collect();
+        scene.lights = lights;
+        scene
+    }
+
+    /// Returns a filter that accepts the primitives linked to a light. Both
+    /// light linking and shadow linking go through this filter.
+    ///
+    /// * `light_index` - Index of the light in `lights`.
+    /// * `link_type`   - The kind of link.
+    pub fn light_link_filter(
+        &self,
+        light_index: usize,
+        link_type: LightLinkType,
+    ) -> impl Fn(&dyn Primitive) -> bool + '_ {
+        let light_name = self.light_names[light_index].as_deref();
+        move |p: &dyn Primitive| {
+            p.get_light_links()
+                .is_none_or(|links| links.linked(link_type, light_name))
+        }
+    }
+
+    /// Returns whether a light illuminates a point on a primitive. Points
+    /// that aren't on a primitive are lit by all lights.
+    ///
+    /// * `light_index` - Index of the light in `lights`.
+    /// * `primitive`   - The primitive.
+    pub fn light_illuminates(&self, light_index: usize, primitive: Option<&dyn Primitive>) -> bool {
+        primitive.is_none_or(self.light_link_filter(light_index, LightLinkType::Illumination))
+    }
+
+    /// Returns the center and radius of the bounding sphere of the scene
+    /// geometry. The radius is 0 for an empty scene.
+    pub fn bounding_sphere(&self) -> (Point3f, Float) {
+        let (center, radius) = self.world_bound.bounding_sphere();
+        if radius > 0.0 {
+            (center, radius)
+        } else {
+            (Point3f::default(), 0.0)
         }
     }
 
@@ -46,32 +133,179 @@ impl Scene {
     ///
     /// * `ray` - The ray to trace.
     pub fn intersect(&self, ray: &mut Ray) -> Option<SurfaceInteraction> {
+        N_INTERSECTION_TESTS.inc();
         self.aggregate.intersect(ray)
     }
 
+    /// Traces a camera ray into the scene with the interiors of the clip shapes
+    /// removed and returns the `SurfaceInteraction` if an intersection
+    /// occurred. Where a clip shape cuts through a solid object the section is
+    /// capped with the clip shape's surface, shaded with the clip shape's
+    /// material or the cut object's material if it has none.
+    ///
+    /// * `ray` - The ray to trace.
+    pub fn intersect_camera_ray(&self, ray: &mut Ray) -> Option<SurfaceInteraction> {
+        let clip = match &self.clip_aggregate {
+            Some(clip) => clip,
+            None => return self.intersect(ray),
+        };
+
+        let mut intervals = Self::clip_intervals(clip, ray);
+        if intervals.is_empty() {
+            return self.intersect(ray);
+        }
+
+        // Skip the geometry inside the intervals and cap solids that were cut.
+        let mut r = ray.clone();
+        let mut t0 = 0.0;
+        let mut cap: Option<(Float, SurfaceInteraction)> = None;
+        loop {
+            let isect = self.intersect(&mut r)?;
+            let t = t0 + r.t_max;
+
+            if let Some((t_cap, mut cap_isect)) = cap.take() {
+                if ray.d.dot(&Vector3f::from(isect.hit.n)) > 0.0 {
+                    // The ray left the clip shape inside a solid.
+                    if cap_isect.primitive.and_then(|p| p.get_material()).is_none() {
+                        cap_isect.primitive = isect.primitive;
+                    }
+                    cap_isect.hit.n = -cap_isect.hit.n;
+                    cap_isect.shading.n = -cap_isect.shading.n;
+                    ray.t_max = t_cap;
+                    return Some(cap_isect);
+                }
+            }
+
+            match intervals.iter_mut().find(|(a, b, _)| t > *a && t < *b) {
+                Some((_, b, exit)) => {
+                    let exit_isect = exit.take()?;
+                    r = exit_isect.hit.spawn_ray(&ray.d);
+                    r.t_max = ray.t_max - *b;
+                    t0 = *b;
+                    cap = Some((*b, exit_isect));
+                }
+                None => {
+                    ray.t_max = t;
+                    return Some(isect);
+                }
+            }
+        }
+    }
+
+    /// Returns the intervals of the ray parameter inside the clip shapes along
+    /// with the surface interactions where the ray leaves them. The last
+    /// interval has no exit if the ray doesn't leave the clip shapes.
+    ///
+    /// * `clip` - The clip shapes.
+    /// * `ray`  - The ray.
+    fn clip_intervals<'a>(clip: &'a ArcPrimitive, ray: &Ray) -> Vec<ClipInterval<'a>> {
+        // Find the intervals along the ray inside the clip shapes and where the
+        // ray leaves them.
+        let mut intervals: Vec<ClipInterval> = vec![];
+        let mut r = ray.clone();
+        let mut t0 = 0.0;
+        let mut depth = 0;
+        let mut start = 0.0;
+        for _ in 0..MAX_CLIP_CROSSINGS {
+            let isect = match clip.intersect(&mut r) {
+                Some(isect) => isect,
+                None => break,
+            };
+            let t = t0 + r.t_max;
+            let next = isect.hit.spawn_ray(&ray.d);
+            if ray.d.dot(&Vector3f::from(isect.hit.n)) < 0.0 {
+                if depth == 0 {
+                    start = t;
+                }
+                depth += 1;
+            } else if depth <= 1 {
+                // An exit without an entry means the ray started inside.
+                let a = if depth == 1 { start } else { 0.0 };
+                intervals.push((a, t, Some(isect)));
+                depth = 0;
+            } else {
+                depth -= 1;
+            }
+            r = next;
+            r.t_max = ray.t_max - t;
+            t0 = t;
+        }
+        if depth > 0 {
+            intervals.push((start, INFINITY, None));
+        }
+        intervals
+    }
+
     /// Traces the ray into the scene and returns whether or not an intersection
     /// occurred.
     ///
     /// * `ray` - The ray to trace.
     pub fn intersect_p(&self, ray: &Ray) -> bool {
+        N_SHADOW_TESTS.inc();
         self.aggregate.intersect_p(ray)
     }
 
+    /// Traces the ray into the scene considering only the primitives accepted
+    /// by the filter and returns the `SurfaceInteraction` if an intersection
+    /// occurred.
+    ///
+    /// * `ray`    - The ray to trace.
+    /// * `filter` - Selects the primitives to consider; all if `None`.
+    pub fn intersect_filtered(
+        &self,
+        ray: &mut Ray,
+        filter: Option<PrimitiveFilter>,
+    ) -> Option<SurfaceInteraction> {
+        N_INTERSECTION_TESTS.inc();
+        match filter {
+            Some(filter) => self.aggregate.intersect_filtered(ray, filter),
+            None => self.aggregate.intersect(ray),
+        }
+    }
+
+    /// Traces the ray into the scene considering only the primitives accepted
+    /// by the filter and returns whether or not an intersection occurred.
+    ///
+    /// * `ray`    - The ray to trace.
+    /// * `filter` - Selects the primitives to consider; all if `None`.
+    pub fn intersect_p_filtered(&self, ray: &Ray, filter: Option<PrimitiveFilter>) -> bool {
+        N_SHADOW_TESTS.inc();
+        match filter {
+            Some(filter) => self.aggregate.intersect_p_filtered(ray, filter),
+            None => self.aggregate.intersect_p(ray),
+        }
+    }
+
+    /// Returns a filter that accepts the primitives casting shadows for a
+    /// light or `None` if the light is unnamed and shadowed by everything.
+    ///
+    /// * `light_index` - Index of the light in `lights`.
+    pub fn shadow_filter(
+        &self,
+        light_index: usize,
+    ) -> Option<impl Fn(&dyn Primitive) -> bool + '_> {
+        self.light_names[light_index]
+            .as_ref()
+            .map(|_| self.light_link_filter(light_index, LightLinkType::Shadow))
+    }
+
     /// Traces the ray into the scene and returns the first intersection with a
     /// light scattering surface along the given ray as the beam transmittance
     /// up to that point.
     ///
     /// * `ray`     - The ray to trace.
     /// * `sampler` - Sampler.
+    /// * `filter`  - Selects the primitives to consider; all if `None`.
     pub fn intersect_tr(
         &self,
         ray: &mut Ray,
         sampler: ArcSampler,
+        filter: Option<PrimitiveFilter>,
     ) -> Option<(SurfaceInteraction, Spectrum)> {
         let mut tr = Spectrum::new(1.0);
 
         loop {
-            let hit_surface = self.intersect(ray);
+            let hit_surface = self.intersect_filtered(ray, filter);
 
             // Accumulate beam transmittance for ray segment
             if let Some(medium) = &ray.medium {
@@ -118,6 +118,9 @@ pub trait CoefficientSpectrum:
     /// * `p` - The power.
     fn pow(&self, p: Float) -> Self;
 
+    /// Takes the exponential of all sample values.
+    fn exp(&self) -> Self;
+
     /// Returns the maximum sample value.
     fn max_component_value(&self) -> Float {
         let samples = self.samples();
@@ -299,11 +302,7 @@ pub fn average_spectrum_samples(
         )
     };
 
-    loop {
-        if i + 1 < n && lambda_end >= samples[i].lambda {
-            break;
-        }
-
+    while i + 1 < n && lambda_end >= samples[i].lambda {
         let seg_lambda_start = max(lambda_start, samples[i].lambda);
         let seg_lambda_end = min(lambda_end, samples[i + 1].lambda);
 
@@ -399,6 +398,10 @@ pub fn blackbody(lambda: &[Float], t: Float) -> Vec<Float> {
 /// * `lambda` - Wavelengths in nanometers.
 /// * `t`      - Temperature in Kelvin.
 pub fn blackbody_normalized(lambda: &[Float], t: Float) -> Vec<Float> {
+    if t <= 0.0 {
+        return vec![0.0; lambda.len()];
+    }
+
     let le = blackbody(lambda, t);
 
     // Normalize `Le` values based on maximum blackbody radiance.
@@ -406,3 +409,24 @@ pub fn blackbody_normalized(lambda: &[Float], t: Float) -> Vec<Float> {
     let max_l = blackbody(&[lambda_max], t);
     le.iter().map(|v| v / max_l[0]).collect()
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_spectrum_samples_integrates_segments() {
+        let samples = Sample::list(&[400.0, 0.0, 500.0, 1.0, 600.0, 1.0]);
+        assert!((average_spectrum_samples(&samples, 400.0, 500.0) - 0.5).abs() < 1e-5);
+        assert!((average_spectrum_samples(&samples, 450.0, 550.0) - 0.875).abs() < 1e-5);
+        assert!((average_spectrum_samples(&samples, 500.0, 600.0) - 1.0).abs() < 1e-5);
+
+        // Values outside the samples are constant.
+        assert!((average_spectrum_samples(&samples, 600.0, 700.0) - 1.0).abs() < 1e-5);
+        assert!(average_spectrum_samples(&samples, 300.0, 400.0).abs() < 1e-5);
+    }
+}
@@ -2,25 +2,28 @@
 
 mod cie;
 mod common;
+mod named_spectrum;
+mod reradiation;
 mod rgb;
 mod rgb_spectrum;
 mod sampled_spectrum;
+mod white_balance;
 
 // Re-export
 pub use cie::*;
 pub use common::*;
+pub use named_spectrum::*;
+pub use reradiation::*;
 pub use rgb::*;
 pub use rgb_spectrum::*;
 pub use sampled_spectrum::*;
+pub use white_balance::*;
 
 /// Default to using `RGBSpectrum` for rendering.
 #[cfg(not(feature = "sampled-spectrum"))]
 pub type Spectrum = RGBSpectrum;
 
-/// Use `SampledSpectrum` for rendering.
-///
-/// Add the following to use SampledSpetrum in Cargo.toml
-/// [features]
-/// sampled-spectrum = []
+/// Use `SampledSpectrum` for rendering when the `sampled-spectrum` feature
+/// is enabled.
 #[cfg(feature = "sampled-spectrum")]
 pub type Spectrum = SampledSpectrum;
@@ -0,0 +1,192 @@
+//! Named Spectra
+
+use super::*;
+use crate::pbrt::*;
+
+/// Sellmeier coefficients of a named glass.
+struct Glass {
+    /// Name of the glass.
+    name: &'static str,
+
+    /// Coefficients B1, B2, B3.
+    b: [Float; 3],
+
+    /// Coefficients C1, C2, C3 in μm^2.
+    c: [Float; 3],
+}
+
+/// The table of glasses from the SCHOTT optical glass catalogue with the
+/// coefficients rounded to `Float` precision.
+#[rustfmt::skip]
+const GLASSES: [Glass; 7] = [
+    Glass { name: "glass-BK7", b: [1.0396122, 0.23179235, 1.0104694], c: [0.0060006985, 0.020017914, 103.56065] },
+    Glass { name: "glass-BAF10", b: [1.5851495, 0.14355938, 1.0852127], c: [0.009266813, 0.04244898, 105.61357] },
+    Glass { name: "glass-FK51A", b: [0.9712478, 0.21690142, 0.90465164], c: [0.00472302, 0.015357561, 168.68133] },
+    Glass { name: "glass-LASF9", b: [2.0002954, 0.2989269, 1.8069184], c: [0.012142601, 0.053873625, 156.53082] },
+    Glass { name: "glass-F5", b: [1.5248189, 0.18708552, 1.4272902], c: [0.011254756, 0.05889954, 129.14168] },
+    Glass { name: "glass-F10", b: [1.621539, 0.25628784, 1.6444756], c: [0.012224146, 0.059573676, 147.4688] },
+    Glass { name: "glass-F11", b: [1.737597, 0.31374735, 1.8987811], c: [0.013188707, 0.062306814, 155.2363] },
+];
+
+/// Wavelength range and spacing in nanometers used to tabulate the glasses.
+const GLASS_LAMBDA_START: usize = 300;
+const GLASS_LAMBDA_END: usize = 800;
+const GLASS_LAMBDA_STEP: usize = 10;
+
+impl Glass {
+    /// Returns the index of refraction at a given wavelength.
+    ///
+    /// * `lambda` - Wavelength in nanometers.
+    fn eta(&self, lambda: Float) -> Float {
+        let l2 = (lambda * 1e-3) * (lambda * 1e-3); // Convert nanometers -> μm.
+        let n2 = (0..3).fold(1.0, |n2, i| n2 + self.b[i] * l2 / (l2 - self.c[i]));
+        n2.sqrt()
+    }
+}
+
+/// Measured complex index of refraction of a named metal.
+struct Metal {
+    /// Name of the metal.
+    name: &'static str,
+
+    /// (wavelength in nanometers, η, k) triples sorted by wavelength.
+    data: &'static [[Float; 3]],
+}
+
+/// Gold from "Optical Constants of the Noble Metals", Johnson and Christy,
+/// Physical Review B 6, 1972.
+#[rustfmt::skip]
+const GOLD: [[Float; 3]; 14] = [
+    [381.5, 1.46, 1.933], [397.4, 1.47, 1.952], [413.3, 1.46, 1.958], [430.5, 1.45, 1.948],
+    [450.9, 1.38, 1.914], [471.4, 1.31, 1.849], [495.9, 1.04, 1.833], [520.9, 0.62, 2.081],
+    [548.6, 0.43, 2.455], [582.1, 0.29, 2.863], [616.8, 0.21, 3.272], [659.5, 0.14, 3.697],
+    [704.5, 0.13, 4.103], [756.0, 0.14, 4.542],
+];
+
+/// Silver from "Optical Constants of the Noble Metals", Johnson and Christy,
+/// Physical Review B 6, 1972.
+#[rustfmt::skip]
+const SILVER: [[Float; 3]; 14] = [
+    [381.5, 0.05, 1.864], [397.4, 0.05, 2.070], [413.3, 0.05, 2.275], [430.5, 0.04, 2.462],
+    [450.9, 0.04, 2.657], [471.4, 0.05, 2.869], [495.9, 0.05, 3.093], [520.9, 0.05, 3.324],
+    [548.6, 0.06, 3.586], [582.1, 0.05, 3.858], [616.8, 0.06, 4.152], [659.5, 0.05, 4.483],
+    [704.5, 0.04, 4.838], [756.0, 0.03, 5.242],
+];
+
+/// Copper from "Optical Constants of the Noble Metals", Johnson and Christy,
+/// Physical Review B 6, 1972, resampled as in pbrt-v3.
+#[rustfmt::skip]
+const COPPER: [[Float; 3]; 34] = [
+    [381.652, 1.2, 2.121562], [387.659, 1.18, 2.21], [393.864, 1.174375, 2.177188],
+    [400.279, 1.175, 2.13], [406.913, 1.1775, 2.160063], [413.780, 1.18, 2.21],
+    [420.891, 1.178125, 2.249938], [428.261, 1.175, 2.289], [435.904, 1.172812, 2.326],
+    [443.837, 1.17, 2.362], [452.077, 1.165312, 2.397625], [460.643, 1.16, 2.433],
+    [469.553, 1.155312, 2.469187], [478.831, 1.15, 2.504], [488.500, 1.142812, 2.535875],
+    [498.586, 1.135, 2.564], [509.116, 1.131562, 2.589625], [520.122, 1.12, 2.605],
+    [531.635, 1.092437, 2.595562], [543.692, 1.04, 2.583], [556.333, 0.950375, 2.5765],
+    [569.600, 0.826, 2.599], [583.542, 0.645875, 2.678062], [598.211, 0.468, 2.809],
+    [613.665, 0.35125, 3.01075], [629.969, 0.272, 3.24], [647.195, 0.230813, 3.458187],
+    [665.423, 0.214, 3.67], [684.745, 0.20925, 3.863125], [705.263, 0.213, 4.05],
+    [727.091, 0.21625, 4.239563], [750.360, 0.223, 4.43], [775.222, 0.2365, 4.619563],
+    [801.852, 0.25, 4.817],
+];
+
+/// Aluminium from "Optical Properties of Metallic Films for Vertical-Cavity
+/// Optoelectronic Devices", Rakić et al., Applied Optics 37, 1998.
+#[rustfmt::skip]
+const ALUMINIUM: [[Float; 3]; 9] = [
+    [400.0, 0.49, 4.86], [450.0, 0.62, 5.47], [500.0, 0.77, 6.08], [550.0, 0.96, 6.69],
+    [600.0, 1.20, 7.26], [650.0, 1.47, 7.79], [700.0, 1.83, 8.31], [750.0, 2.40, 8.62],
+    [800.0, 2.80, 8.45],
+];
+
+/// The table of metals by chemical symbol.
+#[rustfmt::skip]
+const METALS: [Metal; 4] = [
+    Metal { name: "Au", data: &GOLD },
+    Metal { name: "Ag", data: &SILVER },
+    Metal { name: "Cu", data: &COPPER },
+    Metal { name: "Al", data: &ALUMINIUM },
+];
+
+/// Returns the samples of a named spectrum or `None` if the name is not
+/// known. Names are matched ignoring case.
+///
+/// * `name` - Name of the spectrum. Glasses are named `glass-<name>` (e.g.
+///            "glass-BK7") and give the index of refraction. Metals are named
+///            `metal-<symbol>-eta` and `metal-<symbol>-k` (e.g. "metal-Au-eta")
+///            and give the real and imaginary parts of the index of refraction.
+pub fn get_named_spectrum_samples(name: &str) -> Option<Vec<Sample>> {
+    if let Some(glass) = GLASSES.iter().find(|g| g.name.eq_ignore_ascii_case(name)) {
+        let samples = (GLASS_LAMBDA_START..=GLASS_LAMBDA_END)
+            .step_by(GLASS_LAMBDA_STEP)
+            .map(|l| Sample::new(l as Float, glass.eta(l as Float)))
+            .collect();
+        return Some(samples);
+    }
+
+    let lower = name.to_ascii_lowercase();
+    let metal = lower.strip_prefix("metal-")?;
+    let (symbol, column) = if let Some(symbol) = metal.strip_suffix("-eta") {
+        (symbol, 1)
+    } else if let Some(symbol) = metal.strip_suffix("-k") {
+        (symbol, 2)
+    } else {
+        return None;
+    };
+    METALS
+        .iter()
+        .find(|m| m.name.eq_ignore_ascii_case(symbol))
+        .map(|m| {
+            m.data
+                .iter()
+                .map(|row| Sample::new(row[0], row[column]))
+                .collect()
+        })
+}
+
+/// Returns a named spectrum or `None` if the name is not known. See
+/// `get_named_spectrum_samples()` for the available names.
+///
+/// * `name` - Name of the spectrum.
+pub fn get_named_spectrum(name: &str) -> Option<Spectrum> {
+    get_named_spectrum_samples(name).map(|samples| Spectrum::from(&samples))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glass_matches_catalogue_index() {
+        // nd at the helium d-line.
+        let bk7 = GLASSES.iter().find(|g| g.name == "glass-BK7").unwrap();
+        assert!((bk7.eta(587.56) - 1.5168).abs() < 1e-4);
+
+        let samples = get_named_spectrum_samples("GLASS-bk7").unwrap();
+        assert_eq!(samples.len(), 51);
+        assert!(samples.windows(2).all(|s| s[0].value > s[1].value));
+    }
+
+    #[test]
+    fn metals_are_found_by_symbol_and_part() {
+        let eta = get_named_spectrum_samples("metal-Au-eta").unwrap();
+        let k = get_named_spectrum_samples("metal-au-k").unwrap();
+        assert_eq!(eta.len(), GOLD.len());
+        assert_eq!(eta[8], Sample::new(548.6, 0.43));
+        assert_eq!(k[8], Sample::new(548.6, 2.455));
+
+        let copper = get_named_spectrum_samples("metal-Cu-eta").unwrap();
+        assert_eq!(copper.len(), COPPER.len());
+        assert!(are_spectrum_samples_sorted(&copper));
+
+        assert!(get_named_spectrum_samples("metal-Au").is_none());
+        assert!(get_named_spectrum_samples("metal-Xx-k").is_none());
+        assert!(get_named_spectrum_samples("glass-XYZ").is_none());
+        assert!(get_named_spectrum("unknown.spd").is_none());
+    }
+}
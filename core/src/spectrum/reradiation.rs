@@ -0,0 +1,249 @@
+//! Re-radiation Matrices
+
+use super::*;
+use crate::pbrt::*;
+use std::fs;
+
+/// Describes how a fluorescent material absorbs light at one wavelength and
+/// re-emits it at another. Rows correspond to the emission wavelengths and
+/// columns to the excitation wavelengths of `SampledSpectrum`.
+#[derive(Clone)]
+pub struct ReradiationMatrix {
+    /// The matrix elements in row major order.
+    m: Vec<Float>,
+}
+
+impl ReradiationMatrix {
+    /// Create a new `ReradiationMatrix` from tabulated data. Each value is the
+    /// fraction of the light absorbed at an excitation wavelength that is
+    /// re-emitted per nm at an emission wavelength. Values in between the
+    /// tabulated wavelengths are linearly interpolated and those outside are
+    /// zero.
+    ///
+    /// * `excitation` - Excitation wavelengths in nm sorted in ascending order.
+    /// * `emission`   - Emission wavelengths in nm sorted in ascending order.
+    /// * `values`     - The values in row major order with a row for each
+    ///                  emission wavelength.
+    pub fn new(excitation: &[Float], emission: &[Float], values: &[Float]) -> Result<Self, String> {
+        if excitation.is_empty() || emission.is_empty() {
+            return Err(String::from("No excitation or emission wavelengths"));
+        }
+        if values.len() != excitation.len() * emission.len() {
+            return Err(format!(
+                "Expected {} values for {} emission and {} excitation wavelengths. Got {}",
+                excitation.len() * emission.len(),
+                emission.len(),
+                excitation.len(),
+                values.len()
+            ));
+        }
+        if !is_sorted(excitation) || !is_sorted(emission) {
+            return Err(String::from("Wavelengths must be in ascending order"));
+        }
+
+        // Sample the data at the center of each wavelength bin and convert
+        // the density per nm to the fraction re-emitted into the bin.
+        let n = SPECTRAL_SAMPLES;
+        let width = (SAMPLED_LAMBDA_END - SAMPLED_LAMBDA_START) as Float / n as Float;
+        let mut m = vec![0.0; n * n];
+        for j in 0..n {
+            let lambda_o = bin_center(j);
+            for i in 0..n {
+                let lambda_i = bin_center(i);
+                m[j * n + i] =
+                    width * interpolate(excitation, emission, values, lambda_i, lambda_o);
+            }
+        }
+
+        Ok(Self { m })
+    }
+
+    /// Read a `ReradiationMatrix` from a text file. Lines starting with `#`
+    /// are ignored. The first line lists the excitation wavelengths and each
+    /// following line has an emission wavelength followed by one value for
+    /// each excitation wavelength.
+    ///
+    /// * `path` - Path to the file.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("{}", err))?;
+
+        let mut excitation: Vec<Float> = vec![];
+        let mut emission: Vec<Float> = vec![];
+        let mut values: Vec<Float> = vec![];
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let row = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<Float>())
+                .collect::<Result<Vec<Float>, _>>()
+                .map_err(|err| format!("{}, line {}", err, line_no + 1))?;
+
+            if excitation.is_empty() {
+                excitation = row;
+            } else if row.len() != excitation.len() + 1 {
+                return Err(format!(
+                    "Expected {} values, line {}",
+                    excitation.len() + 1,
+                    line_no + 1
+                ));
+            } else {
+                emission.push(row[0]);
+                values.extend_from_slice(&row[1..]);
+            }
+        }
+
+        Self::new(&excitation, &emission, &values)
+    }
+
+    /// Returns the matrix scaled by a constant.
+    ///
+    /// * `s` - The scale.
+    pub fn scale(&self, s: Float) -> Self {
+        Self {
+            m: self.m.iter().map(|v| v * s).collect(),
+        }
+    }
+
+    /// Returns the largest fraction of the light at any excitation wavelength
+    /// that is re-emitted. Values greater than 1 do not conserve energy.
+    pub fn max_efficiency(&self) -> Float {
+        let n = SPECTRAL_SAMPLES;
+        (0..n)
+            .map(|i| (0..n).map(|j| self.m[j * n + i]).sum::<Float>())
+            .fold(0.0, Float::max)
+    }
+
+    /// Returns the spectrum re-emitted for incident light.
+    ///
+    /// * `s` - Spectrum of the incident light.
+    pub fn apply(&self, s: &SampledSpectrum) -> SampledSpectrum {
+        let n = SPECTRAL_SAMPLES;
+        let c = s.samples();
+        let mut r = SampledSpectrum::new(0.0);
+        for (j, v) in r.samples_mut().iter_mut().enumerate() {
+            let row = &self.m[j * n..(j + 1) * n];
+            *v = row.iter().zip(c.iter()).map(|(a, b)| a * b).sum();
+        }
+        r
+    }
+}
+
+/// Returns the wavelength at the center of a `SampledSpectrum` bin.
+///
+/// * `i` - Index of the bin.
+fn bin_center(i: usize) -> Float {
+    lerp(
+        (i as Float + 0.5) / SPECTRAL_SAMPLES as Float,
+        SAMPLED_LAMBDA_START as Float,
+        SAMPLED_LAMBDA_END as Float,
+    )
+}
+
+/// Returns `true` if the values are in ascending order.
+///
+/// * `v` - The values.
+fn is_sorted(v: &[Float]) -> bool {
+    v.windows(2).all(|w| w[0] < w[1])
+}
+
+/// Returns the weights for linearly interpolating tabulated values at `x`
+/// or `None` if `x` is outside the table.
+///
+/// * `xs` - The tabulated positions in ascending order.
+/// * `x`  - The position.
+fn lerp_weights(xs: &[Float], x: Float) -> Option<(usize, Float)> {
+    let last = xs.len() - 1;
+    if x < xs[0] || x > xs[last] {
+        None
+    } else if last == 0 || x == xs[last] {
+        Some((last, 0.0))
+    } else {
+        let i = xs.iter().rposition(|v| *v <= x).unwrap_or(0).min(last - 1);
+        Some((i, (x - xs[i]) / (xs[i + 1] - xs[i])))
+    }
+}
+
+/// Bilinearly interpolates a tabulated re-radiation matrix.
+///
+/// * `excitation` - Excitation wavelengths.
+/// * `emission`   - Emission wavelengths.
+/// * `values`     - The values in row major order.
+/// * `lambda_i`   - The excitation wavelength.
+/// * `lambda_o`   - The emission wavelength.
+fn interpolate(
+    excitation: &[Float],
+    emission: &[Float],
+    values: &[Float],
+    lambda_i: Float,
+    lambda_o: Float,
+) -> Float {
+    let nx = excitation.len();
+    match (
+        lerp_weights(excitation, lambda_i),
+        lerp_weights(emission, lambda_o),
+    ) {
+        (Some((i, ti)), Some((j, tj))) => {
+            let v = |j: usize, i: usize| values[j * nx + min(i, nx - 1)];
+            let j1 = min(j + 1, emission.len() - 1);
+            let v0 = lerp(ti, v(j, i), v(j, i + 1));
+            let v1 = lerp(ti, v(j1, i), v(j1, i + 1));
+            lerp(tj, v0, v1)
+        }
+        _ => 0.0,
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reradiation_shifts_wavelengths() {
+        // Light absorbed around 450 nm is re-emitted around 550 nm.
+        let excitation = [440.0, 450.0, 460.0];
+        let emission = [540.0, 550.0, 560.0];
+        #[rustfmt::skip]
+        let values = [
+            0.0, 0.0, 0.0,
+            0.0, 0.1, 0.0,
+            0.0, 0.0, 0.0,
+        ];
+        let m = ReradiationMatrix::new(&excitation, &emission, &values).unwrap();
+
+        let mut blue = SampledSpectrum::new(0.0);
+        blue[10] = 1.0; // 450 - 455 nm.
+        let r = m.apply(&blue);
+        for (i, v) in r.samples().iter().enumerate() {
+            if (28..32).contains(&i) {
+                assert!(*v > 0.0, "bin {} = {}", i, v);
+            } else {
+                assert_eq!(*v, 0.0, "bin {}", i);
+            }
+        }
+
+        // Light outside the excitation band is not re-emitted.
+        assert!(m.apply(&SampledSpectrum::new(0.0)).is_black());
+        let mut red = SampledSpectrum::new(0.0);
+        red[50] = 1.0;
+        assert!(m.apply(&red).is_black());
+
+        assert!(m.max_efficiency() <= 1.0);
+        assert!((m.scale(2.0).apply(&blue)[30] - 2.0 * r[30]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn reradiation_validates_data() {
+        assert!(ReradiationMatrix::new(&[], &[500.0], &[]).is_err());
+        assert!(ReradiationMatrix::new(&[450.0], &[500.0], &[0.1, 0.2]).is_err());
+        assert!(ReradiationMatrix::new(&[460.0, 450.0], &[500.0], &[0.1, 0.2]).is_err());
+    }
+}
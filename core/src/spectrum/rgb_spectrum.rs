@@ -7,16 +7,27 @@ use std::fmt;
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
+#[cfg(feature = "simd")]
+use wide::{f32x4, CmpEq};
 
 /// Number of spectral samples to use for `RGBSpectrum`.
 pub const RGB_SAMPLES: usize = 3;
 
+/// Storage for the sample values.
+#[cfg(not(feature = "simd"))]
+type Samples = [Float; RGB_SAMPLES];
+
+/// Storage for the sample values in the first three lanes of a SIMD vector.
+/// The last lane is kept at zero.
+#[cfg(feature = "simd")]
+type Samples = f32x4;
+
 /// RGBSpectrum represents an spectral power distribution (SPD) with
 /// a weighted sum of red, green and blue components.
 #[derive(Copy, Clone)]
 pub struct RGBSpectrum {
     /// The sampled spectral values.
-    c: [Float; 3],
+    c: Samples,
 }
 
 impl RGBSpectrum {
@@ -25,20 +36,34 @@ impl RGBSpectrum {
     ///
     /// * `v` - Constant value.
     pub fn new(v: Float) -> Self {
-        let ret = Self {
-            c: [v; RGB_SAMPLES],
-        };
+        let ret = Self::with_samples([v; RGB_SAMPLES]);
         assert!(!ret.has_nans());
         ret
     }
+
+    /// Create a new `RGBSpectrum` from sample values without checking them.
+    ///
+    /// * `c` - Sample values.
+    #[cfg(not(feature = "simd"))]
+    fn with_samples(c: [Float; RGB_SAMPLES]) -> Self {
+        Self { c }
+    }
+
+    /// Create a new `RGBSpectrum` from sample values without checking them.
+    ///
+    /// * `c` - Sample values.
+    #[cfg(feature = "simd")]
+    fn with_samples(c: [Float; RGB_SAMPLES]) -> Self {
+        Self {
+            c: f32x4::from([c[0], c[1], c[2], 0.0]),
+        }
+    }
 }
 
 impl Default for RGBSpectrum {
     /// Return a black `RGBSpectrum`.
     fn default() -> Self {
-        Self {
-            c: [0.0; RGB_SAMPLES],
-        }
+        Self::with_samples([0.0; RGB_SAMPLES])
     }
 }
 
@@ -57,15 +82,13 @@ impl From<Vec<Float>> for RGBSpectrum {
     ///
     /// * `c` - Sample values.
     fn from(c: Vec<Float>) -> Self {
-        let ret = Self {
-            c: c.try_into().unwrap_or_else(|v: Vec<Float>| {
-                panic!(
-                    "Expected a Vec of length {} but it was {}",
-                    RGB_SAMPLES,
-                    v.len()
-                )
-            }),
-        };
+        let ret = Self::with_samples(c.try_into().unwrap_or_else(|v: Vec<Float>| {
+            panic!(
+                "Expected a Vec of length {} but it was {}",
+                RGB_SAMPLES,
+                v.len()
+            )
+        }));
         assert!(!ret.has_nans());
         ret
     }
@@ -76,7 +99,7 @@ impl From<[Float; RGB_SAMPLES]> for RGBSpectrum {
     ///
     /// * `c` - Sample values.
     fn from(c: [Float; RGB_SAMPLES]) -> Self {
-        let ret = Self { c };
+        let ret = Self::with_samples(c);
         assert!(!ret.has_nans());
         ret
     }
@@ -94,7 +117,8 @@ impl From<&Vec<Sample>> for RGBSpectrum {
         };
 
         let xyz = (0..CIE_SAMPLES).fold([0.0; 3], |v, i| {
-            let val = interpolate_spectrum_samples(samples, (CIE_LAMBDA_START + i) as Float);
+            let val =
+                interpolate_spectrum_samples(&sorted_samples, (CIE_LAMBDA_START + i) as Float);
             [
                 v[0] + val * CIE_X[i],
                 v[1] + val * CIE_Y[i],
@@ -105,40 +129,56 @@ impl From<&Vec<Sample>> for RGBSpectrum {
         let scale =
             (CIE_LAMBDA_END - CIE_LAMBDA_START) as Float / (CIE_Y_INTEGRAL * CIE_SAMPLES as Float);
 
-        Self {
-            c: [xyz[0] * scale, xyz[1] * scale, xyz[2] * scale],
-        }
+        Self::with_samples(xyz_to_rgb(&[
+            xyz[0] * scale,
+            xyz[1] * scale,
+            xyz[2] * scale,
+        ]))
     }
 }
 
 impl CoefficientSpectrum for RGBSpectrum {
     /// Returns the stored samples.
+    #[cfg(not(feature = "simd"))]
     fn samples(&self) -> &[Float] {
         &self.c
     }
 
     /// Returns stored samples as mutable.
+    #[cfg(not(feature = "simd"))]
     fn samples_mut(&mut self) -> &mut [Float] {
         &mut self.c
     }
 
+    /// Returns the stored samples.
+    #[cfg(feature = "simd")]
+    fn samples(&self) -> &[Float] {
+        &self.c.as_array_ref()[..RGB_SAMPLES]
+    }
+
+    /// Returns stored samples as mutable.
+    #[cfg(feature = "simd")]
+    fn samples_mut(&mut self) -> &mut [Float] {
+        &mut self.c.as_array_mut()[..RGB_SAMPLES]
+    }
+
     /// Converts XYZ values to a full SPD.
     ///
     /// * `xyz`           - XYZ colour value.
     /// * `spectrum_type` - Indicates type of colour value. If `None`,
     ///                     defaults to `SpectrumType::Reflectance`.
     fn from_xyz(xyz: &[Float; 3], _spectrum_type: Option<SpectrumType>) -> Self {
-        Self { c: xyz_to_rgb(xyz) }
+        Self::with_samples(xyz_to_rgb(xyz))
     }
 
     /// Convert the SPD to XYZ cooefficients.
     fn to_xyz(&self) -> [Float; 3] {
-        rgb_to_xyz(&self.c)
+        rgb_to_xyz(&self.to_rgb())
     }
 
     /// Returns the y-coefficient of XYZ colour.
     fn y(&self) -> Float {
-        0.212671 * self.c[0] + 0.715160 * self.c[1] + 0.072169 * self.c[2]
+        0.212671 * self[0] + 0.715160 * self[1] + 0.072169 * self[2]
     }
 
     /// Converts RGB values to a full SPD.
@@ -147,36 +187,82 @@ impl CoefficientSpectrum for RGBSpectrum {
     /// * `spectrum_type` - Indicates type of colour value. If `None`,
     ///                     defaults to `SpectrumType::Reflectance`.
     fn from_rgb(rgb: &[Float; 3], _spectrum_type: Option<SpectrumType>) -> Self {
-        Self {
-            c: [rgb[0], rgb[1], rgb[2]],
-        }
+        Self::with_samples(*rgb)
     }
 
     /// Convert the SPD to RGB cooefficients.
     fn to_rgb(&self) -> [Float; 3] {
-        [self.c[0], self.c[1], self.c[2]]
+        [self[0], self[1], self[2]]
     }
 
     /// Takes the square root of all sample values.
     fn sqrt(&self) -> Self {
-        Self {
-            c: [self.c[0].sqrt(), self.c[1].sqrt(), self.c[2].sqrt()],
-        }
+        Self::with_samples([self[0].sqrt(), self[1].sqrt(), self[2].sqrt()])
     }
 
     /// Raises the sample values to a given power.
     ///
     /// * `p` - The power.
     fn pow(&self, p: Float) -> Self {
-        Self {
-            c: [self.c[0].powf(p), self.c[1].powf(p), self.c[2].powf(p)],
-        }
+        Self::with_samples([self[0].powf(p), self[1].powf(p), self[2].powf(p)])
+    }
+
+    /// Takes the exponential of all sample values.
+    fn exp(&self) -> Self {
+        Self::with_samples([self[0].exp(), self[1].exp(), self[2].exp()])
     }
 
     /// Converts to an `RGBSpectrum`.
     fn to_rgb_spectrum(&self) -> RGBSpectrum {
         *self
     }
+
+    /// Adds the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn add(&mut self, other: &Self) {
+        self.c += other.c;
+        assert!(!self.has_nans());
+    }
+
+    /// Subtract the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn sub(&mut self, other: &Self) {
+        self.c -= other.c;
+        assert!(!self.has_nans());
+    }
+
+    /// Multiplies the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn mul(&mut self, other: &Self) {
+        self.c *= other.c;
+        assert!(!self.has_nans());
+    }
+
+    /// Divides the sample values from another SPD. The last lane is divided
+    /// by one to keep it at zero.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn div(&mut self, other: &Self) {
+        let padding = f32x4::from([0.0, 0.0, 0.0, 1.0]);
+        self.c /= padding.cmp_eq(f32x4::ZERO).blend(other.c, padding);
+        assert!(!self.has_nans());
+    }
+
+    /// Scales the sample values by a constant factor.
+    ///
+    /// * `f` - The factor.
+    #[cfg(feature = "simd")]
+    fn scale(&mut self, f: Float) {
+        self.c *= f32x4::splat(f);
+        assert!(!self.has_nans());
+    }
 }
 
 impl From<SampledSpectrum> for RGBSpectrum {
@@ -184,7 +270,7 @@ impl From<SampledSpectrum> for RGBSpectrum {
     ///
     /// * `s` - The `SampledSpectrum`.
     fn from(s: SampledSpectrum) -> Self {
-        RGBSpectrum { c: s.to_rgb() }
+        RGBSpectrum::with_samples(s.to_rgb())
     }
 }
 
@@ -359,7 +445,7 @@ impl Index<usize> for RGBSpectrum {
     ///
     /// * `i` -  The index.
     fn index(&self, index: usize) -> &Self::Output {
-        &self.c[index]
+        &self.samples()[index]
     }
 }
 
@@ -368,7 +454,7 @@ impl IndexMut<usize> for RGBSpectrum {
     ///
     /// * `i` - The index.
     fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        &mut self.c[i]
+        &mut self.samples_mut()[i]
     }
 }
 
@@ -378,13 +464,11 @@ impl Clamp<Float> for RGBSpectrum {
     /// * `low`  - Low value.
     /// * `high` - High value.
     fn clamp(&self, low: Float, high: Float) -> Self {
-        Self {
-            c: [
-                clamp(self.c[0], low, high),
-                clamp(self.c[1], low, high),
-                clamp(self.c[2], low, high),
-            ],
-        }
+        Self::with_samples([
+            clamp(self[0], low, high),
+            clamp(self[1], low, high),
+            clamp(self[2], low, high),
+        ])
     }
 
     /// Clamps the values to [0.0, INFINITY].
@@ -396,6 +480,34 @@ impl Clamp<Float> for RGBSpectrum {
 impl fmt::Display for RGBSpectrum {
     /// Formats the value using the given formatter.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}, {}, {}]", self.c[0], self.c[1], self.c[2])
+        write!(f, "[{}, {}, {}]", self[0], self[1], self[2])
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_is_per_sample() {
+        let a = RGBSpectrum::from([1.0, 2.0, 4.0]);
+        let b = RGBSpectrum::from([2.0, 0.5, 8.0]);
+
+        assert_eq!((a + b).to_rgb(), [3.0, 2.5, 12.0]);
+        assert_eq!((a - b).to_rgb(), [-1.0, 1.5, -4.0]);
+        assert_eq!((a * b).to_rgb(), [2.0, 1.0, 32.0]);
+        assert_eq!((a / b).to_rgb(), [0.5, 4.0, 0.5]);
+        assert_eq!((a * 2.0).to_rgb(), [2.0, 4.0, 8.0]);
+        assert_eq!((a / 2.0).to_rgb(), [0.5, 1.0, 2.0]);
+        assert_eq!((-a).to_rgb(), [-1.0, -2.0, -4.0]);
+        assert_eq!(a.samples().len(), RGB_SAMPLES);
+        assert_eq!(a.max_component_value(), 4.0);
+        assert!((a - a).is_black());
+        assert!(!(a / b).has_nans());
+        assert_eq!(RGBSpectrum::default().exp().to_rgb(), [1.0, 1.0, 1.0]);
     }
 }
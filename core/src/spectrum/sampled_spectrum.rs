@@ -7,6 +7,8 @@ use std::fmt;
 use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
+#[cfg(feature = "simd")]
+use wide::f32x4;
 
 /// Starting wavelength in nm for SPDs.
 pub const SAMPLED_LAMBDA_START: usize = 400;
@@ -17,6 +19,22 @@ pub const SAMPLED_LAMBDA_END: usize = 700;
 /// Number of spectral samples to use for `SampledSpectrum`.
 pub const SPECTRAL_SAMPLES: usize = 60;
 
+lazy_static! {
+    /// Scales the illuminant spectrum converted from RGB white to unit
+    /// luminance so that RGB emitters keep their brightness when rendering
+    /// with `SampledSpectrum`.
+    static ref ILLUMINANT_SCALE: Float =
+        1.0 / RGB_TO_SPECTRUM[SpectrumType::Illuminant][WHITE].y();
+}
+
+/// Storage for the sample values.
+#[cfg(not(feature = "simd"))]
+type Samples = [Float; SPECTRAL_SAMPLES];
+
+/// Storage for the sample values in SIMD vectors of 4 samples each.
+#[cfg(feature = "simd")]
+type Samples = [f32x4; SPECTRAL_SAMPLES / 4];
+
 /// SampledSpectrum represents an spectral power distribution (SPD) with
 /// uniformly spaced samples between a starting and ending wavelength.
 ///
@@ -25,7 +43,7 @@ pub const SPECTRAL_SAMPLES: usize = 60;
 #[derive(Copy, Clone)]
 pub struct SampledSpectrum {
     /// The sampled spectral values.
-    c: [Float; SPECTRAL_SAMPLES],
+    c: Samples,
 }
 
 impl SampledSpectrum {
@@ -34,20 +52,38 @@ impl SampledSpectrum {
     ///
     /// * `v` - Constant value.
     pub fn new(v: Float) -> Self {
-        let ret = Self {
-            c: [v; SPECTRAL_SAMPLES],
-        };
+        let ret = Self::with_samples([v; SPECTRAL_SAMPLES]);
         assert!(!ret.has_nans());
         ret
     }
+
+    /// Create a new `SampledSpectrum` from sample values without checking
+    /// them.
+    ///
+    /// * `c` - Sample values.
+    #[cfg(not(feature = "simd"))]
+    fn with_samples(c: [Float; SPECTRAL_SAMPLES]) -> Self {
+        Self { c }
+    }
+
+    /// Create a new `SampledSpectrum` from sample values without checking
+    /// them.
+    ///
+    /// * `c` - Sample values.
+    #[cfg(feature = "simd")]
+    fn with_samples(c: [Float; SPECTRAL_SAMPLES]) -> Self {
+        let mut ret = Self {
+            c: [f32x4::ZERO; SPECTRAL_SAMPLES / 4],
+        };
+        ret.samples_mut().copy_from_slice(&c);
+        ret
+    }
 }
 
 impl Default for SampledSpectrum {
     /// Return a black `SampledSpectrum`.
     fn default() -> Self {
-        Self {
-            c: [0.0; SPECTRAL_SAMPLES],
-        }
+        Self::with_samples([0.0; SPECTRAL_SAMPLES])
     }
 }
 
@@ -66,15 +102,13 @@ impl From<Vec<Float>> for SampledSpectrum {
     ///
     /// * `c` - Sample values.
     fn from(c: Vec<Float>) -> Self {
-        let ret = Self {
-            c: c.try_into().unwrap_or_else(|v: Vec<Float>| {
-                panic!(
-                    "Expected a Vec of length {} but it was {}",
-                    SPECTRAL_SAMPLES,
-                    v.len()
-                )
-            }),
-        };
+        let ret = Self::with_samples(c.try_into().unwrap_or_else(|v: Vec<Float>| {
+            panic!(
+                "Expected a Vec of length {} but it was {}",
+                SPECTRAL_SAMPLES,
+                v.len()
+            )
+        }));
         assert!(!ret.has_nans());
         ret
     }
@@ -104,24 +138,38 @@ impl From<&Vec<Sample>> for SampledSpectrum {
                 SAMPLED_LAMBDA_START as Float,
                 SAMPLED_LAMBDA_END as Float,
             );
-            c[i] = average_spectrum_samples(samples, lambda0, lambda1);
+            c[i] = average_spectrum_samples(&sorted_samples, lambda0, lambda1);
         }
 
-        Self { c }
+        Self::with_samples(c)
     }
 }
 
 impl CoefficientSpectrum for SampledSpectrum {
     /// Returns the stored samples.
+    #[cfg(not(feature = "simd"))]
     fn samples(&self) -> &[Float] {
         &self.c
     }
 
     /// Returns stored samples as mutable.
+    #[cfg(not(feature = "simd"))]
     fn samples_mut(&mut self) -> &mut [Float] {
         &mut self.c
     }
 
+    /// Returns the stored samples.
+    #[cfg(feature = "simd")]
+    fn samples(&self) -> &[Float] {
+        bytemuck::cast_slice(&self.c)
+    }
+
+    /// Returns stored samples as mutable.
+    #[cfg(feature = "simd")]
+    fn samples_mut(&mut self) -> &mut [Float] {
+        bytemuck::cast_slice_mut(&mut self.c)
+    }
+
     /// Converts XYZ values to a full SPD.
     ///
     /// * `xyz`           - XYZ colour value.
@@ -135,9 +183,9 @@ impl CoefficientSpectrum for SampledSpectrum {
     fn to_xyz(&self) -> [Float; 3] {
         let (x, y, z) = (0..SPECTRAL_SAMPLES).fold((0.0, 0.0, 0.0), |(sx, sy, sz), i| {
             (
-                sx + CIE_CURVES.x[i] * self.c[i],
-                sy + CIE_CURVES.y[i] * self.c[i],
-                sz + CIE_CURVES.z[i] * self.c[i],
+                sx + CIE_CURVES.x[i] * self[i],
+                sy + CIE_CURVES.y[i] * self[i],
+                sz + CIE_CURVES.z[i] * self[i],
             )
         });
 
@@ -149,8 +197,9 @@ impl CoefficientSpectrum for SampledSpectrum {
 
     /// Returns the y-coefficient of XYZ colour.
     fn y(&self) -> Float {
-        let yy = (0..SPECTRAL_SAMPLES).fold(0.0, |a, i| a + CIE_CURVES.y[i] * self.c[i]);
-        yy * (SAMPLED_LAMBDA_END - SAMPLED_LAMBDA_START) as Float / (SPECTRAL_SAMPLES as Float)
+        let yy = (0..SPECTRAL_SAMPLES).fold(0.0, |a, i| a + CIE_CURVES.y[i] * self[i]);
+        yy * (SAMPLED_LAMBDA_END - SAMPLED_LAMBDA_START) as Float
+            / (CIE_Y_INTEGRAL * SPECTRAL_SAMPLES as Float)
     }
 
     /// Converts RGB values to a full SPD.
@@ -198,7 +247,7 @@ impl CoefficientSpectrum for SampledSpectrum {
 
         match spectrum_type {
             SpectrumType::Reflectance => r * 0.94,
-            SpectrumType::Illuminant => r * 0.86445,
+            SpectrumType::Illuminant => r * *ILLUMINANT_SCALE,
         }
     }
 
@@ -211,9 +260,9 @@ impl CoefficientSpectrum for SampledSpectrum {
     fn sqrt(&self) -> Self {
         let mut c = [0.0; SPECTRAL_SAMPLES];
         for i in 0..SPECTRAL_SAMPLES {
-            c[i] = self.c[i].sqrt();
+            c[i] = self[i].sqrt();
         }
-        Self { c }
+        Self::with_samples(c)
     }
 
     /// Raises the sample values to a given power.
@@ -222,15 +271,80 @@ impl CoefficientSpectrum for SampledSpectrum {
     fn pow(&self, p: Float) -> Self {
         let mut c = [0.0; SPECTRAL_SAMPLES];
         for i in 0..SPECTRAL_SAMPLES {
-            c[i] = self.c[i].powf(p);
+            c[i] = self[i].powf(p);
         }
-        Self { c }
+        Self::with_samples(c)
+    }
+
+    /// Takes the exponential of all sample values.
+    fn exp(&self) -> Self {
+        let mut c = [0.0; SPECTRAL_SAMPLES];
+        for i in 0..SPECTRAL_SAMPLES {
+            c[i] = self[i].exp();
+        }
+        Self::with_samples(c)
     }
 
     /// Converts to an `RGBSpectrum`.
     fn to_rgb_spectrum(&self) -> RGBSpectrum {
         RGBSpectrum::from(self.to_rgb())
     }
+
+    /// Adds the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn add(&mut self, other: &Self) {
+        for (c, o) in self.c.iter_mut().zip(other.c.iter()) {
+            *c += *o;
+        }
+        assert!(!self.has_nans());
+    }
+
+    /// Subtract the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn sub(&mut self, other: &Self) {
+        for (c, o) in self.c.iter_mut().zip(other.c.iter()) {
+            *c -= *o;
+        }
+        assert!(!self.has_nans());
+    }
+
+    /// Multiplies the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn mul(&mut self, other: &Self) {
+        for (c, o) in self.c.iter_mut().zip(other.c.iter()) {
+            *c *= *o;
+        }
+        assert!(!self.has_nans());
+    }
+
+    /// Divides the sample values from another SPD.
+    ///
+    /// * `other` - The other SPD.
+    #[cfg(feature = "simd")]
+    fn div(&mut self, other: &Self) {
+        for (c, o) in self.c.iter_mut().zip(other.c.iter()) {
+            *c /= *o;
+        }
+        assert!(!self.has_nans());
+    }
+
+    /// Scales the sample values by a constant factor.
+    ///
+    /// * `f` - The factor.
+    #[cfg(feature = "simd")]
+    fn scale(&mut self, f: Float) {
+        let f = f32x4::splat(f);
+        for c in self.c.iter_mut() {
+            *c *= f;
+        }
+        assert!(!self.has_nans());
+    }
 }
 
 impl Add for SampledSpectrum {
@@ -404,7 +518,7 @@ impl Index<usize> for SampledSpectrum {
     ///
     /// * `i` -  The index.
     fn index(&self, index: usize) -> &Self::Output {
-        &self.c[index]
+        &self.samples()[index]
     }
 }
 
@@ -413,7 +527,7 @@ impl IndexMut<usize> for SampledSpectrum {
     ///
     /// * `i` - The index.
     fn index_mut(&mut self, i: usize) -> &mut Self::Output {
-        &mut self.c[i]
+        &mut self.samples_mut()[i]
     }
 }
 
@@ -425,9 +539,9 @@ impl Clamp<Float> for SampledSpectrum {
     fn clamp(&self, low: Float, high: Float) -> Self {
         let mut c = [0.0; SPECTRAL_SAMPLES];
         for i in 0..SPECTRAL_SAMPLES {
-            c[i] = clamp(self.c[i], low, high);
+            c[i] = clamp(self[i], low, high);
         }
-        Self { c }
+        Self::with_samples(c)
     }
 
     /// Clamps the values to [0.0, INFINITY].
@@ -440,7 +554,7 @@ impl fmt::Display for SampledSpectrum {
     /// Formats the value using the given formatter.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "[")?;
-        for (i, v) in self.c.iter().enumerate() {
+        for (i, v) in self.samples().iter().enumerate() {
             write!(f, "{}", v)?;
             if i < SPECTRAL_SAMPLES - 1 {
                 write!(f, ", ")?;
@@ -449,3 +563,39 @@ impl fmt::Display for SampledSpectrum {
         write!(f, "]")
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_is_per_sample() {
+        let a = SampledSpectrum::from(
+            (0..SPECTRAL_SAMPLES)
+                .map(|i| i as Float)
+                .collect::<Vec<_>>(),
+        );
+        let b = SampledSpectrum::new(2.0);
+
+        let sum = a + b;
+        let difference = a - b;
+        let product = a * b;
+        let quotient = a / b;
+        let scaled = a * 0.5;
+        for i in 0..SPECTRAL_SAMPLES {
+            let v = i as Float;
+            assert_eq!(sum[i], v + 2.0);
+            assert_eq!(difference[i], v - 2.0);
+            assert_eq!(product[i], v * 2.0);
+            assert_eq!(quotient[i], v / 2.0);
+            assert_eq!(scaled[i], v * 0.5);
+        }
+        assert_eq!(a.samples().len(), SPECTRAL_SAMPLES);
+        assert_eq!(a.max_component_value(), (SPECTRAL_SAMPLES - 1) as Float);
+        assert!((a - a).is_black());
+    }
+}
@@ -0,0 +1,281 @@
+//! White Balance
+
+use crate::pbrt::*;
+
+/// XYZ coordinates of the D65 white point with Y = 1. This is the white
+/// point of the RGB primaries used by `xyz_to_rgb()`.
+pub const D65_WHITE_XYZ: [Float; 3] = [0.95047, 1.0, 1.08883];
+
+/// Bradford cone response matrix.
+#[rustfmt::skip]
+const BRADFORD: [[Float; 3]; 3] = [
+    [ 0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135,  0.0367],
+    [ 0.0389, -0.0685, 1.0296],
+];
+
+/// CIECAM02 cone response matrix.
+#[rustfmt::skip]
+const CAT02: [[Float; 3]; 3] = [
+    [ 0.7328, 0.4296, -0.1624],
+    [-0.7036, 1.6975,  0.0061],
+    [ 0.0030, 0.0136,  0.9834],
+];
+
+/// Chromatic adaptation transforms.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChromaticAdaptation {
+    /// Bradford transform.
+    Bradford,
+
+    /// CIECAM02 transform.
+    Cat02,
+}
+
+impl ChromaticAdaptation {
+    /// Returns the chromatic adaptation transform with the given name.
+    ///
+    /// * `name` - Name of the transform (`bradford` or `cat02`).
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "bradford" => Ok(Self::Bradford),
+            "cat02" => Ok(Self::Cat02),
+            _ => Err(format!("Unknown chromatic adaptation '{}'", name)),
+        }
+    }
+
+    /// Returns the matrix converting XYZ to cone responses.
+    fn cone_response(&self) -> [[Float; 3]; 3] {
+        match self {
+            Self::Bradford => BRADFORD,
+            Self::Cat02 => CAT02,
+        }
+    }
+}
+
+/// Adapts XYZ colours so that a reference white is mapped to the D65 white
+/// point. Images lit by a non-D65 illuminant are neutralized by using the
+/// illuminant's white as the reference white.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WhiteBalance {
+    /// Matrix applied to XYZ colours.
+    matrix: [[Float; 3]; 3],
+}
+
+impl WhiteBalance {
+    /// Create a new `WhiteBalance` for a reference white.
+    ///
+    /// * `white`      - XYZ coordinates of the reference white.
+    /// * `adaptation` - The chromatic adaptation transform.
+    pub fn new(white: &[Float; 3], adaptation: ChromaticAdaptation) -> Self {
+        let m = adaptation.cone_response();
+        let src = mul_vector(&m, white);
+        let dst = mul_vector(&m, &D65_WHITE_XYZ);
+
+        // Scale cone responses of the reference white to those of D65.
+        let mut scale = [[0.0; 3]; 3];
+        for i in 0..3 {
+            scale[i][i] = dst[i] / src[i];
+        }
+
+        Self {
+            matrix: mul_matrix(&inverse(&m), &mul_matrix(&scale, &m)),
+        }
+    }
+
+    /// Create a new `WhiteBalance` for a reference white given by its CIE xy
+    /// chromaticity.
+    ///
+    /// * `xy`         - Chromaticity of the reference white.
+    /// * `adaptation` - The chromatic adaptation transform.
+    pub fn from_chromaticity(xy: &[Float; 2], adaptation: ChromaticAdaptation) -> Self {
+        Self::new(&xy_to_xyz(xy), adaptation)
+    }
+
+    /// Create a new `WhiteBalance` for a reference white on the Planckian
+    /// locus offset by a tint.
+    ///
+    /// * `temperature` - Correlated colour temperature in Kelvin.
+    /// * `tint`        - Offset perpendicular to the Planckian locus in CIE
+    ///                   1960 uv units of 0.001. Positive values move the
+    ///                   reference white toward green.
+    /// * `adaptation`  - The chromatic adaptation transform.
+    pub fn from_temperature(
+        temperature: Float,
+        tint: Float,
+        adaptation: ChromaticAdaptation,
+    ) -> Self {
+        Self::from_chromaticity(&temperature_to_xy(temperature, tint), adaptation)
+    }
+
+    /// Returns the adapted XYZ colour.
+    ///
+    /// * `xyz` - The XYZ colour.
+    pub fn apply(&self, xyz: &[Float; 3]) -> [Float; 3] {
+        mul_vector(&self.matrix, xyz)
+    }
+}
+
+/// Returns the CIE xy chromaticity of a colour temperature offset by a tint.
+///
+/// The Planckian locus is approximated with the cubic splines of Kim et al.
+/// which are valid from 1667K to 25000K.
+///
+/// * `temperature` - Correlated colour temperature in Kelvin.
+/// * `tint`        - Offset perpendicular to the Planckian locus in CIE 1960
+///                   uv units of 0.001. Positive values move toward green.
+pub fn temperature_to_xy(temperature: Float, tint: Float) -> [Float; 2] {
+    let t = clamp(temperature, 1667.0, 25000.0);
+    let xy = planckian_xy(t);
+    if tint == 0.0 {
+        return xy;
+    }
+
+    // Offset along the normal of the locus toward green (increasing v).
+    let uv0 = xy_to_uv(&planckian_xy(t - 1.0));
+    let uv1 = xy_to_uv(&planckian_xy(t + 1.0));
+    let (du, dv) = (uv1[0] - uv0[0], uv1[1] - uv0[1]);
+    let len = (du * du + dv * dv).sqrt();
+    let normal = if du >= 0.0 {
+        [-dv / len, du / len]
+    } else {
+        [dv / len, -du / len]
+    };
+    let uv = xy_to_uv(&xy);
+    let offset = 0.001 * tint;
+    uv_to_xy(&[uv[0] + offset * normal[0], uv[1] + offset * normal[1]])
+}
+
+/// Returns the CIE xy chromaticity of the Planckian locus at a temperature.
+///
+/// * `t` - Temperature in Kelvin from 1667K to 25000K.
+fn planckian_xy(t: Float) -> [Float; 2] {
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.107038e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.3481102 * x2 + 2.1855583 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.3741859 * x2 + 2.09137 * x - 0.16748867
+    } else {
+        3.081758 * x3 - 5.873387 * x2 + 3.7511299 * x - 0.37001483
+    };
+
+    [x, y]
+}
+
+/// Converts CIE xy chromaticity to XYZ with Y = 1.
+///
+/// * `xy` - The chromaticity.
+pub fn xy_to_xyz(xy: &[Float; 2]) -> [Float; 3] {
+    let [x, y] = *xy;
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+/// Converts CIE xy chromaticity to CIE 1960 uv.
+///
+/// * `xy` - The chromaticity.
+fn xy_to_uv(xy: &[Float; 2]) -> [Float; 2] {
+    let [x, y] = *xy;
+    let d = -2.0 * x + 12.0 * y + 3.0;
+    [4.0 * x / d, 6.0 * y / d]
+}
+
+/// Converts CIE 1960 uv to CIE xy chromaticity.
+///
+/// * `uv` - The chromaticity.
+fn uv_to_xy(uv: &[Float; 2]) -> [Float; 2] {
+    let [u, v] = *uv;
+    let d = 2.0 * u - 8.0 * v + 4.0;
+    [3.0 * u / d, 2.0 * v / d]
+}
+
+/// Returns the product of a 3x3 matrix and a vector.
+///
+/// * `m` - The matrix.
+/// * `v` - The vector.
+fn mul_vector(m: &[[Float; 3]; 3], v: &[Float; 3]) -> [Float; 3] {
+    let mut r = [0.0; 3];
+    for (i, row) in m.iter().enumerate() {
+        r[i] = row[0] * v[0] + row[1] * v[1] + row[2] * v[2];
+    }
+    r
+}
+
+/// Returns the product of two 3x3 matrices.
+///
+/// * `a` - The first matrix.
+/// * `b` - The second matrix.
+fn mul_matrix(a: &[[Float; 3]; 3], b: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    let mut r = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            r[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j];
+        }
+    }
+    r
+}
+
+/// Returns the inverse of a 3x3 matrix using its adjugate.
+///
+/// * `m` - The matrix.
+fn inverse(m: &[[Float; 3]; 3]) -> [[Float; 3]; 3] {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    let mut r = [[0.0; 3]; 3];
+    for (i, row) in r.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = cofactor(j, i) / det;
+        }
+    }
+    r
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &[Float], b: &[Float]) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn reference_white_maps_to_d65() {
+        // Illuminant A.
+        let white = xy_to_xyz(&[0.44757, 0.40745]);
+        for adaptation in [ChromaticAdaptation::Bradford, ChromaticAdaptation::Cat02].iter() {
+            let wb = WhiteBalance::new(&white, *adaptation);
+            assert_near(&wb.apply(&white), &D65_WHITE_XYZ);
+
+            let wb = WhiteBalance::new(&D65_WHITE_XYZ, *adaptation);
+            assert_near(&wb.apply(&[0.2, 0.3, 0.4]), &[0.2, 0.3, 0.4]);
+        }
+    }
+
+    #[test]
+    fn temperature_chromaticity() {
+        // D65 lies slightly above the Planckian locus at about 6504K.
+        assert_near(&temperature_to_xy(6504.0, 0.0), &[0.3135, 0.3236]);
+        assert_near(&temperature_to_xy(2856.0, 0.0), &[0.4476, 0.4074]);
+
+        let [_, v0] = xy_to_uv(&temperature_to_xy(5000.0, 0.0));
+        let [_, v1] = xy_to_uv(&temperature_to_xy(5000.0, 10.0));
+        assert!(v1 > v0);
+    }
+}
@@ -0,0 +1,913 @@
+//! Statistics
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::LocalKey;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Statistics for the scene being rendered.
+    pub static ref STATS: Stats = Stats::new();
+}
+
+/// Key identifying a counter by kind, category and name.
+type CounterKey = (&'static str, String, String);
+
+/// Counts evaluations of a texture or material and the time spent in them.
+pub struct ProfileCounter {
+    /// Kind of object (texture or material).
+    kind: &'static str,
+
+    /// Type name of the object such as `imagemap` or `plastic`.
+    category: String,
+
+    /// Name of the object.
+    name: String,
+
+    /// Memory used by the object in bytes.
+    memory: usize,
+
+    /// Number of evaluations.
+    count: AtomicU64,
+
+    /// Total time spent in evaluations in nanoseconds.
+    nanos: AtomicU64,
+}
+
+impl ProfileCounter {
+    /// Records one evaluation that started at the given instant.
+    ///
+    /// * `start` - Start of the evaluation.
+    pub fn record(&self, start: Instant) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counts tessellations of a type of shape built and reused from the cache.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TessellationCounts {
+    /// Number of tessellations built.
+    pub built: usize,
+
+    /// Number of shapes that reused a cached tessellation.
+    pub reused: usize,
+
+    /// Number of triangles in the tessellations built.
+    pub triangles: usize,
+
+    /// Number of triangles not built because a tessellation was reused.
+    pub triangles_shared: usize,
+
+    /// Memory used by the tessellations built in bytes.
+    pub memory: usize,
+
+    /// Memory saved by reusing tessellations in bytes.
+    pub memory_shared: usize,
+}
+
+/// Counts builds of a type of acceleration structure and the time spent in
+/// them.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BuildCounts {
+    /// Number of acceleration structures built.
+    pub built: usize,
+
+    /// Number of primitives in the acceleration structures.
+    pub primitives: usize,
+
+    /// Total time spent building the acceleration structures.
+    pub time: Duration,
+}
+
+/// Kind of value accumulated by a statistic.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StatKind {
+    /// Number of events.
+    Counter,
+
+    /// Memory in bytes.
+    Memory,
+
+    /// Numerator and denominator of a ratio.
+    Ratio,
+
+    /// Sum, number and range of integer values.
+    Distribution,
+}
+
+/// Values of a statistic accumulated by one thread. Every thread updates its
+/// own values so that counting doesn't contend with other threads; the report
+/// combines the values of all threads.
+pub struct StatValues {
+    /// Title of the statistic as `category/name`.
+    title: &'static str,
+
+    /// Kind of statistic.
+    kind: StatKind,
+
+    /// Counter value, memory in bytes, ratio numerator or sum of values.
+    sum: AtomicU64,
+
+    /// Ratio denominator or number of values.
+    count: AtomicU64,
+
+    /// Smallest value.
+    min: AtomicU64,
+
+    /// Largest value.
+    max: AtomicU64,
+}
+
+impl StatValues {
+    /// Create a new `StatValues`.
+    ///
+    /// * `title` - Title of the statistic as `category/name`.
+    /// * `kind`  - Kind of statistic.
+    fn new(title: &'static str, kind: StatKind) -> Self {
+        Self {
+            title,
+            kind,
+            sum: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    /// Resets the values.
+    fn reset(&self) {
+        self.sum.store(0, Ordering::Relaxed);
+        self.count.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Per thread values of a statistic declared with one of the `stat_*!`
+/// macros.
+pub type LocalStatValues = LocalKey<Arc<StatValues>>;
+
+/// Updates the calling thread's values of a statistic. Updates made while the
+/// thread is shutting down are dropped.
+///
+/// * `values` - The per thread values.
+/// * `f`      - Updates the values.
+fn update_local<F: FnOnce(&StatValues)>(values: &'static LocalStatValues, f: F) {
+    let _ = values.try_with(|v| f(v));
+}
+
+/// Counts events. Declare with `stat_counter!`.
+pub struct StatCounter(&'static LocalStatValues);
+
+impl StatCounter {
+    /// Create a new `StatCounter`. Use `stat_counter!` instead.
+    ///
+    /// * `values` - The per thread values.
+    #[doc(hidden)]
+    pub const fn new(values: &'static LocalStatValues) -> Self {
+        Self(values)
+    }
+
+    /// Counts one event.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Counts a number of events.
+    ///
+    /// * `n` - Number of events.
+    pub fn add(&self, n: u64) {
+        update_local(self.0, |v| {
+            v.sum.fetch_add(n, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Accumulates memory in bytes. Declare with `stat_memory!`.
+pub struct StatMemory(&'static LocalStatValues);
+
+impl StatMemory {
+    /// Create a new `StatMemory`. Use `stat_memory!` instead.
+    ///
+    /// * `values` - The per thread values.
+    #[doc(hidden)]
+    pub const fn new(values: &'static LocalStatValues) -> Self {
+        Self(values)
+    }
+
+    /// Adds memory.
+    ///
+    /// * `bytes` - Memory in bytes.
+    pub fn add(&self, bytes: usize) {
+        update_local(self.0, |v| {
+            v.sum.fetch_add(bytes as u64, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Accumulates the numerator and denominator of a ratio such as nodes visited
+/// per ray. Declare with `stat_ratio!`.
+pub struct StatRatio(&'static LocalStatValues);
+
+impl StatRatio {
+    /// Create a new `StatRatio`. Use `stat_ratio!` instead.
+    ///
+    /// * `values` - The per thread values.
+    #[doc(hidden)]
+    pub const fn new(values: &'static LocalStatValues) -> Self {
+        Self(values)
+    }
+
+    /// Adds to the numerator and denominator.
+    ///
+    /// * `numerator`   - Amount added to the numerator.
+    /// * `denominator` - Amount added to the denominator.
+    pub fn add(&self, numerator: u64, denominator: u64) {
+        update_local(self.0, |v| {
+            v.sum.fetch_add(numerator, Ordering::Relaxed);
+            v.count.fetch_add(denominator, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Accumulates the average and range of integer values such as path lengths.
+/// Declare with `stat_int_distribution!`.
+pub struct StatDistribution(&'static LocalStatValues);
+
+impl StatDistribution {
+    /// Create a new `StatDistribution`. Use `stat_int_distribution!` instead.
+    ///
+    /// * `values` - The per thread values.
+    #[doc(hidden)]
+    pub const fn new(values: &'static LocalStatValues) -> Self {
+        Self(values)
+    }
+
+    /// Records a value.
+    ///
+    /// * `value` - The value.
+    pub fn record(&self, value: u64) {
+        update_local(self.0, |v| {
+            v.sum.fetch_add(value, Ordering::Relaxed);
+            v.count.fetch_add(1, Ordering::Relaxed);
+            v.min.fetch_min(value, Ordering::Relaxed);
+            v.max.fetch_max(value, Ordering::Relaxed);
+        });
+    }
+}
+
+/// Values of a statistic combined over all threads.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StatTotals {
+    /// Kind of statistic.
+    pub kind: StatKind,
+
+    /// Counter value, memory in bytes, ratio numerator or sum of values.
+    pub sum: u64,
+
+    /// Ratio denominator or number of values.
+    pub count: u64,
+
+    /// Smallest value or `u64::MAX` if there are none.
+    pub min: u64,
+
+    /// Largest value.
+    pub max: u64,
+}
+
+impl StatTotals {
+    /// Returns the formatted value of the statistic.
+    fn value(&self) -> String {
+        match self.kind {
+            StatKind::Counter => format!("{}", self.sum),
+            StatKind::Memory => format_bytes(self.sum as usize),
+            StatKind::Ratio => format!(
+                "{} / {} ({:.2}x)",
+                self.sum,
+                self.count,
+                self.sum as f64 / self.count.max(1) as f64
+            ),
+            StatKind::Distribution => format!(
+                "{:.3} avg [range {} - {}]",
+                self.sum as f64 / self.count.max(1) as f64,
+                self.min,
+                self.max
+            ),
+        }
+    }
+}
+
+/// Declares a static `StatCounter` that counts events under a title of the
+/// form `category/name`.
+///
+/// ```ignore
+/// stat_counter!("Integrator/Camera rays traced", N_CAMERA_RAYS);
+/// N_CAMERA_RAYS.inc();
+/// ```
+#[macro_export]
+macro_rules! stat_counter {
+    ($title:expr, $name:ident) => {
+        $crate::__stat!(StatCounter, Counter, $title, $name);
+    };
+}
+
+/// Declares a static `StatMemory` that accumulates memory in bytes under a
+/// title of the form `category/name`.
+///
+/// ```ignore
+/// stat_memory!("Memory/Texture MIP maps", TEXTURE_MEMORY);
+/// TEXTURE_MEMORY.add(bytes);
+/// ```
+#[macro_export]
+macro_rules! stat_memory {
+    ($title:expr, $name:ident) => {
+        $crate::__stat!(StatMemory, Memory, $title, $name);
+    };
+}
+
+/// Declares a static `StatRatio` that accumulates a ratio under a title of the
+/// form `category/name`.
+///
+/// ```ignore
+/// stat_ratio!("BVH/Nodes visited per ray", NODES_VISITED);
+/// NODES_VISITED.add(nodes, 1);
+/// ```
+#[macro_export]
+macro_rules! stat_ratio {
+    ($title:expr, $name:ident) => {
+        $crate::__stat!(StatRatio, Ratio, $title, $name);
+    };
+}
+
+/// Declares a static `StatDistribution` that accumulates the average and
+/// range of integer values under a title of the form `category/name`.
+///
+/// ```ignore
+/// stat_int_distribution!("Integrator/Path length", PATH_LENGTH);
+/// PATH_LENGTH.record(bounces);
+/// ```
+#[macro_export]
+macro_rules! stat_int_distribution {
+    ($title:expr, $name:ident) => {
+        $crate::__stat!(StatDistribution, Distribution, $title, $name);
+    };
+}
+
+/// Declares a static statistic backed by per thread values that are
+/// registered with `STATS` when a thread first updates them.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __stat {
+    ($ty:ident, $kind:ident, $title:expr, $name:ident) => {
+        static $name: $crate::stats::$ty = {
+            ::std::thread_local! {
+                static VALUES: ::std::sync::Arc<$crate::stats::StatValues> =
+                    $crate::stats::STATS.register_stat($title, $crate::stats::StatKind::$kind);
+            }
+            $crate::stats::$ty::new(&VALUES)
+        };
+    };
+}
+
+/// Registry of profile counters reported after rendering.
+pub struct Stats {
+    /// The counters keyed by kind, category and name.
+    counters: Mutex<BTreeMap<CounterKey, Arc<ProfileCounter>>>,
+
+    /// Tessellation counts keyed by shape name.
+    tessellations: Mutex<BTreeMap<String, TessellationCounts>>,
+
+    /// Build counts keyed by acceleration structure name.
+    builds: Mutex<BTreeMap<String, BuildCounts>>,
+
+    /// Memory used by each subsystem in bytes keyed by subsystem name.
+    memory: Mutex<BTreeMap<String, usize>>,
+
+    /// Per thread values of the statistics declared with the `stat_*!`
+    /// macros.
+    stats: Mutex<Vec<Arc<StatValues>>>,
+
+    /// Resident memory of the process after the scene was built in bytes or
+    /// 0 if unknown.
+    build_resident: AtomicUsize,
+}
+
+impl Stats {
+    /// Create a new `Stats`.
+    pub fn new() -> Self {
+        Self {
+            counters: Mutex::new(BTreeMap::new()),
+            tessellations: Mutex::new(BTreeMap::new()),
+            builds: Mutex::new(BTreeMap::new()),
+            memory: Mutex::new(BTreeMap::new()),
+            stats: Mutex::new(vec![]),
+            build_resident: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the counter for an object, creating it if needed. Objects with
+    /// the same kind, category and name share a counter.
+    ///
+    /// * `kind`     - Kind of object (texture or material).
+    /// * `category` - Type name of the object.
+    /// * `name`     - Name of the object.
+    /// * `memory`   - Memory used by the object in bytes.
+    pub fn counter(
+        &self,
+        kind: &'static str,
+        category: &str,
+        name: &str,
+        memory: usize,
+    ) -> Arc<ProfileCounter> {
+        let key = (kind, String::from(category), String::from(name));
+        let mut counters = self.counters.lock().unwrap();
+        Arc::clone(counters.entry(key).or_insert_with(|| {
+            Arc::new(ProfileCounter {
+                kind,
+                category: String::from(category),
+                name: String::from(name),
+                memory,
+                count: AtomicU64::new(0),
+                nanos: AtomicU64::new(0),
+            })
+        }))
+    }
+
+    /// Records a tessellated shape.
+    ///
+    /// * `shape`     - Name of the shape such as `loopsubdiv`.
+    /// * `triangles` - Number of triangles in the tessellation.
+    /// * `memory`    - Memory used by the tessellation in bytes.
+    /// * `reused`    - Whether a cached tessellation was reused.
+    pub fn record_tessellation(&self, shape: &str, triangles: usize, memory: usize, reused: bool) {
+        let mut tessellations = self.tessellations.lock().unwrap();
+        let counts = tessellations.entry(String::from(shape)).or_default();
+        if reused {
+            counts.reused += 1;
+            counts.triangles_shared += triangles;
+            counts.memory_shared += memory;
+        } else {
+            counts.built += 1;
+            counts.triangles += triangles;
+            counts.memory += memory;
+        }
+    }
+
+    /// Returns the tessellation counts for a shape.
+    ///
+    /// * `shape` - Name of the shape.
+    pub fn tessellation_counts(&self, shape: &str) -> TessellationCounts {
+        self.tessellations
+            .lock()
+            .unwrap()
+            .get(shape)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the total memory saved by reusing tessellations in bytes.
+    pub fn tessellation_memory_shared(&self) -> usize {
+        self.tessellations
+            .lock()
+            .unwrap()
+            .values()
+            .map(|counts| counts.memory_shared)
+            .sum()
+    }
+
+    /// Records the build of an acceleration structure.
+    ///
+    /// * `accelerator` - Name of the acceleration structure such as `bvh`.
+    /// * `primitives`  - Number of primitives in the acceleration structure.
+    /// * `time`        - Time spent building it.
+    pub fn record_build(&self, accelerator: &str, primitives: usize, time: Duration) {
+        let mut builds = self.builds.lock().unwrap();
+        let counts = builds.entry(String::from(accelerator)).or_default();
+        counts.built += 1;
+        counts.primitives += primitives;
+        counts.time += time;
+    }
+
+    /// Returns the build counts for an acceleration structure.
+    ///
+    /// * `accelerator` - Name of the acceleration structure.
+    pub fn build_counts(&self, accelerator: &str) -> BuildCounts {
+        self.builds
+            .lock()
+            .unwrap()
+            .get(accelerator)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Records memory used by a subsystem.
+    ///
+    /// * `subsystem` - Name of the subsystem such as `shapes` or `lights`.
+    /// * `bytes`     - Memory in bytes.
+    pub fn add_memory(&self, subsystem: &str, bytes: usize) {
+        *self
+            .memory
+            .lock()
+            .unwrap()
+            .entry(String::from(subsystem))
+            .or_default() += bytes;
+    }
+
+    /// Returns the memory recorded for a subsystem in bytes.
+    ///
+    /// * `subsystem` - Name of the subsystem.
+    pub fn memory(&self, subsystem: &str) -> usize {
+        self.memory
+            .lock()
+            .unwrap()
+            .get(subsystem)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Registers the values of a statistic for the calling thread. Used by the
+    /// `stat_*!` macros.
+    ///
+    /// * `title` - Title of the statistic as `category/name`.
+    /// * `kind`  - Kind of statistic.
+    pub fn register_stat(&self, title: &'static str, kind: StatKind) -> Arc<StatValues> {
+        debug_assert!(title.contains('/'), "statistic {} has no category", title);
+        let values = Arc::new(StatValues::new(title, kind));
+        self.stats.lock().unwrap().push(Arc::clone(&values));
+        values
+    }
+
+    /// Returns the values of the statistics combined over all threads keyed by
+    /// title. Statistics without values are left out.
+    pub fn stat_totals(&self) -> BTreeMap<&'static str, StatTotals> {
+        let mut totals: BTreeMap<&'static str, StatTotals> = BTreeMap::new();
+        for v in self.stats.lock().unwrap().iter() {
+            let t = totals.entry(v.title).or_insert(StatTotals {
+                kind: v.kind,
+                sum: 0,
+                count: 0,
+                min: u64::MAX,
+                max: 0,
+            });
+            t.sum += v.sum.load(Ordering::Relaxed);
+            t.count += v.count.load(Ordering::Relaxed);
+            t.min = t.min.min(v.min.load(Ordering::Relaxed));
+            t.max = t.max.max(v.max.load(Ordering::Relaxed));
+        }
+        totals.retain(|_, t| t.sum > 0 || t.count > 0);
+        totals
+    }
+
+    /// Records the resident memory of the process once the scene is built.
+    pub fn record_build_memory(&self) {
+        let bytes = process_memory("VmRSS").unwrap_or(0);
+        self.build_resident.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Removes all counters before rendering a new scene.
+    pub fn clear(&self) {
+        self.counters.lock().unwrap().clear();
+        self.tessellations.lock().unwrap().clear();
+        self.builds.lock().unwrap().clear();
+        self.memory.lock().unwrap().clear();
+        for v in self.stats.lock().unwrap().iter() {
+            v.reset();
+        }
+        self.build_resident.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns a report of evaluation counts, time and memory for each object
+    /// sorted by time followed by totals for each category. Times include
+    /// nested evaluations so a material's time includes its textures.
+    pub fn report(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+
+        let mut objects: Vec<&Arc<ProfileCounter>> = counters.values().collect();
+        objects.sort_by_key(|c| std::cmp::Reverse(c.nanos.load(Ordering::Relaxed)));
+
+        let mut report = String::from("Statistics\n");
+        let mut categories: BTreeMap<(&str, &str), (u64, u64, usize)> = BTreeMap::new();
+        for kind in ["texture", "material"].iter() {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:<14} {:>12} {:>12} {:>10} {:>10}",
+                kind, "name", "type", "evaluations", "time (ms)", "ns/eval", "memory"
+            );
+            for c in objects.iter().filter(|c| c.kind == *kind) {
+                let count = c.count.load(Ordering::Relaxed);
+                let nanos = c.nanos.load(Ordering::Relaxed);
+                let _ = writeln!(
+                    report,
+                    "  {:<9} {:<24} {:<14} {:>12} {:>12.3} {:>10.1} {:>10}",
+                    "",
+                    c.name,
+                    c.category,
+                    count,
+                    nanos as f64 * 1e-6,
+                    nanos as f64 / count.max(1) as f64,
+                    format_bytes(c.memory)
+                );
+
+                let total = categories.entry((c.kind, &c.category)).or_default();
+                total.0 += count;
+                total.1 += nanos;
+                total.2 += c.memory;
+            }
+        }
+
+        let _ = writeln!(
+            report,
+            "  {:<9} {:<24} {:<14} {:>12} {:>12} {:>10} {:>10}",
+            "totals", "kind", "type", "evaluations", "time (ms)", "ns/eval", "memory"
+        );
+        for ((kind, category), (count, nanos, memory)) in categories.iter() {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:<14} {:>12} {:>12.3} {:>10.1} {:>10}",
+                "",
+                kind,
+                category,
+                count,
+                *nanos as f64 * 1e-6,
+                *nanos as f64 / (*count).max(1) as f64,
+                format_bytes(*memory)
+            );
+        }
+
+        let tessellations = self.tessellations.lock().unwrap();
+        if !tessellations.is_empty() {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10}",
+                "shapes", "name", "built", "reused", "triangles", "shared", "memory", "saved"
+            );
+            for (shape, counts) in tessellations.iter() {
+                let _ = writeln!(
+                    report,
+                    "  {:<9} {:<24} {:>12} {:>12} {:>12} {:>12} {:>10} {:>10}",
+                    "",
+                    shape,
+                    counts.built,
+                    counts.reused,
+                    counts.triangles,
+                    counts.triangles_shared,
+                    format_bytes(counts.memory),
+                    format_bytes(counts.memory_shared)
+                );
+            }
+        }
+
+        let builds = self.builds.lock().unwrap();
+        if !builds.is_empty() {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:>12} {:>12} {:>12}",
+                "builds", "accelerator", "built", "primitives", "time (ms)"
+            );
+            for (accelerator, counts) in builds.iter() {
+                let _ = writeln!(
+                    report,
+                    "  {:<9} {:<24} {:>12} {:>12} {:>12.3}",
+                    "",
+                    accelerator,
+                    counts.built,
+                    counts.primitives,
+                    counts.time.as_secs_f64() * 1e3
+                );
+            }
+        }
+
+        let mut category = "";
+        for (title, totals) in self.stat_totals().iter() {
+            let (c, name) = title.split_at(title.find('/').unwrap_or(0));
+            if c != category {
+                category = c;
+                let _ = writeln!(report, "  {}", category);
+            }
+            let _ = writeln!(
+                report,
+                "    {:<40} {:>12}",
+                name.trim_start_matches('/'),
+                totals.value()
+            );
+        }
+
+        report.push_str(&self.memory_report());
+        report
+    }
+
+    /// Returns a report of the memory used by each subsystem after the scene
+    /// was built, the resident memory at that point and the peak resident
+    /// memory so far.
+    pub fn memory_report(&self) -> String {
+        let memory = self.memory.lock().unwrap();
+        let mut report = String::new();
+        if memory.is_empty() {
+            return report;
+        }
+
+        let _ = writeln!(
+            report,
+            "  {:<9} {:<24} {:>10}",
+            "memory", "subsystem", "size"
+        );
+        for (subsystem, bytes) in memory.iter() {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:>10}",
+                "",
+                subsystem,
+                format_bytes(*bytes)
+            );
+        }
+        let total: usize = memory.values().sum();
+        let _ = writeln!(
+            report,
+            "  {:<9} {:<24} {:>10}",
+            "",
+            "total",
+            format_bytes(total)
+        );
+
+        let build_resident = self.build_resident.load(Ordering::Relaxed);
+        if build_resident > 0 {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:>10}",
+                "",
+                "resident after build",
+                format_bytes(build_resident)
+            );
+        }
+        if let Some(peak) = process_memory("VmHWM") {
+            let _ = writeln!(
+                report,
+                "  {:<9} {:<24} {:>10}",
+                "",
+                "peak resident",
+                format_bytes(peak)
+            );
+        }
+
+        report
+    }
+}
+
+impl Default for Stats {
+    /// Returns a new `Stats`.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns a memory statistic of the process in bytes from `/proc` or `None`
+/// if it isn't available.
+///
+/// * `field` - Name of the field in `/proc/self/status` such as `VmRSS`.
+fn process_memory(field: &str) -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<usize>()
+                .ok()
+        })
+        .map(|kb| kb * 1024)
+}
+
+/// Returns a human readable memory size.
+///
+/// * `bytes` - Size in bytes.
+pub fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else if bytes >= 1 << 10 {
+        format!("{:.1} KiB", bytes as f64 / (1 << 10) as f64)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_shared_by_key() {
+        let stats = Stats::new();
+        let a = stats.counter("texture", "imagemap", "wood", 2048);
+        let b = stats.counter("texture", "imagemap", "wood", 2048);
+        a.record(Instant::now());
+        b.record(Instant::now());
+        assert_eq!(a.count.load(Ordering::Relaxed), 2);
+
+        let report = stats.report();
+        assert!(report.contains("wood"));
+        assert!(report.contains("2.0 KiB"));
+    }
+
+    #[test]
+    fn tessellations_count_shared_triangles() {
+        let stats = Stats::new();
+        stats.record_tessellation("loopsubdiv", 100, 4096, false);
+        stats.record_tessellation("loopsubdiv", 100, 4096, true);
+        stats.record_tessellation("loopsubdiv", 100, 4096, true);
+
+        let counts = stats.tessellation_counts("loopsubdiv");
+        assert_eq!(counts.built, 1);
+        assert_eq!(counts.reused, 2);
+        assert_eq!(counts.triangles, 100);
+        assert_eq!(counts.triangles_shared, 200);
+        assert_eq!(counts.memory, 4096);
+        assert_eq!(counts.memory_shared, 8192);
+        assert_eq!(stats.tessellation_memory_shared(), 8192);
+        assert!(stats.report().contains("8.0 KiB"));
+    }
+
+    #[test]
+    fn builds_accumulate_time() {
+        let stats = Stats::new();
+        stats.record_build("bvh", 100, Duration::from_millis(2));
+        stats.record_build("bvh", 50, Duration::from_millis(3));
+
+        let counts = stats.build_counts("bvh");
+        assert_eq!(counts.built, 2);
+        assert_eq!(counts.primitives, 150);
+        assert_eq!(counts.time, Duration::from_millis(5));
+        assert_eq!(stats.build_counts("kdtree"), BuildCounts::default());
+        assert!(stats.report().contains("5.000"));
+
+        stats.clear();
+        assert_eq!(stats.build_counts("bvh").built, 0);
+    }
+
+    #[test]
+    fn memory_is_accumulated_by_subsystem() {
+        let stats = Stats::new();
+        stats.add_memory("shapes", 1024);
+        stats.add_memory("shapes", 1024);
+        stats.add_memory("lights", 512);
+        assert_eq!(stats.memory("shapes"), 2048);
+        assert_eq!(stats.memory("textures"), 0);
+
+        let report = stats.memory_report();
+        assert!(report.contains("shapes"));
+        assert!(report.contains("2.0 KiB"));
+        assert!(report.contains("2.5 KiB"));
+
+        stats.clear();
+        assert!(stats.memory_report().is_empty());
+    }
+
+    #[test]
+    fn stats_are_combined_over_threads() {
+        crate::stat_counter!("Test/Events", EVENTS);
+        crate::stat_int_distribution!("Test/Lengths", LENGTHS);
+
+        let threads: Vec<_> = (1..=4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    EVENTS.add(10);
+                    LENGTHS.record(i);
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        let totals = STATS.stat_totals();
+        assert_eq!(totals["Test/Events"].sum, 40);
+        let lengths = totals["Test/Lengths"];
+        assert_eq!((lengths.sum, lengths.count), (10, 4));
+        assert_eq!((lengths.min, lengths.max), (1, 4));
+        assert_eq!(lengths.value(), "2.500 avg [range 1 - 4]");
+    }
+
+    #[test]
+    fn stats_are_reported_by_category() {
+        crate::stat_ratio!("Test Report/Nodes per ray", NODES);
+        crate::stat_memory!("Test Report/Tables", TABLES);
+        NODES.add(30, 4);
+        TABLES.add(3072);
+
+        let report = STATS.report();
+        assert!(report.contains("  Test Report\n"));
+        assert!(report.contains("Nodes per ray"));
+        assert!(report.contains("30 / 4 (7.50x)"));
+        assert!(report.contains("3.0 KiB"));
+    }
+}
@@ -0,0 +1,103 @@
+//! Texture Baking
+
+#![allow(dead_code)]
+use super::*;
+use crate::image_io::*;
+use crate::pbrt::*;
+use crate::spectrum::*;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Evaluates a floating point texture over the unit (u, v) domain and writes
+/// the result to an image as greyscale.
+///
+/// * `texture`    - The texture.
+/// * `resolution` - The image resolution.
+/// * `path`       - Output file path.
+pub fn bake_float_texture(
+    texture: &ArcTexture<Float>,
+    resolution: &Point2<usize>,
+    path: &str,
+) -> Result<(), String> {
+    bake(resolution, path, |si| {
+        let v = texture.evaluate(si);
+        [v, v, v]
+    })
+}
+
+/// Evaluates a spectrum texture over the unit (u, v) domain and writes the
+/// result to an image.
+///
+/// * `texture`    - The texture.
+/// * `resolution` - The image resolution.
+/// * `path`       - Output file path.
+pub fn bake_spectrum_texture(
+    texture: &ArcTexture<Spectrum>,
+    resolution: &Point2<usize>,
+    path: &str,
+) -> Result<(), String> {
+    bake(resolution, path, |si| texture.evaluate(si).to_rgb())
+}
+
+/// Evaluates a function at the centre of each pixel of a flat unit square
+/// parameterized by (u, v) and writes the result to an image. The surface
+/// lies in the z = 0 plane with p = (u, v, 0) so 2D and 3D texture mappings
+/// both see the same domain. Screen space differentials span one pixel so
+/// filtered textures are antialiased at the chosen resolution.
+///
+/// * `resolution` - The image resolution.
+/// * `path`       - Output file path.
+/// * `f`          - Function returning RGB values at a surface interaction.
+fn bake<F>(resolution: &Point2<usize>, path: &str, f: F) -> Result<(), String>
+where
+    F: Fn(&SurfaceInteraction) -> [Float; 3],
+{
+    let (width, height) = (resolution.x, resolution.y);
+    if width == 0 || height == 0 {
+        return Err(format!(
+            "Invalid bake resolution {}x{} for {}.",
+            width, height, path
+        ));
+    }
+
+    let identity = Arc::new(Transform::default());
+    let shape_data = Arc::new(ShapeData::new(
+        Arc::clone(&identity),
+        Some(identity),
+        false,
+    ));
+
+    let du = 1.0 / width as Float;
+    let dv = 1.0 / height as Float;
+
+    let mut rgb = vec![0.0; 3 * width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let uv = Point2f::new((x as Float + 0.5) * du, (y as Float + 0.5) * dv);
+
+            let mut si = SurfaceInteraction::new(
+                Point3f::new(uv.x, uv.y, 0.0),
+                Vector3f::default(),
+                uv,
+                Vector3f::new(0.0, 0.0, 1.0),
+                Vector3f::new(1.0, 0.0, 0.0),
+                Vector3f::new(0.0, 1.0, 0.0),
+                Normal3f::default(),
+                Normal3f::default(),
+                0.0,
+                Arc::clone(&shape_data),
+                None,
+            );
+            si.dudx = du;
+            si.dvdy = dv;
+            si.dpdx = Vector3f::new(du, 0.0, 0.0);
+            si.dpdy = Vector3f::new(0.0, dv, 0.0);
+
+            let offset = 3 * (y * width + x);
+            rgb[offset..offset + 3].copy_from_slice(&f(&si));
+        }
+    }
+
+    let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(width as Int, height as Int));
+    write_image(path, &rgb, &bounds, &BTreeMap::new())
+}
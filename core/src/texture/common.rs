@@ -42,13 +42,16 @@ const NOISE_PERM: [usize; 2 * NOISE_PERM_SIZE] = [
 pub fn noise(p: Point3f) -> Float {
     let Point3f { x, y, z } = p;
 
-    // Compute noise cell coordinates and offsets.
-    let ix = x.floor() as usize & (NOISE_PERM_SIZE - 1);
-    let iy = y.floor() as usize & (NOISE_PERM_SIZE - 1);
-    let iz = z.floor() as usize & (NOISE_PERM_SIZE - 1);
-    let dx = x - ix as Float;
-    let dy = y - iy as Float;
-    let dz = z - iz as Float;
+    // Compute noise cell coordinates and offsets. The offsets must be taken
+    // relative to the unwrapped cell so they stay in [0, 1) for negative and
+    // large coordinates.
+    let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+    let ix = (fx as Int & (NOISE_PERM_SIZE as Int - 1)) as usize;
+    let iy = (fy as Int & (NOISE_PERM_SIZE as Int - 1)) as usize;
+    let iz = (fz as Int & (NOISE_PERM_SIZE as Int - 1)) as usize;
+    let dx = x - fx;
+    let dy = y - fy;
+    let dz = z - fz;
 
     // Compute gradient weights
     let w000 = grad(ix, iy, iz, dx, dy, dz);
@@ -110,6 +113,25 @@ fn noise_weight(t: Float) -> Float {
     6.0 * t4 * t - 15.0 * t4 + 10.0 * t3
 }
 
+/// Returns the number of noise octaves, possibly fractional, that can be
+/// summed without aliasing for a shading point whose texture space footprint
+/// is given by the partial derivatives `∂p/∂x` and `∂p/∂y`. Each octave doubles
+/// the frequency, so octaves whose wavelength falls below the filter width
+/// are dropped. When no differentials are available the full number of
+/// octaves is used.
+///
+/// * `dpdx`        - Partial derivative at point p with respect to x.
+/// * `dpdy`        - Partial derivative at point p with respect to y.
+/// * `max_octaves` - Maximum number of octaves of noise to use for the sum.
+pub fn noise_octaves(dpdx: &Vector3f, dpdy: &Vector3f, max_octaves: usize) -> Float {
+    let len2 = max(dpdx.length_squared(), dpdy.length_squared());
+    if len2 == 0.0 || len2.is_nan() {
+        max_octaves as Float
+    } else {
+        clamp(-1.0 - 0.5 * len2.log2(), 0.0, max_octaves as Float)
+    }
+}
+
 /// Uses Perlin Noise to generate values based on Fractional Brownian motion.
 ///
 /// * `p`           - The point.
@@ -125,8 +147,7 @@ pub fn fbm(
     max_octaves: usize,
 ) -> Float {
     // Compute number of octaves for antialiased FBm.
-    let len2 = max(dpdx.length_squared(), dpdy.length_squared());
-    let n = clamp(-1.0 - 0.5 * len2.log2(), 0.0, max_octaves as Float);
+    let n = noise_octaves(dpdx, dpdy, max_octaves);
     let n_int = n.floor() as usize;
 
     // Compute sum of octaves of noise for FBm
@@ -157,9 +178,8 @@ pub fn turbulence(
     omega: Float,
     max_octaves: usize,
 ) -> Float {
-    // Compute number of octaves for antialiased FBm
-    let len2 = max(dpdx.length_squared(), dpdy.length_squared());
-    let n = clamp(-1.0 - 0.5 * len2.log2(), 0.0, max_octaves as Float);
+    // Compute number of octaves for antialiased turbulence.
+    let n = noise_octaves(dpdx, dpdy, max_octaves);
     let n_int = n.floor() as usize;
 
     // Compute sum of octaves of noise for turbulence
@@ -204,3 +224,38 @@ pub fn lanczos(x: Float, tau: Float) -> Float {
         s * lanczos
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_zero_at_lattice_points() {
+        for &c in [-300.0, -1.0, 0.0, 3.0, 257.0, 1000.0].iter() {
+            let p = Point3f::new(c, c + 1.0, c - 2.0);
+            assert!(abs(noise(p)) < 1e-4, "noise({}) = {}", c, noise(p));
+        }
+    }
+
+    #[test]
+    fn noise_is_periodic_over_permutation_table() {
+        let p = Point3f::new(-0.3, 1.7, 2.25);
+        let q = p + Vector3f::new(NOISE_PERM_SIZE as Float, 0.0, 0.0);
+        assert!(abs(noise(p) - noise(q)) < 1e-3);
+    }
+
+    #[test]
+    fn noise_octaves_rolls_off_with_filter_width() {
+        let zero = Vector3f::default();
+        assert_eq!(noise_octaves(&zero, &zero, 8), 8.0);
+
+        let fine = Vector3f::new(1e-3, 0.0, 0.0);
+        let coarse = Vector3f::new(0.5, 0.0, 0.0);
+        assert!(noise_octaves(&coarse, &zero, 8) < noise_octaves(&fine, &zero, 8));
+        assert_eq!(noise_octaves(&Vector3f::new(4.0, 0.0, 0.0), &zero, 8), 0.0);
+    }
+}
@@ -1,10 +1,7 @@
 //! Constant Texture
 
-use core::geometry::*;
-use core::paramset::*;
-use core::pbrt::*;
-use core::spectrum::*;
-use core::texture::*;
+use super::*;
+use crate::paramset::*;
 
 /// Implements a texture that returns the same value everywhere.
 #[derive(Clone)]
@@ -12,6 +9,7 @@ pub struct ConstantTexture<T> {
     /// The texture value.
     value: T,
 }
+
 impl<T> ConstantTexture<T> {
     /// Create a new `ConstantTexture<T>`.
     ///
@@ -3,18 +3,26 @@
 use super::*;
 
 /// Implements 3D identity mapping by simply transforming the hit point and
-/// partials from world space to texture space.
+/// partials from world space to texture space. In object space, they are
+/// first transformed back to the object space of the shape.
 pub struct IdentityMapping3D {
     /// Transformation from world space to texture space.
     world_to_texture: Transform,
+
+    /// Space in which the texture is evaluated.
+    space: TextureSpace,
 }
 
 impl IdentityMapping3D {
     /// Create a new `IdentityMapping3D`.
     ///
     /// * `world_to_texture` - Transformation from world space to texture space.
-    pub fn new(world_to_texture: Transform) -> Self {
-        Self { world_to_texture }
+    /// * `space`            - Space in which the texture is evaluated.
+    pub fn new(world_to_texture: Transform, space: TextureSpace) -> Self {
+        Self {
+            world_to_texture,
+            space,
+        }
     }
 }
 
@@ -23,9 +31,21 @@ impl TextureMapping3D for IdentityMapping3D {
     ///
     /// * `si` - The surface interaction.
     fn map(&self, si: &SurfaceInteraction) -> TextureMap3DResult {
-        let dpdx = self.world_to_texture.transform_vector(&si.dpdx);
-        let dpdy = self.world_to_texture.transform_vector(&si.dpdy);
-        let p = self.world_to_texture.transform_point(&si.hit.p);
-        TextureMap3DResult::new(p, dpdx, dpdy)
+        let (p, dpdx, dpdy) = match self.space {
+            TextureSpace::World => (si.hit.p, si.dpdx, si.dpdy),
+            TextureSpace::Object => {
+                let world_to_object = si.world_to_object();
+                (
+                    world_to_object.transform_point(&si.hit.p),
+                    world_to_object.transform_vector(&si.dpdx),
+                    world_to_object.transform_vector(&si.dpdy),
+                )
+            }
+        };
+        TextureMap3DResult::new(
+            self.world_to_texture.transform_point(&p),
+            self.world_to_texture.transform_vector(&dpdx),
+            self.world_to_texture.transform_vector(&dpdy),
+        )
     }
 }
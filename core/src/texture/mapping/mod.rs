@@ -61,6 +61,34 @@ impl TextureMap3DResult {
         Self { p, dpdx, dpdy }
     }
 }
+/// Space in which 3D textures are evaluated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureSpace {
+    /// World space.
+    World,
+
+    /// Object space of the shape, undoing any object instance
+    /// transformation so instances are textured identically.
+    Object,
+}
+
+impl From<&str> for TextureSpace {
+    /// Returns the texture space for a name; `world` or `object`. Unknown
+    /// names use world space.
+    ///
+    /// * `name` - The name.
+    fn from(name: &str) -> Self {
+        match name {
+            "world" => Self::World,
+            "object" => Self::Object,
+            _ => {
+                warn!("Texture space '{}' unknown. Using 'world'.", name);
+                Self::World
+            }
+        }
+    }
+}
+
 /// Interface for 3D texture mapping.
 pub trait TextureMapping3D {
     /// Returns the (s, t) texture coordinates and partial derivitives.
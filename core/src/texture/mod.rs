@@ -3,6 +3,7 @@
 #![allow(dead_code)]
 use crate::geometry::*;
 use crate::pbrt::Float;
+use crate::scene::Scene;
 use crate::spectrum::Spectrum;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -13,6 +14,19 @@ pub trait Texture<T: Copy> {
     ///
     /// * `si` - Surface interaction.
     fn evaluate(&self, si: &SurfaceInteraction) -> T;
+
+    /// Returns the memory used by the texture in bytes. Defaults to 0 for
+    /// procedural textures.
+    fn memory(&self) -> usize {
+        0
+    }
+
+    /// Prepares the texture for rendering a scene once it is created.
+    /// Defaults to doing nothing for textures that do not depend on the
+    /// scene.
+    ///
+    /// * `_scene` - The scene.
+    fn preprocess(&self, _scene: &Scene) {}
 }
 
 /// Atomic reference counted `Texture`.
@@ -24,9 +38,17 @@ pub type FloatTextureMap = HashMap<String, ArcTexture<Float>>;
 /// Map of spectrum textures.
 pub type SpectrumTextureMap = HashMap<String, ArcTexture<Spectrum>>;
 
+mod bake;
 mod common;
+mod constant;
 mod mapping;
+mod profiled;
+mod projector;
 
 // Re-export
+pub use bake::*;
 pub use common::*;
+pub use constant::*;
 pub use mapping::*;
+pub use profiled::*;
+pub use projector::*;
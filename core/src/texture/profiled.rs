@@ -0,0 +1,57 @@
+//! Profiled Texture
+
+use super::*;
+use crate::app::OPTIONS;
+use crate::stats::*;
+use std::time::Instant;
+
+/// Wraps a texture and records its evaluations in the statistics.
+pub struct ProfiledTexture<T: Copy> {
+    /// The texture.
+    texture: ArcTexture<T>,
+
+    /// Counter for evaluations of the texture.
+    counter: Arc<ProfileCounter>,
+}
+
+impl<T: Copy> Texture<T> for ProfiledTexture<T> {
+    /// Evaluate the texture at surface interaction.
+    ///
+    /// * `si` - Surface interaction.
+    fn evaluate(&self, si: &SurfaceInteraction) -> T {
+        let start = Instant::now();
+        let value = self.texture.evaluate(si);
+        self.counter.record(start);
+        value
+    }
+
+    /// Returns the memory used by the texture in bytes.
+    fn memory(&self) -> usize {
+        self.texture.memory()
+    }
+
+    /// Prepares the texture for rendering a scene.
+    ///
+    /// * `scene` - The scene.
+    fn preprocess(&self, scene: &Scene) {
+        self.texture.preprocess(scene)
+    }
+}
+
+/// Returns the texture wrapped so its evaluations are recorded in the
+/// statistics if they are enabled; otherwise the texture itself.
+///
+/// * `texture`  - The texture.
+/// * `category` - Texture type such as `imagemap`.
+/// * `name`     - Texture name.
+pub fn profile_texture<T>(texture: ArcTexture<T>, category: &str, name: &str) -> ArcTexture<T>
+where
+    T: Copy + 'static,
+{
+    if OPTIONS.stats {
+        let counter = STATS.counter("texture", category, name, texture.memory());
+        Arc::new(ProfiledTexture { texture, counter })
+    } else {
+        texture
+    }
+}
@@ -0,0 +1,216 @@
+//! Texture Projector
+
+use super::*;
+use crate::pbrt::*;
+use crate::scene::Scene;
+use rayon::prelude::*;
+use std::sync::RwLock;
+
+/// Projects points onto an image plane from a pinhole like a slide
+/// projector. The projector looks down the positive z-axis of its space
+/// like a perspective camera so an image can be projected back from the
+/// camera it was shot with. Points hidden from the projector can be
+/// excluded with a depth map rendered from the projector.
+pub struct Projector {
+    /// Transformation from world space to projector space.
+    world_to_projector: Transform,
+
+    /// Transformation from projector space to world space.
+    projector_to_world: Transform,
+
+    /// Half extents of the image on the plane z = 1 in projector space.
+    screen: Vector2f,
+
+    /// Whether points hidden from the projector are excluded.
+    occlusion: bool,
+
+    /// Relative tolerance for comparing depths with the depth map.
+    depth_bias: Float,
+
+    /// Resolution of the depth map along the longer image axis.
+    depth_resolution: usize,
+
+    /// Depth map with the distance along the z-axis to the nearest surface
+    /// for each texel. It is empty until the scene is known.
+    depth_map: RwLock<DepthMap>,
+}
+
+/// Distances to the nearest surfaces seen through a grid over the image.
+#[derive(Default)]
+struct DepthMap {
+    /// Resolution in texels.
+    resolution: Point2<usize>,
+
+    /// Distances along the z-axis in projector space, bottom row first.
+    depths: Vec<Float>,
+}
+
+impl Projector {
+    /// Create a new `Projector`.
+    ///
+    /// * `projector_to_world` - Transformation from projector space to world
+    ///                          space.
+    /// * `fov`                - Field of view in degrees of the shorter image
+    ///                          axis.
+    /// * `aspect`             - Aspect ratio of the image, width over height.
+    /// * `occlusion`          - Whether points hidden from the projector are
+    ///                          excluded.
+    /// * `depth_bias`         - Relative tolerance for comparing depths.
+    /// * `depth_resolution`   - Resolution of the depth map along the longer
+    ///                          image axis.
+    pub fn new(
+        projector_to_world: &Transform,
+        fov: Float,
+        aspect: Float,
+        occlusion: bool,
+        depth_bias: Float,
+        depth_resolution: usize,
+    ) -> Self {
+        let tan_half_fov = (0.5 * fov).to_radians().tan();
+        let screen = if aspect >= 1.0 {
+            Vector2f::new(tan_half_fov * aspect, tan_half_fov)
+        } else {
+            Vector2f::new(tan_half_fov, tan_half_fov / aspect)
+        };
+
+        Self {
+            world_to_projector: projector_to_world.inverse(),
+            projector_to_world: *projector_to_world,
+            screen,
+            occlusion,
+            depth_bias,
+            depth_resolution: max(1, depth_resolution),
+            depth_map: RwLock::new(DepthMap::default()),
+        }
+    }
+
+    /// Returns the image coordinates of a point in world space and its depth
+    /// or `None` if it is outside the image.
+    ///
+    /// * `p` - The point.
+    fn project_point(&self, p: &Point3f) -> Option<(Point2f, Float)> {
+        let pp = self.world_to_projector.transform_point(p);
+        if pp.z <= 0.0 {
+            return None;
+        }
+
+        let s = 0.5 * (1.0 + pp.x / (pp.z * self.screen.x));
+        let t = 0.5 * (1.0 + pp.y / (pp.z * self.screen.y));
+        if (0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t) {
+            Some((Point2f::new(s, t), pp.z))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the (s, t) image coordinates and texture differentials of a
+    /// surface interaction or `None` if the point is outside the image or is
+    /// hidden from the projector.
+    ///
+    /// * `si` - The surface interaction.
+    pub fn map(&self, si: &SurfaceInteraction) -> Option<TextureMap2DResult> {
+        let (st, depth) = self.project_point(&si.hit.p)?;
+        if self.occlusion && self.is_occluded(&st, depth) {
+            return None;
+        }
+
+        // Approximate the differentials by projecting the offset points.
+        let differential = |dp: &Vector3f| {
+            self.project_point(&(si.hit.p + *dp))
+                .map_or(Vector2f::default(), |(st_d, _)| st_d - st)
+        };
+        Some(TextureMap2DResult::new(
+            st,
+            differential(&si.dpdx),
+            differential(&si.dpdy),
+        ))
+    }
+
+    /// Returns whether a point is farther from the projector than the nearest
+    /// surface in the depth map.
+    ///
+    /// * `st`    - Image coordinates of the point.
+    /// * `depth` - Distance of the point along the z-axis in projector space.
+    fn is_occluded(&self, st: &Point2f, depth: Float) -> bool {
+        let depth_map = self.depth_map.read().unwrap();
+        let Point2 { x: w, y: h } = depth_map.resolution;
+        if depth_map.depths.is_empty() {
+            return false;
+        }
+
+        let x = min((st.x * w as Float) as usize, w - 1);
+        let y = min((st.y * h as Float) as usize, h - 1);
+        depth > depth_map.depths[y * w + x] * (1.0 + self.depth_bias)
+    }
+
+    /// Renders the depth map of a scene if occlusion is enabled.
+    ///
+    /// * `scene` - The scene.
+    pub fn preprocess(&self, scene: &Scene) {
+        if !self.occlusion {
+            return;
+        }
+
+        let aspect = self.screen.x / self.screen.y;
+        let n = self.depth_resolution;
+        let resolution = if aspect >= 1.0 {
+            Point2::new(n, max(1, (n as Float / aspect).round() as usize))
+        } else {
+            Point2::new(max(1, (n as Float * aspect).round() as usize), n)
+        };
+
+        let origin = self.projector_to_world.transform_point(&Point3f::default());
+        let depths: Vec<Float> = (0..resolution.x * resolution.y)
+            .into_par_iter()
+            .map(|i| {
+                let s = ((i % resolution.x) as Float + 0.5) / resolution.x as Float;
+                let t = ((i / resolution.x) as Float + 0.5) / resolution.y as Float;
+
+                // The direction has unit z so the ray parameter is the depth.
+                let d = Vector3f::new(
+                    (2.0 * s - 1.0) * self.screen.x,
+                    (2.0 * t - 1.0) * self.screen.y,
+                    1.0,
+                );
+                let d = self.projector_to_world.transform_vector(&d);
+                let mut ray = Ray::new(origin, d, INFINITY, 0.0, None);
+                match scene.intersect(&mut ray) {
+                    Some(_) => ray.t_max,
+                    None => INFINITY,
+                }
+            })
+            .collect();
+
+        *self.depth_map.write().unwrap() = DepthMap { resolution, depths };
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_point() {
+        let projector = Projector::new(&Transform::default(), 90.0, 2.0, false, 0.01, 16);
+
+        let (st, depth) = projector
+            .project_point(&Point3f::new(0.0, 0.0, 3.0))
+            .unwrap();
+        assert_eq!(st, Point2f::new(0.5, 0.5));
+        assert_eq!(depth, 3.0);
+
+        // The shorter axis spans 90 degrees.
+        let (st, _) = projector
+            .project_point(&Point3f::new(1.0, 1.0, 1.0))
+            .unwrap();
+        assert!((st.x - 0.75).abs() < 1e-5);
+        assert!((st.y - 1.0).abs() < 1e-5);
+
+        assert!(projector.project_point(&Point3f::new(0.0, 0.0, -1.0)).is_none());
+        assert!(projector.project_point(&Point3f::new(3.0, 0.0, 1.0)).is_none());
+    }
+}
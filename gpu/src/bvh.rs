@@ -0,0 +1,148 @@
+//! GPU BVH
+
+use crate::scene::*;
+use bytemuck::{Pod, Zeroable};
+
+/// Maximum number of triangles in a leaf node.
+const MAX_TRIANGLES_IN_NODE: usize = 4;
+
+/// A node of the bounding volume hierarchy laid out for the path tracer's
+/// storage buffer. Nodes are stored in depth first order so that the first
+/// child of an interior node follows it.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuBVHNode {
+    /// Minimum corner of the bounds.
+    pub p_min: [f32; 3],
+
+    /// Index of the first triangle for leaf nodes or of the second child for
+    /// interior nodes.
+    pub offset: u32,
+
+    /// Maximum corner of the bounds.
+    pub p_max: [f32; 3],
+
+    /// Number of triangles for leaf nodes or 0 for interior nodes.
+    pub count: u32,
+}
+
+// The fields are plain numbers without padding between them.
+unsafe impl Zeroable for GpuBVHNode {}
+unsafe impl Pod for GpuBVHNode {}
+
+/// Builds a bounding volume hierarchy over triangles by splitting them at
+/// the median centroid along the axis of largest extent. The triangles are
+/// reordered so that leaf nodes reference contiguous ranges.
+///
+/// * `triangles` - The triangles.
+pub fn build_bvh(triangles: &mut [GpuTriangle]) -> Vec<GpuBVHNode> {
+    let mut nodes = Vec::with_capacity(2 * triangles.len() / MAX_TRIANGLES_IN_NODE + 1);
+    if !triangles.is_empty() {
+        build_node(triangles, 0, &mut nodes);
+    }
+    nodes
+}
+
+/// Recursively builds the nodes for a range of triangles.
+///
+/// * `triangles` - The triangles in the range.
+/// * `offset`    - Index of the first triangle in the range.
+/// * `nodes`     - The nodes built so far.
+fn build_node(triangles: &mut [GpuTriangle], offset: usize, nodes: &mut Vec<GpuBVHNode>) {
+    let mut node = GpuBVHNode {
+        p_min: [f32::INFINITY; 3],
+        p_max: [f32::NEG_INFINITY; 3],
+        ..GpuBVHNode::default()
+    };
+    let mut c_min = [f32::INFINITY; 3];
+    let mut c_max = [f32::NEG_INFINITY; 3];
+    for triangle in triangles.iter() {
+        for p in [triangle.p0, triangle.p1, triangle.p2].iter() {
+            union_point(&mut node.p_min, &mut node.p_max, p);
+        }
+        union_point(&mut c_min, &mut c_max, &triangle.centroid());
+    }
+
+    let axis = (0..3)
+        .max_by(|a, b| {
+            (c_max[*a] - c_min[*a])
+                .partial_cmp(&(c_max[*b] - c_min[*b]))
+                .unwrap()
+        })
+        .unwrap();
+    if triangles.len() <= MAX_TRIANGLES_IN_NODE || c_max[axis] == c_min[axis] {
+        node.offset = offset as u32;
+        node.count = triangles.len() as u32;
+        nodes.push(node);
+        return;
+    }
+
+    triangles.sort_by(|a, b| a.centroid()[axis].partial_cmp(&b.centroid()[axis]).unwrap());
+    let mid = triangles.len() / 2;
+    let index = nodes.len();
+    nodes.push(node);
+
+    let (first, second) = triangles.split_at_mut(mid);
+    build_node(first, offset, nodes);
+    nodes[index].offset = nodes.len() as u32;
+    build_node(second, offset + mid, nodes);
+}
+
+/// Grows the bounds given by their corners to include a point.
+///
+/// * `p_min` - Minimum corner of the bounds.
+/// * `p_max` - Maximum corner of the bounds.
+/// * `p`     - The point.
+fn union_point(p_min: &mut [f32; 3], p_max: &mut [f32; 3], p: &[f32; 3]) {
+    for ((p_min, p_max), p) in p_min.iter_mut().zip(p_max.iter_mut()).zip(p.iter()) {
+        *p_min = p_min.min(*p);
+        *p_max = p_max.max(*p);
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::geometry::*;
+
+    #[test]
+    fn leaves_cover_all_triangles_within_bounds() {
+        let mut triangles: Vec<GpuTriangle> = (0..37)
+            .map(|i| {
+                let x = i as f32;
+                let p = [
+                    Point3f::new(x, 0.0, 0.0),
+                    Point3f::new(x + 1.0, 0.0, 0.0),
+                    Point3f::new(x, 1.0, (i % 5) as f32),
+                ];
+                GpuTriangle::new(&p, &Normal3f::new(0.0, 0.0, 1.0), i)
+            })
+            .collect();
+        let nodes = build_bvh(&mut triangles);
+
+        let mut covered = vec![false; triangles.len()];
+        for node in nodes.iter().filter(|node| node.count > 0) {
+            assert!(node.count as usize <= MAX_TRIANGLES_IN_NODE);
+            let start = node.offset as usize;
+            for i in start..start + node.count as usize {
+                assert!(!covered[i]);
+                covered[i] = true;
+                for p in [triangles[i].p0, triangles[i].p1, triangles[i].p2].iter() {
+                    assert!((0..3).all(|a| node.p_min[a] <= p[a] && p[a] <= node.p_max[a]));
+                }
+            }
+        }
+        assert!(covered.iter().all(|c| *c));
+
+        // The root bounds all triangles and the second child of an interior
+        // node follows the subtree of the first child.
+        assert_eq!(nodes[0].p_min, [0.0, 0.0, 0.0]);
+        assert_eq!(nodes[0].p_max, [37.0, 1.0, 4.0]);
+        assert!(nodes[0].count == 0 && nodes[0].offset as usize > 1);
+        assert!(build_bvh(&mut []).is_empty());
+    }
+}
@@ -0,0 +1,24 @@
+//! GPU
+//!
+//! A path tracer running as a `wgpu` compute shader, similar to pbrt-v4's
+//! wavefront renderer. It renders a subset of scenes:
+//!
+//! * Triangle meshes.
+//! * `matte` and `plastic` materials with constant reflectances. Plastic has
+//!   a smooth coating instead of a rough one.
+//! * `diffuse` area lights and constant `infinite` lights.
+//!
+//! Adding anything else to a `GpuScene` returns an error so the caller can
+//! render the scene another way.
+//!
+//! Paths are traced by sampling the BSDFs only, so light has to be found by
+//! hitting emitting triangles or escaping to the environment.
+
+mod bvh;
+mod renderer;
+mod scene;
+
+// Re-export.
+pub use bvh::*;
+pub use renderer::*;
+pub use scene::*;
@@ -0,0 +1,244 @@
+// Path tracer that adds one sample per pixel to the accumulated radiance of
+// each dispatch. Paths sample the BSDF at each vertex and add the radiance
+// of emitting triangles they hit and of the environment they escape to.
+
+struct Params {
+    raster_to_world: mat4x4<f32>,
+    environment: vec4<f32>,
+    pixel_min: vec2<i32>,
+    size: vec2<u32>,
+    sample_index: u32,
+    max_depth: u32,
+    n_nodes: u32,
+    pad: u32,
+}
+
+struct Node {
+    p_min: vec3<f32>,
+    offset: u32,
+    p_max: vec3<f32>,
+    count: u32,
+}
+
+struct Triangle {
+    p0: vec3<f32>,
+    material: u32,
+    p1: vec3<f32>,
+    pad1: u32,
+    p2: vec3<f32>,
+    pad2: u32,
+    n: vec3<f32>,
+    pad3: u32,
+}
+
+struct Material {
+    kind: u32,
+    two_sided: u32,
+    pad: vec2<u32>,
+    kd: vec4<f32>,
+    ks: vec4<f32>,
+    emission: vec4<f32>,
+}
+
+struct Hit {
+    t: f32,
+    index: i32,
+}
+
+const MATTE: u32 = 0u;
+const PLASTIC: u32 = 1u;
+const PLASTIC_ETA: f32 = 1.5;
+const PI: f32 = 3.14159265358979323846;
+const STACK_SIZE: u32 = 64u;
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> nodes: array<Node>;
+@group(0) @binding(2) var<storage, read> triangles: array<Triangle>;
+@group(0) @binding(3) var<storage, read> materials: array<Material>;
+@group(0) @binding(4) var<storage, read_write> radiance: array<vec4<f32>>;
+
+var<private> rng_state: u32;
+
+// PCG hash used to seed and advance the random number generator.
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Returns a uniform random number in [0, 1).
+fn rand() -> f32 {
+    rng_state = pcg(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+// Returns whether the ray hits the bounds closer than `t_max`.
+fn hit_bounds(p_min: vec3<f32>, p_max: vec3<f32>, o: vec3<f32>, inv_d: vec3<f32>, t_max: f32) -> bool {
+    let t0 = (p_min - o) * inv_d;
+    let t1 = (p_max - o) * inv_d;
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+    let t_enter = max(max(t_near.x, t_near.y), max(t_near.z, 0.0));
+    let t_exit = min(min(t_far.x, t_far.y), t_far.z);
+    return t_enter <= t_exit && t_enter < t_max;
+}
+
+// Returns the ray parameter where the ray hits the triangle or -1.
+fn hit_triangle(triangle: Triangle, o: vec3<f32>, d: vec3<f32>) -> f32 {
+    let e1 = triangle.p1 - triangle.p0;
+    let e2 = triangle.p2 - triangle.p0;
+    let p = cross(d, e2);
+    let det = dot(e1, p);
+    if (abs(det) < 1e-12) {
+        return -1.0;
+    }
+    let inv_det = 1.0 / det;
+    let s = o - triangle.p0;
+    let u = dot(s, p) * inv_det;
+    if (u < 0.0 || u > 1.0) {
+        return -1.0;
+    }
+    let q = cross(s, e1);
+    let v = dot(d, q) * inv_det;
+    if (v < 0.0 || u + v > 1.0) {
+        return -1.0;
+    }
+    return dot(e2, q) * inv_det;
+}
+
+// Returns the closest triangle the ray hits by traversing the BVH.
+fn intersect(o: vec3<f32>, d: vec3<f32>) -> Hit {
+    var hit = Hit(3.4e38, -1);
+    if (params.n_nodes == 0u) {
+        return hit;
+    }
+
+    let inv_d = 1.0 / select(d, vec3<f32>(1e-20), abs(d) < vec3<f32>(1e-20));
+    var stack: array<u32, STACK_SIZE>;
+    var n_stack = 0u;
+    var index = 0u;
+    loop {
+        let node = nodes[index];
+        if (hit_bounds(node.p_min, node.p_max, o, inv_d, hit.t)) {
+            if (node.count > 0u) {
+                for (var i = node.offset; i < node.offset + node.count; i++) {
+                    let t = hit_triangle(triangles[i], o, d);
+                    if (t > 0.0 && t < hit.t) {
+                        hit = Hit(t, i32(i));
+                    }
+                }
+            } else if (n_stack < STACK_SIZE) {
+                stack[n_stack] = node.offset;
+                n_stack++;
+                index++;
+                continue;
+            }
+        }
+        if (n_stack == 0u) {
+            break;
+        }
+        n_stack--;
+        index = stack[n_stack];
+    }
+    return hit;
+}
+
+// Returns a direction sampled with a cosine-weighted distribution over the
+// hemisphere around `n`.
+fn cosine_sample_hemisphere(n: vec3<f32>) -> vec3<f32> {
+    let r = sqrt(rand());
+    let phi = 2.0 * PI * rand();
+    let x = r * cos(phi);
+    let y = r * sin(phi);
+    let z = sqrt(max(0.0, 1.0 - x * x - y * y));
+
+    // Coordinate system around `n` (Duff et al. 2017).
+    let sign = select(-1.0, 1.0, n.z >= 0.0);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let s = vec3<f32>(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let t = vec3<f32>(b, sign + n.y * n.y * a, -n.y);
+    return normalize(x * s + y * t + z * n);
+}
+
+// Returns the Fresnel reflectance of a dielectric entered from outside.
+fn fr_dielectric(cos_theta_i: f32, eta: f32) -> f32 {
+    let sin_theta_t = sqrt(max(0.0, 1.0 - cos_theta_i * cos_theta_i)) / eta;
+    if (sin_theta_t >= 1.0) {
+        return 1.0;
+    }
+    let cos_theta_t = sqrt(max(0.0, 1.0 - sin_theta_t * sin_theta_t));
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    return (r_parl * r_parl + r_perp * r_perp) / 2.0;
+}
+
+// Returns the origin of a ray leaving `p` on the side `n` faces.
+fn offset_ray_origin(p: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let scale = 1.0 + max(max(abs(p.x), abs(p.y)), abs(p.z));
+    return p + n * (1e-4 * scale);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.size.x || id.y >= params.size.y) {
+        return;
+    }
+    let pixel = id.y * params.size.x + id.x;
+    rng_state = pcg(pixel ^ pcg(params.sample_index));
+
+    // Generate the camera ray from the near and far planes of the camera
+    // through a uniformly sampled point of the pixel.
+    let p_film = vec2<f32>(vec2<i32>(id.xy) + params.pixel_min) + vec2<f32>(rand(), rand());
+    let p_near = params.raster_to_world * vec4<f32>(p_film, 0.0, 1.0);
+    let p_far = params.raster_to_world * vec4<f32>(p_film, 1.0, 1.0);
+    var o = p_near.xyz / p_near.w;
+    var d = normalize(p_far.xyz / p_far.w - o);
+
+    var l = vec3<f32>(0.0);
+    var beta = vec3<f32>(1.0);
+    for (var depth = 0u; ; depth++) {
+        let hit = intersect(o, d);
+        if (hit.index < 0) {
+            l += beta * params.environment.xyz;
+            break;
+        }
+
+        let triangle = triangles[hit.index];
+        let material = materials[triangle.material];
+        if (dot(triangle.n, d) < 0.0 || material.two_sided != 0u) {
+            l += beta * material.emission.xyz;
+        }
+        if (depth == params.max_depth) {
+            break;
+        }
+
+        // Sample the BSDF on the side of the surface the ray arrives from.
+        let p = o + d * hit.t;
+        let n = faceForward(triangle.n, d, triangle.n);
+        if (material.kind == PLASTIC && rand() < 0.5) {
+            let f = fr_dielectric(-dot(d, n), PLASTIC_ETA);
+            d = reflect(d, n);
+            beta *= material.ks.xyz * (2.0 * f);
+        } else {
+            d = cosine_sample_hemisphere(n);
+            beta *= select(1.0, 2.0, material.kind == PLASTIC) * material.kd.xyz;
+        }
+        o = offset_ray_origin(p, n);
+
+        // Terminate the path with Russian roulette.
+        let max_beta = max(max(beta.x, beta.y), beta.z);
+        if (max_beta <= 0.0) {
+            break;
+        }
+        if (depth > 3u && max_beta < 1.0) {
+            let q = max(0.05, 1.0 - max_beta);
+            if (rand() < q) {
+                break;
+            }
+            beta /= 1.0 - q;
+        }
+    }
+
+    radiance[pixel] += vec4<f32>(l, 1.0);
+}
@@ -0,0 +1,429 @@
+//! GPU Renderer
+
+use crate::bvh::*;
+use crate::scene::*;
+use bytemuck::{Pod, Zeroable};
+use core::geometry::*;
+use std::borrow::Cow;
+use std::sync::mpsc::channel;
+use wgpu::util::DeviceExt;
+
+/// Width and height of the workgroups of the path tracer in pixels.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Uniform parameters of the path tracer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+struct Params {
+    /// Columns of the transformation from raster space to world space.
+    raster_to_world: [[f32; 4]; 4],
+
+    /// Radiance of the environment.
+    environment: [f32; 4],
+
+    /// First pixel of the image.
+    pixel_min: [i32; 2],
+
+    /// Width and height of the image in pixels.
+    size: [u32; 2],
+
+    /// Index of the sample added by a dispatch.
+    sample_index: u32,
+
+    /// Maximum number of bounces.
+    max_depth: u32,
+
+    /// Number of BVH nodes.
+    n_nodes: u32,
+
+    /// Padding to align the size of the parameters.
+    pad: u32,
+}
+
+// The fields are plain numbers without padding between them.
+unsafe impl Zeroable for Params {}
+unsafe impl Pod for Params {}
+
+/// The camera used by the GPU path tracer.
+pub struct GpuCamera {
+    /// Transformation from raster space to world space. Points on the near
+    /// plane have depth 0 and points on the far plane have depth 1.
+    pub raster_to_world: Transform,
+
+    /// Bounds of the pixels to render in raster space.
+    pub pixel_bounds: Bounds2i,
+}
+
+/// Renders a `GpuScene` with a path tracer running as a compute shader.
+pub struct GpuRenderer {
+    /// Name of the adapter.
+    adapter_name: String,
+
+    /// The logical device.
+    device: wgpu::Device,
+
+    /// Queue of the device.
+    queue: wgpu::Queue,
+
+    /// The path tracer.
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuRenderer {
+    /// Returns a new `GpuRenderer` using the first adapter found with
+    /// compute shaders or an error if there is none.
+    pub fn new() -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok_or_else(|| String::from("No GPU adapter found."))?;
+
+        let info = adapter.get_info();
+        let capabilities = adapter.get_downlevel_capabilities();
+        if !capabilities
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            return Err(format!(
+                "GPU '{}' doesn't support compute shaders.",
+                info.name
+            ));
+        }
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("pbrt"),
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|err| format!("Unable to open GPU '{}'. {}", info.name, err))?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("path tracer"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("path_tracer.wgsl"))),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("path tracer"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            return Err(format!("Unable to create the GPU path tracer. {}", err));
+        }
+
+        Ok(Self {
+            adapter_name: info.name,
+            device,
+            queue,
+            pipeline,
+        })
+    }
+
+    /// Returns the name of the adapter.
+    pub fn adapter_name(&self) -> &str {
+        &self.adapter_name
+    }
+
+    /// Render the scene and return the RGB radiance of the pixels in
+    /// scanline order.
+    ///
+    /// * `scene`             - The scene.
+    /// * `camera`            - The camera.
+    /// * `samples_per_pixel` - Number of samples per pixel.
+    /// * `max_depth`         - Maximum number of bounces.
+    pub fn render(
+        &self,
+        scene: &GpuScene,
+        camera: &GpuCamera,
+        samples_per_pixel: u32,
+        max_depth: u32,
+    ) -> Result<Vec<[f32; 3]>, String> {
+        let extent = camera.pixel_bounds.diagonal();
+        if extent.x <= 0 || extent.y <= 0 {
+            return Ok(vec![]);
+        }
+        let (width, height) = (extent.x as u32, extent.y as u32);
+        let n_pixels = (width * height) as usize;
+
+        // Storage buffers can't be empty so empty scenes get a triangle that
+        // is never tested.
+        let mut triangles = scene.triangles.clone();
+        let nodes = build_bvh(&mut triangles);
+        let n_nodes = nodes.len() as u32;
+        let nodes = if nodes.is_empty() {
+            vec![GpuBVHNode::default()]
+        } else {
+            nodes
+        };
+        if triangles.is_empty() {
+            triangles.push(GpuTriangle::default());
+        }
+        let mut materials = scene.materials.clone();
+        if materials.is_empty() {
+            materials.push(GpuMaterial::default());
+        }
+
+        let max_size = self.device.limits().max_storage_buffer_binding_size as usize;
+        let size = std::mem::size_of_val(&triangles[..]);
+        if size > max_size {
+            return Err(format!(
+                "The triangles need {} bytes but GPU '{}' supports buffers of {} bytes.",
+                size, self.adapter_name, max_size
+            ));
+        }
+
+        let m = &camera.raster_to_world.m.m;
+        let mut params = Params {
+            environment: [
+                scene.environment[0],
+                scene.environment[1],
+                scene.environment[2],
+                0.0,
+            ],
+            pixel_min: [camera.pixel_bounds.p_min.x, camera.pixel_bounds.p_min.y],
+            size: [width, height],
+            max_depth,
+            n_nodes,
+            ..Params::default()
+        };
+        for (c, column) in params.raster_to_world.iter_mut().enumerate() {
+            for (r, v) in column.iter_mut().enumerate() {
+                *v = m[r][c];
+            }
+        }
+
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let params_buffer = self.buffer("params", &[params], wgpu::BufferUsages::UNIFORM);
+        let node_buffer = self.buffer("nodes", &nodes, wgpu::BufferUsages::STORAGE);
+        let triangle_buffer = self.buffer("triangles", &triangles, wgpu::BufferUsages::STORAGE);
+        let material_buffer = self.buffer("materials", &materials, wgpu::BufferUsages::STORAGE);
+        let radiance_buffer = self.buffer(
+            "radiance",
+            &vec![[0.0_f32; 4]; n_pixels],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: radiance_buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("path tracer"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                params_buffer.as_entire_binding(),
+                node_buffer.as_entire_binding(),
+                triangle_buffer.as_entire_binding(),
+                material_buffer.as_entire_binding(),
+                radiance_buffer.as_entire_binding(),
+            ]
+            .iter()
+            .enumerate()
+            .map(|(i, resource)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: resource.clone(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        // Add one sample per pixel with each dispatch so that a dispatch
+        // doesn't run long enough to trigger the driver's watchdog.
+        for sample_index in 0..samples_per_pixel {
+            params.sample_index = sample_index;
+            self.queue
+                .write_buffer(&params_buffer, 0, bytemuck::bytes_of(&params));
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("path tracer"),
+                    timestamp_writes: None,
+                });
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+            if sample_index + 1 == samples_per_pixel {
+                encoder.copy_buffer_to_buffer(
+                    &radiance_buffer,
+                    0,
+                    &readback_buffer,
+                    0,
+                    radiance_buffer.size(),
+                );
+            }
+            self.queue.submit(Some(encoder.finish()));
+        }
+
+        if let Some(err) = pollster::block_on(self.device.pop_error_scope()) {
+            return Err(format!("Unable to render on the GPU. {}", err));
+        }
+
+        // Read back the accumulated radiance.
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap_or(())
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        match receiver.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => return Err(format!("Unable to read the GPU image. {}", err)),
+            Err(err) => return Err(format!("Unable to read the GPU image. {}", err)),
+        }
+
+        let data = slice.get_mapped_range();
+        let scale = 1.0 / samples_per_pixel.max(1) as f32;
+        let pixels = bytemuck::cast_slice::<u8, [f32; 4]>(&data)
+            .iter()
+            .map(|l| [l[0] * scale, l[1] * scale, l[2] * scale])
+            .collect();
+        drop(data);
+        readback_buffer.unmap();
+        Ok(pixels)
+    }
+
+    /// Returns a buffer initialized with the given values.
+    ///
+    /// * `label`  - Label of the buffer.
+    /// * `values` - The values.
+    /// * `usage`  - How the buffer is used.
+    fn buffer<T: Pod>(&self, label: &str, values: &[T], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(values),
+                usage: usage | wgpu::BufferUsages::COPY_DST,
+            })
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::pbrt::*;
+
+    /// Returns a renderer or `None` if there is no GPU adapter with compute
+    /// shaders, in which case the test is skipped.
+    fn renderer() -> Option<GpuRenderer> {
+        match GpuRenderer::new() {
+            Ok(renderer) => Some(renderer),
+            Err(err) => {
+                eprintln!("Skipping GPU test. {}", err);
+                None
+            }
+        }
+    }
+
+    /// Returns a camera looking down the z-axis at the square [-1, 1]^2 with
+    /// an image of 4x4 pixels.
+    fn camera() -> GpuCamera {
+        let raster_to_screen =
+            Transform::translate(&Vector3f::new(-1.0, 1.0, 0.0)) * Transform::scale(0.5, -0.5, 1.0);
+        GpuCamera {
+            raster_to_world: Transform::translate(&Vector3f::new(0.0, 0.0, -1.0))
+                * raster_to_screen,
+            pixel_bounds: Bounds2i::new(Point2i::new(0, 0), Point2i::new(4, 4)),
+        }
+    }
+
+    /// Returns a scene with a square at z = 0 that fills the image.
+    ///
+    /// * `material` - The material of the square.
+    fn square(material: GpuMaterial) -> GpuScene {
+        let mut scene = GpuScene::default();
+        let m = scene.add_material(material);
+        let p = [
+            Point3f::new(-1.0, -1.0, 0.0),
+            Point3f::new(1.0, -1.0, 0.0),
+            Point3f::new(1.0, 1.0, 0.0),
+            Point3f::new(-1.0, 1.0, 0.0),
+        ];
+        let n = Normal3f::new(0.0, 0.0, -1.0);
+        scene.add_triangle(&[p[0], p[1], p[2]], &n, m);
+        scene.add_triangle(&[p[0], p[2], p[3]], &n, m);
+        scene
+    }
+
+    fn average(pixels: &[[Float; 3]]) -> Float {
+        let sum: Float = pixels.iter().map(|p| p.iter().sum::<Float>()).sum();
+        sum / (3 * pixels.len()) as Float
+    }
+
+    #[test]
+    fn emitting_square_and_environment() {
+        let renderer = match renderer() {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        // An emitting black square is seen directly.
+        let mut material = GpuMaterial::default();
+        material.emission = [2.0, 2.0, 2.0, 0.0];
+        let pixels = renderer.render(&square(material), &camera(), 4, 5).unwrap();
+        assert_eq!(pixels.len(), 16);
+        assert!(pixels.iter().flatten().all(|v| (v - 2.0).abs() < 1e-4));
+
+        // The back of a one-sided emitter is black and the environment is
+        // seen around it.
+        let mut scene = square(material);
+        for t in scene.triangles.iter_mut() {
+            t.n = [0.0, 0.0, 1.0];
+        }
+        scene.environment = [1.0, 1.0, 1.0];
+        assert_eq!(
+            average(&renderer.render(&scene, &camera(), 4, 5).unwrap()),
+            0.0
+        );
+        scene.triangles.clear();
+        assert_eq!(
+            average(&renderer.render(&scene, &camera(), 1, 5).unwrap()),
+            1.0
+        );
+    }
+
+    #[test]
+    fn diffuse_square_reflects_environment() {
+        let renderer = match renderer() {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        // A matte square with albedo 0.5 lit by a uniform environment
+        // reflects half of the environment's radiance.
+        let mut material = GpuMaterial::default();
+        material.kd = [0.5, 0.5, 0.5, 0.0];
+        let mut scene = square(material);
+        scene.environment = [1.0, 1.0, 1.0];
+        let pixels = renderer.render(&scene, &camera(), 16, 5).unwrap();
+        assert!((average(&pixels) - 0.5).abs() < 1e-4);
+
+        // Without bounces only emission is seen.
+        assert_eq!(
+            average(&renderer.render(&scene, &camera(), 1, 0).unwrap()),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! GPU Scene
+
+use bytemuck::{Pod, Zeroable};
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::spectrum::*;
+
+/// Lambertian reflection.
+pub const GPU_MATTE: u32 = 0;
+
+/// Lambertian reflection under a smooth dielectric coating.
+pub const GPU_PLASTIC: u32 = 1;
+
+/// A triangle laid out for the path tracer's storage buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuTriangle {
+    /// First vertex.
+    pub p0: [f32; 3],
+
+    /// Index of the material.
+    pub material: u32,
+
+    /// Second vertex.
+    pub p1: [f32; 3],
+
+    /// Padding to align `p2`.
+    pad1: u32,
+
+    /// Third vertex.
+    pub p2: [f32; 3],
+
+    /// Padding to align `n`.
+    pad2: u32,
+
+    /// Surface normal on the side the triangle emits light from.
+    pub n: [f32; 3],
+
+    /// Padding to align the next triangle.
+    pad3: u32,
+}
+
+// The fields are plain numbers without padding between them.
+unsafe impl Zeroable for GpuTriangle {}
+unsafe impl Pod for GpuTriangle {}
+
+impl GpuTriangle {
+    /// Create a new `GpuTriangle`.
+    ///
+    /// * `p`        - The vertices.
+    /// * `n`        - Surface normal on the side the triangle emits light from.
+    /// * `material` - Index of the material.
+    pub fn new(p: &[Point3f; 3], n: &Normal3f, material: u32) -> Self {
+        Self {
+            p0: [p[0].x, p[0].y, p[0].z],
+            material,
+            p1: [p[1].x, p[1].y, p[1].z],
+            p2: [p[2].x, p[2].y, p[2].z],
+            n: [n.x, n.y, n.z],
+            ..Self::default()
+        }
+    }
+
+    /// Returns the centroid.
+    pub fn centroid(&self) -> [f32; 3] {
+        let mut c = [0.0; 3];
+        for (i, c) in c.iter_mut().enumerate() {
+            *c = (self.p0[i] + self.p1[i] + self.p2[i]) / 3.0;
+        }
+        c
+    }
+}
+
+/// A material laid out for the path tracer's storage buffer.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuMaterial {
+    /// The type of material, `GPU_MATTE` or `GPU_PLASTIC`.
+    pub kind: u32,
+
+    /// Emit light on both sides of the surface instead of the side the
+    /// normal faces.
+    pub two_sided: u32,
+
+    /// Padding to align `kd`.
+    pad: [u32; 2],
+
+    /// Diffuse reflectance.
+    pub kd: [f32; 4],
+
+    /// Specular reflectance of the coating.
+    pub ks: [f32; 4],
+
+    /// Emitted radiance.
+    pub emission: [f32; 4],
+}
+
+// The fields are plain numbers without padding between them.
+unsafe impl Zeroable for GpuMaterial {}
+unsafe impl Pod for GpuMaterial {}
+
+impl GpuMaterial {
+    /// Returns a material for the given type of material and its parameters
+    /// or an error if the GPU can't render it. Only `matte` and `plastic`
+    /// with constant reflectances are supported.
+    ///
+    /// * `name` - Type of material.
+    /// * `tp`   - Texture parameter set.
+    pub fn new(name: &str, tp: &TextureParams) -> Result<Self, String> {
+        let (kind, kd, ks) = match name {
+            "matte" => (GPU_MATTE, 0.5, 0.0),
+            "plastic" => (GPU_PLASTIC, 0.25, 0.25),
+            _ => return Err(format!("Material '{}' isn't supported on the GPU.", name)),
+        };
+        for param in ["Kd", "Ks", "bumpmap"].iter() {
+            if !tp.find_texture(param, String::new()).is_empty() {
+                return Err(format!("Texture '{}' isn't supported on the GPU.", param));
+            }
+        }
+
+        let reflectance = |param: &str, default: Float| -> [f32; 4] {
+            let [r, g, b] = tp.find_spectrum(param, Spectrum::new(default)).to_rgb();
+            [r, g, b, 0.0]
+        };
+
+        Ok(Self {
+            kind,
+            kd: reflectance("Kd", kd),
+            ks: if kind == GPU_PLASTIC {
+                reflectance("Ks", ks)
+            } else {
+                [0.0; 4]
+            },
+            ..Self::default()
+        })
+    }
+
+    /// Returns the material emitting light like the given area light or an
+    /// error if the GPU can't render it.
+    ///
+    /// * `name`   - Type of area light.
+    /// * `params` - Area light parameters.
+    pub fn with_area_light(mut self, name: &str, params: &ParamSet) -> Result<Self, String> {
+        if name != "diffuse" {
+            return Err(format!("Area light '{}' isn't supported on the GPU.", name));
+        }
+
+        let l = params.find_one_illuminant("L", Spectrum::new(1.0));
+        let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
+        let [r, g, b] = (l * sc).to_rgb();
+        self.emission = [r, g, b, 0.0];
+        self.two_sided = params.find_one_bool("twosided", false) as u32;
+        Ok(self)
+    }
+}
+
+/// The triangles, materials and environment light rendered on the GPU.
+#[derive(Clone, Debug, Default)]
+pub struct GpuScene {
+    /// Triangles.
+    pub triangles: Vec<GpuTriangle>,
+
+    /// Materials referenced by the triangles.
+    pub materials: Vec<GpuMaterial>,
+
+    /// Radiance arriving from infinitely far away in all directions.
+    pub environment: [f32; 3],
+}
+
+impl GpuScene {
+    /// Add a material and return its index.
+    ///
+    /// * `material` - The material.
+    pub fn add_material(&mut self, material: GpuMaterial) -> u32 {
+        self.materials.push(material);
+        (self.materials.len() - 1) as u32
+    }
+
+    /// Add a triangle.
+    ///
+    /// * `p`        - The vertices.
+    /// * `n`        - Surface normal on the side the triangle emits light from.
+    /// * `material` - Index of the material.
+    pub fn add_triangle(&mut self, p: &[Point3f; 3], n: &Normal3f, material: u32) {
+        self.triangles.push(GpuTriangle::new(p, n, material));
+    }
+
+    /// Add a light source or return an error if the GPU can't render it.
+    /// Only constant infinite lights are supported.
+    ///
+    /// * `name`   - Type of light.
+    /// * `params` - Light parameters.
+    pub fn add_light(&mut self, name: &str, params: &ParamSet) -> Result<(), String> {
+        if name != "infinite" {
+            return Err(format!("Light '{}' isn't supported on the GPU.", name));
+        }
+        if !params.find_one_string("mapname", String::new()).is_empty() {
+            return Err(String::from(
+                "Environment maps aren't supported on the GPU.",
+            ));
+        }
+
+        let l = params.find_one_illuminant("L", Spectrum::new(1.0));
+        let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
+        for (e, v) in self.environment.iter_mut().zip((l * sc).to_rgb().iter()) {
+            *e += *v;
+        }
+        Ok(())
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn texture_params(geom_params: ParamSet, mat_params: ParamSet) -> TextureParams {
+        TextureParams::new(geom_params, mat_params, HashMap::new(), HashMap::new())
+    }
+
+    /// Returns an RGB parameter value as it is read back from its spectrum.
+    ///
+    /// * `rgb`           - The RGB value.
+    /// * `spectrum_type` - Indicates the type of spectrum.
+    fn rgba(rgb: [Float; 3], spectrum_type: Option<SpectrumType>) -> [f32; 4] {
+        let [r, g, b] = Spectrum::from_rgb(&rgb, spectrum_type).to_rgb();
+        [r, g, b, 0.0]
+    }
+
+    #[test]
+    fn materials_use_reflectances_of_parameters() {
+        let mut mat_params = ParamSet::new();
+        mat_params.add_rgb_spectrum("Kd", &[0.1, 0.2, 0.3]);
+        let mut geom_params = ParamSet::new();
+        geom_params.add_rgb_spectrum("Ks", &[0.4, 0.5, 0.6]);
+        let tp = texture_params(geom_params, mat_params);
+
+        let matte = GpuMaterial::new("matte", &tp).unwrap();
+        assert_eq!(matte.kind, GPU_MATTE);
+        assert_eq!(matte.kd, rgba([0.1, 0.2, 0.3], None));
+        assert_eq!(matte.ks, [0.0; 4]);
+
+        let plastic = GpuMaterial::new("plastic", &tp).unwrap();
+        assert_eq!(plastic.kind, GPU_PLASTIC);
+        assert_eq!(plastic.ks, rgba([0.4, 0.5, 0.6], None));
+    }
+
+    #[test]
+    fn unsupported_materials_and_textures_are_errors() {
+        let tp = texture_params(ParamSet::new(), ParamSet::new());
+        assert!(GpuMaterial::new("hair", &tp).is_err());
+
+        let mut mat_params = ParamSet::new();
+        mat_params.add_texture("Kd", &[String::from("checks")]);
+        let tp = texture_params(ParamSet::new(), mat_params);
+        assert!(GpuMaterial::new("matte", &tp).is_err());
+    }
+
+    #[test]
+    fn area_lights_and_infinite_lights_emit() {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("L", &[2.0, 2.0, 2.0]);
+        params.add_bool("twosided", &[true]);
+        let tp = texture_params(ParamSet::new(), ParamSet::new());
+        let material = GpuMaterial::new("matte", &tp)
+            .unwrap()
+            .with_area_light("diffuse", &params)
+            .unwrap();
+        let emission = rgba([2.0, 2.0, 2.0], Some(SpectrumType::Illuminant));
+        assert_eq!(material.emission, emission);
+        assert_eq!(material.two_sided, 1);
+        assert!(GpuMaterial::new("matte", &tp)
+            .unwrap()
+            .with_area_light("spot", &params)
+            .is_err());
+
+        let mut scene = GpuScene::default();
+        scene.add_light("infinite", &params).unwrap();
+        scene.add_light("infinite", &params).unwrap();
+        assert!(scene.add_light("point", &params).is_err());
+        let environment = [2.0 * emission[0], 2.0 * emission[1], 2.0 * emission[2]];
+        assert_eq!(scene.environment, environment);
+
+        params.add_string("mapname", &[String::from("sky.exr")]);
+        assert!(scene.add_light("infinite", &params).is_err());
+    }
+}
@@ -0,0 +1,1170 @@
+//! Bidirectional Path Tracing Integrator
+//!
+//! Traces a subpath from the camera and another from a light source for each
+//! camera sample and connects every prefix of one to every prefix of the
+//! other. The contributions of the strategies that can create the same path
+//! are combined with multiple importance sampling using the balance heuristic.
+//! Paths connected directly to the camera land on arbitrary pixels and are
+//! splatted to the film.
+//!
+//! Participating media aren't sampled. Subpaths only have vertices on the
+//! camera, lights and surfaces and pass through media without scattering or
+//! attenuation. Use `volpath` for scenes with media.
+
+use core::camera::*;
+use core::film::*;
+use core::geometry::*;
+use core::integrator::*;
+use core::light::*;
+use core::material::*;
+use core::medium::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::sampler::*;
+use core::sampling::*;
+use core::scene::*;
+use core::spectrum::*;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Implements bidirectional path tracing.
+pub struct BDPTIntegrator {
+    /// Common data for sampler integrators.
+    pub data: SamplerIntegratorData,
+
+    /// Light sampling strategy; `uniform`, `power` or `spatial`.
+    pub light_sample_strategy: String,
+
+    /// Distribution for choosing the light that starts a light subpath.
+    light_distribution: Option<ArcLightDistribution>,
+
+    /// Index of each light in the scene keyed by its address.
+    light_to_index: HashMap<usize, usize>,
+}
+
+impl BDPTIntegrator {
+    /// Create a new `BDPTIntegrator`.
+    ///
+    /// * `max_depths`            - Maximum recursion depths.
+    /// * `camera`                - The camera.
+    /// * `sampler`               - The sampler.
+    /// * `pixel_bounds`          - Pixel bounds for the image.
+    /// * `light_sample_strategy` - Light sampling strategy; `uniform`, `power`
+    ///                             or `spatial`.
+    pub fn new(
+        max_depths: MaxDepths,
+        camera: ArcCamera,
+        sampler: ArcSampler,
+        pixel_bounds: Bounds2i,
+        light_sample_strategy: &str,
+    ) -> Self {
+        Self {
+            data: SamplerIntegratorData::new(max_depths, camera, sampler, pixel_bounds),
+            light_sample_strategy: String::from(light_sample_strategy),
+            light_distribution: None,
+            light_to_index: HashMap::new(),
+        }
+    }
+
+    /// Initialize the light distribution before rendering begins.
+    ///
+    /// * `scene` - The scene.
+    fn preprocess(&mut self, scene: Arc<Scene>) {
+        self.light_to_index = scene
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(i, light)| (light_key(light), i))
+            .collect();
+        self.light_distribution =
+            create_light_sample_distribution(&self.light_sample_strategy, scene);
+    }
+
+    /// Returns the subpath starting at the camera for a camera ray.
+    ///
+    /// * `ctx`     - Scene data used to evaluate path vertices.
+    /// * `ray`     - The camera ray.
+    /// * `sampler` - The sampler.
+    fn generate_camera_subpath<'a>(
+        &self,
+        ctx: &PathContext<'a>,
+        ray: &Ray,
+        sampler: &mut ArcSampler,
+    ) -> Vec<Vertex<'a>> {
+        let max_depth = self.data.max_depths.total + 2;
+        let hit = Hit::new(
+            ray.o,
+            ray.time,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::zero(),
+            Some(MediumInterface::from(ray.medium.clone())),
+        );
+        let beta = Spectrum::new(1.0);
+        let pdf_dir = ctx.camera.read().unwrap().pdf_we(ray).dir;
+
+        let mut path = vec![Vertex::camera(hit, beta)];
+        random_walk(
+            ctx,
+            ray.clone(),
+            sampler,
+            beta,
+            pdf_dir,
+            max_depth - 1,
+            TransportMode::Radiance,
+            &mut path,
+        );
+        path
+    }
+
+    /// Returns the subpath starting at a sampled light.
+    ///
+    /// * `ctx`     - Scene data used to evaluate path vertices.
+    /// * `time`    - Time of the camera sample.
+    /// * `sampler` - The sampler.
+    fn generate_light_subpath<'a>(
+        &self,
+        ctx: &PathContext<'a>,
+        time: Float,
+        sampler: &mut ArcSampler,
+    ) -> Vec<Vertex<'a>> {
+        let max_depth = self.data.max_depths.total + 1;
+
+        // Sample initial ray for light subpath.
+        let samp = Arc::get_mut(sampler).unwrap();
+        let (light_num, light_pdf, _) = ctx.light_distr.sample_discrete(samp.get_1d());
+        if light_pdf == 0.0 {
+            return vec![];
+        }
+        let light = &ctx.scene.lights[light_num];
+        let u1 = samp.get_2d();
+        let u2 = samp.get_2d();
+        let Le {
+            ray,
+            n_light,
+            pdf_pos,
+            pdf_dir,
+            value: le,
+        } = light.sample_le(&u1, &u2, time);
+        if pdf_pos == 0.0 || pdf_dir == 0.0 || le.is_black() {
+            return vec![];
+        }
+
+        // Generate first vertex on light subpath and start random walk.
+        let hit = Hit::new(
+            ray.o,
+            time,
+            Vector3f::default(),
+            Vector3f::default(),
+            n_light,
+            Some(MediumInterface::from(ray.medium.clone())),
+        );
+        let mut path = vec![Vertex::light(
+            hit,
+            Some(Arc::clone(light)),
+            le,
+            pdf_pos * light_pdf,
+        )];
+        let beta = le * n_light.abs_dot(&ray.d) / (light_pdf * pdf_pos * pdf_dir);
+        random_walk(
+            ctx,
+            ray.clone(),
+            sampler,
+            beta,
+            pdf_dir,
+            max_depth - 1,
+            TransportMode::Importance,
+            &mut path,
+        );
+
+        // Correct subpath sampling densities for infinite area lights.
+        if path[0].is_infinite_light() {
+            if path.len() > 1 {
+                let mut pdf_fwd = pdf_pos;
+                if path[1].is_on_surface() {
+                    pdf_fwd *= ray.d.abs_dot(&path[1].ng());
+                }
+                path[1].pdf_fwd = pdf_fwd;
+            }
+            path[0].pdf_fwd = infinite_light_density(ctx, &ray.d);
+        }
+        path
+    }
+
+    /// Returns the contribution of the path made by connecting the first `s`
+    /// vertices of the light subpath to the first `t` vertices of the camera
+    /// subpath and the raster position of the contribution if the camera
+    /// vertex was sampled again.
+    ///
+    /// * `ctx`             - Scene data used to evaluate path vertices.
+    /// * `light_vertices`  - The light subpath.
+    /// * `camera_vertices` - The camera subpath.
+    /// * `s`               - Number of light subpath vertices.
+    /// * `t`               - Number of camera subpath vertices.
+    /// * `sampler`         - The sampler.
+    fn connect(
+        &self,
+        ctx: &PathContext,
+        light_vertices: &[Vertex],
+        camera_vertices: &[Vertex],
+        s: usize,
+        t: usize,
+        sampler: &mut ArcSampler,
+    ) -> (Spectrum, Option<Point2f>) {
+        // Ignore invalid connections related to infinite area lights.
+        if t > 1 && s != 0 && camera_vertices[t - 1].is_infinite_light() {
+            return (Spectrum::new(0.0), None);
+        }
+
+        // Perform connection and write contribution to `l`.
+        let mut l = Spectrum::new(0.0);
+        let mut sampled = None;
+        let mut p_raster = None;
+        if s == 0 {
+            // Interpret the camera subpath as a complete path.
+            let pt = &camera_vertices[t - 1];
+            if pt.is_light() {
+                l = pt.le(ctx, &camera_vertices[t - 2]) * pt.beta;
+            }
+        } else if t == 1 {
+            // Sample a point on the camera and connect it to the light subpath.
+            let qs = &light_vertices[s - 1];
+            if qs.is_connectible() {
+                let u = Arc::get_mut(sampler).unwrap().get_2d();
+                let SampleResult {
+                    spectrum: wi_importance,
+                    wi,
+                    pdf,
+                    p_raster: p,
+                    vis,
+                } = ctx.camera.read().unwrap().sample_wi(qs.hit(), &u);
+                if pdf > 0.0 && !wi_importance.is_black() {
+                    let camera = Vertex::camera(vis.p0.clone(), wi_importance / pdf);
+                    l = qs.beta * qs.f(&camera, TransportMode::Importance) * camera.beta;
+                    if qs.is_on_surface() {
+                        l *= wi.abs_dot(&qs.ns());
+                    }
+                    // Only check visibility after we know that the path would
+                    // make a non-zero contribution.
+                    if !l.is_black() {
+                        l *= vis.tr(Arc::clone(ctx.scene), Arc::clone(sampler));
+                    }
+                    sampled = Some(camera);
+                    p_raster = p;
+                }
+            }
+        } else if s == 1 {
+            // Sample a point on a light and connect it to the camera subpath.
+            let pt = &camera_vertices[t - 1];
+            if pt.is_connectible() {
+                let samp = Arc::get_mut(sampler).unwrap();
+                let (light_num, light_pdf, _) = ctx.light_distr.sample_discrete(samp.get_1d());
+                let u = samp.get_2d();
+                let light = &ctx.scene.lights[light_num];
+                let Li {
+                    wi,
+                    pdf,
+                    visibility,
+                    value,
+                    n_light,
+                } = light.sample_li(pt.hit(), &u);
+                if let Some(vis) = visibility {
+                    if pdf > 0.0 && light_pdf > 0.0 && !value.is_black() {
+                        let hit = Hit::new(
+                            vis.p1,
+                            pt.hit().time,
+                            Vector3f::default(),
+                            Vector3f::default(),
+                            n_light,
+                            None,
+                        );
+                        let beta = value / (pdf * light_pdf);
+                        let mut light_vertex =
+                            Vertex::light(hit, Some(Arc::clone(light)), beta, 0.0);
+                        light_vertex.pdf_fwd = light_vertex.pdf_light_origin(ctx, pt);
+                        l = pt.beta
+                            * pt.f(&light_vertex, TransportMode::Radiance)
+                            * light_vertex.beta;
+                        if pt.is_on_surface() {
+                            l *= wi.abs_dot(&pt.ns());
+                        }
+                        // Only check visibility if the path would carry radiance.
+                        if !l.is_black() {
+                            l *= vis.tr(Arc::clone(ctx.scene), Arc::clone(sampler));
+                        }
+                        sampled = Some(light_vertex);
+                    }
+                }
+            }
+        } else {
+            // Handle all other bidirectional connection cases.
+            let qs = &light_vertices[s - 1];
+            let pt = &camera_vertices[t - 1];
+            if qs.is_connectible() && pt.is_connectible() {
+                l = qs.beta
+                    * qs.f(pt, TransportMode::Importance)
+                    * pt.f(qs, TransportMode::Radiance)
+                    * pt.beta;
+                if !l.is_black() {
+                    l *= g(ctx, qs, pt, sampler);
+                }
+            }
+        }
+
+        // Compute MIS weight for connection strategy.
+        if !l.is_black() {
+            l *= mis_weight(ctx, light_vertices, camera_vertices, sampled.as_ref(), s, t);
+        }
+        (l, p_raster)
+    }
+
+    /// Returns the radiance arriving at the origin of a camera ray from the
+    /// paths that contribute to its pixel. Paths that are connected to the
+    /// camera directly are returned in `splats` with their raster positions.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `splats`  - Receives the contributions to splat to the film.
+    fn trace_paths(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        splats: &mut Vec<(Point2f, Spectrum)>,
+    ) -> Spectrum {
+        let light_distr = match self.light_distribution.as_ref() {
+            Some(distribution) => distribution.lookup(&ray.o),
+            None => return Spectrum::new(0.0),
+        };
+        let ctx = PathContext {
+            scene: &scene,
+            camera: &self.data.camera,
+            light_distr: &light_distr,
+            light_to_index: &self.light_to_index,
+        };
+
+        // Trace the camera and light subpaths.
+        let camera_vertices = self.generate_camera_subpath(&ctx, ray, sampler);
+        let time = camera_vertices[0].hit().time;
+        let light_vertices = self.generate_light_subpath(&ctx, time, sampler);
+
+        // Execute all BDPT connection strategies.
+        let max_depth = self.data.max_depths.total;
+        let mut l = Spectrum::new(0.0);
+        for t in 1..=camera_vertices.len() {
+            for s in 0..=light_vertices.len() {
+                if (s == 1 && t == 1) || s + t < 2 || s + t - 2 > max_depth {
+                    continue;
+                }
+
+                let (l_path, p_raster) =
+                    self.connect(&ctx, &light_vertices, &camera_vertices, s, t, sampler);
+                if t != 1 {
+                    l += l_path;
+                } else if let Some(p) = p_raster {
+                    if !l_path.is_black() {
+                        splats.push((p, l_path));
+                    }
+                }
+            }
+        }
+        l
+    }
+}
+
+impl SamplerIntegrator for BDPTIntegrator {
+    /// Returns the common data.
+    fn get_data(&self) -> &SamplerIntegratorData {
+        &self.data
+    }
+
+    /// Returns the radiance arriving at the origin of a camera ray from the
+    /// paths that contribute to its pixel. Paths that are connected to the
+    /// camera directly are splatted to the tile.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `tile`    - The film tile of the camera ray.
+    fn li_splats(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        tile: &mut FilmTile,
+    ) -> Spectrum {
+        self.trace_paths(ray, scene, sampler, &mut tile.splats)
+    }
+
+    /// Returns the scale factor for splats when writing the image after the
+    /// given number of samples per pixel. Every sample splats the paths
+    /// connected to the camera so they are averaged over the samples.
+    ///
+    /// * `samples_per_pixel` - Number of samples taken in each pixel.
+    fn splat_scale(&self, samples_per_pixel: usize) -> Float {
+        if samples_per_pixel > 0 {
+            1.0 / samples_per_pixel as Float
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Integrator for BDPTIntegrator {
+    /// Render the scene.
+    ///
+    /// * `scene` - The scene.
+    fn render(&mut self, scene: Arc<Scene>) {
+        self.preprocess(Arc::clone(&scene));
+        SamplerIntegrator::render(self, scene);
+    }
+
+    /// Returns the radiance arriving at the origin of a camera ray from the
+    /// paths that contribute to its pixel. Paths that are connected to the
+    /// camera directly are splatted to the film.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `_depth`  - The recursion depth.
+    fn li(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        _depth: usize,
+    ) -> Spectrum {
+        let mut splats = vec![];
+        let l = self.trace_paths(ray, scene, sampler, &mut splats);
+        if !splats.is_empty() {
+            let mut camera = self.data.camera.write().unwrap();
+            let camera = Arc::get_mut(&mut *camera).unwrap();
+            for (p, v) in splats.iter() {
+                camera.add_film_splat(p, v);
+            }
+        }
+        l
+    }
+}
+
+impl From<(&ParamSet, ArcSampler, ArcCamera)> for BDPTIntegrator {
+    /// Create a `BDPTIntegrator` from given parameter set and camera.
+    ///
+    /// * `p` - A tuple containing parameter set and camera.
+    fn from(p: (&ParamSet, ArcSampler, ArcCamera)) -> Self {
+        let (params, sampler, camera) = p;
+
+        let max_depths = MaxDepths::from(params);
+
+        let pb = params.find_int("pixelbounds");
+        let np = pb.len();
+
+        let mut pixel_bounds = camera.get_film_sample_bounds();
+        if np > 0 {
+            if np != 4 {
+                error!("Expected 4 values for 'pixel_bounds' parameter. Got {}", np);
+            } else {
+                pixel_bounds = pixel_bounds.intersect(&Bounds2i::new(
+                    Point2i::new(pb[0], pb[2]),
+                    Point2i::new(pb[1], pb[3]),
+                ));
+                if pixel_bounds.area() == 0 {
+                    error!("Degenerate 'pixel_bounds' specified.");
+                }
+            }
+        }
+
+        let light_sample_strategy =
+            params.find_one_string("lightsamplestrategy", String::from("power"));
+
+        // Light tracing splats assume every pixel takes the same number of
+        // samples so adaptive sampling isn't supported.
+        if AdaptiveSampling::from(params).is_enabled() {
+            warn!("Adaptive sampling isn't supported by 'bdpt'. Ignoring 'maxvariance'.");
+        }
+
+        Self::new(
+            max_depths,
+            Arc::clone(&camera),
+            Arc::clone(&sampler),
+            pixel_bounds,
+            &light_sample_strategy,
+        )
+    }
+}
+
+/// Scene data used to evaluate path vertices.
+struct PathContext<'a> {
+    /// The scene.
+    scene: &'a Arc<Scene>,
+
+    /// The camera.
+    camera: &'a RwLock<ArcCamera>,
+
+    /// Distribution for choosing lights.
+    light_distr: &'a Distribution1D,
+
+    /// Index of each light in the scene keyed by its address.
+    light_to_index: &'a HashMap<usize, usize>,
+}
+
+/// The kind of a path vertex and the interaction it is at.
+#[derive(Clone)]
+enum VertexKind<'a> {
+    /// Point on the camera lens.
+    Camera { hit: Hit },
+
+    /// Point on a light source. Camera rays that escape the scene end at a
+    /// light vertex without a light that stands for the infinite lights.
+    Light { hit: Hit, light: Option<ArcLight> },
+
+    /// Point on a surface.
+    Surface { si: Box<SurfaceInteraction<'a>> },
+}
+
+/// A vertex of a camera or light subpath.
+#[derive(Clone)]
+struct Vertex<'a> {
+    /// The kind of vertex.
+    kind: VertexKind<'a>,
+
+    /// Product of the BSDF or phase function values, transmittances and
+    /// cosines divided by the sampling densities from the start of the
+    /// subpath up to the vertex.
+    beta: Spectrum,
+
+    /// Whether the vertex was sampled from a specular lobe.
+    delta: bool,
+
+    /// Density of sampling the vertex in the direction the subpath was
+    /// generated, with respect to area.
+    pdf_fwd: Float,
+
+    /// Density of sampling the vertex in the reverse direction, with respect
+    /// to area.
+    pdf_rev: Float,
+}
+
+impl<'a> Vertex<'a> {
+    /// Returns a vertex on the camera.
+    ///
+    /// * `hit`  - The point on the camera.
+    /// * `beta` - Path throughput.
+    fn camera(hit: Hit, beta: Spectrum) -> Self {
+        Self::new(VertexKind::Camera { hit }, beta, 0.0)
+    }
+
+    /// Returns a vertex on a light.
+    ///
+    /// * `hit`     - The point on the light.
+    /// * `light`   - The light or `None` for rays escaping the scene.
+    /// * `beta`    - Path throughput.
+    /// * `pdf_fwd` - Density of sampling the vertex.
+    fn light(hit: Hit, light: Option<ArcLight>, beta: Spectrum, pdf_fwd: Float) -> Self {
+        Self::new(VertexKind::Light { hit, light }, beta, pdf_fwd)
+    }
+
+    /// Returns a vertex on a surface.
+    ///
+    /// * `si`   - The surface interaction.
+    /// * `beta` - Path throughput.
+    /// * `pdf`  - Density of sampling the direction from `prev` with respect
+    ///            to solid angle.
+    /// * `prev` - The previous vertex of the subpath.
+    fn surface(si: SurfaceInteraction<'a>, beta: Spectrum, pdf: Float, prev: &Vertex) -> Self {
+        let mut v = Self::new(VertexKind::Surface { si: Box::new(si) }, beta, 0.0);
+        v.pdf_fwd = prev.convert_density(pdf, &v);
+        v
+    }
+
+    /// Returns a new vertex.
+    ///
+    /// * `kind`    - The kind of vertex.
+    /// * `beta`    - Path throughput.
+    /// * `pdf_fwd` - Density of sampling the vertex.
+    fn new(kind: VertexKind<'a>, beta: Spectrum, pdf_fwd: Float) -> Self {
+        Self {
+            kind,
+            beta,
+            delta: false,
+            pdf_fwd,
+            pdf_rev: 0.0,
+        }
+    }
+
+    /// Returns the interaction point.
+    fn hit(&self) -> &Hit {
+        match &self.kind {
+            VertexKind::Camera { hit } | VertexKind::Light { hit, .. } => hit,
+            VertexKind::Surface { si } => &si.hit,
+        }
+    }
+
+    /// Returns the position of the vertex.
+    fn p(&self) -> Point3f {
+        self.hit().p
+    }
+
+    /// Returns the geometric normal or zero if the vertex isn't on a surface.
+    fn ng(&self) -> Normal3f {
+        self.hit().n
+    }
+
+    /// Returns the shading normal or zero if the vertex isn't on a surface.
+    fn ns(&self) -> Normal3f {
+        match &self.kind {
+            VertexKind::Surface { si } => si.shading.n,
+            _ => self.hit().n,
+        }
+    }
+
+    /// Returns whether the vertex is on a surface.
+    fn is_on_surface(&self) -> bool {
+        self.ng() != Normal3f::zero()
+    }
+
+    /// Returns the light source at the vertex if any.
+    fn light_source(&self) -> Option<ArcLight> {
+        match &self.kind {
+            VertexKind::Light { light, .. } => light.clone(),
+            VertexKind::Camera { .. } | VertexKind::Surface { .. } => None,
+        }
+    }
+
+    /// Returns the area light of the surface at the vertex if any.
+    fn area_light(&self) -> Option<ArcAreaLight> {
+        match &self.kind {
+            VertexKind::Surface { si } => si.primitive.and_then(|p| p.get_area_light()),
+            VertexKind::Camera { .. } | VertexKind::Light { .. } => None,
+        }
+    }
+
+    /// Returns whether the vertex emits light.
+    fn is_light(&self) -> bool {
+        match &self.kind {
+            VertexKind::Light { .. } => true,
+            VertexKind::Surface { .. } => self.area_light().is_some(),
+            VertexKind::Camera { .. } => false,
+        }
+    }
+
+    /// Returns whether the vertex is on a light described by a delta
+    /// distribution.
+    fn is_delta_light(&self) -> bool {
+        self.light_source().is_some_and(|l| l.is_delta_light())
+    }
+
+    /// Returns whether the vertex is on an infinite or distant light.
+    fn is_infinite_light(&self) -> bool {
+        match &self.kind {
+            VertexKind::Light { light, .. } => light
+                .as_ref()
+                .is_none_or(|l| l.get_type().matches(INFINITE_LIGHT | DELTA_DIRECTION_LIGHT)),
+            VertexKind::Camera { .. } | VertexKind::Surface { .. } => false,
+        }
+    }
+
+    /// Returns whether the vertex can be connected to another vertex.
+    fn is_connectible(&self) -> bool {
+        match &self.kind {
+            VertexKind::Camera { .. } => true,
+            VertexKind::Light { light, .. } => light
+                .as_ref()
+                .is_none_or(|l| !l.get_type().matches(DELTA_DIRECTION_LIGHT)),
+            VertexKind::Surface { si } => si.bsdf.as_ref().is_some_and(|bsdf| {
+                bsdf.num_components(BxDFType::from(BSDF_ALL & !BSDF_SPECULAR)) > 0
+            }),
+        }
+    }
+
+    /// Returns the BSDF value for light scattered from the vertex towards
+    /// another one.
+    ///
+    /// * `next` - The other vertex.
+    /// * `mode` - Transport mode.
+    fn f(&self, next: &Vertex, mode: TransportMode) -> Spectrum {
+        let wi = next.p() - self.p();
+        if wi.length_squared() == 0.0 {
+            return Spectrum::new(0.0);
+        }
+        let wi = wi.normalize();
+        match &self.kind {
+            VertexKind::Surface { si } => match &si.bsdf {
+                Some(bsdf) => {
+                    let wo = si.hit.wo;
+                    bsdf.f(&wo, &wi, BxDFType::from(BSDF_ALL))
+                        * correct_shading_normal(si, &wo, &wi, mode)
+                }
+                None => Spectrum::new(0.0),
+            },
+            VertexKind::Camera { .. } | VertexKind::Light { .. } => Spectrum::new(0.0),
+        }
+    }
+
+    /// Returns the radiance emitted from the vertex towards another one.
+    ///
+    /// * `ctx` - Scene data used to evaluate path vertices.
+    /// * `v`   - The other vertex.
+    fn le(&self, ctx: &PathContext, v: &Vertex) -> Spectrum {
+        if !self.is_light() {
+            return Spectrum::new(0.0);
+        }
+        let w = v.p() - self.p();
+        if w.length_squared() == 0.0 {
+            return Spectrum::new(0.0);
+        }
+        let w = w.normalize();
+
+        if self.is_infinite_light() {
+            // Return emitted radiance for infinite light sources.
+            let p = self.p();
+            let ray = Ray::new(p, -w, INFINITY, 0.0, None);
+            ctx.scene
+                .infinite_lights
+                .iter()
+                .fold(Spectrum::new(0.0), |le, light| le + light.le(&ray))
+        } else {
+            match &self.kind {
+                VertexKind::Surface { si } => self
+                    .area_light()
+                    .map_or(Spectrum::new(0.0), |light| light.l(&si.hit, &w)),
+                VertexKind::Camera { .. } | VertexKind::Light { .. } => Spectrum::new(0.0),
+            }
+        }
+    }
+
+    /// Converts a density with respect to solid angle at the vertex to a
+    /// density with respect to area at another vertex.
+    ///
+    /// * `pdf`  - Density with respect to solid angle.
+    /// * `next` - The other vertex.
+    fn convert_density(&self, pdf: Float, next: &Vertex) -> Float {
+        // Return solid angle density if `next` is an infinite area light.
+        if next.is_infinite_light() {
+            return pdf;
+        }
+
+        let w = next.p() - self.p();
+        if w.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let inv_dist2 = 1.0 / w.length_squared();
+        let mut pdf = pdf;
+        if next.is_on_surface() {
+            pdf *= next.ng().abs_dot(&(w * inv_dist2.sqrt()));
+        }
+        pdf * inv_dist2
+    }
+
+    /// Returns the density with respect to area of sampling `next` from the
+    /// vertex given the previous vertex.
+    ///
+    /// * `ctx`  - Scene data used to evaluate path vertices.
+    /// * `prev` - The previous vertex or `None` for vertices on the camera.
+    /// * `next` - The vertex to sample.
+    fn pdf(&self, ctx: &PathContext, prev: Option<&Vertex>, next: &Vertex) -> Float {
+        // Compute directions to preceding and next vertex.
+        let wn = next.p() - self.p();
+        if wn.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let wn = wn.normalize();
+
+        // Compute directional density depending on the vertex types.
+        let pdf = match &self.kind {
+            VertexKind::Light { .. } => return self.pdf_light(ctx, next),
+            VertexKind::Camera { hit } => {
+                let ray = hit.spawn_ray(&wn);
+                ctx.camera.read().unwrap().pdf_we(&ray).dir
+            }
+            VertexKind::Surface { si } => {
+                let wp = match prev {
+                    Some(prev) => prev.p() - self.p(),
+                    None => return 0.0,
+                };
+                if wp.length_squared() == 0.0 {
+                    return 0.0;
+                }
+                si.bsdf.as_ref().map_or(0.0, |bsdf| {
+                    bsdf.pdf(&wp.normalize(), &wn, BxDFType::from(BSDF_ALL))
+                })
+            }
+        };
+
+        // Return probability per unit area at vertex `next`.
+        self.convert_density(pdf, next)
+    }
+
+    /// Returns the density with respect to area of sampling `v` by emitting
+    /// light from the vertex.
+    ///
+    /// * `ctx` - Scene data used to evaluate path vertices.
+    /// * `v`   - The vertex to sample.
+    fn pdf_light(&self, ctx: &PathContext, v: &Vertex) -> Float {
+        let w = v.p() - self.p();
+        let inv_dist2 = 1.0 / w.length_squared();
+        let w = w * inv_dist2.sqrt();
+
+        let mut pdf = if self.is_infinite_light() {
+            // Compute planar sampling density for infinite light sources.
+            let (_center, radius) = ctx.scene.bounding_sphere();
+            1.0 / (PI * radius * radius)
+        } else {
+            // Compute sampling density for non-infinite light sources.
+            self.pdf_le(&w).map_or(0.0, |pdf| pdf.pdf_dir * inv_dist2)
+        };
+        if v.is_on_surface() {
+            pdf *= v.ng().abs_dot(&w);
+        }
+        pdf
+    }
+
+    /// Returns the density with respect to area of sampling the vertex as
+    /// the origin of a light subpath.
+    ///
+    /// * `ctx` - Scene data used to evaluate path vertices.
+    /// * `v`   - The next vertex of the light subpath.
+    fn pdf_light_origin(&self, ctx: &PathContext, v: &Vertex) -> Float {
+        let w = v.p() - self.p();
+        if w.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let w = w.normalize();
+
+        if self.is_infinite_light() {
+            // Return solid angle density for infinite light sources.
+            return infinite_light_density(ctx, &w);
+        }
+
+        // Return solid angle density for non-infinite light sources.
+        let key = match (self.light_source(), self.area_light()) {
+            (Some(light), _) => light_key(&light),
+            (None, Some(light)) => light_key(&light),
+            (None, None) => return 0.0,
+        };
+        let pdf_choice = ctx
+            .light_to_index
+            .get(&key)
+            .map_or(0.0, |index| ctx.light_distr.discrete_pdf(*index));
+        self.pdf_le(&w).map_or(0.0, |pdf| pdf.pdf_pos * pdf_choice)
+    }
+
+    /// Returns the densities of the light at the vertex emitting a ray in a
+    /// direction or `None` if there is no light at the vertex.
+    ///
+    /// * `w` - Direction of the ray.
+    fn pdf_le(&self, w: &Vector3f) -> Option<Pdf> {
+        let hit = self.hit();
+        let ray = Ray::new(hit.p, *w, INFINITY, hit.time, None);
+        let n = self.ng();
+        match (self.light_source(), self.area_light()) {
+            (Some(light), _) => Some(light.pdf_le(&ray, &n)),
+            (None, Some(light)) => Some(light.pdf_le(&ray, &n)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Extends a subpath by sampling the BSDFs at the surfaces it hits. Returns
+/// the number of vertices added.
+///
+/// * `ctx`       - Scene data used to evaluate path vertices.
+/// * `ray`       - Ray leaving the last vertex of the subpath.
+/// * `sampler`   - The sampler.
+/// * `beta`      - Path throughput of the subpath.
+/// * `pdf`       - Density of sampling the ray's direction with respect to
+///                 solid angle.
+/// * `max_depth` - Maximum number of vertices to add.
+/// * `mode`      - Transport mode.
+/// * `path`      - The subpath.
+#[allow(clippy::too_many_arguments)]
+fn random_walk<'a>(
+    ctx: &PathContext<'a>,
+    mut ray: Ray,
+    sampler: &mut ArcSampler,
+    mut beta: Spectrum,
+    pdf: Float,
+    max_depth: usize,
+    mode: TransportMode,
+    path: &mut Vec<Vertex<'a>>,
+) -> usize {
+    if max_depth == 0 {
+        return 0;
+    }
+
+    let scene: &'a Scene = ctx.scene;
+    let mut bounces = 0;
+    let mut pdf_fwd = pdf;
+    loop {
+        // Trace a ray to the next surface. Media aren't sampled so there are
+        // no medium vertices. Camera rays skip the geometry removed by clip
+        // shapes.
+        let hit_surface = if mode == TransportMode::Radiance && bounces == 0 {
+            scene.intersect_camera_ray(&mut ray)
+        } else {
+            scene.intersect(&mut ray)
+        };
+        if beta.is_black() {
+            break;
+        }
+
+        let mut isect = match hit_surface {
+            Some(isect) => isect,
+            None => {
+                // Capture escaped rays when tracing from the camera.
+                if mode == TransportMode::Radiance {
+                    let hit = Hit::new(
+                        ray.at(1.0),
+                        ray.time,
+                        Vector3f::default(),
+                        Vector3f::default(),
+                        Normal3f::from(-ray.d),
+                        None,
+                    );
+                    path.push(Vertex::light(hit, None, beta, pdf_fwd));
+                    bounces += 1;
+                }
+                break;
+            }
+        };
+
+        // Compute scattering functions for `mode` and skip over medium
+        // boundaries.
+        isect.compute_scattering_functions(&ray, true, mode);
+        let bsdf = match isect.bsdf.clone() {
+            Some(bsdf) => bsdf,
+            None => {
+                ray = isect.hit.spawn_ray(&ray.d);
+                continue;
+            }
+        };
+
+        // Sample BSDF at current vertex and compute reverse probability.
+        let beta_vertex = beta;
+        let pdf_vertex = pdf_fwd;
+        let mut delta = false;
+        let mut next = None;
+        bounces += 1;
+        if bounces < max_depth {
+            let wo = isect.hit.wo;
+            let u = Arc::get_mut(sampler).unwrap().get_2d();
+            let BxDFSample {
+                f,
+                pdf,
+                wi,
+                sampled_type,
+            } = bsdf.sample_f(&wo, &u, BxDFType::from(BSDF_ALL));
+            if !f.is_black() && pdf != 0.0 {
+                pdf_fwd = pdf;
+                beta *= f * wi.abs_dot(&isect.shading.n) / pdf;
+                let mut pdf_rev = bsdf.pdf(&wi, &wo, BxDFType::from(BSDF_ALL));
+                if sampled_type.matches(BSDF_SPECULAR) {
+                    delta = true;
+                    pdf_rev = 0.0;
+                    pdf_fwd = 0.0;
+                }
+                beta *= correct_shading_normal(&isect, &wo, &wi, mode);
+                next = Some((isect.hit.spawn_ray(&wi), pdf_rev));
+            }
+        }
+
+        let mut vertex = Vertex::surface(isect, beta_vertex, pdf_vertex, path.last().unwrap());
+        vertex.delta = delta;
+        path.push(vertex);
+
+        match next {
+            Some((next_ray, pdf_rev)) => {
+                ray = next_ray;
+                let n = path.len();
+                path[n - 2].pdf_rev = path[n - 1].convert_density(pdf_rev, &path[n - 2]);
+            }
+            None => break,
+        }
+    }
+    bounces
+}
+
+/// Returns the generalized geometric term between two vertices including the
+/// transmittance along the segment between them.
+///
+/// * `ctx`     - Scene data used to evaluate path vertices.
+/// * `v0`      - The first vertex.
+/// * `v1`      - The second vertex.
+/// * `sampler` - The sampler.
+fn g(ctx: &PathContext, v0: &Vertex, v1: &Vertex, sampler: &ArcSampler) -> Spectrum {
+    let d = v0.p() - v1.p();
+    let mut g = 1.0 / d.length_squared();
+    let d = d * g.sqrt();
+    if v0.is_on_surface() {
+        g *= v0.ns().abs_dot(&d);
+    }
+    if v1.is_on_surface() {
+        g *= v1.ns().abs_dot(&d);
+    }
+    let vis = VisibilityTester::new(v0.hit().clone(), v1.p());
+    vis.tr(Arc::clone(ctx.scene), Arc::clone(sampler)) * g
+}
+
+/// Returns the density with respect to solid angle of the infinite lights
+/// emitting light in a direction.
+///
+/// * `ctx` - Scene data used to evaluate path vertices.
+/// * `w`   - Direction the light travels in.
+fn infinite_light_density(ctx: &PathContext, w: &Vector3f) -> Float {
+    let hit = Hit::new(
+        Point3f::default(),
+        0.0,
+        Vector3f::default(),
+        Vector3f::default(),
+        Normal3f::zero(),
+        None,
+    );
+    ctx.scene
+        .infinite_lights
+        .iter()
+        .filter_map(|light| {
+            ctx.light_to_index
+                .get(&light_key(light))
+                .map(|index| light.pdf_li(&hit, &(-*w)) * ctx.light_distr.discrete_pdf(*index))
+        })
+        .sum()
+}
+
+/// Returns the factor that corrects the asymmetry of shading normals for
+/// light paths.
+///
+/// * `si`   - The surface interaction.
+/// * `wo`   - Outgoing direction.
+/// * `wi`   - Incident direction.
+/// * `mode` - Transport mode.
+fn correct_shading_normal(
+    si: &SurfaceInteraction,
+    wo: &Vector3f,
+    wi: &Vector3f,
+    mode: TransportMode,
+) -> Float {
+    if mode == TransportMode::Importance {
+        let num = wo.abs_dot(&si.shading.n) * wi.abs_dot(&si.hit.n);
+        let denom = wo.abs_dot(&si.hit.n) * wi.abs_dot(&si.shading.n);
+        // `wi` is in the plane of the surface; return 0 to avoid dividing by 0.
+        if denom == 0.0 {
+            0.0
+        } else {
+            num / denom
+        }
+    } else {
+        1.0
+    }
+}
+
+/// Returns the weight of the path made by connecting `s` light subpath
+/// vertices to `t` camera subpath vertices using the balance heuristic over
+/// all strategies that could have sampled the same path.
+///
+/// * `ctx`             - Scene data used to evaluate path vertices.
+/// * `light_vertices`  - The light subpath.
+/// * `camera_vertices` - The camera subpath.
+/// * `sampled`         - Vertex sampled again for `s = 1` or `t = 1`.
+/// * `s`               - Number of light subpath vertices.
+/// * `t`               - Number of camera subpath vertices.
+fn mis_weight(
+    ctx: &PathContext,
+    light_vertices: &[Vertex],
+    camera_vertices: &[Vertex],
+    sampled: Option<&Vertex>,
+    s: usize,
+    t: usize,
+) -> Float {
+    if s + t == 2 {
+        return 1.0;
+    }
+
+    // Use the sampled vertex in place of the subpath's endpoint for the
+    // `s = 1` or `t = 1` strategies.
+    let mut light: Vec<&Vertex> = light_vertices[..s].iter().collect();
+    let mut camera: Vec<&Vertex> = camera_vertices[..t].iter().collect();
+    if let Some(v) = sampled {
+        if s == 1 {
+            light[0] = v;
+        } else if t == 1 {
+            camera[0] = v;
+        }
+    }
+
+    // Reverse densities and delta flags of the connected path.
+    let mut light_rev: Vec<Float> = light.iter().map(|v| v.pdf_rev).collect();
+    let mut light_delta: Vec<bool> = light.iter().map(|v| v.delta).collect();
+    let mut camera_rev: Vec<Float> = camera.iter().map(|v| v.pdf_rev).collect();
+    let mut camera_delta: Vec<bool> = camera.iter().map(|v| v.delta).collect();
+
+    // Mark connection vertices as non-degenerate.
+    let qs = if s > 0 { Some(light[s - 1]) } else { None };
+    let pt = if t > 0 { Some(camera[t - 1]) } else { None };
+    let qs_minus = if s > 1 { Some(light[s - 2]) } else { None };
+    let pt_minus = if t > 1 { Some(camera[t - 2]) } else { None };
+    if t > 0 {
+        camera_delta[t - 1] = false;
+    }
+    if s > 0 {
+        light_delta[s - 1] = false;
+    }
+
+    // Update reverse density of vertices `pt` and `pt_minus`.
+    if let Some(pt) = pt {
+        camera_rev[t - 1] = match (qs, pt_minus) {
+            (Some(qs), _) => qs.pdf(ctx, qs_minus, pt),
+            (None, Some(pt_minus)) => pt.pdf_light_origin(ctx, pt_minus),
+            (None, None) => 0.0,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera_rev[t - 2] = match qs {
+                Some(qs) => pt.pdf(ctx, Some(qs), pt_minus),
+                None => pt.pdf_light(ctx, pt_minus),
+            };
+        }
+    }
+
+    // Update reverse density of vertices `qs` and `qs_minus`.
+    if let (Some(qs), Some(pt)) = (qs, pt) {
+        light_rev[s - 1] = pt.pdf(ctx, pt_minus, qs);
+        if let Some(qs_minus) = qs_minus {
+            light_rev[s - 2] = qs.pdf(ctx, Some(pt), qs_minus);
+        }
+    }
+
+    // Consider hypothetical connection strategies along the camera subpath.
+    let remap0 = |f: Float| if f != 0.0 { f } else { 1.0 };
+    let mut sum_ri = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap0(camera_rev[i]) / remap0(camera[i].pdf_fwd);
+        if !camera_delta[i] && !camera_delta[i - 1] {
+            sum_ri += ri;
+        }
+    }
+
+    // Consider hypothetical connection strategies along the light subpath.
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap0(light_rev[i]) / remap0(light[i].pdf_fwd);
+        let delta_light_vertex = if i > 0 {
+            light_delta[i - 1]
+        } else {
+            light[0].is_delta_light()
+        };
+        if !light_delta[i] && !delta_light_vertex {
+            sum_ri += ri;
+        }
+    }
+
+    1.0 / (1.0 + sum_ri)
+}
+
+/// Returns the key used to find the index of a light in the scene.
+///
+/// * `light` - The light.
+fn light_key<L: ?Sized>(light: &Arc<L>) -> usize {
+    Arc::as_ptr(light) as *const u8 as usize
+}
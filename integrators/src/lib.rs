@@ -3,7 +3,17 @@
 #[macro_use]
 extern crate log;
 
+mod bdpt;
+#[cfg(feature = "polarization")]
+mod polarized_whitted;
+mod preview;
+mod volpath;
 mod whitted;
 
 // Re-export.
+pub use bdpt::*;
+#[cfg(feature = "polarization")]
+pub use polarized_whitted::*;
+pub use preview::*;
+pub use volpath::*;
 pub use whitted::*;
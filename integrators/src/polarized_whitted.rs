@@ -0,0 +1,280 @@
+//! Polarized Whitted Integrator
+
+#![allow(dead_code)]
+
+use core::camera::*;
+use core::geometry::*;
+use core::integrator::*;
+use core::light::*;
+use core::material::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::polarization::*;
+use core::reflection::*;
+use core::sampler::*;
+use core::scene::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Quantity written to the image by `PolarizedWhittedIntegrator`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PolarizationOutput {
+    /// Intensity of the light passing the camera's polarizer.
+    Intensity,
+
+    /// Fraction of the light that is polarized.
+    DegreeOfPolarization,
+}
+
+/// Implements Whitted's ray tracing algorithm carrying radiance as Stokes
+/// vectors so that Fresnel reflection and refraction polarize light. Light
+/// sources emit unpolarized light and a linear polarizing filter can be
+/// placed in front of the camera.
+pub struct PolarizedWhittedIntegrator {
+    /// Common data for sampler integrators.
+    pub data: SamplerIntegratorData,
+
+    /// Angle in radians of the camera polarizer's transmission axis from the
+    /// horizontal axis of the image or `None` for no polarizer.
+    pub polarizer: Option<Float>,
+
+    /// Quantity written to the image.
+    pub output: PolarizationOutput,
+}
+
+impl PolarizedWhittedIntegrator {
+    /// Create a new `PolarizedWhittedIntegrator`.
+    ///
+    /// * `max_depths`   - Maximum recursion depths.
+    /// * `camera`       - The camera.
+    /// * `sampler`      - The sampler.
+    /// * `pixel_bounds` - Pixel bounds for the image.
+    /// * `polarizer`    - Angle in radians of the camera polarizer's
+    ///                    transmission axis from the horizontal axis of the
+    ///                    image or `None` for no polarizer.
+    /// * `output`       - Quantity written to the image.
+    pub fn new(
+        max_depths: MaxDepths,
+        camera: ArcCamera,
+        sampler: ArcSampler,
+        pixel_bounds: Bounds2i,
+        polarizer: Option<Float>,
+        output: PolarizationOutput,
+    ) -> Self {
+        Self {
+            data: SamplerIntegratorData::new(max_depths, camera, sampler, pixel_bounds),
+            polarizer,
+            output,
+        }
+    }
+
+    /// Returns the Stokes vector of light arriving at the origin of a ray and
+    /// the x-axis of its frame. The light propagates along `-ray.d`.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `depth`   - The recursion depth.
+    fn li_stokes(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        depth: usize,
+    ) -> (Stokes, Vector3f) {
+        // Find closest ray intersection or return background radiance.
+        let hit_surface = if depth == 0 {
+            scene.intersect_camera_ray(ray)
+        } else {
+            scene.intersect(ray)
+        };
+        let mut isect = match hit_surface {
+            Some(isect) => isect,
+            None => {
+                let mut l = Spectrum::new(0.0);
+                for light in scene.lights.iter() {
+                    if light.visibility().emission_visible(depth) {
+                        l += light.le(ray);
+                    }
+                }
+                return (Stokes::unpolarized(l), any_axis(&ray.d));
+            }
+        };
+
+        // Compute scattering functions for surface interaction.
+        isect.compute_scattering_functions(ray, false, TransportMode::Radiance);
+        let bsdf = match isect.bsdf.clone() {
+            Some(bsdf) => bsdf,
+            None => {
+                let mut new_ray = isect.hit.spawn_ray(&ray.d);
+                return self.li_stokes(&mut new_ray, scene, sampler, depth);
+            }
+        };
+        let n = isect.shading.n;
+        let wo = isect.hit.wo;
+
+        // The Mueller matrices give results in the frame of the outgoing
+        // direction.
+        let x_out = bsdf.polarization_axis(&wo);
+
+        // Compute emitted light if ray hit an area light source.
+        let mut l = Stokes::unpolarized(isect.le_at_depth(&wo, depth));
+
+        // Add contribution of each light source linked to the surface.
+        let non_specular = BxDFType::from(BSDF_ALL & !BSDF_SPECULAR);
+        for (j, light) in scene.lights.iter().enumerate() {
+            let sample = Arc::get_mut(sampler).unwrap().get_2d();
+            if !light.visibility().illumination || !scene.light_illuminates(j, isect.primitive) {
+                continue;
+            }
+            let Li {
+                wi,
+                pdf,
+                visibility,
+                value: li,
+                ..
+            } = light.sample_li(&isect.hit, &sample);
+
+            if li.is_black() || pdf == 0.0 {
+                continue;
+            }
+
+            let m = bsdf.mueller(&wo, &wi, non_specular);
+            let unoccluded =
+                visibility.map_or(true, |vis| vis.for_light(j).unoccluded(scene.clone()));
+            if !m.is_black() && unoccluded {
+                l += m.apply(&Stokes::unpolarized(li)) * (wi.abs_dot(&n) / pdf);
+            }
+        }
+
+        // Trace rays for specular reflection and refraction.
+        let max_depths = self.data.max_depths;
+        for &flags in [BSDF_REFLECTION, BSDF_TRANSMISSION].iter() {
+            let bxdf_type = BxDFType::from(flags | BSDF_SPECULAR);
+            if !max_depths.allows(bxdf_type, depth) {
+                continue;
+            }
+
+            let sample = Arc::get_mut(sampler).unwrap().get_2d();
+            let BxDFSample { f, pdf, wi, .. } = bsdf.sample_f(&wo, &sample, bxdf_type);
+            if pdf == 0.0 || f.is_black() || wi.abs_dot(&n) == 0.0 {
+                continue;
+            }
+
+            // Bring the incident light into the frame of the Mueller matrix.
+            let mut rd = isect.hit.spawn_ray(&wi);
+            let (li, x_in) = self.li_stokes(&mut rd, Arc::clone(&scene), sampler, depth + 1);
+            let m = bsdf.mueller(&wo, &wi, bxdf_type)
+                * rotate_frame(&x_in, &bsdf.polarization_axis(&wi), &-wi);
+            l += m.apply(&li) * (wi.abs_dot(&n) / pdf);
+        }
+
+        (l, x_out)
+    }
+}
+
+impl SamplerIntegrator for PolarizedWhittedIntegrator {
+    /// Returns the common data.
+    fn get_data(&self) -> &SamplerIntegratorData {
+        &self.data
+    }
+}
+
+impl Integrator for PolarizedWhittedIntegrator {
+    /// Render the scene.
+    ///
+    /// * `scene` - The scene.
+    fn render(&mut self, scene: Arc<Scene>) {
+        SamplerIntegrator::render(self, scene);
+    }
+
+    /// Returns the incident radiance at the origin of a given ray after the
+    /// camera's polarizer.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `depth`   - The recursion depth.
+    fn li(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        depth: usize,
+    ) -> Spectrum {
+        let (mut l, x) = self.li_stokes(ray, scene, sampler, depth);
+
+        if let Some(angle) = self.polarizer {
+            // The horizontal axis of the image is the direction of the ray
+            // differential in x.
+            let d = -ray.d;
+            let horizontal = ray
+                .differentials
+                .map(|rd| rd.rx_direction - ray.d)
+                .map(|h| h - ray.d * (h.dot(&ray.d) / ray.d.length_squared()))
+                .filter(|h| h.length_squared() > 0.0)
+                .map_or_else(|| any_axis(&d), |h| h.normalize());
+            let m = Mueller::linear_polarizer(angle) * rotate_frame(&x, &horizontal, &d);
+            l = m.apply(&l);
+        }
+
+        match self.output {
+            PolarizationOutput::Intensity => l.intensity(),
+            PolarizationOutput::DegreeOfPolarization => l.degree_of_polarization(),
+        }
+    }
+}
+
+impl From<(&ParamSet, ArcSampler, ArcCamera)> for PolarizedWhittedIntegrator {
+    /// Create a `PolarizedWhittedIntegrator` from given parameter set and
+    /// camera.
+    ///
+    /// * `p` - A tuple containing parameter set and camera.
+    fn from(p: (&ParamSet, ArcSampler, ArcCamera)) -> Self {
+        let (params, sampler, camera) = p;
+
+        let max_depths = MaxDepths::from(params);
+
+        let pb = params.find_int("pixelbounds");
+        let np = pb.len();
+
+        let mut pixel_bounds = camera.get_film_sample_bounds();
+        if np > 0 {
+            if np != 4 {
+                error!("Expected 4 values for 'pixel_bounds' parameter. Got {}", np);
+            } else {
+                pixel_bounds = pixel_bounds.intersect(&Bounds2i::new(
+                    Point2i::new(pb[0], pb[2]),
+                    Point2i::new(pb[1], pb[3]),
+                ));
+                if pixel_bounds.area() == 0 {
+                    error!("Degenerate 'pixel_bounds' specified.");
+                }
+            }
+        }
+
+        // The polarizer angle is given in degrees.
+        let polarizer = params.find_float("polarizer").first().map(|a| a.to_radians());
+
+        let output = params.find_one_string("output", String::from("intensity"));
+        let output = match output.as_str() {
+            "intensity" => PolarizationOutput::Intensity,
+            "dop" => PolarizationOutput::DegreeOfPolarization,
+            _ => {
+                error!("Unknown polarization output '{}'. Using 'intensity'.", output);
+                PolarizationOutput::Intensity
+            }
+        };
+
+        let mut integrator = Self::new(
+            max_depths,
+            Arc::clone(&camera),
+            Arc::clone(&sampler),
+            pixel_bounds,
+            polarizer,
+            output,
+        );
+        integrator.data.adaptive_sampling = AdaptiveSampling::from(params);
+        integrator
+    }
+}
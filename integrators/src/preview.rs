@@ -0,0 +1,158 @@
+//! Preview Integrator
+
+#![allow(dead_code)]
+
+use core::camera::*;
+use core::geometry::*;
+use core::integrator::*;
+use core::material::*;
+use core::paramset::*;
+use core::sampler::*;
+use core::scene::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Implements a fast first-bounce integrator for interactive feedback. It
+/// computes emitted light and direct lighting from one light chosen with the
+/// light sampling strategy at the first intersection and the environment for
+/// rays that escape. No indirect lighting is computed.
+pub struct PreviewIntegrator {
+    /// Common data for sampler integrators.
+    pub data: SamplerIntegratorData,
+
+    /// Light sampling strategy.
+    light_sample_strategy: String,
+
+    /// Light sampling distribution computed before rendering.
+    light_distribution: Option<ArcLightDistribution>,
+}
+
+impl PreviewIntegrator {
+    /// Create a new `PreviewIntegrator`.
+    ///
+    /// * `camera`                - The camera.
+    /// * `sampler`               - The sampler.
+    /// * `pixel_bounds`          - Pixel bounds for the image.
+    /// * `light_sample_strategy` - Light sampling strategy.
+    pub fn new(
+        camera: ArcCamera,
+        sampler: ArcSampler,
+        pixel_bounds: Bounds2i,
+        light_sample_strategy: &str,
+    ) -> Self {
+        Self {
+            data: SamplerIntegratorData::new(MaxDepths::new(1), camera, sampler, pixel_bounds),
+            light_sample_strategy: String::from(light_sample_strategy),
+            light_distribution: None,
+        }
+    }
+}
+
+impl SamplerIntegrator for PreviewIntegrator {
+    /// Returns the common data.
+    fn get_data(&self) -> &SamplerIntegratorData {
+        &self.data
+    }
+}
+
+impl Integrator for PreviewIntegrator {
+    /// Render the scene.
+    ///
+    /// * `scene` - The scene.
+    fn render(&mut self, scene: Arc<Scene>) {
+        self.light_distribution =
+            create_light_sample_distribution(&self.light_sample_strategy, Arc::clone(&scene));
+        SamplerIntegrator::render(self, scene);
+    }
+
+    /// Returns the incident radiance at the origin of a given ray.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `depth`   - The recursion depth.
+    fn li(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        _depth: usize,
+    ) -> Spectrum {
+        let mut l = Spectrum::new(0.0);
+
+        // Find closest ray intersection or return environment radiance. Skip
+        // over surfaces without a BSDF such as medium boundaries and the
+        // geometry removed by clip shapes.
+        let mut ray = ray.clone();
+        loop {
+            if let Some(mut isect) = scene.intersect_camera_ray(&mut ray) {
+                isect.compute_scattering_functions(&ray, false, TransportMode::Radiance);
+                if isect.bsdf.is_none() {
+                    ray = isect.hit.spawn_ray(&ray.d);
+                    continue;
+                }
+
+                // Compute emitted light if ray hit an area light source.
+                let wo = isect.hit.wo;
+                l += isect.le_at_depth(&wo, 0);
+
+                // Sample direct lighting from one light.
+                let it = Interaction::Surface { si: isect };
+                l += sample_one_light(
+                    &it,
+                    Arc::clone(&scene),
+                    sampler,
+                    false,
+                    self.light_distribution.as_ref(),
+                );
+            } else {
+                for light in scene.lights.iter() {
+                    if light.visibility().emission_visible(0) {
+                        l += light.le(&ray);
+                    }
+                }
+            }
+
+            return l;
+        }
+    }
+}
+
+impl From<(&ParamSet, ArcSampler, ArcCamera)> for PreviewIntegrator {
+    /// Create a `PreviewIntegrator` from given parameter set and camera.
+    ///
+    /// * `p` - A tuple containing parameter set and camera.
+    fn from(p: (&ParamSet, ArcSampler, ArcCamera)) -> Self {
+        let (params, sampler, camera) = p;
+
+        let pb = params.find_int("pixelbounds");
+        let np = pb.len();
+
+        let mut pixel_bounds = camera.get_film_sample_bounds();
+        if np > 0 {
+            if np != 4 {
+                error!("Expected 4 values for 'pixel_bounds' parameter. Got {}", np);
+            } else {
+                pixel_bounds = pixel_bounds.intersect(&Bounds2i::new(
+                    Point2i::new(pb[0], pb[2]),
+                    Point2i::new(pb[1], pb[3]),
+                ));
+                if pixel_bounds.area() == 0 {
+                    error!("Degenerate 'pixel_bounds' specified.");
+                }
+            }
+        }
+
+        let light_sample_strategy =
+            params.find_one_string("lightsamplestrategy", String::from("spatial"));
+
+        let mut integrator = Self::new(
+            Arc::clone(&camera),
+            Arc::clone(&sampler),
+            pixel_bounds,
+            &light_sample_strategy,
+        );
+        integrator.data.adaptive_sampling = AdaptiveSampling::from(params);
+        integrator
+    }
+}
@@ -0,0 +1,423 @@
+//! Volumetric Path Tracing Integrator
+
+#![allow(dead_code)]
+
+use core::camera::*;
+use core::geometry::*;
+use core::integrator::*;
+use core::material::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::sampler::*;
+use core::scene::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+core::stat_int_distribution!("Integrator/Path length", PATH_LENGTH);
+
+/// Implements path tracing with participating media. Rays track the medium
+/// they travel through using the medium interfaces of the surfaces they
+/// cross. Distance sampling in the medium decides whether light is scattered
+/// at a point in the medium or at the next surface and the phase function is
+/// sampled to continue paths scattered by the medium.
+pub struct VolPathIntegrator {
+    /// Common data for sampler integrators.
+    pub data: SamplerIntegratorData,
+
+    /// Russian roulette terminates paths whose throughput falls below this.
+    rr_threshold: Float,
+
+    /// Light sampling strategy.
+    light_sample_strategy: String,
+
+    /// Light sampling distribution computed before rendering.
+    light_distribution: Option<ArcLightDistribution>,
+}
+
+impl VolPathIntegrator {
+    /// Create a new `VolPathIntegrator`.
+    ///
+    /// * `max_depths`            - Maximum recursion depths.
+    /// * `camera`                - The camera.
+    /// * `sampler`               - The sampler.
+    /// * `pixel_bounds`          - Pixel bounds for the image.
+    /// * `rr_threshold`          - Russian roulette threshold.
+    /// * `light_sample_strategy` - Light sampling strategy.
+    pub fn new(
+        max_depths: MaxDepths,
+        camera: ArcCamera,
+        sampler: ArcSampler,
+        pixel_bounds: Bounds2i,
+        rr_threshold: Float,
+        light_sample_strategy: &str,
+    ) -> Self {
+        Self {
+            data: SamplerIntegratorData::new(max_depths, camera, sampler, pixel_bounds),
+            rr_threshold,
+            light_sample_strategy: String::from(light_sample_strategy),
+            light_distribution: None,
+        }
+    }
+
+    /// Returns the direct lighting scattered at an interaction along a path
+    /// and records it with the lobe that scattered it if `paths` is given.
+    ///
+    /// * `it`      - The interaction.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `beta`    - Throughput of the path up to the interaction.
+    /// * `paths`   - Records the contributions and their paths.
+    fn direct_lighting(
+        &self,
+        it: &Interaction,
+        scene: &Arc<Scene>,
+        sampler: &mut ArcSampler,
+        beta: Spectrum,
+        paths: Option<&mut LightPathRecorder>,
+    ) -> Spectrum {
+        let light_distribution = self.light_distribution.as_ref();
+        match (paths, it) {
+            (Some(paths), Interaction::Surface { .. }) => {
+                // Estimate each lobe separately so the lighting can be told
+                // apart by light path expressions.
+                let mut l = Spectrum::new(0.0);
+                for event in DIRECT_LIGHTING_EVENTS.iter() {
+                    let bsdf_flags = event.bxdf_type().unwrap();
+                    let ld = beta
+                        * sample_one_light_lobes(
+                            it,
+                            Arc::clone(scene),
+                            sampler,
+                            true,
+                            light_distribution,
+                            bsdf_flags,
+                        );
+                    paths.record_scattered(*event, ld);
+                    l += ld;
+                }
+                l
+            }
+            (paths, _) => {
+                let ld = beta
+                    * sample_one_light(it, Arc::clone(scene), sampler, true, light_distribution);
+                if let Some(paths) = paths {
+                    paths.record_scattered(PathEvent::Volume, ld);
+                }
+                ld
+            }
+        }
+    }
+
+    /// Returns the incident radiance at the origin of a camera ray and records
+    /// the paths it arrived along if `paths` is given. Events are recorded
+    /// with unit weight because contributions already include the path
+    /// throughput.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `paths`   - Records the contributions and their paths.
+    fn trace(
+        &self,
+        ray: &Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        mut paths: Option<&mut LightPathRecorder>,
+    ) -> Spectrum {
+        let mut l = Spectrum::new(0.0);
+        let mut beta = Spectrum::new(1.0);
+        let mut ray = ray.clone();
+        let mut specular_bounce = false;
+        let mut bounces = 0;
+        let max_depths = self.data.max_depths;
+
+        // Tracks the accumulated effect of radiance scaling due to rays
+        // passing through refractive boundaries.
+        let mut eta_scale = 1.0;
+
+        loop {
+            // Intersect `ray` with scene. Camera rays skip the geometry
+            // removed by clip shapes.
+            let hit_surface = if bounces == 0 {
+                scene.intersect_camera_ray(&mut ray)
+            } else {
+                scene.intersect(&mut ray)
+            };
+
+            // Sample the participating medium, if present.
+            let mut medium_interaction = None;
+            if let Some(medium) = ray.medium.clone() {
+                let (tr, mi) = medium.sample(&ray, sampler);
+                beta *= tr;
+                medium_interaction = mi;
+            }
+            if beta.is_black() {
+                break;
+            }
+
+            if let Some(mi) = medium_interaction {
+                // Handle scattering at point in medium for volumetric path
+                // tracer.
+                if bounces >= max_depths.total {
+                    break;
+                }
+
+                let it = Interaction::Medium { mi: mi.clone() };
+                l += self.direct_lighting(&it, &scene, sampler, beta, paths.as_deref_mut());
+
+                // Sample the phase function to continue the path. The phase
+                // function is sampled exactly so `beta` is unchanged.
+                let u = Arc::get_mut(sampler).unwrap().get_2d();
+                let (_p, wi) = mi.phase.sample_p(&mi.hit.wo, &u);
+                ray = mi.hit.spawn_ray(&wi);
+                specular_bounce = false;
+                if let Some(paths) = paths.as_deref_mut() {
+                    paths.push(PathEvent::Volume, Spectrum::new(1.0));
+                }
+            } else {
+                // Handle scattering at point on surface for volumetric path
+                // tracer.
+
+                // Possibly add emitted light at intersection.
+                if bounces == 0 || specular_bounce {
+                    match hit_surface.as_ref() {
+                        Some(isect) => {
+                            let le = beta * isect.le_at_depth(&(-ray.d), bounces);
+                            if let Some(paths) = paths.as_deref_mut() {
+                                paths.record(le);
+                            }
+                            l += le;
+                        }
+                        None => {
+                            for light in scene.infinite_lights.iter() {
+                                if light.visibility().emission_visible(bounces) {
+                                    let le = beta * light.le(&ray);
+                                    if let Some(paths) = paths.as_deref_mut() {
+                                        paths.record(le);
+                                    }
+                                    l += le;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Terminate path if ray escaped or `max_depth` was reached.
+                let mut isect = match hit_surface {
+                    Some(isect) if bounces < max_depths.total => isect,
+                    _ => break,
+                };
+
+                // Compute scattering functions and skip over medium
+                // boundaries.
+                isect.compute_scattering_functions(&ray, true, TransportMode::Radiance);
+                let bsdf = match isect.bsdf.clone() {
+                    Some(bsdf) => bsdf,
+                    None => {
+                        ray = isect.hit.spawn_ray(&ray.d);
+                        continue;
+                    }
+                };
+
+                // Sample illumination from lights to find attenuated path
+                // contribution.
+                let wo = isect.hit.wo;
+                let n = isect.hit.n;
+                let ns = isect.shading.n;
+                let spawn = isect.hit.clone();
+                let bssrdf = isect.bssrdf.clone();
+                if bsdf.num_components(BxDFType::from(BSDF_ALL & !BSDF_SPECULAR)) > 0 {
+                    let it = Interaction::Surface { si: isect };
+                    l += self.direct_lighting(&it, &scene, sampler, beta, paths.as_deref_mut());
+                }
+
+                // Sample BSDF to get new path direction.
+                let u = Arc::get_mut(sampler).unwrap().get_2d();
+                let BxDFSample {
+                    f,
+                    pdf,
+                    wi,
+                    sampled_type,
+                } = bsdf.sample_f(&wo, &u, BxDFType::from(BSDF_ALL));
+                if f.is_black() || pdf == 0.0 || !max_depths.scatters(sampled_type, bounces) {
+                    break;
+                }
+                beta *= f * wi.abs_dot(&ns) / pdf;
+                specular_bounce = sampled_type.matches(BSDF_SPECULAR);
+                if let Some(paths) = paths.as_deref_mut() {
+                    paths.push(PathEvent::scatter(sampled_type), Spectrum::new(1.0));
+                }
+                if specular_bounce && sampled_type.matches(BSDF_TRANSMISSION) {
+                    let eta = bsdf.eta;
+                    // Update the term that tracks radiance scaling for
+                    // refraction depending on whether the ray is entering or
+                    // leaving a medium.
+                    eta_scale *= if wo.dot(&n) > 0.0 {
+                        eta * eta
+                    } else {
+                        1.0 / (eta * eta)
+                    };
+                }
+
+                match bssrdf {
+                    Some(bssrdf) if sampled_type.matches(BSDF_TRANSMISSION) => {
+                        // Account for subsurface scattering by sampling the
+                        // point where light entered the surface.
+                        let sampler_mut = Arc::get_mut(sampler).unwrap();
+                        let u1 = sampler_mut.get_1d();
+                        let u2 = sampler_mut.get_2d();
+                        let (s, pi, pdf) = bssrdf.sample_s(&scene, u1, &u2);
+                        let pi = match pi {
+                            Some(pi) if !s.is_black() && pdf > 0.0 => pi,
+                            _ => break,
+                        };
+                        beta *= s / pdf;
+
+                        // Account for the direct subsurface scattering
+                        // component.
+                        let pi_bsdf = match pi.bsdf.clone() {
+                            Some(bsdf) => bsdf,
+                            None => break,
+                        };
+                        let pi_hit = pi.hit.clone();
+                        let pi_ns = pi.shading.n;
+                        let it = Interaction::Surface { si: pi };
+                        l += self.direct_lighting(&it, &scene, sampler, beta, paths.as_deref_mut());
+
+                        // Account for the indirect subsurface scattering
+                        // component.
+                        let u = Arc::get_mut(sampler).unwrap().get_2d();
+                        let BxDFSample {
+                            f,
+                            pdf,
+                            wi,
+                            sampled_type,
+                        } = pi_bsdf.sample_f(&pi_hit.wo, &u, BxDFType::from(BSDF_ALL));
+                        if f.is_black() || pdf == 0.0 {
+                            break;
+                        }
+                        beta *= f * wi.abs_dot(&pi_ns) / pdf;
+                        specular_bounce = sampled_type.matches(BSDF_SPECULAR);
+                        if let Some(paths) = paths.as_deref_mut() {
+                            paths.push(PathEvent::scatter(sampled_type), Spectrum::new(1.0));
+                        }
+                        ray = pi_hit.spawn_ray(&wi);
+                    }
+                    _ => ray = spawn.spawn_ray(&wi),
+                }
+            }
+
+            // Possibly terminate the path with Russian roulette. Factor out
+            // radiance scaling due to refraction in `rr_beta`.
+            let rr_beta = beta * eta_scale;
+            if rr_beta.max_component_value() < self.rr_threshold && bounces > 3 {
+                let q = max(0.05, 1.0 - rr_beta.max_component_value());
+                if Arc::get_mut(sampler).unwrap().get_1d() < q {
+                    break;
+                }
+                beta /= 1.0 - q;
+            }
+
+            bounces += 1;
+        }
+        PATH_LENGTH.record(bounces as u64);
+
+        l
+    }
+}
+
+impl SamplerIntegrator for VolPathIntegrator {
+    /// Returns the common data.
+    fn get_data(&self) -> &SamplerIntegratorData {
+        &self.data
+    }
+
+    /// Returns the incident radiance at the origin of a camera ray and records
+    /// the paths it arrived along for light path expression AOVs.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `paths`   - Records the contributions and their paths.
+    fn li_paths(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        paths: &mut LightPathRecorder,
+    ) -> Spectrum {
+        self.trace(ray, scene, sampler, Some(paths))
+    }
+}
+
+impl Integrator for VolPathIntegrator {
+    /// Render the scene.
+    ///
+    /// * `scene` - The scene.
+    fn render(&mut self, scene: Arc<Scene>) {
+        self.light_distribution =
+            create_light_sample_distribution(&self.light_sample_strategy, Arc::clone(&scene));
+        SamplerIntegrator::render(self, scene);
+    }
+
+    /// Returns the incident radiance at the origin of a given ray.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `_depth`  - The recursion depth.
+    fn li(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        _depth: usize,
+    ) -> Spectrum {
+        self.trace(ray, scene, sampler, None)
+    }
+}
+
+impl From<(&ParamSet, ArcSampler, ArcCamera)> for VolPathIntegrator {
+    /// Create a `VolPathIntegrator` from given parameter set and camera.
+    ///
+    /// * `p` - A tuple containing parameter set and camera.
+    fn from(p: (&ParamSet, ArcSampler, ArcCamera)) -> Self {
+        let (params, sampler, camera) = p;
+
+        let max_depths = MaxDepths::from(params);
+
+        let pb = params.find_int("pixelbounds");
+        let np = pb.len();
+
+        let mut pixel_bounds = camera.get_film_sample_bounds();
+        if np > 0 {
+            if np != 4 {
+                error!("Expected 4 values for 'pixel_bounds' parameter. Got {}", np);
+            } else {
+                pixel_bounds = pixel_bounds.intersect(&Bounds2i::new(
+                    Point2i::new(pb[0], pb[2]),
+                    Point2i::new(pb[1], pb[3]),
+                ));
+                if pixel_bounds.area() == 0 {
+                    error!("Degenerate 'pixel_bounds' specified.");
+                }
+            }
+        }
+
+        let rr_threshold = params.find_one_float("rrthreshold", 1.0);
+        let light_sample_strategy =
+            params.find_one_string("lightsamplestrategy", String::from("spatial"));
+
+        let mut integrator = Self::new(
+            max_depths,
+            Arc::clone(&camera),
+            Arc::clone(&sampler),
+            pixel_bounds,
+            rr_threshold,
+            &light_sample_strategy,
+        );
+        integrator.data.adaptive_sampling = AdaptiveSampling::from(params);
+        integrator
+    }
+}
@@ -23,105 +23,147 @@ pub struct WhittedIntegrator {
 impl WhittedIntegrator {
     /// Create a new `WhittedIntegrator`.
     ///
-    /// * `max_depth`    - Maximum recursion depth.
+    /// * `max_depths`   - Maximum recursion depths.
     /// * `camera`       - The camera.
     /// * `sampler`      - The sampler.
     /// * `pixel_bounds` - Pixel bounds for the image.
     pub fn new(
-        max_depth: usize,
+        max_depths: MaxDepths,
         camera: ArcCamera,
         sampler: ArcSampler,
         pixel_bounds: Bounds2i,
     ) -> Self {
         Self {
-            data: SamplerIntegratorData::new(max_depth, camera, sampler, pixel_bounds)
+            data: SamplerIntegratorData::new(max_depths, camera, sampler, pixel_bounds)
         }
     }
-}
-
-impl SamplerIntegrator for WhittedIntegrator {
-   /// Returns the common data.
-    fn get_data(&self) -> &SamplerIntegratorData {
-        &self.data
-    }
-}
-
-impl Integrator for WhittedIntegrator {
-    /// Render the scene.
-    ///
-    /// * `scene` - The scene.
-    fn render(&mut self, scene: Arc<Scene>) {
-        SamplerIntegrator::render(self, scene);
-    }
 
-    /// Returns the incident radiance at the origin of a given ray.
+    /// Returns the incident radiance at the origin of a given ray and records
+    /// the paths it arrived along if `paths` is given.
     ///
     /// * `ray`     - The ray.
     /// * `scene`   - The scene.
     /// * `sampler` - The sampler.
     /// * `depth`   - The recursion depth.
-    fn li(
+    /// * `paths`   - Records the contributions and their paths.
+    fn trace(
         &self,
         ray: &mut Ray,
         scene: Arc<Scene>,
         sampler: &mut ArcSampler,
         depth: usize,
+        mut paths: Option<&mut LightPathRecorder>,
     ) -> Spectrum {
         let mut l = Spectrum::new(0.0);
 
-        // Find closest ray intersection or return background radiance.
-        if let Some(mut isect) = scene.intersect(ray) {
+        // Find closest ray intersection or return background radiance. Camera
+        // rays skip the geometry removed by clip shapes.
+        let hit_surface = if depth == 0 {
+            scene.intersect_camera_ray(ray)
+        } else {
+            scene.intersect(ray)
+        };
+        if let Some(mut isect) = hit_surface {
             // Compute emitted and reflected light at ray intersection point.
 
-            // Initialize common variables for Whitted integrator.
-            let n = isect.shading.n;
-            let wo = isect.hit.wo;
-
             // Compute scattering functions for surface interaction.
             isect.compute_scattering_functions(ray, false, TransportMode::Radiance);
             if isect.bsdf.is_none() {
                 let mut new_ray = isect.hit.spawn_ray(&ray.d);
-                return self.li(&mut new_ray, scene.clone(), sampler, depth);
+                return self.trace(&mut new_ray, scene.clone(), sampler, depth, paths);
             }
 
+            // Initialize common variables for Whitted integrator. The shading
+            // normal may have been perturbed by the material.
+            let n = isect.shading.n;
+            let wo = isect.hit.wo;
+
             // Compute emitted light if ray hit an area light source.
-            l += isect.le(&wo);
+            let le = isect.le_at_depth(&wo, depth);
+            if let Some(paths) = paths.as_deref_mut() {
+                paths.record(le);
+            }
+            l += le;
 
-            // Add contribution of each light source.
-            for light in scene.lights.iter() {
+            // Add contribution of each light source linked to the surface.
+            let bsdf = isect.bsdf.as_ref().unwrap();
+            for (j, light) in scene.lights.iter().enumerate() {
                 let sample = Arc::get_mut(sampler).unwrap().get_2d();
+                if !light.visibility().illumination || !scene.light_illuminates(j, isect.primitive)
+                {
+                    continue;
+                }
                 let Li {
                     wi,
                     pdf,
                     visibility,
                     value: li,
+                    ..
                 } = light.sample_li(&isect.hit, &sample);
 
                 if li.is_black() || pdf == 0.0 {
                     continue;
                 }
 
-                let f = isect
-                    .bsdf
-                    .as_ref()
-                    .unwrap()
-                    .f(&wo, &wi, BxDFType::from(BSDF_ALL));
+                let f = bsdf.scatter(&wo, &wi, &li, BxDFType::from(BSDF_ALL));
 
                 // If no visiblity tester, then unoccluded = true.
-                let unoccluded = visibility.map_or(true, |vis| vis.unoccluded(scene.clone()));
+                let unoccluded =
+                    visibility.map_or(true, |vis| vis.for_light(j).unoccluded(scene.clone()));
                 if !f.is_black() && unoccluded {
-                    l += f * li * wi.abs_dot(&n) / pdf;
+                    l += f * wi.abs_dot(&n) / pdf;
+
+                    // Record the light scattered by each lobe separately.
+                    if let Some(paths) = paths.as_deref_mut() {
+                        for event in DIRECT_LIGHTING_EVENTS.iter() {
+                            let bxdf_type = event.bxdf_type().unwrap();
+                            let f = bsdf.scatter(&wo, &wi, &li, bxdf_type);
+                            paths.record_scattered(*event, f * wi.abs_dot(&n) / pdf);
+                        }
+                    }
                 }
             }
-            if depth + 1 < self.data.max_depth {
-                // Trace rays for specular reflection and refraction.
-                l += self.specular_reflect(ray, &isect, Arc::clone(&scene), sampler, depth);
-                l += self.specular_transmit(ray, &isect, Arc::clone(&scene), sampler, depth);
+
+            // Trace rays for specular reflection and refraction.
+            let max_depths = self.data.max_depths;
+            for transmission in [false, true].iter().copied() {
+                let event = PathEvent::Scatter {
+                    transmission,
+                    lobe: Lobe::Specular,
+                };
+                if !max_depths.allows(event.bxdf_type().unwrap(), depth) {
+                    continue;
+                }
+                let specular_ray = if transmission {
+                    self.specular_transmit_ray(ray, &isect, sampler)
+                } else {
+                    self.specular_reflect_ray(ray, &isect, sampler)
+                };
+                if let Some((mut rd, weight)) = specular_ray {
+                    if let Some(paths) = paths.as_deref_mut() {
+                        paths.push(event, weight);
+                    }
+                    let li = self.trace(
+                        &mut rd,
+                        Arc::clone(&scene),
+                        sampler,
+                        depth + 1,
+                        paths.as_deref_mut(),
+                    );
+                    if let Some(paths) = paths.as_deref_mut() {
+                        paths.pop();
+                    }
+                    l += weight * li;
+                }
             }
         } else {
-            if let Some(rd) = ray.differentials {
-                for light in scene.lights.iter() {
-                    l += light.le(&rd);
+            for light in scene.lights.iter() {
+                if light.visibility().emission_visible(depth) {
+                    let le = light.le(ray);
+                    if let Some(paths) = paths.as_deref_mut() {
+                        paths.record(le);
+                    }
+                    l += le;
                 }
             }
         }
@@ -130,6 +172,55 @@ impl Integrator for WhittedIntegrator {
     }
 }
 
+impl SamplerIntegrator for WhittedIntegrator {
+   /// Returns the common data.
+    fn get_data(&self) -> &SamplerIntegratorData {
+        &self.data
+    }
+
+    /// Returns the incident radiance at the origin of a camera ray and records
+    /// the paths it arrived along for light path expression AOVs.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `paths`   - Records the contributions and their paths.
+    fn li_paths(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        paths: &mut LightPathRecorder,
+    ) -> Spectrum {
+        self.trace(ray, scene, sampler, 0, Some(paths))
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    /// Render the scene.
+    ///
+    /// * `scene` - The scene.
+    fn render(&mut self, scene: Arc<Scene>) {
+        SamplerIntegrator::render(self, scene);
+    }
+
+    /// Returns the incident radiance at the origin of a given ray.
+    ///
+    /// * `ray`     - The ray.
+    /// * `scene`   - The scene.
+    /// * `sampler` - The sampler.
+    /// * `depth`   - The recursion depth.
+    fn li(
+        &self,
+        ray: &mut Ray,
+        scene: Arc<Scene>,
+        sampler: &mut ArcSampler,
+        depth: usize,
+    ) -> Spectrum {
+        self.trace(ray, scene, sampler, depth, None)
+    }
+}
+
 impl From<(&ParamSet, ArcSampler, ArcCamera)> for WhittedIntegrator {
     /// Create a `WhittedIntegrator` from given parameter set and camera.
     ///
@@ -137,7 +228,7 @@ impl From<(&ParamSet, ArcSampler, ArcCamera)> for WhittedIntegrator {
     fn from(p: (&ParamSet, ArcSampler, ArcCamera)) -> Self {
         let (params, sampler, camera) = p;
 
-        let max_depth = params.find_one_int("max_depth", 5) as usize;
+        let max_depths = MaxDepths::from(params);
 
         let pb = params.find_int("pixelbounds");
         let np = pb.len();
@@ -148,8 +239,8 @@ impl From<(&ParamSet, ArcSampler, ArcCamera)> for WhittedIntegrator {
                 error!("Expected 4 values for 'pixel_bounds' parameter. Got {}", np);
             } else {
                 pixel_bounds = pixel_bounds.intersect(&Bounds2i::new(
-                    Point2i::new(pb[0], pb[1]),
-                    Point2i::new(pb[2], pb[3]),
+                    Point2i::new(pb[0], pb[2]),
+                    Point2i::new(pb[1], pb[3]),
                 ));
                 if pixel_bounds.area() == 0 {
                     error!("Degenerate 'pixel_bounds' specified.");
@@ -157,12 +248,13 @@ impl From<(&ParamSet, ArcSampler, ArcCamera)> for WhittedIntegrator {
             }
         }
 
-        Self::new(
-            max_depth,
+        let mut integrator = Self::new(
+            max_depths,
             Arc::clone(&camera),
             Arc::clone(&sampler),
             pixel_bounds,
-        )
+        );
+        integrator.data.adaptive_sampling = AdaptiveSampling::from(params);
+        integrator
     }
 }
-
@@ -41,6 +41,12 @@ pub struct DiffuseAreaLight {
 
     /// Indicates whether light source 2-sided.
     pub two_sided: bool,
+
+    /// Visibility to camera rays and illumination.
+    pub visibility: LightVisibility,
+
+    /// Light samples closer than this to the reference point are discarded.
+    pub min_sample_distance: Float,
 }
 
 impl DiffuseAreaLight {
@@ -74,18 +80,8 @@ impl DiffuseAreaLight {
             shape: Arc::clone(&shape),
             two_sided,
             area,
-        }
-    }
-
-    /// Returns emitted radiance based on `two_sided` flag.
-    ///
-    /// * `intr` - The interaction point.
-    /// * `w`    - Direction.
-    fn l(&self, intr: &Hit, w: &Vector3f) -> Spectrum {
-        if self.two_sided || intr.n.dot(w) > 0.0 {
-            self.l_emit
-        } else {
-            Spectrum::new(0.0)
+            visibility: LightVisibility::default(),
+            min_sample_distance: MIN_LIGHT_SAMPLE_DISTANCE,
         }
     }
 }
@@ -101,7 +97,9 @@ impl Light for DiffuseAreaLight {
     /// * `hit` - The interaction hit point.
     /// * `u`   - Sample value for Monte Carlo integration.
     fn sample_li(&self, hit: &Hit, u: &Point2f) -> Li {
-        let (mut p_shape_hit, pdf) = self.shape.sample_solid_angle(hit, u);
+        let (mut p_shape_hit, pdf) =
+            self.shape
+                .sample_solid_angle(hit, u, self.min_sample_distance);
         p_shape_hit.medium_interface = Some(self.medium_interface.clone());
 
         let wi = p_shape_hit.p - hit.p;
@@ -111,9 +109,10 @@ impl Light for DiffuseAreaLight {
             let value = Spectrum::new(0.0);
             Li::new(wi, pdf, visibility, value)
         } else {
+            let wi = wi.normalize();
             let visibility = Some(VisibilityTester::new(hit.clone(), p_shape_hit.p));
             let value = self.l(&p_shape_hit, &(-wi));
-            Li::new(wi, pdf, visibility, value)
+            Li::new(wi, pdf, visibility, value).with_light_normal(p_shape_hit.n)
         }
     }
 
@@ -132,7 +131,8 @@ impl Light for DiffuseAreaLight {
     /// * `hit` - The interaction hit point.
     /// * `wi`  - The incident direction.
     fn pdf_li(&self, hit: &Hit, wi: &Vector3f) -> Float {
-        self.shape.pdf_solid_angle(hit, wi)
+        self.shape
+            .pdf_solid_angle(hit, wi, self.min_sample_distance)
     }
 
     /// Returns a sampled light-carrying ray leaving the light source.
@@ -181,21 +181,74 @@ impl Light for DiffuseAreaLight {
     ///
     /// * `ray`     - The ray.
     /// * `n_light` - The normal.
-    fn pdf_le(&self, _ray: &Ray, _n_light: &Normal3f) -> Pdf {
-        Pdf::new(0.0, uniform_sphere_pdf())
+    fn pdf_le(&self, ray: &Ray, n_light: &Normal3f) -> Pdf {
+        let hit = Hit::new(
+            ray.o,
+            ray.time,
+            Vector3f::default(),
+            Vector3f::default(),
+            *n_light,
+            None,
+        );
+        let pdf_pos = self.shape.pdf(&hit);
+        let pdf_dir = if self.two_sided {
+            0.5 * cosine_hemisphere_pdf(n_light.abs_dot(&ray.d))
+        } else {
+            cosine_hemisphere_pdf(n_light.dot(&ray.d))
+        };
+        Pdf::new(pdf_pos, pdf_dir)
+    }
+
+    /// Returns whether the light is visible to camera rays and whether it
+    /// illuminates the scene.
+    fn visibility(&self) -> LightVisibility {
+        self.visibility
+    }
+}
+
+impl AreaLight for DiffuseAreaLight {
+    /// Returns emitted radiance based on `two_sided` flag.
+    ///
+    /// * `intr` - The interaction point.
+    /// * `w`    - Direction.
+    fn l(&self, intr: &Hit, w: &Vector3f) -> Spectrum {
+        if self.two_sided || intr.n.dot(w) > 0.0 {
+            self.l_emit
+        } else {
+            Spectrum::new(0.0)
+        }
     }
 }
 
-impl From<(&ParamSet, ArcTransform, Option<ArcMedium>, ArcShape)> for DiffuseAreaLight {
+impl
+    From<(
+        &ParamSet,
+        ArcTransform,
+        Option<ArcMedium>,
+        ArcShape,
+        Option<Float>,
+    )> for DiffuseAreaLight
+{
     /// Create a `DiffuseAreaLight` from given parameter set, light to world transform
-    /// medium, and shape.
+    /// medium, shape and declared scene scale.
     ///
     /// * `p` - A tuple containing the parameter set, light to world transform,
-    ///         medium, and shape.
-    fn from(p: (&ParamSet, ArcTransform, Option<ArcMedium>, ArcShape)) -> Self {
-        let (params, light_to_world, medium, shape) = p;
+    ///         medium, shape and scene scale declared with
+    ///         `Option "float scenescale"`.
+    fn from(
+        p: (
+            &ParamSet,
+            ArcTransform,
+            Option<ArcMedium>,
+            ArcShape,
+            Option<Float>,
+        ),
+    ) -> Self {
+        let (params, light_to_world, medium, shape, scene_scale) = p;
+        let (_, radius) = shape.world_bound().bounding_sphere();
+        let scene_scale = object_scene_scale(scene_scale, radius);
 
-        let l = params.find_one_spectrum("L", Spectrum::new(1.0));
+        let l = params.find_one_illuminant("L", Spectrum::new(1.0));
         let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
         let two_sided = params.find_one_bool("twosided", false);
 
@@ -204,13 +257,16 @@ impl From<(&ParamSet, ArcTransform, Option<ArcMedium>, ArcShape)> for DiffuseAre
             n_samples = max(1, n_samples / 4);
         }
 
-        Self::new(
+        let mut light = Self::new(
             light_to_world,
             MediumInterface::from(medium),
             l * sc,
             n_samples as usize,
             shape,
             two_sided,
-        )
+        );
+        light.visibility = LightVisibility::from(params);
+        light.min_sample_distance = MIN_LIGHT_SAMPLE_DISTANCE * scene_scale;
+        light
     }
 }
@@ -45,7 +45,7 @@ impl DistantLight {
     /// * `light_to_world`   - Transformation from light coordinate system to
     ///                        world coordinate system.
     /// * `emitted_radiance` - The emitted radiance.
-    /// * `w_light`          - Direction of light.
+    /// * `w_light`          - Direction of light in light coordinate system.
     pub fn new(
         light_to_world: ArcTransform,
         emitted_radiance: Spectrum,
@@ -60,7 +60,7 @@ impl DistantLight {
             medium_interface: MediumInterface::vacuum(),
             world_center: Point3f::default(), // Calculated in preprocess().
             world_radius: 1.0,                // Calculated in preprocess().
-            w_light,
+            w_light: light_to_world.transform_vector(&w_light).normalize(),
             emitted_radiance,
         }
     }
@@ -71,7 +71,7 @@ impl Light for DistantLight {
     ///
     /// * `scene` - The scene.
     fn preprocess(&mut self, scene: &Scene) {
-        let (world_center, world_radius) = scene.world_bound.bounding_sphere();
+        let (world_center, world_radius) = scene.bounding_sphere();
         self.world_center = world_center;
         self.world_radius = world_radius;
     }
@@ -88,7 +88,8 @@ impl Light for DistantLight {
     fn sample_li(&self, hit: &Hit, _u: &Point2f) -> Li {
         let wi = self.w_light;
         let pdf = 1.0;
-        let p_outside = hit.p + self.w_light * (2.0 * self.world_radius);
+        let exit_distance = 2.0 * (self.world_radius + hit.p.distance(self.world_center));
+        let p_outside = hit.p + self.w_light * exit_distance;
         let visibility = Some(VisibilityTester::new(hit.clone(), p_outside));
         let value = self.emitted_radiance;
         Li::new(wi, pdf, visibility, value)
@@ -153,7 +154,7 @@ impl From<(&ParamSet, ArcTransform)> for DistantLight {
     fn from(p: (&ParamSet, ArcTransform)) -> Self {
         let (params, light_to_world) = p;
 
-        let emitted_radiance = params.find_one_spectrum("L", Spectrum::new(1.0));
+        let emitted_radiance = params.find_one_illuminant("L", Spectrum::new(1.0));
         let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
         let from = params.find_one_point3f("from", Point3f::new(0.0, 0.0, 0.0));
         let to = params.find_one_point3f("to", Point3f::new(0.0, 0.0, 0.1));
@@ -0,0 +1,287 @@
+//! Goniophotometric Diagram Light Source
+
+use core::geometry::*;
+use core::image_io::*;
+use core::light::*;
+use core::medium::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::sampling::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Implements a point light source whose intensity varies with direction
+/// according to a goniophotometric diagram. The diagram is stored as a
+/// latitude-longitude image around the +y axis of the light coordinate
+/// system.
+#[derive(Clone)]
+pub struct GonioPhotometricLight {
+    /// Light source type.
+    pub light_type: LightType,
+
+    /// Participating medium.
+    pub medium_interface: MediumInterface,
+
+    /// Transformation from light coordinate system to world coordinate system.
+    pub light_to_world: ArcTransform,
+
+    /// Transformation from world coordinate system to light coordinate system.
+    pub world_to_light: ArcTransform,
+
+    /// Position.
+    pub p_light: Point3f,
+
+    /// Intensity.
+    pub intensity: Spectrum,
+
+    /// Angular scale of the intensity. If none is given, light is emitted
+    /// uniformly in all directions.
+    pub mipmap: Option<MIPMap<Float>>,
+}
+
+impl GonioPhotometricLight {
+    /// Returns a new `GonioPhotometricLight`.
+    ///
+    /// * `light_to_world`   - Transformation from light coordinate system to
+    ///                        world coordinate system.
+    /// * `medium_interface` - Participating medium.
+    /// * `intensity`        - Intensity.
+    /// * `texmap`           - Path to the goniophotometric diagram image.
+    pub fn new(
+        light_to_world: ArcTransform,
+        medium_interface: MediumInterface,
+        intensity: Spectrum,
+        texmap: &str,
+    ) -> Self {
+        let world_to_light = Arc::clone(&light_to_world).inverse();
+        let p_light = Arc::clone(&light_to_world).transform_point(&Point3f::default());
+
+        // Create `mipmap` for `GonioPhotometricLight` using the luminance of
+        // the image. Clamp lookups so directions near one pole don't blend
+        // with the opposite pole.
+        let mipmap = match texmap {
+            "" => None,
+            _ => match read_image(texmap) {
+                Ok(RGBImage { pixels, resolution }) => {
+                    let texels: Vec<Float> = pixels.iter().map(|texel| texel.y()).collect();
+                    Some(MIPMap::new(
+                        &resolution,
+                        &texels,
+                        FilteringMethod::Trilinear,
+                        ImageWrap::Clamp,
+                        0.0,
+                    ))
+                }
+                Err(err) => {
+                    warn!("Problem reading file '{}'. {}", texmap, err);
+                    None
+                }
+            },
+        };
+
+        Self {
+            light_type: LightType::from(DELTA_POSITION_LIGHT),
+            medium_interface: medium_interface.clone(),
+            light_to_world: Arc::clone(&light_to_world),
+            world_to_light: Arc::new(world_to_light),
+            p_light,
+            intensity,
+            mipmap,
+        }
+    }
+
+    /// Returns the scale applied to the intensity for a direction given in
+    /// world space.
+    ///
+    /// * `w` - The direction.
+    fn scale(&self, w: &Vector3f) -> Spectrum {
+        match self.mipmap.as_ref() {
+            Some(mipmap) => {
+                // The diagram's poles lie along the y-axis so swap y and z.
+                let wp = self.world_to_light.transform_vector(w).normalize();
+                let wp = Vector3f::new(wp.x, wp.z, wp.y);
+                let st = Point2f::new(
+                    spherical_phi(&wp) * INV_TWO_PI,
+                    spherical_theta(&wp) * INV_PI,
+                );
+                Spectrum::new(mipmap.lookup_triangle(&st, 0.0))
+            }
+            None => Spectrum::new(1.0),
+        }
+    }
+}
+
+impl Light for GonioPhotometricLight {
+    /// Returns the type of light.
+    fn get_type(&self) -> LightType {
+        self.light_type
+    }
+
+    /// Return the radiance arriving at an interaction point.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `u`   - Sample value for Monte Carlo integration.
+    fn sample_li(&self, hit: &Hit, _u: &Point2f) -> Li {
+        let wi = (self.p_light - hit.p).normalize();
+        let pdf = 1.0;
+        let visibility = Some(VisibilityTester::new(hit.clone(), self.p_light));
+        let value = self.intensity * self.scale(&-wi) / self.p_light.distance_squared(hit.p);
+        Li::new(wi, pdf, visibility, value)
+    }
+
+    /// Return the total emitted power.
+    fn power(&self) -> Spectrum {
+        let scale = self
+            .mipmap
+            .as_ref()
+            .map_or(1.0, |m| m.lookup_triangle(&Point2f::new(0.5, 0.5), 0.5));
+        FOUR_PI * self.intensity * scale
+    }
+
+    /// Returns the probability density with respect to solid angle for the light’s
+    /// `sample_li()`.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `wi`  - The incident direction.
+    fn pdf_li(&self, _hit: &Hit, _wi: &Vector3f) -> Float {
+        0.0
+    }
+
+    /// Returns a sampled light-carrying ray leaving the light source.
+    ///
+    /// * `u1`   - Sample values for Monte Carlo.
+    /// * `u2`   - Sample values for Monte Carlo.
+    /// * `time` - Time to use for the ray.
+    fn sample_le(&self, u1: &Point2f, _u2: &Point2f, time: Float) -> Le {
+        let dir = uniform_sample_sphere(u1);
+        let ray = Ray::new(
+            self.p_light,
+            dir,
+            INFINITY,
+            time,
+            self.medium_interface.inside.clone(),
+        );
+        Le::new(
+            ray,
+            Normal3f::from(dir),
+            1.0,
+            uniform_sphere_pdf(),
+            self.intensity * self.scale(&dir),
+        )
+    }
+
+    /// Returns the probability density for the light’s `sample_le()`.
+    ///
+    /// * `ray`     - The ray.
+    /// * `n_light` - The normal.
+    fn pdf_le(&self, _ray: &Ray, _n_light: &Normal3f) -> Pdf {
+        Pdf::new(0.0, uniform_sphere_pdf())
+    }
+
+    /// Returns the memory used by the light and its diagram in bytes.
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Self>() + self.mipmap.as_ref().map_or(0, |m| m.memory())
+    }
+}
+
+impl From<(&ParamSet, ArcTransform, Option<ArcMedium>)> for GonioPhotometricLight {
+    /// Create a `GonioPhotometricLight` from given parameter set, light to
+    /// world transform and medium.
+    ///
+    /// * `p` - A tuple containing the parameter set, light to world transform
+    ///         and medium.
+    fn from(p: (&ParamSet, ArcTransform, Option<ArcMedium>)) -> Self {
+        let (params, light_to_world, medium) = p;
+
+        let intensity = params.find_one_illuminant("I", Spectrum::new(1.0));
+        let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
+        let texmap = params.find_one_filename("mapname", String::from(""));
+
+        Self::new(
+            light_to_world,
+            MediumInterface::from(medium),
+            intensity * sc,
+            &texmap,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    /// Returns a reference point at distance 2 from the origin in the
+    /// xz-plane at angle `phi` degrees from the +x axis.
+    ///
+    /// * `phi` - Angle in degrees.
+    fn hit_at(phi: Float) -> Hit {
+        let (sin, cos) = phi.to_radians().sin_cos();
+        Hit::new(
+            Point3f::new(2.0 * cos, 0.0, 2.0 * sin),
+            0.0,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::default(),
+            None,
+        )
+    }
+
+    #[test]
+    fn uniform_light_emits_in_all_directions() {
+        let light = GonioPhotometricLight::new(
+            Arc::new(Transform::default()),
+            MediumInterface::from(None),
+            Spectrum::new(4.0),
+            "",
+        );
+        let li = light.sample_li(&hit_at(30.0), &Point2f::new(0.5, 0.5));
+        assert_eq!(li.pdf, 1.0);
+        assert!((li.value[0] - 1.0).abs() < 1e-5);
+        assert_eq!(light.pdf_li(&hit_at(30.0), &li.wi), 0.0);
+        assert!((light.power()[0] - 4.0 * FOUR_PI).abs() < 1e-4);
+    }
+
+    #[test]
+    fn diagram_scales_intensity_by_direction() {
+        // The left half of the diagram is 4 times brighter than the right.
+        let (width, height) = (8, 2);
+        let mut rgb = vec![0.0; 3 * width * height];
+        for (i, texel) in rgb.chunks_mut(3).enumerate() {
+            let v = if i % width < width / 2 { 1.0 } else { 0.25 };
+            texel.copy_from_slice(&[v, v, v]);
+        }
+        let path = std::env::temp_dir().join("goniometric_test.pfm");
+        let path = path.to_string_lossy().into_owned();
+        let bounds = Bounds2i::new(Point2i::new(0, 0), Point2i::new(8, 2));
+        write_image(&path, &rgb, &bounds, &BTreeMap::new()).unwrap();
+
+        let light = GonioPhotometricLight::new(
+            Arc::new(Transform::default()),
+            MediumInterface::from(None),
+            Spectrum::new(4.0),
+            &path,
+        );
+        fs::remove_file(&path).unwrap();
+
+        // Azimuth is measured in the xz-plane of the light.
+        let u = Point2f::new(0.5, 0.5);
+        let bright = light.sample_li(&hit_at(45.0), &u).value[0];
+        let dim = light.sample_li(&hit_at(225.0), &u).value[0];
+        assert!((bright - 1.0).abs() < 1e-4, "{}", bright);
+        assert!((dim - 0.25).abs() < 1e-4, "{}", dim);
+
+        let power = light.power()[0];
+        assert!(
+            power > 4.0 * FOUR_PI * 0.25 && power < 4.0 * FOUR_PI,
+            "{}",
+            power
+        );
+    }
+}
@@ -44,6 +44,19 @@ pub struct InfiniteAreaLight {
 
     /// 2-d distribution
     pub distribution: Distribution2D,
+
+    /// Scale applied to the radiance map at load time from luminance
+    /// normalization and exposure compensation.
+    pub radiance_scale: Float,
+
+    /// Exposure compensation in EV (stops).
+    pub exposure: Float,
+
+    /// Target average luminance of the radiance map.
+    pub luminance: Option<Float>,
+
+    /// Visibility to camera rays and illumination.
+    pub visibility: LightVisibility,
 }
 
 impl InfiniteAreaLight {
@@ -56,7 +69,18 @@ impl InfiniteAreaLight {
     /// * `n_samples`        - Used to trace multiple shadow rays to the light
     ///                        to compute soft shadows. Default to 1.
     /// * `texmap`           - Path to the image to use for the radiance map.
-    pub fn new(light_to_world: ArcTransform, l: Spectrum, n_samples: usize, texmap: &str) -> Self {
+    /// * `exposure`         - Exposure compensation in EV (stops) applied to the
+    ///                        radiance map.
+    /// * `luminance`        - Optional target for the average luminance of the
+    ///                        radiance map over the sphere of directions.
+    pub fn new(
+        light_to_world: ArcTransform,
+        l: Spectrum,
+        n_samples: usize,
+        texmap: &str,
+        exposure: Float,
+        luminance: Option<Float>,
+    ) -> Self {
         let world_to_light = Arc::clone(&light_to_world).inverse();
 
         let lrgb = l.to_rgb_spectrum();
@@ -76,6 +100,26 @@ impl InfiniteAreaLight {
             },
         };
 
+        // Normalize to the target luminance and apply exposure compensation.
+        let mut radiance_scale = exposure.exp2();
+        if let Some(target) = luminance {
+            let average = average_luminance(&texels, &resolution);
+            if average > 0.0 {
+                radiance_scale *= target / average;
+            } else {
+                warn!("Radiance map '{}' is black; ignoring 'luminance'.", texmap);
+            }
+        }
+        let texels: Vec<RGBSpectrum> = if radiance_scale != 1.0 {
+            info!(
+                "Scaling radiance map '{}' by {} (exposure {} EV).",
+                texmap, radiance_scale, exposure
+            );
+            texels.iter().map(|texel| *texel * radiance_scale).collect()
+        } else {
+            texels
+        };
+
         let l_map = MIPMap::new(
             &resolution,
             &texels,
@@ -118,8 +162,44 @@ impl InfiniteAreaLight {
             distribution,
             world_center: Point3f::default(), // Calculated in preprocess().
             world_radius: 1.0,                // Calculated in preprocess()
+            radiance_scale,
+            exposure,
+            luminance,
+            visibility: LightVisibility::default(),
         }
     }
+
+    /// Returns a distance along any direction from a point that is guaranteed
+    /// to leave the scene's bounding sphere.
+    ///
+    /// * `p` - The point.
+    fn exit_distance(&self, p: &Point3f) -> Float {
+        2.0 * (self.world_radius + p.distance(self.world_center))
+    }
+}
+
+/// Returns the average luminance of a latitude-longitude radiance map over the
+/// sphere of directions. Each row is weighted by `sin(θ)` to account for the
+/// solid angle subtended by its texels.
+///
+/// * `texels`     - The texels.
+/// * `resolution` - The radiance map resolution.
+fn average_luminance(texels: &[RGBSpectrum], resolution: &Point2<usize>) -> Float {
+    let (width, height) = (resolution.x, resolution.y);
+    let mut sum = 0.0;
+    let mut weight_sum = 0.0;
+    for v in 0..height {
+        let sin_theta = sin(PI * (v as Float + 0.5) / height as Float);
+        for u in 0..width {
+            sum += texels[v * width + u].y() * sin_theta;
+        }
+        weight_sum += sin_theta * width as Float;
+    }
+    if weight_sum > 0.0 {
+        sum / weight_sum
+    } else {
+        0.0
+    }
 }
 
 impl Light for InfiniteAreaLight {
@@ -127,7 +207,7 @@ impl Light for InfiniteAreaLight {
     ///
     /// * `scene` - The scene.
     fn preprocess(&mut self, scene: &Scene) {
-        let (world_center, world_radius) = scene.world_bound.bounding_sphere();
+        let (world_center, world_radius) = scene.bounding_sphere();
         self.world_center = world_center;
         self.world_radius = world_radius;
     }
@@ -167,9 +247,10 @@ impl Light for InfiniteAreaLight {
                 pdf = 0.0;
             }
 
-            // Return radiance value for infinite light direction
+            // Return radiance value for infinite light direction. The shadow
+            // ray must leave the scene bounds even if `hit` lies outside them.
             let p0 = hit.clone();
-            let p1 = hit.p + wi * (2.0 * self.world_radius);
+            let p1 = hit.p + wi * self.exit_distance(&hit.p);
             let vis = VisibilityTester::new(p0, p1);
 
             let rgb = self.l_map.lookup_triangle(&uv, 0.0).to_rgb();
@@ -188,6 +269,47 @@ impl Light for InfiniteAreaLight {
         PI * self.world_radius * self.world_radius * spectrum
     }
 
+    /// Returns emitted radiance due to that light along a ray that escapes the
+    /// scene bounds.
+    ///
+    /// * `ray` - The ray.
+    fn le(&self, ray: &Ray) -> Spectrum {
+        let wr = self.world_to_light.transform_vector(&ray.d).normalize();
+        let st = Point2f::new(
+            spherical_phi(&wr) * INV_TWO_PI,
+            spherical_theta(&wr) * INV_PI,
+        );
+        let rgb = self.l_map.lookup_triangle(&st, 0.0).to_rgb();
+        Spectrum::from_rgb(&rgb, Some(SpectrumType::Illuminant))
+    }
+
+    /// Returns the scaling applied to the radiance map.
+    fn film_metadata(&self) -> Vec<(String, String)> {
+        let mut metadata = vec![
+            (
+                String::from("radianceScale"),
+                self.radiance_scale.to_string(),
+            ),
+            (String::from("exposure"), self.exposure.to_string()),
+        ];
+        if let Some(luminance) = self.luminance {
+            metadata.push((String::from("luminance"), luminance.to_string()));
+        }
+        metadata
+    }
+
+    /// Returns whether the light is visible to camera rays and whether it
+    /// illuminates the scene.
+    fn visibility(&self) -> LightVisibility {
+        self.visibility
+    }
+
+    /// Returns the memory used by the light, its radiance map and sampling
+    /// distribution in bytes.
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Self>() + self.l_map.memory() + self.distribution.memory()
+    }
+
     /// Returns the probability density with respect to solid angle for the light’s
     /// `sample_li()`.
     ///
@@ -284,7 +406,7 @@ impl From<(&ParamSet, ArcTransform)> for InfiniteAreaLight {
     fn from(p: (&ParamSet, ArcTransform)) -> Self {
         let (params, light_to_world) = p;
 
-        let l = params.find_one_spectrum("L", Spectrum::new(1.0));
+        let l = params.find_one_illuminant("L", Spectrum::new(1.0));
         let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
         let texmap = params.find_one_filename("mapname", String::from(""));
 
@@ -293,6 +415,23 @@ impl From<(&ParamSet, ArcTransform)> for InfiniteAreaLight {
             n_samples = max(1, n_samples / 4);
         }
 
-        Self::new(light_to_world, l * sc, n_samples as usize, &texmap)
+        let exposure = params.find_one_float("exposure", 0.0);
+        let luminance = params.find_one_float("luminance", 0.0);
+        let luminance = if luminance > 0.0 {
+            Some(luminance)
+        } else {
+            None
+        };
+
+        let mut light = Self::new(
+            light_to_world,
+            l * sc,
+            n_samples as usize,
+            &texmap,
+            exposure,
+            luminance,
+        );
+        light.visibility = LightVisibility::from(params);
+        light
     }
 }
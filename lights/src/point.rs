@@ -30,6 +30,10 @@ pub struct PointLight {
 
     /// Intensity.
     pub intensity: Spectrum,
+
+    /// Photometric profile that shapes the emission. Its nadir points down
+    /// the -y axis of the light coordinate system.
+    pub ies: Option<IESProfile>,
 }
 
 impl PointLight {
@@ -53,6 +57,21 @@ impl PointLight {
             world_to_light: Arc::new(world_to_light),
             p_light,
             intensity,
+            ies: None,
+        }
+    }
+
+    /// Returns the scale applied to the intensity by the photometric profile
+    /// for a direction given in world space.
+    ///
+    /// * `w` - The direction.
+    fn scale(&self, w: &Vector3f) -> Float {
+        match self.ies.as_ref() {
+            Some(ies) => {
+                let wl = self.world_to_light.transform_vector(w);
+                ies.evaluate(&Vector3f::new(wl.x, wl.z, -wl.y))
+            }
+            None => 1.0,
         }
     }
 }
@@ -71,13 +90,14 @@ impl Light for PointLight {
         let wi = (self.p_light - hit.p).normalize();
         let pdf = 1.0;
         let visibility = Some(VisibilityTester::new(hit.clone(), self.p_light));
-        let value = self.intensity / self.p_light.distance_squared(hit.p);
+        let value = self.intensity * self.scale(&-wi) / self.p_light.distance_squared(hit.p);
         Li::new(wi, pdf, visibility, value)
     }
 
     /// Return the total emitted power.
     fn power(&self) -> Spectrum {
-        FOUR_PI * self.intensity
+        let scale = self.ies.as_ref().map_or(1.0, |ies| ies.average());
+        FOUR_PI * self.intensity * scale
     }
 
     /// Returns the probability density with respect to solid angle for the light’s
@@ -108,7 +128,7 @@ impl Light for PointLight {
             Normal3f::from(dir),
             1.0,
             uniform_sphere_pdf(),
-            self.intensity,
+            self.intensity * self.scale(&dir),
         )
     }
 
@@ -130,10 +150,12 @@ impl From<(&ParamSet, ArcTransform, Option<ArcMedium>)> for PointLight {
     fn from(p: (&ParamSet, ArcTransform, Option<ArcMedium>)) -> Self {
         let (params, light_to_world, medium) = p;
 
-        let intensity = params.find_one_spectrum("I", Spectrum::new(1.0));
+        let intensity = params.find_one_illuminant("I", Spectrum::new(1.0));
         let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
         let p = params.find_one_point3f("from", Point3f::default());
         let l2w = Transform::translate(&Vector3f::new(p.x, p.y, p.z)) * *light_to_world;
-        Self::new(Arc::new(l2w), MediumInterface::from(medium), intensity * sc)
+        let mut light = Self::new(Arc::new(l2w), MediumInterface::from(medium), intensity * sc);
+        light.ies = ies_profile(params);
+        light
     }
 }
@@ -0,0 +1,323 @@
+//! Projection Light Source
+
+use core::geometry::*;
+use core::image_io::*;
+use core::light::*;
+use core::medium::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::sampling::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Implements a light source that projects an image into the scene like a
+/// slide projector. The light points down the +z axis of its coordinate
+/// system.
+#[derive(Clone)]
+pub struct ProjectionLight {
+    /// Light source type.
+    pub light_type: LightType,
+
+    /// Participating medium.
+    pub medium_interface: MediumInterface,
+
+    /// Transformation from light coordinate system to world coordinate system.
+    pub light_to_world: ArcTransform,
+
+    /// Transformation from world coordinate system to light coordinate system.
+    pub world_to_light: ArcTransform,
+
+    /// Position.
+    pub p_light: Point3f,
+
+    /// Intensity.
+    pub intensity: Spectrum,
+
+    /// The projected image. If none is given, light is projected uniformly.
+    pub projection_map: Option<MIPMap<RGBSpectrum>>,
+
+    /// Projection from light coordinate system to the image plane.
+    pub light_projection: Transform,
+
+    /// Near z-plane of the projection.
+    pub hither: Float,
+
+    /// Far z-plane of the projection.
+    pub yon: Float,
+
+    /// Bounds of the image on the image plane.
+    pub screen_bounds: Bounds2f,
+
+    /// Cosine of the angle between the +z axis and the corner of the image.
+    pub cos_total_width: Float,
+}
+
+impl ProjectionLight {
+    /// Returns a new `ProjectionLight`.
+    ///
+    /// * `light_to_world`   - Transformation from light coordinate system to
+    ///                        world coordinate system.
+    /// * `medium_interface` - Participating medium.
+    /// * `intensity`        - Intensity.
+    /// * `texmap`           - Path to the image to project.
+    /// * `fov`              - Field of view angle in degrees.
+    pub fn new(
+        light_to_world: ArcTransform,
+        medium_interface: MediumInterface,
+        intensity: Spectrum,
+        texmap: &str,
+        fov: Float,
+    ) -> Self {
+        let world_to_light = Arc::clone(&light_to_world).inverse();
+        let p_light = Arc::clone(&light_to_world).transform_point(&Point3f::default());
+
+        // Create `ProjectionLight` MIP map.
+        let projection_map = match texmap {
+            "" => None,
+            _ => match read_image(texmap) {
+                Ok(RGBImage { pixels, resolution }) => Some(MIPMap::new(
+                    &resolution,
+                    &pixels,
+                    FilteringMethod::Trilinear,
+                    ImageWrap::Repeat,
+                    0.0,
+                )),
+                Err(err) => {
+                    warn!("Problem reading file '{}'. {}", texmap, err);
+                    None
+                }
+            },
+        };
+
+        // Initialize `ProjectionLight` projection matrix.
+        let aspect = projection_map
+            .as_ref()
+            .map_or(1.0, |m| m.width() as Float / m.height() as Float);
+        let screen_bounds = if aspect > 1.0 {
+            Bounds2f::new(Point2f::new(-aspect, -1.0), Point2f::new(aspect, 1.0))
+        } else {
+            Bounds2f::new(
+                Point2f::new(-1.0, -1.0 / aspect),
+                Point2f::new(1.0, 1.0 / aspect),
+            )
+        };
+        let hither = 1e-3;
+        let yon = 1e30;
+        let light_projection = Transform::perspective(fov, hither, yon);
+
+        // Compute cosine of cone surrounding projection directions.
+        let screen_to_light = light_projection.inverse();
+        let p_corner = Point3f::new(screen_bounds.p_max.x, screen_bounds.p_max.y, 0.0);
+        let w_corner = Vector3f::from(screen_to_light.transform_point(&p_corner)).normalize();
+        let cos_total_width = w_corner.z;
+
+        Self {
+            light_type: LightType::from(DELTA_POSITION_LIGHT),
+            medium_interface: medium_interface.clone(),
+            light_to_world: Arc::clone(&light_to_world),
+            world_to_light: Arc::new(world_to_light),
+            p_light,
+            intensity,
+            projection_map,
+            light_projection,
+            hither,
+            yon,
+            screen_bounds,
+            cos_total_width,
+        }
+    }
+
+    /// Returns the fraction of the intensity projected in a direction given
+    /// in world space.
+    ///
+    /// * `w` - The direction.
+    fn projection(&self, w: &Vector3f) -> Spectrum {
+        let wl = self.world_to_light.transform_vector(w);
+
+        // Discard directions behind projection light.
+        if wl.z < self.hither {
+            return Spectrum::new(0.0);
+        }
+
+        // Project point onto projection plane and compute light.
+        let p = self
+            .light_projection
+            .transform_point(&Point3f::new(wl.x, wl.y, wl.z));
+        let p = Point2f::new(p.x, p.y);
+        if !self.screen_bounds.contains(&p) {
+            return Spectrum::new(0.0);
+        }
+        match self.projection_map.as_ref() {
+            Some(projection_map) => {
+                let st = Point2f::from(self.screen_bounds.offset(&p));
+                let rgb = projection_map.lookup_triangle(&st, 0.0).to_rgb();
+                Spectrum::from_rgb(&rgb, Some(SpectrumType::Illuminant))
+            }
+            None => Spectrum::new(1.0),
+        }
+    }
+}
+
+impl Light for ProjectionLight {
+    /// Returns the type of light.
+    fn get_type(&self) -> LightType {
+        self.light_type
+    }
+
+    /// Return the radiance arriving at an interaction point.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `u`   - Sample value for Monte Carlo integration.
+    fn sample_li(&self, hit: &Hit, _u: &Point2f) -> Li {
+        let wi = (self.p_light - hit.p).normalize();
+        let pdf = 1.0;
+        let visibility = Some(VisibilityTester::new(hit.clone(), self.p_light));
+        let value = self.intensity * self.projection(&-wi) / self.p_light.distance_squared(hit.p);
+        Li::new(wi, pdf, visibility, value)
+    }
+
+    /// Return the total emitted power.
+    fn power(&self) -> Spectrum {
+        let map = match self.projection_map.as_ref() {
+            Some(projection_map) => {
+                let rgb = projection_map
+                    .lookup_triangle(&Point2f::new(0.5, 0.5), 0.5)
+                    .to_rgb();
+                Spectrum::from_rgb(&rgb, Some(SpectrumType::Illuminant))
+            }
+            None => Spectrum::new(1.0),
+        };
+        map * self.intensity * TWO_PI * (1.0 - self.cos_total_width)
+    }
+
+    /// Returns the probability density with respect to solid angle for the light’s
+    /// `sample_li()`.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `wi`  - The incident direction.
+    fn pdf_li(&self, _hit: &Hit, _wi: &Vector3f) -> Float {
+        0.0
+    }
+
+    /// Returns a sampled light-carrying ray leaving the light source.
+    ///
+    /// * `u1`   - Sample values for Monte Carlo.
+    /// * `u2`   - Sample values for Monte Carlo.
+    /// * `time` - Time to use for the ray.
+    fn sample_le(&self, u1: &Point2f, _u2: &Point2f, time: Float) -> Le {
+        let w = uniform_sample_cone(u1, self.cos_total_width);
+        let dir = self.light_to_world.transform_vector(&w).normalize();
+        let ray = Ray::new(
+            self.p_light,
+            dir,
+            INFINITY,
+            time,
+            self.medium_interface.inside.clone(),
+        );
+        Le::new(
+            ray,
+            Normal3f::from(dir),
+            1.0,
+            uniform_cone_pdf(self.cos_total_width),
+            self.intensity * self.projection(&dir),
+        )
+    }
+
+    /// Returns the probability density for the light’s `sample_le()`.
+    ///
+    /// * `ray`     - The ray.
+    /// * `n_light` - The normal.
+    fn pdf_le(&self, ray: &Ray, _n_light: &Normal3f) -> Pdf {
+        let wl = self.world_to_light.transform_vector(&ray.d).normalize();
+        let pdf_dir = if cos_theta(&wl) >= self.cos_total_width {
+            uniform_cone_pdf(self.cos_total_width)
+        } else {
+            0.0
+        };
+        Pdf::new(0.0, pdf_dir)
+    }
+
+    /// Returns the memory used by the light and its projected image in bytes.
+    fn memory(&self) -> usize {
+        std::mem::size_of::<Self>() + self.projection_map.as_ref().map_or(0, |m| m.memory())
+    }
+}
+
+impl From<(&ParamSet, ArcTransform, Option<ArcMedium>)> for ProjectionLight {
+    /// Create a `ProjectionLight` from given parameter set, light to world
+    /// transform and medium.
+    ///
+    /// * `p` - A tuple containing the parameter set, light to world transform
+    ///         and medium.
+    fn from(p: (&ParamSet, ArcTransform, Option<ArcMedium>)) -> Self {
+        let (params, light_to_world, medium) = p;
+
+        let intensity = params.find_one_illuminant("I", Spectrum::new(1.0));
+        let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
+        let fov = params.find_one_float("fov", 45.0);
+        let texmap = params.find_one_filename("mapname", String::from(""));
+
+        Self::new(
+            light_to_world,
+            MediumInterface::from(medium),
+            intensity * sc,
+            &texmap,
+            fov,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a reference point at distance 2 from the origin in the
+    /// direction `theta` degrees from the +z axis in the xz-plane.
+    ///
+    /// * `theta` - Angle in degrees.
+    fn hit_at(theta: Float) -> Hit {
+        let (sin, cos) = theta.to_radians().sin_cos();
+        Hit::new(
+            Point3f::new(2.0 * sin, 0.0, 2.0 * cos),
+            0.0,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::default(),
+            None,
+        )
+    }
+
+    #[test]
+    fn uniform_projection_covers_the_field_of_view() {
+        let light = ProjectionLight::new(
+            Arc::new(Transform::default()),
+            MediumInterface::from(None),
+            Spectrum::new(4.0),
+            "",
+            90.0,
+        );
+        let u = Point2f::new(0.5, 0.5);
+
+        let li = light.sample_li(&hit_at(0.0), &u);
+        assert_eq!(li.pdf, 1.0);
+        assert!((li.value[0] - 1.0).abs() < 1e-5, "{}", li.value[0]);
+        assert_eq!(light.pdf_li(&hit_at(0.0), &li.wi), 0.0);
+
+        // The square image plane spans 45 degrees either side of the axis.
+        assert!((light.sample_li(&hit_at(40.0), &u).value[0] - 1.0).abs() < 1e-5);
+        assert_eq!(light.sample_li(&hit_at(50.0), &u).value[0], 0.0);
+        assert_eq!(light.sample_li(&hit_at(180.0), &u).value[0], 0.0);
+
+        // The cone through the image corners subtends the emitted power.
+        let cos_total_width = 1.0 / Float::sqrt(3.0);
+        assert!((light.cos_total_width - cos_total_width).abs() < 1e-5);
+        let power = light.power();
+        assert!((power[0] - 4.0 * TWO_PI * (1.0 - cos_total_width)).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,290 @@
+//! Spotlight Source
+
+use core::geometry::*;
+use core::light::*;
+use core::medium::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::sampling::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Implements a spotlight that emits light in a cone of directions from its
+/// position. The light points down the +z axis of its coordinate system.
+#[derive(Clone)]
+pub struct SpotLight {
+    /// Light source type.
+    pub light_type: LightType,
+
+    /// Participating medium.
+    pub medium_interface: MediumInterface,
+
+    /// Transformation from light coordinate system to world coordinate system.
+    pub light_to_world: ArcTransform,
+
+    /// Transformation from world coordinate system to light coordinate system.
+    pub world_to_light: ArcTransform,
+
+    /// Position.
+    pub p_light: Point3f,
+
+    /// Intensity.
+    pub intensity: Spectrum,
+
+    /// Cosine of the angle beyond which no light is emitted.
+    pub cos_total_width: Float,
+
+    /// Cosine of the angle at which the falloff starts.
+    pub cos_falloff_start: Float,
+
+    /// Photometric profile that shapes the emission within the cone. Its
+    /// nadir points down the +z axis of the light coordinate system.
+    pub ies: Option<IESProfile>,
+}
+
+impl SpotLight {
+    /// Returns a new `SpotLight`.
+    ///
+    /// * `light_to_world`   - Transformation from light coordinate system to
+    ///                        world coordinate system.
+    /// * `medium_interface` - Participating medium.
+    /// * `intensity`        - Intensity.
+    /// * `total_width`      - Angle in degrees beyond which no light is emitted.
+    /// * `falloff_start`    - Angle in degrees at which the falloff starts.
+    pub fn new(
+        light_to_world: ArcTransform,
+        medium_interface: MediumInterface,
+        intensity: Spectrum,
+        total_width: Float,
+        falloff_start: Float,
+    ) -> Self {
+        let world_to_light = Arc::clone(&light_to_world).inverse();
+        let p_light = Arc::clone(&light_to_world).transform_point(&Point3f::default());
+        Self {
+            light_type: LightType::from(DELTA_POSITION_LIGHT),
+            medium_interface: medium_interface.clone(),
+            light_to_world: Arc::clone(&light_to_world),
+            world_to_light: Arc::new(world_to_light),
+            p_light,
+            intensity,
+            cos_total_width: cos(total_width.to_radians()),
+            cos_falloff_start: cos(falloff_start.to_radians()),
+            ies: None,
+        }
+    }
+
+    /// Returns the distribution of light within the cone for a direction
+    /// given in world space.
+    ///
+    /// * `w` - The direction.
+    fn falloff(&self, w: &Vector3f) -> Float {
+        let wl = self.world_to_light.transform_vector(w).normalize();
+        let cos_theta = cos_theta(&wl);
+        let falloff = if cos_theta < self.cos_total_width {
+            0.0
+        } else if cos_theta >= self.cos_falloff_start {
+            1.0
+        } else {
+            // Compute falloff inside spotlight cone.
+            let delta = (cos_theta - self.cos_total_width)
+                / (self.cos_falloff_start - self.cos_total_width);
+            (delta * delta) * (delta * delta)
+        };
+        match self.ies.as_ref() {
+            Some(ies) if falloff > 0.0 => falloff * ies.evaluate(&wl),
+            _ => falloff,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    /// Returns the type of light.
+    fn get_type(&self) -> LightType {
+        self.light_type
+    }
+
+    /// Return the radiance arriving at an interaction point.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `u`   - Sample value for Monte Carlo integration.
+    fn sample_li(&self, hit: &Hit, _u: &Point2f) -> Li {
+        let wi = (self.p_light - hit.p).normalize();
+        let pdf = 1.0;
+        let visibility = Some(VisibilityTester::new(hit.clone(), self.p_light));
+        let value = self.intensity * self.falloff(&-wi) / self.p_light.distance_squared(hit.p);
+        Li::new(wi, pdf, visibility, value)
+    }
+
+    /// Return the total emitted power.
+    fn power(&self) -> Spectrum {
+        self.intensity * TWO_PI * (1.0 - 0.5 * (self.cos_falloff_start + self.cos_total_width))
+    }
+
+    /// Returns the probability density with respect to solid angle for the light’s
+    /// `sample_li()`.
+    ///
+    /// * `hit` - The interaction hit point.
+    /// * `wi`  - The incident direction.
+    fn pdf_li(&self, _hit: &Hit, _wi: &Vector3f) -> Float {
+        0.0
+    }
+
+    /// Returns a sampled light-carrying ray leaving the light source.
+    ///
+    /// * `u1`   - Sample values for Monte Carlo.
+    /// * `u2`   - Sample values for Monte Carlo.
+    /// * `time` - Time to use for the ray.
+    fn sample_le(&self, u1: &Point2f, _u2: &Point2f, time: Float) -> Le {
+        let w = uniform_sample_cone(u1, self.cos_total_width);
+        let dir = self.light_to_world.transform_vector(&w).normalize();
+        let ray = Ray::new(
+            self.p_light,
+            dir,
+            INFINITY,
+            time,
+            self.medium_interface.inside.clone(),
+        );
+        Le::new(
+            ray,
+            Normal3f::from(dir),
+            1.0,
+            uniform_cone_pdf(self.cos_total_width),
+            self.intensity * self.falloff(&dir),
+        )
+    }
+
+    /// Returns the probability density for the light’s `sample_le()`.
+    ///
+    /// * `ray`     - The ray.
+    /// * `n_light` - The normal.
+    fn pdf_le(&self, ray: &Ray, _n_light: &Normal3f) -> Pdf {
+        let wl = self.world_to_light.transform_vector(&ray.d).normalize();
+        let pdf_dir = if cos_theta(&wl) >= self.cos_total_width {
+            uniform_cone_pdf(self.cos_total_width)
+        } else {
+            0.0
+        };
+        Pdf::new(0.0, pdf_dir)
+    }
+}
+
+impl From<(&ParamSet, ArcTransform, Option<ArcMedium>)> for SpotLight {
+    /// Create a `SpotLight` from given parameter set, light to world transform
+    /// and medium.
+    ///
+    /// * `p` - A tuple containing the parameter set, light to world transform
+    ///         and medium.
+    fn from(p: (&ParamSet, ArcTransform, Option<ArcMedium>)) -> Self {
+        let (params, light_to_world, medium) = p;
+
+        let intensity = params.find_one_illuminant("I", Spectrum::new(1.0));
+        let sc = params.find_one_spectrum("scale", Spectrum::new(1.0));
+        let cone_angle = params.find_one_float("coneangle", 30.0);
+        let cone_delta = params.find_one_float("conedelta", 5.0);
+
+        // Compute spotlight world to light transformation.
+        let from = params.find_one_point3f("from", Point3f::new(0.0, 0.0, 0.0));
+        let to = params.find_one_point3f("to", Point3f::new(0.0, 0.0, 1.0));
+        let l2w = *light_to_world * dir_to_z(&from, &to).inverse();
+
+        let mut light = Self::new(
+            Arc::new(l2w),
+            MediumInterface::from(medium),
+            intensity * sc,
+            cone_angle,
+            cone_angle - cone_delta,
+        );
+        light.ies = ies_profile(params);
+        light
+    }
+}
+
+/// Returns a transformation that moves `from` to the origin and rotates the
+/// direction from `from` to `to` onto the +z axis.
+///
+/// * `from` - Position of the light.
+/// * `to`   - Point the light is aimed at.
+#[rustfmt::skip]
+pub(crate) fn dir_to_z(from: &Point3f, to: &Point3f) -> Transform {
+    let dir = (*to - *from).normalize();
+    let (du, dv) = coordinate_system(&dir);
+    let rotate = Transform::new([
+        [du.x,  du.y,  du.z,  0.0],
+        [dv.x,  dv.y,  dv.z,  0.0],
+        [dir.x, dir.y, dir.z, 0.0],
+        [0.0,   0.0,   0.0,   1.0],
+    ]);
+    rotate * Transform::translate(&Vector3f::new(-from.x, -from.y, -from.z))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a spotlight at the origin pointing down +z with a 30 degree
+    /// cone that starts falling off at 20 degrees.
+    fn spotlight() -> SpotLight {
+        SpotLight::new(
+            Arc::new(Transform::default()),
+            MediumInterface::from(None),
+            Spectrum::new(2.0),
+            30.0,
+            20.0,
+        )
+    }
+
+    /// Returns a reference point at unit distance from the light in the
+    /// direction `theta` degrees from the +z axis.
+    ///
+    /// * `theta` - Angle in degrees.
+    fn hit_at(theta: Float) -> Hit {
+        let (sin, cos) = theta.to_radians().sin_cos();
+        Hit::new(
+            Point3f::new(sin, 0.0, cos),
+            0.0,
+            Vector3f::default(),
+            Vector3f::default(),
+            Normal3f::default(),
+            None,
+        )
+    }
+
+    #[test]
+    fn sample_li_falls_off_at_the_cone_edges() {
+        let light = spotlight();
+
+        let li = light.sample_li(&hit_at(0.0), &Point2f::new(0.5, 0.5));
+        assert_eq!(li.pdf, 1.0);
+        assert!((li.wi - Vector3f::new(0.0, 0.0, -1.0)).length() < 1e-6);
+        assert!((li.value[0] - 2.0).abs() < 1e-5);
+
+        // Full intensity up to the falloff start and none beyond the cone.
+        let li = light.sample_li(&hit_at(19.9), &Point2f::new(0.5, 0.5));
+        assert!((li.value[0] - 2.0).abs() < 1e-4);
+        let li = light.sample_li(&hit_at(30.1), &Point2f::new(0.5, 0.5));
+        assert_eq!(li.value[0], 0.0);
+
+        // Smooth falloff in between.
+        let li = light.sample_li(&hit_at(25.0), &Point2f::new(0.5, 0.5));
+        let (cos_total, cos_start) = (cos(30.0_f32.to_radians()), cos(20.0_f32.to_radians()));
+        let delta = (cos(25.0_f32.to_radians()) - cos_total) / (cos_start - cos_total);
+        assert!((li.value[0] - 2.0 * delta.powi(4)).abs() < 1e-4);
+        assert!(li.value[0] > 0.0 && li.value[0] < 2.0);
+    }
+
+    #[test]
+    fn pdf_li_and_power() {
+        let light = spotlight();
+        let wi = Vector3f::new(0.0, 0.0, -1.0);
+        assert_eq!(light.pdf_li(&hit_at(0.0), &wi), 0.0);
+
+        let cos_mid = 0.5 * (cos(20.0_f32.to_radians()) + cos(30.0_f32.to_radians()));
+        let power = light.power();
+        assert!((power[0] - 2.0 * TWO_PI * (1.0 - cos_mid)).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,148 @@
+//! Fluorescent Material
+
+use core::geometry::*;
+use core::material::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+
+/// Implements diffuse surfaces that re-emit some of the light they absorb at
+/// longer wavelengths such as paper with optical brighteners.
+pub struct FluorescentMaterial {
+    /// Spectral diffuse reflection of light that is not re-emitted.
+    kd: ArcTexture<Spectrum>,
+
+    /// Describes the re-emitted light for each wavelength of incident light
+    /// or `None` if there is none.
+    reradiation: Option<Arc<ReradiationMatrix>>,
+
+    /// Scale applied to the re-emitted light.
+    scale: ArcTexture<Float>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+}
+
+impl FluorescentMaterial {
+    /// Create a new `FluorescentMaterial`.
+    ///
+    /// * `kd`          - Spectral diffuse reflection of light that is not
+    ///                   re-emitted.
+    /// * `reradiation` - Describes the re-emitted light for each wavelength
+    ///                   of incident light or `None` if there is none.
+    /// * `scale`       - Scale applied to the re-emitted light.
+    /// * `bump_map`    - Optional bump map.
+    /// * `normal_map`  - Optional normal map.
+    pub fn new(
+        kd: ArcTexture<Spectrum>,
+        reradiation: Option<Arc<ReradiationMatrix>>,
+        scale: ArcTexture<Float>,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+    ) -> Self {
+        Self {
+            kd: Arc::clone(&kd),
+            reradiation,
+            scale,
+            bump_map,
+            normal_map,
+        }
+    }
+}
+
+impl Material for FluorescentMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode (ignored).
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available (ignored).
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        let mut bsdf = BSDF::new(si, None);
+
+        let r = self.kd.evaluate(si).clamp_default();
+        if !r.is_black() {
+            bsdf.add(Arc::new(LambertianReflection::new(r)));
+        }
+        if let Some(reradiation) = self.reradiation.as_ref() {
+            let scale = self.scale.evaluate(si);
+            if scale > 0.0 {
+                bsdf.add(Arc::new(FluorescentReflection::new(
+                    Arc::clone(reradiation),
+                    scale,
+                )));
+            }
+        }
+
+        si.bsdf = Some(bsdf);
+    }
+}
+
+impl From<&TextureParams> for FluorescentMaterial {
+    /// Create a fluorescent material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let kd = tp
+            .get_spectrum_texture_or_else("Kd", Arc::new(ConstantTexture::new(Spectrum::new(0.5))));
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+
+        // The re-radiation matrix is read from a file or given inline.
+        let find_floats = |name: &str| {
+            let values = tp.geom_params.find_float(name);
+            if values.is_empty() {
+                tp.mat_params.find_float(name)
+            } else {
+                values
+            }
+        };
+        let path = tp.find_filename("filename", String::from(""));
+        let reradiation = if !path.is_empty() {
+            ReradiationMatrix::from_file(&path)
+                .map_err(|err| format!("Unable to load re-radiation matrix '{}'. {}.", path, err))
+        } else {
+            ReradiationMatrix::new(
+                &find_floats("excitation"),
+                &find_floats("emission"),
+                &find_floats("reradiation"),
+            )
+            .map_err(|err| format!("Invalid re-radiation matrix. {}.", err))
+        };
+
+        // A textured scale can only be checked where it is evaluated.
+        let scale = tp.get_float_texture_or_else("scale", Arc::new(ConstantTexture::new(1.0)));
+        let reradiation = match reradiation {
+            Ok(m) => {
+                if m.max_efficiency() * tp.find_float("scale", 1.0) > 1.0 {
+                    warn!("Re-radiation matrix emits more light than it absorbs.");
+                }
+                Some(Arc::new(m))
+            }
+            Err(err) => {
+                error!("{} Material will not fluoresce.", err);
+                None
+            }
+        };
+
+        Self::new(kd, reradiation, scale, bump_map, normal_map)
+    }
+}
@@ -2,9 +2,11 @@
 
 use core::geometry::*;
 use core::material::*;
+use core::mipmap::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::reflection::*;
+use core::spectrum::*;
 use core::texture::*;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -21,15 +23,23 @@ pub struct FourierMaterial {
 
     /// Bump map.
     bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
 }
 
 impl FourierMaterial {
     /// Create a new `FourierMaterial`.
     ///
     ///
-    /// * `path`     - Path to the Fourier BSDF data file.
-    /// * `bump_map` - Optional bump map.
-    pub fn new(path: &str, bump_map: Option<ArcTexture<Float>>) -> Self {
+    /// * `path`       - Path to the Fourier BSDF data file.
+    /// * `bump_map`   - Optional bump map.
+    /// * `normal_map` - Optional normal map.
+    pub fn new(
+        path: &str,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+    ) -> Self {
         let key = String::from(path);
 
         // Use preloaded BSDF data if available.
@@ -44,7 +54,8 @@ impl FourierMaterial {
                     t
                 }
                 Err(err) => {
-                    panic!("Unable to load file {}. {:}.", path, err);
+                    error!("Unable to load file '{}'. {}", path, err);
+                    Arc::new(FourierBSDFTable::default())
                 }
             }
         };
@@ -52,6 +63,7 @@ impl FourierMaterial {
         Self {
             bsdf_table,
             bump_map: bump_map.clone(),
+            normal_map,
         }
     }
 }
@@ -72,10 +84,8 @@ impl Material for FourierMaterial {
         mode: TransportMode,
         _allow_multiple_lobes: bool,
     ) {
-        // Perform bump mapping with `bump_map`, if present.
-        if let Some(bump_map) = self.bump_map.clone() {
-            Material::bump(self, bump_map, si);
-        }
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
 
         let mut bsdf = BSDF::new(&si, None);
 
@@ -98,7 +108,8 @@ impl From<&TextureParams> for FourierMaterial {
     /// * `tp` - Texture parameter set.
     fn from(tp: &TextureParams) -> Self {
         let bump_map = tp.get_float_texture("bumpmap");
-        let path = tp.find_filename("bsdfffile", String::from(""));
-        Self::new(&path, bump_map)
+        let normal_map = tp.get_normal_map();
+        let path = tp.find_filename("bsdffile", String::from(""));
+        Self::new(&path, bump_map, normal_map)
     }
 }
@@ -0,0 +1,166 @@
+//! Hair Material
+
+use core::geometry::*;
+use core::material::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+
+/// Specifies how the absorption coefficient of the hair interior is given.
+#[derive(Clone)]
+pub enum HairAbsorption {
+    /// Absorption coefficient given directly.
+    SigmaA(ArcTexture<Spectrum>),
+
+    /// Hair color from which the absorption coefficient is derived.
+    Color(ArcTexture<Spectrum>),
+
+    /// Concentrations of eumelanin and pheomelanin pigments.
+    Melanin {
+        eumelanin: Option<ArcTexture<Float>>,
+        pheomelanin: Option<ArcTexture<Float>>,
+    },
+}
+
+/// Implements hair material for curve shapes. The offset across the width of
+/// the curve is taken from the `v` parametric coordinate.
+pub struct HairMaterial {
+    /// Absorption coefficient of the hair interior.
+    absorption: HairAbsorption,
+
+    /// Index of refraction of the hair interior.
+    eta: ArcTexture<Float>,
+
+    /// Longitudinal roughness.
+    beta_m: ArcTexture<Float>,
+
+    /// Azimuthal roughness.
+    beta_n: ArcTexture<Float>,
+
+    /// Angle of the cuticle scales in degrees.
+    alpha: ArcTexture<Float>,
+}
+
+impl HairMaterial {
+    /// Create a new `HairMaterial`.
+    ///
+    /// * `absorption` - Absorption coefficient of the hair interior.
+    /// * `eta`        - Index of refraction of the hair interior.
+    /// * `beta_m`     - Longitudinal roughness.
+    /// * `beta_n`     - Azimuthal roughness.
+    /// * `alpha`      - Angle of the cuticle scales in degrees.
+    pub fn new(
+        absorption: HairAbsorption,
+        eta: ArcTexture<Float>,
+        beta_m: ArcTexture<Float>,
+        beta_n: ArcTexture<Float>,
+        alpha: ArcTexture<Float>,
+    ) -> Self {
+        Self {
+            absorption,
+            eta: Arc::clone(&eta),
+            beta_m: Arc::clone(&beta_m),
+            beta_n: Arc::clone(&beta_n),
+            alpha: Arc::clone(&alpha),
+        }
+    }
+}
+
+impl Material for HairMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode (ignored).
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available (ignored).
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        let bm = clamp(self.beta_m.evaluate(si), 0.0, 1.0);
+        let bn = clamp(self.beta_n.evaluate(si), 0.0, 1.0);
+        let a = self.alpha.evaluate(si);
+        let e = self.eta.evaluate(si);
+
+        let mut bsdf = BSDF::new(si, Some(e));
+
+        // Compute absorption coefficient for the hair interior.
+        let sig_a = match &self.absorption {
+            HairAbsorption::SigmaA(sigma_a) => sigma_a.evaluate(si).clamp_default(),
+            HairAbsorption::Color(color) => {
+                let c = color.evaluate(si).clamp_default();
+                HairBSDF::sigma_a_from_reflectance(&c, bn)
+            }
+            HairAbsorption::Melanin {
+                eumelanin,
+                pheomelanin,
+            } => {
+                let ce = eumelanin.as_ref().map_or(0.0, |t| max(0.0, t.evaluate(si)));
+                let cp = pheomelanin
+                    .as_ref()
+                    .map_or(0.0, |t| max(0.0, t.evaluate(si)));
+                HairBSDF::sigma_a_from_concentration(ce, cp)
+            }
+        };
+
+        // Offset along width.
+        let h = -1.0 + 2.0 * si.uv[1];
+        bsdf.add(Arc::new(HairBSDF::new(h, e, sig_a, bm, bn, a)));
+
+        si.bsdf = Some(bsdf);
+    }
+}
+
+impl From<&TextureParams> for HairMaterial {
+    /// Create a hair material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let sigma_a = tp.get_spectrum_texture("sigma_a");
+        let color = tp.get_spectrum_texture("color");
+        let eumelanin = tp.get_float_texture("eumelanin");
+        let pheomelanin = tp.get_float_texture("pheomelanin");
+        let has_melanin = eumelanin.is_some() || pheomelanin.is_some();
+
+        let absorption = if let Some(sigma_a) = sigma_a {
+            if color.is_some() {
+                warn!("Ignoring 'color' parameter since 'sigma_a' was provided.");
+            }
+            if has_melanin {
+                warn!(
+                    "Ignoring 'eumelanin'/'pheomelanin' parameters since 'sigma_a' was provided."
+                );
+            }
+            HairAbsorption::SigmaA(sigma_a)
+        } else if let Some(color) = color {
+            if has_melanin {
+                warn!("Ignoring 'eumelanin'/'pheomelanin' parameters since 'color' was provided.");
+            }
+            HairAbsorption::Color(color)
+        } else if has_melanin {
+            HairAbsorption::Melanin {
+                eumelanin,
+                pheomelanin,
+            }
+        } else {
+            // Default to brown-ish hair.
+            HairAbsorption::SigmaA(Arc::new(ConstantTexture::new(
+                HairBSDF::sigma_a_from_concentration(1.3, 0.0),
+            )))
+        };
+
+        let eta = tp.get_float_texture_or_else("eta", Arc::new(ConstantTexture::new(1.55)));
+        let beta_m = tp.get_float_texture_or_else("beta_m", Arc::new(ConstantTexture::new(0.3)));
+        let beta_n = tp.get_float_texture_or_else("beta_n", Arc::new(ConstantTexture::new(0.3)));
+        let alpha = tp.get_float_texture_or_else("alpha", Arc::new(ConstantTexture::new(2.0)));
+        Self::new(absorption, eta, beta_m, beta_n, alpha)
+    }
+}
@@ -0,0 +1,209 @@
+//! Kd Subsurface Material
+
+use crate::subsurface::dielectric_boundary_bsdf;
+use core::bssrdf::*;
+use core::geometry::*;
+use core::material::*;
+use core::medium::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+
+/// Implements a translucent material with subsurface light transport given by
+/// its diffuse reflectance and mean free path instead of the scattering
+/// coefficients. The surface is a dielectric boundary.
+pub struct KdSubsurfaceMaterial {
+    /// Scale factor converting the mean free path to scene units.
+    scale: Float,
+
+    /// Spectral diffuse reflection from subsurface scattering.
+    kd: ArcTexture<Spectrum>,
+
+    /// Spectral reflection of the dielectric boundary.
+    kr: ArcTexture<Spectrum>,
+
+    /// Spectral transmission of the dielectric boundary.
+    kt: ArcTexture<Spectrum>,
+
+    /// Mean free path.
+    mfp: ArcTexture<Spectrum>,
+
+    /// Microfacet roughness in the u direction.
+    u_roughness: ArcTexture<Float>,
+
+    /// Microfacet roughness in the v direction.
+    v_roughness: ArcTexture<Float>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+
+    /// Index of refraction of the interior.
+    eta: Float,
+
+    /// Remap roughness value to [0, 1] where higher values represent larger
+    /// highlights. If this is `false`, use the microfacet distributions `alpha`
+    /// parameter.
+    remap_roughness: bool,
+
+    /// The tabulated scattering profiles.
+    table: Arc<BSSRDFTable>,
+}
+
+impl KdSubsurfaceMaterial {
+    /// Create a new `KdSubsurfaceMaterial`.
+    ///
+    /// * `scale`           - Scale factor converting the mean free path to
+    ///                       scene units.
+    /// * `kd`              - Spectral diffuse reflection from subsurface
+    ///                       scattering.
+    /// * `kr`              - Spectral reflection of the dielectric boundary.
+    /// * `kt`              - Spectral transmission of the dielectric boundary.
+    /// * `mfp`             - Mean free path.
+    /// * `g`               - The asymmetry parameter of the phase function.
+    /// * `eta`             - Index of refraction of the interior.
+    /// * `u_roughness`     - Microfacet roughness in the u direction.
+    /// * `v_roughness`     - Microfacet roughness in the v direction.
+    /// * `bump_map`        - Optional bump map.
+    /// * `normal_map`      - Optional normal map.
+    /// * `remap_roughness` - Remap roughness value to [0, 1] where higher
+    ///                       values represent larger highlights. If this is
+    ///                       `false`, use the microfacet distributions `alpha`
+    ///                       parameter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        scale: Float,
+        kd: ArcTexture<Spectrum>,
+        kr: ArcTexture<Spectrum>,
+        kt: ArcTexture<Spectrum>,
+        mfp: ArcTexture<Spectrum>,
+        g: Float,
+        eta: Float,
+        u_roughness: ArcTexture<Float>,
+        v_roughness: ArcTexture<Float>,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+        remap_roughness: bool,
+    ) -> Self {
+        Self {
+            scale,
+            kd: Arc::clone(&kd),
+            kr: Arc::clone(&kr),
+            kt: Arc::clone(&kt),
+            mfp: Arc::clone(&mfp),
+            u_roughness: Arc::clone(&u_roughness),
+            v_roughness: Arc::clone(&v_roughness),
+            bump_map: bump_map.clone(),
+            normal_map,
+            eta,
+            remap_roughness,
+            table: Arc::new(compute_beam_diffusion_bssrdf(g, eta)),
+        }
+    }
+}
+
+impl Material for KdSubsurfaceMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode.
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available.
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        mode: TransportMode,
+        allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        // Initialize BSDF for the dielectric boundary.
+        let bsdf = dielectric_boundary_bsdf(
+            si,
+            mode,
+            allow_multiple_lobes,
+            self.eta,
+            &self.kr,
+            &self.kt,
+            (&self.u_roughness, &self.v_roughness),
+            self.remap_roughness,
+        );
+        si.bsdf = Some(bsdf);
+
+        // Find the coefficients that give the diffuse reflectance and
+        // initialize the BSSRDF.
+        let mfree = self.scale * self.mfp.evaluate(si).clamp_default();
+        let kd = self.kd.evaluate(si).clamp_default();
+        let props = subsurface_from_diffuse(&self.table, &kd, &mfree);
+        let bssrdf = TabulatedBSSRDF::new(
+            si,
+            self.eta,
+            mode,
+            &props.sigma_a,
+            &props.sigma_s,
+            Arc::clone(&self.table),
+        );
+        si.bssrdf = Some(Arc::new(bssrdf));
+    }
+}
+
+impl From<&TextureParams> for KdSubsurfaceMaterial {
+    /// Create a Kd subsurface material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let kd = tp
+            .get_spectrum_texture_or_else("Kd", Arc::new(ConstantTexture::new(Spectrum::new(0.5))));
+        let mfp = tp.get_spectrum_texture_or_else(
+            "mfp",
+            Arc::new(ConstantTexture::new(Spectrum::new(1.0))),
+        );
+        let kr = tp
+            .get_spectrum_texture_or_else("Kr", Arc::new(ConstantTexture::new(Spectrum::new(1.0))));
+        let kt = tp
+            .get_spectrum_texture_or_else("Kt", Arc::new(ConstantTexture::new(Spectrum::new(1.0))));
+        let g = tp.find_float("g", 0.0);
+        let eta = tp.find_float("eta", 1.33);
+
+        // Scale the mean free path and convert it to scene units like the
+        // coefficients of participating media.
+        let units = tp.find_string("units", String::new());
+        let scene_units = tp.find_string("sceneunits", String::from("m"));
+        let scale =
+            tp.find_float("scale", 1.0) / ScatteringProperties::unit_scale(&units, &scene_units);
+        if tp.find_texture("mfp", String::new()).is_empty() {
+            let mfp = tp.find_spectrum("mfp", Spectrum::new(1.0));
+            ScatteringProperties::check_mean_free_path(scale * mfp.max_component_value());
+        }
+        let u_roughness =
+            tp.get_float_texture_or_else("uroughness", Arc::new(ConstantTexture::new(0.0)));
+        let v_roughness =
+            tp.get_float_texture_or_else("vroughness", Arc::new(ConstantTexture::new(0.0)));
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+        let remap_roughness = tp.find_bool("remaproughness", true);
+        Self::new(
+            scale,
+            kd,
+            kr,
+            kt,
+            mfp,
+            g,
+            eta,
+            u_roughness,
+            v_roughness,
+            bump_map,
+            normal_map,
+            remap_roughness,
+        )
+    }
+}
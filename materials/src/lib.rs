@@ -2,14 +2,32 @@
 
 #[macro_use]
 extern crate lazy_static;
+#[macro_use]
+extern crate log;
 
+#[cfg(feature = "sampled-spectrum")]
+mod fluorescent;
 mod fourier;
+mod hair;
+mod kdsubsurface;
 mod matte;
+mod measured;
 mod mix;
 mod plastic;
+mod substrate;
+mod subsurface;
+mod translucent;
 
 // Re-export
+#[cfg(feature = "sampled-spectrum")]
+pub use fluorescent::*;
 pub use fourier::*;
+pub use hair::*;
+pub use kdsubsurface::*;
 pub use matte::*;
+pub use measured::*;
 pub use mix::*;
 pub use plastic::*;
+pub use substrate::*;
+pub use subsurface::*;
+pub use translucent::*;
@@ -2,13 +2,13 @@
 
 use core::geometry::*;
 use core::material::*;
+use core::mipmap::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::reflection::*;
 use core::spectrum::*;
 use core::texture::*;
 use std::sync::Arc;
-use textures::*;
 
 /// Implements purely diffuse surfaces.
 pub struct MatteMaterial {
@@ -20,24 +20,30 @@ pub struct MatteMaterial {
 
     /// Bump map.
     bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
 }
 
 impl MatteMaterial {
     /// Create a new `MatteMaterial`.
     ///
     ///
-    /// * `kd`       - Spectral diffuse reflection.
-    /// * `sigma`    - Roughness.
-    /// * `bump_map` - Optional bump map.
+    /// * `kd`         - Spectral diffuse reflection.
+    /// * `sigma`      - Roughness.
+    /// * `bump_map`   - Optional bump map.
+    /// * `normal_map` - Optional normal map.
     pub fn new(
         kd: ArcTexture<Spectrum>,
         sigma: ArcTexture<Float>,
         bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
     ) -> Self {
         Self {
             kd: Arc::clone(&kd),
             sigma: Arc::clone(&sigma),
             bump_map: bump_map.clone(),
+            normal_map,
         }
     }
 }
@@ -58,10 +64,8 @@ impl Material for MatteMaterial {
         _mode: TransportMode,
         _allow_multiple_lobes: bool,
     ) {
-        // Perform bump mapping with `bump_map`, if present.
-        if let Some(bump_map) = self.bump_map.clone() {
-            Material::bump(self, bump_map, si);
-        }
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
 
         let mut bsdf = BSDF::new(&si, None);
 
@@ -89,6 +93,7 @@ impl From<&TextureParams> for MatteMaterial {
             .get_spectrum_texture_or_else("Kd", Arc::new(ConstantTexture::new(Spectrum::new(0.5))));
         let sigma = tp.get_float_texture_or_else("sigma", Arc::new(ConstantTexture::new(0.0)));
         let bump_map = tp.get_float_texture("bumpmap");
-        Self::new(kd, sigma, bump_map)
+        let normal_map = tp.get_normal_map();
+        Self::new(kd, sigma, bump_map, normal_map)
     }
 }
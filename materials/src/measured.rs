@@ -0,0 +1,143 @@
+//! Measured Material
+
+use core::geometry::*;
+use core::material::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    /// Caches BRDF table data by file path.
+    static ref BRDF_TABLES: Mutex<HashMap<String, MeasuredBRDFTable>> = Mutex::new(HashMap::new());
+}
+
+/// Measured BRDF data loaded from a file.
+#[derive(Clone)]
+enum MeasuredBRDFTable {
+    /// Isotropic BRDF from the MERL database (`.binary` files).
+    Merl(Arc<MERLBRDFTable>),
+
+    /// BRDF from the RGL material database (`.bsdf` files).
+    Rgl(Arc<RGLBRDFTable>),
+}
+
+impl MeasuredBRDFTable {
+    /// Loads a measured BRDF choosing the format from the file extension.
+    ///
+    /// * `path` - Path to the BRDF data file.
+    fn from_file(path: &str) -> Result<Self, String> {
+        if path.ends_with(".binary") {
+            MERLBRDFTable::from_file(path).map(|table| Self::Merl(Arc::new(table)))
+        } else if path.ends_with(".bsdf") {
+            RGLBRDFTable::from_file(path).map(|table| Self::Rgl(Arc::new(table)))
+        } else {
+            Err(String::from(
+                "Expected a MERL '.binary' or RGL '.bsdf' file",
+            ))
+        }
+    }
+}
+
+/// Implements materials using measured BRDF data from the MERL or RGL
+/// material databases.
+pub struct MeasuredMaterial {
+    /// The measured BRDF data or `None` if it could not be loaded.
+    brdf_table: Option<MeasuredBRDFTable>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+}
+
+impl MeasuredMaterial {
+    /// Create a new `MeasuredMaterial`.
+    ///
+    /// * `path`       - Path to the BRDF data file.
+    /// * `bump_map`   - Optional bump map.
+    /// * `normal_map` - Optional normal map.
+    pub fn new(
+        path: &str,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+    ) -> Self {
+        let key = String::from(path);
+
+        // Use preloaded BRDF data if available.
+        let mut tables = BRDF_TABLES.lock().unwrap();
+        let brdf_table = if let Some(table) = tables.get(&key) {
+            Some(table.clone())
+        } else {
+            match MeasuredBRDFTable::from_file(path) {
+                Ok(table) => {
+                    tables.insert(key, table.clone());
+                    Some(table)
+                }
+                Err(err) => {
+                    error!("Unable to load measured BRDF '{}'. {}.", path, err);
+                    None
+                }
+            }
+        };
+
+        Self {
+            brdf_table,
+            bump_map: bump_map.clone(),
+            normal_map,
+        }
+    }
+}
+
+impl Material for MeasuredMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode (ignored).
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available (ignored).
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        let mut bsdf = BSDF::new(si, None);
+
+        // Surfaces with missing data don't scatter light.
+        match &self.brdf_table {
+            Some(MeasuredBRDFTable::Merl(table)) => {
+                bsdf.add(Arc::new(MERLBRDF::new(Arc::clone(table))));
+            }
+            Some(MeasuredBRDFTable::Rgl(table)) => {
+                bsdf.add(Arc::new(RGLBRDF::new(Arc::clone(table))));
+            }
+            None => {}
+        }
+
+        si.bsdf = Some(bsdf);
+    }
+}
+
+impl From<&TextureParams> for MeasuredMaterial {
+    /// Create a measured material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+        let path = tp.find_filename("filename", String::from(""));
+        Self::new(&path, bump_map, normal_map)
+    }
+}
@@ -8,7 +8,6 @@ use core::reflection::*;
 use core::spectrum::*;
 use core::texture::*;
 use std::sync::Arc;
-use textures::*;
 
 /// Combines two materials with varying weights.
 pub struct MixMaterial {
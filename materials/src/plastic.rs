@@ -3,13 +3,13 @@
 use core::geometry::*;
 use core::material::*;
 use core::microfacet::*;
+use core::mipmap::*;
 use core::paramset::*;
 use core::pbrt::*;
 use core::reflection::*;
 use core::spectrum::*;
 use core::texture::*;
 use std::sync::Arc;
-use textures::*;
 
 /// Implements plastic material.
 pub struct PlasticMaterial {
@@ -25,36 +25,65 @@ pub struct PlasticMaterial {
     /// Bump map.
     bump_map: Option<ArcTexture<Float>>,
 
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+
     /// Remap roughness value to [0, 1] where higher values represent larger
     /// highlights. If this is `false`, use the microfacet distributions `alpha`
     /// parameter.
     remap_roughness: bool,
+
+    /// Add back energy lost by single scattering at high roughness.
+    energy_compensation: bool,
+
+    /// Weight the diffuse reflection by the Fresnel transmittance of the
+    /// coating so it fades at grazing angles.
+    fresnel_weighted: bool,
+
+    /// Index of refraction of the coating.
+    eta: Float,
 }
 
 impl PlasticMaterial {
     /// Create a new `PlasticMaterial`.
     ///
     ///
-    /// * `kd`              - Spectral diffuse reflection.
-    /// * `ks`              - Spectral specular reflection.
-    /// * `roughness`       - Roughness.
-    /// * `remap_roughness` - Remap roughness value to [0, 1] where higher values
-    ///                       represent larger highlights. If this is `false`,
-    ///                       use the microfacet distributions `alpha` parameter.
-    /// * `bump_map`        - Optional bump map.
+    /// * `kd`                  - Spectral diffuse reflection.
+    /// * `ks`                  - Spectral specular reflection.
+    /// * `roughness`           - Roughness.
+    /// * `remap_roughness`     - Remap roughness value to [0, 1] where higher
+    ///                           values represent larger highlights. If this is
+    ///                           `false`, use the microfacet distributions
+    ///                           `alpha` parameter.
+    /// * `energy_compensation` - Add back energy lost by single scattering at
+    ///                           high roughness.
+    /// * `fresnel_weighted`    - Weight the diffuse reflection by the Fresnel
+    ///                           transmittance of the coating.
+    /// * `eta`                 - Index of refraction of the coating.
+    /// * `bump_map`            - Optional bump map.
+    /// * `normal_map`          - Optional normal map.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         kd: ArcTexture<Spectrum>,
         ks: ArcTexture<Spectrum>,
         roughness: ArcTexture<Float>,
         remap_roughness: bool,
+        energy_compensation: bool,
+        fresnel_weighted: bool,
+        eta: Float,
         bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
     ) -> Self {
         Self {
             kd: Arc::clone(&kd),
             ks: Arc::clone(&ks),
             roughness: Arc::clone(&roughness),
             remap_roughness,
+            energy_compensation,
+            fresnel_weighted,
+            eta,
             bump_map: bump_map.clone(),
+            normal_map,
         }
     }
 }
@@ -75,32 +104,39 @@ impl Material for PlasticMaterial {
         _mode: TransportMode,
         _allow_multiple_lobes: bool,
     ) {
-        // Perform bump mapping with `bump_map`, if present.
-        if let Some(bump_map) = self.bump_map.clone() {
-            Material::bump(self, bump_map, si);
-        }
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
 
         let mut bsdf = BSDF::new(&si, None);
 
         // Initialize diffuse component of plastic material.
         let kd = self.kd.evaluate(si).clamp_default();
         if !kd.is_black() {
-            bsdf.add(Arc::new(LambertianReflection::new(kd)));
+            if self.fresnel_weighted {
+                bsdf.add(Arc::new(FresnelWeightedLambertian::new(kd, self.eta)));
+            } else {
+                bsdf.add(Arc::new(LambertianReflection::new(kd)));
+            }
         }
 
         // Initialize specular component of plastic material.
         let ks = self.ks.evaluate(si).clamp_default();
         if !ks.is_black() {
-            let fresnel = Arc::new(FresnelDielectric::new(1.5, 1.0));
+            let fresnel = Arc::new(FresnelDielectric::new(self.eta, 1.0));
 
             // Create microfacet distribution for plastic material.
             let mut rough = self.roughness.evaluate(si);
             if self.remap_roughness {
                 rough = TrowbridgeReitzDistribution::roughness_to_alpha(rough);
             }
-            let distrib = Arc::new(TrowbridgeReitzDistribution::new(rough, rough, true));
-            let spec = MicrofacetReflection::new(ks, distrib, fresnel);
+            let distrib: ArcMicrofacetDistribution =
+                Arc::new(TrowbridgeReitzDistribution::new(rough, rough, true));
+            let spec = MicrofacetReflection::new(ks, Arc::clone(&distrib), fresnel);
             bsdf.add(Arc::new(spec));
+
+            if self.energy_compensation {
+                bsdf.add(Arc::new(MicrofacetMultipleScattering::new(ks, distrib)));
+            }
         }
 
         si.bsdf = Some(bsdf);
@@ -123,7 +159,95 @@ impl From<&TextureParams> for PlasticMaterial {
         let roughness =
             tp.get_float_texture_or_else("roughness", Arc::new(ConstantTexture::new(0.1)));
         let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
         let remap_roughness = tp.find_bool("remaproughness", true);
-        Self::new(kd, ks, roughness, remap_roughness, bump_map)
+        let energy_compensation = tp.find_bool("energycompensation", false);
+        let fresnel_weighted = tp.find_bool("fresnelweighted", false);
+        let eta = tp.find_float("eta", 1.5);
+        Self::new(
+            kd,
+            ks,
+            roughness,
+            remap_roughness,
+            energy_compensation,
+            fresnel_weighted,
+            eta,
+            bump_map,
+            normal_map,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Returns the diffuse reflection of a plastic material without a
+    /// specular coating lobe for light arriving along the normal.
+    ///
+    /// * `fresnel_weighted` - Weight the diffuse reflection by the Fresnel
+    ///                        transmittance of the coating.
+    /// * `eta`              - Index of refraction of the coating.
+    /// * `wo`               - Outgoing direction.
+    fn diffuse_reflection(fresnel_weighted: bool, eta: Option<Float>, wo: &Vector3f) -> Float {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("Ks", &[0.0, 0.0, 0.0]);
+        params.add_bool("fresnelweighted", &[fresnel_weighted]);
+        if let Some(eta) = eta {
+            params.add_float("eta", &[eta]);
+        }
+        let tp = TextureParams::new(ParamSet::new(), params, HashMap::new(), HashMap::new());
+        let material = PlasticMaterial::from(&tp);
+
+        let identity = Arc::new(Transform::default());
+        let shape_data = Arc::new(ShapeData::new(Arc::clone(&identity), Some(identity), false));
+        let mut si = SurfaceInteraction::new(
+            Point3f::default(),
+            Vector3f::default(),
+            Point2f::default(),
+            *wo,
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Normal3f::default(),
+            Normal3f::default(),
+            0.0,
+            shape_data,
+            None,
+        );
+        material.compute_scattering_functions(&mut si, TransportMode::Radiance, false);
+
+        let wi = Vector3f::new(0.0, 0.0, 1.0);
+        let bsdf = si.bsdf.as_ref().unwrap();
+        bsdf.f(wo, &wi, BxDFType::from(BSDF_ALL))[0]
+    }
+
+    #[test]
+    fn fresnel_weighted_diffuse_fades_at_grazing_angles() {
+        let normal = Vector3f::new(0.0, 0.0, 1.0);
+        let grazing = Vector3f::new(0.995, 0.0, 0.1).normalize();
+
+        // Plain Lambertian reflection is the same in all directions.
+        let plain = diffuse_reflection(false, None, &normal);
+        assert!((plain - 0.25 * INV_PI).abs() < 1e-6);
+        assert!((diffuse_reflection(false, None, &grazing) - plain).abs() < 1e-6);
+
+        // Light passes the coating twice with 4% reflected each time at
+        // normal incidence with eta = 1.5.
+        let weighted = diffuse_reflection(true, None, &normal);
+        assert!((weighted - plain * 0.96 * 0.96).abs() < 1e-4);
+        assert!(diffuse_reflection(true, None, &grazing) < 0.7 * weighted);
+
+        // A denser coating reflects 1/9 at normal incidence with eta = 2 and
+        // a matched one doesn't reflect at all.
+        let dense = diffuse_reflection(true, Some(2.0), &normal);
+        let t = 1.0 - 1.0 / 9.0;
+        assert!((dense - plain * t * t).abs() < 1e-4);
+        let matched = diffuse_reflection(true, Some(1.0), &grazing);
+        assert!((matched - plain).abs() < 1e-6);
     }
 }
@@ -0,0 +1,140 @@
+//! Substrate Material
+
+use core::geometry::*;
+use core::material::*;
+use core::microfacet::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+
+/// Implements layered surfaces with a glossy coating over a diffuse base
+/// using the Ashikhmin-Shirley model.
+pub struct SubstrateMaterial {
+    /// Spectral diffuse reflection of the base.
+    kd: ArcTexture<Spectrum>,
+
+    /// Spectral specular reflection of the coating.
+    ks: ArcTexture<Spectrum>,
+
+    /// Microfacet roughness in the u direction.
+    u_roughness: ArcTexture<Float>,
+
+    /// Microfacet roughness in the v direction.
+    v_roughness: ArcTexture<Float>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+
+    /// Remap roughness value to [0, 1] where higher values represent larger
+    /// highlights. If this is `false`, use the microfacet distributions `alpha`
+    /// parameter.
+    remap_roughness: bool,
+}
+
+impl SubstrateMaterial {
+    /// Create a new `SubstrateMaterial`.
+    ///
+    /// * `kd`              - Spectral diffuse reflection of the base.
+    /// * `ks`              - Spectral specular reflection of the coating.
+    /// * `u_roughness`     - Microfacet roughness in the u direction.
+    /// * `v_roughness`     - Microfacet roughness in the v direction.
+    /// * `remap_roughness` - Remap roughness value to [0, 1] where higher
+    ///                       values represent larger highlights. If this is
+    ///                       `false`, use the microfacet distributions `alpha`
+    ///                       parameter.
+    /// * `bump_map`        - Optional bump map.
+    /// * `normal_map`      - Optional normal map.
+    pub fn new(
+        kd: ArcTexture<Spectrum>,
+        ks: ArcTexture<Spectrum>,
+        u_roughness: ArcTexture<Float>,
+        v_roughness: ArcTexture<Float>,
+        remap_roughness: bool,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+    ) -> Self {
+        Self {
+            kd: Arc::clone(&kd),
+            ks: Arc::clone(&ks),
+            u_roughness: Arc::clone(&u_roughness),
+            v_roughness: Arc::clone(&v_roughness),
+            remap_roughness,
+            bump_map: bump_map.clone(),
+            normal_map,
+        }
+    }
+}
+
+impl Material for SubstrateMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode (ignored).
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available (ignored).
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        _mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        let mut bsdf = BSDF::new(si, None);
+
+        let d = self.kd.evaluate(si).clamp_default();
+        let s = self.ks.evaluate(si).clamp_default();
+        if !d.is_black() || !s.is_black() {
+            let mut u_rough = self.u_roughness.evaluate(si);
+            let mut v_rough = self.v_roughness.evaluate(si);
+            if self.remap_roughness {
+                u_rough = TrowbridgeReitzDistribution::roughness_to_alpha(u_rough);
+                v_rough = TrowbridgeReitzDistribution::roughness_to_alpha(v_rough);
+            }
+            let distrib: ArcMicrofacetDistribution =
+                Arc::new(TrowbridgeReitzDistribution::new(u_rough, v_rough, true));
+            bsdf.add(Arc::new(FresnelBlend::new(d, s, distrib)));
+        }
+
+        si.bsdf = Some(bsdf);
+    }
+}
+
+impl From<&TextureParams> for SubstrateMaterial {
+    /// Create a substrate material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let kd = tp
+            .get_spectrum_texture_or_else("Kd", Arc::new(ConstantTexture::new(Spectrum::new(0.5))));
+        let ks = tp
+            .get_spectrum_texture_or_else("Ks", Arc::new(ConstantTexture::new(Spectrum::new(0.5))));
+        let u_roughness =
+            tp.get_float_texture_or_else("uroughness", Arc::new(ConstantTexture::new(0.1)));
+        let v_roughness =
+            tp.get_float_texture_or_else("vroughness", Arc::new(ConstantTexture::new(0.1)));
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+        let remap_roughness = tp.find_bool("remaproughness", true);
+        Self::new(
+            kd,
+            ks,
+            u_roughness,
+            v_roughness,
+            remap_roughness,
+            bump_map,
+            normal_map,
+        )
+    }
+}
@@ -0,0 +1,324 @@
+//! Subsurface Material
+
+use core::bssrdf::*;
+use core::geometry::*;
+use core::material::*;
+use core::medium::*;
+use core::microfacet::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+use textures::*;
+
+/// Implements a translucent material with subsurface light transport given by
+/// the absorption and scattering coefficients of its interior. The surface
+/// is a dielectric boundary.
+pub struct SubsurfaceMaterial {
+    /// Spectral reflection of the dielectric boundary.
+    kr: ArcTexture<Spectrum>,
+
+    /// Spectral transmission of the dielectric boundary.
+    kt: ArcTexture<Spectrum>,
+
+    /// Absorption coefficient σa in scene units.
+    sigma_a: ArcTexture<Spectrum>,
+
+    /// Scattering coefficient σs in scene units.
+    sigma_s: ArcTexture<Spectrum>,
+
+    /// Microfacet roughness in the u direction.
+    u_roughness: ArcTexture<Float>,
+
+    /// Microfacet roughness in the v direction.
+    v_roughness: ArcTexture<Float>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+
+    /// Index of refraction of the interior.
+    eta: Float,
+
+    /// Remap roughness value to [0, 1] where higher values represent larger
+    /// highlights. If this is `false`, use the microfacet distributions `alpha`
+    /// parameter.
+    remap_roughness: bool,
+
+    /// The tabulated scattering profiles.
+    table: Arc<BSSRDFTable>,
+}
+
+impl SubsurfaceMaterial {
+    /// Create a new `SubsurfaceMaterial`.
+    ///
+    /// * `kr`              - Spectral reflection of the dielectric boundary.
+    /// * `kt`              - Spectral transmission of the dielectric boundary.
+    /// * `sigma_a`         - Absorption coefficient σa in scene units.
+    /// * `sigma_s`         - Scattering coefficient σs in scene units.
+    /// * `g`               - The asymmetry parameter of the phase function.
+    /// * `eta`             - Index of refraction of the interior.
+    /// * `u_roughness`     - Microfacet roughness in the u direction.
+    /// * `v_roughness`     - Microfacet roughness in the v direction.
+    /// * `bump_map`        - Optional bump map.
+    /// * `normal_map`      - Optional normal map.
+    /// * `remap_roughness` - Remap roughness value to [0, 1] where higher
+    ///                       values represent larger highlights. If this is
+    ///                       `false`, use the microfacet distributions `alpha`
+    ///                       parameter.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        kr: ArcTexture<Spectrum>,
+        kt: ArcTexture<Spectrum>,
+        sigma_a: ArcTexture<Spectrum>,
+        sigma_s: ArcTexture<Spectrum>,
+        g: Float,
+        eta: Float,
+        u_roughness: ArcTexture<Float>,
+        v_roughness: ArcTexture<Float>,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+        remap_roughness: bool,
+    ) -> Self {
+        Self {
+            kr: Arc::clone(&kr),
+            kt: Arc::clone(&kt),
+            sigma_a: Arc::clone(&sigma_a),
+            sigma_s: Arc::clone(&sigma_s),
+            u_roughness: Arc::clone(&u_roughness),
+            v_roughness: Arc::clone(&v_roughness),
+            bump_map: bump_map.clone(),
+            normal_map,
+            eta,
+            remap_roughness,
+            table: Arc::new(compute_beam_diffusion_bssrdf(g, eta)),
+        }
+    }
+}
+
+impl Material for SubsurfaceMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode.
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available.
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        mode: TransportMode,
+        allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        // Initialize BSDF for the dielectric boundary.
+        let bsdf = dielectric_boundary_bsdf(
+            si,
+            mode,
+            allow_multiple_lobes,
+            self.eta,
+            &self.kr,
+            &self.kt,
+            (&self.u_roughness, &self.v_roughness),
+            self.remap_roughness,
+        );
+        si.bsdf = Some(bsdf);
+
+        // Initialize the BSSRDF from the coefficients.
+        let sig_a = self.sigma_a.evaluate(si).clamp_default();
+        let sig_s = self.sigma_s.evaluate(si).clamp_default();
+        let bssrdf =
+            TabulatedBSSRDF::new(si, self.eta, mode, &sig_a, &sig_s, Arc::clone(&self.table));
+        si.bssrdf = Some(Arc::new(bssrdf));
+    }
+}
+
+impl From<&TextureParams> for SubsurfaceMaterial {
+    /// Create a subsurface material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        // The table of measured media gives reduced scattering coefficients.
+        let mut g = tp.find_float("g", 0.0);
+        let name = tp.find_string("name", String::new());
+        if g != 0.0 && get_medium_scattering_properties(&name).is_some() {
+            warn!("Ignoring 'g' {} for named material '{}'.", g, name);
+            g = 0.0;
+        }
+
+        // Scale the coefficients and convert them to scene units like those
+        // of participating media. Textures are scaled the same way.
+        let props = ScatteringProperties::from(tp);
+        let coefficient = |param: &str, constant: Spectrum| -> ArcTexture<Spectrum> {
+            if tp.find_texture(param, String::new()).is_empty() {
+                return Arc::new(ConstantTexture::new(constant));
+            }
+            let units = tp.find_string("units", String::new());
+            let scene_units = tp.find_string("sceneunits", String::from("m"));
+            let scale = tp.find_float("scale", 1.0)
+                * ScatteringProperties::unit_scale(&units, &scene_units);
+            Arc::new(ScaleTexture::new(
+                tp.get_spectrum_texture_or_else(param, Arc::new(ConstantTexture::new(constant))),
+                Arc::new(ConstantTexture::new(Spectrum::new(scale))),
+            ))
+        };
+        let sigma_a = coefficient("sigma_a", props.sigma_a);
+        let sigma_s = coefficient("sigma_s", props.sigma_s);
+        let eta = tp.find_float("eta", 1.33);
+        let kr = tp
+            .get_spectrum_texture_or_else("Kr", Arc::new(ConstantTexture::new(Spectrum::new(1.0))));
+        let kt = tp
+            .get_spectrum_texture_or_else("Kt", Arc::new(ConstantTexture::new(Spectrum::new(1.0))));
+        let u_roughness =
+            tp.get_float_texture_or_else("uroughness", Arc::new(ConstantTexture::new(0.0)));
+        let v_roughness =
+            tp.get_float_texture_or_else("vroughness", Arc::new(ConstantTexture::new(0.0)));
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+        let remap_roughness = tp.find_bool("remaproughness", true);
+        Self::new(
+            kr,
+            kt,
+            sigma_a,
+            sigma_s,
+            g,
+            eta,
+            u_roughness,
+            v_roughness,
+            bump_map,
+            normal_map,
+            remap_roughness,
+        )
+    }
+}
+
+/// Returns the BSDF of the dielectric boundary of a subsurface scattering
+/// material. The boundary is specular if both roughness values are zero.
+///
+/// * `si`                   - The surface interaction at the intersection.
+/// * `mode`                 - Transport mode.
+/// * `allow_multiple_lobes` - Indicates whether to use a single BxDF for
+///                            specular reflection and transmission.
+/// * `eta`                  - Index of refraction of the interior.
+/// * `kr`                   - Spectral reflection.
+/// * `kt`                   - Spectral transmission.
+/// * `roughness`            - Microfacet roughness in the u and v directions.
+/// * `remap_roughness`      - Remap roughness value to [0, 1].
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn dielectric_boundary_bsdf(
+    si: &SurfaceInteraction,
+    mode: TransportMode,
+    allow_multiple_lobes: bool,
+    eta: Float,
+    kr: &ArcTexture<Spectrum>,
+    kt: &ArcTexture<Spectrum>,
+    roughness: (&ArcTexture<Float>, &ArcTexture<Float>),
+    remap_roughness: bool,
+) -> BSDF {
+    let mut bsdf = BSDF::new(si, Some(eta));
+
+    let r = kr.evaluate(si).clamp_default();
+    let t = kt.evaluate(si).clamp_default();
+    if r.is_black() && t.is_black() {
+        return bsdf;
+    }
+
+    let mut u_rough = roughness.0.evaluate(si);
+    let mut v_rough = roughness.1.evaluate(si);
+    if remap_roughness {
+        u_rough = TrowbridgeReitzDistribution::roughness_to_alpha(u_rough);
+        v_rough = TrowbridgeReitzDistribution::roughness_to_alpha(v_rough);
+    }
+
+    let is_specular = u_rough == 0.0 && v_rough == 0.0;
+    if is_specular && allow_multiple_lobes {
+        bsdf.add(Arc::new(FresnelSpecular::new(r, t, 1.0, eta, mode)));
+    } else if is_specular {
+        if !r.is_black() {
+            let fresnel = Arc::new(FresnelDielectric::new(1.0, eta));
+            bsdf.add(Arc::new(SpecularReflection::new(r, fresnel)));
+        }
+        if !t.is_black() {
+            bsdf.add(Arc::new(SpecularTransmission::new(t, 1.0, eta, mode)));
+        }
+    } else {
+        let distrib: ArcMicrofacetDistribution =
+            Arc::new(TrowbridgeReitzDistribution::new(u_rough, v_rough, true));
+        if !r.is_black() {
+            let fresnel = Arc::new(FresnelDielectric::new(1.0, eta));
+            bsdf.add(Arc::new(MicrofacetReflection::new(
+                r,
+                Arc::clone(&distrib),
+                fresnel,
+            )));
+        }
+        if !t.is_black() {
+            bsdf.add(Arc::new(MicrofacetTransmission::new(
+                t, distrib, 1.0, eta, mode,
+            )));
+        }
+    }
+    bsdf
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn coefficients_are_scaled_and_converted_to_scene_units() {
+        let mut params = ParamSet::new();
+        params.add_rgb_spectrum("sigma_a", &[1.0, 1.0, 1.0]);
+        params.add_texture("sigma_s", &[String::from("dots")]);
+        params.add_string("units", &[String::from("mm")]);
+        params.add_float("scale", &[0.5]);
+        let mut spectrum_textures: HashMap<String, ArcTexture<Spectrum>> = HashMap::new();
+        spectrum_textures.insert(
+            String::from("dots"),
+            Arc::new(ConstantTexture::new(Spectrum::new(2.0))),
+        );
+        let tp = TextureParams::new(ParamSet::new(), params, HashMap::new(), spectrum_textures);
+        let material = SubsurfaceMaterial::from(&tp);
+
+        let identity = Arc::new(Transform::default());
+        let shape_data = Arc::new(ShapeData::new(Arc::clone(&identity), Some(identity), false));
+        let si = SurfaceInteraction::new(
+            Point3f::default(),
+            Vector3f::default(),
+            Point2f::default(),
+            Vector3f::new(0.0, 0.0, 1.0),
+            Vector3f::new(1.0, 0.0, 0.0),
+            Vector3f::new(0.0, 1.0, 0.0),
+            Normal3f::default(),
+            Normal3f::default(),
+            0.0,
+            shape_data,
+            None,
+        );
+
+        // Coefficients per millimetre are 1000 times larger per metre.
+        let white = Spectrum::from_rgb(&[1.0, 1.0, 1.0], None).y();
+        let sigma_a = material.sigma_a.evaluate(&si).y();
+        let sigma_s = material.sigma_s.evaluate(&si).y();
+        assert!((sigma_a - 500.0 * white).abs() < 1e-2, "{}", sigma_a);
+        assert!(
+            (sigma_s - Spectrum::new(1000.0).y()).abs() < 1e-2,
+            "{}",
+            sigma_s
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Translucent Material
+
+use core::geometry::*;
+use core::material::*;
+use core::microfacet::*;
+use core::mipmap::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::reflection::*;
+use core::spectrum::*;
+use core::texture::*;
+use std::sync::Arc;
+
+/// Index of refraction of the translucent surface.
+const ETA: Float = 1.5;
+
+/// Implements thin translucent surfaces that scatter light diffusely and
+/// glossily through to the other side.
+pub struct TranslucentMaterial {
+    /// Spectral diffuse scattering.
+    kd: ArcTexture<Spectrum>,
+
+    /// Spectral glossy scattering.
+    ks: ArcTexture<Spectrum>,
+
+    /// Roughness.
+    roughness: ArcTexture<Float>,
+
+    /// Fraction of light that is reflected.
+    reflect: ArcTexture<Spectrum>,
+
+    /// Fraction of light that is transmitted.
+    transmit: ArcTexture<Spectrum>,
+
+    /// Bump map.
+    bump_map: Option<ArcTexture<Float>>,
+
+    /// Normal map.
+    normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+
+    /// Remap roughness value to [0, 1] where higher values represent larger
+    /// highlights. If this is `false`, use the microfacet distributions `alpha`
+    /// parameter.
+    remap_roughness: bool,
+}
+
+impl TranslucentMaterial {
+    /// Create a new `TranslucentMaterial`.
+    ///
+    /// * `kd`              - Spectral diffuse scattering.
+    /// * `ks`              - Spectral glossy scattering.
+    /// * `roughness`       - Roughness.
+    /// * `reflect`         - Fraction of light that is reflected.
+    /// * `transmit`        - Fraction of light that is transmitted.
+    /// * `remap_roughness` - Remap roughness value to [0, 1] where higher
+    ///                       values represent larger highlights. If this is
+    ///                       `false`, use the microfacet distributions `alpha`
+    ///                       parameter.
+    /// * `bump_map`        - Optional bump map.
+    /// * `normal_map`      - Optional normal map.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        kd: ArcTexture<Spectrum>,
+        ks: ArcTexture<Spectrum>,
+        roughness: ArcTexture<Float>,
+        reflect: ArcTexture<Spectrum>,
+        transmit: ArcTexture<Spectrum>,
+        remap_roughness: bool,
+        bump_map: Option<ArcTexture<Float>>,
+        normal_map: Option<ArcMIPMap<RGBSpectrum>>,
+    ) -> Self {
+        Self {
+            kd: Arc::clone(&kd),
+            ks: Arc::clone(&ks),
+            roughness: Arc::clone(&roughness),
+            reflect: Arc::clone(&reflect),
+            transmit: Arc::clone(&transmit),
+            remap_roughness,
+            bump_map: bump_map.clone(),
+            normal_map,
+        }
+    }
+}
+
+impl Material for TranslucentMaterial {
+    /// Initializes representations of the light-scattering properties of the
+    /// material at the intersection point on the surface.
+    ///
+    /// * `si`                   - The surface interaction at the intersection.
+    /// * `mode`                 - Transport mode.
+    /// * `allow_multiple_lobes` - Indicates whether the material should use
+    ///                            BxDFs that aggregate multiple types of
+    ///                            scattering into a single BxDF when such BxDFs
+    ///                            are available (ignored).
+    fn compute_scattering_functions(
+        &self,
+        si: &mut SurfaceInteraction,
+        mode: TransportMode,
+        _allow_multiple_lobes: bool,
+    ) {
+        // Perform bump or normal mapping, if present.
+        self.perturb_normal(&self.bump_map, &self.normal_map, si);
+
+        let mut bsdf = BSDF::new(si, Some(ETA));
+
+        let r = self.reflect.evaluate(si).clamp_default();
+        let t = self.transmit.evaluate(si).clamp_default();
+        if r.is_black() && t.is_black() {
+            si.bsdf = Some(bsdf);
+            return;
+        }
+
+        // Initialize diffuse component of translucent material.
+        let kd = self.kd.evaluate(si).clamp_default();
+        if !kd.is_black() {
+            if !r.is_black() {
+                bsdf.add(Arc::new(LambertianReflection::new(r * kd)));
+            }
+            if !t.is_black() {
+                bsdf.add(Arc::new(LambertianTransmission::new(t * kd)));
+            }
+        }
+
+        // Initialize glossy component of translucent material.
+        let ks = self.ks.evaluate(si).clamp_default();
+        if !ks.is_black() {
+            let mut rough = self.roughness.evaluate(si);
+            if self.remap_roughness {
+                rough = TrowbridgeReitzDistribution::roughness_to_alpha(rough);
+            }
+            let distrib: ArcMicrofacetDistribution =
+                Arc::new(TrowbridgeReitzDistribution::new(rough, rough, true));
+            if !r.is_black() {
+                let fresnel = Arc::new(FresnelDielectric::new(1.0, ETA));
+                bsdf.add(Arc::new(MicrofacetReflection::new(
+                    r * ks,
+                    Arc::clone(&distrib),
+                    fresnel,
+                )));
+            }
+            if !t.is_black() {
+                bsdf.add(Arc::new(MicrofacetTransmission::new(
+                    t * ks,
+                    distrib,
+                    1.0,
+                    ETA,
+                    mode,
+                )));
+            }
+        }
+
+        si.bsdf = Some(bsdf);
+    }
+}
+
+impl From<&TextureParams> for TranslucentMaterial {
+    /// Create a translucent material from given parameter set.
+    ///
+    /// * `tp` - Texture parameter set.
+    fn from(tp: &TextureParams) -> Self {
+        let kd = tp.get_spectrum_texture_or_else(
+            "Kd",
+            Arc::new(ConstantTexture::new(Spectrum::new(0.25))),
+        );
+        let ks = tp.get_spectrum_texture_or_else(
+            "Ks",
+            Arc::new(ConstantTexture::new(Spectrum::new(0.25))),
+        );
+        let reflect = tp.get_spectrum_texture_or_else(
+            "reflect",
+            Arc::new(ConstantTexture::new(Spectrum::new(0.5))),
+        );
+        let transmit = tp.get_spectrum_texture_or_else(
+            "transmit",
+            Arc::new(ConstantTexture::new(Spectrum::new(0.5))),
+        );
+        let roughness =
+            tp.get_float_texture_or_else("roughness", Arc::new(ConstantTexture::new(0.1)));
+        let bump_map = tp.get_float_texture("bumpmap");
+        let normal_map = tp.get_normal_map();
+        let remap_roughness = tp.find_bool("remaproughness", true);
+        Self::new(
+            kd,
+            ks,
+            roughness,
+            reflect,
+            transmit,
+            remap_roughness,
+            bump_map,
+            normal_map,
+        )
+    }
+}
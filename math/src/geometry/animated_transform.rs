@@ -1243,20 +1243,6 @@ impl AnimatedTransform {
         Arc::new(Transform::translate(&trans) * Transform::from(rotate) * Transform::from(scale))
     }
 
-    /// Applies animated transformation to a given ray.
-    ///
-    /// * `r` - The ray.
-    pub fn transform_ray(&self, r: &Ray) -> Ray {
-        let t = if !self.actually_animated || r.time <= self.start_time {
-            Arc::clone(&self.start_transform)
-        } else if r.time >= self.end_time {
-            Arc::clone(&self.end_transform)
-        } else {
-            self.interpolate(r.time)
-        };
-        t.transform_ray(r)
-    }
-
     /// Applies animated transformation to a given point.
     ///
     /// * `time` - The time.
@@ -1374,7 +1360,6 @@ fn decompose(m: &Matrix4x4, t: &mut Vector3f, r_quat: &mut Quaternion, s: &mut M
 
     // Extract rotation R from transformation matrix
     let mut r = m1;
-    let mut norm = 0.0;
     let mut count = 0;
     loop {
         // Compute the next matrix R_next in series
@@ -1387,6 +1372,7 @@ fn decompose(m: &Matrix4x4, t: &mut Vector3f, r_quat: &mut Quaternion, s: &mut M
         }
 
         // Compute norm of difference between R and R_next
+        let mut norm = 0.0;
         for i in 0..3 {
             let n = abs(r[i][0] - r_next[i][0])
                 + abs(r[i][1] - r_next[i][1])
@@ -1397,14 +1383,14 @@ fn decompose(m: &Matrix4x4, t: &mut Vector3f, r_quat: &mut Quaternion, s: &mut M
         r = r_next;
 
         count += 1;
-        if count >= 100 && norm <= 0.0001 {
+        if count >= 100 || norm <= 0.0001 {
             break;
         }
     }
     *r_quat = Quaternion::from(Transform::from(r));
 
     // Compute scale S using rotation and original matrix
-    *s = r.inverse() * *m;
+    *s = r.inverse() * m1;
 }
 
 /// DerivativeTerm encapsulates the coefficients `ki` to bound the motion of a
@@ -1430,3 +1416,66 @@ impl DerivativeTerm {
         self.kc + self.kx * p.x + self.ky * p.y + self.kz * p.z
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: Point3f, b: Point3f) {
+        assert!((a - b).length() < 1e-3, "{:?} != {:?}", a, b);
+    }
+
+    fn animated(end: Transform) -> AnimatedTransform {
+        AnimatedTransform::new(Arc::new(Transform::default()), Arc::new(end), 0.0, 1.0)
+    }
+
+    #[test]
+    fn interpolates_translation_and_scale() {
+        let end =
+            Transform::translate(&Vector3f::new(2.0, 0.0, 0.0)) * Transform::scale(3.0, 1.0, 1.0);
+        let at = animated(end);
+        let p = Point3f::new(1.0, 1.0, 1.0);
+
+        assert_near(at.transform_point(0.0, &p), p);
+        assert_near(at.transform_point(0.5, &p), Point3f::new(3.0, 1.0, 1.0));
+        assert_near(at.transform_point(1.0, &p), Point3f::new(5.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn interpolates_rotation_along_arc() {
+        let at = animated(Transform::rotate_z(90.0));
+        let p = Point3f::new(1.0, 0.0, 0.0);
+
+        let mid = at.transform_point(0.5, &p);
+        let h = 0.5_f32.sqrt();
+        assert_near(mid, Point3f::new(h, h, 0.0));
+        assert_near(at.transform_point(1.0, &p), Point3f::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn motion_bounds_contain_motion() {
+        let end = Transform::translate(&Vector3f::new(0.0, 0.0, 1.0))
+            * Transform::rotate_y(120.0)
+            * Transform::scale(2.0, 2.0, 2.0);
+        let at = animated(end);
+        let b = Bounds3f::new(Point3f::new(-1.0, -1.0, -1.0), Point3f::new(1.0, 1.0, 1.0));
+        let bounds = at.motion_bounds(&b);
+
+        for i in 0..=16 {
+            let time = i as Float / 16.0;
+            for corner in 0..8 {
+                let p = at.transform_point(time, &b.corner(corner));
+                assert!(
+                    bounds.expand(1e-3).contains(&p),
+                    "{:?} outside {:?}",
+                    p,
+                    bounds
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Geometry
+//!
+//! Vectors, points, normals, bounding boxes, matrices, quaternions and
+//! transformations.
+
+// Define macros for property based testing.
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_range {
+    ($name: ident, $t: ty, $r: expr) => {
+        prop_compose! {
+            fn $name()(f in $r) -> $t {
+                f
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_non_zero_range {
+    ($name: ident, $t: ty, $r: expr) => {
+        prop_compose! {
+            fn $name()(f in $r.prop_filter("non-zero", |x| !(*x).is_zero())) -> $t {
+                f
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_vector2 {
+    ($name: ident, $t: ty, $xr: expr, $yr: expr) => {
+        prop_compose! {
+            fn $name()(x in $xr, y in $yr) -> Vector2<$t> {
+                Vector2 { x, y }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_vector3 {
+    ($name: ident, $t: ty, $xr: expr, $yr: expr, $zr: expr) => {
+        prop_compose! {
+            fn $name()(x in $xr, y in $yr, z in $zr) -> Vector3<$t> {
+                Vector3 { x, y, z }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_normal3 {
+    ($name: ident, $t: ty, $xr: expr, $yr: expr, $zr: expr) => {
+        prop_compose! {
+            fn $name()(x in $xr, y in $yr, z in $zr) -> Normal3<$t> {
+                Normal3 { x, y, z }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_point2 {
+    ($name: ident, $t: ty, $xr: expr, $yr: expr) => {
+        prop_compose! {
+            fn $name()(x in $xr, y in $yr) -> Point2<$t> {
+                Point2 { x, y }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! prop_point3 {
+    ($name: ident, $t: ty, $xr: expr, $yr: expr, $zr: expr) => {
+        prop_compose! {
+            fn $name()(x in $xr, y in $yr, z in $zr) -> Point3<$t> {
+                Point3 { x, y, z }
+            }
+        }
+    };
+}
+
+mod animated_transform;
+mod bounds2;
+mod bounds3;
+mod common;
+mod coordinate_system;
+mod interval;
+mod matrix4x4;
+mod normal;
+mod point2;
+mod point3;
+mod quaternion;
+mod transform;
+mod util;
+mod vector2;
+mod vector3;
+
+// Re-export
+pub use animated_transform::AnimatedTransform;
+pub use bounds2::{Bounds2, Bounds2f, Bounds2i, Bounds2iIterator};
+pub use bounds3::{Bounds3, Bounds3f, Bounds3i};
+pub use common::{Dot, FaceForward, Intersect, Union};
+pub use coordinate_system::coordinate_system;
+pub use interval::Interval;
+pub use matrix4x4::{solve_linear_system_2x2, Matrix4x4, IDENTITY_MATRIX, ZERO_MATRIX};
+pub use normal::{Normal3, Normal3f};
+pub use point2::{Point2, Point2f, Point2i};
+pub use point3::{Point3, Point3f, Point3i};
+pub use quaternion::Quaternion;
+pub use transform::{ArcTransform, Transform};
+pub use util::{
+    spherical_direction, spherical_direction_in_coord_frame, spherical_phi, spherical_theta,
+};
+pub use vector2::{Vector2, Vector2f, Vector2i};
+pub use vector3::{Vector3, Vector3f, Vector3i};
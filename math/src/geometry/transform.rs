@@ -414,94 +414,6 @@ impl Transform {
         )
     }
 
-    /// Applies transformation to a given ray.
-    ///
-    /// * `r` - The ray.
-    pub fn transform_ray(&self, r: &Ray) -> Ray {
-        let (mut o, o_error) = self.transform_point_with_error(&r.o);
-        let d = self.transform_vector(&r.d);
-
-        // Offset ray origin to edge of error bounds and compute t_max.
-        let length_squared = d.length_squared();
-        let mut t_max = r.t_max;
-        if length_squared > 0.0 {
-            let dt = d.abs().dot(&o_error) / length_squared;
-            o += d * dt;
-            t_max -= dt;
-        }
-
-        // Handle differentials.
-        if let Some(diff) = r.differentials {
-            let td = RayDifferential::new(
-                self.transform_point(&diff.rx_origin),
-                self.transform_point(&diff.ry_origin),
-                self.transform_vector(&diff.rx_direction),
-                self.transform_vector(&diff.ry_direction),
-            );
-            Ray::new_with_differentials(o, d, t_max, r.time, td, r.medium.clone())
-        } else {
-            Ray::new(o, d, t_max, r.time, r.medium.clone())
-        }
-    }
-
-    /// Returns the transformed ray with absolute errors due to applying the
-    /// transformation to its origin and direction.
-    ///
-    /// * `r` - The ray.
-    pub fn transform_ray_with_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f) {
-        let (mut o, o_error) = self.transform_point_with_error(&r.o);
-        let (d, d_error) = self.transform_vector_with_error(&r.d);
-
-        // Offset ray origin to edge of error bounds.
-        let length_sqquared = d.length_squared();
-        if length_sqquared > 0.0 {
-            let dt = d.abs().dot(&o_error) / length_sqquared;
-            o += d * dt;
-        }
-
-        // Handle differentials.
-        if let Some(diff) = r.differentials {
-            let td = RayDifferential::new(
-                self.transform_point(&diff.rx_origin),
-                self.transform_point(&diff.ry_origin),
-                self.transform_vector(&diff.rx_direction),
-                self.transform_vector(&diff.ry_direction),
-            );
-
-            (
-                Ray::new_with_differentials(o, d, r.t_max, r.time, td, r.medium.clone()),
-                o_error,
-                d_error,
-            )
-        } else {
-            (
-                Ray::new(o, d, r.t_max, r.time, r.medium.clone()),
-                o_error,
-                d_error,
-            )
-        }
-    }
-
-    /// Transforms the ray taking into account absolute errors due to applying the
-    /// transformation to its origin and direction.
-    ///
-    /// * `r` - The ray.
-    pub fn transform_ray_with_abs_error(&self, r: &Ray) -> (Ray, Vector3f, Vector3f) {
-        let (mut tr, o_error_in, d_error_in) = self.transform_ray_with_error(r);
-
-        // Calculate error in result for origin and direction.
-        let o_error_out = self.transform_point_abs_error(&tr.o, &o_error_in);
-        let d_error_out = self.transform_vector_abs_error(&tr.d, &d_error_in);
-        // Offset ray origin to edge of error bounds.
-        let length_sqquared = tr.d.length_squared();
-        if length_sqquared > 0.0 {
-            let dt = tr.d.abs().dot(&o_error_out) / length_sqquared;
-            tr.o += tr.d * dt;
-        }
-
-        (tr, o_error_out, d_error_out)
-    }
-
     /// Applies transformation to a given bounding box.
     ///
     /// * `b` - The bounding box.
@@ -516,48 +428,6 @@ impl Transform {
             .union(&self.transform_point(&Point3::new(b.p_max.x, b.p_max.y, b.p_max.z)))
     }
 
-    /// Applies transformation to a given surface interaction.
-    ///
-    /// * `si` - The surface interaction.
-    pub fn transform_surface_interaction<'a>(
-        &self,
-        si: &SurfaceInteraction<'a>,
-    ) -> SurfaceInteraction<'a> {
-        // Transform p and p_error in SurfaceInteraction
-        let (p, p_error) = self.transform_point_with_error(&si.hit.p);
-
-        // Transform remaining members of SurfaceInteraction.
-        let mut si = SurfaceInteraction::new(
-            p,
-            p_error,
-            si.uv,
-            self.transform_vector(&si.hit.wo).normalize(),
-            self.transform_vector(&si.dpdu),
-            self.transform_vector(&si.dpdv),
-            self.transform_normal(&si.dndu),
-            self.transform_normal(&si.dndv),
-            si.hit.time,
-            Arc::clone(&si.shape_data),
-            si.primitive,
-        );
-
-        // Transform n in SurfaceInteraction.hit
-        let n = self.transform_normal(&si.hit.n).normalize();
-        si.hit.n = n;
-
-        // Handle transformations for shading parameters..
-        si.shading = Shading::new(
-            self.transform_normal(&si.shading.n).normalize(),
-            self.transform_vector(&si.shading.dpdu),
-            self.transform_vector(&si.shading.dpdv),
-            self.transform_normal(&si.shading.dndu),
-            self.transform_normal(&si.shading.dndv),
-        );
-        si.shading.n = si.shading.n.face_forward(&Vector3::from(n));
-
-        si
-    }
-
     /// Returns `true` if the transformation changes the handedness of the
     /// coordinate system.
     pub fn swaps_handedness(&self) -> bool {
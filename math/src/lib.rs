@@ -0,0 +1,13 @@
+//! PBRT Math
+//!
+//! The numeric types, vectors, points, normals, bounding boxes, matrices,
+//! transformations and floating point error bounds used by the renderer. The
+//! crate has no renderer dependencies so other projects can use it by itself.
+//!
+//! * `pbrt`     - `Float`, constants and common numeric functions.
+//! * `geometry` - Geometric types and transformations.
+//! * `efloat`   - Floating point values with error bounds.
+
+pub mod efloat;
+pub mod geometry;
+pub mod pbrt;
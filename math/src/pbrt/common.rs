@@ -249,7 +249,7 @@ where
         }
     }
 
-    clamp(first - 1, 0, size - 2)
+    clamp(first.saturating_sub(1), 0, size - 2)
 }
 
 /// Return the cosine of an angle.
@@ -381,3 +381,33 @@ pub fn erf_inv(x: Float) -> Float {
         p * x
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_interval_brackets_value() {
+        let values = [0.0, 1.0, 2.0, 4.0];
+        let find = |x: Float| find_interval(values.len(), |i| values[i] <= x);
+        assert_eq!(find(0.5), 0);
+        assert_eq!(find(1.0), 1);
+        assert_eq!(find(3.0), 2);
+    }
+
+    #[test]
+    fn find_interval_clamps_to_end_intervals() {
+        let values = [1.0, 2.0, 4.0];
+        let find = |x: Float| find_interval(values.len(), |i| values[i] <= x);
+
+        // The predicate fails at index 0 below the first value.
+        assert_eq!(find(0.0), 0);
+        assert_eq!(find(-1.0), 0);
+        assert_eq!(find(4.0), 1);
+        assert_eq!(find(10.0), 1);
+    }
+}
@@ -0,0 +1,12 @@
+//! PBRT common stuff
+
+mod axis;
+mod clamp;
+mod common;
+mod log2;
+
+// Re-export
+pub use axis::*;
+pub use clamp::*;
+pub use common::*;
+pub use log2::*;
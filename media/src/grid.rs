@@ -0,0 +1,432 @@
+//! Grid Density Medium
+
+use core::geometry::*;
+use core::medium::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::rng::*;
+use core::sampler::*;
+use core::spectrum::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// Implements a heterogeneous medium whose density is given by a regular grid
+/// of samples over the unit cube of the medium coordinate system. The
+/// absorption and scattering coefficients are scaled by the trilinearly
+/// interpolated density. Distances are sampled with delta tracking and
+/// transmittance is estimated with ratio tracking.
+#[derive(Clone)]
+pub struct GridDensityMedium {
+    /// Absorption coefficient σa at unit density.
+    pub sigma_a: Spectrum,
+
+    /// Scattering coefficient σs at unit density.
+    pub sigma_s: Spectrum,
+
+    /// Attenuation coefficient σt = σa + σs at unit density. Delta tracking
+    /// needs a single majorant so this is the same for all wavelengths.
+    pub sigma_t: Float,
+
+    /// The asymmetry parameter of the phase function.
+    pub g: Float,
+
+    /// Number of density samples along the x-axis.
+    pub nx: usize,
+
+    /// Number of density samples along the y-axis.
+    pub ny: usize,
+
+    /// Number of density samples along the z-axis.
+    pub nz: usize,
+
+    /// Transformation from world coordinate system to medium coordinate system.
+    pub world_to_medium: ArcTransform,
+
+    /// Density samples stored with x varying fastest, then y, then z.
+    pub density: Vec<Float>,
+
+    /// Reciprocal of the maximum density.
+    pub inv_max_density: Float,
+}
+
+impl GridDensityMedium {
+    /// Returns a new `GridDensityMedium`.
+    ///
+    /// * `sigma_a`         - Absorption coefficient σa at unit density.
+    /// * `sigma_s`         - Scattering coefficient σs at unit density.
+    /// * `g`               - The asymmetry parameter of the phase function.
+    /// * `nx`              - Number of density samples along the x-axis.
+    /// * `ny`              - Number of density samples along the y-axis.
+    /// * `nz`              - Number of density samples along the z-axis.
+    /// * `medium_to_world` - Transformation from medium coordinate system to
+    ///                       world coordinate system.
+    /// * `density`         - Density samples stored with x varying fastest.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sigma_a: Spectrum,
+        sigma_s: Spectrum,
+        g: Float,
+        nx: usize,
+        ny: usize,
+        nz: usize,
+        medium_to_world: ArcTransform,
+        density: Vec<Float>,
+    ) -> Self {
+        // Use the average attenuation if it varies with wavelength.
+        let st = sigma_a + sigma_s;
+        let samples = st.samples();
+        let sigma_t = samples.iter().sum::<Float>() / samples.len() as Float;
+        if samples.iter().any(|s| *s != sigma_t) {
+            warn!("GridDensityMedium requires a spectrally uniform attenuation coefficient.");
+        }
+
+        let max_density = density.iter().cloned().fold(0.0, Float::max);
+        let inv_max_density = if max_density > 0.0 {
+            1.0 / max_density
+        } else {
+            0.0
+        };
+
+        Self {
+            sigma_a,
+            sigma_s,
+            sigma_t,
+            g,
+            nx,
+            ny,
+            nz,
+            world_to_medium: Arc::new(medium_to_world.inverse()),
+            density,
+            inv_max_density,
+        }
+    }
+
+    /// Creates a `GridDensityMedium` from given parameter set and medium to
+    /// world transform. The density grid fills the box between `p0` and `p1`
+    /// in the medium's coordinate system.
+    ///
+    /// * `params`          - Parameter set.
+    /// * `medium_to_world` - Transformation from medium coordinate system to
+    ///                       world coordinate system.
+    pub fn from_props(params: &ParamSet, medium_to_world: ArcTransform) -> Result<Self, String> {
+        let props = ScatteringProperties::from(params);
+        let g = params.find_one_float("g", 0.0);
+        if g <= -1.0 || g >= 1.0 {
+            warn!("Phase function 'g' {} should be in (-1, 1).", g);
+        }
+
+        let density = params.find_float("density");
+        if density.is_empty() {
+            return Err(String::from(
+                "No 'density' values provided for heterogeneous medium.",
+            ));
+        }
+        let nx = params.find_one_int("nx", 1);
+        let ny = params.find_one_int("ny", 1);
+        let nz = params.find_one_int("nz", 1);
+        if nx < 1 || ny < 1 || nz < 1 {
+            return Err(format!("Invalid grid resolution {}x{}x{}.", nx, ny, nz));
+        }
+        let (nx, ny, nz) = (nx as usize, ny as usize, nz as usize);
+        if density.len() != nx * ny * nz {
+            return Err(format!(
+                "Heterogeneous medium has {} density values; expected nx*ny*nz = {}.",
+                density.len(),
+                nx * ny * nz
+            ));
+        }
+
+        // Map the unit cube onto the box given by `p0` and `p1`.
+        let p0 = params.find_one_point3f("p0", Point3f::new(0.0, 0.0, 0.0));
+        let p1 = params.find_one_point3f("p1", Point3f::new(1.0, 1.0, 1.0));
+        let extent = p1 - p0;
+        let data_to_medium = Transform::translate(&Vector3f::new(p0.x, p0.y, p0.z))
+            * Transform::scale(extent.x, extent.y, extent.z);
+
+        Ok(Self::new(
+            props.sigma_a,
+            props.sigma_s,
+            g,
+            nx,
+            ny,
+            nz,
+            Arc::new(*medium_to_world * data_to_medium),
+            density,
+        ))
+    }
+
+    /// Returns the density sample at a grid point or 0 outside the grid.
+    ///
+    /// * `p` - The grid point.
+    fn d(&self, p: &Point3i) -> Float {
+        if p.x < 0
+            || p.y < 0
+            || p.z < 0
+            || p.x as usize >= self.nx
+            || p.y as usize >= self.ny
+            || p.z as usize >= self.nz
+        {
+            0.0
+        } else {
+            self.density[(p.z as usize * self.ny + p.y as usize) * self.nx + p.x as usize]
+        }
+    }
+
+    /// Returns the trilinearly interpolated density at a point in the unit
+    /// cube of the medium coordinate system.
+    ///
+    /// * `p` - The point.
+    fn density(&self, p: &Point3f) -> Float {
+        // Compute voxel coordinates and offsets for `p`.
+        let p_samples = Point3f::new(
+            p.x * self.nx as Float - 0.5,
+            p.y * self.ny as Float - 0.5,
+            p.z * self.nz as Float - 0.5,
+        );
+        let pi = Point3i::new(
+            p_samples.x.floor() as Int,
+            p_samples.y.floor() as Int,
+            p_samples.z.floor() as Int,
+        );
+        let d = Vector3f::new(
+            p_samples.x - pi.x as Float,
+            p_samples.y - pi.y as Float,
+            p_samples.z - pi.z as Float,
+        );
+
+        // Trilinearly interpolate density values to compute local density.
+        let at = |x: Int, y: Int, z: Int| self.d(&Point3i::new(pi.x + x, pi.y + y, pi.z + z));
+        let d00 = lerp(d.x, at(0, 0, 0), at(1, 0, 0));
+        let d10 = lerp(d.x, at(0, 1, 0), at(1, 1, 0));
+        let d01 = lerp(d.x, at(0, 0, 1), at(1, 0, 1));
+        let d11 = lerp(d.x, at(0, 1, 1), at(1, 1, 1));
+        let d0 = lerp(d.y, d00, d10);
+        let d1 = lerp(d.y, d01, d11);
+        lerp(d.z, d0, d1)
+    }
+
+    /// Returns the ray in the medium coordinate system with a normalized
+    /// direction and the parametric range where it overlaps the grid, if it
+    /// does.
+    ///
+    /// * `ray` - The ray in world space.
+    fn medium_ray(&self, ray: &Ray) -> Option<(Ray, Float, Float)> {
+        let ray_length = ray.d.length();
+        let r = self.world_to_medium.transform_ray(&Ray::new(
+            ray.o,
+            ray.d / ray_length,
+            ray.t_max * ray_length,
+            ray.time,
+            None,
+        ));
+        let b = Bounds3f::new(Point3f::new(0.0, 0.0, 0.0), Point3f::new(1.0, 1.0, 1.0));
+        b.intersect_p(&r).map(|(t_min, t_max)| (r, t_min, t_max))
+    }
+}
+
+impl Medium for GridDensityMedium {
+    /// Returns the beam transmittance along a given ray using ratio tracking.
+    /// The sampler is shared with the caller here so random numbers are drawn
+    /// from a generator seeded by the ray instead.
+    ///
+    /// * `ray`      - The ray.
+    /// * `_sampler` - The sampler.
+    fn tr(&self, ray: &Ray, _sampler: ArcSampler) -> Spectrum {
+        let (r, t_min, t_max) = match self.medium_ray(ray) {
+            Some(x) => x,
+            None => return Spectrum::new(1.0),
+        };
+        if self.sigma_t == 0.0 || self.inv_max_density == 0.0 {
+            return Spectrum::new(1.0);
+        }
+
+        // Perform ratio tracking to estimate the transmittance value.
+        let mut rng = RNG::new(ray_seed(ray));
+        let mut tr = 1.0;
+        let mut t = t_min;
+        loop {
+            let u: Float = rng.uniform();
+            t -= (1.0 - u).ln() * self.inv_max_density / self.sigma_t;
+            if t >= t_max {
+                break;
+            }
+            let density = self.density(&r.at(t));
+            tr *= 1.0 - max(0.0, density * self.inv_max_density);
+
+            // Terminate low-throughput paths with Russian roulette.
+            let rr_threshold = 0.1;
+            if tr < rr_threshold {
+                let q = max(0.05, 1.0 - tr);
+                let u: Float = rng.uniform();
+                if u < q {
+                    return Spectrum::new(0.0);
+                }
+                tr /= 1.0 - q;
+            }
+        }
+        Spectrum::new(tr)
+    }
+
+    /// Samples a scattering interaction along a given ray up to its `t_max`
+    /// using delta tracking. The interaction takes the ray's medium so rays
+    /// scattered from it remain in the same medium.
+    ///
+    /// * `ray`     - The ray.
+    /// * `sampler` - The sampler.
+    fn sample(&self, ray: &Ray, sampler: &mut ArcSampler) -> (Spectrum, Option<MediumInteraction>) {
+        let (r, t_min, t_max) = match self.medium_ray(ray) {
+            Some(x) => x,
+            None => return (Spectrum::new(1.0), None),
+        };
+        if self.sigma_t == 0.0 || self.inv_max_density == 0.0 {
+            return (Spectrum::new(1.0), None);
+        }
+
+        // Run delta-tracking iterations to sample a medium interaction.
+        let sampler = Arc::get_mut(sampler).unwrap();
+        let mut t = t_min;
+        loop {
+            t -= (1.0 - sampler.get_1d()).ln() * self.inv_max_density / self.sigma_t;
+            if t >= t_max {
+                return (Spectrum::new(1.0), None);
+            }
+            if self.density(&r.at(t)) * self.inv_max_density > sampler.get_1d() {
+                // Populate the interaction at the world space point.
+                let mi = ray.medium.as_ref().map(|medium| {
+                    let phase: ArcPhaseFunction = Arc::new(HenyeyGreenstein::new(self.g));
+                    MediumInteraction::new(
+                        ray.o + ray.d.normalize() * t,
+                        -ray.d,
+                        ray.time,
+                        Arc::clone(medium),
+                        phase,
+                    )
+                });
+                return (self.sigma_s / self.sigma_t, mi);
+            }
+        }
+    }
+
+    /// Returns a bound on the attenuation coefficient σt along a given ray
+    /// from the maximum density.
+    ///
+    /// * `_ray` - The ray.
+    fn majorant(&self, _ray: &Ray) -> Option<Float> {
+        if self.inv_max_density > 0.0 {
+            Some(self.sigma_t / self.inv_max_density)
+        } else {
+            Some(0.0)
+        }
+    }
+
+    /// Returns the absorption and scattering coefficients σa and σs scaled
+    /// by the density at a point.
+    ///
+    /// * `p` - The point.
+    fn coefficients(&self, p: &Point3f) -> (Spectrum, Spectrum) {
+        let density = self.density(&self.world_to_medium.transform_point(p));
+        (self.sigma_a * density, self.sigma_s * density)
+    }
+
+    /// Returns the phase function at a point.
+    ///
+    /// * `_p` - The point.
+    fn phase(&self, _p: &Point3f) -> ArcPhaseFunction {
+        Arc::new(HenyeyGreenstein::new(self.g))
+    }
+}
+
+/// Returns a seed for a random number generator derived from a ray's origin
+/// and direction.
+///
+/// * `ray` - The ray.
+fn ray_seed(ray: &Ray) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for v in [ray.o.x, ray.o.y, ray.o.z, ray.d.x, ray.d.y, ray.d.z].iter() {
+        v.to_bits().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use samplers::RandomSampler;
+
+    /// Returns a medium over the unit cube with the given density grid.
+    ///
+    /// * `n`       - Grid resolution along each axis.
+    /// * `density` - Density samples.
+    fn grid(n: (usize, usize, usize), density: Vec<Float>) -> GridDensityMedium {
+        GridDensityMedium::new(
+            Spectrum::new(1.0),
+            Spectrum::new(1.0),
+            0.0,
+            n.0,
+            n.1,
+            n.2,
+            Arc::new(Transform::default()),
+            density,
+        )
+    }
+
+    /// Returns a ray through the centre of the unit cube along the x-axis.
+    ///
+    /// * `offset` - Distance of the origin from the cube, which varies the
+    ///              random numbers used for ratio tracking.
+    fn ray(offset: Float) -> Ray {
+        Ray::new(
+            Point3f::new(-1.0 - offset, 0.5, 0.5),
+            Vector3f::new(1.0, 0.0, 0.0),
+            INFINITY,
+            0.0,
+            None,
+        )
+    }
+
+    #[test]
+    fn density_is_interpolated_between_samples() {
+        let medium = grid((2, 1, 1), vec![0.0, 1.0]);
+        let density = |x| medium.density(&Point3f::new(x, 0.5, 0.5));
+        assert_eq!(density(0.25), 0.0);
+        assert_eq!(density(0.5), 0.5);
+        assert_eq!(density(0.75), 1.0);
+
+        let (sigma_a, sigma_s) = medium.coefficients(&Point3f::new(0.5, 0.5, 0.5));
+        assert_eq!((sigma_a[0], sigma_s[0]), (0.5, 0.5));
+        assert_eq!(medium.majorant(&ray(0.0)), Some(2.0));
+    }
+
+    #[test]
+    fn ratio_tracking_estimates_transmittance() {
+        // Density falls off to zero outside the grid so the optical depth
+        // along the ray is 2 * 0.875.
+        let medium = grid((2, 2, 2), vec![1.0; 8]);
+        let expected = (-1.75 as Float).exp();
+        let n = 4000;
+
+        let sampler: ArcSampler = Arc::new(RandomSampler::new(1, Some(7)));
+        let tr = (0..n)
+            .map(|i| medium.tr(&ray(i as Float * 1e-3), Arc::clone(&sampler))[0])
+            .sum::<Float>()
+            / n as Float;
+        assert!((tr - expected).abs() < 0.02, "{}", tr);
+
+        // Delta tracking escapes the medium with the same probability.
+        let mut sampler: ArcSampler = Arc::new(RandomSampler::new(1, Some(7)));
+        let mut escaped = 0;
+        for i in 0..n {
+            let (beta, mi) = medium.sample(&ray(i as Float * 1e-3), &mut sampler);
+            if mi.is_none() && beta[0] == 1.0 {
+                escaped += 1;
+            }
+        }
+        let escaped = escaped as Float / n as Float;
+        assert!((escaped - expected).abs() < 0.02, "{}", escaped);
+    }
+}
@@ -0,0 +1,205 @@
+//! Homogeneous Medium
+
+use core::geometry::*;
+use core::medium::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::sampler::*;
+use core::spectrum::*;
+use std::sync::Arc;
+
+/// Implements a medium with constant absorption and scattering coefficients
+/// and a Henyey-Greenstein phase function throughout. Transmittance follows
+/// Beer's law and distances are sampled exactly.
+#[derive(Clone)]
+pub struct HomogeneousMedium {
+    /// Absorption coefficient σa.
+    pub sigma_a: Spectrum,
+
+    /// Scattering coefficient σs.
+    pub sigma_s: Spectrum,
+
+    /// Attenuation coefficient σt = σa + σs.
+    pub sigma_t: Spectrum,
+
+    /// The asymmetry parameter of the phase function.
+    pub g: Float,
+}
+
+impl HomogeneousMedium {
+    /// Returns a new `HomogeneousMedium`.
+    ///
+    /// * `sigma_a` - Absorption coefficient σa.
+    /// * `sigma_s` - Scattering coefficient σs.
+    /// * `g`       - The asymmetry parameter of the phase function.
+    pub fn new(sigma_a: Spectrum, sigma_s: Spectrum, g: Float) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            sigma_t: sigma_a + sigma_s,
+            g,
+        }
+    }
+}
+
+impl Medium for HomogeneousMedium {
+    /// Returns the beam transmittance along a given ray.
+    ///
+    /// * `ray`      - The ray.
+    /// * `_sampler` - The sampler.
+    fn tr(&self, ray: &Ray, _sampler: ArcSampler) -> Spectrum {
+        (-self.sigma_t * min(ray.t_max * ray.d.length(), Float::MAX)).exp()
+    }
+
+    /// Samples a scattering interaction along a given ray up to its `t_max`.
+    /// The interaction takes the ray's medium so rays scattered from it
+    /// remain in the same medium.
+    ///
+    /// * `ray`     - The ray.
+    /// * `sampler` - The sampler.
+    fn sample(&self, ray: &Ray, sampler: &mut ArcSampler) -> (Spectrum, Option<MediumInteraction>) {
+        // Sample a channel and distance along the ray.
+        let sampler = Arc::get_mut(sampler).unwrap();
+        let n = self.sigma_t.samples().len();
+        let channel = min((sampler.get_1d() * n as Float) as usize, n - 1);
+        let dist = -(1.0 - sampler.get_1d()).ln() / self.sigma_t[channel];
+        let ray_length = ray.d.length();
+        let t = min(dist / ray_length, ray.t_max);
+        let sampled_medium = t < ray.t_max;
+        let mi = match ray.medium.as_ref() {
+            Some(medium) if sampled_medium => {
+                let phase: ArcPhaseFunction = Arc::new(HenyeyGreenstein::new(self.g));
+                Some(MediumInteraction::new(
+                    ray.at(t),
+                    -ray.d,
+                    ray.time,
+                    Arc::clone(medium),
+                    phase,
+                ))
+            }
+            _ => None,
+        };
+
+        // Compute the transmittance and sampling density.
+        let tr = (-self.sigma_t * min(t, Float::MAX) * ray_length).exp();
+        let density = if sampled_medium {
+            self.sigma_t * tr
+        } else {
+            tr
+        };
+        let mut pdf = density.samples().iter().sum::<Float>() / n as Float;
+        if pdf == 0.0 {
+            pdf = 1.0;
+        }
+
+        let beta = if sampled_medium {
+            tr * self.sigma_s / pdf
+        } else {
+            tr / pdf
+        };
+        (beta, mi)
+    }
+
+    /// Returns a bound on the attenuation coefficient σt along a given ray.
+    ///
+    /// * `_ray` - The ray.
+    fn majorant(&self, _ray: &Ray) -> Option<Float> {
+        Some(self.sigma_t.max_component_value())
+    }
+
+    /// Returns the absorption and scattering coefficients σa and σs at a
+    /// point.
+    ///
+    /// * `_p` - The point.
+    fn coefficients(&self, _p: &Point3f) -> (Spectrum, Spectrum) {
+        (self.sigma_a, self.sigma_s)
+    }
+
+    /// Returns the phase function at a point.
+    ///
+    /// * `_p` - The point.
+    fn phase(&self, _p: &Point3f) -> ArcPhaseFunction {
+        Arc::new(HenyeyGreenstein::new(self.g))
+    }
+}
+
+impl From<&ParamSet> for HomogeneousMedium {
+    /// Create a `HomogeneousMedium` from given parameter set.
+    ///
+    /// * `params` - Parameter set.
+    fn from(params: &ParamSet) -> Self {
+        let props = ScatteringProperties::from(params);
+        let g = params.find_one_float("g", 0.0);
+        if g <= -1.0 || g >= 1.0 {
+            warn!("Phase function 'g' {} should be in (-1, 1).", g);
+        }
+        Self::new(props.sigma_a, props.sigma_s, g)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use samplers::RandomSampler;
+
+    /// Returns a ray of length 1 along the x-axis in the given medium.
+    ///
+    /// * `medium` - The medium.
+    fn unit_ray(medium: ArcMedium) -> Ray {
+        // A direction of length 2 checks distances are in world units.
+        Ray::new(
+            Point3f::new(0.0, 0.0, 0.0),
+            Vector3f::new(2.0, 0.0, 0.0),
+            0.5,
+            0.0,
+            Some(medium),
+        )
+    }
+
+    #[test]
+    fn transmittance_follows_beers_law() {
+        let mut sigma_a = Spectrum::new(0.3);
+        sigma_a[0] = 1.0;
+        let medium = HomogeneousMedium::new(sigma_a, Spectrum::new(0.2), 0.0);
+        let sampler: ArcSampler = Arc::new(RandomSampler::new(1, Some(7)));
+
+        let ray = unit_ray(Arc::new(medium.clone()));
+        let tr = medium.tr(&ray, sampler);
+        assert!((tr[0] - (-1.2 as Float).exp()).abs() < 1e-5, "{}", tr[0]);
+        assert!((tr[1] - (-0.5 as Float).exp()).abs() < 1e-5, "{}", tr[1]);
+    }
+
+    #[test]
+    fn sampled_distances_follow_transmittance() {
+        let medium = HomogeneousMedium::new(Spectrum::new(0.3), Spectrum::new(0.2), 0.0);
+        let mut sampler: ArcSampler = Arc::new(RandomSampler::new(1, Some(7)));
+        let ray = unit_ray(Arc::new(medium.clone()));
+
+        let n = 20000;
+        let mut escaped = 0;
+        for _ in 0..n {
+            let (beta, mi) = medium.sample(&ray, &mut sampler);
+            match mi {
+                // Scattered samples are weighted by the albedo.
+                Some(mi) => {
+                    assert!((beta[0] - 0.4).abs() < 1e-5, "{}", beta[0]);
+                    assert!(mi.hit.p.x > 0.0 && mi.hit.p.x < 1.0);
+                }
+                None => {
+                    assert!((beta[0] - 1.0).abs() < 1e-5, "{}", beta[0]);
+                    escaped += 1;
+                }
+            }
+        }
+        let escaped = escaped as Float / n as Float;
+        assert!(
+            (escaped - (-0.5 as Float).exp()).abs() < 0.01,
+            "{}",
+            escaped
+        );
+    }
+}
@@ -1,9 +1,19 @@
 #[macro_use]
 extern crate log;
 
+use api::batch::*;
+use api::camera_path::*;
 use api::parser::*;
+use api::server::*;
 use api::*;
 use core::app::*;
+use core::geometry::*;
+use core::image_io::*;
+use core::imagemetrics::*;
+use core::mipmap::*;
+use core::pbrt::*;
+use core::plugin::*;
+use std::collections::BTreeMap;
 
 fn main() {
     // Initialize `env_logger`.
@@ -18,14 +28,68 @@ fn main() {
         .build_global()
         .unwrap();
 
+    // Limit the memory used by image textures.
+    set_texture_memory_budget(options.texture_memory);
+
+    // Load plugins before any scene refers to them.
+    if !options.plugins.is_empty() {
+        PLUGINS.write().unwrap().load_all(&options.plugins);
+    }
+
+    // Compare images instead of rendering.
+    if let Some(compare) = options.compare.as_ref() {
+        if let Err(err) = compare_images(compare) {
+            error!("{}", err);
+        }
+        return;
+    }
+
+    // Accept render jobs over HTTP until the process is terminated.
+    if let Some(serve) = options.serve.as_ref() {
+        if let Err(err) = RenderServer::new().and_then(|server| server.serve(serve)) {
+            error!("{}", err);
+        }
+        return;
+    }
+
+    // Render a batch of scenes, each with its own API state.
+    if let Some(batch) = options.batch.as_ref() {
+        if !options.image_file.is_empty() {
+            warn!("Every scene in the batch will be written to the same outfile.");
+        }
+        match read_batch_file(batch) {
+            Ok(scenes) => {
+                let failed = render_batch(&scenes);
+                if failed > 0 {
+                    error!("{} of {} scenes failed.", failed, scenes.len());
+                }
+            }
+            Err(err) => error!("{}", err),
+        }
+        return;
+    }
+
+    // Render the scene for each frame of a camera path.
+    if let Some(camera_path) = options.camera_path.as_ref() {
+        match read_camera_path(camera_path) {
+            Ok(frames) => {
+                let failed = render_camera_path(&options.paths, &frames);
+                if failed > 0 {
+                    error!("{} of {} frames failed.", failed, frames.len());
+                }
+            }
+            Err(err) => error!("{}", err),
+        }
+        return;
+    }
+
     // Initialize PBRT API.
     let mut api = Api::new();
     api.pbrt_init();
 
     // Process scene description.
     for path in options.paths.iter() {
-        let parser = PbrtFileParser::new(path);
-        match parser.parse(&mut api) {
+        match parse_scene_file(path, &mut api) {
             Ok(_) => (),
             Err(err) => error!("{}", err),
         }
@@ -33,3 +97,27 @@ fn main() {
 
     api.pbrt_cleanup();
 }
+
+/// Print the error metrics of an image against a reference and optionally
+/// write the per-pixel FLIP error.
+///
+/// * `compare` - The comparison options.
+fn compare_images(compare: &CompareOptions) -> Result<(), String> {
+    let read = |path: &str| read_image(path).map_err(|err| format!("{}: {}", path, err));
+    let image = read(&compare.image_file)?;
+    let reference = read(&compare.reference_file)?;
+
+    let metrics = ImageMetrics::compute(&image, &reference)?;
+    println!("{}", metrics);
+
+    if let Some(error_file) = compare.error_file.as_ref() {
+        let errors = flip_error_map(&image, &reference)?;
+        let rgb: Vec<Float> = errors.iter().flat_map(|e| vec![*e; 3]).collect();
+        let bounds = Bounds2i::new(
+            Point2i::new(0, 0),
+            Point2i::new(image.resolution.x as Int, image.resolution.y as Int),
+        );
+        write_image(error_file, &rgb, &bounds, &BTreeMap::new())?;
+    }
+    Ok(())
+}
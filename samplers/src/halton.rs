@@ -5,6 +5,7 @@ use core::geometry::*;
 use core::low_discrepency::*;
 use core::paramset::*;
 use core::pbrt::*;
+use core::profiler::*;
 use core::rng::*;
 use core::sampler::*;
 use std::sync::atomic::AtomicUsize;
@@ -208,14 +209,16 @@ impl Sampler for HaltonSampler {
         self.data.start_pixel(p);
 
         self.gdata.dimension = 0;
-        self.gdata.interval_sample_index = self.get_index_for_sample(0);
+        self.gdata.interval_sample_index = self.get_index_for_sample(self.data.first_sample_index);
 
         // Compute 1D array samples for `GlobalSampler`.
         let len_1d_sizes = self.data.samples_1d_array_sizes.len();
         for i in 0..len_1d_sizes {
-            let n_samples = self.data.samples_1d_array_sizes[i] * self.data.samples_per_pixel;
+            let n = self.data.samples_1d_array_sizes[i];
+            let n_samples = n * self.data.samples_per_pixel;
+            let first = self.data.first_sample_index * n;
             for j in 0..n_samples {
-                let index = self.get_index_for_sample(j);
+                let index = self.get_index_for_sample(first + j);
                 self.data.sample_array_1d[i][j] =
                     self.sample_dimension(index, self.gdata.array_start_dim + i as u16);
             }
@@ -225,9 +228,11 @@ impl Sampler for HaltonSampler {
         let mut dim = self.gdata.array_start_dim + self.data.samples_1d_array_sizes.len() as u16;
         let len_2d_sizes = self.data.samples_2d_array_sizes.len();
         for i in 0..len_2d_sizes {
-            let n_samples = self.data.samples_2d_array_sizes[i] * self.data.samples_per_pixel;
+            let n = self.data.samples_2d_array_sizes[i];
+            let n_samples = n * self.data.samples_per_pixel;
+            let first = self.data.first_sample_index * n;
             for j in 0..n_samples {
-                let index = self.get_index_for_sample(j);
+                let index = self.get_index_for_sample(first + j);
                 self.data.sample_array_2d[i][j] = Point2f::new(
                     self.sample_dimension(index, dim),
                     self.sample_dimension(index, dim + 1),
@@ -242,6 +247,7 @@ impl Sampler for HaltonSampler {
     /// Returns the sample value for the next dimension of the current sample
     /// vector.
     fn get_1d(&mut self) -> Float {
+        let _p = ProfilePhase::new(Prof::GetSample);
         if self.gdata.dimension >= self.gdata.array_start_dim
             && self.gdata.dimension < self.gdata.array_end_dim
         {
@@ -259,6 +265,7 @@ impl Sampler for HaltonSampler {
     /// Returns the sample value for the next two dimensions of the current
     /// sample vector.
     fn get_2d(&mut self) -> Point2f {
+        let _p = ProfilePhase::new(Prof::GetSample);
         if self.gdata.dimension + 1 >= self.gdata.array_start_dim
             && self.gdata.dimension < self.gdata.array_end_dim
         {
@@ -283,8 +290,9 @@ impl Sampler for HaltonSampler {
     /// `current_pixel_sample_index` < `samples_per_pixel`; otherwise `false`.
     fn start_next_sample(&mut self) -> bool {
         self.gdata.dimension = 0;
-        self.gdata.interval_sample_index =
-            self.get_index_for_sample(self.data.current_pixel_sample_index + 1);
+        self.gdata.interval_sample_index = self.get_index_for_sample(
+            self.data.first_sample_index + self.data.current_pixel_sample_index + 1,
+        );
         self.data.start_next_sample()
     }
 
@@ -295,7 +303,8 @@ impl Sampler for HaltonSampler {
     /// * `sample_num` - The sample number.
     fn set_sample_number(&mut self, sample_num: usize) -> bool {
         self.gdata.dimension = 0;
-        self.gdata.interval_sample_index = self.get_index_for_sample(sample_num);
+        self.gdata.interval_sample_index =
+            self.get_index_for_sample(self.data.first_sample_index + sample_num);
         self.data.set_sample_number(sample_num)
     }
 }
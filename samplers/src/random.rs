@@ -3,6 +3,7 @@
 use core::geometry::*;
 use core::paramset::*;
 use core::pbrt::*;
+use core::profiler::*;
 use core::rng::*;
 use core::sampler::*;
 use std::sync::Arc;
@@ -72,12 +73,14 @@ impl Sampler for RandomSampler {
     /// Returns the sample value for the next dimension of the current sample
     /// vector.
     fn get_1d(&mut self) -> Float {
+        let _p = ProfilePhase::new(Prof::GetSample);
         self.rng.uniform()
     }
 
     /// Returns the sample value for the next two dimensions of the current
     /// sample vector.
     fn get_2d(&mut self) -> Point2f {
+        let _p = ProfilePhase::new(Prof::GetSample);
         Point2f::new(self.rng.uniform(), self.rng.uniform())
     }
 }
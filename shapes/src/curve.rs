@@ -596,7 +596,7 @@ impl Curve {
         let eps = max(self.common.width[0], self.common.width[1]) * 0.05; // width / 20
 
         // Compute log base 4 by dividing log2 in half.
-        let r0 = Log2::log2(1.41421356237 * 6.0 * l0 / (8.0 * eps)) / 2;
+        let r0 = Log2::log2(std::f32::consts::SQRT_2 * 6.0 * l0 / (8.0 * eps)) / 2;
         let max_depth = clamp(r0, 0, 10);
 
         self.recursive_intersect(
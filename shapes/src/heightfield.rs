@@ -0,0 +1,139 @@
+//! Heightfields
+
+use crate::triangle::*;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use std::sync::Arc;
+
+/// Heightfield over the unit square in the xy-plane given by a regular grid
+/// of z-values. It is converted to a triangle mesh when created.
+pub struct Heightfield2 {}
+
+impl Heightfield2 {
+    /// Triangulate a heightfield. Each cell of the grid is split into two
+    /// triangles and the uv-coordinates follow the x and y coordinates.
+    ///
+    /// * `object_to_world`     - The object to world transfomation.
+    /// * `world_to_object`     - The world to object transfomation.
+    /// * `reverse_orientation` - Indicates whether their surface normal directions
+    ///                           should be reversed from the default.
+    /// * `nu`                  - Number of samples in the x-direction.
+    /// * `nv`                  - Number of samples in the y-direction.
+    /// * `z`                   - Heights, `nu` per row for `nv` rows.
+    pub fn create(
+        object_to_world: ArcTransform,
+        world_to_object: ArcTransform,
+        reverse_orientation: bool,
+        nu: usize,
+        nv: usize,
+        z: &[Float],
+    ) -> Vec<ArcShape> {
+        assert!(nu > 1 && nv > 1);
+        assert!(z.len() == nu * nv);
+
+        let mut p = Vec::with_capacity(nu * nv);
+        let mut uv = Vec::with_capacity(nu * nv);
+        for y in 0..nv {
+            for x in 0..nu {
+                let u = x as Float / (nu - 1) as Float;
+                let v = y as Float / (nv - 1) as Float;
+                p.push(Point3f::new(u, v, z[y * nu + x]));
+                uv.push(Point2f::new(u, v));
+            }
+        }
+
+        let vert = |x: usize, y: usize| x + y * nu;
+        let mut vertex_indices = Vec::with_capacity(6 * (nu - 1) * (nv - 1));
+        for y in 0..nv - 1 {
+            for x in 0..nu - 1 {
+                vertex_indices.extend_from_slice(&[
+                    vert(x, y),
+                    vert(x + 1, y),
+                    vert(x + 1, y + 1),
+                    vert(x, y),
+                    vert(x + 1, y + 1),
+                    vert(x, y + 1),
+                ]);
+            }
+        }
+
+        TriangleMesh::create(
+            Arc::clone(&object_to_world),
+            Arc::clone(&world_to_object),
+            reverse_orientation,
+            vertex_indices,
+            p,
+            vec![],
+            vec![],
+            uv,
+            None,
+            None,
+            vec![],
+        )
+    }
+
+    /// Create `Heightfield2` from given parameter set, object to world
+    /// transform, world to object transform and whether or not surface normal
+    /// orientation is reversed.
+    ///
+    /// NOTE: Because we return a set of triangles as `Vec<Arc<Shape>>` we
+    /// cannot implement this as `From` trait :(
+    ///
+    /// * `p` - A tuple containing the parameter set, object to world transform,
+    ///         world to object transform and whether or not surface normal
+    ///         orientation is reversed.
+    pub fn from_props(p: (&ParamSet, ArcTransform, ArcTransform, bool)) -> Vec<ArcShape> {
+        let (params, o2w, w2o, reverse_orientation) = p;
+
+        let nu = params.find_one_int("nu", -1);
+        let nv = params.find_one_int("nv", -1);
+        let z = params.find_float("Pz");
+        if nu < 2 || nv < 2 {
+            error!("'nu' and 'nv' must be at least 2 for heightfield shape");
+            return vec![];
+        }
+
+        let (nu, nv) = (nu as usize, nv as usize);
+        if z.len() != nu * nv {
+            error!(
+                "Number of 'Pz' for heightfield must be {} ('nu' * 'nv'). Found {}.",
+                nu * nv,
+                z.len()
+            );
+            return vec![];
+        }
+
+        Self::create(
+            Arc::clone(&o2w),
+            Arc::clone(&w2o),
+            reverse_orientation,
+            nu,
+            nv,
+            &z,
+        )
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triangulates_grid() {
+        let identity = Arc::new(Transform::default());
+        let z = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let tris = Heightfield2::create(Arc::clone(&identity), identity, false, 3, 2, &z);
+        assert_eq!(tris.len(), 4);
+
+        let bounds = tris
+            .iter()
+            .fold(Bounds3f::empty(), |b, t| b.union(&t.world_bound()));
+        assert_eq!(bounds.p_min, Point3f::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.p_max, Point3f::new(1.0, 1.0, 5.0));
+    }
+}
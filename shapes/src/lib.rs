@@ -1,5 +1,7 @@
 //! Geometry
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
@@ -7,9 +9,13 @@ mod cone;
 mod curve;
 mod cylinder;
 mod disk;
+mod heightfield;
 mod hyperboloid;
 mod loopsubdiv;
+mod metaball;
+mod nurbs;
 mod paraboloid;
+mod plymesh;
 mod sphere;
 mod triangle;
 
@@ -18,8 +24,12 @@ pub use cone::*;
 pub use curve::*;
 pub use cylinder::*;
 pub use disk::*;
+pub use heightfield::*;
 pub use hyperboloid::*;
 pub use loopsubdiv::*;
+pub use metaball::*;
+pub use nurbs::*;
 pub use paraboloid::*;
+pub use plymesh::*;
 pub use sphere::*;
 pub use triangle::*;
@@ -174,7 +174,7 @@ impl SDEdge {
     /// * `v1` - Second endpoint.
     fn new(v0: i64, v1: i64) -> Self {
         Self {
-            v: [v0, v1],
+            v: [min(v0, v1), max(v0, v1)],
             f: [-1, -1],
             f0_edge_num: -1,
         }
@@ -385,8 +385,9 @@ impl LoopSubDiv {
                 } else {
                     // Handle previously seen edge.
                     let e = edges.take(&e).unwrap();
+                    let f0 = Arc::get_mut(&mut faces[e.f[0] as usize]).unwrap();
+                    f0.f[e.f0_edge_num as usize] = i as i64;
                     let f = Arc::get_mut(&mut faces[i]).unwrap();
-                    f.f[e.f0_edge_num as usize] = i as i64;
                     f.f[edge_num as usize] = e.f[0];
                 }
             }
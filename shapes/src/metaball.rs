@@ -0,0 +1,512 @@
+//! Metaballs
+
+#![allow(dead_code)]
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::rng::ONE_MINUS_EPSILON;
+use core::sampling::*;
+use std::sync::Arc;
+
+/// Maximum number of sphere tracing steps before giving up on a ray.
+const MAX_STEPS: usize = 256;
+
+/// Number of bisection steps used to refine a bracketed root.
+const REFINE_STEPS: usize = 16;
+
+/// Lipschitz constant of the kernel `(1 - s^2)^3` with respect to `s`. The
+/// derivative `6s(1 - s^2)^2` peaks at `s = 1/√5`.
+const KERNEL_LIPSCHITZ: Float = 1.717_300_3;
+
+/// A single control point of a metaball.
+#[derive(Copy, Clone)]
+pub struct Blob {
+    /// Centre of the kernel.
+    pub center: Point3f,
+
+    /// Radius of influence; the kernel is zero beyond it.
+    pub radius: Float,
+
+    /// Weight applied to the kernel.
+    pub weight: Float,
+}
+
+impl Blob {
+    /// Create a new `Blob`.
+    ///
+    /// * `center` - Centre of the kernel.
+    /// * `radius` - Radius of influence.
+    /// * `weight` - Weight applied to the kernel.
+    pub fn new(center: Point3f, radius: Float, weight: Float) -> Self {
+        Self {
+            center,
+            radius,
+            weight,
+        }
+    }
+
+    /// Returns the radius at which this blob alone reaches the given threshold
+    /// or 0 if it never does.
+    ///
+    /// * `threshold` - Iso-surface threshold.
+    fn iso_radius(&self, threshold: Float) -> Float {
+        if self.weight <= threshold {
+            0.0
+        } else {
+            self.radius * (1.0 - (threshold / self.weight).cbrt()).sqrt()
+        }
+    }
+}
+
+/// An implicit surface defined as the iso-surface of a sum of polynomial
+/// kernels `w (1 - (d/r)^2)^3` centered at control points. Intersections are
+/// found by sphere tracing using the Lipschitz bound of the field and then
+/// refined with bisection.
+#[derive(Clone)]
+pub struct Metaball {
+    /// Common shape data.
+    pub data: Arc<ShapeData>,
+
+    /// The control points.
+    pub blobs: Vec<Blob>,
+
+    /// The iso-surface threshold.
+    pub threshold: Float,
+
+    /// Tolerance of the root finder in object space.
+    pub epsilon: Float,
+
+    /// Lipschitz constant of the field.
+    lipschitz: Float,
+
+    /// Object space bounds of the region where the field is non-zero.
+    bounds: Bounds3f,
+
+    /// Approximate surface area (sum of the iso-spheres of each blob).
+    area: Float,
+}
+
+impl Metaball {
+    /// Create a new metaball. Returns an error if there are no control
+    /// points.
+    ///
+    /// * `object_to_world`     - The object to world transfomation.
+    /// * `world_to_object`     - The world to object transfomation.
+    /// * `reverse_orientation` - Indicates whether their surface normal directions
+    ///                           should be reversed from the default
+    /// * `blobs`               - The control points.
+    /// * `threshold`           - The iso-surface threshold.
+    /// * `epsilon`             - Tolerance of the root finder in object space.
+    pub fn new(
+        object_to_world: ArcTransform,
+        world_to_object: ArcTransform,
+        reverse_orientation: bool,
+        blobs: Vec<Blob>,
+        threshold: Float,
+        epsilon: Float,
+    ) -> Result<Self, String> {
+        if blobs.is_empty() {
+            return Err(String::from(
+                "Metaball requires at least one control point 'P'.",
+            ));
+        }
+
+        let bounds = blobs.iter().fold(Bounds3f::empty(), |b, blob| {
+            let r = Vector3f::new(blob.radius, blob.radius, blob.radius);
+            b.union(&Bounds3f::new(blob.center - r, blob.center + r))
+        });
+        let lipschitz = blobs
+            .iter()
+            .map(|blob| abs(blob.weight) * KERNEL_LIPSCHITZ / blob.radius)
+            .sum();
+        let area = blobs
+            .iter()
+            .map(|blob| {
+                let r = blob.iso_radius(threshold);
+                FOUR_PI * r * r
+            })
+            .sum();
+
+        Ok(Self {
+            data: Arc::new(ShapeData::new(
+                Arc::clone(&object_to_world),
+                Some(Arc::clone(&world_to_object)),
+                reverse_orientation,
+            )),
+            blobs,
+            threshold,
+            epsilon,
+            lipschitz,
+            bounds,
+            area,
+        })
+    }
+
+    /// Returns the default root finder tolerance scaled by the declared scene
+    /// scale, or by one derived from the object space bounds if there is none.
+    ///
+    /// * `scene_scale` - Scene scale declared with `Option "float scenescale"`.
+    pub fn default_epsilon(&self, scene_scale: Option<Float>) -> Float {
+        let (_, radius) = self.bounds.bounding_sphere();
+        1e-4 * object_scene_scale(scene_scale, radius)
+    }
+
+    /// Evaluates the field at a point in object space.
+    ///
+    /// * `p` - The point.
+    pub fn field(&self, p: &Point3f) -> Float {
+        self.blobs
+            .iter()
+            .map(|blob| {
+                let s2 = p.distance_squared(blob.center) / (blob.radius * blob.radius);
+                if s2 < 1.0 {
+                    let k = 1.0 - s2;
+                    blob.weight * k * k * k
+                } else {
+                    0.0
+                }
+            })
+            .sum()
+    }
+
+    /// Evaluates the gradient of the field at a point in object space.
+    ///
+    /// * `p` - The point.
+    pub fn gradient(&self, p: &Point3f) -> Vector3f {
+        self.blobs.iter().fold(Vector3f::default(), |g, blob| {
+            let inv_r2 = 1.0 / (blob.radius * blob.radius);
+            let d = *p - blob.center;
+            let s2 = d.length_squared() * inv_r2;
+            if s2 < 1.0 {
+                let k = 1.0 - s2;
+                g + d * (-6.0 * blob.weight * k * k * inv_r2)
+            } else {
+                g
+            }
+        })
+    }
+
+    /// Finds the nearest root of `threshold - field` along the ray within the
+    /// object space bounds and returns the ray parameter.
+    ///
+    /// * `ray` - The ray in object space.
+    fn find_root(&self, ray: &Ray) -> Option<Float> {
+        if self.blobs.is_empty() || self.lipschitz == 0.0 {
+            return None;
+        }
+
+        let (t0, t1) = self.bounds.intersect_p(ray)?;
+        let d_len = ray.d.length();
+        if d_len == 0.0 {
+            return None;
+        }
+
+        // The signed distance to the surface is bounded below by `g / L`.
+        let g = |t: Float| self.threshold - self.field(&ray.at(t));
+        let min_step = self.epsilon / d_len;
+
+        let mut t = max(t0, 0.0);
+        let mut gt = g(t);
+        if gt <= 0.0 {
+            // Ray starts inside; march until the field drops below threshold.
+            for _ in 0..MAX_STEPS {
+                let t_next = t + max(abs(gt) / (self.lipschitz * d_len), min_step);
+                if t_next > t1 {
+                    return None;
+                }
+                let g_next = g(t_next);
+                if g_next > 0.0 {
+                    return Some(self.refine(&g, t, t_next));
+                }
+                t = t_next;
+                gt = g_next;
+            }
+            return None;
+        }
+
+        for _ in 0..MAX_STEPS {
+            let t_next = t + max(gt / (self.lipschitz * d_len), min_step);
+            if t_next > t1 {
+                return None;
+            }
+            let g_next = g(t_next);
+            if g_next <= 0.0 {
+                return Some(self.refine(&g, t, t_next));
+            }
+            t = t_next;
+            gt = g_next;
+        }
+        None
+    }
+
+    /// Bisects a bracketed root of `g` in `[a, b]`.
+    ///
+    /// * `g` - The function.
+    /// * `a` - Lower end of the bracket.
+    /// * `b` - Upper end of the bracket.
+    fn refine<G: Fn(Float) -> Float>(&self, g: &G, mut a: Float, mut b: Float) -> Float {
+        let ga_positive = g(a) > 0.0;
+        for _ in 0..REFINE_STEPS {
+            let m = 0.5 * (a + b);
+            if (g(m) > 0.0) == ga_positive {
+                a = m;
+            } else {
+                b = m;
+            }
+        }
+        0.5 * (a + b)
+    }
+}
+
+impl Shape for Metaball {
+    /// Returns the underlying shape data.
+    fn get_data(&self) -> Arc<ShapeData> {
+        Arc::clone(&self.data)
+    }
+
+    /// Returns a bounding box in the shapes object space.
+    fn object_bound(&self) -> Bounds3f {
+        self.bounds
+    }
+
+    /// Returns geometric details if a ray intersects the shape intersection.
+    /// If there is no intersection, `None` is returned.
+    ///
+    /// * `r`                  - The ray.
+    /// * `test_alpha_texture` - Perform alpha texture tests (not supported).
+    fn intersect<'a>(&self, r: &Ray, _test_alpha_texture: bool) -> Option<Intersection<'a>> {
+        // Transform ray to object space.
+        let ray = self
+            .data
+            .world_to_object
+            .as_ref()
+            .map(|w2o| w2o.transform_ray(r))
+            .unwrap();
+
+        let t_hit = self.find_root(&ray)?;
+        let p_hit = ray.at(t_hit);
+
+        // The field decreases outwards so the outward normal is `-∇f`.
+        let grad = self.gradient(&p_hit);
+        if grad.length_squared() == 0.0 {
+            return None;
+        }
+        let n = -grad.normalize();
+
+        // Parameterize by the direction from the bounds centre.
+        let (center, _radius) = self.bounds.bounding_sphere();
+        let dir = (p_hit - center).normalize();
+        let mut phi = dir.y.atan2(dir.x);
+        if phi < 0.0 {
+            phi += TWO_PI;
+        }
+        let theta = clamp(dir.z, -1.0, 1.0).acos();
+        let uv = Point2f::new(phi * INV_TWO_PI, theta * INV_PI);
+
+        // Build tangents so that `dpdu x dpdv` points along the normal.
+        let (dpdu, dpdv) = coordinate_system(&n);
+
+        let p_error = Vector3f::new(self.epsilon, self.epsilon, self.epsilon)
+            + gamma(5) * Vector3f::from(p_hit).abs();
+
+        let si = SurfaceInteraction::new(
+            p_hit,
+            p_error,
+            uv,
+            -ray.d,
+            dpdu,
+            dpdv,
+            Normal3f::default(),
+            Normal3f::default(),
+            ray.time,
+            Arc::clone(&self.data),
+            None,
+        );
+
+        let isect = self.data.object_to_world.transform_surface_interaction(&si);
+        Some(Intersection::new(t_hit, isect))
+    }
+
+    /// Returns `true` if a ray-shape intersection succeeds; otherwise `false`.
+    ///
+    /// * `r`                  - The ray.
+    /// * `test_alpha_texture` - Perform alpha texture tests (not supported).
+    fn intersect_p(&self, r: &Ray, _test_alpha_texture: bool) -> bool {
+        let ray = self
+            .data
+            .world_to_object
+            .as_ref()
+            .map(|w2o| w2o.transform_ray(r))
+            .unwrap();
+        self.find_root(&ray).is_some()
+    }
+
+    /// Returns the surface area of the shape in object space. This is
+    /// approximated by the iso-spheres of the individual blobs.
+    fn area(&self) -> Float {
+        self.area
+    }
+
+    /// Sample a point on the surface and return the PDF with respect to area on
+    /// the surface. A blob is chosen proportional to its iso-sphere area and a
+    /// point on that iso-sphere is returned.
+    ///
+    /// NOTE: The returned `Hit` value will have `wo` = Vector3f::default().
+    ///
+    /// * `u` - Sample value to use.
+    fn sample_area(&self, u: &Point2f) -> (Hit, Float) {
+        // Pick a blob proportional to its iso-sphere area and remap `u.x`.
+        let mut u = *u;
+        let mut chosen = self.blobs[0];
+        let mut acc = 0.0;
+        for blob in self.blobs.iter() {
+            let r = blob.iso_radius(self.threshold);
+            let a = FOUR_PI * r * r / self.area;
+            if a > 0.0 && u.x < acc + a {
+                chosen = *blob;
+                u.x = min((u.x - acc) / a, ONE_MINUS_EPSILON);
+                break;
+            }
+            acc += a;
+        }
+
+        let r = chosen.iso_radius(self.threshold);
+        let w = uniform_sample_sphere(&u);
+        let p_obj = chosen.center + r * w;
+
+        let mut n = self
+            .data
+            .object_to_world
+            .transform_normal(&Normal3f::new(w.x, w.y, w.z))
+            .normalize();
+        if self.data.reverse_orientation {
+            n *= -1.0;
+        }
+
+        let p_obj_error = gamma(5) * Vector3f::from(p_obj).abs();
+        let p = self.data.object_to_world.transform_point(&p_obj);
+        let p_error = self
+            .data
+            .object_to_world
+            .transform_point_abs_error(&p_obj, &p_obj_error);
+        let it = Hit::new(p, 0.0, p_error, Vector3f::default(), n, None);
+        (it, 1.0 / self.area)
+    }
+}
+
+impl Metaball {
+    /// Create `Metaball` shapes from given parameter set, object to world
+    /// transform, world to object transform and whether or not surface normal
+    /// orientation is reversed. The default root finder tolerance is scaled
+    /// by the declared scene scale, or by one derived from the metaball's
+    /// bounds if there is none.
+    ///
+    /// * `p`           - A tuple containing the parameter set, object to world
+    ///                   transform, world to object transform and whether or
+    ///                   not surface normal orientation is reversed.
+    /// * `scene_scale` - Scene scale declared with `Option "float scenescale"`.
+    pub fn from_props(
+        p: (&ParamSet, ArcTransform, ArcTransform, bool),
+        scene_scale: Option<Float>,
+    ) -> Vec<ArcShape> {
+        let (params, o2w, w2o, reverse_orientation) = p;
+
+        let centers = params.find_point3f("P");
+        let radii = params.find_float("radius");
+        let weights = params.find_float("weight");
+        let threshold = params.find_one_float("threshold", 0.5);
+
+        // Radius and weight may be given once for all blobs or once per blob.
+        let value_at = |values: &[Float], i: usize, default: Float| match values.len() {
+            0 => default,
+            1 => values[0],
+            n if i < n => values[i],
+            _ => {
+                warn!("Metaball parameter count mismatch; using {}.", default);
+                default
+            }
+        };
+
+        let blobs = centers
+            .iter()
+            .enumerate()
+            .map(|(i, c)| Blob::new(*c, value_at(&radii, i, 1.0), value_at(&weights, i, 1.0)))
+            .collect();
+
+        match Self::new(
+            Arc::clone(&o2w),
+            Arc::clone(&w2o),
+            reverse_orientation,
+            blobs,
+            threshold,
+            1e-4,
+        ) {
+            Ok(mut metaball) => {
+                let epsilon = metaball.default_epsilon(scene_scale);
+                metaball.epsilon = params.find_one_float("epsilon", epsilon);
+                vec![Arc::new(metaball)]
+            }
+            Err(err) => {
+                error!("{}", err);
+                vec![]
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_blob_lists_are_rejected() {
+        let identity = Arc::new(Transform::default());
+        let metaball = |blobs| {
+            Metaball::new(
+                Arc::clone(&identity),
+                Arc::clone(&identity),
+                false,
+                blobs,
+                0.5,
+                1e-4,
+            )
+        };
+        assert!(metaball(vec![]).is_err());
+
+        let params = ParamSet::new();
+        let shapes = Metaball::from_props(
+            (&params, Arc::clone(&identity), identity.clone(), false),
+            None,
+        );
+        assert!(shapes.is_empty());
+
+        // A single blob samples its iso-sphere.
+        let blob = Blob::new(Point3f::new(1.0, 0.0, 0.0), 2.0, 1.0);
+        let (hit, pdf) = metaball(vec![blob])
+            .unwrap()
+            .sample_area(&Point2f::new(0.3, 0.6));
+        let r = blob.iso_radius(0.5);
+        assert!(((hit.p - blob.center).length() - r).abs() < 1e-3);
+        assert!((pdf - 1.0 / (FOUR_PI * r * r)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn default_epsilon_follows_scene_scale() {
+        let identity = Arc::new(Transform::default());
+        let metaball = |radius| {
+            let blob = Blob::new(Point3f::new(0.0, 0.0, 0.0), radius, 1.0);
+            let (o2w, w2o) = (Arc::clone(&identity), Arc::clone(&identity));
+            Metaball::new(o2w, w2o, false, vec![blob], 0.5, 1e-4).unwrap()
+        };
+
+        assert_eq!(metaball(1.0).default_epsilon(None), 1e-4);
+        assert!((metaball(1.0).default_epsilon(Some(10.0)) - 1e-3).abs() < 1e-9);
+        // A kilometre sized metaball derives its own scale.
+        assert!(metaball(1e5).default_epsilon(None) > 1e-3);
+        assert!((metaball(1e5).default_epsilon(Some(1.0)) - 1e-4).abs() < 1e-9);
+    }
+}
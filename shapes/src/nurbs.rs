@@ -0,0 +1,479 @@
+//! NURBS Surfaces
+
+use crate::triangle::*;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use std::sync::Arc;
+
+/// Number of points the surface is evaluated at along each parametric
+/// direction when it is converted to a triangle mesh.
+const DICE: usize = 30;
+
+/// A control point in homogeneous coordinates.
+#[derive(Copy, Clone, Debug, Default)]
+struct Homogeneous3 {
+    x: Float,
+    y: Float,
+    z: Float,
+    w: Float,
+}
+
+impl Homogeneous3 {
+    /// Returns the blend `alpha * self + (1 - alpha) * other`.
+    ///
+    /// * `alpha` - Weight of this point.
+    /// * `other` - The other point.
+    fn blend(&self, alpha: Float, other: &Self) -> Self {
+        Self {
+            x: self.x * alpha + other.x * (1.0 - alpha),
+            y: self.y * alpha + other.y * (1.0 - alpha),
+            z: self.z * alpha + other.z * (1.0 - alpha),
+            w: self.w * alpha + other.w * (1.0 - alpha),
+        }
+    }
+
+    /// Returns the point projected back from homogeneous coordinates.
+    fn project(&self) -> Point3f {
+        Point3f::new(self.x / self.w, self.y / self.w, self.z / self.w)
+    }
+}
+
+/// Non-uniform rational B-spline surface. It is converted to a triangle mesh
+/// when created by evaluating it over a regular grid of parameter values.
+pub struct Nurbs {}
+
+impl Nurbs {
+    /// Triangulate a NURBS surface.
+    ///
+    /// * `object_to_world`     - The object to world transfomation.
+    /// * `world_to_object`     - The world to object transfomation.
+    /// * `reverse_orientation` - Indicates whether their surface normal directions
+    ///                           should be reversed from the default.
+    /// * `u`                   - Order, knots and parameter range in u.
+    /// * `v`                   - Order, knots and parameter range in v.
+    /// * `nu`                  - Number of control points in u.
+    /// * `nv`                  - Number of control points in v.
+    /// * `pw`                  - Homogeneous control points, `nu` per row for
+    ///                           `nv` rows.
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        object_to_world: ArcTransform,
+        world_to_object: ArcTransform,
+        reverse_orientation: bool,
+        u: &NurbsBasis,
+        v: &NurbsBasis,
+        nu: usize,
+        nv: usize,
+        pw: &[Homogeneous3],
+    ) -> Vec<ArcShape> {
+        // Evaluate the surface over a grid of points.
+        let mut p = Vec::with_capacity(DICE * DICE);
+        let mut n = Vec::with_capacity(DICE * DICE);
+        let mut uv = Vec::with_capacity(DICE * DICE);
+        for iv in 0..DICE {
+            let sv = lerp(iv as Float / (DICE - 1) as Float, v.t0, v.t1);
+            for iu in 0..DICE {
+                let su = lerp(iu as Float / (DICE - 1) as Float, u.t0, u.t1);
+                let (pt, dpdu, dpdv) = evaluate_surface(u, nu, su, v, nv, sv, pw);
+                p.push(pt);
+                n.push(Normal3f::from(dpdu.cross(&dpdv).normalize()));
+                uv.push(Point2f::new(su, sv));
+            }
+        }
+
+        let vert = |x: usize, y: usize| x + y * DICE;
+        let mut vertex_indices = Vec::with_capacity(6 * (DICE - 1) * (DICE - 1));
+        for y in 0..DICE - 1 {
+            for x in 0..DICE - 1 {
+                vertex_indices.extend_from_slice(&[
+                    vert(x, y),
+                    vert(x + 1, y),
+                    vert(x + 1, y + 1),
+                    vert(x, y),
+                    vert(x + 1, y + 1),
+                    vert(x, y + 1),
+                ]);
+            }
+        }
+
+        TriangleMesh::create(
+            Arc::clone(&object_to_world),
+            Arc::clone(&world_to_object),
+            reverse_orientation,
+            vertex_indices,
+            p,
+            n,
+            vec![],
+            uv,
+            None,
+            None,
+            vec![],
+        )
+    }
+
+    /// Create `Nurbs` from given parameter set, object to world transform,
+    /// world to object transform and whether or not surface normal
+    /// orientation is reversed.
+    ///
+    /// NOTE: Because we return a set of triangles as `Vec<Arc<Shape>>` we
+    /// cannot implement this as `From` trait :(
+    ///
+    /// * `p` - A tuple containing the parameter set, object to world transform,
+    ///         world to object transform and whether or not surface normal
+    ///         orientation is reversed.
+    pub fn from_props(p: (&ParamSet, ArcTransform, ArcTransform, bool)) -> Vec<ArcShape> {
+        let (params, o2w, w2o, reverse_orientation) = p;
+
+        let nu = params.find_one_int("nu", -1);
+        let nv = params.find_one_int("nv", -1);
+        if nu < 1 || nv < 1 {
+            error!("Must provide number of control points 'nu' and 'nv' with NURBS shape.");
+            return vec![];
+        }
+        let (nu, nv) = (nu as usize, nv as usize);
+
+        let u = match NurbsBasis::from_params(params, "u", nu) {
+            Ok(u) => u,
+            Err(err) => {
+                error!("{}", err);
+                return vec![];
+            }
+        };
+        let v = match NurbsBasis::from_params(params, "v", nv) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("{}", err);
+                return vec![];
+            }
+        };
+
+        let p = params.find_point3f("P");
+        let pw: Vec<Homogeneous3> = if !p.is_empty() {
+            p.iter()
+                .map(|p| Homogeneous3 {
+                    x: p.x,
+                    y: p.y,
+                    z: p.z,
+                    w: 1.0,
+                })
+                .collect()
+        } else {
+            let pw = params.find_float("Pw");
+            if pw.is_empty() {
+                error!("Must provide control points via 'P' or 'Pw' parameter to NURBS shape.");
+                return vec![];
+            }
+            if pw.len() % 4 != 0 {
+                error!("Number of 'Pw' control points provided to NURBS shape must be multiple of four.");
+                return vec![];
+            }
+            pw.chunks_exact(4)
+                .map(|p| Homogeneous3 {
+                    x: p[0],
+                    y: p[1],
+                    z: p[2],
+                    w: p[3],
+                })
+                .collect()
+        };
+        if pw.len() != nu * nv {
+            error!(
+                "NURBS shape was expecting {}x{}={} control points, was given {}.",
+                nu,
+                nv,
+                nu * nv,
+                pw.len()
+            );
+            return vec![];
+        }
+
+        Self::create(
+            Arc::clone(&o2w),
+            Arc::clone(&w2o),
+            reverse_orientation,
+            &u,
+            &v,
+            nu,
+            nv,
+            &pw,
+        )
+    }
+}
+
+/// The B-spline basis along one parametric direction.
+struct NurbsBasis {
+    /// Order of the basis functions.
+    order: usize,
+
+    /// The knot vector.
+    knots: Vec<Float>,
+
+    /// Start of the parameter range to evaluate.
+    t0: Float,
+
+    /// End of the parameter range to evaluate.
+    t1: Float,
+}
+
+impl NurbsBasis {
+    /// Returns the basis for a parametric direction from the `uorder`,
+    /// `uknots`, `u0` and `u1` parameters or their `v` counterparts.
+    ///
+    /// * `params` - The parameter set.
+    /// * `dir`    - The parametric direction; `u` or `v`.
+    /// * `np`     - Number of control points in the direction.
+    fn from_params(params: &ParamSet, dir: &str, np: usize) -> Result<Self, String> {
+        let order = params.find_one_int(&format!("{}order", dir), -1);
+        if order < 2 {
+            return Err(format!(
+                "Must provide {} order '{}order' of at least 2 with NURBS shape.",
+                dir, dir
+            ));
+        }
+        let order = order as usize;
+
+        let knots = params.find_float(&format!("{}knots", dir));
+        if knots.len() != np + order {
+            return Err(format!(
+                "Number of knots in {} knot vector {} doesn't match sum of number of {} \
+                 control points {} and {} order {}.",
+                dir,
+                knots.len(),
+                dir,
+                np,
+                dir,
+                order
+            ));
+        }
+
+        // Limit the range to where the basis functions sum to one.
+        let (lo, hi) = (knots[order - 1], knots[np]);
+        let t0 = params.find_one_float(&format!("{}0", dir), lo);
+        let t1 = params.find_one_float(&format!("{}1", dir), hi);
+        Ok(Self {
+            order,
+            knots,
+            t0: clamp(t0, lo, hi),
+            t1: clamp(t1, lo, hi),
+        })
+    }
+
+    /// Returns the index of the knot interval containing a parameter value.
+    ///
+    /// * `np` - Number of control points.
+    /// * `t`  - The parameter value.
+    fn knot_offset(&self, np: usize, t: Float) -> usize {
+        let mut offset = self.order - 1;
+        while offset + 1 < np && t > self.knots[offset + 1] {
+            offset += 1;
+        }
+        offset
+    }
+
+    /// Evaluates the B-spline curve with the given control points with de
+    /// Boor's algorithm and returns the point and the derivative of its
+    /// projection with respect to the parameter.
+    ///
+    /// * `np` - Number of control points.
+    /// * `t`  - The parameter value.
+    /// * `cp` - Returns a control point given its index.
+    fn evaluate<F>(&self, np: usize, t: Float, cp: F) -> (Homogeneous3, Vector3f)
+    where
+        F: Fn(usize) -> Homogeneous3,
+    {
+        let order = self.order;
+        let offset = self.knot_offset(np, t);
+        let knot = |i: usize| self.knots[offset + i + 1 - order];
+
+        // `knot(i)` is the knot `i - order + 1` relative to the interval.
+        let cp_offset = offset + 1 - order;
+        let mut cp_work: Vec<Homogeneous3> = (0..order).map(|i| cp(cp_offset + i)).collect();
+        for i in 0..order - 2 {
+            for j in 0..order - 1 - i {
+                let k1 = knot(order + j);
+                let alpha = (k1 - t) / (k1 - knot(j + 1 + i));
+                cp_work[j] = cp_work[j].blend(alpha, &cp_work[j + 1]);
+            }
+        }
+
+        let (k0, k1) = (knot(order - 1), knot(order));
+        let alpha = (k1 - t) / (k1 - k0);
+        let val = cp_work[0].blend(alpha, &cp_work[1]);
+
+        let factor = (order - 1) as Float / (k1 - k0);
+        let delta = Homogeneous3 {
+            x: (cp_work[1].x - cp_work[0].x) * factor,
+            y: (cp_work[1].y - cp_work[0].y) * factor,
+            z: (cp_work[1].z - cp_work[0].z) * factor,
+            w: (cp_work[1].w - cp_work[0].w) * factor,
+        };
+        let w2 = val.w * val.w;
+        let deriv = Vector3f::new(
+            delta.x / val.w - val.x * delta.w / w2,
+            delta.y / val.w - val.y * delta.w / w2,
+            delta.z / val.w - val.z * delta.w / w2,
+        );
+        (val, deriv)
+    }
+}
+
+/// Evaluates a NURBS surface and returns the point and its partial
+/// derivatives.
+///
+/// * `u`  - Basis in u.
+/// * `nu` - Number of control points in u.
+/// * `su` - The u parameter value.
+/// * `v`  - Basis in v.
+/// * `nv` - Number of control points in v.
+/// * `sv` - The v parameter value.
+/// * `cp` - Homogeneous control points, `nu` per row for `nv` rows.
+fn evaluate_surface(
+    u: &NurbsBasis,
+    nu: usize,
+    su: Float,
+    v: &NurbsBasis,
+    nv: usize,
+    sv: Float,
+    cp: &[Homogeneous3],
+) -> (Point3f, Vector3f, Vector3f) {
+    // Evaluate the curves in v through the control points affecting `su`
+    // and then the curve in u through the resulting points.
+    let u_first = u.knot_offset(nu, su) + 1 - u.order;
+    let iso: Vec<Homogeneous3> = (0..u.order)
+        .map(|i| v.evaluate(nv, sv, |j| cp[j * nu + u_first + i]).0)
+        .collect();
+    let (p, dpdu) = u.evaluate(nu, su, |i| iso[i - u_first]);
+
+    // Do the same the other way round for the derivative in v.
+    let v_first = v.knot_offset(nv, sv) + 1 - v.order;
+    let iso: Vec<Homogeneous3> = (0..v.order)
+        .map(|j| u.evaluate(nu, su, |i| cp[(v_first + j) * nu + i]).0)
+        .collect();
+    let (_, dpdv) = v.evaluate(nv, sv, |j| iso[j - v_first]);
+
+    (p.project(), dpdu, dpdv)
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a basis over [0, 1] without interior knots.
+    ///
+    /// * `order` - Order of the basis functions.
+    fn bezier(order: usize) -> NurbsBasis {
+        let mut knots = vec![0.0; order];
+        knots.extend(vec![1.0; order]);
+        NurbsBasis {
+            order,
+            knots,
+            t0: 0.0,
+            t1: 1.0,
+        }
+    }
+
+    #[test]
+    fn bilinear_patch_is_triangulated() {
+        let identity = Arc::new(Transform::default());
+        let mut params = ParamSet::new();
+        params.add_int("nu", &[2]);
+        params.add_int("nv", &[2]);
+        params.add_int("uorder", &[2]);
+        params.add_int("vorder", &[2]);
+        params.add_float("uknots", &[0.0, 0.0, 1.0, 1.0]);
+        params.add_float("vknots", &[0.0, 0.0, 1.0, 1.0]);
+        params.add_point3f(
+            "P",
+            &[
+                Point3f::new(0.0, 0.0, 0.0),
+                Point3f::new(2.0, 0.0, 0.0),
+                Point3f::new(0.0, 1.0, 0.0),
+                Point3f::new(2.0, 1.0, 0.0),
+            ],
+        );
+
+        let tris = Nurbs::from_props((&params, Arc::clone(&identity), identity, false));
+        assert_eq!(tris.len(), 2 * (DICE - 1) * (DICE - 1));
+
+        let bounds = tris
+            .iter()
+            .fold(Bounds3f::empty(), |b, t| b.union(&t.world_bound()));
+        assert_eq!(bounds.p_min, Point3f::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.p_max, Point3f::new(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn missing_knots_are_rejected() {
+        let identity = Arc::new(Transform::default());
+        let mut params = ParamSet::new();
+        params.add_int("nu", &[2]);
+        params.add_int("nv", &[2]);
+        params.add_int("uorder", &[2]);
+        params.add_int("vorder", &[2]);
+        params.add_float("uknots", &[0.0, 1.0]);
+        params.add_float("vknots", &[0.0, 0.0, 1.0, 1.0]);
+        params.add_float("Pw", &[0.0; 16]);
+        assert!(Nurbs::from_props((&params, Arc::clone(&identity), identity, false)).is_empty());
+    }
+
+    #[test]
+    fn rational_quarter_circle() {
+        // A quadratic rational Bezier curve with these weights is exactly a
+        // quarter of the unit circle.
+        let w = 1.0 / Float::sqrt(2.0);
+        let cp = [
+            Homogeneous3 {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+                w: 1.0,
+            },
+            Homogeneous3 {
+                x: w,
+                y: w,
+                z: 0.0,
+                w,
+            },
+            Homogeneous3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+                w: 1.0,
+            },
+        ];
+        let basis = bezier(3);
+        for i in 0..=10 {
+            let t = i as Float / 10.0;
+            let (p, d) = basis.evaluate(3, t, |i| cp[i]);
+            let p = p.project();
+            assert!((Vector3f::new(p.x, p.y, p.z).length() - 1.0).abs() < 1e-5);
+
+            // The tangent is perpendicular to the radius.
+            assert!((d.x * p.x + d.y * p.y).abs() < 1e-4, "{} {:?}", t, d);
+        }
+    }
+
+    #[test]
+    fn surface_derivatives_follow_control_points() {
+        // A bilinear patch scaled by 2 in u and 3 in v.
+        let cp: Vec<Homogeneous3> = [(0.0, 0.0), (2.0, 0.0), (0.0, 3.0), (2.0, 3.0)]
+            .iter()
+            .map(|&(x, y)| Homogeneous3 {
+                x,
+                y,
+                z: 0.0,
+                w: 1.0,
+            })
+            .collect();
+        let (u, v) = (bezier(2), bezier(2));
+        let (p, dpdu, dpdv) = evaluate_surface(&u, 2, 0.25, &v, 2, 0.5, &cp);
+        assert_eq!(p, Point3f::new(0.5, 1.5, 0.0));
+        assert_eq!(dpdu, Vector3f::new(2.0, 0.0, 0.0));
+        assert_eq!(dpdv, Vector3f::new(0.0, 3.0, 0.0));
+    }
+}
@@ -0,0 +1,558 @@
+//! PLY Meshes
+
+use crate::triangle::*;
+use core::geometry::*;
+use core::paramset::*;
+use core::pbrt::*;
+use core::texture::*;
+use std::collections::HashMap;
+use std::fs;
+use std::str::{self, SplitWhitespace};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    /// PLY meshes read by this process by canonical path. Scenes rendered one
+    /// after another in the same process share them instead of reading the
+    /// files again.
+    static ref PLY_MESHES: Mutex<HashMap<String, Arc<PlyMesh>>> = Mutex::new(HashMap::new());
+}
+
+/// Triangle mesh loaded from a PLY file. Polygons with more than three
+/// vertices are triangulated as fans.
+#[derive(Clone, Default)]
+pub struct PlyMesh {
+    /// Vertex positions.
+    pub p: Vec<Point3f>,
+
+    /// Vertex normals. This will be empty if there are none.
+    pub n: Vec<Normal3f>,
+
+    /// Paramteric uv-coordinates per vertex. This will be empty if there are none.
+    pub uv: Vec<Point2f>,
+
+    /// Vertex indices, three per triangle.
+    pub vertex_indices: Vec<usize>,
+
+    /// Face indices, one per triangle. This will be empty if there are none.
+    pub face_indices: Vec<usize>,
+}
+
+impl PlyMesh {
+    /// Reads a PLY file.
+    ///
+    /// * `path` - Path to the file.
+    pub fn read(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("Error reading '{}'. {}", path, err))?;
+        Self::parse(&bytes).map_err(|err| format!("Error parsing '{}'. {}", path, err))
+    }
+
+    /// Returns the mesh of a PLY file from the cache of meshes read by this
+    /// process; if it isn't cached the file is read and the mesh is cached.
+    ///
+    /// * `path` - Canonical path to the file.
+    pub fn read_cached(path: &str) -> Result<Arc<Self>, String> {
+        let mut meshes = PLY_MESHES.lock().expect("Unable to access PLY mesh mutex");
+        match meshes.get(path) {
+            Some(mesh) => Ok(Arc::clone(mesh)),
+            None => {
+                let mesh = Arc::new(Self::read(path)?);
+                meshes.insert(path.to_string(), Arc::clone(&mesh));
+                Ok(mesh)
+            }
+        }
+    }
+
+    /// Parses the contents of a PLY file in ASCII or binary format.
+    ///
+    /// * `bytes` - Contents of the file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let (header, body) = split_header(bytes)?;
+        let (format, elements) = parse_header(header)?;
+
+        let mut reader = match format {
+            PlyFormat::Ascii => {
+                let text = str::from_utf8(body).map_err(|err| err.to_string())?;
+                PlyReader::Ascii(text.split_whitespace())
+            }
+            PlyFormat::BinaryLittleEndian => PlyReader::Binary(body, false),
+            PlyFormat::BinaryBigEndian => PlyReader::Binary(body, true),
+        };
+
+        let mut mesh = Self::default();
+        for element in elements.iter() {
+            match &element.name[..] {
+                "vertex" => mesh.read_vertices(element, &mut reader)?,
+                "face" => mesh.read_faces(element, &mut reader)?,
+                _ => {
+                    for _ in 0..element.count {
+                        for property in element.properties.iter() {
+                            reader.read_property(property)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        if mesh.p.is_empty() {
+            return Err(String::from("No vertices found."));
+        }
+        if mesh.vertex_indices.is_empty() {
+            return Err(String::from("No faces found."));
+        }
+        if let Some(i) = mesh.vertex_indices.iter().find(|&&i| i >= mesh.p.len()) {
+            return Err(format!(
+                "Out-of-bounds vertex index {} ({} vertices were given).",
+                i,
+                mesh.p.len()
+            ));
+        }
+        Ok(mesh)
+    }
+
+    /// Reads the vertex positions, normals and uv-coordinates.
+    ///
+    /// * `element` - The vertex element.
+    /// * `reader`  - Reader for the body of the file.
+    fn read_vertices(
+        &mut self,
+        element: &PlyElement,
+        reader: &mut PlyReader,
+    ) -> Result<(), String> {
+        let has = |names: &[&str]| names.iter().all(|name| element.property(name).is_some());
+        let has_n = has(&["nx", "ny", "nz"]);
+        let uv_names = [
+            ["u", "v"],
+            ["s", "t"],
+            ["texture_u", "texture_v"],
+            ["texture_s", "texture_t"],
+        ];
+        let uv_names = uv_names.iter().find(|names| has(&names[..]));
+
+        for _ in 0..element.count {
+            let mut values: HashMap<&str, Float> = HashMap::new();
+            for property in element.properties.iter() {
+                if let Some(v) = reader.read_property(property)? {
+                    values.insert(&property.name, v as Float);
+                }
+            }
+            let get = |name: &str| values.get(name).copied().unwrap_or(0.0);
+
+            self.p.push(Point3f::new(get("x"), get("y"), get("z")));
+            if has_n {
+                self.n.push(Normal3f::new(get("nx"), get("ny"), get("nz")));
+            }
+            if let Some([u, v]) = uv_names {
+                self.uv.push(Point2f::new(get(u), get(v)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the faces and triangulates them.
+    ///
+    /// * `element` - The face element.
+    /// * `reader`  - Reader for the body of the file.
+    fn read_faces(&mut self, element: &PlyElement, reader: &mut PlyReader) -> Result<(), String> {
+        for _ in 0..element.count {
+            let mut indices: Vec<usize> = vec![];
+            let mut face_index = None;
+            for property in element.properties.iter() {
+                match (&property.name[..], &property.kind) {
+                    ("vertex_indices", PlyPropertyKind::List(count_type, item_type))
+                    | ("vertex_index", PlyPropertyKind::List(count_type, item_type)) => {
+                        let count = reader.read(*count_type)? as usize;
+                        for _ in 0..count {
+                            indices.push(reader.read(*item_type)? as usize);
+                        }
+                    }
+                    ("face_indices", PlyPropertyKind::Scalar(t)) => {
+                        face_index = Some(reader.read(*t)? as usize);
+                    }
+                    _ => {
+                        reader.read_property(property)?;
+                    }
+                }
+            }
+
+            if indices.len() < 3 {
+                warn!("Skipping PLY face with {} vertices.", indices.len());
+                continue;
+            }
+            for i in 1..indices.len() - 1 {
+                self.vertex_indices
+                    .extend_from_slice(&[indices[0], indices[i], indices[i + 1]]);
+                if let Some(face_index) = face_index {
+                    self.face_indices.push(face_index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a triangle mesh from a PLY file given by the parameter set,
+    /// object to world transform, world to object transform and whether or
+    /// not surface normal orientation is reversed.
+    ///
+    /// * `p`              - A tuple containing the parameter set, object to
+    ///                      world transform, world to object transform and
+    ///                      whether or not surface normal orientation is reversed.
+    /// * `float_textures` - Float textures.
+    pub fn from_props(
+        p: (&ParamSet, ArcTransform, ArcTransform, bool),
+        float_textures: &HashMap<String, ArcTexture<Float>>,
+    ) -> Vec<ArcShape> {
+        let (params, o2w, w2o, reverse_orientation) = p;
+
+        let filename = params.find_one_string("filename", String::from(""));
+        let path = params.find_one_filename("filename", filename);
+        if path.is_empty() {
+            error!("Filename 'filename' not provided with plymesh shape");
+            return vec![];
+        }
+
+        let mesh = match Self::read_cached(&path) {
+            Ok(mesh) => mesh,
+            Err(err) => {
+                error!("{}", err);
+                return vec![];
+            }
+        };
+
+        let alpha_tex = alpha_texture(params, float_textures, "alpha");
+        let shadow_alpha_tex = alpha_texture(params, float_textures, "shadowalpha");
+
+        TriangleMesh::create(
+            Arc::clone(&o2w),
+            Arc::clone(&w2o),
+            reverse_orientation,
+            mesh.vertex_indices.clone(),
+            mesh.p.clone(),
+            mesh.n.clone(),
+            vec![],
+            mesh.uv.clone(),
+            alpha_tex,
+            shadow_alpha_tex,
+            mesh.face_indices.clone(),
+        )
+    }
+}
+
+/// Storage format of the body of a PLY file.
+#[derive(Copy, Clone, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+/// Scalar types of PLY properties.
+#[derive(Copy, Clone, PartialEq)]
+enum PlyType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    /// Returns the type given its name in the header.
+    ///
+    /// * `name` - Type name.
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "char" | "int8" => Ok(Self::Int8),
+            "uchar" | "uint8" => Ok(Self::UInt8),
+            "short" | "int16" => Ok(Self::Int16),
+            "ushort" | "uint16" => Ok(Self::UInt16),
+            "int" | "int32" => Ok(Self::Int32),
+            "uint" | "uint32" => Ok(Self::UInt32),
+            "float" | "float32" => Ok(Self::Float32),
+            "double" | "float64" => Ok(Self::Float64),
+            _ => Err(format!("Unknown property type '{}'.", name)),
+        }
+    }
+
+    /// Returns the size of a binary value in bytes.
+    fn size(&self) -> usize {
+        match self {
+            Self::Int8 | Self::UInt8 => 1,
+            Self::Int16 | Self::UInt16 => 2,
+            Self::Int32 | Self::UInt32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+}
+
+/// Kinds of PLY properties.
+enum PlyPropertyKind {
+    /// A single value.
+    Scalar(PlyType),
+
+    /// A list of values preceded by their count.
+    List(PlyType, PlyType),
+}
+
+/// A property of a PLY element.
+struct PlyProperty {
+    /// Property name.
+    name: String,
+
+    /// Property kind.
+    kind: PlyPropertyKind,
+}
+
+/// An element declared in the header of a PLY file.
+struct PlyElement {
+    /// Element name.
+    name: String,
+
+    /// Number of instances of the element in the body.
+    count: usize,
+
+    /// Properties of each instance.
+    properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    /// Returns the property with the given name.
+    ///
+    /// * `name` - Property name.
+    fn property(&self, name: &str) -> Option<&PlyProperty> {
+        self.properties.iter().find(|p| p.name == name)
+    }
+}
+
+/// Reads values from the body of a PLY file.
+enum PlyReader<'a> {
+    /// ASCII tokens.
+    Ascii(SplitWhitespace<'a>),
+
+    /// Remaining bytes and whether they are big endian.
+    Binary(&'a [u8], bool),
+}
+
+impl<'a> PlyReader<'a> {
+    /// Reads one value.
+    ///
+    /// * `t` - Value type.
+    fn read(&mut self, t: PlyType) -> Result<f64, String> {
+        match self {
+            Self::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| String::from("Unexpected end of file."))?;
+                token
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid value '{}'.", token))
+            }
+            Self::Binary(bytes, big_endian) => {
+                let size = t.size();
+                if bytes.len() < size {
+                    return Err(String::from("Unexpected end of file."));
+                }
+                let mut b = [0_u8; 8];
+                b[..size].copy_from_slice(&bytes[..size]);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+
+                Ok(match t {
+                    PlyType::Int8 => b[0] as i8 as f64,
+                    PlyType::UInt8 => b[0] as f64,
+                    PlyType::Int16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::UInt16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::Int32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::UInt32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::Float64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+
+    /// Reads a property and returns its value if it is a scalar. Lists are
+    /// skipped.
+    ///
+    /// * `property` - The property.
+    fn read_property(&mut self, property: &PlyProperty) -> Result<Option<f64>, String> {
+        match property.kind {
+            PlyPropertyKind::Scalar(t) => self.read(t).map(Some),
+            PlyPropertyKind::List(count_type, item_type) => {
+                let count = self.read(count_type)? as usize;
+                for _ in 0..count {
+                    self.read(item_type)?;
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Splits the contents of a PLY file into the header and the body.
+///
+/// * `bytes` - Contents of the file.
+fn split_header(bytes: &[u8]) -> Result<(&str, &[u8]), String> {
+    const END_HEADER: &[u8] = b"end_header";
+
+    if !bytes.starts_with(b"ply") {
+        return Err(String::from("Not a PLY file."));
+    }
+    let end = bytes
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .ok_or_else(|| String::from("Missing 'end_header'."))?;
+    let body = bytes[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(bytes.len(), |i| end + i + 1);
+
+    let header = str::from_utf8(&bytes[..end]).map_err(|err| err.to_string())?;
+    Ok((header, &bytes[body..]))
+}
+
+/// Parses the header of a PLY file and returns the format and the elements.
+///
+/// * `header` - The header up to `end_header`.
+fn parse_header(header: &str) -> Result<(PlyFormat, Vec<PlyElement>), String> {
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = vec![];
+
+    for line in header.lines().skip(1) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", f, _] => {
+                format = Some(match *f {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(format!("Unknown format '{}'.", f)),
+                });
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: String::from(*name),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("Invalid element count '{}'.", count))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| String::from("Property declared before any element."))?;
+                element.properties.push(PlyProperty {
+                    name: String::from(*name),
+                    kind: PlyPropertyKind::List(
+                        PlyType::parse(count_type)?,
+                        PlyType::parse(item_type)?,
+                    ),
+                });
+            }
+            ["property", t, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| String::from("Property declared before any element."))?;
+                element.properties.push(PlyProperty {
+                    name: String::from(*name),
+                    kind: PlyPropertyKind::Scalar(PlyType::parse(t)?),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("Invalid header line '{}'.", line)),
+        }
+    }
+
+    let format = format.ok_or_else(|| String::from("Missing 'format'."))?;
+    Ok((format, elements))
+}
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ascii_quad() {
+        let ply = b"ply
+format ascii 1.0
+comment A unit quad
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float u
+property float v
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1 0 0
+1 0 0 0 0 1 1 0
+1 1 0 0 0 1 1 1
+0 1 0 0 0 1 0 1
+4 0 1 2 3
+";
+        let mesh = PlyMesh::parse(ply).unwrap();
+        assert_eq!(mesh.p.len(), 4);
+        assert_eq!(mesh.p[2], Point3f::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.n[3], Normal3f::new(0.0, 0.0, 1.0));
+        assert_eq!(mesh.uv[1], Point2f::new(1.0, 0.0));
+        assert_eq!(mesh.vertex_indices, vec![0, 1, 2, 0, 2, 3]);
+        assert!(mesh.face_indices.is_empty());
+    }
+
+    #[test]
+    fn parse_binary_big_endian() {
+        let mut ply = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property double x
+property double y
+property double z
+element face 1
+property list uchar uint vertex_indices
+property int face_indices
+end_header
+"
+        .to_vec();
+        for v in [0.0_f64, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0].iter() {
+            ply.extend_from_slice(&v.to_be_bytes());
+        }
+        ply.push(3);
+        for i in [0_u32, 1, 2, 7].iter() {
+            ply.extend_from_slice(&i.to_be_bytes());
+        }
+
+        let mesh = PlyMesh::parse(&ply).unwrap();
+        assert_eq!(mesh.p[1], Point3f::new(1.0, 0.0, 0.0));
+        assert!(mesh.n.is_empty() && mesh.uv.is_empty());
+        assert_eq!(mesh.vertex_indices, vec![0, 1, 2]);
+        assert_eq!(mesh.face_indices, vec![7]);
+    }
+
+    #[test]
+    fn out_of_bounds_index() {
+        let ply = b"ply
+format ascii 1.0
+element vertex 1
+property float x
+property float y
+property float z
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0
+3 0 1 2
+";
+        assert!(PlyMesh::parse(ply).is_err());
+    }
+}
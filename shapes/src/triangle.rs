@@ -108,6 +108,17 @@ impl TriangleMesh {
         }
     }
 
+    /// Returns the memory used by the mesh data in bytes.
+    pub fn memory(&self) -> usize {
+        size_of::<Self>()
+            + self.vertex_indices.capacity() * size_of::<usize>()
+            + self.p.capacity() * size_of::<Point3f>()
+            + self.n.capacity() * size_of::<Normal3f>()
+            + self.s.capacity() * size_of::<Vector3f>()
+            + self.uv.capacity() * size_of::<Point2f>()
+            + self.face_indices.capacity() * size_of::<usize>()
+    }
+
     /// Create a triangle mesh from vertex positions, normals, tangents, uv-coordinates
     /// and alpha mask.
     ///
@@ -127,7 +138,7 @@ impl TriangleMesh {
     /// * `uv`                  - Paramteric uv-coordinates.
     /// * `alpha_mask`          - Optional alpha mask texture, which can be used to
     ///                           cut away parts of triangle surfaces
-    /// * `ehadow_alpha_mask`   - Optional shadow alpha mask texture.
+    /// * `shadow_alpha_mask`   - Optional shadow alpha mask texture.
     /// * `face_indices`        - Face indices.
     pub fn create(
         object_to_world: ArcTransform,
@@ -288,41 +299,8 @@ impl TriangleMesh {
             face_indices = vec![];
         }
 
-        let alpha_tex_name = params.find_one_texture("alpha", String::from(""));
-        let alpha_tex = if alpha_tex_name.len() > 0 {
-            if let Some(tex) = float_textures.get(&alpha_tex_name) {
-                Arc::clone(tex)
-            } else {
-                warn!(
-                    "Couldn't find float texture '{}' for 'alpha' parameter. 
-                    Using float 'alpha' parameterer instead.",
-                    alpha_tex_name
-                );
-                let alpha = params.find_one_float("alpha", 1.0);
-                Arc::new(ConstantTexture::new(alpha))
-            }
-        } else {
-            let alpha = params.find_one_float("alpha", 1.0);
-            Arc::new(ConstantTexture::new(alpha))
-        };
-
-        let shadow_alpha_tex_name = params.find_one_texture("shadowalpha", String::from(""));
-        let shadow_alpha_tex = if shadow_alpha_tex_name.len() > 0 {
-            if let Some(tex) = float_textures.get(&shadow_alpha_tex_name) {
-                Arc::clone(tex)
-            } else {
-                warn!(
-                    "Couldn't find float texture '{}' for 'shadowalpha' 
-                    parameter.  Using float 'shadowalpha' parameterer instead.",
-                    alpha_tex_name
-                );
-                let alpha = params.find_one_float("shadowalpha", 1.0);
-                Arc::new(ConstantTexture::new(alpha))
-            }
-        } else {
-            let alpha = params.find_one_float("shadowalpha", 1.0);
-            Arc::new(ConstantTexture::new(alpha))
-        };
+        let alpha_tex = alpha_texture(params, float_textures, "alpha");
+        let shadow_alpha_tex = alpha_texture(params, float_textures, "shadowalpha");
 
         Self::create(
             Arc::clone(&o2w),
@@ -333,13 +311,43 @@ impl TriangleMesh {
             n,
             s,
             uvs,
-            Some(alpha_tex),
-            Some(shadow_alpha_tex),
+            alpha_tex,
+            shadow_alpha_tex,
             face_indices,
         )
     }
 }
 
+/// Returns the alpha mask texture given by the shape's parameters. A named
+/// float texture is used if one is given. Otherwise a float parameter of the
+/// same name set to 0 cuts away the whole surface. Returns `None` if there is
+/// no mask so intersections skip the alpha test.
+///
+/// * `params`         - Shape parameters.
+/// * `float_textures` - Float textures.
+/// * `name`           - Parameter name, "alpha" or "shadowalpha".
+pub(crate) fn alpha_texture(
+    params: &ParamSet,
+    float_textures: &HashMap<String, ArcTexture<Float>>,
+    name: &str,
+) -> Option<ArcTexture<Float>> {
+    let tex_name = params.find_one_texture(name, String::from(""));
+    if !tex_name.is_empty() {
+        if let Some(tex) = float_textures.get(&tex_name) {
+            return Some(Arc::clone(tex));
+        }
+        warn!(
+            "Couldn't find float texture '{}' for '{}' parameter. Using float '{}' parameter instead.",
+            tex_name, name, name
+        );
+    }
+    if params.find_one_float(name, 1.0) == 0.0 {
+        Some(Arc::new(ConstantTexture::new(0.0)))
+    } else {
+        None
+    }
+}
+
 /// Triangle.
 #[derive(Clone)]
 pub struct Triangle {
@@ -583,7 +591,7 @@ impl Shape for Triangle {
         let uv_hit = b0 * uv[0] + b1 * uv[1] + b2 * uv[2];
 
         // Test intersection against alpha texture, if present.
-        if test_alpha_texture && !self.mesh.alpha_mask.is_none() {
+        if test_alpha_texture && self.mesh.alpha_mask.is_some() {
             let isect_local = SurfaceInteraction::new(
                 p_hit,
                 Vector3f::default(),
@@ -830,8 +838,10 @@ impl Shape for Triangle {
             return false;
         }
 
-        // Test intersection against alpha texture, if present.
-        if test_alpha_texture && !self.mesh.alpha_mask.is_none() {
+        // Test shadow ray intersection against alpha textures, if present.
+        if test_alpha_texture
+            && (self.mesh.alpha_mask.is_some() || self.mesh.shadow_alpha_mask.is_some())
+        {
             // Compute triangle partial derivatives.
             let uv = self.get_uvs();
 
@@ -880,15 +890,34 @@ impl Shape for Triangle {
                 None,
             );
 
-            let alpha_mask = self.mesh.alpha_mask.clone().unwrap();
-            if alpha_mask.evaluate(&isect_local) == 0.0 {
-                return false;
+            if let Some(alpha_mask) = self.mesh.alpha_mask.as_ref() {
+                if alpha_mask.evaluate(&isect_local) == 0.0 {
+                    return false;
+                }
+            }
+            if let Some(shadow_alpha_mask) = self.mesh.shadow_alpha_mask.as_ref() {
+                if shadow_alpha_mask.evaluate(&isect_local) == 0.0 {
+                    return false;
+                }
             }
         }
 
         true
     }
 
+    /// Returns the world space vertices unless the mesh has alpha masks.
+    fn triangle_vertices(&self) -> Option<[Point3f; 3]> {
+        if self.mesh.alpha_mask.is_some() || self.mesh.shadow_alpha_mask.is_some() {
+            None
+        } else {
+            Some([
+                self.mesh.p[self.mesh.vertex_indices[self.v]],
+                self.mesh.p[self.mesh.vertex_indices[self.v + 1]],
+                self.mesh.p[self.mesh.vertex_indices[self.v + 2]],
+            ])
+        }
+    }
+
     /// Returns the surface area of the shape in object space.
     fn area(&self) -> Float {
         let p0 = self.mesh.p[self.mesh.vertex_indices[self.v]];
@@ -897,6 +926,13 @@ impl Shape for Triangle {
         0.5 * (p1 - p0).cross(&(p2 - p0)).length()
     }
 
+    /// Returns the memory used by the triangle and its share of the mesh in
+    /// bytes.
+    fn memory(&self) -> usize {
+        let n_triangles = (self.mesh.vertex_indices.len() / 3).max(1);
+        size_of::<Self>() + self.mesh.memory() / n_triangles
+    }
+
     /// Sample a point on the surface and return the PDF with respect to area on
     /// the surface.
     ///
@@ -936,3 +972,69 @@ impl Shape for Triangle {
         (it, pdf)
     }
 }
+
+// ----------------------------------------------------------------------------
+// Tests
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a single triangle facing +z with the given alpha parameters.
+    ///
+    /// * `alpha`        - Value of the "alpha" parameter.
+    /// * `shadow_alpha` - Value of the "shadowalpha" parameter.
+    fn triangle(alpha: Float, shadow_alpha: Float) -> ArcShape {
+        let mut params = ParamSet::new();
+        params.add_float("alpha", &[alpha]);
+        params.add_float("shadowalpha", &[shadow_alpha]);
+        let float_textures = HashMap::new();
+
+        let identity = Arc::new(Transform::default());
+        let tris = TriangleMesh::create(
+            Arc::clone(&identity),
+            identity,
+            false,
+            vec![0, 1, 2],
+            vec![
+                Point3f::new(-1.0, -1.0, 0.0),
+                Point3f::new(1.0, -1.0, 0.0),
+                Point3f::new(0.0, 1.0, 0.0),
+            ],
+            vec![],
+            vec![],
+            vec![],
+            alpha_texture(&params, &float_textures, "alpha"),
+            alpha_texture(&params, &float_textures, "shadowalpha"),
+            vec![],
+        );
+        Arc::clone(&tris[0])
+    }
+
+    #[test]
+    fn alpha_cuts_away_surface() {
+        let ray = Ray::new(
+            Point3f::new(0.0, 0.0, 1.0),
+            Vector3f::new(0.0, 0.0, -1.0),
+            INFINITY,
+            0.0,
+            None,
+        );
+
+        let opaque = triangle(1.0, 1.0);
+        assert!(opaque.intersect(&ray, true).is_some());
+        assert!(opaque.intersect_p(&ray, true));
+
+        let cutout = triangle(0.0, 1.0);
+        assert!(cutout.intersect(&ray, true).is_none());
+        assert!(!cutout.intersect_p(&ray, true));
+        assert!(cutout.intersect(&ray, false).is_some());
+
+        // Shadow alpha only affects shadow rays.
+        let shadowless = triangle(1.0, 0.0);
+        assert!(shadowless.intersect(&ray, true).is_some());
+        assert!(!shadowless.intersect_p(&ray, true));
+        assert!(shadowless.intersect_p(&ray, false));
+    }
+}
@@ -93,12 +93,12 @@ where
             }
 
             // Apply box filter to checkerboard region.
-            let bump_int = |x: Float| -> Int {
-                (x / 2.0).floor() as Int + 2 * max(x / 2.0 - (x / 2.0).floor() - 0.5, 0.0) as Int
+            let bump_int = |x: Float| -> Float {
+                (x / 2.0).floor() + 2.0 * max(x / 2.0 - (x / 2.0).floor() - 0.5, 0.0)
             };
 
-            let sint = (bump_int(s1) - bump_int(s0)) as Float / (2.0 * ds);
-            let tint = (bump_int(t1) - bump_int(t0)) as Float / (2.0 * dt);
+            let sint = (bump_int(s1) - bump_int(s0)) / (2.0 * ds);
+            let tint = (bump_int(t1) - bump_int(t0)) / (2.0 * dt);
             let area2 = if ds > 1.0 || dt > 1.0 {
                 0.5
             } else {
@@ -73,7 +73,7 @@ macro_rules! from_params {
                 let tex2 = tp
                     .$get_texture_or_else_func("tex2", Arc::new(ConstantTexture::new(0.0.into())));
                 // Initialize 3D texture mapping `map` from `tex2world`.
-                let map = Arc::new(IdentityMapping3D::new(*tex2world));
+                let map = get_texture_mapping_3d(tp, tex2world);
                 Self::new(tex1, tex2, map)
             }
         }
@@ -93,7 +93,7 @@ macro_rules! from_params {
                     "outside",
                     Arc::new(ConstantTexture::new(0.0.into())),
                 );
-                Self::new(inside, outside, map)
+                Self::new(outside, inside, map)
             }
         }
     };
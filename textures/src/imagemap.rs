@@ -20,7 +20,7 @@ where
         + Add<Tmemory, Output = Tmemory>
         + AddAssign
         + Clamp<Float>,
-    Spectrum: ConvertIn<Tmemory>,
+    RGBSpectrum: ConvertIn<Tmemory>,
 {
     /// 2D mapping.
     mapping: ArcTextureMapping2D,
@@ -94,6 +94,12 @@ impl Texture<Spectrum> for ImageTexture<RGBSpectrum> {
         let rgb = mem.to_rgb();
         Spectrum::from_rgb(&rgb, None)
     }
+
+    /// Returns the memory used by the MIPMap in bytes. MIPMaps are shared
+    /// between textures using the same image and filtering parameters.
+    fn memory(&self) -> usize {
+        self.mipmap.memory()
+    }
 }
 
 /// Implement `ImageTexture` stored in MIPMaps as `Float` and evaluate to
@@ -113,6 +119,12 @@ impl Texture<Float> for ImageTexture<Float> {
         // Convert out to `Float`.
         self.mipmap.lookup(&st, &dstdx, &dstdy)
     }
+
+    /// Returns the memory used by the MIPMap in bytes. MIPMaps are shared
+    /// between textures using the same image and filtering parameters.
+    fn memory(&self) -> usize {
+        self.mipmap.memory()
+    }
 }
 
 macro_rules! from_params {
@@ -11,12 +11,12 @@ extern crate log;
 mod bilerp;
 mod checkerboard_2d;
 mod checkerboard_3d;
-mod constant;
 mod dots;
 mod fbm;
 mod imagemap;
 mod marble;
 mod mix;
+mod projection;
 mod scale;
 mod uv;
 mod windy;
@@ -25,12 +25,13 @@ mod windy;
 pub use bilerp::*;
 pub use checkerboard_2d::*;
 pub use checkerboard_3d::*;
-pub use constant::*;
+pub use core::texture::ConstantTexture;
 pub use dots::*;
 pub use fbm::*;
 pub use imagemap::*;
 pub use marble::*;
 pub use mix::*;
+pub use projection::*;
 pub use scale::*;
 pub use uv::*;
 pub use windy::*;
@@ -63,3 +64,15 @@ fn get_texture_mapping(tp: &TextureParams, tex2world: &Transform) -> ArcTextureM
         }
     }
 }
+
+/// Returns a 3D texture mapping reference from the texture parameters.
+///
+/// * `tp`        - Texture parameters.
+/// * `tex2world` - Texture space to world space transform.
+fn get_texture_mapping_3d(tp: &TextureParams, tex2world: &Transform) -> ArcTextureMapping3D {
+    let space = tp.find_string("space", String::from("world"));
+    Arc::new(IdentityMapping3D::new(
+        *tex2world,
+        TextureSpace::from(&space[..]),
+    ))
+}
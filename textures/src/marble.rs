@@ -1,5 +1,6 @@
 //! Marble Texture
 
+use super::get_texture_mapping_3d;
 use core::geometry::*;
 use core::paramset::*;
 use core::pbrt::*;
@@ -101,7 +102,7 @@ impl From<(&TextureParams, &Transform)> for MarbleTexture {
     ///         to world space transform.
     fn from(p: (&TextureParams, &Transform)) -> Self {
         let (tp, tex2world) = p;
-        let map = Arc::new(IdentityMapping3D::new(*tex2world));
+        let map = get_texture_mapping_3d(tp, tex2world);
         Self::new(
             map,
             tp.find_float("roughness", 0.5),
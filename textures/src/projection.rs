@@ -0,0 +1,215 @@
+//! Projection Texture
+
+use super::*;
+use core::geometry::*;
+use core::mipmap::*;
+use core::pbrt::*;
+use core::scene::Scene;
+use core::spectrum::*;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign};
+use std::sync::Arc;
+
+/// Stores an image projected onto surfaces from a projector such as the
+/// camera the image was shot with. Points outside the image or hidden from
+/// the projector use another texture.
+pub struct ProjectionTexture<Tmemory, T>
+where
+    Tmemory: Copy
+        + Default
+        + Mul<Float, Output = Tmemory>
+        + MulAssign<Float>
+        + Div<Float, Output = Tmemory>
+        + DivAssign<Float>
+        + Add<Tmemory, Output = Tmemory>
+        + AddAssign
+        + Clamp<Float>,
+    RGBSpectrum: ConvertIn<Tmemory>,
+{
+    /// The projector.
+    projector: Projector,
+
+    /// The mipmaps.
+    mipmap: ArcMIPMap<Tmemory>,
+
+    /// Texture used for points that are not lit by the projector.
+    outside: ArcTexture<T>,
+}
+
+macro_rules! new_projection_texture {
+    ($tmemory: ty, $t: ty) => {
+        impl ProjectionTexture<$tmemory, $t> {
+            /// Create a new `ProjectionTexture<$tmemory, $t>`.
+            ///
+            /// * `projector_to_world` - Transformation from projector space to
+            ///                          world space.
+            /// * `fov`                - Field of view in degrees of the
+            ///                          shorter image axis.
+            /// * `path`               - The path to the image file.
+            /// * `filtering_method`   - Type of filtering to use for mipmaps.
+            /// * `scale`              - Scale for the texel values.
+            /// * `gamma`              - Do gamma correction for the texel values.
+            /// * `max_anisotropy`     - Used to clamp the ellipse eccentricity
+            ///                          (EWA).
+            /// * `occlusion`          - Whether points hidden from the
+            ///                          projector use `outside`.
+            /// * `depth_bias`         - Relative tolerance for comparing depths.
+            /// * `depth_resolution`   - Resolution of the depth map along the
+            ///                          longer image axis.
+            /// * `outside`            - Texture used for points that are not
+            ///                          lit by the projector.
+            #[allow(clippy::too_many_arguments)]
+            pub fn new(
+                projector_to_world: &Transform,
+                fov: Float,
+                path: &str,
+                filtering_method: FilteringMethod,
+                scale: Float,
+                gamma: bool,
+                max_anisotropy: Float,
+                occlusion: bool,
+                depth_bias: Float,
+                depth_resolution: usize,
+                outside: ArcTexture<$t>,
+            ) -> Self {
+                let tex_info = TexInfo::new(
+                    path,
+                    filtering_method,
+                    ImageWrap::Clamp,
+                    scale,
+                    gamma,
+                    max_anisotropy,
+                );
+                let mipmap: ArcMIPMap<$tmemory> = match MIPMapCache::get(tex_info) {
+                    Ok(mipmap) => mipmap,
+                    Err(err) => panic!("Unable to load MIPMap: {}", err),
+                };
+
+                let aspect = mipmap.width() as Float / mipmap.height() as Float;
+                let projector = Projector::new(
+                    projector_to_world,
+                    fov,
+                    aspect,
+                    occlusion,
+                    depth_bias,
+                    depth_resolution,
+                );
+                Self {
+                    projector,
+                    mipmap,
+                    outside,
+                }
+            }
+        }
+    };
+}
+new_projection_texture!(RGBSpectrum, Spectrum);
+new_projection_texture!(Float, Float);
+
+/// Implement `ProjectionTexture` stored in MIPMaps as `RGBSpectrum` and
+/// evaluate to `Spectrum`.
+impl Texture<Spectrum> for ProjectionTexture<RGBSpectrum, Spectrum> {
+    /// Evaluate the texture at surface interaction.
+    ///
+    /// * `si` - Surface interaction.
+    fn evaluate(&self, si: &SurfaceInteraction) -> Spectrum {
+        match self.projector.map(si) {
+            Some(TextureMap2DResult {
+                p: st,
+                dstdx,
+                dstdy,
+            }) => {
+                let rgb = self.mipmap.lookup(&st, &dstdx, &dstdy).to_rgb();
+                Spectrum::from_rgb(&rgb, None)
+            }
+            None => self.outside.evaluate(si),
+        }
+    }
+
+    /// Returns the memory used by the MIPMap in bytes.
+    fn memory(&self) -> usize {
+        self.mipmap.memory()
+    }
+
+    /// Renders the depth map of the projector.
+    ///
+    /// * `scene` - The scene.
+    fn preprocess(&self, scene: &Scene) {
+        self.projector.preprocess(scene);
+    }
+}
+
+/// Implement `ProjectionTexture` stored in MIPMaps as `Float` and evaluate to
+/// `Float`.
+impl Texture<Float> for ProjectionTexture<Float, Float> {
+    /// Evaluate the texture at surface interaction.
+    ///
+    /// * `si` - Surface interaction.
+    fn evaluate(&self, si: &SurfaceInteraction) -> Float {
+        match self.projector.map(si) {
+            Some(TextureMap2DResult {
+                p: st,
+                dstdx,
+                dstdy,
+            }) => self.mipmap.lookup(&st, &dstdx, &dstdy),
+            None => self.outside.evaluate(si),
+        }
+    }
+
+    /// Returns the memory used by the MIPMap in bytes.
+    fn memory(&self) -> usize {
+        self.mipmap.memory()
+    }
+
+    /// Renders the depth map of the projector.
+    ///
+    /// * `scene` - The scene.
+    fn preprocess(&self, scene: &Scene) {
+        self.projector.preprocess(scene);
+    }
+}
+
+macro_rules! from_params {
+    ($tmemory: ty, $t: ty, $get_texture_or_else_func: ident) => {
+        impl From<(&TextureParams, &Transform)> for ProjectionTexture<$tmemory, $t> {
+            /// Create a `ProjectionTexture<$tmemory, $t>` from given parameter
+            /// set and transformation from projector space to world space.
+            ///
+            /// * `p` - Tuple containing texture parameters and projector space
+            ///         to world space transform.
+            fn from(p: (&TextureParams, &Transform)) -> Self {
+                let (tp, projector_to_world) = p;
+
+                let fov = tp.find_float("fov", 90.0);
+                let max_anisotropy = tp.find_float("maxanisotropy", 8.0);
+                let filtering_method = if tp.find_bool("trilinear", false) {
+                    FilteringMethod::Trilinear
+                } else {
+                    FilteringMethod::Ewa
+                };
+                let scale = tp.find_float("scale", 1.0);
+                let path = tp.find_filename("filename", String::from(""));
+                let gamma = tp.find_bool("gamma", path.ends_with(".tga") || path.ends_with(".png"));
+                let occlusion = tp.find_bool("occlusion", false);
+                let depth_bias = tp.find_float("depthbias", 0.01);
+                let depth_resolution = max(1, tp.find_int("depthresolution", 512)) as usize;
+                let outside = tp
+                    .$get_texture_or_else_func("outside", Arc::new(ConstantTexture::new(0.0.into())));
+                Self::new(
+                    projector_to_world,
+                    fov,
+                    &path,
+                    filtering_method,
+                    scale,
+                    gamma,
+                    max_anisotropy,
+                    occlusion,
+                    depth_bias,
+                    depth_resolution,
+                    outside,
+                )
+            }
+        }
+    };
+}
+from_params!(RGBSpectrum, Spectrum, get_spectrum_texture_or_else);
+from_params!(Float, Float, get_float_texture_or_else);
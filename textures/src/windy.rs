@@ -1,5 +1,6 @@
 //! Windy Waves Texture
 
+use super::get_texture_mapping_3d;
 use core::geometry::*;
 use core::paramset::*;
 use core::pbrt::*;
@@ -52,8 +53,8 @@ impl<T> From<(&TextureParams, &Transform)> for WindyTexture<T> {
     /// * `p` - Tuple containing texture parameters and texture space
     ///         to world space transform.
     fn from(p: (&TextureParams, &Transform)) -> Self {
-        let (_tp, tex2world) = p;
-        let map = Arc::new(IdentityMapping3D::new(*tex2world));
+        let (tp, tex2world) = p;
+        let map = get_texture_mapping_3d(tp, tex2world);
         Self::new(map)
     }
 }